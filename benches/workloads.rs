@@ -0,0 +1,20 @@
+// Runs every workload of `beyond_rdb::bench` in a database of its own, with commits both waiting
+// for the log and not, printing a line of how fast each went.
+use beyond_rdb::bench::{self, Options, Workload};
+use beyond_rdb::buffer::Durability;
+use beyond_rdb::database::Database;
+
+const POOL_SIZE: usize = 256;
+
+fn main() {
+    for workload in Workload::ALL {
+        for durability in [Durability::Sync, Durability::Async] {
+            let dir = tempfile::tempdir().unwrap();
+            let db = Database::open(dir.path(), POOL_SIZE).unwrap();
+            let options = Options { workload, clients: 4, durability, ..Options::default() };
+            let report = bench::run(&db, &options).unwrap();
+            println!("{:?} {}", durability, report);
+            db.close().unwrap();
+        }
+    }
+}
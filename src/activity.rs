@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::buffer::{BufferPoolManager, CancelToken};
+use crate::catalog::{Column, VirtualTable};
+use crate::query;
+use crate::tuple::{DataType, Tuple, Value};
+use crate::wal::TxId;
+
+// What the sessions are doing, as pg_stat_activity shows it: the user each is of, whether it's
+// running a statement, idle or idle in a transaction, and the statement it's running or ran
+// last. A session's statement can be canceled by its ID, and the session terminated: what it's
+// running is canceled, its transaction rolled back, and whatever it runs from then on fails. As
+// the statements of the sessions take turns with the engine, one is only canceled while it runs
+// from another thread, which the registry can be sent to (see `Database::activity`). Readable as
+// the virtual table `stat_activity`, and acted on by CANCEL QUERY and CANCEL SESSION.
+
+pub const TABLE_NAME: &str = "stat_activity";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Idle,
+    Active,
+    IdleInTransaction,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            State::Idle => "idle",
+            State::Active => "active",
+            State::IdleInTransaction => "idle in transaction",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionActivity {
+    pub id: u64,
+    // None for a superuser's, as `Session::user`
+    pub user: Option<String>,
+    pub state: State,
+    // running, or run last
+    pub query: Option<String>,
+    // since when it's been in the state
+    pub since: Instant,
+    // of the transaction running in it as of its last statement, if that has written
+    pub txid: Option<TxId>,
+    pub terminated: bool,
+    cancel: CancelToken,
+}
+
+// The sessions of a database by ID, shared by them and with any thread.
+#[derive(Debug, Clone, Default)]
+pub struct Activity(Arc<Mutex<BTreeMap<u64, SessionActivity>>>);
+
+impl Activity {
+    pub(crate) fn start(&self, id: u64, cancel: CancelToken) {
+        let session = SessionActivity { id, user: None, state: State::Idle, query: None, since: Instant::now(), txid: None, terminated: false, cancel };
+        self.0.lock().unwrap().insert(id, session);
+    }
+
+    pub(crate) fn end(&self, id: u64) {
+        self.0.lock().unwrap().remove(&id);
+    }
+
+    pub(crate) fn set_user(&self, id: u64, user: Option<&str>) {
+        if let Some(session) = self.0.lock().unwrap().get_mut(&id) {
+            session.user = user.map(str::to_string);
+        }
+    }
+
+    // Marks the session as running `query`, or a statement with no text if it's empty.
+    pub(crate) fn run(&self, id: u64, query: &str) {
+        if let Some(session) = self.0.lock().unwrap().get_mut(&id) {
+            session.state = State::Active;
+            session.query = Some(query.to_string()).filter(|query| !query.is_empty());
+            session.since = Instant::now();
+        }
+    }
+
+    // Marks the session as done with its statement, in the transaction `txid` if `in_transaction`.
+    pub(crate) fn finish(&self, id: u64, in_transaction: bool, txid: Option<TxId>) {
+        if let Some(session) = self.0.lock().unwrap().get_mut(&id) {
+            session.state = if in_transaction { State::IdleInTransaction } else { State::Idle };
+            session.since = Instant::now();
+            session.txid = txid;
+        }
+    }
+
+    // The sessions, in the order they started.
+    pub fn sessions(&self) -> Vec<SessionActivity> {
+        self.0.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<SessionActivity> {
+        self.0.lock().unwrap().get(&id).cloned()
+    }
+
+    // Cancels the statement session `id` is running, if any. Returns whether there's the session.
+    pub fn cancel(&self, id: u64) -> bool {
+        self.0.lock().unwrap().get(&id).map(|session| session.cancel.cancel()).is_some()
+    }
+
+    // Terminates session `id`, its transaction being rolled back the next time the engine runs
+    // anything. Returns whether there's the session.
+    pub fn terminate(&self, id: u64) -> bool {
+        let mut sessions = self.0.lock().unwrap();
+        let Some(session) = sessions.get_mut(&id) else {
+            return false;
+        };
+        session.terminated = true;
+        session.cancel.cancel();
+        true
+    }
+
+    pub fn is_terminated(&self, id: u64) -> bool {
+        self.0.lock().unwrap().get(&id).is_some_and(|session| session.terminated)
+    }
+
+    // The transactions of the sessions terminated which are to be rolled back, each once.
+    pub(crate) fn take_terminated(&self) -> Vec<TxId> {
+        let mut sessions = self.0.lock().unwrap();
+        sessions.values_mut().filter(|session| session.terminated).filter_map(|session| session.txid.take()).collect()
+    }
+}
+
+// The sessions as a virtual table, with one row each of
+//   session_id INTEGER, user_name, state, query TEXT, txid, state_ms INTEGER
+// the user of a superuser's session being NULL, and state_ms how long it's been in the state.
+pub struct ActivityTable(pub Activity);
+
+impl VirtualTable for ActivityTable {
+    fn columns(&self) -> Vec<Column> {
+        let column = |name: &str, data_type| Column { name: name.to_string(), data_type };
+        vec![
+            column("session_id", DataType::Integer),
+            column("user_name", DataType::Text),
+            column("state", DataType::Text),
+            column("query", DataType::Text),
+            column("txid", DataType::Integer),
+            column("state_ms", DataType::Integer),
+        ]
+    }
+
+    fn rows(&self, _bufmgr: &mut BufferPoolManager) -> Result<Vec<Tuple>, query::Error> {
+        let int = |n: u64| Value::Int(n.try_into().unwrap_or(i64::MAX));
+        let text = |s: Option<&str>| s.map_or(Value::Null, |s| Value::Text(s.to_string()));
+        Ok(self
+            .0
+            .sessions()
+            .into_iter()
+            .map(|session| {
+                vec![
+                    int(session.id),
+                    text(session.user.as_deref()),
+                    Value::Text(session.state.to_string()),
+                    text(session.query.as_deref()),
+                    session.txid.map_or(Value::Null, int),
+                    int(session.since.elapsed().as_millis().try_into().unwrap_or(u64::MAX)),
+                ]
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::{Database, Session};
+    use crate::sql::{self, QueryResult};
+    use crate::tuple::{Tuple, Value};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path(), 16).unwrap();
+        let mut session = db.session();
+        session.execute("CREATE TABLE t (id INTEGER PRIMARY KEY); CREATE USER alice; CREATE USER bob; GRANT ALL ON t TO alice, bob").unwrap();
+        let rows = |session: &mut Session, sql: &str| -> Vec<Tuple> {
+            match session.execute(sql).unwrap().pop().unwrap() {
+                QueryResult::Rows { rows, .. } => rows,
+                result => panic!("{:?}", result),
+            }
+        };
+        let text = |s: &str| Value::Text(s.to_string());
+
+        // each session with what it's doing, the one asking running the query
+        let mut alice = db.session();
+        alice.set_user(Some("alice"));
+        alice.execute("BEGIN; INSERT INTO t VALUES (1)").unwrap();
+        let mut bob = db.session();
+        bob.set_user(Some("bob"));
+        bob.execute("SELECT * FROM t").unwrap();
+        let query = "SELECT session_id, user_name, state, query, txid IS NULL FROM stat_activity";
+        assert_eq!(
+            vec![
+                vec![Value::Int(session.id() as i64), Value::Null, text("active"), text(query), Value::Bool(true)],
+                vec![Value::Int(alice.id() as i64), text("alice"), text("idle in transaction"), text("INSERT INTO t VALUES (1)"), Value::Bool(false)],
+                vec![Value::Int(bob.id() as i64), text("bob"), text("idle"), text("SELECT * FROM t"), Value::Bool(true)],
+            ],
+            rows(&mut session, query)
+        );
+        assert_eq!(3, db.activity().sessions().len());
+
+        // only superusers cancel the sessions of other users
+        let denied = bob.execute(&format!("CANCEL SESSION {}", alice.id())).unwrap_err();
+        assert!(matches!(denied, sql::Error::PermissionDenied(_)), "{:?}", denied);
+        bob.execute(&format!("CANCEL QUERY {}", bob.id())).unwrap();
+        assert_eq!("session not found: 99", session.execute("CANCEL QUERY 99").unwrap_err().to_string());
+
+        // a session terminated has its transaction rolled back, releasing its locks, and fails
+        // from then on
+        session.execute(&format!("CANCEL SESSION {}", alice.id())).unwrap();
+        assert!(db.activity().get(alice.id()).unwrap().terminated);
+        assert_eq!(vec![Value::Int(0)], rows(&mut bob, "SELECT count(*) FROM t").pop().unwrap());
+        bob.execute("INSERT INTO t VALUES (1)").unwrap();
+        assert_eq!("terminated by request", alice.execute("SELECT * FROM t").unwrap_err().to_string());
+        assert!(alice.execute("SHOW lock_timeout").is_err() && !alice.in_transaction());
+        let alice_id = alice.id();
+        drop(alice);
+        assert!(db.activity().get(alice_id).is_none() && !db.activity().terminate(alice_id));
+
+        // a statement running is canceled from another thread, by the registry sent to it
+        let values: Vec<String> = (2..200).map(|i| format!("({})", i)).collect();
+        session.execute(&format!("INSERT INTO t VALUES {}", values.join(", "))).unwrap();
+        let (activity, id) = (db.activity(), session.id());
+        let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let canceler = {
+            let done = done.clone();
+            std::thread::spawn(move || {
+                // until it's canceled after it started, as starting a statement clears the token
+                while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                    if activity.get(id).unwrap().state == super::State::Active {
+                        activity.cancel(id);
+                    }
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                }
+            })
+        };
+        let err = session.execute("SELECT count(*) FROM t a, t b, t c, t d").unwrap_err();
+        done.store(true, std::sync::atomic::Ordering::Relaxed);
+        canceler.join().unwrap();
+        assert_eq!("statement canceled", err.to_string());
+        rows(&mut session, "SELECT * FROM t");
+    }
+}
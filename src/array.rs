@@ -0,0 +1,130 @@
+use crate::tuple::{ElementType, Value};
+
+// Arrays are written as text the way PostgreSQL writes them, e.g. {1,2,NULL} and {a,"b c","\"d\""}:
+// elements are separated by commas, and those which are empty, spell NULL, or have whitespace or
+// any of {},"\ in them are quoted, with " and \ escaped by backslashes.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("malformed array literal: \"{0}\"")]
+    Syntax(String),
+}
+
+pub fn to_string(values: &[Value]) -> String {
+    let mut text = String::from("{");
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            text.push(',');
+        }
+        let element = match value {
+            Value::Null => {
+                text.push_str("NULL");
+                continue;
+            }
+            Value::Int(n) => n.to_string(),
+            Value::Text(s) => s.clone(),
+            Value::Bool(b) => (if *b { "t" } else { "f" }).to_string(),
+            // not elements of arrays
+            Value::Json(_) | Value::Array(_) | Value::Point(_) | Value::Box(_) => unreachable!(),
+            Value::Float(_) | Value::Numeric(_) | Value::Date(_) | Value::Timestamp(_) => unreachable!(),
+        };
+        let quoted = element.is_empty()
+            || element.eq_ignore_ascii_case("null")
+            || element.chars().any(|c| c.is_whitespace() || "{},\"\\".contains(c));
+        if quoted {
+            text.push('"');
+            for c in element.chars() {
+                if c == '"' || c == '\\' {
+                    text.push('\\');
+                }
+                text.push(c);
+            }
+            text.push('"');
+        } else {
+            text.push_str(&element);
+        }
+    }
+    text.push('}');
+    text
+}
+
+// Parses the text of an array of `element` values. Unquoted elements have the whitespace around
+// them trimmed, and are NULL if they spell it.
+pub fn parse(text: &str, element: ElementType) -> Result<Vec<Value>, Error> {
+    let error = || Error::Syntax(text.to_string());
+    let inner = text.trim().strip_prefix('{').and_then(|s| s.strip_suffix('}')).ok_or_else(error)?;
+    let mut values = vec![];
+    if inner.trim().is_empty() {
+        return Ok(values);
+    }
+    let mut chars = inner.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let (raw, quoted) = if chars.next_if_eq(&'"').is_some() {
+            let mut raw = String::new();
+            loop {
+                match chars.next().ok_or_else(error)? {
+                    '"' => break,
+                    '\\' => raw.push(chars.next().ok_or_else(error)?),
+                    c => raw.push(c),
+                }
+            }
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            (raw, true)
+        } else {
+            let mut raw = String::new();
+            while let Some(c) = chars.next_if(|&c| c != ',') {
+                match c {
+                    '{' | '}' | '"' => return Err(error()),
+                    '\\' => raw.push(chars.next().ok_or_else(error)?),
+                    c => raw.push(c),
+                }
+            }
+            (raw.trim_end().to_string(), false)
+        };
+        values.push(match element {
+            _ if !quoted && raw.eq_ignore_ascii_case("null") => Value::Null,
+            _ if !quoted && raw.is_empty() => return Err(error()),
+            ElementType::Text => Value::Text(raw),
+            ElementType::Integer => Value::Int(raw.trim().parse().map_err(|_| error())?),
+            ElementType::Boolean => match raw.trim().to_lowercase().as_str() {
+                "t" | "true" | "y" | "yes" | "on" | "1" => Value::Bool(true),
+                "f" | "false" | "n" | "no" | "off" | "0" => Value::Bool(false),
+                _ => return Err(error()),
+            },
+        });
+        match chars.next() {
+            Some(',') => {}
+            None => return Ok(values),
+            Some(_) => return Err(error()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let values = vec![
+            Value::Text("a".to_string()),
+            Value::Null,
+            Value::Text("".to_string()),
+            Value::Text("NULL".to_string()),
+            Value::Text("b c".to_string()),
+            Value::Text("{\"\\,}".to_string()),
+        ];
+        let text = to_string(&values);
+        assert_eq!(r#"{a,NULL,"","NULL","b c","{\"\\,}"}"#, text);
+        assert_eq!(values, parse(&text, ElementType::Text).unwrap());
+
+        assert_eq!(vec![Value::Int(1), Value::Null, Value::Int(-3)], parse(" { 1 , null,-3 } ", ElementType::Integer).unwrap());
+        assert_eq!("{t,f}", to_string(&parse("{true,off}", ElementType::Boolean).unwrap()));
+        assert!(parse("{}", ElementType::Integer).unwrap().is_empty());
+        for malformed in ["", "1,2", "{1,}", "{,}", "{\"a}", "{a}b}", "{1,x}", "{{1}}"] {
+            let element = if malformed == "{a}b}" { ElementType::Text } else { ElementType::Integer };
+            assert!(parse(malformed, element).is_err(), "{}", malformed);
+        }
+    }
+}
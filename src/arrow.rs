@@ -0,0 +1,612 @@
+use std::io::{self, Write};
+
+use crate::array;
+use crate::geometry::{Point, Rect};
+use crate::datetime;
+use crate::json;
+use crate::numeric::{Float, Numeric};
+use crate::tuple::{DataType, Tuple, Value};
+
+// Record batches of Arrow: the values of each column of a batch of rows in buffers laid out as
+// Arrow lays them out in memory, so a consumer of Arrow takes them as they are. Integers are
+// Int64, a buffer of 8 bytes each, double precision floats FloatingPoint DOUBLE, likewise,
+// booleans Bool, a bitmap, and text Utf8, a buffer of the i32 offset of each value then one of
+// their bytes after each other. JSON, arrays, numerics, dates and timestamps are Utf8 of their
+// text. A bitmap of validity before
+// those has a 0 for each NULL, or is left out where there's none. Bitmaps are of the lowest bit
+// first and everything little endian.
+//
+// The Arrow IPC stream format, in which Arrow Flight and the readers of Arrow take batches, is
+//   [schema message] [record batch message ...] [0xffffffff] [0: u32]
+// where a message is
+//   [0xffffffff] [metadata length: u32] [metadata] [body]
+// the metadata the flatbuffer of the schema, or of where the buffers of a batch are in the body.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("{value:?} is not a value of column {column}")]
+    TypeMismatch { column: String, value: Value },
+}
+
+// Rows a batch holds, or fewer once its buffers are of this many bytes.
+pub const BATCH_ROWS: usize = 8 * 1024;
+pub const BATCH_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub data_type: DataType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordBatch {
+    pub fields: Vec<Field>,
+    pub columns: Vec<Array>,
+}
+
+impl RecordBatch {
+    pub fn num_rows(&self) -> usize {
+        self.columns.first().map_or(0, Array::len)
+    }
+
+    pub fn row(&self, i: usize) -> Tuple {
+        self.columns.iter().map(|column| column.value(i)).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Array {
+    data_type: DataType,
+    len: usize,
+    null_count: usize,
+    validity: Option<Vec<u8>>,
+    // the values, or the offsets and then the values of text
+    buffers: Vec<Vec<u8>>,
+}
+
+impl Array {
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn null_count(&self) -> usize {
+        self.null_count
+    }
+
+    pub fn validity(&self) -> Option<&[u8]> {
+        self.validity.as_deref()
+    }
+
+    pub fn buffers(&self) -> &[Vec<u8>] {
+        &self.buffers
+    }
+
+    pub fn value(&self, i: usize) -> Value {
+        assert!(i < self.len, "row {} of {}", i, self.len);
+        if self.validity.as_ref().is_some_and(|validity| !bit(validity, i)) {
+            return Value::Null;
+        }
+        match self.data_type {
+            DataType::Integer => Value::Int(i64::from_le_bytes(self.buffers[0][i * 8..i * 8 + 8].try_into().unwrap())),
+            DataType::Float => Value::Float(Float(f64::from_le_bytes(self.buffers[0][i * 8..i * 8 + 8].try_into().unwrap()))),
+            DataType::Boolean => Value::Bool(bit(&self.buffers[0], i)),
+            DataType::Text | DataType::Json | DataType::Array(_) | DataType::Point | DataType::Box | DataType::Numeric | DataType::Date | DataType::Timestamp => {
+                let offset = |i: usize| i32::from_le_bytes(self.buffers[0][i * 4..i * 4 + 4].try_into().unwrap()) as usize;
+                let bytes = &self.buffers[1][offset(i)..offset(i + 1)];
+                let text = String::from_utf8(bytes.to_vec()).expect("text is UTF-8");
+                match self.data_type {
+                    DataType::Json => Value::Json(json::parse(&text).expect("JSON was pushed as its text")),
+                    DataType::Array(element) => Value::Array(array::parse(&text, element).expect("arrays are pushed as their text")),
+                    DataType::Point => Value::Point(Point::parse(&text).expect("points are pushed as their text")),
+                    DataType::Box => Value::Box(Rect::parse(&text).expect("boxes are pushed as their text")),
+                    DataType::Numeric => Value::Numeric(Numeric::parse(&text).expect("numerics are pushed as their text")),
+                    DataType::Date => Value::Date(datetime::parse_date(&text).expect("dates are pushed as their text")),
+                    DataType::Timestamp => Value::Timestamp(datetime::parse_timestamp(&text).expect("timestamps are pushed as their text")),
+                    _ => Value::Text(text),
+                }
+            }
+        }
+    }
+}
+
+// Builds a batch a row at a time.
+pub struct RecordBatchBuilder {
+    fields: Vec<Field>,
+    columns: Vec<Array>,
+}
+
+impl RecordBatchBuilder {
+    pub fn new(fields: Vec<Field>) -> Self {
+        let columns = fields.iter().map(|field| empty_array(field.data_type)).collect();
+        Self { fields, columns }
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns.first().map_or(0, Array::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Whether the batch holds as many rows, or bytes, as it should.
+    pub fn is_full(&self) -> bool {
+        let bytes: usize = self.columns.iter().flat_map(|column| &column.buffers).map(Vec::len).sum();
+        self.len() >= BATCH_ROWS || bytes >= BATCH_BYTES
+    }
+
+    pub fn push(&mut self, row: Tuple) -> Result<(), Error> {
+        assert_eq!(self.fields.len(), row.len(), "a value for each column");
+        for (field, value) in self.fields.iter().zip(&row) {
+            if !field.data_type.accepts(value) {
+                return Err(Error::TypeMismatch { column: field.name.clone(), value: value.clone() });
+            }
+        }
+        for (column, value) in self.columns.iter_mut().zip(row) {
+            let i = column.len;
+            if value.is_null() && column.validity.is_none() {
+                // all of those before were valid
+                let mut validity = vec![0xff; i.div_ceil(8)];
+                if i % 8 != 0 {
+                    *validity.last_mut().unwrap() = (1 << (i % 8)) - 1;
+                }
+                column.validity = Some(validity);
+            }
+            if let Some(validity) = &mut column.validity {
+                push_bit(validity, i, !value.is_null());
+            }
+            column.null_count += value.is_null() as usize;
+            match value {
+                // a NULL has a value all the same, of zeroes, or no bytes of text
+                Value::Null => match column.data_type {
+                    DataType::Integer | DataType::Float => column.buffers[0].extend_from_slice(&[0; 8]),
+                    DataType::Boolean => push_bit(&mut column.buffers[0], i, false),
+                    DataType::Text | DataType::Json | DataType::Array(_) | DataType::Point | DataType::Box | DataType::Numeric | DataType::Date | DataType::Timestamp => {
+                        let end = column.buffers[1].len() as i32;
+                        column.buffers[0].extend_from_slice(&end.to_le_bytes());
+                    }
+                },
+                Value::Int(n) => column.buffers[0].extend_from_slice(&n.to_le_bytes()),
+                Value::Bool(b) => push_bit(&mut column.buffers[0], i, b),
+                Value::Text(s) => {
+                    column.buffers[1].extend_from_slice(s.as_bytes());
+                    let end = column.buffers[1].len() as i32;
+                    column.buffers[0].extend_from_slice(&end.to_le_bytes());
+                }
+                Value::Json(bytes) => {
+                    column.buffers[1].extend_from_slice(json::to_string(&bytes).as_bytes());
+                    let end = column.buffers[1].len() as i32;
+                    column.buffers[0].extend_from_slice(&end.to_le_bytes());
+                }
+                Value::Array(values) => {
+                    column.buffers[1].extend_from_slice(array::to_string(&values).as_bytes());
+                    let end = column.buffers[1].len() as i32;
+                    column.buffers[0].extend_from_slice(&end.to_le_bytes());
+                }
+                Value::Point(point) => {
+                    column.buffers[1].extend_from_slice(point.to_string().as_bytes());
+                    let end = column.buffers[1].len() as i32;
+                    column.buffers[0].extend_from_slice(&end.to_le_bytes());
+                }
+                Value::Box(rect) => {
+                    column.buffers[1].extend_from_slice(rect.to_string().as_bytes());
+                    let end = column.buffers[1].len() as i32;
+                    column.buffers[0].extend_from_slice(&end.to_le_bytes());
+                }
+                Value::Float(f) => column.buffers[0].extend_from_slice(&f.0.to_le_bytes()),
+                Value::Numeric(n) => {
+                    column.buffers[1].extend_from_slice(n.to_string().as_bytes());
+                    let end = column.buffers[1].len() as i32;
+                    column.buffers[0].extend_from_slice(&end.to_le_bytes());
+                }
+                Value::Date(days) => {
+                    column.buffers[1].extend_from_slice(datetime::format_date(days).as_bytes());
+                    let end = column.buffers[1].len() as i32;
+                    column.buffers[0].extend_from_slice(&end.to_le_bytes());
+                }
+                Value::Timestamp(micros) => {
+                    column.buffers[1].extend_from_slice(datetime::format_timestamp(micros).as_bytes());
+                    let end = column.buffers[1].len() as i32;
+                    column.buffers[0].extend_from_slice(&end.to_le_bytes());
+                }
+            }
+            column.len += 1;
+        }
+        Ok(())
+    }
+
+    // The batch of the rows pushed since the last one.
+    pub fn finish(&mut self) -> RecordBatch {
+        let columns = self.fields.iter().map(|field| empty_array(field.data_type)).collect();
+        RecordBatch {
+            fields: self.fields.clone(),
+            columns: std::mem::replace(&mut self.columns, columns),
+        }
+    }
+}
+
+fn empty_array(data_type: DataType) -> Array {
+    let buffers = match data_type {
+        // the offset the first value starts at
+        DataType::Text | DataType::Json | DataType::Array(_) | DataType::Point | DataType::Box | DataType::Numeric | DataType::Date | DataType::Timestamp => vec![0i32.to_le_bytes().to_vec(), vec![]],
+        DataType::Integer | DataType::Float | DataType::Boolean => vec![vec![]],
+    };
+    Array { data_type, len: 0, null_count: 0, validity: None, buffers }
+}
+
+fn bit(bitmap: &[u8], i: usize) -> bool {
+    bitmap[i / 8] >> (i % 8) & 1 == 1
+}
+
+fn push_bit(bitmap: &mut Vec<u8>, i: usize, set: bool) {
+    if i.is_multiple_of(8) {
+        bitmap.push(0);
+    }
+    if set {
+        *bitmap.last_mut().unwrap() |= 1 << (i % 8);
+    }
+}
+
+const CONTINUATION: [u8; 4] = [0xff; 4];
+
+// MetadataVersion V5
+const METADATA_VERSION: i16 = 4;
+
+// types of the MessageHeader union
+const SCHEMA: u8 = 1;
+const RECORD_BATCH: u8 = 3;
+
+// types of the Type union
+const INT: u8 = 2;
+const FLOATING_POINT: u8 = 3;
+const UTF8: u8 = 5;
+const BOOL: u8 = 6;
+
+// Precision of FloatingPoint
+const DOUBLE: i16 = 2;
+
+// Writes batches in the Arrow IPC stream format.
+pub struct StreamWriter<W> {
+    output: W,
+    fields: Vec<Field>,
+}
+
+impl<W: Write> StreamWriter<W> {
+    pub fn new(mut output: W, fields: Vec<Field>) -> Result<Self, Error> {
+        let fields_fb = fields
+            .iter()
+            .map(|field| {
+                let (type_type, type_table) = match field.data_type {
+                    DataType::Integer => (INT, Flatbuffer::Table(vec![(0, Slot::I32(64)), (1, Slot::Bool(true))])),
+                    DataType::Text | DataType::Json | DataType::Array(_) | DataType::Point | DataType::Box | DataType::Numeric | DataType::Date | DataType::Timestamp => (UTF8, Flatbuffer::Table(vec![])),
+                    DataType::Float => (FLOATING_POINT, Flatbuffer::Table(vec![(0, Slot::I16(DOUBLE))])),
+                    DataType::Boolean => (BOOL, Flatbuffer::Table(vec![])),
+                };
+                Flatbuffer::Table(vec![
+                    (0, Slot::Offset(Flatbuffer::String(field.name.clone()))),
+                    (1, Slot::Bool(true)),
+                    (2, Slot::U8(type_type)),
+                    (3, Slot::Offset(type_table)),
+                    (5, Slot::Offset(Flatbuffer::Tables(vec![]))),
+                ])
+            })
+            .collect();
+        let schema = Flatbuffer::Table(vec![(1, Slot::Offset(Flatbuffer::Tables(fields_fb)))]);
+        write_message(&mut output, SCHEMA, schema, &[])?;
+        Ok(Self { output, fields })
+    }
+
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<(), Error> {
+        assert_eq!(self.fields, batch.fields, "batches of the schema of the stream");
+        let (mut nodes, mut buffers, mut body) = (vec![], vec![], vec![]);
+        for column in &batch.columns {
+            nodes.extend_from_slice(&(column.len as i64).to_le_bytes());
+            nodes.extend_from_slice(&(column.null_count as i64).to_le_bytes());
+            let validity = column.validity.as_deref().unwrap_or(&[]);
+            for buffer in std::iter::once(validity).chain(column.buffers.iter().map(Vec::as_slice)) {
+                buffers.extend_from_slice(&(body.len() as i64).to_le_bytes());
+                buffers.extend_from_slice(&(buffer.len() as i64).to_le_bytes());
+                body.extend_from_slice(buffer);
+                // each aligned to 8 bytes
+                body.resize(body.len().next_multiple_of(8), 0);
+            }
+        }
+        let record_batch = Flatbuffer::Table(vec![
+            (0, Slot::I64(batch.num_rows() as i64)),
+            (1, Slot::Offset(Flatbuffer::Structs(batch.columns.len(), nodes))),
+            (2, Slot::Offset(Flatbuffer::Structs(buffers.len() / 16, buffers))),
+        ]);
+        write_message(&mut self.output, RECORD_BATCH, record_batch, &body)
+    }
+
+    // Ends the stream, returning the output.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.output.write_all(&CONTINUATION)?;
+        self.output.write_all(&0u32.to_le_bytes())?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+fn write_message(output: &mut impl Write, header_type: u8, header: Flatbuffer, body: &[u8]) -> Result<(), Error> {
+    let message = Flatbuffer::Table(vec![
+        (0, Slot::I16(METADATA_VERSION)),
+        (1, Slot::U8(header_type)),
+        (2, Slot::Offset(header)),
+        (3, Slot::I64(body.len() as i64)),
+    ]);
+    let mut metadata = message.finish();
+    // the body starts aligned to 8 bytes
+    metadata.resize(metadata.len().next_multiple_of(8), 0);
+    output.write_all(&CONTINUATION)?;
+    output.write_all(&(metadata.len() as u32).to_le_bytes())?;
+    output.write_all(&metadata)?;
+    output.write_all(body)?;
+    Ok(())
+}
+
+// What a flatbuffer is made of. A table is the i32 offset back to its vtable, then its fields; a
+// vtable the u16 size of itself and of the table, then the u16 offset in the table of each field,
+// by index, 0 for one left out. Tables refer to strings, vectors and other tables by their u32
+// offset forward from where the offset is, the root table from the start of the buffer. Vectors
+// are their u32 length then their elements, offsets to tables or structs inline, and strings
+// their length, bytes and a 0. Everything is aligned to its size.
+enum Flatbuffer {
+    Table(Vec<(u16, Slot)>),
+    String(String),
+    Tables(Vec<Flatbuffer>),
+    // structs of 8-byte fields, of their number and bytes
+    Structs(usize, Vec<u8>),
+}
+
+enum Slot {
+    Bool(bool),
+    U8(u8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Offset(Flatbuffer),
+}
+
+impl Flatbuffer {
+    // The buffer of which this is the root table.
+    fn finish(&self) -> Vec<u8> {
+        let mut buf = vec![0; 4];
+        let root = self.write(&mut buf);
+        buf[..4].copy_from_slice(&(root as u32).to_le_bytes());
+        buf
+    }
+
+    // Writes this at the end of `buf`, what it refers to after it, returning where it is.
+    fn write(&self, buf: &mut Vec<u8>) -> usize {
+        match self {
+            Flatbuffer::Table(slots) => {
+                let num_slots = slots.iter().map(|(index, _)| *index as usize + 1).max().unwrap_or(0);
+                let vtable = align(buf, 2);
+                buf.resize(vtable + 4 + 2 * num_slots, 0);
+                let table = align(buf, 4);
+                buf.extend_from_slice(&((table - vtable) as i32).to_le_bytes());
+                let mut offsets = vec![];
+                for (index, slot) in slots {
+                    let (size, bytes) = match slot {
+                        Slot::Bool(b) => (1, vec![*b as u8]),
+                        Slot::U8(n) => (1, vec![*n]),
+                        Slot::I16(n) => (2, n.to_le_bytes().to_vec()),
+                        Slot::I32(n) => (4, n.to_le_bytes().to_vec()),
+                        Slot::I64(n) => (8, n.to_le_bytes().to_vec()),
+                        Slot::Offset(_) => (4, vec![0; 4]),
+                    };
+                    let at = align(buf, size);
+                    buf.extend_from_slice(&bytes);
+                    set_u16(buf, vtable + 4 + 2 * *index as usize, (at - table) as u16);
+                    offsets.push(at);
+                }
+                set_u16(buf, vtable, (4 + 2 * num_slots) as u16);
+                let table_len = buf.len() - table;
+                set_u16(buf, vtable + 2, table_len as u16);
+                for ((_, slot), at) in slots.iter().zip(offsets) {
+                    if let Slot::Offset(target) = slot {
+                        let target = target.write(buf);
+                        buf[at..at + 4].copy_from_slice(&((target - at) as u32).to_le_bytes());
+                    }
+                }
+                table
+            }
+            Flatbuffer::String(s) => {
+                let at = align(buf, 4);
+                buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                buf.extend_from_slice(s.as_bytes());
+                buf.push(0);
+                at
+            }
+            Flatbuffer::Tables(tables) => {
+                let at = align(buf, 4);
+                buf.extend_from_slice(&(tables.len() as u32).to_le_bytes());
+                buf.resize(at + 4 + 4 * tables.len(), 0);
+                for (i, table) in tables.iter().enumerate() {
+                    let element = at + 4 + 4 * i;
+                    let target = table.write(buf);
+                    buf[element..element + 4].copy_from_slice(&((target - element) as u32).to_le_bytes());
+                }
+                at
+            }
+            Flatbuffer::Structs(len, bytes) => {
+                // the structs aligned to 8, just after the length
+                while !(buf.len() + 4).is_multiple_of(8) {
+                    buf.push(0);
+                }
+                let at = buf.len();
+                buf.extend_from_slice(&(*len as u32).to_le_bytes());
+                buf.extend_from_slice(bytes);
+                at
+            }
+        }
+    }
+}
+
+fn align(buf: &mut Vec<u8>, size: usize) -> usize {
+    buf.resize(buf.len().next_multiple_of(size), 0);
+    buf.len()
+}
+
+fn set_u16(buf: &mut [u8], at: usize, n: u16) {
+    buf[at..at + 2].copy_from_slice(&n.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reads the flatbuffers written, the fields of a table by index.
+    struct Table<'a> {
+        buf: &'a [u8],
+        at: usize,
+    }
+
+    impl<'a> Table<'a> {
+        fn root(buf: &'a [u8]) -> Self {
+            Self { buf, at: u32_at(buf, 0) }
+        }
+
+        fn field(&self, index: usize) -> Option<usize> {
+            let vtable = self.at - i32::from_le_bytes(self.buf[self.at..self.at + 4].try_into().unwrap()) as usize;
+            let vtable_len = u16::from_le_bytes(self.buf[vtable..vtable + 2].try_into().unwrap()) as usize;
+            if 4 + 2 * index >= vtable_len {
+                return None;
+            }
+            let offset = u16::from_le_bytes(self.buf[vtable + 4 + 2 * index..vtable + 6 + 2 * index].try_into().unwrap()) as usize;
+            (offset != 0).then_some(self.at + offset)
+        }
+
+        fn scalar(&self, index: usize, size: usize) -> i64 {
+            let at = self.field(index).unwrap();
+            assert_eq!(0, at % size, "aligned");
+            let mut bytes = [0; 8];
+            bytes[..size].copy_from_slice(&self.buf[at..at + size]);
+            i64::from_le_bytes(bytes)
+        }
+
+        fn target(&self, index: usize) -> usize {
+            let at = self.field(index).unwrap();
+            at + u32_at(self.buf, at)
+        }
+
+        fn table(&self, index: usize) -> Table<'a> {
+            Table { buf: self.buf, at: self.target(index) }
+        }
+
+        fn tables(&self, index: usize) -> Vec<Table<'a>> {
+            let at = self.target(index);
+            (0..u32_at(self.buf, at)).map(|i| at + 4 + 4 * i).map(|element| Table { buf: self.buf, at: element + u32_at(self.buf, element) }).collect()
+        }
+
+        fn string(&self, index: usize) -> &'a str {
+            let at = self.target(index);
+            std::str::from_utf8(&self.buf[at + 4..at + 4 + u32_at(self.buf, at)]).unwrap()
+        }
+
+        // the 8-byte fields of a vector of structs
+        fn structs(&self, index: usize, fields: usize) -> Vec<Vec<i64>> {
+            let at = self.target(index);
+            assert_eq!(0, (at + 4) % 8, "aligned");
+            let longs: Vec<i64> = self.buf[at + 4..at + 4 + 8 * fields * u32_at(self.buf, at)]
+                .chunks(8)
+                .map(|b| i64::from_le_bytes(b.try_into().unwrap()))
+                .collect();
+            longs.chunks(fields).map(<[i64]>::to_vec).collect()
+        }
+    }
+
+    fn u32_at(buf: &[u8], at: usize) -> usize {
+        u32::from_le_bytes(buf[at..at + 4].try_into().unwrap()) as usize
+    }
+
+    // The metadata and body of each message of a stream.
+    fn messages(mut stream: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut messages = vec![];
+        loop {
+            assert_eq!(CONTINUATION, stream[..4]);
+            let len = u32_at(stream, 4);
+            if len == 0 {
+                assert_eq!(8, stream.len());
+                return messages;
+            }
+            let metadata = stream[8..8 + len].to_vec();
+            let body_len = Table::root(&metadata).scalar(3, 8) as usize;
+            messages.push((metadata, stream[8 + len..8 + len + body_len].to_vec()));
+            stream = &stream[8 + len + body_len..];
+        }
+    }
+
+    #[test]
+    fn test() {
+        let fields = vec![
+            Field { name: "id".to_string(), data_type: DataType::Integer },
+            Field { name: "name".to_string(), data_type: DataType::Text },
+            Field { name: "ok".to_string(), data_type: DataType::Boolean },
+        ];
+        let rows: Vec<Tuple> = (0..10)
+            .map(|i| {
+                vec![
+                    Value::Int(i * 10),
+                    if i == 9 { Value::Null } else { Value::Text("x".repeat(i as usize)) },
+                    if i % 4 == 1 { Value::Null } else { Value::Bool(i % 2 == 0) },
+                ]
+            })
+            .collect();
+        let mut builder = RecordBatchBuilder::new(fields.clone());
+        for row in &rows {
+            builder.push(row.clone()).unwrap();
+        }
+        assert!(matches!(builder.push(vec![Value::Bool(true), Value::Null, Value::Null]), Err(Error::TypeMismatch { .. })));
+        let batch = builder.finish();
+        assert!(builder.is_empty());
+        assert_eq!(10, batch.num_rows());
+        assert_eq!(rows, (0..10).map(|i| batch.row(i)).collect::<Vec<_>>());
+
+        // laid out as Arrow has them
+        let (id, name, ok) = (&batch.columns[0], &batch.columns[1], &batch.columns[2]);
+        assert_eq!((None, 0), (id.validity(), id.null_count()));
+        assert_eq!(80, id.buffers()[0].len());
+        assert_eq!(Some(&[0xff, 0b01][..]), name.validity());
+        let offsets: Vec<i32> = name.buffers()[0].chunks(4).map(|b| i32::from_le_bytes(b.try_into().unwrap())).collect();
+        assert_eq!(vec![0, 0, 1, 3, 6, 10, 15, 21, 28, 36, 36], offsets);
+        assert_eq!(Some(&[0b1101_1101, 0b01][..]), ok.validity());
+        assert_eq!((3, vec![vec![0b0101_0101, 0b01]]), (ok.null_count(), ok.buffers().to_vec()));
+
+        // and streamed so
+        let mut writer = StreamWriter::new(vec![], fields).unwrap();
+        writer.write(&batch).unwrap();
+        let stream = writer.finish().unwrap();
+        let messages = messages(&stream);
+        assert_eq!(2, messages.len());
+        let message = Table::root(&messages[0].0);
+        assert_eq!((METADATA_VERSION as i64, SCHEMA as i64), (message.scalar(0, 2), message.scalar(1, 1)));
+        let schema_fields = message.table(2).tables(1);
+        let field = |i: usize| (schema_fields[i].string(0).to_string(), schema_fields[i].scalar(1, 1), schema_fields[i].scalar(2, 1));
+        assert_eq!(vec![("id".to_string(), 1, INT as i64), ("name".to_string(), 1, UTF8 as i64), ("ok".to_string(), 1, BOOL as i64)], (0..3).map(field).collect::<Vec<_>>());
+        assert_eq!((64, 1), (schema_fields[0].table(3).scalar(0, 4), schema_fields[0].table(3).scalar(1, 1)));
+        assert!(schema_fields.iter().all(|field| field.tables(5).is_empty()));
+
+        let (metadata, body) = &messages[1];
+        let message = Table::root(metadata);
+        assert_eq!(RECORD_BATCH as i64, message.scalar(1, 1));
+        let record_batch = message.table(2);
+        assert_eq!(10, record_batch.scalar(0, 8));
+        assert_eq!(vec![vec![10, 0], vec![10, 1], vec![10, 3]], record_batch.structs(1, 2));
+        let buffers = record_batch.structs(2, 2);
+        assert_eq!(vec![vec![0, 0], vec![0, 80], vec![80, 2], vec![88, 44], vec![136, 36], vec![176, 2], vec![184, 2]], buffers);
+        assert_eq!(192, body.len());
+        assert_eq!(&name.buffers()[1][..], &body[136..172]);
+    }
+}
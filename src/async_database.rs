@@ -0,0 +1,289 @@
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::thread::{self, JoinHandle};
+
+use tokio::runtime;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{self, LocalSet};
+
+use crate::buffer::CancelToken;
+use crate::database::{self, Database, Session};
+use crate::sql::{self, PreparedStatement, QueryResult};
+use crate::tuple::Value;
+
+// An async mode of the database for Tokio applications, behind the tokio feature: the database
+// runs on a thread of its own, with a runtime of that thread, and is used through handles which
+// can be sent to and shared by the tasks of any runtime, awaiting a statement blocking none of
+// its threads.
+//
+//   let db = AsyncDatabase::open("db", 256).await?;
+//   let session = db.session().await?;
+//   session.execute("INSERT INTO t VALUES ($1, $2)", vec![Value::Int(1), Value::Text("a".into())]).await?;
+//
+// The engine being of one thread, each session is a task of the database's thread, and the
+// sessions take turns with it: a SELECT is run as a portal (see `Session::execute_portal`), its
+// rows fetched a batch at a time, the task yielding to the others between batches, as it does
+// between statements. A long scan of one session doesn't hold up the short statements of the
+// others, only the statements other than SELECTs running to the end once begun. The pages of
+// the data file and the log are read and written synchronously by the database's thread, which
+// is the only one the engine blocks, however many statements are running.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Database(#[from] database::Error),
+    #[error(transparent)]
+    Sql(#[from] sql::Error),
+    #[error("the database has been closed")]
+    Closed,
+}
+
+// Rows of a SELECT fetched at a turn of its session.
+pub const BATCH_ROWS: u64 = 1000;
+
+// Name of the portal the SELECTs of a session are run as.
+const PORTAL: &str = "";
+
+enum Request {
+    Session(oneshot::Sender<AsyncSession>),
+    Close(oneshot::Sender<Result<(), database::Error>>),
+}
+
+enum Command {
+    Execute { sql: String, params: Vec<Value>, reply: oneshot::Sender<Result<Vec<QueryResult>, sql::Error>> },
+    SetUser(Option<String>),
+}
+
+pub struct AsyncDatabase {
+    requests: mpsc::UnboundedSender<Request>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AsyncDatabase {
+    // Opens the database in `dir` as `Database::open` does, on a thread of its own.
+    pub async fn open(dir: impl Into<PathBuf>, pool_size: usize) -> Result<Self, Error> {
+        let dir = dir.into();
+        Self::start(move || Database::open(dir, pool_size)).await
+    }
+
+    // Starts the thread of the database which `open` opens on it, as configured say.
+    pub async fn start(open: impl FnOnce() -> Result<Database, database::Error> + Send + 'static) -> Result<Self, Error> {
+        let (requests, receiver) = mpsc::unbounded_channel();
+        let (opened, result) = oneshot::channel();
+        let thread = thread::spawn(move || {
+            let db = match open() {
+                Ok(db) => db,
+                Err(err) => {
+                    let _ = opened.send(Err(err));
+                    return;
+                }
+            };
+            let _ = opened.send(Ok(()));
+            serve(db, receiver);
+        });
+        match result.await {
+            Ok(Ok(())) => Ok(Self { requests, thread: Some(thread) }),
+            Ok(Err(err)) => Err(err.into()),
+            Err(_) => Err(Error::Closed),
+        }
+    }
+
+    // Starts a session, as `Database::session` does.
+    pub async fn session(&self) -> Result<AsyncSession, Error> {
+        let (reply, session) = oneshot::channel();
+        self.requests.send(Request::Session(reply)).map_err(|_| Error::Closed)?;
+        session.await.map_err(|_| Error::Closed)
+    }
+
+    // Ends the sessions, rolling back their transactions, then closes the database as
+    // `Database::close` does.
+    pub async fn close(mut self) -> Result<(), Error> {
+        let (reply, closed) = oneshot::channel();
+        self.requests.send(Request::Close(reply)).map_err(|_| Error::Closed)?;
+        let result = closed.await.map_err(|_| Error::Closed)?;
+        if let Some(thread) = self.thread.take() {
+            // it's done once it's replied
+            let _ = thread.join();
+        }
+        Ok(result?)
+    }
+}
+
+impl Drop for AsyncDatabase {
+    fn drop(&mut self) {
+        // the thread ends with the sessions once the requests stop, without waiting for it
+        let _ = self.requests.send(Request::Close(oneshot::channel().0));
+    }
+}
+
+// A session of an `AsyncDatabase`, ended when dropped.
+#[derive(Clone)]
+pub struct AsyncSession {
+    id: u64,
+    cancel: CancelToken,
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl AsyncSession {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    // Runs every statement in `sql`, returning one result per statement. `params` are bound to
+    // the $1, $2, ... of the statement if there's one, and must be empty if there are more.
+    pub async fn execute(&self, sql: &str, params: Vec<Value>) -> Result<Vec<QueryResult>, Error> {
+        let (reply, result) = oneshot::channel();
+        self.commands.send(Command::Execute { sql: sql.to_string(), params, reply }).map_err(|_| Error::Closed)?;
+        Ok(result.await.map_err(|_| Error::Closed)??)
+    }
+
+    // Runs the statements from now on with the privileges of `user`, as `Session::set_user`.
+    pub fn set_user(&self, user: Option<&str>) -> Result<(), Error> {
+        self.commands.send(Command::SetUser(user.map(str::to_string))).map_err(|_| Error::Closed)
+    }
+
+    // Cancels the statement the session is running, if any.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+}
+
+// Runs the database until it's closed, with a task for each session.
+fn serve(db: Database, mut requests: mpsc::UnboundedReceiver<Request>) {
+    let runtime = runtime::Builder::new_current_thread().build().expect("a runtime of the current thread");
+    let local = LocalSet::new();
+    let closed = local.block_on(&runtime, async {
+        while let Some(request) = requests.recv().await {
+            match request {
+                Request::Session(reply) => {
+                    let session = db.session();
+                    let (commands, receiver) = mpsc::unbounded_channel();
+                    let handle = AsyncSession { id: session.id(), cancel: session.cancel_token(), commands };
+                    if reply.send(handle).is_ok() {
+                        task::spawn_local(run_session(session, receiver));
+                    }
+                }
+                Request::Close(reply) => return Some(reply),
+            }
+        }
+        None
+    });
+    // the tasks of the sessions dropped, with them
+    drop(local);
+    let result = db.close();
+    if let Some(reply) = closed {
+        let _ = reply.send(result);
+    }
+}
+
+// Runs the commands of a session until its handles are dropped.
+async fn run_session(mut session: Session, mut commands: mpsc::UnboundedReceiver<Command>) {
+    while let Some(command) = commands.recv().await {
+        match command {
+            Command::Execute { sql, params, reply } => {
+                let result = execute(&mut session, &sql, &params).await;
+                let _ = reply.send(result);
+            }
+            Command::SetUser(user) => session.set_user(user.as_deref()),
+        }
+    }
+}
+
+async fn execute(session: &mut Session, sql: &str, params: &[Value]) -> Result<Vec<QueryResult>, sql::Error> {
+    let statements = session.parse(sql)?;
+    if statements.len() > 1 && !params.is_empty() {
+        return Err(sql::Error::Invalid("parameters can only be given to a single statement".to_string()));
+    }
+    let mut results = vec![];
+    for (statement, text) in &statements {
+        let statement = Rc::new(session.prepare_statement(statement)?.with_sql(text));
+        let result = match statement.command() {
+            "SELECT" => select(session, &statement, params).await?,
+            _ => session.execute_prepared(&statement, params)?,
+        };
+        results.push(result);
+        task::yield_now().await;
+    }
+    Ok(results)
+}
+
+// Runs a SELECT a batch of rows at a turn, in a transaction of its own if none is running.
+async fn select(session: &mut Session, statement: &Rc<PreparedStatement>, params: &[Value]) -> Result<QueryResult, sql::Error> {
+    let mut rows = vec![];
+    let result = loop {
+        match session.execute_portal(PORTAL, statement, params, Some(BATCH_ROWS)) {
+            Ok((QueryResult::Rows { columns, rows: batch }, suspended)) => {
+                rows.extend(batch);
+                if !suspended {
+                    break Ok(QueryResult::Rows { columns, rows });
+                }
+            }
+            result => break result.map(|(result, _)| result),
+        }
+        task::yield_now().await;
+    };
+    // ending the transaction begun for the portal, if it was, rolled back if the SELECT failed
+    let synced = session.sync();
+    let result = result?;
+    synced?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        let dir = tempdir().unwrap();
+        let runtime = runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let db = AsyncDatabase::open(dir.path(), 64).await.unwrap();
+            let session = db.session().await.unwrap();
+            let values: Vec<String> = (0..20 * BATCH_ROWS).map(|id| format!("({})", id)).collect();
+            let sql = format!("CREATE TABLE t (id INTEGER PRIMARY KEY); CREATE TABLE u (id INTEGER PRIMARY KEY); INSERT INTO t VALUES {}", values.join(", "));
+            session.execute(&sql, vec![]).await.unwrap();
+            let count = |results: Vec<QueryResult>| match &results[..] {
+                [QueryResult::Rows { rows, .. }] => rows.len(),
+                results => panic!("{:?}", results),
+            };
+            assert_eq!(1, count(session.execute("SELECT id FROM t WHERE id = $1", vec![Value::Int(7)]).await.unwrap()));
+
+            // a long scan lets the statements of the other sessions run while it goes on
+            let other = db.session().await.unwrap();
+            let finished = Arc::new(Mutex::new(vec![]));
+            let scan = {
+                let (session, finished) = (session.clone(), finished.clone());
+                tokio::spawn(async move {
+                    let rows = count(session.execute("SELECT * FROM t", vec![]).await.unwrap());
+                    finished.lock().unwrap().push("scan");
+                    rows
+                })
+            };
+            // sent just after it
+            task::yield_now().await;
+            other.execute("INSERT INTO u VALUES (1)", vec![]).await.unwrap();
+            finished.lock().unwrap().push("insert");
+            assert_eq!(20 * BATCH_ROWS as usize, scan.await.unwrap());
+            assert_eq!(vec!["insert", "scan"], *finished.lock().unwrap());
+            other.execute("INSERT INTO t VALUES (-1)", vec![]).await.unwrap();
+
+            // or in the transaction block running, which fails as any other on an error
+            other.execute("BEGIN; INSERT INTO t VALUES (-2)", vec![]).await.unwrap();
+            assert_eq!(2, count(other.execute("SELECT * FROM t WHERE id < 0", vec![]).await.unwrap()));
+            assert!(matches!(other.execute("SELECT nope FROM t", vec![]).await, Err(Error::Sql(sql::Error::UnknownColumn(_)))));
+            assert!(matches!(other.execute("COMMIT", vec![]).await.unwrap()[..], [QueryResult::RolledBack]));
+            assert!(matches!(session.execute("SELECT 1; SELECT 2", vec![Value::Int(1)]).await, Err(Error::Sql(sql::Error::Invalid(_)))));
+
+            // the sessions ended, their transactions rolled back, when the database is closed
+            other.execute("BEGIN; INSERT INTO t VALUES (-3)", vec![]).await.unwrap();
+            db.close().await.unwrap();
+            assert!(matches!(session.execute("SELECT 1", vec![]).await, Err(Error::Closed)));
+            let db = AsyncDatabase::open(dir.path(), 64).await.unwrap();
+            let session = db.session().await.unwrap();
+            assert_eq!(1, count(session.execute("SELECT * FROM t WHERE id < 0", vec![]).await.unwrap()));
+        });
+    }
+}
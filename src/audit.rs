@@ -0,0 +1,223 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::sql::{self, PreparedStatement, QueryResult, StatementClass};
+
+// Every DDL and DML statement the sessions run, failed ones included, with when it ran, the
+// session and user it was run by, the tables it was about and the rows it changed, appended to
+// a file or passed to a callback, as regulated environments require of what's done to their
+// data (see `Database::set_audit_log`). Statements which fail before they're prepared, e.g.
+// those of tables not found, are of no class and not logged.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub time: SystemTime,
+    pub session: u64,
+    // None for a superuser's session, as `Session::user`
+    pub user: Option<String>,
+    pub class: StatementClass,
+    // e.g. INSERT or CREATE TABLE
+    pub command: &'static str,
+    // written or defined first, then read (see `PreparedStatement::tables`)
+    pub tables: Vec<String>,
+    // changed by DML
+    pub rows: usize,
+    pub sql: String,
+    pub error: Option<String>,
+}
+
+// One line a statement, the time being in UTC:
+//   2026-10-14T09:30:00.123Z session: 3 user: alice class: DML command: INSERT tables: t rows: 2 statement: INSERT ...
+impl fmt::Display for AuditRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let class = match self.class {
+            StatementClass::Ddl => "DDL",
+            StatementClass::Dml => "DML",
+        };
+        let tables = if self.tables.is_empty() { "-".to_string() } else { self.tables.join(",") };
+        write!(
+            f,
+            "{} session: {} user: {} class: {} command: {} tables: {} rows: {}",
+            timestamp(self.time),
+            self.session,
+            self.user.as_deref().unwrap_or("-"),
+            class,
+            self.command,
+            tables,
+            self.rows
+        )?;
+        if let Some(error) = &self.error {
+            write!(f, " error: {}", error)?;
+        }
+        // a statement spanning lines is kept to one
+        write!(f, " statement: {}", self.sql.split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+}
+
+// RFC 3339, to the millisecond.
+fn timestamp(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (days, secs) = ((since.as_secs() / 86400) as i64, since.as_secs() % 86400);
+    // the civil date of the days since the epoch
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        since.subsec_millis()
+    )
+}
+
+pub struct AuditLog {
+    sink: Sink,
+}
+
+enum Sink {
+    File(File),
+    Callback(Box<dyn FnMut(&AuditRecord)>),
+}
+
+impl AuditLog {
+    // Appends the statements to the file at `path`, created if there's none, a line each and
+    // written at once, so that lines of sessions aren't interleaved.
+    pub fn to_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { sink: Sink::File(file) })
+    }
+
+    pub fn with_callback(f: impl FnMut(&AuditRecord) + 'static) -> Self {
+        Self { sink: Sink::Callback(Box::new(f)) }
+    }
+
+    // Logs `statement`, parsed from `sql`, if it's DDL or DML.
+    pub fn log_statement(&mut self, session: u64, user: Option<&str>, sql: &str, statement: &PreparedStatement, result: &Result<QueryResult, sql::Error>) {
+        let Some(class) = statement.class() else {
+            return;
+        };
+        self.log(&AuditRecord {
+            time: SystemTime::now(),
+            session,
+            user: user.map(str::to_string),
+            class,
+            command: statement.command(),
+            tables: statement.tables().into_iter().map(str::to_string).collect(),
+            rows: result.as_ref().map_or(0, QueryResult::num_rows),
+            sql: sql.to_string(),
+            error: result.as_ref().err().map(|err| err.to_string()),
+        });
+    }
+
+    pub fn log(&mut self, record: &AuditRecord) {
+        match &mut self.sink {
+            Sink::File(file) => {
+                // the statement has been run by now, so there's no failing it
+                let _ = file.write_all(format!("{}\n", record).as_bytes());
+            }
+            Sink::Callback(f) => f(record),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::tuple::Value;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::rc::Rc;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        assert_eq!("1970-01-01T00:00:00.000Z", timestamp(UNIX_EPOCH));
+        assert_eq!("2024-02-29T23:59:59.250Z", timestamp(UNIX_EPOCH + Duration::from_millis(1709251199250)));
+
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path(), 16).unwrap();
+        let logged = Rc::new(RefCell::new(vec![]));
+        let sink = logged.clone();
+        db.set_audit_log(Some(AuditLog::with_callback(move |record| sink.borrow_mut().push(record.clone()))));
+
+        let mut session = db.session();
+        session
+            .execute(
+                "CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT); CREATE TABLE u (id INTEGER PRIMARY KEY);
+                 INSERT INTO t VALUES (1, 'a'), (2, 'b');\n  INSERT INTO u VALUES ((SELECT count(*) FROM t)); SELECT * FROM t;
+                 CREATE INDEX t_name ON t (name); CREATE USER alice; GRANT SELECT ON t TO alice",
+            )
+            .unwrap();
+        let mut alice = db.session();
+        alice.set_user(Some("alice"));
+        alice.execute("SELECT * FROM t").unwrap();
+        assert!(alice.execute("INSERT INTO t VALUES (3, 'c')").is_err());
+        let insert = session.prepare("INSERT INTO u VALUES (?)").unwrap();
+        session.execute_prepared(&insert, &[Value::Int(3)]).unwrap();
+        session.load("u", || Ok(None), None).unwrap();
+        assert!(session.execute("INSERT INTO nothing VALUES (1)").is_err());
+        {
+            let logged = logged.borrow();
+            let summary: Vec<_> = logged
+                .iter()
+                .map(|record| (record.user.as_deref(), record.class, record.command, record.tables.iter().map(String::as_str).collect::<Vec<_>>(), record.rows))
+                .collect();
+            assert_eq!(
+                vec![
+                    (None, StatementClass::Ddl, "CREATE TABLE", vec!["t"], 0),
+                    (None, StatementClass::Ddl, "CREATE TABLE", vec!["u"], 0),
+                    (None, StatementClass::Dml, "INSERT", vec!["t"], 2),
+                    (None, StatementClass::Dml, "INSERT", vec!["u", "t"], 1),
+                    (None, StatementClass::Ddl, "CREATE INDEX", vec!["t"], 0),
+                    (None, StatementClass::Ddl, "CREATE USER", vec![], 0),
+                    (None, StatementClass::Ddl, "GRANT", vec!["t"], 0),
+                    (Some("alice"), StatementClass::Dml, "INSERT", vec!["t"], 0),
+                    (None, StatementClass::Dml, "INSERT", vec!["u"], 1),
+                    (None, StatementClass::Dml, "COPY", vec!["u"], 0),
+                ],
+                summary
+            );
+            assert!(logged.iter().all(|record| record.session == [session.id(), alice.id()][record.user.is_some() as usize]));
+            assert_eq!(Some("permission denied: INSERT on t"), logged[7].error.as_deref());
+            assert!(logged[3].error.is_none() && logged[3].time <= SystemTime::now());
+            let line = logged[3].to_string();
+            assert!(line.contains(" user: - class: DML command: INSERT tables: u,t rows: 1 statement: INSERT INTO u VALUES ((SELECT count(*) FROM t))"), "{}", line);
+        }
+
+        // appended to the file, which is kept
+        let path = dir.path().join("audit.log");
+        fs::write(&path, "before\n").unwrap();
+        db.set_audit_log(Some(AuditLog::to_file(&path).unwrap()));
+        session.execute("INSERT INTO u VALUES (4); SELECT * FROM u; CREATE VIEW v AS SELECT id FROM u").unwrap();
+        db.set_audit_log(None);
+        session.execute("INSERT INTO u VALUES (5)").unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(3, lines.len(), "{}", text);
+        assert!(lines[1].ends_with("command: INSERT tables: u rows: 1 statement: INSERT INTO u VALUES (4)"), "{}", lines[1]);
+        assert!(lines[2].contains("class: DDL command: CREATE VIEW tables: v "), "{}", lines[2]);
+
+        // logged to the file configured
+        drop((session, alice));
+        db.close().unwrap();
+        let config = crate::config::Config { audit_log: Some("audit.log".into()), ..Default::default() };
+        let db = Database::builder().config(config).open(dir.path()).unwrap();
+        db.session().execute("INSERT INTO u VALUES (6)").unwrap();
+        assert!(fs::read_to_string(&path).unwrap().lines().last().unwrap().ends_with("INSERT INTO u VALUES (6)"));
+    }
+}
@@ -0,0 +1,689 @@
+use std::fmt;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::buffer::Durability;
+use crate::database::{Database, Session};
+use crate::server::{self, Server};
+use crate::sim::Rng;
+use crate::sql::{self, PreparedStatement};
+use crate::tuple::Value;
+use crate::worker;
+
+// Workloads after YCSB and TPC-B, run against a database to report the throughput and the
+// percentiles of the latency of their operations. Rows are updated by UPDATEs of their keys:
+// YCSB's records given a new field, and TPC-B's balances added the delta of the transaction, as
+// pgbench's are. Keys are chosen uniformly.
+//
+// The clients of a thread take turns, a statement at a time, and an operation's latency includes
+// waiting for its turns. On one thread, they're sessions of the database, as those of a server
+// are. The engine being of one thread, on more they're connections to a server of the database
+// run in the calling thread, each thread its own clients, and the throughput is the sum of the
+// threads'. Transactions of the clients overlapping as they do, TPC-B's updating a row another
+// has updated fail, to be rolled back and retried from the start, as pgbench does with
+// --max-tries.
+
+// Bytes of the field of a YCSB record.
+const FIELD_LEN: usize = 100;
+// Keys a YCSB scan reads at most.
+const MAX_SCAN_LEN: u64 = 100;
+// TPC-B's accounts and tellers per branch.
+const ACCOUNTS_PER_BRANCH: usize = 100_000;
+const TELLERS_PER_BRANCH: usize = 10;
+// TPC-B operations after which its branches and tellers are vacuumed. Their rows are updated by
+// most of the transactions, and the versions of a row are only pruned by VACUUM, so they'd pile up
+// into rows too large for a page otherwise.
+const VACUUM_EVERY: usize = 10;
+// The user the clients of a server start up as.
+const USER: &str = "bench";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Sql(#[from] sql::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Worker(#[from] worker::Error),
+    // what the server answered a client's statement with
+    #[error("{message} (SQLSTATE {code})")]
+    Server { code: String, message: String },
+}
+
+impl Error {
+    // The SQLSTATE of the error the database failed a statement with, None if it's the client's.
+    fn sqlstate(&self) -> Option<&str> {
+        match self {
+            Error::Sql(err) => Some(server::sqlstate(err)),
+            Error::Server { code, .. } => Some(code),
+            Error::Io(_) | Error::Worker(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Workload {
+    // half reads, half updates
+    YcsbA,
+    // 95% reads, the rest updates
+    YcsbB,
+    // reads only
+    YcsbC,
+    // 95% scans of a range of keys, the rest inserts of new keys
+    YcsbE,
+    // transactions adding a delta to the balances of an account, a teller and a branch, reading
+    // that of the account, and inserting a history row
+    TpcB,
+}
+
+impl Workload {
+    pub const ALL: [Workload; 5] = [Workload::YcsbA, Workload::YcsbB, Workload::YcsbC, Workload::YcsbE, Workload::TpcB];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Workload::YcsbA => "ycsb-a",
+            Workload::YcsbB => "ycsb-b",
+            Workload::YcsbC => "ycsb-c",
+            Workload::YcsbE => "ycsb-e",
+            Workload::TpcB => "tpcb",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|workload| workload.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub workload: Workload,
+    // rows loaded before the operations: YCSB's records, or TPC-B's accounts
+    pub records: usize,
+    pub operations: usize,
+    // of each thread, taking turns in it
+    pub clients: usize,
+    // running the clients, those of more than one connecting to a server of the database
+    pub threads: usize,
+    // of the commits of the clients
+    pub durability: Durability,
+    pub seed: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self { workload: Workload::YcsbA, records: 10_000, operations: 10_000, clients: 1, threads: 1, durability: Durability::Sync, seed: 1 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub workload: Workload,
+    // with those which failed, which aren't retried unless on a conflict
+    pub operations: usize,
+    pub failed: usize,
+    // of the operations conflicting with those of other clients (SQLSTATE class 40)
+    pub retries: usize,
+    // of the longest running thread
+    pub elapsed: Duration,
+    // of each operation, shortest first
+    pub latencies: Vec<Duration>,
+    // the operations of each thread, and how long it ran them in
+    pub threads: Vec<(usize, Duration)>,
+}
+
+impl Report {
+    fn new(workload: Workload) -> Self {
+        Self { workload, operations: 0, failed: 0, retries: 0, elapsed: Duration::ZERO, latencies: vec![], threads: vec![] }
+    }
+
+    // Operations per second, summed across the threads.
+    pub fn throughput(&self) -> f64 {
+        self.threads.iter().map(|(operations, elapsed)| *operations as f64 / elapsed.as_secs_f64().max(f64::MIN_POSITIVE)).sum()
+    }
+
+    // The latency `percent`% of the operations took at most.
+    pub fn percentile(&self, percent: f64) -> Duration {
+        let rank = (self.latencies.len() as f64 * percent / 100.0).ceil() as usize;
+        self.latencies.get(rank.clamp(1, self.latencies.len().max(1)) - 1).copied().unwrap_or_default()
+    }
+
+    // Adds what the operations of another thread came to.
+    fn merge(&mut self, other: Report) {
+        self.operations += other.operations;
+        self.failed += other.failed;
+        self.retries += other.retries;
+        self.elapsed = self.elapsed.max(other.elapsed);
+        self.latencies.extend(other.latencies);
+        self.latencies.sort_unstable();
+        self.threads.extend(other.threads);
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let threads = if self.threads.len() == 1 { "thread" } else { "threads" };
+        write!(
+            f,
+            "{}: {} operations ({} failed, {} retries) in {:.3}s, {:.1} ops/s on {} {}, latency p50 {:?} p95 {:?} p99 {:?} max {:?}",
+            self.workload.name(),
+            self.operations,
+            self.failed,
+            self.retries,
+            self.elapsed.as_secs_f64(),
+            self.throughput(),
+            self.threads.len(),
+            threads,
+            self.percentile(50.0),
+            self.percentile(95.0),
+            self.percentile(99.0),
+            self.latencies.last().copied().unwrap_or_default(),
+        )
+    }
+}
+
+// The statements of each workload, by their position here in the steps of an operation, the
+// first three being those of transactions.
+const YCSB: &[&str] = &[
+    "BEGIN",
+    "COMMIT",
+    "ROLLBACK",
+    "SELECT * FROM usertable WHERE ycsb_key = ?",
+    "SELECT * FROM usertable WHERE ycsb_key >= ? AND ycsb_key < ?",
+    "INSERT INTO usertable VALUES (?, ?)",
+    "UPDATE usertable SET field0 = ? WHERE ycsb_key = ?",
+];
+const TPCB: &[&str] = &[
+    "BEGIN",
+    "COMMIT",
+    "ROLLBACK",
+    "UPDATE accounts SET abalance = abalance + ? WHERE aid = ?",
+    "SELECT abalance FROM accounts WHERE aid = ?",
+    "UPDATE tellers SET tbalance = tbalance + ? WHERE tid = ?",
+    "UPDATE branches SET bbalance = bbalance + ? WHERE bid = ?",
+    "INSERT INTO history VALUES (?, ?, ?, ?, ?)",
+];
+const BEGIN: usize = 0;
+const COMMIT: usize = 1;
+const ROLLBACK: usize = 2;
+
+// What a client runs the statements of the workload over.
+trait Connection {
+    // Runs the statement at `statement` of the workload with `params`.
+    fn execute(&mut self, statement: usize, params: &[Value]) -> Result<(), Error>;
+
+    // Runs `sql`, e.g. a VACUUM, which isn't of the workload.
+    fn run(&mut self, sql: &str) -> Result<(), Error>;
+
+    fn in_transaction(&self) -> bool;
+}
+
+// A session of the database, in the thread of the engine.
+struct Local {
+    session: Session,
+    statements: Vec<PreparedStatement>,
+}
+
+impl Connection for Local {
+    fn execute(&mut self, statement: usize, params: &[Value]) -> Result<(), Error> {
+        self.session.execute_prepared(&self.statements[statement], params)?;
+        Ok(())
+    }
+
+    fn run(&mut self, sql: &str) -> Result<(), Error> {
+        self.session.execute(sql)?;
+        Ok(())
+    }
+
+    fn in_transaction(&self) -> bool {
+        self.session.in_transaction()
+    }
+}
+
+// A connection to a server of the database, which has prepared the statements of the workload,
+// each named by its position, and is sent their parameters as text.
+struct Remote {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    // messages to send with the next Sync
+    out: Vec<u8>,
+    // as of the last ReadyForQuery
+    in_transaction: bool,
+}
+
+impl Remote {
+    fn connect(addr: SocketAddr, durability: Durability, statements: &[&str]) -> Result<Self, Error> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        let mut remote = Self { reader: BufReader::new(stream.try_clone()?), stream, out: vec![], in_transaction: false };
+        let mut body = server::PROTOCOL_VERSION.to_be_bytes().to_vec();
+        for s in ["user", USER, ""] {
+            server::cstr(&mut body, s);
+        }
+        remote.out.extend(((body.len() + 4) as i32).to_be_bytes());
+        remote.out.extend(body);
+        remote.ready()?;
+        let durability = match durability {
+            Durability::Sync => "sync",
+            Durability::Async => "async",
+        };
+        remote.run(&format!("SET durability = '{}'", durability))?;
+        for (i, sql) in statements.iter().enumerate() {
+            server::message(&mut remote.out, b'P', |body| {
+                server::cstr(body, &i.to_string());
+                server::cstr(body, sql);
+                body.extend(0i16.to_be_bytes());
+            });
+        }
+        server::message(&mut remote.out, b'S', |_| {});
+        remote.ready()?;
+        Ok(remote)
+    }
+
+    // Sends the messages written, then reads the answers up to ReadyForQuery, failing with the
+    // first error among them.
+    fn ready(&mut self) -> Result<(), Error> {
+        self.stream.write_all(&self.out)?;
+        self.out.clear();
+        let mut error = None;
+        loop {
+            let mut header = [0; 5];
+            self.reader.read_exact(&mut header)?;
+            let len = i32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+            let mut body = vec![0; (len as usize).saturating_sub(4)];
+            self.reader.read_exact(&mut body)?;
+            match header[0] {
+                b'R' if body.get(..4) != Some(&[0; 4]) => {
+                    return Err(Error::Server { code: "28000".to_string(), message: format!("the user {:?} logs in with a password", USER) });
+                }
+                b'E' => {
+                    let (fatal, err) = error_response(&body);
+                    // the connection's closed after, with nothing more
+                    if fatal {
+                        return Err(err);
+                    }
+                    error.get_or_insert(err);
+                }
+                b'Z' => {
+                    self.in_transaction = body.first() != Some(&b'I');
+                    return error.map_or(Ok(()), Err);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Connection for Remote {
+    fn execute(&mut self, statement: usize, params: &[Value]) -> Result<(), Error> {
+        server::message(&mut self.out, b'B', |body| {
+            server::cstr(body, "");
+            server::cstr(body, &statement.to_string());
+            body.extend(0i16.to_be_bytes());
+            body.extend((params.len() as i16).to_be_bytes());
+            for param in params {
+                let text = match param {
+                    Value::Null => {
+                        body.extend((-1i32).to_be_bytes());
+                        continue;
+                    }
+                    Value::Int(n) => n.to_string(),
+                    Value::Text(text) => text.clone(),
+                    value => unreachable!("the workloads have no parameter {:?}", value),
+                };
+                body.extend((text.len() as i32).to_be_bytes());
+                body.extend(text.as_bytes());
+            }
+            body.extend(0i16.to_be_bytes());
+        });
+        server::message(&mut self.out, b'E', |body| {
+            server::cstr(body, "");
+            body.extend(0i32.to_be_bytes());
+        });
+        server::message(&mut self.out, b'S', |_| {});
+        self.ready()
+    }
+
+    fn run(&mut self, sql: &str) -> Result<(), Error> {
+        server::message(&mut self.out, b'Q', |body| server::cstr(body, sql));
+        self.ready()
+    }
+
+    fn in_transaction(&self) -> bool {
+        self.in_transaction
+    }
+}
+
+// Whether the ErrorResponse of `body` is FATAL, and the error it is.
+fn error_response(body: &[u8]) -> (bool, Error) {
+    let (mut fatal, mut code, mut message) = (false, String::new(), String::new());
+    for field in body.split(|&byte| byte == 0) {
+        let Some((&kind, text)) = field.split_first() else { continue };
+        let text = String::from_utf8_lossy(text).into_owned();
+        match kind {
+            b'S' => fatal = text == "FATAL",
+            b'C' => code = text,
+            b'M' => message = text,
+            _ => {}
+        }
+    }
+    (fatal, Error::Server { code, message })
+}
+
+struct Client<C> {
+    connection: C,
+    // of the running operation, in order, to retry it by
+    operation: Vec<(usize, Vec<Value>)>,
+    // those of them left, the next last
+    steps: Vec<(usize, Vec<Value>)>,
+    started: Instant,
+}
+
+impl<C> Client<C> {
+    fn new(connection: C) -> Self {
+        Self { connection, operation: vec![], steps: vec![], started: Instant::now() }
+    }
+}
+
+// Creates and loads the tables of the workload in `db`, which mustn't have them, then runs the
+// operations.
+pub fn run(db: &Database, options: &Options) -> Result<Report, Error> {
+    let mut session = db.session();
+    let (keys, statements) = match options.workload {
+        Workload::TpcB => {
+            session.execute(
+                "CREATE TABLE branches (bid INTEGER PRIMARY KEY, bbalance INTEGER); \
+                 CREATE TABLE tellers (tid INTEGER PRIMARY KEY, bid INTEGER, tbalance INTEGER); \
+                 CREATE TABLE accounts (aid INTEGER PRIMARY KEY, bid INTEGER, abalance INTEGER); \
+                 CREATE TABLE history (hid INTEGER PRIMARY KEY, tid INTEGER, bid INTEGER, aid INTEGER, delta INTEGER)",
+            )?;
+            let branches = branches(options.records) as i64;
+            load(&mut session, "branches", branches, |bid| vec![Value::Int(bid), Value::Int(0)])?;
+            let tellers = branches * TELLERS_PER_BRANCH as i64;
+            load(&mut session, "tellers", tellers, |tid| vec![Value::Int(tid), Value::Int(tid % branches), Value::Int(0)])?;
+            load(&mut session, "accounts", options.records as i64, |aid| vec![Value::Int(aid), Value::Int(aid % branches), Value::Int(0)])?;
+            (0, TPCB)
+        }
+        _ => {
+            session.execute("CREATE TABLE usertable (ycsb_key INTEGER PRIMARY KEY, field0 TEXT)")?;
+            load(&mut session, "usertable", options.records as i64, |key| vec![Value::Int(key), field(key)])?;
+            (options.records as u64, YCSB)
+        }
+    };
+    // shared by the threads: the keys there are, and the operations started
+    let (keys, started) = (AtomicU64::new(keys), AtomicUsize::new(0));
+    if options.threads > 1 {
+        return run_threads(db, options, statements, &keys, &started);
+    }
+
+    let mut clients = vec![];
+    for _ in 0..options.clients.max(1) {
+        let mut session = db.session();
+        session.settings_mut().durability = options.durability;
+        let statements = statements.iter().map(|sql| session.prepare(sql)).collect::<Result<_, _>>()?;
+        clients.push(Client::new(Local { session, statements }));
+    }
+    let mut maintenance = Local { session, statements: vec![] };
+    drive(&mut clients, &mut maintenance, options, &mut Rng::new(options.seed), &keys, &started)
+}
+
+// Runs the operations on threads connecting to a server of `db`, which serves them in this one
+// until they're done.
+fn run_threads(db: &Database, options: &Options, statements: &[&str], keys: &AtomicU64, started: &AtomicUsize) -> Result<Report, Error> {
+    let mut server = Server::bind(db.handle(), ("127.0.0.1", 0))?;
+    let (addr, stopped) = (server.local_addr(), server.stop_flag());
+    let running = AtomicUsize::new(options.threads);
+    thread::scope(|scope| {
+        let threads: Vec<_> = (0..options.threads)
+            .map(|i| {
+                let (stopped, running) = (&stopped, &running);
+                scope.spawn(move || {
+                    let report = run_thread(addr, options, statements, i, keys, started);
+                    // the last thread done stops the server
+                    if running.fetch_sub(1, Ordering::AcqRel) == 1 {
+                        stopped.store(true, Ordering::Relaxed);
+                    }
+                    report
+                })
+            })
+            .collect();
+        let served = server.serve();
+        let mut report = Report::new(options.workload);
+        for thread in threads {
+            report.merge(thread.join().expect("a thread of the bench panicked")?);
+        }
+        served?;
+        Ok(report)
+    })
+}
+
+// Runs the operations of the `i`th thread on clients connecting to the server at `addr`.
+fn run_thread(addr: SocketAddr, options: &Options, statements: &[&str], i: usize, keys: &AtomicU64, started: &AtomicUsize) -> Result<Report, Error> {
+    let mut maintenance = Remote::connect(addr, options.durability, &[])?;
+    let mut clients = vec![];
+    for _ in 0..options.clients.max(1) {
+        clients.push(Client::new(Remote::connect(addr, options.durability, statements)?));
+    }
+    drive(&mut clients, &mut maintenance, options, &mut Rng::new(options.seed.wrapping_add(i as u64)), keys, started)
+}
+
+// Runs operations on `clients`, taking turns a statement at a time, until `started` counts all of
+// those of `options`, vacuuming with `maintenance` as the workload needs.
+fn drive<C: Connection>(clients: &mut [Client<C>], maintenance: &mut C, options: &Options, rng: &mut Rng, keys: &AtomicU64, started: &AtomicUsize) -> Result<Report, Error> {
+    let mut report = Report::new(options.workload);
+    let start = Instant::now();
+    let mut busy = true;
+    while busy {
+        busy = false;
+        for client in clients.iter_mut() {
+            if client.steps.is_empty() {
+                let n = started.fetch_add(1, Ordering::Relaxed);
+                if n >= options.operations {
+                    continue;
+                }
+                client.operation = operation(options, rng, keys, n as i64);
+                client.steps = client.operation.iter().rev().cloned().collect();
+                client.started = Instant::now();
+            }
+            busy = true;
+            let (statement, params) = client.steps.pop().unwrap();
+            if let Err(err) = client.connection.execute(statement, &params) {
+                let Some(conflict) = err.sqlstate().map(|code| code.starts_with("40")) else {
+                    return Err(err);
+                };
+                if client.connection.in_transaction() {
+                    client.connection.execute(ROLLBACK, &[])?;
+                }
+                client.steps.clear();
+                if conflict {
+                    report.retries += 1;
+                    client.steps = client.operation.iter().rev().cloned().collect();
+                } else {
+                    report.failed += 1;
+                }
+            }
+            if client.steps.is_empty() {
+                report.latencies.push(client.started.elapsed());
+                report.operations += 1;
+                if options.workload == Workload::TpcB && report.operations.is_multiple_of(VACUUM_EVERY) {
+                    maintenance.run("VACUUM branches")?;
+                    maintenance.run("VACUUM tellers")?;
+                }
+            }
+        }
+    }
+    report.elapsed = start.elapsed();
+    report.latencies.sort_unstable();
+    report.threads.push((report.operations, report.elapsed));
+    Ok(report)
+}
+
+// The statements, and their parameters, of the `n`th operation.
+fn operation(options: &Options, rng: &mut Rng, keys: &AtomicU64, n: i64) -> Vec<(usize, Vec<Value>)> {
+    let reads = match options.workload {
+        Workload::YcsbA => 50,
+        Workload::YcsbB | Workload::YcsbE => 95,
+        Workload::YcsbC => 100,
+        Workload::TpcB => {
+            let branches = branches(options.records) as u64;
+            let aid = Value::Int(rng.below(options.records.max(1) as u64) as i64);
+            let tid = Value::Int(rng.below(branches * TELLERS_PER_BRANCH as u64) as i64);
+            let bid = Value::Int(rng.below(branches) as i64);
+            let delta = Value::Int(rng.below(10_001) as i64 - 5000);
+            let history = vec![Value::Int(n), tid.clone(), bid.clone(), aid.clone(), delta.clone()];
+            return vec![
+                (BEGIN, vec![]),
+                (3, vec![delta.clone(), aid.clone()]),
+                (4, vec![aid]),
+                (5, vec![delta.clone(), tid]),
+                (6, vec![delta, bid]),
+                (7, history),
+                (COMMIT, vec![]),
+            ];
+        }
+    };
+    if rng.below(100) >= reads {
+        // E inserts new keys, the others update those there
+        if options.workload == Workload::YcsbE {
+            let key = keys.fetch_add(1, Ordering::Relaxed) as i64;
+            return vec![(5, vec![Value::Int(key), field(key)])];
+        }
+        let key = rng.below(keys.load(Ordering::Relaxed).max(1)) as i64;
+        return vec![(6, vec![field(key + n + 1), Value::Int(key)])];
+    }
+    let key = rng.below(keys.load(Ordering::Relaxed).max(1)) as i64;
+    match options.workload {
+        Workload::YcsbE => vec![(4, vec![Value::Int(key), Value::Int(key + 1 + rng.below(MAX_SCAN_LEN) as i64)])],
+        _ => vec![(3, vec![Value::Int(key)])],
+    }
+}
+
+fn branches(accounts: usize) -> usize {
+    accounts.div_ceil(ACCOUNTS_PER_BRANCH).max(1)
+}
+
+fn field(key: i64) -> Value {
+    let text = format!("{:x}", key);
+    Value::Text(text.repeat(FIELD_LEN.div_ceil(text.len()))[..FIELD_LEN].to_string())
+}
+
+fn load(session: &mut Session, table: &str, rows: i64, row: impl Fn(i64) -> Vec<Value>) -> Result<usize, sql::Error> {
+    let mut next = 0;
+    session.load(
+        table,
+        || {
+            next += 1;
+            Ok((next <= rows).then(|| row(next - 1)))
+        },
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::QueryResult;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        for workload in Workload::ALL {
+            assert_eq!(Some(workload), Workload::from_name(workload.name()));
+            let dir = tempdir().unwrap();
+            let db = Database::open(dir.path(), 32).unwrap();
+            let options = Options { workload, records: 500, operations: 200, clients: 3, ..Options::default() };
+            let report = run(&db, &options).unwrap();
+            assert_eq!((200, 0, 200), (report.operations, report.failed, report.latencies.len()), "{:?}", workload);
+            assert!(report.latencies.windows(2).all(|pair| pair[0] <= pair[1]));
+            assert!(report.percentile(50.0) <= report.percentile(99.0));
+            assert!(report.to_string().starts_with(&format!("{}: 200 operations (0 failed, {} retries) in ", workload.name(), report.retries)), "{}", report);
+
+            let query = |sql: &str| match db.session().execute(sql).unwrap().pop() {
+                Some(QueryResult::Rows { rows, .. }) => rows,
+                result => panic!("unexpected result: {:?}", result),
+            };
+            let count = |sql: &str| query(sql).len();
+            match workload {
+                Workload::TpcB => {
+                    // the transactions whose balances conflicted with another's retried until they went through
+                    assert!(report.retries > 0);
+                    assert_eq!(200, count("SELECT hid FROM history"));
+                    assert_eq!((1, 10, 500), (count("SELECT bid FROM branches"), count("SELECT tid FROM tellers"), count("SELECT aid FROM accounts")));
+                    // their deltas added to each of the balances
+                    let sums: Vec<_> = ["SELECT SUM(delta) FROM history", "SELECT SUM(abalance) FROM accounts", "SELECT SUM(tbalance) FROM tellers", "SELECT SUM(bbalance) FROM branches"]
+                        .into_iter()
+                        .map(|sql| query(sql).remove(0))
+                        .collect();
+                    assert!(sums.iter().all(|sum| *sum == sums[0]), "{:?}", sums);
+                    assert_ne!(vec![Value::Int(0)], sums[0]);
+                }
+                Workload::YcsbE => assert!(count("SELECT ycsb_key FROM usertable") > 500),
+                _ => {
+                    assert_eq!(0, report.retries);
+                    let rows = query("SELECT * FROM usertable");
+                    assert_eq!(500, rows.len());
+                    let updated = rows.iter().filter(|row| !matches!(row[0], Value::Int(key) if row[1] == field(key))).count();
+                    match workload {
+                        Workload::YcsbC => assert_eq!(0, updated),
+                        _ => assert!(updated > 0),
+                    }
+                }
+            }
+            // the tables are there already
+            assert!(run(&db, &options).is_err());
+        }
+
+        // commits which don't wait for the log
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path(), 32).unwrap();
+        let options = Options { workload: Workload::TpcB, records: 100, operations: 50, durability: Durability::Async, ..Options::default() };
+        assert_eq!(50, run(&db, &options).unwrap().operations);
+
+        // clients on threads of their own, of a server of the database
+        for workload in [Workload::YcsbA, Workload::TpcB] {
+            let dir = tempdir().unwrap();
+            let db = Database::open(dir.path(), 32).unwrap();
+            let options = Options { workload, records: 500, operations: 200, clients: 2, threads: 3, ..Options::default() };
+            let report = run(&db, &options).unwrap();
+            assert_eq!((200, 200, 3), (report.operations, report.latencies.len(), report.threads.len()), "{:?}", workload);
+            assert_eq!(200, report.threads.iter().map(|(operations, _)| operations).sum::<usize>());
+            assert!(report.threads.iter().all(|(_, elapsed)| *elapsed <= report.elapsed));
+            assert!(report.to_string().contains(" ops/s on 3 threads, "), "{}", report);
+            let mut session = db.session();
+            let sum = |session: &mut Session, sql: &str| match session.execute(sql).unwrap().pop() {
+                Some(QueryResult::Rows { mut rows, .. }) => rows.remove(0),
+                result => panic!("unexpected result: {:?}", result),
+            };
+            match workload {
+                // a transaction left running on another thread keeps the versions of the branch
+                // from being vacuumed, which may pile up into a row too large to update, failing
+                // those updating it then, which are rolled back
+                Workload::TpcB => {
+                    let committed = Value::Int(200 - report.failed as i64);
+                    assert_eq!(vec![committed], sum(&mut session, "SELECT COUNT(*) FROM history"));
+                    assert_eq!(sum(&mut session, "SELECT SUM(delta) FROM history"), sum(&mut session, "SELECT SUM(bbalance) FROM branches"));
+                }
+                _ => assert_eq!(0, report.failed),
+            }
+        }
+
+        let report = Report {
+            workload: Workload::YcsbC,
+            operations: 4,
+            failed: 0,
+            retries: 0,
+            elapsed: Duration::from_secs(2),
+            latencies: (1..=4).map(Duration::from_millis).collect(),
+            threads: vec![(4, Duration::from_secs(2))],
+        };
+        assert_eq!(2.0, report.throughput());
+        assert!(report.to_string().contains(" 2.0 ops/s on 1 thread, "), "{}", report);
+        // the sum of the threads', each over its own time
+        let threads = Report { threads: vec![(4, Duration::from_secs(2)), (3, Duration::from_secs(1))], ..report.clone() };
+        assert_eq!(5.0, threads.throughput());
+        assert_eq!((Duration::from_millis(2), Duration::from_millis(4)), (report.percentile(50.0), report.percentile(99.0)));
+        assert_eq!(Duration::from_millis(1), report.percentile(0.0));
+    }
+}
@@ -0,0 +1,687 @@
+use std::collections::BTreeSet;
+use std::convert::TryInto;
+
+use crate::buffer::{self, Buffer, BufferPoolManager, Page, PAGE_BODY_SIZE};
+use crate::disk::PageId;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error("duplicate key")]
+    DuplicateKey,
+    #[error("key and value are too large")]
+    EntryTooLarge,
+    #[error("malformed b+tree page")]
+    Malformed,
+}
+
+// Limit on key + value length. Keeping entries well below a quarter of a page guarantees
+// that both halves of a split fit in a page.
+pub const MAX_ENTRY_SIZE: usize = 1000;
+
+// Page layouts (all integers little endian):
+//   meta:   [root_page_id: u64]
+//   leaf:   [LEAF: u8][num_entries: u16][next_page_id: u64] + ([key_len: u16][value_len: u16][key][value])*
+//   branch: [BRANCH: u8][num_keys: u16][child_0: u64] + ([key_len: u16][key][child_i+1: u64])*
+// Child i of a branch holds keys in [key_i-1, key_i).
+const LEAF: u8 = 0;
+const BRANCH: u8 = 1;
+const NODE_HEADER_SIZE: usize = 11;
+
+// (key, value)
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BTree {
+    pub meta_page_id: PageId,
+}
+
+pub enum SearchMode {
+    Start,
+    // the first entry whose key is >= the given key
+    Key(Vec<u8>),
+}
+
+// A node as read off its page, public for tools reading pages directly.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Node {
+    Leaf {
+        entries: Vec<Entry>,
+        next: Option<PageId>,
+    },
+    Branch {
+        keys: Vec<Vec<u8>>,
+        children: Vec<PageId>,
+    },
+}
+
+impl BTree {
+    pub fn create(bufmgr: &mut BufferPoolManager) -> Result<Self, Error> {
+        let meta_buffer = bufmgr.create_page()?;
+        let root_buffer = bufmgr.create_page()?;
+        let root = Node::Leaf { entries: vec![], next: None };
+        root.write_to(bufmgr, &root_buffer)?;
+        set_root_page_id(bufmgr, &meta_buffer, root_buffer.page_id)?;
+        Ok(Self {
+            meta_page_id: meta_buffer.page_id,
+        })
+    }
+
+    pub fn root_page_id(&self, bufmgr: &mut BufferPoolManager) -> Result<PageId, Error> {
+        let buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        let page = buffer.page.borrow();
+        Ok(PageId(u64::from_le_bytes(page[0..8].try_into().unwrap())))
+    }
+
+    pub fn insert(&self, bufmgr: &mut BufferPoolManager, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.put(bufmgr, key, value, false)
+    }
+
+    // Inserts the entry, replacing the value if the key already exists.
+    pub fn upsert(&self, bufmgr: &mut BufferPoolManager, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.put(bufmgr, key, value, true)
+    }
+
+    fn put(&self, bufmgr: &mut BufferPoolManager, key: &[u8], value: &[u8], replace: bool) -> Result<(), Error> {
+        if key.len() + value.len() > MAX_ENTRY_SIZE {
+            return Err(Error::EntryTooLarge);
+        }
+        let root_page_id = self.root_page_id(bufmgr)?;
+        if let Some((key, right_page_id)) = insert_into(bufmgr, root_page_id, key, value, replace)? {
+            // the root was split; grow the tree by one level
+            let root = Node::Branch {
+                keys: vec![key],
+                children: vec![root_page_id, right_page_id],
+            };
+            let root_buffer = bufmgr.create_page_beside(self.meta_page_id)?;
+            root.write_to(bufmgr, &root_buffer)?;
+            let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+            set_root_page_id(bufmgr, &meta_buffer, root_buffer.page_id)?;
+        }
+        Ok(())
+    }
+
+    // Appends `entries`, in key order and all above the keys in the tree, along its right edge,
+    // filling the leaves and branches to `fill_factor` percent of a page where inserts would
+    // split them in half. Each page is written once however many entries go in it. Returns false,
+    // appending nothing, if the first key isn't above those in the tree.
+    pub fn append(&self, bufmgr: &mut BufferPoolManager, entries: &[Entry], fill_factor: usize) -> Result<bool, Error> {
+        if entries.iter().any(|(key, value)| key.len() + value.len() > MAX_ENTRY_SIZE) {
+            return Err(Error::EntryTooLarge);
+        }
+        let Some((first, _)) = entries.first() else {
+            return Ok(true);
+        };
+        // the branches on the path to the last leaf, the root first
+        let mut branches: Vec<(PageId, Node)> = vec![];
+        let mut page_id = self.root_page_id(bufmgr)?;
+        let mut leaf = loop {
+            match Node::load(bufmgr, page_id)? {
+                Node::Branch { keys, children } => {
+                    let child = *children.last().unwrap();
+                    branches.push((page_id, Node::Branch { keys, children }));
+                    page_id = child;
+                }
+                Node::Leaf { entries, .. } => break entries,
+            }
+        };
+        // the last leaf holds the keys from the last separator on the way to it on
+        let separator = branches.iter().rev().find_map(|(_, node)| match node {
+            Node::Branch { keys, .. } => keys.last(),
+            Node::Leaf { .. } => None,
+        });
+        let above = match leaf.last() {
+            Some((key, _)) => first > key,
+            None => separator.is_none_or(|separator| first >= separator),
+        };
+        if !above {
+            return Ok(false);
+        }
+        let limit = PAGE_BODY_SIZE * fill_factor.clamp(10, 100) / 100;
+        let mut size = Node::Leaf { entries: leaf.clone(), next: None }.size();
+        let mut new_root = false;
+        for (key, value) in entries {
+            if !leaf.is_empty() && size + 4 + key.len() + value.len() > limit {
+                // on to a new leaf, its first key separating it from the full one
+                let right = bufmgr.create_page_beside(page_id)?.page_id;
+                Node::Leaf { entries: std::mem::take(&mut leaf), next: Some(right) }.store(bufmgr, page_id)?;
+                new_root |= append_child(bufmgr, &mut branches, key.clone(), page_id, right, limit)?;
+                page_id = right;
+                size = NODE_HEADER_SIZE;
+            }
+            size += 4 + key.len() + value.len();
+            leaf.push((key.clone(), value.clone()));
+        }
+        Node::Leaf { entries: leaf, next: None }.store(bufmgr, page_id)?;
+        for (page_id, node) in &branches {
+            node.store(bufmgr, *page_id)?;
+        }
+        if new_root {
+            let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+            set_root_page_id(bufmgr, &meta_buffer, branches[0].0)?;
+        }
+        Ok(true)
+    }
+
+    // Removes the entry with `key`, returning whether there was one. Nodes aren't merged, so
+    // the space is reused by entries inserted later in the leaf, which may be left empty.
+    pub fn delete(&self, bufmgr: &mut BufferPoolManager, key: &[u8]) -> Result<bool, Error> {
+        let mut page_id = self.root_page_id(bufmgr)?;
+        loop {
+            match Node::load(bufmgr, page_id)? {
+                Node::Branch { keys, children } => page_id = children[keys.partition_point(|k| k.as_slice() <= key)],
+                Node::Leaf { mut entries, next } => {
+                    let Ok(pos) = entries.binary_search_by(|(k, _)| k.as_slice().cmp(key)) else {
+                        return Ok(false);
+                    };
+                    entries.remove(pos);
+                    Node::Leaf { entries, next }.store(bufmgr, page_id)?;
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    pub fn search(&self, bufmgr: &mut BufferPoolManager, mode: SearchMode) -> Result<Iter, Error> {
+        let mut page_id = self.root_page_id(bufmgr)?;
+        let mut path = vec![];
+        loop {
+            match Node::load(bufmgr, page_id)? {
+                Node::Branch { keys, children } => {
+                    let child = match &mode {
+                        SearchMode::Start => 0,
+                        SearchMode::Key(key) => keys.partition_point(|k| k <= key),
+                    };
+                    page_id = children[child];
+                    path.push((children, child));
+                }
+                Node::Leaf { entries, next } => {
+                    let pos = match &mode {
+                        SearchMode::Start => 0,
+                        SearchMode::Key(key) => entries.partition_point(|(k, _)| k < key),
+                    };
+                    return Ok(Iter { entries, pos, leaf: page_id, next, path });
+                }
+            }
+        }
+    }
+
+    // The pages of the nodes of the tree, the meta page aside. Fails as malformed if a node is
+    // reached twice.
+    pub fn pages(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<PageId>, Error> {
+        let mut pages = BTreeSet::new();
+        let mut pending = vec![self.root_page_id(bufmgr)?];
+        while let Some(page_id) = pending.pop() {
+            if !pages.insert(page_id) {
+                return Err(Error::Malformed);
+            }
+            if let Node::Branch { children, .. } = Node::load(bufmgr, page_id)? {
+                pending.extend(children);
+            }
+        }
+        Ok(pages.into_iter().collect())
+    }
+
+    // Empties the tree with a new root beside the meta page, whatever it held before, e.g. for an
+    // unlogged table whose pages were lost when the database went down.
+    pub fn clear(&self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        let root_buffer = bufmgr.create_page_beside(self.meta_page_id)?;
+        Node::Leaf { entries: vec![], next: None }.write_to(bufmgr, &root_buffer)?;
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        set_root_page_id(bufmgr, &meta_buffer, root_buffer.page_id)
+    }
+
+    // Makes the nodes of `other` those of the tree in place of its own, which are left to the
+    // caller to free along with the meta page of `other`.
+    pub fn replace_nodes(&self, bufmgr: &mut BufferPoolManager, other: &BTree) -> Result<(), Error> {
+        let root_page_id = other.root_page_id(bufmgr)?;
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        set_root_page_id(bufmgr, &meta_buffer, root_page_id)
+    }
+}
+
+fn set_root_page_id(bufmgr: &mut BufferPoolManager, meta_buffer: &Buffer, root_page_id: PageId) -> Result<(), Error> {
+    let mut page = *meta_buffer.page.borrow();
+    page[0..8].copy_from_slice(&root_page_id.0.to_le_bytes());
+    bufmgr.update_page(meta_buffer, &page)?;
+    Ok(())
+}
+
+// Adds `right` with the keys from `key` on as the last child of the lowest of `branches`, the path
+// along the right edge of a tree being appended to, past `left`. Branches as full as `limit` are
+// written and followed by new ones, up to a new root over the old. Returns whether there's one.
+fn append_child(
+    bufmgr: &mut BufferPoolManager,
+    branches: &mut Vec<(PageId, Node)>,
+    key: Vec<u8>,
+    mut left: PageId,
+    mut right: PageId,
+    limit: usize,
+) -> Result<bool, Error> {
+    for level in (0..branches.len()).rev() {
+        let (page_id, node) = &mut branches[level];
+        let size = node.size();
+        let Node::Branch { keys, children } = node else { unreachable!() };
+        if keys.is_empty() || size + 10 + key.len() <= limit {
+            keys.push(key);
+            children.push(right);
+            return Ok(false);
+        }
+        // the key goes up to separate the full branch from the new one
+        node.store(bufmgr, *page_id)?;
+        left = *page_id;
+        let new_page_id = bufmgr.create_page_beside(left)?.page_id;
+        branches[level] = (new_page_id, Node::Branch { keys: vec![], children: vec![right] });
+        right = new_page_id;
+    }
+    let root_page_id = bufmgr.create_page_beside(left)?.page_id;
+    branches.insert(0, (root_page_id, Node::Branch { keys: vec![key], children: vec![left, right] }));
+    Ok(true)
+}
+
+// Inserts the entry into the subtree rooted at `page_id`. An existing entry with the same key
+// is an error unless `replace` is set, in which case its value is replaced.
+// If the node had to be split, returns the first key of the new right sibling and its page id.
+fn insert_into(
+    bufmgr: &mut BufferPoolManager,
+    page_id: PageId,
+    key: &[u8],
+    value: &[u8],
+    replace: bool,
+) -> Result<Option<(Vec<u8>, PageId)>, Error> {
+    let mut node = Node::load(bufmgr, page_id)?;
+    match &mut node {
+        Node::Leaf { entries, .. } => {
+            match entries.binary_search_by(|(k, _)| k.as_slice().cmp(key)) {
+                Ok(pos) if replace => entries[pos].1 = value.to_vec(),
+                Ok(_) => return Err(Error::DuplicateKey),
+                Err(pos) => entries.insert(pos, (key.to_vec(), value.to_vec())),
+            }
+        }
+        Node::Branch { keys, children } => {
+            let child_idx = keys.partition_point(|k| k.as_slice() <= key);
+            match insert_into(bufmgr, children[child_idx], key, value, replace)? {
+                Some((separator, right_page_id)) => {
+                    keys.insert(child_idx, separator);
+                    children.insert(child_idx + 1, right_page_id);
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    if node.size() <= PAGE_BODY_SIZE {
+        node.store(bufmgr, page_id)?;
+        return Ok(None);
+    }
+
+    let right_buffer = bufmgr.create_page_beside(page_id)?;
+    let right_page_id = right_buffer.page_id;
+    drop(right_buffer);
+    let (separator, right) = node.split(right_page_id);
+    node.store(bufmgr, page_id)?;
+    right.store(bufmgr, right_page_id)?;
+    Ok(Some((separator, right_page_id)))
+}
+
+impl Node {
+    fn load(bufmgr: &mut BufferPoolManager, page_id: PageId) -> Result<Self, Error> {
+        let buffer = bufmgr.fetch_page(page_id)?;
+        let page = buffer.page.borrow();
+        Self::read(&page)
+    }
+
+    fn store(&self, bufmgr: &mut BufferPoolManager, page_id: PageId) -> Result<(), Error> {
+        let buffer = bufmgr.fetch_page(page_id)?;
+        self.write_to(bufmgr, &buffer)
+    }
+
+    // Writes the node to the page in `buffer` through the buffer pool manager, which logs the change.
+    fn write_to(&self, bufmgr: &mut BufferPoolManager, buffer: &Buffer) -> Result<(), Error> {
+        let mut page = *buffer.page.borrow();
+        self.write(&mut page);
+        bufmgr.update_page(buffer, &page)?;
+        Ok(())
+    }
+
+    pub fn read(page: &Page) -> Result<Self, Error> {
+        // the node doesn't go past the body into the LSN
+        let body = &page[..PAGE_BODY_SIZE];
+        let mut pos = 0;
+        let mut take = |len: usize| -> Result<&[u8], Error> {
+            let slice = body.get(pos..pos + len).ok_or(Error::Malformed)?;
+            pos += len;
+            Ok(slice)
+        };
+        let node_type = take(1)?[0];
+        let count = u16::from_le_bytes(take(2)?.try_into().unwrap()) as usize;
+        let header_page_id = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        match node_type {
+            LEAF => {
+                let mut entries = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let key_len = u16::from_le_bytes(take(2)?.try_into().unwrap()) as usize;
+                    let value_len = u16::from_le_bytes(take(2)?.try_into().unwrap()) as usize;
+                    let key = take(key_len)?.to_vec();
+                    let value = take(value_len)?.to_vec();
+                    entries.push((key, value));
+                }
+                let next = Some(PageId(header_page_id)).filter(|&id| id != PageId::INVALID_PAGE_ID);
+                Ok(Node::Leaf { entries, next })
+            }
+            BRANCH => {
+                let mut keys = Vec::with_capacity(count);
+                let mut children = vec![PageId(header_page_id)];
+                for _ in 0..count {
+                    let key_len = u16::from_le_bytes(take(2)?.try_into().unwrap()) as usize;
+                    keys.push(take(key_len)?.to_vec());
+                    children.push(PageId(u64::from_le_bytes(take(8)?.try_into().unwrap())));
+                }
+                Ok(Node::Branch { keys, children })
+            }
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    // Writes the node to the body of `page`, which it must fit in, as `read` reads it.
+    pub fn write(&self, page: &mut Page) {
+        let mut buf = Vec::with_capacity(PAGE_BODY_SIZE);
+        match self {
+            Node::Leaf { entries, next } => {
+                buf.push(LEAF);
+                buf.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+                buf.extend_from_slice(&next.unwrap_or(PageId::INVALID_PAGE_ID).0.to_le_bytes());
+                for (key, value) in entries {
+                    buf.extend_from_slice(&(key.len() as u16).to_le_bytes());
+                    buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+                    buf.extend_from_slice(key);
+                    buf.extend_from_slice(value);
+                }
+            }
+            Node::Branch { keys, children } => {
+                buf.push(BRANCH);
+                buf.extend_from_slice(&(keys.len() as u16).to_le_bytes());
+                buf.extend_from_slice(&children[0].0.to_le_bytes());
+                for (key, child) in keys.iter().zip(&children[1..]) {
+                    buf.extend_from_slice(&(key.len() as u16).to_le_bytes());
+                    buf.extend_from_slice(key);
+                    buf.extend_from_slice(&child.0.to_le_bytes());
+                }
+            }
+        }
+        page[..buf.len()].copy_from_slice(&buf);
+    }
+
+    // Bytes of its page's body the node takes.
+    pub fn size(&self) -> usize {
+        NODE_HEADER_SIZE
+            + match self {
+                Node::Leaf { entries, .. } => entries.iter().map(|(k, v)| 4 + k.len() + v.len()).sum::<usize>(),
+                Node::Branch { keys, .. } => keys.iter().map(|k| 10 + k.len()).sum::<usize>(),
+            }
+    }
+
+    // Moves the upper half (by bytes) of this node into a new node which will be stored at `right_page_id`.
+    // Returns the separator key for the parent and the new node.
+    fn split(&mut self, right_page_id: PageId) -> (Vec<u8>, Node) {
+        let half = self.size() / 2;
+        match self {
+            Node::Leaf { entries, next } => {
+                let mut size = NODE_HEADER_SIZE;
+                let mut mid = 0;
+                while size < half && mid < entries.len() - 1 {
+                    size += 4 + entries[mid].0.len() + entries[mid].1.len();
+                    mid += 1;
+                }
+                let right_entries = entries.split_off(mid.max(1));
+                let right = Node::Leaf {
+                    entries: right_entries,
+                    next: *next,
+                };
+                *next = Some(right_page_id);
+                let separator = match &right {
+                    Node::Leaf { entries, .. } => entries[0].0.clone(),
+                    Node::Branch { .. } => unreachable!(),
+                };
+                (separator, right)
+            }
+            Node::Branch { keys, children } => {
+                let mut size = NODE_HEADER_SIZE;
+                let mut mid = 0;
+                while size < half && mid < keys.len() - 2 {
+                    size += 10 + keys[mid].len();
+                    mid += 1;
+                }
+                let mid = mid.max(1);
+                let right_keys = keys.split_off(mid + 1);
+                let separator = keys.pop().unwrap();
+                let right_children = children.split_off(mid + 1);
+                (
+                    separator,
+                    Node::Branch {
+                        keys: right_keys,
+                        children: right_children,
+                    },
+                )
+            }
+        }
+    }
+}
+
+// Iterates leaf entries in key order. No page stays pinned between calls.
+pub struct Iter {
+    entries: Vec<Entry>,
+    pos: usize,
+    // the page of the leaf the entries are of
+    leaf: PageId,
+    next: Option<PageId>,
+    // the children of the branches down to the current leaf, and which of them it's under, as
+    // they were when it was reached, to tell the leaves after it by (see `leaves_ahead`), empty
+    // once they're known to have changed
+    path: Vec<(Vec<PageId>, usize)>,
+}
+
+impl Iter {
+    pub fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Entry>, Error> {
+        if !self.fill(bufmgr)? {
+            return Ok(None);
+        }
+        let entry = std::mem::take(&mut self.entries[self.pos]);
+        self.pos += 1;
+        Ok(Some(entry))
+    }
+
+    // The entries left of the current leaf, or those of the next one.
+    pub fn next_leaf(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Vec<Entry>>, Error> {
+        if !self.fill(bufmgr)? {
+            return Ok(None);
+        }
+        let entries = self.entries.split_off(self.pos);
+        self.entries.clear();
+        self.pos = 0;
+        Ok(Some(entries))
+    }
+
+    // The page of the current leaf, that of the entries `next_leaf` last returned.
+    pub fn leaf(&self) -> PageId {
+        self.leaf
+    }
+
+    // The pages of up to `n` leaves after the current one, as the tree was when it was reached,
+    // to be read ahead of the leaves themselves (see `BufferPoolManager::prefetch`). Fewer if a
+    // branch on the way has changed so that it can't be read as one.
+    pub fn leaves_ahead(&self, bufmgr: &mut BufferPoolManager, n: usize) -> Vec<PageId> {
+        let mut path = self.path.clone();
+        let mut leaves = vec![];
+        while leaves.len() < n {
+            match leaf_after(bufmgr, &mut path) {
+                Ok(Some(page_id)) => leaves.push(page_id),
+                _ => break,
+            }
+        }
+        leaves
+    }
+
+    // Loads the next leaf if the current one has been read, returning whether there's an entry left.
+    fn fill(&mut self, bufmgr: &mut BufferPoolManager) -> Result<bool, Error> {
+        while self.pos >= self.entries.len() {
+            let page_id = match self.next {
+                Some(page_id) => page_id,
+                None => return Ok(false),
+            };
+            if !self.path.is_empty() && !matches!(leaf_after(bufmgr, &mut self.path), Ok(Some(leaf)) if leaf == page_id) {
+                self.path.clear();
+            }
+            match Node::load(bufmgr, page_id)? {
+                Node::Leaf { entries, next } => {
+                    self.entries = entries;
+                    self.pos = 0;
+                    self.leaf = page_id;
+                    self.next = next;
+                }
+                Node::Branch { .. } => return Err(Error::Malformed),
+            }
+        }
+        Ok(true)
+    }
+}
+
+// Moves `path`, that of a leaf, to the next leaf, returning its page, None after the last one.
+fn leaf_after(bufmgr: &mut BufferPoolManager, path: &mut Vec<(Vec<PageId>, usize)>) -> Result<Option<PageId>, Error> {
+    let depth = path.len();
+    let Some(level) = path.iter().rposition(|(children, child)| child + 1 < children.len()) else {
+        return Ok(None);
+    };
+    path.truncate(level + 1);
+    path[level].1 += 1;
+    while path.len() < depth {
+        let (children, child) = path.last().unwrap();
+        match Node::load(bufmgr, children[*child])? {
+            Node::Branch { children, .. } => path.push((children, 0)),
+            Node::Leaf { .. } => return Err(Error::Malformed),
+        }
+    }
+    Ok(path.last().map(|(children, child)| children[*child]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(8));
+        let btree = BTree::create(&mut bufmgr).unwrap();
+
+        // insert in a scrambled order so that splits happen all over the tree
+        for i in 0..2000u32 {
+            let n = (i * 7919) % 2000;
+            let key = format!("key{:06}", n);
+            btree.insert(&mut bufmgr, key.as_bytes(), &vec![b'v'; (n % 50) as usize]).unwrap();
+        }
+        assert!(matches!(btree.insert(&mut bufmgr, b"key000042", b""), Err(Error::DuplicateKey)));
+        assert!(matches!(btree.insert(&mut bufmgr, &[0; MAX_ENTRY_SIZE + 1], b""), Err(Error::EntryTooLarge)));
+        // growing values may split leaves too
+        for n in (0..2000).step_by(3) {
+            let key = format!("key{:06}", n);
+            btree.upsert(&mut bufmgr, key.as_bytes(), &[b'w'; 200]).unwrap();
+        }
+
+        let mut iter = btree.search(&mut bufmgr, SearchMode::Start).unwrap();
+        let mut n = 0;
+        while let Some((key, value)) = iter.next(&mut bufmgr).unwrap() {
+            assert_eq!(format!("key{:06}", n).as_bytes(), key.as_slice());
+            if n % 3 == 0 {
+                assert_eq!(vec![b'w'; 200], value);
+            } else {
+                assert_eq!((n % 50) as usize, value.len());
+            }
+            n += 1;
+        }
+        assert_eq!(2000, n);
+
+        let mut iter = btree.search(&mut bufmgr, SearchMode::Key(b"key001234x".to_vec())).unwrap();
+        let (key, _) = iter.next(&mut bufmgr).unwrap().unwrap();
+        assert_eq!(b"key001235", key.as_slice());
+
+        // deleting all but a few leaves empty leaves behind, which are skipped
+        for n in 0..2000 {
+            if n % 500 != 0 {
+                assert!(btree.delete(&mut bufmgr, format!("key{:06}", n).as_bytes()).unwrap());
+            }
+        }
+        assert!(!btree.delete(&mut bufmgr, b"key000001").unwrap());
+        let mut iter = btree.search(&mut bufmgr, SearchMode::Key(b"key000001".to_vec())).unwrap();
+        let mut keys = vec![];
+        while let Some((key, _)) = iter.next(&mut bufmgr).unwrap() {
+            keys.push(String::from_utf8(key).unwrap());
+        }
+        assert_eq!(vec!["key000500", "key001000", "key001500"], keys);
+
+        // appending packs the pages, here to 90%, and with deeper trees than a leaf builds branches
+        let leaves = |bufmgr: &mut BufferPoolManager, btree: &BTree| {
+            let mut iter = btree.search(bufmgr, SearchMode::Start).unwrap();
+            let mut leaves = 0;
+            while iter.next_leaf(bufmgr).unwrap().is_some() {
+                leaves += 1;
+            }
+            leaves
+        };
+        let entry = |n: u32| (format!("key{:06}", n).into_bytes(), vec![b'v'; 40]);
+        let inserted = BTree::create(&mut bufmgr).unwrap();
+        for n in 0..3000 {
+            let (key, value) = entry(n);
+            inserted.insert(&mut bufmgr, &key, &value).unwrap();
+        }
+        let appended = BTree::create(&mut bufmgr).unwrap();
+        for run in (0..3000).collect::<Vec<_>>().chunks(700) {
+            let entries: Vec<_> = run.iter().map(|&n| entry(n)).collect();
+            assert!(appended.append(&mut bufmgr, &entries, 90).unwrap());
+        }
+        // the leaves ahead as the links between them have it
+        let mut iter = inserted.search(&mut bufmgr, SearchMode::Start).unwrap();
+        let ahead = iter.leaves_ahead(&mut bufmgr, 5);
+        assert_eq!(5, ahead.len());
+        let mut linked = vec![];
+        while linked.len() < 5 {
+            iter.next_leaf(&mut bufmgr).unwrap();
+            linked.extend(iter.next);
+        }
+        assert_eq!(ahead, linked);
+        assert!(!iter.path.is_empty());
+        let (inserted_leaves, appended_leaves) = (leaves(&mut bufmgr, &inserted), leaves(&mut bufmgr, &appended));
+        assert!(appended_leaves * 10 < inserted_leaves * 6, "{} {}", appended_leaves, inserted_leaves);
+        let page_entries = PAGE_BODY_SIZE * 9 / 10 / (4 + 9 + 40);
+        assert!(appended_leaves <= 3000 / page_entries + 5);
+        let mut iter = appended.search(&mut bufmgr, SearchMode::Start).unwrap();
+        for n in 0..3000 {
+            assert_eq!(Some(entry(n)), iter.next(&mut bufmgr).unwrap());
+        }
+        assert_eq!(None, iter.next(&mut bufmgr).unwrap());
+        let mut iter = appended.search(&mut bufmgr, SearchMode::Key(b"key002345".to_vec())).unwrap();
+        assert_eq!(Some(entry(2345)), iter.next(&mut bufmgr).unwrap());
+        let wide = |n: u32| (format!("key{:06}{}", n, "k".repeat(300)).into_bytes(), vec![]);
+        let deep = BTree::create(&mut bufmgr).unwrap();
+        for run in (0..3000).collect::<Vec<_>>().chunks(500) {
+            assert!(deep.append(&mut bufmgr, &run.iter().map(|&n| wide(n)).collect::<Vec<_>>(), 90).unwrap());
+        }
+        for n in (0..3000).step_by(97) {
+            let mut iter = deep.search(&mut bufmgr, SearchMode::Key(wide(n).0)).unwrap();
+            assert_eq!(Some(wide(n)), iter.next(&mut bufmgr).unwrap());
+        }
+        // only above the keys there, after which inserts go on as before
+        assert!(!appended.append(&mut bufmgr, &[entry(2999)], 90).unwrap());
+        assert!(!appended.append(&mut bufmgr, &[entry(5)], 90).unwrap());
+        appended.insert(&mut bufmgr, b"key000005x", b"").unwrap();
+        assert!(appended.append(&mut bufmgr, &[entry(3000)], 90).unwrap());
+        let mut iter = appended.search(&mut bufmgr, SearchMode::Key(b"key000005".to_vec())).unwrap();
+        assert_eq!(Some(entry(5)), iter.next(&mut bufmgr).unwrap());
+        assert_eq!(b"key000005x", iter.next(&mut bufmgr).unwrap().unwrap().0.as_slice());
+        assert!(matches!(appended.append(&mut bufmgr, &[(b"z".to_vec(), vec![0; MAX_ENTRY_SIZE])], 90), Err(Error::EntryTooLarge)));
+    }
+}
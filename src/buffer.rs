@@ -1,7 +1,20 @@
-use crate::disk::{PAGE_SIZE, PageId, DiskManager};
+use crate::disk::{self, PAGE_SIZE, PageId, DiskManager, CHECKSUM_OFFSET};
+use crate::mvcc::{Isolation, Snapshot, Visibility, FROZEN};
+use crate::pool::ThreadPool;
+use crate::lock::LockManager;
+use crate::recovery;
+use crate::ssi::Ssi;
+use crate::temp::{TempFileManager, TempPage, TempPageId};
+use crate::trace;
+use crate::wal::{self, Checkpoint, LogRecord, Lsn, Record, Relation, TxId, Wal, DEFAULT_SEGMENT_SIZE};
 use std::{rc::Rc, cell::RefCell, cell::Cell};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryInto;
 use std::io;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
 // page
 // buffer pool manager
@@ -15,10 +28,140 @@ pub enum Error {
   Io(#[from] io::Error),
   #[error("no free buffer available in buffer pool")]
   NoFreeBuffer,
+  #[error(transparent)]
+  Wal(#[from] wal::Error),
+  #[error("statement timed out after {0:?}")]
+  StatementTimeout(Duration),
+  #[error("statement canceled")]
+  Canceled,
+  #[error("out of the memory budget of {0} bytes")]
+  OutOfMemoryBudget(usize),
+  #[error("cannot write in a read-only transaction")]
+  ReadOnly,
+  #[error("canceled on conflict with the replay of the log")]
+  ReplicationConflict,
+  #[error("terminated for being idle in a transaction for {0:?}")]
+  IdleTimeout(Duration),
+  #[error("terminated for holding a snapshot for {0:?}")]
+  SnapshotTimeout(Duration),
+  #[error("terminated by request")]
+  Terminated,
+  #[error("unlogged tables can't be read on a replica")]
+  Unlogged,
+  #[error("page {} fails its checksum, corrupted on disk", .0 .0)]
+  Checksum(PageId),
+}
+
+// Why a transaction was ended, or its snapshot taken away, from elsewhere, which it fails with.
+#[derive(Debug, Clone, Copy)]
+enum Cancel {
+  Conflict,
+  Idle(Duration),
+  Snapshot(Duration),
+  Terminated,
+}
+
+impl From<Cancel> for Error {
+    fn from(cancel: Cancel) -> Self {
+        match cancel {
+            Cancel::Conflict => Error::ReplicationConflict,
+            Cancel::Idle(timeout) => Error::IdleTimeout(timeout),
+            Cancel::Snapshot(timeout) => Error::SnapshotTimeout(timeout),
+            Cancel::Terminated => Error::Terminated,
+        }
+    }
+}
+
+// Cancels the statement running with it, from any thread, e.g. when its client has gone away:
+// the statement fails the next time it reads a page, is to wait for a lock, or an operator has gone
+// through its next so many rows, and is rolled back like any which fails. One running no statement has nothing to cancel, starting one clearing it.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_canceled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Memory an operator holds, reserved against the budget of the statement by
+// `BufferPoolManager::reserve_memory`, and given back when released or dropped, so that an
+// operator ending early, or started again and again for a subquery, doesn't keep it.
+#[derive(Debug, Default)]
+pub struct MemoryReservation {
+    // what the statement it's reserved for holds
+    used: Arc<AtomicUsize>,
+    bytes: usize,
+}
+
+impl MemoryReservation {
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn release(&mut self) {
+        self.used.fetch_sub(std::mem::take(&mut self.bytes), Ordering::Relaxed);
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+// When a commit is durable: before it returns, its record being flushed first, or once the log is
+// next flushed, by a later commit, a page being written back or the flusher, all of it being lost
+// to a crash before then (but never half of it).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Durability {
+    #[default]
+    Sync,
+    Async,
 }
 
 pub type Page = [u8; PAGE_SIZE as usize];
 
+// The 8 bytes before the checksum of every page (see `disk::CHECKSUM_OFFSET`) hold the LSN of the
+// last log record applied to it, 0 if none. The rest is the body, which is up to the users of
+// the page.
+pub const PAGE_BODY_SIZE: usize = CHECKSUM_OFFSET - 8;
+
+// Bytes of log after which commit takes a checkpoint, unless configured otherwise.
+pub const DEFAULT_CHECKPOINT_INTERVAL: u64 = DEFAULT_SEGMENT_SIZE;
+// Most threads the parallel workers of a transaction may use unless configured otherwise.
+pub const DEFAULT_MAX_PARALLEL_WORKERS: usize = 8;
+// Dirty pages written back after each commit, so that checkpoints don't have to wait for
+// eviction to write the pages changed long ago.
+const WRITE_BACK_PAGES: usize = 4;
+// Unchanged bytes between two changes to a page at which they're logged in records of their own,
+// about what a record costs.
+const DELTA_GAP: usize = 64;
+// The file of the pages of temporary tables, in the directory of the temporary files.
+const TEMP_TABLES_FILE: &str = "tables";
+// The bits of page ids telling where the pages are (see `PageId`).
+const SPACE_BITS: u64 = PageId::TEMP_BIT | PageId::MEMORY_BIT | PageId::UNLOGGED_BIT;
+
+pub fn page_lsn(page: &Page) -> Lsn {
+    u64::from_le_bytes(page[PAGE_BODY_SIZE..CHECKSUM_OFFSET].try_into().unwrap())
+}
+
+fn set_page_lsn(page: &mut Page, lsn: Lsn) {
+    page[PAGE_BODY_SIZE..CHECKSUM_OFFSET].copy_from_slice(&lsn.to_le_bytes());
+}
+
+// The body of `page` to log before changing it, if the change is its first since the checkpoint
+// begun at `checkpoint`, which a write of the page torn by a crash is redone from. Only pages in
+// the data file are written, those of in-memory tables being logged whole by each checkpoint.
+fn page_image(page_id: PageId, page: &Page, checkpoint: Lsn) -> Option<Record> {
+    let image = page[..PAGE_BODY_SIZE].to_vec();
+    Some(Record::PageImage { page_id, image }).filter(|_| page_id.in_data_file() && page_lsn(page) < checkpoint)
+}
+
 #[derive(Default, Clone, Copy)]
 pub struct BufferId(usize);
 
@@ -26,17 +169,201 @@ pub struct BufferPoolManager {
   disk: DiskManager,
   pool: BufferPool,
   page_table:HashMap<PageId, BufferId>,
+  stats: BufferStats,
+  // without a log, changes are written to pages in place and can't be rolled back
+  wal: Option<Wal>,
+  // the transactions which have begun and not ended, of which `current` is the one running
+  transactions: BTreeMap<TxId, Transaction>,
+  current: TransactionState,
+  next_txid: TxId,
+  aborted: BTreeSet<TxId>,
+  ssi: Ssi,
+  // the snapshots in use by id
+  snapshots: BTreeMap<u64, SnapshotUse>,
+  next_snapshot_id: u64,
+  // whether this is a replica, where transactions are those of the log replayed and only read
+  standby: bool,
+  // whether the checksums of the pages read are checked, which `Inspector` doesn't, to show what
+  // those corrupted hold
+  verify_checksums: bool,
+  // the transactions aborted by `reap` and the snapshots taken away by it or replay, which those
+  // running them fail with until they end
+  terminated: BTreeMap<TxId, Cancel>,
+  canceled: BTreeMap<u64, Cancel>,
+  // how long a transaction may be idle between statements, and hold on to a snapshot, before
+  // `reap` aborts it, None if as long as it likes
+  idle_timeout: Option<Duration>,
+  snapshot_timeout: Option<Duration>,
+  // whether transactions lock what they read and write rather than reading snapshots
+  locking: bool,
+  locks: LockManager,
+  // whether row changes are logged as `Record::Change` for logical decoding
+  logical: bool,
+  // whether the changes being made are in `redo_only`
+  redo_only: bool,
+  // pages freed which `create_page` reuses before allocating new ones, None outside of
+  // `reusing_pages`
+  free_pages: Option<BTreeSet<PageId>>,
+  // LSN of the latest checkpoint and the bytes of log after which the next one is taken
+  last_checkpoint: Lsn,
+  checkpoint_interval: u64,
+  // for the temporary pages of statements, opened when first needed unless set
+  temp: Option<TempFileManager>,
+  // for the pages of temporary tables, neither logged nor synced, opened in the directory of
+  // `temp` when first needed, with the pages freed by the sessions which had them
+  temp_tables: Option<DiskManager>,
+  temp_tables_free: BTreeSet<PageId>,
+  // the pages of in-memory tables, never evicted nor written to a file, numbered from 0 by the
+  // next to allocate (see `creating_memory_pages`)
+  memory_pages: BTreeMap<PageId, Rc<Buffer>>,
+  next_memory_page: u64,
+  // the bits set in the ids of the pages `create_page` creates, none for those of the data file
+  // (see `creating_temp_pages` and `creating_memory_pages`)
+  creating: u64,
+  // for the parallel workers of the transactions, as many as the most any has asked for, which
+  // is no more than `max_parallel_workers`
+  threads: ThreadPool,
+  max_parallel_workers: usize,
+  // the longest any statement may run, whatever its transaction sets, None if as long as it sets
+  max_statement_timeout: Option<Duration>,
+}
+
+// A transaction which has logged something.
+#[derive(Debug, Clone, Copy)]
+struct Transaction {
+  // its first and latest log records
+  first_lsn: Lsn,
+  last_lsn: Lsn,
+  // since when it's been between statements, if it is
+  idle_since: Option<Instant>,
+}
+
+// A snapshot in use, with the oldest transaction it may not see, the transaction reading it if
+// that has begun, and when it was taken.
+#[derive(Debug, Clone, Copy)]
+struct SnapshotUse {
+  xmin: TxId,
+  txid: Option<TxId>,
+  taken: Instant,
+}
+
+// The transaction the changes made through `update_page` belong to, with the snapshot it reads,
+// both of which are taken when first needed. Set aside by `switch` for another to run meanwhile.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionState {
+  txid: Option<TxId>,
+  snapshot: Option<Snapshot>,
+  // by which the snapshot is registered as taken
+  snapshot_id: u64,
+  // set if begun by `begin`, then running until committed or aborted
+  isolation: Option<Isolation>,
+  // set once a statement of the block has failed, rolling back its transaction, until it's ended
+  failed: bool,
+  // declared read-only, so it takes no locks and mustn't write
+  read_only: bool,
+  // how long a lock is waited for and a statement may run, None if as long as it takes
+  lock_timeout: Option<Duration>,
+  statement_timeout: Option<Duration>,
+  // when the running statement times out
+  deadline: Option<Instant>,
+  cancel: CancelToken,
+  // bytes the operators of a statement may hold on to, as much as they like if None, and hold
+  memory_budget: Option<usize>,
+  memory_used: Arc<AtomicUsize>,
+  // allocated by the running statement, freed when the next one starts or the transaction ends
+  temp_pages: Vec<TempPageId>,
+  // threads scans may use besides this one, none if 0
+  parallel_workers: usize,
+  durability: Durability,
+  // what BEGIN without a level begins
+  default_isolation: Isolation,
+}
+
+impl TransactionState {
+    // Isolation level of the transaction if it was begun by `begin`.
+    pub fn isolation(&self) -> Option<Isolation> {
+        self.isolation
+    }
+
+    // Whether the transaction begun by `begin` has failed (see `BufferPoolManager::fail_block`).
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+
+    // The transaction, if it has written.
+    pub fn txid(&self) -> Option<TxId> {
+        self.txid
+    }
+}
+
+// What a statement read and spilled to, kept past its end for a cursor to go on with it in later
+// statements (see `BufferPoolManager::hold_statement`).
+#[derive(Debug)]
+pub struct HeldStatement {
+  snapshot: Snapshot,
+  snapshot_id: u64,
+  temp_pages: Vec<TempPageId>,
+}
+
+// Number of fetch_page calls served from the pool (hits) and read from disk (misses).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BufferStats {
+  pub hits: u64,
+  pub misses: u64,
+}
+
+impl BufferStats {
+    // The fetches since the pool's counts were `earlier`.
+    pub fn since(self, earlier: BufferStats) -> BufferStats {
+        BufferStats { hits: self.hits - earlier.hits, misses: self.misses - earlier.misses }
+    }
+}
+
+// How full the pool is: its frames, those holding a page, and those holding one changed since it
+// was last written back.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PoolUsage {
+    pub frames: usize,
+    pub pages: usize,
+    pub dirty: usize,
+}
+
+// A transaction which has begun and not ended, as `BufferPoolManager::transactions` tells of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionInfo {
+    pub txid: TxId,
+    pub first_lsn: Lsn,
+    pub last_lsn: Lsn,
+    // for how long it's been between statements, if it is
+    pub idle: Option<Duration>,
+    // whether it's the one switched in
+    pub current: bool,
+}
+
+// Which frame a page read in takes the place of.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    // the first the clock sweep finds unused since it last went past, each use sparing it once
+    #[default]
+    Clock,
+    // the one used the longest ago
+    Lru,
 }
 
 pub struct BufferPool {
   frames: Vec<Frame>,
 // buffer with this next_victim_id will be judged whether it is a victim next time.
   next_victim_id: BufferId,
+  policy: EvictionPolicy,
+  // uses of the frames so far, by which they're ordered for LRU
+  uses: u64,
 }
 
 #[derive(Debug, Default)]
 pub struct Frame {
   usage_count: u64,
+  // `uses` at the last use
+  last_used: u64,
   buffer: Rc<Buffer>,
 }
 
@@ -45,6 +372,8 @@ pub struct Buffer {
   pub page_id: PageId,
   pub page: RefCell<Page>,
   pub is_dirty: Cell<bool>,
+  // LSN of the first logged change since the page was last written back, 0 if there is none
+  pub rec_lsn: Cell<Lsn>,
 }
 
 impl Default for Buffer {
@@ -53,21 +382,40 @@ impl Default for Buffer {
             page_id: Default::default(),
             page: RefCell::new([0u8; PAGE_SIZE as usize]),
             is_dirty: Cell::new(false),
+            rec_lsn: Cell::new(0),
         }
     }
 }
 
 impl BufferPool {
     pub fn new(pool_size: usize) -> Self {
+        Self::with_policy(pool_size, EvictionPolicy::default())
+    }
+
+    pub fn with_policy(pool_size: usize, policy: EvictionPolicy) -> Self {
         let mut frames = vec![];
         frames.resize_with(pool_size, Default::default);
         let next_victim_id = BufferId::default();
         Self {
             frames,
             next_victim_id,
+            policy,
+            uses: 0,
         }
     }
 
+    pub fn policy(&self) -> EvictionPolicy {
+        self.policy
+    }
+
+    // Records a use of the frame, the first since its page was read in if `first`.
+    fn record_use(&mut self, buffer_id: BufferId, first: bool) {
+        self.uses += 1;
+        let frame = &mut self.frames[buffer_id.0];
+        frame.usage_count = if first { 1 } else { frame.usage_count + 1 };
+        frame.last_used = self.uses;
+    }
+
     fn size(&self) -> usize {
         self.frames.len()
     }
@@ -78,6 +426,10 @@ impl BufferPool {
     // 2. If the checked buffer is NOT referenced at the time, decrement its usage_count.
     // 3. If the checked buffer is referenced at the time, skip this. If this happens #size times, returns None.
     fn evict(&mut self) -> Option<BufferId> {
+        if self.policy == EvictionPolicy::Lru {
+            let unreferenced = self.frames.iter().enumerate().filter(|(_, frame)| Rc::strong_count(&frame.buffer) == 1);
+            return unreferenced.min_by_key(|(_, frame)| frame.last_used).map(|(id, _)| BufferId(id));
+        }
         let pool_size = self.size();
         let mut num_consecutively_checked_buffers = 0;
 
@@ -116,39 +468,1147 @@ impl BufferPoolManager {
             disk,
             pool,
             page_table,
+            stats: BufferStats::default(),
+            wal: None,
+            transactions: BTreeMap::new(),
+            current: TransactionState::default(),
+            next_txid: 1,
+            aborted: BTreeSet::new(),
+            ssi: Ssi::default(),
+            snapshots: BTreeMap::new(),
+            next_snapshot_id: 1,
+            standby: false,
+            verify_checksums: true,
+            terminated: BTreeMap::new(),
+            canceled: BTreeMap::new(),
+            idle_timeout: None,
+            snapshot_timeout: None,
+            locking: false,
+            logical: false,
+            locks: LockManager::default(),
+            redo_only: false,
+            free_pages: None,
+            last_checkpoint: 0,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            temp: None,
+            temp_tables: None,
+            temp_tables_free: BTreeSet::new(),
+            memory_pages: BTreeMap::new(),
+            next_memory_page: 0,
+            creating: 0,
+            threads: ThreadPool::default(),
+            max_parallel_workers: DEFAULT_MAX_PARALLEL_WORKERS,
+            max_statement_timeout: None,
+        }
+    }
+
+    pub fn set_checkpoint_interval(&mut self, bytes: u64) {
+        self.checkpoint_interval = bytes;
+    }
+
+    // Sets how long a transaction may be idle between statements before `reap` aborts it.
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout = timeout;
+    }
+
+    // Sets how long a transaction may hold on to a snapshot before `reap` aborts it.
+    pub fn set_snapshot_timeout(&mut self, timeout: Option<Duration>) {
+        self.snapshot_timeout = timeout;
+    }
+
+    // Opens a database whose changes are logged in `wal`, first recovering it from a crash:
+    // the changes of committed transactions are redone and those of the others undone.
+    pub fn with_wal(disk: DiskManager, pool: BufferPool, wal: Wal) -> Result<Self, Error> {
+        let mut bufmgr = Self::new(disk, pool);
+        bufmgr.logical = wal.logical()?;
+        bufmgr.wal = Some(wal);
+        recovery::recover(&mut bufmgr)?;
+        Ok(bufmgr)
+    }
+
+    pub(crate) fn wal(&mut self) -> Option<&mut Wal> {
+        self.wal.as_mut()
+    }
+
+    pub(crate) fn disk(&mut self) -> &mut DiskManager {
+        &mut self.disk
+    }
+
+    pub(crate) fn set_next_txid(&mut self, txid: TxId) {
+        self.next_txid = txid;
+    }
+
+    pub(crate) fn set_aborted(&mut self, aborted: BTreeSet<TxId>) {
+        self.aborted = aborted;
+    }
+
+    // Id of the current transaction, which is begun if there is none. None without a log, where
+    // changes aren't made in transactions. Fails in a read-only one, which only needs one to write,
+    // and on a replica.
+    pub fn txid(&mut self) -> Result<Option<TxId>, Error> {
+        if self.current.read_only || self.standby {
+            return Err(Error::ReadOnly);
+        }
+        self.begin_txid()
+    }
+
+    fn begin_txid(&mut self) -> Result<Option<TxId>, Error> {
+        if let Some(cancel) = self.canceled() {
+            return Err(cancel.into());
+        }
+        let Some(wal) = &mut self.wal else {
+            return Ok(None);
+        };
+        if let Some(txid) = self.current.txid {
+            return Ok(Some(txid));
+        }
+        let txid = self.next_txid;
+        self.next_txid += 1;
+        let lsn = wal.append(txid, None, &Record::Begin)?;
+        self.transactions.insert(txid, Transaction { first_lsn: lsn, last_lsn: lsn, idle_since: None });
+        self.current.txid = Some(txid);
+        if let Some(snapshot) = &mut self.current.snapshot {
+            snapshot.txid = txid;
+            self.snapshots.get_mut(&self.current.snapshot_id).unwrap().txid = Some(txid);
+        }
+        Ok(Some(txid))
+    }
+
+    // Id of the current transaction if it has begun.
+    pub fn current_txid(&self) -> Option<TxId> {
+        self.current.txid
+    }
+
+    // Begins a transaction of many statements, each ended by `end_statement`. Until then, what's
+    // done is a transaction of its own. A serializable one begins right away, with its snapshot, even if read-only.
+    pub fn begin(&mut self, isolation: Isolation) -> Result<(), Error> {
+        self.current.isolation = Some(isolation);
+        if isolation == Isolation::Serializable {
+            if let Some(txid) = self.begin_txid()? {
+                let snapshot = self.snapshot().clone();
+                self.ssi.register(txid, snapshot);
+            }
+        }
+        Ok(())
+    }
+
+    // Declares the current transaction read-only, or not. A read-only one fails to write, and in
+    // return takes no locks, reading its snapshot even with locking.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.current.read_only = read_only;
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.current.read_only
+    }
+
+    // Makes this a replica, whose transactions are replayed from the log of the primary by
+    // `replay`, so that those running here only read, with snapshots of them.
+    pub fn set_standby(&mut self) {
+        self.standby = true;
+    }
+
+    pub fn set_verify_checksums(&mut self, verify: bool) {
+        self.verify_checksums = verify;
+    }
+
+    pub fn standby(&self) -> bool {
+        self.standby
+    }
+
+    // Keeps track of the transactions of the primary as `record` of its log is replayed.
+    pub(crate) fn replay(&mut self, record: &LogRecord) {
+        let (txid, lsn) = (record.txid, record.lsn);
+        match &record.record {
+            Record::Begin => {
+                self.transactions.insert(txid, Transaction { first_lsn: lsn, last_lsn: lsn, idle_since: None });
+                self.next_txid = self.next_txid.max(txid + 1);
+            }
+            Record::Commit { .. } => {
+                self.transactions.remove(&txid);
+            }
+            // the end of one rolled back in recovery after the primary crashed
+            Record::Abort | Record::End if self.transactions.remove(&txid).is_some() => {
+                self.aborted.insert(txid);
+            }
+            // nothing is logged between the two records, so the transactions running are just
+            // those, the others having been lost in a crash
+            Record::CheckpointEnd(checkpoint) => {
+                let running: BTreeMap<_, _> = checkpoint
+                    .transactions
+                    .iter()
+                    .map(|&(txid, lsn)| {
+                        // begun before the log replayed, so where isn't known
+                        let txn = self.transactions.get(&txid).copied();
+                        (txid, txn.unwrap_or(Transaction { first_lsn: 0, last_lsn: lsn, idle_since: None }))
+                    })
+                    .collect();
+                let lost = std::mem::replace(&mut self.transactions, running);
+                self.aborted.extend(lost.into_keys().filter(|txid| !self.transactions.contains_key(txid)));
+                self.aborted.extend(checkpoint.aborted.iter().copied());
+                self.next_txid = self.next_txid.max(checkpoint.next_txid);
+            }
+            _ => {}
+        }
+    }
+
+    // The transactions replayed so far on a replica, as `replay` takes them in again.
+    pub(crate) fn standby_state(&self) -> Checkpoint {
+        Checkpoint {
+            next_txid: self.next_txid,
+            transactions: self.transactions.iter().map(|(&txid, txn)| (txid, txn.last_lsn)).collect(),
+            dirty_pages: vec![],
+            aborted: self.aborted.iter().copied().collect(),
+        }
+    }
+
+    // The snapshots in use which may see versions vacuum removes before `horizon`, as the
+    // primary logs with `log_vacuum`.
+    pub(crate) fn conflicting_snapshots(&self, horizon: TxId) -> Vec<u64> {
+        self.snapshots.iter().filter(|(_, snapshot)| snapshot.xmin < horizon).map(|(&id, _)| id).collect()
+    }
+
+    // Makes the transactions reading the snapshots `ids` fail from their next page fetch on.
+    pub(crate) fn cancel_snapshots(&mut self, ids: &[u64]) {
+        for id in ids {
+            self.snapshots.remove(id);
+            self.canceled.insert(*id, Cancel::Conflict);
+        }
+    }
+
+    // Logs that vacuum is about to remove the versions no snapshot sees which only transactions
+    // before `horizon` have ended, so that replicas can tell which of their readers may see them.
+    pub(crate) fn log_vacuum(&mut self, horizon: TxId) -> Result<(), Error> {
+        if self.txid()?.is_some() {
+            self.wal.as_mut().unwrap().append(0, None, &Record::Vacuum { horizon })?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn ssi(&mut self) -> &mut Ssi {
+        &mut self.ssi
+    }
+
+    // Turns locking on or off, which is best done while no transaction is running.
+    pub fn set_locking(&mut self, locking: bool) {
+        self.locking = locking;
+    }
+
+    pub fn locking(&self) -> bool {
+        self.locking
+    }
+
+    pub(crate) fn locks(&mut self) -> &mut LockManager {
+        &mut self.locks
+    }
+
+    // Turns logging row changes for logical decoding on or off, which is best done while no
+    // transaction is running, since one is decoded from the changes logged. It stays so across
+    // restarts, kept in the control data of the log.
+    pub fn set_logical(&mut self, logical: bool) -> Result<(), Error> {
+        if let Some(wal) = &mut self.wal {
+            wal.set_logical(logical)?;
+        }
+        self.logical = logical;
+        Ok(())
+    }
+
+    pub fn logical(&self) -> bool {
+        self.logical
+    }
+
+    // Logs that a row of `table`, of `relation`, changed from `old` to `new` in the current
+    // transaction if logical decoding is turned on.
+    pub fn log_change(&mut self, table: PageId, relation: Option<&Relation>, old: Option<Vec<u8>>, new: Option<Vec<u8>>) -> Result<(), Error> {
+        if !self.logical || !table.is_logged() {
+            return Ok(());
+        }
+        let Some(txid) = self.txid()? else {
+            return Ok(());
+        };
+        let txn = self.transactions.get_mut(&txid).unwrap();
+        txn.last_lsn = self.wal.as_mut().unwrap().append(txid, Some(txn.last_lsn), &Record::Change { table, relation: relation.cloned(), old, new })?;
+        Ok(())
+    }
+
+    // LSN of the first record of the oldest running transaction, if any.
+    pub(crate) fn oldest_lsn(&self) -> Option<Lsn> {
+        self.transactions.values().map(|txn| txn.first_lsn).min()
+    }
+
+    // Keeps the log from `lsn` on for the slot `name`, e.g. what a consumer of logical changes
+    // resumes from, or drops the slot if None.
+    pub fn retain_log(&mut self, name: &str, lsn: Option<Lsn>) -> Result<(), Error> {
+        if let Some(wal) = &mut self.wal {
+            wal.set_slot(name, lsn)?;
+        }
+        Ok(())
+    }
+
+    // Isolation level of the current transaction if it was begun by `begin`.
+    pub fn isolation(&self) -> Option<Isolation> {
+        self.current.isolation
+    }
+
+    // Leaves the transaction block of `isolation`, whose transaction `abort` has rolled back,
+    // failed: it goes on without one, for the statements up to the COMMIT or ROLLBACK ending it
+    // to be refused.
+    pub fn fail_block(&mut self, isolation: Isolation) {
+        self.current.isolation = Some(isolation);
+        self.current.failed = true;
+    }
+
+    // Whether the current transaction block has failed.
+    pub fn block_failed(&self) -> bool {
+        self.current.failed
+    }
+
+    // Sets how long the current transaction waits for a lock before failing.
+    pub fn set_lock_timeout(&mut self, timeout: Option<Duration>) {
+        self.current.lock_timeout = timeout;
+    }
+
+    pub fn lock_timeout(&self) -> Option<Duration> {
+        self.current.lock_timeout
+    }
+
+    // Sets how long each statement of the current transaction may run before failing, which it
+    // does when fetching a page after that, no longer than `max_statement_timeout` if it's set.
+    pub fn set_statement_timeout(&mut self, timeout: Option<Duration>) {
+        self.current.statement_timeout = match (timeout, self.max_statement_timeout) {
+            (Some(timeout), Some(max)) => Some(timeout.min(max)),
+            (timeout, max) => timeout.or(max),
+        };
+    }
+
+    // Sets the longest the statements of any transaction may run, from the next one they set
+    // their timeout for.
+    pub fn set_max_statement_timeout(&mut self, timeout: Option<Duration>) {
+        self.max_statement_timeout = timeout;
+    }
+
+    // Sets how many threads a parallel scan of the current transaction may use, no more than
+    // `max_parallel_workers` whatever it asks for.
+    pub fn set_parallel_workers(&mut self, workers: usize) {
+        self.current.parallel_workers = workers.min(self.max_parallel_workers);
+    }
+
+    // Sets the most threads the parallel workers of any transaction may use, those started
+    // already being kept.
+    pub fn set_max_parallel_workers(&mut self, workers: usize) {
+        self.max_parallel_workers = workers;
+        self.current.parallel_workers = self.current.parallel_workers.min(workers);
+    }
+
+    pub fn max_parallel_workers(&self) -> usize {
+        self.max_parallel_workers
+    }
+
+    pub fn parallel_workers(&self) -> usize {
+        self.current.parallel_workers
+    }
+
+    // The threads the parallel workers of the current transaction run on, started the first time
+    // it asks for more of them than there are.
+    pub fn thread_pool(&mut self) -> &ThreadPool {
+        self.threads.grow(self.current.parallel_workers);
+        &self.threads
+    }
+
+    // Sets whether the current transaction's commit waits for its record to be flushed.
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.current.durability = durability;
+    }
+
+    pub fn durability(&self) -> Durability {
+        self.current.durability
+    }
+
+    // Sets the isolation level of the transactions begun without one.
+    pub fn set_default_isolation(&mut self, isolation: Isolation) {
+        self.current.default_isolation = isolation;
+    }
+
+    pub fn default_isolation(&self) -> Isolation {
+        self.current.default_isolation
+    }
+
+    pub fn set_cancel_token(&mut self, token: CancelToken) {
+        self.current.cancel = token;
+    }
+
+    pub fn is_canceled(&self) -> bool {
+        self.current.cancel.is_canceled()
+    }
+
+    pub fn set_memory_budget(&mut self, bytes: Option<usize>) {
+        self.current.memory_budget = bytes;
+    }
+
+    // Adds `bytes` more held by an operator of the running statement to `reservation`, failing
+    // without if that would take it over budget, for the operator to spill what it holds or fail.
+    // What it holds from an earlier statement, that of a cursor say, is counted in this one's.
+    pub fn reserve_memory(&mut self, reservation: &mut MemoryReservation, bytes: usize) -> Result<(), Error> {
+        let used = &self.current.memory_used;
+        if !Arc::ptr_eq(&reservation.used, used) {
+            let held = reservation.bytes;
+            reservation.release();
+            reservation.used = used.clone();
+            reservation.bytes = held;
+            used.fetch_add(held, Ordering::Relaxed);
+        }
+        match self.current.memory_budget {
+            Some(budget) if used.load(Ordering::Relaxed) + bytes > budget => Err(Error::OutOfMemoryBudget(budget)),
+            _ => {
+                used.fetch_add(bytes, Ordering::Relaxed);
+                reservation.bytes += bytes;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.current.memory_used.load(Ordering::Relaxed)
+    }
+
+    pub fn set_temp_files(&mut self, temp: TempFileManager) {
+        self.temp = Some(temp);
+    }
+
+    pub fn temp_files(&self) -> Option<&TempFileManager> {
+        self.temp.as_ref()
+    }
+
+    // A temporary page for the running statement, e.g. to spill to.
+    pub fn allocate_temp_page(&mut self) -> Result<TempPageId, Error> {
+        if self.temp.is_none() {
+            self.temp = Some(TempFileManager::open_default()?);
+        }
+        let page_id = self.temp.as_mut().unwrap().allocate()?;
+        self.current.temp_pages.push(page_id);
+        Ok(page_id)
+    }
+
+    pub fn temp_page(&mut self, page_id: TempPageId) -> Result<&TempPage, Error> {
+        self.check_interrupted()?;
+        Ok(self.temp.as_mut().expect("no temporary page allocated").page(page_id)?)
+    }
+
+    pub fn temp_page_mut(&mut self, page_id: TempPageId) -> Result<&mut TempPage, Error> {
+        self.check_interrupted()?;
+        Ok(self.temp.as_mut().expect("no temporary page allocated").page_mut(page_id)?)
+    }
+
+    fn free_temp_pages(&mut self) {
+        if let Some(temp) = &mut self.temp {
+            for page_id in self.current.temp_pages.drain(..) {
+                temp.free(page_id);
+            }
+        }
+    }
+
+    pub fn start_statement(&mut self) {
+        self.free_temp_pages();
+        self.current.deadline = self.current.statement_timeout.map(|timeout| Instant::now() + timeout);
+        self.current.cancel.0.store(false, Ordering::Relaxed);
+        self.current.memory_used = Arc::default();
+        self.set_idle(None);
+    }
+
+    // Lets the next statement read a new snapshot in read committed.
+    pub fn end_statement(&mut self) {
+        self.current.deadline = None;
+        self.set_idle(Some(Instant::now()));
+        if self.current.isolation == Some(Isolation::ReadCommitted) {
+            self.current.snapshot = None;
+            self.snapshots.remove(&self.current.snapshot_id);
+            self.canceled.remove(&self.current.snapshot_id);
+        }
+    }
+
+    fn set_idle(&mut self, since: Option<Instant>) {
+        if let Some(txn) = self.current.txid.and_then(|txid| self.transactions.get_mut(&txid)) {
+            txn.idle_since = since;
+        }
+    }
+
+    // Why the current transaction has been terminated or its snapshot taken away, if it has.
+    fn canceled(&self) -> Option<Cancel> {
+        let terminated = self.current.txid.and_then(|txid| self.terminated.get(&txid));
+        let canceled = self.current.snapshot.as_ref().and_then(|_| self.canceled.get(&self.current.snapshot_id));
+        terminated.or(canceled).copied()
+    }
+
+    // Aborts the transactions, other than the current one, which have been idle or held on to a
+    // snapshot for longer than allowed, so that they don't keep vacuum from removing versions or
+    // others waiting for their locks, e.g. those whose clients have gone away. Each fails from
+    // then on until ended. Run on every commit and before vacuum. Returns how many there were.
+    pub fn reap(&mut self) -> Result<usize, Error> {
+        let now = Instant::now();
+        let over = |since: Instant, timeout: Option<Duration>| timeout.filter(|&timeout| now.duration_since(since) >= timeout);
+        let mut reaped = vec![];
+        for (&txid, txn) in &self.transactions {
+            let timeout = txn.idle_since.and_then(|since| over(since, self.idle_timeout));
+            if let Some(timeout) = timeout.filter(|_| Some(txid) != self.current.txid) {
+                reaped.push((Some(txid), None, Cancel::Idle(timeout)));
+            }
+        }
+        for (&id, snapshot) in &self.snapshots {
+            if let Some(timeout) = over(snapshot.taken, self.snapshot_timeout).filter(|_| id != self.current.snapshot_id) {
+                reaped.push((snapshot.txid, Some(id), Cancel::Snapshot(timeout)));
+            }
+        }
+        let mut count = 0;
+        for (txid, snapshot_id, cancel) in reaped {
+            let mut terminated = false;
+            if let Some(id) = snapshot_id {
+                self.snapshots.remove(&id);
+                self.canceled.insert(id, cancel);
+                terminated = txid.is_none();
+            }
+            // one both idle and holding a snapshot is aborted once
+            if let Some(txid) = txid.filter(|txid| !self.terminated.contains_key(txid)) {
+                self.terminate_with(txid, cancel)?;
+                terminated = true;
+            }
+            count += terminated as usize;
+        }
+        Ok(count)
+    }
+
+    // Aborts the transaction `txid`, of a session terminated on request, which fails from then
+    // on until ended as those reaped do. Returns whether it was running, other than as the
+    // current one.
+    pub fn terminate(&mut self, txid: TxId) -> Result<bool, Error> {
+        if !self.transactions.contains_key(&txid) || self.terminated.contains_key(&txid) || self.current.txid == Some(txid) {
+            return Ok(false);
+        }
+        self.terminate_with(txid, Cancel::Terminated)?;
+        Ok(true)
+    }
+
+    fn terminate_with(&mut self, txid: TxId, cancel: Cancel) -> Result<(), Error> {
+        self.terminated.insert(txid, cancel);
+        let state = self.switch(Default::default());
+        let result = self.roll_back(txid);
+        self.switch(state);
+        result
+    }
+
+    // Keeps the snapshot and the temporary pages of the running statement past its end, for a
+    // cursor to go on reading them in later statements of the transaction (see `resume`). The
+    // snapshot is registered anew, so that vacuum keeps what it sees until it's released.
+    pub fn hold_statement(&mut self) -> HeldStatement {
+        let snapshot = self.snapshot().clone();
+        let snapshot_id = self.next_snapshot_id;
+        self.next_snapshot_id += 1;
+        // or taken away with it, if it has been
+        if let Some(&snapshot_use) = self.snapshots.get(&self.current.snapshot_id) {
+            self.snapshots.insert(snapshot_id, snapshot_use);
+        } else if let Some(&cancel) = self.canceled.get(&self.current.snapshot_id) {
+            self.canceled.insert(snapshot_id, cancel);
+        }
+        HeldStatement { snapshot, snapshot_id, temp_pages: std::mem::take(&mut self.current.temp_pages) }
+    }
+
+    // Runs `f` in the running statement as if it were the one `held` was, reading its snapshot
+    // and allocating its temporary pages.
+    pub fn resume<T>(&mut self, held: &mut HeldStatement, f: impl FnOnce(&mut Self) -> T) -> T {
+        let snapshot = self.current.snapshot.replace(held.snapshot.clone());
+        let snapshot_id = std::mem::replace(&mut self.current.snapshot_id, held.snapshot_id);
+        std::mem::swap(&mut self.current.temp_pages, &mut held.temp_pages);
+        let result = f(self);
+        std::mem::swap(&mut self.current.temp_pages, &mut held.temp_pages);
+        self.current.snapshot = snapshot;
+        self.current.snapshot_id = snapshot_id;
+        result
+    }
+
+    pub fn release(&mut self, held: HeldStatement) {
+        self.snapshots.remove(&held.snapshot_id);
+        self.canceled.remove(&held.snapshot_id);
+        if let Some(temp) = &mut self.temp {
+            for page_id in held.temp_pages {
+                temp.free(page_id);
+            }
+        }
+    }
+
+    // Sets aside the current transaction for `state`, e.g. one set aside earlier, returning it.
+    pub fn switch(&mut self, state: TransactionState) -> TransactionState {
+        std::mem::replace(&mut self.current, state)
+    }
+
+    // The snapshot of the current transaction, taken now if it hasn't read anything yet.
+    pub fn snapshot(&mut self) -> &Snapshot {
+        if self.current.snapshot.is_none() {
+            let snapshot = Snapshot {
+                txid: self.current.txid.unwrap_or(FROZEN),
+                next: self.next_txid,
+                running: self.transactions.keys().copied().filter(|&txid| Some(txid) != self.current.txid).collect(),
+            };
+            let xmin = snapshot.running.first().copied().unwrap_or(snapshot.next);
+            self.current.snapshot_id = self.next_snapshot_id;
+            self.next_snapshot_id += 1;
+            let snapshot_use = SnapshotUse { xmin, txid: self.current.txid, taken: Instant::now() };
+            self.snapshots.insert(self.current.snapshot_id, snapshot_use);
+            self.current.snapshot = Some(snapshot);
+        }
+        self.current.snapshot.as_ref().unwrap()
+    }
+
+    // The transactions before this one have ended, and those which have committed are seen by
+    // every snapshot in use and every one taken from now on.
+    pub fn horizon(&self) -> TxId {
+        let oldest_running = self.transactions.keys().next().copied();
+        let xmins = self.snapshots.values().map(|snapshot| snapshot.xmin);
+        xmins.chain(oldest_running).min().unwrap_or(self.next_txid).min(self.next_txid)
+    }
+
+    // Forgets that the transactions before `txid` aborted, which is for once none of their
+    // versions are left, e.g. when every table has been vacuumed since `txid` was the horizon.
+    // Nothing is forgotten while there are temporary tables, which only their sessions vacuum.
+    pub fn forget_aborted(&mut self, txid: TxId) {
+        if self.temp_tables.as_ref().is_some_and(|file| file.num_pages() > self.temp_tables_free.len() as u64) {
+            return;
+        }
+        self.aborted = self.aborted.split_off(&txid);
+    }
+
+    pub fn is_committed(&self, txid: TxId) -> bool {
+        txid == FROZEN || txid < self.next_txid && !self.aborted.contains(&txid) && !self.transactions.contains_key(&txid)
+    }
+
+    // Whether the current transaction sees the changes of `txid`. With locking, that's if it has
+    // committed by now, the rows read being locked, unless it's read-only.
+    pub fn sees(&mut self, txid: TxId) -> bool {
+        if self.locking && !self.current.read_only {
+            return !self.aborted.contains(&txid) && !self.is_running_elsewhere(txid);
+        }
+        !self.aborted.contains(&txid) && self.snapshot().sees(txid)
+    }
+
+    // What `sees` decides by, unless it needs more than the snapshot: with locking, or in
+    // serializable, where the versions read are recorded.
+    pub fn visibility(&mut self) -> Option<Visibility> {
+        if self.locking && !self.current.read_only || self.current.isolation == Some(Isolation::Serializable) {
+            return None;
+        }
+        Some(Visibility { snapshot: self.snapshot().clone(), aborted: self.aborted.clone() })
+    }
+
+    pub fn is_aborted(&self, txid: TxId) -> bool {
+        self.aborted.contains(&txid)
+    }
+
+    // Whether `txid` is running, other than the current transaction.
+    pub fn is_running_elsewhere(&self, txid: TxId) -> bool {
+        Some(txid) != self.current.txid && self.transactions.contains_key(&txid)
+    }
+
+    // Runs `f` with the changes it makes logged as one which is redone but never rolled back once
+    // `f` has succeeded, e.g. row versions, which are invisible once their transaction aborts, and
+    // the splits of the nodes they go in. Those of an `f` which fails, or is cut short by a crash,
+    // are rolled back with the transaction, so that none of them is left half done.
+    pub fn redo_only<T, E: From<Error>>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, E>) -> Result<T, E> {
+        if self.redo_only {
+            return f(self);
+        }
+        // the transaction may begin in `f`
+        let undo_next = self.current.txid.map(|txid| self.transactions[&txid].last_lsn);
+        self.redo_only = true;
+        let result = f(self);
+        self.redo_only = false;
+        let value = result?;
+        if let Some(txid) = self.current.txid {
+            let txn = self.transactions.get_mut(&txid).unwrap();
+            if Some(txn.last_lsn) != undo_next {
+                let record = Record::AtomicEnd { undo_next };
+                txn.last_lsn = self.wal.as_mut().unwrap().append(txid, Some(txn.last_lsn), &record).map_err(Error::from)?;
+            }
+        }
+        Ok(value)
+    }
+
+    // Runs `f` with the pages it creates taken from `free`, the lowest first, before any are
+    // allocated at the end of the file, leaving in `free` those it hasn't taken along with those
+    // it's freed (see `free_page`). A page taken keeps what it held until it's written, as it's a
+    // node written whole which goes in it, its changes logged against what was there.
+    pub fn reusing_pages<T>(&mut self, free: &mut BTreeSet<PageId>, f: impl FnOnce(&mut Self) -> T) -> T {
+        let outer = self.free_pages.replace(std::mem::take(free));
+        let result = f(self);
+        *free = std::mem::replace(&mut self.free_pages, outer).unwrap();
+        result
+    }
+
+    // Frees a page no longer of any tree, for `create_page` to reuse within the `reusing_pages`
+    // it's freed in.
+    pub fn free_page(&mut self, page_id: PageId) {
+        self.free_pages.as_mut().expect("freed outside of reusing_pages").insert(page_id);
+    }
+
+    // Runs `f` with the pages it creates being those of temporary tables: kept in a file of their
+    // own, which is neither logged nor synced, and removed when the database is next opened.
+    // Pages are created beside them as their trees grow (see `create_page_beside`).
+    pub fn creating_temp_pages<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.creating_pages(PageId::TEMP_BIT, f)
+    }
+
+    // Runs `f` with the pages it creates being those of in-memory tables: kept in the pool, never
+    // evicted, rather than in any file. Those of logged ones are logged as others are, and whole
+    // at each checkpoint, for recovery to bring them back from the log alone. Those of unlogged
+    // ones aren't logged at all, and are lost when the database goes down.
+    pub fn creating_memory_pages<T>(&mut self, logged: bool, f: impl FnOnce(&mut Self) -> T) -> T {
+        let bits = if logged { PageId::MEMORY_BIT } else { PageId::MEMORY_BIT | PageId::UNLOGGED_BIT };
+        self.creating_pages(bits, f)
+    }
+
+    // Runs `f` with the pages it creates where `page_id` is, e.g. for an index of the table whose
+    // tree that is.
+    pub fn creating_pages_beside<T>(&mut self, page_id: PageId, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.creating_pages(page_id.0 & SPACE_BITS, f)
+    }
+
+    // A new page where `page_id` is, e.g. for a node split off the one in it.
+    pub fn create_page_beside(&mut self, page_id: PageId) -> Result<Rc<Buffer>, Error> {
+        self.creating_pages_beside(page_id, Self::create_page)
+    }
+
+    fn creating_pages<T>(&mut self, bits: u64, f: impl FnOnce(&mut Self) -> T) -> T {
+        let outer = std::mem::replace(&mut self.creating, bits);
+        let result = f(self);
+        self.creating = outer;
+        result
+    }
+
+    // Pages of in-memory tables.
+    pub fn num_memory_pages(&self) -> usize {
+        self.memory_pages.len()
+    }
+
+    // The pages of logged in-memory tables, e.g. for a replica to keep at a restart point along
+    // with the data file, and to load again with `load_memory_page`.
+    pub(crate) fn logged_memory_pages(&self) -> Vec<(PageId, Box<Page>)> {
+        let logged = self.memory_pages.iter().filter(|(page_id, _)| page_id.is_logged());
+        logged.map(|(&page_id, buffer)| (page_id, Box::new(*buffer.page.borrow()))).collect()
+    }
+
+    pub(crate) fn load_memory_page(&mut self, page_id: PageId, page: &Page) {
+        self.mark_allocated(page_id);
+        let buffer = Buffer { page_id, ..Buffer::default() };
+        *buffer.page.borrow_mut() = *page;
+        self.memory_pages.insert(page_id, Rc::new(buffer));
+    }
+
+    // Makes sure `page_id` is never allocated again, e.g. as it's come up in the log.
+    pub(crate) fn mark_allocated(&mut self, page_id: PageId) {
+        if page_id.is_memory() {
+            self.next_memory_page = self.next_memory_page.max((page_id.0 & !SPACE_BITS) + 1);
+        } else if !page_id.is_temp() {
+            self.disk.mark_allocated(page_id);
+        }
+    }
+
+    // Frees the pages of temporary tables no longer used, e.g. of a session which has ended, for
+    // `create_page` to reuse, dropping them from the pool unwritten.
+    pub fn free_temp_table_pages(&mut self, pages: impl IntoIterator<Item = PageId>) {
+        for page_id in pages {
+            if let Some(buffer_id) = self.page_table.remove(&page_id) {
+                self.pool.frames[buffer_id.0].buffer = Rc::new(Buffer::default());
+            }
+            self.temp_tables_free.insert(page_id);
+        }
+    }
+
+    // Pages of temporary tables, and how many of them are free.
+    pub fn num_temp_table_pages(&self) -> u64 {
+        self.temp_tables.as_ref().map_or(0, DiskManager::num_pages)
+    }
+
+    pub fn num_free_temp_table_pages(&self) -> usize {
+        self.temp_tables_free.len()
+    }
+
+    // Replaces the body of the page in `buffer` with that of `page`. With a log, the change is
+    // logged first as part of the current transaction, which is begun if there is none.
+    pub fn update_page(&mut self, buffer: &Buffer, page: &Page) -> Result<(), Error> {
+        let mut current = buffer.page.borrow_mut();
+        // only the changed ranges are logged and copied, those far apart in records of their own
+        let mut ranges: Vec<Range<usize>> = vec![];
+        for i in (0..PAGE_BODY_SIZE).filter(|&i| current[i] != page[i]) {
+            match ranges.last_mut() {
+                Some(range) if i - range.end < DELTA_GAP => range.end = i + 1,
+                _ => ranges.push(i..i + 1),
+            }
+        }
+        if ranges.is_empty() {
+            return Ok(());
+        }
+        // those of temporary and unlogged tables aren't logged
+        let txid = if buffer.page_id.is_logged() { self.txid()? } else { None };
+        if let Some(txid) = txid {
+            let txn = self.transactions.get_mut(&txid).unwrap();
+            let wal = self.wal.as_mut().unwrap();
+            let mut first_lsn = None;
+            if let Some(image) = page_image(buffer.page_id, &current, self.last_checkpoint) {
+                txn.last_lsn = wal.append(txid, Some(txn.last_lsn), &image)?;
+                first_lsn = Some(txn.last_lsn);
+            }
+            for range in &ranges {
+                let (page_id, offset) = (buffer.page_id, range.start as u16);
+                let record = Record::Update { page_id, offset, before: current[range.clone()].to_vec(), after: page[range.clone()].to_vec() };
+                txn.last_lsn = wal.append(txid, Some(txn.last_lsn), &record)?;
+                first_lsn.get_or_insert(txn.last_lsn);
+            }
+            set_page_lsn(&mut current, txn.last_lsn);
+            // redone from the first of the records, not the last
+            if buffer.rec_lsn.get() == 0 {
+                buffer.rec_lsn.set(first_lsn.unwrap());
+            }
+        }
+        for range in ranges {
+            current[range.clone()].copy_from_slice(&page[range]);
+        }
+        buffer.is_dirty.set(true);
+        Ok(())
+    }
+
+    // Logs `page_id` whole as the record of `txid` after `prev_lsn` if a change to it is the first
+    // since the latest checkpoint, returning the LSN of the latest record of `txid` (see `page_image`).
+    pub(crate) fn log_page_image(&mut self, txid: TxId, prev_lsn: Lsn, page_id: PageId) -> Result<Lsn, Error> {
+        let buffer = self.fetch_page(page_id)?;
+        let image = page_image(page_id, &buffer.page.borrow(), self.last_checkpoint);
+        match image {
+            Some(image) => Ok(self.wal.as_mut().unwrap().append(txid, Some(prev_lsn), &image)?),
+            None => Ok(prev_lsn),
+        }
+    }
+
+    // Takes the checkpoint begun at `lsn` as the latest one, e.g. the one recovery starts from.
+    pub(crate) fn set_last_checkpoint(&mut self, lsn: Lsn) {
+        self.last_checkpoint = lsn;
+    }
+
+    // Writes `data` at `offset` of a page as the change logged at `lsn`, during recovery or a rollback.
+    // Overwrites the body of the page with `image`, as `apply` does, whatever the page read was,
+    // e.g. a write of it torn by a crash which fails its checksum.
+    pub(crate) fn apply_image(&mut self, page_id: PageId, image: &[u8], lsn: Lsn) -> Result<(), Error> {
+        if !self.page_table.contains_key(&page_id) {
+            self.load_page(page_id, None, false)?;
+        }
+        self.apply(page_id, 0, image, lsn)
+    }
+
+    pub(crate) fn apply(&mut self, page_id: PageId, offset: usize, data: &[u8], lsn: Lsn) -> Result<(), Error> {
+        let buffer = self.fetch_page(page_id)?;
+        let mut page = buffer.page.borrow_mut();
+        page[offset..offset + data.len()].copy_from_slice(data);
+        set_page_lsn(&mut page, lsn);
+        buffer.is_dirty.set(true);
+        if buffer.rec_lsn.get() == 0 {
+            buffer.rec_lsn.set(lsn);
+        }
+        Ok(())
+    }
+
+    // Commits the current transaction, if any, making its changes durable. Then writes back some
+    // dirty pages and takes a checkpoint if enough has been logged since the last one. One which
+    // hasn't written, and so never begun in the log, has nothing to do. Fails if it's been
+    // terminated or its snapshot taken away, having been rolled back then if it had written.
+    pub fn commit(&mut self) -> Result<(), Error> {
+        self.free_temp_pages();
+        let state = std::mem::take(&mut self.current);
+        self.snapshots.remove(&state.snapshot_id);
+        let canceled = self.canceled.remove(&state.snapshot_id);
+        if let Some(cancel) = state.txid.and_then(|txid| self.terminated.remove(&txid)).or(canceled) {
+            return Err(cancel.into());
+        }
+        let (Some(wal), Some(txid)) = (&mut self.wal, state.txid) else {
+            return Ok(());
+        };
+        let txn = self.transactions.remove(&txid).unwrap();
+        let lsn = wal.append(txid, Some(txn.last_lsn), &Record::Commit { time: SystemTime::now() })?;
+        if state.durability == Durability::Sync {
+            wal.flush(lsn + 1)?;
+        }
+        wal.append(txid, Some(lsn), &Record::End)?;
+        self.ssi.end(txid, true);
+        self.locks.release(txid);
+        self.reap()?;
+        if self.wal.as_ref().unwrap().end() - self.last_checkpoint >= self.checkpoint_interval {
+            self.checkpoint()?;
+        } else if state.durability == Durability::Sync {
+            // an asynchronous commit leaves writing to the flusher, as writing a page flushes the log
+            self.write_back(WRITE_BACK_PAGES)?;
+        }
+        Ok(())
+    }
+
+    // Writes back up to `max_pages` of the dirty pages which have been dirty the longest, which
+    // moves forward the point recovery has to redo from.
+    pub fn write_back(&mut self, max_pages: usize) -> Result<(), Error> {
+        let mut dirty: Vec<_> = self
+            .page_table
+            .iter()
+            .map(|(&page_id, &buffer_id)| (page_id, self.pool.frames[buffer_id.0].buffer.clone()))
+            .filter(|(_, buffer)| buffer.is_dirty.get() && buffer.rec_lsn.get() != 0)
+            .collect();
+        dirty.sort_by_key(|(page_id, buffer)| (buffer.rec_lsn.get(), *page_id));
+        for (page_id, buffer) in dirty.into_iter().take(max_pages) {
+            self.write_buffer(page_id, &buffer)?;
+        }
+        Ok(())
+    }
+
+    // Takes a checkpoint: writes back the dirty pages and logs the running transactions, then
+    // removes the log segments recovery no longer needs. No page is dirty across a checkpoint, so
+    // that a page written after one has been logged whole since (see `page_image`).
+    pub fn checkpoint(&mut self) -> Result<(), Error> {
+        if self.wal.is_none() {
+            return Ok(());
+        }
+        self.write_back(usize::MAX)?;
+        // pages written back before now are taken to be on disk from here on, the log before
+        // this maybe being removed, which they're only once synced
+        self.disk.sync()?;
+        let wal = self.wal.as_mut().unwrap();
+        let begin = wal.append(0, None, &Record::CheckpointBegin)?;
+        // the pages of logged in-memory tables are in no file, so recovery brings them back from here
+        for (&page_id, buffer) in self.memory_pages.iter().filter(|(page_id, _)| page_id.is_logged()) {
+            let after = buffer.page.borrow()[..PAGE_BODY_SIZE].to_vec();
+            wal.append(0, None, &Record::Redo { page_id, offset: 0, after })?;
         }
+        let mut dirty_pages: Vec<_> = self
+            .page_table
+            .iter()
+            .map(|(&page_id, &buffer_id)| (page_id, &self.pool.frames[buffer_id.0].buffer))
+            .filter(|(_, buffer)| buffer.is_dirty.get() && buffer.rec_lsn.get() != 0)
+            .map(|(page_id, buffer)| (page_id, buffer.rec_lsn.get()))
+            .collect();
+        dirty_pages.sort_unstable();
+        let checkpoint = Checkpoint {
+            next_txid: self.next_txid,
+            transactions: self.transactions.iter().map(|(&txid, txn)| (txid, txn.last_lsn)).collect(),
+            dirty_pages,
+            aborted: self.aborted.iter().copied().collect(),
+        };
+        // recovery reads the log from the checkpoint, or from where a dirty page may be missing
+        // changes or a running transaction began if that's earlier
+        let needed = checkpoint
+            .dirty_pages
+            .iter()
+            .map(|&(_, lsn)| lsn)
+            .chain(self.transactions.values().map(|txn| txn.first_lsn))
+            .fold(begin, Lsn::min);
+        wal.append(0, None, &Record::CheckpointEnd(checkpoint))?;
+        wal.flush_all()?;
+        wal.set_last_checkpoint(begin)?;
+        wal.remove_before(needed)?;
+        self.last_checkpoint = begin;
+        Ok(())
+    }
+
+    // Cuts the data file to its first `num_pages` pages, those after being of no tree, e.g. freed
+    // by VACUUM FULL and no longer in the free list. The cut is logged first, so that recovery
+    // and replicas make it at the same point, and the other pages written back along with a
+    // checkpoint after, so that recovery never redoes anything from before it.
+    pub fn truncate(&mut self, num_pages: u64) -> Result<(), Error> {
+        if num_pages >= self.disk.num_pages() {
+            return Ok(());
+        }
+        if let Some(wal) = &mut self.wal {
+            wal.append(0, None, &Record::Truncate { num_pages })?;
+            wal.flush_all()?;
+        }
+        self.truncate_file(num_pages)?;
+        self.flush()?;
+        self.checkpoint()
+    }
+
+    // Cuts the data file as logged in `Record::Truncate`, dropping the pages after from the pool
+    // unwritten.
+    pub(crate) fn truncate_file(&mut self, num_pages: u64) -> Result<(), Error> {
+        let dropped: Vec<_> = self.page_table.iter().filter(|(page_id, _)| page_id.0 >= num_pages && page_id.in_data_file()).map(|(&page_id, &buffer_id)| (page_id, buffer_id)).collect();
+        for (page_id, buffer_id) in dropped {
+            self.page_table.remove(&page_id);
+            self.pool.frames[buffer_id.0].buffer = Rc::new(Buffer::default());
+        }
+        self.disk.truncate(num_pages)?;
+        Ok(())
+    }
+
+    fn write_buffer(&mut self, page_id: PageId, buffer: &Buffer) -> Result<(), Error> {
+        if let Some(wal) = &mut self.wal {
+            wal.flush(page_lsn(&buffer.page.borrow()) + 1)?;
+        }
+        let (file, page_id) = file_of(&mut self.disk, &mut self.temp_tables, page_id);
+        file.write_page_data(page_id, buffer.page.borrow().as_ref())?;
+        buffer.is_dirty.set(false);
+        buffer.rec_lsn.set(0);
+        Ok(())
+    }
+
+    // Rolls back the changes of the current transaction, if it has made any, and makes its row
+    // versions invisible. Returns whether there was one.
+    pub fn abort(&mut self) -> Result<bool, Error> {
+        self.free_temp_pages();
+        let state = std::mem::take(&mut self.current);
+        self.snapshots.remove(&state.snapshot_id);
+        self.canceled.remove(&state.snapshot_id);
+        let (Some(_), Some(txid)) = (&self.wal, state.txid) else {
+            return Ok(false);
+        };
+        // rolled back already when terminated
+        if self.terminated.remove(&txid).is_none() {
+            self.roll_back(txid)?;
+        }
+        Ok(true)
+    }
+
+    fn roll_back(&mut self, txid: TxId) -> Result<(), Error> {
+        let txn = self.transactions.remove(&txid).unwrap();
+        let lsn = self.wal.as_mut().unwrap().append(txid, Some(txn.last_lsn), &Record::Abort)?;
+        self.aborted.insert(txid);
+        self.ssi.end(txid, false);
+        recovery::rollback(self, BTreeMap::from([(txid, lsn)]))?;
+        self.locks.release(txid);
+        Ok(())
+    }
+
+    pub fn stats(&self) -> BufferStats {
+        self.stats
+    }
+
+    pub fn pool_usage(&self) -> PoolUsage {
+        let dirty = self.page_table.values().filter(|buffer_id| self.pool.frames[buffer_id.0].buffer.is_dirty.get()).count();
+        PoolUsage { frames: self.pool.size(), pages: self.page_table.len(), dirty }
+    }
+
+    // The transactions which have begun and not ended, in the order they began.
+    pub fn transactions(&self) -> Vec<TransactionInfo> {
+        self.transactions
+            .iter()
+            .map(|(&txid, transaction)| TransactionInfo {
+                txid,
+                first_lsn: transaction.first_lsn,
+                last_lsn: transaction.last_lsn,
+                idle: transaction.idle_since.map(|since| since.elapsed()),
+                current: self.current.txid == Some(txid),
+            })
+            .collect()
+    }
+
+    // Pages of the data file, those allocated but never written included.
+    pub fn num_pages(&self) -> u64 {
+        self.disk.num_pages()
     }
 
-    fn fetch_page(&mut self, page_id: PageId) -> Result<Rc<Buffer>, Error> {
+    // Fails if the running statement has timed out or been canceled, or the transaction terminated.
+    // Operators going through many rows without reading a page call it every so many.
+    pub fn check_interrupted(&self) -> Result<(), Error> {
+        if self.current.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(Error::StatementTimeout(self.current.statement_timeout.unwrap()));
+        }
+        if self.is_canceled() {
+            return Err(Error::Canceled);
+        }
+        if let Some(cancel) = self.canceled() {
+            return Err(cancel.into());
+        }
+        Ok(())
+    }
+
+    pub fn fetch_page(&mut self, page_id: PageId) -> Result<Rc<Buffer>, Error> {
+        self.check_interrupted()?;
+        if page_id.is_memory() {
+            return self.fetch_memory_page(page_id);
+        }
         if let Some(&buffer_id) = self.page_table.get(&page_id) {
-            let frame = &mut self.pool.frames[buffer_id.0];
-            frame.usage_count += 1;
+            self.pool.record_use(buffer_id, false);
+            self.stats.hits += 1;
+            trace::event!(Trace, "hit", page_id = page_id.0);
 
-            return Ok(frame.buffer.clone())
+            return Ok(self.pool.frames[buffer_id.0].buffer.clone())
         }
 
+        self.load_page(page_id, None, self.verify_checksums)
+    }
+
+    // Reads those of `page_ids` in the data file which aren't in the pool into it, by the threads
+    // of the pool at once if the file can be read from them, for them to be fetched without
+    // waiting on the disk each in turn. No more than fit in half the pool are, for those read
+    // ahead not to evict one another before they're fetched.
+    pub fn prefetch(&mut self, page_ids: &[PageId]) -> Result<(), Error> {
+        let Some(reader) = self.disk.reader() else {
+            return Ok(());
+        };
+        let mut missing: Vec<PageId> = page_ids.iter().copied().filter(|page_id| page_id.in_data_file() && !self.page_table.contains_key(page_id)).collect();
+        missing.dedup();
+        missing.truncate(self.pool.size() / 2);
+        if missing.len() < 2 {
+            return Ok(());
+        }
+        let pages = self.thread_pool().map(&missing, |&page_id| {
+            let mut page = vec![0; PAGE_SIZE as usize];
+            reader.read_page_data(page_id, &mut page).map(|()| page)
+        });
+        for (page_id, page) in missing.into_iter().zip(pages) {
+            match self.load_page(page_id, Some(page?), self.verify_checksums) {
+                // the rest left to be read when they're fetched
+                Err(Error::NoFreeBuffer) => break,
+                result => result?,
+            };
+        }
+        Ok(())
+    }
+
+    // Puts `page_id` in a frame of the pool, its page read from its file unless it's been
+    // already, evicting the page the frame had. Fails, the frame left empty, if `verify` and the
+    // page read isn't as it was written.
+    fn load_page(&mut self, page_id: PageId, read: Option<Vec<u8>>, verify: bool) -> Result<Rc<Buffer>, Error> {
         let evicted_buffer_id = match self.pool.evict() {
             Some(buffer_id) => buffer_id,
             None => return Err(Error::NoFreeBuffer),
         };
+        self.stats.misses += 1;
+        trace::event!(Trace, "miss", page_id = page_id.0);
 
         let update_frame = &mut self.pool.frames[evicted_buffer_id.0];
         let evict_page_id = update_frame.buffer.page_id;
 
         let buffer = Rc::get_mut(&mut update_frame.buffer).unwrap();
+        trace::event!(Debug, "evict", page_id = evict_page_id.0, dirty = buffer.is_dirty.get());
 
         if buffer.is_dirty.get() {
             // evictされる前にdiskに書き込む
-            self.disk.write_page_data(evict_page_id, buffer.page.get_mut())?;
+            if let Some(wal) = &mut self.wal {
+                wal.flush(page_lsn(buffer.page.get_mut()) + 1)?;
+            }
+            let (file, evict_page_id) = file_of(&mut self.disk, &mut self.temp_tables, evict_page_id);
+            file.write_page_data(evict_page_id, buffer.page.get_mut())?;
         }
 
         buffer.page_id = page_id;
         buffer.is_dirty.set(false);
+        buffer.rec_lsn.set(0);
 
-        self.disk.read_page_data(page_id, buffer.page.get_mut())?;
-        update_frame.usage_count = 1;
+        match read {
+            Some(page) => buffer.page.get_mut().copy_from_slice(&page),
+            None => {
+                let (file, page_id_in_file) = file_of(&mut self.disk, &mut self.temp_tables, page_id);
+                file.read_page_data(page_id_in_file, buffer.page.get_mut())?;
+            }
+        }
+        // of its id in its file, that of a temporary table's without its bit
+        let page_id_in_file = if page_id.is_temp() { PageId(page_id.0 & !PageId::TEMP_BIT) } else { page_id };
+        if verify && !disk::verify_page(page_id_in_file, buffer.page.get_mut()) {
+            buffer.page_id = PageId::INVALID_PAGE_ID;
+            self.page_table.remove(&evict_page_id);
+            return Err(Error::Checksum(page_id));
+        }
 
         let page = update_frame.buffer.clone();
+        self.pool.record_use(evicted_buffer_id, true);
 
         self.page_table.remove(&evict_page_id);
         self.page_table.insert(page_id, evicted_buffer_id);
@@ -156,29 +1616,101 @@ impl BufferPoolManager {
         Ok(page)
     }
 
+    // A page of an in-memory table, zeroed if it's not been created, e.g. before recovery brings
+    // it back. Those of unlogged tables are left empty on a replica, where none of them can be read.
+    fn fetch_memory_page(&mut self, page_id: PageId) -> Result<Rc<Buffer>, Error> {
+        if self.standby && !page_id.is_logged() {
+            return Err(Error::Unlogged);
+        }
+        self.stats.hits += 1;
+        let buffer = self.memory_pages.entry(page_id).or_insert_with(|| Rc::new(Buffer { page_id, ..Buffer::default() }));
+        Ok(buffer.clone())
+    }
+
     // Copied from https://github.com/KOBA789/relly/blob/3b1e656b7ae67ba2ddde2ba7d2748816b4792d1e/src/buffer.rs#L150-L172
     pub fn create_page(&mut self) -> Result<Rc<Buffer>, Error> {
+        let creating_temp = self.creating == PageId::TEMP_BIT;
+        if self.creating & PageId::MEMORY_BIT != 0 {
+            let page_id = PageId(self.next_memory_page | self.creating);
+            self.next_memory_page += 1;
+            let buffer = Rc::new(Buffer { page_id, ..Buffer::default() });
+            self.memory_pages.insert(page_id, buffer.clone());
+            return Ok(buffer);
+        }
+        let free = if creating_temp { Some(&mut self.temp_tables_free) } else { self.free_pages.as_mut() };
+        if let Some(page_id) = free.and_then(BTreeSet::pop_first) {
+            return self.fetch_page(page_id);
+        }
+        if creating_temp && self.temp_tables.is_none() {
+            if self.temp.is_none() {
+                self.temp = Some(TempFileManager::open_default()?);
+            }
+            self.temp_tables = Some(DiskManager::open(self.temp.as_ref().unwrap().dir().join(TEMP_TABLES_FILE))?);
+        }
         let buffer_id = self.pool.evict().ok_or(Error::NoFreeBuffer)?;
         let frame = &mut self.pool.frames[buffer_id.0];
         let evict_page_id = frame.buffer.page_id;
         let page_id = {
             let buffer = Rc::get_mut(&mut frame.buffer).unwrap();
             if buffer.is_dirty.get() {
-                self.disk
-                    .write_page_data(evict_page_id, buffer.page.get_mut())?;
+                if let Some(wal) = &mut self.wal {
+                    wal.flush(page_lsn(buffer.page.get_mut()) + 1)?;
+                }
+                let (file, evict_page_id) = file_of(&mut self.disk, &mut self.temp_tables, evict_page_id);
+                file.write_page_data(evict_page_id, buffer.page.get_mut())?;
             }
-            let page_id = self.disk.allocate_page();
+            let page_id = match &mut self.temp_tables {
+                Some(file) if creating_temp => PageId(file.allocate_page().0 | PageId::TEMP_BIT),
+                _ => self.disk.allocate_page(),
+            };
             *buffer = Buffer::default();
             buffer.page_id = page_id;
             buffer.is_dirty.set(true);
-            frame.usage_count = 1;
             page_id
         };
         let page = Rc::clone(&frame.buffer);
+        self.pool.record_use(buffer_id, true);
         self.page_table.remove(&evict_page_id);
         self.page_table.insert(page_id, buffer_id);
         Ok(page)
     }
+
+    // Flushes every record appended, making the commits which didn't wait for it durable.
+    pub fn flush_log(&mut self) -> Result<(), Error> {
+        if let Some(wal) = &mut self.wal {
+            wal.flush_all()?;
+        }
+        Ok(())
+    }
+
+    // Writes every dirty page back to disk and syncs the file, flushing the log first.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if let Some(wal) = &mut self.wal {
+            wal.flush_all()?;
+        }
+        // in order of the pages, for the file and for runs to be repeatable (see `sim`)
+        let mut pages: Vec<_> = self.page_table.iter().map(|(&page_id, &buffer_id)| (page_id, buffer_id)).collect();
+        pages.sort_unstable_by_key(|&(page_id, _)| page_id);
+        for (page_id, buffer_id) in pages {
+            let buffer = &self.pool.frames[buffer_id.0].buffer;
+            if buffer.is_dirty.get() {
+                let (file, page_id) = file_of(&mut self.disk, &mut self.temp_tables, page_id);
+                file.write_page_data(page_id, buffer.page.borrow().as_ref())?;
+                buffer.is_dirty.set(false);
+                buffer.rec_lsn.set(0);
+            }
+        }
+        self.disk.sync()?;
+        Ok(())
+    }
+}
+
+// The file `page_id` is in, that of the temporary tables if it's one of theirs, and its id there.
+fn file_of<'a>(disk: &'a mut DiskManager, temp_tables: &'a mut Option<DiskManager>, page_id: PageId) -> (&'a mut DiskManager, PageId) {
+    match temp_tables {
+        Some(file) if page_id.is_temp() => (file, PageId(page_id.0 & !PageId::TEMP_BIT)),
+        _ => (disk, page_id),
+    }
 }
 
 // Copied from https://github.com/KOBA789/relly/blob/3b1e656b7ae67ba2ddde2ba7d2748816b4792d1e/src/buffer.rs#L185-L234
@@ -212,7 +1744,7 @@ mod tests {
         {
             let buffer = bufmgr.fetch_page(page1_id).unwrap();
             let page = buffer.page.borrow();
-            assert_eq!(&hello, page.as_ref());
+            assert_eq!(&hello[..CHECKSUM_OFFSET], &page[..CHECKSUM_OFFSET]);
         }
         let page2_id = {
             let buffer = bufmgr.create_page().unwrap();
@@ -224,13 +1756,165 @@ mod tests {
         {
             let buffer = bufmgr.fetch_page(page1_id).unwrap();
             let page = buffer.page.borrow();
-            assert_eq!(&hello, page.as_ref());
+            assert_eq!(&hello[..CHECKSUM_OFFSET], &page[..CHECKSUM_OFFSET]);
         }
         {
             let buffer = bufmgr.fetch_page(page2_id).unwrap();
             let page = buffer.page.borrow();
-            assert_eq!(&world, page.as_ref());
+            assert_eq!(&world[..CHECKSUM_OFFSET], &page[..CHECKSUM_OFFSET]);
+        }
+        assert_eq!(BufferStats { hits: 1, misses: 2 }, bufmgr.stats());
+
+        // pages read ahead by the threads of the workers are fetched from the pool, as many as
+        // fit in half of it
+        let file = tempfile().unwrap();
+        let mut bufmgr = BufferPoolManager::new(DiskManager::new(file.try_clone().unwrap()).unwrap(), BufferPool::new(8));
+        let page_ids: Vec<_> = (0..6u8).map(|i| {
+            let buffer = bufmgr.create_page().unwrap();
+            buffer.page.borrow_mut()[0] = i;
+            buffer.is_dirty.set(true);
+            buffer.page_id
+        }).collect();
+        bufmgr.flush().unwrap();
+        let mut bufmgr = BufferPoolManager::new(DiskManager::new(file).unwrap(), BufferPool::new(8));
+        bufmgr.set_parallel_workers(2);
+        bufmgr.prefetch(&page_ids).unwrap();
+        assert_eq!(BufferStats { hits: 0, misses: 4 }, bufmgr.stats());
+        for (i, &page_id) in page_ids.iter().enumerate() {
+            assert_eq!(i as u8, bufmgr.fetch_page(page_id).unwrap().page.borrow()[0]);
+        }
+        assert_eq!(BufferStats { hits: 4, misses: 6 }, bufmgr.stats());
+        assert_eq!(2, bufmgr.thread_pool().size());
+
+        // pages can't be fetched once the statement has timed out
+        bufmgr.set_statement_timeout(Some(Duration::ZERO));
+        bufmgr.start_statement();
+        assert!(matches!(bufmgr.fetch_page(page1_id), Err(Error::StatementTimeout(Duration::ZERO))));
+        bufmgr.end_statement();
+        assert!(bufmgr.fetch_page(page1_id).is_ok());
+        bufmgr.set_statement_timeout(Some(Duration::from_secs(3600)));
+        bufmgr.start_statement();
+        assert!(bufmgr.fetch_page(page1_id).is_ok());
+        // and the timeouts are of the transaction
+        bufmgr.commit().unwrap();
+        bufmgr.start_statement();
+        assert!(bufmgr.fetch_page(page1_id).is_ok());
+        // nor once it's canceled, from another thread say, until the next one starts
+        let token = CancelToken::default();
+        bufmgr.set_cancel_token(token.clone());
+        let canceling = token.clone();
+        std::thread::spawn(move || canceling.cancel()).join().unwrap();
+        assert!(matches!(bufmgr.fetch_page(page1_id), Err(Error::Canceled)));
+        bufmgr.start_statement();
+        assert!(!token.is_canceled());
+        assert!(bufmgr.fetch_page(page1_id).is_ok());
+
+        // memory is held up to the budget, each statement starting afresh
+        bufmgr.set_memory_budget(Some(100));
+        let (mut first, mut second) = (MemoryReservation::default(), MemoryReservation::default());
+        bufmgr.reserve_memory(&mut first, 60).unwrap();
+        assert!(matches!(bufmgr.reserve_memory(&mut second, 41), Err(Error::OutOfMemoryBudget(100))));
+        assert_eq!((60, 0), (bufmgr.memory_used(), second.bytes()));
+        first.release();
+        bufmgr.reserve_memory(&mut second, 60).unwrap();
+        drop(second);
+        assert_eq!(0, bufmgr.memory_used());
+        bufmgr.reserve_memory(&mut first, 60).unwrap();
+        bufmgr.start_statement();
+        assert_eq!(0, bufmgr.memory_used());
+        // one held on to past its statement counts in the next it reserves more in
+        bufmgr.reserve_memory(&mut first, 10).unwrap();
+        assert_eq!(70, bufmgr.memory_used());
+        drop(first);
+
+        // changes far apart on a page are logged as records of their own, leaving out what's between,
+        // after the page whole if it's the first change since the checkpoint
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let wal_dir = tempfile::tempdir().unwrap();
+        let wal = Wal::open(wal_dir.path(), DEFAULT_SEGMENT_SIZE).unwrap();
+        let mut bufmgr = BufferPoolManager::with_wal(disk, BufferPool::new(1), wal).unwrap();
+        let buffer = bufmgr.create_page().unwrap();
+        let mut page = *buffer.page.borrow();
+        page[0] = 1;
+        page[3000] = 1;
+        bufmgr.txid().unwrap();
+        let start = bufmgr.wal().unwrap().end();
+        bufmgr.update_page(&buffer, &page).unwrap();
+        let wal = bufmgr.wal().unwrap();
+        assert!(wal.end() - start < 200);
+        let (image, next) = wal.read(start).unwrap().unwrap();
+        let (first, next) = wal.read(next).unwrap().unwrap();
+        let (second, _) = wal.read(next).unwrap().unwrap();
+        assert!(matches!(image.record, Record::PageImage { .. }));
+        assert!(matches!(first.record, Record::Update { offset: 0, .. }));
+        assert!(matches!(second.record, Record::Update { offset: 3000, .. }));
+        assert_eq!(page[..PAGE_BODY_SIZE], buffer.page.borrow()[..PAGE_BODY_SIZE]);
+        bufmgr.commit().unwrap();
+
+        // transactions idle or holding a snapshot for too long are rolled back, failing from then on
+        let page_id = buffer.page_id;
+        drop(buffer);
+        page[0] = 2;
+        bufmgr.start_statement();
+        let buffer = bufmgr.fetch_page(page_id).unwrap();
+        bufmgr.update_page(&buffer, &page).unwrap();
+        drop(buffer);
+        bufmgr.end_statement();
+        let idle = bufmgr.switch(Default::default());
+        bufmgr.snapshot();
+        let reader = bufmgr.switch(Default::default());
+        assert_eq!(0, bufmgr.reap().unwrap());
+        bufmgr.set_idle_timeout(Some(Duration::ZERO));
+        assert_eq!(1, bufmgr.reap().unwrap());
+        assert_eq!(1, bufmgr.fetch_page(page_id).unwrap().page.borrow()[0]);
+        bufmgr.set_snapshot_timeout(Some(Duration::ZERO));
+        assert_eq!(1, bufmgr.reap().unwrap());
+        assert_eq!(bufmgr.next_txid, bufmgr.horizon());
+        bufmgr.switch(idle);
+        assert!(matches!(bufmgr.fetch_page(page_id), Err(Error::IdleTimeout(Duration::ZERO))));
+        assert!(matches!(bufmgr.txid(), Err(Error::IdleTimeout(_))));
+        assert!(matches!(bufmgr.commit(), Err(Error::IdleTimeout(_))));
+        bufmgr.switch(reader);
+        assert!(matches!(bufmgr.fetch_page(page_id), Err(Error::SnapshotTimeout(Duration::ZERO))));
+        assert!(!bufmgr.abort().unwrap());
+        // the current one is left alone
+        bufmgr.snapshot();
+        assert_eq!(0, bufmgr.reap().unwrap());
+        assert!(bufmgr.fetch_page(page_id).is_ok());
+
+        // a statement held keeps its snapshot and temporary pages, for later ones to resume it
+        bufmgr.abort().unwrap();
+        bufmgr.set_idle_timeout(None);
+        bufmgr.set_snapshot_timeout(None);
+        bufmgr.begin(Isolation::ReadCommitted).unwrap();
+        bufmgr.start_statement();
+        let mut held = bufmgr.hold_statement();
+        let temp_page = bufmgr.resume(&mut held, |bufmgr| bufmgr.allocate_temp_page()).unwrap();
+        bufmgr.end_statement();
+        let reader = bufmgr.switch(Default::default());
+        let txid = bufmgr.txid().unwrap().unwrap();
+        bufmgr.commit().unwrap();
+        bufmgr.switch(reader);
+        bufmgr.start_statement();
+        assert!(bufmgr.sees(txid) && !bufmgr.resume(&mut held, |bufmgr| bufmgr.sees(txid)));
+        assert_ne!(temp_page, bufmgr.allocate_temp_page().unwrap());
+        assert!(bufmgr.horizon() <= txid);
+        bufmgr.release(held);
+        assert!(bufmgr.horizon() > txid);
+        bufmgr.commit().unwrap();
+
+        // LRU evicts the page used the longest ago, where the clock spares the one used the most
+        for (policy, evicted) in [(EvictionPolicy::Clock, 1), (EvictionPolicy::Lru, 0)] {
+            let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+            let mut bufmgr = BufferPoolManager::new(disk, BufferPool::with_policy(2, policy));
+            let pages: Vec<_> = (0..2).map(|_| bufmgr.create_page().unwrap().page_id).collect();
+            for page_id in [pages[0], pages[0], pages[0], pages[1]] {
+                bufmgr.fetch_page(page_id).unwrap();
+            }
+            bufmgr.create_page().unwrap();
+            let misses = bufmgr.stats().misses;
+            bufmgr.fetch_page(pages[1 - evicted]).unwrap();
+            assert_eq!(misses, bufmgr.stats().misses, "{:?}", policy);
         }
     }
 }
-
@@ -0,0 +1,1700 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::btree::{self, BTree, SearchMode};
+use crate::buffer::BufferPoolManager;
+use crate::columnar::Columnar;
+use crate::dictionary::Dictionary;
+use crate::lock::{self, LockMode};
+use crate::query;
+use crate::disk::PageId;
+use crate::json;
+use crate::partition::{Partition, PartitionBound, Partitioning, Strategy};
+use crate::stats::{ColumnStats, TableStats};
+use crate::table::{self, Index, IndexKind, Table, Ttl};
+use crate::tuple::{self, DataType, ElementType, Tuple, Value};
+use crate::query::expr::BinaryOp;
+use crate::trigger::{Action, Event, Timing, TriggerInfo};
+use crate::udf::{self, AggregateFunction, ScalarFunction, TableFunction};
+use crate::wal::{Relation, TxId};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Table(#[from] table::Error),
+    #[error(transparent)]
+    Btree(#[from] btree::Error),
+    #[error(transparent)]
+    Tuple(#[from] tuple::Error),
+    #[error(transparent)]
+    Lock(#[from] lock::Error),
+    #[error("table already exists: {0}")]
+    TableExists(String),
+    #[error("view already exists: {0}")]
+    ViewExists(String),
+    #[error("a virtual table already exists: {0}")]
+    VirtualTableExists(String),
+    #[error("index already exists: {0}")]
+    IndexExists(String),
+    #[error("table not found: {0}")]
+    UnknownTable(String),
+    #[error("index not found: {0}")]
+    UnknownIndex(String),
+    #[error("view not found: {0}")]
+    UnknownView(String),
+    #[error("column already exists: {0}")]
+    ColumnExists(String),
+    #[error("column not found: {0}")]
+    UnknownColumn(String),
+    #[error("trigger already exists: {0}")]
+    TriggerExists(String),
+    #[error("user already exists: {0}")]
+    UserExists(String),
+    #[error("user not found: {0}")]
+    UnknownUser(String),
+    #[error("table is partitioned: {0}")]
+    Partitioned(String),
+    #[error("table is not partitioned: {0}")]
+    NotPartitioned(String),
+    #[error("invalid bound of partition {0}: {1}")]
+    InvalidBound(String, String),
+    #[error("partition {0} would overlap partition {1}")]
+    PartitionOverlap(String, String),
+    #[error("no partition of {0} for the row")]
+    NoPartition(String),
+    #[error("the row is outside the bound of partition {0}")]
+    OutsidePartition(String),
+    #[error("table is temporary: {0}")]
+    Temporary(String),
+    #[error("table is in memory: {0}")]
+    InMemory(String),
+    #[error("table is columnar: {0}")]
+    Columnar(String),
+    #[error("table is a partition: {0}")]
+    Partition(String),
+    #[error("table has triggers: {0}")]
+    HasTriggers(String),
+    #[error("the catalog must be created in an empty database")]
+    NotEmpty,
+    #[error("malformed catalog entry")]
+    Malformed,
+}
+
+// The catalog B+tree is the first thing created in a database, so its meta page is always page 0.
+pub const CATALOG_META_PAGE_ID: PageId = PageId(0);
+
+// Entries of the catalog B+tree. Keys are memcomparable encoded, values are encoded tuples:
+//   ["table", name] => [btree meta page, num_key_elems, num_columns, (column name, type)*,
+//                       (index name, btree meta page, num_columns, column*)*]
+//   ["stats", name] => [row_count, (null_count, distinct_count, min, max, num_bounds, bound*)*]
+//   ["view", name] => [query text, (column name)*]
+//   ["user", name] => [password as SCRAM keeps it, or NULL, superuser]
+//   ["grant", table, user] => [privileges, a bit each of `Privilege::ALL`]
+//   ["building", table, index] => [], while CREATE INDEX CONCURRENTLY hasn't made the index valid
+//   ["free", page] => [], of each page REINDEX, VACUUM FULL or DROP has freed and nothing has reused
+//                     since
+//   ["partitioned", name] => [strategy, key column], of a partitioned table (see `partition`)
+//   ["partition", name] => [parent, strategy, lower or NULL, upper or NULL] of a range partition,
+//                          [parent, strategy, modulus, remainder] of a hash partition
+//   ["columnar", name] => [stripes meta page, segments meta page], of a columnar table (see `columnar`)
+//   ["ttl", name] => [column, seconds], of a table whose rows expire (see `table::Ttl`)
+//   ["dictionary", name] => [codes meta page, values meta page, column*], of a table with
+//                           dictionary columns (see `dictionary`)
+//   ["index_paths", table, index] => [(key column position, num_steps, step*, text)*], of the
+//                                    expressions of an index on them (see `json::Path`)
+//   ["fulltext", table, index] => [], of a full-text index (see `fulltext`)
+//   ["rtree", table, index] => [], of an R-tree index (see `rtree`)
+//   ["zonemap", table, index] => [], of a zone map index (see `zonemap`)
+//   ["trigger", name] => [table, timing, event, CREATE TRIGGER text], of a trigger created by
+//                        CREATE TRIGGER, the timing 0: BEFORE, 1: AFTER, the event 0: INSERT,
+//                        1: UPDATE, 2: DELETE (see `trigger`)
+// Types are stored as 0: INTEGER, 1: TEXT, 2: BOOLEAN, 3: JSON, 4-6: arrays of the first three,
+// 7: POINT, 8: BOX, strategies as 0: RANGE, 1: HASH.
+const TABLE_ENTRY: &str = "table";
+const STATS_ENTRY: &str = "stats";
+const VIEW_ENTRY: &str = "view";
+const USER_ENTRY: &str = "user";
+const GRANT_ENTRY: &str = "grant";
+const BUILDING_ENTRY: &str = "building";
+const FREE_ENTRY: &str = "free";
+const PARTITIONED_ENTRY: &str = "partitioned";
+const PARTITION_ENTRY: &str = "partition";
+const COLUMNAR_ENTRY: &str = "columnar";
+const TTL_ENTRY: &str = "ttl";
+const DICTIONARY_ENTRY: &str = "dictionary";
+const INDEX_PATHS_ENTRY: &str = "index_paths";
+const FULLTEXT_ENTRY: &str = "fulltext";
+const RTREE_ENTRY: &str = "rtree";
+const ZONEMAP_ENTRY: &str = "zonemap";
+const TRIGGER_ENTRY: &str = "trigger";
+
+// Kinds of the entries of a table, whose ids have its name after the kind, along with how many
+// values they have: those of its indexes and of the privileges on it the name of the index or of
+// the user too.
+const TABLE_ENTRY_KINDS: [(&str, usize); 13] = [
+    (TABLE_ENTRY, 2),
+    (STATS_ENTRY, 2),
+    (GRANT_ENTRY, 3),
+    (BUILDING_ENTRY, 3),
+    (PARTITIONED_ENTRY, 2),
+    (PARTITION_ENTRY, 2),
+    (COLUMNAR_ENTRY, 2),
+    (TTL_ENTRY, 2),
+    (DICTIONARY_ENTRY, 2),
+    (INDEX_PATHS_ENTRY, 3),
+    (FULLTEXT_ENTRY, 3),
+    (RTREE_ENTRY, 3),
+    (ZONEMAP_ENTRY, 3),
+];
+// Kinds of the entries of an index, whose ids are of its table and its name.
+const INDEX_ENTRY_KINDS: [&str; 5] = [BUILDING_ENTRY, INDEX_PATHS_ENTRY, FULLTEXT_ENTRY, RTREE_ENTRY, ZONEMAP_ENTRY];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    pub data_type: DataType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableInfo {
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub table: Table,
+    // names of `table.indexes`, in the same order
+    pub index_names: Vec<String>,
+    // as of the last ANALYZE, None if the table has never been analyzed
+    pub stats: Option<TableStats>,
+    // how its rows are divided among its partitions if it's partitioned, in which case it has none
+    pub partitioning: Option<Partitioning>,
+    // the table it's a partition of, if it is one
+    pub partition: Option<Partition>,
+}
+
+impl TableInfo {
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.name == name)
+    }
+
+    // Sets the name and columns the table logs with its changes to those it has.
+    fn describe_relation(&mut self) {
+        self.table.relation = Some(Relation { name: self.name.clone(), columns: self.columns.iter().map(|c| c.name.clone()).collect() });
+    }
+}
+
+// A named query, expanded wherever the view is referenced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ViewInfo {
+    pub name: String,
+    // the SELECT the view was created with, parsed again each time it's planned
+    pub sql: String,
+    pub columns: Vec<String>,
+}
+
+// A user the network server lets log in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserInfo {
+    pub name: String,
+    // as `scram::Credentials` formats it, None if the user can't log in with a password
+    pub password: Option<String>,
+    // may do anything, whatever they've been granted
+    pub superuser: bool,
+}
+
+// What a user may do to a table or view, granted with GRANT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Privilege {
+    Select,
+    Insert,
+    Update,
+    Delete,
+}
+
+impl Privilege {
+    pub const ALL: [Privilege; 4] = [Privilege::Select, Privilege::Insert, Privilege::Update, Privilege::Delete];
+
+    fn bit(self) -> i64 {
+        1 << Self::ALL.iter().position(|&p| p == self).unwrap()
+    }
+}
+
+impl fmt::Display for Privilege {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Privilege::Select => "SELECT",
+            Privilege::Insert => "INSERT",
+            Privilege::Update => "UPDATE",
+            Privilege::Delete => "DELETE",
+        })
+    }
+}
+
+// A table of rows made each time it's scanned, e.g. of statistics kept in memory or of a source
+// outside the database, registered with `Catalog::register_virtual_table`. It can't be written
+// to, and isn't stored on disk.
+pub trait VirtualTable {
+    fn columns(&self) -> Vec<Column>;
+
+    fn rows(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<Tuple>, query::Error>;
+
+    // The rows, of which those not satisfying all of `constraints`, what the query compares the
+    // columns to, may be left out. The rows returned are filtered by the query all the same.
+    fn scan(&self, bufmgr: &mut BufferPoolManager, constraints: &[Constraint]) -> Result<Vec<Tuple>, query::Error> {
+        let _ = constraints;
+        self.rows(bufmgr)
+    }
+}
+
+// `column op value` of a conjunct of a query on a virtual table, `op` a comparison. NULL compared
+// to isn't satisfied by any row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constraint {
+    pub column: usize,
+    pub op: BinaryOp,
+    pub value: Value,
+}
+
+impl Constraint {
+    // Whether `row` satisfies it, as the query would tell.
+    pub fn holds(&self, row: &[Value]) -> bool {
+        matches!(query::expr::eval_binary(self.op, row[self.column].clone(), self.value.clone()), Ok(Value::Bool(true)))
+    }
+}
+
+impl fmt::Debug for dyn VirtualTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VirtualTable").field("columns", &self.columns()).finish()
+    }
+}
+
+// Temporary tables by name, each with the transaction which created it if that had begun.
+pub type TempTables = BTreeMap<String, (TableInfo, Option<TxId>)>;
+
+// Schema and statistics of all tables, definitions of all views, the users and what they've been
+// granted, keyed by name. Tables, views and virtual tables share a namespace, users have one of
+// their own.
+// Changes are written through to the catalog B+tree; reads are served from memory.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Catalog {
+    btree: BTree,
+    tables: BTreeMap<String, TableInfo>,
+    views: BTreeMap<String, ViewInfo>,
+    users: BTreeMap<String, UserInfo>,
+    // privileges by table and user, as bits
+    grants: BTreeMap<(String, String), i64>,
+    // pages of no tree, which the trees created take before new ones are allocated
+    free_pages: BTreeSet<PageId>,
+    // registered again each time the database is opened
+    virtual_tables: BTreeMap<String, Rc<dyn VirtualTable>>,
+    functions: BTreeMap<String, Rc<ScalarFunction>>,
+    aggregates: BTreeMap<String, Arc<AggregateFunction>>,
+    table_functions: BTreeMap<String, Rc<TableFunction>>,
+    // of their own namespace, those with callbacks registered again each time the database is
+    // opened
+    triggers: BTreeMap<String, TriggerInfo>,
+    // of the session running, in memory only, whose pages are neither logged nor kept past a
+    // restart, hiding the tables of the same name others create (see `Session`)
+    temp_tables: TempTables,
+    // as committed, kept from when a transaction begun by BEGIN first changes it until it's taken
+    // for the sessions running other transactions to be given (see `Session::run`)
+    committed: Option<(TxId, Box<Catalog>)>,
+    // a number no other catalog has had, changed with whatever statements are planned by, so
+    // that the plans of a version are those of the catalog as it is (see `PlanCache`)
+    version: u64,
+}
+
+// A version of a catalog which none has had before.
+fn next_version() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+impl Catalog {
+    // Creates the catalog of a new database. Nothing may have been allocated in the database before.
+    pub fn create(bufmgr: &mut BufferPoolManager) -> Result<Self, Error> {
+        let btree = BTree::create(bufmgr)?;
+        if btree.meta_page_id != CATALOG_META_PAGE_ID {
+            return Err(Error::NotEmpty);
+        }
+        Ok(Self {
+            btree,
+            tables: BTreeMap::new(),
+            views: BTreeMap::new(),
+            users: BTreeMap::new(),
+            grants: BTreeMap::new(),
+            free_pages: BTreeSet::new(),
+            virtual_tables: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            aggregates: BTreeMap::new(),
+            table_functions: BTreeMap::new(),
+            triggers: BTreeMap::new(),
+            temp_tables: BTreeMap::new(),
+            committed: None,
+            version: next_version(),
+        })
+    }
+
+    // Loads the catalog of an existing database.
+    pub fn open(bufmgr: &mut BufferPoolManager) -> Result<Self, Error> {
+        let btree = BTree {
+            meta_page_id: CATALOG_META_PAGE_ID,
+        };
+        let mut tables = BTreeMap::new();
+        let mut views = BTreeMap::new();
+        let mut triggers = BTreeMap::new();
+        let mut users = BTreeMap::new();
+        let mut grants = BTreeMap::new();
+        let mut stats = vec![];
+        let mut building = vec![];
+        let mut free_pages = BTreeSet::new();
+        let mut partitioned = vec![];
+        let mut partitions = vec![];
+        let mut columnar = vec![];
+        let mut ttls = vec![];
+        let mut dictionaries = vec![];
+        let mut index_paths = vec![];
+        let mut kinds = vec![];
+        let mut iter = btree.search(bufmgr, SearchMode::Start)?;
+        while let Some((_, value)) = iter.next(bufmgr)? {
+            let (entry, _) = tuple::decode(&value)?;
+            let mut reader = EntryReader { values: entry.into_iter() };
+            let kind = reader.text()?;
+            let name = reader.text()?;
+            match kind.as_str() {
+                TABLE_ENTRY => {
+                    let mut info = reader.table_info(name)?;
+                    info.describe_relation();
+                    tables.insert(info.name.clone(), info);
+                }
+                STATS_ENTRY => stats.push((name, reader.stats()?)),
+                VIEW_ENTRY => {
+                    let info = reader.view_info(name)?;
+                    views.insert(info.name.clone(), info);
+                }
+                TRIGGER_ENTRY => {
+                    let info = reader.trigger_info(name)?;
+                    triggers.insert(info.name.clone(), info);
+                }
+                USER_ENTRY => {
+                    let password = match reader.value()? {
+                        Value::Text(password) => Some(password),
+                        Value::Null => None,
+                        _ => return Err(Error::Malformed),
+                    };
+                    let superuser = match reader.value()? {
+                        Value::Bool(superuser) => superuser,
+                        _ => return Err(Error::Malformed),
+                    };
+                    users.insert(name.clone(), UserInfo { name, password, superuser });
+                }
+                GRANT_ENTRY => {
+                    let user = reader.text()?;
+                    grants.insert((name, user), reader.int()?);
+                }
+                BUILDING_ENTRY => building.push((name, reader.text()?)),
+                FULLTEXT_ENTRY => kinds.push((name, reader.text()?, IndexKind::FullText)),
+                RTREE_ENTRY => kinds.push((name, reader.text()?, IndexKind::RTree)),
+                ZONEMAP_ENTRY => kinds.push((name, reader.text()?, IndexKind::ZoneMap)),
+                FREE_ENTRY => {
+                    free_pages.insert(PageId(name.parse().map_err(|_| Error::Malformed)?));
+                }
+                PARTITIONED_ENTRY => {
+                    let strategy = reader.strategy()?;
+                    partitioned.push((name, Partitioning { strategy, column: reader.int()? as usize, partitions: vec![] }));
+                }
+                PARTITION_ENTRY => partitions.push((name, reader.partition()?)),
+                COLUMNAR_ENTRY => {
+                    let mut btree = || Ok::<_, Error>(BTree { meta_page_id: PageId(reader.int()? as u64) });
+                    columnar.push((name, Columnar { stripes: btree()?, segments: btree()? }));
+                }
+                TTL_ENTRY => ttls.push((name, Ttl { column: reader.int()? as usize, seconds: reader.int()? })),
+                DICTIONARY_ENTRY => {
+                    let mut btree = || Ok::<_, Error>(BTree { meta_page_id: PageId(reader.int()? as u64) });
+                    let (codes, values) = (btree()?, btree()?);
+                    let mut columns = vec![];
+                    while !reader.is_empty() {
+                        columns.push(reader.int()? as usize);
+                    }
+                    dictionaries.push((name, Dictionary { columns, codes, values }));
+                }
+                INDEX_PATHS_ENTRY => {
+                    let index_name = reader.text()?;
+                    let mut paths = vec![];
+                    while !reader.is_empty() {
+                        let position = reader.int()? as usize;
+                        let steps = (0..reader.int()?).map(|_| reader.text()).collect::<Result<_, _>>()?;
+                        let text = match reader.value()? {
+                            Value::Bool(text) => text,
+                            _ => return Err(Error::Malformed),
+                        };
+                        paths.push((position, json::Path { steps, text }));
+                    }
+                    index_paths.push((name, index_name, paths));
+                }
+                _ => return Err(Error::Malformed),
+            }
+        }
+        for (name, table_stats) in stats {
+            tables.get_mut(&name).ok_or(Error::Malformed)?.stats = Some(table_stats);
+        }
+        for (table_name, index_name) in building {
+            let info = tables.get_mut(&table_name).ok_or(Error::Malformed)?;
+            let i = info.index_names.iter().position(|name| *name == index_name).ok_or(Error::Malformed)?;
+            info.table.indexes[i].valid = false;
+        }
+        for (table_name, index_name, kind) in kinds {
+            let info = tables.get_mut(&table_name).ok_or(Error::Malformed)?;
+            let i = info.index_names.iter().position(|name| *name == index_name).ok_or(Error::Malformed)?;
+            info.table.indexes[i].kind = kind;
+        }
+        for (name, partitioning) in partitioned {
+            tables.get_mut(&name).ok_or(Error::Malformed)?.partitioning = Some(partitioning);
+        }
+        for (name, partition) in partitions {
+            let parent = tables.get_mut(&partition.parent).and_then(|info| info.partitioning.as_mut()).ok_or(Error::Malformed)?;
+            parent.partitions.push(name.clone());
+            tables.get_mut(&name).ok_or(Error::Malformed)?.partition = Some(partition);
+        }
+        for (name, columnar) in columnar {
+            tables.get_mut(&name).ok_or(Error::Malformed)?.table.columnar = Some(columnar);
+        }
+        for (name, ttl) in ttls {
+            tables.get_mut(&name).ok_or(Error::Malformed)?.table.ttl = Some(ttl);
+        }
+        for (name, dictionary) in dictionaries {
+            tables.get_mut(&name).ok_or(Error::Malformed)?.table.dictionary = Some(dictionary);
+        }
+        for (table_name, index_name, paths) in index_paths {
+            let info = tables.get_mut(&table_name).ok_or(Error::Malformed)?;
+            let i = info.index_names.iter().position(|name| *name == index_name).ok_or(Error::Malformed)?;
+            let index = &mut info.table.indexes[i];
+            for (position, path) in paths {
+                *index.paths.get_mut(position).ok_or(Error::Malformed)? = Some(path);
+            }
+        }
+        Ok(Self { btree, tables, views, users, grants, free_pages, virtual_tables: BTreeMap::new(), functions: BTreeMap::new(), aggregates: BTreeMap::new(), table_functions: BTreeMap::new(), triggers, temp_tables: BTreeMap::new(), committed: None, version: next_version() })
+    }
+
+    // Loads the catalog again, e.g. after a rollback, keeping the virtual and temporary tables and
+    // the functions and triggers registered.
+    pub fn reload(&mut self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        let virtual_tables = std::mem::take(&mut self.virtual_tables);
+        let functions = std::mem::take(&mut self.functions);
+        let aggregates = std::mem::take(&mut self.aggregates);
+        let table_functions = std::mem::take(&mut self.table_functions);
+        let callbacks: Vec<_> = self.triggers.values().filter(|info| matches!(info.action, Action::Callback(_))).cloned().collect();
+        let temp_tables = std::mem::take(&mut self.temp_tables);
+        *self = Self::open(bufmgr)?;
+        self.virtual_tables = virtual_tables;
+        self.functions = functions;
+        self.aggregates = aggregates;
+        self.table_functions = table_functions;
+        self.triggers.extend(callbacks.into_iter().map(|info| (info.name.clone(), info)));
+        self.temp_tables = temp_tables;
+        Ok(())
+    }
+
+    // The catalog as committed, with the transaction which has changed it since, if one begun by
+    // BEGIN has.
+    pub fn take_committed(&mut self) -> Option<(TxId, Catalog)> {
+        self.committed.take().map(|(txid, catalog)| (txid, *catalog))
+    }
+
+    // Locks the catalog for the current transaction, which is about to change it, keeping it as
+    // committed if the transaction is one begun by BEGIN changing it for the first time. Changes
+    // to it in memory come after, so that a reload undoes them if the transaction rolls back.
+    fn lock(&mut self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        let first = !lock::holds_catalog(bufmgr);
+        lock::lock_catalog(bufmgr)?;
+        if let (true, Some(_), Some(txid)) = (first, bufmgr.isolation(), bufmgr.current_txid()) {
+            self.committed = Some((txid, Box::new(Catalog { committed: None, temp_tables: BTreeMap::new(), ..self.clone() })));
+        }
+        self.version = next_version();
+        Ok(())
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn table(&self, name: &str) -> Option<&TableInfo> {
+        self.temp_tables.get(name).map(|(info, _)| info).or_else(|| self.tables.get(name))
+    }
+
+    // The tables stored in the database, the temporary ones left out.
+    pub fn tables(&self) -> impl Iterator<Item = &TableInfo> {
+        self.tables.values()
+    }
+
+    pub fn temp_tables(&self) -> impl Iterator<Item = &TableInfo> {
+        self.temp_tables.values().map(|(info, _)| info)
+    }
+
+    pub fn is_temp(&self, name: &str) -> bool {
+        self.temp_tables.contains_key(name)
+    }
+
+    // Sets the temporary tables, those of the session about to run, returning those there were.
+    pub fn set_temp_tables(&mut self, tables: TempTables) -> TempTables {
+        std::mem::replace(&mut self.temp_tables, tables)
+    }
+
+    // Drops the temporary tables created by transactions which have aborted, or all of them if
+    // `all`, freeing their pages. Those whose pages can't be read are left to take up space in
+    // the file until the database is next opened.
+    pub fn drop_temp_tables(&mut self, bufmgr: &mut BufferPoolManager, all: bool) {
+        let dropped: Vec<String> = self
+            .temp_tables
+            .iter()
+            .filter(|(_, (_, txid))| all || txid.is_some_and(|txid| bufmgr.is_aborted(txid)))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in dropped {
+            let (info, _) = self.temp_tables.remove(&name).unwrap();
+            self.version = next_version();
+            if let Ok(pages) = info.table.pages(bufmgr) {
+                bufmgr.free_temp_table_pages(pages);
+            }
+        }
+    }
+
+    pub fn view(&self, name: &str) -> Option<&ViewInfo> {
+        self.views.get(name)
+    }
+
+    pub fn views(&self) -> impl Iterator<Item = &ViewInfo> {
+        self.views.values()
+    }
+
+    pub fn user(&self, name: &str) -> Option<&UserInfo> {
+        self.users.get(name)
+    }
+
+    pub fn users(&self) -> impl Iterator<Item = &UserInfo> {
+        self.users.values()
+    }
+
+    // What `user` has been granted on the table or view.
+    pub fn privileges(&self, table: &str, user: &str) -> Vec<Privilege> {
+        let bits = self.grants.get(&(table.to_string(), user.to_string())).copied().unwrap_or(0);
+        Privilege::ALL.into_iter().filter(|p| bits & p.bit() != 0).collect()
+    }
+
+    // (table, user, privileges) of each grant, by table then user.
+    pub fn grants(&self) -> impl Iterator<Item = (&str, &str, Vec<Privilege>)> {
+        self.grants
+            .keys()
+            .map(|(table, user)| (table.as_str(), user.as_str(), self.privileges(table, user)))
+            .filter(|(_, _, privileges)| !privileges.is_empty())
+    }
+
+    pub fn virtual_table(&self, name: &str) -> Option<&Rc<dyn VirtualTable>> {
+        self.virtual_tables.get(name)
+    }
+
+    pub fn virtual_tables(&self) -> impl Iterator<Item = (&str, &Rc<dyn VirtualTable>)> {
+        self.virtual_tables.iter().map(|(name, table)| (name.as_str(), table))
+    }
+
+    // Registers `table` under `name`, in memory only.
+    pub fn register_virtual_table(&mut self, name: &str, table: Rc<dyn VirtualTable>) -> Result<(), Error> {
+        self.check_name(name)?;
+        self.virtual_tables.insert(name.to_string(), table);
+        self.version = next_version();
+        Ok(())
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Rc<ScalarFunction>> {
+        self.functions.get(name)
+    }
+
+    pub fn aggregate(&self, name: &str) -> Option<&Arc<AggregateFunction>> {
+        self.aggregates.get(name)
+    }
+
+    pub fn aggregates(&self) -> impl Iterator<Item = &Arc<AggregateFunction>> {
+        self.aggregates.values()
+    }
+
+    // Registers `func` under its name, in memory only, in place of the scalar or aggregate
+    // function of the name if any. Functions have a namespace of their own.
+    pub fn register_function(&mut self, func: ScalarFunction) {
+        self.aggregates.remove(&func.name);
+        self.functions.insert(func.name.clone(), Rc::new(func));
+        self.version = next_version();
+    }
+
+    pub fn register_aggregate(&mut self, func: AggregateFunction) {
+        self.functions.remove(&func.name);
+        self.aggregates.insert(func.name.clone(), Arc::new(func));
+        self.version = next_version();
+    }
+
+    // Those in FROM are of a namespace of their own, registered ones found before built-in ones.
+    pub fn table_function(&self, name: &str) -> Option<Rc<TableFunction>> {
+        self.table_functions.get(name).cloned().or_else(|| udf::builtin_table_function(name))
+    }
+
+    pub fn register_table_function(&mut self, func: TableFunction) {
+        self.table_functions.insert(func.name.clone(), Rc::new(func));
+        self.version = next_version();
+    }
+
+    pub fn triggers(&self) -> impl Iterator<Item = &TriggerInfo> {
+        self.triggers.values()
+    }
+
+    // Those run at `timing` for `event` on the rows of `table`, in order of their names.
+    pub fn triggers_on<'a>(&'a self, table: &'a str, timing: Timing, event: Event) -> impl Iterator<Item = &'a TriggerInfo> {
+        self.triggers.values().filter(move |info| info.table == table && info.timing == timing && info.event == event)
+    }
+
+    // The statement of a trigger of `Action::Sql` is not checked here; the caller has planned it
+    // already. One of `Action::Callback` is kept in memory only.
+    pub fn create_trigger(&mut self, bufmgr: &mut BufferPoolManager, info: TriggerInfo) -> Result<&TriggerInfo, Error> {
+        if self.triggers.contains_key(&info.name) {
+            return Err(Error::TriggerExists(info.name));
+        }
+        if self.table(&info.table).is_none() {
+            return Err(Error::UnknownTable(info.table));
+        }
+        if let Action::Sql(sql) = &info.action {
+            self.lock(bufmgr)?;
+            let timing = match info.timing {
+                Timing::Before => 0,
+                Timing::After => 1,
+            };
+            let event = match info.event {
+                Event::Insert => 0,
+                Event::Update => 1,
+                Event::Delete => 2,
+            };
+            self.put(bufmgr, TRIGGER_ENTRY, &info.name, vec![Value::Text(info.table.clone()), Value::Int(timing), Value::Int(event), Value::Text(sql.clone())])?;
+        }
+        self.version = next_version();
+        Ok(self.triggers.entry(info.name.clone()).or_insert(info))
+    }
+
+    // Creates a table whose primary key is its first `num_key_elems` columns.
+    pub fn create_table(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        name: &str,
+        columns: Vec<Column>,
+        num_key_elems: usize,
+    ) -> Result<&TableInfo, Error> {
+        self.check_name(name)?;
+        self.lock(bufmgr)?;
+        let table = self.reusing_free_pages(bufmgr, |bufmgr, _| Ok(Table::create(bufmgr, num_key_elems)?))?;
+        self.put_table(bufmgr, name, columns, table)
+    }
+
+    // Creates a table as `create_table` does, its pages kept in memory (see
+    // `BufferPoolManager::creating_memory_pages`). An unlogged one is emptied each time the
+    // database is opened (see `clear_unlogged_tables`).
+    pub fn create_memory_table(&mut self, bufmgr: &mut BufferPoolManager, name: &str, columns: Vec<Column>, num_key_elems: usize, logged: bool) -> Result<&TableInfo, Error> {
+        self.check_name(name)?;
+        self.lock(bufmgr)?;
+        let table = bufmgr.creating_memory_pages(logged, |bufmgr| Table::create(bufmgr, num_key_elems))?;
+        self.put_table(bufmgr, name, columns, table)
+    }
+
+    // Creates a columnar table (see `columnar`), as `create_table` does a table.
+    pub fn create_columnar_table(&mut self, bufmgr: &mut BufferPoolManager, name: &str, columns: Vec<Column>, num_key_elems: usize) -> Result<&TableInfo, Error> {
+        self.check_name(name)?;
+        self.lock(bufmgr)?;
+        let table = self.reusing_free_pages(bufmgr, |bufmgr, _| Ok(Table::create_columnar(bufmgr, num_key_elems)?))?;
+        let columnar = table.columnar.unwrap();
+        let page = |btree: BTree| Value::Int(btree.meta_page_id.0 as i64);
+        self.put(bufmgr, COLUMNAR_ENTRY, name, vec![page(columnar.stripes), page(columnar.segments)])?;
+        self.put_table(bufmgr, name, columns, table)
+    }
+
+    // Makes the rows of table `name` expire by `ttl` (see `table::Ttl`).
+    pub fn set_ttl(&mut self, bufmgr: &mut BufferPoolManager, name: &str, ttl: Ttl) -> Result<&TableInfo, Error> {
+        if !self.tables.contains_key(name) {
+            return Err(Error::UnknownTable(name.to_string()));
+        }
+        self.lock(bufmgr)?;
+        self.put(bufmgr, TTL_ENTRY, name, vec![int(ttl.column), Value::Int(ttl.seconds)])?;
+        let info = self.tables.get_mut(name).unwrap();
+        info.table.ttl = Some(ttl);
+        Ok(info)
+    }
+
+    // Keeps the values of `columns` of table `name`, which has no rows yet, in a dictionary (see
+    // `dictionary`).
+    pub fn set_dictionary(&mut self, bufmgr: &mut BufferPoolManager, name: &str, columns: Vec<usize>) -> Result<&TableInfo, Error> {
+        if !self.tables.contains_key(name) {
+            return Err(Error::UnknownTable(name.to_string()));
+        }
+        self.lock(bufmgr)?;
+        self.reusing_free_pages(bufmgr, |bufmgr, catalog| Ok(catalog.tables.get_mut(name).unwrap().table.create_dictionary(bufmgr, columns)?))?;
+        let dictionary = self.tables[name].table.dictionary.clone().unwrap();
+        let page = |btree: BTree| Value::Int(btree.meta_page_id.0 as i64);
+        let mut entry = vec![page(dictionary.codes), page(dictionary.values)];
+        entry.extend(dictionary.columns.iter().map(|&column| int(column)));
+        self.put(bufmgr, DICTIONARY_ENTRY, name, entry)?;
+        Ok(&self.tables[name])
+    }
+
+    // Empties the unlogged tables, whose pages were lost when the database went down, as it's
+    // opened. Their meta pages, which the catalog still points to, are allocated again first.
+    pub fn clear_unlogged_tables(&self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        let unlogged: Vec<&Table> = self.tables.values().map(|info| &info.table).filter(|table| !table.is_logged()).collect();
+        for table in &unlogged {
+            bufmgr.mark_allocated(table.btree.meta_page_id);
+            for index in &table.indexes {
+                bufmgr.mark_allocated(index.btree.meta_page_id);
+            }
+            for btree in table.dictionary.iter().flat_map(|dictionary| [dictionary.codes, dictionary.values]) {
+                bufmgr.mark_allocated(btree.meta_page_id);
+            }
+        }
+        for table in unlogged {
+            table.clear(bufmgr)?;
+        }
+        Ok(())
+    }
+
+    fn put_table(&mut self, bufmgr: &mut BufferPoolManager, name: &str, columns: Vec<Column>, mut table: Table) -> Result<&TableInfo, Error> {
+        table.num_columns = Some(columns.len());
+        let mut info = TableInfo {
+            name: name.to_string(),
+            columns,
+            table,
+            index_names: vec![],
+            stats: None,
+            partitioning: None,
+            partition: None,
+        };
+        info.describe_relation();
+        self.put(bufmgr, TABLE_ENTRY, name, encode_table_info(&info))?;
+        Ok(self.tables.entry(name.to_string()).or_insert(info))
+    }
+
+    // Creates a temporary table, as `create_table` does but in memory only, its pages being those
+    // of temporary tables (see `BufferPoolManager::creating_temp_pages`). It's dropped if the
+    // transaction aborts, and otherwise when the session ends.
+    pub fn create_temp_table(&mut self, bufmgr: &mut BufferPoolManager, name: &str, columns: Vec<Column>, num_key_elems: usize) -> Result<&TableInfo, Error> {
+        self.check_name(name)?;
+        let txid = bufmgr.txid().map_err(btree::Error::from)?;
+        let table = bufmgr.creating_temp_pages(|bufmgr| Table::create(bufmgr, num_key_elems))?;
+        let mut info = TableInfo {
+            name: name.to_string(),
+            columns,
+            table,
+            index_names: vec![],
+            stats: None,
+            partitioning: None,
+            partition: None,
+        };
+        info.describe_relation();
+        self.version = next_version();
+        Ok(&self.temp_tables.entry(name.to_string()).or_insert((info, txid)).0)
+    }
+
+    // Creates a table partitioned by `strategy` on `column`, which must be of its primary key. Its
+    // rows are in the partitions created by `create_partition`.
+    pub fn create_partitioned_table(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        name: &str,
+        columns: Vec<Column>,
+        num_key_elems: usize,
+        strategy: Strategy,
+        column: usize,
+    ) -> Result<&TableInfo, Error> {
+        assert!(column < num_key_elems);
+        self.create_table(bufmgr, name, columns, num_key_elems)?;
+        let strategy_value = Value::Int(match strategy {
+            Strategy::Range => 0,
+            Strategy::Hash => 1,
+        });
+        self.put(bufmgr, PARTITIONED_ENTRY, name, vec![strategy_value, int(column)])?;
+        let info = self.tables.get_mut(name).unwrap();
+        info.partitioning = Some(Partitioning { strategy, column, partitions: vec![] });
+        Ok(info)
+    }
+
+    // Creates a partition of `parent`, with its columns and primary key, holding the rows whose
+    // partition key is within `bound`, which may not overlap those of its other partitions.
+    pub fn create_partition(&mut self, bufmgr: &mut BufferPoolManager, name: &str, parent: &str, bound: PartitionBound) -> Result<&TableInfo, Error> {
+        let info = self.tables.get(parent).ok_or_else(|| Error::UnknownTable(parent.to_string()))?;
+        let partitioning = info.partitioning.as_ref().ok_or_else(|| Error::NotPartitioned(parent.to_string()))?;
+        if bound.strategy() != partitioning.strategy {
+            return Err(Error::InvalidBound(name.to_string(), format!("{} is partitioned by {}", parent, partitioning.strategy)));
+        }
+        bound.check(info.columns[partitioning.column].data_type).map_err(|reason| Error::InvalidBound(name.to_string(), reason))?;
+        for other in &partitioning.partitions {
+            if self.tables[other].partition.as_ref().is_some_and(|partition| partition.bound.overlaps(&bound)) {
+                return Err(Error::PartitionOverlap(name.to_string(), other.clone()));
+            }
+        }
+        let (columns, num_key_elems) = (info.columns.clone(), info.table.num_key_elems);
+        self.create_table(bufmgr, name, columns, num_key_elems)?;
+        let mut fields = vec![Value::Text(parent.to_string())];
+        match &bound {
+            PartitionBound::Range { lower, upper } => {
+                fields.extend([Value::Int(0), lower.clone().unwrap_or(Value::Null), upper.clone().unwrap_or(Value::Null)]);
+            }
+            PartitionBound::Hash { modulus, remainder } => fields.extend([Value::Int(1), Value::Int(*modulus as i64), Value::Int(*remainder as i64)]),
+        }
+        self.put(bufmgr, PARTITION_ENTRY, name, fields)?;
+        let partitions = &mut self.tables.get_mut(parent).unwrap().partitioning.as_mut().unwrap().partitions;
+        let position = partitions.partition_point(|other| other.as_str() < name);
+        partitions.insert(position, name.to_string());
+        let info = self.tables.get_mut(name).unwrap();
+        info.partition = Some(Partition { parent: parent.to_string(), bound });
+        Ok(info)
+    }
+
+    // The table `row`, being written to `info`, goes to: the partition it belongs to if `info` is
+    // partitioned, `info` itself otherwise, if it's within its bound as a partition.
+    pub fn route<'c>(&'c self, info: &'c TableInfo, row: &[Value]) -> Result<&'c TableInfo, Error> {
+        if let Some(partitioning) = &info.partitioning {
+            let key = &row[partitioning.column];
+            return partitioning
+                .partitions
+                .iter()
+                .map(|name| &self.tables[name])
+                .find(|partition| partition.partition.as_ref().is_some_and(|p| p.bound.contains(key)))
+                .ok_or_else(|| Error::NoPartition(info.name.clone()));
+        }
+        if let (Some(partition), Some(partitioning)) = (&info.partition, info.partition.as_ref().and_then(|p| self.tables[&p.parent].partitioning.as_ref())) {
+            if !partition.bound.contains(&row[partitioning.column]) {
+                return Err(Error::OutsidePartition(info.name.clone()));
+            }
+        }
+        Ok(info)
+    }
+
+    // The query of the view is not checked here; the caller has planned it already.
+    pub fn create_view(&mut self, bufmgr: &mut BufferPoolManager, info: ViewInfo) -> Result<&ViewInfo, Error> {
+        self.check_name(&info.name)?;
+        self.lock(bufmgr)?;
+        let mut fields = vec![Value::Text(info.sql.clone())];
+        fields.extend(info.columns.iter().map(|c| Value::Text(c.clone())));
+        self.put(bufmgr, VIEW_ENTRY, &info.name, fields)?;
+        Ok(self.views.entry(info.name.clone()).or_insert(info))
+    }
+
+    pub fn create_index(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        table_name: &str,
+        index_name: &str,
+        columns: Vec<usize>,
+        paths: Vec<Option<json::Path>>,
+        kind: IndexKind,
+    ) -> Result<(), Error> {
+        if self.tables.values().chain(self.temp_tables()).any(|t| t.index_names.iter().any(|n| n == index_name)) {
+            return Err(Error::IndexExists(index_name.to_string()));
+        }
+        // in memory only, like the table
+        if let Some((info, _)) = self.temp_tables.get_mut(table_name) {
+            info.table.create_index(bufmgr, columns, paths, kind)?;
+            info.index_names.push(index_name.to_string());
+            return Ok(());
+        }
+        match self.tables.get(table_name) {
+            None => return Err(Error::UnknownTable(table_name.to_string())),
+            // it has no rows to index, its partitions are indexed instead
+            Some(info) if info.partitioning.is_some() => return Err(Error::Partitioned(table_name.to_string())),
+            // the rows of its stripes can't be pointed to
+            Some(info) if info.table.columnar.is_some() => return Err(Error::Columnar(table_name.to_string())),
+            Some(_) => {}
+        }
+        self.lock(bufmgr)?;
+        self.reusing_free_pages(bufmgr, |bufmgr, catalog| Ok(catalog.tables.get_mut(table_name).unwrap().table.create_index(bufmgr, columns, paths, kind)?))?;
+        let info = self.tables.get_mut(table_name).unwrap();
+        info.index_names.push(index_name.to_string());
+        let entry = encode_table_info(info);
+        self.put(bufmgr, TABLE_ENTRY, table_name, entry)?;
+        self.put_index_entries(bufmgr, table_name, index_name)
+    }
+
+    // Adds an empty index, not valid until it's been filled (see `Table::build_index`) and
+    // `validate_index` has been called, but maintained by writes from now on.
+    pub fn add_index(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        table_name: &str,
+        index_name: &str,
+        columns: Vec<usize>,
+        paths: Vec<Option<json::Path>>,
+        kind: IndexKind,
+    ) -> Result<(), Error> {
+        if self.tables.values().chain(self.temp_tables()).any(|t| t.index_names.iter().any(|n| n == index_name)) {
+            return Err(Error::IndexExists(index_name.to_string()));
+        }
+        self.check_not_temp(table_name)?;
+        match self.tables.get(table_name) {
+            None => return Err(Error::UnknownTable(table_name.to_string())),
+            // it has no rows to index, its partitions are indexed instead
+            Some(info) if info.partitioning.is_some() => return Err(Error::Partitioned(table_name.to_string())),
+            // the rows of its stripes can't be pointed to
+            Some(info) if info.table.columnar.is_some() => return Err(Error::Columnar(table_name.to_string())),
+            Some(_) => {}
+        }
+        self.lock(bufmgr)?;
+        self.reusing_free_pages(bufmgr, |bufmgr, catalog| Ok(catalog.tables.get_mut(table_name).unwrap().table.add_index(bufmgr, columns, paths, kind)?))?;
+        let info = self.tables.get_mut(table_name).unwrap();
+        info.index_names.push(index_name.to_string());
+        let entry = encode_table_info(info);
+        self.put(bufmgr, TABLE_ENTRY, table_name, entry)?;
+        self.put_index_entries(bufmgr, table_name, index_name)?;
+        self.put_entry(bufmgr, &[BUILDING_ENTRY, table_name, index_name], vec![])
+    }
+
+    // Puts the entry of the expressions of the index just added, if it's on any, and that of it
+    // being a full-text, R-tree or zone map index if it is.
+    fn put_index_entries(&self, bufmgr: &mut BufferPoolManager, table_name: &str, index_name: &str) -> Result<(), Error> {
+        let index = self.tables[table_name].table.indexes.last().unwrap();
+        match index.kind {
+            IndexKind::FullText => self.put_entry(bufmgr, &[FULLTEXT_ENTRY, table_name, index_name], vec![])?,
+            IndexKind::RTree => self.put_entry(bufmgr, &[RTREE_ENTRY, table_name, index_name], vec![])?,
+            IndexKind::ZoneMap => self.put_entry(bufmgr, &[ZONEMAP_ENTRY, table_name, index_name], vec![])?,
+            IndexKind::BTree => {}
+        }
+        let mut fields = vec![];
+        for (position, path) in index.paths.iter().enumerate() {
+            if let Some(path) = path {
+                fields.extend([int(position), int(path.steps.len())]);
+                fields.extend(path.steps.iter().map(|step| Value::Text(step.clone())));
+                fields.push(Value::Bool(path.text));
+            }
+        }
+        if fields.is_empty() {
+            return Ok(());
+        }
+        self.put_entry(bufmgr, &[INDEX_PATHS_ENTRY, table_name, index_name], fields)
+    }
+
+    // Makes an index added by `add_index` one queries read from.
+    pub fn validate_index(&mut self, bufmgr: &mut BufferPoolManager, table_name: &str, index_name: &str) -> Result<(), Error> {
+        let info = self.tables.get(table_name).ok_or_else(|| Error::UnknownTable(table_name.to_string()))?;
+        let i = info.index_names.iter().position(|name| name == index_name).ok_or_else(|| Error::UnknownIndex(index_name.to_string()))?;
+        self.lock(bufmgr)?;
+        self.delete_entry(bufmgr, &[BUILDING_ENTRY, table_name, index_name])?;
+        self.tables.get_mut(table_name).unwrap().table.indexes[i].valid = true;
+        Ok(())
+    }
+
+    // Rebuilds an index (see `Table::rebuild_index`), in pages freed before if there are any,
+    // freeing those it no longer uses. One left being built by CREATE INDEX CONCURRENTLY is valid
+    // once rebuilt.
+    pub fn reindex(&mut self, bufmgr: &mut BufferPoolManager, table_name: &str, index_name: &str) -> Result<(), Error> {
+        self.check_freeable(table_name)?;
+        let info = self.tables.get(table_name).ok_or_else(|| Error::UnknownTable(table_name.to_string()))?;
+        let i = info.index_names.iter().position(|name| name == index_name).ok_or_else(|| Error::UnknownIndex(index_name.to_string()))?;
+        self.lock(bufmgr)?;
+        self.reusing_free_pages(bufmgr, |bufmgr, catalog| Ok(catalog.tables[table_name].table.rebuild_index(bufmgr, i)?))?;
+        if !self.tables[table_name].table.indexes[i].valid {
+            self.validate_index(bufmgr, table_name, index_name)?;
+        }
+        Ok(())
+    }
+
+    // Rewrites a table and its indexes into as few pages as they fit in (see `Table::rewrite`),
+    // the lowest of those it had and of those freed before, freeing the rest, as VACUUM FULL does.
+    pub fn rewrite_table(&mut self, bufmgr: &mut BufferPoolManager, table_name: &str) -> Result<(), Error> {
+        self.check_freeable(table_name)?;
+        if !self.tables.contains_key(table_name) {
+            return Err(Error::UnknownTable(table_name.to_string()));
+        }
+        self.lock(bufmgr)?;
+        self.reusing_free_pages(bufmgr, |bufmgr, catalog| Ok(catalog.tables[table_name].table.rewrite(bufmgr)?))
+    }
+
+    // Drops a table with its indexes, the privileges granted on it and its triggers, and a
+    // partitioned one with its partitions, freeing their pages. Those of an in-memory table are
+    // left to the pool, as those `Table::clear` replaces are. Views of it fail once it's gone, as
+    // they're planned. The entries are deleted and the pages freed as the transaction's changes,
+    // which its rollback undoes as it does any other.
+    pub fn drop_table(&mut self, bufmgr: &mut BufferPoolManager, name: &str) -> Result<(), Error> {
+        self.check_not_temp(name)?;
+        let info = self.tables.get(name).ok_or_else(|| Error::UnknownTable(name.to_string()))?;
+        let mut dropped = vec![name.to_string()];
+        dropped.extend(info.partitioning.iter().flat_map(|partitioning| partitioning.partitions.iter().cloned()));
+        self.lock(bufmgr)?;
+        for name in &dropped {
+            let table = &self.tables[name].table;
+            table.lock(bufmgr, LockMode::Exclusive)?;
+            let pages = if table.in_memory() { vec![] } else { table.pages(bufmgr)? };
+            for (kind, _) in TABLE_ENTRY_KINDS {
+                for (key, _) in self.entries_of(bufmgr, kind, name)? {
+                    self.btree.delete(bufmgr, &key)?;
+                }
+            }
+            let triggers: Vec<String> = self.triggers.values().filter(|info| info.table == *name).map(|info| info.name.clone()).collect();
+            for trigger in triggers {
+                self.delete_entry(bufmgr, &[TRIGGER_ENTRY, &trigger])?;
+                self.triggers.remove(&trigger);
+            }
+            self.free(bufmgr, pages)?;
+            let info = self.tables.remove(name).unwrap();
+            if let Some(parent) = info.partition.and_then(|partition| self.tables.get_mut(&partition.parent)) {
+                parent.partitioning.as_mut().unwrap().partitions.retain(|partition| partition != name);
+            }
+            self.grants.retain(|(table, _), _| table != name);
+        }
+        Ok(())
+    }
+
+    // Drops an index, freeing its pages as `drop_table` does.
+    pub fn drop_index(&mut self, bufmgr: &mut BufferPoolManager, index_name: &str) -> Result<(), Error> {
+        if let Some(info) = self.temp_tables().find(|info| info.index_names.iter().any(|name| name == index_name)) {
+            return Err(Error::Temporary(info.name.clone()));
+        }
+        let info = self.tables.values().find(|info| info.index_names.iter().any(|name| name == index_name));
+        let table_name = info.ok_or_else(|| Error::UnknownIndex(index_name.to_string()))?.name.clone();
+        self.lock(bufmgr)?;
+        let info = self.tables.get_mut(&table_name).unwrap();
+        info.table.lock(bufmgr, LockMode::Exclusive)?;
+        let i = info.index_names.iter().position(|name| name == index_name).unwrap();
+        info.index_names.remove(i);
+        let index = info.table.indexes.remove(i);
+        let pages = if info.table.in_memory() { vec![] } else { index.pages(bufmgr)? };
+        let entry = encode_table_info(info);
+        self.put(bufmgr, TABLE_ENTRY, &table_name, entry)?;
+        for kind in INDEX_ENTRY_KINDS {
+            self.delete_entry(bufmgr, &[kind, &table_name, index_name])?;
+        }
+        self.free(bufmgr, pages)
+    }
+
+    pub fn drop_view(&mut self, bufmgr: &mut BufferPoolManager, name: &str) -> Result<(), Error> {
+        if !self.views.contains_key(name) {
+            return Err(Error::UnknownView(name.to_string()));
+        }
+        self.lock(bufmgr)?;
+        self.delete_entry(bufmgr, &[VIEW_ENTRY, name])?;
+        for (key, _) in self.entries_of(bufmgr, GRANT_ENTRY, name)? {
+            self.btree.delete(bufmgr, &key)?;
+        }
+        self.views.remove(name);
+        self.grants.retain(|(table, _), _| table != name);
+        Ok(())
+    }
+
+    // Renames a table, with the entries of its indexes, of the privileges granted on it and of
+    // its partitions if it's partitioned. One with triggers can't be renamed, their statements
+    // naming it. Views of it fail once it's renamed, as they do once it's dropped.
+    pub fn rename_table(&mut self, bufmgr: &mut BufferPoolManager, name: &str, new_name: &str) -> Result<(), Error> {
+        self.check_not_temp(name)?;
+        if !self.tables.contains_key(name) {
+            return Err(Error::UnknownTable(name.to_string()));
+        }
+        self.check_name(new_name)?;
+        if self.triggers.values().any(|info| info.table == name) {
+            return Err(Error::HasTriggers(name.to_string()));
+        }
+        self.lock(bufmgr)?;
+        for (kind, num_ids) in TABLE_ENTRY_KINDS {
+            for (key, mut entry) in self.entries_of(bufmgr, kind, name)? {
+                self.btree.delete(bufmgr, &key)?;
+                entry[1] = Value::Text(new_name.to_string());
+                let mut key = vec![];
+                tuple::encode_key(&entry[..num_ids], &mut key);
+                let mut value = vec![];
+                tuple::encode(&entry, &mut value);
+                self.btree.upsert(bufmgr, &key, &value)?;
+            }
+        }
+        let mut info = self.tables.remove(name).unwrap();
+        info.name = new_name.to_string();
+        info.describe_relation();
+        for partition in info.partitioning.iter().flat_map(|partitioning| &partitioning.partitions) {
+            let mut entry = self.entries_of(bufmgr, PARTITION_ENTRY, partition)?.pop().ok_or(Error::Malformed)?.1;
+            // of [kind, name, parent, ...]
+            entry[2] = Value::Text(new_name.to_string());
+            self.put_entry(bufmgr, &[PARTITION_ENTRY, partition], entry.split_off(2))?;
+            self.tables.get_mut(partition).unwrap().partition.as_mut().unwrap().parent = new_name.to_string();
+        }
+        if let Some(parent) = info.partition.as_ref().and_then(|partition| self.tables.get_mut(&partition.parent)) {
+            let partitions = &mut parent.partitioning.as_mut().unwrap().partitions;
+            partitions.retain(|partition| partition != name);
+            let position = partitions.partition_point(|other| other.as_str() < new_name);
+            partitions.insert(position, new_name.to_string());
+        }
+        self.tables.insert(new_name.to_string(), info);
+        self.grants = std::mem::take(&mut self.grants)
+            .into_iter()
+            .map(|((table, user), bits)| ((if table == name { new_name.to_string() } else { table }, user), bits))
+            .collect();
+        Ok(())
+    }
+
+    // Adds a column to the end of those of a table, and of its partitions if it's partitioned,
+    // NULL in the rows it has (see `Table`), which aren't rewritten. The statistics of the table
+    // are dropped, to be collected again with those of the column.
+    pub fn add_column(&mut self, bufmgr: &mut BufferPoolManager, name: &str, column: Column) -> Result<(), Error> {
+        let tables = self.altered_tables(name)?;
+        if self.tables[name].columns.iter().any(|c| c.name == column.name) {
+            return Err(Error::ColumnExists(column.name));
+        }
+        // the columns of the rows of its stripes are fixed
+        if self.tables[name].table.columnar.is_some() {
+            return Err(Error::Columnar(name.to_string()));
+        }
+        self.lock(bufmgr)?;
+        for name in tables {
+            let info = self.tables.get_mut(&name).unwrap();
+            info.columns.push(column.clone());
+            info.table.num_columns = Some(info.columns.len());
+            info.describe_relation();
+            info.stats = None;
+            let entry = encode_table_info(info);
+            self.put(bufmgr, TABLE_ENTRY, &name, entry)?;
+            self.delete_entry(bufmgr, &[STATS_ENTRY, &name])?;
+        }
+        Ok(())
+    }
+
+    // Renames a column of a table, and of its partitions if it's partitioned.
+    pub fn rename_column(&mut self, bufmgr: &mut BufferPoolManager, name: &str, column: &str, new_column: &str) -> Result<(), Error> {
+        let tables = self.altered_tables(name)?;
+        let info = &self.tables[name];
+        let i = info.column_index(column).ok_or_else(|| Error::UnknownColumn(column.to_string()))?;
+        if info.column_index(new_column).is_some() {
+            return Err(Error::ColumnExists(new_column.to_string()));
+        }
+        self.lock(bufmgr)?;
+        for name in tables {
+            let info = self.tables.get_mut(&name).unwrap();
+            info.columns[i].name = new_column.to_string();
+            info.describe_relation();
+            let entry = encode_table_info(info);
+            self.put(bufmgr, TABLE_ENTRY, &name, entry)?;
+        }
+        Ok(())
+    }
+
+    // The tables whose columns are changed with those of table `name`: it and its partitions. A
+    // partition's are those of its parent, changed with them only.
+    fn altered_tables(&self, name: &str) -> Result<Vec<String>, Error> {
+        self.check_not_temp(name)?;
+        let info = self.tables.get(name).ok_or_else(|| Error::UnknownTable(name.to_string()))?;
+        if info.partition.is_some() {
+            return Err(Error::Partition(name.to_string()));
+        }
+        let mut tables = vec![name.to_string()];
+        tables.extend(info.partitioning.iter().flat_map(|partitioning| partitioning.partitions.iter().cloned()));
+        Ok(tables)
+    }
+
+    // The keys and values of the entries of `kind` of `name`, whose ids begin with them.
+    fn entries_of(&self, bufmgr: &mut BufferPoolManager, kind: &str, name: &str) -> Result<Vec<(Vec<u8>, Tuple)>, Error> {
+        let (prefix, _) = entry_key(&[kind, name]);
+        let mut entries = vec![];
+        let mut iter = self.btree.search(bufmgr, SearchMode::Key(prefix.clone()))?;
+        while let Some((key, value)) = iter.next(bufmgr)? {
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let (entry, _) = tuple::decode(&value)?;
+            entries.push((key, entry));
+        }
+        Ok(entries)
+    }
+
+    // Adds `pages`, of what's been dropped, to the free pages.
+    fn free(&mut self, bufmgr: &mut BufferPoolManager, pages: Vec<PageId>) -> Result<(), Error> {
+        self.reusing_free_pages(bufmgr, |bufmgr, _| {
+            for page_id in pages {
+                bufmgr.free_page(page_id);
+            }
+            Ok(())
+        })
+    }
+
+    // Takes the free pages at the end of the file out of the free list, returning how many pages
+    // it has without them: what it's to be truncated to once the transaction has committed.
+    pub fn take_free_tail(&mut self, bufmgr: &mut BufferPoolManager) -> Result<u64, Error> {
+        let mut num_pages = bufmgr.num_pages();
+        while num_pages > 0 && self.free_pages.contains(&PageId(num_pages - 1)) {
+            num_pages -= 1;
+        }
+        let tail: Vec<PageId> = self.free_pages.range(PageId(num_pages)..).copied().collect();
+        if !tail.is_empty() {
+            self.lock(bufmgr)?;
+        }
+        for page_id in tail {
+            self.delete_entry(bufmgr, &[FREE_ENTRY, &page_id.0.to_string()])?;
+            self.free_pages.remove(&page_id);
+        }
+        Ok(num_pages)
+    }
+
+    // The pages freed and not reused since.
+    pub fn free_pages(&self) -> impl Iterator<Item = PageId> + '_ {
+        self.free_pages.iter().copied()
+    }
+
+    // Runs `f` with the pages it creates taken from those freed, the lowest first, and those it
+    // frees added to them, putting and removing their entries after.
+    fn reusing_free_pages<T>(&mut self, bufmgr: &mut BufferPoolManager, f: impl FnOnce(&mut BufferPoolManager, &mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        let mut free = self.free_pages.clone();
+        let result = bufmgr.reusing_pages(&mut free, |bufmgr| f(bufmgr, self))?;
+        let taken: Vec<PageId> = self.free_pages.difference(&free).copied().collect();
+        for page_id in taken {
+            self.delete_entry(bufmgr, &[FREE_ENTRY, &page_id.0.to_string()])?;
+            self.free_pages.remove(&page_id);
+        }
+        let freed: Vec<PageId> = free.difference(&self.free_pages).copied().collect();
+        for page_id in freed {
+            self.put_entry(bufmgr, &[FREE_ENTRY, &page_id.0.to_string()], vec![])?;
+            self.free_pages.insert(page_id);
+        }
+        Ok(result)
+    }
+
+    pub fn create_user(&mut self, bufmgr: &mut BufferPoolManager, info: UserInfo) -> Result<(), Error> {
+        if self.users.contains_key(&info.name) {
+            return Err(Error::UserExists(info.name));
+        }
+        self.put_user(bufmgr, info)
+    }
+
+    // Replaces the password of a user, None for none.
+    pub fn set_password(&mut self, bufmgr: &mut BufferPoolManager, name: &str, password: Option<String>) -> Result<(), Error> {
+        let info = self.users.get(name).ok_or_else(|| Error::UnknownUser(name.to_string()))?;
+        self.put_user(bufmgr, UserInfo { password, ..info.clone() })
+    }
+
+    fn put_user(&mut self, bufmgr: &mut BufferPoolManager, info: UserInfo) -> Result<(), Error> {
+        self.lock(bufmgr)?;
+        let password = info.password.clone().map_or(Value::Null, Value::Text);
+        self.put(bufmgr, USER_ENTRY, &info.name, vec![password, Value::Bool(info.superuser)])?;
+        self.users.insert(info.name.clone(), info);
+        Ok(())
+    }
+
+    // Grants `privileges` on a table or view to a user, besides what they have already.
+    pub fn grant(&mut self, bufmgr: &mut BufferPoolManager, table: &str, user: &str, privileges: &[Privilege]) -> Result<(), Error> {
+        let bits = self.grant_bits(table, user)?;
+        self.put_grant(bufmgr, table, user, privileges.iter().fold(bits, |bits, p| bits | p.bit()))
+    }
+
+    // Takes `privileges` on a table or view away from a user, those they don't have aside.
+    pub fn revoke(&mut self, bufmgr: &mut BufferPoolManager, table: &str, user: &str, privileges: &[Privilege]) -> Result<(), Error> {
+        let bits = self.grant_bits(table, user)?;
+        self.put_grant(bufmgr, table, user, privileges.iter().fold(bits, |bits, p| bits & !p.bit()))
+    }
+
+    fn grant_bits(&self, table: &str, user: &str) -> Result<i64, Error> {
+        if !self.tables.contains_key(table) && !self.views.contains_key(table) {
+            return Err(Error::UnknownTable(table.to_string()));
+        }
+        if !self.users.contains_key(user) {
+            return Err(Error::UnknownUser(user.to_string()));
+        }
+        Ok(self.grants.get(&(table.to_string(), user.to_string())).copied().unwrap_or(0))
+    }
+
+    fn put_grant(&mut self, bufmgr: &mut BufferPoolManager, table: &str, user: &str, bits: i64) -> Result<(), Error> {
+        self.lock(bufmgr)?;
+        self.put_entry(bufmgr, &[GRANT_ENTRY, table, user], vec![Value::Int(bits)])?;
+        self.grants.insert((table.to_string(), user.to_string()), bits);
+        Ok(())
+    }
+
+    // Replaces the statistics of a table, e.g. after ANALYZE.
+    pub fn set_stats(&mut self, bufmgr: &mut BufferPoolManager, table_name: &str, stats: TableStats) -> Result<(), Error> {
+        if let Some((info, _)) = self.temp_tables.get_mut(table_name) {
+            info.stats = Some(stats);
+            self.version = next_version();
+            return Ok(());
+        }
+        if !self.tables.contains_key(table_name) {
+            return Err(Error::UnknownTable(table_name.to_string()));
+        }
+        self.lock(bufmgr)?;
+        self.put(bufmgr, STATS_ENTRY, table_name, encode_stats(&stats))?;
+        self.tables.get_mut(table_name).unwrap().stats = Some(stats);
+        Ok(())
+    }
+
+    fn check_name(&self, name: &str) -> Result<(), Error> {
+        if self.tables.contains_key(name) || self.temp_tables.contains_key(name) {
+            return Err(Error::TableExists(name.to_string()));
+        }
+        if self.views.contains_key(name) {
+            return Err(Error::ViewExists(name.to_string()));
+        }
+        if self.virtual_tables.contains_key(name) {
+            return Err(Error::VirtualTableExists(name.to_string()));
+        }
+        Ok(())
+    }
+
+    // Fails for a temporary table, whose pages aren't freed and reused as those of the others are.
+    fn check_not_temp(&self, table_name: &str) -> Result<(), Error> {
+        match self.temp_tables.contains_key(table_name) {
+            true => Err(Error::Temporary(table_name.to_string())),
+            false => Ok(()),
+        }
+    }
+
+    // Fails unless the table's pages are of the data file, whose free list is what a rebuild
+    // frees them to, as those of temporary and in-memory tables aren't.
+    fn check_freeable(&self, table_name: &str) -> Result<(), Error> {
+        self.check_not_temp(table_name)?;
+        match self.tables.get(table_name) {
+            Some(info) if info.table.in_memory() => Err(Error::InMemory(table_name.to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    fn put(&self, bufmgr: &mut BufferPoolManager, kind: &str, name: &str, fields: Tuple) -> Result<(), Error> {
+        self.put_entry(bufmgr, &[kind, name], fields)
+    }
+
+    fn delete_entry(&self, bufmgr: &mut BufferPoolManager, id: &[&str]) -> Result<(), Error> {
+        let (key, _) = entry_key(id);
+        self.btree.delete(bufmgr, &key)?;
+        Ok(())
+    }
+
+    fn put_entry(&self, bufmgr: &mut BufferPoolManager, id: &[&str], fields: Tuple) -> Result<(), Error> {
+        let (key, id) = entry_key(id);
+        let mut value = vec![];
+        tuple::encode(&[id, fields].concat(), &mut value);
+        self.btree.upsert(bufmgr, &key, &value)?;
+        Ok(())
+    }
+}
+
+// The key of the entry `id` in the catalog B+tree, along with its values.
+fn entry_key(id: &[&str]) -> (Vec<u8>, Vec<Value>) {
+    let id: Vec<Value> = id.iter().map(|s| Value::Text(s.to_string())).collect();
+    let mut key = vec![];
+    tuple::encode_key(&id, &mut key);
+    (key, id)
+}
+
+fn int(n: usize) -> Value {
+    Value::Int(n as i64)
+}
+
+fn encode_table_info(info: &TableInfo) -> Tuple {
+    let mut fields = vec![
+        Value::Int(info.table.btree.meta_page_id.0 as i64),
+        int(info.table.num_key_elems),
+        int(info.columns.len()),
+    ];
+    for column in &info.columns {
+        let data_type = match column.data_type {
+            DataType::Integer => 0,
+            DataType::Text => 1,
+            DataType::Boolean => 2,
+            DataType::Json => 3,
+            DataType::Array(ElementType::Integer) => 4,
+            DataType::Array(ElementType::Text) => 5,
+            DataType::Array(ElementType::Boolean) => 6,
+            DataType::Point => 7,
+            DataType::Box => 8,
+            DataType::Float => 9,
+            DataType::Numeric => 10,
+            DataType::Date => 11,
+            DataType::Timestamp => 12,
+        };
+        fields.extend([Value::Text(column.name.clone()), Value::Int(data_type)]);
+    }
+    for (name, index) in info.index_names.iter().zip(&info.table.indexes) {
+        fields.extend([Value::Text(name.clone()), Value::Int(index.btree.meta_page_id.0 as i64), int(index.columns.len())]);
+        fields.extend(index.columns.iter().map(|&c| int(c)));
+    }
+    fields
+}
+
+fn encode_stats(stats: &TableStats) -> Tuple {
+    let mut fields = vec![Value::Int(stats.row_count as i64)];
+    for column in &stats.columns {
+        fields.extend([
+            Value::Int(column.null_count as i64),
+            Value::Int(column.distinct_count as i64),
+            column.min.clone(),
+            column.max.clone(),
+            int(column.histogram.len()),
+        ]);
+        fields.extend(column.histogram.iter().cloned());
+    }
+    fields
+}
+
+struct EntryReader {
+    values: std::vec::IntoIter<Value>,
+}
+
+impl EntryReader {
+    fn is_empty(&self) -> bool {
+        self.values.len() == 0
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        self.values.next().ok_or(Error::Malformed)
+    }
+
+    fn int(&mut self) -> Result<i64, Error> {
+        match self.value()? {
+            Value::Int(n) if n >= 0 => Ok(n),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    fn text(&mut self) -> Result<String, Error> {
+        match self.value()? {
+            Value::Text(s) => Ok(s),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    fn table_info(&mut self, name: String) -> Result<TableInfo, Error> {
+        let btree = BTree {
+            meta_page_id: PageId(self.int()? as u64),
+        };
+        let num_key_elems = self.int()? as usize;
+        let mut columns = vec![];
+        for _ in 0..self.int()? {
+            let name = self.text()?;
+            let data_type = match self.int()? {
+                0 => DataType::Integer,
+                1 => DataType::Text,
+                2 => DataType::Boolean,
+                3 => DataType::Json,
+                4 => DataType::Array(ElementType::Integer),
+                5 => DataType::Array(ElementType::Text),
+                6 => DataType::Array(ElementType::Boolean),
+                7 => DataType::Point,
+                8 => DataType::Box,
+                9 => DataType::Float,
+                10 => DataType::Numeric,
+                11 => DataType::Date,
+                12 => DataType::Timestamp,
+                _ => return Err(Error::Malformed),
+            };
+            columns.push(Column { name, data_type });
+        }
+        let mut indexes = vec![];
+        let mut index_names = vec![];
+        while !self.is_empty() {
+            index_names.push(self.text()?);
+            let btree = BTree {
+                meta_page_id: PageId(self.int()? as u64),
+            };
+            let columns: Vec<usize> = (0..self.int()?).map(|_| Ok(self.int()? as usize)).collect::<Result<_, Error>>()?;
+            indexes.push(Index { btree, paths: Index::paths_of(&columns, vec![]), columns, valid: true, kind: IndexKind::BTree });
+        }
+        let num_columns = Some(columns.len());
+        Ok(TableInfo {
+            name,
+            columns,
+            table: Table {
+                btree,
+                num_key_elems,
+                indexes,
+                columnar: None,
+                ttl: None,
+                dictionary: None,
+                num_columns,
+                relation: None,
+            },
+            index_names,
+            stats: None,
+            partitioning: None,
+            partition: None,
+        })
+    }
+
+    fn strategy(&mut self) -> Result<Strategy, Error> {
+        match self.int()? {
+            0 => Ok(Strategy::Range),
+            1 => Ok(Strategy::Hash),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    fn partition(&mut self) -> Result<Partition, Error> {
+        let parent = self.text()?;
+        let bound = match self.strategy()? {
+            Strategy::Range => {
+                let mut bound = || Ok::<_, Error>(Some(self.value()?).filter(|value| !value.is_null()));
+                PartitionBound::Range { lower: bound()?, upper: bound()? }
+            }
+            Strategy::Hash => PartitionBound::Hash { modulus: self.int()? as u64, remainder: self.int()? as u64 },
+        };
+        Ok(Partition { parent, bound })
+    }
+
+    fn trigger_info(&mut self, name: String) -> Result<TriggerInfo, Error> {
+        let table = self.text()?;
+        let timing = match self.int()? {
+            0 => Timing::Before,
+            1 => Timing::After,
+            _ => return Err(Error::Malformed),
+        };
+        let event = match self.int()? {
+            0 => Event::Insert,
+            1 => Event::Update,
+            2 => Event::Delete,
+            _ => return Err(Error::Malformed),
+        };
+        Ok(TriggerInfo { name, table, timing, event, action: Action::Sql(self.text()?) })
+    }
+
+    fn view_info(&mut self, name: String) -> Result<ViewInfo, Error> {
+        let sql = self.text()?;
+        let mut columns = vec![];
+        while !self.is_empty() {
+            columns.push(self.text()?);
+        }
+        Ok(ViewInfo { name, sql, columns })
+    }
+
+    fn stats(&mut self) -> Result<TableStats, Error> {
+        let row_count = self.int()? as u64;
+        let mut columns = vec![];
+        while !self.is_empty() {
+            let null_count = self.int()? as u64;
+            let distinct_count = self.int()? as u64;
+            let min = self.value()?;
+            let max = self.value()?;
+            let histogram = (0..self.int()?).map(|_| self.value()).collect::<Result<_, _>>()?;
+            columns.push(ColumnStats {
+                null_count,
+                distinct_count,
+                min,
+                max,
+                histogram,
+            });
+        }
+        Ok(TableStats { row_count, columns })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let file = tempfile().unwrap();
+        let disk = DiskManager::new(file.try_clone().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(8));
+        let mut catalog = Catalog::create(&mut bufmgr).unwrap();
+        let columns = vec![
+            Column {
+                name: "id".to_string(),
+                data_type: DataType::Integer,
+            },
+            Column {
+                name: "name".to_string(),
+                data_type: DataType::Text,
+            },
+        ];
+        let table = catalog.create_table(&mut bufmgr, "users", columns, 1).unwrap().table.clone();
+        table.insert(&mut bufmgr, &[Value::Int(1), Value::Text("alice".to_string())]).unwrap();
+        catalog.create_index(&mut bufmgr, "users", "users_name", vec![1], vec![], IndexKind::BTree).unwrap();
+        let info = catalog.table("users").unwrap();
+        let stats = TableStats::collect(&mut bufmgr, &info.table, 2).unwrap();
+        catalog.set_stats(&mut bufmgr, "users", stats).unwrap();
+        assert!(matches!(catalog.create_index(&mut bufmgr, "users", "users_name", vec![0], vec![], IndexKind::BTree), Err(Error::IndexExists(_))));
+        let view = ViewInfo {
+            name: "names".to_string(),
+            sql: "SELECT name FROM users".to_string(),
+            columns: vec!["name".to_string()],
+        };
+        catalog.create_view(&mut bufmgr, view.clone()).unwrap();
+        assert!(matches!(catalog.create_view(&mut bufmgr, view.clone()), Err(Error::ViewExists(_))));
+        assert!(matches!(catalog.create_table(&mut bufmgr, "names", vec![], 0), Err(Error::ViewExists(_))));
+        assert!(matches!(Catalog::create(&mut bufmgr), Err(Error::NotEmpty)));
+        // users are named apart from tables
+        let user = UserInfo { name: "users".to_string(), password: None, superuser: true };
+        catalog.create_user(&mut bufmgr, user.clone()).unwrap();
+        assert!(matches!(catalog.create_user(&mut bufmgr, user), Err(Error::UserExists(_))));
+        catalog.set_password(&mut bufmgr, "users", Some("SCRAM-SHA-256$1:AA==$".to_string())).unwrap();
+        assert!(matches!(catalog.set_password(&mut bufmgr, "nobody", None), Err(Error::UnknownUser(_))));
+        catalog.grant(&mut bufmgr, "names", "users", &[Privilege::Select, Privilege::Delete, Privilege::Insert]).unwrap();
+        catalog.revoke(&mut bufmgr, "names", "users", &[Privilege::Insert, Privilege::Update]).unwrap();
+        assert!(matches!(catalog.grant(&mut bufmgr, "nothing", "users", &Privilege::ALL), Err(Error::UnknownTable(_))));
+        assert!(matches!(catalog.grant(&mut bufmgr, "users", "nobody", &Privilege::ALL), Err(Error::UnknownUser(_))));
+        // an index added empty isn't read from until it's made valid
+        catalog.add_index(&mut bufmgr, "users", "users_id", vec![0], vec![], IndexKind::BTree).unwrap();
+        catalog.add_index(&mut bufmgr, "users", "users_both", vec![1, 0], vec![], IndexKind::BTree).unwrap();
+        assert!(matches!(catalog.add_index(&mut bufmgr, "users", "users_id", vec![0], vec![], IndexKind::BTree), Err(Error::IndexExists(_))));
+        let table = &catalog.table("users").unwrap().table;
+        assert_eq!(None, table.build_index(&mut bufmgr, 1, None, 10).unwrap());
+        assert_eq!(2, table.access_paths().len());
+        catalog.validate_index(&mut bufmgr, "users", "users_id").unwrap();
+        assert_eq!(3, catalog.table("users").unwrap().table.access_paths().len());
+        assert!(matches!(catalog.validate_index(&mut bufmgr, "users", "users_none"), Err(Error::UnknownIndex(_))));
+        // triggers of a statement are kept, those of a callback only in memory
+        let sql = "CREATE TRIGGER log AFTER INSERT ON users BEGIN INSERT INTO users VALUES (NEW.id + 1, NULL); END".to_string();
+        let trigger = TriggerInfo { name: "log".to_string(), table: "users".to_string(), timing: Timing::After, event: Event::Insert, action: Action::Sql(sql) };
+        catalog.create_trigger(&mut bufmgr, trigger.clone()).unwrap();
+        assert!(matches!(catalog.create_trigger(&mut bufmgr, trigger.clone()), Err(Error::TriggerExists(_))));
+        assert!(matches!(catalog.create_trigger(&mut bufmgr, TriggerInfo { name: "other".to_string(), table: "names".to_string(), ..trigger.clone() }), Err(Error::UnknownTable(_))));
+        let callback = TriggerInfo { name: "check".to_string(), timing: Timing::Before, action: Action::Callback(Rc::new(|_| Ok(true))), ..trigger.clone() };
+        catalog.create_trigger(&mut bufmgr, callback).unwrap();
+        assert_eq!(vec!["log"], catalog.triggers_on("users", Timing::After, Event::Insert).map(|info| info.name.as_str()).collect::<Vec<_>>());
+        bufmgr.flush().unwrap();
+
+        let disk = DiskManager::new(file).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(8));
+        let mut reopened = Catalog::open(&mut bufmgr).unwrap();
+        let info = reopened.table("users").unwrap();
+        assert_eq!(catalog.table("users"), Some(info));
+        assert_eq!(1, info.stats.as_ref().unwrap().row_count);
+        assert_eq!(vec![true, true, false], info.table.indexes.iter().map(|index| index.valid).collect::<Vec<_>>());
+        assert_eq!(catalog.view("names"), reopened.view("names"));
+        assert_eq!(vec![&trigger], reopened.triggers().collect::<Vec<_>>());
+        assert_eq!(Some("SCRAM-SHA-256$1:AA==$"), reopened.user("users").and_then(|user| user.password.as_deref()));
+        assert!(reopened.user("users").unwrap().superuser);
+        assert_eq!(vec![Privilege::Select, Privilege::Delete], reopened.privileges("names", "users"));
+        assert!(reopened.privileges("users", "users").is_empty());
+        let mut iter = info.table.scan(&mut bufmgr).unwrap();
+        assert_eq!(Some(vec![Value::Int(1), Value::Text("alice".to_string())]), iter.next(&mut bufmgr).unwrap());
+        // rebuilt, an index is valid, and the pages it no longer uses are free for the next
+        reopened.reindex(&mut bufmgr, "users", "users_both").unwrap();
+        let info = reopened.table("users").unwrap();
+        assert!(info.table.indexes[2].valid);
+        assert_eq!(1, info.table.lookup(&mut bufmgr, table::Access::Index(2), &[Value::Text("alice".to_string())]).unwrap().len());
+        let num_pages = bufmgr.num_pages();
+        assert_eq!(1, reopened.free_pages().count());
+        reopened.reindex(&mut bufmgr, "users", "users_both").unwrap();
+        assert_eq!((num_pages, 1), (bufmgr.num_pages(), reopened.free_pages().count()));
+        assert_eq!(reopened.table("users"), Catalog::open(&mut bufmgr).unwrap().table("users"));
+        assert!(matches!(reopened.reindex(&mut bufmgr, "users", "users_none"), Err(Error::UnknownIndex(_))));
+
+        // partitions have the columns of their table, and rows are routed to them by their keys
+        let columns = catalog.table("users").unwrap().columns.clone();
+        reopened.create_partitioned_table(&mut bufmgr, "accounts", columns, 1, Strategy::Range, 0).unwrap();
+        let range = |lower: Option<i64>, upper: Option<i64>| PartitionBound::Range { lower: lower.map(Value::Int), upper: upper.map(Value::Int) };
+        reopened.create_partition(&mut bufmgr, "accounts_high", "accounts", range(Some(100), None)).unwrap();
+        reopened.create_partition(&mut bufmgr, "accounts_low", "accounts", range(None, Some(100))).unwrap();
+        assert!(matches!(reopened.create_partition(&mut bufmgr, "accounts_mid", "accounts", range(Some(50), Some(150))), Err(Error::PartitionOverlap(_, _))));
+        let hash = PartitionBound::Hash { modulus: 2, remainder: 0 };
+        assert!(matches!(reopened.create_partition(&mut bufmgr, "accounts_mid", "accounts", hash.clone()), Err(Error::InvalidBound(_, _))));
+        assert!(matches!(reopened.create_partition(&mut bufmgr, "users_0", "users", hash), Err(Error::NotPartitioned(_))));
+        assert!(matches!(reopened.create_index(&mut bufmgr, "accounts", "accounts_name", vec![1], vec![], IndexKind::BTree), Err(Error::Partitioned(_))));
+        let accounts = reopened.table("accounts").unwrap();
+        assert_eq!(vec!["accounts_high", "accounts_low"], accounts.partitioning.as_ref().unwrap().partitions);
+        let row = |id: i64| vec![Value::Int(id), Value::Null];
+        assert_eq!("accounts_low", reopened.route(accounts, &row(99)).unwrap().name);
+        assert_eq!("accounts_high", reopened.route(accounts, &row(100)).unwrap().name);
+        let high = reopened.table("accounts_high").unwrap();
+        assert!(matches!(reopened.route(high, &row(99)), Err(Error::OutsidePartition(_))));
+        let reopened_again = Catalog::open(&mut bufmgr).unwrap();
+        for name in ["accounts", "accounts_high", "accounts_low"] {
+            assert_eq!(reopened.table(name), reopened_again.table(name));
+        }
+    }
+}
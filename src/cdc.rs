@@ -0,0 +1,691 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::buffer::{self, BufferPoolManager};
+use crate::decoding::{self, Decoder, Operation, Position, Transaction};
+use crate::replication;
+use crate::sql;
+use crate::tuple::Tuple;
+use crate::disk::PageId;
+use crate::wal::Lsn;
+
+// Change data capture: a connector delivers the transactions logical decoding reads back from the
+// log (see `decoding`) to a sink, one message a transaction in the order they committed, each a
+// JSON object of the changes to the tables, named and with the columns they had when the changes
+// were made, even if they've been dropped or altered since:
+//
+//   {"offset":1234,"txid":7,"time":1700000000000,"changes":[
+//     {"table":"t","operation":"update","old":{"id":1,"name":"a"},"new":{"id":1,"name":"b"}}]}
+//
+// with the time of the commit in milliseconds since the epoch. The offset, the position in the
+// log after the commit, grows from one transaction to the next, for consumers to tell those
+// delivered more than once apart.
+//
+// Delivery is at least once: a connector keeps where it resumes from in an offset file, written
+// once the sink has taken the transactions before it, and keeps the log from being removed past
+// it with a slot of its own (see `BufferPoolManager::retain_log`). A transaction the sink fails to
+// take is tried again by the next step, and those taken since the offset file was last written
+// are delivered again after a crash. Sinks are pluggable (see `Sink`), those here appending to a
+// file, producing to a topic of a Kafka broker, and posting to a webhook.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error(transparent)]
+    Decoding(#[from] decoding::Error),
+    #[error(transparent)]
+    Replication(#[from] replication::Error),
+    #[error("malformed offset file")]
+    MalformedOffset,
+    #[error("logical decoding is turned off, which the logical key of the config turns on")]
+    NotLogical,
+    #[error("a change to the table with meta page {} was logged without its name and columns", .0 .0)]
+    Undecodable(PageId),
+    #[error("the sink failed: {0}")]
+    Sink(String),
+}
+
+// Transactions delivered by a step at most, before the offset file is written.
+const MAX_STEP_TRANSACTIONS: usize = 100;
+// How long a sink waits for a broker or webhook to answer.
+const SINK_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Where the transactions of a connector go.
+pub trait Sink {
+    // Delivers `message`, the transaction with the offset `offset`, failing unless the sink has
+    // taken it.
+    fn deliver(&mut self, offset: Lsn, message: &str) -> Result<(), Error>;
+}
+
+pub struct Connector {
+    // the name of the slot keeping the log
+    name: String,
+    offset_path: PathBuf,
+    decoder: Decoder,
+    sink: Box<dyn Sink>,
+    // decoded but not taken by the sink yet
+    undelivered: Option<Transaction>,
+    // where the offset file says to resume from
+    saved: Position,
+}
+
+impl Connector {
+    // Opens the connector `name`, delivering to `sink` from the position in the offset file at
+    // `offset_path`, or from the transactions committing from now on if there's no such file.
+    // Fails unless row changes are logged for logical decoding, which would be missed.
+    pub fn open(bufmgr: &mut BufferPoolManager, name: &str, offset_path: impl AsRef<Path>, sink: Box<dyn Sink>) -> Result<Self, Error> {
+        if !bufmgr.logical() {
+            return Err(Error::NotLogical);
+        }
+        let offset_path = offset_path.as_ref().to_path_buf();
+        let decoder = match fs::read(&offset_path) {
+            Ok(bytes) if bytes.len() == 16 => Decoder::resume(Position { restart: u64::from_le_bytes(bytes[..8].try_into().unwrap()), decoded: u64::from_le_bytes(bytes[8..].try_into().unwrap()) }),
+            Ok(_) => return Err(Error::MalformedOffset),
+            Err(e) if e.kind() == ErrorKind::NotFound => Decoder::start(bufmgr),
+            Err(e) => return Err(e.into()),
+        };
+        let mut connector = Self { name: name.to_string(), offset_path, saved: decoder.position(), decoder, sink, undelivered: None };
+        connector.save(bufmgr, connector.saved)?;
+        Ok(connector)
+    }
+
+    // Position the connector resumes from when opened again.
+    pub fn position(&self) -> Position {
+        self.saved
+    }
+
+    // Delivers the transactions committed since, up to MAX_STEP_TRANSACTIONS of them, returning
+    // how many were. Fails if the sink does, or the transaction can't be encoded, leaving it to be
+    // delivered by the next step.
+    pub fn step(&mut self, bufmgr: &mut BufferPoolManager) -> Result<usize, Error> {
+        let mut delivered = 0;
+        let mut position = self.saved;
+        let result = loop {
+            if delivered == MAX_STEP_TRANSACTIONS {
+                break Ok(());
+            }
+            if self.undelivered.is_none() {
+                match self.decoder.next(bufmgr) {
+                    Ok(Some(transaction)) => self.undelivered = Some(transaction),
+                    Ok(None) => break Ok(()),
+                    Err(e) => break Err(e.into()),
+                }
+            }
+            // one which can't be encoded fails every step, rather than being skipped
+            let transaction = self.undelivered.as_ref().unwrap();
+            if let Err(e) = encode(transaction).and_then(|message| self.sink.deliver(transaction.position.decoded, &message)) {
+                break Err(e);
+            }
+            position = transaction.position;
+            self.undelivered = None;
+            delivered += 1;
+        };
+        if position != self.saved {
+            self.save(bufmgr, position)?;
+        }
+        result.map(|_| delivered)
+    }
+
+    fn save(&mut self, bufmgr: &mut BufferPoolManager, position: Position) -> Result<(), Error> {
+        let mut bytes = position.restart.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&position.decoded.to_le_bytes());
+        let dir = self.offset_path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let name = self.offset_path.file_name().and_then(|name| name.to_str()).ok_or(Error::MalformedOffset)?;
+        replication::write_file(dir, name, &bytes)?;
+        bufmgr.retain_log(&self.name, Some(position.restart))?;
+        self.saved = position;
+        Ok(())
+    }
+
+    // Drops the slot of the connector `name`, for the log it kept to be removed.
+    pub fn drop_slot(bufmgr: &mut BufferPoolManager, name: &str) -> Result<(), Error> {
+        Ok(bufmgr.retain_log(name, None)?)
+    }
+}
+
+// The message of `transaction`. Fails on a change logged without the name and columns of its
+// table, which can't be told apart from others.
+pub fn encode(transaction: &Transaction) -> Result<String, Error> {
+    let time = transaction.time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis());
+    let mut out = format!("{{\"offset\":{},\"txid\":{},\"time\":{},\"changes\":[", transaction.position.decoded, transaction.txid, time);
+    let mut first = true;
+    for change in &transaction.changes {
+        let relation = change.relation.as_ref().ok_or(Error::Undecodable(change.table))?;
+        if !first {
+            out.push(',');
+        }
+        first = false;
+        out.push_str("{\"table\":");
+        sql::push_json_string(&mut out, &relation.name);
+        let operation = match change.operation {
+            Operation::Insert => "insert",
+            Operation::Update => "update",
+            Operation::Delete => "delete",
+        };
+        out.push_str(&format!(",\"operation\":\"{}\"", operation));
+        let columns: Vec<&str> = relation.columns.iter().map(String::as_str).collect();
+        for (key, row) in [("old", &change.old), ("new", &change.new)] {
+            out.push_str(&format!(",\"{}\":", key));
+            push_row(&mut out, &columns, row.as_ref());
+        }
+        out.push('}');
+    }
+    out.push_str("]}");
+    Ok(out)
+}
+
+fn push_row(out: &mut String, columns: &[&str], row: Option<&Tuple>) {
+    let Some(row) = row else {
+        out.push_str("null");
+        return;
+    };
+    out.push('{');
+    for (i, (name, value)) in columns.iter().zip(row).enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        sql::push_json_string(out, name);
+        out.push(':');
+        sql::push_json_value(out, value.clone());
+    }
+    out.push('}');
+}
+
+// Appends the messages to a file, a line each, synced before a message counts as taken. Those
+// whose offsets aren't past the last in the file are skipped, so that the file has each once.
+pub struct FileSink {
+    file: File,
+    last: Option<Lsn>,
+}
+
+impl FileSink {
+    // Opens the file at `path` to append to, cutting off a line torn by a crash at its end.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes)?;
+        let complete = bytes.iter().rposition(|&b| b == b'\n').map_or(0, |pos| pos + 1);
+        file.set_len(complete as u64)?;
+        let last = bytes[..complete].split(|&b| b == b'\n').rev().find(|line| !line.is_empty()).and_then(offset_of);
+        Ok(Self { file, last })
+    }
+}
+
+// The offset of a message, the first member of its object.
+fn offset_of(message: &[u8]) -> Option<Lsn> {
+    let rest = message.strip_prefix(b"{\"offset\":")?;
+    let digits = rest.iter().position(|b| !b.is_ascii_digit()).unwrap_or(rest.len());
+    std::str::from_utf8(&rest[..digits]).ok()?.parse().ok()
+}
+
+impl Sink for FileSink {
+    fn deliver(&mut self, offset: Lsn, message: &str) -> Result<(), Error> {
+        if self.last.is_some_and(|last| offset <= last) {
+            return Ok(());
+        }
+        self.file.write_all(format!("{}\n", message).as_bytes())?;
+        self.file.sync_data()?;
+        self.last = Some(offset);
+        Ok(())
+    }
+}
+
+// Produces the messages to a partition of a topic of a Kafka broker, or another speaking its
+// protocol, a record each keyed by its offset, taken once the broker answers that every replica
+// in sync has it. Records are sent in batches of the format of Kafka 0.11 on (magic 2), by
+// requests of version 3, the oldest brokers from 4.0 on still take. The broker must be the leader
+// of the partition.
+pub struct KafkaSink {
+    addr: SocketAddr,
+    topic: String,
+    partition: i32,
+    stream: Option<TcpStream>,
+    correlation_id: i32,
+}
+
+const PRODUCE_KEY: i16 = 0;
+const PRODUCE_VERSION: i16 = 3;
+const CLIENT_ID: &str = "beyond_rdb";
+
+impl KafkaSink {
+    pub fn new(addr: impl ToSocketAddrs, topic: &str, partition: i32) -> Result<Self, Error> {
+        let addr = addr.to_socket_addrs()?.next().ok_or_else(|| Error::Sink("no address for the broker".to_string()))?;
+        Ok(Self { addr, topic: topic.to_string(), partition, stream: None, correlation_id: 0 })
+    }
+
+    fn produce(&mut self, request: &[u8]) -> Result<Vec<u8>, Error> {
+        if self.stream.is_none() {
+            let stream = TcpStream::connect_timeout(&self.addr, SINK_TIMEOUT)?;
+            stream.set_read_timeout(Some(SINK_TIMEOUT))?;
+            stream.set_write_timeout(Some(SINK_TIMEOUT))?;
+            stream.set_nodelay(true)?;
+            self.stream = Some(stream);
+        }
+        let stream = self.stream.as_mut().unwrap();
+        stream.write_all(request)?;
+        let mut len = [0; 4];
+        stream.read_exact(&mut len)?;
+        let mut response = vec![0; i32::from_be_bytes(len).max(0) as usize];
+        stream.read_exact(&mut response)?;
+        Ok(response)
+    }
+}
+
+impl Sink for KafkaSink {
+    fn deliver(&mut self, offset: Lsn, message: &str) -> Result<(), Error> {
+        self.correlation_id = self.correlation_id.wrapping_add(1);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as i64);
+        let batch = record_batch(offset.to_string().as_bytes(), message.as_bytes(), now);
+        let mut body = vec![];
+        body.extend(PRODUCE_KEY.to_be_bytes());
+        body.extend(PRODUCE_VERSION.to_be_bytes());
+        body.extend(self.correlation_id.to_be_bytes());
+        put_string(&mut body, CLIENT_ID);
+        // no transactional id, and acknowledged by every replica in sync
+        body.extend((-1i16).to_be_bytes());
+        body.extend((-1i16).to_be_bytes());
+        body.extend((SINK_TIMEOUT.as_millis() as i32).to_be_bytes());
+        body.extend(1i32.to_be_bytes());
+        put_string(&mut body, &self.topic);
+        body.extend(1i32.to_be_bytes());
+        body.extend(self.partition.to_be_bytes());
+        body.extend((batch.len() as i32).to_be_bytes());
+        body.extend(batch);
+        let mut request = (body.len() as i32).to_be_bytes().to_vec();
+        request.extend(body);
+        let response = match self.produce(&request) {
+            Ok(response) => response,
+            Err(e) => {
+                // connected again for the next try
+                self.stream = None;
+                return Err(e);
+            }
+        };
+        let error_code = produce_error(&response, self.correlation_id).ok_or_else(|| {
+            self.stream = None;
+            Error::Sink("malformed produce response".to_string())
+        })?;
+        match error_code {
+            0 => Ok(()),
+            code => Err(Error::Sink(format!("the broker answered with error code {}", code))),
+        }
+    }
+}
+
+fn put_string(out: &mut Vec<u8>, s: &str) {
+    out.extend((s.len() as i16).to_be_bytes());
+    out.extend(s.as_bytes());
+}
+
+// A batch of a record of `key` and `value`.
+fn record_batch(key: &[u8], value: &[u8], timestamp: i64) -> Vec<u8> {
+    let mut record = vec![0];
+    // timestamp and offset deltas
+    put_varint(&mut record, 0);
+    put_varint(&mut record, 0);
+    put_varint(&mut record, key.len() as i64);
+    record.extend(key);
+    put_varint(&mut record, value.len() as i64);
+    record.extend(value);
+    // no headers
+    put_varint(&mut record, 0);
+    // what the CRC is of, from the attributes on
+    let mut checked = vec![];
+    // no compression, the time of creation
+    checked.extend(0i16.to_be_bytes());
+    // last offset delta
+    checked.extend(0i32.to_be_bytes());
+    checked.extend(timestamp.to_be_bytes());
+    checked.extend(timestamp.to_be_bytes());
+    // no producer id, epoch nor sequence
+    checked.extend((-1i64).to_be_bytes());
+    checked.extend((-1i16).to_be_bytes());
+    checked.extend((-1i32).to_be_bytes());
+    checked.extend(1i32.to_be_bytes());
+    put_varint(&mut checked, record.len() as i64);
+    checked.extend(record);
+    let mut batch = vec![];
+    // base offset, and the length of what follows
+    batch.extend(0i64.to_be_bytes());
+    batch.extend(((4 + 1 + 4 + checked.len()) as i32).to_be_bytes());
+    // no partition leader epoch
+    batch.extend((-1i32).to_be_bytes());
+    batch.push(2);
+    batch.extend(crc32c(&checked).to_be_bytes());
+    batch.extend(checked);
+    batch
+}
+
+// Zigzag encoded.
+fn put_varint(out: &mut Vec<u8>, value: i64) {
+    let mut value = ((value << 1) ^ (value >> 63)) as u64;
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+// CRC-32C (Castagnoli), which record batches are checked with.
+fn crc32c(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x82f6_3b78 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// The error code of the one partition a produce response is of, None if it's malformed or of
+// another request.
+fn produce_error(response: &[u8], correlation_id: i32) -> Option<i16> {
+    let bytes = &mut &response[..];
+    let int = |bytes: &mut &[u8]| Some(i32::from_be_bytes(take(bytes, 4)?.try_into().unwrap()));
+    if int(bytes)? != correlation_id || int(bytes)? != 1 {
+        return None;
+    }
+    let topic_len = i16::from_be_bytes(take(bytes, 2)?.try_into().unwrap()).max(0) as usize;
+    take(bytes, topic_len)?;
+    if int(bytes)? != 1 {
+        return None;
+    }
+    take(bytes, 4)?;
+    Some(i16::from_be_bytes(take(bytes, 2)?.try_into().unwrap()))
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    let taken = bytes.get(..len)?;
+    *bytes = &bytes[len..];
+    Some(taken)
+}
+
+// Posts the messages to a URL over HTTP/1.1, a request each, taken once answered with a status of
+// 2xx. Only plain http:// URLs are supported.
+pub struct WebhookSink {
+    host: String,
+    addr: SocketAddr,
+    path: String,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str) -> Result<Self, Error> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| Error::Sink(format!("not an http:// URL: {}", url)))?;
+        let (host, path) = match rest.find('/') {
+            Some(pos) => (&rest[..pos], &rest[pos..]),
+            None => (rest, "/"),
+        };
+        let addr = match host.contains(':') {
+            true => host.to_socket_addrs(),
+            false => (host, 80).to_socket_addrs(),
+        }?
+        .next()
+        .ok_or_else(|| Error::Sink(format!("no address for {}", host)))?;
+        Ok(Self { host: host.to_string(), addr, path: path.to_string() })
+    }
+}
+
+impl Sink for WebhookSink {
+    fn deliver(&mut self, _offset: Lsn, message: &str) -> Result<(), Error> {
+        let mut stream = TcpStream::connect_timeout(&self.addr, SINK_TIMEOUT)?;
+        stream.set_read_timeout(Some(SINK_TIMEOUT))?;
+        stream.set_write_timeout(Some(SINK_TIMEOUT))?;
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.path,
+            self.host,
+            message.len(),
+            message
+        );
+        stream.write_all(request.as_bytes())?;
+        let mut status_line = String::new();
+        BufReader::new(stream).read_line(&mut status_line)?;
+        let status = status_line.split(' ').nth(1).and_then(|status| status.parse::<u16>().ok());
+        match status {
+            Some(200..=299) => Ok(()),
+            _ => Err(Error::Sink(format!("the webhook answered {:?}", status_line.trim_end()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::database::Database;
+    use crate::tuple::Value;
+    use crate::table::Table;
+    use std::cell::RefCell;
+    use std::net::TcpListener;
+    use std::rc::Rc;
+    use std::thread;
+    use tempfile::tempdir;
+
+    // Fails the first `failures` messages, and keeps the offsets of the others.
+    struct Flaky {
+        failures: usize,
+        offsets: Rc<RefCell<Vec<Lsn>>>,
+    }
+
+    impl Sink for Flaky {
+        fn deliver(&mut self, offset: Lsn, _message: &str) -> Result<(), Error> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(Error::Sink("flaky".to_string()));
+            }
+            self.offsets.borrow_mut().push(offset);
+            Ok(())
+        }
+    }
+
+    fn get_varint(bytes: &mut &[u8]) -> i64 {
+        let (mut value, mut shift) = (0u64, 0);
+        loop {
+            let b = take(bytes, 1).unwrap()[0];
+            value |= ((b & 0x7f) as u64) << shift;
+            shift += 7;
+            if b < 0x80 {
+                return (value >> 1) as i64 ^ -((value & 1) as i64);
+            }
+        }
+    }
+
+    #[test]
+    fn test() {
+        assert_eq!(0xe306_9283, crc32c(b"123456789"));
+        let dir = tempdir().unwrap();
+        let out = tempdir().unwrap();
+        // none opens unless logical decoding is turned on
+        let db = Database::open(dir.path(), 16).unwrap();
+        let sink = Box::new(FileSink::open(out.path().join("off.jsonl")).unwrap());
+        assert!(matches!(db.with_engine(|bufmgr, _| Connector::open(bufmgr, "off", out.path().join("off"), sink)), Err(Error::NotLogical)));
+        drop(db);
+        let db = Database::builder().config(Config { pool_size: 16, logical: Some(true), ..Config::default() }).open(dir.path()).unwrap();
+        let mut session = db.session();
+        session.execute("CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        let (offsets, lines) = (out.path().join("offsets"), out.path().join("changes.jsonl"));
+        let open = |name: &str, offsets: &Path, sink: Box<dyn Sink>| db.with_engine(|bufmgr, _| Connector::open(bufmgr, name, offsets, sink)).unwrap();
+        let step = |connector: &mut Connector| db.with_engine(|bufmgr, _| connector.step(bufmgr));
+        let read = || fs::read_to_string(&lines).unwrap().lines().map(str::to_string).collect::<Vec<_>>();
+
+        // the transactions committed since the connector was opened, those rolled back aside
+        let mut connector = open("file", &offsets, Box::new(FileSink::open(&lines).unwrap()));
+        let first_offsets = fs::read(&offsets).unwrap();
+        session.execute("INSERT INTO t VALUES (1, 'a'), (2, 'b')").unwrap();
+        session.execute("BEGIN; INSERT INTO t VALUES (1, 'c') ON CONFLICT (id) DO UPDATE SET name = excluded.name; INSERT INTO t VALUES (3, 'x'); ROLLBACK").unwrap();
+        session.execute("INSERT INTO t VALUES (1, 'c') ON CONFLICT (id) DO UPDATE SET name = excluded.name").unwrap();
+        db.with_engine(|bufmgr, catalog| {
+            assert!(catalog.table("t").unwrap().table.delete(bufmgr, &[Value::Int(2)]).unwrap());
+            bufmgr.commit().unwrap();
+        });
+        assert_eq!(3, step(&mut connector).unwrap());
+        assert_eq!(0, step(&mut connector).unwrap());
+        let messages = read();
+        assert_eq!(3, messages.len());
+        assert!(messages[0].ends_with(r#""changes":[{"table":"t","operation":"insert","old":null,"new":{"id":1,"name":"a"}},{"table":"t","operation":"insert","old":null,"new":{"id":2,"name":"b"}}]}"#), "{}", messages[0]);
+        assert!(messages[1].ends_with(r#""changes":[{"table":"t","operation":"update","old":{"id":1,"name":"a"},"new":{"id":1,"name":"c"}}]}"#), "{}", messages[1]);
+        assert!(messages[2].ends_with(r#""changes":[{"table":"t","operation":"delete","old":{"id":2,"name":"b"},"new":null}]}"#), "{}", messages[2]);
+        let offset = |message: &str| offset_of(message.as_bytes()).unwrap();
+        assert!(offset(&messages[0]) < offset(&messages[1]) && offset(&messages[1]) < offset(&messages[2]));
+        assert_eq!(offset(&messages[2]), connector.position().decoded);
+        assert_eq!(Some(connector.position().restart), db.with_engine(|bufmgr, _| bufmgr.wal().unwrap().slot("file")));
+
+        // as if it had crashed before writing the offset file, those delivered are delivered
+        // again, which the file has already
+        drop(connector);
+        fs::write(&offsets, first_offsets).unwrap();
+        let mut connector = open("file", &offsets, Box::new(FileSink::open(&lines).unwrap()));
+        assert_eq!(3, step(&mut connector).unwrap());
+        assert_eq!(messages, read());
+        // and it resumes from where it was, with a line torn off the end of the file
+        drop(connector);
+        session.execute("INSERT INTO t VALUES (4, 'd')").unwrap();
+        OpenOptions::new().append(true).open(&lines).unwrap().write_all(b"{\"offset\":").unwrap();
+        let mut connector = open("file", &offsets, Box::new(FileSink::open(&lines).unwrap()));
+        assert_eq!(1, step(&mut connector).unwrap());
+        let messages = read();
+        assert_eq!(4, messages.len());
+        assert!(messages[3].ends_with(r#"{"id":4,"name":"d"}}]}"#), "{}", messages[3]);
+
+        // one failing to take a transaction is given it again by the next step
+        let delivered = Rc::new(RefCell::new(vec![]));
+        let flaky_offsets = out.path().join("flaky");
+        let mut flaky = open("flaky", &flaky_offsets, Box::new(Flaky { failures: 2, offsets: delivered.clone() }));
+        let position = flaky.position();
+        session.execute("INSERT INTO t VALUES (5, 'e')").unwrap();
+        assert!(matches!(step(&mut flaky), Err(Error::Sink(_))));
+        assert!(matches!(step(&mut flaky), Err(Error::Sink(_))));
+        assert_eq!(position, flaky.position());
+        assert_eq!(1, step(&mut flaky).unwrap());
+        assert_eq!(vec![flaky.position().decoded], *delivered.borrow());
+        db.with_engine(|bufmgr, _| Connector::drop_slot(bufmgr, "flaky")).unwrap();
+        assert_eq!(None, db.with_engine(|bufmgr, _| bufmgr.wal().unwrap().slot("flaky")));
+
+        // changes are named as their table and columns were when they were made, even those to
+        // one renamed or dropped before they're delivered
+        let altered_lines = out.path().join("altered.jsonl");
+        let mut altered = open("altered", &out.path().join("altered"), Box::new(FileSink::open(&altered_lines).unwrap()));
+        session.execute("CREATE TABLE u (id INTEGER PRIMARY KEY, note TEXT); INSERT INTO u VALUES (1, 'u')").unwrap();
+        session.execute("ALTER TABLE u RENAME TO v; ALTER TABLE v RENAME COLUMN note TO memo; INSERT INTO v VALUES (2, 'v')").unwrap();
+        session.execute("DROP TABLE v").unwrap();
+        step(&mut altered).unwrap();
+        let messages = fs::read_to_string(&altered_lines).unwrap();
+        assert!(messages.contains(r#"{"table":"u","operation":"insert","old":null,"new":{"id":1,"note":"u"}}"#), "{}", messages);
+        assert!(messages.contains(r#"{"table":"v","operation":"insert","old":null,"new":{"id":2,"memo":"v"}}"#), "{}", messages);
+        db.with_engine(|bufmgr, _| Connector::drop_slot(bufmgr, "altered")).unwrap();
+        // and one logged without them fails every step, rather than being skipped
+        let mut blind = open("blind", &out.path().join("blind"), Box::new(Flaky { failures: 0, offsets: delivered.clone() }));
+        let position = blind.position();
+        db.with_engine(|bufmgr, _| {
+            let table = Table::create(bufmgr, 1).unwrap();
+            table.insert(bufmgr, &[Value::Int(1)]).unwrap();
+            bufmgr.commit().unwrap();
+        });
+        assert!(matches!(step(&mut blind), Err(Error::Undecodable(_))));
+        assert!(matches!(step(&mut blind), Err(Error::Undecodable(_))));
+        assert_eq!(position, blind.position());
+        db.with_engine(|bufmgr, _| Connector::drop_slot(bufmgr, "blind")).unwrap();
+
+        // produced to a Kafka broker, which fails the first try
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let broker_addr = listener.local_addr().unwrap();
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut records = vec![];
+            for error_code in [6i16, 0] {
+                let mut len = [0; 4];
+                stream.read_exact(&mut len).unwrap();
+                let mut request = vec![0; i32::from_be_bytes(len) as usize];
+                stream.read_exact(&mut request).unwrap();
+                let bytes = &mut &request[..];
+                assert_eq!([0, 0, 0, 3], take(bytes, 4).unwrap());
+                let correlation_id = take(bytes, 4).unwrap().to_vec();
+                let client_len = i16::from_be_bytes(take(bytes, 2).unwrap().try_into().unwrap()) as usize;
+                assert_eq!(CLIENT_ID.as_bytes(), take(bytes, client_len).unwrap());
+                // no transactional id, acks, timeout, one topic
+                take(bytes, 2 + 2 + 4 + 4).unwrap();
+                assert_eq!(b"\0\x07changes", take(bytes, 9).unwrap());
+                assert_eq!([0, 0, 0, 1, 0, 0, 0, 0], take(bytes, 8).unwrap());
+                let batch_len = i32::from_be_bytes(take(bytes, 4).unwrap().try_into().unwrap()) as usize;
+                let batch = take(bytes, batch_len).unwrap();
+                assert!(bytes.is_empty());
+                assert_eq!(2, batch[16]);
+                assert_eq!(crc32c(&batch[21..]), u32::from_be_bytes(batch[17..21].try_into().unwrap()));
+                let record = &mut &batch[61..];
+                get_varint(record);
+                take(record, 1).unwrap();
+                assert_eq!((0, 0), (get_varint(record), get_varint(record)));
+                let key_len = get_varint(record) as usize;
+                let key = String::from_utf8(take(record, key_len).unwrap().to_vec()).unwrap();
+                let value_len = get_varint(record) as usize;
+                let value = String::from_utf8(take(record, value_len).unwrap().to_vec()).unwrap();
+                records.push((key, value));
+                let mut response = correlation_id;
+                response.extend(1i32.to_be_bytes());
+                put_string(&mut response, "changes");
+                response.extend(1i32.to_be_bytes());
+                response.extend(0i32.to_be_bytes());
+                response.extend(error_code.to_be_bytes());
+                response.extend([0; 8 + 8 + 4]);
+                stream.write_all(&(response.len() as i32).to_be_bytes()).unwrap();
+                stream.write_all(&response).unwrap();
+            }
+            records
+        });
+        let mut kafka = open("kafka", &out.path().join("kafka"), Box::new(KafkaSink::new(broker_addr, "changes", 0).unwrap()));
+
+        // posted to a webhook, which fails the first try
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let webhook_addr = listener.local_addr().unwrap();
+        let webhook = thread::spawn(move || {
+            let mut bodies = vec![];
+            for status in ["503 Service Unavailable", "204 No Content"] {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                assert_eq!("POST /hooks/changes HTTP/1.1\r\n", line);
+                let mut len = 0;
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.strip_prefix("Content-Length: ") {
+                        len = value.trim_end().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                bodies.push(String::from_utf8(body).unwrap());
+                reader.into_inner().write_all(format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).as_bytes()).unwrap();
+            }
+            bodies
+        });
+        let url = format!("http://{}/hooks/changes", webhook_addr);
+        let mut hook = open("webhook", &out.path().join("webhook"), Box::new(WebhookSink::new(&url).unwrap()));
+
+        session.execute("INSERT INTO t VALUES (6, 'f')").unwrap();
+        assert!(matches!(step(&mut kafka), Err(Error::Sink(e)) if e.contains("error code 6")));
+        assert_eq!(1, step(&mut kafka).unwrap());
+        assert!(matches!(step(&mut hook), Err(Error::Sink(e)) if e.contains("503")));
+        assert_eq!(1, step(&mut hook).unwrap());
+        let records = broker.join().unwrap();
+        let bodies = webhook.join().unwrap();
+        assert_eq!(records[0], records[1]);
+        assert_eq!(bodies[0], bodies[1]);
+        let (key, value) = &records[0];
+        assert_eq!(&bodies[0], value);
+        assert!(value.ends_with(r#"{"id":6,"name":"f"}}]}"#), "{}", value);
+        assert_eq!(offset(value).to_string(), *key);
+        assert!(WebhookSink::new("https://example.com/").is_err());
+    }
+}
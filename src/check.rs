@@ -0,0 +1,657 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::btree::{self, BTree, Entry, Node, MAX_ENTRY_SIZE};
+use crate::buffer::{self, BufferPoolManager, Page, PAGE_BODY_SIZE};
+use crate::catalog::{Catalog, TableInfo, CATALOG_META_PAGE_ID};
+use crate::disk::PageId;
+use crate::dump;
+use crate::geometry;
+use crate::inspect::format_values;
+use crate::mvcc;
+use crate::planner::Planner;
+use crate::rtree::{self, RTree};
+use crate::sql::{self, ast};
+use crate::table::IndexKind;
+use crate::tuple::{self, DataType, Value};
+use crate::wal::Lsn;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error(transparent)]
+    Btree(#[from] btree::Error),
+    #[error(transparent)]
+    Rtree(#[from] rtree::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    // what reads may go wrong on, or return wrong results for
+    Error,
+    // what's harmless but shouldn't be, e.g. pages leaked
+    Warning,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub severity: Severity,
+    // e.g. "table t", "index t.t_name", "catalog"
+    pub object: String,
+    pub page_id: Option<PageId>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub problems: Vec<Problem>,
+    pub pages: u64,
+    pub rows: u64,
+    pub index_entries: u64,
+}
+
+impl Report {
+    pub fn errors(&self) -> usize {
+        self.problems.iter().filter(|problem| problem.severity == Severity::Error).count()
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.problems.iter().filter(|problem| problem.severity == Severity::Warning).count()
+    }
+
+    // A row of (severity, object, page, problem) for each problem, then one of what was checked.
+    pub fn rows(&self, object: &str) -> Vec<Vec<Value>> {
+        let mut rows: Vec<_> = self
+            .problems
+            .iter()
+            .map(|problem| {
+                vec![
+                    Value::Text(problem.severity.name().to_string()),
+                    Value::Text(problem.object.clone()),
+                    problem.page_id.map_or(Value::Null, |page_id| Value::Int(page_id.0 as i64)),
+                    Value::Text(problem.message.clone()),
+                ]
+            })
+            .collect();
+        let summary = format!(
+            "checked {} pages, {} rows and {} index entries: {} errors, {} warnings",
+            self.pages,
+            self.rows,
+            self.index_entries,
+            self.errors(),
+            self.warnings()
+        );
+        rows.push(vec![Value::Text("info".to_string()), Value::Text(object.to_string()), Value::Null, Value::Text(summary)]);
+        rows
+    }
+}
+
+pub const REPORT_COLUMNS: [&str; 4] = ["severity", "object", "page", "problem"];
+
+// Checks the database, or only the table if given and its indexes, for corruption and bugs
+// leaving it inconsistent:
+//   - every page of a tree decodes as a node which fits in its page, whose LSN isn't beyond the
+//     end of the log, and is of that tree only
+//   - the keys of each node are in order and in the range its parent has it for, the leaves are
+//     all at the same depth and each links to the next
+//   - the versions of each row decode, are of the table's columns and types and of the row's
+//     primary key, and only the newest isn't deleted
+//   - each index has an entry of each version of each row, of its primary key
+//   - the catalog on disk is the one in memory, its tables' columns and indexes are in range and
+//     its views still plan
+//   - no page is in no tree, which would be leaked, unless it's been freed, and none freed is in
+//     one
+// A page which fails its checksum is reported as it's read, and what's in it left unchecked.
+pub fn check(bufmgr: &mut BufferPoolManager, catalog: &Catalog, table: Option<&str>) -> Result<Report, Error> {
+    let end = bufmgr.wal().map(|wal| wal.end());
+    let mut checker = Checker {
+        num_pages: bufmgr.num_pages(),
+        bufmgr,
+        end,
+        owners: BTreeMap::new(),
+        report: Report::default(),
+    };
+    let tables: Vec<_> = match table {
+        Some(name) => catalog.table(name).into_iter().collect(),
+        None => {
+            checker.catalog(catalog)?;
+            catalog.tables().collect()
+        }
+    };
+    for info in tables {
+        checker.table_info(info);
+        checker.table(info)?;
+    }
+    if table.is_none() {
+        for page_id in catalog.free_pages() {
+            checker.claim("free pages", page_id);
+        }
+        let leaked: Vec<_> = (0..checker.num_pages).map(PageId).filter(|page_id| !checker.owners.contains_key(page_id)).collect();
+        for page_id in leaked {
+            checker.warning("database", Some(page_id), "page of no tree, leaked".to_string());
+        }
+    }
+    checker.report.pages = checker.owners.len() as u64;
+    Ok(checker.report)
+}
+
+struct Checker<'a> {
+    bufmgr: &'a mut BufferPoolManager,
+    num_pages: u64,
+    // the end of the log, if there's one
+    end: Option<Lsn>,
+    // the tree each page checked is of
+    owners: BTreeMap<PageId, String>,
+    report: Report,
+}
+
+impl Checker<'_> {
+    fn error(&mut self, object: &str, page_id: Option<PageId>, message: String) {
+        self.report.problems.push(Problem { severity: Severity::Error, object: object.to_string(), page_id, message });
+    }
+
+    fn warning(&mut self, object: &str, page_id: Option<PageId>, message: String) {
+        self.report.problems.push(Problem { severity: Severity::Warning, object: object.to_string(), page_id, message });
+    }
+
+    // The page, or None if it fails its checksum, an error of `object`.
+    fn page(&mut self, object: &str, page_id: PageId) -> Result<Option<Box<Page>>, Error> {
+        let buffer = match self.bufmgr.fetch_page(page_id) {
+            Ok(buffer) => buffer,
+            Err(err @ buffer::Error::Checksum(_)) => {
+                self.error(object, Some(page_id), err.to_string());
+                return Ok(None);
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let page = Box::new(*buffer.page.borrow());
+        Ok(Some(page))
+    }
+
+    // Records the page as of `object`, unless it's out of range of the data file, if it's of it,
+    // or of another tree already.
+    fn claim(&mut self, object: &str, page_id: PageId) -> bool {
+        if page_id.in_data_file() && page_id.0 >= self.num_pages {
+            self.error(object, Some(page_id), format!("page beyond the end of the file of {} pages", self.num_pages));
+            return false;
+        }
+        if let Some(owner) = self.owners.get(&page_id) {
+            let message = format!("page also of {}", owner);
+            self.error(object, Some(page_id), message);
+            return false;
+        }
+        self.owners.insert(page_id, object.to_string());
+        true
+    }
+
+    fn check_lsn(&mut self, object: &str, page_id: PageId, page: &Page) {
+        let lsn = buffer::page_lsn(page);
+        if let Some(end) = self.end.filter(|&end| lsn > end) {
+            self.error(object, Some(page_id), format!("page LSN {} beyond the end of the log at {}", lsn, end));
+        }
+    }
+
+    // Checks the structure of the tree, returning its leaves in order.
+    fn tree(&mut self, object: &str, meta_page_id: PageId) -> Result<Vec<PageId>, Error> {
+        if !self.claim(object, meta_page_id) {
+            return Ok(vec![]);
+        }
+        let Some(meta) = self.page(object, meta_page_id)? else {
+            return Ok(vec![]);
+        };
+        self.check_lsn(object, meta_page_id, &meta);
+        let root = (BTree { meta_page_id }).root_page_id(self.bufmgr)?;
+        let mut leaves: Vec<(PageId, Option<PageId>)> = vec![];
+        let mut leaf_depth = None;
+        // (page, lower and upper bound of its keys, depth), children pushed last first, so that
+        // the leaves are reached in order
+        let mut pending = vec![(root, None, None, 0)];
+        while let Some((page_id, lower, upper, depth)) = pending.pop() {
+            if !self.claim(object, page_id) {
+                continue;
+            }
+            let Some(page) = self.page(object, page_id)? else {
+                continue;
+            };
+            self.check_lsn(object, page_id, &page);
+            let node = match Node::read(&page) {
+                Ok(node) => node,
+                Err(_) => {
+                    self.error(object, Some(page_id), "malformed b+tree node".to_string());
+                    continue;
+                }
+            };
+            if node.size() > PAGE_BODY_SIZE {
+                self.error(object, Some(page_id), format!("node of {} bytes overflowing its page", node.size()));
+            }
+            let keys: Vec<&[u8]> = match &node {
+                Node::Leaf { entries, .. } => entries.iter().map(|(key, _)| key.as_slice()).collect(),
+                Node::Branch { keys, .. } => keys.iter().map(Vec::as_slice).collect(),
+            };
+            if let Some(i) = keys.windows(2).position(|pair| pair[0] >= pair[1]) {
+                self.error(object, Some(page_id), format!("key {} out of order", format_key(keys[i + 1])));
+            }
+            let below = keys.first().copied().zip(lower.as_deref()).filter(|(key, lower)| key < lower);
+            let above = keys.last().copied().zip(upper.as_deref()).filter(|(key, upper)| key >= upper);
+            if let Some((key, _)) = below.or(above) {
+                self.error(object, Some(page_id), format!("key {} out of the range of the parent's", format_key(key)));
+            }
+            match node {
+                Node::Leaf { entries, next } => {
+                    if let Some(expected) = leaf_depth.filter(|&expected| expected != depth) {
+                        self.error(object, Some(page_id), format!("leaf at depth {} where the others are at {}", depth, expected));
+                    }
+                    leaf_depth.get_or_insert(depth);
+                    if let Some((key, value)) = entries.iter().find(|(key, value)| key.len() + value.len() > MAX_ENTRY_SIZE) {
+                        self.error(object, Some(page_id), format!("entry of {} bytes of key {}", key.len() + value.len(), format_key(key)));
+                    }
+                    leaves.push((page_id, next));
+                }
+                Node::Branch { keys, children } => {
+                    for (i, &child) in children.iter().enumerate().rev() {
+                        let lower = if i == 0 { lower.clone() } else { Some(keys[i - 1].clone()) };
+                        let upper = if i == keys.len() { upper.clone() } else { Some(keys[i].clone()) };
+                        pending.push((child, lower, upper, depth + 1));
+                    }
+                }
+            }
+        }
+        for (i, &(page_id, next)) in leaves.iter().enumerate() {
+            let expected = leaves.get(i + 1).map(|&(page_id, _)| page_id);
+            if next != expected {
+                let name = |page_id: Option<PageId>| page_id.map_or("none".to_string(), |page_id| page_id.0.to_string());
+                self.error(object, Some(page_id), format!("next leaf {} where it's {}", name(next), name(expected)));
+            }
+        }
+        Ok(leaves.into_iter().map(|(page_id, _)| page_id).collect())
+    }
+
+    // Checks the structure of the R-tree, returning the entries of its leaves.
+    fn rtree(&mut self, object: &str, meta_page_id: PageId) -> Result<Vec<(PageId, rtree::Entry)>, Error> {
+        if !self.claim(object, meta_page_id) {
+            return Ok(vec![]);
+        }
+        let Some(meta) = self.page(object, meta_page_id)? else {
+            return Ok(vec![]);
+        };
+        self.check_lsn(object, meta_page_id, &meta);
+        let root = (RTree { meta_page_id }).root_page_id(self.bufmgr)?;
+        let mut entries = vec![];
+        let mut leaf_depth = None;
+        // (page, box its parent has it in, depth)
+        let mut pending = vec![(root, None, 0)];
+        while let Some((page_id, parent_bounds, depth)) = pending.pop() {
+            if !self.claim(object, page_id) {
+                continue;
+            }
+            let Some(page) = self.page(object, page_id)? else {
+                continue;
+            };
+            self.check_lsn(object, page_id, &page);
+            let node = match rtree::Node::read(&page) {
+                Ok(node) => node,
+                Err(_) => {
+                    self.error(object, Some(page_id), "malformed r-tree node".to_string());
+                    continue;
+                }
+            };
+            if node.size() > PAGE_BODY_SIZE {
+                self.error(object, Some(page_id), format!("node of {} bytes overflowing its page", node.size()));
+            }
+            if let Some(bounds) = parent_bounds.filter(|&bounds| Some(bounds) != node.bounds()) {
+                let found = node.bounds().map_or("nothing".to_string(), |rect| rect.to_string());
+                self.error(object, Some(page_id), format!("node of entries in {} where its parent has them in {}", found, bounds));
+            }
+            match node {
+                rtree::Node::Leaf(leaf) => {
+                    if let Some(expected) = leaf_depth.filter(|&expected| expected != depth) {
+                        self.error(object, Some(page_id), format!("leaf at depth {} where the others are at {}", depth, expected));
+                    }
+                    leaf_depth.get_or_insert(depth);
+                    entries.extend(leaf.into_iter().map(|entry| (page_id, entry)));
+                }
+                rtree::Node::Branch(children) => {
+                    for (rect, child) in children {
+                        pending.push((child, Some(rect), depth + 1));
+                    }
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    fn entries(&mut self, object: &str, leaf: PageId) -> Result<Vec<Entry>, Error> {
+        match self.page(object, leaf)?.as_deref().map(Node::read) {
+            Some(Ok(Node::Leaf { entries, .. })) => Ok(entries),
+            _ => Ok(vec![]),
+        }
+    }
+
+    fn table(&mut self, info: &TableInfo) -> Result<(), Error> {
+        let object = format!("table {}", info.name);
+        let num_key_elems = info.table.num_key_elems;
+        // the entries each index should have
+        let mut expected = vec![BTreeSet::new(); info.table.indexes.len()];
+        for leaf in self.tree(&object, info.table.btree.meta_page_id)? {
+            for (key, value) in self.entries(&object, leaf)? {
+                self.report.rows += 1;
+                let mut versions = match mvcc::decode_versions(&value) {
+                    Ok(versions) => versions,
+                    Err(_) => {
+                        self.error(&object, Some(leaf), format!("malformed versions of the row of key {}", format_key(&key)));
+                        continue;
+                    }
+                };
+                if let Some(dictionary) = &info.table.dictionary {
+                    for version in &mut versions {
+                        if dictionary.decode(self.bufmgr, &mut version.row).is_err() {
+                            self.error(&object, Some(leaf), format!("version {} with a code not in the dictionary", format_values(&version.row)));
+                        }
+                    }
+                }
+                if versions.is_empty() {
+                    self.error(&object, Some(leaf), format!("row of key {} with no versions", format_key(&key)));
+                }
+                for (i, version) in versions.iter_mut().enumerate() {
+                    let row = format_values(&version.row);
+                    // fewer if written before columns were added, NULL in those
+                    if version.row.len() > info.columns.len() || version.row.len() < num_key_elems {
+                        self.error(&object, Some(leaf), format!("version {} of {} columns", row, version.row.len()));
+                        continue;
+                    }
+                    version.row.resize(info.columns.len(), Value::Null);
+                    for (value, column) in version.row.iter().zip(&info.columns) {
+                        if !column.data_type.accepts(value) {
+                            let message = format!("version {} with {} in column {} of type {}", row, dump::literal(value), column.name, dump::type_name(column.data_type));
+                            self.error(&object, Some(leaf), message);
+                        }
+                    }
+                    let mut pkey = vec![];
+                    tuple::encode_key(&version.row[..num_key_elems.min(version.row.len())], &mut pkey);
+                    if pkey != key {
+                        self.error(&object, Some(leaf), format!("version {} of the row of key {}", row, format_key(&key)));
+                    }
+                    if i > 0 && version.xmax.is_none() {
+                        self.error(&object, Some(leaf), format!("version {} older than another but not deleted", row));
+                    }
+                    for (index, expected) in info.table.indexes.iter().zip(&mut expected) {
+                        expected.extend(index.keys(&version.row, num_key_elems));
+                    }
+                }
+            }
+        }
+        for ((name, index), mut expected) in info.index_names.iter().zip(&info.table.indexes).zip(expected) {
+            let object = format!("index {}.{}", info.name, name);
+            let num_values = index.columns.len() + num_key_elems;
+            // of extents rather than of versions
+            if index.kind == IndexKind::ZoneMap {
+                self.tree(&object, index.btree.meta_page_id)?;
+                continue;
+            }
+            if index.kind == IndexKind::RTree {
+                for (leaf, (rect, key)) in self.rtree(&object, index.btree.meta_page_id)? {
+                    self.report.index_entries += 1;
+                    match tuple::decode_key(&key) {
+                        Ok(values) if values.len() == num_values => {
+                            if geometry::bounds(&values[0]) != Some(rect) {
+                                self.error(&object, Some(leaf), format!("entry {} in box {}", format_key(&key), rect));
+                            }
+                        }
+                        _ => self.error(&object, Some(leaf), format!("entry {} not of {} values", format_key(&key), num_values)),
+                    }
+                    if !expected.remove(&key) {
+                        self.warning(&object, Some(leaf), format!("entry {} of no version of its row", format_key(&key)));
+                    }
+                }
+            }
+            let leaves = if index.kind == IndexKind::RTree { vec![] } else { self.tree(&object, index.btree.meta_page_id)? };
+            for leaf in leaves {
+                for (key, value) in self.entries(&object, leaf)? {
+                    self.report.index_entries += 1;
+                    match tuple::decode_key(&key) {
+                        Ok(values) if values.len() == num_values => {
+                            let mut pkey = vec![];
+                            tuple::encode_key(&values[index.columns.len()..], &mut pkey);
+                            if pkey != value {
+                                self.error(&object, Some(leaf), format!("entry {} of another primary key", format_key(&key)));
+                            }
+                        }
+                        _ => self.error(&object, Some(leaf), format!("entry {} not of {} values", format_key(&key), num_values)),
+                    }
+                    // entries of versions replaced in place, or dropped as aborted, are left
+                    if !expected.remove(&key) {
+                        self.warning(&object, Some(leaf), format!("entry {} of no version of its row", format_key(&key)));
+                    }
+                }
+            }
+            // one being built may not have them all yet
+            for key in expected.into_iter().filter(|_| index.valid) {
+                self.error(&object, None, format!("no entry {} of a version of its row", format_key(&key)));
+            }
+        }
+        if let Some(columnar) = &info.table.columnar {
+            self.tree(&format!("stripes of table {}", info.name), columnar.stripes.meta_page_id)?;
+            self.tree(&format!("segments of table {}", info.name), columnar.segments.meta_page_id)?;
+        }
+        if let Some(dictionary) = &info.table.dictionary {
+            self.tree(&format!("dictionary codes of table {}", info.name), dictionary.codes.meta_page_id)?;
+            self.tree(&format!("dictionary values of table {}", info.name), dictionary.values.meta_page_id)?;
+        }
+        Ok(())
+    }
+
+    // What the catalog says of the table being in range.
+    fn table_info(&mut self, info: &TableInfo) {
+        let object = format!("table {}", info.name);
+        let num_columns = info.columns.len();
+        if info.table.num_key_elems == 0 || info.table.num_key_elems > num_columns {
+            self.error(&object, None, format!("primary key of {} of the {} columns", info.table.num_key_elems, num_columns));
+        }
+        for (i, column) in info.columns.iter().enumerate() {
+            if info.columns[..i].iter().any(|c| c.name == column.name) {
+                self.error(&object, None, format!("duplicate column {}", column.name));
+            }
+        }
+        if info.index_names.len() != info.table.indexes.len() {
+            self.error(&object, None, format!("{} index names for {} indexes", info.index_names.len(), info.table.indexes.len()));
+        }
+        for (i, (name, index)) in info.index_names.iter().zip(&info.table.indexes).enumerate() {
+            if info.index_names[..i].contains(name) {
+                self.error(&object, None, format!("duplicate index {}", name));
+            }
+            if index.columns.is_empty() || index.columns.iter().any(|&column| column >= num_columns) {
+                self.error(&object, None, format!("index {} of columns {:?} of {}", name, index.columns, num_columns));
+            }
+        }
+        let not_text = |column: usize| column < info.table.num_key_elems || info.columns.get(column).is_none_or(|c| c.data_type != DataType::Text);
+        if let Some(dictionary) = info.table.dictionary.as_ref().filter(|dictionary| dictionary.columns.iter().any(|&column| not_text(column))) {
+            self.error(&object, None, format!("dictionary of columns {:?}, not all of text outside the primary key", dictionary.columns));
+        }
+        if let Some(stats) = info.stats.as_ref().filter(|stats| stats.columns.len() != num_columns) {
+            self.error(&object, None, format!("statistics of {} columns", stats.columns.len()));
+        }
+    }
+
+    fn catalog(&mut self, catalog: &Catalog) -> Result<(), Error> {
+        let object = "catalog";
+        for leaf in self.tree(object, CATALOG_META_PAGE_ID)? {
+            for (key, value) in self.entries(object, leaf)? {
+                let values = match tuple::decode(&value) {
+                    Ok((values, _)) if values.len() >= 2 => values,
+                    _ => {
+                        self.error(object, Some(leaf), format!("malformed entry of key {}", format_key(&key)));
+                        continue;
+                    }
+                };
+                // of an id of two values, or of three, e.g. of a table and an index
+                let of_key = |n: usize| {
+                    let mut expected = vec![];
+                    tuple::encode_key(&values[..n.min(values.len())], &mut expected);
+                    expected == key
+                };
+                if !of_key(2) && !of_key(3) {
+                    self.error(object, Some(leaf), format!("entry {} of key {}", format_values(&values[..2]), format_key(&key)));
+                }
+            }
+        }
+        match Catalog::open(self.bufmgr) {
+            Ok(on_disk) => {
+                if !on_disk.tables().eq(catalog.tables()) || !on_disk.views().eq(catalog.views()) {
+                    self.error(object, None, "the catalog on disk isn't the one in memory".to_string());
+                }
+            }
+            Err(err) => self.error(object, None, format!("cannot read the catalog: {}", err)),
+        }
+        for view in catalog.views() {
+            let object = format!("view {}", view.name);
+            if catalog.table(&view.name).is_some() {
+                self.error(&object, None, "view of the name of a table".to_string());
+            }
+            let columns = match sql::parse(&view.sql).map_err(|err| err.to_string()) {
+                Ok(statements) => match statements.as_slice() {
+                    [ast::Statement::Select(query)] => Planner::new(catalog).plan_query(query).map(|plan| plan.columns.len()).map_err(|err| err.to_string()),
+                    _ => Err("not a query".to_string()),
+                },
+                Err(err) => Err(err),
+            };
+            match columns {
+                Ok(columns) if columns != view.columns.len() => {
+                    self.error(&object, None, format!("query of {} columns for {}", columns, view.columns.len()));
+                }
+                Ok(_) => {}
+                Err(err) => self.error(&object, None, format!("query which doesn't plan: {}", err)),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn format_key(key: &[u8]) -> String {
+    match tuple::decode_key(key) {
+        Ok(values) => format_values(&values),
+        Err(_) => format!("{:02x?}", key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Database, DATA_FILE};
+    use crate::disk::{self, PAGE_SIZE};
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path(), 64).unwrap();
+        let mut session = db.session();
+        session
+            .execute("CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT); CREATE INDEX t_name ON t (name); CREATE VIEW v AS SELECT name FROM t")
+            .unwrap();
+        for i in 0..300 {
+            session.execute(&format!("INSERT INTO t VALUES ({}, 'row {:040}')", i, i)).unwrap();
+        }
+        session.execute("BEGIN; INSERT INTO t VALUES (1000, 'aborted'); ROLLBACK").unwrap();
+        // and an R-tree index of several levels
+        session.execute("CREATE TABLE s (id INTEGER PRIMARY KEY, at POINT); CREATE INDEX s_at ON s USING rtree (at)").unwrap();
+        for i in 0..200 {
+            session.execute(&format!("INSERT INTO s VALUES ({}, point({}, {}))", i, i % 20, i / 20)).unwrap();
+        }
+        let rows = |session: &mut crate::database::Session, sql: &str| match session.execute(sql).unwrap().pop().unwrap() {
+            sql::QueryResult::Rows { columns, rows } => {
+                assert_eq!(REPORT_COLUMNS.to_vec(), columns);
+                rows
+            }
+            result => panic!("{:?}", result),
+        };
+        let report = rows(&mut session, "CHECKDB");
+        assert_eq!(1, report.len(), "{:?}", report);
+        let summary = &report[0];
+        assert_eq!(Value::Text("info".to_string()), summary[0]);
+        assert_eq!(Value::Text("database".to_string()), summary[1]);
+        assert!(matches!(&summary[3], Value::Text(s) if s.starts_with("checked ") && s.contains(" 501 rows and 501 index entries: 0 errors, 0 warnings")), "{:?}", summary);
+        let report = rows(&mut session, "CHECKDB t");
+        assert_eq!(Value::Text("table t".to_string()), report[0][1]);
+        assert!(session.execute("CHECKDB nothing").is_err());
+        drop(session);
+        db.close().unwrap();
+
+        // a leaf of the table overwritten with the entries of another leaf of it, as a bug would,
+        // its checksum right
+        let table_leaves = |dir: &std::path::Path| {
+            let mut inspector = crate::inspect::Inspector::open(dir).unwrap();
+            let leaves: Vec<_> = (0..inspector.num_pages())
+                .map(PageId)
+                .filter(|&page_id| inspector.role(page_id) == Some(&(crate::inspect::Tree::Table("t".to_string()), crate::inspect::Kind::Leaf)))
+                .collect();
+            assert!(inspector.summary().is_ok());
+            leaves
+        };
+        let leaves = table_leaves(dir.path());
+        assert!(leaves.len() >= 3);
+        let path = dir.path().join(DATA_FILE);
+        let mut file = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+        let mut read = |page_id: PageId| {
+            let mut page = vec![0; PAGE_SIZE as usize];
+            file.seek(SeekFrom::Start(page_id.0 * PAGE_SIZE)).unwrap();
+            std::io::Read::read_exact(&mut file, &mut page).unwrap();
+            page
+        };
+        // the entries and their next leaf, but not the LSN
+        let (mut page, other) = (read(leaves[0]), read(leaves[2]));
+        page[..PAGE_BODY_SIZE].copy_from_slice(&other[..PAGE_BODY_SIZE]);
+        let checksum = disk::page_checksum(leaves[0], &page);
+        page[disk::CHECKSUM_OFFSET..].copy_from_slice(&checksum.to_le_bytes());
+        file.seek(SeekFrom::Start(leaves[0].0 * PAGE_SIZE)).unwrap();
+        file.write_all(&page).unwrap();
+        drop(file);
+
+        let db = Database::open(dir.path(), 64).unwrap();
+        let mut session = db.session();
+        let report = rows(&mut session, "CHECKDB");
+        let messages: Vec<_> = report.iter().map(|row| format!("{:?} {:?} {:?}", row[0], row[1], row[3])).collect();
+        let has = |severity: &str, object: &str, message: &str| {
+            report.iter().any(|row| {
+                row[0] == Value::Text(severity.to_string())
+                    && row[1] == Value::Text(object.to_string())
+                    && matches!(&row[3], Value::Text(s) if s.contains(message))
+            })
+        };
+        assert!(has("error", "table t", "out of the range of the parent's"), "{:#?}", messages);
+        assert!(has("error", "table t", "next leaf "), "{:#?}", messages);
+        assert!(has("warning", "index t.t_name", "of no version of its row"), "{:#?}", messages);
+        assert!(matches!(&report.last().unwrap()[3], Value::Text(s) if !s.ends_with(" 0 errors, 0 warnings")));
+        drop(session);
+        db.close().unwrap();
+
+        // a byte of another leaf flipped on disk, which fails the page's checksum when it's read
+        let mut file = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+        let mut page = vec![0; PAGE_SIZE as usize];
+        file.seek(SeekFrom::Start(leaves[1].0 * PAGE_SIZE)).unwrap();
+        std::io::Read::read_exact(&mut file, &mut page).unwrap();
+        let Ok(Node::Leaf { entries, .. }) = Node::read(page.as_slice().try_into().unwrap()) else { panic!("not a leaf") };
+        let id = tuple::decode_key(&entries[0].0).unwrap()[0].clone();
+        page[100] ^= 0x10;
+        file.seek(SeekFrom::Start(leaves[1].0 * PAGE_SIZE)).unwrap();
+        file.write_all(&page).unwrap();
+        drop(file);
+        let db = Database::open(dir.path(), 64).unwrap();
+        let mut session = db.session();
+        let err = session.execute(&format!("SELECT name FROM t WHERE id = {}", dump::literal(&id))).unwrap_err();
+        assert_eq!(format!("page {} fails its checksum, corrupted on disk", leaves[1].0), err.to_string());
+        let report = rows(&mut session, "CHECKDB t");
+        let corrupted = Value::Text(format!("page {} fails its checksum, corrupted on disk", leaves[1].0));
+        assert!(report.iter().any(|row| row[0] == Value::Text("error".to_string()) && row[2] == Value::Int(leaves[1].0 as i64) && row[3] == corrupted), "{:?}", report);
+    }
+}
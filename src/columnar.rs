@@ -0,0 +1,436 @@
+use crate::btree::{self, BTree, SearchMode};
+use crate::buffer::BufferPoolManager;
+use crate::disk::PageId;
+use crate::lock::{self, LockMode, Resource};
+use crate::table::{Error, Table};
+use crate::tuple::{self, Tuple, Value};
+
+// A columnar table keeps its rows in two places: those written lately in the B+tree of the table,
+// as any other does, and those vacuum has compacted, which every snapshot saw, in stripes of up to
+// `STRIPE_ROWS` rows sorted by primary key. A stripe is stored a column at a time, each column as
+// a segment encoded by run lengths or with a dictionary, whichever is smaller, so that a scan
+// reads and decodes only the columns it needs. Each stripe has a zone map, the least and greatest
+// value of each column, by which a scan skips those its predicates can't hold for.
+//
+// The descriptors of the stripes are the rows of a table of their own, with versions as any row
+// has, and the rows compacted are deleted from the B+tree by the transaction which inserts their
+// stripe, so each snapshot sees a row in one place or the other. Stripes are never written again:
+// a row in one is deleted by inserting a delete marker of it into the same table, which the
+// snapshots that see it leave the row out for, and updated by deleting it there and inserting it
+// into the B+tree, to be compacted again. Transactions deleting rows of the same stripe don't
+// conflict, only those writing the same row. Secondary indexes, which would have to point into
+// stripes, can't be created on a columnar table.
+
+// Rows of a stripe at most.
+pub const STRIPE_ROWS: usize = 1024;
+// Segments are split into chunks which fit in an entry with their key.
+const CHUNK_SIZE: usize = btree::MAX_ENTRY_SIZE - 32;
+// Longer text is left out of zone maps, whose descriptor would otherwise not fit in an entry.
+pub const MAX_ZONE_TEXT: usize = 64;
+
+// Segment encodings: runs of the same value as a tuple of (length, value)*, or the distinct values
+// as a tuple followed by the index of each value among them, a byte each.
+const RLE: u8 = 0;
+const DICTIONARY: u8 = 1;
+const MAX_DICTIONARY: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Columnar {
+    // the descriptors of the stripes: [stripe, num_rows, (min, max)*], min and max NULL if the
+    // column has no zone, being all NULL or having text too long, and the delete markers of their
+    // rows: [stripe, row], the row by its position in the stripe
+    pub stripes: BTree,
+    // the segments, in chunks: [stripe, column, chunk] => encoded values
+    pub segments: BTree,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stripe {
+    pub number: i64,
+    pub num_rows: usize,
+    // of each column, the least and greatest values other than NULL
+    pub zones: Vec<Option<(Value, Value)>>,
+    // the positions of the rows the current transaction sees deleted, in order
+    pub deleted: Vec<usize>,
+}
+
+impl Stripe {
+    // The row of the descriptor.
+    pub fn encode(&self) -> Tuple {
+        let mut row = vec![Value::Int(self.number), Value::Int(self.num_rows as i64)];
+        for zone in &self.zones {
+            match zone {
+                Some((min, max)) => row.extend([min.clone(), max.clone()]),
+                None => row.extend([Value::Null, Value::Null]),
+            }
+        }
+        row
+    }
+
+    fn decode(row: Tuple) -> Result<Self, tuple::Error> {
+        let (number, num_rows) = match row.get(..2) {
+            Some([Value::Int(number), Value::Int(num_rows)]) if row.len().is_multiple_of(2) => (*number, *num_rows as usize),
+            _ => return Err(tuple::Error::Malformed),
+        };
+        let zones = row[2..]
+            .chunks(2)
+            .map(|zone| match zone {
+                [Value::Null, Value::Null] => None,
+                _ => Some((zone[0].clone(), zone[1].clone())),
+            })
+            .collect();
+        Ok(Self { number, num_rows, zones, deleted: vec![] })
+    }
+}
+
+impl Columnar {
+    pub fn create(bufmgr: &mut BufferPoolManager) -> Result<Self, btree::Error> {
+        Ok(Self {
+            stripes: BTree::create(bufmgr)?,
+            segments: BTree::create(bufmgr)?,
+        })
+    }
+
+    // The table of the descriptors of the stripes.
+    pub fn descriptors(&self) -> Table {
+        Table {
+            btree: self.stripes,
+            num_key_elems: 1,
+            indexes: vec![],
+            columnar: None,
+            ttl: None,
+            dictionary: None,
+            num_columns: None,
+            relation: None,
+        }
+    }
+
+    // The table of the delete markers of the rows of the stripes, in the B+tree of the
+    // descriptors.
+    pub fn markers(&self) -> Table {
+        Table { num_key_elems: 2, ..self.descriptors() }
+    }
+
+    // The stripes the current transaction sees, in order, with the rows it sees deleted.
+    pub fn stripes(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<Stripe>, Error> {
+        let mut iter = self.descriptors().scan(bufmgr)?;
+        let mut stripes: Vec<Stripe> = vec![];
+        while let Some(row) = iter.next(bufmgr)? {
+            // a marker comes after the descriptor of its stripe, the key of which is a prefix of its
+            match row.as_slice() {
+                [Value::Int(number), Value::Int(i)] => {
+                    if let Some(stripe) = stripes.last_mut().filter(|stripe| stripe.number == *number) {
+                        stripe.deleted.push(*i as usize);
+                    }
+                }
+                _ => stripes.push(Stripe::decode(row)?),
+            }
+        }
+        Ok(stripes)
+    }
+
+    // Reads the rows of the stripes `keep` holds for, only `columns` of them if given, the others
+    // being NULL.
+    pub fn scan(&self, bufmgr: &mut BufferPoolManager, columns: Option<Vec<usize>>, keep: impl Fn(&Stripe) -> bool) -> Result<StripeIter, Error> {
+        let stripes: Vec<_> = self.stripes(bufmgr)?.into_iter().filter(keep).collect();
+        Ok(StripeIter {
+            columnar: *self,
+            stripes: stripes.into_iter(),
+            columns,
+            rows: vec![].into_iter(),
+        })
+    }
+
+    // The row of primary key `key` in a stripe, if there's one, with the number of the stripe and
+    // its position there.
+    pub fn find(&self, bufmgr: &mut BufferPoolManager, key: &[Value]) -> Result<Option<(i64, usize, Tuple)>, Error> {
+        for stripe in self.stripes(bufmgr)? {
+            if stripe.zones[0].as_ref().is_some_and(|(min, max)| key[0] < *min || key[0] > *max) {
+                continue;
+            }
+            let columns: Vec<_> = (0..key.len()).collect();
+            let rows = self.read(bufmgr, &stripe, Some(&columns))?;
+            // one deleted may have been compacted again into a later stripe
+            match rows.binary_search_by(|row| row[..key.len()].cmp(key)) {
+                Ok(i) if stripe.deleted.binary_search(&i).is_err() => {
+                    let mut rows = self.read(bufmgr, &stripe, None)?;
+                    return Ok(Some((stripe.number, i, rows.swap_remove(i))));
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    // Writes the segments of `rows`, sorted by primary key, as those of new stripes, returning
+    // the stripes for the transaction to insert. The segments are rolled back if it aborts, and
+    // written by one transaction of a table at a time.
+    pub fn write(&self, bufmgr: &mut BufferPoolManager, rows: &[Tuple]) -> Result<Vec<Stripe>, Error> {
+        lock::lock(bufmgr, Resource::Table(self.segments.meta_page_id), LockMode::Exclusive)?;
+        // those of aborted transactions are there until vacuumed
+        let mut iter = self.stripes.search(bufmgr, SearchMode::Start)?;
+        let mut number = 0;
+        while let Some((key, _)) = iter.next(bufmgr)? {
+            if let [Value::Int(n)] = tuple::decode_key(&key)?.as_slice() {
+                number = number.max(n + 1);
+            }
+        }
+        let mut stripes = vec![];
+        for rows in rows.chunks(STRIPE_ROWS) {
+            let mut zones = vec![];
+            for column in 0..rows[0].len() {
+                let values: Vec<_> = rows.iter().map(|row| row[column].clone()).collect();
+                for (chunk, bytes) in encode_segment(&values).chunks(CHUNK_SIZE).enumerate() {
+                    let key = segment_key(&[number, column as i64, chunk as i64]);
+                    self.segments.insert(bufmgr, &key, bytes)?;
+                }
+                zones.push(zone(&values));
+            }
+            stripes.push(Stripe { number, num_rows: rows.len(), zones, deleted: vec![] });
+            number += 1;
+        }
+        Ok(stripes)
+    }
+
+    // The rows of `stripe`, with only `columns` if given, the others being NULL.
+    fn read(&self, bufmgr: &mut BufferPoolManager, stripe: &Stripe, columns: Option<&[usize]>) -> Result<Vec<Tuple>, Error> {
+        let num_columns = stripe.zones.len();
+        let mut rows = vec![vec![Value::Null; num_columns]; stripe.num_rows];
+        for column in (0..num_columns).filter(|c| columns.is_none_or(|columns| columns.contains(c))) {
+            let prefix = segment_key(&[stripe.number, column as i64]);
+            let mut iter = self.segments.search(bufmgr, SearchMode::Key(prefix.clone()))?;
+            let mut bytes = vec![];
+            while let Some((key, chunk)) = iter.next(bufmgr)? {
+                if !key.starts_with(&prefix) {
+                    break;
+                }
+                bytes.extend(chunk);
+            }
+            for (row, value) in rows.iter_mut().zip(decode_segment(&bytes, stripe.num_rows)?) {
+                row[column] = value;
+            }
+        }
+        Ok(rows)
+    }
+
+    // The pages of the descriptors and the segments, the meta pages included.
+    pub fn pages(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<PageId>, btree::Error> {
+        let mut pages = vec![];
+        for btree in [&self.stripes, &self.segments] {
+            pages.push(btree.meta_page_id);
+            pages.extend(btree.pages(bufmgr)?);
+        }
+        Ok(pages)
+    }
+}
+
+fn segment_key(values: &[i64]) -> Vec<u8> {
+    let mut key = vec![];
+    tuple::encode_key(&values.iter().map(|&n| Value::Int(n)).collect::<Vec<_>>(), &mut key);
+    key
+}
+
+fn zone(values: &[Value]) -> Option<(Value, Value)> {
+    let mut values = values.iter().filter(|value| !value.is_null());
+    let first = values.next()?;
+    let (min, max) = values.fold((first, first), |(min, max), value| (min.min(value), max.max(value)));
+    let long = |value: &Value| matches!(value, Value::Text(s) if s.len() > MAX_ZONE_TEXT);
+    (!long(min) && !long(max)).then(|| (min.clone(), max.clone()))
+}
+
+fn encode_segment(values: &[Value]) -> Vec<u8> {
+    let mut runs = vec![];
+    let mut rest = values;
+    while let Some(value) = rest.first() {
+        let len = rest.iter().take_while(|v| *v == value).count();
+        runs.extend([Value::Int(len as i64), value.clone()]);
+        rest = &rest[len..];
+    }
+    let mut rle = vec![RLE];
+    tuple::encode(&runs, &mut rle);
+    let mut dictionary: Vec<_> = values.to_vec();
+    dictionary.sort_unstable();
+    dictionary.dedup();
+    if dictionary.len() > MAX_DICTIONARY {
+        return rle;
+    }
+    let mut encoded = vec![DICTIONARY];
+    tuple::encode(&dictionary, &mut encoded);
+    encoded.extend(values.iter().map(|value| dictionary.binary_search(value).unwrap() as u8));
+    if encoded.len() < rle.len() {
+        encoded
+    } else {
+        rle
+    }
+}
+
+fn decode_segment(bytes: &[u8], num_rows: usize) -> Result<Vec<Value>, tuple::Error> {
+    let (&encoding, bytes) = bytes.split_first().ok_or(tuple::Error::Malformed)?;
+    let (values, len) = tuple::decode(bytes)?;
+    let mut decoded = Vec::with_capacity(num_rows);
+    match encoding {
+        RLE => {
+            for run in values.chunks(2) {
+                match run {
+                    [Value::Int(len), value] if *len > 0 && decoded.len() + *len as usize <= num_rows => {
+                        decoded.extend(std::iter::repeat_n(value, *len as usize).cloned());
+                    }
+                    _ => return Err(tuple::Error::Malformed),
+                }
+            }
+        }
+        DICTIONARY => {
+            for &i in &bytes[len..] {
+                decoded.push(values.get(i as usize).ok_or(tuple::Error::Malformed)?.clone());
+            }
+        }
+        _ => return Err(tuple::Error::Malformed),
+    }
+    match decoded.len() == num_rows {
+        true => Ok(decoded),
+        false => Err(tuple::Error::Malformed),
+    }
+}
+
+// Rows of stripes, decoded a stripe at a time.
+pub struct StripeIter {
+    columnar: Columnar,
+    stripes: std::vec::IntoIter<Stripe>,
+    columns: Option<Vec<usize>>,
+    rows: std::vec::IntoIter<Tuple>,
+}
+
+impl StripeIter {
+    pub fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        loop {
+            if let Some(row) = self.rows.next() {
+                return Ok(Some(row));
+            }
+            let Some(stripe) = self.stripes.next() else {
+                return Ok(None);
+            };
+            let rows = self.columnar.read(bufmgr, &stripe, self.columns.as_deref())?.into_iter().enumerate();
+            self.rows = rows.filter(|(i, _)| stripe.deleted.binary_search(i).is_err()).map(|(_, row)| row).collect::<Vec<_>>().into_iter();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::wal::{Wal, DEFAULT_SEGMENT_SIZE};
+    use tempfile::{tempdir, NamedTempFile};
+
+    fn rows(bufmgr: &mut BufferPoolManager, table: &Table, columns: Option<Vec<usize>>, keep: impl Fn(&Stripe) -> bool) -> Vec<Tuple> {
+        let mut iter = table.scan_columns(bufmgr, columns, keep).unwrap();
+        let mut rows = vec![];
+        while let Some(row) = iter.next(bufmgr).unwrap() {
+            rows.push(row);
+        }
+        rows
+    }
+
+    #[test]
+    fn test() {
+        // segments take whichever encoding is smaller
+        let repeated = vec![Value::Int(7); 100];
+        let alternating: Vec<_> = (0..100).map(|i| Value::Text(["a", "b"][i % 2].into())).collect();
+        let distinct: Vec<_> = (0..300).map(Value::Int).collect();
+        for (values, encoding) in [(&repeated, RLE), (&alternating, DICTIONARY), (&distinct, RLE)] {
+            let bytes = encode_segment(values);
+            assert_eq!(encoding, bytes[0]);
+            assert_eq!(*values, decode_segment(&bytes, values.len()).unwrap());
+            assert!(decode_segment(&bytes, values.len() + 1).is_err());
+        }
+        assert_eq!(Some((Value::Int(0), Value::Int(299))), zone(&distinct));
+        assert_eq!(None, zone(&[Value::Null, Value::Null]));
+        assert_eq!(None, zone(&[Value::Text("x".repeat(MAX_ZONE_TEXT + 1))]));
+        let stripe = Stripe { number: 3, num_rows: 2, zones: vec![Some((Value::Int(1), Value::Int(2))), None], deleted: vec![] };
+        assert_eq!(stripe, Stripe::decode(stripe.encode()).unwrap());
+
+        let (data_file, data_path) = NamedTempFile::new().unwrap().into_parts();
+        drop(data_file);
+        let wal_dir = tempdir().unwrap();
+        let disk = DiskManager::open(&data_path).unwrap();
+        let wal = Wal::open(wal_dir.path(), DEFAULT_SEGMENT_SIZE).unwrap();
+        let mut bufmgr = BufferPoolManager::with_wal(disk, BufferPool::new(16), wal).unwrap();
+        let row = |id: i64, kind: &str| vec![Value::Int(id), Value::Text(kind.into())];
+        let table = Table::create_columnar(&mut bufmgr, 1).unwrap();
+        for id in 0..2000 {
+            table.insert(&mut bufmgr, &row(id, ["a", "b", "c"][id as usize % 3])).unwrap();
+        }
+        bufmgr.commit().unwrap();
+        let all = rows(&mut bufmgr, &table, None, |_| true);
+
+        // vacuum moves the rows every snapshot sees into stripes, sorted by primary key, which those
+        // written since a snapshot in use was taken aren't
+        let reader = bufmgr.switch(Default::default());
+        table.insert(&mut bufmgr, &row(5000, "d")).unwrap();
+        bufmgr.commit().unwrap();
+        let stats = table.vacuum(&mut bufmgr).unwrap();
+        assert_eq!(2000, stats.compacted);
+        bufmgr.commit().unwrap();
+        let columnar = table.columnar.unwrap();
+        let stripes = columnar.stripes(&mut bufmgr).unwrap();
+        assert_eq!(vec![1024, 976], stripes.iter().map(|stripe| stripe.num_rows).collect::<Vec<_>>());
+        assert_eq!(Some((Value::Int(1024), Value::Int(1999))), stripes[1].zones[0]);
+        assert_eq!(Some((Value::Text("a".into()), Value::Text("c".into()))), stripes[1].zones[1]);
+        bufmgr.commit().unwrap();
+        // while a snapshot taken before sees them where they were
+        let writer = bufmgr.switch(reader);
+        assert_eq!(all, rows(&mut bufmgr, &table, None, |_| true));
+        bufmgr.commit().unwrap();
+        bufmgr.switch(writer);
+
+        // rows written later are read before those of the stripes, which some columns may be read of
+        table.insert(&mut bufmgr, &row(3000, "e")).unwrap();
+        bufmgr.commit().unwrap();
+        let ids = rows(&mut bufmgr, &table, Some(vec![0]), |stripe| stripe.zones[0].as_ref().is_some_and(|(_, max)| *max > Value::Int(1500)));
+        assert_eq!(vec![Value::Null; 976], ids[2..].iter().map(|row| row[1].clone()).collect::<Vec<_>>());
+        assert_eq!(vec![Value::Int(3000), Value::Int(5000), Value::Int(1024)], ids[..3].iter().map(|row| row[0].clone()).collect::<Vec<_>>());
+        assert_eq!(Some((1, 476, row(1500, "a"))), columnar.find(&mut bufmgr, &[Value::Int(1500)]).unwrap());
+        assert_eq!(None, columnar.find(&mut bufmgr, &[Value::Int(2500)]).unwrap());
+        assert!(matches!(table.insert(&mut bufmgr, &row(7, "x")), Err(Error::Btree(btree::Error::DuplicateKey))));
+        assert!(!table.delete(&mut bufmgr, &[Value::Int(2500)]).unwrap());
+        bufmgr.commit().unwrap();
+
+        // rows in stripes are deleted by markers, and updated by moving them back into the B+tree
+        let before = rows(&mut bufmgr, &table, None, |_| true);
+        let reader = bufmgr.switch(Default::default());
+        assert!(table.update(&mut bufmgr, &row(7, "x")).unwrap());
+        assert!(table.delete(&mut bufmgr, &[Value::Int(8)]).unwrap());
+        assert!(!table.delete(&mut bufmgr, &[Value::Int(8)]).unwrap());
+        assert_eq!(Some(row(7, "x")), table.get_for_update(&mut bufmgr, &[Value::Int(7)]).unwrap());
+        assert_eq!(vec![7, 8], columnar.stripes(&mut bufmgr).unwrap()[0].deleted);
+        bufmgr.commit().unwrap();
+        let ids: Vec<_> = rows(&mut bufmgr, &table, None, |_| true).into_iter().map(|row| row[0].clone()).collect();
+        assert_eq!((2000 + 2 - 1, Value::Int(7)), (ids.len(), ids[0].clone()));
+        assert!(!ids.contains(&Value::Int(8)));
+        bufmgr.commit().unwrap();
+        // which a snapshot taken before doesn't see, nor does one after a transaction aborting them
+        let writer = bufmgr.switch(reader);
+        assert_eq!(before, rows(&mut bufmgr, &table, None, |_| true));
+        bufmgr.commit().unwrap();
+        bufmgr.switch(writer);
+        assert!(table.delete(&mut bufmgr, &[Value::Int(9)]).unwrap());
+        bufmgr.abort().unwrap();
+        assert_eq!(Some(row(9, "a")), table.get_for_update(&mut bufmgr, &[Value::Int(9)]).unwrap());
+        table.insert(&mut bufmgr, &row(8, "y")).unwrap();
+        bufmgr.commit().unwrap();
+
+        // the stripes of an aborted compaction are never seen, and the rows stay in the B+tree
+        assert_eq!(4, table.vacuum(&mut bufmgr).unwrap().compacted);
+        bufmgr.abort().unwrap();
+        assert_eq!(2, columnar.stripes(&mut bufmgr).unwrap().len());
+        assert_eq!(row(7, "x"), rows(&mut bufmgr, &table, None, |_| true)[0]);
+        bufmgr.commit().unwrap();
+        assert_eq!(4, table.vacuum(&mut bufmgr).unwrap().compacted);
+        bufmgr.commit().unwrap();
+        assert_eq!(3, columnar.stripes(&mut bufmgr).unwrap().len());
+        assert_eq!(2002, rows(&mut bufmgr, &table, None, |_| true).len());
+        assert_eq!(Some((2, 1, row(8, "y"))), columnar.find(&mut bufmgr, &[Value::Int(8)]).unwrap());
+        bufmgr.commit().unwrap();
+    }
+}
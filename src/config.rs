@@ -0,0 +1,363 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::buffer::{Durability, EvictionPolicy, DEFAULT_CHECKPOINT_INTERVAL, DEFAULT_MAX_PARALLEL_WORKERS};
+use crate::database::Settings;
+use crate::plan_cache;
+use crate::server::Limits;
+use crate::wal::DEFAULT_SEGMENT_SIZE;
+use crate::worker::Task;
+
+// How a database is opened and its sessions start, as `Database::builder` takes it, loadable
+// from a TOML file of the keys below, every one optional, durations being in milliseconds:
+//
+//   pool_size = 256
+//   eviction_policy = "clock"         # or "lru"
+//   wal_dir = "/fast/disk/wal"        # relative to the database's directory, `wal` in it if none
+//   wal_segment_size = 16777216
+//   checkpoint_interval = 16777216    # bytes of log
+//   locking = false
+//   logical = true                    # logs row changes for logical decoding, as last set if not given
+//   idle_timeout_ms = 60000
+//   snapshot_timeout_ms = 60000
+//   slow_query_threshold_ms = 1000    # statements as slow are logged, none if not given
+//   slow_query_log = "slow.log"       # relative to the database's directory
+//   audit_log = "audit.log"           # DDL and DML are logged there, relative to the directory
+//   plan_cache_size = 1000            # plans of statements kept for them to be run again, 0 for none
+//   max_parallel_workers = 8          # the most a session may set parallel_workers to
+//
+//   [session]                         # the settings sessions start with
+//   durability = "sync"               # or "async"
+//   lock_timeout_ms = 1000
+//   statement_timeout_ms = 30000
+//   parallel_workers = 0
+//   memory_budget = 67108864          # bytes
+//
+//   [server]                          # of the connections served, none if not given
+//   max_connections = 100
+//   max_connections_per_user = 10
+//   queue_timeout_ms = 5000           # connections over the limits wait so long, else refused
+//   statement_timeout_ms = 10000      # the longest a statement holds up the other connections
+//   send_timeout_ms = 30000           # clients leaving their answers unread so long are disconnected
+//
+//   [workers]                         # how often each background worker runs, none if not given
+//   flush_ms = 100
+//   checkpoint_ms = 300000
+//   vacuum_ms = 60000
+//   expire_ms = 60000                 # deletes the rows of tables with a TTL which have expired
+//   analyze_ms = 60000
+//
+// Only this much of TOML is read: tables, comments, and keys of integers, booleans and strings.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("line {line}: {message}")]
+    Syntax { line: usize, message: String },
+    #[error("line {line}: unknown key {key:?}")]
+    UnknownKey { line: usize, key: String },
+    #[error("line {line}: invalid value for {key:?}")]
+    InvalidValue { line: usize, key: String },
+}
+
+pub const DEFAULT_POOL_SIZE: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    // pages
+    pub pool_size: usize,
+    pub eviction_policy: EvictionPolicy,
+    pub wal_dir: Option<PathBuf>,
+    pub wal_segment_size: u64,
+    pub checkpoint_interval: u64,
+    pub locking: bool,
+    // turns logging row changes for logical decoding on or off for good, left as it is if None
+    pub logical: Option<bool>,
+    pub idle_timeout: Option<Duration>,
+    pub snapshot_timeout: Option<Duration>,
+    pub slow_query_threshold: Option<Duration>,
+    pub slow_query_log: Option<PathBuf>,
+    pub audit_log: Option<PathBuf>,
+    pub plan_cache_size: usize,
+    // threads the parallel workers of a session may use at most
+    pub max_parallel_workers: usize,
+    // what sessions start with
+    pub session: Settings,
+    pub server: Limits,
+    pub workers: BTreeMap<Task, Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            pool_size: DEFAULT_POOL_SIZE,
+            eviction_policy: EvictionPolicy::default(),
+            wal_dir: None,
+            wal_segment_size: DEFAULT_SEGMENT_SIZE,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            locking: false,
+            logical: None,
+            idle_timeout: None,
+            snapshot_timeout: None,
+            slow_query_threshold: None,
+            slow_query_log: None,
+            audit_log: None,
+            plan_cache_size: plan_cache::DEFAULT_CAPACITY,
+            max_parallel_workers: DEFAULT_MAX_PARALLEL_WORKERS,
+            session: Settings::default(),
+            server: Limits::default(),
+            workers: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Int(u64),
+    Bool(bool),
+    Str(String),
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, Error> {
+        let mut config = Config::default();
+        let mut table = String::new();
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let syntax = |message: &str| Error::Syntax { line: line_number, message: message.to_string() };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix('[') {
+                let (name, rest) = rest.split_once(']').ok_or_else(|| syntax("unclosed table header"))?;
+                if !comment_or_nothing(rest) {
+                    return Err(syntax("trailing characters after the table header"));
+                }
+                table = name.trim().to_string();
+                continue;
+            }
+            let (key, rest) = line.split_once('=').ok_or_else(|| syntax("expected a key and a value"))?;
+            let key = key.trim();
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                return Err(syntax("invalid key"));
+            }
+            let (value, rest) = parse_value(rest.trim()).ok_or_else(|| syntax("invalid value"))?;
+            if !comment_or_nothing(rest) {
+                return Err(syntax("trailing characters after the value"));
+            }
+            let key = if table.is_empty() { key.to_string() } else { format!("{}.{}", table, key) };
+            config.set(&key, value).map_err(|valid| match valid {
+                true => Error::InvalidValue { line: line_number, key: key.clone() },
+                false => Error::UnknownKey { line: line_number, key: key.clone() },
+            })?;
+        }
+        Ok(config)
+    }
+
+    // Sets the key to the value, failing with whether the key is one at least.
+    fn set(&mut self, key: &str, value: Value) -> Result<(), bool> {
+        let millis = |value: &Value| match value {
+            Value::Int(ms) => Ok(Duration::from_millis(*ms)),
+            _ => Err(true),
+        };
+        let int = |value: &Value| match value {
+            Value::Int(n) => Ok(*n),
+            _ => Err(true),
+        };
+        let size = |value: &Value| int(value).and_then(|n| usize::try_from(n).map_err(|_| true));
+        match key {
+            "pool_size" => self.pool_size = size(&value).and_then(|n| if n > 0 { Ok(n) } else { Err(true) })?,
+            "eviction_policy" => {
+                self.eviction_policy = match value {
+                    Value::Str(s) if s == "clock" => EvictionPolicy::Clock,
+                    Value::Str(s) if s == "lru" => EvictionPolicy::Lru,
+                    _ => return Err(true),
+                }
+            }
+            "wal_dir" => match value {
+                Value::Str(dir) => self.wal_dir = Some(PathBuf::from(dir)),
+                _ => return Err(true),
+            },
+            "wal_segment_size" => self.wal_segment_size = int(&value)?,
+            "checkpoint_interval" => self.checkpoint_interval = int(&value)?,
+            "locking" => match value {
+                Value::Bool(locking) => self.locking = locking,
+                _ => return Err(true),
+            },
+            "logical" => match value {
+                Value::Bool(logical) => self.logical = Some(logical),
+                _ => return Err(true),
+            },
+            "idle_timeout_ms" => self.idle_timeout = Some(millis(&value)?),
+            "snapshot_timeout_ms" => self.snapshot_timeout = Some(millis(&value)?),
+            "slow_query_threshold_ms" => self.slow_query_threshold = Some(millis(&value)?),
+            "slow_query_log" => match value {
+                Value::Str(path) => self.slow_query_log = Some(PathBuf::from(path)),
+                _ => return Err(true),
+            },
+            "audit_log" => match value {
+                Value::Str(path) => self.audit_log = Some(PathBuf::from(path)),
+                _ => return Err(true),
+            },
+            "plan_cache_size" => self.plan_cache_size = size(&value)?,
+            "max_parallel_workers" => self.max_parallel_workers = size(&value)?,
+            "session.durability" => {
+                self.session.durability = match value {
+                    Value::Str(s) if s == "sync" => Durability::Sync,
+                    Value::Str(s) if s == "async" => Durability::Async,
+                    _ => return Err(true),
+                }
+            }
+            "session.lock_timeout_ms" => self.session.lock_timeout = Some(millis(&value)?),
+            "session.statement_timeout_ms" => self.session.statement_timeout = Some(millis(&value)?),
+            "session.parallel_workers" => self.session.parallel_workers = size(&value)?,
+            "session.memory_budget" => self.session.memory_budget = Some(size(&value)?),
+            "server.max_connections" => self.server.max_connections = Some(size(&value)?),
+            "server.max_connections_per_user" => self.server.max_connections_per_user = Some(size(&value)?),
+            "server.queue_timeout_ms" => self.server.queue_timeout = Some(millis(&value)?),
+            "server.statement_timeout_ms" => self.server.statement_timeout = Some(millis(&value)?),
+            "server.send_timeout_ms" => self.server.send_timeout = Some(millis(&value)?),
+            "workers.flush_ms" | "workers.checkpoint_ms" | "workers.vacuum_ms" | "workers.expire_ms" | "workers.analyze_ms" => {
+                let task = match key {
+                    "workers.flush_ms" => Task::Flush,
+                    "workers.checkpoint_ms" => Task::Checkpoint,
+                    "workers.vacuum_ms" => Task::Vacuum,
+                    "workers.expire_ms" => Task::Expire,
+                    _ => Task::Analyze,
+                };
+                self.workers.insert(task, millis(&value).and_then(|interval| if interval.is_zero() { Err(true) } else { Ok(interval) })?);
+            }
+            _ => return Err(false),
+        }
+        Ok(())
+    }
+}
+
+// Parses the value at the head of `s`, returning it and the rest.
+fn parse_value(s: &str) -> Option<(Value, &str)> {
+    if let Some(rest) = s.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Some((Value::Str(value), &rest[i + 1..])),
+                '\\' => value.push(match chars.next()?.1 {
+                    'n' => '\n',
+                    't' => '\t',
+                    '"' => '"',
+                    '\\' => '\\',
+                    _ => return None,
+                }),
+                c => value.push(c),
+            }
+        }
+        return None;
+    }
+    let end = s.find(|c: char| c.is_whitespace() || c == '#').unwrap_or(s.len());
+    let (token, rest) = s.split_at(end);
+    let value = match token {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ if token.starts_with(|c: char| c.is_ascii_digit()) && !token.ends_with('_') => Value::Int(token.replace('_', "").parse().ok()?),
+        _ => return None,
+    };
+    Some((value, rest))
+}
+
+fn comment_or_nothing(s: &str) -> bool {
+    let s = s.trim();
+    s.is_empty() || s.starts_with('#')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(Config::default(), Config::from_toml("").unwrap());
+        let config = Config::from_toml(
+            r#"
+            # the pool
+            pool_size = 1_024
+            eviction_policy = "lru"   # rather than the clock
+            wal_dir = "/var/lib/wal \"1\""
+            locking = true
+            logical = false
+            idle_timeout_ms = 500
+            slow_query_threshold_ms = 250
+            audit_log = "audit.log"
+            plan_cache_size = 0
+            max_parallel_workers = 2
+
+            [session]
+            durability = "async"
+            statement_timeout_ms = 30000
+            memory_budget = 1048576
+
+            [server]
+            max_connections = 20
+            queue_timeout_ms = 1000
+            statement_timeout_ms = 5000
+            send_timeout_ms = 2000
+
+            [ workers ]
+            flush_ms = 100
+            checkpoint_ms = 60000
+            expire_ms = 1000
+            "#,
+        )
+        .unwrap();
+        let expected = Config {
+            pool_size: 1024,
+            eviction_policy: EvictionPolicy::Lru,
+            wal_dir: Some(PathBuf::from("/var/lib/wal \"1\"")),
+            locking: true,
+            logical: Some(false),
+            idle_timeout: Some(Duration::from_millis(500)),
+            slow_query_threshold: Some(Duration::from_millis(250)),
+            audit_log: Some(PathBuf::from("audit.log")),
+            plan_cache_size: 0,
+            max_parallel_workers: 2,
+            session: Settings {
+                durability: Durability::Async,
+                statement_timeout: Some(Duration::from_secs(30)),
+                memory_budget: Some(1 << 20),
+                ..Settings::default()
+            },
+            server: Limits {
+                max_connections: Some(20),
+                max_connections_per_user: None,
+                queue_timeout: Some(Duration::from_secs(1)),
+                statement_timeout: Some(Duration::from_secs(5)),
+                send_timeout: Some(Duration::from_secs(2)),
+            },
+            workers: BTreeMap::from([(Task::Flush, Duration::from_millis(100)), (Task::Checkpoint, Duration::from_secs(60)), (Task::Expire, Duration::from_secs(1))]),
+            ..Config::default()
+        };
+        assert_eq!(expected, config);
+
+        let error = |text: &str| Config::from_toml(text).unwrap_err().to_string();
+        assert_eq!("line 2: unknown key \"pool\"", error("pool_size = 1\npool = 2"));
+        assert_eq!("line 2: unknown key \"session.pool_size\"", error("[session]\npool_size = 1"));
+        assert_eq!("line 1: invalid value for \"pool_size\"", error("pool_size = \"big\""));
+        assert_eq!("line 1: invalid value for \"pool_size\"", error("pool_size = 0"));
+        assert_eq!("line 1: invalid value for \"eviction_policy\"", error("eviction_policy = \"fifo\""));
+        assert_eq!("line 1: invalid value for \"logical\"", error("logical = 1"));
+        assert_eq!("line 2: invalid value for \"workers.flush_ms\"", error("[workers]\nflush_ms = 0"));
+        assert_eq!("line 1: invalid value", error("pool_size = 12abc"));
+        assert_eq!("line 1: trailing characters after the value", error("pool_size = 12 13"));
+        assert_eq!("line 1: invalid value", error("wal_dir = \"unclosed"));
+        assert_eq!("line 1: expected a key and a value", error("pool_size"));
+        assert_eq!("line 1: unclosed table header", error("[session"));
+    }
+}
@@ -0,0 +1,537 @@
+use std::io::Write;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::rc::Rc;
+
+#[cfg(feature = "arrow")]
+use crate::arrow::RecordBatch;
+use crate::database::{self, Database, Session};
+use crate::sql::ast::CopyFormat;
+use crate::sql::{self, QueryResult};
+use crate::tuple::{Tuple, Value};
+
+// An embedded API in the manner of rusqlite: a connection is a database and a session of it,
+// running SQL with parameters ($1, $2, ...) bound from Rust values and returning the rows as
+// values converted back.
+//
+//   let mut conn = Connection::open("db")?;
+//   conn.execute("INSERT INTO t VALUES ($1, $2)", &[&1, &"a"])?;
+//   for row in conn.query("SELECT name FROM t WHERE id = $1", &[&1])? {
+//       let name: String = row.get(0)?;
+//   }
+//
+// Structs map to rows by the names of their fields, which are those of the columns, with
+// `impl_row!` implementing `FromRow` and `ToRow` for them (in place of serde derives, which this
+// crate goes without). Values are type checked as they're converted, and parameters against the
+// columns they're bound to.
+//
+//   struct Item { id: i64, name: Option<String> }
+//   impl_row!(Item { id, name });
+//   conn.insert("items", &Item { id: 1, name: None })?;
+//   conn.insert_many("items", &[Item { id: 2, name: None }, Item { id: 3, name: None }])?;
+//   let items: Vec<Item> = conn.query_as("SELECT * FROM items", &[])?;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Database(#[from] database::Error),
+    #[error(transparent)]
+    Sql(#[from] sql::Error),
+    #[error("no column {0}")]
+    InvalidColumn(String),
+    #[error("cannot convert {value:?} of column {column}")]
+    InvalidType { column: String, value: Value },
+    #[error("query returned no rows")]
+    QueryReturnedNoRows,
+    #[error("a transaction is running already")]
+    InTransaction,
+    #[error("the transaction failed, and was rolled back")]
+    RolledBack,
+}
+
+// Pages the buffer pool of a connection holds.
+pub const DEFAULT_POOL_SIZE: usize = 256;
+
+pub struct Connection {
+    session: Session,
+    db: Database,
+}
+
+impl Connection {
+    // Opens the database in the directory at `path`, creating it if there's none.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::open_with_pool_size(path, DEFAULT_POOL_SIZE)
+    }
+
+    pub fn open_with_pool_size(path: impl AsRef<Path>, pool_size: usize) -> Result<Self, Error> {
+        let db = Database::open(path, pool_size)?;
+        Ok(Self { session: db.session(), db })
+    }
+
+    // Runs a statement, returning the rows it changed.
+    pub fn execute(&mut self, sql: &str, params: &[&dyn ToValue]) -> Result<usize, Error> {
+        match self.run(sql, params)? {
+            QueryResult::RowsAffected(n) => Ok(n),
+            QueryResult::Rows { .. } | QueryResult::Done | QueryResult::RolledBack => Ok(0),
+        }
+    }
+
+    // Runs every statement in `sql`, which takes no parameters.
+    pub fn execute_batch(&mut self, sql: &str) -> Result<(), Error> {
+        self.session.execute(sql)?;
+        Ok(())
+    }
+
+    // Runs a query, returning its rows, none for a statement returning none.
+    pub fn query(&mut self, sql: &str, params: &[&dyn ToValue]) -> Result<Rows, Error> {
+        let (columns, rows) = match self.run(sql, params)? {
+            QueryResult::Rows { columns, rows } => (columns, rows),
+            QueryResult::RowsAffected(_) | QueryResult::Done | QueryResult::RolledBack => (vec![], vec![]),
+        };
+        Ok(Rows { columns: columns.into(), rows: rows.into_iter() })
+    }
+
+    // Runs a query, returning `f` of its first row.
+    pub fn query_row<T>(&mut self, sql: &str, params: &[&dyn ToValue], f: impl FnOnce(&Row) -> Result<T, Error>) -> Result<T, Error> {
+        let row = self.query(sql, params)?.next().ok_or(Error::QueryReturnedNoRows)?;
+        f(&row)
+    }
+
+    // Runs a query, returning its rows as `T`, of which each field is the column of its name.
+    pub fn query_as<T: FromRow>(&mut self, sql: &str, params: &[&dyn ToValue]) -> Result<Vec<T>, Error> {
+        self.query(sql, params)?.map(|row| T::from_row(&row)).collect()
+    }
+
+    // Inserts `row` into `table`, each field into the column of its name.
+    pub fn insert<T: ToRow>(&mut self, table: &str, row: &T) -> Result<usize, Error> {
+        let columns = T::columns();
+        let placeholders: Vec<_> = (1..=columns.len()).map(|i| format!("${}", i)).collect();
+        let sql = format!("INSERT INTO {} ({}) VALUES ({})", table, columns.join(", "), placeholders.join(", "));
+        let statement = self.session.prepare(&sql)?;
+        match self.session.execute_prepared(&statement, &row.to_row())? {
+            QueryResult::RowsAffected(n) => Ok(n),
+            QueryResult::Rows { .. } | QueryResult::Done | QueryResult::RolledBack => Ok(0),
+        }
+    }
+
+    // Inserts `rows` into `table` as `insert` does each, as one statement, which writes them in
+    // batches (see `Table::insert_many`) rather than a row at a time. Returns how many there were.
+    pub fn insert_many<T: ToRow>(&mut self, table: &str, rows: &[T]) -> Result<usize, Error> {
+        if rows.is_empty() {
+            return Ok(0);
+        }
+        let columns = T::columns();
+        let values: Vec<_> = (0..rows.len())
+            .map(|row| {
+                let placeholders: Vec<_> = (1..=columns.len()).map(|i| format!("${}", row * columns.len() + i)).collect();
+                format!("({})", placeholders.join(", "))
+            })
+            .collect();
+        let sql = format!("INSERT INTO {} ({}) VALUES {}", table, columns.join(", "), values.join(", "));
+        let statement = self.session.prepare(&sql)?;
+        let params: Vec<_> = rows.iter().flat_map(ToRow::to_row).collect();
+        match self.session.execute_prepared(&statement, &params)? {
+            QueryResult::RowsAffected(n) => Ok(n),
+            QueryResult::Rows { .. } | QueryResult::Done | QueryResult::RolledBack => Ok(0),
+        }
+    }
+
+    // Runs a query, writing its rows to `output` as they're produced, in the format of COPY TO,
+    // and returning how many there were.
+    pub fn copy_to(&mut self, sql: &str, params: &[&dyn ToValue], output: impl Write, format: &CopyFormat) -> Result<usize, Error> {
+        let statement = self.session.prepare(sql)?;
+        let params: Vec<_> = params.iter().map(|param| param.to_value()).collect();
+        Ok(self.session.copy_to(&statement, &params, output, format)?)
+    }
+
+    // Runs a query, returning its rows in Arrow record batches, one at least.
+    #[cfg(feature = "arrow")]
+    pub fn query_arrow(&mut self, sql: &str, params: &[&dyn ToValue]) -> Result<Vec<RecordBatch>, Error> {
+        let statement = self.session.prepare(sql)?;
+        let params: Vec<_> = params.iter().map(|param| param.to_value()).collect();
+        let mut batches = vec![];
+        self.session.stream(&statement, &params, |rows| {
+            sql::record_batches(rows, |batch| {
+                batches.push(batch);
+                Ok(())
+            })
+        })?;
+        Ok(batches)
+    }
+
+    // Begins a transaction, rolled back unless committed.
+    pub fn transaction(&mut self) -> Result<Transaction<'_>, Error> {
+        if self.session.in_transaction() {
+            return Err(Error::InTransaction);
+        }
+        self.session.execute("BEGIN")?;
+        Ok(Transaction { conn: self, done: false })
+    }
+
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    // The session the connection runs its statements in, for its settings say.
+    pub fn session(&mut self) -> &mut Session {
+        &mut self.session
+    }
+
+    fn run(&mut self, sql: &str, params: &[&dyn ToValue]) -> Result<QueryResult, Error> {
+        let statement = self.session.prepare(sql)?;
+        let params: Vec<_> = params.iter().map(|param| param.to_value()).collect();
+        Ok(self.session.execute_prepared(&statement, &params)?)
+    }
+}
+
+// A transaction of a connection, which runs statements as the connection does until committed or
+// rolled back, and rolls back if dropped before.
+pub struct Transaction<'a> {
+    conn: &'a mut Connection,
+    done: bool,
+}
+
+impl Transaction<'_> {
+    // Fails if a statement of the transaction has, which rolled it back.
+    pub fn commit(mut self) -> Result<(), Error> {
+        self.done = true;
+        match self.conn.session.execute("COMMIT")?[..] {
+            [QueryResult::RolledBack] => Err(Error::RolledBack),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn rollback(mut self) -> Result<(), Error> {
+        self.done = true;
+        self.conn.session.execute("ROLLBACK")?;
+        Ok(())
+    }
+}
+
+impl Deref for Transaction<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+    }
+}
+
+impl DerefMut for Transaction<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        // one failed has been rolled back already
+        if !self.done && self.conn.session.in_transaction() {
+            let _ = self.conn.session.execute("ROLLBACK");
+        }
+    }
+}
+
+pub struct Rows {
+    columns: Rc<[String]>,
+    rows: std::vec::IntoIter<Tuple>,
+}
+
+impl Rows {
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+}
+
+impl Iterator for Rows {
+    type Item = Row;
+
+    fn next(&mut self) -> Option<Row> {
+        Some(Row { columns: self.columns.clone(), values: self.rows.next()? })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Row {
+    columns: Rc<[String]>,
+    values: Tuple,
+}
+
+impl Row {
+    // The value of a column, by its index or name, converted to `T`.
+    pub fn get<T: FromValue>(&self, column: impl ColumnIndex) -> Result<T, Error> {
+        let i = column.index(&self.columns)?;
+        let value = &self.values[i];
+        T::from_value(value).ok_or_else(|| Error::InvalidType { column: self.columns[i].clone(), value: value.clone() })
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+}
+
+// Rust values made of a row, such as structs with the fields named after the columns.
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self, Error>;
+}
+
+// Rust values inserted as rows, into the columns named.
+pub trait ToRow {
+    fn columns() -> &'static [&'static str];
+
+    fn to_row(&self) -> Vec<Value>;
+}
+
+// Implements `FromRow` and `ToRow` for a struct, the fields listed mapping to the columns of their
+// names. Each field is a `FromValue` and a `ToValue`.
+#[macro_export]
+macro_rules! impl_row {
+    ($name:ident { $($field:ident),* $(,)? }) => {
+        impl $crate::connection::FromRow for $name {
+            fn from_row(row: &$crate::connection::Row) -> Result<Self, $crate::connection::Error> {
+                Ok(Self { $($field: row.get(stringify!($field))?),* })
+            }
+        }
+
+        impl $crate::connection::ToRow for $name {
+            fn columns() -> &'static [&'static str] {
+                &[$(stringify!($field)),*]
+            }
+
+            fn to_row(&self) -> Vec<$crate::tuple::Value> {
+                vec![$($crate::connection::ToValue::to_value(&self.$field)),*]
+            }
+        }
+    };
+}
+
+pub trait ColumnIndex {
+    fn index(&self, columns: &[String]) -> Result<usize, Error>;
+}
+
+impl ColumnIndex for usize {
+    fn index(&self, columns: &[String]) -> Result<usize, Error> {
+        (*self < columns.len()).then_some(*self).ok_or_else(|| Error::InvalidColumn(self.to_string()))
+    }
+}
+
+impl ColumnIndex for &str {
+    fn index(&self, columns: &[String]) -> Result<usize, Error> {
+        columns.iter().position(|column| column == self).ok_or_else(|| Error::InvalidColumn(self.to_string()))
+    }
+}
+
+// Rust values bound to parameters.
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+}
+
+impl ToValue for Value {
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl ToValue for i64 {
+    fn to_value(&self) -> Value {
+        Value::Int(*self)
+    }
+}
+
+impl ToValue for i32 {
+    fn to_value(&self) -> Value {
+        Value::Int(*self as i64)
+    }
+}
+
+impl ToValue for bool {
+    fn to_value(&self) -> Value {
+        Value::Bool(*self)
+    }
+}
+
+impl ToValue for str {
+    fn to_value(&self) -> Value {
+        Value::Text(self.to_string())
+    }
+}
+
+impl ToValue for &str {
+    fn to_value(&self) -> Value {
+        Value::Text(self.to_string())
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        Value::Text(self.clone())
+    }
+}
+
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self) -> Value {
+        self.as_ref().map_or(Value::Null, T::to_value)
+    }
+}
+
+// Rust values columns convert to, None where the value doesn't.
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for i32 {
+    fn from_value(value: &Value) -> Option<Self> {
+        i64::from_value(value)?.try_into().ok()
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Text(text) => Some(text.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        let dir = tempdir().unwrap();
+        let mut conn = Connection::open_with_pool_size(dir.path(), 16).unwrap();
+        conn.execute_batch("CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT, ok BOOLEAN)").unwrap();
+        assert_eq!(1, conn.execute("INSERT INTO t VALUES ($1, $2, $3)", &[&1, &"a", &true]).unwrap());
+        let none: Option<&str> = None;
+        assert_eq!(1, conn.execute("INSERT INTO t VALUES ($1, $2, $3)", &[&2i64, &none, &false]).unwrap());
+
+        // values by index or name, NULL as None
+        let rows = conn.query("SELECT id, name FROM t WHERE id >= $1", &[&1]).unwrap();
+        assert_eq!(["id", "name"], rows.columns());
+        let rows: Vec<(i64, Option<String>)> = rows.map(|row| (row.get(0).unwrap(), row.get("name").unwrap())).collect();
+        assert_eq!(vec![(1, Some("a".to_string())), (2, None)], rows);
+        let ok: bool = conn.query_row("SELECT ok FROM t WHERE id = $1", &[&1], |row| row.get(0)).unwrap();
+        assert!(ok);
+        let row = conn.query("SELECT * FROM t", &[]).unwrap().next().unwrap();
+        assert!(matches!(row.get::<i64>(1), Err(Error::InvalidType { .. })));
+        assert!(matches!(row.get::<i64>("nope"), Err(Error::InvalidColumn(_))));
+        assert!(matches!(row.get::<i64>(3), Err(Error::InvalidColumn(_))));
+        let result = conn.query_row("SELECT id FROM t WHERE id = 3", &[], |row| row.get::<i64>(0));
+        assert!(matches!(result, Err(Error::QueryReturnedNoRows)));
+
+        // transactions are rolled back unless committed
+        let count = |conn: &mut Connection| conn.query_row("SELECT count(*) FROM t", &[], |row| row.get::<i64>(0)).unwrap();
+        let mut tx = conn.transaction().unwrap();
+        tx.execute("INSERT INTO t VALUES (3, 'c', NULL)", &[]).unwrap();
+        assert_eq!(3, count(&mut tx));
+        assert!(matches!(tx.transaction(), Err(Error::InTransaction)));
+        drop(tx);
+        assert_eq!(2, count(&mut conn));
+        let mut tx = conn.transaction().unwrap();
+        tx.execute("INSERT INTO t VALUES (3, 'c', NULL)", &[]).unwrap();
+        tx.rollback().unwrap();
+        assert_eq!(2, count(&mut conn));
+        let mut tx = conn.transaction().unwrap();
+        tx.execute("INSERT INTO t VALUES (3, 'c', NULL)", &[]).unwrap();
+        tx.commit().unwrap();
+        assert!(!conn.session().in_transaction());
+        let mut tx = conn.transaction().unwrap();
+        tx.execute("INSERT INTO t VALUES (4, 'd', NULL)", &[]).unwrap();
+        assert!(tx.execute("INSERT INTO t VALUES (4, 'd', NULL)", &[]).is_err());
+        assert!(matches!(tx.commit(), Err(Error::RolledBack)));
+        assert_eq!(3, count(&mut conn));
+
+        // structs map to rows by the names of their fields, whatever the order of the columns
+        #[derive(Debug, PartialEq)]
+        struct Item {
+            ok: Option<bool>,
+            id: i64,
+            name: Option<String>,
+        }
+        impl_row!(Item { ok, id, name });
+        assert_eq!(1, conn.insert("t", &Item { ok: None, id: 4, name: Some("d".to_string()) }).unwrap());
+        let items: Vec<Item> = conn.query_as("SELECT * FROM t WHERE id >= $1", &[&3]).unwrap();
+        assert_eq!(vec![Item { ok: None, id: 3, name: Some("c".to_string()) }, Item { ok: None, id: 4, name: Some("d".to_string()) }], items);
+        // and values are type checked both ways
+        struct Wrong {
+            id: String,
+        }
+        impl_row!(Wrong { id });
+        assert!(matches!(conn.query_as::<Wrong>("SELECT id FROM t", &[]), Err(Error::InvalidType { .. })));
+        assert!(matches!(conn.query_as::<Item>("SELECT id FROM t", &[]), Err(Error::InvalidColumn(_))));
+        assert!(matches!(conn.insert("t", &Wrong { id: "5".to_string() }), Err(Error::Sql(sql::Error::Invalid(_)))));
+
+        // query results stream to a writer
+        let mut output = vec![];
+        let format = CopyFormat::Csv(sql::ast::CsvOptions { header: true, ..Default::default() });
+        assert_eq!(2, conn.copy_to("SELECT * FROM t WHERE id <= $1", &[&2], &mut output, &format).unwrap());
+        assert_eq!("id,name,ok\n1,a,true\n2,,false\n", String::from_utf8(output).unwrap());
+        let mut output = vec![];
+        assert_eq!(1, conn.copy_to("SELECT id, name FROM t WHERE id = 4", &[], &mut output, &CopyFormat::JsonLines).unwrap());
+        assert_eq!("{\"id\":4,\"name\":\"d\"}\n", String::from_utf8(output).unwrap());
+        assert!(conn.copy_to("INSERT INTO t VALUES (5, 'e', NULL)", &[], vec![], &format).is_err());
+        // or come in Arrow record batches
+        #[cfg(feature = "arrow")]
+        {
+            use crate::tuple::DataType;
+            let batches = conn.query_arrow("SELECT id, name, NULL AS nothing FROM t WHERE id >= $1", &[&2]).unwrap();
+            assert_eq!(1, batches.len());
+            let batch = &batches[0];
+            let types: Vec<_> = batch.fields.iter().map(|field| (field.name.as_str(), field.data_type)).collect();
+            assert_eq!(vec![("id", DataType::Integer), ("name", DataType::Text), ("nothing", DataType::Text)], types);
+            assert_eq!(3, batch.num_rows());
+            assert_eq!(vec![Value::Int(2), Value::Null, Value::Null], batch.row(0));
+            assert_eq!((1, 3), (batch.columns[1].null_count(), batch.columns[2].null_count()));
+            let batches = conn.query_arrow("SELECT id FROM t WHERE id > 100", &[]).unwrap();
+            assert_eq!((1, 0), (batches.len(), batches[0].num_rows()));
+        }
+
+        // many rows at once, inserted or failing as one statement
+        let items: Vec<_> = (10..1010).map(|id| Item { ok: Some(id % 2 == 0), id, name: None }).collect();
+        assert_eq!(1000, conn.insert_many("t", &items).unwrap());
+        let duplicate = [Item { ok: None, id: 2000, name: None }, Item { ok: None, id: 10, name: None }];
+        assert!(conn.insert_many("t", &duplicate).is_err());
+        assert_eq!(0, conn.insert_many::<Item>("t", &[]).unwrap());
+        assert_eq!(501, conn.query_row("SELECT count(*) FROM t WHERE ok", &[], |row| row.get::<i64>(0)).unwrap());
+
+        // and what's committed is there when the database is opened again
+        drop(conn);
+        let mut conn = Connection::open_with_pool_size(dir.path(), 16).unwrap();
+        assert_eq!(1004, count(&mut conn));
+    }
+}
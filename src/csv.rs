@@ -0,0 +1,229 @@
+use std::io::{self, BufRead, Write};
+
+// CSV as COPY reads and writes it: fields separated by the delimiter, quoted where they hold it,
+// the quote, or a line break, with quotes doubled inside quotes. A field left unquoted which is
+// the NULL marker, nothing by default, is NULL, where a quoted one never is.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("line {0}: unterminated quoted field")]
+    UnterminatedQuote(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub quote: char,
+    // whether the first line names the columns, and is skipped
+    pub header: bool,
+    pub null: String,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self { delimiter: ',', quote: '"', header: false, null: String::new() }
+    }
+}
+
+// Reads the records of CSV, skipping the header line if there's one.
+pub struct Reader<R> {
+    input: R,
+    options: CsvOptions,
+    // of the last line read
+    line: usize,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(input: R, options: CsvOptions) -> Self {
+        Self { input, options, line: 0 }
+    }
+
+    // The line the last record read ended on.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    // The fields of the next record, None for NULL, or None at the end.
+    pub fn next_record(&mut self) -> Result<Option<Vec<Option<String>>>, Error> {
+        if self.line == 0 && self.options.header && self.read_record()?.is_none() {
+            return Ok(None);
+        }
+        self.read_record()
+    }
+
+    fn read_record(&mut self) -> Result<Option<Vec<Option<String>>>, Error> {
+        let mut line = String::new();
+        if !self.read_line(&mut line)? {
+            return Ok(None);
+        }
+        let start = self.line;
+        let (delimiter, quote) = (self.options.delimiter, self.options.quote);
+        let mut fields = vec![];
+        let mut field = String::new();
+        let (mut quoted, mut in_quotes) = (false, false);
+        let mut chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        loop {
+            if i == chars.len() {
+                if !in_quotes {
+                    break;
+                }
+                // a line break inside quotes is part of the field
+                field.push('\n');
+                line.clear();
+                if !self.read_line(&mut line)? {
+                    return Err(Error::UnterminatedQuote(start));
+                }
+                chars = line.chars().collect();
+                i = 0;
+                continue;
+            }
+            let c = chars[i];
+            i += 1;
+            if in_quotes {
+                if c != quote {
+                    field.push(c);
+                } else if chars.get(i) == Some(&quote) {
+                    field.push(quote);
+                    i += 1;
+                } else {
+                    in_quotes = false;
+                }
+            } else if c == quote {
+                (quoted, in_quotes) = (true, true);
+            } else if c == delimiter {
+                fields.push(self.field(std::mem::take(&mut field), quoted));
+                quoted = false;
+            } else {
+                field.push(c);
+            }
+        }
+        fields.push(self.field(field, quoted));
+        Ok(Some(fields))
+    }
+
+    fn field(&self, field: String, quoted: bool) -> Option<String> {
+        (quoted || field != self.options.null).then_some(field)
+    }
+
+    // Reads a line without its line break, returning false at the end.
+    fn read_line(&mut self, line: &mut String) -> Result<bool, Error> {
+        if self.input.read_line(line)? == 0 {
+            return Ok(false);
+        }
+        self.line += 1;
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(true)
+    }
+}
+
+// Writes records of CSV, quoting only the fields which have to be.
+pub struct Writer<W> {
+    output: W,
+    options: CsvOptions,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(output: W, options: CsvOptions) -> Self {
+        Self { output, options }
+    }
+
+    // Writes the fields, None for NULL, as a line.
+    pub fn write_record<'a>(&mut self, fields: impl IntoIterator<Item = Option<&'a str>>) -> Result<(), Error> {
+        let (delimiter, quote) = (self.options.delimiter, self.options.quote);
+        let mut line = String::new();
+        for (i, field) in fields.into_iter().enumerate() {
+            if i > 0 {
+                line.push(delimiter);
+            }
+            let field = match field {
+                Some(field) => field,
+                None => {
+                    line.push_str(&self.options.null);
+                    continue;
+                }
+            };
+            // which would read back as NULL, or as more than the field, unquoted
+            let quoted = field == self.options.null || field.contains([delimiter, quote, '\n', '\r']);
+            if !quoted {
+                line.push_str(field);
+                continue;
+            }
+            line.push(quote);
+            for c in field.chars() {
+                if c == quote {
+                    line.push(quote);
+                }
+                line.push(c);
+            }
+            line.push(quote);
+        }
+        line.push('\n');
+        Ok(self.output.write_all(line.as_bytes())?)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let records = |input: &str, options: CsvOptions| {
+            let mut reader = Reader::new(input.as_bytes(), options);
+            let mut records = vec![];
+            while let Some(record) = reader.next_record().unwrap() {
+                records.push(record);
+            }
+            records
+        };
+        let field = |s: &str| Some(s.to_string());
+        let input = "id,name\r\n1,plain\n2,\"a, \"\"quoted\"\"\nvalue\"\n3,\n4,\"\"\n";
+        assert_eq!(
+            vec![
+                vec![field("1"), field("plain")],
+                vec![field("2"), field("a, \"quoted\"\nvalue")],
+                vec![field("3"), None],
+                vec![field("4"), field("")],
+            ],
+            records(input, CsvOptions { header: true, ..Default::default() })
+        );
+        let options = CsvOptions { delimiter: '|', quote: '\'', null: "\\N".to_string(), ..Default::default() };
+        assert_eq!(vec![vec![field("a|b"), None, field(""), field("\\N")]], records("'a|b'|\\N||'\\N'", options));
+        assert!(records("", CsvOptions { header: true, ..Default::default() }).is_empty());
+
+        let mut reader = Reader::new("1,\"open\n2\n".as_bytes(), CsvOptions::default());
+        assert!(matches!(reader.next_record(), Err(Error::UnterminatedQuote(1))));
+
+        // what's written reads back the same
+        let written = vec![
+            vec![field("1"), field("plain")],
+            vec![field("a, \"quoted\"\nvalue"), None],
+            vec![field(""), field("\r")],
+        ];
+        let mut writer = Writer::new(vec![], CsvOptions::default());
+        for record in &written {
+            writer.write_record(record.iter().map(Option::as_deref)).unwrap();
+        }
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!("1,plain\n\"a, \"\"quoted\"\"\nvalue\",\n\"\",\"\r\"\n", output);
+        assert_eq!(written, records(&output, CsvOptions::default()));
+        let options = CsvOptions { delimiter: '\t', null: "\\N".to_string(), ..Default::default() };
+        let mut writer = Writer::new(vec![], options.clone());
+        writer.write_record([Some(""), None, Some("\\N"), Some("a,b")]).unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!("\t\\N\t\"\\N\"\ta,b\n", output);
+        assert_eq!(vec![vec![field(""), None, field("\\N"), field("a,b")]], records(&output, options));
+    }
+}
@@ -0,0 +1,1600 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::activity::{self, Activity, ActivityTable};
+use crate::audit::{AuditLog, AuditRecord};
+use crate::buffer::{self, BufferPool, BufferPoolManager, CancelToken, Durability, EvictionPolicy, TransactionState};
+use crate::config::Config;
+use crate::catalog::{self, Catalog, Column, Privilege, TempTables, VirtualTable};
+use crate::disk::DiskManager;
+use crate::information_schema;
+use crate::mvcc::Isolation;
+use crate::optimizer::PlannerSettings;
+use crate::query;
+use crate::slowlog::SlowQueryLog;
+use crate::statements::{self, StatementStat, StatementStats, StatementsTable};
+use crate::temp::TempFileManager;
+use crate::storage::{FileSystem, StorageBackend};
+use crate::plan_cache::{PlanCache, PlanCacheStats};
+use crate::sql::{self, ast, Cursor, PreparedStatement, QueryResult, RowStream, StatementClass};
+use crate::tuple::{DataType, Tuple, Value};
+use crate::wal::{self, TxId, Wal};
+use crate::worker::{self, Task, Workers};
+use crate::trigger::{Action, Event, RowImages, Timing, TriggerInfo};
+use crate::udf::{AggregateFunction, ScalarFunction, TableFunction};
+
+// A database in a directory, holding the data file, the log, and the temporary files of its
+// sessions, which clients each run statements in with a transaction of their own. Sessions take
+// turns with the engine, each switching in its transaction for as long as a call lasts. The
+// maintenance tasks queued by background workers are run in between (see `Workers`).
+//   data: the data file
+//   wal: the log segments
+//   tmp/<session id>: the temporary files of a session, removed when it ends
+//   tmp/spill: the temporary pages of statements (see `TempFileManager`), and in tmp/spill/tables
+//              those of the temporary tables of the sessions
+//   slow.log: the slow statements, if configured to be logged there (see `SlowQueryLog`)
+//   audit.log, say: the DDL and DML run, if configured (see `AuditLog`)
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Wal(#[from] wal::Error),
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error(transparent)]
+    Catalog(#[from] catalog::Error),
+    #[error(transparent)]
+    Worker(#[from] worker::Error),
+}
+
+pub const DATA_FILE: &str = "data";
+pub const WAL_DIR: &str = "wal";
+const TEMP_DIR: &str = "tmp";
+const SPILL_DIR: &str = "spill";
+pub const SLOW_LOG_FILE: &str = "slow.log";
+
+// What the sessions share.
+struct Engine {
+    bufmgr: BufferPoolManager,
+    catalog: Catalog,
+    next_session_id: u64,
+    workers: Workers,
+    // of a task run after a call of a session, until reported by `Database::maintain`
+    failed: Option<worker::Error>,
+    // what sessions start with
+    settings: Settings,
+    slow_log: Option<SlowQueryLog>,
+    audit_log: Option<AuditLog>,
+    // shared with the virtual table of them
+    statements: Rc<RefCell<StatementStats>>,
+    activity: Activity,
+    plans: PlanCache,
+}
+
+impl Engine {
+    // Rolls back the transactions of the sessions terminated, then runs the queued maintenance
+    // tasks, stopping at the first to fail.
+    fn maintain(&mut self) -> Result<Vec<Task>, worker::Error> {
+        self.roll_back_terminated()?;
+        let tasks = self.workers.queued();
+        for &task in &tasks {
+            task.run(&mut self.bufmgr, &mut self.catalog)?;
+        }
+        Ok(tasks)
+    }
+
+    // Rolls back the transactions of the sessions terminated since last run, so that they
+    // release their locks without waiting for the sessions to run anything.
+    fn roll_back_terminated(&mut self) -> Result<(), buffer::Error> {
+        for txid in self.activity.take_terminated() {
+            self.bufmgr.terminate(txid)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Database {
+    dir: PathBuf,
+    engine: Rc<RefCell<Engine>>,
+}
+
+impl Database {
+    // Opens the database in `dir`, recovering it from a crash, or creates it there if there's none.
+    pub fn open(dir: impl AsRef<Path>, pool_size: usize) -> Result<Self, Error> {
+        Self::builder().pool_size(pool_size).open(dir)
+    }
+
+    // Like `open`, with the data file and the log kept in `storage`, the temporary files still
+    // being under `dir` on the file system.
+    pub fn open_in(storage: Rc<dyn StorageBackend>, dir: impl AsRef<Path>, pool_size: usize) -> Result<Self, Error> {
+        Self::builder().pool_size(pool_size).storage(storage).open(dir)
+    }
+
+    // Opens a database as configured, the defaults being those of `Config`.
+    pub fn builder() -> Builder {
+        Builder { config: Config::default(), storage: Rc::new(FileSystem), slow_log: None, audit_log: None }
+    }
+
+    // Starts a session, with no transaction running and the settings configured.
+    pub fn session(&self) -> Session {
+        let mut engine = self.engine.borrow_mut();
+        let id = engine.next_session_id;
+        engine.next_session_id += 1;
+        let cancel = CancelToken::default();
+        engine.activity.start(id, cancel.clone());
+        Session {
+            id,
+            engine: self.engine.clone(),
+            temp_dir: self.dir.join(TEMP_DIR).join(id.to_string()),
+            state: TransactionState::default(),
+            settings: engine.settings.clone(),
+            cancel,
+            user: None,
+            cursors: HashMap::new(),
+            portals: HashMap::new(),
+            implicit: false,
+            catalog: None,
+            temp_tables: TempTables::new(),
+        }
+    }
+
+    // What the sessions are doing, to cancel or terminate them by, from any thread.
+    pub fn activity(&self) -> Activity {
+        self.engine.borrow().activity.clone()
+    }
+
+    // Logs the statements of every session which are slow from now on, to `log`, or none if
+    // None. Returns the log it replaces.
+    pub fn set_slow_query_log(&self, log: Option<SlowQueryLog>) -> Option<SlowQueryLog> {
+        std::mem::replace(&mut self.engine.borrow_mut().slow_log, log)
+    }
+
+    // Logs the DDL and DML of every session from now on to `log`, or none if None. Returns the
+    // log it replaces.
+    pub fn set_audit_log(&self, log: Option<AuditLog>) -> Option<AuditLog> {
+        std::mem::replace(&mut self.engine.borrow_mut().audit_log, log)
+    }
+
+    // Statistics of the statements run, those which took the longest in all first.
+    pub fn statement_stats(&self) -> Vec<StatementStat> {
+        self.engine.borrow().statements.borrow().statements().into_iter().cloned().collect()
+    }
+
+    pub fn reset_statement_stats(&self) {
+        self.engine.borrow().statements.borrow_mut().reset();
+    }
+
+    pub fn plan_cache_stats(&self) -> PlanCacheStats {
+        self.engine.borrow().plans.stats()
+    }
+
+    pub fn clear_plan_cache(&self) {
+        self.engine.borrow_mut().plans.clear();
+    }
+
+    // Another handle of the database, e.g. for a server of it while its opener runs sessions too.
+    pub(crate) fn handle(&self) -> Self {
+        Self { dir: self.dir.clone(), engine: self.engine.clone() }
+    }
+
+    // Runs `f` with the engine outside of any session, e.g. to take a checkpoint.
+    pub fn with_engine<T>(&self, f: impl FnOnce(&mut BufferPoolManager, &mut Catalog) -> T) -> T {
+        let mut engine = self.engine.borrow_mut();
+        let Engine { bufmgr, catalog, .. } = &mut *engine;
+        f(bufmgr, catalog)
+    }
+
+    // Registers `func` as the SQL function `name` taking arguments of types `args` and returning
+    // a value of type `returns`, for statements from now on to call (see `udf`).
+    pub fn create_function(&self, name: &str, args: &[DataType], returns: DataType, func: impl Fn(&[Value]) -> Result<Value, String> + 'static) {
+        let func = ScalarFunction { name: name.to_string(), args: args.to_vec(), returns, func: Box::new(func) };
+        self.engine.borrow_mut().catalog.register_function(func);
+    }
+
+    // Registers the SQL aggregate function `name` of an argument of type `arg`, computed as the
+    // state from `init` which `step` returns given the state and each value, and returning what
+    // `finalize` does given the last state, of type `returns` (see `udf::AggregateFunction`).
+    pub fn create_aggregate(
+        &self,
+        name: &str,
+        arg: DataType,
+        returns: DataType,
+        init: Value,
+        step: impl Fn(Value, &Value) -> Result<Value, String> + Send + Sync + 'static,
+        finalize: impl Fn(Value) -> Result<Value, String> + Send + Sync + 'static,
+    ) {
+        let func = AggregateFunction { name: name.to_string(), arg, returns, init, step: Box::new(step), finalize: Box::new(finalize) };
+        self.engine.borrow_mut().catalog.register_aggregate(func);
+    }
+
+    // Registers the table function `name` taking arguments of types `args`, whose rows of
+    // `columns` `func` returns given their values, to be read from in FROM (see
+    // `udf::TableFunction`).
+    pub fn create_table_function(&self, name: &str, args: &[DataType], columns: Vec<Column>, func: impl Fn(&[Value]) -> Result<Vec<Tuple>, String> + 'static) {
+        let func = TableFunction { name: name.to_string(), args: args.to_vec(), required: args.len(), columns, func: Box::new(func) };
+        self.engine.borrow_mut().catalog.register_table_function(func);
+    }
+
+    // Registers the trigger `name` on `table`, for which `callback` is called at `timing` for each
+    // row `event` writes (see `trigger`).
+    pub fn create_trigger(
+        &self,
+        name: &str,
+        table: &str,
+        timing: Timing,
+        event: Event,
+        callback: impl Fn(&mut RowImages) -> Result<bool, String> + 'static,
+    ) -> Result<(), Error> {
+        let info = TriggerInfo { name: name.to_string(), table: table.to_string(), timing, event, action: Action::Callback(Rc::new(callback)) };
+        let mut engine = self.engine.borrow_mut();
+        let engine = &mut *engine;
+        engine.catalog.create_trigger(&mut engine.bufmgr, info)?;
+        Ok(())
+    }
+
+    // Registers `table` under `name`, e.g. of a source outside the database, for statements from
+    // now on to read like a table. It's not in information_schema.
+    pub fn register_virtual_table(&self, name: &str, table: Rc<dyn VirtualTable>) -> Result<(), Error> {
+        Ok(self.engine.borrow_mut().catalog.register_virtual_table(name, table)?)
+    }
+
+    // Starts a background worker queuing `task` every `interval`, in place of the one for it if any.
+    pub fn start_worker(&self, task: Task, interval: Duration) {
+        self.engine.borrow_mut().workers.start(task, interval);
+    }
+
+    pub fn stop_worker(&self, task: Task) -> bool {
+        self.engine.borrow_mut().workers.stop(task)
+    }
+
+    // Runs the maintenance tasks queued since they were last run, returning them. Fails with the
+    // failure of one run after a call of a session, if any, first.
+    pub fn maintain(&self) -> Result<Vec<Task>, worker::Error> {
+        let mut engine = self.engine.borrow_mut();
+        if let Some(err) = engine.failed.take() {
+            return Err(err);
+        }
+        engine.maintain()
+    }
+
+    // Stops the workers, then writes back every dirty page and takes a checkpoint, so the
+    // database opens again without much to recover. Sessions still open go on without workers.
+    pub fn close(self) -> Result<(), Error> {
+        let mut engine = self.engine.borrow_mut();
+        engine.workers.shutdown();
+        engine.bufmgr.flush()?;
+        engine.bufmgr.checkpoint()?;
+        Ok(())
+    }
+}
+
+pub struct Builder {
+    config: Config,
+    storage: Rc<dyn StorageBackend>,
+    slow_log: Option<SlowQueryLog>,
+    audit_log: Option<AuditLog>,
+}
+
+impl Builder {
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn pool_size(mut self, pool_size: usize) -> Self {
+        self.config.pool_size = pool_size;
+        self
+    }
+
+    pub fn eviction_policy(mut self, policy: EvictionPolicy) -> Self {
+        self.config.eviction_policy = policy;
+        self
+    }
+
+    pub fn wal_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.wal_dir = Some(dir.into());
+        self
+    }
+
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.config.session.durability = durability;
+        self
+    }
+
+    // Where the data file and the log are kept, the file system by default.
+    pub fn storage(mut self, storage: Rc<dyn StorageBackend>) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    // Where slow statements are logged, in place of the file configured.
+    pub fn slow_query_log(mut self, log: SlowQueryLog) -> Self {
+        self.slow_log = Some(log);
+        self
+    }
+
+    // Where DDL and DML are logged, in place of the file configured.
+    pub fn audit_log(mut self, log: AuditLog) -> Self {
+        self.audit_log = Some(log);
+        self
+    }
+
+    // Opens the database in `dir`, recovering it from a crash, or creates it there if there's
+    // none, then starts the workers configured.
+    pub fn open(self, dir: impl AsRef<Path>) -> Result<Database, Error> {
+        let Builder { config, storage, slow_log, audit_log } = self;
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        storage.create_dir_all(&dir)?;
+        // left behind by sessions which were running when the database went down
+        let temp_dir = dir.join(TEMP_DIR);
+        if temp_dir.exists() {
+            fs::remove_dir_all(&temp_dir)?;
+        }
+        let disk = DiskManager::open_in(&*storage, dir.join(DATA_FILE))?;
+        // so that a data file just created is still there after a crash
+        storage.sync_dir(&dir)?;
+        let wal_dir = dir.join(config.wal_dir.as_deref().unwrap_or(Path::new(WAL_DIR)));
+        let wal = Wal::open_in(storage, wal_dir, config.wal_segment_size)?;
+        let pool = BufferPool::with_policy(config.pool_size, config.eviction_policy);
+        let mut bufmgr = BufferPoolManager::with_wal(disk, pool, wal)?;
+        bufmgr.set_temp_files(TempFileManager::open(temp_dir.join(SPILL_DIR))?);
+        bufmgr.set_checkpoint_interval(config.checkpoint_interval);
+        bufmgr.set_locking(config.locking);
+        if let Some(logical) = config.logical {
+            bufmgr.set_logical(logical)?;
+        }
+        bufmgr.set_idle_timeout(config.idle_timeout);
+        bufmgr.set_snapshot_timeout(config.snapshot_timeout);
+        bufmgr.set_max_parallel_workers(config.max_parallel_workers);
+        let mut catalog = if bufmgr.disk().num_pages() == 0 {
+            let catalog = Catalog::create(&mut bufmgr)?;
+            bufmgr.commit()?;
+            catalog
+        } else {
+            let catalog = Catalog::open(&mut bufmgr)?;
+            catalog.clear_unlogged_tables(&mut bufmgr)?;
+            catalog
+        };
+        let statements = Rc::new(RefCell::new(StatementStats::default()));
+        catalog.register_virtual_table(statements::TABLE_NAME, Rc::new(StatementsTable(statements.clone())))?;
+        let activity = Activity::default();
+        catalog.register_virtual_table(activity::TABLE_NAME, Rc::new(ActivityTable(activity.clone())))?;
+        information_schema::register(&mut catalog)?;
+        let mut workers = Workers::default();
+        for (&task, &interval) in &config.workers {
+            workers.start(task, interval);
+        }
+        let slow_log = match (slow_log, config.slow_query_threshold) {
+            (Some(log), _) => Some(log),
+            (None, Some(threshold)) => Some(SlowQueryLog::to_file(threshold, dir.join(config.slow_query_log.as_deref().unwrap_or(Path::new(SLOW_LOG_FILE))))?),
+            (None, None) => None,
+        };
+        let audit_log = match (audit_log, &config.audit_log) {
+            (Some(log), _) => Some(log),
+            (None, Some(path)) => Some(AuditLog::to_file(dir.join(path))?),
+            (None, None) => None,
+        };
+        let plans = PlanCache::new(config.plan_cache_size);
+        let engine = Engine { bufmgr, catalog, next_session_id: 1, workers, failed: None, settings: config.session, slow_log, audit_log, statements, activity, plans };
+        Ok(Database { dir, engine: Rc::new(RefCell::new(engine)) })
+    }
+}
+
+// Settings of a session, applied to each statement it runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Settings {
+    pub lock_timeout: Option<Duration>,
+    pub statement_timeout: Option<Duration>,
+    // threads a parallel scan may use besides the session's own
+    pub parallel_workers: usize,
+    // bytes the operators of a statement may hold, e.g. sorts before spilling
+    pub memory_budget: Option<usize>,
+    pub durability: Durability,
+    // of the transactions begun by BEGIN without a level
+    pub default_isolation: Isolation,
+    // how statements may read and join tables when they're prepared
+    pub planner: PlannerSettings,
+}
+
+// Names of the settings as SET and SHOW take them, in the order SHOW ALL lists them. Durations
+// are in milliseconds and sizes in bytes, 0 being none.
+pub const SETTING_NAMES: [&str; 11] = [
+    "default_transaction_isolation",
+    "durability",
+    "enable_hashjoin",
+    "enable_indexscan",
+    "enable_mergejoin",
+    "enable_nestloop",
+    "enable_seqscan",
+    "lock_timeout",
+    "memory_budget",
+    "parallel_workers",
+    "statement_timeout",
+];
+
+// A client of the database. The transaction running in it, if any, is rolled back when it ends.
+pub struct Session {
+    id: u64,
+    engine: Rc<RefCell<Engine>>,
+    temp_dir: PathBuf,
+    // set aside while the session isn't running anything
+    state: TransactionState,
+    settings: Settings,
+    cancel: CancelToken,
+    // whose privileges the statements are run with, None for a superuser's
+    user: Option<String>,
+    // declared in the transaction running, closed when it ends
+    cursors: HashMap<String, Cursor>,
+    // of the extended query protocol, suspended with rows left (see `execute_portal`), closed
+    // with the cursors
+    portals: HashMap<String, Cursor>,
+    // whether the transaction running was begun for a portal, to be ended by `sync`
+    implicit: bool,
+    // the catalog as the transaction running has changed it, which the other sessions don't see
+    // until it commits, with the transaction
+    catalog: Option<(TxId, Catalog)>,
+    // created in the session, which only it sees, set in the catalog while it runs something and
+    // dropped when it ends
+    temp_tables: TempTables,
+}
+
+impl Session {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        &mut self.settings
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    // Runs the statements from now on with the privileges of `user` (see
+    // `PreparedStatement::authorize`), or those of a superuser if None, as sessions start.
+    pub fn set_user(&mut self, user: Option<&str>) {
+        self.user = user.map(str::to_string);
+        self.engine.borrow().activity.set_user(self.id, user);
+    }
+
+    // Whether the session has been terminated, failing whatever it runs from then on.
+    pub fn is_terminated(&self) -> bool {
+        self.engine.borrow().activity.is_terminated(self.id)
+    }
+
+    // Cancels the statement the session is running when canceled, from another thread say.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    // Parses and runs every statement in `sql`, returning one result per statement. Any error,
+    // of parsing or planning too, fails the transaction block running (see `fail_on_error`).
+    pub fn execute(&mut self, sql: &str) -> Result<Vec<QueryResult>, sql::Error> {
+        let result = self.execute_statements(sql);
+        self.fail_on_error(result)
+    }
+
+    fn execute_statements(&mut self, sql: &str) -> Result<Vec<QueryResult>, sql::Error> {
+        // a statement run before is neither parsed nor planned again
+        if let Some(statement) = self.cached_plan(sql) {
+            return Ok(vec![self.run_prepared(&statement, &[])?]);
+        }
+        let statements = sql::parse_with_text(sql)?;
+        let mut results = vec![];
+        for (statement, text) in &statements {
+            results.push(self.execute_statement(statement, text)?);
+        }
+        Ok(results)
+    }
+
+    // Runs a statement parsed from `text`, SET and SHOW on the settings of the session, with the
+    // plan kept for it if there's one, keeping the plan for the next time if not.
+    fn execute_statement(&mut self, statement: &ast::Statement, text: &str) -> Result<QueryResult, sql::Error> {
+        self.check_terminated()?;
+        if let Some(result) = self.execute_session_statement(statement, text) {
+            return result;
+        }
+        // refused before it's planned, against tables the transaction may have created say
+        if self.state.failed() && !matches!(statement, ast::Statement::Commit | ast::Statement::Rollback) {
+            return Err(sql::Error::TransactionAborted);
+        }
+        if let Some(cached) = self.cached_plan(text) {
+            return self.run_prepared(&cached, &[]);
+        }
+        let (settings, cancel) = (self.settings.clone(), self.cancel.clone());
+        let cacheable = self.temp_tables.is_empty();
+        let mut prepared = None;
+        let result = self.run_monitored(text, |bufmgr, catalog, mut monitor| {
+            settings.apply(bufmgr);
+            bufmgr.set_cancel_token(cancel);
+            let prepare = |catalog: &Catalog| {
+                let statement = Rc::new(sql::prepare_statement_with(catalog, statement, settings.planner)?.with_sql(text));
+                prepared = Some((catalog.version(), statement.clone()));
+                Ok(statement)
+            };
+            monitor.execute(bufmgr, catalog, text, prepare, &[])
+        });
+        if let (Some((version, statement)), true) = (prepared, cacheable) {
+            self.engine.borrow_mut().plans.insert(text, version, settings.planner, statement);
+        }
+        result
+    }
+
+    // The plan kept for the statement `sql`, if it's been run before with the catalog as the
+    // session sees it, temporary tables aside.
+    fn cached_plan(&self, sql: &str) -> Option<Rc<PreparedStatement>> {
+        if !self.temp_tables.is_empty() {
+            return None;
+        }
+        let mut engine = self.engine.borrow_mut();
+        let Engine { catalog, plans, .. } = &mut *engine;
+        let catalog = self.catalog.as_ref().map_or(&*catalog, |(_, own)| own);
+        plans.get(sql, catalog, self.settings.planner)
+    }
+
+    // Runs `statement`, parsed from `text`, if it's a SET or SHOW, a setting being set to DEFAULT
+    // taking the value sessions start with, a CANCEL, or one of a cursor. Those are refused too
+    // while the transaction has failed.
+    fn execute_session_statement(&mut self, statement: &ast::Statement, text: &str) -> Option<Result<QueryResult, sql::Error>> {
+        let failed = self.state.failed();
+        let result = match statement {
+            ast::Statement::Cancel { .. }
+            | ast::Statement::DeclareCursor { .. }
+            | ast::Statement::Fetch { .. }
+            | ast::Statement::CloseCursor(_)
+            | ast::Statement::Set { .. }
+            | ast::Statement::Show(_)
+                if failed =>
+            {
+                Err(sql::Error::TransactionAborted)
+            }
+            &ast::Statement::Cancel { session, terminate } => self.cancel(session, terminate).map(|()| QueryResult::Done),
+            ast::Statement::DeclareCursor { name, query } => self.declare_cursor(name, query, text).map(|()| QueryResult::Done),
+            ast::Statement::Fetch { name, count } => self.fetch(name, *count, text),
+            ast::Statement::CloseCursor(name) => self.close_cursor(name.as_deref()).map(|()| QueryResult::Done),
+            ast::Statement::Set { name, value } => {
+                let engine = self.engine.borrow();
+                let (defaults, max_workers) = (engine.settings.clone(), engine.bufmgr.max_parallel_workers());
+                drop(engine);
+                let mut settings = self.settings.clone();
+                settings.set(name, value.as_ref(), &defaults).and_then(|()| {
+                    // as many threads as asked for would be started by the first parallel scan
+                    if settings.parallel_workers > max_workers {
+                        return Err(sql::Error::Invalid(format!(
+                            "{} is outside the valid range for setting parallel_workers (0 .. {}, as max_parallel_workers is configured)",
+                            settings.parallel_workers, max_workers
+                        )));
+                    }
+                    self.settings = settings;
+                    Ok(QueryResult::Done)
+                })
+            }
+            ast::Statement::Show(Some(name)) => self.settings.show(name).map(|setting| QueryResult::Rows {
+                columns: vec![name.clone()],
+                rows: vec![vec![Value::Text(setting)]],
+            }),
+            ast::Statement::Show(None) => Ok(QueryResult::Rows {
+                columns: vec!["name".to_string(), "setting".to_string()],
+                rows: SETTING_NAMES
+                    .iter()
+                    .map(|name| vec![Value::Text(name.to_string()), Value::Text(self.settings.show(name).unwrap())])
+                    .collect(),
+            }),
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    // Cancels the statement of session `id`, or terminates it, rolling back its transaction.
+    // Users other than superusers may only cancel their own sessions.
+    fn cancel(&mut self, id: u64, terminate: bool) -> Result<(), sql::Error> {
+        let mut engine = self.engine.borrow_mut();
+        let session = engine.activity.get(id).ok_or_else(|| sql::Error::Invalid(format!("session not found: {}", id)))?;
+        if let Some(user) = &self.user {
+            let superuser = self.catalog(&engine).user(user).is_some_and(|info| info.superuser);
+            if !superuser && session.user.as_ref() != Some(user) {
+                return Err(sql::Error::PermissionDenied("only superusers may cancel the sessions of other users".to_string()));
+            }
+        }
+        if !terminate {
+            engine.activity.cancel(id);
+            return Ok(());
+        }
+        engine.activity.terminate(id);
+        engine.roll_back_terminated().map_err(query::Error::from)?;
+        Ok(())
+    }
+
+    // Declares cursor `name` over `query`, started as a statement of the transaction block, if
+    // the user may run it.
+    fn declare_cursor(&mut self, name: &str, query: &ast::Query, text: &str) -> Result<(), sql::Error> {
+        if self.cursors.contains_key(name) {
+            return Err(sql::Error::Invalid(format!("cursor already exists: {}", name)));
+        }
+        let statement = self.prepare_statement(&ast::Statement::Select(Box::new(query.clone())))?;
+        let (settings, cancel, user) = (self.settings.clone(), self.cancel.clone(), self.user.clone());
+        let cursor = self.run(text, |bufmgr, catalog| {
+            settings.apply(bufmgr);
+            bufmgr.set_cancel_token(cancel);
+            authorize(catalog, user.as_deref(), &statement)?;
+            Cursor::declare(Rc::new(statement), &[], bufmgr, catalog)
+        })?;
+        self.cursors.insert(name.to_string(), cursor);
+        Ok(())
+    }
+
+    // The next `count` rows of cursor `name`, or all those left if None.
+    fn fetch(&mut self, name: &str, count: Option<u64>, text: &str) -> Result<QueryResult, sql::Error> {
+        // taken out while it's fetched from, put back if the transaction's still running then
+        let mut cursor = self.cursors.remove(name).ok_or_else(|| unknown_cursor(name))?;
+        let columns = cursor.columns().to_vec();
+        let (settings, cancel) = (self.settings.clone(), self.cancel.clone());
+        let (rows, cursor) = self.run(text, |bufmgr, catalog| {
+            settings.apply(bufmgr);
+            bufmgr.set_cancel_token(cancel);
+            let rows = cursor.fetch(bufmgr, catalog, count);
+            if bufmgr.isolation().is_some() {
+                return (rows, Some(cursor));
+            }
+            cursor.close(bufmgr);
+            (rows, None)
+        });
+        if let Some(cursor) = cursor {
+            self.cursors.insert(name.to_string(), cursor);
+        }
+        Ok(QueryResult::Rows { columns, rows: rows? })
+    }
+
+    // Closes cursor `name`, or every one if None.
+    fn close_cursor(&mut self, name: Option<&str>) -> Result<(), sql::Error> {
+        let cursors = match name {
+            Some(name) => vec![self.cursors.remove(name).ok_or_else(|| unknown_cursor(name))?],
+            None => self.cursors.drain().map(|(_, cursor)| cursor).collect(),
+        };
+        self.run("", |bufmgr, _| cursors.into_iter().for_each(|cursor| cursor.close(bufmgr)));
+        Ok(())
+    }
+
+    // Runs `statement` with `params` as portal `name` of the extended query protocol, returning
+    // no more than `max_rows` of its rows if given, and whether it's been suspended with rows
+    // left, for the next run of the portal to go on from there. A SELECT suspended runs as a
+    // cursor, in a transaction begun for it if none is running, which `sync` ends.
+    pub fn execute_portal(&mut self, name: &str, statement: &Rc<PreparedStatement>, params: &[Value], max_rows: Option<u64>) -> Result<(QueryResult, bool), sql::Error> {
+        let result = self.run_portal(name, statement, params, max_rows);
+        self.fail_on_error(result)
+    }
+
+    fn run_portal(&mut self, name: &str, statement: &Rc<PreparedStatement>, params: &[Value], max_rows: Option<u64>) -> Result<(QueryResult, bool), sql::Error> {
+        // taken out while it's fetched from, put back if it's suspended
+        let mut cursor = match self.portals.remove(name) {
+            Some(cursor) => cursor,
+            None if max_rows.is_none() || statement.command() != "SELECT" => return Ok((self.run_prepared(statement, params)?, false)),
+            None => {
+                self.check_terminated()?;
+                if self.state.failed() {
+                    return Err(sql::Error::TransactionAborted);
+                }
+                if !self.in_transaction() {
+                    let begin = self.prepare_statement(&ast::Statement::Begin { isolation: None, read_only: false })?;
+                    self.run("", |bufmgr, catalog| begin.execute(bufmgr, catalog, &[]))?;
+                    self.implicit = true;
+                }
+                let (settings, cancel, user) = (self.settings.clone(), self.cancel.clone(), self.user.clone());
+                self.run(statement.sql(), |bufmgr, catalog| {
+                    settings.apply(bufmgr);
+                    bufmgr.set_cancel_token(cancel);
+                    authorize(catalog, user.as_deref(), statement)?;
+                    Cursor::declare(statement.clone(), params, bufmgr, catalog)
+                })?
+            }
+        };
+        let columns = cursor.columns().to_vec();
+        let (settings, cancel) = (self.settings.clone(), self.cancel.clone());
+        let (rows, cursor) = self.run(statement.sql(), |bufmgr, catalog| {
+            settings.apply(bufmgr);
+            bufmgr.set_cancel_token(cancel);
+            let rows = cursor.fetch(bufmgr, catalog, max_rows);
+            let suspended = rows.as_ref().is_ok_and(|rows| max_rows == Some(rows.len() as u64));
+            if suspended && bufmgr.isolation().is_some() {
+                return (rows, Some(cursor));
+            }
+            cursor.close(bufmgr);
+            (rows, None)
+        });
+        let suspended = cursor.is_some();
+        if let Some(cursor) = cursor {
+            self.portals.insert(name.to_string(), cursor);
+        }
+        Ok((QueryResult::Rows { columns, rows: rows? }, suspended))
+    }
+
+    // Closes portal `name` if it's been suspended.
+    pub fn close_portal(&mut self, name: &str) {
+        if let Some(cursor) = self.portals.remove(name) {
+            self.run("", |bufmgr, _| cursor.close(bufmgr));
+        }
+    }
+
+    // Closes the portals suspended, and ends the transaction begun for them if there's one as
+    // COMMIT would, at a Sync of the extended query protocol.
+    pub fn sync(&mut self) -> Result<(), sql::Error> {
+        let portals: Vec<_> = self.portals.drain().map(|(_, cursor)| cursor).collect();
+        self.run("", |bufmgr, _| portals.into_iter().for_each(|cursor| cursor.close(bufmgr)));
+        if !std::mem::take(&mut self.implicit) || !self.in_transaction() {
+            return Ok(());
+        }
+        let commit = self.prepare_statement(&ast::Statement::Commit)?;
+        self.run("", |bufmgr, catalog| commit.execute(bufmgr, catalog, &[])).map(|_| ())
+    }
+
+    // Name and type, if known before it runs, of each column of the rows `statement` returns,
+    // those of its cursor for a FETCH. None if it returns no rows.
+    pub fn result_columns(&self, statement: &PreparedStatement) -> Option<Vec<(String, Option<DataType>)>> {
+        match statement.session_statement() {
+            Some(ast::Statement::Fetch { name, .. }) => self.cursors.get(name).map(Cursor::result_columns),
+            _ => statement.result_columns(),
+        }
+    }
+
+    // Fails if the session has been terminated, rolling back its transaction if it hasn't been.
+    fn check_terminated(&mut self) -> Result<(), sql::Error> {
+        if !self.is_terminated() {
+            return Ok(());
+        }
+        self.run("", |bufmgr, _| bufmgr.abort()).map_err(query::Error::from)?;
+        Err(query::Error::from(buffer::Error::Terminated).into())
+    }
+
+    // Parses the statements in `sql`, each with its text, as `execute` does.
+    pub fn parse<'a>(&mut self, sql: &'a str) -> Result<Vec<(ast::Statement, &'a str)>, sql::Error> {
+        let result = sql::parse_with_text(sql);
+        self.fail_on_error(result)
+    }
+
+    pub fn prepare(&mut self, sql: &str) -> Result<PreparedStatement, sql::Error> {
+        let result = self.with_catalog(|catalog| sql::prepare_with(catalog, sql, self.settings.planner));
+        self.fail_on_error(result)
+    }
+
+    pub fn prepare_statement(&mut self, statement: &ast::Statement) -> Result<PreparedStatement, sql::Error> {
+        let result = self.with_catalog(|catalog| sql::prepare_statement_with(catalog, statement, self.settings.planner));
+        self.fail_on_error(result)
+    }
+
+    // Runs `f` with the catalog as the session sees it, its transaction's changes and its
+    // temporary tables included, the latter in a copy of it.
+    fn with_catalog<T>(&self, f: impl FnOnce(&Catalog) -> T) -> T {
+        let engine = self.engine.borrow();
+        if self.temp_tables.is_empty() {
+            return f(self.catalog(&engine));
+        }
+        let mut catalog = self.catalog(&engine).clone();
+        catalog.set_temp_tables(self.temp_tables.clone());
+        f(&catalog)
+    }
+
+    // The catalog as the session sees it, its transaction's changes included.
+    fn catalog<'a>(&'a self, engine: &'a Engine) -> &'a Catalog {
+        self.catalog.as_ref().map_or(&engine.catalog, |(_, catalog)| catalog)
+    }
+
+    pub fn execute_prepared(&mut self, statement: &PreparedStatement, params: &[Value]) -> Result<QueryResult, sql::Error> {
+        let result = self.run_prepared(statement, params);
+        self.fail_on_error(result)
+    }
+
+    fn run_prepared(&mut self, statement: &PreparedStatement, params: &[Value]) -> Result<QueryResult, sql::Error> {
+        self.check_terminated()?;
+        if let Some(result) = statement.session_statement().and_then(|session_statement| self.execute_session_statement(session_statement, statement.sql())) {
+            return result;
+        }
+        let (settings, cancel) = (self.settings.clone(), self.cancel.clone());
+        self.run_monitored(statement.sql(), |bufmgr, catalog, mut monitor| {
+            settings.apply(bufmgr);
+            bufmgr.set_cancel_token(cancel);
+            monitor.execute(bufmgr, catalog, statement.sql(), |_| Ok(statement), params)
+        })
+    }
+
+    // Runs a SELECT, writing its rows to `output` as they're produced (see `sql::copy_to`).
+    pub fn copy_to(
+        &mut self,
+        statement: &PreparedStatement,
+        params: &[Value],
+        output: impl Write,
+        format: &ast::CopyFormat,
+    ) -> Result<usize, sql::Error> {
+        self.check_terminated()?;
+        let (settings, cancel, user) = (self.settings.clone(), self.cancel.clone(), self.user.clone());
+        let result = self.run(statement.sql(), |bufmgr, catalog| {
+            settings.apply(bufmgr);
+            bufmgr.set_cancel_token(cancel);
+            authorize(catalog, user.as_deref(), statement)?;
+            statement.copy_to(bufmgr, catalog, params, output, format)
+        });
+        self.fail_on_error(result)
+    }
+
+    // Runs a SELECT, passing `f` the stream of its rows as they're produced.
+    pub fn stream<T>(
+        &mut self,
+        statement: &PreparedStatement,
+        params: &[Value],
+        f: impl FnOnce(RowStream<'_>) -> Result<T, sql::Error>,
+    ) -> Result<T, sql::Error> {
+        self.check_terminated()?;
+        let (settings, cancel, user) = (self.settings.clone(), self.cancel.clone(), self.user.clone());
+        let result = self.run(statement.sql(), |bufmgr, catalog| {
+            settings.apply(bufmgr);
+            bufmgr.set_cancel_token(cancel);
+            authorize(catalog, user.as_deref(), statement)?;
+            statement.stream(bufmgr, catalog, params, f)
+        });
+        self.fail_on_error(result)
+    }
+
+    // Loads the rows of `next` into `table` in bulk as a statement, of all its columns in order,
+    // passing those which can't be stored to `rejected` if there is one (see `sql::load`).
+    pub fn load(
+        &mut self,
+        table: &str,
+        next: impl FnMut() -> Result<Option<Tuple>, sql::Error>,
+        rejected: Option<&mut dyn FnMut(usize, sql::Error)>,
+    ) -> Result<usize, sql::Error> {
+        self.check_terminated()?;
+        let (settings, cancel, user) = (self.settings.clone(), self.cancel.clone(), self.user.clone());
+        let sql = format!("COPY {} FROM STDIN", table);
+        let result = self.run_monitored(&sql, |bufmgr, catalog, monitor| {
+            settings.apply(bufmgr);
+            bufmgr.set_cancel_token(cancel);
+            let result = match &user {
+                Some(user) => sql::authorize(catalog, user, table, Privilege::Insert),
+                None => Ok(()),
+            };
+            let result = result.and_then(|()| sql::run_statement(bufmgr, catalog, false, |bufmgr, catalog| sql::load(bufmgr, catalog, table, next, rejected)));
+            if let Some(audit_log) = monitor.audit_log {
+                audit_log.log(&AuditRecord {
+                    time: SystemTime::now(),
+                    session: monitor.session,
+                    user: user.clone(),
+                    class: StatementClass::Dml,
+                    command: "COPY",
+                    tables: vec![table.to_string()],
+                    rows: *result.as_ref().unwrap_or(&0),
+                    sql: sql.clone(),
+                    error: result.as_ref().err().map(|err| err.to_string()),
+                });
+            }
+            result
+        });
+        self.fail_on_error(result)
+    }
+
+    // Fails the transaction block running, if it hasn't failed yet, when `result` is an error,
+    // whatever it was of: parsing, planning, privileges or running the statement. The transaction
+    // is rolled back, for none of it to be committed, and the statements up to the COMMIT or
+    // ROLLBACK ending the block are refused.
+    fn fail_on_error<T>(&mut self, result: Result<T, sql::Error>) -> Result<T, sql::Error> {
+        if result.is_err() && self.in_transaction() && !self.state.failed() {
+            self.run("", sql::fail_block)?;
+        }
+        result
+    }
+
+    // Whether a transaction begun by BEGIN is running in the session.
+    pub fn in_transaction(&self) -> bool {
+        self.state.isolation().is_some()
+    }
+
+    // Whether the transaction begun by BEGIN has failed, to be ended by COMMIT or ROLLBACK.
+    pub fn transaction_failed(&self) -> bool {
+        self.state.failed()
+    }
+
+    // The directory for the temporary files of the session, created when first asked for.
+    pub fn temp_dir(&self) -> io::Result<&Path> {
+        fs::create_dir_all(&self.temp_dir)?;
+        Ok(&self.temp_dir)
+    }
+
+    // Runs `f` in the transaction of the session, as running `sql` for `Activity`.
+    fn run<T>(&mut self, sql: &str, f: impl FnOnce(&mut BufferPoolManager, &mut Catalog) -> T) -> T {
+        self.run_monitored(sql, |bufmgr, catalog, _| f(bufmgr, catalog))
+    }
+
+    // Like `run`, `f` being passed what its statements are timed for too.
+    fn run_monitored<T>(&mut self, sql: &str, f: impl FnOnce(&mut BufferPoolManager, &mut Catalog, Monitor<'_>) -> T) -> T {
+        let mut engine = self.engine.borrow_mut();
+        engine.activity.run(self.id, sql);
+        let Engine { bufmgr, catalog, slow_log, audit_log, statements, .. } = &mut *engine;
+        let idle = bufmgr.switch(std::mem::take(&mut self.state));
+        // run with the catalog as the transaction has changed it, the one committed set aside
+        let committed = self.catalog.take().map(|(txid, mut own)| {
+            std::mem::swap(catalog, &mut own);
+            (txid, own)
+        });
+        catalog.set_temp_tables(std::mem::take(&mut self.temp_tables));
+        let monitor = Monitor { session: self.id, user: self.user.as_deref(), slow_log: slow_log.as_mut(), audit_log: audit_log.as_mut(), statements };
+        let result = f(bufmgr, catalog, monitor);
+        // taken back before the catalog is, those created by a transaction rolled back dropped
+        catalog.drop_temp_tables(bufmgr, false);
+        self.temp_tables = catalog.set_temp_tables(TempTables::new());
+        // a transaction which has changed the catalog, since before or for the first time, keeps
+        // its changes to itself while it goes on, and to the others once it's committed
+        if let Some((txid, committed)) = catalog.take_committed().or(committed) {
+            if bufmgr.is_aborted(txid) {
+                *catalog = committed;
+            } else if bufmgr.isolation().is_some() {
+                self.catalog = Some((txid, std::mem::replace(catalog, committed)));
+            }
+        }
+        if bufmgr.isolation().is_none() || bufmgr.block_failed() {
+            for (_, cursor) in self.cursors.drain().chain(self.portals.drain()) {
+                cursor.close(bufmgr);
+            }
+        }
+        self.state = bufmgr.switch(idle);
+        engine.activity.finish(self.id, self.state.isolation().is_some(), self.state.txid());
+        if engine.failed.is_none() {
+            engine.failed = engine.maintain().err();
+        }
+        result
+    }
+}
+
+// Fails unless `user` may run `statement`, anyone may if None.
+fn authorize(catalog: &Catalog, user: Option<&str>, statement: &PreparedStatement) -> Result<(), sql::Error> {
+    match user {
+        Some(user) => statement.authorize(catalog, user),
+        None => Ok(()),
+    }
+}
+
+// What the statements a session runs are timed and logged for: the slow query log and the
+// audit log, if the database has them, and the statistics of statements.
+struct Monitor<'a> {
+    session: u64,
+    // whose privileges the statements are run with, as `Session::user`
+    user: Option<&'a str>,
+    slow_log: Option<&'a mut SlowQueryLog>,
+    audit_log: Option<&'a mut AuditLog>,
+    statements: &'a RefCell<StatementStats>,
+}
+
+impl Monitor<'_> {
+    // Prepares a statement parsed from `sql` with `prepare` and runs it if the user may, timing
+    // both.
+    fn execute<P: std::borrow::Borrow<PreparedStatement>>(
+        &mut self,
+        bufmgr: &mut BufferPoolManager,
+        catalog: &mut Catalog,
+        sql: &str,
+        prepare: impl FnOnce(&Catalog) -> Result<P, sql::Error>,
+        params: &[Value],
+    ) -> Result<QueryResult, sql::Error> {
+        use std::borrow::Borrow;
+        let (start, buffers) = (Instant::now(), bufmgr.stats());
+        let (statement, result) = match prepare(catalog) {
+            Ok(statement) => {
+                let result = authorize(catalog, self.user, statement.borrow()).and_then(|()| statement.borrow().execute(bufmgr, catalog, params));
+                (Some(statement), result)
+            }
+            Err(err) => (None, Err(err)),
+        };
+        let (duration, buffers) = (start.elapsed(), bufmgr.stats().since(buffers));
+        // statements prepared from their AST alone have no text to be counted by
+        if let (Ok(result), false) = (&result, sql.is_empty()) {
+            self.statements.borrow_mut().record(sql, duration, result.num_rows(), buffers);
+        }
+        if let Some(slow_log) = &mut self.slow_log {
+            slow_log.log(self.session, sql, statement.as_ref().map(Borrow::borrow), &result, duration, buffers);
+        }
+        if let (Some(audit_log), Some(statement)) = (&mut self.audit_log, &statement) {
+            audit_log.log_statement(self.session, self.user, sql, statement.borrow(), &result);
+        }
+        result
+    }
+}
+
+impl Settings {
+    fn apply(&self, bufmgr: &mut BufferPoolManager) {
+        bufmgr.set_lock_timeout(self.lock_timeout);
+        bufmgr.set_statement_timeout(self.statement_timeout);
+        bufmgr.set_parallel_workers(self.parallel_workers);
+        bufmgr.set_memory_budget(self.memory_budget);
+        bufmgr.set_durability(self.durability);
+        bufmgr.set_default_isolation(self.default_isolation);
+    }
+
+    // Sets the setting `name` of SETTING_NAMES to `value`, or to its value in `defaults` if None.
+    pub fn set(&mut self, name: &str, value: Option<&Value>, defaults: &Settings) -> Result<(), sql::Error> {
+        let value = match value {
+            Some(value) => value.clone(),
+            None => Value::Text(defaults.show(name)?),
+        };
+        let invalid = || sql::Error::Invalid(format!("invalid value for setting {}", name));
+        // numbers may be given as strings too, as DEFAULT gives them
+        let int = || match &value {
+            Value::Int(n) => usize::try_from(*n).map_err(|_| invalid()),
+            Value::Text(s) => s.parse().map_err(|_| invalid()),
+            _ => Err(invalid()),
+        };
+        let text = || match &value {
+            Value::Text(s) => Ok(s.to_lowercase()),
+            _ => Err(invalid()),
+        };
+        let bool = || match &value {
+            Value::Bool(b) => Ok(*b),
+            Value::Int(0) => Ok(false),
+            Value::Int(1) => Ok(true),
+            _ => match text()?.as_str() {
+                "on" | "true" => Ok(true),
+                "off" | "false" => Ok(false),
+                _ => Err(invalid()),
+            },
+        };
+        let millis = || int().map(|ms| if ms == 0 { None } else { Some(Duration::from_millis(ms as u64)) });
+        match name {
+            "default_transaction_isolation" => {
+                self.default_isolation = match text()?.as_str() {
+                    "read committed" => Isolation::ReadCommitted,
+                    "repeatable read" => Isolation::RepeatableRead,
+                    "serializable" => Isolation::Serializable,
+                    _ => return Err(invalid()),
+                }
+            }
+            "durability" => {
+                self.durability = match text()?.as_str() {
+                    "sync" => Durability::Sync,
+                    "async" => Durability::Async,
+                    _ => return Err(invalid()),
+                }
+            }
+            "enable_hashjoin" => self.planner.enable_hashjoin = bool()?,
+            "enable_indexscan" => self.planner.enable_indexscan = bool()?,
+            "enable_mergejoin" => self.planner.enable_mergejoin = bool()?,
+            "enable_nestloop" => self.planner.enable_nestloop = bool()?,
+            "enable_seqscan" => self.planner.enable_seqscan = bool()?,
+            "lock_timeout" => self.lock_timeout = millis()?,
+            "memory_budget" => self.memory_budget = Some(int()?).filter(|&bytes| bytes > 0),
+            "parallel_workers" => self.parallel_workers = int()?,
+            "statement_timeout" => self.statement_timeout = millis()?,
+            _ => return Err(unknown_setting(name)),
+        }
+        Ok(())
+    }
+
+    // The setting `name` of SETTING_NAMES as SHOW shows it.
+    pub fn show(&self, name: &str) -> Result<String, sql::Error> {
+        let on_off = |enabled: bool| if enabled { "on" } else { "off" }.to_string();
+        let millis = |duration: Option<Duration>| duration.map_or(0, |d| d.as_millis()).to_string();
+        Ok(match name {
+            "default_transaction_isolation" => match self.default_isolation {
+                Isolation::ReadCommitted => "read committed",
+                Isolation::RepeatableRead => "repeatable read",
+                Isolation::Serializable => "serializable",
+            }
+            .to_string(),
+            "durability" => match self.durability {
+                Durability::Sync => "sync",
+                Durability::Async => "async",
+            }
+            .to_string(),
+            "enable_hashjoin" => on_off(self.planner.enable_hashjoin),
+            "enable_indexscan" => on_off(self.planner.enable_indexscan),
+            "enable_mergejoin" => on_off(self.planner.enable_mergejoin),
+            "enable_nestloop" => on_off(self.planner.enable_nestloop),
+            "enable_seqscan" => on_off(self.planner.enable_seqscan),
+            "lock_timeout" => millis(self.lock_timeout),
+            "memory_budget" => self.memory_budget.unwrap_or(0).to_string(),
+            "parallel_workers" => self.parallel_workers.to_string(),
+            "statement_timeout" => millis(self.statement_timeout),
+            _ => return Err(unknown_setting(name)),
+        })
+    }
+}
+
+fn unknown_cursor(name: &str) -> sql::Error {
+    sql::Error::Invalid(format!("cursor not found: {}", name))
+}
+
+fn unknown_setting(name: &str) -> sql::Error {
+    sql::Error::Invalid(format!("setting not found: {}", name))
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // there's nowhere to report a failure to, and recovery rolls it back anyway
+        let _ = self.run("", |bufmgr, catalog| {
+            let aborted = bufmgr.abort();
+            catalog.drop_temp_tables(bufmgr, true);
+            aborted
+        });
+        self.engine.borrow().activity.end(self.id);
+        let _ = fs::remove_dir_all(&self.temp_dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::expr::BinaryOp;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path(), 16).unwrap();
+        let (mut s1, mut s2) = (db.session(), db.session());
+        assert_ne!(s1.id(), s2.id());
+        let ids = |session: &mut Session| match session.execute("SELECT id FROM t").unwrap().pop() {
+            Some(QueryResult::Rows { rows, .. }) => rows.into_iter().map(|row| row[0].clone()).collect::<Vec<_>>(),
+            result => panic!("unexpected result: {:?}", result),
+        };
+        s1.execute("CREATE TABLE t (id INTEGER PRIMARY KEY); INSERT INTO t VALUES (1)").unwrap();
+
+        // each session has a transaction of its own
+        s1.execute("BEGIN; INSERT INTO t VALUES (2)").unwrap();
+        assert!(s1.in_transaction() && !s2.in_transaction());
+        assert_eq!(vec![Value::Int(1)], ids(&mut s2));
+        let insert = s2.prepare("INSERT INTO t VALUES (?)").unwrap();
+        assert_eq!(QueryResult::RowsAffected(1), s2.execute_prepared(&insert, &[Value::Int(3)]).unwrap());
+        assert_eq!(vec![Value::Int(1), Value::Int(2), Value::Int(3)], ids(&mut s1));
+        s1.execute("COMMIT").unwrap();
+        assert_eq!(vec![Value::Int(1), Value::Int(2), Value::Int(3)], ids(&mut s2));
+
+        // and settings of its own
+        s2.settings_mut().statement_timeout = Some(Duration::ZERO);
+        let err = s2.execute("SELECT id FROM t").unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{:?}", err);
+        assert_eq!(3, ids(&mut s1).len());
+        *s2.settings_mut() = Settings::default();
+        assert_eq!(3, ids(&mut s2).len());
+
+        // ending a session rolls back its transaction and removes its temporary files
+        s2.execute("BEGIN; INSERT INTO t VALUES (4)").unwrap();
+        let temp_dir = s2.temp_dir().unwrap().to_path_buf();
+        fs::write(temp_dir.join("run"), b"spilled").unwrap();
+        drop(s2);
+        assert!(!temp_dir.exists());
+        s1.execute("INSERT INTO t VALUES (4)").unwrap();
+
+        // maintenance queued by workers runs between the calls of sessions
+        let analyzed = |db: &Database| db.with_engine(|_, catalog| catalog.table("t").unwrap().stats.clone());
+        assert_eq!(None, analyzed(&db));
+        db.start_worker(Task::Analyze, Duration::from_millis(1));
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(4, ids(&mut s1).len());
+        assert_eq!(4, analyzed(&db).unwrap().row_count);
+        assert!(db.stop_worker(Task::Analyze));
+        db.maintain().unwrap();
+        assert!(db.maintain().unwrap().is_empty());
+
+        // DDL takes part in the transaction, the others seeing what it changed once it commits,
+        // and changing the catalog only once it's ended
+        let mut s2 = db.session();
+        s1.execute("INSERT INTO t VALUES (5); INSERT INTO t VALUES (6)").unwrap();
+        s1.execute("BEGIN; CREATE TABLE x (id INTEGER PRIMARY KEY); INSERT INTO x VALUES (1); CREATE INDEX t_id ON t (id)").unwrap();
+        assert_eq!(1, s1.execute("SELECT * FROM x").unwrap().pop().unwrap().num_rows());
+        assert_eq!("table not found: x", s2.execute("SELECT * FROM x").unwrap_err().to_string());
+        assert!(s2.prepare("SELECT * FROM x").is_err() && db.with_engine(|_, catalog| catalog.table("t").unwrap().index_names.is_empty()));
+        let err = s2.execute("CREATE TABLE y (id INTEGER PRIMARY KEY)").unwrap_err();
+        assert_eq!("waiting for a lock held by another transaction", err.to_string());
+        s1.execute("COMMIT").unwrap();
+        assert_eq!(1, s2.execute("SELECT * FROM x").unwrap().pop().unwrap().num_rows());
+        // rolled back, what it changed is gone, without losing what another committed in between
+        s1.execute("BEGIN; CREATE TABLE y (id INTEGER PRIMARY KEY); CREATE INDEX x_id ON x (id); GRANT SELECT ON x TO nobody").unwrap_err();
+        s1.execute("ROLLBACK").unwrap();
+        s1.execute("BEGIN; CREATE TABLE y (id INTEGER PRIMARY KEY); CREATE INDEX x_id ON x (id); CREATE USER carol").unwrap();
+        s1.execute("CREATE TABLE y (id INTEGER PRIMARY KEY)").unwrap_err();
+        // the block failing until it's ended, a COMMIT rolling back
+        assert!(s1.in_transaction() && s1.transaction_failed());
+        assert_eq!("current transaction is aborted, commands ignored until end of transaction block", s1.execute("SHOW durability").unwrap_err().to_string());
+        assert!(matches!(s1.execute("SELECT * FROM y").unwrap_err(), sql::Error::TransactionAborted));
+        assert!(matches!(s1.execute("COMMIT").unwrap()[..], [QueryResult::RolledBack]));
+        assert!(!s1.in_transaction());
+        s2.execute("CREATE TABLE z (id INTEGER PRIMARY KEY); INSERT INTO z VALUES (1)").unwrap();
+        s1.execute("BEGIN; CREATE TABLE y (id INTEGER PRIMARY KEY); ROLLBACK").unwrap();
+        for session in [&mut s1, &mut s2] {
+            assert!(session.execute("SELECT * FROM y").is_err() && session.execute("SELECT * FROM z").is_ok());
+        }
+        db.with_engine(|_, catalog| assert!(catalog.table("x").unwrap().index_names.is_empty() && catalog.user("carol").is_none()));
+        // as it is when the transaction is terminated
+        s1.execute("BEGIN; CREATE TABLE w (id INTEGER PRIMARY KEY)").unwrap();
+        s2.execute(&format!("CANCEL SESSION {}", s1.id())).unwrap();
+        s2.execute("CREATE TABLE w (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        assert!(s1.execute("SELECT * FROM w").is_err());
+        assert_eq!(2, db.with_engine(|_, catalog| catalog.table("w").unwrap().columns.len()));
+        drop(s2);
+
+        // what's committed is there when the database is opened again, the catalog included
+        drop(s1);
+        db.close().unwrap();
+        let db = Database::open(dir.path(), 16).unwrap();
+        let mut session = db.session();
+        assert_eq!((1..=6).map(Value::Int).collect::<Vec<_>>(), ids(&mut session));
+        db.with_engine(|_, catalog| {
+            assert_eq!(vec!["t", "w", "x", "z"], catalog.tables().map(|info| info.name.as_str()).collect::<Vec<_>>());
+            assert_eq!(2, catalog.table("w").unwrap().columns.len());
+            assert_eq!(vec!["t_id"], catalog.table("t").unwrap().index_names);
+        });
+
+        // a commit which doesn't wait for the log to be flushed is durable once the flusher has run
+        session.settings_mut().durability = Durability::Async;
+        session.execute("INSERT INTO t VALUES (7)").unwrap();
+        let unflushed = |db: &Database| db.with_engine(|bufmgr, _| bufmgr.wal().is_some_and(|wal| wal.flushed() < wal.end()));
+        assert!(unflushed(&db));
+        db.with_engine(|bufmgr, catalog| Task::Flush.run(bufmgr, catalog)).unwrap();
+        assert!(!unflushed(&db));
+
+        // functions registered are called from any expression, with their arguments cast
+        db.create_function("clamp", &[DataType::Integer, DataType::Integer], DataType::Integer, |args| match args {
+            [Value::Int(n), Value::Int(max)] if *max >= 0 => Ok(Value::Int((*n).min(*max))),
+            [Value::Int(_), Value::Int(_)] => Err("negative maximum".to_string()),
+            _ => Ok(Value::Null),
+        });
+        db.create_function("shout", &[DataType::Text], DataType::Text, |args| match &args[0] {
+            Value::Text(s) => Ok(Value::Text(format!("{}!", s.to_uppercase()))),
+            _ => Ok(Value::Int(0)),
+        });
+        let rows = |session: &mut Session, sql: &str| match session.execute(sql).unwrap().pop() {
+            Some(QueryResult::Rows { rows, .. }) => rows,
+            result => panic!("unexpected result: {:?}", result),
+        };
+        assert_eq!(vec![vec![Value::Int(3)]], rows(&mut session, "SELECT clamp(id, '3') FROM t WHERE id = 5"));
+        assert_eq!(vec![vec![Value::Int(1)], vec![Value::Int(2)]], rows(&mut session, "SELECT id FROM t WHERE clamp(id * 10, 20) = id * 10"));
+        session.execute("CREATE VIEW loud AS SELECT shout(concat('t', id)) AS name FROM t WHERE id < 3; BEGIN; CREATE TABLE v (id INTEGER PRIMARY KEY); ROLLBACK").unwrap();
+        assert_eq!(vec![vec![Value::Text("T1!".to_string())], vec![Value::Text("T2!".to_string())]], rows(&mut session, "SELECT * FROM loud"));
+        let clamp = session.prepare("SELECT clamp(?, ?)").unwrap();
+        assert_eq!(Some(vec![("clamp".to_string(), Some(DataType::Integer))]), session.result_columns(&clamp));
+        assert!(matches!(session.execute_prepared(&clamp, &[Value::Int(9), Value::Null]), Ok(QueryResult::Rows { rows, .. }) if rows == vec![vec![Value::Null]]));
+        assert_eq!("function clamp failed: negative maximum", session.execute("SELECT clamp(1, -1)").unwrap_err().to_string());
+        assert!(session.execute("SELECT shout(NULL)").unwrap_err().to_string().contains("function shout returned Int(0), not of type text"));
+        assert_eq!("function clamp takes 2 arguments, not 1", session.execute("SELECT clamp(1)").unwrap_err().to_string());
+        assert_eq!("unknown function: nothing", session.execute("SELECT nothing(1)").unwrap_err().to_string());
+
+        // and aggregates registered over the groups of GROUP BY, in parallel too
+        db.create_aggregate("product", DataType::Integer, DataType::Integer, Value::Int(1), |state, value| match (state, value) {
+            (Value::Int(p), Value::Int(n)) => p.checked_mul(*n).map(Value::Int).ok_or_else(|| "overflow".to_string()),
+            _ => Err("not an integer".to_string()),
+        }, Ok);
+        db.create_aggregate("joined", DataType::Text, DataType::Text, Value::Null, |state, value| match (state, value) {
+            (Value::Text(s), Value::Text(v)) => Ok(Value::Text(format!("{}+{}", s, v))),
+            (_, value) => Ok(value.clone()),
+        }, |state| Ok(if state.is_null() { Value::Text("none".to_string()) } else { state }));
+        for workers in [0, 2] {
+            session.settings_mut().parallel_workers = workers;
+            assert_eq!(vec![vec![Value::Int(720), Value::Text("1+2+3+4+5+6".to_string())]], rows(&mut session, "SELECT product(id), joined(id) FROM t WHERE id < 7"));
+            let sql = "SELECT id % 2, product(id) + 1, joined(concat(id)) FROM t WHERE id < 7 GROUP BY id % 2 HAVING product(id) > 20";
+            assert_eq!(vec![vec![Value::Int(0), Value::Int(49), Value::Text("2+4+6".to_string())]], rows(&mut session, sql));
+        }
+        session.settings_mut().parallel_workers = 0;
+        assert_eq!(vec![vec![Value::Text("none".to_string())]], rows(&mut session, "SELECT joined(concat(id)) FROM t WHERE id > 100"));
+        let product = session.prepare("SELECT product(id) FROM t").unwrap();
+        assert_eq!(Some(vec![("product".to_string(), Some(DataType::Integer))]), session.result_columns(&product));
+        assert_eq!("function product failed: overflow", session.execute("SELECT product(id * 1000000000) FROM t").unwrap_err().to_string());
+        assert_eq!("aggregate functions are not allowed here", session.execute("SELECT id FROM t WHERE product(id) > 1").unwrap_err().to_string());
+        assert_eq!("unknown function: avg", session.execute("SELECT COUNT(DISTINCT id), product(id), avg(id) FROM t").unwrap_err().to_string());
+
+        // virtual tables registered are read like tables, given the comparisons of their columns
+        // to constants to skip the rows of
+        struct Numbers(RefCell<Vec<catalog::Constraint>>);
+        impl VirtualTable for Numbers {
+            fn columns(&self) -> Vec<Column> {
+                vec![Column { name: "n".to_string(), data_type: DataType::Integer }]
+            }
+            fn rows(&self, _bufmgr: &mut BufferPoolManager) -> Result<Vec<Tuple>, query::Error> {
+                Ok((1..=10).map(|n| vec![Value::Int(n)]).collect())
+            }
+            fn scan(&self, bufmgr: &mut BufferPoolManager, constraints: &[catalog::Constraint]) -> Result<Vec<Tuple>, query::Error> {
+                *self.0.borrow_mut() = constraints.to_vec();
+                // only the equalities, what's left being filtered by the query
+                Ok(self.rows(bufmgr)?.into_iter().filter(|row| constraints.iter().all(|c| c.op != BinaryOp::Eq || c.holds(row))).collect())
+            }
+        }
+        let numbers = Rc::new(Numbers(RefCell::default()));
+        db.register_virtual_table("numbers", numbers.clone()).unwrap();
+        assert!(db.register_virtual_table("t", numbers.clone()).is_err());
+        assert_eq!(vec![vec![Value::Int(8)], vec![Value::Int(9)]], rows(&mut session, "SELECT n FROM numbers WHERE n > 7 AND 9 >= n"));
+        let constraint = |op, n| catalog::Constraint { column: 0, op, value: Value::Int(n) };
+        assert_eq!(vec![constraint(BinaryOp::Gt, 7), constraint(BinaryOp::LtEq, 9)], *numbers.0.borrow());
+        assert_eq!(vec![vec![Value::Int(2), Value::Int(2)]], rows(&mut session, "SELECT id, n FROM t, numbers WHERE n = id AND n = '2'"));
+        assert_eq!(vec![constraint(BinaryOp::Eq, 2)], *numbers.0.borrow());
+        let prepared = session.prepare("SELECT count(*) FROM numbers WHERE n <> ?").unwrap();
+        assert!(matches!(session.execute_prepared(&prepared, &[Value::Int(3)]), Ok(QueryResult::Rows { rows, .. }) if rows == vec![vec![Value::Int(9)]]));
+        assert_eq!(vec![constraint(BinaryOp::NotEq, 3)], *numbers.0.borrow());
+        let explain = rows(&mut session, "EXPLAIN SELECT * FROM numbers WHERE (n < 3 OR n > 8) AND 4 <= n").into_iter().map(|row| format!("{:?}", row)).collect::<String>();
+        assert!(explain.contains("Virtual Scan on numbers (constraints: (#0 >= 4))"), "{}", explain);
+
+        // and table functions, built in or registered, in FROM
+        assert_eq!(vec![vec![Value::Int(55)]], rows(&mut session, "SELECT sum(n) FROM generate_series(1, 10) AS g (n)"));
+        assert_eq!((0..4).map(|i| vec![Value::Int(10 - 3 * i)]).collect::<Vec<_>>(), rows(&mut session, "SELECT * FROM generate_series(10, 0, -3)"));
+        assert_eq!(vec![vec![Value::Int(2)]], rows(&mut session, "SELECT count(*) FROM t JOIN generate_series(2, 3) AS g ON t.id = g.generate_series"));
+        // which a timeout interrupts even when no pages are read
+        session.execute("SET statement_timeout = 50").unwrap();
+        let err = session.execute("SELECT count(*) FROM generate_series(1, 100000) a, generate_series(1, 100000) b").unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{:?}", err);
+        session.execute("SET statement_timeout = 0").unwrap();
+        let prepared = session.prepare("SELECT * FROM generate_series(1, ?)").unwrap();
+        assert!(matches!(session.execute_prepared(&prepared, &[Value::Int(2)]), Ok(QueryResult::Rows { rows, .. }) if rows.len() == 2));
+        db.create_table_function("split", &[DataType::Text], vec![Column { name: "word".to_string(), data_type: DataType::Text }], |args| match &args[0] {
+            Value::Text(s) => Ok(s.split(',').map(|word| vec![Value::Text(word.to_string())]).collect()),
+            _ => Ok(vec![vec![Value::Int(0)]]),
+        });
+        assert_eq!(vec![vec![Value::Text("b".to_string())]], rows(&mut session, "SELECT w FROM split('a,b,c') AS s (w) WHERE w = 'b'"));
+        assert!(session.execute("SELECT * FROM split(NULL)").unwrap_err().to_string().contains("function split returned [Int(0)], not a row of its columns"));
+        assert_eq!("function generate_series takes 2 to 3 arguments, not 1", session.execute("SELECT * FROM generate_series(1)").unwrap_err().to_string());
+        assert_eq!("function generate_series failed: step size cannot be zero", session.execute("SELECT * FROM generate_series(1, 2, 0)").unwrap_err().to_string());
+        assert_eq!("unknown table function: nothing", session.execute("SELECT * FROM nothing()").unwrap_err().to_string());
+
+        // triggers of callbacks, which before a row is written may change it or leave it out
+        session.execute("CREATE TABLE orders (id INTEGER PRIMARY KEY, qty INTEGER)").unwrap();
+        db.create_trigger("limit", "orders", Timing::Before, Event::Insert, |images| match &mut images.new.as_mut().unwrap()[1] {
+            Value::Int(qty) if *qty < 0 => Ok(false),
+            Value::Int(qty) => {
+                *qty = (*qty).min(10);
+                Ok(true)
+            }
+            _ => Err("no quantity".to_string()),
+        })
+        .unwrap();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let log = seen.clone();
+        db.create_trigger("log", "orders", Timing::After, Event::Update, move |images| {
+            log.borrow_mut().push((images.old.clone().unwrap()[1].clone(), images.new.clone().unwrap()[1].clone()));
+            Ok(false)
+        })
+        .unwrap();
+        db.create_trigger("rekey", "orders", Timing::Before, Event::Update, |images| {
+            let new = images.new.as_mut().unwrap();
+            if new[1] == Value::Int(0) {
+                new[0] = Value::Int(0);
+            }
+            Ok(true)
+        })
+        .unwrap();
+        assert!(matches!(session.execute("INSERT INTO orders VALUES (1, 50), (2, -1), (3, 5)").unwrap()[..], [QueryResult::RowsAffected(2)]));
+        session.execute("BEGIN; CREATE TABLE rolled (id INTEGER PRIMARY KEY); ROLLBACK").unwrap();
+        session.execute("INSERT INTO orders VALUES (3, 7) ON CONFLICT (id) DO UPDATE SET qty = orders.qty + excluded.qty").unwrap();
+        assert_eq!(vec![vec![Value::Int(1), Value::Int(10)], vec![Value::Int(3), Value::Int(12)]], rows(&mut session, "SELECT * FROM orders"));
+        assert_eq!(vec![(Value::Int(5), Value::Int(12))], *seen.borrow());
+        assert_eq!("trigger limit failed: no quantity", session.execute("INSERT INTO orders VALUES (4, NULL)").unwrap_err().to_string());
+        let err = session.execute("INSERT INTO orders VALUES (1, 0) ON CONFLICT (id) DO UPDATE SET qty = 0").unwrap_err();
+        assert_eq!("trigger rekey failed: the primary key of a row updated can't be changed", err.to_string());
+        db.create_trigger("keep", "orders", Timing::Before, Event::Delete, |images| Ok(images.old.as_ref().unwrap()[1] != Value::Int(10))).unwrap();
+        assert!(matches!(session.execute("DELETE FROM orders").unwrap()[..], [QueryResult::RowsAffected(1)]));
+        assert_eq!(vec![vec![Value::Int(1), Value::Int(10)]], rows(&mut session, "SELECT * FROM orders"));
+        assert!(matches!(db.create_trigger("other", "nothing", Timing::After, Event::Delete, |_| Ok(true)), Err(Error::Catalog(catalog::Error::UnknownTable(_)))));
+
+        // opened as configured
+        let dir = tempdir().unwrap();
+        let config = Config {
+            pool_size: 8,
+            eviction_policy: EvictionPolicy::Lru,
+            wal_dir: Some(PathBuf::from("log")),
+            session: Settings { statement_timeout: Some(Duration::from_secs(60)), ..Settings::default() },
+            workers: [(Task::Flush, Duration::from_secs(3600))].into(),
+            ..Config::default()
+        };
+        let db = Database::builder().config(config).durability(Durability::Async).open(dir.path()).unwrap();
+        let mut session = db.session();
+        assert_eq!(Some(Duration::from_secs(60)), session.settings().statement_timeout);
+        assert_eq!(Durability::Async, session.settings().durability);
+        assert!(dir.path().join("log").is_dir() && !dir.path().join(WAL_DIR).exists());
+        assert!(db.stop_worker(Task::Flush));
+
+        // settings of the session set and shown by SET and SHOW, DEFAULT being what it started with
+        let show = |session: &mut Session, name: &str| match session.execute(&format!("SHOW {}", name)).unwrap().pop().unwrap() {
+            QueryResult::Rows { rows, .. } => rows[0][rows[0].len() - 1].clone(),
+            result => panic!("{:?}", result),
+        };
+        session.execute("SET statement_timeout = 500; SET memory_budget TO '1048576'; SET durability = sync").unwrap();
+        assert_eq!(Some(Duration::from_millis(500)), session.settings().statement_timeout);
+        assert_eq!((Some(1 << 20), Durability::Sync), (session.settings().memory_budget, session.settings().durability));
+        // the memory of a join run again for each row of a subquery is given back each time
+        let values: Vec<String> = (0..100).map(|i| format!("({}, {})", i, i % 10)).collect();
+        session.execute(&format!("CREATE TABLE budgeted (id INTEGER PRIMARY KEY, g INTEGER); INSERT INTO budgeted VALUES {}", values.join(", "))).unwrap();
+        session.execute("SET memory_budget = 20000").unwrap();
+        assert_eq!(vec![vec![Value::Int(1000)]], rows(&mut session, "SELECT COUNT(*) FROM budgeted x, budgeted y WHERE x.g = y.g"));
+        let sql = "SELECT SUM((SELECT COUNT(*) FROM budgeted x, budgeted y WHERE x.g = y.g AND x.g = o.g)) FROM budgeted o";
+        assert_eq!(vec![vec![Value::Int(10000)]], rows(&mut session, sql));
+        // as are the rows an UPDATE or DELETE finds before writing them
+        session.execute("SET memory_budget = 2000").unwrap();
+        for sql in ["UPDATE budgeted SET g = g + 1", "DELETE FROM budgeted WHERE g >= 0"] {
+            assert_eq!("out of the memory budget of 2000 bytes", session.execute(sql).unwrap_err().to_string());
+        }
+        session.execute("SET memory_budget = 1048576").unwrap();
+        session.execute("UPDATE budgeted SET g = g - 1; UPDATE budgeted SET g = g + 1").unwrap();
+        assert_eq!(Value::Text("500".to_string()), show(&mut session, "statement_timeout"));
+        session.execute("SET statement_timeout = DEFAULT; SET durability TO DEFAULT").unwrap();
+        assert_eq!(Some(Duration::from_secs(60)), session.settings().statement_timeout);
+        assert_eq!(Value::Text("async".to_string()), show(&mut session, "durability"));
+        assert_eq!(Value::Text("0".to_string()), show(&mut session, "lock_timeout"));
+        assert_eq!(SETTING_NAMES.len(), match session.execute("SHOW ALL").unwrap().pop().unwrap() {
+            QueryResult::Rows { rows, .. } => rows.len(),
+            result => panic!("{:?}", result),
+        });
+        assert_eq!("setting not found: nothing", session.execute("SHOW nothing").unwrap_err().to_string());
+        assert!(session.execute("SET nothing = 1").is_err());
+        assert!(session.execute("SET enable_hashjoin = maybe").is_err());
+        let set = session.prepare("SET parallel_workers = 2").unwrap();
+        session.execute_prepared(&set, &[]).unwrap();
+        assert_eq!(2, session.settings().parallel_workers);
+        // no more workers than configured, which the threads of scans are bounded by too
+        let err = session.execute("SET parallel_workers = 1000000").unwrap_err();
+        assert_eq!("1000000 is outside the valid range for setting parallel_workers (0 .. 8, as max_parallel_workers is configured)", err.to_string());
+        assert_eq!(2, session.settings().parallel_workers);
+        session.settings_mut().parallel_workers = 1_000_000;
+        session.execute("SELECT * FROM budgeted").unwrap();
+        db.with_engine(|bufmgr, _| {
+            bufmgr.set_parallel_workers(1_000_000);
+            assert_eq!(buffer::DEFAULT_MAX_PARALLEL_WORKERS, bufmgr.parallel_workers());
+            assert!(bufmgr.thread_pool().size() <= buffer::DEFAULT_MAX_PARALLEL_WORKERS);
+            bufmgr.set_parallel_workers(0);
+        });
+        session.settings_mut().parallel_workers = 2;
+
+        // BEGIN without a level begins at the session's default
+        session.execute("SET default_transaction_isolation = serializable; BEGIN").unwrap();
+        assert_eq!(Some(Isolation::Serializable), session.state.isolation());
+        session.execute("ROLLBACK; SET default_transaction_isolation = 'Repeatable Read'").unwrap();
+        assert_eq!(Isolation::RepeatableRead, session.settings().default_isolation);
+
+        // the ways of reading tables the planner may choose
+        session.execute("CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        let plan = |session: &mut Session| match session.execute("EXPLAIN SELECT name FROM t WHERE id = 1").unwrap().pop().unwrap() {
+            QueryResult::Rows { rows, .. } => format!("{:?}", rows),
+            result => panic!("{:?}", result),
+        };
+        assert!(plan(&mut session).contains("Index Scan"), "{}", plan(&mut session));
+        session.execute("SET enable_indexscan = off").unwrap();
+        assert!(!session.settings().planner.enable_indexscan);
+        assert!(plan(&mut session).contains("Seq Scan"), "{}", plan(&mut session));
+        // chosen anyway if there's no other way
+        session.execute("SET enable_seqscan = false").unwrap();
+        assert!(plan(&mut session).contains("Scan"));
+
+        // users run what they've been granted the privileges for, a view reading tables with
+        // the privileges on the view
+        session
+            .execute(
+                "CREATE VIEW names AS SELECT name FROM t; CREATE USER alice; CREATE USER root WITH SUPERUSER;
+                 GRANT SELECT, INSERT ON t TO alice; GRANT SELECT ON names TO alice; REVOKE INSERT ON t FROM alice",
+            )
+            .unwrap();
+        let mut alice = db.session();
+        alice.set_user(Some("alice"));
+        alice.execute("SELECT * FROM t; SELECT * FROM names; SELECT count(*) FROM stat_statements; BEGIN; LOCK TABLE t IN SHARE MODE; COMMIT").unwrap();
+        let denied = |session: &mut Session, sql: &str| match session.execute(sql) {
+            Err(sql::Error::PermissionDenied(message)) => message,
+            result => panic!("{:?}", result),
+        };
+        assert_eq!("INSERT on t", denied(&mut alice, "INSERT INTO t VALUES (1, 'a')"));
+        assert_eq!("DELETE on t", denied(&mut alice, "DELETE FROM t WHERE id = 1"));
+        assert_eq!("only superusers may run CREATE TABLE", denied(&mut alice, "CREATE TABLE u (id INTEGER PRIMARY KEY)"));
+        assert_eq!("only superusers may run GRANT", denied(&mut alice, "GRANT INSERT ON t TO alice"));
+        assert_eq!("only superusers may run ALTER USER", denied(&mut alice, "ALTER USER root PASSWORD 'x'"));
+        alice.execute("ALTER USER alice PASSWORD 'x'").unwrap();
+        let insert = session.prepare("INSERT INTO t VALUES (?, 'a')").unwrap();
+        assert!(matches!(alice.execute_prepared(&insert, &[Value::Int(1)]), Err(sql::Error::PermissionDenied(_))));
+        assert!(matches!(alice.load("t", || Ok(None), None), Err(sql::Error::PermissionDenied(_))));
+        session.execute("GRANT ALL ON t TO alice; REVOKE SELECT ON names FROM alice").unwrap();
+        alice.execute_prepared(&insert, &[Value::Int(1)]).unwrap();
+        alice.execute("UPDATE t SET name = 'b' WHERE id = 1; DELETE FROM t WHERE id = 1").unwrap();
+        alice.execute_prepared(&insert, &[Value::Int(1)]).unwrap();
+        assert_eq!("SELECT on names", denied(&mut alice, "SELECT * FROM names"));
+        alice.set_user(Some("root"));
+        alice.execute("SELECT * FROM names; CREATE TABLE u (id INTEGER PRIMARY KEY)").unwrap();
+
+        // an error fails the transaction block whatever it's of, none of the block being committed:
+        // planning a statement
+        let count = |session: &mut Session| rows(session, "SELECT count(*) FROM t WHERE id = 9");
+        session.execute("BEGIN; INSERT INTO t VALUES (9, 'z')").unwrap();
+        assert!(matches!(session.execute("SELECT nope FROM t"), Err(sql::Error::UnknownColumn(_))));
+        assert!(session.transaction_failed());
+        assert!(matches!(session.execute("SELECT 1"), Err(sql::Error::TransactionAborted)));
+        assert!(matches!(session.execute("COMMIT").unwrap()[..], [QueryResult::RolledBack]));
+        assert_eq!(vec![vec![Value::Int(0)]], count(&mut session));
+        // preparing or parsing one
+        for sql in ["SELECT nope FROM t", "SELEC 1"] {
+            session.execute("BEGIN; INSERT INTO t VALUES (9, 'z')").unwrap();
+            assert!(session.prepare(sql).is_err() && session.transaction_failed());
+            session.execute("COMMIT").unwrap();
+            assert_eq!(vec![vec![Value::Int(0)]], count(&mut session));
+        }
+        // or running one the user hasn't the privileges for
+        alice.set_user(Some("alice"));
+        alice.execute("BEGIN; INSERT INTO t VALUES (9, 'z')").unwrap();
+        assert_eq!("SELECT on names", denied(&mut alice, "SELECT * FROM names"));
+        assert!(matches!(alice.execute_prepared(&insert, &[Value::Int(10)]), Err(sql::Error::TransactionAborted)));
+        assert!(matches!(alice.execute("COMMIT").unwrap()[..], [QueryResult::RolledBack]));
+        assert_eq!(vec![vec![Value::Int(0)]], count(&mut session));
+        alice.set_user(Some("root"));
+
+        // cursors fetch the rows of a SELECT some at a time, as of when they were declared
+        session.execute("SET enable_indexscan = on; SET enable_seqscan = on; INSERT INTO t VALUES (2, 'b'), (3, 'c'), (4, 'd')").unwrap();
+        let fetch = |session: &mut Session, sql: &str| match session.execute(sql).unwrap().pop().unwrap() {
+            QueryResult::Rows { rows, .. } => rows.into_iter().map(|row| row[0].clone()).collect::<Vec<_>>(),
+            result => panic!("{:?}", result),
+        };
+        let ints = |ids: &[i64]| ids.iter().map(|&id| Value::Int(id)).collect::<Vec<_>>();
+        let err = session.execute("DECLARE c CURSOR FOR SELECT id FROM t").unwrap_err();
+        assert_eq!("DECLARE CURSOR can only be used in transaction blocks", err.to_string());
+        session.execute("BEGIN ISOLATION LEVEL READ COMMITTED; DECLARE c CURSOR FOR SELECT id FROM t").unwrap();
+        assert_eq!(ints(&[1, 2]), fetch(&mut session, "FETCH 2 FROM c"));
+        // not seeing rows committed since, even in read committed, unlike statements of its own
+        alice.execute("INSERT INTO t VALUES (5, 'e')").unwrap();
+        assert_eq!(ints(&[3]), fetch(&mut session, "FETCH c"));
+        assert_eq!(5, fetch(&mut session, "SELECT id FROM t").len());
+        assert_eq!(ints(&[4]), fetch(&mut session, "FETCH ALL IN c"));
+        assert!(fetch(&mut session, "FETCH c").is_empty());
+        assert_eq!("cursor already exists: c", session.execute("DECLARE c CURSOR FOR SELECT id FROM t").unwrap_err().to_string());
+        // which fails the block, as any error does
+        assert!(session.transaction_failed());
+        session.execute("ROLLBACK; BEGIN; DECLARE c CURSOR FOR SELECT id FROM t; CLOSE c").unwrap();
+        assert_eq!("cursor not found: c", session.execute("FETCH c").unwrap_err().to_string());
+        // each going on from where it left off
+        session.execute("ROLLBACK; BEGIN; DECLARE c CURSOR FOR SELECT id FROM t WHERE id > 1; DECLARE d CURSOR FOR SELECT name FROM t").unwrap();
+        assert_eq!(ints(&[2, 3]), fetch(&mut session, "FETCH 2 c"));
+        assert_eq!(vec![Value::Text("a".to_string())], fetch(&mut session, "FETCH d"));
+        assert_eq!(ints(&[4, 5]), fetch(&mut session, "FETCH FORWARD ALL c"));
+        // closed when the transaction ends
+        session.execute("COMMIT").unwrap();
+        assert_eq!("cursor not found: d", session.execute("FETCH d").unwrap_err().to_string());
+        // of SELECTs the user may run
+        alice.set_user(Some("alice"));
+        alice.execute("BEGIN").unwrap();
+        assert_eq!("SELECT on names", denied(&mut alice, "DECLARE n CURSOR FOR SELECT * FROM names"));
+        alice.execute("ROLLBACK; BEGIN; DECLARE n CURSOR FOR SELECT name FROM t; CLOSE ALL; ROLLBACK").unwrap();
+
+        // temporary tables are the session's alone, their pages unlogged, and dropped when it ends
+        let mut other = db.session();
+        session.execute("CREATE TEMP TABLE scratch (id INTEGER PRIMARY KEY, name TEXT); CREATE INDEX scratch_name ON scratch (name)").unwrap();
+        let end = db.with_engine(|bufmgr, _| bufmgr.wal().unwrap().end());
+        let values: Vec<String> = (1..=300).map(|id| format!("({}, '{}{}')", id, "x".repeat(100), id)).collect();
+        session.execute(&format!("INSERT INTO scratch VALUES {}; ANALYZE scratch", values.join(", "))).unwrap();
+        assert!(db.with_engine(|bufmgr, _| bufmgr.wal().unwrap().end()) - end < 1024);
+        assert_eq!(ints(&[7]), fetch(&mut session, &format!("SELECT id FROM scratch WHERE name = '{}7'", "x".repeat(100))));
+        assert!(session.with_catalog(|catalog| catalog.table("scratch").unwrap().stats.as_ref().is_some_and(|stats| stats.row_count == 300)));
+        assert_eq!("table not found: scratch", other.execute("SELECT * FROM scratch").unwrap_err().to_string());
+        assert!(session.prepare("SELECT * FROM scratch").is_ok() && other.prepare("SELECT * FROM scratch").is_err());
+        other.execute("CREATE TEMP TABLE scratch (id INTEGER PRIMARY KEY); INSERT INTO scratch VALUES (1)").unwrap();
+        assert_eq!(1, fetch(&mut other, "SELECT * FROM scratch").len());
+        assert_eq!(300, fetch(&mut session, "SELECT * FROM scratch").len());
+        assert!(db.with_engine(|_, catalog| catalog.table("scratch").is_none()));
+        // created by a transaction rolled back, it's gone
+        session.execute("BEGIN; CREATE TEMP TABLE gone (id INTEGER PRIMARY KEY); INSERT INTO gone VALUES (1); ROLLBACK").unwrap();
+        assert_eq!("table not found: gone", session.execute("SELECT * FROM gone").unwrap_err().to_string());
+        // rows inserted by one rolled back stay invisible, whatever vacuum forgets of it
+        session.execute("BEGIN; INSERT INTO scratch VALUES (301, 'y'); ROLLBACK; VACUUM; VACUUM FULL").unwrap();
+        assert_eq!(300, fetch(&mut session, "SELECT * FROM scratch").len());
+        assert_eq!(1, fetch(&mut other, "SELECT * FROM scratch").len());
+        assert_eq!("table is temporary: scratch", session.execute("REINDEX TABLE scratch").unwrap_err().to_string());
+        assert_eq!("table is temporary: scratch", session.execute("CREATE INDEX CONCURRENTLY i ON scratch (name)").unwrap_err().to_string());
+        assert!(session.execute("CREATE TEMP TABLE p (id INTEGER PRIMARY KEY) PARTITION BY HASH (id)").is_err());
+        let used = |db: &Database| db.with_engine(|bufmgr, _| bufmgr.num_temp_table_pages() - bufmgr.num_free_temp_table_pages() as u64);
+        let num_pages = db.with_engine(|bufmgr, _| bufmgr.num_temp_table_pages());
+        assert!(used(&db) > 0);
+        drop(session);
+        drop(other);
+        assert_eq!(0, used(&db));
+        // the pages freed being reused by those of other sessions
+        let mut session = db.session();
+        session.execute("CREATE TEMP TABLE scratch (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        session.execute(&format!("INSERT INTO scratch VALUES {}", values.join(", "))).unwrap();
+        assert_eq!(num_pages, db.with_engine(|bufmgr, _| bufmgr.num_temp_table_pages()));
+    }
+}
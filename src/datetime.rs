@@ -0,0 +1,138 @@
+// Dates, as days since 1970-01-01, and timestamps without a time zone, as microseconds since its
+// midnight, of the years 1 to 9999 of the proleptic Gregorian calendar. They're written as text as
+// PostgreSQL writes them in the ISO style: 2024-02-29, and 2024-02-29 13:45:00.5 with as many
+// digits of the second's fraction as it has. A timestamp is read with a space or a T between the
+// date and the time, and a date alone is its midnight.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("invalid input syntax for type date: \"{0}\"")]
+    Date(String),
+    #[error("invalid input syntax for type timestamp: \"{0}\"")]
+    Timestamp(String),
+}
+
+pub const MICROS_PER_DAY: i64 = 24 * 60 * 60 * 1_000_000;
+
+pub fn parse_date(text: &str) -> Result<i32, Error> {
+    date(text.trim()).ok_or_else(|| Error::Date(text.to_string()))
+}
+
+pub fn parse_timestamp(text: &str) -> Result<i64, Error> {
+    let error = || Error::Timestamp(text.to_string());
+    let trimmed = text.trim();
+    let (date_part, time) = match trimmed.split_once([' ', 'T']) {
+        Some((date_part, time)) => (date_part, Some(time.trim_start())),
+        None => (trimmed, None),
+    };
+    let days = date(date_part).ok_or_else(error)?;
+    let micros = match time {
+        Some(time) => time_of_day(time).ok_or_else(error)?,
+        None => 0,
+    };
+    Ok(days as i64 * MICROS_PER_DAY + micros)
+}
+
+pub fn format_date(days: i32) -> String {
+    let (year, month, day) = civil_from_days(days as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+pub fn format_timestamp(micros: i64) -> String {
+    let (days, micros) = (micros.div_euclid(MICROS_PER_DAY), micros.rem_euclid(MICROS_PER_DAY));
+    let seconds = micros / 1_000_000;
+    let mut text = format!("{} {:02}:{:02}:{:02}", format_date(days as i32), seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if micros % 1_000_000 != 0 {
+        text.push_str(format!(".{:06}", micros % 1_000_000).trim_end_matches('0'));
+    }
+    text
+}
+
+// YYYY-MM-DD
+fn date(text: &str) -> Option<i32> {
+    let mut fields = text.splitn(3, '-');
+    let mut field = |len: std::ops::RangeInclusive<usize>| {
+        let field = fields.next()?;
+        (len.contains(&field.len()) && field.bytes().all(|b| b.is_ascii_digit())).then(|| field.parse::<i64>().ok())?
+    };
+    let (year, month, day) = (field(4..=4)?, field(1..=2)?, field(1..=2)?);
+    if year == 0 || !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) as i32)
+}
+
+// HH:MM[:SS[.ffffff]], of microseconds since midnight
+fn time_of_day(text: &str) -> Option<i64> {
+    let (time, fraction) = text.split_once('.').unwrap_or((text, ""));
+    let fields: Vec<_> = time.split(':').collect();
+    if !(2..=3).contains(&fields.len()) || fraction.len() > 6 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |i: usize, max: i64| {
+        let field = fields.get(i).copied().unwrap_or("0");
+        (field.len() <= 2 && field.bytes().all(|b| b.is_ascii_digit())).then(|| field.parse::<i64>().ok().filter(|n| *n <= max))?
+    };
+    let (hour, minute, second) = (field(0, 23)?, field(1, 59)?, field(2, 59)?);
+    let micros = format!("{:0<6}", fraction).parse::<i64>().ok()?;
+    Some(((hour * 60 + minute) * 60 + second) * 1_000_000 + micros)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 of a date, and back (see http://howardhinnant.github.io/date_algorithms.html).
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    (year_of_era + era * 400 + (month <= 2) as i64, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(0, parse_date("1970-01-01").unwrap());
+        assert_eq!(19782, parse_date(" 2024-02-29 ").unwrap());
+        assert_eq!(-719162, parse_date("0001-01-01").unwrap());
+        for days in [-719162, -1, 0, 59, 19782, 2932896] {
+            assert_eq!(days, parse_date(&format_date(days)).unwrap());
+        }
+        assert_eq!("9999-12-31", format_date(2932896));
+        for text in ["2023-02-29", "2024-13-01", "2024-1-1x", "24-01-01", "0000-01-01", ""] {
+            assert!(parse_date(text).is_err(), "{}", text);
+        }
+
+        assert_eq!(19782 * MICROS_PER_DAY + 49_500_500_000, parse_timestamp("2024-02-29 13:45:00.5").unwrap());
+        assert_eq!(parse_timestamp("2024-02-29T13:45").unwrap(), parse_timestamp("2024-02-29 13:45:00").unwrap());
+        assert_eq!(MICROS_PER_DAY, parse_timestamp("1970-01-02").unwrap());
+        assert_eq!("2024-02-29 13:45:00.5", format_timestamp(parse_timestamp("2024-02-29 13:45:00.500").unwrap()));
+        assert_eq!("1969-12-31 23:59:59.999999", format_timestamp(-1));
+        for text in ["2024-02-29 24:00", "2024-02-29 12", "2024-02-29 12:00:00.1234567", "2024-02-29 12:60"] {
+            assert!(parse_timestamp(text).is_err(), "{}", text);
+        }
+    }
+}
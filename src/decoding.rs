@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use crate::buffer::{self, BufferPoolManager};
+use crate::disk::PageId;
+use crate::tuple::{self, Tuple};
+use crate::wal::{self, Lsn, Record, Relation, TxId};
+
+// Logical decoding: the row changes logged with logical decoding turned on (see
+// `BufferPoolManager::set_logical`, or the `logical` key of the config) are read back from the log as the transactions which made
+// them, in the order they committed. The changes of a transaction are held until it ends, so
+// those of one which rolled back are never seen. Each comes with the name and columns its table
+// had when it was made, as they were logged with it, whatever has become of the table since.
+//
+// Each transaction comes with the position to resume from after it, which is where the oldest
+// transaction with changes still held began. A consumer keeps the log from being removed past the
+// position it would resume from with a slot of its own (see `BufferPoolManager::retain_log`), as
+// the connectors delivering them to sinks do (see `cdc`).
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error(transparent)]
+    Wal(#[from] wal::Error),
+    #[error(transparent)]
+    Tuple(#[from] tuple::Error),
+    #[error("the log before {0} has been removed")]
+    Removed(Lsn),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Insert,
+    Update,
+    Delete,
+}
+
+// A change to a row of the table with meta page `table`, of `relation` then if it was logged
+// with one, with the row before unless inserted and after unless deleted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub table: PageId,
+    pub relation: Option<Relation>,
+    pub operation: Operation,
+    pub old: Option<Tuple>,
+    pub new: Option<Tuple>,
+}
+
+// Where decoding resumes: the log is read from `restart`, and the transactions which committed
+// before `decoded` are skipped, having been decoded already.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub restart: Lsn,
+    pub decoded: Lsn,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transaction {
+    pub txid: TxId,
+    pub time: SystemTime,
+    pub changes: Vec<Change>,
+    // to resume from once this transaction has been handled
+    pub position: Position,
+}
+
+#[derive(Debug)]
+pub struct Decoder {
+    position: Position,
+    // where the next record is read from
+    next: Lsn,
+    // first LSN and changes of each transaction with changes which hasn't ended
+    pending: BTreeMap<TxId, (Lsn, Vec<Change>)>,
+}
+
+impl Decoder {
+    // Decodes the transactions committing from now on, including those running already.
+    pub fn start(bufmgr: &mut BufferPoolManager) -> Self {
+        let end = bufmgr.wal().map_or(0, |wal| wal.end());
+        let restart = bufmgr.oldest_lsn().unwrap_or(end);
+        Self::resume(Position { restart, decoded: end })
+    }
+
+    pub fn resume(position: Position) -> Self {
+        Self { position, next: position.restart, pending: BTreeMap::new() }
+    }
+
+    // Position to resume from after the transactions decoded so far.
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    // Decodes the next transaction which committed with changes, None if there's none yet.
+    pub fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Transaction>, Error> {
+        let Some(wal) = bufmgr.wal() else {
+            return Ok(None);
+        };
+        if self.next < wal.start() {
+            return Err(Error::Removed(self.next));
+        }
+        while let Some((record, next)) = wal.read(self.next)? {
+            self.next = next;
+            match record.record {
+                Record::Change { table, relation, old, new } => {
+                    let decode = |row: Option<Vec<u8>>| row.map(|row| tuple::decode(&row).map(|(row, _)| row)).transpose();
+                    let (old, new) = (decode(old)?, decode(new)?);
+                    let operation = match (&old, &new) {
+                        (None, _) => Operation::Insert,
+                        (_, None) => Operation::Delete,
+                        _ => Operation::Update,
+                    };
+                    let (_, changes) = self.pending.entry(record.txid).or_insert((record.lsn, vec![]));
+                    changes.push(Change { table, relation, operation, old, new });
+                }
+                Record::Commit { time } => {
+                    let Some((_, changes)) = self.pending.remove(&record.txid) else {
+                        continue;
+                    };
+                    if record.lsn < self.position.decoded {
+                        continue;
+                    }
+                    let restart = self.pending.values().map(|&(lsn, _)| lsn).min().unwrap_or(next);
+                    self.position = Position { restart, decoded: next };
+                    return Ok(Some(Transaction { txid: record.txid, time, changes, position: self.position }));
+                }
+                // the end of a transaction which has rolled back, if it hadn't committed
+                Record::Abort | Record::End => {
+                    self.pending.remove(&record.txid);
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::table::Table;
+    use crate::tuple::Value;
+    use crate::wal::{Wal, DEFAULT_SEGMENT_SIZE};
+    use tempfile::{tempdir, NamedTempFile};
+
+    #[test]
+    fn test() {
+        let (data_file, data_path) = NamedTempFile::new().unwrap().into_parts();
+        drop(data_file);
+        let wal_dir = tempdir().unwrap();
+        let open = || {
+            let disk = DiskManager::open(&data_path).unwrap();
+            let wal = Wal::open(wal_dir.path(), DEFAULT_SEGMENT_SIZE).unwrap();
+            BufferPoolManager::with_wal(disk, BufferPool::new(8), wal).unwrap()
+        };
+        let mut bufmgr = open();
+        bufmgr.set_logical(true).unwrap();
+        let relation = Relation { name: "t".to_string(), columns: vec!["id".to_string(), "name".to_string()] };
+        let table = Table { relation: Some(relation.clone()), ..Table::create(&mut bufmgr, 1).unwrap() };
+        let row = |id: i64, name: &str| vec![Value::Int(id), Value::Text(name.to_string())];
+        table.insert(&mut bufmgr, &row(1, "a")).unwrap();
+        bufmgr.commit().unwrap();
+
+        // a transaction running when decoding starts is decoded whole, unlike one committed already
+        table.insert(&mut bufmgr, &row(2, "b")).unwrap();
+        let mut decoder = Decoder::start(&mut bufmgr);
+        assert_eq!(None, decoder.next(&mut bufmgr).unwrap());
+        table.update(&mut bufmgr, &row(1, "c")).unwrap();
+        let running = bufmgr.switch(Default::default());
+        // one rolled back is never seen
+        table.insert(&mut bufmgr, &row(3, "d")).unwrap();
+        bufmgr.abort().unwrap();
+        table.insert(&mut bufmgr, &row(4, "e")).unwrap();
+        let other = bufmgr.switch(running);
+        bufmgr.commit().unwrap();
+        let table_id = table.btree.meta_page_id;
+        let first = decoder.next(&mut bufmgr).unwrap().unwrap();
+        let change = |operation, old, new| Change { table: table_id, relation: Some(relation.clone()), operation, old, new };
+        assert_eq!(
+            vec![
+                change(Operation::Insert, None, Some(row(2, "b"))),
+                change(Operation::Update, Some(row(1, "a")), Some(row(1, "c"))),
+            ],
+            first.changes
+        );
+        assert_eq!(None, decoder.next(&mut bufmgr).unwrap());
+        // the one still running began before, so it's resumed from there
+        assert!(first.position.restart < first.position.decoded);
+        bufmgr.retain_log("decoding", Some(first.position.restart)).unwrap();
+        bufmgr.switch(other);
+        assert!(table.delete(&mut bufmgr, &[Value::Int(4)]).unwrap());
+        bufmgr.commit().unwrap();
+        let second = decoder.next(&mut bufmgr).unwrap().unwrap();
+        assert_eq!(
+            vec![change(Operation::Insert, None, Some(row(4, "e"))), change(Operation::Delete, Some(row(4, "e")), None)],
+            second.changes
+        );
+        assert!(first.time <= second.time);
+
+        // resuming from the position of a transaction decodes those after it, across restarts,
+        // which logical logging stays turned on across
+        bufmgr.checkpoint().unwrap();
+        drop(bufmgr);
+        let mut bufmgr = open();
+        assert!(bufmgr.logical());
+        assert_eq!(Some(first.position.restart), bufmgr.wal().unwrap().slot("decoding"));
+        let mut decoder = Decoder::resume(first.position);
+        assert_eq!(Some(second.clone()), decoder.next(&mut bufmgr).unwrap());
+        assert_eq!(None, decoder.next(&mut bufmgr).unwrap());
+        let mut decoder = Decoder::resume(second.position);
+        assert_eq!(None, decoder.next(&mut bufmgr).unwrap());
+        bufmgr.retain_log("decoding", None).unwrap();
+        assert_eq!(None, bufmgr.wal().unwrap().slot("decoding"));
+        assert!(matches!(Decoder::resume(Position { restart: 1, decoded: 1 }).next(&mut bufmgr), Err(Error::Removed(1))));
+    }
+}
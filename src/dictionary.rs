@@ -0,0 +1,170 @@
+use crate::btree::{self, BTree, SearchMode};
+use crate::buffer::BufferPoolManager;
+use crate::disk::PageId;
+use crate::table::Error;
+use crate::tuple::{self, Value};
+
+// A table with dictionary columns keeps each text value written to them once, in a dictionary of
+// the table, and in the rows a code in its place, so that the rows of repetitive values take less
+// room, on disk and in the buffer pool. Rows are encoded as they're written and decoded as they're
+// read, so nothing above the table sees the codes. Values shorter than MIN_TEXT, which a code
+// would take as much room as, and those too long for an entry of the dictionary are left as they
+// are, as are the columns of the primary key, which can't be dictionary columns.
+//
+// Codes are never removed nor reused, so the entries of a value, added before any row with its
+// code is written, are left in place if the transaction aborts, as index entries are, and serve
+// every version of every row from then on.
+
+// The least length of a value to encode: a code takes 9 bytes in a row, text 5 and its bytes.
+pub const MIN_TEXT: usize = 5;
+
+// The key of the next code to give out in `values`, before those of the codes.
+const NEXT_CODE: &[u8] = b"";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dictionary {
+    pub columns: Vec<usize>,
+    // encoded value => code, 8 bytes little endian
+    pub codes: BTree,
+    // encoded code => the bytes of its value
+    pub values: BTree,
+}
+
+impl Dictionary {
+    pub fn create(bufmgr: &mut BufferPoolManager, columns: Vec<usize>) -> Result<Self, btree::Error> {
+        Ok(Self {
+            columns,
+            codes: BTree::create(bufmgr)?,
+            values: BTree::create(bufmgr)?,
+        })
+    }
+
+    // Replaces the values of the dictionary columns of `row` by their codes, giving codes to those
+    // without one yet. Codes are left as they are.
+    pub fn encode(&self, bufmgr: &mut BufferPoolManager, row: &mut [Value]) -> Result<(), Error> {
+        for &column in &self.columns {
+            let Some(Value::Text(text)) = row.get(column) else {
+                continue;
+            };
+            let mut key = vec![];
+            tuple::encode_key(std::slice::from_ref(&row[column]), &mut key);
+            if text.len() < MIN_TEXT || key.len() + 8 > btree::MAX_ENTRY_SIZE {
+                continue;
+            }
+            let code = match get(bufmgr, &self.codes, &key)? {
+                Some(code) => int(&code)?,
+                None => bufmgr.redo_only(|bufmgr| -> Result<_, Error> {
+                    let code = match get(bufmgr, &self.values, NEXT_CODE)? {
+                        Some(next) => int(&next)?,
+                        None => 0,
+                    };
+                    self.values.upsert(bufmgr, NEXT_CODE, &(code + 1).to_le_bytes())?;
+                    self.values.insert(bufmgr, &code_key(code), text.as_bytes())?;
+                    self.codes.insert(bufmgr, &key, &code.to_le_bytes())?;
+                    Ok(code)
+                })?,
+            };
+            row[column] = Value::Int(code);
+        }
+        Ok(())
+    }
+
+    // Replaces the codes in the dictionary columns of `row` by their values.
+    pub fn decode(&self, bufmgr: &mut BufferPoolManager, row: &mut [Value]) -> Result<(), Error> {
+        for &column in &self.columns {
+            let Some(Value::Int(code)) = row.get(column) else {
+                continue;
+            };
+            let text = get(bufmgr, &self.values, &code_key(*code))?.ok_or(tuple::Error::Malformed)?;
+            row[column] = Value::Text(String::from_utf8(text).map_err(|_| tuple::Error::Malformed)?);
+        }
+        Ok(())
+    }
+
+    pub fn clear(&self, bufmgr: &mut BufferPoolManager) -> Result<(), btree::Error> {
+        self.codes.clear(bufmgr)?;
+        self.values.clear(bufmgr)
+    }
+
+    pub fn pages(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<PageId>, btree::Error> {
+        let mut pages = vec![];
+        for btree in [&self.codes, &self.values] {
+            pages.push(btree.meta_page_id);
+            pages.extend(btree.pages(bufmgr)?);
+        }
+        Ok(pages)
+    }
+}
+
+fn code_key(code: i64) -> Vec<u8> {
+    let mut key = vec![];
+    tuple::encode_key(&[Value::Int(code)], &mut key);
+    key
+}
+
+fn get(bufmgr: &mut BufferPoolManager, btree: &BTree, key: &[u8]) -> Result<Option<Vec<u8>>, btree::Error> {
+    match btree.search(bufmgr, SearchMode::Key(key.to_vec()))?.next(bufmgr)? {
+        Some((found, value)) if found == key => Ok(Some(value)),
+        _ => Ok(None),
+    }
+}
+
+fn int(bytes: &[u8]) -> Result<i64, tuple::Error> {
+    Ok(i64::from_le_bytes(bytes.try_into().map_err(|_| tuple::Error::Malformed)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(8));
+        let dictionary = Dictionary::create(&mut bufmgr, vec![1, 2]).unwrap();
+        let text = |s: &str| Value::Text(s.to_string());
+        let row = vec![text("Kyoto"), text("Tokyo"), text("Japan"), Value::Int(3)];
+
+        // the first value to be written gets the first code, the primary key aside
+        let mut encoded = row.clone();
+        dictionary.encode(&mut bufmgr, &mut encoded).unwrap();
+        assert_eq!(vec![text("Kyoto"), Value::Int(0), Value::Int(1), Value::Int(3)], encoded);
+        let mut again = encoded.clone();
+        dictionary.encode(&mut bufmgr, &mut again).unwrap();
+        assert_eq!(encoded, again);
+        let mut other = vec![Value::Int(1), text("Japan"), text("Osaka"), Value::Null];
+        dictionary.encode(&mut bufmgr, &mut other).unwrap();
+        assert_eq!(vec![Value::Int(1), Value::Int(1), Value::Int(2), Value::Null], other);
+        // short values, NULLs and those too long for an entry are kept in the row
+        let long = "x".repeat(btree::MAX_ENTRY_SIZE);
+        let mut kept = vec![Value::Int(2), text("Nara"), Value::Null, text(&long)];
+        dictionary.encode(&mut bufmgr, &mut kept).unwrap();
+        assert_eq!(vec![Value::Int(2), text("Nara"), Value::Null, text(&long)], kept);
+        let mut kept = vec![Value::Int(2), text(&long), text("Nara")];
+        dictionary.encode(&mut bufmgr, &mut kept).unwrap();
+        assert_eq!(vec![Value::Int(2), text(&long), text("Nara")], kept);
+
+        dictionary.decode(&mut bufmgr, &mut encoded).unwrap();
+        assert_eq!(row, encoded);
+        dictionary.decode(&mut bufmgr, &mut encoded).unwrap();
+        assert_eq!(row, encoded);
+        dictionary.decode(&mut bufmgr, &mut other).unwrap();
+        assert_eq!(vec![Value::Int(1), text("Japan"), text("Osaka"), Value::Null], other);
+        assert!(dictionary.decode(&mut bufmgr, &mut [Value::Null, Value::Int(7)]).is_err());
+
+        // many values spill onto pages of their own, as they're looked up
+        for i in 0..2000 {
+            let mut row = vec![Value::Int(i), text(&format!("city {}", i % 500)), Value::Null];
+            dictionary.encode(&mut bufmgr, &mut row).unwrap();
+            assert_eq!(Value::Int(3 + i % 500), row[1]);
+            dictionary.decode(&mut bufmgr, &mut row).unwrap();
+            assert_eq!(text(&format!("city {}", i % 500)), row[1]);
+        }
+        assert!(dictionary.pages(&mut bufmgr).unwrap().len() > 4);
+        dictionary.clear(&mut bufmgr).unwrap();
+        assert_eq!(4, dictionary.pages(&mut bufmgr).unwrap().len());
+    }
+}
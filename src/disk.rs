@@ -1,40 +1,92 @@
 use std::fs::File;
-use std::io::{self, Read, Write};
+use std::io;
 use std::path::Path;
-use std::io::Seek;
+use std::sync::Arc;
+
+use crate::storage::{self, FileSystem, OsFile, ReadAt, StorageBackend, StorageFile};
+use crate::trace;
 
 pub const PAGE_SIZE: u64 = 4096;
 
+// The last 4 bytes of each page written hold a checksum of the rest of it and of its id, set as
+// it's written, by which a page corrupted on disk, torn by a crash or written in the place of
+// another is told apart once read (see `verify_page`).
+pub const CHECKSUM_OFFSET: usize = PAGE_SIZE as usize - 4;
+
 // #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, FromBytes, AsBytes)]
 // #[repr(C)]
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct PageId(pub u64);
 
+impl PageId {
+    // Page id of buffers which hold no page yet.
+    // This must not be a real page id, otherwise evicting an unused buffer drops the real page from the page table.
+    pub const INVALID_PAGE_ID: PageId = PageId(u64::MAX);
+    // Set in the ids of the pages of temporary tables, which are numbered from 0 in a file of
+    // their own (see `BufferPoolManager::creating_temp_pages`).
+    pub const TEMP_BIT: u64 = 1 << 62;
+    // Set in the ids of the pages of in-memory tables, which are in no file (see
+    // `BufferPoolManager::creating_memory_pages`), along with `UNLOGGED_BIT` for unlogged ones.
+    pub const MEMORY_BIT: u64 = 1 << 61;
+    pub const UNLOGGED_BIT: u64 = 1 << 60;
+
+    pub fn is_temp(self) -> bool {
+        self.0 & Self::TEMP_BIT != 0 && self != Self::INVALID_PAGE_ID
+    }
+
+    pub fn is_memory(self) -> bool {
+        self.0 & Self::MEMORY_BIT != 0 && self != Self::INVALID_PAGE_ID
+    }
+
+    // Whether changes to the page are logged, which those of temporary and unlogged tables aren't.
+    pub fn is_logged(self) -> bool {
+        !(self.is_temp() || self.is_memory() && self.0 & Self::UNLOGGED_BIT != 0)
+    }
+
+    // Whether the page is of the data file, rather than of temporary or in-memory tables.
+    pub fn in_data_file(self) -> bool {
+        !self.is_temp() && !self.is_memory()
+    }
+}
+
+impl Default for PageId {
+    fn default() -> Self {
+        Self::INVALID_PAGE_ID
+    }
+}
+
 pub struct DiskManager{
     // ヒープファイルのファイルディスクリプタ
-    heap_file: File,
+    heap_file: Box<dyn StorageFile>,
     // 採番するページIDを決めるカウンタ
     next_page_id: u64,
+    // of the file, for other threads, if it can be read from them
+    reader: Option<Arc<dyn ReadAt>>,
 }
 
 impl DiskManager{
     pub fn new(data_file: File) -> io::Result<Self>  {
-        let size = data_file.metadata()?.len();
+        Self::with_file(Box::new(OsFile(data_file)))
+    }
 
-        if size % PAGE_SIZE != 0 {
-            return Err(io::Error::new(io::ErrorKind::Other, "unexpected file size"))
-        }
+    pub fn with_file(data_file: Box<dyn StorageFile>) -> io::Result<Self> {
+        let size = data_file.size()?;
 
+        // a page torn by a crash while it was being added is there only in part, which reads as
+        // zeros until recovery writes it
         Ok(Self {
+            reader: data_file.reader(),
             heap_file: data_file,
-            next_page_id: size / PAGE_SIZE,
+            next_page_id: size.div_ceil(PAGE_SIZE),
         })
     }
 
     pub fn open(data_file_path: impl AsRef<Path>) -> io::Result<Self> {
-        let heap_file = std::fs::OpenOptions::new().read(true).write(true).create(true).open(data_file_path)?;
+        Self::open_in(&FileSystem, data_file_path)
+    }
 
-        Self::new(heap_file)
+    pub fn open_in(storage: &dyn StorageBackend, data_file_path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::with_file(storage.open(data_file_path.as_ref(), true)?)
     }
 
     pub fn allocate_page(&mut self) -> PageId {
@@ -44,25 +96,88 @@ impl DiskManager{
         PageId(page_id)
     }
 
+    // Pages allocated but never written, e.g. before a crash, read as zeros.
     pub fn read_page_data(&mut self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
+        trace::event!(Trace, "read", page_id = page_id.0);
         let offset = page_id.0 * PAGE_SIZE;
 
-        self.heap_file.seek(std::io::SeekFrom::Start(offset))?;
-        self.heap_file.read_exact(data)?;
+        match storage::read_exact_at(&mut *self.heap_file, offset, data) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && page_id.0 < self.next_page_id => data.fill(0),
+            result => result?,
+        }
 
         Ok(())
     }
 
+    pub fn num_pages(&self) -> u64 {
+        self.next_page_id
+    }
+
+    // Reads the pages written so far from other threads, as `read_page_data` does, if the file
+    // can be read from them.
+    pub fn reader(&self) -> Option<PageReader> {
+        Some(PageReader { file: self.reader.clone()?, num_pages: self.next_page_id })
+    }
+
+    // Makes sure `page_id` is never allocated again, e.g. when recovery finds it in the log
+    // although it was never written to the file.
+    pub fn mark_allocated(&mut self, page_id: PageId) {
+        self.next_page_id = self.next_page_id.max(page_id.0 + 1);
+    }
+
+    // Cuts the file to its first `num_pages` pages, those after being allocated anew.
+    pub fn truncate(&mut self, num_pages: u64) -> io::Result<()> {
+        self.heap_file.set_len(num_pages * PAGE_SIZE)?;
+        self.next_page_id = num_pages;
+        self.heap_file.sync()
+    }
+
+    // Writes the page with its checksum in its last bytes, whatever they were.
     pub fn write_page_data(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
+        trace::event!(Trace, "write", page_id = page_id.0);
         let offset = page_id.0 * PAGE_SIZE;
 
-        self.heap_file.seek(std::io::SeekFrom::Start(offset))?;
-        self.heap_file.write_all(data)?;
+        let mut page = [0; PAGE_SIZE as usize];
+        page.copy_from_slice(data);
+        let checksum = page_checksum(page_id, &page);
+        page[CHECKSUM_OFFSET..].copy_from_slice(&checksum.to_le_bytes());
+        self.heap_file.write_at(offset, &page)
+    }
+
+    // ヒープファイルへの書き込みをディスクに永続化する
+    pub fn sync(&mut self) -> io::Result<()> {
+        let _span = trace::span!(Debug, "sync");
+        self.heap_file.sync()
+    }
+}
 
+// Reads the pages of a data file from any thread (see `DiskManager::reader`).
+pub struct PageReader {
+    file: Arc<dyn ReadAt>,
+    num_pages: u64,
+}
+
+impl PageReader {
+    pub fn read_page_data(&self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
+        match storage::fill(|offset, buf| self.file.read_at(offset, buf), page_id.0 * PAGE_SIZE, data) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && page_id.0 < self.num_pages => data.fill(0),
+            result => result?,
+        }
         Ok(())
     }
 }
 
+// FNV-1a of the id and the page up to its checksum.
+pub fn page_checksum(page_id: PageId, page: &[u8]) -> u32 {
+    page_id.0.to_le_bytes().iter().chain(&page[..CHECKSUM_OFFSET]).fold(0x811c9dc5, |hash, &b| (hash ^ b as u32).wrapping_mul(0x01000193))
+}
+
+// Whether the page read as `page_id` is as it was written, or all zeros, never having been.
+pub fn verify_page(page_id: PageId, page: &[u8]) -> bool {
+    let checksum = u32::from_le_bytes(page[CHECKSUM_OFFSET..].try_into().unwrap());
+    checksum == page_checksum(page_id, page) || page.iter().all(|&b| b == 0)
+}
+
 // Copied from https://github.com/KOBA789/relly/blob/3b1e656b7ae67ba2ddde2ba7d2748816b4792d1e/src/disk.rs#L96-L123
 #[cfg(test)]
 mod tests {
@@ -89,8 +204,22 @@ mod tests {
         let mut disk2 = DiskManager::open(&data_file_path).unwrap();
         let mut buf = vec![0; page_size];
         disk2.read_page_data(hello_page_id, &mut buf).unwrap();
-        assert_eq!(hello, buf);
+        assert_eq!(hello[..CHECKSUM_OFFSET], buf[..CHECKSUM_OFFSET]);
+        assert!(verify_page(hello_page_id, &buf));
+        // not as the page of another id
+        assert!(!verify_page(world_page_id, &buf));
         disk2.read_page_data(world_page_id, &mut buf).unwrap();
-        assert_eq!(world, buf);
+        assert_eq!(world[..CHECKSUM_OFFSET], buf[..CHECKSUM_OFFSET]);
+        buf[7] ^= 1;
+        assert!(!verify_page(world_page_id, &buf));
+        drop(disk2);
+
+        let file = std::fs::OpenOptions::new().write(true).open(&data_file_path).unwrap();
+        file.set_len(PAGE_SIZE * 2 + 100).unwrap();
+        let mut disk3 = DiskManager::open(&data_file_path).unwrap();
+        assert_eq!(3, disk3.num_pages());
+        disk3.read_page_data(PageId(2), &mut buf).unwrap();
+        assert_eq!(vec![0; page_size], buf);
+        assert!(verify_page(PageId(2), &buf));
     }
 }
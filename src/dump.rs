@@ -0,0 +1,603 @@
+use std::collections::HashSet;
+use std::io::{self, BufRead, Read, Write};
+
+use crate::catalog::{TableInfo, ViewInfo};
+use crate::database::{Database, Session};
+use crate::datetime;
+use crate::json;
+use crate::lz4;
+use crate::partition::PartitionBound;
+use crate::planner;
+use crate::sql::{self, ast, quote_ident, RowStream};
+use crate::table::IndexKind;
+use crate::trigger::{Action, TriggerInfo};
+use crate::tuple::{self, DataType, ElementType, Tuple, Value};
+
+// Logical backups: the statements creating the tables, indexes and views of a database and the
+// rows of its tables, as of a single snapshot, which `restore` runs into another database, where
+// a physical backup only restores into one of the same version and page size. The tables are
+// created and loaded first, then indexed, which is quicker than indexing the rows as they're
+// loaded, and the views created last, each after those it selects from.
+//
+// A dump is SQL, a statement a line before any line breaks of its text, the rows as INSERTs of
+// ROWS_PER_INSERT rows, so the shell can `.read` it too, or an archive of records
+//   [kind: u8][len: u32][checksum: u32][body: len bytes]
+// after MAGIC and VERSION, of a statement, a block of rows, or the end, a block being
+//   [table len: u32][table][rows: u32][encoded len: u32][lz4 of the rows, tuple-encoded]
+// of round BLOCK_SIZE bytes of rows, which are loaded in bulk. The checksum is FNV-1a of the body.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Sql(#[from] sql::Error),
+    #[error("corrupt archive: {0}")]
+    Corrupt(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    Sql,
+    Archive,
+}
+
+const MAGIC: &[u8; 8] = b"BRDBDUMP";
+const VERSION: u32 = 1;
+const ROWS_PER_INSERT: usize = 1000;
+const BLOCK_SIZE: usize = 1 << 20;
+
+const STATEMENT: u8 = 1;
+const ROWS: u8 = 2;
+const END: u8 = 3;
+
+// Writes a dump of `db` to `output`, read in a transaction of its own, so of one snapshot.
+pub fn dump(db: &Database, output: impl Write, format: DumpFormat) -> Result<(), Error> {
+    let mut session = db.session();
+    session.execute("BEGIN ISOLATION LEVEL REPEATABLE READ READ ONLY")?;
+    let result = dump_snapshot(db, &mut session, output, format);
+    let end = session.execute(if result.is_ok() { "COMMIT" } else { "ROLLBACK" });
+    result?;
+    end?;
+    Ok(())
+}
+
+fn dump_snapshot(db: &Database, session: &mut Session, output: impl Write, format: DumpFormat) -> Result<(), Error> {
+    let (tables, views, triggers) = db.with_engine(|_, catalog| {
+        // partitions after the tables they're of, whose rows they hold
+        let mut tables: Vec<_> = catalog.tables().collect();
+        tables.sort_by_key(|info| info.partition.is_some());
+        let tables: Vec<_> = tables.into_iter().map(|info| (info.name.clone(), create_table(info), create_indexes(info), info.partitioning.is_some())).collect();
+        (tables, views_in_order(catalog.views()), create_triggers(catalog.triggers()))
+    });
+    let mut writer = Writer { output, format };
+    writer.start()?;
+    for (_, create, _, _) in &tables {
+        writer.statement(create)?;
+    }
+    for (name, _, _, _) in tables.iter().filter(|(_, _, _, partitioned)| !partitioned) {
+        let select = session.prepare(&format!("SELECT * FROM {}", quote_ident(name)))?;
+        session.stream(&select, &[], |rows| writer.rows(name, rows))?;
+    }
+    // triggers last, so as not to run for the rows restored
+    for statement in tables.iter().flat_map(|(_, _, indexes, _)| indexes).chain(&views).chain(&triggers) {
+        writer.statement(statement)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+// The statement creating the table.
+pub fn create_table(info: &TableInfo) -> String {
+    if let Some(partition) = &info.partition {
+        let bound = match &partition.bound {
+            PartitionBound::Range { lower, upper } => {
+                let lower = lower.as_ref().map_or("MINVALUE".to_string(), literal);
+                format!("FROM ({}) TO ({})", lower, upper.as_ref().map_or("MAXVALUE".to_string(), literal))
+            }
+            PartitionBound::Hash { modulus, remainder } => format!("WITH (MODULUS {}, REMAINDER {})", modulus, remainder),
+        };
+        return format!("CREATE TABLE {} PARTITION OF {} FOR VALUES {}", quote_ident(&info.name), quote_ident(&partition.parent), bound);
+    }
+    let mut defs: Vec<_> = info.columns.iter().map(|column| format!("{} {}", quote_ident(&column.name), type_name(column.data_type))).collect();
+    let key: Vec<_> = info.columns[..info.table.num_key_elems].iter().map(|column| quote_ident(&column.name)).collect();
+    defs.push(format!("PRIMARY KEY ({})", key.join(", ")));
+    let unlogged = if info.table.is_logged() { "" } else { "UNLOGGED " };
+    let create = format!("CREATE {}TABLE {} ({})", unlogged, quote_ident(&info.name), defs.join(", "));
+    let create = match &info.partitioning {
+        Some(partitioning) => format!("{} PARTITION BY {} ({})", create, partitioning.strategy, quote_ident(&info.columns[partitioning.column].name)),
+        None if info.table.in_memory() => format!("{} USING memory", create),
+        None if info.table.columnar.is_some() => format!("{} USING columnar", create),
+        None => create,
+    };
+    let create = match info.table.ttl {
+        Some(ttl) => format!("{} TTL {} + {}", create, quote_ident(&info.columns[ttl.column].name), ttl.seconds),
+        None => create,
+    };
+    match &info.table.dictionary {
+        Some(dictionary) => {
+            let columns: Vec<_> = dictionary.columns.iter().map(|&i| quote_ident(&info.columns[i].name)).collect();
+            format!("{} DICTIONARY ({})", create, columns.join(", "))
+        }
+        None => create,
+    }
+}
+
+// The statements creating the indexes of the table.
+pub fn create_indexes(info: &TableInfo) -> Vec<String> {
+    info.index_names
+        .iter()
+        .zip(&info.table.indexes)
+        .map(|(name, index)| {
+            let columns: Vec<_> = index
+                .columns
+                .iter()
+                .zip(&index.paths)
+                .map(|(&i, path)| match path {
+                    Some(path) => format!("({} {})", quote_ident(&info.columns[i].name), path),
+                    None => quote_ident(&info.columns[i].name),
+                })
+                .collect();
+            let using = match index.kind {
+                IndexKind::BTree => "",
+                IndexKind::FullText => " USING fulltext",
+                IndexKind::RTree => " USING rtree",
+                IndexKind::ZoneMap => " USING zonemap",
+            };
+            format!("CREATE INDEX {} ON {}{} ({})", quote_ident(name), quote_ident(&info.name), using, columns.join(", "))
+        })
+        .collect()
+}
+
+// The statement creating the view, naming its columns as they were named.
+pub fn create_view(view: &ViewInfo) -> String {
+    let columns: Vec<_> = view.columns.iter().map(|name| quote_ident(name)).collect();
+    format!("CREATE VIEW {} ({}) AS {}", quote_ident(&view.name), columns.join(", "), view.sql)
+}
+
+// The statements creating the triggers of CREATE TRIGGER, those of callbacks being for the
+// application to register again.
+pub fn create_triggers<'a>(triggers: impl Iterator<Item = &'a TriggerInfo>) -> Vec<String> {
+    triggers
+        .filter_map(|info| match &info.action {
+            Action::Sql(sql) => Some(sql.clone()),
+            Action::Callback(_) => None,
+        })
+        .collect()
+}
+
+pub fn type_name(data_type: DataType) -> &'static str {
+    match data_type {
+        DataType::Integer => "INTEGER",
+        DataType::Text => "TEXT",
+        DataType::Boolean => "BOOLEAN",
+        DataType::Json => "JSON",
+        DataType::Array(ElementType::Integer) => "INTEGER[]",
+        DataType::Array(ElementType::Text) => "TEXT[]",
+        DataType::Array(ElementType::Boolean) => "BOOLEAN[]",
+        DataType::Point => "POINT",
+        DataType::Box => "BOX",
+        DataType::Float => "DOUBLE PRECISION",
+        DataType::Numeric => "NUMERIC",
+        DataType::Date => "DATE",
+        DataType::Timestamp => "TIMESTAMP",
+    }
+}
+
+// The statements creating the views, each after the views it selects from.
+fn views_in_order<'a>(views: impl Iterator<Item = &'a ViewInfo>) -> Vec<String> {
+    let mut pending: Vec<_> = views
+        .map(|view| {
+            let query = match sql::parse(&view.sql).ok().as_deref() {
+                Some([ast::Statement::Select(query)]) => Some(query.clone()),
+                _ => None,
+            };
+            (view, query)
+        })
+        .collect();
+    let mut created = HashSet::new();
+    let mut statements = vec![];
+    while !pending.is_empty() {
+        let before = pending.len();
+        let names: Vec<_> = pending.iter().map(|(view, _)| view.name.clone()).collect();
+        pending.retain(|(view, query)| {
+            let ready = query.as_ref().is_none_or(|query| {
+                names.iter().all(|name| *name == view.name || created.contains(name) || planner::references(query, name) == 0)
+            });
+            if ready {
+                statements.push(create_view(view));
+                created.insert(view.name.clone());
+            }
+            !ready
+        });
+        // of views selecting from one another, which CREATE VIEW can't make, rather than loop
+        if pending.len() == before {
+            statements.extend(pending.drain(..).map(|(view, _)| create_view(view)));
+        }
+    }
+    statements
+}
+
+// The value as a literal of SQL which reads back as it.
+pub fn literal(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        // of which the magnitude is out of range of a literal
+        Value::Int(i64::MIN) => format!("({} - 1)", i64::MIN + 1),
+        Value::Int(n) => n.to_string(),
+        Value::Text(s) => format!("'{}'", s.replace('\'', "''")),
+        Value::Bool(b) => if *b { "TRUE" } else { "FALSE" }.to_string(),
+        Value::Json(bytes) => format!("'{}'::JSON", json::to_string(bytes).replace('\'', "''")),
+        Value::Array(values) => format!("ARRAY[{}]", values.iter().map(literal).collect::<Vec<_>>().join(", ")),
+        Value::Point(point) => format!("'{}'::POINT", point),
+        Value::Box(rect) => format!("'{}'::BOX", rect),
+        Value::Float(f) => format!("'{}'::DOUBLE PRECISION", f),
+        Value::Numeric(n) => format!("'{}'::NUMERIC", n),
+        Value::Date(days) => format!("'{}'::DATE", datetime::format_date(*days)),
+        Value::Timestamp(micros) => format!("'{}'::TIMESTAMP", datetime::format_timestamp(*micros)),
+    }
+}
+
+fn checksum(body: &[u8]) -> u32 {
+    body.iter().fold(0x811c9dc5, |hash, &b| (hash ^ b as u32).wrapping_mul(0x01000193))
+}
+
+struct Writer<W> {
+    output: W,
+    format: DumpFormat,
+}
+
+impl<W: Write> Writer<W> {
+    fn start(&mut self) -> io::Result<()> {
+        match self.format {
+            DumpFormat::Sql => writeln!(self.output, "-- beyond_rdb dump"),
+            DumpFormat::Archive => {
+                self.output.write_all(MAGIC)?;
+                self.output.write_all(&VERSION.to_le_bytes())
+            }
+        }
+    }
+
+    fn statement(&mut self, sql: &str) -> io::Result<()> {
+        match self.format {
+            DumpFormat::Sql => writeln!(self.output, "{};", sql),
+            DumpFormat::Archive => self.record(STATEMENT, sql.as_bytes()),
+        }
+    }
+
+    fn rows(&mut self, table: &str, rows: RowStream<'_>) -> Result<(), sql::Error> {
+        let mut values: Vec<String> = vec![];
+        let mut block = vec![];
+        let mut count = 0;
+        for row in rows {
+            let row = row?;
+            match self.format {
+                DumpFormat::Sql => {
+                    let literals: Vec<_> = row.iter().map(literal).collect();
+                    values.push(format!("({})", literals.join(", ")));
+                    if values.len() == ROWS_PER_INSERT {
+                        self.insert(table, &mut values)?;
+                    }
+                }
+                DumpFormat::Archive => {
+                    tuple::encode(&row, &mut block);
+                    count += 1;
+                    if block.len() >= BLOCK_SIZE {
+                        self.block(table, &mut block, &mut count)?;
+                    }
+                }
+            }
+        }
+        if !values.is_empty() {
+            self.insert(table, &mut values)?;
+        }
+        if count > 0 {
+            self.block(table, &mut block, &mut count)?;
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, table: &str, values: &mut Vec<String>) -> io::Result<()> {
+        let insert = format!("INSERT INTO {} VALUES {}", quote_ident(table), values.join(", "));
+        values.clear();
+        self.statement(&insert)
+    }
+
+    fn block(&mut self, table: &str, block: &mut Vec<u8>, count: &mut u32) -> io::Result<()> {
+        let mut body = vec![];
+        body.extend_from_slice(&(table.len() as u32).to_le_bytes());
+        body.extend_from_slice(table.as_bytes());
+        body.extend_from_slice(&count.to_le_bytes());
+        body.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        body.extend_from_slice(&lz4::compress(block));
+        block.clear();
+        *count = 0;
+        self.record(ROWS, &body)
+    }
+
+    fn record(&mut self, kind: u8, body: &[u8]) -> io::Result<()> {
+        self.output.write_all(&[kind])?;
+        self.output.write_all(&(body.len() as u32).to_le_bytes())?;
+        self.output.write_all(&checksum(body).to_le_bytes())?;
+        self.output.write_all(body)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.format == DumpFormat::Archive {
+            self.record(END, &[])?;
+        }
+        self.output.flush()
+    }
+}
+
+// Runs a dump read from `input`, SQL or an archive, which it tells apart, into `db`, in a
+// transaction of its own, so all of it or, after an error, none of it.
+pub fn restore(db: &Database, mut input: impl BufRead) -> Result<(), Error> {
+    let mut session = db.session();
+    session.execute("BEGIN")?;
+    let result = if input.fill_buf()?.starts_with(MAGIC) {
+        restore_archive(&mut session, input)
+    } else {
+        restore_sql(&mut session, input)
+    };
+    let end = session.execute(if result.is_ok() { "COMMIT" } else { "ROLLBACK" });
+    result?;
+    end?;
+    Ok(())
+}
+
+fn restore_sql(session: &mut Session, mut input: impl BufRead) -> Result<(), Error> {
+    let mut statement = String::new();
+    while read_statement(&mut input, &mut statement)? {
+        session.execute(&statement)?;
+    }
+    Ok(())
+}
+
+// Reads lines up to one which ends a statement, with `;` outside quotes, into `statement`,
+// without the comments. Returns false at the end of `input` if there's no statement left.
+fn read_statement(input: &mut impl BufRead, statement: &mut String) -> io::Result<bool> {
+    statement.clear();
+    let mut quote = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(!statement.trim().is_empty());
+        }
+        let mut end = line.len();
+        let mut prev = None;
+        for (i, c) in line.char_indices() {
+            match quote {
+                Some(q) if c == q => quote = None,
+                Some(_) => {}
+                None if c == '\'' || c == '"' => quote = Some(c),
+                None if c == '-' && prev == Some('-') => {
+                    end = i - 1;
+                    break;
+                }
+                None => {}
+            }
+            prev = Some(c);
+        }
+        statement.push_str(&line[..end]);
+        if end < line.len() {
+            statement.push('\n');
+        }
+        if quote.is_none() && statement.trim_end().ends_with(';') {
+            return Ok(true);
+        }
+    }
+}
+
+fn restore_archive(session: &mut Session, mut input: impl Read) -> Result<(), Error> {
+    let mut header = [0; 12];
+    read_exact(&mut input, &mut header)?;
+    let version = u32::from_le_bytes(header[8..].try_into().unwrap());
+    if version != VERSION {
+        return Err(Error::Corrupt(format!("unknown version {}", version)));
+    }
+    loop {
+        let mut head = [0; 9];
+        read_exact(&mut input, &mut head)?;
+        let len = u32::from_le_bytes(head[1..5].try_into().unwrap()) as u64;
+        let mut body = vec![];
+        input.by_ref().take(len).read_to_end(&mut body)?;
+        if body.len() as u64 != len {
+            return Err(Error::Corrupt("truncated".to_string()));
+        }
+        if checksum(&body) != u32::from_le_bytes(head[5..].try_into().unwrap()) {
+            return Err(Error::Corrupt("checksum mismatch".to_string()));
+        }
+        match head[0] {
+            STATEMENT => {
+                let statement = std::str::from_utf8(&body).map_err(|_| Error::Corrupt("statement not UTF-8".to_string()))?;
+                session.execute(statement)?;
+            }
+            ROWS => {
+                let (table, rows) = decode_block(&body)?;
+                let mut rows = rows.into_iter();
+                session.load(&table, || Ok(rows.next()), None)?;
+            }
+            END => return Ok(()),
+            kind => return Err(Error::Corrupt(format!("unknown record kind {}", kind))),
+        }
+    }
+}
+
+fn read_exact(input: &mut impl Read, buf: &mut [u8]) -> Result<(), Error> {
+    input.read_exact(buf).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => Error::Corrupt("truncated".to_string()),
+        _ => Error::Io(err),
+    })
+}
+
+fn decode_block(mut body: &[u8]) -> Result<(String, Vec<Tuple>), Error> {
+    let malformed = || Error::Corrupt("malformed block of rows".to_string());
+    let mut take = |n: usize| {
+        let (taken, rest) = body.split_at_checked(n).ok_or_else(malformed)?;
+        body = rest;
+        Ok::<_, Error>(taken)
+    };
+    let len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+    let table = String::from_utf8(take(len)?.to_vec()).map_err(|_| malformed())?;
+    let count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+    let len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+    let block = lz4::decompress(body, len).ok_or_else(malformed)?;
+    let mut rows = vec![];
+    let mut offset = 0;
+    for _ in 0..count {
+        let (row, read) = tuple::decode(block.get(offset..).ok_or_else(malformed)?).map_err(|_| malformed())?;
+        rows.push(row);
+        offset += read;
+    }
+    if offset != block.len() {
+        return Err(malformed());
+    }
+    Ok((table, rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::QueryResult;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path().join("source"), 64).unwrap();
+        let mut session = db.session();
+        session
+            .execute(
+                "CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT, flag BOOLEAN);
+                 CREATE TABLE \"Order\" (\"select\" INTEGER, \"a b\" TEXT, PRIMARY KEY (\"select\", \"a b\"));
+                 CREATE TABLE empty (id INTEGER PRIMARY KEY);
+                 CREATE INDEX t_name ON t (name);
+                 CREATE INDEX t_words ON t USING fulltext (name);
+                 CREATE TABLE places (id INTEGER PRIMARY KEY, at POINT, area BOX);
+                 CREATE INDEX places_at ON places USING rtree (at);
+                 INSERT INTO places VALUES (1, '(1,-2)', '((0,0),(3,4))');
+                 CREATE TRIGGER places_new AFTER INSERT ON places BEGIN INSERT INTO empty VALUES (new.id); END;
+                 CREATE INDEX \"Order by\" ON \"Order\" (\"a b\");
+                 CREATE VIEW b AS SELECT id, name FROM t WHERE flag;
+                 CREATE VIEW a (n) AS SELECT name FROM b WHERE id > 0;
+                 INSERT INTO \"Order\" VALUES (1, 'x'), (1, 'y');
+                 CREATE TABLE m (id INTEGER PRIMARY KEY, name TEXT) PARTITION BY RANGE (id);
+                 CREATE TABLE m_low PARTITION OF m FOR VALUES FROM (MINVALUE) TO (10);
+                 CREATE TABLE m_high PARTITION OF m FOR VALUES FROM (10) TO (MAXVALUE);
+                 CREATE TABLE h (name TEXT PRIMARY KEY) PARTITION BY HASH (name);
+                 CREATE TABLE a_h PARTITION OF h FOR VALUES WITH (MODULUS 2, REMAINDER 0);
+                 CREATE TABLE h_1 PARTITION OF h FOR VALUES WITH (MODULUS 2, REMAINDER 1);
+                 INSERT INTO m VALUES (1, 'a'), (10, 'b'), (20, 'c');
+                 INSERT INTO h VALUES ('a'), ('b'), ('c');
+                 CREATE TABLE hot (id INTEGER PRIMARY KEY, n INTEGER) USING memory;
+                 CREATE UNLOGGED TABLE scratch (id INTEGER PRIMARY KEY) USING memory;
+                 CREATE INDEX hot_n ON hot (n);
+                 INSERT INTO hot VALUES (1, 2), (2, 4);
+                 INSERT INTO scratch VALUES (1);
+                 CREATE TABLE facts (id INTEGER PRIMARY KEY, kind TEXT) USING columnar;
+                 INSERT INTO facts VALUES (1, 'a'), (2, 'b');
+                 CREATE TABLE visits (id INTEGER PRIMARY KEY, at INTEGER, page TEXT) TTL at + 86400 DICTIONARY (page);
+                 INSERT INTO visits VALUES (1, 0, '/index'), (2, 4102444800, '/index'), (3, NULL, NULL), (4, NULL, '/');
+                 CREATE TABLE docs (id INTEGER PRIMARY KEY, doc JSON);
+                 CREATE INDEX docs_kind ON docs (id, (doc #>> '{kind,it''s}'));
+                 INSERT INTO docs VALUES (1, '{\"kind\": {\"it''s\": \"a\"}, \"n\": [1, 2.5, null]}'), (2, NULL);",
+            )
+            .unwrap();
+        session.execute("VACUUM facts").unwrap();
+        session.execute("INSERT INTO facts VALUES (3, 'a')").unwrap();
+        let texts = ["plain", "it's \"quoted\"", "two\nlines; -- not a comment", "", "back\\slash", "ünïcode"];
+        let mut values = vec![];
+        for i in 0..2500i64 {
+            let id = if i == 0 { i64::MIN } else { i * 7 - 1000 };
+            let name = if i % 9 == 0 { "NULL".to_string() } else { literal(&Value::Text(texts[i as usize % texts.len()].to_string())) };
+            values.push(format!("({}, {}, {})", literal(&Value::Int(id)), name, if i % 2 == 0 { "TRUE" } else { "FALSE" }));
+        }
+        session.execute(&format!("INSERT INTO t VALUES {}", values.join(", "))).unwrap();
+
+        let contents = |db: &Database| {
+            let mut session = db.session();
+            let queries = [
+                "SELECT * FROM t",
+                "SELECT * FROM \"Order\"",
+                "SELECT * FROM empty",
+                "SELECT * FROM a",
+                "SELECT * FROM m",
+                "SELECT * FROM m_high",
+                "SELECT * FROM h",
+                "SELECT * FROM hot",
+                "SELECT * FROM scratch",
+                "SELECT COUNT(*) FROM facts WHERE kind = 'a'",
+                "SELECT kind FROM facts WHERE id = 2",
+                "SELECT * FROM visits",
+                "SELECT * FROM docs",
+                "EXPLAIN SELECT id FROM docs WHERE id = 1 AND doc #>> '{kind,it''s}' = 'a'",
+                "SELECT id FROM t WHERE name = 'plain'",
+                "EXPLAIN SELECT id FROM t WHERE name = 'plain'",
+                "EXPLAIN SELECT id FROM t WHERE name @@ 'Quoted'",
+            ];
+            queries.map(|query| session.execute(query).unwrap().remove(0))
+        };
+        let schema = |db: &Database| {
+            db.with_engine(|_, catalog| {
+                let mut statements: Vec<_> = catalog.tables().flat_map(|info| [vec![create_table(info)], create_indexes(info)].concat()).collect();
+                statements.extend(catalog.views().map(create_view));
+                statements.extend(create_triggers(catalog.triggers()));
+                statements
+            })
+        };
+        let expected = contents(&db);
+        assert!(matches!(&expected[0], QueryResult::Rows { rows, .. } if rows.len() == 2500 && rows[0][0] == Value::Int(i64::MIN)));
+
+        let mut sql_len = 0;
+        for (i, format) in [DumpFormat::Sql, DumpFormat::Archive].into_iter().enumerate() {
+            let mut output = vec![];
+            dump(&db, &mut output, format).unwrap();
+            let target = Database::open(dir.path().join(format!("target{}", i)), 64).unwrap();
+            restore(&target, &output[..]).unwrap();
+            assert_eq!(schema(&db), schema(&target));
+            assert_eq!(expected, contents(&target));
+            if format == DumpFormat::Sql {
+                sql_len = output.len();
+                let output = String::from_utf8(output).unwrap();
+                assert!(output.contains("CREATE TABLE \"Order\" (\"select\" INTEGER, \"a b\" TEXT, PRIMARY KEY (\"select\", \"a b\"));\n"));
+                assert!(output.find("CREATE VIEW b").unwrap() < output.find("CREATE VIEW a").unwrap());
+                assert!(output.find("CREATE TRIGGER places_new").unwrap() > output.find("INSERT INTO places").unwrap());
+                assert_eq!(3, output.matches("INSERT INTO t VALUES").count());
+                assert!(output.contains("CREATE TABLE m (id INTEGER, name TEXT, PRIMARY KEY (id)) PARTITION BY RANGE (id);\n"));
+                assert!(output.contains("CREATE TABLE m_low PARTITION OF m FOR VALUES FROM (MINVALUE) TO (10);\n"));
+                assert!(output.contains("CREATE TABLE a_h PARTITION OF h FOR VALUES WITH (MODULUS 2, REMAINDER 0);\n"));
+                assert!(output.find("CREATE TABLE a_h").unwrap() > output.find("CREATE TABLE h ").unwrap());
+                assert!(!output.contains("INSERT INTO m VALUES") && output.contains("INSERT INTO m_high VALUES"));
+                assert!(output.contains("CREATE TABLE hot (id INTEGER, n INTEGER, PRIMARY KEY (id)) USING memory;\n"));
+                assert!(output.contains("CREATE UNLOGGED TABLE scratch (id INTEGER, PRIMARY KEY (id)) USING memory;\n"));
+                assert!(output.contains("CREATE TABLE visits (id INTEGER, at INTEGER, page TEXT, PRIMARY KEY (id)) TTL at + 86400 DICTIONARY (page);\n"));
+                assert!(output.contains("CREATE INDEX docs_kind ON docs (id, (doc #>> '{kind,it''s}'));\n"));
+                assert!(output.contains("CREATE INDEX t_words ON t USING fulltext (name);\n"));
+                assert!(output.contains("CREATE INDEX places_at ON places USING rtree (at);\n"));
+                assert!(output.contains("INSERT INTO places VALUES (1, '(1,-2)'::POINT, '(3,4),(0,0)'::BOX);\n"));
+            } else {
+                assert!(output.len() < sql_len / 2, "{} of {}", output.len(), sql_len);
+                // all or nothing: the tables exist now, so restoring again fails, leaving them
+                assert!(matches!(restore(&target, &output[..]), Err(Error::Sql(_))));
+                assert_eq!(expected, contents(&target));
+
+                let mut corrupt = output.clone();
+                corrupt[40] ^= 1;
+                let empty = Database::open(dir.path().join("corrupt"), 64).unwrap();
+                assert!(matches!(restore(&empty, &corrupt[..]), Err(Error::Corrupt(_))));
+                assert!(matches!(restore(&empty, &output[..output.len() - 9]), Err(Error::Corrupt(_))));
+                assert!(schema(&empty).is_empty());
+            }
+        }
+
+        let mut statement = String::new();
+        let mut input = "-- a comment\nSELECT 'a;\n-- b'; -- after\n\nSELECT 1\n".as_bytes();
+        assert!(read_statement(&mut input, &mut statement).unwrap());
+        assert_eq!("\nSELECT 'a;\n-- b'; \n", statement);
+        assert!(read_statement(&mut input, &mut statement).unwrap());
+        assert_eq!("\nSELECT 1\n", statement);
+        assert!(!read_statement(&mut input, &mut statement).unwrap());
+    }
+}
@@ -0,0 +1,45 @@
+use std::collections::BTreeSet;
+
+// Text is searched by its words: the runs of alphanumeric characters in it, lowercased. A query
+// is words too, which a text matches if it has all of them, ranked by how many times it has them.
+
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).map(str::to_lowercase)
+}
+
+// The distinct words of `text`, as a full-text index has an entry of each.
+pub fn terms(text: &str) -> BTreeSet<String> {
+    tokenize(text).collect()
+}
+
+// Whether `text` has every word of `query`. A query of no words matches nothing.
+pub fn matches(text: &str, query: &str) -> bool {
+    let query = terms(query);
+    !query.is_empty() && query.is_subset(&terms(text))
+}
+
+// How many of the words of `text` are words of `query`, for the texts matching it to be ranked by.
+pub fn rank(text: &str, query: &str) -> i64 {
+    let query = terms(query);
+    tokenize(text).filter(|word| query.contains(word)).count() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(vec!["the", "quick", "brown", "fox", "42"], tokenize("The quick-brown FOX, 42!").collect::<Vec<_>>());
+        assert_eq!(vec!["café", "naïve"], tokenize("  Café/naïve ").collect::<Vec<_>>());
+        assert_eq!(0, tokenize(" ,.!? ").count());
+
+        assert!(matches("The quick brown fox", "FOX quick"));
+        assert!(!matches("The quick brown fox", "quick dog"));
+        assert!(!matches("The quick brown fox", "fo"));
+        assert!(!matches("anything", "  !! "));
+
+        assert_eq!(3, rank("fox, Fox and the dog", "fox dog"));
+        assert_eq!(0, rank("the dog", "fox"));
+    }
+}
@@ -0,0 +1,151 @@
+use crate::btree::Node;
+use crate::buffer::{self, Page};
+use crate::disk::PAGE_SIZE;
+use crate::mvcc;
+use crate::tuple;
+use crate::wal::{LogRecord, Lsn};
+
+// Entry points for fuzzing the decoding of what's read off disk, run by the targets under fuzz/
+// with cargo-fuzz. Each takes any bytes at all, which must be decoded or rejected with an error.
+// A panic is a bug, and so is something decoded which doesn't decode the same once encoded again.
+
+// The LSN records and frames are decoded at, which their checksums are of.
+pub const LSN: Lsn = 8;
+
+// A tuple, a key made of values, and a chain of versions of a row.
+pub fn tuple(data: &[u8]) {
+    if let Ok((values, _)) = tuple::decode(data) {
+        let mut encoded = vec![];
+        tuple::encode(&values, &mut encoded);
+        assert_eq!(values, tuple::decode(&encoded).unwrap().0);
+    }
+    if let Ok(values) = tuple::decode_key(data) {
+        let mut encoded = vec![];
+        tuple::encode_key(&values, &mut encoded);
+        assert_eq!(values, tuple::decode_key(&encoded).unwrap());
+    }
+    if let Ok(versions) = mvcc::decode_versions(data) {
+        let mut encoded = vec![];
+        mvcc::encode_versions(&versions, &mut encoded);
+        assert_eq!(versions, mvcc::decode_versions(&encoded).unwrap());
+    }
+}
+
+// A page, truncated or padded with zeros to its size, as a B+Tree node and the keys and values of
+// a leaf.
+pub fn page(data: &[u8]) {
+    let mut page: Page = [0; PAGE_SIZE as usize];
+    let len = data.len().min(page.len());
+    page[..len].copy_from_slice(&data[..len]);
+    buffer::page_lsn(&page);
+    let Ok(node) = Node::read(&page) else {
+        return;
+    };
+    let mut written: Page = [0; PAGE_SIZE as usize];
+    node.write(&mut written);
+    assert_eq!(node, Node::read(&written).unwrap());
+    if let Node::Leaf { entries, .. } = &node {
+        for (key, value) in entries {
+            tuple(key);
+            tuple(value);
+        }
+    }
+}
+
+// A log record's body, and a frame of a segment holding one.
+pub fn wal(data: &[u8]) {
+    if let Ok(record) = LogRecord::decode(LSN, data) {
+        assert_eq!(record, LogRecord::decode(LSN, &record.encode()).unwrap());
+    }
+    if let Ok((record, next)) = LogRecord::decode_frame(LSN, data) {
+        assert_eq!((record.clone(), next), LogRecord::decode_frame(LSN, &record.encode_frame()).unwrap());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::PageId;
+    use crate::sim::Rng;
+    use crate::tuple::Value;
+    use crate::wal::{Checkpoint, Record, Relation};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // Random changes to `data`: bytes flipped, replaced, dropped or added, and a cut.
+    fn mutate(rng: &mut Rng, data: &[u8]) -> Vec<u8> {
+        let mut data = data.to_vec();
+        for _ in 0..1 + rng.below(4) {
+            let pos = rng.below(data.len() as u64 + 1) as usize;
+            match rng.below(5) {
+                0 if pos < data.len() => data[pos] ^= 1 << rng.below(8),
+                1 if pos < data.len() => data[pos] = [0, 1, 0x7f, 0x80, 0xff][rng.below(5) as usize],
+                2 if pos < data.len() => {
+                    data.remove(pos);
+                }
+                3 => data.insert(pos, rng.below(256) as u8),
+                _ => data.truncate(pos),
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test() {
+        let row = vec![Value::Int(-7), Value::Null, Value::Text("a\0b".to_string()), Value::Bool(true)];
+        let mut tuple_bytes = vec![];
+        tuple::encode(&row, &mut tuple_bytes);
+        let mut key = vec![];
+        tuple::encode_key(&row, &mut key);
+        let mut versions = vec![];
+        mvcc::encode_versions(&[mvcc::Version { xmin: 3, xmax: Some(5), row: row.clone() }, mvcc::Version { xmin: 2, xmax: None, row: vec![] }], &mut versions);
+
+        let mut leaf: Page = [0; PAGE_SIZE as usize];
+        Node::Leaf { entries: vec![(key.clone(), versions.clone()), (vec![], vec![1])], next: Some(PageId(9)) }.write(&mut leaf);
+        let mut branch: Page = [0; PAGE_SIZE as usize];
+        Node::Branch { keys: vec![key.clone()], children: vec![PageId(3), PageId(4)] }.write(&mut branch);
+
+        let records = [
+            Record::Begin,
+            Record::Update { page_id: PageId(3), offset: 10, before: vec![0; 100], after: vec![1; 100] },
+            Record::Compensation { page_id: PageId(3), offset: 10, after: vec![2; 5], undo_next: Some(40) },
+            Record::Redo { page_id: PageId(4), offset: 0, after: b"abcd".repeat(50) },
+            Record::Change { table: PageId(2), relation: Some(Relation { name: "t".to_string(), columns: vec!["id".to_string()] }), old: None, new: Some(tuple_bytes.clone()) },
+            Record::Commit { time: UNIX_EPOCH + Duration::from_micros(123) },
+            Record::Vacuum { horizon: 7 },
+            Record::Truncate { num_pages: 12 },
+            Record::CheckpointEnd(Checkpoint { next_txid: 5, transactions: vec![(4, 8)], dirty_pages: vec![(PageId(3), 16)], aborted: vec![2] }),
+        ];
+        let mut bodies = vec![];
+        let mut frames = vec![];
+        for record in records {
+            let record = LogRecord { lsn: LSN, txid: 4, prev_lsn: Some(8), record };
+            assert_eq!(record, LogRecord::decode(LSN, &record.encode()).unwrap());
+            let frame = record.encode_frame();
+            assert_eq!((record.clone(), LSN + frame.len() as u64), LogRecord::decode_frame(LSN, &frame).unwrap());
+            // a frame at another LSN is rejected by its checksum
+            assert!(LogRecord::decode_frame(LSN + 1, &frame).is_err());
+            bodies.push(record.encode());
+            frames.push(frame);
+        }
+
+        let mut rng = Rng::new(1);
+        for _ in 0..2000 {
+            let random: Vec<u8> = (0..rng.below(64)).map(|_| rng.below(256) as u8).collect();
+            tuple(&random);
+            page(&random);
+            wal(&random);
+            for seed in [&tuple_bytes, &key, &versions] {
+                tuple(&mutate(&mut rng, seed));
+            }
+            for seed in [&leaf, &branch] {
+                page(&mutate(&mut rng, seed));
+            }
+            let i = rng.below(bodies.len() as u64) as usize;
+            wal(&mutate(&mut rng, &bodies[i]));
+            wal(&mutate(&mut rng, &frames[i]));
+        }
+        // a count of values far more than there are bytes for
+        tuple(&[0xff, 0xff, 0xff, 0xff, 0]);
+        assert!(tuple::decode(&[0xff, 0xff, 0xff, 0xff, 0]).is_err());
+    }
+}
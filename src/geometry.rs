@@ -0,0 +1,149 @@
+use crate::tuple::Value;
+
+// Points and boxes on a grid of integer coordinates, there being no floating-point type. They're
+// written as text the way PostgreSQL writes them: a point as (x,y), a box as (x1,y1),(x2,y2) with
+// its high corner first, whichever corners it was given with.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("malformed point literal: \"{0}\"")]
+    Point(String),
+    #[error("malformed box literal: \"{0}\"")]
+    Box(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Point {
+    pub x: i64,
+    pub y: i64,
+}
+
+// A box, with `low` at or below and left of `high`; a point is the box of it at both corners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rect {
+    pub low: Point,
+    pub high: Point,
+}
+
+impl Point {
+    pub fn parse(text: &str) -> Result<Point, Error> {
+        parse_point(text).ok_or_else(|| Error::Point(text.to_string()))
+    }
+}
+
+impl std::fmt::Display for Point {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({},{})", self.x, self.y)
+    }
+}
+
+impl Rect {
+    // The box with corners `a` and `b`, whichever they are.
+    pub fn new(a: Point, b: Point) -> Rect {
+        Rect {
+            low: Point { x: a.x.min(b.x), y: a.y.min(b.y) },
+            high: Point { x: a.x.max(b.x), y: a.y.max(b.y) },
+        }
+    }
+
+    pub fn of_point(point: Point) -> Rect {
+        Rect { low: point, high: point }
+    }
+
+    // Parses ((x1,y1),(x2,y2)), or (x1,y1),(x2,y2) as PostgreSQL also takes.
+    pub fn parse(text: &str) -> Result<Rect, Error> {
+        let error = || Error::Box(text.to_string());
+        let trimmed = text.trim();
+        let inner = match trimmed.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+            Some(inner) if inner.trim_start().starts_with('(') => inner,
+            _ => trimmed,
+        };
+        let (a, b) = inner.split_once("),").ok_or_else(error)?;
+        let a = parse_point(&format!("{})", a)).ok_or_else(error)?;
+        let b = parse_point(b).ok_or_else(error)?;
+        Ok(Rect::new(a, b))
+    }
+
+    pub fn contains(&self, other: &Rect) -> bool {
+        self.low.x <= other.low.x && self.low.y <= other.low.y && other.high.x <= self.high.x && other.high.y <= self.high.y
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.low.x <= other.high.x && other.low.x <= self.high.x && self.low.y <= other.high.y && other.low.y <= self.high.y
+    }
+
+    // The smallest box both are in.
+    pub fn union(&self, other: &Rect) -> Rect {
+        Rect {
+            low: Point { x: self.low.x.min(other.low.x), y: self.low.y.min(other.low.y) },
+            high: Point { x: self.high.x.max(other.high.x), y: self.high.y.max(other.high.y) },
+        }
+    }
+
+    // Widths are up to 2^64 - 1, so their product fits in a u128.
+    pub fn area(&self) -> u128 {
+        let width = (self.high.x as i128 - self.low.x as i128) as u128;
+        let height = (self.high.y as i128 - self.low.y as i128) as u128;
+        width * height
+    }
+}
+
+impl std::fmt::Display for Rect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.high, self.low)
+    }
+}
+
+// The box a point or box value is in, None for any other.
+pub fn bounds(value: &Value) -> Option<Rect> {
+    match value {
+        Value::Point(point) => Some(Rect::of_point(*point)),
+        Value::Box(rect) => Some(*rect),
+        _ => None,
+    }
+}
+
+// (x,y), or x,y as PostgreSQL also takes.
+fn parse_point(text: &str) -> Option<Point> {
+    let trimmed = text.trim();
+    let inner = trimmed.strip_prefix('(').and_then(|s| s.strip_suffix(')')).unwrap_or(trimmed);
+    let (x, y) = inner.split_once(',')?;
+    Some(Point { x: x.trim().parse().ok()?, y: y.trim().parse().ok()? })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let point = |x, y| Point { x, y };
+        assert_eq!(point(1, -2), Point::parse(" ( 1 , -2 ) ").unwrap());
+        assert_eq!(point(3, 4), Point::parse("3,4").unwrap());
+        assert_eq!("(1,-2)", point(1, -2).to_string());
+        assert!(Point::parse("(1,2").is_err());
+        assert!(Point::parse("(1.5,2)").is_err());
+        assert!(Point::parse("(1,2,3)").is_err());
+
+        let rect = Rect::parse("((4,0),(0,3))").unwrap();
+        assert_eq!(Rect { low: point(0, 0), high: point(4, 3) }, rect);
+        assert_eq!(rect, Rect::parse("(0,3), (4,0)").unwrap());
+        assert_eq!("(4,3),(0,0)", rect.to_string());
+        assert_eq!(rect, Rect::parse(&rect.to_string()).unwrap());
+        assert!(Rect::parse("((0,0))").is_err());
+        assert!(Rect::parse("(0,0),(1,x)").is_err());
+
+        assert!(rect.contains(&Rect::of_point(point(4, 3))));
+        assert!(rect.contains(&Rect::new(point(1, 1), point(2, 2))));
+        assert!(!rect.contains(&Rect::new(point(1, 1), point(5, 2))));
+        assert!(rect.intersects(&Rect::new(point(4, 3), point(9, 9))));
+        assert!(!rect.intersects(&Rect::new(point(5, 0), point(9, 9))));
+        assert_eq!(12, rect.area());
+        assert_eq!(Rect::new(point(-1, 0), point(4, 5)), rect.union(&Rect::of_point(point(-1, 5))));
+        let all = Rect::new(point(i64::MIN, i64::MIN), point(i64::MAX, i64::MAX));
+        assert_eq!(u64::MAX as u128 * u64::MAX as u128, all.area());
+
+        assert_eq!(Some(Rect::of_point(point(1, 2))), bounds(&Value::Point(point(1, 2))));
+        assert_eq!(None, bounds(&Value::Null));
+    }
+}
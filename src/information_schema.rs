@@ -0,0 +1,281 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::buffer::BufferPoolManager;
+use crate::catalog::{self, Catalog, Column, VirtualTable};
+use crate::query;
+use crate::query::expr::type_name;
+use crate::tuple::{DataType, Tuple, Value};
+
+// Virtual tables of what's in the catalog and what the engine is doing, so that both can be
+// queried with plain SQL, each named `information_schema.<name>`:
+//   tables: table_name, table_type ('BASE TABLE', 'VIEW' or 'VIRTUAL'), row_count as of the
+//     last ANALYZE
+//   columns: table_name, column_name, ordinal_position, data_type, is_key, of the tables, views
+//     and virtual tables, a view's columns being of no known type
+//   indexes: index_name, table_name, column_names, separated by commas (with their paths into JSON
+//     columns, of an index on them)
+//   table_privileges: grantee, table_name, privilege_type, one row each privilege granted
+//   transactions: txid, first_lsn, last_lsn, idle_ms while between statements, is_current
+//   buffers: one row of frames, pages, dirty_pages, hits, misses
+// The tables and views are read from the catalog as it is when scanned, the virtual tables as
+// they were when these were registered.
+
+pub const SCHEMA: &str = "information_schema";
+
+// Names and columns of the virtual tables.
+type VirtualTables = Rc<RefCell<Vec<(String, Vec<Column>)>>>;
+
+// Registers the tables of the schema in `catalog`, after the other virtual tables, if any.
+pub fn register(catalog: &mut Catalog) -> Result<(), catalog::Error> {
+    let virtual_tables = VirtualTables::default();
+    let tables: [(&str, Rc<dyn VirtualTable>); 6] = [
+        ("tables", Rc::new(Tables(virtual_tables.clone()))),
+        ("columns", Rc::new(Columns(virtual_tables.clone()))),
+        ("indexes", Rc::new(Indexes)),
+        ("table_privileges", Rc::new(TablePrivileges)),
+        ("transactions", Rc::new(Transactions)),
+        ("buffers", Rc::new(Buffers)),
+    ];
+    for (name, table) in tables {
+        catalog.register_virtual_table(&format!("{}.{}", SCHEMA, name), table)?;
+    }
+    *virtual_tables.borrow_mut() = catalog.virtual_tables().map(|(name, table)| (name.to_string(), table.columns())).collect();
+    Ok(())
+}
+
+fn columns(columns: &[(&str, DataType)]) -> Vec<Column> {
+    columns.iter().map(|&(name, data_type)| Column { name: name.to_string(), data_type }).collect()
+}
+
+fn text(s: &str) -> Value {
+    Value::Text(s.to_string())
+}
+
+fn int(n: impl TryInto<i64>) -> Value {
+    Value::Int(n.try_into().unwrap_or(i64::MAX))
+}
+
+struct Tables(VirtualTables);
+
+impl VirtualTable for Tables {
+    fn columns(&self) -> Vec<Column> {
+        columns(&[("table_name", DataType::Text), ("table_type", DataType::Text), ("row_count", DataType::Integer)])
+    }
+
+    fn rows(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<Tuple>, query::Error> {
+        let catalog = Catalog::open(bufmgr)?;
+        let mut rows: Vec<Tuple> = catalog
+            .tables()
+            .map(|info| vec![text(&info.name), text("BASE TABLE"), info.stats.as_ref().map_or(Value::Null, |stats| int(stats.row_count))])
+            .collect();
+        rows.extend(catalog.views().map(|info| vec![text(&info.name), text("VIEW"), Value::Null]));
+        rows.extend(self.0.borrow().iter().map(|(name, _)| vec![text(name), text("VIRTUAL"), Value::Null]));
+        Ok(rows)
+    }
+}
+
+struct Columns(VirtualTables);
+
+impl VirtualTable for Columns {
+    fn columns(&self) -> Vec<Column> {
+        columns(&[
+            ("table_name", DataType::Text),
+            ("column_name", DataType::Text),
+            ("ordinal_position", DataType::Integer),
+            ("data_type", DataType::Text),
+            ("is_key", DataType::Boolean),
+        ])
+    }
+
+    fn rows(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<Tuple>, query::Error> {
+        let catalog = Catalog::open(bufmgr)?;
+        let row = |table: &str, i: usize, name: &str, data_type: Option<DataType>, is_key: bool| {
+            vec![text(table), text(name), int(i + 1), data_type.map_or(Value::Null, |t| text(type_name(t))), Value::Bool(is_key)]
+        };
+        let mut rows = vec![];
+        for info in catalog.tables() {
+            for (i, column) in info.columns.iter().enumerate() {
+                rows.push(row(&info.name, i, &column.name, Some(column.data_type), i < info.table.num_key_elems));
+            }
+        }
+        for info in catalog.views() {
+            for (i, name) in info.columns.iter().enumerate() {
+                rows.push(row(&info.name, i, name, None, false));
+            }
+        }
+        for (table, table_columns) in self.0.borrow().iter() {
+            for (i, column) in table_columns.iter().enumerate() {
+                rows.push(row(table, i, &column.name, Some(column.data_type), false));
+            }
+        }
+        Ok(rows)
+    }
+}
+
+struct Indexes;
+
+impl VirtualTable for Indexes {
+    fn columns(&self) -> Vec<Column> {
+        columns(&[("index_name", DataType::Text), ("table_name", DataType::Text), ("column_names", DataType::Text)])
+    }
+
+    fn rows(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<Tuple>, query::Error> {
+        let catalog = Catalog::open(bufmgr)?;
+        let mut rows = vec![];
+        for info in catalog.tables() {
+            for (name, index) in info.index_names.iter().zip(&info.table.indexes) {
+                let names: Vec<String> = index
+                    .columns
+                    .iter()
+                    .zip(&index.paths)
+                    .map(|(&i, path)| match path {
+                        Some(path) => format!("{} {}", info.columns[i].name, path),
+                        None => info.columns[i].name.clone(),
+                    })
+                    .collect();
+                rows.push(vec![text(name), text(&info.name), text(&names.join(", "))]);
+            }
+        }
+        Ok(rows)
+    }
+}
+
+struct TablePrivileges;
+
+impl VirtualTable for TablePrivileges {
+    fn columns(&self) -> Vec<Column> {
+        columns(&[("grantee", DataType::Text), ("table_name", DataType::Text), ("privilege_type", DataType::Text)])
+    }
+
+    fn rows(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<Tuple>, query::Error> {
+        let catalog = Catalog::open(bufmgr)?;
+        let mut rows = vec![];
+        for (table, user, privileges) in catalog.grants() {
+            rows.extend(privileges.into_iter().map(|privilege| vec![text(user), text(table), text(&privilege.to_string())]));
+        }
+        Ok(rows)
+    }
+}
+
+struct Transactions;
+
+impl VirtualTable for Transactions {
+    fn columns(&self) -> Vec<Column> {
+        columns(&[
+            ("txid", DataType::Integer),
+            ("first_lsn", DataType::Integer),
+            ("last_lsn", DataType::Integer),
+            ("idle_ms", DataType::Integer),
+            ("is_current", DataType::Boolean),
+        ])
+    }
+
+    fn rows(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<Tuple>, query::Error> {
+        Ok(bufmgr
+            .transactions()
+            .into_iter()
+            .map(|info| {
+                let idle = info.idle.map_or(Value::Null, |idle| int(idle.as_millis()));
+                vec![int(info.txid), int(info.first_lsn), int(info.last_lsn), idle, Value::Bool(info.current)]
+            })
+            .collect())
+    }
+}
+
+struct Buffers;
+
+impl VirtualTable for Buffers {
+    fn columns(&self) -> Vec<Column> {
+        columns(&[
+            ("frames", DataType::Integer),
+            ("pages", DataType::Integer),
+            ("dirty_pages", DataType::Integer),
+            ("hits", DataType::Integer),
+            ("misses", DataType::Integer),
+        ])
+    }
+
+    fn rows(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<Tuple>, query::Error> {
+        let (usage, stats) = (bufmgr.pool_usage(), bufmgr.stats());
+        Ok(vec![vec![int(usage.frames), int(usage.pages), int(usage.dirty), int(stats.hits), int(stats.misses)]])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::Database;
+    use crate::sql::QueryResult;
+    use crate::tuple::{Tuple, Value};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path(), 16).unwrap();
+        let mut session = db.session();
+        session
+            .execute(
+                "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, active BOOLEAN);
+                 CREATE INDEX users_name ON users (name, active);
+                 CREATE VIEW names AS SELECT name FROM users;
+                 INSERT INTO users VALUES (1, 'a', true), (2, 'b', false);
+                 ANALYZE users;
+                 CREATE USER alice;
+                 GRANT SELECT, DELETE ON users TO alice",
+            )
+            .unwrap();
+        let mut rows = |sql: &str| -> Vec<Tuple> {
+            match session.execute(sql).unwrap().pop().unwrap() {
+                QueryResult::Rows { rows, .. } => rows,
+                result => panic!("{:?}", result),
+            }
+        };
+        let text = |s: &str| Value::Text(s.to_string());
+
+        assert_eq!(
+            vec![
+                vec![text("users"), text("BASE TABLE"), Value::Int(2)],
+                vec![text("names"), text("VIEW"), Value::Null],
+                vec![text("information_schema.tables"), text("VIRTUAL"), Value::Null],
+            ],
+            rows("SELECT table_name, table_type, row_count FROM information_schema.tables WHERE table_type <> 'VIRTUAL' OR table_name = 'information_schema.tables'")
+        );
+        assert_eq!(
+            vec![
+                vec![text("id"), Value::Int(1), text("integer"), Value::Bool(true)],
+                vec![text("name"), Value::Int(2), text("text"), Value::Bool(false)],
+                vec![text("active"), Value::Int(3), text("boolean"), Value::Bool(false)],
+                vec![text("name"), Value::Int(1), Value::Null, Value::Bool(false)],
+            ],
+            rows("SELECT column_name, ordinal_position, data_type, is_key FROM information_schema.columns WHERE columns.table_name = 'users' OR table_name = 'names'")
+        );
+        assert_eq!(vec![vec![text("name")]], rows("SELECT c.column_name FROM information_schema.columns c WHERE c.table_name = 'names'"));
+        assert_eq!(
+            vec![vec![text("users_name"), text("users"), text("name, active")]],
+            rows("SELECT * FROM information_schema.indexes")
+        );
+        assert_eq!(
+            vec![vec![text("alice"), text("users"), text("SELECT")], vec![text("alice"), text("users"), text("DELETE")]],
+            rows("SELECT * FROM information_schema.table_privileges")
+        );
+        assert_eq!(vec![vec![Value::Int(7)]], rows("SELECT count(*) FROM information_schema.columns WHERE table_name = 'stat_statements'"));
+
+        // the transaction of the session is the current one, others idle
+        let mut other = db.session();
+        other.execute("BEGIN; INSERT INTO users VALUES (3, 'c', true)").unwrap();
+        session.execute("BEGIN; INSERT INTO users VALUES (4, 'd', true)").unwrap();
+        let transactions = match session.execute("SELECT is_current, idle_ms IS NULL FROM information_schema.transactions").unwrap().pop().unwrap() {
+            QueryResult::Rows { rows, .. } => rows,
+            result => panic!("{:?}", result),
+        };
+        assert_eq!(vec![vec![Value::Bool(false), Value::Bool(false)], vec![Value::Bool(true), Value::Bool(true)]], transactions);
+        session.execute("COMMIT").unwrap();
+
+        let buffers = match session.execute("SELECT frames, pages > 0, hits > 0 FROM information_schema.buffers").unwrap().pop().unwrap() {
+            QueryResult::Rows { rows, .. } => rows,
+            result => panic!("{:?}", result),
+        };
+        assert_eq!(vec![vec![Value::Int(16), Value::Bool(true), Value::Bool(true)]], buffers);
+    }
+}
@@ -0,0 +1,417 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::btree::{BTree, Node};
+use crate::buffer::{self, BufferPool, BufferPoolManager, Page, PAGE_BODY_SIZE};
+use crate::catalog::{Catalog, CATALOG_META_PAGE_ID};
+use crate::database::DATA_FILE;
+use crate::disk::{self, DiskManager, PageId};
+use crate::dump;
+use crate::mvcc;
+use crate::rtree;
+use crate::tuple::{self, Value};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error("no database in {0}")]
+    NoDatabase(PathBuf),
+    #[error("no page {0}, the file has {1}")]
+    NoSuchPage(u64, u64),
+}
+
+// Pages read at a time; the inspector doesn't keep them.
+const POOL_SIZE: usize = 16;
+// Bytes of a hex dump line.
+const HEX_LINE: usize = 16;
+
+// The tree a page is of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tree {
+    Catalog,
+    Table(String),
+    // of the table, named
+    Index(String, String),
+}
+
+impl fmt::Display for Tree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tree::Catalog => write!(f, "catalog"),
+            Tree::Table(name) => write!(f, "table {}", name),
+            Tree::Index(table, name) => write!(f, "index {}.{}", table, name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Meta,
+    Branch,
+    Leaf,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Meta => "meta",
+            Kind::Branch => "branch",
+            Kind::Leaf => "leaf",
+        }
+    }
+}
+
+// Decodes the pages of the data file of a database for debugging, e.g. corruption: which tree
+// each page is of, found by walking the catalog and the trees of the tables and indexes in it,
+// and what the page holds, down to the versions of rows. Nothing is written nor recovered, so the
+// pages are as last written back, which is all of them once the database is closed.
+//
+// Pages are b+tree nodes laid out as in `btree`, entries one after the other with no slot array.
+// What's checked is that they're as written by their checksums, which doesn't keep those which
+// aren't from being decoded, that they decode, and that no page is in two trees.
+pub struct Inspector {
+    bufmgr: BufferPoolManager,
+    num_pages: u64,
+    roles: BTreeMap<PageId, (Tree, Kind)>,
+    // what's wrong with the trees, found walking them
+    problems: Vec<String>,
+}
+
+impl Inspector {
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = dir.as_ref().join(DATA_FILE);
+        if !path.is_file() {
+            return Err(Error::NoDatabase(dir.as_ref().to_path_buf()));
+        }
+        let disk = DiskManager::open(path)?;
+        let num_pages = disk.num_pages();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(POOL_SIZE));
+        bufmgr.set_verify_checksums(false);
+        let mut inspector = Self { bufmgr, num_pages, roles: BTreeMap::new(), problems: vec![] };
+        for page_id in (0..num_pages).map(PageId) {
+            if !disk::verify_page(page_id, &*inspector.page(page_id)?) {
+                inspector.problems.push(format!("page {} fails its checksum", page_id.0));
+            }
+        }
+        inspector.walk(Tree::Catalog, CATALOG_META_PAGE_ID);
+        match Catalog::open(&mut inspector.bufmgr) {
+            Ok(catalog) => {
+                // those in memory have no pages in the file
+                for info in catalog.tables().filter(|info| !info.table.in_memory()) {
+                    inspector.walk(Tree::Table(info.name.clone()), info.table.btree.meta_page_id);
+                    for (name, index) in info.index_names.iter().zip(&info.table.indexes) {
+                        inspector.walk(Tree::Index(info.name.clone(), name.clone()), index.btree.meta_page_id);
+                    }
+                }
+            }
+            Err(err) => inspector.problems.push(format!("cannot read the catalog: {}", err)),
+        }
+        Ok(inspector)
+    }
+
+    pub fn num_pages(&self) -> u64 {
+        self.num_pages
+    }
+
+    pub fn role(&self, page_id: PageId) -> Option<&(Tree, Kind)> {
+        self.roles.get(&page_id)
+    }
+
+    pub fn problems(&self) -> &[String] {
+        &self.problems
+    }
+
+    // A line for each page, then the problems found, e.g.
+    //   page 0: meta of catalog, lsn 120, root 1
+    //   page 1: leaf of catalog, lsn 4211, 2 entries, 190 of 4084 bytes
+    pub fn summary(&mut self) -> Result<String, Error> {
+        let mut out = String::new();
+        for page_id in (0..self.num_pages).map(PageId) {
+            let page = self.page(page_id)?;
+            let role = self.describe(page_id);
+            out.push_str(&format!("page {}: {}, lsn {}", page_id.0, role, buffer::page_lsn(&page)));
+            match self.roles.get(&page_id) {
+                Some((_, Kind::Meta)) => out.push_str(&format!(", root {}", root(&page))),
+                _ => match Node::read(&page) {
+                    Ok(Node::Leaf { entries, .. }) if !is_zeroed(&page) => {
+                        out.push_str(&format!(", {} entries, {} of {} bytes", entries.len(), used(&page), PAGE_BODY_SIZE))
+                    }
+                    Ok(Node::Branch { keys, .. }) => {
+                        out.push_str(&format!(", {} keys, {} of {} bytes", keys.len(), used(&page), PAGE_BODY_SIZE))
+                    }
+                    Ok(_) => out.push_str(", zeroed"),
+                    Err(_) => out.push_str(", malformed"),
+                },
+            }
+            out.push('\n');
+        }
+        let unreached = (0..self.num_pages).filter(|&id| !self.roles.contains_key(&PageId(id))).count();
+        out.push_str(&format!("{} pages, {} in no tree\n", self.num_pages, unreached));
+        for problem in &self.problems {
+            out.push_str(&format!("problem: {}\n", problem));
+        }
+        Ok(out)
+    }
+
+    // Everything on the page: its header, then the entries of a leaf, decoded as of the tree it's
+    // in, or the keys and children of a branch. A page of no tree is decoded as a node if it is
+    // one, and dumped in hex if not.
+    pub fn page_info(&mut self, page_id: PageId) -> Result<String, Error> {
+        let page = self.page(page_id)?;
+        let mut out = format!("page {}: {}\n", page_id.0, self.describe(page_id));
+        out.push_str(&format!("lsn: {}\n", buffer::page_lsn(&page)));
+        out.push_str(&format!("checksum: {}\n", if disk::verify_page(page_id, &page[..]) { "ok" } else { "mismatch" }));
+        let role = self.roles.get(&page_id).cloned();
+        if let Some((_, Kind::Meta)) = role {
+            out.push_str(&format!("root: {}\n", root(&page)));
+            return Ok(out);
+        }
+        match Node::read(&page) {
+            Ok(Node::Leaf { entries, next }) => {
+                out.push_str(&format!("leaf, {} entries, {} of {} bytes\n", entries.len(), used(&page), PAGE_BODY_SIZE));
+                out.push_str(&format!("next leaf: {}\n", next.map_or("none".to_string(), |id| id.0.to_string())));
+                let tree = role.map(|(tree, _)| tree);
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    out.push_str(&format!("[{}] key {}\n", i, format_key(key)));
+                    for line in format_value(tree.as_ref(), value) {
+                        out.push_str(&format!("    {}\n", line));
+                    }
+                }
+            }
+            Ok(Node::Branch { keys, children }) => {
+                out.push_str(&format!("branch, {} keys, {} of {} bytes\n", keys.len(), used(&page), PAGE_BODY_SIZE));
+                out.push_str(&format!("child {}\n", children[0].0));
+                for (key, child) in keys.iter().zip(&children[1..]) {
+                    out.push_str(&format!("key {}\nchild {}\n", format_key(key), child.0));
+                }
+            }
+            Err(_) => {
+                out.push_str("not a b+tree node:\n");
+                out.push_str(&hex_dump(&page[..PAGE_BODY_SIZE]));
+            }
+        }
+        Ok(out)
+    }
+
+    fn page(&mut self, page_id: PageId) -> Result<Box<Page>, Error> {
+        if page_id.0 >= self.num_pages {
+            return Err(Error::NoSuchPage(page_id.0, self.num_pages));
+        }
+        let buffer = self.bufmgr.fetch_page(page_id)?;
+        let page = Box::new(*buffer.page.borrow());
+        Ok(page)
+    }
+
+    fn describe(&self, page_id: PageId) -> String {
+        match self.roles.get(&page_id) {
+            Some((tree, kind)) => format!("{} of {}", kind.name(), tree),
+            // e.g. freed by REINDEX, or of a tree never created after a crash
+            None => "in no tree".to_string(),
+        }
+    }
+
+    fn walk(&mut self, tree: Tree, meta_page_id: PageId) {
+        if meta_page_id.0 >= self.num_pages {
+            self.problems.push(format!("the meta page {} of {} is beyond the end of the file", meta_page_id.0, tree));
+            return;
+        }
+        if !self.claim(meta_page_id, &tree, Kind::Meta) {
+            return;
+        }
+        let root = match (BTree { meta_page_id }).root_page_id(&mut self.bufmgr) {
+            Ok(root) => root,
+            Err(err) => {
+                self.problems.push(format!("cannot read the meta page {} of {}: {}", meta_page_id.0, tree, err));
+                return;
+            }
+        };
+        let mut pending = vec![root];
+        while let Some(page_id) = pending.pop() {
+            let node = match self.page(page_id) {
+                Ok(page) => Node::read(&page).map_err(|err| err.to_string()),
+                Err(err) => Err(err.to_string()),
+            };
+            match node {
+                Ok(Node::Leaf { .. }) => {
+                    self.claim(page_id, &tree, Kind::Leaf);
+                }
+                Ok(Node::Branch { children, .. }) => {
+                    if self.claim(page_id, &tree, Kind::Branch) {
+                        pending.extend(children.into_iter().rev());
+                    }
+                }
+                // the node of an R-tree index, if not of a B+tree
+                Err(err) => match self.page(page_id).ok().and_then(|page| rtree::Node::read(&page).ok()) {
+                    Some(rtree::Node::Leaf(_)) => {
+                        self.claim(page_id, &tree, Kind::Leaf);
+                    }
+                    Some(rtree::Node::Branch(children)) => {
+                        if self.claim(page_id, &tree, Kind::Branch) {
+                            pending.extend(children.into_iter().rev().map(|(_, child)| child));
+                        }
+                    }
+                    None => self.problems.push(format!("page {} of {}: {}", page_id.0, tree, err)),
+                },
+            }
+        }
+    }
+
+    // Records the page as of the tree unless it's of one already, which is a problem.
+    fn claim(&mut self, page_id: PageId, tree: &Tree, kind: Kind) -> bool {
+        if let Some((other, other_kind)) = self.roles.get(&page_id) {
+            self.problems.push(format!(
+                "page {} is both a {} of {} and a {} of {}",
+                page_id.0,
+                other_kind.name(),
+                other,
+                kind.name(),
+                tree
+            ));
+            return false;
+        }
+        self.roles.insert(page_id, (tree.clone(), kind));
+        true
+    }
+}
+
+fn root(page: &Page) -> u64 {
+    u64::from_le_bytes(page[..8].try_into().unwrap())
+}
+
+fn used(page: &Page) -> usize {
+    Node::read(page).map_or(0, |node| node.size())
+}
+
+// Never written, e.g. allocated just before a crash, which reads as an empty leaf.
+fn is_zeroed(page: &Page) -> bool {
+    page.iter().all(|&b| b == 0)
+}
+
+pub fn format_values(values: &[Value]) -> String {
+    format!("({})", values.iter().map(dump::literal).collect::<Vec<_>>().join(", "))
+}
+
+fn format_key(key: &[u8]) -> String {
+    match tuple::decode_key(key) {
+        Ok(values) => format!("{} ({})", format_values(&values), hex(key)),
+        Err(_) => format!("malformed ({})", hex(key)),
+    }
+}
+
+// The lines of a value: the versions of a row for tables, the primary key for indexes, the
+// entry for the catalog.
+fn format_value(tree: Option<&Tree>, value: &[u8]) -> Vec<String> {
+    let decoded = match tree {
+        Some(Tree::Table(_)) => mvcc::decode_versions(value).map(|versions| {
+            versions
+                .iter()
+                .map(|version| {
+                    let xmax = version.xmax.map_or("-".to_string(), |xmax| xmax.to_string());
+                    format!("xmin {}, xmax {}: {}", version.xmin, xmax, format_values(&version.row))
+                })
+                .collect()
+        }),
+        Some(Tree::Index(..)) => tuple::decode_key(value).map(|values| vec![format!("primary key {}", format_values(&values))]),
+        Some(Tree::Catalog) => tuple::decode(value).map(|(values, _)| vec![format_values(&values)]),
+        None => Ok(vec![format!("value {}", hex(value))]),
+    };
+    decoded.unwrap_or_else(|_| vec![format!("malformed value {}", hex(value))])
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Lines of offset, bytes and their ASCII, runs of zero lines folded into a `*`.
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    let mut folded = false;
+    for (i, line) in bytes.chunks(HEX_LINE).enumerate() {
+        if i > 0 && line.iter().all(|&b| b == 0) {
+            if !folded {
+                out.push_str("*\n");
+                folded = true;
+            }
+            continue;
+        }
+        folded = false;
+        let hex: Vec<_> = line.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = line.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect();
+        out.push_str(&format!("{:04x}  {:<47}  {}\n", i * HEX_LINE, hex.join(" "), ascii));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        let dir = tempdir().unwrap();
+        assert!(matches!(Inspector::open(dir.path()), Err(Error::NoDatabase(_))));
+        let db = Database::open(dir.path(), 64).unwrap();
+        db.session().execute("CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT); CREATE INDEX t_name ON t (name)").unwrap();
+        let mut session = db.session();
+        for i in 0..200 {
+            let name = if i == 7 { "it''s".to_string() } else { format!("row {:040}", i) };
+            session.execute(&format!("INSERT INTO t VALUES ({}, '{}')", i, name)).unwrap();
+        }
+        drop(session);
+        db.close().unwrap();
+
+        let mut inspector = Inspector::open(dir.path()).unwrap();
+        assert!(inspector.problems().is_empty(), "{:?}", inspector.problems());
+        let summary = inspector.summary().unwrap();
+        assert!(summary.starts_with("page 0: meta of catalog, lsn "), "{}", summary);
+        assert!(summary.contains(": branch of table t, lsn "), "{}", summary);
+        assert!(summary.contains(": leaf of index t.t_name, lsn "), "{}", summary);
+        assert!(summary.contains(&format!("{} pages, 0 in no tree\n", inspector.num_pages())), "{}", summary);
+
+        let page_of = |inspector: &Inspector, tree: &Tree, kind: Kind| {
+            (0..inspector.num_pages()).map(PageId).find(|&id| inspector.role(id) == Some(&(tree.clone(), kind))).unwrap()
+        };
+        let table = Tree::Table("t".to_string());
+        let meta = page_of(&inspector, &table, Kind::Meta);
+        let info = inspector.page_info(meta).unwrap();
+        assert!(info.contains("\nchecksum: ok\nroot: "), "{}", info);
+        let branch = page_of(&inspector, &table, Kind::Branch);
+        let info = inspector.page_info(branch).unwrap();
+        assert!(info.contains("\nchild "), "{}", info);
+        assert!(info.contains("\nkey ("), "{}", info);
+        // the leftmost leaf, of row 7
+        let leaf = page_of(&inspector, &table, Kind::Leaf);
+        let info = inspector.page_info(leaf).unwrap();
+        assert!(info.contains(&format!("[0] key (0) ({})\n", hex(&[1, 0x80, 0, 0, 0, 0, 0, 0, 0]))), "{}", info);
+        assert!(info.contains("xmax -: (7, 'it''s')\n"), "{}", info);
+        let info = inspector.page_info(page_of(&inspector, &Tree::Index("t".to_string(), "t_name".to_string()), Kind::Leaf)).unwrap();
+        assert!(info.contains("primary key ("), "{}", info);
+        let info = inspector.page_info(page_of(&inspector, &Tree::Catalog, Kind::Leaf)).unwrap();
+        assert!(info.contains("('table', 't', "), "{}", info);
+        let num_pages = inspector.num_pages();
+        assert!(matches!(inspector.page_info(PageId(num_pages)), Err(Error::NoSuchPage(_, _))));
+        drop(inspector);
+
+        // a page overwritten, its node type of neither kind
+        let mut file = OpenOptions::new().write(true).open(dir.path().join(DATA_FILE)).unwrap();
+        file.seek(SeekFrom::Start(leaf.0 * crate::disk::PAGE_SIZE)).unwrap();
+        file.write_all(b"garbage").unwrap();
+        drop(file);
+        let mut inspector = Inspector::open(dir.path()).unwrap();
+        assert_eq!(vec![format!("page {} fails its checksum", leaf.0), format!("page {} of table t: malformed b+tree page", leaf.0)], inspector.problems());
+        let info = inspector.page_info(leaf).unwrap();
+        assert!(info.contains("not a b+tree node:\n0000  67 61 72 62 61 67 65 "), "{}", info);
+        assert!(info.contains("\nchecksum: mismatch\n"), "{}", info);
+        assert!(info.contains("garbage"), "{}", info);
+        assert!(inspector.summary().unwrap().contains(&format!("page {}: in no tree, lsn ", leaf.0)));
+    }
+}
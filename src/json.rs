@@ -0,0 +1,577 @@
+use std::fmt::{self, Write};
+
+use crate::tuple::Value;
+
+// JSON values are stored in a binary form, each as a tag followed by its payload:
+//   null, false, true: [tag]
+//   integer:           [tag][i64 little endian]
+//   other number:      [tag][f64 little endian]
+//   string:            [tag][len: u32][utf-8 bytes]
+//   array:             [tag][count: u32][size: u32][offset: u32 * count][element*]
+//   object:            [tag][count: u32][size: u32][offset: u32 * count][([len: u32][key][value])*]
+// where `size` is the length of what follows it, and the offsets are those of the elements, or
+// the members, from the end of the offsets. An element is found by its offset without decoding
+// those before it, and a member by a binary search, the members being sorted by key. Whitespace
+// and the order and duplicates of keys are lost (the last of a key is kept), so equal values
+// have the same bytes.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("invalid JSON: {0}")]
+    Syntax(String),
+}
+
+const NULL: u8 = 0;
+const FALSE: u8 = 1;
+const TRUE: u8 = 2;
+const INT: u8 = 3;
+const FLOAT: u8 = 4;
+const STRING: u8 = 5;
+const ARRAY: u8 = 6;
+const OBJECT: u8 = 7;
+
+// Arrays and objects nested deeper aren't parsed, so that parsing doesn't run out of stack.
+const MAX_DEPTH: usize = 128;
+
+// Parses JSON text into its binary form.
+pub fn parse(text: &str) -> Result<Vec<u8>, Error> {
+    let mut parser = Parser { text: text.as_bytes(), pos: 0 };
+    let mut bytes = vec![];
+    parser.value(&mut bytes, 0)?;
+    parser.skip_whitespace();
+    match parser.pos == text.len() {
+        true => Ok(bytes),
+        false => Err(parser.error("unexpected text after the value")),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> Error {
+        Error::Syntax(format!("{} at offset {}", message, self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.text.get(self.pos).is_some_and(|b| b" \t\n\r".contains(b)) {
+            self.pos += 1;
+        }
+    }
+
+    fn consume(&mut self, literal: &str) -> bool {
+        let found = self.text[self.pos..].starts_with(literal.as_bytes());
+        if found {
+            self.pos += literal.len();
+        }
+        found
+    }
+
+    fn value(&mut self, bytes: &mut Vec<u8>, depth: usize) -> Result<(), Error> {
+        self.skip_whitespace();
+        match self.text.get(self.pos) {
+            Some(b'n') if self.consume("null") => bytes.push(NULL),
+            Some(b'f') if self.consume("false") => bytes.push(FALSE),
+            Some(b't') if self.consume("true") => bytes.push(TRUE),
+            Some(b'"') => {
+                let s = self.string()?;
+                push_string(bytes, &s);
+            }
+            Some(b'-' | b'0'..=b'9') => self.number(bytes)?,
+            Some(b'[' | b'{') if depth == MAX_DEPTH => return Err(self.error("nested too deep")),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut elements = vec![];
+                if !self.close(b']') {
+                    loop {
+                        let mut element = vec![];
+                        self.value(&mut element, depth + 1)?;
+                        elements.push(element);
+                        if self.close(b']') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                push_container(bytes, ARRAY, elements);
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = vec![];
+                if !self.close(b'}') {
+                    loop {
+                        self.skip_whitespace();
+                        if self.text.get(self.pos) != Some(&b'"') {
+                            return Err(self.error("expected a key"));
+                        }
+                        let key = self.string()?;
+                        self.expect(b':')?;
+                        let mut value = vec![];
+                        self.value(&mut value, depth + 1)?;
+                        members.push((key, value));
+                        if self.close(b'}') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                // the last of each key is kept
+                members.reverse();
+                members.sort_by(|a, b| a.0.cmp(&b.0));
+                members.dedup_by(|a, b| a.0 == b.0);
+                let members = members
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let mut member = (key.len() as u32).to_le_bytes().to_vec();
+                        member.extend(key.as_bytes());
+                        member.extend(value);
+                        member
+                    })
+                    .collect();
+                push_container(bytes, OBJECT, members);
+            }
+            _ => return Err(self.error("expected a value")),
+        }
+        Ok(())
+    }
+
+    // Consumes `close` if it's next.
+    fn close(&mut self, close: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.text.get(self.pos) == Some(&close);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), Error> {
+        match self.close(b) {
+            true => Ok(()),
+            false => Err(self.error(&format!("expected '{}'", b as char))),
+        }
+    }
+
+    fn number(&mut self, bytes: &mut Vec<u8>) -> Result<(), Error> {
+        let start = self.pos;
+        self.consume("-");
+        let digits = |parser: &mut Self| {
+            let start = parser.pos;
+            while parser.text.get(parser.pos).is_some_and(u8::is_ascii_digit) {
+                parser.pos += 1;
+            }
+            parser.pos > start
+        };
+        let leading_zero = self.text.get(self.pos) == Some(&b'0') && self.text.get(self.pos + 1).is_some_and(u8::is_ascii_digit);
+        if !digits(self) || leading_zero {
+            return Err(self.error("invalid number"));
+        }
+        let mut integral = true;
+        if self.consume(".") {
+            integral = false;
+            if !digits(self) {
+                return Err(self.error("invalid number"));
+            }
+        }
+        if self.consume("e") || self.consume("E") {
+            integral = false;
+            let _ = self.consume("+") || self.consume("-");
+            if !digits(self) {
+                return Err(self.error("invalid number"));
+            }
+        }
+        let text = std::str::from_utf8(&self.text[start..self.pos]).unwrap();
+        match text.parse::<i64>() {
+            Ok(n) if integral => {
+                bytes.push(INT);
+                bytes.extend(n.to_le_bytes());
+            }
+            _ => {
+                let n: f64 = text.parse().map_err(|_| self.error("invalid number"))?;
+                if !n.is_finite() {
+                    return Err(self.error("number out of range"));
+                }
+                bytes.push(FLOAT);
+                bytes.extend(n.to_le_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let mut s = vec![];
+        loop {
+            let Some(&b) = self.text.get(self.pos) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escaped) = self.text.get(self.pos) else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.pos += 1;
+                    let c = match escaped {
+                        b'"' | b'\\' | b'/' => escaped as char,
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let high = self.hex4()?;
+                            let code = if (0xd800..0xdc00).contains(&high) {
+                                if !self.consume("\\u") {
+                                    return Err(self.error("unpaired surrogate"));
+                                }
+                                let low = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(self.error("unpaired surrogate"));
+                                }
+                                0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                            } else {
+                                high
+                            };
+                            char::from_u32(code).ok_or_else(|| self.error("unpaired surrogate"))?
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    s.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                0..0x20 => return Err(self.error("control character in string")),
+                _ => s.push(b),
+            }
+        }
+        // the text was a string, and escapes are pushed whole
+        Ok(String::from_utf8(s).unwrap())
+    }
+
+    fn hex4(&mut self) -> Result<u32, Error> {
+        let digits = self.text.get(self.pos..self.pos + 4).and_then(|digits| std::str::from_utf8(digits).ok());
+        let code = digits.and_then(|digits| u32::from_str_radix(digits, 16).ok()).ok_or_else(|| self.error("invalid escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+}
+
+fn push_string(bytes: &mut Vec<u8>, s: &str) {
+    bytes.push(STRING);
+    bytes.extend((s.len() as u32).to_le_bytes());
+    bytes.extend(s.as_bytes());
+}
+
+fn push_container(bytes: &mut Vec<u8>, tag: u8, items: Vec<Vec<u8>>) {
+    let offsets_len = items.len() * 4;
+    let size = offsets_len + items.iter().map(Vec::len).sum::<usize>();
+    bytes.push(tag);
+    bytes.extend((items.len() as u32).to_le_bytes());
+    bytes.extend((size as u32).to_le_bytes());
+    let mut offset = 0;
+    for item in &items {
+        bytes.extend((offset as u32).to_le_bytes());
+        offset += item.len();
+    }
+    bytes.extend(items.concat());
+}
+
+// Reading a value checks the bounds of what it reads, so that malformed bytes, of a corrupt page
+// say, are taken as a value which is there to be found no further into.
+
+fn u32_at(bytes: &[u8], pos: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(bytes.get(pos..pos.checked_add(4)?)?.try_into().unwrap()) as usize)
+}
+
+fn u64_at(bytes: &[u8], pos: usize) -> Option<[u8; 8]> {
+    bytes.get(pos..pos.checked_add(8)?)?.try_into().ok()
+}
+
+// The length of the value at the head of `bytes`.
+fn value_len(bytes: &[u8]) -> Option<usize> {
+    match *bytes.first()? {
+        NULL | FALSE | TRUE => Some(1),
+        INT | FLOAT => Some(9),
+        STRING => 5usize.checked_add(u32_at(bytes, 1)?),
+        ARRAY | OBJECT => 9usize.checked_add(u32_at(bytes, 5)?),
+        _ => None,
+    }
+}
+
+// The value at the head of `bytes`, its bytes alone.
+fn value_at(bytes: &[u8]) -> Option<&[u8]> {
+    bytes.get(..value_len(bytes)?)
+}
+
+// The `i`th item of the array or object at the head of `bytes`, with its count.
+fn item(bytes: &[u8], i: usize) -> Option<&[u8]> {
+    let body = 9usize.checked_add(u32_at(bytes, 1)?.checked_mul(4)?)?;
+    let offset = u32_at(bytes, 9 + i * 4)?;
+    bytes.get(body.checked_add(offset)?..value_len(bytes)?)
+}
+
+fn count(bytes: &[u8]) -> usize {
+    u32_at(bytes, 1).unwrap_or(0)
+}
+
+// The key and the value of a member of an object.
+fn member(item: &[u8]) -> Option<(&str, &[u8])> {
+    let len = u32_at(item, 0)?;
+    let key = std::str::from_utf8(item.get(4..4usize.checked_add(len)?)?).ok()?;
+    Some((key, value_at(&item[4 + len..])?))
+}
+
+// The value of the member `step` of an object, or the element at index `step` of an array,
+// counting back from the end if negative.
+pub fn get<'a>(bytes: &'a [u8], step: &str) -> Option<&'a [u8]> {
+    match *bytes.first()? {
+        ARRAY => {
+            let n = count(bytes) as i64;
+            let i = step.parse::<i64>().ok()?;
+            let i = if i < 0 { i + n } else { i };
+            if !(0..n).contains(&i) {
+                return None;
+            }
+            value_at(item(bytes, i as usize)?)
+        }
+        OBJECT => {
+            let (mut low, mut high) = (0, count(bytes));
+            while low < high {
+                let mid = (low + high) / 2;
+                let (key, value) = member(item(bytes, mid)?)?;
+                match key.cmp(step) {
+                    std::cmp::Ordering::Equal => return Some(value),
+                    std::cmp::Ordering::Less => low = mid + 1,
+                    std::cmp::Ordering::Greater => high = mid,
+                }
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+// The value a path of steps leads to, as `get` takes each.
+pub fn get_path<'a>(bytes: &'a [u8], steps: &[String]) -> Option<&'a [u8]> {
+    steps.iter().try_fold(bytes, |bytes, step| get(bytes, step))
+}
+
+// A value as text: a string as it is, null as NULL, others as their JSON text.
+pub fn to_text(bytes: &[u8]) -> Value {
+    match bytes.first() {
+        Some(&NULL) => Value::Null,
+        Some(&STRING) => match string(bytes) {
+            Some(s) => Value::Text(s.to_string()),
+            None => Value::Null,
+        },
+        _ => Value::Text(to_string(bytes)),
+    }
+}
+
+fn string(bytes: &[u8]) -> Option<&str> {
+    let len = u32_at(bytes, 1)?;
+    std::str::from_utf8(bytes.get(5..5usize.checked_add(len)?)?).ok()
+}
+
+// JSON text of a value, without whitespace.
+pub fn to_string(bytes: &[u8]) -> String {
+    let mut s = String::new();
+    write_value(&mut s, bytes).unwrap();
+    s
+}
+
+fn write_value(s: &mut String, bytes: &[u8]) -> fmt::Result {
+    match bytes.first() {
+        Some(&FALSE) => s.push_str("false"),
+        Some(&TRUE) => s.push_str("true"),
+        Some(&INT) => match u64_at(bytes, 1) {
+            Some(n) => write!(s, "{}", i64::from_le_bytes(n))?,
+            None => s.push_str("null"),
+        },
+        Some(&FLOAT) => match u64_at(bytes, 1) {
+            Some(n) => write!(s, "{:?}", f64::from_le_bytes(n))?,
+            None => s.push_str("null"),
+        },
+        Some(&STRING) => write_string(s, string(bytes).unwrap_or(""))?,
+        Some(&ARRAY) => {
+            s.push('[');
+            for i in 0..count(bytes) {
+                if i > 0 {
+                    s.push(',');
+                }
+                write_value(s, item(bytes, i).and_then(value_at).unwrap_or(&[NULL]))?;
+            }
+            s.push(']');
+        }
+        Some(&OBJECT) => {
+            s.push('{');
+            for i in 0..count(bytes) {
+                if i > 0 {
+                    s.push(',');
+                }
+                let (key, value) = item(bytes, i).and_then(member).unwrap_or(("", &[NULL]));
+                write_string(s, key)?;
+                s.push(':');
+                write_value(s, value)?;
+            }
+            s.push('}');
+        }
+        _ => s.push_str("null"),
+    }
+    Ok(())
+}
+
+fn write_string(s: &mut String, text: &str) -> fmt::Result {
+    s.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            '\r' => s.push_str("\\r"),
+            '\t' => s.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(s, "\\u{:04x}", c as u32)?,
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+    Ok(())
+}
+
+// The steps of a path as written for `#>`, an array of text: e.g. '{a,0,b}', or '{"a,b",c}' where
+// one has a comma, a brace, a quote or a backslash or space, quoted with `\` escaping inside.
+pub fn parse_path(text: &str) -> Option<Vec<String>> {
+    let inner = text.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let mut chars = inner.trim().chars().peekable();
+    let mut steps = vec![];
+    if chars.peek().is_none() {
+        return Some(steps);
+    }
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut step = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => step.push(chars.next()?),
+                    c => step.push(c),
+                }
+            }
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        } else {
+            while let Some(c) = chars.next_if(|c| !matches!(c, ',' | '{' | '}' | '"' | '\\')) {
+                step.push(c);
+            }
+            step.truncate(step.trim_end().len());
+            if step.is_empty() {
+                return None;
+            }
+        }
+        steps.push(step);
+        match chars.next() {
+            None => return Some(steps),
+            Some(',') => {}
+            Some(_) => return None,
+        }
+    }
+}
+
+fn write_step(f: &mut fmt::Formatter<'_>, step: &str) -> fmt::Result {
+    let plain = !step.is_empty() && !step.chars().any(|c| matches!(c, ',' | '{' | '}' | '"' | '\\') || c.is_whitespace());
+    match plain {
+        true => write!(f, "{}", step),
+        false => write!(f, "\"{}\"", step.replace('\\', "\\\\").replace('"', "\\\"")),
+    }
+}
+
+// What an expression index keys on, e.g. `doc #>> '{a,b}'`: the value at `steps` into the JSON
+// of a column, as text if `text`, SQL NULL if there's none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path {
+    pub steps: Vec<String>,
+    pub text: bool,
+}
+
+impl Path {
+    pub fn extract(&self, value: &Value) -> Value {
+        let Value::Json(bytes) = value else {
+            return Value::Null;
+        };
+        match get_path(bytes, &self.steps) {
+            Some(found) if self.text => to_text(found),
+            Some(found) => Value::Json(found.to_vec()),
+            None => Value::Null,
+        }
+    }
+}
+
+// As `#>` or `#>>` with the path, e.g. `#>> '{a,b}'`.
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} '{{", if self.text { "#>>" } else { "#>" })?;
+        for (i, step) in self.steps.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            // in the quotes of the literal
+            write_step(f, &step.replace('\'', "''"))?;
+        }
+        write!(f, "}}'")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let bytes = parse(r#" {"b": [1, -2.5, "x\n\u00e9\ud83d\ude00"], "a": {"c": null, "d": true}, "b": [false, 7, {}], "e": 1e2} "#).unwrap();
+        assert_eq!(r#"{"a":{"c":null,"d":true},"b":[false,7,{}],"e":100.0}"#, to_string(&bytes));
+        assert_eq!(bytes, parse(&to_string(&bytes)).unwrap());
+        assert_eq!(parse(r#"{"e": 100.0, "b": [false, 7, {}], "a": {"d": true, "c": null}}"#).unwrap(), bytes);
+        let s = parse(r#"["x\n\u00e9\ud83d\ude00\"", []]"#).unwrap();
+        assert_eq!(Value::Text("x\né😀\"".to_string()), to_text(get(&s, "0").unwrap()));
+        assert_eq!(r#"["x\né😀\"",[]]"#, to_string(&s));
+
+        // members are found by key and elements by index, from the end if negative
+        let path = |steps: &[&str]| steps.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(Some("7".to_string()), get_path(&bytes, &path(&["b", "1"])).map(to_string));
+        assert_eq!(Some("{}".to_string()), get_path(&bytes, &path(&["b", "-1"])).map(to_string));
+        assert_eq!(Some("true".to_string()), get_path(&bytes, &path(&["a", "d"])).map(to_string));
+        for missing in [&["z"][..], &["b", "3"], &["b", "-4"], &["b", "x"], &["e", "0"], &["a", "c", "d"]] {
+            assert_eq!(None, get_path(&bytes, &path(missing)));
+        }
+        assert_eq!(Value::Null, to_text(get(get(&bytes, "a").unwrap(), "c").unwrap()));
+        assert_eq!(Value::Text("{\"c\":null,\"d\":true}".to_string()), to_text(get(&bytes, "a").unwrap()));
+        let doc = Value::Json(bytes.clone());
+        assert_eq!(Value::Text("100.0".to_string()), Path { steps: path(&["e"]), text: true }.extract(&doc));
+        assert_eq!(Value::Json(parse("[false,7,{}]").unwrap()), Path { steps: path(&["b"]), text: false }.extract(&doc));
+        assert_eq!(Value::Null, Path { steps: path(&["b"]), text: false }.extract(&Value::Null));
+        assert_eq!(Some(path(&["a", "0"])), parse_path("{a, 0}"));
+        assert_eq!(Some(vec![]), parse_path("{}"));
+        assert_eq!(None, parse_path("a,0"));
+        assert_eq!(Some(path(&["a b", "", "x,\"}\\"])), parse_path(r#"{ a b , "", "x,\"}\\" }"#));
+        for invalid in ["{a,}", "{,}", "{a\"b}", "{\"a\" b}", "{\"a}", "{{a}}"] {
+            assert_eq!(None, parse_path(invalid), "{}", invalid);
+        }
+        assert_eq!("#>> '{a,0}'", Path { steps: path(&["a", "0"]), text: true }.to_string());
+        let odd = Path { steps: path(&["it's", "a b", "", "{\"\\"]), text: false };
+        assert_eq!(r#"#> '{it''s,"a b","","{\"\\"}'"#, odd.to_string());
+        assert_eq!(Some(odd.steps.clone()), parse_path(&odd.to_string()[3..].trim_matches('\'').replace("''", "'")));
+
+        for invalid in ["", "nul", "[1,]", "{\"a\" 1}", "{1: 2}", "01", "1.", "-", "\"\\x\"", "\"\\ud800\"", "\"a", "[1] 2", "1e999", "\"\u{1}\""] {
+            assert!(parse(invalid).is_err(), "{:?}", invalid);
+        }
+        assert!(parse(&"[".repeat(MAX_DEPTH + 1)).is_err());
+        assert!(parse(&format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH))).is_ok());
+        // malformed bytes are read as far as they go
+        for end in 0..bytes.len() {
+            to_string(&bytes[..end]);
+            get_path(&bytes[..end], &path(&["b", "1"]));
+        }
+    }
+}
@@ -1,2 +1,68 @@
+pub mod trace;
+pub mod storage;
 pub mod disk;
+pub mod temp;
+pub mod lz4;
+pub mod scram;
+pub mod tls;
+pub mod wal;
+pub mod pool;
 pub mod buffer;
+pub mod recovery;
+pub mod replication;
+pub mod raft;
+pub mod tuple;
+pub mod json;
+pub mod array;
+pub mod fulltext;
+pub mod geometry;
+pub mod numeric;
+pub mod datetime;
+pub mod mvcc;
+pub mod ssi;
+pub mod lock;
+pub mod btree;
+pub mod rtree;
+pub mod table;
+pub mod columnar;
+pub mod zonemap;
+pub mod dictionary;
+pub mod decoding;
+pub mod cdc;
+pub mod stats;
+pub mod partition;
+pub mod udf;
+pub mod trigger;
+pub mod catalog;
+pub mod check;
+pub mod information_schema;
+pub mod query;
+pub mod optimizer;
+pub mod planner;
+pub mod csv;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod sql;
+pub mod plan_cache;
+pub mod worker;
+pub mod config;
+pub mod database;
+pub mod slowlog;
+pub mod audit;
+pub mod statements;
+pub mod activity;
+pub mod sim;
+pub mod fuzz;
+pub mod bench;
+pub mod connection;
+#[cfg(feature = "tokio")]
+pub mod async_database;
+pub mod dump;
+pub mod inspect;
+pub mod waldump;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod repl;
+pub mod server;
@@ -0,0 +1,479 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::buffer::{self, BufferPoolManager};
+use crate::disk::PageId;
+use crate::wal::TxId;
+
+// Locking, the pessimistic alternative to reading snapshots: with it turned on, transactions lock
+// the rows they read shared and those they write exclusive, holding the locks until they end
+// (strict two-phase locking), and read the newest committed versions. Tables are locked too, with
+// the intention of locking rows of them before doing so, so that a table lock only has to be
+// checked against other table locks: shared by scans, which then needn't lock the rows, and
+// exclusive by DDL changing what's in them. Read-only transactions read snapshots instead, taking
+// no locks.
+//
+// Transactions are run one at a time, so one can't wait for a lock: the request is queued and
+// fails with `Error::Wait`, to be made again once the holders have ended, e.g. after switching to
+// them. Requests are granted in the order they're queued, except upgrades, which go first.
+//
+// A request which would make transactions wait for each other in a cycle isn't queued, failing
+// with `Error::Deadlock` instead: the transaction making it is the victim, to be rolled back.
+// One made again after the lock timeout of the transaction has passed since it was queued is
+// withdrawn, failing with `Error::Timeout`, as is one made again once the statement making it has
+// been canceled (see `CancelToken`), failing with `buffer::Error::Canceled`.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error("waiting for a lock held by another transaction")]
+    Wait,
+    #[error("deadlock detected")]
+    Deadlock,
+    #[error("lock wait timed out after {0:?}")]
+    Timeout(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Resource {
+    // changed by DDL, by one transaction at a time (see `lock_catalog`)
+    Catalog,
+    // by the meta page of its btree
+    Table(PageId),
+    // by the primary key of the row, which tables are clustered on
+    Row(PageId, Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    // of tables, by those which are to lock rows of them in the mode
+    IntentionShared,
+    IntentionExclusive,
+    Shared,
+    // shared, with the intention of locking rows exclusive
+    SharedIntentionExclusive,
+    Exclusive,
+}
+
+impl LockMode {
+    fn is_compatible(self, other: LockMode) -> bool {
+        use LockMode::*;
+        match (self, other) {
+            (Exclusive, _) | (_, Exclusive) => false,
+            (IntentionShared, _) | (_, IntentionShared) => true,
+            (IntentionExclusive, IntentionExclusive) | (Shared, Shared) => true,
+            _ => false,
+        }
+    }
+
+    // The weakest mode which is at least as strong as both.
+    fn combine(self, other: LockMode) -> LockMode {
+        use LockMode::*;
+        match (self, other) {
+            (Exclusive, _) | (_, Exclusive) => Exclusive,
+            (mode, IntentionShared) | (IntentionShared, mode) => mode,
+            (mode, other) if mode == other => mode,
+            _ => SharedIntentionExclusive,
+        }
+    }
+
+    fn covers(self, other: LockMode) -> bool {
+        self.combine(other) == self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LockManager {
+    queues: BTreeMap<Resource, LockQueue>,
+    // what each transaction holds, and what it's waiting for since when
+    held: BTreeMap<TxId, BTreeSet<Resource>>,
+    waiting: BTreeMap<TxId, (Resource, Instant)>,
+}
+
+#[derive(Debug, Default)]
+struct LockQueue {
+    granted: BTreeMap<TxId, LockMode>,
+    waiting: VecDeque<(TxId, LockMode)>,
+}
+
+impl LockQueue {
+    fn can_grant(&self, txid: TxId, mode: LockMode) -> bool {
+        self.granted.iter().all(|(&holder, &held)| holder == txid || held.is_compatible(mode))
+    }
+}
+
+impl LockManager {
+    // Locks `resource` for `txid` in `mode`, returning whether it's granted. If not, the request
+    // is queued, and granted when the locks in the way are released, unless that would deadlock.
+    pub fn acquire(&mut self, txid: TxId, resource: &Resource, mode: LockMode) -> Result<bool, Error> {
+        match self.waiting.get(&txid) {
+            Some((waited, _)) if waited == resource => return Ok(false),
+            // a request made instead of the one waiting replaces it
+            Some(_) => {
+                self.cancel(txid);
+            }
+            None => {}
+        }
+        let queue = self.queues.entry(resource.clone()).or_default();
+        let held = queue.granted.get(&txid).copied();
+        if held.is_some_and(|held| held.covers(mode)) {
+            return Ok(true);
+        }
+        // an upgrade to a mode covering both
+        let mode = held.map_or(mode, |held| held.combine(mode));
+        if queue.can_grant(txid, mode) && (held.is_some() || queue.waiting.is_empty()) {
+            queue.granted.insert(txid, mode);
+            self.held.entry(txid).or_default().insert(resource.clone());
+            return Ok(true);
+        }
+        if held.is_some() {
+            queue.waiting.push_front((txid, mode));
+        } else {
+            queue.waiting.push_back((txid, mode));
+        }
+        self.waiting.insert(txid, (resource.clone(), Instant::now()));
+        if self.is_deadlocked(txid) {
+            self.withdraw(txid);
+            self.grant_queued(resource);
+            return Err(Error::Deadlock);
+        }
+        Ok(false)
+    }
+
+    // The transactions `txid` waits for: the holders of locks incompatible with its request, and
+    // those queued before it with incompatible requests.
+    fn waits_for(&self, txid: TxId) -> Vec<TxId> {
+        let Some((resource, _)) = self.waiting.get(&txid) else {
+            return vec![];
+        };
+        let queue = &self.queues[resource];
+        let position = queue.waiting.iter().position(|&(waiter, _)| waiter == txid).unwrap();
+        let mode = queue.waiting[position].1;
+        let holders = queue.granted.iter().map(|(&holder, &held)| (holder, held));
+        let ahead = queue.waiting.iter().take(position).copied();
+        holders
+            .chain(ahead)
+            .filter(|&(other, other_mode)| other != txid && !other_mode.is_compatible(mode))
+            .map(|(other, _)| other)
+            .collect()
+    }
+
+    // Whether `txid` waits for itself through others in the waits-for graph.
+    fn is_deadlocked(&self, txid: TxId) -> bool {
+        let mut visited = BTreeSet::new();
+        let mut stack = self.waits_for(txid);
+        while let Some(other) = stack.pop() {
+            if other == txid {
+                return true;
+            }
+            if visited.insert(other) {
+                stack.extend(self.waits_for(other));
+            }
+        }
+        false
+    }
+
+    // Whether `txid` holds `resource` in a mode covering `mode`.
+    pub fn holds(&self, txid: TxId, resource: &Resource, mode: LockMode) -> bool {
+        self.queues
+            .get(resource)
+            .and_then(|queue| queue.granted.get(&txid))
+            .is_some_and(|held| held.covers(mode))
+    }
+
+    // Releases the locks of `txid` and withdraws its request, granting those queued which can be
+    // now. Returns the transactions granted them.
+    pub fn release(&mut self, txid: TxId) -> Vec<TxId> {
+        let mut resources = self.held.remove(&txid).unwrap_or_default();
+        resources.extend(self.withdraw(txid));
+        let mut granted = vec![];
+        for resource in resources {
+            self.queues.get_mut(&resource).unwrap().granted.remove(&txid);
+            granted.extend(self.grant_queued(&resource));
+        }
+        granted
+    }
+
+    // Grants the requests at the front of the queue of `resource` which can be, returning by whom.
+    fn grant_queued(&mut self, resource: &Resource) -> Vec<TxId> {
+        let queue = self.queues.get_mut(resource).unwrap();
+        let mut granted = vec![];
+        while let Some(&(waiter, mode)) = queue.waiting.front() {
+            if !queue.can_grant(waiter, mode) {
+                break;
+            }
+            queue.waiting.pop_front();
+            queue.granted.insert(waiter, mode);
+            self.held.entry(waiter).or_default().insert(resource.clone());
+            self.waiting.remove(&waiter);
+            granted.push(waiter);
+        }
+        if queue.granted.is_empty() && queue.waiting.is_empty() {
+            self.queues.remove(resource);
+        }
+        granted
+    }
+
+    // How long `txid` has been waiting for a lock, if it is.
+    pub fn waited(&self, txid: TxId) -> Option<Duration> {
+        self.waiting.get(&txid).map(|(_, since)| since.elapsed())
+    }
+
+    // Withdraws the request `txid` is waiting on, if any, granting those which can be now.
+    pub fn cancel(&mut self, txid: TxId) -> Vec<TxId> {
+        match self.withdraw(txid) {
+            Some(resource) => self.grant_queued(&resource),
+            None => vec![],
+        }
+    }
+
+    // Withdraws the request `txid` is waiting on, if any, returning what it's for.
+    fn withdraw(&mut self, txid: TxId) -> Option<Resource> {
+        let (resource, _) = self.waiting.remove(&txid)?;
+        self.queues.get_mut(&resource).unwrap().waiting.retain(|&(waiter, _)| waiter != txid);
+        Some(resource)
+    }
+}
+
+// Locks `resource` for the current transaction if locking is turned on and it may write, failing if it has to wait,
+// would deadlock or has waited too long.
+pub fn lock(bufmgr: &mut BufferPoolManager, resource: Resource, mode: LockMode) -> Result<(), Error> {
+    if !bufmgr.locking() || bufmgr.read_only() {
+        return Ok(());
+    }
+    acquire(bufmgr, resource, mode)
+}
+
+// Locks the catalog exclusive for the current transaction, which is about to change it, whether
+// locking is turned on or not: changes to the catalog are undone in place when a transaction
+// rolls back, undoing those made to the same pages by any other since, so only one transaction
+// at a time may have changes to it not committed.
+pub fn lock_catalog(bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+    acquire(bufmgr, Resource::Catalog, LockMode::Exclusive)
+}
+
+// Whether the current transaction has locked the catalog, and so may have changed it.
+pub fn holds_catalog(bufmgr: &mut BufferPoolManager) -> bool {
+    let Some(txid) = bufmgr.current_txid() else {
+        return false;
+    };
+    bufmgr.locks().holds(txid, &Resource::Catalog, LockMode::Exclusive)
+}
+
+fn acquire(bufmgr: &mut BufferPoolManager, resource: Resource, mode: LockMode) -> Result<(), Error> {
+    let Some(txid) = bufmgr.txid()? else {
+        return Ok(());
+    };
+    if bufmgr.locks().acquire(txid, &resource, mode)? {
+        return Ok(());
+    }
+    if bufmgr.is_canceled() {
+        bufmgr.locks().cancel(txid);
+        return Err(buffer::Error::Canceled.into());
+    }
+    if let Some(timeout) = bufmgr.lock_timeout() {
+        if bufmgr.locks().waited(txid).unwrap() >= timeout {
+            bufmgr.locks().cancel(txid);
+            return Err(Error::Timeout(timeout));
+        }
+    }
+    Err(Error::Wait)
+}
+
+// Locks the row of `table` with `pkey`, shared or exclusive, having locked the table with the
+// intention to. The row isn't locked if the table is in a mode covering that already.
+pub fn lock_row(bufmgr: &mut BufferPoolManager, table: PageId, pkey: &[u8], mode: LockMode) -> Result<(), Error> {
+    let intention = match mode {
+        LockMode::Shared => LockMode::IntentionShared,
+        _ => LockMode::IntentionExclusive,
+    };
+    lock(bufmgr, Resource::Table(table), intention)?;
+    if let Some(txid) = bufmgr.current_txid() {
+        if bufmgr.locks().holds(txid, &Resource::Table(table), mode) {
+            return Ok(());
+        }
+    }
+    lock(bufmgr, Resource::Row(table, pkey.to_vec()), mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::table::{self, IndexKind, Table};
+    use crate::tuple::Value;
+    use crate::wal::{Wal, DEFAULT_SEGMENT_SIZE};
+    use tempfile::{tempdir, tempfile};
+
+    fn is_wait<T>(result: Result<T, table::Error>) -> bool {
+        matches!(result, Err(table::Error::Lock(Error::Wait)))
+    }
+
+    #[test]
+    fn test() {
+        use LockMode::*;
+        let mut locks = LockManager::default();
+        let (table, row) = (Resource::Table(PageId(1)), Resource::Row(PageId(1), vec![1]));
+        assert!(locks.acquire(4, &table, Exclusive).unwrap() && locks.holds(4, &table, Shared));
+        assert!(locks.acquire(1, &row, Shared).unwrap());
+        assert!(locks.acquire(2, &row, Shared).unwrap());
+        assert!(!locks.acquire(3, &row, Exclusive).unwrap());
+        // queued behind the exclusive request
+        assert!(!locks.acquire(4, &row, Shared).unwrap());
+        // an upgrade waits for the other holders only
+        assert!(!locks.acquire(2, &row, Exclusive).unwrap());
+        assert_eq!(vec![2], locks.release(1));
+        assert!(locks.holds(2, &row, Exclusive) && locks.acquire(2, &row, Shared).unwrap());
+        assert_eq!(vec![3], locks.release(2));
+        assert_eq!(vec![4], locks.release(3));
+        assert!(locks.holds(4, &row, Shared) && locks.holds(4, &table, Exclusive));
+        // release withdraws a queued request too
+        assert!(!locks.acquire(5, &table, Shared).unwrap());
+        assert!(locks.release(5).is_empty() && locks.release(4).is_empty());
+        assert!(locks.queues.is_empty() && locks.held.is_empty() && locks.waiting.is_empty());
+
+        // three waiting for each other in a cycle
+        let rows: Vec<_> = (0..3).map(|i| Resource::Row(PageId(1), vec![i])).collect();
+        for (txid, row) in (1..).zip(&rows) {
+            assert!(locks.acquire(txid, row, Exclusive).unwrap());
+        }
+        assert!(!locks.acquire(1, &rows[1], Shared).unwrap());
+        assert!(!locks.acquire(2, &rows[2], Shared).unwrap());
+        assert!(matches!(locks.acquire(3, &rows[0], Shared), Err(Error::Deadlock)));
+        // the victim isn't left waiting, so the others go on when it's rolled back
+        assert_eq!(vec![2], locks.release(3));
+        assert_eq!(vec![1], locks.release(2));
+        locks.release(1);
+        // two upgrading the lock they share
+        assert!(locks.acquire(1, &row, Shared).unwrap() && locks.acquire(2, &row, Shared).unwrap());
+        assert!(!locks.acquire(1, &row, Exclusive).unwrap());
+        assert!(matches!(locks.acquire(2, &row, Exclusive), Err(Error::Deadlock)));
+        assert!(locks.holds(2, &row, Shared));
+        assert_eq!(vec![1], locks.release(2));
+        // a request made while waiting replaces the one waiting
+        assert!(!locks.acquire(3, &row, Shared).unwrap());
+        assert!(locks.acquire(3, &table, Shared).unwrap());
+        // and deadlocks like any other
+        assert!(!locks.acquire(1, &table, Exclusive).unwrap());
+        assert!(matches!(locks.acquire(3, &row, Shared), Err(Error::Deadlock)));
+        locks.release(3);
+        locks.release(1);
+
+        // intention locks go with each other, but not with table locks covering what they're for
+        let table = Resource::Table(PageId(2));
+        assert!(locks.acquire(1, &table, IntentionExclusive).unwrap() && locks.acquire(2, &table, IntentionShared).unwrap());
+        assert!(locks.acquire(3, &table, IntentionExclusive).unwrap());
+        assert!(!locks.acquire(4, &table, Shared).unwrap());
+        assert!(locks.release(1).is_empty());
+        assert_eq!(vec![4], locks.release(3));
+        assert!(!locks.acquire(2, &table, IntentionExclusive).unwrap());
+        assert!(locks.acquire(4, &table, IntentionExclusive).unwrap() && locks.holds(4, &table, SharedIntentionExclusive));
+        assert!(!locks.holds(4, &table, Exclusive));
+        assert_eq!(vec![2], locks.release(4));
+        assert!(locks.holds(2, &table, IntentionShared) && !locks.holds(2, &table, Shared));
+        locks.release(2);
+        assert!(locks.queues.is_empty() && locks.held.is_empty() && locks.waiting.is_empty());
+
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let wal_dir = tempdir().unwrap();
+        let wal = Wal::open(wal_dir.path(), DEFAULT_SEGMENT_SIZE).unwrap();
+        let mut bufmgr = BufferPoolManager::with_wal(disk, BufferPool::new(8), wal).unwrap();
+        bufmgr.set_locking(true);
+        let table = Table::create(&mut bufmgr, 1).unwrap();
+        let row = |id: i64, name: &str| vec![Value::Int(id), Value::Text(name.to_string())];
+        table.insert(&mut bufmgr, &row(1, "a")).unwrap();
+        table.insert(&mut bufmgr, &row(2, "b")).unwrap();
+        bufmgr.commit().unwrap();
+
+        // a scan locks the whole table, waiting for those writing rows of it
+        table.update(&mut bufmgr, &row(2, "c")).unwrap();
+        let writer = bufmgr.switch(Default::default());
+        assert!(is_wait(table.scan(&mut bufmgr)));
+        let reader = bufmgr.switch(writer);
+        bufmgr.abort().unwrap();
+        bufmgr.switch(reader);
+        let mut iter = table.scan(&mut bufmgr).unwrap();
+        assert_eq!(Some(row(1, "a")), iter.next(&mut bufmgr).unwrap());
+        // and rows can't be written until it ends
+        let reader = bufmgr.switch(Default::default());
+        assert!(is_wait(table.update(&mut bufmgr, &row(1, "d"))));
+        let writer = bufmgr.switch(reader);
+        assert_eq!(Some(row(2, "b")), iter.next(&mut bufmgr).unwrap());
+        assert_eq!(None, iter.next(&mut bufmgr).unwrap());
+        // other than by itself, upgrading the lock
+        table.update(&mut bufmgr, &row(2, "c")).unwrap();
+        let (txid, resource) = (bufmgr.current_txid().unwrap(), Resource::Table(table.btree.meta_page_id));
+        assert!(bufmgr.locks().holds(txid, &resource, LockMode::SharedIntentionExclusive));
+        bufmgr.abort().unwrap();
+        bufmgr.switch(writer);
+        table.update(&mut bufmgr, &row(1, "d")).unwrap();
+        bufmgr.abort().unwrap();
+
+        // a committed version is read even though it's newer than the reader
+        let lookup = |bufmgr: &mut BufferPoolManager, id: i64| table.lookup(bufmgr, table::Access::PrimaryKey, &[Value::Int(id)]);
+        assert_eq!(vec![row(2, "b")], lookup(&mut bufmgr, 2).unwrap());
+        let reader = bufmgr.switch(Default::default());
+        table.update(&mut bufmgr, &row(1, "e")).unwrap();
+        let writer = bufmgr.switch(reader);
+        assert!(is_wait(lookup(&mut bufmgr, 1)));
+        let reader = bufmgr.switch(writer);
+        bufmgr.commit().unwrap();
+        bufmgr.switch(reader);
+        assert_eq!(vec![row(1, "e")], lookup(&mut bufmgr, 1).unwrap());
+        bufmgr.commit().unwrap();
+
+        // indexes can't be created while the table is scanned
+        let mut iter = table.scan(&mut bufmgr).unwrap();
+        iter.next(&mut bufmgr).unwrap();
+        let reader = bufmgr.switch(Default::default());
+        let mut indexed = table.clone();
+        assert!(is_wait(indexed.create_index(&mut bufmgr, vec![1], vec![], IndexKind::BTree)));
+        let creator = bufmgr.switch(reader);
+        bufmgr.commit().unwrap();
+        bufmgr.switch(creator);
+        indexed.create_index(&mut bufmgr, vec![1], vec![], IndexKind::BTree).unwrap();
+        bufmgr.commit().unwrap();
+
+        // updating rows in opposite orders
+        table.update(&mut bufmgr, &row(1, "f")).unwrap();
+        let first = bufmgr.switch(Default::default());
+        table.update(&mut bufmgr, &row(2, "g")).unwrap();
+        let second = bufmgr.switch(first);
+        assert!(is_wait(table.update(&mut bufmgr, &row(2, "h"))));
+        let first = bufmgr.switch(second);
+        let err = table.update(&mut bufmgr, &row(1, "i")).unwrap_err();
+        assert!(matches!(err, table::Error::Lock(Error::Deadlock)), "{:?}", err);
+        bufmgr.abort().unwrap();
+        bufmgr.switch(first);
+        table.update(&mut bufmgr, &row(2, "h")).unwrap();
+        bufmgr.commit().unwrap();
+        assert_eq!(vec![row(1, "f"), row(2, "h")], table.lookup(&mut bufmgr, table::Access::PrimaryKey, &[]).unwrap());
+
+        // with a lock timeout, a request made again after it fails and is withdrawn
+        table.update(&mut bufmgr, &row(1, "j")).unwrap();
+        let writer = bufmgr.switch(Default::default());
+        bufmgr.set_lock_timeout(Some(Duration::from_secs(3600)));
+        assert!(is_wait(lookup(&mut bufmgr, 1)));
+        bufmgr.set_lock_timeout(Some(Duration::ZERO));
+        let err = lookup(&mut bufmgr, 1).unwrap_err();
+        assert!(matches!(err, table::Error::Lock(Error::Timeout(Duration::ZERO))), "{:?}", err);
+        let txid = bufmgr.current_txid().unwrap();
+        assert_eq!(None, bufmgr.locks().waited(txid));
+        bufmgr.abort().unwrap();
+        // as is one made again once the statement has been canceled
+        let token = buffer::CancelToken::default();
+        bufmgr.set_cancel_token(token.clone());
+        let resource = Resource::Table(table.btree.meta_page_id);
+        assert!(matches!(lock(&mut bufmgr, resource.clone(), LockMode::Shared), Err(Error::Wait)));
+        token.cancel();
+        let err = lock(&mut bufmgr, resource, LockMode::Shared).unwrap_err();
+        assert!(matches!(err, Error::Buffer(buffer::Error::Canceled)), "{:?}", err);
+        let txid = bufmgr.current_txid().unwrap();
+        assert_eq!(None, bufmgr.locks().waited(txid));
+        bufmgr.abort().unwrap();
+        bufmgr.switch(writer);
+        bufmgr.commit().unwrap();
+    }
+}
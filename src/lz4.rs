@@ -0,0 +1,143 @@
+// The LZ4 block format: a sequence of literals copied as they are, each followed by a match,
+// copied from up to 64KiB back in the output, except the last one.
+//   [token: u8][literals length][literals][offset: u16][match length]
+// where the token holds the two lengths, the match length less MIN_MATCH, one in each nibble, and
+// those of 15 or more continue in bytes after the token or the offset, each added until one isn't 255.
+
+const MIN_MATCH: usize = 4;
+// The last match starts at least this far from the end, and the last bytes are always literals.
+const MF_LIMIT: usize = 12;
+const LAST_LITERALS: usize = 5;
+const HASH_BITS: u32 = 12;
+// No block decompresses to more than this many bytes for each of its own, a byte of a length
+// adding at most 255.
+const MAX_RATIO: usize = 255;
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    // 1 + the last position of each hashed 4 bytes, 0 if none
+    let mut table = vec![0; 1 << HASH_BITS];
+    let (mut anchor, mut pos) = (0, 0);
+    let limit = input.len().saturating_sub(MF_LIMIT);
+    while pos < limit {
+        let seq = u32::from_le_bytes(input[pos..pos + 4].try_into().unwrap());
+        let hash = (seq.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize;
+        let candidate = std::mem::replace(&mut table[hash], pos + 1);
+        if candidate == 0 || pos - (candidate - 1) > u16::MAX as usize || input[candidate - 1..candidate + 3] != input[pos..pos + 4] {
+            pos += 1;
+            continue;
+        }
+        let candidate = candidate - 1;
+        let mut len = MIN_MATCH;
+        while pos + len < input.len() - LAST_LITERALS && input[candidate + len] == input[pos + len] {
+            len += 1;
+        }
+        write_sequence(&mut out, &input[anchor..pos], Some((pos - candidate, len)));
+        pos += len;
+        anchor = pos;
+    }
+    write_sequence(&mut out, &input[anchor..], None);
+    out
+}
+
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push((literals.len().min(15) as u8) << 4 | match_len.min(15) as u8);
+    write_length(out, literals.len());
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        write_length(out, match_len);
+    }
+}
+
+fn write_length(out: &mut Vec<u8>, len: usize) {
+    if len >= 15 {
+        let mut rest = len - 15;
+        while rest >= 255 {
+            out.push(255);
+            rest -= 255;
+        }
+        out.push(rest as u8);
+    }
+}
+
+// Decompresses `input` into `len` bytes, None if it doesn't. A `len` the block couldn't make, as
+// read from a corrupt header, is refused before anything is allocated for it.
+pub fn decompress(input: &[u8], len: usize) -> Option<Vec<u8>> {
+    if len > input.len().saturating_mul(MAX_RATIO) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(len);
+    let mut pos = 0;
+    loop {
+        let token = *input.get(pos)?;
+        pos += 1;
+        let literals = read_length(input, &mut pos, (token >> 4) as usize)?;
+        out.extend_from_slice(input.get(pos..pos.checked_add(literals)?)?);
+        pos += literals;
+        if pos == input.len() {
+            break;
+        }
+        let offset = u16::from_le_bytes(input.get(pos..pos + 2)?.try_into().unwrap()) as usize;
+        pos += 2;
+        let match_len = read_length(input, &mut pos, (token & 15) as usize)? + MIN_MATCH;
+        if offset == 0 || offset > out.len() || out.len() + match_len > len {
+            return None;
+        }
+        // the match may overlap what it copies, repeating it
+        let start = out.len() - offset;
+        for i in start..start + match_len {
+            out.push(out[i]);
+        }
+    }
+    (out.len() == len).then_some(out)
+}
+
+fn read_length(input: &[u8], pos: &mut usize, mut len: usize) -> Option<usize> {
+    if len == 15 {
+        loop {
+            let byte = *input.get(*pos)?;
+            *pos += 1;
+            len += byte as usize;
+            if byte != 255 {
+                break;
+            }
+        }
+    }
+    Some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let mut text = b"abcdefgh".repeat(100);
+        text.extend((0..=255u8).cycle().take(1000).map(|b| b.wrapping_mul(31)));
+        text.extend(vec![0; 5000]);
+        let inputs: Vec<Vec<u8>> = vec![vec![], vec![1], b"hello, world".to_vec(), vec![7; 20], text];
+        for input in inputs {
+            let compressed = compress(&input);
+            assert_eq!(Some(input.clone()), decompress(&compressed, input.len()));
+            assert_eq!(None, decompress(&compressed, input.len() + 1));
+        }
+        // repetitive bytes compress well, and the rest hardly grow
+        assert!(compress(&[0; 4000]).len() < 40);
+        let mut x = 1u64;
+        let noise: Vec<u8> = (0..4000)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect();
+        assert!(compress(&noise).len() < noise.len() + noise.len() / 100 + 16);
+        // garbage fails to decompress rather than panicking
+        assert_eq!(None, decompress(&[0xf0, 0xff], 10));
+        assert_eq!(None, decompress(&[0x1f, 1, 0, 0], 30));
+        assert_eq!(None, decompress(&compress(b"hello, world"), usize::MAX));
+    }
+}
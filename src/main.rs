@@ -1,3 +1,270 @@
-fn main() {
-    println!("Hello, world!");
+use std::fs::File;
+use std::io::{self, BufReader, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use beyond_rdb::bench::{self, Workload};
+use beyond_rdb::buffer::Durability;
+use beyond_rdb::config::Config;
+use beyond_rdb::database::{Database, WAL_DIR};
+use beyond_rdb::disk::PageId;
+use beyond_rdb::inspect::Inspector;
+use beyond_rdb::repl::Repl;
+use beyond_rdb::server::Server;
+use beyond_rdb::tls::{self, ServerConfig};
+use beyond_rdb::waldump::{self, Filter};
+
+const USAGE: &str = "usage: beyond_rdb [--config <file>] <database directory> [script | --listen <address> [--tls-cert <file> --tls-key <file> [--tls-client-ca <file> [--tls-client-cert-required]] [--tls-required]] | --import-sqlite <file> | --inspect [page] | --waldump [--txid <id>] [--page <id>] [--from <lsn>] | --bench <workload> [--records <n>] [--operations <n>] [--clients <n>] [--threads <n>] [--pool <pages>] [--durability sync|async]]";
+
+// Opens the database in the directory, creating it if there's none, then runs the script if
+// given, or serves clients of the PostgreSQL protocol on the address, over TLS for those asking
+// for SSL if given the certificate and key to, or imports the tables of the SQLite database, or
+// else runs the lines read from the standard input, prompting for them on a terminal. With
+// --inspect, prints the pages of the database, or everything on the one given, without opening
+// it. With --waldump, prints the records of its log, or of the segments in the directory if it's
+// an archive of them. With --bench, runs a workload (ycsb-a, ycsb-b, ycsb-c, ycsb-e or tpcb) in
+// a database which doesn't have its tables, printing how fast it went, its clients being
+// sessions taking turns in this thread, or connections to a server of the database taking turns
+// in each of the threads given with --threads. The database is opened as configured in the TOML
+// file given with --config (see `Config`).
+fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut config = Config::default();
+    if args.first().is_some_and(|arg| arg == "--config") {
+        let Some(file) = args.get(1) else {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        };
+        config = match Config::load(file) {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("cannot load {}: {}", file, err);
+                return ExitCode::FAILURE;
+            }
+        };
+        args.drain(..2);
+    }
+    match args.as_slice() {
+        [dir, flag] if flag == "--inspect" => return inspect(dir, None),
+        [dir, flag, page] if flag == "--inspect" => return inspect(dir, Some(page)),
+        [dir, flag, options @ ..] if flag == "--waldump" => return waldump(dir, options),
+        [dir, flag, workload, options @ ..] if flag == "--bench" => return bench(config, dir, workload, options),
+        _ => {}
+    }
+    let (dir, script, listen, sqlite) = match args.as_slice() {
+        [dir] => (dir, None, None, None),
+        [dir, flag, addr, options @ ..] if flag == "--listen" => (dir, None, Some((addr, options)), None),
+        [dir, flag, file] if flag == "--import-sqlite" => (dir, None, None, Some(file)),
+        [dir, script] if !script.starts_with("--") => (dir, Some(script), None, None),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    let limits = config.server.clone();
+    let db = match Database::builder().config(config).open(dir) {
+        Ok(db) => db,
+        Err(err) => {
+            eprintln!("cannot open {}: {}", dir, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Some(file) = sqlite {
+        return import_sqlite(&db, file);
+    }
+    if let Some((addr, options)) = listen {
+        let result = tls_config(options)
+            .and_then(|tls| {
+                match tls {
+                    Some(tls) => Server::bind_tls(db, addr.as_str(), tls),
+                    None => Server::bind(db, addr.as_str()),
+                }
+                .map_err(|err| err.to_string())
+            })
+            .map(|server| server.with_limits(limits))
+            .and_then(|mut server| {
+                eprintln!("listening on {}", server.local_addr());
+                server.serve().map_err(|err| err.to_string())
+            });
+        return match result {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("{}", err);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    let mut repl = Repl::new(db, io::stdout());
+    let result = match script {
+        Some(script) => File::open(script).and_then(|file| repl.run(BufReader::new(file), false)),
+        None => {
+            let stdin = io::stdin();
+            let interactive = stdin.is_terminal();
+            repl.run(stdin.lock(), interactive)
+        }
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn inspect(dir: &str, page: Option<&String>) -> ExitCode {
+    let page_id = match page.map(|page| page.parse()).transpose() {
+        Ok(page_id) => page_id.map(PageId),
+        Err(_) => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    let result = Inspector::open(dir).and_then(|mut inspector| match page_id {
+        Some(page_id) => inspector.page_info(page_id),
+        None => inspector.summary(),
+    });
+    match result {
+        Ok(output) => {
+            print!("{}", output);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("cannot inspect {}: {}", dir, err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn waldump(dir: &str, options: &[String]) -> ExitCode {
+    let mut filter = Filter::default();
+    for option in options.chunks(2) {
+        let value = option.get(1).and_then(|value| value.parse().ok());
+        match (option[0].as_str(), value) {
+            ("--txid", Some(txid)) => filter.txid = Some(txid),
+            ("--page", Some(page_id)) => filter.page_id = Some(PageId(page_id)),
+            ("--from", Some(lsn)) => filter.start = Some(lsn),
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let wal_dir = Path::new(dir).join(WAL_DIR);
+    let wal_dir = if wal_dir.is_dir() { wal_dir } else { PathBuf::from(dir) };
+    match waldump::dump(&wal_dir, &mut io::stdout().lock(), &filter) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("cannot dump the log in {}: {}", wal_dir.display(), err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// The TLS the server is configured with by the options after --listen, None without any.
+fn tls_config(options: &[String]) -> Result<Option<ServerConfig>, String> {
+    let (mut cert, mut key, mut client_ca) = (None, None, None);
+    let (mut client_cert_required, mut required) = (false, false);
+    let mut options = options.iter().map(String::as_str);
+    while let Some(option) = options.next() {
+        match option {
+            "--tls-cert" => cert = options.next(),
+            "--tls-key" => key = options.next(),
+            "--tls-client-ca" => client_ca = options.next(),
+            "--tls-client-cert-required" => client_cert_required = true,
+            "--tls-required" => required = true,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let (cert, key) = match (cert, key) {
+        (Some(cert), Some(key)) => (cert, key),
+        (None, None) if client_ca.is_none() && !client_cert_required && !required => return Ok(None),
+        _ => return Err(USAGE.to_string()),
+    };
+    let loaded = std::fs::read_to_string(cert)
+        .map_err(tls::Error::from)
+        .and_then(|pem| tls::certificates_from_pem(&pem))
+        .and_then(|certificates| Ok((certificates, tls::private_key_from_pem(&std::fs::read_to_string(key)?)?)));
+    let (certificates, key) = loaded.map_err(|err| format!("cannot load {} and {}: {}", cert, key, err))?;
+    let config = match client_ca {
+        Some(file) => {
+            let cas = std::fs::read_to_string(file).map_err(tls::Error::from).and_then(|pem| tls::certificates_from_pem(&pem));
+            let cas = cas.map_err(|err| format!("cannot load {}: {}", file, err))?;
+            ServerConfig::with_client_cas(certificates, key, cas, client_cert_required)
+        }
+        None if client_cert_required => return Err(USAGE.to_string()),
+        None => ServerConfig::new(certificates, key),
+    };
+    let config = config.map_err(|err| err.to_string())?;
+    Ok(Some(if required { config.require_tls() } else { config }))
+}
+
+fn bench(mut config: Config, dir: &str, workload: &str, options: &[String]) -> ExitCode {
+    let Some(workload) = Workload::from_name(workload) else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let mut bench_options = bench::Options { workload, durability: config.session.durability, ..bench::Options::default() };
+    for option in options.chunks(2) {
+        let value = option.get(1).map(String::as_str);
+        let number = value.and_then(|value| value.parse().ok());
+        match (option[0].as_str(), number, value) {
+            ("--records", Some(n), _) => bench_options.records = n,
+            ("--operations", Some(n), _) => bench_options.operations = n,
+            ("--clients", Some(n), _) if n > 0 => bench_options.clients = n,
+            ("--threads", Some(n), _) if n > 0 => bench_options.threads = n,
+            ("--pool", Some(n), _) if n > 0 => config.pool_size = n,
+            ("--durability", _, Some("sync")) => bench_options.durability = Durability::Sync,
+            ("--durability", _, Some("async")) => bench_options.durability = Durability::Async,
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let result = Database::builder().config(config).open(dir).map_err(|err| err.to_string()).and_then(|db| {
+        let report = bench::run(&db, &bench_options).map_err(|err| err.to_string())?;
+        db.close().map_err(|err| err.to_string())?;
+        Ok(report)
+    });
+    match result {
+        Ok(report) => {
+            println!("{}", report);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("cannot run {} in {}: {}", workload.name(), dir, err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn import_sqlite(db: &Database, file: &str) -> ExitCode {
+    let result = File::open(file).map_err(|err| err.to_string()).and_then(|file| {
+        beyond_rdb::sqlite::import(db, BufReader::new(file)).map_err(|err| err.to_string())
+    });
+    match result {
+        Ok(import) => {
+            for warning in &import.warnings {
+                eprintln!("warning: {}", warning);
+            }
+            for (table, rows) in &import.tables {
+                println!("{}: {} rows", table, rows);
+            }
+            println!("{} indexes", import.indexes.len());
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("cannot import {}: {}", file, err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn import_sqlite(_: &Database, _: &str) -> ExitCode {
+    eprintln!("this build has no SQLite support");
+    ExitCode::FAILURE
 }
@@ -0,0 +1,264 @@
+use std::collections::BTreeSet;
+use std::convert::TryInto;
+
+use crate::buffer::BufferPoolManager;
+use crate::tuple::{self, Tuple};
+use crate::wal::TxId;
+
+// Versions written outside of any transaction, i.e. without a log, are as if by this one, which
+// every snapshot sees. Without a transaction, rows are deleted and updated in place. Vacuum also
+// freezes the versions every snapshot sees as created, so that whether their transactions
+// committed needn't be known any more. Transaction ids are 64-bit, so don't wrap around.
+pub const FROZEN: TxId = 0;
+
+// Isolation level of a transaction begun explicitly, a statement being a transaction otherwise.
+//   read committed:  each statement reads a snapshot taken when it starts
+//   repeatable read: snapshot isolation, i.e. every statement reads the snapshot taken by the
+//                    first one, and a row can't be written if it was by a transaction which
+//                    committed after that (first committer wins)
+//   serializable:    like repeatable read, but failing where the transactions may not be
+//                    serializable, see ssi
+// In any case, a row can't be written while another transaction which has written it is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Isolation {
+    #[default]
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+// A version of a row, created by `xmin` and deleted, or replaced by a newer version, by `xmax`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub xmin: TxId,
+    pub xmax: Option<TxId>,
+    pub row: Tuple,
+}
+
+// Which transactions' changes a transaction sees: those committed when it took the snapshot,
+// and its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    // the transaction reading, FROZEN if it hasn't written anything yet
+    pub txid: TxId,
+    // the transactions from this one on hadn't begun
+    pub next: TxId,
+    // and these hadn't ended
+    pub running: BTreeSet<TxId>,
+}
+
+impl Snapshot {
+    // Whether the changes of `txid` are seen, if it hasn't aborted.
+    pub fn sees(&self, txid: TxId) -> bool {
+        txid == FROZEN || txid == self.txid || txid < self.next && !self.running.contains(&txid)
+    }
+}
+
+// What decides which versions a transaction sees, as of when it's taken, out of the buffer pool
+// manager so that rows can be checked elsewhere, e.g. by the workers of a parallel scan.
+#[derive(Debug, Clone)]
+pub struct Visibility {
+    pub snapshot: Snapshot,
+    pub aborted: BTreeSet<TxId>,
+}
+
+impl Visibility {
+    pub fn visible(&self, version: &Version) -> bool {
+        let sees = |txid: TxId| !self.aborted.contains(&txid) && self.snapshot.sees(txid);
+        sees(version.xmin) && !version.xmax.is_some_and(sees)
+    }
+}
+
+// Whether `version` is in the snapshot of the current transaction.
+pub fn visible(bufmgr: &mut BufferPoolManager, version: &Version) -> bool {
+    bufmgr.sees(version.xmin) && !version.xmax.is_some_and(|xmax| bufmgr.sees(xmax))
+}
+
+// The versions of a row which a snapshot in use or taken from now on may see: those other than of
+// aborted transactions, down to the newest one created by a transaction committed before the
+// horizon, unless it was deleted by one too. That one is frozen.
+pub fn prune(bufmgr: &BufferPoolManager, versions: &[Version]) -> Vec<Version> {
+    let horizon = bufmgr.horizon();
+    let before_horizon = |txid: TxId| txid < horizon && bufmgr.is_committed(txid);
+    let mut kept = vec![];
+    for version in versions.iter().filter(|version| !bufmgr.is_aborted(version.xmin)) {
+        let mut version = version.clone();
+        if version.xmax.is_some_and(|xmax| bufmgr.is_aborted(xmax)) {
+            version.xmax = None;
+        }
+        if before_horizon(version.xmin) {
+            if !version.xmax.is_some_and(before_horizon) {
+                version.xmin = FROZEN;
+                kept.push(version);
+            }
+            break;
+        }
+        kept.push(version);
+    }
+    kept
+}
+
+// The chain of versions of a row, which is what tables store as the value of a key, is encoded
+// newest first as ([xmin: u64][xmax: u64][row])*, xmax being 0 if none.
+pub fn encode_versions(versions: &[Version], out: &mut Vec<u8>) {
+    for version in versions {
+        out.extend_from_slice(&version.xmin.to_le_bytes());
+        out.extend_from_slice(&version.xmax.unwrap_or(0).to_le_bytes());
+        tuple::encode(&version.row, out);
+    }
+}
+
+pub fn decode_versions(mut bytes: &[u8]) -> Result<Vec<Version>, tuple::Error> {
+    let mut versions = vec![];
+    while !bytes.is_empty() {
+        let header = bytes.get(..16).ok_or(tuple::Error::Malformed)?;
+        let xmin = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let xmax = Some(u64::from_le_bytes(header[8..].try_into().unwrap())).filter(|&xmax| xmax != 0);
+        let (row, len) = tuple::decode(&bytes[16..])?;
+        versions.push(Version { xmin, xmax, row });
+        bytes = &bytes[16 + len..];
+    }
+    Ok(versions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::table::{self, Access, IndexKind, Table};
+    use crate::tuple::Value;
+    use crate::wal::{Wal, DEFAULT_SEGMENT_SIZE};
+    use tempfile::{tempdir, NamedTempFile};
+
+    fn ids(bufmgr: &mut BufferPoolManager, table: &Table) -> Vec<i64> {
+        let mut iter = table.scan(bufmgr).unwrap();
+        let mut ids = vec![];
+        while let Some(row) = iter.next(bufmgr).unwrap() {
+            match row[0] {
+                Value::Int(id) => ids.push(id),
+                _ => unreachable!(),
+            }
+        }
+        ids
+    }
+
+    #[test]
+    fn test() {
+        let versions = vec![
+            Version { xmin: 3, xmax: None, row: vec![Value::Int(1)] },
+            Version { xmin: 2, xmax: Some(3), row: vec![Value::Null] },
+        ];
+        let mut bytes = vec![];
+        encode_versions(&versions, &mut bytes);
+        assert_eq!(versions, decode_versions(&bytes).unwrap());
+        let snapshot = Snapshot { txid: 7, next: 5, running: BTreeSet::from([3]) };
+        assert_eq!(vec![true, true, false, true, false, true], [0, 2, 3, 4, 5, 7].map(|txid| snapshot.sees(txid)));
+
+        let (data_file, data_path) = NamedTempFile::new().unwrap().into_parts();
+        drop(data_file);
+        let wal_dir = tempdir().unwrap();
+        let open = || {
+            let disk = DiskManager::open(&data_path).unwrap();
+            let wal = Wal::open(wal_dir.path(), DEFAULT_SEGMENT_SIZE).unwrap();
+            BufferPoolManager::with_wal(disk, BufferPool::new(8), wal).unwrap()
+        };
+        let mut bufmgr = open();
+        let row = |id: i64, group: i64| vec![Value::Int(id), Value::Int(group)];
+        let mut table = Table::create(&mut bufmgr, 1).unwrap();
+        table.create_index(&mut bufmgr, vec![1], vec![], IndexKind::BTree).unwrap();
+        for id in 0..5 {
+            table.insert(&mut bufmgr, &row(id, id % 2)).unwrap();
+        }
+        bufmgr.commit().unwrap();
+
+        // the changes of a transaction are seen only by itself until it commits
+        table.insert(&mut bufmgr, &row(10, 0)).unwrap();
+        assert!(table.delete(&mut bufmgr, &[Value::Int(1)]).unwrap());
+        assert!(!table.delete(&mut bufmgr, &[Value::Int(1)]).unwrap());
+        assert!(table.update(&mut bufmgr, &row(2, 1)).unwrap());
+        assert_eq!(vec![0, 2, 3, 4, 10], ids(&mut bufmgr, &table));
+        let writer = bufmgr.switch(Default::default());
+        assert_eq!(vec![0, 1, 2, 3, 4], ids(&mut bufmgr, &table));
+        let by_group = |bufmgr: &mut BufferPoolManager, group: i64| -> Vec<Value> {
+            let rows = table.lookup(bufmgr, Access::Index(0), &[Value::Int(group)]).unwrap();
+            rows.into_iter().map(|row| row[0].clone()).collect()
+        };
+        assert_eq!(vec![Value::Int(0), Value::Int(2), Value::Int(4)], by_group(&mut bufmgr, 0));
+        // rows are written by one transaction at a time
+        assert!(matches!(table.delete(&mut bufmgr, &[Value::Int(2)]), Err(table::Error::WriteConflict)));
+        assert!(matches!(table.insert(&mut bufmgr, &row(10, 1)), Err(table::Error::WriteConflict)));
+        let reader = bufmgr.switch(writer);
+        bufmgr.commit().unwrap();
+        // once committed, they're seen by new snapshots but not by those taken before
+        assert_eq!(vec![0, 2, 3, 4, 10], ids(&mut bufmgr, &table));
+        assert_eq!(vec![Value::Int(0), Value::Int(4), Value::Int(10)], by_group(&mut bufmgr, 0));
+        bufmgr.commit().unwrap();
+        bufmgr.switch(reader);
+        assert_eq!(vec![0, 1, 2, 3, 4], ids(&mut bufmgr, &table));
+        assert_eq!(vec![Value::Int(0), Value::Int(2), Value::Int(4)], by_group(&mut bufmgr, 0));
+        bufmgr.commit().unwrap();
+
+        // the versions of an aborted transaction are never seen, even after reopening
+        table.insert(&mut bufmgr, &row(20, 0)).unwrap();
+        table.insert(&mut bufmgr, &row(21, 0)).unwrap();
+        assert!(bufmgr.abort().unwrap());
+        table.insert(&mut bufmgr, &row(21, 1)).unwrap();
+        bufmgr.commit().unwrap();
+        drop(bufmgr);
+        let mut bufmgr = open();
+        assert_eq!(vec![0, 2, 3, 4, 10, 21], ids(&mut bufmgr, &table));
+        assert_eq!(vec![Value::Int(0), Value::Int(4), Value::Int(10)], by_group(&mut bufmgr, 0));
+        bufmgr.checkpoint().unwrap();
+        drop(bufmgr);
+        let mut bufmgr = open();
+        assert_eq!(vec![0, 2, 3, 4, 10, 21], ids(&mut bufmgr, &table));
+
+        // vacuum removes the versions which no snapshot sees
+        let mut table = Table::create(&mut bufmgr, 1).unwrap();
+        table.create_index(&mut bufmgr, vec![1], vec![], IndexKind::BTree).unwrap();
+        let by_group = |bufmgr: &mut BufferPoolManager, group: i64| -> Vec<Value> {
+            let rows = table.lookup(bufmgr, Access::Index(0), &[Value::Int(group)]).unwrap();
+            rows.into_iter().map(|row| row[0].clone()).collect()
+        };
+        for id in 0..4 {
+            table.insert(&mut bufmgr, &row(id, id % 2)).unwrap();
+        }
+        bufmgr.commit().unwrap();
+        assert_eq!(vec![0, 1, 2, 3], ids(&mut bufmgr, &table));
+        let reader = bufmgr.switch(Default::default());
+        table.update(&mut bufmgr, &row(1, 0)).unwrap();
+        table.delete(&mut bufmgr, &[Value::Int(2)]).unwrap();
+        bufmgr.commit().unwrap();
+        table.insert(&mut bufmgr, &row(5, 0)).unwrap();
+        let aborted = bufmgr.current_txid().unwrap();
+        bufmgr.abort().unwrap();
+        // which may be the old versions once they're no longer in use
+        let stats = table.vacuum(&mut bufmgr).unwrap();
+        assert_eq!(table::VacuumStats { versions: 1, rows: 1, index_entries: 1, compacted: 0 }, stats);
+        bufmgr.commit().unwrap();
+        let writer = bufmgr.switch(reader);
+        assert_eq!(vec![0, 1, 2, 3], ids(&mut bufmgr, &table));
+        assert_eq!(vec![Value::Int(1), Value::Int(3)], by_group(&mut bufmgr, 1));
+        bufmgr.commit().unwrap();
+        bufmgr.switch(writer);
+        let horizon = bufmgr.horizon();
+        let stats = table.vacuum(&mut bufmgr).unwrap();
+        assert_eq!(table::VacuumStats { versions: 2, rows: 1, index_entries: 2, compacted: 0 }, stats);
+        bufmgr.commit().unwrap();
+        assert_eq!(vec![0, 1, 3], ids(&mut bufmgr, &table));
+        assert_eq!(vec![Value::Int(0), Value::Int(1)], by_group(&mut bufmgr, 0));
+        assert_eq!(table::VacuumStats::default(), table.vacuum(&mut bufmgr).unwrap());
+        // and freezes the others, so that the aborted transactions can be forgotten once every
+        // table has been vacuumed
+        let mut iter = table.btree.search(&mut bufmgr, btree::SearchMode::Start).unwrap();
+        while let Some((_, value)) = iter.next(&mut bufmgr).unwrap() {
+            assert!(decode_versions(&value).unwrap().iter().all(|version| version.xmin == FROZEN));
+        }
+        assert!(aborted < horizon && bufmgr.is_aborted(aborted));
+        bufmgr.forget_aborted(horizon);
+        assert!(!bufmgr.is_aborted(aborted));
+        assert_eq!(vec![0, 1, 3], ids(&mut bufmgr, &table));
+    }
+}
@@ -0,0 +1,384 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+
+// The floating-point and exact decimal types, double precision and numeric. Floats are compared as
+// PostgreSQL compares them, -0 equal to 0 and NaN equal to itself and above every other value, so
+// that they can be sorted and be keys. Numerics are of up to 38 digits, of which up to
+// `MAX_SCALE` after the point; those equal but of different scales, as 1.5 and 1.50, are equal,
+// each keeping its scale when written as text.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("invalid input syntax for type double precision: \"{0}\"")]
+    Float(String),
+    #[error("invalid input syntax for type numeric: \"{0}\"")]
+    Numeric(String),
+    #[error("numeric out of range")]
+    OutOfRange,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Float(pub f64);
+
+impl Float {
+    pub fn parse(text: &str) -> Result<Float, Error> {
+        // which takes inf, infinity and nan, in any case
+        text.trim().parse().map(Float).map_err(|_| Error::Float(text.to_string()))
+    }
+
+    // The bits of the value, the same of all zeros and of all NaNs.
+    fn canonical_bits(self) -> u64 {
+        if self.0.is_nan() {
+            f64::NAN.to_bits()
+        } else if self.0 == 0.0 {
+            0
+        } else {
+            self.0.to_bits()
+        }
+    }
+
+    // Order preserving encoding, as `tuple::encode_key` takes.
+    pub fn key(self) -> [u8; 8] {
+        let bits = self.canonical_bits();
+        let bits = if bits >> 63 == 1 { !bits } else { bits ^ (1 << 63) };
+        bits.to_be_bytes()
+    }
+
+    pub fn from_key(bytes: [u8; 8]) -> Float {
+        let bits = u64::from_be_bytes(bytes);
+        let bits = if bits >> 63 == 1 { bits ^ (1 << 63) } else { !bits };
+        Float(f64::from_bits(bits))
+    }
+}
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Float {}
+
+impl PartialOrd for Float {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Float {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.partial_cmp(&other.0).unwrap_or_else(|| self.0.is_nan().cmp(&other.0.is_nan()))
+    }
+}
+
+impl Hash for Float {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical_bits().hash(state);
+    }
+}
+
+impl std::fmt::Display for Float {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            x if x.is_nan() => write!(f, "NaN"),
+            x if x.is_infinite() => write!(f, "{}Infinity", if x < 0.0 { "-" } else { "" }),
+            // in scientific notation if the exponent is below -4 or above 14, as 1e+20 and 1.5e-05
+            x if x != 0.0 && !(1e-4..1e15).contains(&x.abs()) => {
+                let text = format!("{:e}", x);
+                let (mantissa, exponent) = text.split_once('e').expect("written with an exponent");
+                let (sign, digits) = exponent.strip_prefix('-').map_or(("+", exponent), |digits| ("-", digits));
+                write!(f, "{}e{}{:0>2}", mantissa, sign, digits)
+            }
+            x => write!(f, "{}", x),
+        }
+    }
+}
+
+// Digits after the point at most, and at least of a quotient.
+pub const MAX_SCALE: u32 = 30;
+const DIVISION_SCALE: u32 = 16;
+// Digits `parts` scales fractions to, which 10^FRACTION_DIGITS fits in an i128 with.
+const FRACTION_DIGITS: u32 = 38;
+
+// `digits` × 10^-`scale`
+#[derive(Debug, Clone, Copy)]
+pub struct Numeric {
+    digits: i128,
+    scale: u32,
+}
+
+fn pow10(n: u32) -> i128 {
+    10i128.pow(n)
+}
+
+impl Numeric {
+    pub fn new(digits: i128, scale: u32) -> Result<Numeric, Error> {
+        match scale <= MAX_SCALE && digits.unsigned_abs() < pow10(FRACTION_DIGITS) as u128 {
+            true => Ok(Numeric { digits, scale }),
+            false => Err(Error::OutOfRange),
+        }
+    }
+
+    pub fn from_int(n: i64) -> Numeric {
+        Numeric { digits: n as i128, scale: 0 }
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    // Parses digits with a point and an exponent optionally, as 12, -1.50 or 2.5e-3.
+    pub fn parse(text: &str) -> Result<Numeric, Error> {
+        let invalid = || Error::Numeric(text.to_string());
+        let trimmed = text.trim();
+        let (mantissa, exponent) = match trimmed.split_once(['e', 'E']) {
+            Some((mantissa, exponent)) => (mantissa, exponent.parse::<i32>().map_err(|_| invalid())?),
+            None => (trimmed, 0),
+        };
+        let (negative, mantissa) = match mantissa.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+        };
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if integer.is_empty() && fraction.is_empty() || !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let mut digits: i128 = 0;
+        for c in integer.chars().chain(fraction.chars()) {
+            digits = digits.checked_mul(10).and_then(|d| d.checked_add(c as i128 - '0' as i128)).ok_or(Error::OutOfRange)?;
+        }
+        let scale = fraction.len() as i64 - exponent as i64;
+        let digits = if negative { -digits } else { digits };
+        match u32::try_from(scale) {
+            Ok(scale) if scale <= MAX_SCALE => Numeric::new(digits, scale),
+            Ok(scale) => Numeric { digits, scale }.rescale(MAX_SCALE),
+            Err(_) => {
+                let shift = u32::try_from(-scale).map_err(|_| Error::OutOfRange)?;
+                let factor = 10i128.checked_pow(shift).ok_or(Error::OutOfRange)?;
+                Numeric::new(digits.checked_mul(factor).ok_or(Error::OutOfRange)?, 0)
+            }
+        }
+    }
+
+    // The value with `scale` digits after the point, rounded half away from zero if fewer.
+    pub fn rescale(self, scale: u32) -> Result<Numeric, Error> {
+        match scale.cmp(&self.scale) {
+            Ordering::Equal => Numeric::new(self.digits, scale),
+            Ordering::Greater => {
+                let factor = 10i128.checked_pow(scale - self.scale).ok_or(Error::OutOfRange)?;
+                Numeric::new(self.digits.checked_mul(factor).ok_or(Error::OutOfRange)?, scale)
+            }
+            Ordering::Less => {
+                let shift = self.scale - scale;
+                if shift > FRACTION_DIGITS {
+                    // less than half of the last digit kept
+                    return Numeric::new(0, scale);
+                }
+                let factor = pow10(shift);
+                let (quotient, remainder) = (self.digits / factor, self.digits % factor);
+                let rounded = quotient + (remainder.abs() >= factor / 2) as i128 * self.digits.signum();
+                Numeric::new(rounded, scale)
+            }
+        }
+    }
+
+    // Rounded half away from zero.
+    pub fn to_i64(self) -> Option<i64> {
+        i64::try_from(self.rescale(0).ok()?.digits).ok()
+    }
+
+    pub fn to_f64(self) -> f64 {
+        // the nearest of the decimal, as parsing it finds
+        self.to_string().parse().expect("a numeric is written as a float can be parsed")
+    }
+
+    // Of the shortest decimal which is read back as `f`, which must be finite.
+    pub fn from_f64(f: f64) -> Result<Numeric, Error> {
+        if !f.is_finite() {
+            return Err(Error::Numeric(Float(f).to_string()));
+        }
+        Numeric::parse(&format!("{:e}", f))
+    }
+
+    pub fn checked_add(self, other: Numeric) -> Result<Numeric, Error> {
+        let scale = self.scale.max(other.scale);
+        let (l, r) = (self.rescale(scale)?, other.rescale(scale)?);
+        Numeric::new(l.digits.checked_add(r.digits).ok_or(Error::OutOfRange)?, scale)
+    }
+
+    pub fn checked_sub(self, other: Numeric) -> Result<Numeric, Error> {
+        self.checked_add(Numeric { digits: -other.digits, scale: other.scale })
+    }
+
+    pub fn checked_mul(self, other: Numeric) -> Result<Numeric, Error> {
+        let digits = self.digits.checked_mul(other.digits).ok_or(Error::OutOfRange)?;
+        let product = Numeric { digits, scale: self.scale + other.scale };
+        product.rescale(product.scale.min(MAX_SCALE))
+    }
+
+    // None if `other` is zero. Of `DIVISION_SCALE` digits after the point at least, rounded.
+    pub fn checked_div(self, other: Numeric) -> Option<Result<Numeric, Error>> {
+        if other.digits == 0 {
+            return None;
+        }
+        let scale = self.scale.max(other.scale).max(DIVISION_SCALE);
+        let quotient = || {
+            // one more digit than the quotient, to be rounded off
+            let shift = scale + 1 + other.scale - self.scale;
+            let dividend = self.digits.checked_mul(10i128.checked_pow(shift)?)?;
+            Some(Numeric { digits: dividend / other.digits, scale: scale + 1 })
+        };
+        Some(quotient().ok_or(Error::OutOfRange).and_then(|quotient| quotient.rescale(scale)))
+    }
+
+    // None if `other` is zero. Of the sign of `self`, as the remainder of integers is.
+    pub fn checked_rem(self, other: Numeric) -> Option<Result<Numeric, Error>> {
+        if other.digits == 0 {
+            return None;
+        }
+        let scale = self.scale.max(other.scale);
+        Some((|| {
+            let (l, r) = (self.rescale(scale)?, other.rescale(scale)?);
+            Numeric::new(l.digits % r.digits, scale)
+        })())
+    }
+
+    // The value as its integer part, rounded down, and the digits of its fraction up to
+    // `FRACTION_DIGITS`, by which values compare whatever their scales.
+    fn parts(&self) -> (i128, i128) {
+        let factor = pow10(self.scale);
+        (self.digits.div_euclid(factor), self.digits.rem_euclid(factor) * pow10(FRACTION_DIGITS - self.scale))
+    }
+
+    // Order preserving encoding, as `tuple::encode_key` takes. Decoded, the value is of the
+    // least scale it can be written with.
+    pub fn key(&self) -> [u8; 32] {
+        let (integer, fraction) = self.parts();
+        let mut key = [0; 32];
+        key[..16].copy_from_slice(&((integer as u128) ^ (1 << 127)).to_be_bytes());
+        key[16..].copy_from_slice(&(fraction as u128).to_be_bytes());
+        key
+    }
+
+    pub fn from_key(bytes: [u8; 32]) -> Result<Numeric, Error> {
+        let integer = (u128::from_be_bytes(bytes[..16].try_into().unwrap()) ^ (1 << 127)) as i128;
+        let fraction = u128::from_be_bytes(bytes[16..].try_into().unwrap()) as i128;
+        let mut scale = FRACTION_DIGITS;
+        let mut fraction = fraction;
+        while scale > 0 && fraction % 10 == 0 {
+            fraction /= 10;
+            scale -= 1;
+        }
+        if scale > MAX_SCALE {
+            return Err(Error::OutOfRange);
+        }
+        let digits = integer.checked_mul(pow10(scale)).and_then(|d| d.checked_add(fraction)).ok_or(Error::OutOfRange)?;
+        Numeric::new(digits, scale)
+    }
+
+    // The stored form: the digits, then the scale.
+    pub fn to_bytes(&self) -> [u8; 17] {
+        let mut bytes = [0; 17];
+        bytes[..16].copy_from_slice(&self.digits.to_le_bytes());
+        bytes[16] = self.scale as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: [u8; 17]) -> Result<Numeric, Error> {
+        Numeric::new(i128::from_le_bytes(bytes[..16].try_into().unwrap()), bytes[16] as u32)
+    }
+}
+
+impl std::ops::Neg for Numeric {
+    type Output = Numeric;
+
+    fn neg(self) -> Numeric {
+        Numeric { digits: -self.digits, scale: self.scale }
+    }
+}
+
+impl PartialEq for Numeric {
+    fn eq(&self, other: &Self) -> bool {
+        self.parts() == other.parts()
+    }
+}
+
+impl Eq for Numeric {}
+
+impl PartialOrd for Numeric {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Numeric {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.parts().cmp(&other.parts())
+    }
+}
+
+impl Hash for Numeric {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.parts().hash(state);
+    }
+}
+
+impl std::fmt::Display for Numeric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let factor = pow10(self.scale) as u128;
+        let magnitude = self.digits.unsigned_abs();
+        let sign = if self.digits < 0 { "-" } else { "" };
+        match self.scale {
+            0 => write!(f, "{}{}", sign, magnitude),
+            scale => write!(f, "{}{}.{:0width$}", sign, magnitude / factor, magnitude % factor, width = scale as usize),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        // floats, of which NaN is the greatest and the zeros are equal
+        let mut floats: Vec<_> = [f64::NAN, 1.5, f64::NEG_INFINITY, -0.0, 0.0, -2.0, f64::INFINITY].map(Float).to_vec();
+        floats.sort();
+        assert_eq!("-Infinity -2 -0 0 1.5 Infinity NaN", floats.iter().map(|f| f.to_string()).collect::<Vec<_>>().join(" "));
+        let mut keys: Vec<_> = floats.iter().map(|f| f.key()).collect();
+        keys.sort();
+        assert_eq!(floats, keys.into_iter().map(Float::from_key).collect::<Vec<_>>());
+        assert_eq!(Float(0.0), Float(-0.0));
+        assert_eq!(Float(f64::NAN), Float::parse("nan").unwrap());
+        assert_eq!(Float(-1e10), Float::parse(" -1e10 ").unwrap());
+        assert_eq!(["1e+20", "-1.5e-05", "0.0001", "123456789012345"], [1e20, -1.5e-5, 1e-4, 123456789012345.0].map(|f| Float(f).to_string()));
+        assert!(Float::parse("1.5x").is_err());
+
+        // numerics, which keep their scale
+        let n = |text: &str| Numeric::parse(text).unwrap();
+        assert_eq!("1.50", n("1.50").to_string());
+        assert_eq!("-0.0025", n("-2.5e-3").to_string());
+        assert_eq!("1200", n("1.2e3").to_string());
+        assert_eq!(n("1.5"), n("1.50"));
+        assert!(n("-1.5") < n("-1.25") && n("-0.1") < n("0") && n("0.1") < n("1"));
+        assert!(Numeric::parse("1.2.3").is_err() && Numeric::parse(".").is_err() && Numeric::parse("").is_err());
+        assert!(matches!(Numeric::parse(&"9".repeat(40)), Err(Error::OutOfRange)));
+        assert_eq!("3.75", n("1.25").checked_add(n("2.5")).unwrap().to_string());
+        assert_eq!("-1.25", n("1.25").checked_sub(n("2.5")).unwrap().to_string());
+        assert_eq!("3.125", n("1.25").checked_mul(n("2.5")).unwrap().to_string());
+        assert_eq!("0.3333333333333333", n("1").checked_div(n("3")).unwrap().unwrap().to_string());
+        assert_eq!("0.6666666666666667", n("2").checked_div(n("3")).unwrap().unwrap().to_string());
+        assert!(n("1").checked_div(n("0.0")).is_none());
+        assert_eq!("-0.5", n("-3.5").checked_rem(n("1.5")).unwrap().unwrap().to_string());
+        assert_eq!((Some(3), Some(-3)), (n("2.5").to_i64(), n("-2.5").to_i64()));
+        assert_eq!(0.1, n("0.1").to_f64());
+        assert_eq!(n("0.1"), Numeric::from_f64(0.1).unwrap());
+        assert_eq!("2.35", n("2.345").rescale(2).unwrap().to_string());
+        let mut numerics = [n("-1.5"), n("3"), n("-1.25"), n("0.001"), n("-100"), n("0")];
+        let mut keys: Vec<_> = numerics.iter().map(|n| n.key()).collect();
+        keys.sort();
+        numerics.sort();
+        assert_eq!(numerics.to_vec(), keys.into_iter().map(|key| Numeric::from_key(key).unwrap()).collect::<Vec<_>>());
+        assert_eq!("1.50", Numeric::from_bytes(n("1.50").to_bytes()).unwrap().to_string());
+    }
+}
@@ -0,0 +1,1177 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::rc::Rc;
+
+use crate::catalog::TableInfo;
+use crate::query::expr::{self, conjunction, like_prefix, BinaryOp, Expr};
+use crate::query::{
+    ColumnarScan, Estimate, Estimated, Filter, FullTextScan, Gather, HashJoin, IndexNestedLoopJoin, IndexScan, KeyRange, MergeJoin,
+    NestedLoopJoin, PlanNode, Project, SeqScan, SpatialScan, ZoneMapScan, DEFAULT_NUM_PARTITIONS, DEFAULT_TABLE_ROWS, PARALLEL_SCAN_MIN_ROWS,
+};
+use crate::stats::ColumnStats;
+use crate::table::{Access, IndexKind};
+use crate::tuple::{DataType, Value};
+
+// Join orders are searched exhaustively for up to this many tables, greedily for more.
+const MAX_EXHAUSTIVE_RELATIONS: usize = 10;
+
+// Added to the cost of ways of reading and joining which are disabled, so that they're chosen
+// only if there's no other way.
+const DISABLED_COST: f64 = 1e10;
+
+// Ways of reading and joining tables the optimizer may choose, as sessions set them with
+// `SET enable_hashjoin = off` and the like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlannerSettings {
+    pub enable_seqscan: bool,
+    pub enable_indexscan: bool,
+    pub enable_hashjoin: bool,
+    pub enable_mergejoin: bool,
+    // nested loops, index lookups by the rows of the outer side included
+    pub enable_nestloop: bool,
+}
+
+impl Default for PlannerSettings {
+    fn default() -> Self {
+        Self {
+            enable_seqscan: true,
+            enable_indexscan: true,
+            enable_hashjoin: true,
+            enable_mergejoin: true,
+            enable_nestloop: true,
+        }
+    }
+}
+
+impl PlannerSettings {
+    fn penalty(enabled: bool) -> f64 {
+        if enabled {
+            0.0
+        } else {
+            DISABLED_COST
+        }
+    }
+
+    fn method_penalty(&self, method: Method) -> f64 {
+        Self::penalty(match method {
+            Method::NestedLoop | Method::IndexNestedLoop => self.enable_nestloop,
+            Method::Hash => self.enable_hashjoin,
+            Method::Merge => self.enable_mergejoin,
+        })
+    }
+}
+
+// How the hints of a query (see `ast::Hint`) have it planned, by the positions of its relations.
+// The ways of reading and joining against them cost DISABLED_COST more, as those disabled by the
+// settings do, so that they're chosen only if there's no other way.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Hints {
+    pub scans: Vec<(usize, ScanHint)>,
+    // joined first, in that order, each to those before it
+    pub leading: Vec<usize>,
+    // the method each set of relations is joined by, once they all are
+    pub joins: Vec<(u64, JoinMethod)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanHint {
+    Seq,
+    // through the access path, or any if None
+    Index(Option<Access>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinMethod {
+    Hash,
+    Merge,
+    // index lookups by the rows of the outer side included
+    NestedLoop,
+}
+
+impl Hints {
+    // The hints of the relations `position` maps to new positions, those of the others dropped.
+    pub fn remap(&self, position: impl Fn(usize) -> Option<usize>) -> Hints {
+        let set = |relations: u64| (0..64).filter(|r| relations & 1 << r != 0).try_fold(0u64, |set, r| Some(set | 1 << position(r)?));
+        Hints {
+            scans: self.scans.iter().filter_map(|&(r, hint)| Some((position(r)?, hint))).collect(),
+            leading: self.leading.iter().map(|&r| position(r)).collect::<Option<_>>().unwrap_or_default(),
+            joins: self.joins.iter().filter_map(|&(relations, method)| Some((set(relations)?, method))).collect(),
+        }
+    }
+
+    // Added to the cost of reading `relation` through `access`, or whole if None.
+    fn scan_penalty(&self, relation: usize, access: Option<Access>) -> f64 {
+        let hinted = |hint: &ScanHint| match (hint, access) {
+            (ScanHint::Seq, access) => access.is_none(),
+            (ScanHint::Index(None), access) => access.is_some(),
+            (ScanHint::Index(Some(hinted)), access) => access == Some(*hinted),
+        };
+        let against = self.scans.iter().any(|(r, hint)| *r == relation && !hinted(hint));
+        PlannerSettings::penalty(!against)
+    }
+
+    // Added to the cost of joining the relations `left` to those `right` by `method`.
+    fn join_penalty(&self, left: u64, right: u64, method: Method) -> f64 {
+        let hinted = |hint: JoinMethod| match hint {
+            JoinMethod::Hash => method == Method::Hash,
+            JoinMethod::Merge => method == Method::Merge,
+            JoinMethod::NestedLoop => matches!(method, Method::NestedLoop | Method::IndexNestedLoop),
+        };
+        let against_method = self.joins.iter().any(|&(relations, hint)| relations == left | right && !hinted(hint));
+        // of the leading relations, only the next may be joined to those before it, and the
+        // others only once all are joined
+        let leading = self.leading.iter().fold(0u64, |set, r| set | 1 << r);
+        let against_order = match (left & leading, right & leading) {
+            (0, 0) => false,
+            (l, 0) => l != leading,
+            (0, r) => r != leading,
+            (l, r) => {
+                let n = l.count_ones() as usize;
+                left != l || right != r || self.leading[..n].iter().fold(0u64, |set, r| set | 1 << r) != l || self.leading.get(n).is_none_or(|&next| r != 1 << next)
+            }
+        };
+        PlannerSettings::penalty(!against_method) + PlannerSettings::penalty(!against_order)
+    }
+}
+
+// Inner join of relations, which the optimizer may read and join in any order.
+pub struct Query<'a> {
+    pub relations: Vec<Source<'a>>,
+    // Conditions on the columns of all relations one after another. They may not contain
+    // subqueries, whose plans can't be moved around.
+    pub conjuncts: Vec<Expr>,
+    // columns read by the plan above the join
+    pub needed: Vec<usize>,
+    pub max_build_rows: usize,
+    pub settings: PlannerSettings,
+    pub hints: Hints,
+}
+
+// Plans `query`, choosing how each table is read, the join order and the join algorithms by
+// their estimated costs. Conjuncts are evaluated as soon as the columns they read are available,
+// and columns nobody needs are dropped as soon as possible.
+// Also returns the columns of the output tuples, which include the needed ones in any order.
+pub fn optimize(query: Query) -> (Box<dyn PlanNode>, Vec<usize>) {
+    let optimizer = Optimizer::new(query);
+    let best = optimizer.search();
+    let (mut plan, layout) = optimizer.realize(&best.tree);
+    if let Some(predicate) = conjunction(optimizer.constant_predicates()) {
+        plan = Box::new(Filter { child: plan, predicate });
+    }
+    (plan, layout)
+}
+
+// Rearranges the tuples of `plan`, which have the query columns in `layout`, into all
+// `num_columns` columns of the query in order. Columns missing from `layout` are NULL.
+pub fn restore_layout(plan: Box<dyn PlanNode>, layout: &[usize], num_columns: usize) -> Box<dyn PlanNode> {
+    if layout.iter().copied().eq(0..num_columns) {
+        return plan;
+    }
+    let exprs = (0..num_columns)
+        .map(|c| layout.iter().position(|&l| l == c).map_or(Expr::Literal(Value::Null), Expr::Column))
+        .collect();
+    Box::new(Project { child: plan, exprs })
+}
+
+// Where the rows of a relation come from.
+pub enum Source<'a> {
+    Table(&'a TableInfo),
+    // rows computed by a plan, e.g. of a view, which can only be read as a whole
+    Plan { plan: Box<dyn PlanNode>, num_columns: usize },
+}
+
+struct Relation<'a> {
+    // None if the rows are computed by `plan`
+    table: Option<&'a TableInfo>,
+    // taken when the plan is realized
+    plan: RefCell<Option<Box<dyn PlanNode>>>,
+    num_columns: usize,
+    // position of the first column of the relation in the query
+    offset: usize,
+    rows: f64,
+    // cost of reading all rows, and the columns of the relation they are read in order of
+    scan_cost: f64,
+    ordering: Vec<usize>,
+    // columns of the query read above the scan, in column order
+    needed: Vec<usize>,
+}
+
+impl<'a> Relation<'a> {
+    fn column_stats(&self, column: usize) -> Option<&ColumnStats> {
+        self.table?.stats.as_ref()?.columns.get(column - self.offset)
+    }
+
+    fn distinct_count(&self, column: usize) -> f64 {
+        self.column_stats(column).map_or(self.rows, |s| s.distinct_count as f64).max(1.0)
+    }
+}
+
+struct Predicate {
+    expr: Expr,
+    // bit set of the relations the predicate reads
+    relations: u64,
+    selectivity: f64,
+}
+
+// `a = b` between columns of different relations.
+struct Edge {
+    columns: [usize; 2],
+    relations: [u64; 2],
+    selectivity: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Method {
+    NestedLoop,
+    Hash,
+    Merge,
+    IndexNestedLoop,
+}
+
+// Plan choices, from which the plan is built once the best one is known.
+enum Tree {
+    Scan {
+        relation: usize,
+        index: Option<Lookup>,
+        rows: f64,
+    },
+    Join {
+        method: Method,
+        left: Rc<Tree>,
+        right: Rc<Tree>,
+        // (left, right) columns compared by the join
+        keys: Vec<(usize, usize)>,
+        // predicates evaluated on the joined rows besides the keys
+        predicates: Vec<usize>,
+        rows: f64,
+    },
+}
+
+// How a scan looks up rows through an access path.
+struct Lookup {
+    access: Access,
+    // of a full-text index, the query searched for
+    keys: Vec<Expr>,
+    range: Option<KeyRange>,
+    // predicates which the lookup evaluates, and so aren't evaluated on the rows found
+    used: Vec<usize>,
+    // estimated number of rows found
+    found: f64,
+}
+
+// Bounds on a column by a predicate, which is `exact` if only the rows within the bounds satisfy
+// it. LIKE 'prefix%' for example is a range too, but the found rows are still matched.
+struct ColumnBounds {
+    column: usize,
+    predicate: usize,
+    lower: Bound<Expr>,
+    upper: Bound<Expr>,
+    exact: bool,
+}
+
+#[derive(Clone)]
+struct Candidate {
+    tree: Rc<Tree>,
+    relations: u64,
+    estimate: Estimate,
+    // query columns of the output tuples
+    layout: Vec<usize>,
+    // query columns the output is sorted by
+    ordering: Vec<usize>,
+}
+
+struct Optimizer<'a> {
+    relations: Vec<Relation<'a>>,
+    predicates: Vec<Predicate>,
+    edges: Vec<Edge>,
+    max_build_rows: usize,
+    settings: PlannerSettings,
+    hints: Hints,
+}
+
+impl<'a> Optimizer<'a> {
+    fn new(query: Query<'a>) -> Self {
+        let mut relations = vec![];
+        let mut offset = 0;
+        for source in query.relations {
+            let relation = match source {
+                Source::Table(info) => {
+                    let rows = info.stats.as_ref().map_or(DEFAULT_TABLE_ROWS, |s| s.row_count as f64);
+                    Relation {
+                        table: Some(info),
+                        plan: RefCell::new(None),
+                        num_columns: info.columns.len(),
+                        offset,
+                        rows,
+                        scan_cost: rows,
+                        ordering: info.table.scan_ordering(),
+                        needed: vec![],
+                    }
+                }
+                Source::Plan { plan, num_columns } => {
+                    let estimate = plan.estimate();
+                    Relation {
+                        table: None,
+                        ordering: plan.ordering(),
+                        plan: RefCell::new(Some(plan)),
+                        num_columns,
+                        offset,
+                        rows: estimate.rows,
+                        scan_cost: estimate.cost,
+                        needed: vec![],
+                    }
+                }
+            };
+            offset += relation.num_columns;
+            relations.push(relation);
+        }
+        assert!(relations.len() <= 64, "too many tables to join");
+        let mut optimizer = Self {
+            relations,
+            predicates: vec![],
+            edges: vec![],
+            max_build_rows: query.max_build_rows,
+            settings: query.settings,
+            hints: query.hints,
+        };
+
+        let mut needed = query.needed;
+        for expr in query.conjuncts {
+            let mut columns = vec![];
+            expr.columns(&mut columns);
+            let relations = columns.iter().fold(0u64, |set, &c| set | 1 << optimizer.relation_of(c));
+            if let Expr::Binary {
+                op: BinaryOp::Eq,
+                left,
+                right,
+            } = &expr
+            {
+                if let (Expr::Column(a), Expr::Column(b)) = (&**left, &**right) {
+                    let (ra, rb) = (optimizer.relation_of(*a), optimizer.relation_of(*b));
+                    if ra != rb {
+                        let selectivity = 1.0
+                            / optimizer.relations[ra]
+                                .distinct_count(*a)
+                                .max(optimizer.relations[rb].distinct_count(*b));
+                        optimizer.edges.push(Edge {
+                            columns: [*a, *b],
+                            relations: [1 << ra, 1 << rb],
+                            selectivity,
+                        });
+                        needed.extend(columns);
+                        continue;
+                    }
+                }
+            }
+            // predicates on one relation are evaluated by its scan, which reads all its columns
+            if relations.count_ones() > 1 {
+                needed.extend(columns);
+            }
+            let selectivity = match relations.count_ones() {
+                1 => optimizer.selectivity(&expr, &optimizer.relations[relations.trailing_zeros() as usize]),
+                _ => expr.selectivity(),
+            };
+            optimizer.predicates.push(Predicate {
+                expr,
+                relations,
+                selectivity,
+            });
+        }
+        for column in needed {
+            let relation = optimizer.relation_of(column);
+            optimizer.relations[relation].needed.push(column);
+        }
+        for relation in &mut optimizer.relations {
+            relation.needed.sort_unstable();
+            relation.needed.dedup();
+        }
+        optimizer
+    }
+
+    fn relation_of(&self, column: usize) -> usize {
+        self.relations.iter().rposition(|r| r.offset <= column).unwrap()
+    }
+
+    // Fraction of the rows of `relation` for which `expr` holds, from the statistics if any.
+    fn selectivity(&self, expr: &Expr, relation: &Relation) -> f64 {
+        let column_stats = |expr: &Expr| match expr {
+            Expr::Column(c) => relation.column_stats(*c),
+            _ => None,
+        };
+        let row_count = relation.rows as u64;
+        let selectivity = match expr {
+            Expr::Binary {
+                op: BinaryOp::And,
+                left,
+                right,
+            } => self.selectivity(left, relation) * self.selectivity(right, relation),
+            Expr::Binary { op, left, right } => {
+                let (op, stats, value) = match (column_stats(left), column_stats(right)) {
+                    (Some(stats), None) if is_constant(right) => (*op, stats, &**right),
+                    (None, Some(stats)) if is_constant(left) => (flip(*op), stats, &**left),
+                    _ => return expr.selectivity(),
+                };
+                let not_null = 1.0 - stats.null_fraction(row_count);
+                let eq = stats.eq_fraction(row_count);
+                let lt = match value {
+                    Expr::Literal(value) => stats.lt_fraction(row_count, value),
+                    _ => not_null / 3.0,
+                };
+                match op {
+                    BinaryOp::Eq => eq,
+                    BinaryOp::NotEq => not_null - eq,
+                    BinaryOp::Lt => lt,
+                    BinaryOp::LtEq => lt + eq,
+                    BinaryOp::Gt => not_null - lt - eq,
+                    BinaryOp::GtEq => not_null - lt,
+                    _ => return expr.selectivity(),
+                }
+            }
+            Expr::IsNull { expr: operand, negated } => match column_stats(operand) {
+                Some(stats) if *negated => 1.0 - stats.null_fraction(row_count),
+                Some(stats) => stats.null_fraction(row_count),
+                None => expr.selectivity(),
+            },
+            expr => expr.selectivity(),
+        };
+        selectivity.clamp(0.0, 1.0)
+    }
+
+    fn constant_predicates(&self) -> Vec<Expr> {
+        self.predicates
+            .iter()
+            .filter(|p| p.relations == 0)
+            .map(|p| p.expr.remap(&|c| c).unwrap())
+            .collect()
+    }
+
+    // Predicates on `relation` alone.
+    fn local_predicates(&self, relation: usize) -> Vec<usize> {
+        (0..self.predicates.len()).filter(|&p| self.predicates[p].relations == 1 << relation).collect()
+    }
+
+    fn scan(&self, relation: usize) -> Candidate {
+        let rel = &self.relations[relation];
+        let predicates = self.local_predicates(relation);
+        let selectivity = |ps: &[usize]| ps.iter().map(|&p| self.predicates[p].selectivity).product::<f64>();
+        let rows = rel.rows * selectivity(&predicates);
+        let layout = rel.needed.clone();
+        let sorted_prefix = |ordering: Vec<usize>| -> Vec<usize> {
+            ordering.into_iter().map(|c| c + rel.offset).take_while(|c| layout.contains(c)).collect()
+        };
+
+        let filter_cost = |estimate: Estimate| if predicates.is_empty() { estimate.cost } else { Filter::cost(estimate) };
+        let seq_scan = Estimate {
+            rows: rel.rows,
+            cost: rel.scan_cost + PlannerSettings::penalty(self.settings.enable_seqscan || rel.table.is_none()) + self.hints.scan_penalty(relation, None),
+        };
+        let mut best = Candidate {
+            tree: Rc::new(Tree::Scan {
+                relation,
+                index: None,
+                rows,
+            }),
+            relations: 1 << relation,
+            estimate: Estimate {
+                rows,
+                cost: filter_cost(seq_scan),
+            },
+            layout: layout.clone(),
+            ordering: sorted_prefix(rel.ordering.clone()),
+        };
+
+        let Some(info) = rel.table else {
+            return best;
+        };
+        // `column = constant` predicates can be looked up in an index on the column, and the
+        // key column after the looked up ones can be restricted to a range by `column < constant`
+        // and the like, or by LIKE with a constant prefix. `column -> 'a' = constant` and the like
+        // can be looked up in an index on the path into the column, and `column @@ constant` searched
+        // for in a full-text index on the column. `column @> constant`, `column <@ constant` and
+        // `column && constant`, either way around, all have the column's box intersect that of the
+        // constant, which an R-tree index on the column finds the rows of.
+        let mut equalities = vec![];
+        let mut bounds = vec![];
+        let mut searches = vec![];
+        let mut areas = vec![];
+        let column_path = |expr: &Expr| {
+            let column = |expr: &Expr| match expr {
+                Expr::Column(c) => Some(c - rel.offset),
+                _ => None,
+            };
+            match expr::column_path(expr, &json_operator, &column) {
+                Some((c, Some(path))) => Some((c, path)),
+                _ => None,
+            }
+        };
+        for &p in &predicates {
+            match &self.predicates[p].expr {
+                Expr::Binary { op: BinaryOp::Eq, left, right } if !matches!((&**left, &**right), (Expr::Column(_), _) | (_, Expr::Column(_))) => {
+                    let found = [(&**left, &**right), (&**right, &**left)]
+                        .into_iter()
+                        .find_map(|(expr, value)| Some((column_path(expr)?, value)).filter(|_| is_constant(value)));
+                    let Some(((column, path), value)) = found else {
+                        continue;
+                    };
+                    // a value of another type can't be compared with that at the path
+                    if let Expr::Literal(literal) = value {
+                        if DataType::of(literal).is_some_and(|t| t != if path.text { DataType::Text } else { DataType::Json }) {
+                            continue;
+                        }
+                    }
+                    equalities.push((column, Some(path), value, p));
+                }
+                Expr::Binary { op: BinaryOp::Match, left, right } => {
+                    if let Expr::Column(c) = &**left {
+                        if is_constant(right) {
+                            searches.push((c - rel.offset, &**right, p));
+                        }
+                    }
+                }
+                Expr::Binary { op: BinaryOp::Contains | BinaryOp::ContainedBy | BinaryOp::Overlaps, left, right } => {
+                    match (&**left, &**right) {
+                        (Expr::Column(c), value) | (value, Expr::Column(c)) if is_constant(value) => areas.push((c - rel.offset, value, p)),
+                        _ => {}
+                    }
+                }
+                Expr::Binary { op, left, right } => {
+                    let (column, op, value) = match (&**left, &**right) {
+                        (Expr::Column(c), value) if is_constant(value) => (c - rel.offset, *op, value),
+                        (value, Expr::Column(c)) if is_constant(value) => (c - rel.offset, flip(*op), value),
+                        _ => continue,
+                    };
+                    // a value of another type can't be compared with the column
+                    if let Expr::Literal(literal) = value {
+                        if DataType::of(literal).is_some_and(|t| t != info.columns[column].data_type) {
+                            continue;
+                        }
+                    }
+                    let (lower, upper) = match op {
+                        BinaryOp::Eq => {
+                            equalities.push((column, None, value, p));
+                            continue;
+                        }
+                        BinaryOp::Gt => (Bound::Excluded(value), Bound::Unbounded),
+                        BinaryOp::GtEq => (Bound::Included(value), Bound::Unbounded),
+                        BinaryOp::Lt => (Bound::Unbounded, Bound::Excluded(value)),
+                        BinaryOp::LtEq => (Bound::Unbounded, Bound::Included(value)),
+                        _ => continue,
+                    };
+                    bounds.push(ColumnBounds {
+                        column,
+                        predicate: p,
+                        lower: copy_bound(lower),
+                        upper: copy_bound(upper),
+                        exact: true,
+                    });
+                }
+                Expr::Like {
+                    expr,
+                    pattern,
+                    negated: false,
+                    case_insensitive: false,
+                } => {
+                    if let (Expr::Column(c), Expr::Literal(Value::Text(pattern))) = (&**expr, &**pattern) {
+                        let prefix = like_prefix(pattern);
+                        if !prefix.is_empty() {
+                            let upper = successor(&prefix).map_or(Bound::Unbounded, |s| Bound::Excluded(Expr::Literal(Value::Text(s))));
+                            bounds.push(ColumnBounds {
+                                column: c - rel.offset,
+                                predicate: p,
+                                lower: Bound::Included(Expr::Literal(Value::Text(prefix))),
+                                upper,
+                                exact: false,
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        let columns: Vec<_> = equalities.iter().map(|(c, path, _, _)| (*c, path.as_ref())).collect();
+        for (access, _) in info.table.access_paths() {
+            let prefix: Vec<_> = info.table.key_expressions(access).into_iter().take_while(|key| columns.contains(key)).collect();
+            let range_column = info.table.key_columns(access).get(prefix.len()).copied();
+            let lower = bounds.iter().find(|b| Some(b.column) == range_column && !matches!(b.lower, Bound::Unbounded));
+            let upper = bounds.iter().find(|b| Some(b.column) == range_column && !matches!(b.upper, Bound::Unbounded));
+            if prefix.is_empty() && lower.is_none() && upper.is_none() {
+                continue;
+            }
+            let mut keys = vec![];
+            let mut used = vec![];
+            for key in prefix {
+                let (_, _, value, p) = equalities.iter().find(|(c, path, _, _)| (*c, path.as_ref()) == key).unwrap();
+                keys.push(value.remap(&|c| c).unwrap());
+                used.push(*p);
+            }
+            // predicates restricting the rows found
+            let mut restricting = used.clone();
+            let range = if lower.is_some() || upper.is_some() {
+                for b in lower.iter().chain(upper.iter()) {
+                    if !restricting.contains(&b.predicate) {
+                        restricting.push(b.predicate);
+                        if b.exact {
+                            used.push(b.predicate);
+                        }
+                    }
+                }
+                Some(KeyRange {
+                    lower: lower.map_or(Bound::Unbounded, |b| copy_bound(b.lower.as_ref())),
+                    upper: upper.map_or(Bound::Unbounded, |b| copy_bound(b.upper.as_ref())),
+                })
+            } else {
+                None
+            };
+            let found = rel.rows * selectivity(&restricting);
+            let index_scan = Estimate {
+                rows: found,
+                cost: IndexScan::cost(access, rel.rows, found),
+            };
+            let rest = predicates.len() > used.len();
+            let cost = if rest { Filter::cost(index_scan) } else { index_scan.cost } + PlannerSettings::penalty(self.settings.enable_indexscan) + self.hints.scan_penalty(relation, Some(access));
+            if cost < best.estimate.cost {
+                best.ordering = sorted_prefix(info.table.key_columns(access));
+                best.estimate.cost = cost;
+                best.tree = Rc::new(Tree::Scan {
+                    relation,
+                    index: Some(Lookup {
+                        access,
+                        keys,
+                        range,
+                        used,
+                        found,
+                    }),
+                    rows,
+                });
+            }
+        }
+        for (i, index) in info.table.indexes.iter().enumerate().filter(|(_, index)| index.valid && matches!(index.kind, IndexKind::FullText | IndexKind::RTree)) {
+            let found = match index.kind {
+                IndexKind::FullText => searches.iter().find(|(c, _, _)| *c == index.columns[0]),
+                _ => areas.iter().find(|(c, _, _)| *c == index.columns[0]),
+            };
+            let Some((_, value, p)) = found else {
+                continue;
+            };
+            let access = Access::Index(i);
+            let found = rel.rows * selectivity(&[*p]);
+            let index_scan = Estimate {
+                rows: found,
+                cost: IndexScan::cost(access, rel.rows, found),
+            };
+            // the area found only narrows down the rows satisfying the predicate
+            let used = if index.kind == IndexKind::FullText { vec![*p] } else { vec![] };
+            let rest = predicates.len() > used.len();
+            let cost = if rest { Filter::cost(index_scan) } else { index_scan.cost } + PlannerSettings::penalty(self.settings.enable_indexscan) + self.hints.scan_penalty(relation, Some(access));
+            if cost < best.estimate.cost {
+                // by rank, or by key, rather than by any column
+                best.ordering = vec![];
+                best.estimate.cost = cost;
+                best.tree = Rc::new(Tree::Scan {
+                    relation,
+                    index: Some(Lookup {
+                        access,
+                        keys: vec![value.remap(&|c| c).unwrap()],
+                        range: None,
+                        used,
+                        found,
+                    }),
+                    rows,
+                });
+            }
+        }
+        best
+    }
+
+    // Candidate plans joining `left` with `right`.
+    fn joins(&self, left: &Candidate, right: &Candidate) -> Vec<Candidate> {
+        let relations = left.relations | right.relations;
+        let mut keys = vec![];
+        let mut key_selectivity = 1.0;
+        for edge in &self.edges {
+            let [a, b] = edge.columns;
+            let key = if edge.relations[0] & left.relations != 0 && edge.relations[1] & right.relations != 0 {
+                (a, b)
+            } else if edge.relations[1] & left.relations != 0 && edge.relations[0] & right.relations != 0 {
+                (b, a)
+            } else {
+                continue;
+            };
+            keys.push(key);
+            key_selectivity *= edge.selectivity;
+        }
+        let rows = left.estimate.rows * right.estimate.rows * key_selectivity;
+        let predicates: Vec<_> = (0..self.predicates.len())
+            .filter(|&p| {
+                let set = self.predicates[p].relations;
+                set & relations == set && set & left.relations != set && set & right.relations != set
+            })
+            .collect();
+        let filtered_rows = rows * predicates.iter().map(|&p| self.predicates[p].selectivity).product::<f64>();
+        let filter_cost = |cost: f64| if predicates.is_empty() { cost } else { Filter::cost(Estimate { rows, cost }) };
+        let mut layout = left.layout.clone();
+        layout.extend(&right.layout);
+        let candidate = |method, keys: &[(usize, usize)], cost: f64, ordering| Candidate {
+            tree: Rc::new(Tree::Join {
+                method,
+                left: left.tree.clone(),
+                right: right.tree.clone(),
+                keys: keys.to_vec(),
+                predicates: predicates.clone(),
+                rows: filtered_rows,
+            }),
+            relations,
+            estimate: Estimate {
+                rows: filtered_rows,
+                cost: cost + self.settings.method_penalty(method) + self.hints.join_penalty(left.relations, right.relations, method),
+            },
+            layout: layout.clone(),
+            ordering,
+        };
+
+        if keys.is_empty() {
+            // the predicates are evaluated by the join itself
+            let cost = NestedLoopJoin::cost(left.estimate, right.estimate);
+            return vec![candidate(Method::NestedLoop, &keys, cost, left.ordering.clone())];
+        }
+        let mut candidates = vec![];
+        let cost = HashJoin::cost(left.estimate, right.estimate, self.max_build_rows);
+        candidates.push(candidate(Method::Hash, &keys, filter_cost(cost), vec![]));
+        // both inputs must be sorted by the keys in the same order
+        let n = keys.len();
+        if left.ordering.len() >= n && right.ordering.len() >= n {
+            let sorted_keys: Vec<_> = left.ordering[..n].iter().copied().zip(right.ordering[..n].iter().copied()).collect();
+            if keys.iter().all(|key| sorted_keys.contains(key)) {
+                let cost = MergeJoin::cost(left.estimate, right.estimate);
+                let ordering = left.ordering[..n].to_vec();
+                candidates.push(candidate(Method::Merge, &sorted_keys, filter_cost(cost), ordering));
+            }
+        }
+        if let Tree::Scan { relation, .. } = &*right.tree {
+            let rel = &self.relations[*relation];
+            let right_columns: Vec<_> = keys.iter().map(|(_, r)| r - rel.offset).collect();
+            if let Some((access, _)) = rel.table.and_then(|info| info.table.access_path(&right_columns)) {
+                // the table's own predicates are evaluated after the lookups too
+                let found = left.estimate.rows * rel.rows * key_selectivity;
+                let outer = IndexNestedLoopJoin::cost(left.estimate, rel.rows, found);
+                let local = !self.local_predicates(*relation).is_empty();
+                let cost = if local || !predicates.is_empty() {
+                    Filter::cost(Estimate {
+                        rows: found,
+                        cost: outer,
+                    })
+                } else {
+                    outer
+                } + self.hints.scan_penalty(*relation, Some(access));
+                candidates.push(candidate(Method::IndexNestedLoop, &keys, cost, left.ordering.clone()));
+            }
+        }
+        candidates
+    }
+
+    fn search(&self) -> Candidate {
+        let n = self.relations.len();
+        let scans: Vec<_> = (0..n).map(|r| self.scan(r)).collect();
+        if n > MAX_EXHAUSTIVE_RELATIONS {
+            return self.search_greedy(scans);
+        }
+        // best plan for each set of relations, built from the best plans of its subsets
+        let mut best: HashMap<u64, Candidate> = scans.into_iter().map(|c| (c.relations, c)).collect();
+        for set in 1..1u64 << n {
+            if set.count_ones() < 2 {
+                continue;
+            }
+            let mut subset = (set - 1) & set;
+            while subset > 0 {
+                for candidate in self.joins(&best[&subset], &best[&(set ^ subset)]) {
+                    keep_cheaper(&mut best, candidate);
+                }
+                subset = (subset - 1) & set;
+            }
+        }
+        best.remove(&((1u64 << n) - 1)).unwrap()
+    }
+
+    // Starts from the smallest table and joins the table which is cheapest to join next.
+    fn search_greedy(&self, mut scans: Vec<Candidate>) -> Candidate {
+        let first = (0..scans.len())
+            .min_by(|&a, &b| scans[a].estimate.rows.total_cmp(&scans[b].estimate.rows))
+            .unwrap();
+        let mut current = scans.remove(first);
+        while !scans.is_empty() {
+            let mut next: Option<(usize, Candidate)> = None;
+            for (i, scan) in scans.iter().enumerate() {
+                let mut candidates = self.joins(&current, scan);
+                candidates.extend(self.joins(scan, &current));
+                for candidate in candidates {
+                    if next.as_ref().is_none_or(|(_, n)| candidate.estimate.cost < n.estimate.cost) {
+                        next = Some((i, candidate));
+                    }
+                }
+            }
+            let (i, candidate) = next.unwrap();
+            scans.remove(i);
+            current = candidate;
+        }
+        current
+    }
+
+    // Builds the plan chosen in `tree`, also returning the query columns of its output tuples.
+    fn realize(&self, tree: &Tree) -> (Box<dyn PlanNode>, Vec<usize>) {
+        match tree {
+            Tree::Scan { relation, index, rows } => {
+                let rel = &self.relations[*relation];
+                let mut predicates = self.local_predicates(*relation);
+                let zone_map = rel.table.and_then(|info| self.zone_map(info, &predicates, rel.offset));
+                let mut plan: Box<dyn PlanNode> = match (rel.table, index) {
+                    (None, _) => rel.plan.borrow_mut().take().expect("relation realized twice"),
+                    (Some(info), None) if info.table.columnar.is_some() => {
+                        // of the columns of the table, those read above the scan and by the predicates
+                        let conjuncts: Vec<_> = predicates.iter().map(|&p| self.predicates[p].expr.remap(&|c| c - rel.offset).unwrap()).collect();
+                        let mut columns: Vec<_> = rel.needed.iter().map(|c| c - rel.offset).collect();
+                        conjuncts.iter().for_each(|conjunct| conjunct.columns(&mut columns));
+                        columns.sort_unstable();
+                        columns.dedup();
+                        Box::new(ColumnarScan {
+                            table: info.table.clone(),
+                            name: info.name.clone(),
+                            columns,
+                            conjuncts,
+                            types: info.columns.iter().map(|column| column.data_type).collect(),
+                            rows: rel.rows,
+                        })
+                    }
+                    (Some(info), None) if zone_map.is_some() => {
+                        let conjuncts: Vec<_> = predicates.iter().map(|&p| self.predicates[p].expr.remap(&|c| c - rel.offset).unwrap()).collect();
+                        Box::new(ZoneMapScan {
+                            table: info.table.clone(),
+                            name: info.name.clone(),
+                            index: zone_map.unwrap(),
+                            conjuncts,
+                            types: info.columns.iter().map(|column| column.data_type).collect(),
+                            rows: rel.rows,
+                        })
+                    }
+                    (Some(info), None) => {
+                        let scan = SeqScan {
+                            table: info.table.clone(),
+                            name: info.name.clone(),
+                            rows: rel.rows,
+                        };
+                        match rel.rows >= PARALLEL_SCAN_MIN_ROWS {
+                            true => Box::new(Gather { scan }),
+                            false => Box::new(scan),
+                        }
+                    }
+                    (Some(info), Some(Lookup { access: Access::Index(i), keys, used, found, .. })) if info.table.indexes[*i].kind == IndexKind::FullText => {
+                        predicates.retain(|p| !used.contains(p));
+                        Box::new(FullTextScan {
+                            table: info.table.clone(),
+                            name: info.name.clone(),
+                            index: *i,
+                            query: keys[0].remap(&|c| c).unwrap(),
+                            table_rows: rel.rows,
+                            rows: *found,
+                        })
+                    }
+                    (Some(info), Some(Lookup { access: Access::Index(i), keys, found, .. })) if info.table.indexes[*i].kind == IndexKind::RTree => {
+                        Box::new(SpatialScan {
+                            table: info.table.clone(),
+                            name: info.name.clone(),
+                            index: *i,
+                            area: keys[0].remap(&|c| c).unwrap(),
+                            table_rows: rel.rows,
+                            rows: *found,
+                        })
+                    }
+                    (Some(info), Some(lookup)) => {
+                        predicates.retain(|p| !lookup.used.contains(p));
+                        Box::new(IndexScan {
+                            table: info.table.clone(),
+                            name: info.name.clone(),
+                            access: lookup.access,
+                            keys: lookup.keys.iter().map(|k| k.remap(&|c| c).unwrap()).collect(),
+                            range: lookup.range.as_ref().map(|range| KeyRange {
+                                lower: copy_bound(range.lower.as_ref()),
+                                upper: copy_bound(range.upper.as_ref()),
+                            }),
+                            table_rows: rel.rows,
+                            rows: lookup.found,
+                        })
+                    }
+                };
+                let all: Vec<_> = (rel.offset..rel.offset + rel.num_columns).collect();
+                if !predicates.is_empty() {
+                    plan = self.filter(plan, &predicates, &all, *rows);
+                }
+                (self.prune(plan, &all, &rel.needed), rel.needed.clone())
+            }
+            Tree::Join {
+                method,
+                left,
+                right,
+                keys,
+                predicates,
+                rows,
+            } => {
+                let (left, left_layout) = self.realize(left);
+                let position = |layout: &[usize], column: usize| layout.iter().position(|&c| c == column).unwrap();
+                if *method == Method::IndexNestedLoop {
+                    let relation = match &**right {
+                        Tree::Scan { relation, .. } => *relation,
+                        Tree::Join { .. } => unreachable!(),
+                    };
+                    let rel = &self.relations[relation];
+                    let info = rel.table.unwrap();
+                    let right_columns: Vec<_> = keys.iter().map(|(_, r)| r - rel.offset).collect();
+                    let (access, order) = info.table.access_path(&right_columns).unwrap();
+                    let plan = Box::new(IndexNestedLoopJoin {
+                        outer: left,
+                        table: info.table.clone(),
+                        name: info.name.clone(),
+                        table_rows: rel.rows,
+                        access,
+                        outer_keys: order.iter().map(|&i| position(&left_layout, keys[i].0)).collect(),
+                        max_build_rows: self.max_build_rows,
+                        switched: Default::default(),
+                    });
+                    let mut joined = left_layout.clone();
+                    joined.extend(rel.offset..rel.offset + rel.num_columns);
+                    let mut all_predicates = self.local_predicates(relation);
+                    all_predicates.extend(predicates);
+                    let plan = self.filter(plan, &all_predicates, &joined, *rows);
+                    let mut layout = left_layout;
+                    layout.extend(&rel.needed);
+                    return (self.prune(plan, &joined, &layout), layout);
+                }
+
+                let (right, right_layout) = self.realize(right);
+                let mut layout = left_layout.clone();
+                layout.extend(&right_layout);
+                let left_keys = keys.iter().map(|(l, _)| position(&left_layout, *l)).collect();
+                let right_keys = keys.iter().map(|(_, r)| position(&right_layout, *r)).collect();
+                let plan: Box<dyn PlanNode> = match method {
+                    Method::NestedLoop => {
+                        let join = NestedLoopJoin {
+                            left,
+                            right,
+                            predicate: conjunction(self.bind(predicates, &layout)),
+                        };
+                        return (Box::new(Estimated { child: Box::new(join), rows: *rows }), layout);
+                    }
+                    Method::Hash => Box::new(HashJoin {
+                        left,
+                        right,
+                        left_keys,
+                        right_keys,
+                        max_build_rows: self.max_build_rows,
+                        num_partitions: DEFAULT_NUM_PARTITIONS,
+                    }),
+                    Method::Merge => Box::new(MergeJoin {
+                        left,
+                        right,
+                        left_keys,
+                        right_keys,
+                    }),
+                    Method::IndexNestedLoop => unreachable!(),
+                };
+                (self.filter(plan, predicates, &layout, *rows), layout)
+            }
+        }
+    }
+
+    // The zone map index of the most columns of the table `predicates` on its relation, whose
+    // columns start at `offset`, are on, if they're on any.
+    fn zone_map(&self, info: &TableInfo, predicates: &[usize], offset: usize) -> Option<usize> {
+        let mut columns = vec![];
+        predicates.iter().for_each(|&p| self.predicates[p].expr.columns(&mut columns));
+        let zone_maps = info.table.indexes.iter().enumerate().filter(|(_, index)| index.valid && index.kind == IndexKind::ZoneMap);
+        zone_maps
+            .map(|(i, index)| (i, index.columns.iter().filter(|&&c| columns.contains(&(c + offset))).count()))
+            .filter(|&(_, n)| n > 0)
+            .max_by_key(|&(_, n)| n)
+            .map(|(i, _)| i)
+    }
+
+    // `predicates` bound to tuples of `layout`.
+    fn bind(&self, predicates: &[usize], layout: &[usize]) -> Vec<Expr> {
+        let position = |column| layout.iter().position(|&c| c == column).unwrap();
+        predicates.iter().map(|&p| self.predicates[p].expr.remap(&position).unwrap()).collect()
+    }
+
+    // Filters `plan` returning tuples of `layout` by `predicates`, which leaves `rows` rows.
+    fn filter(&self, plan: Box<dyn PlanNode>, predicates: &[usize], layout: &[usize], rows: f64) -> Box<dyn PlanNode> {
+        let plan = match conjunction(self.bind(predicates, layout)) {
+            Some(predicate) => Box::new(Filter { child: plan, predicate }),
+            None => plan,
+        };
+        Box::new(Estimated { child: plan, rows })
+    }
+
+    // Drops the columns of tuples of `layout` which aren't in `needed`.
+    fn prune(&self, plan: Box<dyn PlanNode>, layout: &[usize], needed: &[usize]) -> Box<dyn PlanNode> {
+        if layout == needed {
+            return plan;
+        }
+        let exprs = needed
+            .iter()
+            .map(|&column| Expr::Column(layout.iter().position(|&c| c == column).unwrap()))
+            .collect();
+        Box::new(Project { child: plan, exprs })
+    }
+}
+
+fn keep_cheaper(best: &mut HashMap<u64, Candidate>, candidate: Candidate) {
+    match best.get(&candidate.relations) {
+        Some(current) if current.estimate.cost <= candidate.estimate.cost => {}
+        _ => {
+            best.insert(candidate.relations, candidate);
+        }
+    }
+}
+
+// The left operand, the operator and the constant right operand of a JSON operator (see
+// `expr::column_path`).
+fn json_operator(expr: &Expr) -> Option<(&Expr, BinaryOp, &Value)> {
+    match expr {
+        Expr::Binary { op: op @ (BinaryOp::JsonGet | BinaryOp::JsonGetText | BinaryOp::JsonPath | BinaryOp::JsonPathText), left, right } => match &**right {
+            Expr::Literal(operand) => Some((&**left, *op, operand)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Whether `expr` has the same value for all rows of the query.
+fn is_constant(expr: &Expr) -> bool {
+    let mut columns = vec![];
+    expr.columns(&mut columns);
+    columns.is_empty() && !expr.has_subquery()
+}
+
+// Copy of a bound given by a constant expression.
+fn copy_bound(bound: Bound<&Expr>) -> Bound<Expr> {
+    bound.map(|expr| expr.remap(&|c| c).unwrap())
+}
+
+// The least string greater than all strings starting with `prefix`, None if there's none.
+fn successor(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(c) = chars.pop() {
+        // the next character, skipping the surrogates which aren't characters
+        let next = (c as u32 + 1..=char::MAX as u32).find_map(char::from_u32);
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
+
+// `op` with its operands swapped.
+fn flip(op: BinaryOp) -> BinaryOp {
+    match op {
+        BinaryOp::Lt => BinaryOp::Gt,
+        BinaryOp::LtEq => BinaryOp::GtEq,
+        BinaryOp::Gt => BinaryOp::Lt,
+        BinaryOp::GtEq => BinaryOp::LtEq,
+        op => op,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::{BufferPool, BufferPoolManager};
+    use crate::catalog::Catalog;
+    use crate::disk::DiskManager;
+    use crate::sql::{execute, QueryResult};
+    use crate::tuple::Value;
+    use tempfile::tempfile;
+
+    fn explain(bufmgr: &mut BufferPoolManager, catalog: &mut Catalog, sql: &str) -> Vec<String> {
+        match execute(bufmgr, catalog, &format!("EXPLAIN {}", sql)).unwrap().pop().unwrap() {
+            QueryResult::Rows { rows, .. } => rows
+                .into_iter()
+                .map(|row| match &row[0] {
+                    Value::Text(line) => line.clone(),
+                    value => panic!("unexpected value: {:?}", value),
+                })
+                .collect(),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(16));
+        let mut catalog = Catalog::create(&mut bufmgr).unwrap();
+        let users: Vec<_> = (0..200).map(|i| format!("({}, 'user{}', {})", i, i, i % 3)).collect();
+        let sql = format!(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, team INTEGER);
+             CREATE TABLE teams (id INTEGER PRIMARY KEY, title TEXT);
+             INSERT INTO users VALUES {};
+             INSERT INTO teams VALUES (0, 'db'), (1, 'web'), (2, 'ops');
+             ANALYZE;",
+            users.join(", ")
+        );
+        execute(&mut bufmgr, &mut catalog, &sql).unwrap();
+        let (b, c) = (&mut bufmgr, &mut catalog);
+
+        // the users row is found through the primary key, then its team
+        let plan = explain(b, c, "SELECT u.name, t.title FROM teams t JOIN users u ON u.team = t.id WHERE u.id = 7");
+        assert!(plan[1].contains("Index Nested Loop Join on teams using primary key"), "{:?}", plan);
+        assert!(plan[3].contains("Index Scan on users using primary key (keys: 7)"), "{:?}", plan);
+        // the smaller input is hashed, and only the columns used above the scans are kept
+        let plan = explain(b, c, "SELECT u.name FROM users u, teams t WHERE u.team = t.id AND t.title = 'web'");
+        assert!(plan[1].contains("Hash Join (keys: [1] = [0])"), "{:?}", plan);
+        assert!(plan[2].contains("Project: #1, #2"), "{:?}", plan);
+        assert!(plan[5].contains("Filter: (#1 = 'web')"), "{:?}", plan);
+        // with an index on users.team, the filtered teams can drive an index nested loop join
+        execute(b, c, "CREATE INDEX users_team ON users (team)").unwrap();
+        let plan = explain(b, c, "SELECT u.name FROM users u, teams t WHERE u.team = t.id AND t.title = 'web'");
+        assert!(plan[2].contains("Index Nested Loop Join on users using index [2]"), "{:?}", plan);
+        // comparisons with constants and prefix LIKE patterns bound index range scans
+        let plan = explain(b, c, "SELECT name FROM users WHERE id >= 10 AND id < 13");
+        assert!(plan.iter().any(|line| line.contains("Index Scan on users using primary key (range: >= 10 AND < 13)")), "{:?}", plan);
+        assert!(!plan.iter().any(|line| line.contains("Filter")), "{:?}", plan);
+        execute(b, c, "CREATE INDEX users_name ON users (name)").unwrap();
+        let sql = "SELECT id FROM users WHERE name LIKE 'user1%'";
+        let plan = explain(b, c, sql);
+        assert!(plan.iter().any(|line| line.contains("Index Scan on users using index [1] (range: >= 'user1' AND < 'user2')")), "{:?}", plan);
+        assert!(plan.iter().any(|line| line.contains("Filter: #1 LIKE 'user1%'")), "{:?}", plan);
+        match execute(b, c, sql).unwrap().pop() {
+            Some(QueryResult::Rows { rows, .. }) => assert_eq!(111, rows.len()),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // hints have the plans be what the estimates wouldn't
+        let plan = explain(b, c, "SELECT /*+ SEQSCAN(users) */ name FROM users WHERE id >= 10 AND id < 13");
+        assert!(plan[3].contains("Seq Scan on users"), "{:?}", plan);
+        let plan = explain(b, c, "SELECT /*+ INDEX(users users_name) */ name FROM users WHERE id = 5 AND name = 'user5'");
+        assert!(plan[3].contains("Index Scan on users using index [1] (keys: 'user5')"), "{:?}", plan);
+        let sql = "u.name FROM users u, teams t WHERE u.team = t.id AND t.title = 'web'";
+        let plan = explain(b, c, &format!("SELECT /*+ HASHJOIN(u t) */ {}", sql));
+        assert!(plan[1].contains("Hash Join (keys: [1] = [0])"), "{:?}", plan);
+        // users first, so joined to teams by their index on the lookup side
+        let plan = explain(b, c, &format!("SELECT /*+ LEADING(u t) NESTLOOP(u t) */ {}", sql));
+        assert!(plan[3].contains("Index Nested Loop Join on teams using primary key"), "{:?}", plan);
+        // or teams first, probing the hash of users
+        let plan = explain(b, c, &format!("SELECT /*+ LEADING(t u) HASHJOIN(t u) */ {}", sql));
+        assert!(plan[1].contains("Hash Join (keys: [0] = [1])") && plan[4].contains("Seq Scan on teams"), "{:?}", plan);
+        assert!(execute(b, c, "SELECT /*+ SEQSCAN(users) */ 1 FROM users u").is_err());
+        assert!(execute(b, c, "SELECT /*+ INDEX(u users_none) */ 1 FROM users u").is_err());
+        match execute(b, c, "SELECT u.name, t.title FROM users u, teams t WHERE u.team = t.id AND u.id < 3").unwrap().pop() {
+            Some(QueryResult::Rows { mut rows, .. }) => {
+                rows.sort();
+                let expected: Vec<_> = [("user0", "db"), ("user1", "web"), ("user2", "ops")]
+                    .iter()
+                    .map(|(u, t)| vec![Value::Text(u.to_string()), Value::Text(t.to_string())])
+                    .collect();
+                assert_eq!(expected, rows);
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
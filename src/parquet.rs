@@ -0,0 +1,1097 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::array;
+use crate::json;
+use crate::datetime;
+use crate::lz4;
+use crate::numeric::Float;
+use crate::tuple::{DataType, Tuple, Value};
+
+// Parquet files of flat columns, as COPY writes and reads them. A file is
+//   PAR1 [row group ...] [file metadata] [metadata length: u32] PAR1
+// where each row group has a chunk of pages for each column, and the metadata, of the schema and
+// where the chunks are, is a Thrift struct of the compact protocol. Written files have a column
+// INT64, DOUBLE, BOOLEAN or BYTE_ARRAY (UTF-8, or JSON of its text) for each of integer, double
+// precision, boolean, text and json, arrays, numerics, dates and timestamps being UTF-8 of their
+// text, all OPTIONAL, and a page of each column a row group, the values PLAIN and the page
+// compressed with LZ4_RAW.
+//
+// Other writers' files read as long as their columns are flat, of those types or INT32, and their
+// pages PLAIN or dictionary encoded, uncompressed or compressed with SNAPPY or LZ4_RAW.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("invalid parquet file: {0}")]
+    Corrupt(String),
+    #[error("unsupported parquet file: {0}")]
+    Unsupported(String),
+    #[error("{value:?} is not a value of column {column}")]
+    TypeMismatch { column: String, value: Value },
+}
+
+const MAGIC: &[u8; 4] = b"PAR1";
+
+// Rows a row group holds, buffered in memory until they're written.
+pub const ROW_GROUP_SIZE: usize = 64 * 1024;
+
+// physical types
+const BOOLEAN: i32 = 0;
+const INT32: i32 = 1;
+const INT64: i32 = 2;
+const DOUBLE: i32 = 5;
+const BYTE_ARRAY: i32 = 6;
+
+// repetition types
+const REQUIRED: i32 = 0;
+const OPTIONAL: i32 = 1;
+
+// encodings
+const PLAIN: i32 = 0;
+const PLAIN_DICTIONARY: i32 = 2;
+const RLE: i32 = 3;
+const RLE_DICTIONARY: i32 = 8;
+
+// compression codecs
+const UNCOMPRESSED: i32 = 0;
+const SNAPPY: i32 = 1;
+const LZ4_RAW: i32 = 7;
+
+// page types
+const DATA_PAGE: i32 = 0;
+const DICTIONARY_PAGE: i32 = 2;
+const DATA_PAGE_V2: i32 = 3;
+
+// Writes rows to a Parquet file, a row group every ROW_GROUP_SIZE rows and one of the rest when
+// finished.
+pub struct Writer<W> {
+    output: W,
+    // bytes written so far
+    offset: u64,
+    columns: Vec<(String, DataType)>,
+    rows: Vec<Tuple>,
+    row_groups: Vec<Thrift>,
+    num_rows: i64,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(mut output: W, columns: Vec<(String, DataType)>) -> Result<Self, Error> {
+        output.write_all(MAGIC)?;
+        Ok(Self {
+            output,
+            offset: MAGIC.len() as u64,
+            columns,
+            rows: vec![],
+            row_groups: vec![],
+            num_rows: 0,
+        })
+    }
+
+    pub fn write_row(&mut self, row: Tuple) -> Result<(), Error> {
+        assert_eq!(self.columns.len(), row.len(), "a value for each column");
+        for ((name, data_type), value) in self.columns.iter().zip(&row) {
+            if !data_type.accepts(value) {
+                return Err(Error::TypeMismatch { column: name.clone(), value: value.clone() });
+            }
+        }
+        self.rows.push(row);
+        if self.rows.len() == ROW_GROUP_SIZE {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    // Writes the rest of the rows and the metadata, returning the output.
+    pub fn finish(mut self) -> Result<W, Error> {
+        if !self.rows.is_empty() {
+            self.write_row_group()?;
+        }
+        let mut schema = vec![Thrift::Struct(vec![
+            (4, Thrift::Binary(b"schema".to_vec())),
+            (5, Thrift::I32(self.columns.len() as i32)),
+        ])];
+        for (name, data_type) in &self.columns {
+            let mut fields = vec![
+                (1, Thrift::I32(physical_type(*data_type))),
+                (3, Thrift::I32(OPTIONAL)),
+                (4, Thrift::Binary(name.as_bytes().to_vec())),
+            ];
+            if matches!(data_type, DataType::Text | DataType::Array(_) | DataType::Point | DataType::Box | DataType::Numeric | DataType::Date | DataType::Timestamp) {
+                // the UTF8 converted type, and the STRING logical type
+                fields.push((6, Thrift::I32(0)));
+                fields.push((10, Thrift::Struct(vec![(1, Thrift::Struct(vec![]))])));
+            }
+            if *data_type == DataType::Json {
+                // the JSON converted type, and the JSON logical type
+                fields.push((6, Thrift::I32(19)));
+                fields.push((10, Thrift::Struct(vec![(12, Thrift::Struct(vec![]))])));
+            }
+            schema.push(Thrift::Struct(fields));
+        }
+        let metadata = Thrift::Struct(vec![
+            (1, Thrift::I32(1)),
+            (2, Thrift::List(STRUCT, schema)),
+            (3, Thrift::I64(self.num_rows)),
+            (4, Thrift::List(STRUCT, std::mem::take(&mut self.row_groups))),
+            (6, Thrift::Binary(b"beyond_rdb".to_vec())),
+        ]);
+        let mut bytes = vec![];
+        metadata.encode_struct(&mut bytes);
+        self.output.write_all(&bytes)?;
+        self.output.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        self.output.write_all(MAGIC)?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+
+    fn write_row_group(&mut self) -> Result<(), Error> {
+        let rows = std::mem::take(&mut self.rows);
+        let start = self.offset;
+        let (mut chunks, mut total_size) = (vec![], 0);
+        for (i, (name, data_type)) in self.columns.iter().enumerate() {
+            // the definition levels, 1 for a value and 0 for NULL, run-length encoded
+            let mut levels = vec![];
+            let mut j = 0;
+            while j < rows.len() {
+                let defined = !rows[j][i].is_null();
+                let run = rows[j..].iter().take_while(|row| row[i].is_null() != defined).count();
+                write_varint(&mut levels, (run as u64) << 1);
+                levels.push(defined as u8);
+                j += run;
+            }
+            let mut page = (levels.len() as u32).to_le_bytes().to_vec();
+            page.extend_from_slice(&levels);
+            let mut bits = 0;
+            for row in &rows {
+                match &row[i] {
+                    Value::Null => {}
+                    Value::Int(n) => page.extend_from_slice(&n.to_le_bytes()),
+                    Value::Float(f) => page.extend_from_slice(&f.0.to_le_bytes()),
+                    Value::Text(s) => {
+                        page.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                        page.extend_from_slice(s.as_bytes());
+                    }
+                    Value::Json(bytes) => {
+                        let s = json::to_string(bytes);
+                        page.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                        page.extend_from_slice(s.as_bytes());
+                    }
+                    Value::Array(values) => {
+                        let s = array::to_string(values);
+                        page.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                        page.extend_from_slice(s.as_bytes());
+                    }
+                    Value::Point(point) => {
+                        let s = point.to_string();
+                        page.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                        page.extend_from_slice(s.as_bytes());
+                    }
+                    Value::Box(rect) => {
+                        let s = rect.to_string();
+                        page.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                        page.extend_from_slice(s.as_bytes());
+                    }
+                    Value::Numeric(n) => {
+                        let s = n.to_string();
+                        page.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                        page.extend_from_slice(s.as_bytes());
+                    }
+                    Value::Date(days) => {
+                        let s = datetime::format_date(*days);
+                        page.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                        page.extend_from_slice(s.as_bytes());
+                    }
+                    Value::Timestamp(micros) => {
+                        let s = datetime::format_timestamp(*micros);
+                        page.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                        page.extend_from_slice(s.as_bytes());
+                    }
+                    // bit-packed, lowest bit first
+                    Value::Bool(b) => {
+                        if bits % 8 == 0 {
+                            page.push(0);
+                        }
+                        *page.last_mut().unwrap() |= (*b as u8) << (bits % 8);
+                        bits += 1;
+                    }
+                }
+            }
+            let compressed = lz4::compress(&page);
+            let header = Thrift::Struct(vec![
+                (1, Thrift::I32(DATA_PAGE)),
+                (2, Thrift::I32(page.len() as i32)),
+                (3, Thrift::I32(compressed.len() as i32)),
+                (
+                    5,
+                    Thrift::Struct(vec![
+                        (1, Thrift::I32(rows.len() as i32)),
+                        (2, Thrift::I32(PLAIN)),
+                        (3, Thrift::I32(RLE)),
+                        (4, Thrift::I32(RLE)),
+                    ]),
+                ),
+            ]);
+            let mut bytes = vec![];
+            header.encode_struct(&mut bytes);
+            let offset = self.offset;
+            let (uncompressed_size, compressed_size) = (bytes.len() + page.len(), bytes.len() + compressed.len());
+            bytes.extend_from_slice(&compressed);
+            self.output.write_all(&bytes)?;
+            self.offset += bytes.len() as u64;
+            total_size += uncompressed_size;
+            let metadata = Thrift::Struct(vec![
+                (1, Thrift::I32(physical_type(*data_type))),
+                (2, Thrift::List(I32, vec![Thrift::I32(PLAIN), Thrift::I32(RLE)])),
+                (3, Thrift::List(BINARY, vec![Thrift::Binary(name.as_bytes().to_vec())])),
+                (4, Thrift::I32(LZ4_RAW)),
+                (5, Thrift::I64(rows.len() as i64)),
+                (6, Thrift::I64(uncompressed_size as i64)),
+                (7, Thrift::I64(compressed_size as i64)),
+                (9, Thrift::I64(offset as i64)),
+            ]);
+            chunks.push(Thrift::Struct(vec![(2, Thrift::I64(offset as i64)), (3, metadata)]));
+        }
+        self.row_groups.push(Thrift::Struct(vec![
+            (1, Thrift::List(STRUCT, chunks)),
+            (2, Thrift::I64(total_size as i64)),
+            (3, Thrift::I64(rows.len() as i64)),
+            (5, Thrift::I64(start as i64)),
+            (6, Thrift::I64((self.offset - start) as i64)),
+        ]));
+        self.num_rows += rows.len() as i64;
+        Ok(())
+    }
+}
+
+fn physical_type(data_type: DataType) -> i32 {
+    match data_type {
+        DataType::Integer => INT64,
+        DataType::Float => DOUBLE,
+        DataType::Boolean => BOOLEAN,
+        DataType::Text | DataType::Json | DataType::Array(_) | DataType::Point | DataType::Box | DataType::Numeric | DataType::Date | DataType::Timestamp => BYTE_ARRAY,
+    }
+}
+
+// Reads the rows of a Parquet file, a row group at a time.
+pub struct Reader<R> {
+    input: R,
+    columns: Vec<ReaderColumn>,
+    row_groups: Vec<Thrift>,
+    // the next row group to read
+    next_group: usize,
+    // the rows of the current one, each column's values in reverse
+    values: Vec<Vec<Value>>,
+}
+
+struct ReaderColumn {
+    name: String,
+    physical_type: i32,
+    optional: bool,
+}
+
+impl<R: Read + Seek> Reader<R> {
+    pub fn new(mut input: R) -> Result<Self, Error> {
+        let len = input.seek(SeekFrom::End(0))?;
+        if len < 12 {
+            return Err(Error::Corrupt("too short".to_string()));
+        }
+        let mut tail = [0; 8];
+        input.seek(SeekFrom::End(-8))?;
+        input.read_exact(&mut tail)?;
+        let mut head = [0; 4];
+        input.seek(SeekFrom::Start(0))?;
+        input.read_exact(&mut head)?;
+        if &head != MAGIC || &tail[4..] != MAGIC {
+            return Err(Error::Corrupt("no PAR1 magic".to_string()));
+        }
+        let metadata_len = u32::from_le_bytes(tail[..4].try_into().unwrap()) as u64;
+        if metadata_len + 12 > len {
+            return Err(Error::Corrupt("metadata out of the file".to_string()));
+        }
+        let mut bytes = vec![0; metadata_len as usize];
+        input.seek(SeekFrom::Start(len - 8 - metadata_len))?;
+        input.read_exact(&mut bytes)?;
+        let metadata = Thrift::decode_struct(&mut &bytes[..])?;
+
+        let schema = metadata.list(2)?;
+        let root = schema.first().ok_or_else(|| Error::Corrupt("no schema".to_string()))?;
+        if root.size(5)? != schema.len() - 1 {
+            return Err(Error::Unsupported("nested columns".to_string()));
+        }
+        let mut columns = vec![];
+        for element in &schema[1..] {
+            let name = String::from_utf8_lossy(element.binary(4)?).into_owned();
+            if element.get(5).is_some() {
+                return Err(Error::Unsupported(format!("column {} is nested", name)));
+            }
+            let repetition = element.get(3).map(Thrift::as_int).transpose()?.unwrap_or(REQUIRED as i64) as i32;
+            if repetition != REQUIRED && repetition != OPTIONAL {
+                return Err(Error::Unsupported(format!("column {} is repeated", name)));
+            }
+            let physical_type = element.int(1)? as i32;
+            if ![BOOLEAN, INT32, INT64, DOUBLE, BYTE_ARRAY].contains(&physical_type) {
+                return Err(Error::Unsupported(format!("column {} is of physical type {}", name, physical_type)));
+            }
+            columns.push(ReaderColumn { name, physical_type, optional: repetition == OPTIONAL });
+        }
+        let row_groups = match metadata.get(4) {
+            Some(Thrift::List(_, row_groups)) => row_groups.clone(),
+            _ => return Err(Error::Corrupt("no row groups".to_string())),
+        };
+        Ok(Self { input, columns, row_groups, next_group: 0, values: vec![] })
+    }
+
+    // Name and type of each column.
+    pub fn columns(&self) -> Vec<(String, DataType)> {
+        self.columns
+            .iter()
+            .map(|column| {
+                let data_type = match column.physical_type {
+                    BOOLEAN => DataType::Boolean,
+                    DOUBLE => DataType::Float,
+                    BYTE_ARRAY => DataType::Text,
+                    _ => DataType::Integer,
+                };
+                (column.name.clone(), data_type)
+            })
+            .collect()
+    }
+
+    pub fn next_row(&mut self) -> Result<Option<Tuple>, Error> {
+        while self.values.first().is_none_or(Vec::is_empty) {
+            if self.next_group == self.row_groups.len() {
+                return Ok(None);
+            }
+            self.read_row_group()?;
+        }
+        Ok(Some(self.values.iter_mut().map(|values| values.pop().unwrap()).collect()))
+    }
+
+    fn read_row_group(&mut self) -> Result<(), Error> {
+        let group = self.row_groups[self.next_group].clone();
+        self.next_group += 1;
+        let num_rows = group.size(3)?;
+        let chunks = group.list(1)?;
+        if chunks.len() != self.columns.len() {
+            return Err(Error::Corrupt("a row group hasn't a chunk for each column".to_string()));
+        }
+        let mut values = vec![];
+        for (chunk, column) in chunks.iter().zip(&self.columns) {
+            let metadata = chunk.get(3).ok_or_else(|| Error::Unsupported("column chunks in other files".to_string()))?;
+            let codec = metadata.int(4)? as i32;
+            let num_values = metadata.size(5)?;
+            let mut start = metadata.int(9)?;
+            if let Some(offset) = metadata.get(11) {
+                start = start.min(offset.as_int()?);
+            }
+            let size = metadata.int(7)?;
+            if start < 0 || !(0..=1 << 31).contains(&size) || num_values != num_rows {
+                return Err(Error::Corrupt(format!("column chunk of {}", column.name)));
+            }
+            let mut bytes = vec![0; size as usize];
+            self.input.seek(SeekFrom::Start(start as u64))?;
+            self.input.read_exact(&mut bytes)?;
+            let mut column_values = read_chunk(column, codec, num_values, &bytes)?;
+            column_values.reverse();
+            values.push(column_values);
+        }
+        self.values = values;
+        Ok(())
+    }
+}
+
+// The values of a column chunk, of `num_values` rows.
+fn read_chunk(column: &ReaderColumn, codec: i32, num_values: usize, mut bytes: &[u8]) -> Result<Vec<Value>, Error> {
+    let corrupt = || Error::Corrupt(format!("pages of column {}", column.name));
+    let mut dictionary: Option<Vec<Value>> = None;
+    let mut values = Vec::with_capacity(num_values.min(bytes.len() * 8));
+    while values.len() < num_values {
+        let header = Thrift::decode_struct(&mut bytes)?;
+        let (uncompressed_size, compressed_size) = (header.size(2)?, header.size(3)?);
+        if compressed_size > bytes.len() {
+            return Err(corrupt());
+        }
+        let (page, rest) = bytes.split_at(compressed_size);
+        bytes = rest;
+        match header.int(1)? as i32 {
+            DICTIONARY_PAGE => {
+                let page = decompress(codec, page, uncompressed_size).ok_or_else(corrupt)?;
+                let dictionary_header = header.get(7).ok_or_else(corrupt)?;
+                let count = dictionary_header.size(1)?;
+                let mut input = &page[..];
+                dictionary = Some(decode_plain(column, &mut input, count)?);
+            }
+            DATA_PAGE => {
+                let page = decompress(codec, page, uncompressed_size).ok_or_else(corrupt)?;
+                let data_header = header.get(5).ok_or_else(corrupt)?;
+                let count = data_header.size(1)?;
+                let mut input = &page[..];
+                let defined = if column.optional {
+                    let len = read_u32(&mut input)? as usize;
+                    if len > input.len() {
+                        return Err(corrupt());
+                    }
+                    let (levels, rest) = input.split_at(len);
+                    input = rest;
+                    Some(decode_hybrid(levels, 1, count)?)
+                } else {
+                    None
+                };
+                decode_values(column, data_header.int(2)? as i32, input, count, defined, dictionary.as_deref(), &mut values)?;
+            }
+            DATA_PAGE_V2 => {
+                let data_header = header.get(8).ok_or_else(corrupt)?;
+                let count = data_header.size(1)?;
+                let (definition_len, repetition_len) = (data_header.size(5)?, data_header.size(6)?);
+                if definition_len.checked_add(repetition_len).is_none_or(|len| len > page.len()) {
+                    return Err(corrupt());
+                }
+                let levels = &page[repetition_len..repetition_len + definition_len];
+                let page_values = &page[repetition_len + definition_len..];
+                let compressed = !matches!(data_header.get(7), Some(Thrift::Bool(false)));
+                let page_values = if compressed {
+                    let len = uncompressed_size.checked_sub(definition_len + repetition_len).ok_or_else(corrupt)?;
+                    decompress(codec, page_values, len).ok_or_else(corrupt)?
+                } else {
+                    page_values.to_vec()
+                };
+                let defined = column.optional.then(|| decode_hybrid(levels, 1, count)).transpose()?;
+                decode_values(column, data_header.int(4)? as i32, &page_values, count, defined, dictionary.as_deref(), &mut values)?;
+            }
+            page_type => return Err(Error::Unsupported(format!("page type {}", page_type))),
+        }
+    }
+    if values.len() != num_values {
+        return Err(corrupt());
+    }
+    Ok(values)
+}
+
+// Appends the `count` values of a data page to `values`, NULL where `defined` has a 0.
+fn decode_values(
+    column: &ReaderColumn,
+    encoding: i32,
+    mut input: &[u8],
+    count: usize,
+    defined: Option<Vec<u32>>,
+    dictionary: Option<&[Value]>,
+    values: &mut Vec<Value>,
+) -> Result<(), Error> {
+    let num_defined = defined.as_ref().map_or(count, |defined| defined.iter().filter(|&&level| level == 1).count());
+    let mut decoded = match encoding {
+        PLAIN => decode_plain(column, &mut input, num_defined)?,
+        PLAIN_DICTIONARY | RLE_DICTIONARY => {
+            let dictionary = dictionary.ok_or_else(|| Error::Corrupt(format!("column {} has no dictionary", column.name)))?;
+            let (&bit_width, indexes) = input.split_first().ok_or_else(|| Error::Corrupt("empty page".to_string()))?;
+            decode_hybrid(indexes, bit_width, num_defined)?
+                .into_iter()
+                .map(|i| dictionary.get(i as usize).cloned().ok_or_else(|| Error::Corrupt("dictionary index out of range".to_string())))
+                .collect::<Result<_, _>>()?
+        }
+        RLE if column.physical_type == BOOLEAN => {
+            let len = read_u32(&mut input)? as usize;
+            let bits = input.get(..len).ok_or_else(|| Error::Corrupt("boolean runs".to_string()))?;
+            decode_hybrid(bits, 1, num_defined)?.into_iter().map(|bit| Value::Bool(bit == 1)).collect()
+        }
+        encoding => return Err(Error::Unsupported(format!("encoding {} of column {}", encoding, column.name))),
+    }
+    .into_iter();
+    match defined {
+        Some(defined) => values.extend(defined.into_iter().map(|level| if level == 1 { decoded.next().unwrap() } else { Value::Null })),
+        None => values.extend(decoded),
+    }
+    Ok(())
+}
+
+fn decode_plain(column: &ReaderColumn, input: &mut &[u8], count: usize) -> Result<Vec<Value>, Error> {
+    let truncated = || Error::Corrupt(format!("values of column {} are cut short", column.name));
+    let mut values = Vec::with_capacity(count.min(input.len() * 8));
+    match column.physical_type {
+        BOOLEAN => {
+            let bytes = input.get(..count.div_ceil(8)).ok_or_else(truncated)?;
+            values.extend((0..count).map(|i| Value::Bool(bytes[i / 8] >> (i % 8) & 1 == 1)));
+            *input = &input[bytes.len()..];
+        }
+        INT32 | INT64 => {
+            let width = if column.physical_type == INT32 { 4 } else { 8 };
+            let len = count.checked_mul(width).filter(|&len| len <= input.len()).ok_or_else(truncated)?;
+            let (bytes, rest) = input.split_at(len);
+            values.extend(bytes.chunks(width).map(|b| {
+                Value::Int(if width == 4 { i32::from_le_bytes(b.try_into().unwrap()) as i64 } else { i64::from_le_bytes(b.try_into().unwrap()) })
+            }));
+            *input = rest;
+        }
+        DOUBLE => {
+            let len = count.checked_mul(8).filter(|&len| len <= input.len()).ok_or_else(truncated)?;
+            let (bytes, rest) = input.split_at(len);
+            values.extend(bytes.chunks(8).map(|b| Value::Float(Float(f64::from_le_bytes(b.try_into().unwrap())))));
+            *input = rest;
+        }
+        _ => {
+            for _ in 0..count {
+                let len = read_u32(input).map_err(|_| truncated())? as usize;
+                if input.len() < len {
+                    return Err(truncated());
+                }
+                let (bytes, rest) = input.split_at(len);
+                let s = String::from_utf8(bytes.to_vec())
+                    .map_err(|_| Error::Unsupported(format!("column {} has values which aren't UTF-8", column.name)))?;
+                values.push(Value::Text(s));
+                *input = rest;
+            }
+        }
+    }
+    Ok(values)
+}
+
+// Decodes `count` values of `bit_width` bits of the RLE/bit-packing hybrid, runs each of
+//   [header: varint] ([value: bit_width rounded up to bytes] if the header is even, of header/2 times the value,
+//                     [values: header/2 groups of 8 packed lowest bit first] if it's odd)
+fn decode_hybrid(mut input: &[u8], bit_width: u8, count: usize) -> Result<Vec<u32>, Error> {
+    let corrupt = || Error::Corrupt("RLE runs".to_string());
+    if bit_width > 32 {
+        return Err(corrupt());
+    }
+    let width = bit_width as usize;
+    let mut values = Vec::with_capacity(count.min(input.len() * 8));
+    while values.len() < count {
+        let header = read_varint(&mut input)?;
+        let run = (header >> 1) as usize;
+        if header & 1 == 0 {
+            let bytes = width.div_ceil(8);
+            let value = input.get(..bytes).ok_or_else(corrupt)?.iter().rev().fold(0, |value, &b| value << 8 | b as u32);
+            input = &input[bytes..];
+            values.extend(std::iter::repeat_n(value, run.min(count - values.len())));
+        } else {
+            let bytes = input.get(..run.checked_mul(width).ok_or_else(corrupt)?).ok_or_else(corrupt)?;
+            input = &input[bytes.len()..];
+            for i in 0..run.checked_mul(8).ok_or_else(corrupt)?.min(count - values.len()) {
+                let mut value = 0;
+                for bit in 0..width {
+                    let at = i * width + bit;
+                    value |= ((bytes[at / 8] >> (at % 8) & 1) as u32) << bit;
+                }
+                values.push(value);
+            }
+        }
+        if run == 0 {
+            return Err(corrupt());
+        }
+    }
+    Ok(values)
+}
+
+fn decompress(codec: i32, input: &[u8], len: usize) -> Option<Vec<u8>> {
+    match codec {
+        UNCOMPRESSED => (input.len() == len).then(|| input.to_vec()),
+        SNAPPY => snappy_decompress(input).filter(|output| output.len() == len),
+        LZ4_RAW => lz4::decompress(input, len),
+        _ => None,
+    }
+}
+
+// Snappy: the length of the output as a varint, then elements each a literal or a copy of what's
+// already output, tagged by the low two bits of their first byte.
+fn snappy_decompress(mut input: &[u8]) -> Option<Vec<u8>> {
+    let len = read_varint(&mut input).ok()? as usize;
+    let mut output = Vec::with_capacity(len.min(input.len() * 32));
+    while let Some((&tag, rest)) = input.split_first() {
+        input = rest;
+        let (copy_len, offset) = match tag & 3 {
+            0 => {
+                let mut literal_len = (tag >> 2) as usize;
+                if literal_len >= 60 {
+                    let bytes = literal_len - 59;
+                    literal_len = input.get(..bytes)?.iter().rev().fold(0, |n, &b| n << 8 | b as usize);
+                    input = &input[bytes..];
+                }
+                let literal = input.get(..literal_len + 1)?;
+                output.extend_from_slice(literal);
+                input = &input[literal.len()..];
+                continue;
+            }
+            1 => {
+                let &b = input.first()?;
+                input = &input[1..];
+                (((tag >> 2) & 7) as usize + 4, ((tag as usize >> 5) << 8) | b as usize)
+            }
+            2 => {
+                let bytes = input.get(..2)?;
+                input = &input[2..];
+                ((tag >> 2) as usize + 1, u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+            }
+            _ => {
+                let bytes = input.get(..4)?;
+                input = &input[4..];
+                ((tag >> 2) as usize + 1, u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+            }
+        };
+        if offset == 0 || offset > output.len() || output.len() + copy_len > len {
+            return None;
+        }
+        // which may overlap what it copies
+        let start = output.len() - offset;
+        for i in 0..copy_len {
+            output.push(output[start + i]);
+        }
+    }
+    (output.len() == len).then_some(output)
+}
+
+fn read_byte(input: &mut &[u8]) -> Result<u8, Error> {
+    let (&b, rest) = input.split_first().ok_or_else(|| Error::Corrupt("cut short".to_string()))?;
+    *input = rest;
+    Ok(b)
+}
+
+fn read_u32(input: &mut &[u8]) -> Result<u32, Error> {
+    let bytes = input.get(..4).ok_or_else(|| Error::Corrupt("cut short".to_string()))?;
+    *input = &input[4..];
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_varint(input: &mut &[u8]) -> Result<u64, Error> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&b, rest) = input.split_first().ok_or_else(|| Error::Corrupt("cut short".to_string()))?;
+        *input = rest;
+        value |= ((b & 0x7f) as u64) << shift;
+        if b & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(Error::Corrupt("varint too long".to_string()))
+}
+
+fn write_varint(output: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        output.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+// Types of the Thrift compact protocol.
+const I32: u8 = 5;
+const BINARY: u8 = 8;
+const STRUCT: u8 = 12;
+
+// Structs nest no deeper than this in what's read.
+const MAX_DEPTH: usize = 32;
+
+// A value of the Thrift compact protocol, structs of their fields by id. Integers are zigzag
+// varints, binaries their length as a varint then their bytes, and the fields of a struct each
+// headed by the difference from the id of the one before and its type, in a byte if they fit,
+// up to a 0.
+#[derive(Debug, Clone)]
+enum Thrift {
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Double(f64),
+    Binary(Vec<u8>),
+    // of the type of its elements
+    List(u8, Vec<Thrift>),
+    Map(Vec<(Thrift, Thrift)>),
+    Struct(Vec<(i16, Thrift)>),
+}
+
+impl Thrift {
+    fn get(&self, id: i16) -> Option<&Thrift> {
+        match self {
+            Thrift::Struct(fields) => fields.iter().find(|(field, _)| *field == id).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn field(&self, id: i16) -> Result<&Thrift, Error> {
+        self.get(id).ok_or_else(|| Error::Corrupt(format!("missing field {}", id)))
+    }
+
+    fn int(&self, id: i16) -> Result<i64, Error> {
+        self.field(id)?.as_int()
+    }
+
+    // A count or length, which mustn't be negative.
+    fn size(&self, id: i16) -> Result<usize, Error> {
+        usize::try_from(self.int(id)?).map_err(|_| Error::Corrupt(format!("field {} is negative", id)))
+    }
+
+    fn binary(&self, id: i16) -> Result<&[u8], Error> {
+        match self.field(id)? {
+            Thrift::Binary(bytes) => Ok(bytes),
+            _ => Err(Error::Corrupt(format!("field {} isn't binary", id))),
+        }
+    }
+
+    fn list(&self, id: i16) -> Result<&[Thrift], Error> {
+        match self.field(id)? {
+            Thrift::List(_, elements) => Ok(elements),
+            _ => Err(Error::Corrupt(format!("field {} isn't a list", id))),
+        }
+    }
+
+    fn as_int(&self) -> Result<i64, Error> {
+        match *self {
+            Thrift::I8(n) => Ok(n as i64),
+            Thrift::I16(n) => Ok(n as i64),
+            Thrift::I32(n) => Ok(n as i64),
+            Thrift::I64(n) => Ok(n),
+            _ => Err(Error::Corrupt("not an integer".to_string())),
+        }
+    }
+
+    fn compact_type(&self) -> u8 {
+        match self {
+            Thrift::Bool(true) => 1,
+            Thrift::Bool(false) => 2,
+            Thrift::I8(_) => 3,
+            Thrift::I16(_) => 4,
+            Thrift::I32(_) => I32,
+            Thrift::I64(_) => 6,
+            Thrift::Double(_) => 7,
+            Thrift::Binary(_) => BINARY,
+            Thrift::List(..) => 9,
+            Thrift::Map(_) => 11,
+            Thrift::Struct(_) => STRUCT,
+        }
+    }
+
+    fn encode_struct(&self, output: &mut Vec<u8>) {
+        let fields = match self {
+            Thrift::Struct(fields) => fields,
+            _ => unreachable!("not a struct"),
+        };
+        let mut last = 0;
+        for (id, value) in fields {
+            let delta = id - last;
+            if (1..=15).contains(&delta) {
+                output.push((delta as u8) << 4 | value.compact_type());
+            } else {
+                output.push(value.compact_type());
+                write_varint(output, zigzag(*id as i64));
+            }
+            last = *id;
+            // booleans are all in the type
+            if !matches!(value, Thrift::Bool(_)) {
+                value.encode(output);
+            }
+        }
+        output.push(0);
+    }
+
+    fn encode(&self, output: &mut Vec<u8>) {
+        match self {
+            Thrift::Bool(b) => output.push(if *b { 1 } else { 2 }),
+            Thrift::I8(n) => output.push(*n as u8),
+            Thrift::I16(n) => write_varint(output, zigzag(*n as i64)),
+            Thrift::I32(n) => write_varint(output, zigzag(*n as i64)),
+            Thrift::I64(n) => write_varint(output, zigzag(*n)),
+            Thrift::Double(x) => output.extend_from_slice(&x.to_le_bytes()),
+            Thrift::Binary(bytes) => {
+                write_varint(output, bytes.len() as u64);
+                output.extend_from_slice(bytes);
+            }
+            Thrift::List(element_type, elements) => {
+                if elements.len() < 15 {
+                    output.push((elements.len() as u8) << 4 | element_type);
+                } else {
+                    output.push(0xf0 | element_type);
+                    write_varint(output, elements.len() as u64);
+                }
+                for element in elements {
+                    element.encode(output);
+                }
+            }
+            Thrift::Map(entries) => {
+                write_varint(output, entries.len() as u64);
+                if let Some((key, value)) = entries.first() {
+                    output.push(key.compact_type() << 4 | value.compact_type());
+                }
+                for (key, value) in entries {
+                    key.encode(output);
+                    value.encode(output);
+                }
+            }
+            Thrift::Struct(_) => self.encode_struct(output),
+        }
+    }
+
+    fn decode_struct(input: &mut &[u8]) -> Result<Thrift, Error> {
+        Self::decode(input, STRUCT, 0)
+    }
+
+    fn decode(input: &mut &[u8], compact_type: u8, depth: usize) -> Result<Thrift, Error> {
+        let corrupt = || Error::Corrupt("thrift cut short".to_string());
+        if depth > MAX_DEPTH {
+            return Err(Error::Corrupt("thrift nested too deep".to_string()));
+        }
+        Ok(match compact_type {
+            // a boolean not of a field, an element of a list say
+            1 | 2 => Thrift::Bool(read_byte(input)? == 1),
+            3 => Thrift::I8(read_byte(input)? as i8),
+            4 => Thrift::I16(unzigzag(read_varint(input)?) as i16),
+            I32 => Thrift::I32(unzigzag(read_varint(input)?) as i32),
+            6 => Thrift::I64(unzigzag(read_varint(input)?)),
+            7 => {
+                let bytes = input.get(..8).ok_or_else(corrupt)?;
+                *input = &input[8..];
+                Thrift::Double(f64::from_le_bytes(bytes.try_into().unwrap()))
+            }
+            BINARY => {
+                let len = read_varint(input)? as usize;
+                let bytes = input.get(..len).ok_or_else(corrupt)?.to_vec();
+                *input = &input[len..];
+                Thrift::Binary(bytes)
+            }
+            9 | 10 => {
+                let header = read_byte(input)?;
+                let element_type = header & 0x0f;
+                let len = match header >> 4 {
+                    15 => read_varint(input)? as usize,
+                    len => len as usize,
+                };
+                // each element is a byte at least
+                if len > input.len() {
+                    return Err(corrupt());
+                }
+                let elements = (0..len).map(|_| Self::decode(input, element_type, depth + 1)).collect::<Result<_, _>>()?;
+                Thrift::List(element_type, elements)
+            }
+            11 => {
+                let len = read_varint(input)? as usize;
+                if len == 0 {
+                    return Ok(Thrift::Map(vec![]));
+                }
+                if len > input.len() {
+                    return Err(corrupt());
+                }
+                let types = read_byte(input)?;
+                let mut entries = vec![];
+                for _ in 0..len {
+                    let key = Self::decode(input, types >> 4, depth + 1)?;
+                    entries.push((key, Self::decode(input, types & 0x0f, depth + 1)?));
+                }
+                Thrift::Map(entries)
+            }
+            STRUCT => {
+                let mut fields = vec![];
+                let mut last = 0;
+                loop {
+                    let header = read_byte(input)?;
+                    if header == 0 {
+                        break;
+                    }
+                    let field_type = header & 0x0f;
+                    let id = match header >> 4 {
+                        0 => unzigzag(read_varint(input)?) as i16,
+                        delta => last + delta as i16,
+                    };
+                    last = id;
+                    let value = match field_type {
+                        1 => Thrift::Bool(true),
+                        2 => Thrift::Bool(false),
+                        _ => Self::decode(input, field_type, depth + 1)?,
+                    };
+                    fields.push((id, value));
+                }
+                Thrift::Struct(fields)
+            }
+            compact_type => return Err(Error::Corrupt(format!("thrift type {}", compact_type))),
+        })
+    }
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test() {
+        let columns = vec![
+            ("id".to_string(), DataType::Integer),
+            ("name".to_string(), DataType::Text),
+            ("ok".to_string(), DataType::Boolean),
+        ];
+        let rows: Vec<Tuple> = (0..ROW_GROUP_SIZE as i64 + 100)
+            .map(|i| {
+                vec![
+                    Value::Int(i - 5),
+                    if i % 3 == 0 { Value::Null } else { Value::Text(format!("name {}", i)) },
+                    if i % 7 == 0 { Value::Null } else { Value::Bool(i % 2 == 0) },
+                ]
+            })
+            .collect();
+        let mut writer = Writer::new(vec![], columns.clone()).unwrap();
+        for row in &rows {
+            writer.write_row(row.clone()).unwrap();
+        }
+        let file = writer.finish().unwrap();
+
+        // what's written reads back, over row groups
+        let mut reader = Reader::new(Cursor::new(&file)).unwrap();
+        assert_eq!(columns, reader.columns());
+        assert_eq!(2, reader.row_groups.len());
+        let mut read = vec![];
+        while let Some(row) = reader.next_row().unwrap() {
+            read.push(row);
+        }
+        assert_eq!(rows, read);
+        // or nothing at all
+        let file = Writer::new(vec![], columns.clone()).unwrap().finish().unwrap();
+        let mut reader = Reader::new(Cursor::new(&file)).unwrap();
+        assert_eq!(None, reader.next_row().unwrap());
+        let mut writer = Writer::new(vec![], columns.clone()).unwrap();
+        assert!(matches!(writer.write_row(vec![Value::Text("1".to_string()), Value::Null, Value::Null]), Err(Error::TypeMismatch { .. })));
+
+        // a file of another writer, with a dictionary page, a v2 data page and a required INT32
+        // column, the pages compressed with snappy: the literal of "\x01\x02\x03\x04" then a copy
+        // of 4 bytes 4 back
+        let mut chunk = vec![];
+        let dictionary = [2u32.to_le_bytes(), *b"ab\0\0"].concat()[..6].to_vec();
+        let page = |page_type, size, compressed: &[u8], header: (i16, Thrift)| {
+            let mut bytes = vec![];
+            Thrift::Struct(vec![
+                (1, Thrift::I32(page_type)),
+                (2, Thrift::I32(size as i32)),
+                (3, Thrift::I32(compressed.len() as i32)),
+                header,
+            ])
+            .encode_struct(&mut bytes);
+            bytes.extend_from_slice(compressed);
+            bytes
+        };
+        let snappy_literal = |bytes: &[u8]| [&[bytes.len() as u8, ((bytes.len() - 1) as u8) << 2][..], bytes].concat();
+        chunk.extend(page(
+            DICTIONARY_PAGE,
+            dictionary.len(),
+            &snappy_literal(&dictionary),
+            (7, Thrift::Struct(vec![(1, Thrift::I32(1)), (2, Thrift::I32(PLAIN_DICTIONARY))])),
+        ));
+        // 3 rows, the second NULL, of the only dictionary value: definition levels bit-packed
+        // 1 0 1, then the indexes of bit width 0
+        let levels = [3, 0b101];
+        let values = [0, 2 << 1, 0];
+        chunk.extend(page(
+            DATA_PAGE_V2,
+            levels.len() + values.len(),
+            &[&levels[..], &snappy_literal(&values)].concat(),
+            (
+                8,
+                Thrift::Struct(vec![
+                    (1, Thrift::I32(3)),
+                    (2, Thrift::I32(1)),
+                    (3, Thrift::I32(3)),
+                    (4, Thrift::I32(RLE_DICTIONARY)),
+                    (5, Thrift::I32(levels.len() as i32)),
+                    (6, Thrift::I32(0)),
+                ]),
+            ),
+        ));
+        let ints = [7i32.to_le_bytes(), 8i32.to_le_bytes(), 7i32.to_le_bytes()].concat();
+        let mut snappy = vec![12, 7 << 2];
+        snappy.extend_from_slice(&ints[..8]);
+        // a copy of 4 bytes 8 back, of the 1-byte offset kind
+        snappy.extend_from_slice(&[1, 8]);
+        let second = chunk.len();
+        chunk.extend(page(
+            DATA_PAGE,
+            ints.len(),
+            &snappy,
+            (5, Thrift::Struct(vec![(1, Thrift::I32(3)), (2, Thrift::I32(PLAIN)), (3, Thrift::I32(RLE)), (4, Thrift::I32(RLE))])),
+        ));
+        let column_chunk = |start: usize, size: usize, physical_type| {
+            Thrift::Struct(vec![
+                (2, Thrift::I64(0)),
+                (
+                    3,
+                    Thrift::Struct(vec![
+                        (1, Thrift::I32(physical_type)),
+                        (4, Thrift::I32(SNAPPY)),
+                        (5, Thrift::I64(3)),
+                        (7, Thrift::I64(size as i64)),
+                        (9, Thrift::I64(start as i64)),
+                    ]),
+                ),
+            ])
+        };
+        let metadata = Thrift::Struct(vec![
+            (1, Thrift::I32(1)),
+            (
+                2,
+                Thrift::List(
+                    STRUCT,
+                    vec![
+                        Thrift::Struct(vec![(4, Thrift::Binary(b"schema".to_vec())), (5, Thrift::I32(2))]),
+                        Thrift::Struct(vec![(1, Thrift::I32(BYTE_ARRAY)), (3, Thrift::I32(OPTIONAL)), (4, Thrift::Binary(b"s".to_vec()))]),
+                        Thrift::Struct(vec![(1, Thrift::I32(INT32)), (3, Thrift::I32(REQUIRED)), (4, Thrift::Binary(b"n".to_vec()))]),
+                    ],
+                ),
+            ),
+            (3, Thrift::I64(3)),
+            (
+                4,
+                Thrift::List(
+                    STRUCT,
+                    vec![Thrift::Struct(vec![
+                        (1, Thrift::List(STRUCT, vec![column_chunk(4, second, BYTE_ARRAY), column_chunk(4 + second, chunk.len() - second, INT32)])),
+                        (2, Thrift::I64(0)),
+                        (3, Thrift::I64(3)),
+                    ])],
+                ),
+            ),
+        ]);
+        let mut file = MAGIC.to_vec();
+        file.extend_from_slice(&chunk);
+        let mut bytes = vec![];
+        metadata.encode_struct(&mut bytes);
+        file.extend_from_slice(&bytes);
+        file.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        file.extend_from_slice(MAGIC);
+        let mut reader = Reader::new(Cursor::new(&file)).unwrap();
+        assert_eq!(vec![("s".to_string(), DataType::Text), ("n".to_string(), DataType::Integer)], reader.columns());
+        let text = |s: &str| Value::Text(s.to_string());
+        assert_eq!(Some(vec![text("ab"), Value::Int(7)]), reader.next_row().unwrap());
+        assert_eq!(Some(vec![Value::Null, Value::Int(8)]), reader.next_row().unwrap());
+        assert_eq!(Some(vec![text("ab"), Value::Int(7)]), reader.next_row().unwrap());
+        assert_eq!(None, reader.next_row().unwrap());
+
+        // and what isn't Parquet doesn't
+        assert!(matches!(Reader::new(Cursor::new(b"PAR1\0\0\0\0PAR2")), Err(Error::Corrupt(_))));
+        let mut cut = file.clone();
+        let len = cut.len();
+        cut[len - 8..len - 4].copy_from_slice(&(len as u32).to_le_bytes());
+        assert!(matches!(Reader::new(Cursor::new(&cut)), Err(Error::Corrupt(_))));
+        assert_eq!(None, snappy_decompress(&[4, 0, b'a', 1, 2]));
+
+        // nor one whose first data page has a negative count of values, as fuzzing found: the
+        // varint of the count at 14 made -1, and -253 with two more bytes garbled
+        let columns = vec![("a".to_string(), DataType::Integer), ("b".to_string(), DataType::Text)];
+        let mut writer = Writer::new(vec![], columns).unwrap();
+        for i in 0..200 {
+            writer.write_row(vec![Value::Int(i), if i % 3 == 0 { Value::Null } else { Value::Text(format!("x{}", i)) }]).unwrap();
+        }
+        let file = writer.finish().unwrap();
+        assert_eq!([0x2c, 0x15, 0x90, 0x03], file[12..16]);
+        for garbled in [vec![(14, 0x01)], vec![(14, 0xf9), (649, 0x18), (996, 0xff)]] {
+            let mut file = file.clone();
+            for (at, byte) in garbled {
+                file[at] = byte;
+            }
+            let mut reader = Reader::new(Cursor::new(&file)).unwrap();
+            assert!(matches!(reader.next_row(), Err(Error::Corrupt(_))));
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+
+use crate::query::expr::{BinaryOp, Expr};
+use crate::tuple::{self, DataType, Value};
+
+// A partitioned table holds no rows itself: they're divided among its partitions, tables of the
+// same columns each holding the rows whose partition key is within its bound, a range of the key
+// or the remainder of its hash by a modulus, as in PostgreSQL. INSERTs and COPY into the
+// partitioned table write each row to the partition it belongs to, and queries read only those
+// partitions the predicates on the key leave (see `KeyFilter`). The key is a column of the
+// primary key, so that a key is unique across the partitions as it is within each.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    Range,
+    Hash,
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Strategy::Range => "RANGE",
+            Strategy::Hash => "HASH",
+        })
+    }
+}
+
+// How the rows of a partitioned table are divided.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partitioning {
+    pub strategy: Strategy,
+    // the partition key
+    pub column: usize,
+    // names of the partitions, in order
+    pub partitions: Vec<String>,
+}
+
+// What a partition is of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    pub parent: String,
+    pub bound: PartitionBound,
+}
+
+// The partition keys of the rows of a partition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionBound {
+    // lower <= key < upper, None being unbounded (MINVALUE and MAXVALUE)
+    Range { lower: Option<Value>, upper: Option<Value> },
+    // hash(key) % modulus == remainder
+    Hash { modulus: u64, remainder: u64 },
+}
+
+impl PartitionBound {
+    pub fn strategy(&self) -> Strategy {
+        match self {
+            PartitionBound::Range { .. } => Strategy::Range,
+            PartitionBound::Hash { .. } => Strategy::Hash,
+        }
+    }
+
+    pub fn contains(&self, key: &Value) -> bool {
+        match self {
+            PartitionBound::Range { lower, upper } => lower.as_ref().is_none_or(|l| key >= l) && upper.as_ref().is_none_or(|u| key < u),
+            PartitionBound::Hash { modulus, remainder } => hash(key) % modulus == *remainder,
+        }
+    }
+
+    // Whether a key could be within both bounds, which are of partitions of the same table.
+    pub fn overlaps(&self, other: &PartitionBound) -> bool {
+        match (self, other) {
+            (PartitionBound::Range { lower: l1, upper: u1 }, PartitionBound::Range { lower: l2, upper: u2 }) => below(l1, u2) && below(l2, u1),
+            // the remainders by both moduli are those of some key if and only if they're the same
+            // by their greatest common divisor
+            (PartitionBound::Hash { modulus: m1, remainder: r1 }, PartitionBound::Hash { modulus: m2, remainder: r2 }) => {
+                let divisor = gcd(*m1, *m2);
+                r1 % divisor == r2 % divisor
+            }
+            _ => true,
+        }
+    }
+
+    // Why the bound can't be one of a partition key of `data_type`, if it can't.
+    pub fn check(&self, data_type: DataType) -> Result<(), String> {
+        match self {
+            PartitionBound::Range { lower, upper } => {
+                if let Some(value) = lower.iter().chain(upper).find(|value| DataType::of(value) != Some(data_type)) {
+                    return Err(format!("{:?} is not a value of the partition key", value));
+                }
+                if !below(lower, upper) {
+                    return Err("the lower bound must be below the upper".to_string());
+                }
+            }
+            PartitionBound::Hash { modulus, remainder } => {
+                if remainder >= modulus {
+                    return Err("the remainder must be below the modulus".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Whether there's a key at or above `lower` and below `upper`.
+fn below(lower: &Option<Value>, upper: &Option<Value>) -> bool {
+    match (lower, upper) {
+        (Some(lower), Some(upper)) => lower < upper,
+        _ => true,
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// FNV-1a of the key encoded as index keys are, which is the same on every platform and release.
+pub fn hash(key: &Value) -> u64 {
+    let mut bytes = vec![];
+    tuple::encode_key(std::slice::from_ref(key), &mut bytes);
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+// The partition keys the conjuncts of a query on a partitioned table leave, as far as they can
+// be told when it's planned: comparisons of the key with literals, and IN lists of them. Those of
+// other columns tell the same of the stripes of columnar tables (see `columnar`).
+pub struct KeyFilter<'e> {
+    // those the key is one of, if it's compared for equality
+    keys: Option<Vec<&'e Value>>,
+    // ranges the key is within
+    ranges: Vec<(Bound<&'e Value>, Bound<&'e Value>)>,
+}
+
+impl<'e> KeyFilter<'e> {
+    // Of `conjuncts` on the rows of the table, whose partition key is `column` of `data_type`.
+    pub fn new(column: usize, data_type: DataType, conjuncts: &'e [Expr]) -> Self {
+        let mut filter = Self { keys: None, ranges: vec![] };
+        // a value of another type can't be compared with the key
+        let literal = |expr: &'e Expr| match expr {
+            Expr::Literal(value) if DataType::of(value) == Some(data_type) => Some(value),
+            _ => None,
+        };
+        for conjunct in conjuncts {
+            match conjunct {
+                Expr::Binary { op, left, right } => {
+                    let (op, value) = match (&**left, &**right) {
+                        (Expr::Column(c), value) if *c == column => (*op, value),
+                        (value, Expr::Column(c)) if *c == column => (flip(*op), value),
+                        _ => continue,
+                    };
+                    let Some(value) = literal(value) else {
+                        continue;
+                    };
+                    match op {
+                        BinaryOp::Eq => filter.restrict(vec![value]),
+                        BinaryOp::Gt => filter.ranges.push((Bound::Excluded(value), Bound::Unbounded)),
+                        BinaryOp::GtEq => filter.ranges.push((Bound::Included(value), Bound::Unbounded)),
+                        BinaryOp::Lt => filter.ranges.push((Bound::Unbounded, Bound::Excluded(value))),
+                        BinaryOp::LtEq => filter.ranges.push((Bound::Unbounded, Bound::Included(value))),
+                        _ => {}
+                    }
+                }
+                Expr::InList { expr, list, negated: false } if matches!(**expr, Expr::Column(c) if c == column) => {
+                    if let Some(values) = list.iter().map(literal).collect::<Option<Vec<_>>>() {
+                        filter.restrict(values);
+                    }
+                }
+                _ => {}
+            }
+        }
+        filter
+    }
+
+    fn restrict(&mut self, keys: Vec<&'e Value>) {
+        self.keys = Some(match self.keys.take() {
+            Some(current) => current.into_iter().filter(|key| keys.contains(key)).collect(),
+            None => keys,
+        });
+    }
+
+    // Whether a row the conjuncts hold for may be in the partition of `bound`.
+    pub fn may_contain(&self, bound: &PartitionBound) -> bool {
+        if let Some(keys) = &self.keys {
+            return keys.iter().any(|key| self.ranges.iter().all(|range| range.contains(*key)) && bound.contains(key));
+        }
+        let PartitionBound::Range { lower, upper } = bound else {
+            return true;
+        };
+        // each range by itself, which may let some through that all of them together wouldn't
+        self.ranges.iter().all(|(start, end)| {
+            let above_start = match (start, upper) {
+                (Bound::Included(value) | Bound::Excluded(value), Some(upper)) => *value < upper,
+                _ => true,
+            };
+            let below_end = match (end, lower) {
+                (Bound::Included(value), Some(lower)) => *value >= lower,
+                (Bound::Excluded(value), Some(lower)) => *value > lower,
+                _ => true,
+            };
+            above_start && below_end
+        })
+    }
+
+    // Whether a row the conjuncts hold for may have a key from `min` to `max`, both included.
+    pub fn may_be_within(&self, min: &Value, max: &Value) -> bool {
+        let within = (Bound::Included(min), Bound::Included(max));
+        if let Some(keys) = &self.keys {
+            return keys.iter().any(|key| within.contains(*key) && self.ranges.iter().all(|range| range.contains(*key)));
+        }
+        self.ranges.iter().all(|(start, end)| {
+            let above_start = match start {
+                Bound::Included(value) => *value <= max,
+                Bound::Excluded(value) => *value < max,
+                Bound::Unbounded => true,
+            };
+            let below_end = match end {
+                Bound::Included(value) => *value >= min,
+                Bound::Excluded(value) => *value > min,
+                Bound::Unbounded => true,
+            };
+            above_start && below_end
+        })
+    }
+}
+
+// `op` with its operands swapped.
+fn flip(op: BinaryOp) -> BinaryOp {
+    match op {
+        BinaryOp::Lt => BinaryOp::Gt,
+        BinaryOp::LtEq => BinaryOp::GtEq,
+        BinaryOp::Gt => BinaryOp::Lt,
+        BinaryOp::GtEq => BinaryOp::LtEq,
+        op => op,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let range = |lower: Option<i64>, upper: Option<i64>| PartitionBound::Range { lower: lower.map(Value::Int), upper: upper.map(Value::Int) };
+        let (low, mid, high) = (range(None, Some(10)), range(Some(10), Some(20)), range(Some(20), None));
+        assert!(low.contains(&Value::Int(i64::MIN)) && !low.contains(&Value::Int(10)) && mid.contains(&Value::Int(10)) && high.contains(&Value::Int(i64::MAX)));
+        assert!(!low.overlaps(&mid) && !mid.overlaps(&high) && !low.overlaps(&high));
+        assert!(range(Some(15), Some(25)).overlaps(&mid) && range(None, None).overlaps(&high));
+        assert_eq!(Ok(()), mid.check(DataType::Integer));
+        assert!(range(Some(20), Some(10)).check(DataType::Integer).is_err() && mid.check(DataType::Text).is_err());
+
+        let hash_bound = |modulus: u64, remainder: u64| PartitionBound::Hash { modulus, remainder };
+        assert!(!hash_bound(4, 1).overlaps(&hash_bound(4, 3)) && hash_bound(4, 1).overlaps(&hash_bound(2, 1)));
+        assert!(!hash_bound(4, 1).overlaps(&hash_bound(2, 0)) && hash_bound(3, 1).overlaps(&hash_bound(2, 0)));
+        assert!(hash_bound(2, 2).check(DataType::Integer).is_err());
+        // each key is in one of the partitions of a modulus
+        let bounds: Vec<_> = (0..4).map(|r| hash_bound(4, r)).collect();
+        for key in (0..100).map(Value::Int).chain([Value::Text("a".to_string())]) {
+            assert_eq!(1, bounds.iter().filter(|bound| bound.contains(&key)).count());
+        }
+        assert_eq!(hash(&Value::Int(1)), hash(&Value::Int(1)));
+        assert_ne!(hash(&Value::Int(1)), hash(&Value::Int(2)));
+
+        let literal = |n: i64| Box::new(Expr::Literal(Value::Int(n)));
+        let compare = |op, n| Expr::Binary { op, left: Box::new(Expr::Column(1)), right: literal(n) };
+        let allowed = |conjuncts: &[Expr], bounds: &[PartitionBound]| {
+            let filter = KeyFilter::new(1, DataType::Integer, conjuncts);
+            bounds.iter().map(|bound| filter.may_contain(bound)).collect::<Vec<_>>()
+        };
+        let ranges = [low.clone(), mid.clone(), high.clone()];
+        assert_eq!(vec![true, true, true], allowed(&[], &ranges));
+        assert_eq!(vec![false, true, false], allowed(&[compare(BinaryOp::Eq, 15)], &ranges));
+        assert_eq!(vec![false, false, true], allowed(&[compare(BinaryOp::GtEq, 20)], &ranges));
+        assert_eq!(vec![true, false, false], allowed(&[compare(BinaryOp::Lt, 10)], &ranges));
+        assert_eq!(vec![false, true, false], allowed(&[Expr::Binary { op: BinaryOp::Lt, left: literal(10), right: Box::new(Expr::Column(1)) }, compare(BinaryOp::LtEq, 15)], &ranges));
+        // only the key is compared
+        assert_eq!(vec![true, true, true], allowed(&[Expr::Binary { op: BinaryOp::Eq, left: Box::new(Expr::Column(0)), right: literal(15) }], &ranges));
+        let in_list = || Expr::InList { expr: Box::new(Expr::Column(1)), list: vec![*literal(1), *literal(25)], negated: false };
+        assert_eq!(vec![true, false, true], allowed(&[in_list()], &ranges));
+        assert_eq!(vec![false, false, true], allowed(&[in_list(), compare(BinaryOp::Gt, 1)], &ranges));
+        assert_eq!(vec![false, false, false], allowed(&[in_list(), compare(BinaryOp::Eq, 2)], &ranges));
+        let allowed_hash = allowed(&[compare(BinaryOp::Eq, 7)], &bounds);
+        assert_eq!(1, allowed_hash.iter().filter(|&&allowed| allowed).count());
+        assert!(bounds[allowed_hash.iter().position(|&allowed| allowed).unwrap()].contains(&Value::Int(7)));
+        assert_eq!(vec![true; 4], allowed(&[compare(BinaryOp::Gt, 7)], &bounds));
+        // and whether a zone of inclusive bounds may have any
+        let within = |conjuncts: &[Expr], min: i64, max: i64| KeyFilter::new(1, DataType::Integer, conjuncts).may_be_within(&Value::Int(min), &Value::Int(max));
+        assert!(within(&[compare(BinaryOp::Eq, 20)], 10, 20) && !within(&[compare(BinaryOp::Eq, 21)], 10, 20));
+        assert!(within(&[compare(BinaryOp::GtEq, 20)], 10, 20) && !within(&[compare(BinaryOp::Gt, 20)], 10, 20));
+        assert!(within(&[compare(BinaryOp::LtEq, 10)], 10, 20) && !within(&[compare(BinaryOp::Lt, 10)], 10, 20));
+        assert!(!within(&[in_list()], 2, 24) && within(&[in_list(), compare(BinaryOp::Gt, 1)], 2, 25));
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::catalog::Catalog;
+use crate::optimizer::PlannerSettings;
+use crate::sql::{self, PreparedStatement};
+
+// Plans of the statements sessions run as text, shared by the sessions for those run again to be
+// neither parsed nor planned again. A statement is looked up by its text normalized (see
+// `sql::normalize`) along with its constants as written, which it's planned with, and by the
+// planner settings of the session. A plan is used only with the version of the catalog it was
+// planned with, so that DDL, ANALYZE and functions registered since invalidate it (see
+// `Catalog::version`). Only SELECT, INSERT and COPY TO are kept, the others having next to nothing
+// to plan, and none are for the sessions with temporary tables, which are theirs alone.
+
+// Plans kept, beyond which the one used longest ago is dropped for a new one.
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlanCacheStats {
+    // statements run with a plan found
+    pub hits: u64,
+    // planned, for want of one, and kept
+    pub misses: u64,
+    // found to be of a catalog changed since, and dropped
+    pub invalidations: u64,
+    pub plans: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    normalized: String,
+    constants: Vec<String>,
+    settings: PlannerSettings,
+}
+
+struct Entry {
+    version: u64,
+    statement: Rc<PreparedStatement>,
+    last_used: u64,
+}
+
+pub struct PlanCache {
+    plans: HashMap<Key, Entry>,
+    capacity: usize,
+    // counts the lookups, for the plans to be told the last time they were used by
+    clock: u64,
+    stats: PlanCacheStats,
+}
+
+impl Default for PlanCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl PlanCache {
+    // Keeps up to `capacity` plans, none if 0.
+    pub fn new(capacity: usize) -> Self {
+        Self { plans: HashMap::new(), capacity, clock: 0, stats: PlanCacheStats::default() }
+    }
+
+    // The plan of the statement `sql` with `settings`, if one is kept for the catalog as it is.
+    pub fn get(&mut self, sql: &str, catalog: &Catalog, settings: PlannerSettings) -> Option<Rc<PreparedStatement>> {
+        let key = key(sql, settings)?;
+        let entry = self.plans.get_mut(&key)?;
+        if entry.version != catalog.version() {
+            self.plans.remove(&key);
+            self.stats.invalidations += 1;
+            return None;
+        }
+        self.clock += 1;
+        entry.last_used = self.clock;
+        self.stats.hits += 1;
+        Some(entry.statement.clone())
+    }
+
+    // Keeps `statement`, prepared from `sql` with `settings` and the catalog of `version`, if it's
+    // one to keep.
+    pub fn insert(&mut self, sql: &str, version: u64, settings: PlannerSettings, statement: Rc<PreparedStatement>) {
+        let Some(key) = key(sql, settings).filter(|_| self.capacity > 0 && statement.is_cacheable()) else {
+            return;
+        };
+        if !self.plans.contains_key(&key) && self.plans.len() >= self.capacity {
+            let oldest = self.plans.iter().min_by_key(|(_, entry)| entry.last_used).map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.plans.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.stats.misses += 1;
+        self.plans.insert(key, Entry { version, statement, last_used: self.clock });
+    }
+
+    pub fn stats(&self) -> PlanCacheStats {
+        PlanCacheStats { plans: self.plans.len(), ..self.stats }
+    }
+
+    pub fn clear(&mut self) {
+        self.plans.clear();
+        self.stats = PlanCacheStats::default();
+    }
+}
+
+// None unless `sql` is a single statement, a `;` after it aside.
+fn key(sql: &str, settings: PlannerSettings) -> Option<Key> {
+    let normalized = sql::normalize(sql).ok()?;
+    let normalized = normalized.trim_end_matches(';');
+    if normalized.is_empty() || normalized.contains(';') {
+        return None;
+    }
+    let constants = sql::constants(sql).ok()?.into_iter().map(str::to_string).collect();
+    Some(Key { normalized: normalized.to_string(), constants, settings })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::database::Database;
+    use crate::sql::QueryResult;
+    use crate::tuple::Value;
+    use tempfile::tempdir;
+
+    fn rows(result: Vec<QueryResult>) -> Vec<Vec<Value>> {
+        match result.into_iter().next_back() {
+            Some(QueryResult::Rows { rows, .. }) => rows,
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test() {
+        let dir = tempdir().unwrap();
+        let db = Database::builder().config(Config { plan_cache_size: 3, ..Config::default() }).open(dir.path()).unwrap();
+        let mut session = db.session();
+        session.execute("CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        session.execute("INSERT INTO t VALUES (1, 'a'); INSERT INTO t VALUES (2, 'b')").unwrap();
+        let stats = db.plan_cache_stats();
+        assert_eq!(PlanCacheStats { hits: 0, misses: 2, invalidations: 0, plans: 2 }, stats);
+
+        // the same but for case, whitespace and comments
+        let select = "SELECT name FROM t WHERE id = 1";
+        assert_eq!(vec![vec![Value::Text("a".to_string())]], rows(session.execute(select).unwrap()));
+        assert_eq!(vec![vec![Value::Text("a".to_string())]], rows(session.execute("select name\n  from t where id = 1; -- again").unwrap()));
+        // full, so the one used longest ago makes way
+        assert_eq!(vec![vec![Value::Text("b".to_string())]], rows(session.execute("SELECT name FROM t WHERE id = 2").unwrap()));
+        assert_eq!(PlanCacheStats { hits: 1, misses: 4, invalidations: 0, plans: 3 }, db.plan_cache_stats());
+        // and the sessions share them
+        let mut other = db.session();
+        assert_eq!(vec![vec![Value::Text("a".to_string())]], rows(other.execute(select).unwrap()));
+        assert_eq!(PlanCacheStats { hits: 2, misses: 4, invalidations: 0, plans: 3 }, db.plan_cache_stats());
+        // of other settings, the plan would be another
+        other.execute("SET enable_seqscan = off").unwrap();
+        other.execute(select).unwrap();
+        assert_eq!((2, 5), (db.plan_cache_stats().hits, db.plan_cache_stats().misses));
+
+        // DDL and statistics make the plans of the catalog before stale
+        session.execute("CREATE INDEX t_name ON t (name)").unwrap();
+        assert_eq!(vec![vec![Value::Text("a".to_string())]], rows(session.execute(select).unwrap()));
+        session.execute("ANALYZE t").unwrap();
+        session.execute(select).unwrap();
+        session.execute(select).unwrap();
+        assert_eq!(PlanCacheStats { hits: 3, misses: 7, invalidations: 2, plans: 3 }, db.plan_cache_stats());
+        // as does a change of a transaction, to its own catalog, while the others keep theirs
+        session.execute("BEGIN; CREATE TABLE u (id INTEGER PRIMARY KEY)").unwrap();
+        session.execute(select).unwrap();
+        let stats = db.plan_cache_stats();
+        let mut third = db.session();
+        third.execute(select).unwrap();
+        assert_eq!(stats.invalidations + 1, db.plan_cache_stats().invalidations);
+        session.execute("ROLLBACK").unwrap();
+
+        // a session's temporary tables hide the tables others create, so it neither finds plans
+        // nor keeps them
+        third.execute("CREATE TEMPORARY TABLE tmp (id INTEGER PRIMARY KEY)").unwrap();
+        let stats = db.plan_cache_stats();
+        third.execute(select).unwrap();
+        third.execute(select).unwrap();
+        assert_eq!(stats, db.plan_cache_stats());
+
+        // nor are statements with little to plan kept, while those run several at once are each
+        let stats = db.plan_cache_stats();
+        session.execute("SHOW enable_seqscan").unwrap();
+        assert_eq!(stats, db.plan_cache_stats());
+        session.execute("SELECT 1; SELECT 2").unwrap();
+        session.execute("SELECT 2").unwrap();
+        assert_eq!((stats.hits + 1, stats.misses + 2), (db.plan_cache_stats().hits, db.plan_cache_stats().misses));
+        db.clear_plan_cache();
+        assert_eq!(PlanCacheStats::default(), db.plan_cache_stats());
+
+        let db = Database::builder().config(Config { plan_cache_size: 0, ..Config::default() }).open(tempdir().unwrap().path()).unwrap();
+        db.session().execute("SELECT 1").unwrap();
+        assert_eq!(0, db.plan_cache_stats().plans);
+    }
+}
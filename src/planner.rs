@@ -0,0 +1,2050 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::catalog::{Catalog, Column, ViewInfo};
+use crate::geometry::Point;
+use crate::optimizer::{optimize, restore_layout, Hints, PlannerSettings, Query, ScanHint, Source};
+use crate::partition::KeyFilter;
+use crate::query::expr::{cast, conjunction, type_name, BinaryOp, Expr, Function, OuterRow, Params, SubqueryKind, UnaryOp};
+use crate::query::{
+    Aggregate, AggregateFunc, Append, CteScan, FunctionScan, CteStorage, Filter, Frame, HashAggregate, HashDistinct, HashSemiJoin, HashSetOp,
+    MaterializeCtes, MaterializedCte, PlanNode, Project, RecursiveUnion, SetOperator, Sort, SortDistinct, Unnest, Values,
+    VirtualScan, Window, WindowFunc, WindowFunction,
+};
+use crate::sql::{ast, parse, Error};
+use crate::table::Access;
+use crate::tuple::{DataType, ElementType, Value};
+
+// Build side size above which hash joins planned here spill to disk.
+pub const DEFAULT_MAX_BUILD_ROWS: usize = 100_000;
+
+pub struct SelectPlan {
+    pub plan: Box<dyn PlanNode>,
+    pub columns: Vec<String>,
+    // type of each column if it's known before execution
+    pub types: Vec<Option<DataType>>,
+}
+
+#[derive(Debug, Clone)]
+struct ScopeColumn {
+    table: Option<String>,
+    name: String,
+    data_type: Option<DataType>,
+}
+
+// Columns visible to expressions evaluated on the tuples of one plan node.
+struct Scope {
+    columns: Vec<ScopeColumn>,
+    // where subqueries of this scope find the row they're evaluated for
+    outer_row: OuterRow,
+}
+
+impl Scope {
+    fn new(columns: Vec<ScopeColumn>) -> Self {
+        Self {
+            columns,
+            outer_row: Rc::new(RefCell::new(vec![])),
+        }
+    }
+
+    fn resolve(&self, table: Option<&str>, name: &str) -> Result<Option<usize>, Error> {
+        let mut found = None;
+        for (i, column) in self.columns.iter().enumerate() {
+            if column.name == name && (table.is_none() || column.table.as_deref() == table) {
+                if found.is_some() {
+                    return Err(Error::AmbiguousColumn(name.to_string()));
+                }
+                found = Some(i);
+            }
+        }
+        Ok(found)
+    }
+}
+
+// Conditions which a decorrelated subquery predicate turns into.
+struct SemiJoinSpec {
+    right: Box<dyn PlanNode>,
+    left_keys: Vec<usize>,
+    right_keys: Vec<usize>,
+    anti: bool,
+    null_aware: bool,
+}
+
+// An UNNEST of the FROM clause reading the columns of the FROM items before it, which so is
+// joined to them in order: `relations` of them come first, and `column` is its own.
+struct Lateral {
+    relations: usize,
+    column: usize,
+    expr: Expr,
+}
+
+// A CTE which can be referred to by the query being planned.
+struct CteBinding {
+    name: String,
+    columns: Vec<String>,
+    types: Vec<Option<DataType>>,
+    source: CteSource,
+}
+
+enum CteSource {
+    // planned wherever it's referred to, seeing only the first `outer_depth` outer scopes as
+    // where it's defined
+    Inline { query: Box<ast::Query>, outer_depth: usize },
+    // rows stored by MaterializeCtes, or the working table of a recursive CTE being planned
+    Stored { storage: Rc<CteStorage>, rows: f64 },
+}
+
+// Binds names in the AST against the catalog and builds plan trees.
+pub struct Planner<'a> {
+    catalog: &'a Catalog,
+    // scopes of the enclosing queries, innermost last, and whether a column of each was referenced
+    outer_scopes: Vec<(Scope, bool)>,
+    // CTEs in scope, innermost last
+    ctes: Vec<CteBinding>,
+    params: Params,
+    // type of each parameter as inferred from where it's used, None if it could be anything
+    param_types: Vec<Option<DataType>>,
+    settings: PlannerSettings,
+    // tables and views read by the plans built so far, not counting those read through views
+    tables: Vec<String>,
+    // how many views deep the query being planned is
+    view_depth: usize,
+}
+
+impl<'a> Planner<'a> {
+    pub fn new(catalog: &'a Catalog) -> Self {
+        Self::with_settings(catalog, PlannerSettings::default())
+    }
+
+    pub fn with_settings(catalog: &'a Catalog, settings: PlannerSettings) -> Self {
+        Self {
+            catalog,
+            outer_scopes: vec![],
+            ctes: vec![],
+            params: Rc::new(RefCell::new(vec![])),
+            param_types: vec![],
+            settings,
+            tables: vec![],
+            view_depth: 0,
+        }
+    }
+
+    // The tables and views the plans built so far read, which they need the privilege to, the
+    // tables read through views aside.
+    pub fn tables(&self) -> &[String] {
+        &self.tables
+    }
+
+    fn read_table(&mut self, name: &str) {
+        if self.view_depth == 0 && !self.tables.iter().any(|t| t == name) {
+            self.tables.push(name.to_string());
+        }
+    }
+
+    // The parameters referred to by the plans built so far, and their inferred types.
+    pub fn into_params(self) -> (Params, Vec<Option<DataType>>) {
+        (self.params, self.param_types)
+    }
+
+    pub fn plan_query(&mut self, query: &ast::Query) -> Result<SelectPlan, Error> {
+        let (op, all, left, right) = match query {
+            ast::Query::Select(select) => return self.plan_select(select),
+            ast::Query::SetOperation { op, all, left, right } => (*op, *all, left, right),
+            ast::Query::With { recursive, ctes, body } => {
+                let depth = self.ctes.len();
+                let plan = self.plan_with(*recursive, ctes, body);
+                self.ctes.truncate(depth);
+                return plan;
+            }
+        };
+        let (left, right) = (self.plan_query(left)?, self.plan_query(right)?);
+        let types = match_types(op, &left, &right)?;
+        let num_columns = types.len();
+        let plan: Box<dyn PlanNode> = match op {
+            SetOperator::Union => {
+                let plan = Box::new(Append {
+                    children: vec![left.plan, right.plan],
+                });
+                if all {
+                    plan
+                } else {
+                    distinct(plan, num_columns)
+                }
+            }
+            SetOperator::Intersect | SetOperator::Except => Box::new(HashSetOp {
+                left: left.plan,
+                right: right.plan,
+                op,
+                all,
+            }),
+        };
+        Ok(SelectPlan {
+            plan,
+            columns: left.columns,
+            types,
+        })
+    }
+
+    // Plans `body` with `ctes` visible. A CTE referred to at most once is planned again wherever
+    // it's referred to, like a view, unless it's MATERIALIZED. Otherwise it's run once each time
+    // the query is and its rows are stored.
+    fn plan_with(&mut self, recursive: bool, ctes: &[ast::Cte], body: &ast::Query) -> Result<SelectPlan, Error> {
+        let mut materialized = vec![];
+        for (i, cte) in ctes.iter().enumerate() {
+            if ctes[..i].iter().any(|c| c.name == cte.name) {
+                return Err(Error::Invalid(format!("WITH query name specified more than once: {}", cte.name)));
+            }
+            let self_referencing = recursive && references(&cte.query, &cte.name) > 0;
+            let plan = if self_referencing {
+                self.plan_recursive(cte)?
+            } else {
+                self.plan_query(&cte.query)?
+            };
+            let columns = cte_columns(cte, &plan)?;
+            let uses: usize = ctes[i + 1..].iter().map(|c| references(&c.query, &cte.name)).sum::<usize>() + references(body, &cte.name);
+            let source = if self_referencing || cte.materialized.unwrap_or(uses > 1) {
+                let storage = Rc::new(CteStorage::default());
+                let rows = plan.plan.estimate().rows;
+                materialized.push(MaterializedCte {
+                    name: cte.name.clone(),
+                    plan: plan.plan,
+                    storage: storage.clone(),
+                });
+                CteSource::Stored { storage, rows }
+            } else {
+                CteSource::Inline {
+                    query: cte.query.clone(),
+                    outer_depth: self.outer_scopes.len(),
+                }
+            };
+            self.ctes.push(CteBinding {
+                name: cte.name.clone(),
+                columns,
+                types: plan.types,
+                source,
+            });
+        }
+        let mut plan = self.plan_query(body)?;
+        if !materialized.is_empty() {
+            plan.plan = Box::new(MaterializeCtes {
+                ctes: materialized,
+                child: plan.plan,
+            });
+        }
+        Ok(plan)
+    }
+
+    // Plans `base UNION [ALL] recursive`, where `recursive` reads the rows of the previous
+    // iteration by the name of the CTE.
+    fn plan_recursive(&mut self, cte: &ast::Cte) -> Result<SelectPlan, Error> {
+        let (all, base, recursive) = match &*cte.query {
+            ast::Query::SetOperation {
+                op: SetOperator::Union,
+                all,
+                left,
+                right,
+            } => (*all, left, right),
+            _ => {
+                return Err(Error::Invalid(format!(
+                    "recursive query {} must be of the form base UNION [ALL] recursive",
+                    cte.name
+                )))
+            }
+        };
+        if references(base, &cte.name) > 0 {
+            return Err(Error::Invalid(format!(
+                "recursive reference to query {} must not appear within its non-recursive term",
+                cte.name
+            )));
+        }
+        let base = self.plan_query(base)?;
+        let work_table = Rc::new(CteStorage::default());
+        self.ctes.push(CteBinding {
+            name: cte.name.clone(),
+            columns: cte_columns(cte, &base)?,
+            types: base.types.clone(),
+            source: CteSource::Stored {
+                storage: work_table.clone(),
+                rows: base.plan.estimate().rows,
+            },
+        });
+        let recursive = self.plan_query(recursive);
+        self.ctes.pop();
+        let recursive = recursive?;
+        let types = match_types(SetOperator::Union, &base, &recursive)?;
+        Ok(SelectPlan {
+            plan: Box::new(RecursiveUnion {
+                base: base.plan,
+                recursive: recursive.plan,
+                work_table,
+                all,
+            }),
+            columns: base.columns,
+            types,
+        })
+    }
+
+    // The plan reading the CTE bound at `position` in `ctes`.
+    fn plan_cte(&mut self, position: usize) -> Result<Box<dyn PlanNode>, Error> {
+        let binding = &self.ctes[position];
+        match &binding.source {
+            CteSource::Stored { storage, rows } => Ok(Box::new(CteScan {
+                name: binding.name.clone(),
+                storage: storage.clone(),
+                rows: *rows,
+            })),
+            CteSource::Inline { query, outer_depth } => {
+                let (query, outer_depth) = (query.clone(), *outer_depth);
+                // the query sees only the CTEs defined before it
+                let later_ctes = self.ctes.split_off(position);
+                let inner_scopes = self.outer_scopes.split_off(outer_depth);
+                let plan = self.plan_query(&query);
+                self.ctes.extend(later_ctes);
+                self.outer_scopes.extend(inner_scopes);
+                Ok(plan?.plan)
+            }
+        }
+    }
+
+    pub fn plan_select(&mut self, select: &ast::Select) -> Result<SelectPlan, Error> {
+        let select = &*resolve_aggregates(select, self.catalog);
+        let (relations, columns, on, laterals) = self.flatten_from(&select.from)?;
+        let scope = Scope::new(columns);
+
+        let mut conjuncts = vec![];
+        for conjunct in on {
+            conjuncts.push(self.bind_expr(conjunct, &scope)?);
+        }
+        let mut semi_joins = vec![];
+        for conjunct in select.selection.iter().flat_map(split_conjuncts) {
+            match self.decorrelate(&scope, conjunct)? {
+                Some(spec) => semi_joins.push(spec),
+                None => conjuncts.push(self.bind_expr(conjunct, &scope)?),
+            }
+        }
+
+        // expressions evaluated on the joined rows: the output columns, or the GROUP BY
+        // expressions and aggregate arguments of a grouped query
+        let mut exprs = vec![];
+        let mut names = vec![];
+        let mut types = vec![];
+        let mut aggregates = vec![];
+        let grouped = is_grouped(select);
+        // the output expressions of a query with window functions are evaluated on the rows
+        // the functions are computed for
+        let mut windows = vec![];
+        for item in &select.projection {
+            if let ast::SelectItem::Expr { expr, .. } = item {
+                collect_windows(expr, &mut windows);
+            }
+        }
+        let windowed = !windows.is_empty();
+        for item in &select.projection {
+            match item {
+                ast::SelectItem::Wildcard if grouped => {
+                    return Err(Error::Invalid("SELECT * is not allowed in grouped queries".to_string()));
+                }
+                ast::SelectItem::Wildcard => {
+                    for (i, column) in scope.columns.iter().enumerate() {
+                        if !windowed {
+                            exprs.push(Expr::Column(i));
+                            types.push(column.data_type);
+                        }
+                        names.push(column.name.clone());
+                    }
+                }
+                ast::SelectItem::Expr { expr, alias } => {
+                    if grouped {
+                        collect_aggregates(expr, &mut aggregates);
+                    } else if !windowed {
+                        exprs.push(self.bind_expr(expr, &scope)?);
+                        types.push(self.static_type(expr, &scope));
+                    }
+                    names.push(output_name(alias.as_deref(), expr));
+                }
+            }
+        }
+        if let Some(having) = &select.having {
+            collect_aggregates(having, &mut aggregates);
+        }
+        if grouped {
+            for expr in &select.group_by {
+                exprs.push(self.bind_expr(expr, &scope)?);
+            }
+            for aggregate in &aggregates {
+                if let ast::Expr::Aggregate { arg: Some(arg), .. } = aggregate {
+                    exprs.push(self.bind_expr(arg, &scope)?);
+                }
+            }
+        }
+
+        let mut needed: Vec<usize> = semi_joins.iter().flat_map(|s| s.left_keys.iter().copied()).collect();
+        for expr in &exprs {
+            expr.columns(&mut needed);
+        }
+        let num_columns = scope.columns.len();
+        // a subquery may read any column of the row it's evaluated for, and so may the window
+        // functions and output expressions which are bound later
+        let all_columns = exprs.iter().any(Expr::has_subquery) || (windowed && !grouped);
+        if all_columns {
+            needed = (0..num_columns).collect();
+        }
+        let mut relations = relations;
+        let hints = Self::resolve_hints(&select.hints, &relations, &scope.columns)?;
+        Self::push_down_constraints(&mut relations, &conjuncts);
+        let (relations, conjuncts) = self.expand_partitions(relations, conjuncts);
+        let (mut plan, mut layout) = plan_lateral(relations, laterals, num_columns, conjuncts, needed, self.settings, hints);
+        if all_columns {
+            plan = restore_layout(plan, &layout, num_columns);
+            layout = (0..num_columns).collect();
+        }
+        let position = |column: usize| layout.iter().position(|&c| c == column).unwrap();
+        let mut exprs = exprs.iter().map(|e| e.remap(&position)).collect::<Option<_>>().unwrap_or(exprs);
+        for spec in semi_joins {
+            plan = Box::new(HashSemiJoin {
+                left: plan,
+                right: spec.right,
+                left_keys: spec.left_keys.into_iter().map(position).collect(),
+                right_keys: spec.right_keys,
+                anti: spec.anti,
+                null_aware: spec.null_aware,
+            });
+        }
+
+        // rows the output expressions are evaluated on, and the expressions if still to be bound
+        let mut scope = scope;
+        let mut output = vec![];
+        if grouped {
+            let mut args = exprs.split_off(select.group_by.len()).into_iter();
+            let mut columns = vec![];
+            for (i, expr) in select.group_by.iter().enumerate() {
+                columns.push(ScopeColumn {
+                    table: None,
+                    name: format!("group#{}", i),
+                    data_type: self.static_type(expr, &scope),
+                });
+            }
+            let mut bound = vec![];
+            for (i, aggregate) in aggregates.iter().enumerate() {
+                columns.push(ScopeColumn {
+                    table: None,
+                    name: format!("aggregate#{}", i),
+                    data_type: self.static_type(aggregate, &scope),
+                });
+                if let ast::Expr::Aggregate { func, arg, distinct } = aggregate {
+                    bound.push(Aggregate {
+                        func: func.clone(),
+                        arg: arg.as_ref().map(|_| args.next().unwrap()),
+                        distinct: *distinct,
+                    });
+                }
+            }
+            plan = Box::new(HashAggregate {
+                child: plan,
+                group_by: exprs,
+                aggregates: bound,
+            });
+
+            // the output expressions and HAVING read the groups and aggregates
+            let grouped_scope = Scope::new(columns);
+            let rewrite = |expr: &ast::Expr| rewrite_grouped(expr, &scope, &select.group_by, &aggregates, self.catalog);
+            if let Some(having) = &select.having {
+                let predicate = self.bind_expr(&rewrite(having)?, &grouped_scope)?;
+                plan = Box::new(Filter { child: plan, predicate });
+            }
+            exprs = vec![];
+            for item in &select.projection {
+                if let ast::SelectItem::Expr { expr, .. } = item {
+                    let expr = rewrite(expr)?;
+                    if windowed {
+                        output.push(expr);
+                    } else {
+                        exprs.push(self.bind_expr(&expr, &grouped_scope)?);
+                        types.push(self.static_type(&expr, &grouped_scope));
+                    }
+                }
+            }
+            scope = grouped_scope;
+        } else if windowed {
+            for item in &select.projection {
+                if let ast::SelectItem::Expr { expr, .. } = item {
+                    output.push(expr.clone());
+                }
+            }
+        }
+        if windowed {
+            let (window_plan, window_scope, output) = self.plan_windows(plan, &scope, &output)?;
+            plan = window_plan;
+            let mut output = output.into_iter();
+            for item in &select.projection {
+                match item {
+                    ast::SelectItem::Wildcard => {
+                        for (i, column) in scope.columns.iter().enumerate() {
+                            exprs.push(Expr::Column(i));
+                            types.push(column.data_type);
+                        }
+                    }
+                    ast::SelectItem::Expr { .. } => {
+                        let expr = output.next().unwrap();
+                        exprs.push(self.bind_expr(&expr, &window_scope)?);
+                        types.push(self.static_type(&expr, &window_scope));
+                    }
+                }
+            }
+        }
+
+        let mut plan: Box<dyn PlanNode> = Box::new(Project { child: plan, exprs });
+        if select.distinct {
+            plan = distinct(plan, names.len());
+        }
+        Ok(SelectPlan {
+            plan,
+            columns: names,
+            types,
+        })
+    }
+
+    // Computes the window functions in `exprs`, which are evaluated on the rows of `plan` described
+    // by `scope`, and replaces them by references to their values, `window#i`. The functions with
+    // the same PARTITION BY and ORDER BY are computed by one Window node over one sort.
+    #[allow(clippy::type_complexity)]
+    fn plan_windows(
+        &mut self,
+        mut plan: Box<dyn PlanNode>,
+        scope: &Scope,
+        exprs: &[ast::Expr],
+    ) -> Result<(Box<dyn PlanNode>, Scope, Vec<ast::Expr>), Error> {
+        let mut windows = vec![];
+        for expr in exprs {
+            collect_windows(expr, &mut windows);
+        }
+        let mut columns = scope.columns.clone();
+        let mut planned = vec![false; windows.len()];
+        for i in 0..windows.len() {
+            if planned[i] {
+                continue;
+            }
+            let (partition_by, order_by) = window_keys(&windows[i]);
+            // the sort keys are appended to the rows, which already have `width` columns
+            let width = columns.len();
+            let mut sort_keys = vec![];
+            for expr in partition_by.iter().chain(order_by.iter().map(|o| &o.expr)) {
+                sort_keys.push(self.bind_expr(expr, scope)?);
+            }
+            let num_keys = sort_keys.len();
+            if num_keys > 0 {
+                let exprs = (0..width).map(Expr::Column).chain(sort_keys).collect();
+                let descending = partition_by.iter().map(|_| false).chain(order_by.iter().map(|o| o.descending)).collect();
+                plan = Box::new(Sort {
+                    child: Box::new(Project { child: plan, exprs }),
+                    keys: (width..width + num_keys).collect(),
+                    descending,
+                    max_rows_in_memory: DEFAULT_MAX_BUILD_ROWS,
+                });
+                for _ in 0..num_keys {
+                    columns.push(ScopeColumn {
+                        table: None,
+                        name: "window key".to_string(),
+                        data_type: None,
+                    });
+                }
+            }
+            let mut functions = vec![];
+            for j in i..windows.len() {
+                if planned[j] || window_keys(&windows[j]) != (partition_by, order_by) {
+                    continue;
+                }
+                if let ast::Expr::Window { func, args, frame, .. } = &windows[j] {
+                    functions.push(WindowFunction {
+                        func: func.clone(),
+                        args: args.iter().map(|arg| self.bind_expr(arg, scope)).collect::<Result<_, _>>()?,
+                        frame: frame.unwrap_or(Frame::implicit(!order_by.is_empty())),
+                    });
+                }
+                columns.push(ScopeColumn {
+                    table: None,
+                    name: format!("window#{}", j),
+                    data_type: self.static_type(&windows[j], scope),
+                });
+                planned[j] = true;
+            }
+            plan = Box::new(Window {
+                child: plan,
+                partition_by: (width..width + partition_by.len()).collect(),
+                order_by: (width + partition_by.len()..width + num_keys).collect(),
+                functions,
+            });
+        }
+        let exprs = exprs.iter().map(|e| rewrite_windows(e, &windows)).collect();
+        Ok((plan, Scope::new(columns), exprs))
+    }
+
+    // The relations of a FROM clause in order, their columns, the conditions of the joins and the
+    // UNNESTs among the relations which read the columns of those before them.
+    #[allow(clippy::type_complexity)]
+    fn flatten_from<'s>(
+        &mut self,
+        from: &'s [ast::TableRef],
+    ) -> Result<(Vec<Source<'a>>, Vec<ScopeColumn>, Vec<&'s ast::Expr>, Vec<Lateral>), Error> {
+        let mut relations = vec![];
+        let mut columns = vec![];
+        let mut on = vec![];
+        let mut laterals = vec![];
+        for table_ref in from {
+            self.flatten_table_ref(table_ref, &mut relations, &mut columns, &mut on, &mut laterals)?;
+        }
+        Ok((relations, columns, on, laterals))
+    }
+
+    fn flatten_table_ref<'s>(
+        &mut self,
+        table_ref: &'s ast::TableRef,
+        relations: &mut Vec<Source<'a>>,
+        columns: &mut Vec<ScopeColumn>,
+        on: &mut Vec<&'s ast::Expr>,
+        laterals: &mut Vec<Lateral>,
+    ) -> Result<(), Error> {
+        match table_ref {
+            ast::TableRef::Table { name, alias } => {
+                let qualifier = alias.clone().unwrap_or_else(|| name.clone());
+                if let Some(position) = self.ctes.iter().rposition(|c| c.name == *name) {
+                    let binding = &self.ctes[position];
+                    columns.extend(binding.columns.iter().zip(&binding.types).map(|(name, data_type)| ScopeColumn {
+                        table: Some(qualifier.clone()),
+                        name: name.clone(),
+                        data_type: *data_type,
+                    }));
+                    let num_columns = binding.columns.len();
+                    relations.push(Source::Plan {
+                        plan: self.plan_cte(position)?,
+                        num_columns,
+                    });
+                    return Ok(());
+                }
+                if let Some(view) = self.catalog.view(name) {
+                    self.read_table(name);
+                    let plan = self.plan_view(view)?;
+                    columns.extend(view.columns.iter().zip(&plan.types).map(|(name, data_type)| ScopeColumn {
+                        table: Some(qualifier.clone()),
+                        name: name.clone(),
+                        data_type: *data_type,
+                    }));
+                    relations.push(Source::Plan {
+                        plan: plan.plan,
+                        num_columns: view.columns.len(),
+                    });
+                    return Ok(());
+                }
+                if let Some(table) = self.catalog.virtual_table(name) {
+                    // referred to without its schema
+                    let qualifier = match (alias, name.split_once('.')) {
+                        (None, Some((_, unqualified))) => unqualified.to_string(),
+                        _ => qualifier,
+                    };
+                    let table_columns = table.columns();
+                    columns.extend(table_columns.iter().map(|c| ScopeColumn {
+                        table: Some(qualifier.clone()),
+                        name: c.name.clone(),
+                        data_type: Some(c.data_type),
+                    }));
+                    relations.push(Source::Plan {
+                        plan: Box::new(VirtualScan { name: name.clone(), table: table.clone(), constraints: vec![] }),
+                        num_columns: table_columns.len(),
+                    });
+                    return Ok(());
+                }
+                let info = self.catalog.table(name).ok_or_else(|| Error::UnknownTable(name.clone()))?;
+                self.read_table(name);
+                columns.extend(info.columns.iter().map(|c| ScopeColumn {
+                    table: Some(qualifier.clone()),
+                    name: c.name.clone(),
+                    data_type: Some(c.data_type),
+                }));
+                relations.push(Source::Table(info));
+            }
+            // all joins are inner joins, whose conditions can be evaluated anywhere above both sides
+            ast::TableRef::Join { left, right, on: condition } => {
+                self.flatten_table_ref(left, relations, columns, on, laterals)?;
+                self.flatten_table_ref(right, relations, columns, on, laterals)?;
+                on.extend(condition.iter().flat_map(split_conjuncts));
+            }
+            // the columns of the FROM items before it can be referred to, as if it were LATERAL
+            ast::TableRef::Unnest { expr, alias, column } => {
+                let scope = Scope::new(columns.clone());
+                let data_type = self.static_type(expr, &scope);
+                if let Some(data_type) = data_type.filter(|t| t.element().is_none()) {
+                    return Err(Error::Invalid(format!("unnest of type {}, which is not an array", type_name(data_type))));
+                }
+                let bound = self.bind_expr(expr, &scope)?;
+                let alias = alias.clone().unwrap_or_else(|| "unnest".to_string());
+                columns.push(ScopeColumn {
+                    name: column.clone().unwrap_or_else(|| alias.clone()),
+                    table: Some(alias),
+                    data_type: data_type.and_then(DataType::element),
+                });
+                let mut referenced = vec![];
+                bound.columns(&mut referenced);
+                if referenced.is_empty() && !bound.has_subquery() {
+                    let plan = Box::new(Unnest {
+                        child: Box::new(Values { rows: vec![vec![]] }),
+                        expr: bound,
+                    });
+                    relations.push(Source::Plan { plan, num_columns: 1 });
+                } else {
+                    laterals.push(Lateral {
+                        relations: relations.len(),
+                        column: columns.len() - 1,
+                        expr: bound,
+                    });
+                    // taking its place until it's joined to the relations before it
+                    relations.push(Source::Plan {
+                        plan: Box::new(Values { rows: vec![] }),
+                        num_columns: 1,
+                    });
+                }
+            }
+            // the arguments may be of the columns of enclosing queries, not of the FROM items
+            // before it
+            ast::TableRef::Function { name, args, alias, columns: names } => {
+                let func = self.catalog.table_function(name).ok_or_else(|| Error::Invalid(format!("unknown table function: {}", name)))?;
+                if args.len() < func.required || args.len() > func.args.len() {
+                    let arity = match func.required == func.args.len() {
+                        true => func.args.len().to_string(),
+                        false => format!("{} to {}", func.required, func.args.len()),
+                    };
+                    return Err(Error::Invalid(format!("function {} takes {} arguments, not {}", name, arity, args.len())));
+                }
+                if names.len() > func.columns.len() {
+                    return Err(Error::Invalid(format!("function {} returns {} columns, not {}", name, func.columns.len(), names.len())));
+                }
+                let scope = Scope::new(vec![]);
+                let mut bound = vec![];
+                for (arg, &data_type) in args.iter().zip(&func.args) {
+                    bound.push(self.bind_coerced(arg, &scope, Some(data_type))?);
+                    self.infer_param(arg, Some(data_type));
+                }
+                let qualifier = alias.clone().unwrap_or_else(|| name.clone());
+                columns.extend(func.columns.iter().enumerate().map(|(i, c)| ScopeColumn {
+                    table: Some(qualifier.clone()),
+                    name: names.get(i).unwrap_or(&c.name).clone(),
+                    data_type: Some(c.data_type),
+                }));
+                relations.push(Source::Plan {
+                    num_columns: func.columns.len(),
+                    plan: Box::new(FunctionScan { func, args: bound }),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Passes the conjuncts comparing a column of a virtual table among `relations` to a literal
+    // or a parameter on to its scan. They're kept in `conjuncts` all the same, the table being
+    // free to ignore them.
+    fn push_down_constraints(relations: &mut [Source<'a>], conjuncts: &[Expr]) {
+        let mut offset = 0;
+        for source in relations {
+            let num_columns = match source {
+                Source::Table(info) => info.columns.len(),
+                Source::Plan { num_columns, .. } => *num_columns,
+            };
+            if let Source::Plan { plan, .. } = source {
+                if let Some(scan) = plan.as_virtual_scan_mut() {
+                    scan.constraints.extend(conjuncts.iter().filter_map(|c| constraint(c, offset..offset + num_columns)));
+                }
+            }
+            offset += num_columns;
+        }
+    }
+
+    // The hints of a SELECT, the tables named as `columns` are qualified. Hints to read a relation
+    // which isn't a table by an index can't be honored, and are left out.
+    fn resolve_hints(hints: &[ast::Hint], relations: &[Source<'a>], columns: &[ScopeColumn]) -> Result<Hints, Error> {
+        let mut qualifiers = vec![];
+        let mut offset = 0;
+        for source in relations {
+            qualifiers.push(columns.get(offset).and_then(|column| column.table.as_deref()));
+            offset += match source {
+                Source::Table(info) => info.columns.len(),
+                Source::Plan { num_columns, .. } => *num_columns,
+            };
+        }
+        let relation = |name: &str| match qualifiers.iter().enumerate().filter(|(_, q)| **q == Some(name)).map(|(r, _)| r).collect::<Vec<_>>()[..] {
+            [r] => Ok(r),
+            [] => Err(Error::Invalid(format!("hint names a table not in FROM: {}", name))),
+            _ => Err(Error::Invalid(format!("hint names a table more than once in FROM: {}", name))),
+        };
+        let mut resolved = Hints::default();
+        for hint in hints {
+            match hint {
+                ast::Hint::SeqScan(table) => resolved.scans.push((relation(table)?, ScanHint::Seq)),
+                ast::Hint::Index { table, index } => {
+                    let r = relation(table)?;
+                    let Source::Table(info) = &relations[r] else {
+                        continue;
+                    };
+                    let access = match index {
+                        Some(index) => {
+                            let i = info.index_names.iter().position(|name| name == index).ok_or_else(|| Error::Invalid(format!("index not found on {}: {}", info.name, index)))?;
+                            Some(Access::Index(i))
+                        }
+                        None => None,
+                    };
+                    resolved.scans.push((r, ScanHint::Index(access)));
+                }
+                ast::Hint::Leading(tables) => resolved.leading = tables.iter().map(|table| relation(table)).collect::<Result<_, _>>()?,
+                ast::Hint::Join { method, tables } => {
+                    let set = tables.iter().try_fold(0u64, |set, table| Ok::<_, Error>(set | 1 << relation(table)?))?;
+                    resolved.joins.push((set, *method));
+                }
+            }
+        }
+        Ok(resolved)
+    }
+
+    // Replaces each partitioned table among `relations` with the partitions its rows may be in
+    // given the conjuncts on it alone, whose plans those conjuncts are taken into.
+    fn expand_partitions(&self, relations: Vec<Source<'a>>, mut conjuncts: Vec<Expr>) -> (Vec<Source<'a>>, Vec<Expr>) {
+        let mut expanded = vec![];
+        let mut offset = 0;
+        for source in relations {
+            let (info, partitioning) = match source {
+                Source::Table(info) => match &info.partitioning {
+                    Some(partitioning) => (info, partitioning),
+                    None => {
+                        offset += info.columns.len();
+                        expanded.push(source);
+                        continue;
+                    }
+                },
+                Source::Plan { num_columns, .. } => {
+                    offset += num_columns;
+                    expanded.push(source);
+                    continue;
+                }
+            };
+            let num_columns = info.columns.len();
+            let (local, rest): (Vec<_>, Vec<_>) = conjuncts.into_iter().partition(|conjunct| {
+                let mut columns = vec![];
+                conjunct.columns(&mut columns);
+                !conjunct.has_subquery() && !columns.is_empty() && columns.iter().all(|c| (offset..offset + num_columns).contains(c))
+            });
+            conjuncts = rest;
+            let local: Vec<Expr> = local.iter().map(|conjunct| conjunct.remap(&|c| c - offset).unwrap()).collect();
+            let filter = KeyFilter::new(partitioning.column, info.columns[partitioning.column].data_type, &local);
+            let mut children = vec![];
+            for name in &partitioning.partitions {
+                let partition = self.catalog.table(name).unwrap();
+                if !partition.partition.as_ref().is_some_and(|p| filter.may_contain(&p.bound)) {
+                    continue;
+                }
+                let (plan, layout) = optimize(Query {
+                    relations: vec![Source::Table(partition)],
+                    conjuncts: local.iter().map(|conjunct| conjunct.remap(&|c| c).unwrap()).collect(),
+                    needed: (0..num_columns).collect(),
+                    max_build_rows: DEFAULT_MAX_BUILD_ROWS,
+                    settings: self.settings,
+                    hints: Hints::default(),
+                });
+                children.push(restore_layout(plan, &layout, num_columns));
+            }
+            let plan: Box<dyn PlanNode> = match children.len() {
+                0 => Box::new(Values { rows: vec![] }),
+                1 => children.pop().unwrap(),
+                _ => Box::new(Append { children }),
+            };
+            expanded.push(Source::Plan { plan, num_columns });
+            offset += num_columns;
+        }
+        (expanded, conjuncts)
+    }
+
+    // Plans the query of `view` as if it were written in place of the view name. It can't see the
+    // columns or the CTEs of the query referring to the view.
+    fn plan_view(&mut self, view: &ViewInfo) -> Result<SelectPlan, Error> {
+        let query = match parse(&view.sql)?.pop() {
+            Some(ast::Statement::Select(query)) => query,
+            _ => return Err(Error::Invalid(format!("malformed view: {}", view.name))),
+        };
+        let outer_scopes = std::mem::take(&mut self.outer_scopes);
+        let ctes = std::mem::take(&mut self.ctes);
+        self.view_depth += 1;
+        let plan = self.plan_query(&query);
+        self.view_depth -= 1;
+        self.outer_scopes = outer_scopes;
+        self.ctes = ctes;
+        plan
+    }
+
+    // Turns `col [NOT] IN (SELECT ...)` and `[NOT] EXISTS (SELECT ...)` into semi / anti joins
+    // when the subquery only refers to the current scope through equalities with its own columns.
+    fn decorrelate(&mut self, scope: &Scope, conjunct: &ast::Expr) -> Result<Option<SemiJoinSpec>, Error> {
+        match conjunct {
+            ast::Expr::InSubquery { expr, subquery, negated } => {
+                let left_key = match &**expr {
+                    ast::Expr::Column { table, name } => match scope.resolve(table.as_deref(), name)? {
+                        Some(i) => i,
+                        None => return Ok(None),
+                    },
+                    _ => return Ok(None),
+                };
+                let (subplan, correlated) = self.with_outer_scope(scope, |planner| planner.plan_query(subquery))?;
+                let subplan = subplan?;
+                if correlated {
+                    return Ok(None);
+                }
+                if subplan.columns.len() != 1 {
+                    return Err(Error::Invalid("subquery must return only one column".to_string()));
+                }
+                Ok(Some(SemiJoinSpec {
+                    right: subplan.plan,
+                    left_keys: vec![left_key],
+                    right_keys: vec![0],
+                    anti: *negated,
+                    null_aware: *negated,
+                }))
+            }
+            // a grouped subquery may return rows even if no row matches
+            ast::Expr::Exists { subquery, negated } => {
+                let subquery = match &**subquery {
+                    ast::Query::Select(select) if !is_grouped(&resolve_aggregates(select, self.catalog)) => select,
+                    _ => return Ok(None),
+                };
+                // an UNNEST or the arguments of a table function may be of the columns of the
+                // current scope
+                if subquery.from.iter().any(has_unnest) {
+                    return Ok(None);
+                }
+                let (relations, inner_columns, on, _) = self.flatten_from(&subquery.from)?;
+                let inner_scope = Scope::new(inner_columns);
+                let mut conjuncts = vec![];
+                for on_conjunct in on {
+                    conjuncts.push(self.bind_expr(on_conjunct, &inner_scope)?);
+                }
+                let mut left_keys = vec![];
+                let mut right_keys = vec![];
+                for inner_conjunct in subquery.selection.iter().flat_map(split_conjuncts) {
+                    let (bound, correlated) =
+                        self.with_outer_scope(scope, |planner| planner.bind_expr(inner_conjunct, &inner_scope))?;
+                    if !correlated {
+                        conjuncts.push(bound?);
+                        continue;
+                    }
+                    match equality_between(inner_conjunct, scope, &inner_scope)? {
+                        Some((outer, inner)) => {
+                            left_keys.push(outer);
+                            right_keys.push(inner);
+                        }
+                        None => return Ok(None),
+                    }
+                }
+                let (right, layout) = plan_join(relations, inner_scope.columns.len(), conjuncts, right_keys.clone(), self.settings, Hints::default());
+                Ok(Some(SemiJoinSpec {
+                    right,
+                    left_keys,
+                    right_keys: right_keys.iter().map(|k| layout.iter().position(|c| c == k).unwrap()).collect(),
+                    anti: *negated,
+                    null_aware: false,
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // Runs `f` with `scope` as the innermost enclosing scope.
+    // Also returns whether `f` referred to a column of `scope`.
+    fn with_outer_scope<T>(&mut self, scope: &Scope, f: impl FnOnce(&mut Self) -> T) -> Result<(T, bool), Error> {
+        self.outer_scopes.push((
+            Scope {
+                columns: scope.columns.clone(),
+                outer_row: scope.outer_row.clone(),
+            },
+            false,
+        ));
+        let result = f(self);
+        let (_, used) = self.outer_scopes.pop().unwrap();
+        Ok((result, used))
+    }
+
+    fn resolve_column(&mut self, scope: &Scope, table: Option<&str>, name: &str) -> Result<Expr, Error> {
+        if let Some(i) = scope.resolve(table, name)? {
+            return Ok(Expr::Column(i));
+        }
+        for (outer, used) in self.outer_scopes.iter_mut().rev() {
+            if let Some(index) = outer.resolve(table, name)? {
+                *used = true;
+                return Ok(Expr::OuterColumn {
+                    row: outer.outer_row.clone(),
+                    index,
+                });
+            }
+        }
+        let name = match table {
+            Some(table) => format!("{}.{}", table, name),
+            None => name.to_string(),
+        };
+        Err(Error::UnknownColumn(name))
+    }
+
+    fn bind_subquery(&mut self, scope: &Scope, subquery: &ast::Query) -> Result<SelectPlan, Error> {
+        let (subplan, _) = self.with_outer_scope(scope, |planner| planner.plan_query(subquery))?;
+        subplan
+    }
+
+    fn bind_expr(&mut self, expr: &ast::Expr, scope: &Scope) -> Result<Expr, Error> {
+        Ok(match expr {
+            ast::Expr::Literal(value) => Expr::Literal(value.clone()),
+            ast::Expr::Parameter(index) => {
+                if self.param_types.len() <= *index {
+                    self.param_types.resize(index + 1, None);
+                }
+                Expr::Parameter {
+                    params: self.params.clone(),
+                    index: *index,
+                }
+            }
+            ast::Expr::Column { table, name } => self.resolve_column(scope, table.as_deref(), name)?,
+            ast::Expr::Unary { op, expr } => {
+                let bound = self.bind_expr(expr, scope)?;
+                self.infer_param(expr, Some(operand_type(*op == UnaryOp::Not)));
+                Expr::Unary {
+                    op: *op,
+                    expr: Box::new(bound),
+                }
+            }
+            ast::Expr::Binary { op, left, right } => {
+                // types the operands are expected to be of: the same for comparisons
+                let (left_type, right_type) = (self.static_type(left, scope), self.static_type(right, scope));
+                let (expected_left, expected_right) = match op {
+                    op if is_comparison(*op) => {
+                        if let (Some(l), Some(r)) = (left_type, right_type) {
+                            if l.common(r).is_none() && !is_text_literal(left) && !is_text_literal(right) {
+                                return Err(Error::Invalid(format!(
+                                    "cannot compare {} with {}",
+                                    type_name(l),
+                                    type_name(r)
+                                )));
+                            }
+                        }
+                        (right_type, left_type)
+                    }
+                    BinaryOp::Subscript => (None, Some(DataType::Integer)),
+                    // a key or an index, or a path written as text
+                    BinaryOp::JsonGet | BinaryOp::JsonGetText => (Some(DataType::Json), None),
+                    BinaryOp::JsonPath | BinaryOp::JsonPathText => (Some(DataType::Json), Some(DataType::Text)),
+                    BinaryOp::Match => (Some(DataType::Text), Some(DataType::Text)),
+                    // a box containing a point or box
+                    BinaryOp::Contains => (Some(DataType::Box), Some(shape_type(right))),
+                    BinaryOp::ContainedBy => (Some(shape_type(left)), Some(DataType::Box)),
+                    BinaryOp::Overlaps => (Some(shape_type(left)), Some(shape_type(right))),
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+                        let (l, r, _) = arithmetic_types(*op, left_type, right_type);
+                        (Some(l), Some(r))
+                    }
+                    _ => {
+                        let data_type = Some(operand_type(matches!(op, BinaryOp::And | BinaryOp::Or)));
+                        (data_type, data_type)
+                    }
+                };
+                let mut bound_left = self.bind_coerced(left, scope, expected_left)?;
+                let mut bound_right = self.bind_coerced(right, scope, expected_right)?;
+                self.infer_param(left, expected_left);
+                self.infer_param(right, expected_right);
+                // numbers of different types compared as the one they're converted to, so that an
+                // index of either is searched with keys of its type
+                if let (true, Some(l), Some(r)) = (is_comparison(*op), left_type, right_type) {
+                    if let Some(common) = l.common(r).filter(|_| l != r && !is_text_literal(left) && !is_text_literal(right)) {
+                        bound_left = convert(bound_left, l, common)?;
+                        bound_right = convert(bound_right, r, common)?;
+                    }
+                }
+                Expr::Binary {
+                    op: *op,
+                    left: Box::new(bound_left),
+                    right: Box::new(bound_right),
+                }
+            }
+            ast::Expr::IsNull { expr, negated } => Expr::IsNull {
+                expr: Box::new(self.bind_expr(expr, scope)?),
+                negated: *negated,
+            },
+            ast::Expr::InList { expr, list, negated } => {
+                let item_type = list.iter().filter(|e| !is_text_literal(e)).find_map(|e| self.static_type(e, scope));
+                let expr_type = self.static_type(expr, scope);
+                let bound = Expr::InList {
+                    expr: Box::new(self.bind_coerced(expr, scope, item_type)?),
+                    list: list.iter().map(|e| self.bind_coerced(e, scope, expr_type)).collect::<Result<_, _>>()?,
+                    negated: *negated,
+                };
+                self.infer_param(expr, item_type);
+                for item in list {
+                    self.infer_param(item, expr_type);
+                }
+                bound
+            }
+            ast::Expr::Like {
+                expr,
+                pattern,
+                negated,
+                case_insensitive,
+            } => {
+                let bound = Expr::Like {
+                    expr: Box::new(self.bind_expr(expr, scope)?),
+                    pattern: Box::new(self.bind_expr(pattern, scope)?),
+                    negated: *negated,
+                    case_insensitive: *case_insensitive,
+                };
+                self.infer_param(expr, Some(DataType::Text));
+                self.infer_param(pattern, Some(DataType::Text));
+                bound
+            }
+            ast::Expr::Function { func: Function::Array, args } => {
+                // of the type of the elements which aren't text literals, those being converted to it
+                let mut element = None;
+                for arg in args.iter().filter(|e| !is_text_literal(e)) {
+                    match (element, self.static_type(arg, scope)) {
+                        (Some(e), Some(t)) if e != t => {
+                            return Err(Error::Invalid(format!("ARRAY elements of types {} and {}", type_name(e), type_name(t))));
+                        }
+                        (_, Some(t)) if ElementType::of(t).is_none() => {
+                            return Err(Error::Invalid(format!("arrays of type {} are not supported", type_name(t))));
+                        }
+                        (None, t) => element = t,
+                        _ => {}
+                    }
+                }
+                let bound = args.iter().map(|e| self.bind_coerced(e, scope, element)).collect::<Result<_, _>>()?;
+                for arg in args {
+                    self.infer_param(arg, element);
+                }
+                Expr::Function { func: Function::Array, args: bound }
+            }
+            ast::Expr::Quantified { op, expr, array, all } => {
+                // the value is compared with elements of its type
+                let (expr_type, array_type) = (self.static_type(expr, scope), self.static_type(array, scope));
+                let element = array_type.and_then(DataType::element);
+                if let (Some(e), Some(t)) = (expr_type, element) {
+                    if e != t && !is_text_literal(expr) {
+                        return Err(Error::Invalid(format!("cannot compare {} with {}", type_name(e), type_name(array_type.unwrap()))));
+                    }
+                }
+                let expected_array = expr_type.and_then(ElementType::of).map(DataType::Array);
+                let bound = Expr::Quantified {
+                    op: *op,
+                    expr: Box::new(self.bind_coerced(expr, scope, element)?),
+                    array: Box::new(self.bind_coerced(array, scope, expected_array)?),
+                    all: *all,
+                };
+                self.infer_param(expr, element);
+                self.infer_param(array, expected_array);
+                bound
+            }
+            ast::Expr::Function { func, args } => {
+                let bound = args.iter().map(|e| self.bind_expr(e, scope)).collect::<Result<_, _>>()?;
+                for (i, arg) in args.iter().enumerate() {
+                    let data_type = match func {
+                        // any value can be concatenated
+                        Function::Concat => None,
+                        Function::Substring if i > 0 => Some(DataType::Integer),
+                        Function::Point => Some(DataType::Integer),
+                        Function::Box => Some(DataType::Point),
+                        _ => Some(DataType::Text),
+                    };
+                    self.infer_param(arg, data_type);
+                }
+                Expr::Function { func: *func, args: bound }
+            }
+            ast::Expr::Call { name, .. } if self.catalog.aggregate(name).is_some() => {
+                return Err(Error::Invalid("aggregate functions are not allowed here".to_string()));
+            }
+            ast::Expr::Call { name, args } => {
+                let func = self.catalog.function(name).ok_or_else(|| Error::Invalid(format!("unknown function: {}", name)))?.clone();
+                if args.len() != func.args.len() {
+                    return Err(Error::Invalid(format!("function {} takes {} arguments, not {}", name, func.args.len(), args.len())));
+                }
+                let mut bound = vec![];
+                for (arg, &data_type) in args.iter().zip(&func.args) {
+                    bound.push(self.bind_coerced(arg, scope, Some(data_type))?);
+                    self.infer_param(arg, Some(data_type));
+                }
+                Expr::Call { func, args: bound }
+            }
+            ast::Expr::InSubquery { expr, subquery, negated } => {
+                let expr = Box::new(self.bind_expr(expr, scope)?);
+                let subplan = self.bind_subquery(scope, subquery)?;
+                if subplan.columns.len() != 1 {
+                    return Err(Error::Invalid("subquery must return only one column".to_string()));
+                }
+                Expr::Subquery {
+                    kind: SubqueryKind::In { expr, negated: *negated },
+                    plan: subplan.plan,
+                    outer_row: scope.outer_row.clone(),
+                }
+            }
+            ast::Expr::Exists { subquery, negated } => Expr::Subquery {
+                kind: SubqueryKind::Exists { negated: *negated },
+                plan: self.bind_subquery(scope, subquery)?.plan,
+                outer_row: scope.outer_row.clone(),
+            },
+            ast::Expr::Aggregate { .. } => {
+                return Err(Error::Invalid("aggregate functions are not allowed here".to_string()));
+            }
+            ast::Expr::Window { .. } => {
+                return Err(Error::Invalid("window functions are not allowed here".to_string()));
+            }
+            ast::Expr::Cast { expr, data_type } => Expr::Cast {
+                expr: Box::new(self.bind_expr(expr, scope)?),
+                to: *data_type,
+            },
+            ast::Expr::Subquery(subquery) => {
+                let subplan = self.bind_subquery(scope, subquery)?;
+                if subplan.columns.len() != 1 {
+                    return Err(Error::Invalid("subquery must return only one column".to_string()));
+                }
+                Expr::Subquery {
+                    kind: SubqueryKind::Scalar,
+                    plan: subplan.plan,
+                    outer_row: scope.outer_row.clone(),
+                }
+            }
+        })
+    }
+
+    // Binds an expression which may not refer to any column, e.g. a value of INSERT.
+    // A parameter given as the whole expression is expected to be of `data_type`.
+    pub fn bind_constant(&mut self, expr: &ast::Expr, data_type: Option<DataType>) -> Result<Expr, Error> {
+        let bound = self.bind_coerced(expr, &Scope::new(vec![]), data_type)?;
+        self.infer_param(expr, data_type);
+        Ok(bound)
+    }
+
+    // Binds an expression of UPDATE, evaluated on each row of `table` it updates, as it was.
+    // `data_type` is as for `bind_constant`.
+    pub fn bind_row(&mut self, table: &str, columns: &[Column], expr: &ast::Expr, data_type: Option<DataType>) -> Result<Expr, Error> {
+        let bound = self.bind_coerced(expr, &table_scope(table, columns, Rc::new(RefCell::new(vec![]))), data_type)?;
+        self.infer_param(expr, data_type);
+        Ok(bound)
+    }
+
+    // Binds an expression of ON CONFLICT DO UPDATE, evaluated on the row of `table` there, which
+    // the columns not qualified by EXCLUDED are of, with `excluded` set to the row proposed for it.
+    // `data_type` is as for `bind_constant`.
+    pub fn bind_conflict(
+        &mut self,
+        table: &str,
+        columns: &[Column],
+        excluded: &OuterRow,
+        expr: &ast::Expr,
+        data_type: Option<DataType>,
+    ) -> Result<Expr, Error> {
+        // the proposed row as if it were of an enclosing query, so that the row there comes first
+        let (bound, _) = self.with_outer_scope(&table_scope("excluded", columns, excluded.clone()), |planner| {
+            let bound = planner.bind_coerced(expr, &table_scope(table, columns, Rc::new(RefCell::new(vec![]))), data_type)?;
+            planner.infer_param(expr, data_type);
+            Ok(bound)
+        })?;
+        bound
+    }
+
+    // Lets the expressions bound from now on refer to the row images of a trigger on a table of
+    // `columns` as the tables `new` and `old` of an enclosing query, `new` and `old` being set to
+    // them when they're evaluated.
+    pub fn add_row_images(&mut self, columns: &[Column], new: &OuterRow, old: &OuterRow) {
+        for (name, row) in [("old", old), ("new", new)] {
+            self.outer_scopes.push((table_scope(name, columns, row.clone()), false));
+        }
+    }
+
+    // Binds the RETURNING list of an INSERT, UPDATE or DELETE of `table`, evaluated on each row it
+    // writes or deletes, into expressions, each with the name and type, if known, of the column it
+    // returns.
+    pub fn bind_returning(&mut self, table: &str, columns: &[Column], items: &[ast::SelectItem]) -> Result<Vec<(Expr, String, Option<DataType>)>, Error> {
+        let scope = table_scope(table, columns, Rc::new(RefCell::new(vec![])));
+        let mut bound = vec![];
+        for item in items {
+            match item {
+                ast::SelectItem::Wildcard => {
+                    for (i, column) in columns.iter().enumerate() {
+                        bound.push((Expr::Column(i), column.name.clone(), Some(column.data_type)));
+                    }
+                }
+                ast::SelectItem::Expr { expr, alias } => {
+                    let data_type = self.static_type(expr, &scope);
+                    bound.push((self.bind_expr(expr, &scope)?, output_name(alias.as_deref(), expr), data_type));
+                }
+            }
+        }
+        Ok(bound)
+    }
+
+    // Binds `expr`, which is expected to be of `data_type`. A string literal, whose type is taken
+    // from where it's used as in `id = '1'`, is converted to the type here.
+    fn bind_coerced(&mut self, expr: &ast::Expr, scope: &Scope, data_type: Option<DataType>) -> Result<Expr, Error> {
+        match (expr, data_type) {
+            (ast::Expr::Literal(value @ Value::Text(_)), Some(data_type)) => Ok(Expr::Literal(cast(value.clone(), data_type)?)),
+            _ => self.bind_expr(expr, scope),
+        }
+    }
+
+    // Type of `expr` if it's known before evaluation.
+    fn static_type(&self, expr: &ast::Expr, scope: &Scope) -> Option<DataType> {
+        match expr {
+            ast::Expr::Literal(value) => DataType::of(value),
+            ast::Expr::Parameter(index) => self.param_types.get(*index).copied().flatten(),
+            ast::Expr::Column { table, name } => {
+                let scopes = std::iter::once(scope).chain(self.outer_scopes.iter().rev().map(|(s, _)| s));
+                for scope in scopes {
+                    if let Ok(Some(i)) = scope.resolve(table.as_deref(), name) {
+                        return scope.columns[i].data_type;
+                    }
+                }
+                None
+            }
+            ast::Expr::Unary { op: UnaryOp::Neg, expr } => self.static_type(expr, scope).filter(|t| t.is_number()).or(Some(DataType::Integer)),
+            ast::Expr::Unary { op, .. } => Some(operand_type(*op == UnaryOp::Not)),
+            ast::Expr::Binary { op: BinaryOp::Subscript, left, .. } => self.static_type(left, scope)?.element(),
+            ast::Expr::Binary { op, left, right } => match op {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+                    Some(arithmetic_types(*op, self.static_type(left, scope), self.static_type(right, scope)).2)
+                }
+                BinaryOp::JsonGet | BinaryOp::JsonPath => Some(DataType::Json),
+                BinaryOp::JsonGetText | BinaryOp::JsonPathText => Some(DataType::Text),
+                _ => Some(DataType::Boolean),
+            },
+            ast::Expr::IsNull { .. }
+            | ast::Expr::InList { .. }
+            | ast::Expr::InSubquery { .. }
+            | ast::Expr::Exists { .. }
+            | ast::Expr::Like { .. }
+            | ast::Expr::Quantified { .. } => Some(DataType::Boolean),
+            ast::Expr::Function { func: Function::Length | Function::TsRank, .. } => Some(DataType::Integer),
+            ast::Expr::Function { func: Function::Point, .. } => Some(DataType::Point),
+            ast::Expr::Function { func: Function::Box, .. } => Some(DataType::Box),
+            ast::Expr::Function { func: Function::Array, args } => {
+                let element = args.iter().filter(|e| !is_text_literal(e)).find_map(|e| self.static_type(e, scope));
+                // of text if all of them are text literals
+                Some(DataType::Array(ElementType::of(element.or(args.first().map(|_| DataType::Text))?)?))
+            }
+            ast::Expr::Cast { data_type, .. } => Some(*data_type),
+            ast::Expr::Function { .. } => Some(DataType::Text),
+            ast::Expr::Call { name, .. } => Some(self.catalog.function(name)?.returns),
+            ast::Expr::Subquery(_) => None,
+            ast::Expr::Aggregate { func, arg, .. } => match func {
+                AggregateFunc::Count => Some(DataType::Integer),
+                AggregateFunc::Sum => Some(sum_type(arg.as_ref().and_then(|arg| self.static_type(arg, scope)))),
+                AggregateFunc::Min | AggregateFunc::Max => self.static_type(arg.as_ref()?, scope),
+                AggregateFunc::User(func) => Some(func.returns),
+            },
+            ast::Expr::Window { func, args, .. } => match func {
+                WindowFunc::RowNumber | WindowFunc::Rank | WindowFunc::DenseRank => Some(DataType::Integer),
+                WindowFunc::Aggregate(AggregateFunc::Count) => Some(DataType::Integer),
+                WindowFunc::Aggregate(AggregateFunc::Sum) => Some(sum_type(args.first().and_then(|arg| self.static_type(arg, scope)))),
+                WindowFunc::Lag | WindowFunc::Lead | WindowFunc::Aggregate(_) => self.static_type(args.first()?, scope),
+            },
+        }
+    }
+
+    // Records the type of `expr` if it's a parameter whose type isn't known yet.
+    fn infer_param(&mut self, expr: &ast::Expr, data_type: Option<DataType>) {
+        if let ast::Expr::Parameter(index) = expr {
+            if self.param_types[*index].is_none() {
+                self.param_types[*index] = data_type;
+            }
+        }
+    }
+}
+
+// Column types of a set operation of `left` and `right`, which must have as many columns. A column
+// whose type is unknown on one side takes the type of the other side.
+fn match_types(op: SetOperator, left: &SelectPlan, right: &SelectPlan) -> Result<Vec<Option<DataType>>, Error> {
+    let name = format!("{:?}", op).to_uppercase();
+    if left.columns.len() != right.columns.len() {
+        return Err(Error::Invalid(format!("each {} query must have the same number of columns", name)));
+    }
+    let mut types = vec![];
+    for (l, r) in left.types.iter().zip(&right.types) {
+        match (l, r) {
+            (Some(l), Some(r)) if l != r => {
+                return Err(Error::Invalid(format!("{} types {:?} and {:?} cannot be matched", name, l, r)));
+            }
+            _ => types.push(l.or(*r)),
+        }
+    }
+    Ok(types)
+}
+
+// Names of the columns of `cte`, whose query is planned as `plan`.
+fn cte_columns(cte: &ast::Cte, plan: &SelectPlan) -> Result<Vec<String>, Error> {
+    match &cte.columns {
+        Some(names) if names.len() != plan.columns.len() => Err(Error::Invalid(format!(
+            "WITH query {} has {} columns but its query returns {}",
+            cte.name,
+            names.len(),
+            plan.columns.len()
+        ))),
+        Some(names) => Ok(names.clone()),
+        None => Ok(plan.columns.clone()),
+    }
+}
+
+// Number of references to the relation `name` in `query`, not counting those to a CTE of the
+// same name defined within it.
+pub fn references(query: &ast::Query, name: &str) -> usize {
+    match query {
+        ast::Query::Select(select) => {
+            let exprs = select
+                .projection
+                .iter()
+                .filter_map(|item| match item {
+                    ast::SelectItem::Expr { expr, .. } => Some(expr),
+                    ast::SelectItem::Wildcard => None,
+                })
+                .chain(&select.selection)
+                .chain(&select.group_by)
+                .chain(&select.having);
+            let from: usize = select.from.iter().map(|t| table_ref_references(t, name)).sum();
+            from + exprs.map(|e| expr_references(e, name)).sum::<usize>()
+        }
+        ast::Query::SetOperation { left, right, .. } => references(left, name) + references(right, name),
+        ast::Query::With { recursive, ctes, body } => {
+            let mut count = 0;
+            for cte in ctes {
+                if cte.name == name {
+                    // the CTE's own query refers to the CTE if it's recursive
+                    if !recursive {
+                        count += references(&cte.query, name);
+                    }
+                    return count;
+                }
+                count += references(&cte.query, name);
+            }
+            count + references(body, name)
+        }
+    }
+}
+
+fn table_ref_references(table_ref: &ast::TableRef, name: &str) -> usize {
+    match table_ref {
+        ast::TableRef::Table { name: table, .. } => (table == name) as usize,
+        ast::TableRef::Join { left, right, on } => {
+            let on: usize = on.iter().map(|e| expr_references(e, name)).sum();
+            table_ref_references(left, name) + table_ref_references(right, name) + on
+        }
+        ast::TableRef::Unnest { expr, .. } => expr_references(expr, name),
+        ast::TableRef::Function { args, .. } => args.iter().map(|e| expr_references(e, name)).sum(),
+    }
+}
+
+fn expr_references(expr: &ast::Expr, name: &str) -> usize {
+    match expr {
+        ast::Expr::InSubquery { expr, subquery, .. } => expr_references(expr, name) + references(subquery, name),
+        ast::Expr::Exists { subquery, .. } | ast::Expr::Subquery(subquery) => references(subquery, name),
+        ast::Expr::Unary { expr, .. } | ast::Expr::IsNull { expr, .. } | ast::Expr::Cast { expr, .. } => expr_references(expr, name),
+        ast::Expr::Binary { left, right, .. } | ast::Expr::Quantified { expr: left, array: right, .. } => {
+            expr_references(left, name) + expr_references(right, name)
+        }
+        ast::Expr::InList { expr, list, .. } => {
+            expr_references(expr, name) + list.iter().map(|e| expr_references(e, name)).sum::<usize>()
+        }
+        ast::Expr::Like { expr, pattern, .. } => expr_references(expr, name) + expr_references(pattern, name),
+        ast::Expr::Function { args, .. } | ast::Expr::Call { args, .. } => args.iter().map(|e| expr_references(e, name)).sum(),
+        ast::Expr::Aggregate { arg, .. } => arg.iter().map(|e| expr_references(e, name)).sum(),
+        ast::Expr::Window { args, partition_by, order_by, .. } => {
+            let exprs = args.iter().chain(partition_by).chain(order_by.iter().map(|o| &o.expr));
+            exprs.map(|e| expr_references(e, name)).sum()
+        }
+        ast::Expr::Literal(_) | ast::Expr::Parameter(_) | ast::Expr::Column { .. } => 0,
+    }
+}
+
+// The name of the column a SELECT item returns, e.g. that of the column it is.
+fn output_name(alias: Option<&str>, expr: &ast::Expr) -> String {
+    match (alias, expr) {
+        (Some(alias), _) => alias.to_string(),
+        (None, ast::Expr::Column { name, .. }) => name.clone(),
+        (None, ast::Expr::Aggregate { func, .. }) => func.name(),
+        (None, ast::Expr::Window { func, .. }) => func.name(),
+        (None, ast::Expr::Function { func, .. }) => func.to_string(),
+        (None, ast::Expr::Call { name, .. }) => name.clone(),
+        _ => "?column?".to_string(),
+    }
+}
+
+// The columns of `table` as a scope, qualified by its name.
+fn table_scope(table: &str, columns: &[Column], outer_row: OuterRow) -> Scope {
+    Scope {
+        columns: columns
+            .iter()
+            .map(|column| ScopeColumn { table: Some(table.to_string()), name: column.name.clone(), data_type: Some(column.data_type) })
+            .collect(),
+        outer_row,
+    }
+}
+
+fn is_text_literal(expr: &ast::Expr) -> bool {
+    matches!(expr, ast::Expr::Literal(Value::Text(_)))
+}
+
+// Type a text literal compared as a point or box is of: a point if it's written as one.
+fn shape_type(expr: &ast::Expr) -> DataType {
+    match expr {
+        ast::Expr::Literal(Value::Text(text)) if Point::parse(text).is_ok() => DataType::Point,
+        _ => DataType::Box,
+    }
+}
+
+// Operand type of the logical operators if `logical`, of the arithmetic ones otherwise.
+// Types the operands of arithmetic on operands of `left` and `right` are expected to be of, and
+// that of its result: days added to or taken from a date, or dates taken from each other, or
+// numbers converted to the same type (see `DataType::common`). An operand of an unknown type, or
+// one which isn't a number, is expected to be of the type of the other or an integer.
+fn arithmetic_types(op: BinaryOp, left: Option<DataType>, right: Option<DataType>) -> (DataType, DataType, DataType) {
+    match (left, right) {
+        (Some(DataType::Date), Some(DataType::Date)) => (DataType::Date, DataType::Date, DataType::Integer),
+        (_, Some(DataType::Date)) if op == BinaryOp::Sub => (DataType::Date, DataType::Date, DataType::Integer),
+        (Some(DataType::Date), _) => (DataType::Date, DataType::Integer, DataType::Date),
+        (_, Some(DataType::Date)) => (DataType::Integer, DataType::Date, DataType::Date),
+        _ => {
+            let (left, right) = (left.filter(|t| t.is_number()), right.filter(|t| t.is_number()));
+            let left = left.or(right).unwrap_or(DataType::Integer);
+            let right = right.unwrap_or(left);
+            (left, right, left.common(right).unwrap())
+        }
+    }
+}
+
+fn is_comparison(op: BinaryOp) -> bool {
+    matches!(op, BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq)
+}
+
+// `expr` of type `from` cast to `to`, a literal cast right away.
+fn convert(expr: Expr, from: DataType, to: DataType) -> Result<Expr, Error> {
+    Ok(match expr {
+        _ if from == to => expr,
+        Expr::Literal(value) => Expr::Literal(cast(value, to)?),
+        expr => Expr::Cast { expr: Box::new(expr), to },
+    })
+}
+
+// Type of the sum of values of `arg`, that of them if they're numerics or floats.
+fn sum_type(arg: Option<DataType>) -> DataType {
+    arg.filter(|t| matches!(t, DataType::Numeric | DataType::Float)).unwrap_or(DataType::Integer)
+}
+
+fn operand_type(logical: bool) -> DataType {
+    if logical {
+        DataType::Boolean
+    } else {
+        DataType::Integer
+    }
+}
+
+// Joins `relations` as `plan_join` does, each of `laterals` to the relations before it, in whose
+// place is a placeholder: the rows of those, for which the conjuncts on their columns alone hold,
+// get the elements of its array appended before they're joined to the rest. The hints of relations
+// on both sides of a lateral are dropped.
+fn plan_lateral(
+    mut relations: Vec<Source>,
+    mut laterals: Vec<Lateral>,
+    num_columns: usize,
+    conjuncts: Vec<Expr>,
+    needed: Vec<usize>,
+    settings: PlannerSettings,
+    hints: Hints,
+) -> (Box<dyn PlanNode>, Vec<usize>) {
+    let Some(lateral) = laterals.pop() else {
+        return plan_join(relations, num_columns, conjuncts, needed, settings, hints);
+    };
+    let after = relations.split_off(lateral.relations + 1);
+    relations.pop();
+    let (before, conjuncts): (Vec<_>, Vec<_>) = conjuncts.into_iter().partition(|conjunct| {
+        let mut columns = vec![];
+        conjunct.columns(&mut columns);
+        !conjunct.has_subquery() && columns.iter().all(|&c| c < lateral.column)
+    });
+    let before_hints = hints.remap(|r| Some(r).filter(|&r| r < lateral.relations));
+    let (plan, layout) = plan_lateral(relations, laterals, lateral.column, before, (0..lateral.column).collect(), settings, before_hints);
+    let plan = Box::new(Unnest {
+        child: restore_layout(plan, &layout, lateral.column),
+        expr: lateral.expr,
+    });
+    let relations = std::iter::once(Source::Plan { plan, num_columns: lateral.column + 1 }).chain(after).collect();
+    let hints = hints.remap(|r| r.checked_sub(lateral.relations).filter(|&r| r > 0));
+    plan_join(relations, num_columns, conjuncts, needed, settings, hints)
+}
+
+// Whether there's an UNNEST or a table function in `table_ref`.
+fn has_unnest(table_ref: &ast::TableRef) -> bool {
+    match table_ref {
+        ast::TableRef::Table { .. } => false,
+        ast::TableRef::Join { left, right, .. } => has_unnest(left) || has_unnest(right),
+        ast::TableRef::Unnest { .. } | ast::TableRef::Function { .. } => true,
+    }
+}
+
+// `#column op value` of `conjunct`, the column numbered from the start of `columns`, if it
+// compares a column of those to a literal or a parameter.
+fn constraint(conjunct: &Expr, columns: std::ops::Range<usize>) -> Option<Expr> {
+    let Expr::Binary { op, left, right } = conjunct else {
+        return None;
+    };
+    if !matches!(op, BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq) {
+        return None;
+    }
+    let constant = |expr: &Expr| matches!(expr, Expr::Literal(_) | Expr::Parameter { .. });
+    let (op, column, value) = match (&**left, &**right) {
+        (Expr::Column(column), value) if constant(value) => (*op, *column, value),
+        (value, Expr::Column(column)) if constant(value) => (flip(*op), *column, value),
+        _ => return None,
+    };
+    if !columns.contains(&column) {
+        return None;
+    }
+    Some(Expr::Binary { op, left: Box::new(Expr::Column(column - columns.start)), right: Box::new(value.remap(&|c| c)?) })
+}
+
+// `op` with its operands swapped.
+fn flip(op: BinaryOp) -> BinaryOp {
+    match op {
+        BinaryOp::Lt => BinaryOp::Gt,
+        BinaryOp::LtEq => BinaryOp::GtEq,
+        BinaryOp::Gt => BinaryOp::Lt,
+        BinaryOp::GtEq => BinaryOp::LtEq,
+        op => op,
+    }
+}
+
+// Joins `relations`, whose columns are `num_columns` in total, keeping the rows for which all
+// `conjuncts` hold. Also returns the columns of the output tuples, which include `needed`.
+fn plan_join(
+    relations: Vec<Source>,
+    num_columns: usize,
+    conjuncts: Vec<Expr>,
+    mut needed: Vec<usize>,
+    settings: PlannerSettings,
+    hints: Hints,
+) -> (Box<dyn PlanNode>, Vec<usize>) {
+    // subqueries are evaluated on the joined rows, any column of which they may read
+    let (residual, conjuncts): (Vec<_>, Vec<_>) = conjuncts.into_iter().partition(Expr::has_subquery);
+    if !residual.is_empty() {
+        needed = (0..num_columns).collect();
+    }
+    let (mut plan, mut layout): (Box<dyn PlanNode>, _) = if relations.is_empty() {
+        // SELECT without FROM works on a single empty row
+        let values = Box::new(Values { rows: vec![vec![]] });
+        match conjunction(conjuncts) {
+            Some(predicate) => (Box::new(Filter { child: values, predicate }), vec![]),
+            None => (values, vec![]),
+        }
+    } else {
+        optimize(Query {
+            relations,
+            conjuncts,
+            needed,
+            max_build_rows: DEFAULT_MAX_BUILD_ROWS,
+            settings,
+            hints,
+        })
+    };
+    if let Some(predicate) = conjunction(residual) {
+        plan = Box::new(Filter {
+            child: restore_layout(plan, &layout, num_columns),
+            predicate,
+        });
+        layout = (0..num_columns).collect();
+    }
+    (plan, layout)
+}
+
+// Removes duplicate rows: by comparing adjacent ones if `plan` is already sorted by all its
+// `num_columns` columns, after sorting if there are too many rows to hash, with a hash set otherwise.
+fn distinct(plan: Box<dyn PlanNode>, num_columns: usize) -> Box<dyn PlanNode> {
+    let ordering = plan.ordering();
+    if (0..num_columns).all(|c| ordering.contains(&c)) {
+        return Box::new(SortDistinct { child: plan });
+    }
+    if plan.estimate().rows > DEFAULT_MAX_BUILD_ROWS as f64 {
+        let sort = Sort {
+            child: plan,
+            keys: (0..num_columns).collect(),
+            descending: vec![false; num_columns],
+            max_rows_in_memory: DEFAULT_MAX_BUILD_ROWS,
+        };
+        return Box::new(SortDistinct { child: Box::new(sort) });
+    }
+    Box::new(HashDistinct { child: plan })
+}
+
+// `select` with the calls in its output and HAVING of registered aggregates, which the parser
+// can't tell from those of other functions, made aggregates.
+fn resolve_aggregates<'a>(select: &'a ast::Select, catalog: &Catalog) -> Cow<'a, ast::Select> {
+    if catalog.aggregates().next().is_none() {
+        return Cow::Borrowed(select);
+    }
+    let projection = select
+        .projection
+        .iter()
+        .map(|item| match item {
+            ast::SelectItem::Expr { expr, alias } => ast::SelectItem::Expr { expr: resolve_calls(expr, catalog), alias: alias.clone() },
+            item => item.clone(),
+        })
+        .collect();
+    let having = select.having.as_ref().map(|having| resolve_calls(having, catalog));
+    Cow::Owned(ast::Select { projection, having, ..select.clone() })
+}
+
+fn resolve_calls(expr: &ast::Expr, catalog: &Catalog) -> ast::Expr {
+    let resolve = |expr: &ast::Expr| Box::new(resolve_calls(expr, catalog));
+    let resolve_all = |exprs: &[ast::Expr]| exprs.iter().map(|e| resolve_calls(e, catalog)).collect();
+    match expr {
+        ast::Expr::Call { name, args } => match catalog.aggregate(name) {
+            Some(func) if args.len() == 1 => ast::Expr::Aggregate {
+                func: AggregateFunc::User(func.clone()),
+                arg: Some(resolve(&args[0])),
+                distinct: false,
+            },
+            _ => ast::Expr::Call { name: name.clone(), args: resolve_all(args) },
+        },
+        ast::Expr::Unary { op, expr } => ast::Expr::Unary { op: *op, expr: resolve(expr) },
+        ast::Expr::Binary { op, left, right } => ast::Expr::Binary { op: *op, left: resolve(left), right: resolve(right) },
+        ast::Expr::IsNull { expr, negated } => ast::Expr::IsNull { expr: resolve(expr), negated: *negated },
+        ast::Expr::InList { expr, list, negated } => ast::Expr::InList { expr: resolve(expr), list: resolve_all(list), negated: *negated },
+        ast::Expr::InSubquery { expr, subquery, negated } => ast::Expr::InSubquery { expr: resolve(expr), subquery: subquery.clone(), negated: *negated },
+        ast::Expr::Like { expr, pattern, negated, case_insensitive } => ast::Expr::Like {
+            expr: resolve(expr),
+            pattern: resolve(pattern),
+            negated: *negated,
+            case_insensitive: *case_insensitive,
+        },
+        ast::Expr::Function { func, args } => ast::Expr::Function { func: *func, args: resolve_all(args) },
+        ast::Expr::Quantified { op, expr, array, all } => ast::Expr::Quantified { op: *op, expr: resolve(expr), array: resolve(array), all: *all },
+        ast::Expr::Cast { expr, data_type } => ast::Expr::Cast { expr: resolve(expr), data_type: *data_type },
+        ast::Expr::Window { func, args, partition_by, order_by, frame } => ast::Expr::Window {
+            func: func.clone(),
+            args: resolve_all(args),
+            partition_by: resolve_all(partition_by),
+            order_by: order_by.iter().map(|o| ast::OrderBy { expr: resolve_calls(&o.expr, catalog), descending: o.descending }).collect(),
+            frame: *frame,
+        },
+        // the arguments of aggregates can't be aggregates, and subqueries are planned of their own
+        ast::Expr::Literal(_)
+        | ast::Expr::Parameter(_)
+        | ast::Expr::Column { .. }
+        | ast::Expr::Exists { .. }
+        | ast::Expr::Subquery(_)
+        | ast::Expr::Aggregate { .. } => expr.clone(),
+    }
+}
+
+// Whether `select` computes aggregates over groups of rows rather than an output row per row.
+fn is_grouped(select: &ast::Select) -> bool {
+    let mut aggregates = vec![];
+    for item in &select.projection {
+        if let ast::SelectItem::Expr { expr, .. } = item {
+            collect_aggregates(expr, &mut aggregates);
+        }
+    }
+    !select.group_by.is_empty() || select.having.is_some() || !aggregates.is_empty()
+}
+
+// Adds the aggregates in `expr` which aren't in `aggregates` yet, except those of subqueries.
+fn collect_aggregates(expr: &ast::Expr, aggregates: &mut Vec<ast::Expr>) {
+    match expr {
+        ast::Expr::Aggregate { .. } => {
+            if !aggregates.contains(expr) {
+                aggregates.push(expr.clone());
+            }
+        }
+        ast::Expr::Unary { expr, .. }
+        | ast::Expr::IsNull { expr, .. }
+        | ast::Expr::InSubquery { expr, .. }
+        | ast::Expr::Cast { expr, .. } => collect_aggregates(expr, aggregates),
+        ast::Expr::Binary { left, right, .. } | ast::Expr::Quantified { expr: left, array: right, .. } => {
+            collect_aggregates(left, aggregates);
+            collect_aggregates(right, aggregates);
+        }
+        ast::Expr::InList { expr, list, .. } => {
+            collect_aggregates(expr, aggregates);
+            list.iter().for_each(|e| collect_aggregates(e, aggregates));
+        }
+        ast::Expr::Like { expr, pattern, .. } => {
+            collect_aggregates(expr, aggregates);
+            collect_aggregates(pattern, aggregates);
+        }
+        ast::Expr::Function { args, .. } | ast::Expr::Call { args, .. } => args.iter().for_each(|e| collect_aggregates(e, aggregates)),
+        // a window function computes an aggregate over a window, but its arguments may be
+        // aggregates over groups
+        ast::Expr::Window { args, partition_by, order_by, .. } => {
+            let exprs = args.iter().chain(partition_by).chain(order_by.iter().map(|o| &o.expr));
+            exprs.for_each(|e| collect_aggregates(e, aggregates));
+        }
+        ast::Expr::Literal(_)
+        | ast::Expr::Parameter(_)
+        | ast::Expr::Column { .. }
+        | ast::Expr::Exists { .. }
+        | ast::Expr::Subquery(_) => {}
+    }
+}
+
+// Replaces the GROUP BY expressions and the aggregates in `expr` by references to the columns of
+// the aggregated rows, `group#i` and `aggregate#i`. Other columns of `scope` can't be referred to.
+// Calls of functions the catalog doesn't have fail as such, whatever their arguments refer to.
+fn rewrite_grouped(
+    expr: &ast::Expr,
+    scope: &Scope,
+    group_by: &[ast::Expr],
+    aggregates: &[ast::Expr],
+    catalog: &Catalog,
+) -> Result<ast::Expr, Error> {
+    let column = |name: String| ast::Expr::Column { table: None, name };
+    let same = |a: &ast::Expr, b: &ast::Expr| match (a, b) {
+        (ast::Expr::Column { table: t1, name: n1 }, ast::Expr::Column { table: t2, name: n2 }) => {
+            let i = scope.resolve(t1.as_deref(), n1).ok().flatten();
+            i.is_some() && i == scope.resolve(t2.as_deref(), n2).ok().flatten()
+        }
+        (a, b) => a == b,
+    };
+    if let Some(i) = group_by.iter().position(|g| same(g, expr)) {
+        return Ok(column(format!("group#{}", i)));
+    }
+    let rewrite = |expr: &ast::Expr| rewrite_grouped(expr, scope, group_by, aggregates, catalog).map(Box::new);
+    Ok(match expr {
+        ast::Expr::Aggregate { .. } => {
+            let i = aggregates.iter().position(|a| a == expr).unwrap();
+            column(format!("aggregate#{}", i))
+        }
+        ast::Expr::Column { table, name } => {
+            if scope.resolve(table.as_deref(), name)?.is_some() {
+                return Err(Error::Invalid(format!(
+                    "column \"{}\" must appear in the GROUP BY clause or be used in an aggregate function",
+                    name
+                )));
+            }
+            expr.clone()
+        }
+        ast::Expr::Unary { op, expr } => ast::Expr::Unary {
+            op: *op,
+            expr: rewrite(expr)?,
+        },
+        ast::Expr::Binary { op, left, right } => ast::Expr::Binary {
+            op: *op,
+            left: rewrite(left)?,
+            right: rewrite(right)?,
+        },
+        ast::Expr::Quantified { op, expr, array, all } => ast::Expr::Quantified {
+            op: *op,
+            expr: rewrite(expr)?,
+            array: rewrite(array)?,
+            all: *all,
+        },
+        ast::Expr::IsNull { expr, negated } => ast::Expr::IsNull {
+            expr: rewrite(expr)?,
+            negated: *negated,
+        },
+        ast::Expr::InList { expr, list, negated } => ast::Expr::InList {
+            expr: rewrite(expr)?,
+            list: list.iter().map(|e| rewrite(e).map(|e| *e)).collect::<Result<_, _>>()?,
+            negated: *negated,
+        },
+        ast::Expr::InSubquery { expr, subquery, negated } => ast::Expr::InSubquery {
+            expr: rewrite(expr)?,
+            subquery: subquery.clone(),
+            negated: *negated,
+        },
+        ast::Expr::Like {
+            expr,
+            pattern,
+            negated,
+            case_insensitive,
+        } => ast::Expr::Like {
+            expr: rewrite(expr)?,
+            pattern: rewrite(pattern)?,
+            negated: *negated,
+            case_insensitive: *case_insensitive,
+        },
+        ast::Expr::Function { func, args } => ast::Expr::Function {
+            func: *func,
+            args: args.iter().map(|e| rewrite(e).map(|e| *e)).collect::<Result<_, _>>()?,
+        },
+        ast::Expr::Call { name, .. } if catalog.function(name).is_none() && catalog.aggregate(name).is_none() => {
+            return Err(Error::Invalid(format!("unknown function: {}", name)));
+        }
+        ast::Expr::Call { name, args } => ast::Expr::Call {
+            name: name.clone(),
+            args: args.iter().map(|e| rewrite(e).map(|e| *e)).collect::<Result<_, _>>()?,
+        },
+        ast::Expr::Cast { expr, data_type } => ast::Expr::Cast {
+            expr: rewrite(expr)?,
+            data_type: *data_type,
+        },
+        ast::Expr::Window { func, args, partition_by, order_by, frame } => ast::Expr::Window {
+            func: func.clone(),
+            args: args.iter().map(|e| rewrite(e).map(|e| *e)).collect::<Result<_, _>>()?,
+            partition_by: partition_by.iter().map(|e| rewrite(e).map(|e| *e)).collect::<Result<_, _>>()?,
+            order_by: order_by
+                .iter()
+                .map(|o| {
+                    let expr = *rewrite(&o.expr)?;
+                    Ok(ast::OrderBy { expr, descending: o.descending })
+                })
+                .collect::<Result<_, Error>>()?,
+            frame: *frame,
+        },
+        ast::Expr::Literal(_) | ast::Expr::Parameter(_) | ast::Expr::Exists { .. } | ast::Expr::Subquery(_) => expr.clone(),
+    })
+}
+
+// Adds the window functions in `expr` which aren't in `windows` yet, except those of subqueries.
+fn collect_windows(expr: &ast::Expr, windows: &mut Vec<ast::Expr>) {
+    match expr {
+        ast::Expr::Window { .. } => {
+            if !windows.contains(expr) {
+                windows.push(expr.clone());
+            }
+        }
+        ast::Expr::Unary { expr, .. }
+        | ast::Expr::IsNull { expr, .. }
+        | ast::Expr::InSubquery { expr, .. }
+        | ast::Expr::Cast { expr, .. } => collect_windows(expr, windows),
+        ast::Expr::Binary { left, right, .. } | ast::Expr::Quantified { expr: left, array: right, .. } => {
+            collect_windows(left, windows);
+            collect_windows(right, windows);
+        }
+        ast::Expr::InList { expr, list, .. } => {
+            collect_windows(expr, windows);
+            list.iter().for_each(|e| collect_windows(e, windows));
+        }
+        ast::Expr::Like { expr, pattern, .. } => {
+            collect_windows(expr, windows);
+            collect_windows(pattern, windows);
+        }
+        ast::Expr::Function { args, .. } | ast::Expr::Call { args, .. } => args.iter().for_each(|e| collect_windows(e, windows)),
+        ast::Expr::Aggregate { arg, .. } => arg.iter().for_each(|e| collect_windows(e, windows)),
+        ast::Expr::Literal(_)
+        | ast::Expr::Parameter(_)
+        | ast::Expr::Column { .. }
+        | ast::Expr::Exists { .. }
+        | ast::Expr::Subquery(_) => {}
+    }
+}
+
+// PARTITION BY and ORDER BY of a window function.
+fn window_keys(window: &ast::Expr) -> (&[ast::Expr], &[ast::OrderBy]) {
+    match window {
+        ast::Expr::Window { partition_by, order_by, .. } => (partition_by, order_by),
+        _ => unreachable!(),
+    }
+}
+
+// Replaces the window functions in `expr` by references to their values, `window#i`.
+fn rewrite_windows(expr: &ast::Expr, windows: &[ast::Expr]) -> ast::Expr {
+    let rewrite = |expr: &ast::Expr| Box::new(rewrite_windows(expr, windows));
+    match expr {
+        ast::Expr::Window { .. } => {
+            let i = windows.iter().position(|w| w == expr).unwrap();
+            ast::Expr::Column {
+                table: None,
+                name: format!("window#{}", i),
+            }
+        }
+        ast::Expr::Unary { op, expr } => ast::Expr::Unary {
+            op: *op,
+            expr: rewrite(expr),
+        },
+        ast::Expr::Binary { op, left, right } => ast::Expr::Binary {
+            op: *op,
+            left: rewrite(left),
+            right: rewrite(right),
+        },
+        ast::Expr::Quantified { op, expr, array, all } => ast::Expr::Quantified {
+            op: *op,
+            expr: rewrite(expr),
+            array: rewrite(array),
+            all: *all,
+        },
+        ast::Expr::IsNull { expr, negated } => ast::Expr::IsNull {
+            expr: rewrite(expr),
+            negated: *negated,
+        },
+        ast::Expr::InList { expr, list, negated } => ast::Expr::InList {
+            expr: rewrite(expr),
+            list: list.iter().map(|e| rewrite_windows(e, windows)).collect(),
+            negated: *negated,
+        },
+        ast::Expr::InSubquery { expr, subquery, negated } => ast::Expr::InSubquery {
+            expr: rewrite(expr),
+            subquery: subquery.clone(),
+            negated: *negated,
+        },
+        ast::Expr::Like {
+            expr,
+            pattern,
+            negated,
+            case_insensitive,
+        } => ast::Expr::Like {
+            expr: rewrite(expr),
+            pattern: rewrite(pattern),
+            negated: *negated,
+            case_insensitive: *case_insensitive,
+        },
+        ast::Expr::Function { func, args } => ast::Expr::Function {
+            func: *func,
+            args: args.iter().map(|e| rewrite_windows(e, windows)).collect(),
+        },
+        ast::Expr::Call { name, args } => ast::Expr::Call {
+            name: name.clone(),
+            args: args.iter().map(|e| rewrite_windows(e, windows)).collect(),
+        },
+        ast::Expr::Cast { expr, data_type } => ast::Expr::Cast {
+            expr: rewrite(expr),
+            data_type: *data_type,
+        },
+        _ => expr.clone(),
+    }
+}
+
+fn split_conjuncts(expr: &ast::Expr) -> Vec<&ast::Expr> {
+    match expr {
+        ast::Expr::Binary {
+            op: BinaryOp::And,
+            left,
+            right,
+        } => {
+            let mut conjuncts = split_conjuncts(left);
+            conjuncts.extend(split_conjuncts(right));
+            conjuncts
+        }
+        expr => vec![expr],
+    }
+}
+
+// If `expr` is `a = b` where one side is a column of `left` and the other one is a column of
+// `right`, returns their indices in (left, right) order. Names are looked up in `right` first,
+// which is the inner scope where both could apply.
+fn equality_between(expr: &ast::Expr, left: &Scope, right: &Scope) -> Result<Option<(usize, usize)>, Error> {
+    let (a, b) = match expr {
+        ast::Expr::Binary {
+            op: BinaryOp::Eq,
+            left: a,
+            right: b,
+        } => (a, b),
+        _ => return Ok(None),
+    };
+    let side = |expr: &ast::Expr| -> Result<(Option<usize>, Option<usize>), Error> {
+        match expr {
+            ast::Expr::Column { table, name } => {
+                let in_right = right.resolve(table.as_deref(), name)?;
+                if in_right.is_some() {
+                    return Ok((None, in_right));
+                }
+                Ok((left.resolve(table.as_deref(), name)?, None))
+            }
+            _ => Ok((None, None)),
+        }
+    };
+    match (side(a)?, side(b)?) {
+        ((Some(l), None), (None, Some(r))) | ((None, Some(r)), (Some(l), None)) => Ok(Some((l, r))),
+        _ => Ok(None),
+    }
+}
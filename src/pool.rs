@@ -0,0 +1,171 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// Threads kept for the parallel operators of the database (see
+// `BufferPoolManager::set_parallel_workers`), which hand them their work a batch at a time
+// rather than spawning threads for each. There are as many as the most workers any transaction
+// has asked for, waiting for work in between, until the pool is dropped.
+pub struct ThreadPool {
+    jobs: Option<Sender<Job>>,
+    receiver: Arc<Mutex<Receiver<Job>>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Default for ThreadPool {
+    fn default() -> Self {
+        let (jobs, receiver) = mpsc::channel();
+        Self { jobs: Some(jobs), receiver: Arc::new(Mutex::new(receiver)), threads: vec![] }
+    }
+}
+
+impl ThreadPool {
+    pub fn size(&self) -> usize {
+        self.threads.len()
+    }
+
+    // Starts threads for there to be at least `size`.
+    pub fn grow(&mut self, size: usize) {
+        while self.threads.len() < size {
+            let receiver = self.receiver.clone();
+            self.threads.push(thread::spawn(move || loop {
+                // the lock released before the job runs, for the others to take the next
+                let job = receiver.lock().unwrap().recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => break,
+                }
+            }));
+        }
+    }
+
+    // `f` of each of `items`, in order, the items run by the threads of the pool at once, or by
+    // this one if it has none. Returns once they're all done, panicking if one of them panicked.
+    // Whatever happens here, the items running included, it doesn't return nor unwind before
+    // every job handed out has run, as they borrow from the caller.
+    pub fn map<T: Send, R: Send>(&self, items: impl IntoIterator<Item = T>, f: impl Fn(T) -> R + Sync) -> Vec<R> {
+        if self.threads.is_empty() {
+            return items.into_iter().map(f).collect();
+        }
+        let (sender, results) = mpsc::channel();
+        let mut pending = Pending { sender: Some(sender), results, count: 0 };
+        let f = &f;
+        for (i, item) in items.into_iter().enumerate() {
+            let sender = pending.sender.as_ref().unwrap().clone();
+            let job: Box<dyn FnOnce() + Send + '_> = Box::new(move || {
+                let _ = sender.send((i, panic::catch_unwind(AssertUnwindSafe(|| f(item)))));
+            });
+            // SAFETY: the job borrows only `f` and what the items do, which outlive this call, as
+            // `pending` waits for every job handed out to have run before this call returns or
+            // unwinds, even if one of them panicked, or the items did
+            let job = unsafe { std::mem::transmute::<Box<dyn FnOnce() + Send + '_>, Job>(job) };
+            self.jobs.as_ref().unwrap().send(job).unwrap();
+            pending.count += 1;
+        }
+        let count = pending.count;
+        let mut outputs: Vec<Option<R>> = (0..count).map(|_| None).collect();
+        let mut panicked = None;
+        for (i, result) in pending {
+            match result {
+                Ok(output) => outputs[i] = Some(output),
+                Err(payload) => panicked = Some(payload),
+            }
+        }
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+        outputs.into_iter().map(Option::unwrap).collect()
+    }
+}
+
+// The results of the jobs `ThreadPool::map` has handed out, as many as `count`, each job sending
+// its own once it's run. Dropped, by a panic say, it waits for those not received yet.
+struct Pending<R> {
+    // cloned for each job, None once they've all been handed out
+    sender: Option<Sender<(usize, thread::Result<R>)>>,
+    results: Receiver<(usize, thread::Result<R>)>,
+    count: usize,
+}
+
+impl<R> Iterator for Pending<R> {
+    type Item = (usize, thread::Result<R>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // a job dropped without running drops its sender, for this not to wait forever
+        self.sender = None;
+        if self.count == 0 {
+            return None;
+        }
+        let result = self.results.recv().ok()?;
+        self.count -= 1;
+        Some(result)
+    }
+}
+
+impl<R> Drop for Pending<R> {
+    fn drop(&mut self) {
+        for _ in self.by_ref() {}
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // the threads stop once there's no more work to wait for
+        self.jobs = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test() {
+        let mut pool = ThreadPool::default();
+        assert_eq!(vec![2, 4, 6], pool.map([1, 2, 3], |n| n * 2));
+        pool.grow(3);
+        pool.grow(2);
+        assert_eq!(3, pool.size());
+
+        // the items borrowed, in order whichever thread runs them
+        let words: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+        let lens = pool.map(words.chunks(7), |chunk| chunk.iter().map(String::len).sum::<usize>());
+        assert_eq!(words.iter().map(String::len).sum::<usize>(), lens.iter().sum());
+        assert_eq!(15, lens.len());
+        let threads = pool.map(0..30, |_| {
+            thread::sleep(std::time::Duration::from_millis(1));
+            thread::current().id()
+        });
+        assert!(threads.iter().all(|id| *id != thread::current().id()));
+        assert!(threads.iter().collect::<HashSet<_>>().len() <= 3);
+
+        // a panic is raised here once the others are done, the threads staying for the next
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| pool.map(0..4, |n| assert_ne!(2, n))));
+        assert!(panicked.is_err());
+        assert_eq!(vec![1; 4], pool.map(0..4, |_| 1));
+        assert_eq!(3, pool.size());
+
+        // as it is if the items panic, once the jobs handed out before are done with what they
+        // borrow
+        let done: Vec<AtomicBool> = (0..10).map(|_| AtomicBool::new(false)).collect();
+        let items = (0..10).inspect(|&n| assert_ne!(5, n));
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.map(items, |n| {
+                thread::sleep(std::time::Duration::from_millis(5));
+                done[n].store(true, Ordering::SeqCst);
+            })
+        }));
+        assert!(panicked.is_err());
+        assert!(done[..5].iter().all(|done| done.load(Ordering::SeqCst)));
+        assert!(done[5..].iter().all(|done| !done.load(Ordering::SeqCst)));
+        assert_eq!(vec![1; 4], pool.map(0..4, |_| 1));
+    }
+}
@@ -0,0 +1,348 @@
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use crate::buffer::{self, BufferPoolManager};
+use crate::catalog::{self, VirtualTable};
+use crate::table;
+use crate::tuple::{self, Tuple};
+use crate::udf::TableFunction;
+
+mod aggregate;
+mod cte;
+mod distinct;
+mod estimated;
+pub mod explain;
+pub mod expr;
+mod filter;
+mod gather;
+mod hash_join;
+mod index_join;
+mod instrument;
+mod merge_join;
+mod nested_loop_join;
+mod project;
+mod scan;
+mod semi_join;
+mod set_op;
+mod sort;
+mod spill;
+mod unnest;
+mod window;
+
+pub use aggregate::{Aggregate, AggregateFunc, HashAggregate};
+pub use cte::{CteScan, CteStorage, MaterializeCtes, MaterializedCte, RecursiveUnion};
+pub use distinct::{HashDistinct, SortDistinct};
+pub use estimated::Estimated;
+pub use filter::Filter;
+pub use gather::Gather;
+pub use hash_join::HashJoin;
+pub use index_join::IndexNestedLoopJoin;
+pub use instrument::{instrument, reset_stats, Instrumented, NodeStats};
+#[cfg(feature = "tracing")]
+pub use instrument::trace;
+pub use merge_join::MergeJoin;
+pub use nested_loop_join::NestedLoopJoin;
+pub use project::Project;
+pub use scan::{ColumnarScan, FullTextScan, IndexScan, KeyRange, SeqScan, SpatialScan, ZoneMapScan};
+pub use semi_join::HashSemiJoin;
+pub use set_op::{Append, HashSetOp, SetOperator};
+pub use sort::Sort;
+pub use unnest::Unnest;
+pub use window::{Frame, FrameBound, FrameUnits, Window, WindowFunc, WindowFunction};
+
+// Partition count used when a hash join built by `equi_join` spills.
+pub const DEFAULT_NUM_PARTITIONS: usize = 8;
+
+// Number of rows assumed for a table when estimating plans.
+pub const DEFAULT_TABLE_ROWS: f64 = 1000.0;
+
+// Estimated rows of a table from which it's scanned in parallel, by a Gather.
+pub const PARALLEL_SCAN_MIN_ROWS: f64 = 10000.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error(transparent)]
+    Tuple(#[from] tuple::Error),
+    #[error(transparent)]
+    Table(#[from] table::Error),
+    #[error(transparent)]
+    Catalog(#[from] catalog::Error),
+    #[error("tuple does not fit in a page")]
+    TupleTooLarge,
+    #[error("type mismatch: {0}")]
+    TypeMismatch(String),
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("numeric overflow")]
+    NumericOverflow,
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("more than one row returned by a subquery used as an expression")]
+    SubqueryReturnedMultipleRows,
+    #[error("function {0} failed: {1}")]
+    Function(String, String),
+}
+
+// Pull-based (volcano style) iterator over the output of a plan node.
+pub trait Executor {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error>;
+}
+
+pub type BoxExecutor<'a> = Box<dyn Executor + 'a>;
+
+// Estimated output size of a plan node and the cost of producing it, in tuples processed by the
+// node and its inputs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub rows: f64,
+    pub cost: f64,
+}
+
+pub trait PlanNode {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error>;
+
+    // Columns the output is known to be sorted by (ascending), most significant first.
+    fn ordering(&self) -> Vec<usize> {
+        vec![]
+    }
+
+    // This node if it is a plain table scan.
+    fn as_seq_scan(&self) -> Option<&SeqScan> {
+        None
+    }
+
+    // This node if it scans a virtual table, for conjuncts to be pushed down to.
+    fn as_virtual_scan_mut(&mut self) -> Option<&mut VirtualScan> {
+        None
+    }
+
+    // Name of the operator and its arguments, as shown by EXPLAIN.
+    fn describe(&self) -> String;
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![]
+    }
+
+    // The same inputs as `children`, to be replaced e.g. by `instrument`.
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![]
+    }
+
+    fn estimate(&self) -> Estimate;
+
+    // Runtime statistics, collected if this node is instrumented.
+    fn stats(&self) -> Option<&Cell<NodeStats>> {
+        None
+    }
+}
+
+// Builds an inner equi-join, choosing the algorithm from the inputs:
+// 1. Inputs already ordered by their join keys are merge joined, which needs neither a hash table nor a sort.
+// 2. If the right input is a table scan and the table has an index on the join keys, the index is probed
+//    for each left row instead of reading the whole table.
+// 3. Otherwise the right input is hashed.
+pub fn equi_join(
+    left: Box<dyn PlanNode>,
+    right: Box<dyn PlanNode>,
+    left_keys: Vec<usize>,
+    right_keys: Vec<usize>,
+    max_build_rows: usize,
+) -> Box<dyn PlanNode> {
+    if left.ordering().starts_with(&left_keys) && right.ordering().starts_with(&right_keys) {
+        return Box::new(MergeJoin { left, right, left_keys, right_keys });
+    }
+    if let Some(scan) = right.as_seq_scan() {
+        if let Some((access, order)) = scan.table.access_path(&right_keys) {
+            return Box::new(IndexNestedLoopJoin {
+                outer: left,
+                table: scan.table.clone(),
+                name: scan.name.clone(),
+                table_rows: scan.rows,
+                access,
+                outer_keys: order.iter().map(|&i| left_keys[i]).collect(),
+                max_build_rows,
+                switched: Default::default(),
+            });
+        }
+    }
+    Box::new(HashJoin {
+        left,
+        right,
+        left_keys,
+        right_keys,
+        max_build_rows,
+        num_partitions: DEFAULT_NUM_PARTITIONS,
+    })
+}
+
+// Emits a fixed list of tuples.
+pub struct Values {
+    pub rows: Vec<Tuple>,
+}
+
+impl PlanNode for Values {
+    fn start(&self, _bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        Ok(Box::new(ExecValues { rows: self.rows.iter() }))
+    }
+
+    fn describe(&self) -> String {
+        format!("Values ({} rows)", self.rows.len())
+    }
+
+    fn estimate(&self) -> Estimate {
+        let rows = self.rows.len() as f64;
+        Estimate { rows, cost: rows }
+    }
+}
+
+struct ExecValues<'a> {
+    rows: std::slice::Iter<'a, Tuple>,
+}
+
+impl<'a> Executor for ExecValues<'a> {
+    fn next(&mut self, _bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        Ok(self.rows.next().cloned())
+    }
+}
+
+// Rows assumed of a virtual table when estimating a scan of it.
+const ASSUMED_VIRTUAL_ROWS: f64 = 100.0;
+
+// Emits the rows of a virtual table, made when it's started.
+pub struct VirtualScan {
+    pub name: String,
+    pub table: Rc<dyn VirtualTable>,
+    // conjuncts `#column op value` of the query to pass on to the table (see `catalog::Constraint`),
+    // of values known when it starts, literals or parameters
+    pub constraints: Vec<expr::Expr>,
+}
+
+impl PlanNode for VirtualScan {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let mut constraints = vec![];
+        for constraint in &self.constraints {
+            if let expr::Expr::Binary { op, left, right } = constraint {
+                if let expr::Expr::Column(column) = **left {
+                    constraints.push(catalog::Constraint { column, op: *op, value: right.eval(&[], bufmgr)? });
+                }
+            }
+        }
+        Ok(Box::new(ExecRows { rows: self.table.scan(bufmgr, &constraints)?.into_iter() }))
+    }
+
+    fn as_virtual_scan_mut(&mut self) -> Option<&mut VirtualScan> {
+        Some(self)
+    }
+
+    fn describe(&self) -> String {
+        match self.constraints.is_empty() {
+            true => format!("Virtual Scan on {}", self.name),
+            false => {
+                let constraints: Vec<_> = self.constraints.iter().map(|c| c.to_string()).collect();
+                format!("Virtual Scan on {} (constraints: {})", self.name, constraints.join(" AND "))
+            }
+        }
+    }
+
+    fn estimate(&self) -> Estimate {
+        Estimate { rows: ASSUMED_VIRTUAL_ROWS, cost: ASSUMED_VIRTUAL_ROWS }
+    }
+}
+
+// Rows of a table function, called with `args` when it starts.
+pub struct FunctionScan {
+    pub func: Rc<TableFunction>,
+    pub args: Vec<expr::Expr>,
+}
+
+impl PlanNode for FunctionScan {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let func = &self.func;
+        let mut values = vec![];
+        for (arg, &data_type) in self.args.iter().zip(&func.args) {
+            values.push(expr::cast(arg.eval(&[], bufmgr)?, data_type)?);
+        }
+        let rows = (func.func)(&values).map_err(|message| Error::Function(func.name.clone(), message))?;
+        for row in &rows {
+            if row.len() != func.columns.len() || !row.iter().zip(&func.columns).all(|(value, column)| column.data_type.accepts(value)) {
+                return Err(Error::TypeMismatch(format!("function {} returned {:?}, not a row of its columns", func.name, row)));
+            }
+        }
+        Ok(Box::new(ExecRows { rows: rows.into_iter() }))
+    }
+
+    fn describe(&self) -> String {
+        let args: Vec<_> = self.args.iter().map(|a| a.to_string()).collect();
+        format!("Function Scan on {}({})", self.func.name, args.join(", "))
+    }
+
+    fn estimate(&self) -> Estimate {
+        Estimate { rows: ASSUMED_VIRTUAL_ROWS, cost: ASSUMED_VIRTUAL_ROWS }
+    }
+}
+
+struct ExecRows {
+    rows: std::vec::IntoIter<Tuple>,
+}
+
+impl Executor for ExecRows {
+    fn next(&mut self, _bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        Ok(self.rows.next())
+    }
+}
+
+// Rows an operator goes through between checks of whether its statement has been interrupted,
+// which reading a page only catches if it reads any.
+const INTERRUPT_CHECK_ROWS: u32 = 1024;
+
+// Counts the rows an operator goes through, failing every INTERRUPT_CHECK_ROWS of them if its
+// statement has timed out or been canceled (see `BufferPoolManager::check_interrupted`).
+#[derive(Default)]
+struct Interrupts(u32);
+
+impl Interrupts {
+    fn check(&mut self, bufmgr: &BufferPoolManager) -> Result<(), Error> {
+        self.0 += 1;
+        if self.0 == INTERRUPT_CHECK_ROWS {
+            self.0 = 0;
+            bufmgr.check_interrupted()?;
+        }
+        Ok(())
+    }
+}
+
+// Output size of an equi-join, assuming each row of the larger input matches one row of the
+// smaller one (e.g. a foreign key join).
+fn equi_join_rows(left: Estimate, right: Estimate) -> f64 {
+    left.rows.max(right.rows)
+}
+
+fn has_null(key: &[tuple::Value]) -> bool {
+    key.iter().any(tuple::Value::is_null)
+}
+
+// Which of `num_partitions` the rows with `key` go to when hash partitioned.
+fn partition_of(key: &[tuple::Value], num_partitions: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    (hasher.finish() % num_partitions as u64) as usize
+}
+
+// About the bytes `tuple` takes up in memory, which operators holding on to tuples reserve (see
+// `BufferPoolManager::reserve_memory`).
+pub(crate) fn memory_of(tuple: &[tuple::Value]) -> usize {
+    let text = |value: &tuple::Value| match value {
+        tuple::Value::Text(text) => text.len(),
+        _ => 0,
+    };
+    std::mem::size_of::<Tuple>() + tuple.iter().map(|value| std::mem::size_of::<tuple::Value>() + text(value)).sum::<usize>()
+}
+
+// Picks the values at `indices` out of `tuple`.
+fn project(tuple: &[tuple::Value], indices: &[usize]) -> Tuple {
+    indices.iter().map(|&i| tuple[i].clone()).collect()
+}
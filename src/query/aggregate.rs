@@ -0,0 +1,384 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use super::expr::{cast, eval_binary, type_name, BinaryOp, Expr};
+use super::{memory_of, partition_of, BoxExecutor, Error, Estimate, Executor, Interrupts, PlanNode};
+use crate::buffer::{BufferPoolManager, MemoryReservation};
+use crate::tuple::{Tuple, Value};
+use crate::udf::AggregateFunction;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AggregateFunc {
+    Count,
+    Sum,
+    Min,
+    Max,
+    // registered (see `udf`)
+    User(Arc<AggregateFunction>),
+}
+
+impl AggregateFunc {
+    pub fn name(&self) -> String {
+        match self {
+            AggregateFunc::User(func) => func.name.clone(),
+            func => format!("{:?}", func).to_lowercase(),
+        }
+    }
+}
+
+// Aggregate function applied to `arg` (to the rows themselves if None, as in `count(*)`).
+// NULL arguments are ignored, and so are duplicate ones if `distinct`.
+pub struct Aggregate {
+    pub func: AggregateFunc,
+    pub arg: Option<Expr>,
+    pub distinct: bool,
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let func = self.func.name();
+        let distinct = if self.distinct { "DISTINCT " } else { "" };
+        match &self.arg {
+            Some(arg) => write!(f, "{}({}{})", func, distinct, arg),
+            None => write!(f, "{}(*)", func),
+        }
+    }
+}
+
+// Rows whose group values and arguments are evaluated at a time before they're aggregated in parallel.
+const PARALLEL_BATCH_ROWS: usize = 4096;
+
+// Groups the input tuples by the values of `group_by` in an in-memory hash table and computes
+// `aggregates` for each group. Output tuples are the group values followed by the aggregates,
+// in the order the groups first appear. Without `group_by`, all tuples form a single group
+// which is emitted even if the input is empty. Fails if the groups don't fit in the memory budget
+// of the statement.
+//
+// With parallel workers (see `BufferPoolManager::set_parallel_workers`), the groups are hash
+// partitioned among them, each aggregating the rows of its groups into a hash table of its own,
+// on one of the threads kept for them (see `BufferPoolManager::thread_pool`).
+// The group values and arguments are evaluated in this thread, being expressions which may read
+// the database, a batch of rows at a time, and the groups of all the workers merged in the end.
+pub struct HashAggregate {
+    pub child: Box<dyn PlanNode>,
+    pub group_by: Vec<Expr>,
+    pub aggregates: Vec<Aggregate>,
+}
+
+impl PlanNode for HashAggregate {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let workers = bufmgr.parallel_workers();
+        if workers > 1 && !self.group_by.is_empty() {
+            return self.start_parallel(bufmgr, workers);
+        }
+        let mut child = self.child.start(bufmgr)?;
+        let mut groups: Vec<(Tuple, Vec<Accumulator>)> = vec![];
+        let mut index = HashMap::new();
+        let mut memory = MemoryReservation::default();
+        if self.group_by.is_empty() {
+            groups.push((vec![], self.accumulators()));
+        }
+        let mut interrupts = Interrupts::default();
+        while let Some(tuple) = child.next(bufmgr)? {
+            interrupts.check(bufmgr)?;
+            let mut key = vec![];
+            for expr in &self.group_by {
+                key.push(expr.eval(&tuple, bufmgr)?);
+            }
+            let group = match index.get(&key) {
+                Some(&group) => group,
+                None if self.group_by.is_empty() => 0,
+                None => {
+                    bufmgr.reserve_memory(&mut memory, self.group_memory(&key))?;
+                    index.insert(key.clone(), groups.len());
+                    groups.push((key, self.accumulators()));
+                    groups.len() - 1
+                }
+            };
+            for (aggregate, accumulator) in self.aggregates.iter().zip(&mut groups[group].1) {
+                let value = match &aggregate.arg {
+                    Some(arg) => arg.eval(&tuple, bufmgr)?,
+                    None => Value::Bool(true),
+                };
+                accumulator.add(&aggregate.func, value)?;
+            }
+        }
+        let rows = self.results(groups.into_iter())?;
+        Ok(Box::new(ExecHashAggregate { rows: rows.into_iter(), memory }))
+    }
+
+    fn describe(&self) -> String {
+        let group_by: Vec<_> = self.group_by.iter().map(|e| e.to_string()).collect();
+        let aggregates: Vec<_> = self.aggregates.iter().map(|a| a.to_string()).collect();
+        format!("Hash Aggregate (group by: [{}], aggregates: [{}])", group_by.join(", "), aggregates.join(", "))
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.child.as_ref()]
+    }
+
+    fn estimate(&self) -> Estimate {
+        let child = self.child.estimate();
+        let rows = if self.group_by.is_empty() { 1.0 } else { (child.rows * 0.1).max(child.rows.min(1.0)) };
+        Estimate {
+            rows,
+            cost: child.cost + child.rows,
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![&mut self.child]
+    }
+}
+
+impl HashAggregate {
+    fn accumulators(&self) -> Vec<Accumulator> {
+        self.aggregates.iter().map(Accumulator::new).collect()
+    }
+
+    // The rows of the groups, their values followed by the results of their aggregates.
+    fn results(&self, groups: impl Iterator<Item = (Tuple, Vec<Accumulator>)>) -> Result<Vec<Tuple>, Error> {
+        let mut rows = vec![];
+        for (mut key, accumulators) in groups {
+            for (aggregate, accumulator) in self.aggregates.iter().zip(accumulators) {
+                key.push(accumulator.result(aggregate)?);
+            }
+            rows.push(key);
+        }
+        Ok(rows)
+    }
+
+    // Memory a group with `key` takes up, its key being in the index too, leaving out the values
+    // of DISTINCT aggregates.
+    fn group_memory(&self, key: &[Value]) -> usize {
+        memory_of(key) * 2 + self.aggregates.len() * std::mem::size_of::<Accumulator>()
+    }
+
+    fn start_parallel(&self, bufmgr: &mut BufferPoolManager, workers: usize) -> Result<BoxExecutor<'_>, Error> {
+        let mut child = self.child.start(bufmgr)?;
+        let funcs: Vec<_> = self.aggregates.iter().map(|aggregate| aggregate.func.clone()).collect();
+        let initial = self.accumulators();
+        let mut partitions: Vec<Partition> = (0..workers).map(|_| Partition::default()).collect();
+        let mut memory = MemoryReservation::default();
+        let (mut row, mut done) = (0, false);
+        let mut interrupts = Interrupts::default();
+        while !done {
+            // (row number, group values, arguments) of the rows of each partition
+            let mut batches: Vec<Vec<(usize, Tuple, Vec<Value>)>> = (0..workers).map(|_| vec![]).collect();
+            for _ in 0..PARALLEL_BATCH_ROWS {
+                let Some(tuple) = child.next(bufmgr)? else {
+                    done = true;
+                    break;
+                };
+                interrupts.check(bufmgr)?;
+                let mut key = vec![];
+                for expr in &self.group_by {
+                    key.push(expr.eval(&tuple, bufmgr)?);
+                }
+                let mut args = vec![];
+                for aggregate in &self.aggregates {
+                    args.push(match &aggregate.arg {
+                        Some(arg) => arg.eval(&tuple, bufmgr)?,
+                        None => Value::Bool(true),
+                    });
+                }
+                batches[partition_of(&key, workers)].push((row, key, args));
+                row += 1;
+            }
+            let (funcs, initial) = (&funcs, &initial);
+            bufmgr
+                .thread_pool()
+                .map(partitions.iter_mut().zip(batches), |(partition, batch)| partition.add(funcs, initial, batch))
+                .into_iter()
+                .collect::<Result<(), _>>()?;
+            for partition in &mut partitions {
+                for (_, key, _) in &partition.groups[partition.reserved..] {
+                    bufmgr.reserve_memory(&mut memory, self.group_memory(key))?;
+                }
+                partition.reserved = partition.groups.len();
+            }
+        }
+        let mut groups: Vec<_> = partitions.into_iter().flat_map(|partition| partition.groups).collect();
+        groups.sort_by_key(|&(first, _, _)| first);
+        let rows = self.results(groups.into_iter().map(|(_, key, accumulators)| (key, accumulators)))?;
+        Ok(Box::new(ExecHashAggregate { rows: rows.into_iter(), memory }))
+    }
+}
+
+// The groups aggregated by a parallel worker, each with the row it first appeared in.
+#[derive(Default)]
+struct Partition {
+    index: HashMap<Tuple, usize>,
+    groups: Vec<(usize, Tuple, Vec<Accumulator>)>,
+    // groups the memory of which has been reserved
+    reserved: usize,
+}
+
+impl Partition {
+    fn add(&mut self, funcs: &[AggregateFunc], initial: &[Accumulator], rows: Vec<(usize, Tuple, Vec<Value>)>) -> Result<(), Error> {
+        for (row, key, args) in rows {
+            let group = match self.index.get(&key) {
+                Some(&group) => group,
+                None => {
+                    self.index.insert(key.clone(), self.groups.len());
+                    self.groups.push((row, key, initial.to_vec()));
+                    self.groups.len() - 1
+                }
+            };
+            for ((func, accumulator), value) in funcs.iter().zip(&mut self.groups[group].2).zip(args) {
+                accumulator.add(func, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// State of an aggregate over the values added so far.
+#[derive(Clone)]
+pub(super) struct Accumulator {
+    count: i64,
+    // sum, min or max so far, or the state of a registered aggregate
+    value: Value,
+    // arguments seen so far, for DISTINCT
+    seen: Option<HashSet<Value>>,
+}
+
+impl Accumulator {
+    pub(super) fn new(aggregate: &Aggregate) -> Self {
+        Self {
+            count: 0,
+            value: match &aggregate.func {
+                AggregateFunc::User(func) => func.init.clone(),
+                _ => Value::Null,
+            },
+            seen: aggregate.distinct.then(HashSet::new),
+        }
+    }
+
+    pub(super) fn add(&mut self, func: &AggregateFunc, value: Value) -> Result<(), Error> {
+        if value.is_null() {
+            return Ok(());
+        }
+        if let Some(seen) = &mut self.seen {
+            if !seen.insert(value.clone()) {
+                return Ok(());
+            }
+        }
+        self.count += 1;
+        if let AggregateFunc::User(func) = func {
+            let state = std::mem::replace(&mut self.value, Value::Null);
+            self.value = (func.step)(state, &cast(value, func.arg)?).map_err(|message| Error::Function(func.name.clone(), message))?;
+            return Ok(());
+        }
+        if self.value.is_null() {
+            self.value = value;
+            return Ok(());
+        }
+        self.value = match func {
+            AggregateFunc::Count => return Ok(()),
+            AggregateFunc::Sum => eval_binary(BinaryOp::Add, std::mem::replace(&mut self.value, Value::Null), value)?,
+            AggregateFunc::Min | AggregateFunc::Max => {
+                let less = eval_binary(BinaryOp::Lt, value.clone(), self.value.clone())? == Value::Bool(true);
+                if less == (*func == AggregateFunc::Min) {
+                    value
+                } else {
+                    return Ok(());
+                }
+            }
+            AggregateFunc::User(_) => unreachable!(),
+        };
+        Ok(())
+    }
+
+    pub(super) fn result(&self, aggregate: &Aggregate) -> Result<Value, Error> {
+        match &aggregate.func {
+            AggregateFunc::Count => Ok(Value::Int(self.count)),
+            AggregateFunc::User(func) => {
+                let value = (func.finalize)(self.value.clone()).map_err(|message| Error::Function(func.name.clone(), message))?;
+                if !func.returns.accepts(&value) {
+                    return Err(Error::TypeMismatch(format!("aggregate {} returned {:?}, not of type {}", func.name, value, type_name(func.returns))));
+                }
+                Ok(value)
+            }
+            _ => Ok(self.value.clone()),
+        }
+    }
+}
+
+struct ExecHashAggregate {
+    rows: std::vec::IntoIter<Tuple>,
+    // reserved for the groups, until they've all been returned
+    memory: MemoryReservation,
+}
+
+impl Executor for ExecHashAggregate {
+    fn next(&mut self, _bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        let row = self.rows.next();
+        if row.is_none() {
+            self.memory.release();
+        }
+        Ok(row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::query::Values;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(4));
+        let rows: Vec<_> = [(1, 5), (2, 3), (1, 5), (1, 2)].iter().map(|&(g, v)| vec![Value::Int(g), Value::Int(v)]).collect();
+        let aggregates = || {
+            vec![
+                Aggregate { func: AggregateFunc::Count, arg: None, distinct: false },
+                Aggregate { func: AggregateFunc::Sum, arg: Some(Expr::Column(1)), distinct: false },
+                Aggregate { func: AggregateFunc::Sum, arg: Some(Expr::Column(1)), distinct: true },
+                Aggregate { func: AggregateFunc::Min, arg: Some(Expr::Column(1)), distinct: false },
+                Aggregate { func: AggregateFunc::Max, arg: Some(Expr::Column(1)), distinct: false },
+            ]
+        };
+        let run = |bufmgr: &mut BufferPoolManager, plan: &HashAggregate| {
+            let mut exec = plan.start(bufmgr).unwrap();
+            let mut result = vec![];
+            while let Some(tuple) = exec.next(bufmgr).unwrap() {
+                result.push(tuple);
+            }
+            result
+        };
+
+        let plan = HashAggregate {
+            child: Box::new(Values { rows: rows.clone() }),
+            group_by: vec![Expr::Column(0)],
+            aggregates: aggregates(),
+        };
+        let ints = |values: &[i64]| values.iter().map(|&n| Value::Int(n)).collect::<Tuple>();
+        assert_eq!(vec![ints(&[1, 3, 12, 7, 2, 5]), ints(&[2, 1, 3, 3, 3, 3])], run(&mut bufmgr, &plan));
+        assert_eq!("Hash Aggregate (group by: [#0], aggregates: [count(*), sum(#1), sum(DISTINCT #1), min(#1), max(#1)])", plan.describe());
+        // the same in parallel, the groups in the order they first came in
+        let many: Vec<_> = (0..10000).map(|i| vec![Value::Int(i * 7 % 101), Value::Int(i % 13)]).collect();
+        let plan = HashAggregate { child: Box::new(Values { rows: many }), group_by: vec![Expr::Column(0)], aggregates: aggregates() };
+        let serial = run(&mut bufmgr, &plan);
+        assert_eq!(101, serial.len());
+        bufmgr.set_parallel_workers(3);
+        assert_eq!(serial, run(&mut bufmgr, &plan));
+        // neither way can the groups take more than the memory budget
+        for workers in [0, 3] {
+            bufmgr.set_parallel_workers(workers);
+            bufmgr.set_memory_budget(Some(50 * plan.group_memory(&[Value::Int(0)])));
+            bufmgr.start_statement();
+            assert!(matches!(plan.start(&mut bufmgr), Err(Error::Buffer(crate::buffer::Error::OutOfMemoryBudget(_)))));
+        }
+        bufmgr.set_memory_budget(None);
+        bufmgr.set_parallel_workers(0);
+
+        let plan = HashAggregate { child: Box::new(Values { rows: vec![] }), group_by: vec![], aggregates: aggregates() };
+        assert_eq!(vec![vec![Value::Int(0), Value::Null, Value::Null, Value::Null, Value::Null]], run(&mut bufmgr, &plan));
+    }
+}
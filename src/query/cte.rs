@@ -0,0 +1,266 @@
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use super::spill::{SpillReader, SpillRun, SpillWriter};
+use super::{BoxExecutor, Error, Estimate, Executor, PlanNode};
+use crate::buffer::BufferPoolManager;
+use crate::tuple::Tuple;
+
+// Number of iterations assumed for a recursive query when estimating it.
+const ASSUMED_ITERATIONS: f64 = 10.0;
+
+// Rows of a CTE, or the working table of a recursive one, written to a spill run so that they can
+// be read any number of times. Empty until filled by `MaterializeCtes` or `RecursiveUnion`.
+#[derive(Debug, Default)]
+pub struct CteStorage {
+    run: Cell<SpillRun>,
+}
+
+impl CteStorage {
+    fn fill(&self, bufmgr: &mut BufferPoolManager, mut exec: BoxExecutor) -> Result<(), Error> {
+        let mut writer = SpillWriter::new();
+        while let Some(tuple) = exec.next(bufmgr)? {
+            writer.push(bufmgr, &tuple)?;
+        }
+        self.run.set(writer.finish());
+        Ok(())
+    }
+}
+
+pub struct MaterializedCte {
+    pub name: String,
+    pub plan: Box<dyn PlanNode>,
+    pub storage: Rc<CteStorage>,
+}
+
+// Runs the plan of each CTE in order, storing its rows, then emits the rows of `child`, which
+// reads the stored rows through `CteScan`s. The CTEs are run again each time this is started.
+pub struct MaterializeCtes {
+    pub ctes: Vec<MaterializedCte>,
+    pub child: Box<dyn PlanNode>,
+}
+
+impl PlanNode for MaterializeCtes {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        for cte in &self.ctes {
+            let exec = cte.plan.start(bufmgr)?;
+            cte.storage.fill(bufmgr, exec)?;
+        }
+        self.child.start(bufmgr)
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.child.ordering()
+    }
+
+    fn describe(&self) -> String {
+        let names: Vec<_> = self.ctes.iter().map(|cte| cte.name.as_str()).collect();
+        format!("Materialize CTEs ({})", names.join(", "))
+    }
+
+    // the CTEs come first, in the order they're run
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        let mut children: Vec<_> = self.ctes.iter().map(|cte| cte.plan.as_ref()).collect();
+        children.push(self.child.as_ref());
+        children
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        let mut children: Vec<_> = self.ctes.iter_mut().map(|cte| &mut cte.plan).collect();
+        children.push(&mut self.child);
+        children
+    }
+
+    fn estimate(&self) -> Estimate {
+        let child = self.child.estimate();
+        let ctes: f64 = self.ctes.iter().map(|cte| cte.plan.estimate()).map(|e| e.cost + e.rows).sum();
+        Estimate {
+            rows: child.rows,
+            cost: ctes + child.cost,
+        }
+    }
+}
+
+// Reads the stored rows of a CTE.
+pub struct CteScan {
+    pub name: String,
+    pub storage: Rc<CteStorage>,
+    // estimated number of stored rows
+    pub rows: f64,
+}
+
+impl PlanNode for CteScan {
+    fn start(&self, _bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        Ok(Box::new(ExecCteScan {
+            reader: self.storage.run.get().reader(),
+        }))
+    }
+
+    fn describe(&self) -> String {
+        format!("CTE Scan on {}", self.name)
+    }
+
+    fn estimate(&self) -> Estimate {
+        Estimate {
+            rows: self.rows,
+            cost: self.rows,
+        }
+    }
+}
+
+struct ExecCteScan {
+    reader: SpillReader,
+}
+
+impl Executor for ExecCteScan {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        self.reader.next(bufmgr)
+    }
+}
+
+// WITH RECURSIVE: emits the rows of `base`, then runs `recursive` over and over, each time with
+// the rows emitted by the previous iteration in `work_table`, until an iteration emits nothing.
+// Without `all`, rows already emitted are dropped (and so not fed back either).
+pub struct RecursiveUnion {
+    pub base: Box<dyn PlanNode>,
+    pub recursive: Box<dyn PlanNode>,
+    pub work_table: Rc<CteStorage>,
+    pub all: bool,
+}
+
+impl PlanNode for RecursiveUnion {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        Ok(Box::new(ExecRecursiveUnion {
+            plan: self,
+            current: self.base.start(bufmgr)?,
+            next: SpillWriter::new(),
+            emitted: false,
+            seen: if self.all { None } else { Some(HashSet::new()) },
+        }))
+    }
+
+    fn describe(&self) -> String {
+        format!("Recursive Union{}", if self.all { " All" } else { "" })
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.base.as_ref(), self.recursive.as_ref()]
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![&mut self.base, &mut self.recursive]
+    }
+
+    fn estimate(&self) -> Estimate {
+        let (base, recursive) = (self.base.estimate(), self.recursive.estimate());
+        Estimate {
+            rows: base.rows + recursive.rows * ASSUMED_ITERATIONS,
+            cost: base.cost + base.rows + (recursive.cost + recursive.rows) * ASSUMED_ITERATIONS,
+        }
+    }
+}
+
+struct ExecRecursiveUnion<'a> {
+    plan: &'a RecursiveUnion,
+    // executor of the current iteration, the base query in the first one
+    current: BoxExecutor<'a>,
+    // rows emitted by the current iteration, the working table of the next one
+    next: SpillWriter,
+    emitted: bool,
+    // rows emitted so far, unless duplicates are kept
+    seen: Option<HashSet<Tuple>>,
+}
+
+impl<'a> Executor for ExecRecursiveUnion<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        loop {
+            match self.current.next(bufmgr)? {
+                Some(tuple) => {
+                    if let Some(seen) = &mut self.seen {
+                        if !seen.insert(tuple.clone()) {
+                            continue;
+                        }
+                    }
+                    self.next.push(bufmgr, &tuple)?;
+                    self.emitted = true;
+                    return Ok(Some(tuple));
+                }
+                None if !self.emitted => return Ok(None),
+                None => {
+                    let run = std::mem::take(&mut self.next).finish();
+                    self.plan.work_table.run.set(run);
+                    self.emitted = false;
+                    self.current = self.plan.recursive.start(bufmgr)?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::query::expr::{BinaryOp, Expr};
+    use crate::query::{Append, Filter, Project, Values};
+    use crate::tuple::Value;
+    use tempfile::tempfile;
+
+    fn run(plan: &dyn PlanNode, bufmgr: &mut BufferPoolManager) -> Vec<Tuple> {
+        let mut exec = plan.start(bufmgr).unwrap();
+        let mut result = vec![];
+        while let Some(tuple) = exec.next(bufmgr).unwrap() {
+            result.push(tuple);
+        }
+        result
+    }
+
+    fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+        Expr::Binary { op, left: Box::new(left), right: Box::new(right) }
+    }
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(4));
+        let ints = |values: &[i64]| -> Vec<Tuple> { values.iter().map(|&i| vec![Value::Int(i)]).collect() };
+
+        // WITH c AS (VALUES 1, 2) SELECT * FROM c UNION ALL SELECT * FROM c
+        let storage = Rc::new(CteStorage::default());
+        let scan = || Box::new(CteScan { name: "c".to_string(), storage: storage.clone(), rows: 2.0 });
+        let plan = MaterializeCtes {
+            ctes: vec![MaterializedCte {
+                name: "c".to_string(),
+                plan: Box::new(Values { rows: ints(&[1, 2]) }),
+                storage: storage.clone(),
+            }],
+            child: Box::new(Append { children: vec![scan(), scan()] }),
+        };
+        assert_eq!(ints(&[1, 2, 1, 2]), run(&plan, &mut bufmgr));
+
+        // WITH RECURSIVE t(n) AS (VALUES 1, 1 UNION [ALL] SELECT n + 1 FROM t WHERE n < 4)
+        for (all, expected) in [(false, ints(&[1, 2, 3, 4])), (true, ints(&[1, 1, 2, 2, 3, 3, 4, 4]))] {
+            let work_table = Rc::new(CteStorage::default());
+            let scan = Box::new(CteScan { name: "t".to_string(), storage: work_table.clone(), rows: 1.0 });
+            let filter = Filter {
+                child: scan,
+                predicate: binary(BinaryOp::Lt, Expr::Column(0), Expr::Literal(Value::Int(4))),
+            };
+            let recursive = Project {
+                child: Box::new(filter),
+                exprs: vec![binary(BinaryOp::Add, Expr::Column(0), Expr::Literal(Value::Int(1)))],
+            };
+            let plan = RecursiveUnion {
+                base: Box::new(Values { rows: ints(&[1, 1]) }),
+                recursive: Box::new(recursive),
+                work_table,
+                all,
+            };
+            let mut result = run(&plan, &mut bufmgr);
+            result.sort();
+            assert_eq!(expected, result);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashSet;
+
+use super::{memory_of, BoxExecutor, Error, Estimate, Executor, Interrupts, PlanNode};
+use crate::buffer::{BufferPoolManager, MemoryReservation};
+use crate::tuple::Tuple;
+
+// Emits the first of each group of equal input tuples, remembering the tuples seen so far in an
+// in-memory hash set. The input order is kept. Fails if they don't fit in the memory budget of the
+// statement.
+pub struct HashDistinct {
+    pub child: Box<dyn PlanNode>,
+}
+
+impl PlanNode for HashDistinct {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        Ok(Box::new(ExecHashDistinct {
+            child: self.child.start(bufmgr)?,
+            seen: HashSet::new(),
+            memory: MemoryReservation::default(),
+            interrupts: Interrupts::default(),
+        }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.child.ordering()
+    }
+
+    fn describe(&self) -> String {
+        "Hash Distinct".to_string()
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.child.as_ref()]
+    }
+
+    fn estimate(&self) -> Estimate {
+        distinct_estimate(self.child.estimate())
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![&mut self.child]
+    }
+}
+
+struct ExecHashDistinct<'a> {
+    child: BoxExecutor<'a>,
+    seen: HashSet<Tuple>,
+    // reserved for the tuples in `seen`
+    memory: MemoryReservation,
+    interrupts: Interrupts,
+}
+
+impl<'a> Executor for ExecHashDistinct<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        while let Some(tuple) = self.child.next(bufmgr)? {
+            self.interrupts.check(bufmgr)?;
+            if !self.seen.contains(&tuple) {
+                bufmgr.reserve_memory(&mut self.memory, memory_of(&tuple))?;
+                self.seen.insert(tuple.clone());
+                return Ok(Some(tuple));
+            }
+        }
+        self.seen = HashSet::new();
+        self.memory.release();
+        Ok(None)
+    }
+}
+
+// Emits the first of each run of equal input tuples, which removes all duplicates if the input
+// is sorted by all of its columns. Needs no memory besides the previous tuple.
+pub struct SortDistinct {
+    pub child: Box<dyn PlanNode>,
+}
+
+impl PlanNode for SortDistinct {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        Ok(Box::new(ExecSortDistinct {
+            child: self.child.start(bufmgr)?,
+            prev: None,
+            interrupts: Interrupts::default(),
+        }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.child.ordering()
+    }
+
+    fn describe(&self) -> String {
+        "Sort Distinct".to_string()
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.child.as_ref()]
+    }
+
+    fn estimate(&self) -> Estimate {
+        distinct_estimate(self.child.estimate())
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![&mut self.child]
+    }
+}
+
+struct ExecSortDistinct<'a> {
+    child: BoxExecutor<'a>,
+    prev: Option<Tuple>,
+    interrupts: Interrupts,
+}
+
+impl<'a> Executor for ExecSortDistinct<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        while let Some(tuple) = self.child.next(bufmgr)? {
+            self.interrupts.check(bufmgr)?;
+            if self.prev.as_ref() != Some(&tuple) {
+                self.prev = Some(tuple.clone());
+                return Ok(Some(tuple));
+            }
+        }
+        Ok(None)
+    }
+}
+
+// Guess that half of the input tuples are duplicates.
+fn distinct_estimate(child: Estimate) -> Estimate {
+    Estimate {
+        rows: (child.rows * 0.5).max(child.rows.min(1.0)),
+        cost: child.cost + child.rows,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::query::{Sort, Values};
+    use crate::tuple::Value;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(4));
+        let rows: Vec<_> = [3, 1, 3, 2, 1, 3].iter().map(|&i| vec![Value::Int(i), Value::Null]).collect();
+
+        let plan = HashDistinct { child: Box::new(Values { rows: rows.clone() }) };
+        let mut exec = plan.start(&mut bufmgr).unwrap();
+        let mut result = vec![];
+        while let Some(tuple) = exec.next(&mut bufmgr).unwrap() {
+            result.push(tuple);
+        }
+        assert_eq!(vec![rows[0].clone(), rows[1].clone(), rows[3].clone()], result);
+
+        let sorted = Sort { child: Box::new(Values { rows: rows.clone() }), keys: vec![0, 1], descending: vec![false; 2], max_rows_in_memory: 2 };
+        let plan = SortDistinct { child: Box::new(sorted) };
+        let mut exec = plan.start(&mut bufmgr).unwrap();
+        let mut result = vec![];
+        while let Some(tuple) = exec.next(&mut bufmgr).unwrap() {
+            result.push(tuple);
+        }
+        assert_eq!(vec![rows[1].clone(), rows[3].clone(), rows[0].clone()], result);
+    }
+}
@@ -0,0 +1,42 @@
+use super::{BoxExecutor, Error, Estimate, PlanNode, SeqScan};
+use crate::buffer::BufferPoolManager;
+
+// Replaces the number of rows `child` estimates it returns, e.g. with one derived from table
+// statistics. Anything else is delegated to the child, so it doesn't show up in EXPLAIN.
+pub struct Estimated {
+    pub child: Box<dyn PlanNode>,
+    pub rows: f64,
+}
+
+impl PlanNode for Estimated {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        self.child.start(bufmgr)
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.child.ordering()
+    }
+
+    fn as_seq_scan(&self) -> Option<&SeqScan> {
+        self.child.as_seq_scan()
+    }
+
+    fn describe(&self) -> String {
+        self.child.describe()
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        self.child.children()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        self.child.children_mut()
+    }
+
+    fn estimate(&self) -> Estimate {
+        Estimate {
+            rows: self.rows,
+            cost: self.child.estimate().cost,
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use super::PlanNode;
+
+// Renders a plan tree for EXPLAIN, one line per node, children indented under their parent.
+// Instrumented nodes also show what they did when they were run (EXPLAIN ANALYZE).
+pub fn explain(plan: &dyn PlanNode) -> Vec<String> {
+    let mut lines = vec![];
+    render(plan, 0, &mut lines);
+    lines
+}
+
+fn render(plan: &dyn PlanNode, depth: usize, lines: &mut Vec<String>) {
+    let estimate = plan.estimate();
+    let prefix = if depth == 0 { String::new() } else { format!("{}-> ", "  ".repeat(depth - 1)) };
+    let mut line = format!("{}{}  (rows={:.0} cost={:.2})", prefix, plan.describe(), estimate.rows, estimate.cost);
+    if let Some(stats) = plan.stats() {
+        let stats = stats.get();
+        line.push_str(&format!(
+            " (actual time={:.3} ms rows={} loops={} buffers: hit={} miss={})",
+            stats.elapsed.as_secs_f64() * 1000.0,
+            stats.rows,
+            stats.loops,
+            stats.buffers.hits,
+            stats.buffers.misses
+        ));
+    }
+    lines.push(line);
+    for child in plan.children() {
+        render(child, depth + 1, lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::expr::{BinaryOp, Expr};
+    use crate::query::{Filter, Values};
+    use crate::tuple::Value;
+
+    #[test]
+    fn test() {
+        let filter = Filter {
+            child: Box::new(Values { rows: vec![vec![Value::Int(1)]; 20] }),
+            predicate: Expr::Binary {
+                op: BinaryOp::Eq,
+                left: Box::new(Expr::Column(0)),
+                right: Box::new(Expr::Literal(Value::Int(1))),
+            },
+        };
+        assert_eq!(
+            vec!["Filter: (#0 = 1)  (rows=2 cost=40.00)", "-> Values (20 rows)  (rows=20 cost=20.00)"],
+            explain(&filter)
+        );
+    }
+}
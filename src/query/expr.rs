@@ -0,0 +1,1045 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+
+use super::{Error, PlanNode};
+use crate::buffer::BufferPoolManager;
+use crate::array;
+use crate::datetime::{self, MICROS_PER_DAY};
+use crate::fulltext;
+use crate::geometry::{self, Point, Rect};
+use crate::json;
+use crate::numeric::{Float, Numeric};
+use crate::tuple::{DataType, ElementType, Tuple, Value};
+use crate::udf::ScalarFunction;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    And,
+    Or,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    // of JSON: `->` and `->>` take a member or an element, `#>` and `#>>` what a path of them
+    // leads to, the latter of each as text
+    JsonGet,
+    JsonGetText,
+    JsonPath,
+    JsonPathText,
+    // array[index], counting from 1, NULL out of bounds
+    Subscript,
+    // text @@ query, whether the text has every word of the query (see `fulltext`)
+    Match,
+    // of points and boxes, by their bounds: `@>` whether the left has the right in, `<@` whether
+    // the right has the left in, `&&` whether they overlap
+    Contains,
+    ContainedBy,
+    Overlaps,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    // in characters
+    Length,
+    Upper,
+    Lower,
+    // (string, start [, length]), counting characters from 1
+    Substring,
+    // of the arguments converted to text, skipping NULLs
+    Concat,
+    // (string [, characters]), removing the characters (spaces by default) from the given ends
+    Trim { leading: bool, trailing: bool },
+    // ARRAY[element, ...]
+    Array,
+    // (text, query), how many of the words of the text are of the query (see `fulltext::rank`)
+    TsRank,
+    // point(x, y)
+    Point,
+    // box(point, point), of the two corners
+    Box,
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Function::Trim { leading: true, trailing: false } => write!(f, "ltrim"),
+            Function::Trim { leading: false, trailing: true } => write!(f, "rtrim"),
+            Function::Trim { .. } => write!(f, "trim"),
+            Function::TsRank => write!(f, "ts_rank"),
+            func => write!(f, "{}", format!("{:?}", func).to_lowercase()),
+        }
+    }
+}
+
+// The row of an enclosing query, shared with the subqueries which refer to its columns.
+// The subquery expression writes the current outer row here before running the subquery.
+pub type OuterRow = Rc<RefCell<Tuple>>;
+
+// Values of the parameters of a prepared statement, set before each execution.
+pub type Params = Rc<RefCell<Vec<Value>>>;
+
+pub enum SubqueryKind {
+    // (SELECT ...) yielding at most one row of one column
+    Scalar,
+    Exists { negated: bool },
+    In { expr: Box<Expr>, negated: bool },
+}
+
+// Expression bound to the layout of the tuples it's evaluated on.
+pub enum Expr {
+    Literal(Value),
+    Column(usize),
+    Parameter { params: Params, index: usize },
+    // column of the row of an enclosing query (correlated reference)
+    OuterColumn { row: OuterRow, index: usize },
+    Unary { op: UnaryOp, expr: Box<Expr> },
+    Binary { op: BinaryOp, left: Box<Expr>, right: Box<Expr> },
+    IsNull { expr: Box<Expr>, negated: bool },
+    InList { expr: Box<Expr>, list: Vec<Expr>, negated: bool },
+    // `%` in the pattern matches any string, `_` any character, and `\` escapes the next character
+    Like { expr: Box<Expr>, pattern: Box<Expr>, negated: bool, case_insensitive: bool },
+    Function { func: Function, args: Vec<Expr> },
+    // of a function registered, the arguments of the types it's declared with or text literals
+    Call { func: Rc<ScalarFunction>, args: Vec<Expr> },
+    // `expr op ANY (array)`, which holds if it does for an element, or ALL, for every element
+    Quantified { op: BinaryOp, expr: Box<Expr>, array: Box<Expr>, all: bool },
+    Cast { expr: Box<Expr>, to: DataType },
+    // The subquery is run each time the expression is evaluated, with `outer_row` set to the
+    // tuple being evaluated.
+    Subquery { kind: SubqueryKind, plan: Box<dyn PlanNode>, outer_row: OuterRow },
+}
+
+impl Expr {
+    pub fn eval(&self, tuple: &[Value], bufmgr: &mut BufferPoolManager) -> Result<Value, Error> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Column(i) => Ok(tuple[*i].clone()),
+            Expr::Parameter { params, index } => Ok(params.borrow()[*index].clone()),
+            Expr::OuterColumn { row, index } => Ok(row.borrow()[*index].clone()),
+            Expr::Unary { op, expr } => {
+                let value = expr.eval(tuple, bufmgr)?;
+                match (op, value) {
+                    (_, Value::Null) => Ok(Value::Null),
+                    (UnaryOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+                    (UnaryOp::Neg, Value::Int(n)) => n.checked_neg().map(Value::Int).ok_or(Error::NumericOverflow),
+                    (UnaryOp::Neg, Value::Float(f)) => Ok(Value::Float(Float(-f.0))),
+                    (UnaryOp::Neg, Value::Numeric(n)) => Ok(Value::Numeric(-n)),
+                    (_, value) => Err(Error::TypeMismatch(format!("invalid operand for {:?}: {:?}", op, value))),
+                }
+            }
+            Expr::Binary { op, left, right } => {
+                let left = left.eval(tuple, bufmgr)?;
+                // short circuit so that e.g. `x <> 0 AND 1 / x > 0` doesn't fail
+                match (op, &left) {
+                    (BinaryOp::And, Value::Bool(false)) => return Ok(Value::Bool(false)),
+                    (BinaryOp::Or, Value::Bool(true)) => return Ok(Value::Bool(true)),
+                    _ => {}
+                }
+                let right = right.eval(tuple, bufmgr)?;
+                eval_binary(*op, left, right)
+            }
+            Expr::IsNull { expr, negated } => Ok(Value::Bool(expr.eval(tuple, bufmgr)?.is_null() != *negated)),
+            Expr::InList { expr, list, negated } => {
+                let value = expr.eval(tuple, bufmgr)?;
+                let mut candidates = vec![];
+                for item in list {
+                    candidates.push(item.eval(tuple, bufmgr)?);
+                }
+                Ok(negate_if(eval_in(&value, candidates)?, *negated))
+            }
+            Expr::Like {
+                expr,
+                pattern,
+                negated,
+                case_insensitive,
+            } => match (expr.eval(tuple, bufmgr)?, pattern.eval(tuple, bufmgr)?) {
+                (Value::Null, _) | (_, Value::Null) => Ok(Value::Null),
+                (Value::Text(s), Value::Text(pattern)) => Ok(Value::Bool(like(&s, &pattern, *case_insensitive) != *negated)),
+                (s, pattern) => Err(Error::TypeMismatch(format!("invalid operands for LIKE: {:?}, {:?}", s, pattern))),
+            },
+            Expr::Function { func, args } => {
+                let mut values = vec![];
+                for arg in args {
+                    values.push(arg.eval(tuple, bufmgr)?);
+                }
+                eval_function(*func, values)
+            }
+            Expr::Call { func, args } => {
+                let mut values = vec![];
+                for (arg, &data_type) in args.iter().zip(&func.args) {
+                    values.push(cast(arg.eval(tuple, bufmgr)?, data_type)?);
+                }
+                let value = (func.func)(&values).map_err(|message| Error::Function(func.name.clone(), message))?;
+                if !func.returns.accepts(&value) {
+                    return Err(Error::TypeMismatch(format!("function {} returned {:?}, not of type {}", func.name, value, type_name(func.returns))));
+                }
+                Ok(value)
+            }
+            Expr::Quantified { op, expr, array, all } => {
+                let value = expr.eval(tuple, bufmgr)?;
+                let elements = match array.eval(tuple, bufmgr)? {
+                    Value::Null => return Ok(Value::Null),
+                    Value::Array(elements) => elements,
+                    array => return Err(Error::TypeMismatch(format!("ANY and ALL apply to arrays, not {:?}", array))),
+                };
+                // like AND of the comparisons for ALL, OR of them for ANY
+                let mut result = Value::Bool(*all);
+                for element in elements {
+                    match eval_binary(*op, value.clone(), element)? {
+                        Value::Bool(b) if b != *all => return Ok(Value::Bool(b)),
+                        Value::Null => result = Value::Null,
+                        _ => {}
+                    }
+                }
+                Ok(result)
+            }
+            Expr::Cast { expr, to } => cast(expr.eval(tuple, bufmgr)?, *to),
+            Expr::Subquery { kind, plan, outer_row } => {
+                *outer_row.borrow_mut() = tuple.to_vec();
+                let mut exec = plan.start(bufmgr)?;
+                match kind {
+                    SubqueryKind::Scalar => {
+                        let value = match exec.next(bufmgr)? {
+                            Some(row) => row.into_iter().next().unwrap_or(Value::Null),
+                            None => return Ok(Value::Null),
+                        };
+                        if exec.next(bufmgr)?.is_some() {
+                            return Err(Error::SubqueryReturnedMultipleRows);
+                        }
+                        Ok(value)
+                    }
+                    SubqueryKind::Exists { negated } => Ok(Value::Bool(exec.next(bufmgr)?.is_some() != *negated)),
+                    SubqueryKind::In { expr, negated } => {
+                        let value = expr.eval(tuple, bufmgr)?;
+                        let mut candidates = vec![];
+                        while let Some(row) = exec.next(bufmgr)? {
+                            candidates.push(row.into_iter().next().unwrap_or(Value::Null));
+                        }
+                        Ok(negate_if(eval_in(&value, candidates)?, *negated))
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Expr {
+    // Adds the indices of the input columns the expression reads to `columns`.
+    // Columns read by the plans of subqueries are not included.
+    pub fn columns(&self, columns: &mut Vec<usize>) {
+        match self {
+            Expr::Column(i) => columns.push(*i),
+            Expr::Literal(_) | Expr::Parameter { .. } | Expr::OuterColumn { .. } => {}
+            Expr::Unary { expr, .. } | Expr::IsNull { expr, .. } | Expr::Cast { expr, .. } => expr.columns(columns),
+            Expr::Binary { left, right, .. } | Expr::Quantified { expr: left, array: right, .. } => {
+                left.columns(columns);
+                right.columns(columns);
+            }
+            Expr::InList { expr, list, .. } => {
+                expr.columns(columns);
+                list.iter().for_each(|e| e.columns(columns));
+            }
+            Expr::Like { expr, pattern, .. } => {
+                expr.columns(columns);
+                pattern.columns(columns);
+            }
+            Expr::Function { args, .. } | Expr::Call { args, .. } => args.iter().for_each(|e| e.columns(columns)),
+            Expr::Subquery { kind, .. } => {
+                if let SubqueryKind::In { expr, .. } = kind {
+                    expr.columns(columns);
+                }
+            }
+        }
+    }
+
+    // Copy of the expression which reads input column `map(i)` where this one reads column `i`.
+    // None if the expression contains a subquery, whose plan can't be copied and which may read
+    // any column of the tuple it's evaluated on.
+    pub fn remap(&self, map: &dyn Fn(usize) -> usize) -> Option<Expr> {
+        let remap_box = |expr: &Expr| expr.remap(map).map(Box::new);
+        Some(match self {
+            Expr::Literal(value) => Expr::Literal(value.clone()),
+            Expr::Column(i) => Expr::Column(map(*i)),
+            Expr::Parameter { params, index } => Expr::Parameter {
+                params: params.clone(),
+                index: *index,
+            },
+            Expr::OuterColumn { row, index } => Expr::OuterColumn {
+                row: row.clone(),
+                index: *index,
+            },
+            Expr::Unary { op, expr } => Expr::Unary {
+                op: *op,
+                expr: remap_box(expr)?,
+            },
+            Expr::Binary { op, left, right } => Expr::Binary {
+                op: *op,
+                left: remap_box(left)?,
+                right: remap_box(right)?,
+            },
+            Expr::IsNull { expr, negated } => Expr::IsNull {
+                expr: remap_box(expr)?,
+                negated: *negated,
+            },
+            Expr::InList { expr, list, negated } => Expr::InList {
+                expr: remap_box(expr)?,
+                list: list.iter().map(|e| e.remap(map)).collect::<Option<_>>()?,
+                negated: *negated,
+            },
+            Expr::Like {
+                expr,
+                pattern,
+                negated,
+                case_insensitive,
+            } => Expr::Like {
+                expr: remap_box(expr)?,
+                pattern: remap_box(pattern)?,
+                negated: *negated,
+                case_insensitive: *case_insensitive,
+            },
+            Expr::Function { func, args } => Expr::Function {
+                func: *func,
+                args: args.iter().map(|e| e.remap(map)).collect::<Option<_>>()?,
+            },
+            Expr::Call { func, args } => Expr::Call {
+                func: func.clone(),
+                args: args.iter().map(|e| e.remap(map)).collect::<Option<_>>()?,
+            },
+            Expr::Quantified { op, expr, array, all } => Expr::Quantified {
+                op: *op,
+                expr: remap_box(expr)?,
+                array: remap_box(array)?,
+                all: *all,
+            },
+            Expr::Cast { expr, to } => Expr::Cast {
+                expr: remap_box(expr)?,
+                to: *to,
+            },
+            Expr::Subquery { .. } => return None,
+        })
+    }
+
+    pub fn has_subquery(&self) -> bool {
+        self.remap(&|i| i).is_none()
+    }
+
+    // Guess of the fraction of tuples for which this predicate holds.
+    pub fn selectivity(&self) -> f64 {
+        match self {
+            Expr::Literal(Value::Bool(true)) => 1.0,
+            Expr::Literal(_) => 0.0,
+            Expr::Unary { op: UnaryOp::Not, expr } => 1.0 - expr.selectivity(),
+            Expr::Binary { op, left, right } => match op {
+                BinaryOp::And => left.selectivity() * right.selectivity(),
+                BinaryOp::Or => {
+                    let (l, r) = (left.selectivity(), right.selectivity());
+                    l + r - l * r
+                }
+                BinaryOp::Eq => 0.1,
+                BinaryOp::NotEq => 0.9,
+                BinaryOp::Match | BinaryOp::Contains | BinaryOp::ContainedBy | BinaryOp::Overlaps => 0.1,
+                _ => 1.0 / 3.0,
+            },
+            Expr::IsNull { negated, .. } => {
+                if *negated {
+                    0.9
+                } else {
+                    0.1
+                }
+            }
+            Expr::InList { list, negated, .. } => {
+                let s = (0.1 * list.len() as f64).min(1.0);
+                if *negated {
+                    1.0 - s
+                } else {
+                    s
+                }
+            }
+            Expr::Like { negated, .. } => {
+                if *negated {
+                    0.9
+                } else {
+                    0.1
+                }
+            }
+            _ => 0.5,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Literal(value) => fmt_value(value, f),
+            Expr::Column(i) => write!(f, "#{}", i),
+            Expr::Parameter { index, .. } => write!(f, "${}", index + 1),
+            Expr::OuterColumn { index, .. } => write!(f, "outer.#{}", index),
+            Expr::Unary { op: UnaryOp::Not, expr } => write!(f, "NOT {}", expr),
+            Expr::Unary { op: UnaryOp::Neg, expr } => write!(f, "-{}", expr),
+            Expr::Binary {
+                op: BinaryOp::Subscript,
+                left,
+                right,
+            } => write!(f, "{}[{}]", left, right),
+            Expr::Binary { op, left, right } => write!(f, "({} {} {})", left, symbol(*op), right),
+            Expr::IsNull { expr, negated } => write!(f, "{} IS {}NULL", expr, if *negated { "NOT " } else { "" }),
+            Expr::InList { expr, list, negated } => {
+                let list: Vec<_> = list.iter().map(|e| e.to_string()).collect();
+                write!(f, "{} {}IN ({})", expr, if *negated { "NOT " } else { "" }, list.join(", "))
+            }
+            Expr::Like {
+                expr,
+                pattern,
+                negated,
+                case_insensitive,
+            } => {
+                let op = if *case_insensitive { "ILIKE" } else { "LIKE" };
+                write!(f, "{} {}{} {}", expr, if *negated { "NOT " } else { "" }, op, pattern)
+            }
+            Expr::Function { func: Function::Array, args } => {
+                let args: Vec<_> = args.iter().map(|e| e.to_string()).collect();
+                write!(f, "ARRAY[{}]", args.join(", "))
+            }
+            Expr::Function { func, args } => {
+                let args: Vec<_> = args.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}({})", func, args.join(", "))
+            }
+            Expr::Call { func, args } => {
+                let args: Vec<_> = args.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}({})", func.name, args.join(", "))
+            }
+            Expr::Quantified { op, expr, array, all } => {
+                write!(f, "{} {} {} ({})", expr, symbol(*op), if *all { "ALL" } else { "ANY" }, array)
+            }
+            Expr::Cast { expr, to } => write!(f, "CAST({} AS {})", expr, type_name(*to).to_uppercase()),
+            Expr::Subquery { kind, .. } => match kind {
+                SubqueryKind::Scalar => write!(f, "(subquery)"),
+                SubqueryKind::Exists { negated } => write!(f, "{}EXISTS (subquery)", if *negated { "NOT " } else { "" }),
+                SubqueryKind::In { expr, negated } => write!(f, "{} {}IN (subquery)", expr, if *negated { "NOT " } else { "" }),
+            },
+        }
+    }
+}
+
+fn symbol(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::And => "AND",
+        BinaryOp::Or => "OR",
+        BinaryOp::Eq => "=",
+        BinaryOp::NotEq => "<>",
+        BinaryOp::Lt => "<",
+        BinaryOp::LtEq => "<=",
+        BinaryOp::Gt => ">",
+        BinaryOp::GtEq => ">=",
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Mod => "%",
+        BinaryOp::JsonGet => "->",
+        BinaryOp::JsonGetText => "->>",
+        BinaryOp::JsonPath => "#>",
+        BinaryOp::JsonPathText => "#>>",
+        BinaryOp::Subscript => "[]",
+        BinaryOp::Match => "@@",
+        BinaryOp::Contains => "@>",
+        BinaryOp::ContainedBy => "<@",
+        BinaryOp::Overlaps => "&&",
+    }
+}
+
+fn fmt_value(value: &Value, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match value {
+        Value::Null => write!(f, "NULL"),
+        Value::Int(n) => write!(f, "{}", n),
+        Value::Text(s) => write!(f, "'{}'", s.replace('\'', "''")),
+        Value::Bool(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+        Value::Json(bytes) => write!(f, "'{}'::json", json::to_string(bytes).replace('\'', "''")),
+        Value::Array(values) => {
+            let values: Vec<_> = values.iter().map(|v| Expr::Literal(v.clone()).to_string()).collect();
+            write!(f, "ARRAY[{}]", values.join(", "))
+        }
+        Value::Point(point) => write!(f, "'{}'::point", point),
+        Value::Box(rect) => write!(f, "'{}'::box", rect),
+        Value::Float(float) => write!(f, "'{}'::double precision", float),
+        Value::Numeric(n) => write!(f, "{}", n),
+        Value::Date(days) => write!(f, "'{}'::date", datetime::format_date(*days)),
+        Value::Timestamp(micros) => write!(f, "'{}'::timestamp", datetime::format_timestamp(*micros)),
+    }
+}
+
+// Returns true if `predicate` holds for the tuple. NULL counts as false.
+// AND of all `predicates`, None if there's none.
+pub fn conjunction(mut predicates: Vec<Expr>) -> Option<Expr> {
+    let mut result = predicates.pop()?;
+    while let Some(predicate) = predicates.pop() {
+        result = Expr::Binary {
+            op: BinaryOp::And,
+            left: Box::new(predicate),
+            right: Box::new(result),
+        };
+    }
+    Some(result)
+}
+
+pub fn is_true(predicate: &Expr, tuple: &[Value], bufmgr: &mut BufferPoolManager) -> Result<bool, Error> {
+    match predicate.eval(tuple, bufmgr)? {
+        Value::Bool(b) => Ok(b),
+        Value::Null => Ok(false),
+        value => Err(Error::TypeMismatch(format!("predicate must be boolean: {:?}", value))),
+    }
+}
+
+fn negate_if(value: Value, negated: bool) -> Value {
+    match value {
+        Value::Bool(b) => Value::Bool(b != negated),
+        value => value,
+    }
+}
+
+// SQL semantics of `value IN (candidates)`: NULL if there's no match but a NULL is involved.
+fn eval_in(value: &Value, candidates: Vec<Value>) -> Result<Value, Error> {
+    if candidates.is_empty() {
+        return Ok(Value::Bool(false));
+    }
+    let mut result = Value::Bool(false);
+    for candidate in candidates {
+        match eval_binary(BinaryOp::Eq, value.clone(), candidate)? {
+            Value::Bool(true) => return Ok(Value::Bool(true)),
+            Value::Null => result = Value::Null,
+            _ => {}
+        }
+    }
+    Ok(result)
+}
+
+// Whether `s` matches the LIKE `pattern`.
+fn like(s: &str, pattern: &str, case_insensitive: bool) -> bool {
+    let fold = |s: &str| -> Vec<char> {
+        if case_insensitive {
+            s.to_lowercase().chars().collect()
+        } else {
+            s.chars().collect()
+        }
+    };
+    let (s, pattern) = (fold(s), fold(pattern));
+    // backtracking to the last `%`, which then matches one more character
+    let (mut i, mut p) = (0, 0);
+    let mut retry: Option<(usize, usize)> = None;
+    while i < s.len() {
+        match pattern.get(p) {
+            Some('%') => {
+                p += 1;
+                retry = Some((i, p));
+                continue;
+            }
+            Some('_') => {
+                i += 1;
+                p += 1;
+                continue;
+            }
+            Some('\\') if pattern.get(p + 1) == Some(&s[i]) => {
+                i += 1;
+                p += 2;
+                continue;
+            }
+            Some(&c) if c != '\\' && c == s[i] => {
+                i += 1;
+                p += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match retry {
+            Some((start, after)) => {
+                i = start + 1;
+                p = after;
+                retry = Some((i, p));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '%')
+}
+
+// The string which all matches of the LIKE `pattern` start with, up to the first wildcard.
+pub fn like_prefix(pattern: &str) -> String {
+    let mut prefix = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' | '_' => break,
+            '\\' => match chars.next() {
+                Some(c) => prefix.push(c),
+                None => break,
+            },
+            c => prefix.push(c),
+        }
+    }
+    prefix
+}
+
+// Explicit conversion of `value` to the type `to`. Text is converted to the other types as it's
+// written in SQL (in JSON for json, as {element,...} for arrays, as in `geometry` for points and
+// boxes), and everything else to text. Arrays are converted element by element to arrays of other
+// types. Integers are true unless 0, which booleans are converted back to. A point is the box of
+// it at both corners. Numbers are converted to each other, rounded half away from zero to
+// integers, and dates to the midnights they begin with, which timestamps are converted back to
+// the dates of.
+pub fn cast(value: Value, to: DataType) -> Result<Value, Error> {
+    let invalid = |s: &str| Error::InvalidArgument(format!("invalid input syntax for type {}: \"{}\"", type_name(to), s));
+    let parsed = |e: &dyn std::error::Error| Error::InvalidArgument(e.to_string());
+    Ok(match (value, to) {
+        (value, to) if to.accepts(&value) => value,
+        (Value::Int(n), DataType::Float) => Value::Float(Float(n as f64)),
+        (Value::Int(n), DataType::Numeric) => Value::Numeric(Numeric::from_int(n)),
+        (Value::Float(f), DataType::Integer) => match f.0.round() {
+            f if (i64::MIN as f64..i64::MAX as f64).contains(&f) => Value::Int(f as i64),
+            _ => return Err(Error::NumericOverflow),
+        },
+        (Value::Float(f), DataType::Numeric) => Value::Numeric(Numeric::from_f64(f.0).map_err(|e| parsed(&e))?),
+        (Value::Numeric(n), DataType::Integer) => Value::Int(n.to_i64().ok_or(Error::NumericOverflow)?),
+        (Value::Numeric(n), DataType::Float) => Value::Float(Float(n.to_f64())),
+        (Value::Text(s), DataType::Float) => Value::Float(Float::parse(&s).map_err(|e| parsed(&e))?),
+        (Value::Text(s), DataType::Numeric) => Value::Numeric(Numeric::parse(&s).map_err(|e| parsed(&e))?),
+        (Value::Text(s), DataType::Date) => Value::Date(datetime::parse_date(&s).map_err(|e| parsed(&e))?),
+        (Value::Text(s), DataType::Timestamp) => Value::Timestamp(datetime::parse_timestamp(&s).map_err(|e| parsed(&e))?),
+        (Value::Float(f), DataType::Text) => Value::Text(f.to_string()),
+        (Value::Numeric(n), DataType::Text) => Value::Text(n.to_string()),
+        (Value::Date(days), DataType::Text) => Value::Text(datetime::format_date(days)),
+        (Value::Timestamp(micros), DataType::Text) => Value::Text(datetime::format_timestamp(micros)),
+        (Value::Date(days), DataType::Timestamp) => Value::Timestamp(days as i64 * MICROS_PER_DAY),
+        (Value::Timestamp(micros), DataType::Date) => Value::Date(micros.div_euclid(MICROS_PER_DAY) as i32),
+        (Value::Int(n), DataType::Text) => Value::Text(n.to_string()),
+        (Value::Bool(b), DataType::Text) => Value::Text(b.to_string()),
+        (Value::Json(bytes), DataType::Text) => Value::Text(json::to_string(&bytes)),
+        (Value::Text(s), DataType::Json) => Value::Json(json::parse(&s).map_err(|e| Error::InvalidArgument(e.to_string()))?),
+        (Value::Array(values), DataType::Text) => Value::Text(array::to_string(&values)),
+        (Value::Text(s), DataType::Array(element)) => Value::Array(array::parse(&s, element).map_err(|e| Error::InvalidArgument(e.to_string()))?),
+        (Value::Array(values), DataType::Array(element)) => {
+            Value::Array(values.into_iter().map(|v| cast(v, element.data_type())).collect::<Result<_, _>>()?)
+        }
+        (Value::Text(s), DataType::Integer) => Value::Int(s.trim().parse().map_err(|_| invalid(&s))?),
+        (Value::Text(s), DataType::Boolean) => match s.trim().to_lowercase().as_str() {
+            "t" | "true" | "y" | "yes" | "on" | "1" => Value::Bool(true),
+            "f" | "false" | "n" | "no" | "off" | "0" => Value::Bool(false),
+            _ => return Err(invalid(&s)),
+        },
+        (Value::Point(point), DataType::Text) => Value::Text(point.to_string()),
+        (Value::Box(rect), DataType::Text) => Value::Text(rect.to_string()),
+        (Value::Text(s), DataType::Point) => Value::Point(Point::parse(&s).map_err(|e| Error::InvalidArgument(e.to_string()))?),
+        (Value::Text(s), DataType::Box) => Value::Box(Rect::parse(&s).map_err(|e| Error::InvalidArgument(e.to_string()))?),
+        (Value::Point(point), DataType::Box) => Value::Box(Rect::of_point(point)),
+        (Value::Int(n), DataType::Boolean) => Value::Bool(n != 0),
+        (Value::Bool(b), DataType::Integer) => Value::Int(b as i64),
+        (value, to) => return Err(Error::TypeMismatch(format!("cannot cast {:?} to {}", value, type_name(to)))),
+    })
+}
+
+pub fn type_name(data_type: DataType) -> &'static str {
+    match data_type {
+        DataType::Integer => "integer",
+        DataType::Text => "text",
+        DataType::Boolean => "boolean",
+        DataType::Json => "json",
+        DataType::Array(ElementType::Integer) => "integer[]",
+        DataType::Array(ElementType::Text) => "text[]",
+        DataType::Array(ElementType::Boolean) => "boolean[]",
+        DataType::Point => "point",
+        DataType::Box => "box",
+        DataType::Float => "double precision",
+        DataType::Numeric => "numeric",
+        DataType::Date => "date",
+        DataType::Timestamp => "timestamp",
+    }
+}
+
+fn eval_function(func: Function, args: Vec<Value>) -> Result<Value, Error> {
+    if func == Function::Concat {
+        let mut result = String::new();
+        for arg in args {
+            match arg {
+                Value::Null => {}
+                Value::Int(n) => result.push_str(&n.to_string()),
+                Value::Text(s) => result.push_str(&s),
+                Value::Bool(b) => result.push_str(if b { "true" } else { "false" }),
+                Value::Json(bytes) => result.push_str(&json::to_string(&bytes)),
+                Value::Array(values) => result.push_str(&array::to_string(&values)),
+                Value::Point(point) => result.push_str(&point.to_string()),
+                Value::Box(rect) => result.push_str(&rect.to_string()),
+                Value::Float(f) => result.push_str(&f.to_string()),
+                Value::Numeric(n) => result.push_str(&n.to_string()),
+                Value::Date(days) => result.push_str(&datetime::format_date(days)),
+                Value::Timestamp(micros) => result.push_str(&datetime::format_timestamp(micros)),
+            }
+        }
+        return Ok(Value::Text(result));
+    }
+    if func == Function::Array {
+        // of one scalar type, the NULLs aside
+        let mut types: Vec<_> = args.iter().filter_map(DataType::of).collect();
+        types.dedup();
+        if types.len() > 1 || types.iter().any(|&t| ElementType::of(t).is_none()) {
+            return Err(Error::TypeMismatch(format!("array elements must be of one scalar type: {:?}", args)));
+        }
+        return Ok(Value::Array(args));
+    }
+    if args.iter().any(Value::is_null) {
+        return Ok(Value::Null);
+    }
+    Ok(match (func, args.as_slice()) {
+        (Function::Length, [Value::Text(s)]) => Value::Int(s.chars().count() as i64),
+        (Function::Upper, [Value::Text(s)]) => Value::Text(s.to_uppercase()),
+        (Function::Lower, [Value::Text(s)]) => Value::Text(s.to_lowercase()),
+        (Function::TsRank, [Value::Text(s), Value::Text(query)]) => Value::Int(fulltext::rank(s, query)),
+        (Function::Point, [Value::Int(x), Value::Int(y)]) => Value::Point(Point { x: *x, y: *y }),
+        (Function::Box, [Value::Point(a), Value::Point(b)]) => Value::Box(Rect::new(*a, *b)),
+        (Function::Substring, [Value::Text(s), Value::Int(start), rest @ ..]) => {
+            // characters in [start, start + length), of which those before the first are dropped
+            let end = match rest {
+                [] => None,
+                [Value::Int(length)] if *length < 0 => {
+                    return Err(Error::InvalidArgument("negative substring length not allowed".to_string()));
+                }
+                [Value::Int(length)] => Some(start.saturating_add(*length)),
+                _ => return Err(Error::TypeMismatch(format!("invalid arguments for {}: {:?}", func, args))),
+            };
+            let chars = s.chars().zip(1..).filter(|(_, i)| i >= start && end.is_none_or(|end| *i < end));
+            Value::Text(chars.map(|(c, _)| c).collect())
+        }
+        (Function::Trim { leading, trailing }, [Value::Text(s), rest @ ..]) => {
+            let characters: Vec<char> = match rest {
+                [] => vec![' '],
+                [Value::Text(characters)] => characters.chars().collect(),
+                _ => return Err(Error::TypeMismatch(format!("invalid arguments for {}: {:?}", func, args))),
+            };
+            let mut s = s.as_str();
+            if leading {
+                s = s.trim_start_matches(characters.as_slice());
+            }
+            if trailing {
+                s = s.trim_end_matches(characters.as_slice());
+            }
+            Value::Text(s.to_string())
+        }
+        _ => return Err(Error::TypeMismatch(format!("invalid arguments for {}: {:?}", func, args))),
+    })
+}
+
+fn compare(left: &Value, right: &Value) -> Result<Ordering, Error> {
+    match (left, right) {
+        (Value::Int(l), Value::Int(r)) => Ok(l.cmp(r)),
+        (Value::Text(l), Value::Text(r)) => Ok(l.cmp(r)),
+        (Value::Bool(l), Value::Bool(r)) => Ok(l.cmp(r)),
+        // by their binary forms, which are equal if the values are
+        (Value::Json(l), Value::Json(r)) => Ok(l.cmp(r)),
+        // element by element, NULLs being equal to each other and less than anything else
+        (Value::Array(l), Value::Array(r)) if DataType::of(left).zip(DataType::of(right)).is_none_or(|(l, r)| l == r) => Ok(l.cmp(r)),
+        // by their coordinates, x first, and of boxes the low corner first
+        (Value::Point(l), Value::Point(r)) => Ok(l.cmp(r)),
+        (Value::Box(l), Value::Box(r)) => Ok(l.cmp(r)),
+        (Value::Float(l), Value::Float(r)) => Ok(l.cmp(r)),
+        (Value::Numeric(l), Value::Numeric(r)) => Ok(l.cmp(r)),
+        (Value::Date(l), Value::Date(r)) => Ok(l.cmp(r)),
+        (Value::Timestamp(l), Value::Timestamp(r)) => Ok(l.cmp(r)),
+        // numbers of different types, or a date and a timestamp, converted to the same type
+        _ => match DataType::of(left).zip(DataType::of(right)).and_then(|(l, r)| l.common(r).filter(|_| l != r)) {
+            Some(common) => compare(&cast(left.clone(), common)?, &cast(right.clone(), common)?),
+            None => Err(Error::TypeMismatch(format!("cannot compare {:?} with {:?}", left, right))),
+        },
+    }
+}
+
+pub fn eval_binary(op: BinaryOp, left: Value, right: Value) -> Result<Value, Error> {
+    use BinaryOp::*;
+    match op {
+        And | Or => {
+            let as_bool = |v: &Value| match v {
+                Value::Bool(b) => Ok(Some(*b)),
+                Value::Null => Ok(None),
+                v => Err(Error::TypeMismatch(format!("invalid operand for {:?}: {:?}", op, v))),
+            };
+            let (l, r) = (as_bool(&left)?, as_bool(&right)?);
+            // three-valued logic: the dominant value wins over NULL
+            let dominant = op == Or;
+            Ok(match (l, r) {
+                (Some(l), _) if l == dominant => Value::Bool(dominant),
+                (_, Some(r)) if r == dominant => Value::Bool(dominant),
+                (Some(_), Some(_)) => Value::Bool(!dominant),
+                _ => Value::Null,
+            })
+        }
+        _ if left.is_null() || right.is_null() => Ok(Value::Null),
+        Eq | NotEq | Lt | LtEq | Gt | GtEq => {
+            let ordering = compare(&left, &right)?;
+            Ok(Value::Bool(match op {
+                Eq => ordering == Ordering::Equal,
+                NotEq => ordering != Ordering::Equal,
+                Lt => ordering == Ordering::Less,
+                LtEq => ordering != Ordering::Greater,
+                Gt => ordering == Ordering::Greater,
+                _ => ordering != Ordering::Less,
+            }))
+        }
+        Add | Sub | Mul | Div | Mod => arithmetic(op, left, right),
+        JsonGet | JsonGetText | JsonPath | JsonPathText => {
+            if !matches!(left, Value::Json(_)) {
+                return Err(Error::TypeMismatch(format!("invalid operands for {:?}: {:?}, {:?}", op, left, right)));
+            }
+            Ok(json_path(op, &right)?.extract(&left))
+        }
+        Subscript => match (&left, &right) {
+            (Value::Array(values), Value::Int(i)) => {
+                Ok(usize::try_from(*i).ok().and_then(|i| values.get(i.checked_sub(1)?)).cloned().unwrap_or(Value::Null))
+            }
+            _ => Err(Error::TypeMismatch(format!("invalid operands for {:?}: {:?}, {:?}", op, left, right))),
+        },
+        Match => match (&left, &right) {
+            (Value::Text(s), Value::Text(query)) => Ok(Value::Bool(fulltext::matches(s, query))),
+            _ => Err(Error::TypeMismatch(format!("invalid operands for {:?}: {:?}, {:?}", op, left, right))),
+        },
+        Contains | ContainedBy | Overlaps => match (geometry::bounds(&left), geometry::bounds(&right)) {
+            (Some(l), Some(r)) => Ok(Value::Bool(match op {
+                Contains => l.contains(&r),
+                ContainedBy => r.contains(&l),
+                _ => l.intersects(&r),
+            })),
+            _ => Err(Error::TypeMismatch(format!("invalid operands for {:?}: {:?}, {:?}", op, left, right))),
+        },
+    }
+}
+
+// Integers combined are integers, and numbers of different types are converted to the same type
+// first (see `DataType::common`). Days are added to and taken from dates, and dates taken from
+// each other give the days between them.
+fn arithmetic(op: BinaryOp, left: Value, right: Value) -> Result<Value, Error> {
+    use BinaryOp::*;
+    let days = |date: i32, days: i64| i32::try_from(date as i64 + days).map(Value::Date).map_err(|_| Error::NumericOverflow);
+    let common = DataType::of(&left).zip(DataType::of(&right)).and_then(|(l, r)| l.common(r));
+    match (&left, &right) {
+        (Value::Int(l), Value::Int(r)) => {
+            if matches!(op, Div | Mod) && *r == 0 {
+                return Err(Error::DivisionByZero);
+            }
+            let result = match op {
+                Add => l.checked_add(*r),
+                Sub => l.checked_sub(*r),
+                Mul => l.checked_mul(*r),
+                Div => l.checked_div(*r),
+                _ => l.checked_rem(*r),
+            };
+            result.map(Value::Int).ok_or(Error::NumericOverflow)
+        }
+        (Value::Date(date), Value::Int(n)) if op == Add => days(*date, *n),
+        (Value::Int(n), Value::Date(date)) if op == Add => days(*date, *n),
+        (Value::Date(date), Value::Int(n)) if op == Sub => days(*date, -*n),
+        (Value::Date(l), Value::Date(r)) if op == Sub => Ok(Value::Int(*l as i64 - *r as i64)),
+        _ if matches!(common, Some(DataType::Float | DataType::Numeric)) => {
+            let common = common.unwrap();
+            match (cast(left, common)?, cast(right, common)?) {
+                (Value::Float(l), Value::Float(r)) => {
+                    if matches!(op, Div | Mod) && r.0 == 0.0 {
+                        return Err(Error::DivisionByZero);
+                    }
+                    let result = match op {
+                        Add => l.0 + r.0,
+                        Sub => l.0 - r.0,
+                        Mul => l.0 * r.0,
+                        Div => l.0 / r.0,
+                        _ => l.0 % r.0,
+                    };
+                    match result.is_infinite() && l.0.is_finite() && r.0.is_finite() {
+                        true => Err(Error::NumericOverflow),
+                        false => Ok(Value::Float(Float(result))),
+                    }
+                }
+                (Value::Numeric(l), Value::Numeric(r)) => {
+                    let result = match op {
+                        Add => l.checked_add(r),
+                        Sub => l.checked_sub(r),
+                        Mul => l.checked_mul(r),
+                        Div => l.checked_div(r).ok_or(Error::DivisionByZero)?,
+                        _ => l.checked_rem(r).ok_or(Error::DivisionByZero)?,
+                    };
+                    result.map(Value::Numeric).map_err(|_| Error::NumericOverflow)
+                }
+                _ => unreachable!(),
+            }
+        }
+        _ => Err(Error::TypeMismatch(format!("invalid operands for {:?}: {:?}, {:?}", op, left, right))),
+    }
+}
+
+// The path a JSON operator takes with `operand`, a key or an index, or a path written as text.
+pub fn json_path(op: BinaryOp, operand: &Value) -> Result<json::Path, Error> {
+    use BinaryOp::*;
+    let steps = match (op, operand) {
+        (JsonGet | JsonGetText, Value::Text(key)) => vec![key.clone()],
+        (JsonGet | JsonGetText, Value::Int(i)) => vec![i.to_string()],
+        (JsonPath | JsonPathText, Value::Text(path)) => json::parse_path(path).ok_or_else(|| Error::InvalidArgument(format!("malformed JSON path: \"{}\"", path)))?,
+        _ => return Err(Error::TypeMismatch(format!("invalid operand for {:?}: {:?}", op, operand))),
+    };
+    Ok(json::Path { steps, text: matches!(op, JsonGetText | JsonPathText) })
+}
+
+// The column an expression is of, with the path of the JSON operators of constant operands
+// taking a value out of it if there are any, e.g. #1 and `#>> '{a,b}'` of `#1 -> 'a' ->> 'b'`:
+// what an index on a column, or on the path into it, can look up. Of the expressions of either
+// the planner or the parser, `operator` taking an operator apart and `column` a column reference.
+pub fn column_path<E>(
+    expr: &E,
+    operator: &impl Fn(&E) -> Option<(&E, BinaryOp, &Value)>,
+    column: &impl Fn(&E) -> Option<usize>,
+) -> Option<(usize, Option<json::Path>)> {
+    let Some((left, op, operand)) = operator(expr) else {
+        return Some((column(expr)?, None));
+    };
+    let (c, path) = column_path(left, operator, column)?;
+    let mut path = path.unwrap_or(json::Path { steps: vec![], text: false });
+    // text has no values in it
+    if path.text {
+        return None;
+    }
+    let next = json_path(op, operand).ok()?;
+    path.steps.extend(next.steps);
+    path.text = next.text;
+    // `#> '{}'` being the column itself
+    Some((c, Some(path).filter(|path| path.text || !path.steps.is_empty())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(1));
+        let binary = |op, left, right| Expr::Binary { op, left: Box::new(left), right: Box::new(right) };
+        let tuple = vec![Value::Int(10), Value::Null];
+
+        let sum = binary(BinaryOp::Add, Expr::Column(0), Expr::Literal(Value::Int(5)));
+        assert_eq!(Value::Int(15), sum.eval(&tuple, &mut bufmgr).unwrap());
+        let unknown = binary(BinaryOp::Eq, Expr::Column(1), Expr::Literal(Value::Int(5)));
+        assert_eq!(Value::Null, unknown.eval(&tuple, &mut bufmgr).unwrap());
+        let or = binary(BinaryOp::Or, unknown, Expr::Literal(Value::Bool(true)));
+        assert_eq!(Value::Bool(true), or.eval(&tuple, &mut bufmgr).unwrap());
+
+        let in_list = Expr::InList {
+            expr: Box::new(Expr::Column(0)),
+            list: vec![Expr::Literal(Value::Int(1)), Expr::Literal(Value::Null)],
+            negated: true,
+        };
+        assert_eq!(Value::Null, in_list.eval(&tuple, &mut bufmgr).unwrap());
+
+        let div = binary(BinaryOp::Div, Expr::Column(0), Expr::Literal(Value::Int(0)));
+        assert!(matches!(div.eval(&tuple, &mut bufmgr), Err(Error::DivisionByZero)));
+        let mismatch = binary(BinaryOp::Lt, Expr::Column(0), Expr::Literal(Value::Text("a".to_string())));
+        assert!(matches!(mismatch.eval(&tuple, &mut bufmgr), Err(Error::TypeMismatch(_))));
+        assert_eq!("(#0 < 'a')", mismatch.to_string());
+        let shifted = mismatch.remap(&|i| i + 2).unwrap();
+        let mut columns = vec![];
+        shifted.columns(&mut columns);
+        assert_eq!(vec![2], columns);
+
+        for (s, pattern, expected) in [
+            ("hello", "h%o", true),
+            ("hello", "h_llo", true),
+            ("hello", "%l%l%", true),
+            ("hello", "h%x", false),
+            ("hello", "hell", false),
+            ("100%", "100\\%", true),
+            ("1000", "100\\%", false),
+            ("", "%", true),
+        ] {
+            assert_eq!(expected, like(s, pattern, false), "{} LIKE {}", s, pattern);
+        }
+        assert!(like("HeLLo", "hel%", true));
+        assert_eq!("ab%c", like_prefix("ab\\%c_d%"));
+
+        let text = |s: &str| Expr::Literal(Value::Text(s.to_string()));
+        let call = |func, args| Expr::Function { func, args };
+        let trim = Function::Trim { leading: true, trailing: false };
+        let point = |x, y| Expr::Literal(Value::Point(Point { x, y }));
+        let boxed = |(x1, y1), (x2, y2)| Value::Box(Rect::new(Point { x: x1, y: y1 }, Point { x: x2, y: y2 }));
+        for (expr, expected) in [
+            (call(Function::Length, vec![text("héllo")]), Value::Int(5)),
+            (call(Function::Upper, vec![text("abc")]), Value::Text("ABC".to_string())),
+            (call(Function::Substring, vec![text("hello"), Expr::Literal(Value::Int(0)), Expr::Literal(Value::Int(3))]), Value::Text("he".to_string())),
+            (call(Function::Substring, vec![text("hello"), Expr::Literal(Value::Int(4))]), Value::Text("lo".to_string())),
+            (call(Function::Concat, vec![text("a"), Expr::Column(1), Expr::Column(0)]), Value::Text("a10".to_string())),
+            (call(trim, vec![text("xxaxx"), text("x")]), Value::Text("axx".to_string())),
+            (call(Function::Lower, vec![Expr::Column(1)]), Value::Null),
+            (call(Function::TsRank, vec![text("Fox, fox and dog"), text("fox")]), Value::Int(2)),
+            (binary(BinaryOp::Match, text("The quick fox"), text("FOX quick")), Value::Bool(true)),
+            (binary(BinaryOp::Match, text("The quick fox"), text("fox dog")), Value::Bool(false)),
+            (call(Function::Point, vec![Expr::Column(0), Expr::Literal(Value::Int(-1))]), Value::Point(Point { x: 10, y: -1 })),
+            (call(Function::Box, vec![point(3, 0), point(0, 3)]), boxed((0, 0), (3, 3))),
+            (call(Function::Point, vec![Expr::Column(1), Expr::Column(0)]), Value::Null),
+            (binary(BinaryOp::Contains, Expr::Literal(boxed((0, 0), (3, 3))), point(3, 1)), Value::Bool(true)),
+            (binary(BinaryOp::Contains, Expr::Literal(boxed((0, 0), (3, 3))), Expr::Literal(boxed((1, 1), (4, 2)))), Value::Bool(false)),
+            (binary(BinaryOp::ContainedBy, point(3, 1), Expr::Literal(boxed((0, 0), (3, 3)))), Value::Bool(true)),
+            (binary(BinaryOp::Overlaps, Expr::Literal(boxed((0, 0), (3, 3))), Expr::Literal(boxed((3, 3), (4, 4)))), Value::Bool(true)),
+            (binary(BinaryOp::Overlaps, Expr::Literal(boxed((0, 0), (3, 3))), point(4, 0)), Value::Bool(false)),
+            (binary(BinaryOp::Overlaps, Expr::Column(1), point(4, 0)), Value::Null),
+        ] {
+            assert_eq!(expected, expr.eval(&tuple, &mut bufmgr).unwrap(), "{}", expr);
+        }
+        let negative = call(Function::Substring, vec![text("a"), Expr::Literal(Value::Int(1)), Expr::Literal(Value::Int(-1))]);
+        assert!(matches!(negative.eval(&tuple, &mut bufmgr), Err(Error::InvalidArgument(_))));
+        assert!(matches!(call(Function::Length, vec![Expr::Column(0)]).eval(&tuple, &mut bufmgr), Err(Error::TypeMismatch(_))));
+
+        assert_eq!(Value::Int(42), cast(Value::Text(" 42 ".to_string()), DataType::Integer).unwrap());
+        assert_eq!(Value::Text("10".to_string()), cast(Value::Int(10), DataType::Text).unwrap());
+        assert_eq!(Value::Bool(false), cast(Value::Text("OFF".to_string()), DataType::Boolean).unwrap());
+        assert_eq!(Value::Int(1), cast(Value::Bool(true), DataType::Integer).unwrap());
+        assert_eq!(Value::Point(Point { x: 1, y: 2 }), cast(Value::Text("(1,2)".to_string()), DataType::Point).unwrap());
+        assert_eq!(boxed((0, 0), (1, 2)), cast(Value::Text("(0,2),(1,0)".to_string()), DataType::Box).unwrap());
+        assert_eq!(boxed((1, 2), (1, 2)), cast(Value::Point(Point { x: 1, y: 2 }), DataType::Box).unwrap());
+        assert_eq!(Value::Text("(1,2),(0,0)".to_string()), cast(boxed((0, 0), (1, 2)), DataType::Text).unwrap());
+        assert!(matches!(cast(Value::Text("(1,)".to_string()), DataType::Point), Err(Error::InvalidArgument(_))));
+        assert_eq!(Value::Null, cast(Value::Null, DataType::Boolean).unwrap());
+        assert!(matches!(cast(Value::Text("1x".to_string()), DataType::Integer), Err(Error::InvalidArgument(_))));
+        let to_text = Expr::Cast { expr: Box::new(Expr::Column(0)), to: DataType::Text };
+        assert_eq!("CAST(#0 AS TEXT)", to_text.to_string());
+
+        // ANY is NULL rather than false, and ALL rather than true, if a comparison with a NULL element is
+        let int = |n: i64| Expr::Literal(Value::Int(n));
+        let array = |values: Vec<Expr>| call(Function::Array, values);
+        let quantified = |all, values| Expr::Quantified { op: BinaryOp::Lt, expr: Box::new(Expr::Column(0)), array: Box::new(array(values)), all };
+        for (expr, expected) in [
+            (quantified(false, vec![int(5), int(20)]), Value::Bool(true)),
+            (quantified(false, vec![int(5), Expr::Column(1)]), Value::Null),
+            (quantified(false, vec![]), Value::Bool(false)),
+            (quantified(true, vec![int(20), Expr::Column(1)]), Value::Null),
+            (quantified(true, vec![int(5), Expr::Column(1)]), Value::Bool(false)),
+            (quantified(true, vec![]), Value::Bool(true)),
+            (binary(BinaryOp::Subscript, array(vec![int(7), int(8)]), int(2)), Value::Int(8)),
+            (binary(BinaryOp::Subscript, array(vec![int(7)]), int(0)), Value::Null),
+        ] {
+            assert_eq!(expected, expr.eval(&tuple, &mut bufmgr).unwrap(), "{}", expr);
+        }
+        assert_eq!("#0 < ALL (ARRAY[5, #1])", quantified(true, vec![int(5), Expr::Column(1)]).to_string());
+        assert!(matches!(array(vec![int(1), text("a")]).eval(&tuple, &mut bufmgr), Err(Error::TypeMismatch(_))));
+        let texts = Value::Array(vec![Value::Text("1".to_string()), Value::Null]);
+        assert_eq!(Value::Array(vec![Value::Int(1), Value::Null]), cast(texts, DataType::Array(ElementType::Integer)).unwrap());
+    }
+}
@@ -0,0 +1,65 @@
+use super::expr::{is_true, Expr};
+use super::{BoxExecutor, Error, Estimate, Executor, PlanNode};
+use crate::buffer::BufferPoolManager;
+use crate::tuple::Tuple;
+
+// Passes through the tuples for which `predicate` is true.
+pub struct Filter {
+    pub child: Box<dyn PlanNode>,
+    pub predicate: Expr,
+}
+
+impl Filter {
+    pub fn cost(child: Estimate) -> f64 {
+        child.cost + child.rows
+    }
+}
+
+impl PlanNode for Filter {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        Ok(Box::new(ExecFilter {
+            child: self.child.start(bufmgr)?,
+            predicate: &self.predicate,
+        }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.child.ordering()
+    }
+
+    fn describe(&self) -> String {
+        format!("Filter: {}", self.predicate)
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.child.as_ref()]
+    }
+
+    fn estimate(&self) -> Estimate {
+        let child = self.child.estimate();
+        Estimate {
+            rows: child.rows * self.predicate.selectivity(),
+            cost: Filter::cost(child),
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![&mut self.child]
+    }
+}
+
+struct ExecFilter<'a> {
+    child: BoxExecutor<'a>,
+    predicate: &'a Expr,
+}
+
+impl<'a> Executor for ExecFilter<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        while let Some(tuple) = self.child.next(bufmgr)? {
+            if is_true(self.predicate, &tuple, bufmgr)? {
+                return Ok(Some(tuple));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+
+use super::{BoxExecutor, Error, Estimate, Executor, PlanNode, SeqScan};
+use crate::buffer::BufferPoolManager;
+use crate::mvcc::Visibility;
+use crate::pool::ThreadPool;
+use crate::table::{self, TableIter};
+use crate::tuple::{self, Tuple};
+
+// Pages of the table each worker is handed at a time.
+const PAGES_PER_WORKER: usize = 4;
+
+// Reads a table like `scan`, with the pages split among the parallel workers of the transaction
+// (see `BufferPoolManager::set_parallel_workers`), which run on the threads of the buffer pool
+// manager (see `BufferPoolManager::thread_pool`). The pages of each batch not in the buffer pool
+// are read from the disk by the workers at once (see `BufferPoolManager::prefetch`), and the rows
+// on them decoded and checked against the snapshot by the workers, a run of pages for each, the
+// codes in dictionary columns then being decoded in this thread, from the dictionary's pages (see
+// `dictionary`). Their rows are gathered in the order of the pages, so they come out in primary
+// key order as they would from the scan.
+//
+// Transactions seeing what isn't in their snapshot alone, with locking or in serializable, read
+// the table by themselves.
+pub struct Gather {
+    pub scan: SeqScan,
+}
+
+impl PlanNode for Gather {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        Ok(Box::new(ExecGather {
+            iter: self.scan.table.scan(bufmgr)?,
+            visibility: bufmgr.visibility(),
+            workers: bufmgr.parallel_workers(),
+            rows: VecDeque::new(),
+            done: false,
+        }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.scan.ordering()
+    }
+
+    fn as_seq_scan(&self) -> Option<&SeqScan> {
+        Some(&self.scan)
+    }
+
+    fn describe(&self) -> String {
+        "Gather".to_string()
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![&self.scan]
+    }
+
+    fn estimate(&self) -> Estimate {
+        self.scan.estimate()
+    }
+}
+
+struct ExecGather {
+    iter: TableIter,
+    visibility: Option<Visibility>,
+    workers: usize,
+    // rows gathered but not returned yet
+    rows: VecDeque<Tuple>,
+    done: bool,
+}
+
+impl Executor for ExecGather {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        let Some(visibility) = &self.visibility else {
+            return Ok(self.iter.next(bufmgr)?);
+        };
+        while self.rows.is_empty() && !self.done {
+            let batch = self.workers.max(1) * PAGES_PER_WORKER;
+            let ahead = self.iter.pages_ahead(bufmgr, batch);
+            bufmgr.prefetch(&ahead)?;
+            let mut pages = vec![];
+            while pages.len() < batch {
+                match self.iter.next_page(bufmgr)? {
+                    Some(page) => pages.push(page),
+                    None => {
+                        self.done = true;
+                        break;
+                    }
+                }
+            }
+            for mut row in decode(visibility, pages, self.workers, bufmgr.thread_pool())?.into_iter().filter(|row| self.iter.live(row)) {
+                self.iter.decode(bufmgr, &mut row)?;
+                self.rows.push_back(row);
+            }
+        }
+        Ok(self.rows.pop_front())
+    }
+}
+
+// The rows seen on `pages`, in order, decoded by up to `workers` threads of `pool`.
+fn decode(visibility: &Visibility, pages: Vec<Vec<Vec<u8>>>, workers: usize, pool: &ThreadPool) -> Result<Vec<Tuple>, tuple::Error> {
+    let rows_of = |pages: &[Vec<Vec<u8>>]| -> Result<Vec<Tuple>, tuple::Error> {
+        let mut rows = vec![];
+        for versions in pages.iter().flatten() {
+            rows.extend(table::visible_in(visibility, versions)?);
+        }
+        Ok(rows)
+    };
+    if workers <= 1 || pages.len() <= 1 {
+        return rows_of(&pages);
+    }
+    let chunk = pages.len().div_ceil(workers);
+    let results = pool.map(pages.chunks(chunk), rows_of);
+    let mut rows = vec![];
+    for result in results {
+        rows.extend(result?);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::mvcc::Isolation;
+    use crate::query::explain::explain;
+    use crate::table::Table;
+    use crate::tuple::Value;
+    use crate::wal::{Wal, DEFAULT_SEGMENT_SIZE};
+    use tempfile::{tempdir, tempfile};
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let wal_dir = tempdir().unwrap();
+        let wal = Wal::open(wal_dir.path(), DEFAULT_SEGMENT_SIZE).unwrap();
+        let mut bufmgr = BufferPoolManager::with_wal(disk, BufferPool::new(16), wal).unwrap();
+        let table = Table::create(&mut bufmgr, 1).unwrap();
+        let row = |i: i64| vec![Value::Int(i), Value::Text(format!("{:0100}", i))];
+        for i in 0..2000 {
+            table.insert(&mut bufmgr, &row(i)).unwrap();
+        }
+        bufmgr.commit().unwrap();
+        let plan = Gather { scan: SeqScan { table: table.clone(), name: "t".to_string(), rows: 2000.0 } };
+        let lines: Vec<_> = explain(&plan).iter().map(|line| line.split("  (").next().unwrap().to_string()).collect();
+        assert_eq!(vec!["Gather", "-> Seq Scan on t"], lines);
+        let run = |bufmgr: &mut BufferPoolManager| {
+            let mut exec = plan.start(bufmgr).unwrap();
+            let mut rows = vec![];
+            while let Some(row) = exec.next(bufmgr).unwrap() {
+                rows.push(row);
+            }
+            bufmgr.commit().unwrap();
+            rows
+        };
+
+        // the rows come out in order however many workers there are, those the snapshot doesn't
+        // see left out
+        assert!(table.delete(&mut bufmgr, &[Value::Int(7)]).unwrap());
+        let deleting = bufmgr.switch(Default::default());
+        let expected: Vec<_> = (0..2000).map(row).collect();
+        for workers in [0, 1, 3, 8] {
+            bufmgr.set_parallel_workers(workers);
+            assert_eq!(expected, run(&mut bufmgr));
+        }
+        bufmgr.switch(deleting);
+        bufmgr.commit().unwrap();
+        let expected: Vec<_> = (0..2000).filter(|&i| i != 7).map(row).collect();
+        bufmgr.set_parallel_workers(4);
+        assert_eq!(expected, run(&mut bufmgr));
+        // and in serializable too, where the table is read in this thread
+        bufmgr.begin(Isolation::Serializable).unwrap();
+        bufmgr.set_parallel_workers(4);
+        assert!(bufmgr.visibility().is_none());
+        assert_eq!(expected, run(&mut bufmgr));
+    }
+}
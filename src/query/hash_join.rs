@@ -0,0 +1,356 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::spill::{SpillReader, SpillRun, SpillWriter};
+use super::{equi_join_rows, has_null, memory_of, partition_of, project, BoxExecutor, Error, Estimate, Executor, Interrupts, PlanNode};
+use crate::buffer::{BufferPoolManager, MemoryReservation};
+use crate::pool::ThreadPool;
+use crate::tuple::{Tuple, Value};
+
+// Inner equi-join. The right input is the build side and the left input is probed against it.
+// Output tuples are the left columns followed by the right columns.
+//
+// If the build side has more than `max_build_rows` tuples, or more than fit in the memory budget
+// of the statement, both inputs are hash partitioned into `num_partitions` spill runs (grace hash
+// join) and joined one partition pair at a time, failing if a partition doesn't fit either.
+// Rows whose key contains NULL never match.
+//
+// With parallel workers (see `BufferPoolManager::set_parallel_workers`), running on the threads
+// of the buffer pool manager (see `BufferPoolManager::thread_pool`), and a build side which fits
+// in memory, the hash table is hash partitioned, each worker hashing a partition. The left
+// rows are then probed a batch at a time, split among the workers, and the joined rows come out
+// in the order of the left rows as they would otherwise.
+pub struct HashJoin {
+    pub left: Box<dyn PlanNode>,
+    pub right: Box<dyn PlanNode>,
+    pub left_keys: Vec<usize>,
+    pub right_keys: Vec<usize>,
+    pub max_build_rows: usize,
+    pub num_partitions: usize,
+}
+
+// Left rows probed at a time in parallel.
+const PARALLEL_BATCH_ROWS: usize = 4096;
+
+impl HashJoin {
+    pub fn cost(left: Estimate, right: Estimate, max_build_rows: usize) -> f64 {
+        // rows are hashed on both sides, and the right ones inserted into the hash table as well
+        let mut cost = left.cost + right.cost + left.rows + 2.0 * right.rows;
+        if right.rows > max_build_rows as f64 {
+            // both inputs are written to and read back from spill runs
+            cost += 2.0 * (left.rows + right.rows);
+        }
+        cost
+    }
+}
+
+impl PlanNode for HashJoin {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let mut build = self.right.start(bufmgr)?;
+        let mut table = HashTable::default();
+        let mut partitions: Option<Vec<SpillWriter>> = None;
+        let mut interrupts = Interrupts::default();
+
+        while let Some(tuple) = build.next(bufmgr)? {
+            interrupts.check(bufmgr)?;
+            let key = project(&tuple, &self.right_keys);
+            if has_null(&key) {
+                continue;
+            }
+            match &mut partitions {
+                Some(writers) => {
+                    push_partitioned(bufmgr, writers, &key, &tuple)?;
+                }
+                None if table.len < self.max_build_rows && bufmgr.reserve_memory(&mut table.memory, memory_of(&tuple)).is_ok() => {
+                    table.insert(key, tuple)
+                }
+                None => {
+                    // The build side does not fit in memory. Move what we have so far to disk
+                    // and keep partitioning the rest.
+                    let mut writers: Vec<_> = (0..self.num_partitions.max(1)).map(|_| SpillWriter::new()).collect();
+                    for (key, tuples) in table.map.drain() {
+                        for tuple in tuples {
+                            push_partitioned(bufmgr, &mut writers, &key, &tuple)?;
+                        }
+                    }
+                    push_partitioned(bufmgr, &mut writers, &key, &tuple)?;
+                    table.len = 0;
+                    table.memory.release();
+                    partitions = Some(writers);
+                }
+            }
+        }
+
+        let workers = bufmgr.parallel_workers();
+        let mut probe = self.left.start(bufmgr)?;
+        let (probe, pending_partitions) = match partitions {
+            None => {
+                if workers > 1 {
+                    table.partition(workers, bufmgr.thread_pool());
+                }
+                (Probe::Child(probe), VecDeque::new())
+            }
+            Some(build_writers) => {
+                let mut probe_writers: Vec<_> = build_writers.iter().map(|_| SpillWriter::new()).collect();
+                while let Some(tuple) = probe.next(bufmgr)? {
+                    interrupts.check(bufmgr)?;
+                    let key = project(&tuple, &self.left_keys);
+                    if has_null(&key) {
+                        continue;
+                    }
+                    push_partitioned(bufmgr, &mut probe_writers, &key, &tuple)?;
+                }
+                let pending = build_writers
+                    .into_iter()
+                    .zip(probe_writers)
+                    .map(|(build, probe)| (build.finish(), probe.finish()))
+                    .collect();
+                (Probe::Done, pending)
+            }
+        };
+
+        Ok(Box::new(ExecHashJoin {
+            left_keys: &self.left_keys,
+            right_keys: &self.right_keys,
+            workers,
+            table,
+            probe,
+            pending_partitions,
+            output: VecDeque::new(),
+            interrupts,
+        }))
+    }
+
+    fn describe(&self) -> String {
+        format!("Hash Join (keys: {:?} = {:?})", self.left_keys, self.right_keys)
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.left.as_ref(), self.right.as_ref()]
+    }
+
+    fn estimate(&self) -> Estimate {
+        let (left, right) = (self.left.estimate(), self.right.estimate());
+        Estimate {
+            rows: equi_join_rows(left, right),
+            cost: HashJoin::cost(left, right, self.max_build_rows),
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![&mut self.left, &mut self.right]
+    }
+}
+
+#[derive(Default)]
+struct HashTable {
+    map: HashMap<Tuple, Vec<Tuple>>,
+    len: usize,
+    // reserved for the tuples in `map`
+    memory: MemoryReservation,
+    // the entries of `map` once hash partitioned among parallel workers
+    partitions: Vec<HashMap<Tuple, Vec<Tuple>>>,
+}
+
+impl HashTable {
+    fn insert(&mut self, key: Tuple, tuple: Tuple) {
+        self.map.entry(key).or_default().push(tuple);
+        self.len += 1;
+    }
+
+    fn get(&self, key: &[Value]) -> Option<&Vec<Tuple>> {
+        match self.partitions.len() {
+            0 => self.map.get(key),
+            n => self.partitions[partition_of(key, n)].get(key),
+        }
+    }
+
+    // Moves the entries into `n` partitions, each hashed by a worker of its own on `pool`.
+    fn partition(&mut self, n: usize, pool: &ThreadPool) {
+        let mut entries: Vec<Vec<_>> = (0..n).map(|_| vec![]).collect();
+        for (key, tuples) in self.map.drain() {
+            entries[partition_of(&key, n)].push((key, tuples));
+        }
+        self.partitions = pool.map(entries, |entries| entries.into_iter().collect());
+    }
+
+    // The left rows joined with their matches, in order.
+    fn probe(&self, lefts: &[Tuple], left_keys: &[usize]) -> Vec<Tuple> {
+        let mut output = vec![];
+        for left in lefts {
+            if let Some(matches) = self.get(&project(left, left_keys)) {
+                for right in matches {
+                    let mut tuple = left.clone();
+                    tuple.extend_from_slice(right);
+                    output.push(tuple);
+                }
+            }
+        }
+        output
+    }
+}
+
+enum Probe<'a> {
+    Child(BoxExecutor<'a>),
+    Run(SpillReader),
+    Done,
+}
+
+struct ExecHashJoin<'a> {
+    left_keys: &'a [usize],
+    right_keys: &'a [usize],
+    workers: usize,
+    table: HashTable,
+    probe: Probe<'a>,
+    // (build, probe) partition pairs which are not joined yet
+    pending_partitions: VecDeque<(SpillRun, SpillRun)>,
+    output: VecDeque<Tuple>,
+    interrupts: Interrupts,
+}
+
+impl<'a> ExecHashJoin<'a> {
+    // Loads the next partition pair. Returns false if there are no partitions left.
+    fn next_partition(&mut self, bufmgr: &mut BufferPoolManager) -> Result<bool, Error> {
+        let (build, probe) = match self.pending_partitions.pop_front() {
+            Some(pair) => pair,
+            None => return Ok(false),
+        };
+        // the memory of the last one given back as it's dropped
+        self.table = HashTable::default();
+        let mut reader = build.reader();
+        while let Some(tuple) = reader.next(bufmgr)? {
+            self.interrupts.check(bufmgr)?;
+            bufmgr.reserve_memory(&mut self.table.memory, memory_of(&tuple))?;
+            self.table.insert(project(&tuple, self.right_keys), tuple);
+        }
+        self.probe = Probe::Run(probe.reader());
+        Ok(true)
+    }
+
+    // Ends the join, giving back the memory of the hash table.
+    fn finish(&mut self) {
+        self.probe = Probe::Done;
+        self.table = HashTable::default();
+    }
+}
+
+impl<'a> Executor for ExecHashJoin<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        loop {
+            if let Some(tuple) = self.output.pop_front() {
+                return Ok(Some(tuple));
+            }
+            if let (Probe::Child(exec), false) = (&mut self.probe, self.table.partitions.is_empty()) {
+                let mut lefts = vec![];
+                while lefts.len() < PARALLEL_BATCH_ROWS {
+                    self.interrupts.check(bufmgr)?;
+                    match exec.next(bufmgr)? {
+                        Some(left) => lefts.push(left),
+                        None => break,
+                    }
+                }
+                if lefts.is_empty() {
+                    self.finish();
+                    return Ok(None);
+                }
+                let (table, left_keys) = (&self.table, self.left_keys);
+                let chunk = lefts.len().div_ceil(self.workers);
+                let outputs = bufmgr.thread_pool().map(lefts.chunks(chunk), |lefts| table.probe(lefts, left_keys));
+                self.output.extend(outputs.into_iter().flatten());
+                continue;
+            }
+            let left = match &mut self.probe {
+                Probe::Child(exec) => exec.next(bufmgr)?,
+                Probe::Run(reader) => reader.next(bufmgr)?,
+                Probe::Done => None,
+            };
+            self.interrupts.check(bufmgr)?;
+            let left = match left {
+                Some(left) => left,
+                None => {
+                    if self.next_partition(bufmgr)? {
+                        continue;
+                    }
+                    self.finish();
+                    return Ok(None);
+                }
+            };
+            self.output.extend(self.table.probe(std::slice::from_ref(&left), self.left_keys));
+        }
+    }
+}
+
+fn push_partitioned(
+    bufmgr: &mut BufferPoolManager,
+    writers: &mut [SpillWriter],
+    key: &[Value],
+    tuple: &[Value],
+) -> Result<(), Error> {
+    let n = partition_of(key, writers.len());
+    writers[n].push(bufmgr, tuple)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::query::Values;
+    use tempfile::tempfile;
+
+    fn run(max_build_rows: usize, workers: usize, memory_budget: Option<usize>) -> Vec<Tuple> {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(4));
+        bufmgr.set_parallel_workers(workers);
+        bufmgr.set_memory_budget(memory_budget);
+        let left = (0..100).map(|i| vec![Value::Int(i), Value::Int(i % 10)]).collect();
+        let mut right: Vec<_> = (0..30).map(|i| vec![Value::Int(i % 15), Value::Text(format!("r{}", i))]).collect();
+        right.push(vec![Value::Null, Value::Text("null".to_string())]);
+        let join = HashJoin {
+            left: Box::new(Values { rows: left }),
+            right: Box::new(Values { rows: right }),
+            left_keys: vec![1],
+            right_keys: vec![0],
+            max_build_rows,
+            num_partitions: 3,
+        };
+        let mut exec = join.start(&mut bufmgr).unwrap();
+        let mut result = vec![];
+        while let Some(tuple) = exec.next(&mut bufmgr).unwrap() {
+            result.push(tuple);
+        }
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn test() {
+        let in_memory = run(usize::MAX, 0, None);
+        // each left row matches the two right rows with the same key
+        assert_eq!(200, in_memory.len());
+        assert!(in_memory.iter().all(|t| t[1] == t[2]));
+        assert_eq!(in_memory, run(5, 0, None));
+        // in parallel, and spilled with the build side too big to be partitioned among workers
+        assert_eq!(in_memory, run(usize::MAX, 3, None));
+        assert_eq!(in_memory, run(5, 3, None));
+        // spilled when the build side doesn't fit in the memory budget, which each partition has to
+        let row_size = memory_of(&[Value::Int(0), Value::Text("r10".to_string())]);
+        assert_eq!(in_memory, run(usize::MAX, 0, Some(15 * row_size)));
+        // the memory of the hash table given back when the join ends, or is dropped before
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(4));
+        let join = HashJoin {
+            left: Box::new(Values { rows: vec![vec![Value::Int(1)]] }),
+            right: Box::new(Values { rows: vec![vec![Value::Int(1)], vec![Value::Int(2)]] }),
+            left_keys: vec![0],
+            right_keys: vec![0],
+            max_build_rows: usize::MAX,
+            num_partitions: 3,
+        };
+        let mut exec = join.start(&mut bufmgr).unwrap();
+        assert_eq!(2 * memory_of(&[Value::Int(1)]), bufmgr.memory_used());
+        while exec.next(&mut bufmgr).unwrap().is_some() {}
+        assert_eq!(0, bufmgr.memory_used());
+        drop(join.start(&mut bufmgr).unwrap());
+        assert_eq!(0, bufmgr.memory_used());
+    }
+}
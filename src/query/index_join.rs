@@ -0,0 +1,243 @@
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+
+use super::{has_null, memory_of, project, BoxExecutor, Error, Estimate, Executor, Interrupts, PlanNode};
+use crate::buffer::{BufferPoolManager, MemoryReservation};
+use crate::table::{Access, Table};
+use crate::tuple::Tuple;
+
+// Inner equi-join which looks up the matching rows of `table` through `access` for each outer row
+// instead of scanning the table. `outer_keys` are the outer columns compared with the leading key
+// columns of `access`, in the key column order.
+// Output tuples are the outer columns followed by the table columns.
+//
+// The join is chosen for few outer rows, so once there turn out to be ADAPTIVE_FACTOR times more
+// than estimated, and the lookups so far have cost more than reading the table would, the table is
+// hashed by its key columns, and the rest of the outer rows are probed against the hash table
+// instead, as a hash join would. That is unless the table has more than `max_build_rows` rows, or
+// more than fit in the memory budget of the statement, or `access` keys on expressions, in which
+// case the lookups go on. The rows come out in the order of the outer rows either way.
+pub struct IndexNestedLoopJoin {
+    pub outer: Box<dyn PlanNode>,
+    pub table: Table,
+    // name of the table, for EXPLAIN
+    pub name: String,
+    // estimated number of rows in the table
+    pub table_rows: f64,
+    pub access: Access,
+    pub outer_keys: Vec<usize>,
+    pub max_build_rows: usize,
+    // whether the last run switched to a hash table, for EXPLAIN ANALYZE
+    pub switched: Cell<bool>,
+}
+
+// How many times more outer rows than estimated make the join switch to a hash table.
+pub const ADAPTIVE_FACTOR: f64 = 10.0;
+
+impl IndexNestedLoopJoin {
+    // Cost of finding `rows` matches for the outer rows in a table of `table_rows` rows.
+    pub fn cost(outer: Estimate, table_rows: f64, rows: f64) -> f64 {
+        outer.cost + outer.rows * table_rows.max(2.0).log2() + rows
+    }
+}
+
+impl PlanNode for IndexNestedLoopJoin {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        self.switched.set(false);
+        let keys = self.table.key_expressions(self.access);
+        let keys = &keys[..self.outer_keys.len()];
+        Ok(Box::new(ExecIndexNestedLoopJoin {
+            plan: self,
+            outer: self.outer.start(bufmgr)?,
+            output: VecDeque::new(),
+            switch_after: match keys.iter().all(|(_, path)| path.is_none()) {
+                true => Some(ADAPTIVE_FACTOR * self.outer.estimate().rows.max(1.0)),
+                false => None,
+            },
+            key_columns: keys.iter().map(|(column, _)| *column).collect(),
+            probed: 0,
+            hashed: None,
+            interrupts: Interrupts::default(),
+        }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.outer.ordering()
+    }
+
+    fn describe(&self) -> String {
+        let access = match self.access {
+            Access::PrimaryKey => "primary key".to_string(),
+            Access::Index(i) => format!("index {:?}", self.table.indexes[i].columns),
+        };
+        let switched = if self.switched.get() { ", switched to hash join" } else { "" };
+        format!("Index Nested Loop Join on {} using {} (keys: {:?}{})", self.name, access, self.outer_keys, switched)
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.outer.as_ref()]
+    }
+
+    fn estimate(&self) -> Estimate {
+        let outer = self.outer.estimate();
+        // a full primary key matches at most one row
+        let unique = self.access == Access::PrimaryKey && self.outer_keys.len() == self.table.num_key_elems;
+        let matches = if unique { 1.0 } else { (self.table_rows / 100.0).max(1.0) };
+        let rows = outer.rows * matches;
+        Estimate {
+            rows,
+            cost: IndexNestedLoopJoin::cost(outer, self.table_rows, rows),
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![&mut self.outer]
+    }
+}
+
+// The table rows by key, with the memory reserved for them.
+type Hashed = (HashMap<Tuple, Vec<Tuple>>, MemoryReservation);
+
+struct ExecIndexNestedLoopJoin<'a> {
+    plan: &'a IndexNestedLoopJoin,
+    outer: BoxExecutor<'a>,
+    output: VecDeque<Tuple>,
+    // outer rows probed beyond which the join may switch to a hash table, None once it may not
+    switch_after: Option<f64>,
+    // the table columns compared with `outer_keys`
+    key_columns: Vec<usize>,
+    probed: usize,
+    // the table rows by key, once switched
+    hashed: Option<Hashed>,
+    interrupts: Interrupts,
+}
+
+impl<'a> ExecIndexNestedLoopJoin<'a> {
+    // Hashes the table rows by their key columns, giving up if there are too many of them.
+    fn hash(&self, bufmgr: &mut BufferPoolManager) -> Result<Option<Hashed>, Error> {
+        let mut map: HashMap<Tuple, Vec<Tuple>> = HashMap::new();
+        let (mut len, mut memory) = (0, MemoryReservation::default());
+        let mut rows = self.plan.table.scan(bufmgr)?;
+        while let Some(row) = rows.next(bufmgr)? {
+            let key = project(&row, &self.key_columns);
+            if has_null(&key) {
+                continue;
+            }
+            if len >= self.plan.max_build_rows || bufmgr.reserve_memory(&mut memory, memory_of(&row)).is_err() {
+                return Ok(None);
+            }
+            len += 1;
+            map.entry(key).or_default().push(row);
+        }
+        Ok(Some((map, memory)))
+    }
+}
+
+impl<'a> Executor for ExecIndexNestedLoopJoin<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        loop {
+            if let Some(tuple) = self.output.pop_front() {
+                return Ok(Some(tuple));
+            }
+            self.interrupts.check(bufmgr)?;
+            let outer = match self.outer.next(bufmgr)? {
+                Some(outer) => outer,
+                None => {
+                    self.hashed = None;
+                    return Ok(None);
+                }
+            };
+            let key = project(&outer, &self.plan.outer_keys);
+            if has_null(&key) {
+                continue;
+            }
+            self.probed += 1;
+            let lookups = self.probed as f64 * self.plan.table_rows.max(2.0).log2();
+            if self.switch_after.is_some_and(|after| self.probed as f64 > after && lookups > self.plan.table_rows) {
+                self.switch_after = None;
+                self.hashed = self.hash(bufmgr)?;
+                self.plan.switched.set(self.hashed.is_some());
+            }
+            let inners = match &self.hashed {
+                Some((map, _)) => map.get(&key).cloned().unwrap_or_default(),
+                None => self.plan.table.lookup(bufmgr, self.plan.access, &key)?,
+            };
+            for inner in inners {
+                let mut tuple = outer.clone();
+                tuple.extend(inner);
+                self.output.push_back(tuple);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::query::{equi_join, Estimated, SeqScan, Values};
+    use crate::table::IndexKind;
+    use crate::tuple::Value;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(8));
+        let mut table = Table::create(&mut bufmgr, 1).unwrap();
+        for i in 0..50 {
+            table.insert(&mut bufmgr, &[Value::Int(i), Value::Int(i % 5)]).unwrap();
+        }
+        table.create_index(&mut bufmgr, vec![1], vec![], IndexKind::BTree).unwrap();
+        let outer = vec![vec![Value::Int(3)], vec![Value::Null], vec![Value::Int(9)]];
+
+        let join = equi_join(
+            Box::new(Values { rows: outer }),
+            Box::new(SeqScan {
+                table: table.clone(),
+                name: "t".to_string(),
+                rows: 50.0,
+            }),
+            vec![0],
+            vec![1],
+            usize::MAX,
+        );
+        let run = |join: &dyn PlanNode, bufmgr: &mut BufferPoolManager| {
+            let mut exec = join.start(bufmgr).unwrap();
+            let mut result = vec![];
+            while let Some(tuple) = exec.next(bufmgr).unwrap() {
+                result.push(tuple);
+            }
+            result
+        };
+        let expected: Vec<_> = (0..10).map(|i| vec![Value::Int(3), Value::Int(i * 5 + 3), Value::Int(3)]).collect();
+        assert_eq!(expected, run(join.as_ref(), &mut bufmgr));
+
+        // far more outer rows than estimated switch the join to a hash table part way, with the
+        // same rows coming out, unless the table doesn't fit in one
+        let outer: Vec<_> = (0..40).map(|i| vec![Value::Int(i % 7)]).collect();
+        let join = |max_build_rows| IndexNestedLoopJoin {
+            outer: Box::new(Estimated { child: Box::new(Values { rows: outer.clone() }), rows: 1.0 }),
+            table: table.clone(),
+            name: "t".to_string(),
+            table_rows: 50.0,
+            access: Access::Index(0),
+            outer_keys: vec![0],
+            max_build_rows,
+            switched: Cell::new(false),
+        };
+        let (adaptive, fixed) = (join(usize::MAX), join(49));
+        let result = run(&adaptive, &mut bufmgr);
+        assert!(adaptive.switched.get());
+        assert!(adaptive.describe().ends_with("(keys: [0], switched to hash join)"));
+        assert_eq!(result, run(&fixed, &mut bufmgr));
+        assert!(!fixed.switched.get());
+        let expected = outer.iter().filter(|o| o[0] != Value::Int(5) && o[0] != Value::Int(6)).count() * 10;
+        assert_eq!(expected, result.len());
+        assert!(result.iter().all(|t| t[0] == t[2]));
+        bufmgr.set_memory_budget(Some(100));
+        assert_eq!(result, run(&adaptive, &mut bufmgr));
+        assert!(!adaptive.switched.get());
+    }
+}
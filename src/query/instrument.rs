@@ -0,0 +1,246 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use super::{BoxExecutor, Error, Estimate, Executor, PlanNode, SeqScan, Values};
+use crate::buffer::{BufferPoolManager, BufferStats};
+#[cfg(feature = "tracing")]
+use crate::trace;
+use crate::tuple::Tuple;
+
+// What a plan node did, summed over all the times it was run.
+// Time and buffer accesses include those of the node's inputs.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct NodeStats {
+    pub rows: u64,
+    pub loops: u64,
+    pub elapsed: Duration,
+    pub buffers: BufferStats,
+}
+
+// Wraps a plan node to collect its NodeStats for EXPLAIN ANALYZE.
+// Anything else is delegated to the wrapped node.
+pub struct Instrumented {
+    node: Box<dyn PlanNode>,
+    stats: Cell<NodeStats>,
+}
+
+// Instruments every node of the plan tree. Plans of subqueries inside expressions are left as they are.
+pub fn instrument(mut plan: Box<dyn PlanNode>) -> Box<dyn PlanNode> {
+    for child in plan.children_mut() {
+        let node = std::mem::replace(child, Box::new(Values { rows: vec![] }));
+        *child = instrument(node);
+    }
+    Box::new(Instrumented {
+        node: plan,
+        stats: Cell::new(NodeStats::default()),
+    })
+}
+
+// Clears the statistics of every instrumented node of the plan tree.
+pub fn reset_stats(plan: &dyn PlanNode) {
+    if let Some(stats) = plan.stats() {
+        stats.set(NodeStats::default());
+    }
+    for child in plan.children() {
+        reset_stats(child);
+    }
+}
+
+// Runs `f`, adding the time and the buffer accesses it took to `stats`.
+fn measure<T>(stats: &Cell<NodeStats>, bufmgr: &mut BufferPoolManager, f: impl FnOnce(&mut BufferPoolManager) -> T) -> T {
+    let buffers = bufmgr.stats();
+    let started = Instant::now();
+    let result = f(bufmgr);
+    let mut s = stats.get();
+    s.elapsed += started.elapsed();
+    s.buffers.hits += bufmgr.stats().hits - buffers.hits;
+    s.buffers.misses += bufmgr.stats().misses - buffers.misses;
+    stats.set(s);
+    result
+}
+
+impl PlanNode for Instrumented {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let mut s = self.stats.get();
+        s.loops += 1;
+        self.stats.set(s);
+        let exec = measure(&self.stats, bufmgr, |bufmgr| self.node.start(bufmgr))?;
+        Ok(Box::new(ExecInstrumented { exec, stats: &self.stats }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.node.ordering()
+    }
+
+    fn as_seq_scan(&self) -> Option<&SeqScan> {
+        self.node.as_seq_scan()
+    }
+
+    fn describe(&self) -> String {
+        self.node.describe()
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        self.node.children()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        self.node.children_mut()
+    }
+
+    fn estimate(&self) -> Estimate {
+        self.node.estimate()
+    }
+
+    fn stats(&self) -> Option<&Cell<NodeStats>> {
+        Some(&self.stats)
+    }
+}
+
+struct ExecInstrumented<'a> {
+    exec: BoxExecutor<'a>,
+    stats: &'a Cell<NodeStats>,
+}
+
+impl<'a> Executor for ExecInstrumented<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        let exec = &mut self.exec;
+        let tuple = measure(self.stats, bufmgr, |bufmgr| exec.next(bufmgr))?;
+        if tuple.is_some() {
+            let mut s = self.stats.get();
+            s.rows += 1;
+            self.stats.set(s);
+        }
+        Ok(tuple)
+    }
+}
+
+// Wraps every node of the plan tree to trace its operators: an event when each starts, and one
+// when it's done of the rows it produced and the time spent in it, its inputs' included.
+#[cfg(feature = "tracing")]
+pub fn trace(mut plan: Box<dyn PlanNode>) -> Box<dyn PlanNode> {
+    for child in plan.children_mut() {
+        let node = std::mem::replace(child, Box::new(Values { rows: vec![] }));
+        *child = trace(node);
+    }
+    Box::new(Traced { node: plan })
+}
+
+#[cfg(feature = "tracing")]
+struct Traced {
+    node: Box<dyn PlanNode>,
+}
+
+#[cfg(feature = "tracing")]
+impl PlanNode for Traced {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        trace::event!(Debug, "start", operator = self.node.describe());
+        let exec = self.node.start(bufmgr)?;
+        // timed only if it's traced
+        let elapsed = trace::enabled!(Debug).then_some(Duration::ZERO);
+        Ok(Box::new(ExecTraced { exec, node: self.node.as_ref(), rows: 0, elapsed }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.node.ordering()
+    }
+
+    fn as_seq_scan(&self) -> Option<&SeqScan> {
+        self.node.as_seq_scan()
+    }
+
+    fn describe(&self) -> String {
+        self.node.describe()
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        self.node.children()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        self.node.children_mut()
+    }
+
+    fn estimate(&self) -> Estimate {
+        self.node.estimate()
+    }
+
+    fn stats(&self) -> Option<&Cell<NodeStats>> {
+        self.node.stats()
+    }
+}
+
+#[cfg(feature = "tracing")]
+struct ExecTraced<'a> {
+    exec: BoxExecutor<'a>,
+    node: &'a dyn PlanNode,
+    rows: u64,
+    elapsed: Option<Duration>,
+}
+
+#[cfg(feature = "tracing")]
+impl Executor for ExecTraced<'_> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        let started = self.elapsed.map(|_| Instant::now());
+        let tuple = self.exec.next(bufmgr)?;
+        if let (Some(elapsed), Some(started)) = (&mut self.elapsed, started) {
+            *elapsed += started.elapsed();
+        }
+        self.rows += tuple.is_some() as u64;
+        Ok(tuple)
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for ExecTraced<'_> {
+    fn drop(&mut self) {
+        if let Some(elapsed) = self.elapsed {
+            trace::event!(Debug, "done", operator = self.node.describe(), rows = self.rows, elapsed = elapsed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::query::expr::{BinaryOp, Expr};
+    use crate::query::{Filter, NestedLoopJoin};
+    use crate::tuple::Value;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(1));
+        let filter = Filter {
+            child: Box::new(Values { rows: (0..10).map(|i| vec![Value::Int(i)]).collect() }),
+            predicate: Expr::Binary {
+                op: BinaryOp::Lt,
+                left: Box::new(Expr::Column(0)),
+                right: Box::new(Expr::Literal(Value::Int(3))),
+            },
+        };
+        let join = NestedLoopJoin {
+            left: Box::new(Values { rows: vec![vec![Value::Int(0)]; 2] }),
+            right: Box::new(filter),
+            predicate: None,
+        };
+        let plan = instrument(Box::new(join));
+        let mut exec = plan.start(&mut bufmgr).unwrap();
+        while exec.next(&mut bufmgr).unwrap().is_some() {}
+        drop(exec);
+
+        let stats = |node: &dyn PlanNode| {
+            let s = node.stats().unwrap().get();
+            (s.rows, s.loops)
+        };
+        assert_eq!((6, 1), stats(plan.as_ref()));
+        let filter = plan.children()[1];
+        assert_eq!((3, 1), stats(filter));
+        assert_eq!((10, 1), stats(filter.children()[0]));
+        reset_stats(plan.as_ref());
+        assert_eq!((0, 0), stats(filter));
+    }
+}
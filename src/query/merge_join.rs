@@ -0,0 +1,185 @@
+use super::{equi_join_rows, has_null, project, BoxExecutor, Error, Estimate, Executor, Interrupts, PlanNode};
+use crate::buffer::BufferPoolManager;
+use crate::tuple::Tuple;
+
+// Inner equi-join of two inputs which are both sorted in ascending order of their join keys.
+// Output tuples are the left columns followed by the right columns, in the order of the left input.
+//
+// Right tuples sharing a key are buffered as a group, so duplicate keys on both sides produce
+// the full cross product of the matching groups. Rows whose key contains NULL never match.
+pub struct MergeJoin {
+    pub left: Box<dyn PlanNode>,
+    pub right: Box<dyn PlanNode>,
+    pub left_keys: Vec<usize>,
+    pub right_keys: Vec<usize>,
+}
+
+impl MergeJoin {
+    pub fn cost(left: Estimate, right: Estimate) -> f64 {
+        left.cost + right.cost + left.rows + right.rows
+    }
+}
+
+impl PlanNode for MergeJoin {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let left = self.left.start(bufmgr)?;
+        let mut right = self.right.start(bufmgr)?;
+        let right_head = right.next(bufmgr)?;
+        Ok(Box::new(ExecMergeJoin {
+            left,
+            right,
+            left_keys: &self.left_keys,
+            right_keys: &self.right_keys,
+            right_head,
+            group_key: None,
+            group: vec![],
+            current: None,
+            interrupts: Interrupts::default(),
+        }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.left_keys.clone()
+    }
+
+    fn describe(&self) -> String {
+        format!("Merge Join (keys: {:?} = {:?})", self.left_keys, self.right_keys)
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.left.as_ref(), self.right.as_ref()]
+    }
+
+    fn estimate(&self) -> Estimate {
+        let (left, right) = (self.left.estimate(), self.right.estimate());
+        Estimate {
+            rows: equi_join_rows(left, right),
+            cost: MergeJoin::cost(left, right),
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![&mut self.left, &mut self.right]
+    }
+}
+
+struct ExecMergeJoin<'a> {
+    left: BoxExecutor<'a>,
+    right: BoxExecutor<'a>,
+    left_keys: &'a [usize],
+    right_keys: &'a [usize],
+    // next right tuple which is not in `group`
+    right_head: Option<Tuple>,
+    // all right tuples whose key is `group_key`
+    group_key: Option<Tuple>,
+    group: Vec<Tuple>,
+    // left tuple being joined with `group` and the index of the next group member
+    current: Option<(Tuple, usize)>,
+    interrupts: Interrupts,
+}
+
+impl<'a> ExecMergeJoin<'a> {
+    // Advances the right input to the first tuple with key >= `key` and collects the group equal to `key`.
+    fn seek_group(&mut self, bufmgr: &mut BufferPoolManager, key: &Tuple) -> Result<(), Error> {
+        self.group.clear();
+        self.group_key = None;
+        while let Some(head) = &self.right_head {
+            let head_key = project(head, self.right_keys);
+            if !has_null(&head_key) && head_key >= *key {
+                if head_key != *key {
+                    return Ok(());
+                }
+                break;
+            }
+            self.interrupts.check(bufmgr)?;
+            self.right_head = self.right.next(bufmgr)?;
+        }
+        while let Some(head) = &self.right_head {
+            if project(head, self.right_keys) != *key {
+                break;
+            }
+            self.group.push(self.right_head.take().unwrap());
+            self.right_head = self.right.next(bufmgr)?;
+        }
+        if !self.group.is_empty() {
+            self.group_key = Some(key.clone());
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Executor for ExecMergeJoin<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        loop {
+            if let Some((left, i)) = &mut self.current {
+                if let Some(right) = self.group.get(*i) {
+                    *i += 1;
+                    self.interrupts.check(bufmgr)?;
+                    let mut tuple = left.clone();
+                    tuple.extend_from_slice(right);
+                    return Ok(Some(tuple));
+                }
+                self.current = None;
+            }
+
+            let left = match self.left.next(bufmgr)? {
+                Some(left) => left,
+                None => return Ok(None),
+            };
+            let key = project(&left, self.left_keys);
+            if has_null(&key) {
+                continue;
+            }
+            if self.group_key.as_ref() != Some(&key) {
+                self.seek_group(bufmgr, &key)?;
+            }
+            if self.group_key.is_some() {
+                self.current = Some((left, 0));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::query::{Sort, Values};
+    use crate::tuple::Value;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(4));
+        let left: Vec<_> = (0..40).map(|i| vec![Value::Int(i % 8), Value::Int(i)]).collect();
+        let mut right: Vec<_> = (0..12).map(|i| vec![Value::Text(format!("r{}", i)), Value::Int(i % 4 * 2)]).collect();
+        right.push(vec![Value::Text("null".to_string()), Value::Null]);
+
+        let mut expected = vec![];
+        for l in &left {
+            for r in &right {
+                if l[0] == r[1] {
+                    expected.push([l.clone(), r.clone()].concat());
+                }
+            }
+        }
+        expected.sort();
+
+        let join = MergeJoin {
+            left: Box::new(Sort { child: Box::new(Values { rows: left }), keys: vec![0], descending: vec![false], max_rows_in_memory: 8 }),
+            right: Box::new(Sort { child: Box::new(Values { rows: right }), keys: vec![1], descending: vec![false], max_rows_in_memory: 8 }),
+            left_keys: vec![0],
+            right_keys: vec![1],
+        };
+        let mut exec = join.start(&mut bufmgr).unwrap();
+        let mut result = vec![];
+        while let Some(tuple) = exec.next(&mut bufmgr).unwrap() {
+            result.push(tuple);
+        }
+        assert!(result.windows(2).all(|w| w[0][0] <= w[1][0]));
+        result.sort();
+        assert_eq!(expected, result);
+    }
+}
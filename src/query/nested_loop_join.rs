@@ -0,0 +1,132 @@
+use super::expr::{is_true, Expr};
+use super::{BoxExecutor, Error, Estimate, Executor, Interrupts, PlanNode};
+use crate::buffer::BufferPoolManager;
+use crate::tuple::Tuple;
+
+// Joins every left tuple with every right tuple for which `predicate` (if any) holds.
+// The right input is read once and kept in memory.
+// Output tuples are the left columns followed by the right columns.
+pub struct NestedLoopJoin {
+    pub left: Box<dyn PlanNode>,
+    pub right: Box<dyn PlanNode>,
+    pub predicate: Option<Expr>,
+}
+
+impl NestedLoopJoin {
+    pub fn cost(left: Estimate, right: Estimate) -> f64 {
+        left.cost + right.cost + left.rows * right.rows
+    }
+}
+
+impl PlanNode for NestedLoopJoin {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let mut right = self.right.start(bufmgr)?;
+        let mut right_rows = vec![];
+        let mut interrupts = Interrupts::default();
+        while let Some(tuple) = right.next(bufmgr)? {
+            interrupts.check(bufmgr)?;
+            right_rows.push(tuple);
+        }
+        Ok(Box::new(ExecNestedLoopJoin {
+            left: self.left.start(bufmgr)?,
+            right_rows,
+            predicate: self.predicate.as_ref(),
+            current: None,
+            interrupts,
+        }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.left.ordering()
+    }
+
+    fn describe(&self) -> String {
+        match &self.predicate {
+            Some(predicate) => format!("Nested Loop Join: {}", predicate),
+            None => "Nested Loop Join".to_string(),
+        }
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.left.as_ref(), self.right.as_ref()]
+    }
+
+    fn estimate(&self) -> Estimate {
+        let (left, right) = (self.left.estimate(), self.right.estimate());
+        let selectivity = self.predicate.as_ref().map_or(1.0, Expr::selectivity);
+        Estimate {
+            rows: left.rows * right.rows * selectivity,
+            cost: NestedLoopJoin::cost(left, right),
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![&mut self.left, &mut self.right]
+    }
+}
+
+struct ExecNestedLoopJoin<'a> {
+    left: BoxExecutor<'a>,
+    right_rows: Vec<Tuple>,
+    predicate: Option<&'a Expr>,
+    // left tuple being joined and the index of the next right tuple
+    current: Option<(Tuple, usize)>,
+    // counting the pairs of rows tried
+    interrupts: Interrupts,
+}
+
+impl<'a> Executor for ExecNestedLoopJoin<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        loop {
+            if let Some((left, i)) = &mut self.current {
+                while let Some(right) = self.right_rows.get(*i) {
+                    *i += 1;
+                    self.interrupts.check(bufmgr)?;
+                    let mut tuple = left.clone();
+                    tuple.extend_from_slice(right);
+                    match self.predicate {
+                        Some(predicate) if !is_true(predicate, &tuple, bufmgr)? => {}
+                        _ => return Ok(Some(tuple)),
+                    }
+                }
+            }
+            match self.left.next(bufmgr)? {
+                Some(left) => self.current = Some((left, 0)),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::query::expr::BinaryOp;
+    use crate::query::Values;
+    use crate::tuple::Value;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(1));
+        let join = NestedLoopJoin {
+            left: Box::new(Values { rows: (0..3).map(|i| vec![Value::Int(i)]).collect() }),
+            right: Box::new(Values { rows: (0..3).map(|i| vec![Value::Int(i)]).collect() }),
+            predicate: Some(Expr::Binary {
+                op: BinaryOp::Lt,
+                left: Box::new(Expr::Column(0)),
+                right: Box::new(Expr::Column(1)),
+            }),
+        };
+        let mut exec = join.start(&mut bufmgr).unwrap();
+        let mut result = vec![];
+        while let Some(tuple) = exec.next(&mut bufmgr).unwrap() {
+            result.push(tuple);
+        }
+        let pair = |l, r| vec![Value::Int(l), Value::Int(r)];
+        assert_eq!(vec![pair(0, 1), pair(0, 2), pair(1, 2)], result);
+    }
+}
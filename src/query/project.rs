@@ -0,0 +1,71 @@
+use super::expr::Expr;
+use super::{BoxExecutor, Error, Estimate, Executor, PlanNode};
+use crate::buffer::BufferPoolManager;
+use crate::tuple::Tuple;
+
+// Evaluates `exprs` on each input tuple.
+pub struct Project {
+    pub child: Box<dyn PlanNode>,
+    pub exprs: Vec<Expr>,
+}
+
+impl PlanNode for Project {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        Ok(Box::new(ExecProject {
+            child: self.child.start(bufmgr)?,
+            exprs: &self.exprs,
+        }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        // the leading sort columns which are passed through unchanged, renumbered
+        let mut ordering = vec![];
+        for column in self.child.ordering() {
+            match self.exprs.iter().position(|e| matches!(e, Expr::Column(c) if *c == column)) {
+                Some(i) => ordering.push(i),
+                None => break,
+            }
+        }
+        ordering
+    }
+
+    fn describe(&self) -> String {
+        let exprs: Vec<_> = self.exprs.iter().map(|e| e.to_string()).collect();
+        format!("Project: {}", exprs.join(", "))
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.child.as_ref()]
+    }
+
+    fn estimate(&self) -> Estimate {
+        let child = self.child.estimate();
+        Estimate {
+            rows: child.rows,
+            cost: child.cost + child.rows,
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![&mut self.child]
+    }
+}
+
+struct ExecProject<'a> {
+    child: BoxExecutor<'a>,
+    exprs: &'a [Expr],
+}
+
+impl<'a> Executor for ExecProject<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        let tuple = match self.child.next(bufmgr)? {
+            Some(tuple) => tuple,
+            None => return Ok(None),
+        };
+        let mut output = Vec::with_capacity(self.exprs.len());
+        for expr in self.exprs {
+            output.push(expr.eval(&tuple, bufmgr)?);
+        }
+        Ok(Some(output))
+    }
+}
@@ -0,0 +1,337 @@
+use std::ops::Bound;
+
+use super::expr::Expr;
+use super::{has_null, BoxExecutor, Error, Estimate, Executor, PlanNode};
+use crate::buffer::BufferPoolManager;
+use crate::columnar::Stripe;
+use crate::geometry;
+use crate::partition::KeyFilter;
+use crate::table::{Access, Table, TableIter};
+use crate::tuple::{DataType, Tuple, Value};
+use crate::zonemap::Extent;
+
+// Reads every row of a table in primary key order.
+pub struct SeqScan {
+    pub table: Table,
+    // name of the table, for EXPLAIN
+    pub name: String,
+    // estimated number of rows in the table
+    pub rows: f64,
+}
+
+impl PlanNode for SeqScan {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        Ok(Box::new(ExecSeqScan {
+            iter: self.table.scan(bufmgr)?,
+        }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.table.scan_ordering()
+    }
+
+    fn as_seq_scan(&self) -> Option<&SeqScan> {
+        Some(self)
+    }
+
+    fn describe(&self) -> String {
+        format!("Seq Scan on {}", self.name)
+    }
+
+    fn estimate(&self) -> Estimate {
+        Estimate {
+            rows: self.rows,
+            cost: self.rows,
+        }
+    }
+}
+
+// Reads every row of a columnar table (see `columnar`), of its stripes only `columns`, the others
+// being NULL, and only those the zone maps tell `conjuncts` may hold for. The conjuncts, on the
+// columns of the table, are still to be evaluated on the rows.
+pub struct ColumnarScan {
+    pub table: Table,
+    // name of the table, for EXPLAIN
+    pub name: String,
+    pub columns: Vec<usize>,
+    pub conjuncts: Vec<Expr>,
+    // of each column of the table, which the values of literals compared with it must be of
+    pub types: Vec<DataType>,
+    // estimated number of rows in the table
+    pub rows: f64,
+}
+
+impl PlanNode for ColumnarScan {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let filters: Vec<_> = self.columns.iter().map(|&c| (c, KeyFilter::new(c, self.types[c], &self.conjuncts))).collect();
+        let keep = |stripe: &Stripe| {
+            filters.iter().all(|(c, filter)| match &stripe.zones[*c] {
+                Some((min, max)) => filter.may_be_within(min, max),
+                None => true,
+            })
+        };
+        Ok(Box::new(ExecSeqScan {
+            iter: self.table.scan_columns(bufmgr, Some(self.columns.clone()), keep)?,
+        }))
+    }
+
+    fn describe(&self) -> String {
+        format!("Columnar Scan on {} ({} of {} columns)", self.name, self.columns.len(), self.types.len())
+    }
+
+    fn estimate(&self) -> Estimate {
+        // what's decoded, of the stripes
+        let read = self.columns.len().max(1) as f64 / self.types.len() as f64;
+        Estimate {
+            rows: self.rows,
+            cost: self.rows * read,
+        }
+    }
+}
+
+// Reads every row of a table in primary key order, as SeqScan does, but for the extents the zone
+// map index `index` tells `conjuncts` can't hold for (see `zonemap`). The conjuncts, on the columns
+// of the table, are still to be evaluated on the rows.
+pub struct ZoneMapScan {
+    pub table: Table,
+    // name of the table, for EXPLAIN
+    pub name: String,
+    pub index: usize,
+    pub conjuncts: Vec<Expr>,
+    // of each column of the table, which the values of literals compared with it must be of
+    pub types: Vec<DataType>,
+    // estimated number of rows in the table
+    pub rows: f64,
+}
+
+impl PlanNode for ZoneMapScan {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let columns = &self.table.indexes[self.index].columns;
+        let filters: Vec<_> = columns.iter().map(|&c| KeyFilter::new(c, self.types[c], &self.conjuncts)).collect();
+        let keep = |extent: &Extent| {
+            extent.zones.iter().zip(&filters).all(|(zone, filter)| match zone.bounds() {
+                Some((min, max)) => filter.may_be_within(min, max),
+                None => true,
+            })
+        };
+        Ok(Box::new(ExecSeqScan {
+            iter: self.table.scan_zones(bufmgr, self.index, keep)?,
+        }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.table.scan_ordering()
+    }
+
+    fn describe(&self) -> String {
+        format!("Seq Scan on {} using zone map {:?}", self.name, self.table.indexes[self.index].columns)
+    }
+
+    fn estimate(&self) -> Estimate {
+        Estimate {
+            rows: self.rows,
+            cost: self.rows,
+        }
+    }
+}
+
+// Reads the rows of a table whose leading key columns of `access` equal `keys`, and whose next
+// key column is within `range` if any, in key order. The keys and bounds can't refer to input
+// columns; they're evaluated when the scan starts.
+pub struct IndexScan {
+    pub table: Table,
+    // name of the table, for EXPLAIN
+    pub name: String,
+    pub access: Access,
+    pub keys: Vec<Expr>,
+    pub range: Option<KeyRange>,
+    // estimated number of rows in the table and found by the scan
+    pub table_rows: f64,
+    pub rows: f64,
+}
+
+pub struct KeyRange {
+    pub lower: Bound<Expr>,
+    pub upper: Bound<Expr>,
+}
+
+impl IndexScan {
+    // Cost of finding `rows` rows through `access` in a table of `table_rows` rows.
+    pub fn cost(access: Access, table_rows: f64, rows: f64) -> f64 {
+        let depth = table_rows.max(2.0).log2();
+        match access {
+            Access::PrimaryKey => depth + rows,
+            // each index entry is followed by a primary key lookup
+            Access::Index(_) => depth + rows * (1.0 + depth),
+        }
+    }
+}
+
+impl PlanNode for IndexScan {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let mut keys = vec![];
+        for key in &self.keys {
+            keys.push(key.eval(&[], bufmgr)?);
+        }
+        let eval = |bound: &Bound<Expr>, bufmgr: &mut BufferPoolManager| -> Result<Bound<_>, Error> {
+            Ok(match bound {
+                Bound::Included(expr) => Bound::Included(expr.eval(&[], bufmgr)?),
+                Bound::Excluded(expr) => Bound::Excluded(expr.eval(&[], bufmgr)?),
+                Bound::Unbounded => Bound::Unbounded,
+            })
+        };
+        let (lower, upper) = match &self.range {
+            Some(range) => (eval(&range.lower, bufmgr)?, eval(&range.upper, bufmgr)?),
+            None => (Bound::Unbounded, Bound::Unbounded),
+        };
+        let bounds: Vec<_> = [&lower, &upper].into_iter().filter_map(bound_value).cloned().collect();
+        // NULL equals nothing, and nothing is greater or less than it
+        let rows = if has_null(&keys) || has_null(&bounds) {
+            vec![]
+        } else {
+            self.table.lookup_range(bufmgr, self.access, &keys, (lower.as_ref(), upper.as_ref()))?
+        };
+        Ok(Box::new(ExecIndexScan { rows: rows.into_iter() }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.table.key_columns(self.access)
+    }
+
+    fn describe(&self) -> String {
+        let keys: Vec<_> = self.keys.iter().map(|e| e.to_string()).collect();
+        let access = match self.access {
+            Access::PrimaryKey => "primary key".to_string(),
+            Access::Index(i) => format!("index {:?}", self.table.indexes[i].columns),
+        };
+        let range = match &self.range {
+            Some(range) => {
+                let lower = match &range.lower {
+                    Bound::Included(expr) => Some(format!(">= {}", expr)),
+                    Bound::Excluded(expr) => Some(format!("> {}", expr)),
+                    Bound::Unbounded => None,
+                };
+                let upper = match &range.upper {
+                    Bound::Included(expr) => Some(format!("<= {}", expr)),
+                    Bound::Excluded(expr) => Some(format!("< {}", expr)),
+                    Bound::Unbounded => None,
+                };
+                let bounds: Vec<_> = lower.into_iter().chain(upper).collect();
+                Some(format!("range: {}", bounds.join(" AND ")))
+            }
+            None => None,
+        };
+        let keys = (!keys.is_empty()).then(|| format!("keys: {}", keys.join(", ")));
+        let conditions: Vec<_> = keys.into_iter().chain(range).collect();
+        format!("Index Scan on {} using {} ({})", self.name, access, conditions.join(", "))
+    }
+
+    fn estimate(&self) -> Estimate {
+        Estimate {
+            rows: self.rows,
+            cost: IndexScan::cost(self.access, self.table_rows, self.rows),
+        }
+    }
+}
+
+// Reads the rows of a table whose column of the full-text index `index` has every word of `query`,
+// the highest ranking first (see `Table::search`). The query can't refer to input columns; it's
+// evaluated when the scan starts.
+pub struct FullTextScan {
+    pub table: Table,
+    // name of the table, for EXPLAIN
+    pub name: String,
+    pub index: usize,
+    pub query: Expr,
+    // estimated number of rows in the table and found by the scan
+    pub table_rows: f64,
+    pub rows: f64,
+}
+
+impl PlanNode for FullTextScan {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let rows = match self.query.eval(&[], bufmgr)? {
+            // NULL matches nothing
+            Value::Null => vec![],
+            Value::Text(query) => self.table.search(bufmgr, self.index, &query)?,
+            value => return Err(Error::TypeMismatch(format!("full-text query must be text: {:?}", value))),
+        };
+        Ok(Box::new(ExecIndexScan { rows: rows.into_iter() }))
+    }
+
+    fn describe(&self) -> String {
+        format!("Full-Text Scan on {} using index {:?} (query: {})", self.name, self.table.indexes[self.index].columns, self.query)
+    }
+
+    fn estimate(&self) -> Estimate {
+        Estimate {
+            rows: self.rows,
+            cost: IndexScan::cost(Access::Index(self.index), self.table_rows, self.rows),
+        }
+    }
+}
+
+// Reads the rows of a table whose column of the R-tree index `index` is in a box intersecting the
+// box of `area`, a point or box (see `Table::search_area`), for the predicate to be checked on. Like
+// the query of a full-text scan, the area is evaluated when the scan starts.
+pub struct SpatialScan {
+    pub table: Table,
+    // name of the table, for EXPLAIN
+    pub name: String,
+    pub index: usize,
+    pub area: Expr,
+    // estimated number of rows in the table and found by the scan
+    pub table_rows: f64,
+    pub rows: f64,
+}
+
+impl PlanNode for SpatialScan {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let value = self.area.eval(&[], bufmgr)?;
+        let rows = match geometry::bounds(&value) {
+            Some(area) => self.table.search_area(bufmgr, self.index, &area)?,
+            // NULL is in no box
+            None if value == Value::Null => vec![],
+            None => return Err(Error::TypeMismatch(format!("area must be a point or box: {:?}", value))),
+        };
+        Ok(Box::new(ExecIndexScan { rows: rows.into_iter() }))
+    }
+
+    fn describe(&self) -> String {
+        format!("Spatial Scan on {} using index {:?} (area: {})", self.name, self.table.indexes[self.index].columns, self.area)
+    }
+
+    fn estimate(&self) -> Estimate {
+        Estimate {
+            rows: self.rows,
+            cost: IndexScan::cost(Access::Index(self.index), self.table_rows, self.rows),
+        }
+    }
+}
+
+fn bound_value<T>(bound: &Bound<T>) -> Option<&T> {
+    match bound {
+        Bound::Included(value) | Bound::Excluded(value) => Some(value),
+        Bound::Unbounded => None,
+    }
+}
+
+struct ExecIndexScan {
+    rows: std::vec::IntoIter<Tuple>,
+}
+
+impl Executor for ExecIndexScan {
+    fn next(&mut self, _bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        Ok(self.rows.next())
+    }
+}
+
+struct ExecSeqScan {
+    iter: TableIter,
+}
+
+impl Executor for ExecSeqScan {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        Ok(self.iter.next(bufmgr)?)
+    }
+}
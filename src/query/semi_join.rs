@@ -0,0 +1,150 @@
+use std::collections::HashSet;
+
+use super::{has_null, project, BoxExecutor, Error, Estimate, Executor, Interrupts, PlanNode};
+use crate::buffer::BufferPoolManager;
+use crate::tuple::{Tuple, Value};
+
+// Emits the left tuples which have (semi join) or don't have (anti join) a right tuple with an
+// equal key. The right keys are kept in an in-memory hash set.
+//
+// With `null_aware`, the anti join follows the semantics of `NOT IN`: nothing passes if the right
+// side contains a NULL key, and a NULL left key only passes if the right side is empty.
+// Otherwise a NULL key never matches, which is the semantics of `EXISTS` / `NOT EXISTS`.
+pub struct HashSemiJoin {
+    pub left: Box<dyn PlanNode>,
+    pub right: Box<dyn PlanNode>,
+    pub left_keys: Vec<usize>,
+    pub right_keys: Vec<usize>,
+    pub anti: bool,
+    pub null_aware: bool,
+}
+
+impl PlanNode for HashSemiJoin {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let mut right = self.right.start(bufmgr)?;
+        let mut keys = HashSet::new();
+        let mut right_is_empty = true;
+        let mut right_has_null = false;
+        let mut interrupts = Interrupts::default();
+        while let Some(tuple) = right.next(bufmgr)? {
+            interrupts.check(bufmgr)?;
+            right_is_empty = false;
+            let key = project(&tuple, &self.right_keys);
+            if has_null(&key) {
+                right_has_null = true;
+            } else {
+                keys.insert(key);
+            }
+        }
+        Ok(Box::new(ExecHashSemiJoin {
+            plan: self,
+            left: self.left.start(bufmgr)?,
+            keys,
+            right_is_empty,
+            right_has_null,
+            interrupts,
+        }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.left.ordering()
+    }
+
+    fn describe(&self) -> String {
+        let kind = match (self.anti, self.null_aware) {
+            (false, _) => "Hash Semi Join",
+            (true, false) => "Hash Anti Join",
+            (true, true) => "Hash Null Aware Anti Join",
+        };
+        format!("{} (keys: {:?} = {:?})", kind, self.left_keys, self.right_keys)
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.left.as_ref(), self.right.as_ref()]
+    }
+
+    fn estimate(&self) -> Estimate {
+        let (left, right) = (self.left.estimate(), self.right.estimate());
+        Estimate {
+            rows: left.rows * 0.5,
+            cost: left.cost + right.cost + left.rows + right.rows,
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![&mut self.left, &mut self.right]
+    }
+}
+
+struct ExecHashSemiJoin<'a> {
+    plan: &'a HashSemiJoin,
+    left: BoxExecutor<'a>,
+    keys: HashSet<Tuple>,
+    right_is_empty: bool,
+    right_has_null: bool,
+    interrupts: Interrupts,
+}
+
+impl<'a> ExecHashSemiJoin<'a> {
+    fn passes(&self, tuple: &[Value]) -> bool {
+        let key = project(tuple, &self.plan.left_keys);
+        let null_key = has_null(&key);
+        let matched = !null_key && self.keys.contains(&key);
+        if !self.plan.anti {
+            return matched;
+        }
+        if !self.plan.null_aware || self.right_is_empty {
+            return !matched;
+        }
+        !matched && !null_key && !self.right_has_null
+    }
+}
+
+impl<'a> Executor for ExecHashSemiJoin<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        while let Some(tuple) = self.left.next(bufmgr)? {
+            self.interrupts.check(bufmgr)?;
+            if self.passes(&tuple) {
+                return Ok(Some(tuple));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::query::Values;
+    use tempfile::tempfile;
+
+    fn run(right: Vec<Value>, anti: bool, null_aware: bool) -> Vec<Value> {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(1));
+        let join = HashSemiJoin {
+            left: Box::new(Values { rows: vec![vec![Value::Int(1)], vec![Value::Int(2)], vec![Value::Null]] }),
+            right: Box::new(Values { rows: right.into_iter().map(|v| vec![v]).collect() }),
+            left_keys: vec![0],
+            right_keys: vec![0],
+            anti,
+            null_aware,
+        };
+        let mut exec = join.start(&mut bufmgr).unwrap();
+        let mut result = vec![];
+        while let Some(mut tuple) = exec.next(&mut bufmgr).unwrap() {
+            result.push(tuple.remove(0));
+        }
+        result
+    }
+
+    #[test]
+    fn test() {
+        assert_eq!(vec![Value::Int(1)], run(vec![Value::Int(1), Value::Null], false, false));
+        assert_eq!(vec![Value::Int(2), Value::Null], run(vec![Value::Int(1), Value::Null], true, false));
+        assert_eq!(Vec::<Value>::new(), run(vec![Value::Int(1), Value::Null], true, true));
+        assert_eq!(vec![Value::Int(2)], run(vec![Value::Int(1)], true, true));
+        assert_eq!(vec![Value::Int(1), Value::Int(2), Value::Null], run(vec![], true, true));
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{BoxExecutor, Error, Estimate, Executor, PlanNode};
+use crate::buffer::BufferPoolManager;
+use crate::tuple::Tuple;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOperator {
+    Union,
+    Intersect,
+    Except,
+}
+
+// Emits the tuples of all children one after another (UNION ALL).
+pub struct Append {
+    pub children: Vec<Box<dyn PlanNode>>,
+}
+
+impl PlanNode for Append {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let mut children = vec![];
+        for child in &self.children {
+            children.push(child.start(bufmgr)?);
+        }
+        Ok(Box::new(ExecAppend {
+            children: children.into_iter(),
+            current: None,
+        }))
+    }
+
+    fn describe(&self) -> String {
+        "Append".to_string()
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        self.children.iter().map(|c| c.as_ref()).collect()
+    }
+
+    fn estimate(&self) -> Estimate {
+        let estimates: Vec<_> = self.children.iter().map(|c| c.estimate()).collect();
+        Estimate {
+            rows: estimates.iter().map(|e| e.rows).sum(),
+            cost: estimates.iter().map(|e| e.cost + e.rows).sum(),
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        self.children.iter_mut().collect()
+    }
+}
+
+struct ExecAppend<'a> {
+    children: std::vec::IntoIter<BoxExecutor<'a>>,
+    current: Option<BoxExecutor<'a>>,
+}
+
+impl<'a> Executor for ExecAppend<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        loop {
+            if let Some(current) = &mut self.current {
+                if let Some(tuple) = current.next(bufmgr)? {
+                    return Ok(Some(tuple));
+                }
+            }
+            match self.children.next() {
+                Some(child) => self.current = Some(child),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+// INTERSECT or EXCEPT of the left and right tuples, [ALL] according to `all`. The right tuples
+// are counted in an in-memory hash table, against which the left ones are streamed.
+// Tuples are compared as a whole, where NULLs are equal to each other.
+pub struct HashSetOp {
+    pub left: Box<dyn PlanNode>,
+    pub right: Box<dyn PlanNode>,
+    // Intersect or Except
+    pub op: SetOperator,
+    pub all: bool,
+}
+
+impl PlanNode for HashSetOp {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let mut right = self.right.start(bufmgr)?;
+        let mut counts = HashMap::new();
+        while let Some(tuple) = right.next(bufmgr)? {
+            *counts.entry(tuple).or_insert(0usize) += 1;
+        }
+        Ok(Box::new(ExecHashSetOp {
+            plan: self,
+            left: self.left.start(bufmgr)?,
+            counts,
+            emitted: HashSet::new(),
+        }))
+    }
+
+    fn describe(&self) -> String {
+        let op = match self.op {
+            SetOperator::Union => "Union",
+            SetOperator::Intersect => "Intersect",
+            SetOperator::Except => "Except",
+        };
+        format!("Hash {}{}", op, if self.all { " All" } else { "" })
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.left.as_ref(), self.right.as_ref()]
+    }
+
+    fn estimate(&self) -> Estimate {
+        let (left, right) = (self.left.estimate(), self.right.estimate());
+        Estimate {
+            rows: left.rows * 0.5,
+            cost: left.cost + right.cost + left.rows + right.rows,
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![&mut self.left, &mut self.right]
+    }
+}
+
+struct ExecHashSetOp<'a> {
+    plan: &'a HashSetOp,
+    left: BoxExecutor<'a>,
+    // right tuples not matched yet
+    counts: HashMap<Tuple, usize>,
+    // left tuples emitted so far, to emit each only once without ALL
+    emitted: HashSet<Tuple>,
+}
+
+impl<'a> Executor for ExecHashSetOp<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        while let Some(tuple) = self.left.next(bufmgr)? {
+            let count = self.counts.get_mut(&tuple);
+            let matched = match count {
+                Some(count) if *count > 0 => {
+                    // with ALL, each right tuple cancels out one left tuple
+                    if self.plan.all {
+                        *count -= 1;
+                    }
+                    true
+                }
+                _ => false,
+            };
+            let emit = matched == (self.plan.op == SetOperator::Intersect);
+            if emit && (self.plan.all || self.emitted.insert(tuple.clone())) {
+                return Ok(Some(tuple));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::query::Values;
+    use crate::tuple::Value;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(4));
+        let tuples = |values: &[i64]| values.iter().map(|&n| vec![Value::Int(n)]).collect::<Vec<_>>();
+        let run = |bufmgr: &mut BufferPoolManager, plan: &dyn PlanNode| {
+            let mut exec = plan.start(bufmgr).unwrap();
+            let mut result = vec![];
+            while let Some(tuple) = exec.next(bufmgr).unwrap() {
+                result.push(tuple);
+            }
+            result
+        };
+
+        let plan = Append { children: vec![Box::new(Values { rows: tuples(&[1, 2]) }), Box::new(Values { rows: tuples(&[2]) })] };
+        assert_eq!(tuples(&[1, 2, 2]), run(&mut bufmgr, &plan));
+
+        let (left, right) = (tuples(&[1, 1, 1, 2, 3, 3]), tuples(&[1, 1, 3, 4]));
+        for (op, all, expected) in [
+            (SetOperator::Intersect, false, tuples(&[1, 3])),
+            (SetOperator::Intersect, true, tuples(&[1, 1, 3])),
+            (SetOperator::Except, false, tuples(&[2])),
+            (SetOperator::Except, true, tuples(&[1, 2, 3])),
+        ] {
+            let plan = HashSetOp {
+                left: Box::new(Values { rows: left.clone() }),
+                right: Box::new(Values { rows: right.clone() }),
+                op,
+                all,
+            };
+            assert_eq!(expected, run(&mut bufmgr, &plan));
+        }
+    }
+}
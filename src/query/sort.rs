@@ -0,0 +1,220 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use super::spill::{SpillReader, SpillWriter};
+use super::{memory_of, project, BoxExecutor, Error, Estimate, Executor, Interrupts, PlanNode};
+use crate::buffer::{BufferPoolManager, MemoryReservation};
+use crate::tuple::{Tuple, Value};
+
+// Sorts its input by `keys`, each in ascending order unless `descending` says otherwise (stable).
+//
+// Up to `max_rows_in_memory` tuples, and as many as fit in the memory budget of the statement,
+// are sorted in memory. Larger inputs are cut into sorted spill runs which are merged while the
+// output is read (external merge sort).
+pub struct Sort {
+    pub child: Box<dyn PlanNode>,
+    pub keys: Vec<usize>,
+    // one per key
+    pub descending: Vec<bool>,
+    pub max_rows_in_memory: usize,
+}
+
+impl PlanNode for Sort {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        let mut child = self.child.start(bufmgr)?;
+        let mut chunk = vec![];
+        let mut runs = vec![];
+        // memory reserved for `chunk`
+        let mut memory = MemoryReservation::default();
+        let mut interrupts = Interrupts::default();
+        while let Some(tuple) = child.next(bufmgr)? {
+            interrupts.check(bufmgr)?;
+            let size = memory_of(&tuple);
+            if chunk.len() >= self.max_rows_in_memory.max(1) || bufmgr.reserve_memory(&mut memory, size).is_err() {
+                if !chunk.is_empty() {
+                    runs.push(self.write_run(bufmgr, &mut chunk)?);
+                }
+                memory.release();
+                bufmgr.reserve_memory(&mut memory, size)?;
+            }
+            chunk.push(tuple);
+        }
+
+        if runs.is_empty() {
+            chunk.sort_by(|a, b| self.compare(a, b));
+            return Ok(Box::new(ExecSortInMemory { rows: chunk.into_iter(), memory }));
+        }
+        if !chunk.is_empty() {
+            runs.push(self.write_run(bufmgr, &mut chunk)?);
+        }
+        memory.release();
+        let mut heap = BinaryHeap::new();
+        for (run, reader) in runs.iter_mut().enumerate() {
+            if let Some(tuple) = reader.next(bufmgr)? {
+                let key = project(&tuple, &self.keys);
+                heap.push(Reverse(MergeEntry { key, descending: &self.descending, run, tuple }));
+            }
+        }
+        Ok(Box::new(ExecSortMerge { keys: &self.keys, descending: &self.descending, runs, heap }))
+    }
+
+    // only the leading ascending keys, as orderings are ascending
+    fn ordering(&self) -> Vec<usize> {
+        self.keys.iter().zip(&self.descending).take_while(|(_, &desc)| !desc).map(|(&k, _)| k).collect()
+    }
+
+    fn describe(&self) -> String {
+        if self.descending.contains(&true) {
+            format!("Sort (keys: {:?}, descending: {:?})", self.keys, self.descending)
+        } else {
+            format!("Sort (keys: {:?})", self.keys)
+        }
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.child.as_ref()]
+    }
+
+    fn estimate(&self) -> Estimate {
+        let child = self.child.estimate();
+        Estimate {
+            rows: child.rows,
+            cost: child.cost + child.rows * child.rows.max(2.0).log2(),
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![&mut self.child]
+    }
+}
+
+impl Sort {
+    fn compare(&self, a: &Tuple, b: &Tuple) -> Ordering {
+        compare_keys(&project(a, &self.keys), &project(b, &self.keys), &self.descending)
+    }
+
+    fn write_run(&self, bufmgr: &mut BufferPoolManager, chunk: &mut Vec<Tuple>) -> Result<SpillReader, Error> {
+        chunk.sort_by(|a, b| self.compare(a, b));
+        let mut writer = SpillWriter::new();
+        for tuple in chunk.drain(..) {
+            writer.push(bufmgr, &tuple)?;
+        }
+        Ok(writer.finish().reader())
+    }
+}
+
+struct ExecSortInMemory {
+    rows: std::vec::IntoIter<Tuple>,
+    // reserved for the rows, until they've all been returned
+    memory: MemoryReservation,
+}
+
+impl Executor for ExecSortInMemory {
+    fn next(&mut self, _bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        let row = self.rows.next();
+        if row.is_none() {
+            self.memory.release();
+        }
+        Ok(row)
+    }
+}
+
+fn compare_keys(a: &[Value], b: &[Value], descending: &[bool]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .zip(descending)
+        .map(|((a, b), &desc)| if desc { b.cmp(a) } else { a.cmp(b) })
+        .find(|o| o.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+// Ordered by key, then by run so that equal keys keep their input order.
+#[derive(PartialEq, Eq)]
+struct MergeEntry<'a> {
+    key: Tuple,
+    descending: &'a [bool],
+    run: usize,
+    tuple: Tuple,
+}
+
+impl PartialOrd for MergeEntry<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MergeEntry<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_keys(&self.key, &other.key, self.descending).then(self.run.cmp(&other.run))
+    }
+}
+
+struct ExecSortMerge<'a> {
+    keys: &'a [usize],
+    descending: &'a [bool],
+    runs: Vec<SpillReader>,
+    heap: BinaryHeap<Reverse<MergeEntry<'a>>>,
+}
+
+impl<'a> Executor for ExecSortMerge<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        let Reverse(entry) = match self.heap.pop() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        if let Some(tuple) = self.runs[entry.run].next(bufmgr)? {
+            let key = project(&tuple, self.keys);
+            self.heap.push(Reverse(MergeEntry { key, descending: self.descending, run: entry.run, tuple }));
+        }
+        Ok(Some(entry.tuple))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::query::Values;
+    use crate::tuple::Value;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(4));
+        let rows: Vec<_> = (0..500).map(|i| vec![Value::Int((i * 7919) % 50), Value::Int(i)]).collect();
+        let mut expected = rows.clone();
+        expected.sort_by_key(|t| t[0].clone());
+
+        let mut descending = rows.clone();
+        descending.sort_by(|a, b| b[0].cmp(&a[0]).then(a[1].cmp(&b[1])));
+
+        // spilled when there are too many rows or they don't fit in the memory budget
+        let row_size = memory_of(&rows[0]);
+        for (max_rows_in_memory, budget) in [(usize::MAX, None), (16, None), (usize::MAX, Some(16 * row_size))] {
+            bufmgr.set_memory_budget(budget);
+            for (desc, expected) in [(false, &expected), (true, &descending)] {
+                let sort = Sort {
+                    child: Box::new(Values { rows: rows.clone() }),
+                    keys: vec![0],
+                    descending: vec![desc],
+                    max_rows_in_memory,
+                };
+                bufmgr.start_statement();
+                let mut exec = sort.start(&mut bufmgr).unwrap();
+                assert!(budget.is_none() || bufmgr.memory_used() == 0);
+                let mut result = vec![];
+                while let Some(tuple) = exec.next(&mut bufmgr).unwrap() {
+                    result.push(tuple);
+                }
+                assert_eq!(expected, &result);
+            }
+        }
+        // but a row has to fit
+        bufmgr.set_memory_budget(Some(row_size - 1));
+        bufmgr.start_statement();
+        let sort = Sort { child: Box::new(Values { rows }), keys: vec![0], descending: vec![false], max_rows_in_memory: usize::MAX };
+        assert!(matches!(sort.start(&mut bufmgr), Err(Error::Buffer(crate::buffer::Error::OutOfMemoryBudget(_)))));
+    }
+}
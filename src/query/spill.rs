@@ -0,0 +1,155 @@
+use std::collections::VecDeque;
+use std::convert::TryInto;
+
+use super::Error;
+use crate::buffer::BufferPoolManager;
+use crate::disk::PAGE_SIZE;
+use crate::temp::TempPageId;
+use crate::tuple::{self, Tuple, Value};
+
+// Spill runs are chains of temporary pages holding encoded tuples back to back, freed when the
+// statement writing them ends (see `BufferPoolManager::allocate_temp_page`).
+// Page layout: [next_page_id: u64][used_len: u32][tuples...]
+// Writers and readers never keep a page between calls, so any number of runs can be open at once.
+const NO_PAGE: u64 = u64::MAX;
+const HEADER_SIZE: usize = 12;
+const BODY_SIZE: usize = PAGE_SIZE as usize - HEADER_SIZE;
+
+#[derive(Debug, Default)]
+pub struct SpillWriter {
+    first_page_id: Option<TempPageId>,
+    // (page being filled, bytes used in its body)
+    current: Option<(TempPageId, usize)>,
+    buf: Vec<u8>,
+}
+
+impl SpillWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bufmgr: &mut BufferPoolManager, tuple: &[Value]) -> Result<(), Error> {
+        self.buf.clear();
+        tuple::encode(tuple, &mut self.buf);
+        if self.buf.len() > BODY_SIZE {
+            return Err(Error::TupleTooLarge);
+        }
+
+        let (page_id, used) = match self.current {
+            Some((page_id, used)) if used + self.buf.len() <= BODY_SIZE => (page_id, used),
+            prev => {
+                let new_page_id = bufmgr.allocate_temp_page()?;
+                bufmgr.temp_page_mut(new_page_id)?[0..8].copy_from_slice(&NO_PAGE.to_le_bytes());
+                if let Some((prev_page_id, _)) = prev {
+                    bufmgr.temp_page_mut(prev_page_id)?[0..8].copy_from_slice(&new_page_id.0.to_le_bytes());
+                }
+                if self.first_page_id.is_none() {
+                    self.first_page_id = Some(new_page_id);
+                }
+                (new_page_id, 0)
+            }
+        };
+
+        let page = bufmgr.temp_page_mut(page_id)?;
+        let new_used = used + self.buf.len();
+        page[HEADER_SIZE + used..HEADER_SIZE + new_used].copy_from_slice(&self.buf);
+        page[8..12].copy_from_slice(&(new_used as u32).to_le_bytes());
+        self.current = Some((page_id, new_used));
+        Ok(())
+    }
+
+    pub fn finish(self) -> SpillRun {
+        SpillRun {
+            first_page_id: self.first_page_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpillRun {
+    first_page_id: Option<TempPageId>,
+}
+
+impl SpillRun {
+    pub fn reader(&self) -> SpillReader {
+        SpillReader {
+            next_page_id: self.first_page_id,
+            buffered: VecDeque::new(),
+        }
+    }
+}
+
+pub struct SpillReader {
+    next_page_id: Option<TempPageId>,
+    // tuples of the page read most recently, not yet returned
+    buffered: VecDeque<Tuple>,
+}
+
+impl SpillReader {
+    pub fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        while self.buffered.is_empty() {
+            let page_id = match self.next_page_id {
+                Some(page_id) => page_id,
+                None => return Ok(None),
+            };
+            let page = bufmgr.temp_page(page_id)?;
+            let next = u64::from_le_bytes(page[0..8].try_into().unwrap());
+            let used = u32::from_le_bytes(page[8..12].try_into().unwrap()) as usize;
+            let body = page.get(HEADER_SIZE..HEADER_SIZE + used).ok_or(tuple::Error::Malformed)?;
+            let mut pos = 0;
+            while pos < body.len() {
+                let (tuple, len) = tuple::decode(&body[pos..])?;
+                self.buffered.push_back(tuple);
+                pos += len;
+            }
+            self.next_page_id = if next == NO_PAGE { None } else { Some(TempPageId(next)) };
+        }
+        Ok(self.buffered.pop_front())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(2));
+
+        let mut writers = vec![SpillWriter::new(), SpillWriter::new(), SpillWriter::new()];
+        for i in 0..3000 {
+            let tuple = vec![Value::Int(i), Value::Text(format!("row-{}", i))];
+            writers[i as usize % 3].push(&mut bufmgr, &tuple).unwrap();
+        }
+        let runs: Vec<_> = writers.into_iter().map(|w| w.finish()).collect();
+        for (n, run) in runs.iter().enumerate() {
+            let mut reader = run.reader();
+            let mut expected = n as i64;
+            while let Some(tuple) = reader.next(&mut bufmgr).unwrap() {
+                assert_eq!(vec![Value::Int(expected), Value::Text(format!("row-{}", expected))], tuple);
+                expected += 3;
+            }
+            assert_eq!(3000 + n as i64, expected);
+        }
+
+        let huge = vec![Value::Text("x".repeat(BODY_SIZE))];
+        assert!(matches!(SpillWriter::new().push(&mut bufmgr, &huge), Err(Error::TupleTooLarge)));
+
+        // the runs are kept apart from the data file, their pages recycled once the statement ends
+        assert_eq!(0, bufmgr.disk().num_pages());
+        let num_pages = bufmgr.temp_files().unwrap().num_pages();
+        assert_eq!(0, bufmgr.temp_files().unwrap().num_free_pages());
+        bufmgr.start_statement();
+        assert_eq!(num_pages as usize, bufmgr.temp_files().unwrap().num_free_pages());
+        let mut writer = SpillWriter::new();
+        writer.push(&mut bufmgr, &[Value::Int(1)]).unwrap();
+        assert_eq!(vec![Value::Int(1)], writer.finish().reader().next(&mut bufmgr).unwrap().unwrap());
+        assert_eq!(num_pages, bufmgr.temp_files().unwrap().num_pages());
+        bufmgr.commit().unwrap();
+        assert_eq!(num_pages as usize, bufmgr.temp_files().unwrap().num_free_pages());
+    }
+}
@@ -0,0 +1,77 @@
+use super::expr::Expr;
+use super::{BoxExecutor, Error, Estimate, Executor, PlanNode};
+use crate::buffer::BufferPoolManager;
+use crate::tuple::{Tuple, Value};
+
+// Elements assumed of an array when estimating.
+const ASSUMED_ELEMENTS: f64 = 10.0;
+
+// Emits each input tuple with each element of the array `expr` evaluates to on it appended, and
+// none for an empty or NULL array.
+pub struct Unnest {
+    pub child: Box<dyn PlanNode>,
+    pub expr: Expr,
+}
+
+impl PlanNode for Unnest {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        Ok(Box::new(ExecUnnest {
+            child: self.child.start(bufmgr)?,
+            expr: &self.expr,
+            tuple: vec![],
+            elements: vec![].into_iter(),
+        }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.child.ordering()
+    }
+
+    fn describe(&self) -> String {
+        format!("Unnest: {}", self.expr)
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.child.as_ref()]
+    }
+
+    fn estimate(&self) -> Estimate {
+        let child = self.child.estimate();
+        let rows = child.rows * ASSUMED_ELEMENTS;
+        Estimate { rows, cost: child.cost + rows }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![&mut self.child]
+    }
+}
+
+struct ExecUnnest<'a> {
+    child: BoxExecutor<'a>,
+    expr: &'a Expr,
+    // the input tuple whose elements are being emitted
+    tuple: Tuple,
+    elements: std::vec::IntoIter<Value>,
+}
+
+impl<'a> Executor for ExecUnnest<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        loop {
+            if let Some(element) = self.elements.next() {
+                let mut output = self.tuple.clone();
+                output.push(element);
+                return Ok(Some(output));
+            }
+            let Some(tuple) = self.child.next(bufmgr)? else {
+                return Ok(None);
+            };
+            self.elements = match self.expr.eval(&tuple, bufmgr)? {
+                Value::Null => vec![],
+                Value::Array(elements) => elements,
+                value => return Err(Error::TypeMismatch(format!("unnest of {:?}, which is not an array", value))),
+            }
+            .into_iter();
+            self.tuple = tuple;
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use std::fmt;
+
+use super::aggregate::Accumulator;
+use super::expr::Expr;
+use super::{project, Aggregate, AggregateFunc, BoxExecutor, Error, Estimate, Executor, PlanNode};
+use crate::buffer::BufferPoolManager;
+use crate::tuple::{Tuple, Value};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowFunc {
+    RowNumber,
+    Rank,
+    DenseRank,
+    // (value, [offset, [default]]) of the row `offset` rows before / after the current one
+    Lag,
+    Lead,
+    // over the rows of the frame
+    Aggregate(AggregateFunc),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameUnits {
+    Rows,
+    // bounds are peer groups, i.e. rows with the same ORDER BY values, rather than rows
+    Range,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameBound {
+    UnboundedPreceding,
+    // offsets are only allowed with ROWS
+    Preceding(usize),
+    CurrentRow,
+    Following(usize),
+    UnboundedFollowing,
+}
+
+// Rows of the partition an aggregate is computed over for each row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub units: FrameUnits,
+    pub start: FrameBound,
+    pub end: FrameBound,
+}
+
+impl WindowFunc {
+    pub fn name(&self) -> String {
+        match self {
+            WindowFunc::RowNumber => "row_number".to_string(),
+            WindowFunc::Rank => "rank".to_string(),
+            WindowFunc::DenseRank => "dense_rank".to_string(),
+            WindowFunc::Lag => "lag".to_string(),
+            WindowFunc::Lead => "lead".to_string(),
+            WindowFunc::Aggregate(func) => func.name(),
+        }
+    }
+}
+
+impl Frame {
+    // The frame used when none is given: the whole partition without ORDER BY, the rows up to
+    // the last peer of the current row with it.
+    pub fn implicit(ordered: bool) -> Self {
+        Self {
+            units: if ordered { FrameUnits::Range } else { FrameUnits::Rows },
+            start: FrameBound::UnboundedPreceding,
+            end: if ordered { FrameBound::CurrentRow } else { FrameBound::UnboundedFollowing },
+        }
+    }
+
+    // Range [start, end) of the frame of the row `i` of a partition of `len` rows, whose peers
+    // are the rows in `peers`.
+    fn range(&self, i: usize, len: usize, peers: (usize, usize)) -> (usize, usize) {
+        let (first_peer, last_peer) = match self.units {
+            FrameUnits::Rows => (i, i + 1),
+            FrameUnits::Range => peers,
+        };
+        let start = match self.start {
+            FrameBound::UnboundedPreceding => 0,
+            FrameBound::Preceding(n) => i.saturating_sub(n),
+            FrameBound::CurrentRow => first_peer,
+            FrameBound::Following(n) => i + n,
+            FrameBound::UnboundedFollowing => len,
+        };
+        let end = match self.end {
+            FrameBound::UnboundedPreceding => 0,
+            FrameBound::Preceding(n) => (i + 1).saturating_sub(n),
+            FrameBound::CurrentRow => last_peer,
+            FrameBound::Following(n) => i + n + 1,
+            FrameBound::UnboundedFollowing => len,
+        };
+        (start.min(len), end.min(len))
+    }
+}
+
+impl fmt::Display for FrameBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameBound::UnboundedPreceding => write!(f, "UNBOUNDED PRECEDING"),
+            FrameBound::Preceding(n) => write!(f, "{} PRECEDING", n),
+            FrameBound::CurrentRow => write!(f, "CURRENT ROW"),
+            FrameBound::Following(n) => write!(f, "{} FOLLOWING", n),
+            FrameBound::UnboundedFollowing => write!(f, "UNBOUNDED FOLLOWING"),
+        }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let units = match self.units {
+            FrameUnits::Rows => "ROWS",
+            FrameUnits::Range => "RANGE",
+        };
+        write!(f, "{} BETWEEN {} AND {}", units, self.start, self.end)
+    }
+}
+
+pub struct WindowFunction {
+    pub func: WindowFunc,
+    pub args: Vec<Expr>,
+    // used by the aggregates only
+    pub frame: Frame,
+}
+
+impl fmt::Display for WindowFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.func.name();
+        match (&self.func, self.args.first()) {
+            (WindowFunc::Aggregate(_), Some(arg)) => write!(f, "{}({}) {}", name, arg, self.frame),
+            (WindowFunc::Aggregate(_), None) => write!(f, "{}(*) {}", name, self.frame),
+            _ => {
+                let args: Vec<_> = self.args.iter().map(|a| a.to_string()).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+        }
+    }
+}
+
+// Computes `functions` for each input tuple, which are emitted followed by the values.
+// The input must be sorted by `partition_by` then `order_by` (see `Sort`); each partition
+// is held in memory while its values are computed.
+pub struct Window {
+    pub child: Box<dyn PlanNode>,
+    pub partition_by: Vec<usize>,
+    pub order_by: Vec<usize>,
+    pub functions: Vec<WindowFunction>,
+}
+
+impl PlanNode for Window {
+    fn start(&self, bufmgr: &mut BufferPoolManager) -> Result<BoxExecutor<'_>, Error> {
+        Ok(Box::new(ExecWindow {
+            plan: self,
+            child: self.child.start(bufmgr)?,
+            next_partition: None,
+            output: vec![].into_iter(),
+        }))
+    }
+
+    fn ordering(&self) -> Vec<usize> {
+        self.child.ordering()
+    }
+
+    fn describe(&self) -> String {
+        let functions: Vec<_> = self.functions.iter().map(|f| f.to_string()).collect();
+        format!(
+            "Window (partition by: {:?}, order by: {:?}, functions: [{}])",
+            self.partition_by,
+            self.order_by,
+            functions.join(", ")
+        )
+    }
+
+    fn children(&self) -> Vec<&dyn PlanNode> {
+        vec![self.child.as_ref()]
+    }
+
+    fn estimate(&self) -> Estimate {
+        let child = self.child.estimate();
+        Estimate {
+            rows: child.rows,
+            cost: child.cost + child.rows,
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Box<dyn PlanNode>> {
+        vec![&mut self.child]
+    }
+}
+
+impl Window {
+    // Appends the values of the functions to each row of a partition.
+    fn compute(&self, bufmgr: &mut BufferPoolManager, mut rows: Vec<Tuple>) -> Result<Vec<Tuple>, Error> {
+        let len = rows.len();
+        // [first, last) peers of each row, and the number of peer groups up to it
+        let mut peers = vec![(0, 0); len];
+        let mut dense_ranks = vec![0; len];
+        let mut first = 0;
+        for i in 0..len {
+            if i > 0 && project(&rows[i], &self.order_by) != project(&rows[first], &self.order_by) {
+                for peer in &mut peers[first..i] {
+                    peer.1 = i;
+                }
+                first = i;
+            }
+            peers[i].0 = first;
+            dense_ranks[i] = if i == 0 { 1 } else { dense_ranks[i - 1] + (first == i) as i64 };
+        }
+        for peer in &mut peers[first..] {
+            peer.1 = len;
+        }
+
+        let mut columns = vec![];
+        for function in &self.functions {
+            let mut values = Vec::with_capacity(len);
+            match &function.func {
+                WindowFunc::RowNumber => values.extend((1..=len).map(|n| Value::Int(n as i64))),
+                WindowFunc::Rank => values.extend(peers.iter().map(|&(first, _)| Value::Int(first as i64 + 1))),
+                WindowFunc::DenseRank => values.extend(dense_ranks.iter().map(|&n| Value::Int(n))),
+                WindowFunc::Lag | WindowFunc::Lead => {
+                    for (i, row) in rows.iter().enumerate() {
+                        let offset = match function.args.get(1) {
+                            Some(expr) => match expr.eval(row, bufmgr)? {
+                                Value::Int(n) => n,
+                                value => return Err(Error::TypeMismatch(format!("invalid offset: {:?}", value))),
+                            },
+                            None => 1,
+                        };
+                        let offset = if function.func == WindowFunc::Lag { -offset } else { offset };
+                        let target = usize::try_from(i as i64 + offset).ok().filter(|&j| j < len);
+                        values.push(match (target, function.args.get(2)) {
+                            (Some(j), _) => function.args[0].eval(&rows[j], bufmgr)?,
+                            (None, Some(default)) => default.eval(row, bufmgr)?,
+                            (None, None) => Value::Null,
+                        });
+                    }
+                }
+                WindowFunc::Aggregate(func) => {
+                    let aggregate = Aggregate { func: func.clone(), arg: None, distinct: false };
+                    let mut args = Vec::with_capacity(len);
+                    for row in &rows {
+                        args.push(match function.args.first() {
+                            Some(arg) => arg.eval(row, bufmgr)?,
+                            None => Value::Bool(true),
+                        });
+                    }
+                    // frames starting at the partition start only grow, so they're computed incrementally
+                    let incremental = function.frame.start == FrameBound::UnboundedPreceding;
+                    let mut accumulator = Accumulator::new(&aggregate);
+                    let mut added = 0;
+                    for (i, &peer) in peers.iter().enumerate() {
+                        let (start, end) = function.frame.range(i, len, peer);
+                        if !incremental {
+                            accumulator = Accumulator::new(&aggregate);
+                            added = start;
+                        }
+                        while added < end {
+                            accumulator.add(&aggregate.func, args[added].clone())?;
+                            added += 1;
+                        }
+                        values.push(accumulator.result(&aggregate)?);
+                    }
+                }
+            }
+            columns.push(values);
+        }
+        for (i, row) in rows.iter_mut().enumerate() {
+            row.extend(columns.iter().map(|values| values[i].clone()));
+        }
+        Ok(rows)
+    }
+}
+
+struct ExecWindow<'a> {
+    plan: &'a Window,
+    child: BoxExecutor<'a>,
+    // first row of the partition after the one being emitted
+    next_partition: Option<Tuple>,
+    output: std::vec::IntoIter<Tuple>,
+}
+
+impl<'a> Executor for ExecWindow<'a> {
+    fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        loop {
+            if let Some(tuple) = self.output.next() {
+                return Ok(Some(tuple));
+            }
+            let first = match self.next_partition.take() {
+                Some(tuple) => tuple,
+                None => match self.child.next(bufmgr)? {
+                    Some(tuple) => tuple,
+                    None => return Ok(None),
+                },
+            };
+            let key = project(&first, &self.plan.partition_by);
+            let mut rows = vec![first];
+            while let Some(tuple) = self.child.next(bufmgr)? {
+                if project(&tuple, &self.plan.partition_by) != key {
+                    self.next_partition = Some(tuple);
+                    break;
+                }
+                rows.push(tuple);
+            }
+            self.output = self.plan.compute(bufmgr, rows)?.into_iter();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::query::Values;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(4));
+        // (partition, order key), sorted by both
+        let rows: Vec<_> = [(1, 10), (1, 20), (1, 20), (1, 30), (2, 5)].iter().map(|&(p, v)| vec![Value::Int(p), Value::Int(v)]).collect();
+        let function = |func, args: Vec<Expr>, frame| WindowFunction { func, args, frame };
+        let sum = WindowFunc::Aggregate(AggregateFunc::Sum);
+        let sliding = Frame { units: FrameUnits::Rows, start: FrameBound::Preceding(1), end: FrameBound::CurrentRow };
+        let plan = Window {
+            child: Box::new(Values { rows }),
+            partition_by: vec![0],
+            order_by: vec![1],
+            functions: vec![
+                function(WindowFunc::RowNumber, vec![], Frame::implicit(true)),
+                function(WindowFunc::Rank, vec![], Frame::implicit(true)),
+                function(WindowFunc::DenseRank, vec![], Frame::implicit(true)),
+                function(WindowFunc::Lag, vec![Expr::Column(1)], Frame::implicit(true)),
+                function(WindowFunc::Lead, vec![Expr::Column(1), Expr::Literal(Value::Int(2)), Expr::Literal(Value::Int(0))], Frame::implicit(true)),
+                function(sum.clone(), vec![Expr::Column(1)], Frame::implicit(true)),
+                function(sum, vec![Expr::Column(1)], sliding),
+                function(WindowFunc::Aggregate(AggregateFunc::Count), vec![], Frame::implicit(false)),
+            ],
+        };
+        let mut exec = plan.start(&mut bufmgr).unwrap();
+        let mut result = vec![];
+        while let Some(tuple) = exec.next(&mut bufmgr).unwrap() {
+            result.push(tuple[2..].to_vec());
+        }
+        let expected = [
+            [1, 1, 1, -1, 20, 10, 10, 4],
+            [2, 2, 2, 10, 30, 50, 30, 4],
+            [3, 2, 2, 20, 0, 50, 40, 4],
+            [4, 4, 3, 20, 0, 80, 50, 4],
+            [1, 1, 1, -1, 0, 5, 5, 1],
+        ];
+        let expected: Vec<Tuple> = expected
+            .iter()
+            .map(|row| row.iter().map(|&n| if n < 0 { Value::Null } else { Value::Int(n) }).collect())
+            .collect();
+        assert_eq!(expected, result);
+    }
+}
@@ -0,0 +1,1285 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryInto;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::buffer::{self, BufferPoolManager};
+use crate::replication::{self, Replica};
+use crate::sim::Rng;
+use crate::wal::{self, Lsn};
+
+// Replication under consensus (Raft): the database and its replicas are the nodes of a cluster,
+// which elect a leader among them by majority, and the log of the leader is copied to the others,
+// an entry of it being committed once a majority have it on disk. The database, leading, proposes
+// the records of its log as they become durable, a batch to an entry, in the messages replicas
+// are sent (see `replication`), and the replicas apply those of the committed entries. The server
+// holds back what it answers until the records before it are committed (see `Server::with_consensus`),
+// so that a commit acknowledged survives the loss of any minority of the nodes.
+//
+// Should the database fail or be cut off, the replicas elect one of them, which commits what the
+// database had proposed to enough of them, if it was committed or not, and drops the rest from the
+// others, so that none of them applies records the others skip. A replica can't write, so it hands
+// the lead back to the database once its log has caught up, the database proposing again from
+// after what remains of its entries. If the database doesn't take it back for FAILOVER_TIMEOUT, a
+// replica given the address of its server fails over: once it has applied every record committed,
+// it commits an entry making it the primary, is promoted (see `Replica::promote`), and proposes
+// its own log from then on, which goes on from the records the others have applied. Every node
+// learns of the new primary from the entry, and keeps it in a file, and the lead is handed to it
+// from then on. The old database, once it hears of it, proposes nothing more, its server turning
+// clients away with the address of the new primary (see `Server::with_consensus`); its log may
+// have records the cluster never committed, so it has to be set up as a replica again.
+//
+// Nodes are run a step at a time, never blocking, and keep their term, vote and log in files of
+// their own in a directory. The entries the state machine has taken are compacted but for the last
+// KEEP_ENTRIES, so that a follower further behind than that has to be set up from a base backup
+// again.
+//
+// Messages between the nodes, integers little endian, are framed [from: u64][len: u32][message].
+// The data of an entry is [kind: u8] followed by the records of the primary's log, as sent to
+// replicas, or by [node: u64][address] of the node made the primary.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Wal(#[from] wal::Error),
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error(transparent)]
+    Replication(#[from] replication::Error),
+    #[error("not the leader, which is {0:?}")]
+    NotLeader(Option<NodeId>),
+    #[error("malformed raft {0}")]
+    Malformed(&'static str),
+}
+
+pub type NodeId = u64;
+
+// How often the nodes tick.
+pub const TICK: Duration = Duration::from_millis(10);
+// Ticks without hearing from a leader before a follower stands for election, and up to as many
+// more, picked at random for the nodes not to stand at once.
+const ELECTION_TICKS: u64 = 15;
+// Ticks between the messages of a leader to each follower, empty if there's nothing new.
+const HEARTBEAT_TICKS: u64 = 3;
+// Entries in a message at most.
+const MAX_APPEND: u64 = 64;
+// Entries taken by the state machine kept in the log, for followers behind to catch up from.
+pub const KEEP_ENTRIES: u64 = 1000;
+// Bytes of records proposed in an entry, beyond which the records go in the next one.
+const MAX_ENTRY_SIZE: usize = 256 * 1024;
+// Bytes waiting to be written to a peer, beyond which messages to it are dropped.
+const MAX_OUTGOING: usize = 4 * 1024 * 1024;
+// How long connecting to a peer may take, and how long after failing to it's tried again.
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(100);
+const RECONNECT_DELAY: Duration = Duration::from_millis(200);
+const FRAME_HEADER_SIZE: usize = 12;
+
+// [term: u64][voted for: u64, NO_VOTE if none]
+const STATE_FILE: &str = "raft_state";
+// [index of the last entry compacted: u64][its term: u64] and the entries after it, each
+// [term: u64][len: u32][data]
+const LOG_FILE: &str = "raft_log";
+const NO_VOTE: u64 = u64::MAX;
+// The slot keeping the log of the database the entries are proposed from.
+const SLOT: &str = "raft";
+// The data of the last entry committed making a node the primary, if any
+const PRIMARY_FILE: &str = "raft_primary";
+// Kinds of entries.
+const RECORDS: u8 = 0;
+const PRIMARY: u8 = 1;
+// How long a replica leads without the primary taking the lead back before it fails over.
+pub const FAILOVER_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub term: u64,
+    // empty for those a leader begins its term with
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    RequestVote { term: u64, last_index: u64, last_term: u64 },
+    Vote { term: u64, granted: bool },
+    // the entries after prev_index, of which the leader has committed those up to commit
+    Append { term: u64, prev_index: u64, prev_term: u64, entries: Vec<Entry>, commit: u64 },
+    // the index up to which the follower has the log of the leader, or if not a success, up to
+    // which it may have it
+    Appended { term: u64, success: bool, index: u64 },
+    // the leader asking a follower with its log to stand for election, to hand it the lead
+    TimeoutNow { term: u64 },
+}
+
+impl Message {
+    pub fn term(&self) -> u64 {
+        match self {
+            Message::RequestVote { term, .. } | Message::Vote { term, .. } | Message::Append { term, .. } | Message::Appended { term, .. } | Message::TimeoutNow { term } => *term,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = vec![];
+        let put = |out: &mut Vec<u8>, tag: u8, values: &[u64]| {
+            out.push(tag);
+            for value in values {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+        };
+        match self {
+            Message::RequestVote { term, last_index, last_term } => put(&mut out, 0, &[*term, *last_index, *last_term]),
+            Message::Vote { term, granted } => put(&mut out, 1, &[*term, *granted as u64]),
+            Message::Append { term, prev_index, prev_term, entries, commit } => {
+                put(&mut out, 2, &[*term, *prev_index, *prev_term, *commit, entries.len() as u64]);
+                for entry in entries {
+                    encode_entry(entry, &mut out);
+                }
+            }
+            Message::Appended { term, success, index } => put(&mut out, 3, &[*term, *success as u64, *index]),
+            Message::TimeoutNow { term } => put(&mut out, 4, &[*term]),
+        }
+        out
+    }
+
+    pub fn decode(mut bytes: &[u8]) -> Result<Self, Error> {
+        let bytes = &mut bytes;
+        let tag = take(bytes, 1)?[0];
+        let message = match tag {
+            0 => Message::RequestVote { term: take_u64(bytes)?, last_index: take_u64(bytes)?, last_term: take_u64(bytes)? },
+            1 => Message::Vote { term: take_u64(bytes)?, granted: take_u64(bytes)? != 0 },
+            2 => {
+                let (term, prev_index, prev_term, commit) = (take_u64(bytes)?, take_u64(bytes)?, take_u64(bytes)?, take_u64(bytes)?);
+                let mut entries = vec![];
+                for _ in 0..take_u64(bytes)? {
+                    entries.push(decode_entry(bytes)?);
+                }
+                Message::Append { term, prev_index, prev_term, entries, commit }
+            }
+            3 => Message::Appended { term: take_u64(bytes)?, success: take_u64(bytes)? != 0, index: take_u64(bytes)? },
+            4 => Message::TimeoutNow { term: take_u64(bytes)? },
+            _ => return Err(Error::Malformed("message")),
+        };
+        if !bytes.is_empty() {
+            return Err(Error::Malformed("message"));
+        }
+        Ok(message)
+    }
+}
+
+fn encode_entry(entry: &Entry, out: &mut Vec<u8>) {
+    out.extend_from_slice(&entry.term.to_le_bytes());
+    out.extend_from_slice(&(entry.data.len() as u32).to_le_bytes());
+    out.extend_from_slice(&entry.data);
+}
+
+fn decode_entry(bytes: &mut &[u8]) -> Result<Entry, Error> {
+    let term = take_u64(bytes)?;
+    let len = u32::from_le_bytes(take(bytes, 4)?.try_into().unwrap()) as usize;
+    Ok(Entry { term, data: take(bytes, len)?.to_vec() })
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if bytes.len() < len {
+        return Err(Error::Malformed("message"));
+    }
+    let (taken, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(taken)
+}
+
+fn take_u64(bytes: &mut &[u8]) -> Result<u64, Error> {
+    Ok(u64::from_le_bytes(take(bytes, 8)?.try_into().unwrap()))
+}
+
+#[derive(Debug)]
+enum Role {
+    Follower,
+    Candidate { votes: BTreeSet<NodeId> },
+    // for each follower, the index of the next entry to send it, and up to which it has the log
+    Leader { next: BTreeMap<NodeId, u64>, matched: BTreeMap<NodeId, u64> },
+}
+
+// A node of a cluster, which is given the messages of the others and ticks, and leaves what it
+// sends them to be taken. Entries are indexed from 1.
+pub struct Node {
+    dir: PathBuf,
+    id: NodeId,
+    peers: Vec<NodeId>,
+    term: u64,
+    voted_for: Option<NodeId>,
+    role: Role,
+    leader: Option<NodeId>,
+    // the index and term of the last entry compacted, the entries after it being in `entries`
+    first: u64,
+    first_term: u64,
+    entries: Vec<Entry>,
+    log: File,
+    commit: u64,
+    // the last entry taken by the state machine
+    taken: u64,
+    // ticks since the last heartbeat sent, or heard from the leader
+    elapsed: u64,
+    timeout: u64,
+    rng: Rng,
+    messages: Vec<(NodeId, Message)>,
+}
+
+impl Node {
+    // Opens the node `id` of the cluster of it and `peers`, whose state is kept in `dir`.
+    pub fn open(dir: impl AsRef<Path>, id: NodeId, peers: &[NodeId]) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let (term, voted_for) = match fs::read(dir.join(STATE_FILE)) {
+            Ok(bytes) if bytes.len() == 16 => {
+                let vote = u64::from_le_bytes(bytes[8..].try_into().unwrap());
+                (u64::from_le_bytes(bytes[..8].try_into().unwrap()), (vote != NO_VOTE).then_some(vote))
+            }
+            Ok(_) => return Err(Error::Malformed("state")),
+            Err(e) if e.kind() == ErrorKind::NotFound => (0, None),
+            Err(e) => return Err(e.into()),
+        };
+        let (first, first_term, entries, log) = match fs::read(dir.join(LOG_FILE)) {
+            Ok(bytes) => {
+                let mut rest = bytes.get(16..).ok_or(Error::Malformed("log"))?;
+                let mut entries = vec![];
+                // those torn by a crash while they were appended are dropped
+                loop {
+                    let mut cursor = rest;
+                    let Ok(entry) = decode_entry(&mut cursor) else {
+                        break;
+                    };
+                    entries.push(entry);
+                    rest = cursor;
+                }
+                let log = OpenOptions::new().append(true).open(dir.join(LOG_FILE))?;
+                log.set_len((bytes.len() - rest.len()) as u64)?;
+                (u64::from_le_bytes(bytes[..8].try_into().unwrap()), u64::from_le_bytes(bytes[8..16].try_into().unwrap()), entries, log)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => (0, 0, vec![], write_log(&dir, 0, 0, &[])?),
+            Err(e) => return Err(e.into()),
+        };
+        let mut node = Self {
+            dir,
+            id,
+            peers: peers.to_vec(),
+            term,
+            voted_for,
+            role: Role::Follower,
+            leader: None,
+            first,
+            first_term,
+            entries,
+            log,
+            commit: first,
+            taken: first,
+            elapsed: 0,
+            timeout: 0,
+            rng: Rng::new(id),
+            messages: vec![],
+        };
+        node.reset_timeout();
+        Ok(node)
+    }
+
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    pub fn term(&self) -> u64 {
+        self.term
+    }
+
+    pub fn is_leader(&self) -> bool {
+        matches!(self.role, Role::Leader { .. })
+    }
+
+    // The leader of the current term, if the node knows it.
+    pub fn leader(&self) -> Option<NodeId> {
+        self.leader
+    }
+
+    // Index of the last entry committed.
+    pub fn committed(&self) -> u64 {
+        self.commit
+    }
+
+    pub fn last_index(&self) -> u64 {
+        self.first + self.entries.len() as u64
+    }
+
+    fn last_term(&self) -> u64 {
+        self.entries.last().map_or(self.first_term, |entry| entry.term)
+    }
+
+    fn term_at(&self, index: u64) -> Option<u64> {
+        match index.checked_sub(self.first)? {
+            0 => Some(self.first_term),
+            n => self.entries.get(n as usize - 1).map(|entry| entry.term),
+        }
+    }
+
+    // The entries after `index`, which mustn't have been compacted.
+    pub fn entries_after(&self, index: u64) -> &[Entry] {
+        &self.entries[(index.max(self.first) - self.first) as usize..]
+    }
+
+    // The messages to send since last taken, with the nodes to send them to.
+    pub fn take_messages(&mut self) -> Vec<(NodeId, Message)> {
+        std::mem::take(&mut self.messages)
+    }
+
+    // The entries committed since last taken, for the state machine to apply in order.
+    pub fn take_committed(&mut self) -> Vec<Entry> {
+        let entries = self.entries[(self.taken - self.first) as usize..(self.commit - self.first) as usize].to_vec();
+        self.taken = self.commit;
+        entries
+    }
+
+    pub fn tick(&mut self) -> Result<(), Error> {
+        self.elapsed += 1;
+        if self.is_leader() {
+            if self.elapsed >= HEARTBEAT_TICKS {
+                self.elapsed = 0;
+                self.broadcast();
+            }
+        } else if self.elapsed >= self.timeout {
+            self.campaign()?;
+        }
+        Ok(())
+    }
+
+    // Stands for election in a term after the current one.
+    pub fn campaign(&mut self) -> Result<(), Error> {
+        self.term += 1;
+        self.voted_for = Some(self.id);
+        self.save_state()?;
+        self.leader = None;
+        self.role = Role::Candidate { votes: BTreeSet::from([self.id]) };
+        self.reset_timeout();
+        if self.quorum() == 1 {
+            return self.become_leader();
+        }
+        let (term, last_index, last_term) = (self.term, self.last_index(), self.last_term());
+        for &peer in &self.peers {
+            self.messages.push((peer, Message::RequestVote { term, last_index, last_term }));
+        }
+        Ok(())
+    }
+
+    // Appends an entry of `data` to the log, if the node is the leader. Returns its index.
+    pub fn propose(&mut self, data: Vec<u8>) -> Result<u64, Error> {
+        if !self.is_leader() {
+            return Err(Error::NotLeader(self.leader));
+        }
+        self.append(vec![Entry { term: self.term, data }])?;
+        self.broadcast();
+        self.advance_commit();
+        Ok(self.last_index())
+    }
+
+    // Hands the lead to `peer`, if the node is the leader and the peer has its whole log.
+    pub fn transfer(&mut self, peer: NodeId) {
+        if let Role::Leader { matched, .. } = &self.role {
+            if matched.get(&peer) == Some(&self.last_index()) {
+                self.messages.push((peer, Message::TimeoutNow { term: self.term }));
+            }
+        }
+    }
+
+    // Drops the entries up to `index`, which the state machine must have taken.
+    pub fn compact(&mut self, index: u64) -> Result<(), Error> {
+        let index = index.min(self.taken);
+        if index <= self.first {
+            return Ok(());
+        }
+        self.first_term = self.term_at(index).unwrap();
+        self.entries.drain(..(index - self.first) as usize);
+        self.first = index;
+        self.log = write_log(&self.dir, self.first, self.first_term, &self.entries)?;
+        Ok(())
+    }
+
+    pub fn handle(&mut self, from: NodeId, message: Message) -> Result<(), Error> {
+        // from a node not of the cluster, which mustn't sway its elections or commits
+        if !self.peers.contains(&from) {
+            return Ok(());
+        }
+        if message.term() > self.term {
+            self.term = message.term();
+            self.voted_for = None;
+            self.save_state()?;
+            self.role = Role::Follower;
+            self.leader = None;
+        }
+        let term = self.term;
+        match message {
+            Message::RequestVote { term: theirs, last_index, last_term } => {
+                let up_to_date = (last_term, last_index) >= (self.last_term(), self.last_index());
+                let granted = theirs == term && up_to_date && self.voted_for.is_none_or(|voted| voted == from);
+                if granted {
+                    self.voted_for = Some(from);
+                    self.save_state()?;
+                    self.reset_timeout();
+                }
+                self.messages.push((from, Message::Vote { term, granted }));
+            }
+            Message::Vote { term: theirs, granted } => {
+                let quorum = self.quorum();
+                if let Role::Candidate { votes } = &mut self.role {
+                    if theirs == term && granted {
+                        votes.insert(from);
+                        if votes.len() >= quorum {
+                            self.become_leader()?;
+                        }
+                    }
+                }
+            }
+            Message::Append { term: theirs, prev_index, prev_term, entries, commit } => {
+                if theirs < term {
+                    self.messages.push((from, Message::Appended { term, success: false, index: 0 }));
+                    return Ok(());
+                }
+                self.role = Role::Follower;
+                self.leader = Some(from);
+                self.reset_timeout();
+                // entries compacted were committed, so are those of the leader
+                match self.term_at(prev_index) {
+                    None if prev_index > self.last_index() => {
+                        self.messages.push((from, Message::Appended { term, success: false, index: self.last_index() }));
+                        return Ok(());
+                    }
+                    Some(found) if found != prev_term => {
+                        self.messages.push((from, Message::Appended { term, success: false, index: prev_index - 1 }));
+                        return Ok(());
+                    }
+                    _ => {}
+                }
+                let last = prev_index + entries.len() as u64;
+                let mut new = vec![];
+                for (index, entry) in (prev_index + 1..).zip(entries) {
+                    match self.term_at(index) {
+                        _ if index <= self.first => {}
+                        Some(found) if found == entry.term && new.is_empty() => {}
+                        Some(_) if new.is_empty() => {
+                            // those of a leader before, which the leader now doesn't have
+                            self.entries.truncate((index - self.first - 1) as usize);
+                            self.log = write_log(&self.dir, self.first, self.first_term, &self.entries)?;
+                            new.push(entry);
+                        }
+                        _ => new.push(entry),
+                    }
+                }
+                self.append(new)?;
+                self.commit = self.commit.max(commit.min(last));
+                self.messages.push((from, Message::Appended { term, success: true, index: last }));
+            }
+            Message::Appended { term: theirs, success, index } => {
+                let Role::Leader { next, matched } = &mut self.role else {
+                    return Ok(());
+                };
+                if theirs != term {
+                    return Ok(());
+                }
+                if success {
+                    let matched = matched.entry(from).or_default();
+                    *matched = (*matched).max(index);
+                    let next = next.entry(from).or_default();
+                    *next = (*next).max(index + 1);
+                    self.advance_commit();
+                } else {
+                    // back to where the follower may have the log up to, and on from there
+                    let least = matched.get(&from).copied().unwrap_or(0) + 1;
+                    next.insert(from, (index + 1).max(least));
+                    self.send_append(from);
+                }
+            }
+            Message::TimeoutNow { term: theirs } => {
+                if theirs == term && !self.is_leader() {
+                    self.campaign()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn quorum(&self) -> usize {
+        let nodes = self.peers.len() + 1;
+        nodes / 2 + 1
+    }
+
+    fn reset_timeout(&mut self) {
+        self.elapsed = 0;
+        self.timeout = ELECTION_TICKS + self.rng.below(ELECTION_TICKS);
+    }
+
+    fn become_leader(&mut self) -> Result<(), Error> {
+        let next = self.last_index() + 1;
+        self.role = Role::Leader { next: self.peers.iter().map(|&peer| (peer, next)).collect(), matched: self.peers.iter().map(|&peer| (peer, 0)).collect() };
+        self.leader = Some(self.id);
+        self.elapsed = 0;
+        // the entries of terms before are committed only along with one of the node's own
+        self.append(vec![Entry { term: self.term, data: vec![] }])?;
+        self.broadcast();
+        self.advance_commit();
+        Ok(())
+    }
+
+    fn broadcast(&mut self) {
+        for peer in self.peers.clone() {
+            self.send_append(peer);
+        }
+    }
+
+    // Sends `peer` the entries from the next it's sent, taking it to have them from then on
+    // until it answers otherwise.
+    fn send_append(&mut self, peer: NodeId) {
+        let Role::Leader { next, .. } = &self.role else {
+            return;
+        };
+        let prev_index = next[&peer] - 1;
+        // compacted, so the follower has to be set up again
+        let Some(prev_term) = self.term_at(prev_index) else {
+            return;
+        };
+        let end = self.last_index().min(prev_index + MAX_APPEND);
+        let entries = self.entries[(prev_index - self.first) as usize..(end - self.first) as usize].to_vec();
+        let message = Message::Append { term: self.term, prev_index, prev_term, entries, commit: self.commit };
+        if let Role::Leader { next, .. } = &mut self.role {
+            next.insert(peer, end + 1);
+        }
+        self.messages.push((peer, message));
+    }
+
+    // Commits up to the last entry a majority have, if it's of the current term.
+    fn advance_commit(&mut self) {
+        let Role::Leader { matched, .. } = &self.role else {
+            return;
+        };
+        let mut indexes: Vec<u64> = matched.values().copied().chain([self.last_index()]).collect();
+        indexes.sort_unstable_by(|a, b| b.cmp(a));
+        let index = indexes[self.quorum() - 1];
+        if index > self.commit && self.term_at(index) == Some(self.term) {
+            self.commit = index;
+        }
+    }
+
+    // Appends the entries to the log on disk, then to those in memory.
+    fn append(&mut self, entries: Vec<Entry>) -> Result<(), Error> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut bytes = vec![];
+        for entry in &entries {
+            encode_entry(entry, &mut bytes);
+        }
+        self.log.write_all(&bytes)?;
+        self.log.sync_data()?;
+        self.entries.extend(entries);
+        Ok(())
+    }
+
+    fn save_state(&self) -> Result<(), Error> {
+        let mut bytes = self.term.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&self.voted_for.unwrap_or(NO_VOTE).to_le_bytes());
+        Ok(replication::write_file(&self.dir, STATE_FILE, &bytes)?)
+    }
+}
+
+// Writes the log anew, returning it opened to append to.
+fn write_log(dir: &Path, first: u64, first_term: u64, entries: &[Entry]) -> Result<File, Error> {
+    let mut bytes = first.to_le_bytes().to_vec();
+    bytes.extend_from_slice(&first_term.to_le_bytes());
+    for entry in entries {
+        encode_entry(entry, &mut bytes);
+    }
+    replication::write_file(dir, LOG_FILE, &bytes)?;
+    Ok(OpenOptions::new().append(true).open(dir.join(LOG_FILE))?)
+}
+
+// Messages between the nodes over TCP, sent on a connection to each peer and received on those
+// the peers connect, a message being dropped if it can't be sent right away, which the protocol
+// copes with as it does with one lost. A connection accepted is bound to the peer its first
+// message is from, which must be connecting from the address of that peer, and is closed on a
+// message from any other.
+pub struct Transport {
+    id: NodeId,
+    listener: TcpListener,
+    peers: BTreeMap<NodeId, SocketAddr>,
+    // connected to the peers, with what's still to be written
+    outgoing: BTreeMap<NodeId, (TcpStream, Vec<u8>)>,
+    // when connecting to each peer last failed
+    failed: BTreeMap<NodeId, Instant>,
+    // accepted from the peers, with what's been read of them
+    incoming: Vec<Incoming>,
+}
+
+struct Incoming {
+    stream: TcpStream,
+    addr: IpAddr,
+    // the peer the connection is bound to, once it's sent a message
+    from: Option<NodeId>,
+    read: Vec<u8>,
+}
+
+impl Transport {
+    pub fn new(id: NodeId, listener: TcpListener, peers: &[(NodeId, SocketAddr)]) -> Result<Self, Error> {
+        listener.set_nonblocking(true)?;
+        Ok(Self { id, listener, peers: peers.iter().copied().collect(), outgoing: BTreeMap::new(), failed: BTreeMap::new(), incoming: vec![] })
+    }
+
+    pub fn send(&mut self, to: NodeId, message: &Message) {
+        if !self.outgoing.contains_key(&to) {
+            let Some(addr) = self.peers.get(&to) else {
+                return;
+            };
+            if self.failed.get(&to).is_some_and(|failed| failed.elapsed() < RECONNECT_DELAY) {
+                return;
+            }
+            let connected = TcpStream::connect_timeout(addr, CONNECT_TIMEOUT).and_then(|stream| {
+                stream.set_nonblocking(true)?;
+                stream.set_nodelay(true)?;
+                Ok(stream)
+            });
+            match connected {
+                Ok(stream) => {
+                    self.failed.remove(&to);
+                    self.outgoing.insert(to, (stream, vec![]));
+                }
+                Err(_) => {
+                    self.failed.insert(to, Instant::now());
+                    return;
+                }
+            }
+        }
+        let (_, out) = self.outgoing.get_mut(&to).unwrap();
+        let body = message.encode();
+        if out.len() + FRAME_HEADER_SIZE + body.len() <= MAX_OUTGOING {
+            out.extend_from_slice(&self.id.to_le_bytes());
+            out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            out.extend_from_slice(&body);
+        }
+    }
+
+    // Writes what can be written without waiting, closing the connections failing.
+    pub fn flush(&mut self) {
+        self.outgoing.retain(|_, (stream, out)| replication::send(stream, out).is_ok());
+    }
+
+    // The messages received since, with the nodes they're from.
+    pub fn receive(&mut self) -> Vec<(NodeId, Message)> {
+        while let Ok((stream, addr)) = self.listener.accept() {
+            if stream.set_nonblocking(true).and_then(|_| stream.set_nodelay(true)).is_ok() {
+                self.incoming.push(Incoming { stream, addr: addr.ip(), from: None, read: vec![] });
+            }
+        }
+        let peers = &self.peers;
+        let mut messages = vec![];
+        self.incoming.retain_mut(|incoming| {
+            let mut open = replication::receive(&mut incoming.stream, &mut incoming.read).is_ok();
+            let mut pos = 0;
+            while let Some(header) = incoming.read.get(pos..pos + FRAME_HEADER_SIZE) {
+                let from = u64::from_le_bytes(header[..8].try_into().unwrap());
+                let len = u32::from_le_bytes(header[8..].try_into().unwrap()) as usize;
+                if incoming.from.is_some_and(|bound| bound != from) || peers.get(&from).is_none_or(|peer| peer.ip() != incoming.addr) {
+                    return false;
+                }
+                incoming.from = Some(from);
+                let Some(body) = incoming.read.get(pos + FRAME_HEADER_SIZE..pos + FRAME_HEADER_SIZE + len) else {
+                    break;
+                };
+                match Message::decode(body) {
+                    Ok(message) => messages.push((from, message)),
+                    Err(_) => open = false,
+                }
+                pos += FRAME_HEADER_SIZE + len;
+            }
+            incoming.read.drain(..pos);
+            open
+        });
+        messages
+    }
+}
+
+// A node of a cluster with its transport, run by the database, proposing its log, or by a
+// replica, applying it.
+pub struct Consensus {
+    node: Node,
+    transport: Transport,
+    // the node of the database, which replicas hand the lead to, and the address of its server
+    // if it's been made the primary by failover
+    primary: NodeId,
+    primary_addr: Option<String>,
+    // the address of the node's own server, without which a replica doesn't fail over
+    addr: Option<String>,
+    // on a replica, since when it's been leading
+    leading_since: Option<Instant>,
+    last_tick: Instant,
+    // on the database, where the next record proposed is read from, once known
+    next: Option<Lsn>,
+    // the term it was worked out in, as the leader of it
+    proposing: Option<u64>,
+    // LSN before which every record is in entries committed
+    replicated: Option<Lsn>,
+    slot: Option<Lsn>,
+}
+
+impl Consensus {
+    // Opens the node `id` of the cluster, whose state is kept in `dir`, listening on `listener`
+    // for the `peers`, the others, one of which or it being the node of the database the cluster
+    // starts with, `primary`.
+    pub fn open(dir: impl AsRef<Path>, id: NodeId, listener: TcpListener, peers: &[(NodeId, SocketAddr)], primary: NodeId) -> Result<Self, Error> {
+        let ids: Vec<NodeId> = peers.iter().map(|&(id, _)| id).collect();
+        let (primary, primary_addr) = match fs::read(dir.as_ref().join(PRIMARY_FILE)) {
+            Ok(bytes) => primary_of(&bytes).map(|(node, addr)| (node, Some(addr))).ok_or(Error::Malformed("primary"))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => (primary, None),
+            Err(e) => return Err(e.into()),
+        };
+        let node = Node::open(dir, id, &ids)?;
+        let transport = Transport::new(id, listener, peers)?;
+        // the records of the entries kept follow on from those of the ones compacted, which were
+        // committed
+        let mut spans = node.entries_after(0).iter().filter_map(|entry| span(&entry.data));
+        let replicated = spans.next().map(|(start, _)| start);
+        Ok(Self {
+            node,
+            transport,
+            primary,
+            primary_addr,
+            addr: None,
+            leading_since: None,
+            last_tick: Instant::now(),
+            next: None,
+            proposing: None,
+            replicated,
+            slot: None,
+        })
+    }
+
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    // Sets the address clients connect to the server of the node at, which a replica needs to
+    // fail over, for the others to redirect clients to.
+    pub fn set_addr(&mut self, addr: &str) {
+        self.addr = Some(addr.to_string());
+    }
+
+    // The node of the primary, which the database's node no longer is once another has been made
+    // the primary.
+    pub fn primary(&self) -> NodeId {
+        self.primary
+    }
+
+    // The address of the server of the primary, if it's been made the primary by failover.
+    pub fn primary_addr(&self) -> Option<&str> {
+        self.primary_addr.as_deref()
+    }
+
+    // Whether another node has been made the primary, or is being, so that the database's node
+    // is to propose nothing more.
+    pub fn deposed(&self) -> bool {
+        self.primary != self.node.id() || self.pending_primary().is_some_and(|(node, _)| node != self.node.id())
+    }
+
+    // The node made the primary by the last entry doing so which isn't committed yet, if any.
+    fn pending_primary(&self) -> Option<(NodeId, String)> {
+        self.node.entries_after(self.node.committed()).iter().rev().find_map(|entry| primary_of(&entry.data))
+    }
+
+    // Takes the entries committed since, returning the records in them, and learning of the
+    // primaries made by them.
+    fn take_committed(&mut self) -> Result<Vec<u8>, Error> {
+        let mut records = vec![];
+        for entry in self.node.take_committed() {
+            if let Some((_, after)) = span(&entry.data) {
+                self.replicated = Some(after);
+                records.extend_from_slice(records_of(&entry.data));
+            }
+            if let Some((node, addr)) = primary_of(&entry.data) {
+                replication::write_file(&self.node.dir, PRIMARY_FILE, &entry.data)?;
+                (self.primary, self.primary_addr) = (node, Some(addr));
+            }
+        }
+        Ok(records)
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.transport.listener.local_addr()
+    }
+
+    // Runs the node of the database a step, proposing the records of the log made durable since
+    // when leading, unless deposed, when it hands the lead to the primary. Returns the LSN before
+    // which every record is committed.
+    pub fn step(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Lsn, Error> {
+        self.exchange()?;
+        let Some(wal) = bufmgr.wal() else {
+            return Err(wal::Error::Config("consensus needs a log".to_string()).into());
+        };
+        if self.deposed() {
+            // the records committed since are the new primary's
+            let replicated = self.replicated;
+            self.take_committed()?;
+            self.replicated = replicated;
+            self.node.transfer(self.primary);
+            self.finish_step()?;
+            return Ok(self.replicated.unwrap_or(0));
+        }
+        self.take_committed()?;
+        let replicated = *self.replicated.get_or_insert(wal.start());
+        if !self.node.is_leader() {
+            self.proposing = None;
+        } else {
+            if self.proposing != Some(self.node.term()) {
+                // of the entries proposed before, those not committed yet may have been dropped
+                self.proposing = Some(self.node.term());
+                let proposed = self.node.entries_after(self.node.committed()).iter().rev().find_map(|entry| span(&entry.data));
+                self.next = Some(proposed.map_or(replicated, |(_, after)| after));
+            }
+            let next = self.next.unwrap();
+            let (mut data, mut after) = (vec![RECORDS], next);
+            while data.len() < MAX_ENTRY_SIZE && after < wal.flushed() {
+                let Some((record, lsn)) = wal.read(after)? else {
+                    break;
+                };
+                replication::encode_record(&record, lsn, &mut data);
+                after = lsn;
+            }
+            if data.len() > 1 {
+                self.node.propose(data)?;
+                self.next = Some(after);
+            }
+        }
+        if self.slot != Some(replicated) {
+            wal.set_slot(SLOT, Some(replicated))?;
+            self.slot = Some(replicated);
+        }
+        self.finish_step()?;
+        Ok(self.replicated.unwrap())
+    }
+
+    // Runs the node of a replica a step, giving it the records of the entries committed since,
+    // and failing over if it's been leading for FAILOVER_TIMEOUT. Returns whether it's been made
+    // the primary, when the replica is to be promoted and the node run by `step` from then on.
+    pub fn step_replica(&mut self, replica: &mut Replica) -> Result<bool, Error> {
+        self.exchange()?;
+        let records = self.take_committed()?;
+        replica.receive(&records);
+        replica.step()?;
+        if !self.node.is_leader() || self.primary == self.node.id() {
+            self.leading_since = None;
+        } else {
+            let since = *self.leading_since.get_or_insert_with(Instant::now);
+            let applied = self.replicated.is_none_or(|replicated| replica.applied() >= replicated);
+            if let Some(addr) = &self.addr {
+                if since.elapsed() >= FAILOVER_TIMEOUT && applied && self.pending_primary().is_none() {
+                    let mut data = vec![PRIMARY];
+                    data.extend_from_slice(&self.node.id().to_le_bytes());
+                    data.extend_from_slice(addr.as_bytes());
+                    self.node.propose(data)?;
+                }
+            }
+        }
+        self.node.transfer(self.pending_primary().map_or(self.primary, |(node, _)| node));
+        self.finish_step()?;
+        Ok(self.primary == self.node.id())
+    }
+
+    fn exchange(&mut self) -> Result<(), Error> {
+        for (from, message) in self.transport.receive() {
+            self.node.handle(from, message)?;
+        }
+        // a tick a step at most, for a node not stepped for a while not to stand for election
+        // before it's heard from the leader
+        if self.last_tick.elapsed() >= TICK {
+            self.last_tick = Instant::now();
+            self.node.tick()?;
+        }
+        Ok(())
+    }
+
+    fn finish_step(&mut self) -> Result<(), Error> {
+        if self.node.committed() >= self.node.first + 2 * KEEP_ENTRIES {
+            self.node.compact(self.node.committed() - KEEP_ENTRIES)?;
+        }
+        for (to, message) in self.node.take_messages() {
+            self.transport.send(to, &message);
+        }
+        self.transport.flush();
+        Ok(())
+    }
+}
+
+// The records in the data of an entry, if it's of them.
+fn records_of(data: &[u8]) -> &[u8] {
+    data.strip_prefix(&[RECORDS]).unwrap_or_default()
+}
+
+// The node made the primary by the data of an entry, if it's of that, and its address.
+fn primary_of(data: &[u8]) -> Option<(NodeId, String)> {
+    let rest = data.strip_prefix(&[PRIMARY])?;
+    let node = u64::from_le_bytes(rest.get(..8)?.try_into().unwrap());
+    let addr = String::from_utf8(rest[8..].to_vec()).ok()?;
+    Some((node, addr))
+}
+
+// The LSN of the first record in the data of an entry and the one after the last, if any.
+fn span(data: &[u8]) -> Option<(Lsn, Lsn)> {
+    let data = records_of(data);
+    let (mut pos, mut span) = (0, None);
+    while let Some(header) = data.get(pos..pos + replication::RECORD_HEADER_SIZE) {
+        let lsn = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let after = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        let len = u32::from_le_bytes(header[16..].try_into().unwrap()) as usize;
+        span = Some((span.map_or(lsn, |(start, _)| start), after));
+        pos += replication::RECORD_HEADER_SIZE + len;
+    }
+    span
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{BTree, SearchMode};
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::recovery;
+    use crate::wal::Wal;
+    use std::thread;
+    use tempfile::{tempdir, NamedTempFile};
+
+    // Delivers the messages of the nodes until there are none, dropping those to or from the
+    // nodes cut off.
+    fn deliver(nodes: &mut BTreeMap<NodeId, Node>, cut_off: &[NodeId]) {
+        loop {
+            let mut messages = vec![];
+            for (&id, node) in nodes.iter_mut() {
+                messages.extend(node.take_messages().into_iter().map(|(to, message)| (id, to, message)));
+            }
+            if messages.is_empty() {
+                return;
+            }
+            for (from, to, message) in messages {
+                if !cut_off.contains(&from) && !cut_off.contains(&to) {
+                    nodes.get_mut(&to).unwrap().handle(from, message).unwrap();
+                }
+            }
+        }
+    }
+
+    fn run(nodes: &mut BTreeMap<NodeId, Node>, cut_off: &[NodeId], ticks: usize) {
+        for _ in 0..ticks {
+            for node in nodes.values_mut() {
+                node.tick().unwrap();
+            }
+            deliver(nodes, cut_off);
+        }
+    }
+
+    fn leader(nodes: &BTreeMap<NodeId, Node>) -> NodeId {
+        nodes.values().filter(|node| node.is_leader()).max_by_key(|node| node.term()).unwrap().id()
+    }
+
+    fn data(entries: &[Entry]) -> Vec<&[u8]> {
+        entries.iter().filter(|entry| !entry.data.is_empty()).map(|entry| &entry.data[..]).collect()
+    }
+
+    #[test]
+    fn test() {
+        let dirs: Vec<_> = (0..3).map(|_| tempdir().unwrap()).collect();
+        let open = |id: NodeId| {
+            let peers: Vec<NodeId> = (1..=3).filter(|&peer| peer != id).collect();
+            Node::open(dirs[id as usize - 1].path(), id, &peers).unwrap()
+        };
+        let mut nodes: BTreeMap<NodeId, Node> = (1..=3).map(|id| (id, open(id))).collect();
+
+        // one of them is elected, which the others follow
+        run(&mut nodes, &[], 100);
+        let first = leader(&nodes);
+        assert_eq!(1, nodes.values().filter(|node| node.is_leader()).count());
+        assert!(nodes.values().all(|node| node.leader() == Some(first) && node.term() == nodes[&first].term()));
+        let follower = (1..=3).find(|&id| id != first).unwrap();
+        assert!(matches!(nodes.get_mut(&follower).unwrap().propose(b"x".to_vec()), Err(Error::NotLeader(Some(id))) if id == first));
+
+        // an entry is committed once a majority have it, and taken in order
+        let index = nodes.get_mut(&first).unwrap().propose(b"a".to_vec()).unwrap();
+        run(&mut nodes, &[], HEARTBEAT_TICKS as usize);
+        for node in nodes.values_mut() {
+            assert_eq!(index, node.committed());
+            assert_eq!(vec![&b"a"[..]], data(&node.take_committed()));
+            assert!(node.take_committed().is_empty());
+        }
+
+        // a leader cut off commits nothing, while the others elect one of them in a term after
+        let lost = nodes.get_mut(&first).unwrap().propose(b"lost".to_vec()).unwrap();
+        run(&mut nodes, &[first], 100);
+        assert!(nodes[&first].is_leader() && nodes[&first].committed() < lost);
+        let second = leader(&nodes);
+        assert!(second != first && nodes[&second].term() > nodes[&first].term());
+        let index = nodes.get_mut(&second).unwrap().propose(b"b".to_vec()).unwrap();
+        run(&mut nodes, &[first], HEARTBEAT_TICKS as usize);
+        assert_eq!(index, nodes[&second].committed());
+        // and once back, it follows, dropping what it had proposed for the entries of the leader
+        run(&mut nodes, &[], 20);
+        assert_eq!(second, leader(&nodes));
+        for node in nodes.values_mut() {
+            assert_eq!(Some(second), node.leader());
+            assert_eq!(vec![&b"b"[..]], data(&node.take_committed()));
+            assert_eq!(vec![&b"a"[..], &b"b"[..]], data(node.entries_after(0)));
+        }
+
+        // the term, vote and log are kept across reopening, but for entries compacted, and what's
+        // torn off the end of the log
+        let committed = nodes[&second].committed();
+        nodes.get_mut(&second).unwrap().compact(committed).unwrap();
+        let terms: Vec<_> = nodes.values().map(|node| (node.term(), node.last_index())).collect();
+        drop(nodes);
+        let mut log = OpenOptions::new().append(true).open(dirs[first as usize - 1].path().join(LOG_FILE)).unwrap();
+        log.write_all(&[1, 0, 0]).unwrap();
+        let mut nodes: BTreeMap<NodeId, Node> = (1..=3).map(|id| (id, open(id))).collect();
+        assert_eq!(terms, nodes.values().map(|node| (node.term(), node.last_index())).collect::<Vec<_>>());
+        assert!(nodes[&second].entries_after(0).is_empty());
+        run(&mut nodes, &[], 100);
+        let third = leader(&nodes);
+        nodes.get_mut(&third).unwrap().propose(b"c".to_vec()).unwrap();
+        run(&mut nodes, &[], HEARTBEAT_TICKS as usize);
+        assert_eq!(vec![&b"c"[..]], data(&nodes.get_mut(&second).unwrap().take_committed()));
+        assert_eq!(vec![&b"a"[..], &b"b"[..], &b"c"[..]], data(&nodes.get_mut(&first).unwrap().take_committed()));
+
+        // alone, a node is its own majority
+        let dir = tempdir().unwrap();
+        let mut node = Node::open(dir.path(), 1, &[]).unwrap();
+        node.campaign().unwrap();
+        assert!(node.is_leader());
+        let index = node.propose(b"d".to_vec()).unwrap();
+        assert_eq!(index, node.committed());
+        assert!(node.take_messages().is_empty());
+
+        // nodes not of the cluster are ignored, neither electing a leader nor committing entries
+        let dir = tempdir().unwrap();
+        let mut node = Node::open(dir.path(), 1, &[2, 3]).unwrap();
+        node.campaign().unwrap();
+        let term = node.term();
+        node.handle(98, Message::Vote { term, granted: true }).unwrap();
+        assert!(!node.is_leader());
+        node.handle(99, Message::RequestVote { term: term + 1, last_index: 100, last_term: term + 1 }).unwrap();
+        assert_eq!(term, node.term());
+        node.handle(2, Message::Vote { term, granted: true }).unwrap();
+        assert!(node.is_leader());
+        let index = node.propose(b"f".to_vec()).unwrap();
+        node.take_messages();
+        for stranger in [98, 99] {
+            node.handle(stranger, Message::Appended { term, success: true, index }).unwrap();
+        }
+        assert!(node.committed() < index);
+        assert!(node.take_messages().is_empty());
+        node.handle(3, Message::Appended { term, success: true, index }).unwrap();
+        assert_eq!(index, node.committed());
+
+        let message = Message::Append { term: 3, prev_index: 7, prev_term: 2, entries: vec![Entry { term: 3, data: b"e".to_vec() }, Entry { term: 3, data: vec![] }], commit: 5 };
+        let bytes = message.encode();
+        assert_eq!(message, Message::decode(&bytes).unwrap());
+        assert!(Message::decode(&bytes[..bytes.len() - 1]).is_err());
+
+        // a connection is closed on a message from a node it isn't bound to, or not of the cluster
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut transport = Transport::new(1, listener, &[(2, addr)]).unwrap();
+        let frame = |from: NodeId| {
+            let body = Message::TimeoutNow { term: 1 }.encode();
+            let mut frame = from.to_le_bytes().to_vec();
+            frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            frame.extend_from_slice(&body);
+            frame
+        };
+        let receive = |transport: &mut Transport, frames: &[NodeId]| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(&frames.iter().flat_map(|&from| frame(from)).collect::<Vec<_>>()).unwrap();
+            thread::sleep(Duration::from_millis(50));
+            transport.receive().into_iter().map(|(from, _)| from).collect::<Vec<_>>()
+        };
+        assert_eq!(vec![2, 2], receive(&mut transport, &[2, 2, 98, 2]));
+        assert!(receive(&mut transport, &[98, 2]).is_empty());
+        assert!(transport.incoming.is_empty());
+
+        // the log of a database replicated to two replicas over TCP
+        let (data_file, data_path) = NamedTempFile::new().unwrap().into_parts();
+        drop(data_file);
+        let wal_dir = tempdir().unwrap();
+        let disk = DiskManager::open(&data_path).unwrap();
+        let mut bufmgr = BufferPoolManager::with_wal(disk, BufferPool::new(8), Wal::open(wal_dir.path(), 16 * 1024).unwrap()).unwrap();
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        let insert = |bufmgr: &mut BufferPoolManager, keys: std::ops::Range<u64>| {
+            for i in keys {
+                btree.insert(bufmgr, &i.to_be_bytes(), &[1; 100]).unwrap();
+            }
+            bufmgr.commit().unwrap();
+        };
+        let keys = |bufmgr: &mut BufferPoolManager| {
+            let mut iter = btree.search(bufmgr, SearchMode::Start).unwrap();
+            let mut keys = vec![];
+            while let Some((key, _)) = iter.next(bufmgr).unwrap() {
+                keys.push(u64::from_be_bytes(key.try_into().unwrap()));
+            }
+            keys
+        };
+        insert(&mut bufmgr, 0..10);
+        let backup_dir = tempdir().unwrap();
+        recovery::backup(&mut bufmgr, backup_dir.path()).unwrap();
+        insert(&mut bufmgr, 10..20);
+
+        let listeners: Vec<_> = (0..3).map(|_| TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+        let addrs: Vec<_> = listeners.iter().map(|listener| listener.local_addr().unwrap()).collect();
+        let peers = |id: NodeId| -> Vec<(NodeId, SocketAddr)> { (1..=3).filter(|&peer| peer != id).map(|peer| (peer, addrs[peer as usize - 1])).collect() };
+        let raft_dirs: Vec<_> = (0..3).map(|_| tempdir().unwrap()).collect();
+        let replica_dirs: Vec<_> = (0..2).map(|_| tempdir().unwrap()).collect();
+        let mut listeners = listeners.into_iter();
+        let primary = Consensus::open(raft_dirs[0].path(), 1, listeners.next().unwrap(), &peers(1), 1).unwrap();
+        let replicas = (2..=3)
+            .zip(listeners)
+            .map(|(id, listener)| {
+                let dir = replica_dirs[id as usize - 2].path();
+                Replica::create(backup_dir.path(), dir).unwrap();
+                (Consensus::open(raft_dirs[id as usize - 1].path(), id, listener, &peers(id), 1).unwrap(), Replica::open(dir, 8).unwrap())
+            })
+            .collect();
+
+        struct Cluster {
+            primary: Consensus,
+            bufmgr: BufferPoolManager,
+            replicas: Vec<(Consensus, Replica)>,
+            // those stepped, the database first
+            up: [bool; 3],
+            replicated: Lsn,
+            // the replica made the primary, if any, and the database it stood in for once back
+            promoted: Option<usize>,
+            deposed: Option<(Consensus, BufferPoolManager)>,
+        }
+        impl Cluster {
+            fn step_until(&mut self, done: impl Fn(&mut Self) -> bool) {
+                let deadline = Instant::now() + Duration::from_secs(30);
+                while !done(self) {
+                    assert!(Instant::now() < deadline);
+                    self.step();
+                }
+            }
+
+            fn step(&mut self) {
+                if self.up[0] {
+                    self.replicated = self.primary.step(&mut self.bufmgr).unwrap();
+                }
+                for (i, (consensus, replica)) in self.replicas.iter_mut().enumerate() {
+                    if self.up[i + 1] && consensus.step_replica(replica).unwrap() {
+                        self.promoted = Some(i);
+                    }
+                }
+                if let Some((consensus, bufmgr)) = &mut self.deposed {
+                    consensus.step(bufmgr).unwrap();
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            fn caught_up(&mut self) -> bool {
+                let flushed = self.bufmgr.wal().unwrap().flushed();
+                self.replicated >= flushed && self.replicas.iter().zip(&self.up[1..]).all(|((_, replica), &up)| !up || replica.applied() >= flushed)
+            }
+        }
+        let mut cluster = Cluster { primary, bufmgr, replicas, up: [true; 3], replicated: 0, promoted: None, deposed: None };
+
+        // whichever is elected, the database leads once the others have its log
+        cluster.step_until(|cluster| cluster.caught_up() && cluster.primary.node().is_leader());
+        for (_, replica) in &mut cluster.replicas {
+            assert_eq!((0..20).collect::<Vec<_>>(), keys(replica.bufmgr()));
+        }
+        // a majority is enough to commit
+        cluster.up[2] = false;
+        insert(&mut cluster.bufmgr, 20..50);
+        cluster.step_until(Cluster::caught_up);
+        assert_eq!((0..50).collect::<Vec<_>>(), keys(cluster.replicas[0].1.bufmgr()));
+        // and short of one, nothing is
+        cluster.up[1] = false;
+        insert(&mut cluster.bufmgr, 50..60);
+        let before = cluster.replicated;
+        let deadline = Instant::now() + Duration::from_millis(300);
+        while Instant::now() < deadline {
+            cluster.step();
+        }
+        assert_eq!(before, cluster.replicated);
+        assert!(before < cluster.bufmgr.wal().unwrap().flushed());
+        cluster.up[1] = true;
+        cluster.step_until(Cluster::caught_up);
+        assert_eq!(Some(cluster.replicated), cluster.bufmgr.wal().unwrap().slot(SLOT));
+
+        // the database gone, the replicas elect one of them, for the one behind to catch up from
+        let flushed = cluster.bufmgr.wal().unwrap().flushed();
+        cluster.up = [false, true, true];
+        cluster.step_until(|cluster| cluster.replicas.iter().any(|(consensus, _)| consensus.node().is_leader()) && cluster.replicas[1].1.applied() >= flushed);
+        assert_eq!((0..60).collect::<Vec<_>>(), keys(cluster.replicas[1].1.bufmgr()));
+        // and hand the lead back once it's back, which goes on with what it wrote meanwhile
+        insert(&mut cluster.bufmgr, 60..70);
+        cluster.up[0] = true;
+        cluster.step_until(|cluster| cluster.caught_up() && cluster.primary.node().is_leader());
+        for (_, replica) in &mut cluster.replicas {
+            assert_eq!((0..70).collect::<Vec<_>>(), keys(replica.bufmgr()));
+        }
+
+        // reopened, it proposes from after the records of its log
+        let listener = cluster.primary.transport.listener.try_clone().unwrap();
+        cluster.primary = Consensus::open(raft_dirs[0].path(), 1, listener, &peers(1), 1).unwrap();
+        cluster.replicated = 0;
+        insert(&mut cluster.bufmgr, 70..80);
+        cluster.step_until(|cluster| cluster.caught_up() && cluster.primary.node().is_leader());
+        for (_, replica) in &mut cluster.replicas {
+            assert_eq!((0..80).collect::<Vec<_>>(), keys(replica.bufmgr()));
+        }
+
+        // the database gone for good, a replica with the address of its server fails over once it's
+        // led for a while, and is promoted, rolling back the transactions the database was running
+        for (consensus, _) in &mut cluster.replicas {
+            let addr = format!("node{}:5432", consensus.node().id());
+            consensus.set_addr(&addr);
+        }
+        for i in 80..90u64 {
+            btree.insert(&mut cluster.bufmgr, &i.to_be_bytes(), &[1; 100]).unwrap();
+        }
+        cluster.bufmgr.wal().unwrap().flush_all().unwrap();
+        cluster.step_until(Cluster::caught_up);
+        assert!(cluster.promoted.is_none());
+        cluster.up[0] = false;
+        cluster.step_until(|cluster| cluster.promoted.is_some());
+        let (consensus, replica) = cluster.replicas.remove(cluster.promoted.take().unwrap());
+        let new = consensus.node().id();
+        let old = std::mem::replace(&mut cluster.primary, consensus);
+        let old_bufmgr = std::mem::replace(&mut cluster.bufmgr, replica.promote(8).unwrap());
+        assert_eq!((0..80).collect::<Vec<_>>(), keys(&mut cluster.bufmgr));
+        // it takes writes, committed once the other replica has them
+        cluster.up = [true, true, false];
+        cluster.replicated = 0;
+        insert(&mut cluster.bufmgr, 100..110);
+        cluster.step_until(Cluster::caught_up);
+        let expected: Vec<_> = (0..80).chain(100..110).collect();
+        assert_eq!(expected, keys(cluster.replicas[0].1.bufmgr()));
+        assert_eq!(new, cluster.replicas[0].0.primary());
+
+        // the database, back, learns of the new primary and proposes nothing more, handing it the
+        // lead, the others going on without it
+        cluster.deposed = Some((old, old_bufmgr));
+        cluster.step_until(|cluster| cluster.deposed.as_ref().unwrap().0.primary() == new);
+        let (old, _) = cluster.deposed.as_ref().unwrap();
+        assert!(old.deposed());
+        assert_eq!(Some(format!("node{}:5432", new).as_str()), old.primary_addr());
+        insert(&mut cluster.bufmgr, 110..120);
+        cluster.step_until(|cluster| cluster.caught_up() && cluster.primary.node().is_leader());
+        let expected: Vec<_> = (0..80).chain(100..120).collect();
+        assert_eq!(expected, keys(cluster.replicas[0].1.bufmgr()));
+        // and knows it when reopened
+        let (old, _) = cluster.deposed.as_mut().unwrap();
+        let listener = old.transport.listener.try_clone().unwrap();
+        *old = Consensus::open(raft_dirs[0].path(), 1, listener, &peers(1), 1).unwrap();
+        assert_eq!(new, old.primary());
+    }
+}
@@ -0,0 +1,511 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::buffer::{self, page_lsn, BufferPoolManager};
+use crate::disk::{PageId, PAGE_SIZE};
+use crate::wal::{self, Error, LogRecord, Lsn, Record, TxId, Wal};
+
+// Files of a base backup: a copy of the data file, the LSN of the checkpoint recovery starts
+// from along with the segment size of the log, as two u64s, and a copy of the segments of the log
+// recovery needs from there.
+const BACKUP_DATA_FILE: &str = "data";
+const BACKUP_LABEL_FILE: &str = "backup_label";
+const BACKUP_WAL_DIR: &str = "wal";
+
+// Where point-in-time recovery stops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    // just before the record at or after the LSN
+    Lsn(Lsn),
+    // just before the first transaction committed after the time
+    Time(SystemTime),
+}
+
+// ARIES style recovery from the log of `bufmgr`, run when the database is opened:
+//   analysis: scans the log from the latest checkpoint for the transactions which were still
+//             running at the crash and the pages which may have changes missing from the file
+//   redo:     repeats history, applying every logged change the page doesn't have yet according
+//             to its LSN, so that running recovery again (e.g. after a crash during it) is harmless,
+//             and restoring logged images of pages whatever they have, which may be torn
+//   undo:     rolls back the transactions which didn't commit, logging compensation records so
+//             that nothing is undone twice, and marks them aborted so that the row versions they
+//             wrote, which are only redone, stay invisible
+// Finally every page is written back and a checkpoint taken, so the next recovery has nothing to redo.
+pub fn recover(bufmgr: &mut BufferPoolManager) -> Result<(), buffer::Error> {
+    let Some(wal) = bufmgr.wal() else {
+        return Ok(());
+    };
+
+    // analysis
+    // (latest LSN, whether it has committed) of each transaction without an END record
+    let mut transactions: BTreeMap<TxId, (Lsn, bool)> = BTreeMap::new();
+    // the first LSN which may have changed each page, from which it has to be redone
+    let mut dirty_pages: BTreeMap<PageId, Lsn> = BTreeMap::new();
+    let mut ended = HashSet::new();
+    let mut aborted = BTreeSet::new();
+    let mut next_txid = 1;
+    // where the next record is read from, which is before it if that's padding at the end of a segment
+    let mut pos = wal.last_checkpoint()?.unwrap_or(wal.start());
+    // the pages the undo changes are logged whole first as they would be after the checkpoint
+    bufmgr.set_last_checkpoint(pos);
+    let wal = bufmgr.wal().unwrap();
+    while let Some((record, next)) = wal.read(pos)? {
+        let lsn = record.lsn;
+        next_txid = next_txid.max(record.txid + 1);
+        match &record.record {
+            Record::End => {
+                transactions.remove(&record.txid);
+                ended.insert(record.txid);
+            }
+            Record::Commit { .. } => {
+                transactions.insert(record.txid, (lsn, true));
+            }
+            Record::Update { page_id, .. } | Record::Compensation { page_id, .. } | Record::Redo { page_id, .. } | Record::PageImage { page_id, .. } => {
+                dirty_pages.entry(*page_id).or_insert(lsn);
+                // unless it's a page of an in-memory table logged whole by a checkpoint
+                if record.txid != 0 {
+                    transactions.insert(record.txid, (lsn, false));
+                }
+            }
+            Record::Abort => {
+                aborted.insert(record.txid);
+                transactions.insert(record.txid, (lsn, false));
+            }
+            Record::Begin | Record::Change { .. } | Record::AtomicEnd { .. } => {
+                transactions.insert(record.txid, (lsn, false));
+            }
+            // the tables are as of some time since the checkpoint began, so what's been read since
+            // then is newer, except that pages may have been dirty from earlier
+            Record::CheckpointEnd(checkpoint) => {
+                next_txid = next_txid.max(checkpoint.next_txid);
+                for &(txid, last_lsn) in &checkpoint.transactions {
+                    if !ended.contains(&txid) {
+                        transactions.entry(txid).or_insert((last_lsn, false));
+                    }
+                }
+                for &(page_id, rec_lsn) in &checkpoint.dirty_pages {
+                    let first = dirty_pages.entry(page_id).or_insert(rec_lsn);
+                    *first = rec_lsn.min(*first);
+                }
+                aborted.extend(&checkpoint.aborted);
+            }
+            Record::CheckpointBegin | Record::Vacuum { .. } | Record::Truncate { .. } => {}
+        }
+        pos = next;
+    }
+    bufmgr.set_next_txid(next_txid);
+    for &page_id in dirty_pages.keys() {
+        bufmgr.mark_allocated(page_id);
+    }
+
+    // redo
+    if let Some(&start) = dirty_pages.values().min() {
+        let mut pos = start;
+        while let Some((record, next)) = bufmgr.wal().unwrap().read(pos)? {
+            let lsn = record.lsn;
+            // a truncation is redone whatever the pages had, those after it being redone from zeros
+            let redone = match record.record.page_id() {
+                Some(page_id) => dirty_pages.get(&page_id).is_some_and(|&first| first <= lsn),
+                None => matches!(record.record, Record::Truncate { .. }),
+            };
+            if redone {
+                redo(bufmgr, &record)?;
+            }
+            pos = next;
+        }
+    }
+
+    // undo
+    let mut losers = BTreeMap::new();
+    for (txid, (last_lsn, committed)) in transactions {
+        if committed {
+            bufmgr.wal().unwrap().append(txid, Some(last_lsn), &Record::End)?;
+        } else {
+            losers.insert(txid, last_lsn);
+            aborted.insert(txid);
+        }
+    }
+    bufmgr.set_aborted(aborted);
+    rollback(bufmgr, losers)?;
+    bufmgr.flush()?;
+    bufmgr.checkpoint()
+}
+
+// Undoes the changes of each transaction in `transactions` back from the given latest LSN, the
+// latest change first across all of them, then ends them.
+pub fn rollback(bufmgr: &mut BufferPoolManager, mut transactions: BTreeMap<TxId, Lsn>) -> Result<(), buffer::Error> {
+    // the next record to undo of each transaction, None once it's rolled back entirely
+    let mut undo_next: BTreeMap<TxId, Option<Lsn>> = transactions.iter().map(|(&txid, &lsn)| (txid, Some(lsn))).collect();
+    loop {
+        let latest = undo_next.iter().filter_map(|(&txid, &lsn)| Some((txid, lsn?))).max_by_key(|&(_, lsn)| lsn);
+        let Some((txid, lsn)) = latest else {
+            break;
+        };
+        let record = read(bufmgr, lsn)?;
+        let next = match record.record {
+            Record::Update { page_id, offset, before, .. } => {
+                let compensation = Record::Compensation {
+                    page_id,
+                    offset,
+                    after: before.clone(),
+                    undo_next: record.prev_lsn,
+                };
+                let last_lsn = transactions.get_mut(&txid).unwrap();
+                *last_lsn = bufmgr.log_page_image(txid, *last_lsn, page_id)?;
+                *last_lsn = bufmgr.wal().unwrap().append(txid, Some(*last_lsn), &compensation)?;
+                bufmgr.apply(page_id, offset as usize, &before, *last_lsn)?;
+                record.prev_lsn
+            }
+            Record::Compensation { undo_next, .. } | Record::AtomicEnd { undo_next } => undo_next,
+            Record::Redo { .. } | Record::PageImage { .. } | Record::Change { .. } | Record::Begin | Record::Commit { .. } | Record::Abort | Record::End => {
+                record.prev_lsn
+            }
+            Record::CheckpointBegin | Record::CheckpointEnd(_) | Record::Vacuum { .. } | Record::Truncate { .. } => return Err(Error::Malformed(lsn).into()),
+        };
+        undo_next.insert(txid, next);
+    }
+    let wal = bufmgr.wal().unwrap();
+    for (txid, last_lsn) in transactions {
+        wal.append(txid, Some(last_lsn), &Record::End)?;
+    }
+    Ok(())
+}
+
+// Applies the change logged in `record`, if any, unless the page has it already.
+pub(crate) fn redo(bufmgr: &mut BufferPoolManager, record: &LogRecord) -> Result<(), buffer::Error> {
+    if let Record::Truncate { num_pages } = record.record {
+        return bufmgr.truncate_file(num_pages);
+    }
+    if let Record::PageImage { page_id, image } = &record.record {
+        bufmgr.mark_allocated(*page_id);
+        return bufmgr.apply_image(*page_id, image, record.lsn);
+    }
+    let (Record::Update { page_id, offset, after, .. } | Record::Compensation { page_id, offset, after, .. } | Record::Redo { page_id, offset, after }) =
+        &record.record
+    else {
+        return Ok(());
+    };
+    // the page may never have been written to the file
+    bufmgr.mark_allocated(*page_id);
+    let buffer = bufmgr.fetch_page(*page_id)?;
+    if page_lsn(&buffer.page.borrow()) < record.lsn {
+        bufmgr.apply(*page_id, *offset as usize, after, record.lsn)?;
+    }
+    Ok(())
+}
+
+fn read(bufmgr: &mut BufferPoolManager, lsn: Lsn) -> Result<LogRecord, buffer::Error> {
+    match bufmgr.wal().unwrap().read(lsn)? {
+        Some((record, _)) => Ok(record),
+        None => Err(Error::Malformed(lsn).into()),
+    }
+}
+
+// Takes a base backup of the database of `bufmgr` into `dir`: a checkpoint is taken after writing
+// back every page, and the data file copied along with the log kept by it. Along with the log from
+// then on, `restore` can bring the database back as of any time since.
+pub fn backup(bufmgr: &mut BufferPoolManager, dir: impl AsRef<Path>) -> Result<(), buffer::Error> {
+    if bufmgr.wal().is_none() {
+        return Err(Error::Config("a backup needs a log".to_string()).into());
+    }
+    bufmgr.flush()?;
+    bufmgr.checkpoint()?;
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    let mut file = File::create(dir.join(BACKUP_DATA_FILE))?;
+    let mut page = vec![0; PAGE_SIZE as usize];
+    for page_id in 0..bufmgr.disk().num_pages() {
+        bufmgr.disk().read_page_data(PageId(page_id), &mut page)?;
+        file.write_all(&page)?;
+    }
+    file.sync_all()?;
+    let wal = bufmgr.wal().unwrap();
+    let wal_dir = dir.join(BACKUP_WAL_DIR);
+    fs::create_dir_all(&wal_dir)?;
+    for start in wal.segments() {
+        fs::write(wal::segment_path(&wal_dir, start), wal.read_segment(start)?)?;
+    }
+    let mut label = wal.last_checkpoint()?.unwrap().to_le_bytes().to_vec();
+    label.extend_from_slice(&wal.segment_size().to_le_bytes());
+    fs::write(dir.join(BACKUP_LABEL_FILE), label)?;
+    Ok(())
+}
+
+// Copies the data file of the base backup in `backup_dir` to `data_path`, returning the LSN of the
+// checkpoint to go on from and the segment size of the log.
+pub(crate) fn copy_backup(backup_dir: &Path, data_path: &Path) -> Result<(Lsn, u64), buffer::Error> {
+    let label = fs::read(backup_dir.join(BACKUP_LABEL_FILE))?;
+    if label.len() != 16 {
+        return Err(Error::Config("malformed backup label".to_string()).into());
+    }
+    let checkpoint = u64::from_le_bytes(label[..8].try_into().unwrap());
+    let segment_size = u64::from_le_bytes(label[8..].try_into().unwrap());
+    fs::copy(backup_dir.join(BACKUP_DATA_FILE), data_path)?;
+    Ok((checkpoint, segment_size))
+}
+
+// Copies the segments of the log in the base backup in `backup_dir` to `wal_dir`, recovery going
+// on from its checkpoint.
+pub(crate) fn copy_backup_log(backup_dir: &Path, wal_dir: &Path, checkpoint: Lsn) -> Result<(), buffer::Error> {
+    fs::create_dir_all(wal_dir)?;
+    for entry in fs::read_dir(backup_dir.join(BACKUP_WAL_DIR))? {
+        let path = entry?.path();
+        fs::copy(&path, wal_dir.join(path.file_name().unwrap()))?;
+    }
+    wal::write_last_checkpoint(wal_dir, checkpoint)?;
+    Ok(())
+}
+
+// Point-in-time recovery: makes a database at `data_path` with its log in `wal_dir` out of the base
+// backup in `backup_dir` and the segments archived since in `archive_dir`, with the log cut at
+// `target`. Opening it then redoes the rest and rolls back the transactions which hadn't committed
+// by the target. Any segments of the lost log which weren't archived yet can be copied in with them.
+//
+// The restored log goes on from the target with LSNs which the archived log already used, so it
+// mustn't be archived to the same place.
+pub fn restore(
+    backup_dir: impl AsRef<Path>,
+    archive_dir: impl AsRef<Path>,
+    data_path: impl AsRef<Path>,
+    wal_dir: impl AsRef<Path>,
+    target: Target,
+) -> Result<(), buffer::Error> {
+    let wal_dir = wal_dir.as_ref();
+    let (checkpoint, segment_size) = copy_backup(backup_dir.as_ref(), data_path.as_ref())?;
+    fs::create_dir_all(wal_dir)?;
+    for entry in fs::read_dir(archive_dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "wal") {
+            fs::copy(&path, wal_dir.join(path.file_name().unwrap()))?;
+        }
+    }
+    wal::write_last_checkpoint(wal_dir, checkpoint)?;
+
+    let mut wal = Wal::open(wal_dir, segment_size)?;
+    let mut pos = checkpoint;
+    while let Some((record, next)) = wal.read(pos)? {
+        let past = match (target, &record.record) {
+            (Target::Lsn(lsn), _) => record.lsn >= lsn,
+            (Target::Time(target), Record::Commit { time }) => *time > target,
+            (Target::Time(_), _) => false,
+        };
+        if past {
+            wal.truncate(record.lsn)?;
+            break;
+        }
+        pos = next;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{BTree, SearchMode};
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use std::thread;
+    use std::time::Duration;
+    use tempfile::{tempdir, NamedTempFile};
+
+    fn keys(bufmgr: &mut BufferPoolManager, btree: &BTree) -> Vec<u64> {
+        let mut iter = btree.search(bufmgr, SearchMode::Start).unwrap();
+        let mut keys = vec![];
+        while let Some((key, _)) = iter.next(bufmgr).unwrap() {
+            keys.push(u64::from_be_bytes(key.try_into().unwrap()));
+        }
+        keys
+    }
+
+    #[test]
+    fn test() {
+        let (data_file, data_path) = NamedTempFile::new().unwrap().into_parts();
+        let wal_dir = tempdir().unwrap();
+        let open = || {
+            let disk = DiskManager::open(&data_path).unwrap();
+            let wal = Wal::open(wal_dir.path(), 16 * 1024).unwrap();
+            BufferPoolManager::with_wal(disk, BufferPool::new(4), wal).unwrap()
+        };
+        drop(data_file);
+        let value = [0; 100];
+
+        // committed changes which never reached the data file are redone
+        let mut bufmgr = open();
+        let btree = BTree::create(&mut bufmgr).unwrap();
+        for i in 0..100u64 {
+            btree.insert(&mut bufmgr, &i.to_be_bytes(), &value).unwrap();
+        }
+        bufmgr.commit().unwrap();
+        drop(bufmgr);
+        let mut bufmgr = open();
+        assert_eq!((0..100).collect::<Vec<_>>(), keys(&mut bufmgr, &btree));
+
+        // uncommitted changes are undone, even if they were written to the data file
+        for i in 100..200u64 {
+            btree.insert(&mut bufmgr, &i.to_be_bytes(), &value).unwrap();
+        }
+        bufmgr.flush().unwrap();
+        drop(bufmgr);
+        let mut bufmgr = open();
+        assert_eq!((0..100).collect::<Vec<_>>(), keys(&mut bufmgr, &btree));
+
+        // and so are aborted ones, from the log entries which are still being buffered
+        for i in 200..300u64 {
+            btree.insert(&mut bufmgr, &i.to_be_bytes(), &value).unwrap();
+        }
+        assert!(bufmgr.abort().unwrap());
+        assert!(!bufmgr.abort().unwrap());
+        btree.insert(&mut bufmgr, &300u64.to_be_bytes(), &value).unwrap();
+        bufmgr.commit().unwrap();
+        let expected: Vec<_> = (0..100).chain([300]).collect();
+        assert_eq!(expected, keys(&mut bufmgr, &btree));
+        drop(bufmgr);
+
+        // recovering again changes nothing
+        for _ in 0..2 {
+            let mut bufmgr = open();
+            assert_eq!(expected, keys(&mut bufmgr, &btree));
+        }
+
+        // pages changed after a checkpoint are logged whole first, from which writes of them torn
+        // by a crash are made whole again
+        let mut bufmgr = open();
+        let before = fs::read(&data_path).unwrap();
+        for i in 400..450u64 {
+            btree.insert(&mut bufmgr, &i.to_be_bytes(), &value).unwrap();
+        }
+        bufmgr.commit().unwrap();
+        bufmgr.flush().unwrap();
+        drop(bufmgr);
+        let mut torn = fs::read(&data_path).unwrap();
+        let page_size = PAGE_SIZE as usize;
+        let mut num_torn = 0;
+        for (page, old) in torn.chunks_mut(page_size).zip(before.chunks(page_size)) {
+            if page != old {
+                // keeping the new LSN at the end, by which redo would take the page to be up to date
+                page[..page_size / 2].copy_from_slice(&old[..page_size / 2]);
+                num_torn += 1;
+            }
+        }
+        assert!(num_torn > 0);
+        fs::write(&data_path, torn).unwrap();
+        let mut bufmgr = open();
+        let expected: Vec<_> = expected.into_iter().chain(400..450).collect();
+        assert_eq!(expected, keys(&mut bufmgr, &btree));
+
+        // the changes made in `redo_only` are kept when their transaction rolls back, unless they
+        // failed partway, splitting nodes for some of the entries
+        bufmgr.redo_only(|bufmgr| (500..600u64).try_for_each(|i| btree.insert(bufmgr, &i.to_be_bytes(), &value))).unwrap();
+        let failed = bufmgr.redo_only(|bufmgr| (600..700u64).chain([500]).try_for_each(|i| btree.insert(bufmgr, &i.to_be_bytes(), &value)));
+        assert!(matches!(failed, Err(crate::btree::Error::DuplicateKey)));
+        assert!(bufmgr.abort().unwrap());
+        let expected: Vec<_> = expected.into_iter().chain(500..600).collect();
+        assert_eq!(expected, keys(&mut bufmgr, &btree));
+        drop(bufmgr);
+        let mut bufmgr = open();
+        assert_eq!(expected, keys(&mut bufmgr, &btree));
+        drop(bufmgr);
+
+        // checkpoints taken as the log grows let old segments go
+        let mut bufmgr = open();
+        bufmgr.set_checkpoint_interval(32 * 1024);
+        for i in 1000..1500u64 {
+            btree.insert(&mut bufmgr, &i.to_be_bytes(), &value).unwrap();
+            bufmgr.commit().unwrap();
+        }
+        let segments = bufmgr.wal().unwrap().segments().len();
+        assert!(segments <= 4, "{} segments", segments);
+        // a transaction running across a checkpoint is still undone
+        btree.insert(&mut bufmgr, &2000u64.to_be_bytes(), &value).unwrap();
+        bufmgr.checkpoint().unwrap();
+        btree.insert(&mut bufmgr, &2001u64.to_be_bytes(), &value).unwrap();
+        drop(bufmgr);
+        let mut bufmgr = open();
+        let expected: Vec<_> = expected.into_iter().chain(1000..1500).collect();
+        assert_eq!(expected, keys(&mut bufmgr, &btree));
+
+        // a base backup and the segments archived since bring the database back as of a point in time
+        let (archive_dir, backup_dir) = (tempdir().unwrap(), tempdir().unwrap());
+        bufmgr.wal().unwrap().set_archiver(wal::archive_to(archive_dir.path()));
+        backup(&mut bufmgr, backup_dir.path()).unwrap();
+        let backed_up = SystemTime::now();
+        for i in 3000..3100u64 {
+            btree.insert(&mut bufmgr, &i.to_be_bytes(), &value).unwrap();
+            bufmgr.commit().unwrap();
+        }
+        thread::sleep(Duration::from_millis(2));
+        let (time, lsn) = (SystemTime::now(), bufmgr.wal().unwrap().end());
+        thread::sleep(Duration::from_millis(2));
+        // a bad change, and one still running at the crash
+        for i in 3000..3100u64 {
+            btree.upsert(&mut bufmgr, &i.to_be_bytes(), &[1; 400]).unwrap();
+        }
+        bufmgr.commit().unwrap();
+        btree.insert(&mut bufmgr, &4000u64.to_be_bytes(), &value).unwrap();
+        bufmgr.flush().unwrap();
+        drop(bufmgr);
+        assert!(fs::read_dir(archive_dir.path()).unwrap().count() > 1);
+        // the segments which weren't archived yet are taken from the lost log
+        for entry in fs::read_dir(wal_dir.path()).unwrap() {
+            let path = entry.unwrap().path();
+            let archived = archive_dir.path().join(path.file_name().unwrap());
+            if path.extension().is_some_and(|ext| ext == "wal") && !archived.exists() {
+                fs::copy(path, archived).unwrap();
+            }
+        }
+        let at_backup = expected.clone();
+        let expected: Vec<_> = expected.into_iter().chain(3000..3100).collect();
+        let targets = [(Target::Time(time), &expected), (Target::Lsn(lsn), &expected), (Target::Time(backed_up), &at_backup)];
+        for (target, expected) in targets {
+            let restored_dir = tempdir().unwrap();
+            let (data_path, wal_path) = (restored_dir.path().join("data"), restored_dir.path().join("wal"));
+            restore(backup_dir.path(), archive_dir.path(), &data_path, &wal_path, target).unwrap();
+            let disk = DiskManager::open(&data_path).unwrap();
+            let wal = Wal::open(&wal_path, 16 * 1024).unwrap();
+            let mut bufmgr = BufferPoolManager::with_wal(disk, BufferPool::new(4), wal).unwrap();
+            assert_eq!(*expected, keys(&mut bufmgr, &btree));
+            let mut iter = btree.search(&mut bufmgr, SearchMode::Key(3000u64.to_be_bytes().to_vec())).unwrap();
+            let found = iter.next(&mut bufmgr).unwrap().filter(|(key, _)| *key == 3000u64.to_be_bytes());
+            assert!(found.is_none_or(|(_, found)| found == value));
+        }
+
+        // a truncation is redone, after a crash and from a backup, the pages after it being
+        // allocated anew
+        let mut bufmgr = open();
+        bufmgr.wal().unwrap().set_archiver(wal::archive_to(archive_dir.path()));
+        let num_pages = bufmgr.num_pages();
+        let dropped = BTree::create(&mut bufmgr).unwrap();
+        for i in 0..100u64 {
+            dropped.insert(&mut bufmgr, &i.to_be_bytes(), &[2; 100]).unwrap();
+        }
+        bufmgr.commit().unwrap();
+        bufmgr.truncate(num_pages).unwrap();
+        assert_eq!(num_pages, bufmgr.num_pages());
+        for i in 5000..5100u64 {
+            btree.insert(&mut bufmgr, &i.to_be_bytes(), &value).unwrap();
+        }
+        bufmgr.commit().unwrap();
+        assert!(bufmgr.num_pages() > num_pages);
+        drop(bufmgr);
+        let expected: Vec<_> = expected.into_iter().chain(5000..5100).collect();
+        let mut bufmgr = open();
+        assert_eq!(expected, keys(&mut bufmgr, &btree));
+        drop(bufmgr);
+        for entry in fs::read_dir(wal_dir.path()).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "wal") {
+                fs::copy(&path, archive_dir.path().join(path.file_name().unwrap())).unwrap();
+            }
+        }
+        let restored_dir = tempdir().unwrap();
+        let (data_path, wal_path) = (restored_dir.path().join("data"), restored_dir.path().join("wal"));
+        restore(backup_dir.path(), archive_dir.path(), &data_path, &wal_path, Target::Lsn(Lsn::MAX)).unwrap();
+        let disk = DiskManager::open(&data_path).unwrap();
+        let wal = Wal::open(&wal_path, 16 * 1024).unwrap();
+        let mut bufmgr = BufferPoolManager::with_wal(disk, BufferPool::new(4), wal).unwrap();
+        assert_eq!(expected, keys(&mut bufmgr, &btree));
+    }
+}
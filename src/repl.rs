@@ -0,0 +1,349 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+use crate::array;
+use crate::catalog::Catalog;
+use crate::database::{Database, Session};
+use crate::datetime;
+use crate::dump::{self, DumpFormat};
+use crate::json;
+use crate::sql::{self, QueryResult};
+use crate::tuple::Value;
+
+// The interactive shell of the `beyond_rdb` binary: SQL statements, which may span lines until
+// one ends with `;`, are run in a session of the database and their results shown as tables.
+// Lines starting with `.` are commands of the shell, those starting with `--` comments.
+//   .tables: the names of the tables
+//   .schema [table]: the statements creating the tables and views, or the table
+//   .stats [table]: the statistics of the tables, or the table, as of the last ANALYZE
+//   .read <file>: runs the lines of the file, stopping at the first which fails
+//   .dump [file]: a dump of the database as SQL, to the file if given (see `dump`)
+//   .restore <file>: restores a dump, SQL or an archive, into the database
+//   .help, .quit
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Sql(#[from] sql::Error),
+    #[error(transparent)]
+    Dump(#[from] dump::Error),
+    #[error("{0}")]
+    Command(String),
+}
+
+const PROMPT: &str = "beyond_rdb> ";
+const CONTINUATION_PROMPT: &str = "       ...> ";
+
+const HELP: &str = "\
+.tables            list the tables
+.schema [table]    show the statements creating the tables and views
+.stats [table]     show the statistics of the tables as of the last ANALYZE
+.read <file>       run the statements in a file
+.dump [file]       write the database as SQL, to the file if given
+.restore <file>    restore a dump into the database
+.help              show this
+.quit              exit
+";
+
+pub struct Repl<W> {
+    db: Database,
+    session: Session,
+    out: W,
+    // lines of the statement being entered
+    pending: String,
+}
+
+impl<W: Write> Repl<W> {
+    pub fn new(db: Database, out: W) -> Self {
+        let session = db.session();
+        Self { db, session, out, pending: String::new() }
+    }
+
+    // Runs the lines of `input` until it ends or `.quit`, prompting for each if `interactive`.
+    // Failures are shown and, if not `interactive`, stop it. Returns whether there were none.
+    pub fn run(&mut self, input: impl BufRead, interactive: bool) -> io::Result<bool> {
+        let mut ok = true;
+        let mut lines = input.lines();
+        loop {
+            if interactive {
+                write!(self.out, "{}", if self.pending.is_empty() { PROMPT } else { CONTINUATION_PROMPT })?;
+                self.out.flush()?;
+            }
+            let Some(line) = lines.next().transpose()? else {
+                break;
+            };
+            match self.line(&line) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) => {
+                    ok = false;
+                    writeln!(self.out, "Error: {}", err)?;
+                    if !interactive {
+                        break;
+                    }
+                }
+            }
+        }
+        if !self.pending.is_empty() {
+            self.pending.clear();
+            writeln!(self.out, "Error: incomplete statement at the end of the input")?;
+            ok = false;
+        }
+        Ok(ok)
+    }
+
+    // Handles a line of input. Returns false on `.quit`.
+    pub fn line(&mut self, line: &str) -> Result<bool, Error> {
+        let trimmed = line.trim();
+        if self.pending.is_empty() {
+            if trimmed.is_empty() || trimmed.starts_with("--") {
+                return Ok(true);
+            }
+            if let Some(command) = trimmed.strip_prefix('.') {
+                return self.command(command);
+            }
+        }
+        self.pending.push_str(line);
+        self.pending.push('\n');
+        if !trimmed.ends_with(';') {
+            return Ok(true);
+        }
+        let sql = std::mem::take(&mut self.pending);
+        for result in self.session.execute(&sql)? {
+            self.show(&result)?;
+        }
+        Ok(true)
+    }
+
+    fn command(&mut self, command: &str) -> Result<bool, Error> {
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let arg = words.next();
+        match name {
+            "quit" | "exit" => return Ok(false),
+            "help" => write!(self.out, "{}", HELP)?,
+            "tables" => {
+                let names: Vec<_> = self.db.with_engine(|_, catalog| catalog.tables().map(|info| info.name.clone()).collect());
+                for name in names {
+                    writeln!(self.out, "{}", name)?;
+                }
+            }
+            "schema" => {
+                let schema = self.db.with_engine(|_, catalog| schema(catalog, arg))?;
+                for statement in schema {
+                    writeln!(self.out, "{};", statement)?;
+                }
+            }
+            "stats" => {
+                let tables = self.db.with_engine(|_, catalog| stats(catalog, arg))?;
+                for (header, columns, rows) in tables {
+                    writeln!(self.out, "{}", header)?;
+                    if !rows.is_empty() {
+                        write_table(&mut self.out, &columns, &rows)?;
+                    }
+                }
+            }
+            "read" => {
+                let path = arg.ok_or_else(|| Error::Command("usage: .read <file>".to_string()))?;
+                let file = File::open(path)?;
+                if !self.run(BufReader::new(file), false)? {
+                    return Err(Error::Command(format!("{} stopped at a failure", path)));
+                }
+            }
+            "dump" => match arg {
+                Some(path) => dump::dump(&self.db, BufWriter::new(File::create(path)?), DumpFormat::Sql)?,
+                None => dump::dump(&self.db, &mut self.out, DumpFormat::Sql)?,
+            },
+            "restore" => {
+                let path = arg.ok_or_else(|| Error::Command("usage: .restore <file>".to_string()))?;
+                dump::restore(&self.db, BufReader::new(File::open(path)?))?;
+            }
+            _ => return Err(Error::Command(format!("unknown command: .{} (see .help)", name))),
+        }
+        Ok(true)
+    }
+
+    fn show(&mut self, result: &QueryResult) -> io::Result<()> {
+        match result {
+            QueryResult::Rows { columns, rows } => {
+                let rows: Vec<Vec<String>> = rows.iter().map(|row| row.iter().map(format_value).collect()).collect();
+                write_table(&mut self.out, columns, &rows)?;
+                writeln!(self.out, "({} row{})", rows.len(), if rows.len() == 1 { "" } else { "s" })
+            }
+            QueryResult::RowsAffected(n) => writeln!(self.out, "{} row{} affected", n, if *n == 1 { "" } else { "s" }),
+            QueryResult::Done => writeln!(self.out, "OK"),
+            QueryResult::RolledBack => writeln!(self.out, "ROLLBACK"),
+        }
+    }
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Int(n) => n.to_string(),
+        Value::Text(text) => text.clone(),
+        Value::Bool(b) => b.to_string(),
+        Value::Json(bytes) => json::to_string(bytes),
+        Value::Array(values) => array::to_string(values),
+        Value::Point(point) => point.to_string(),
+        Value::Box(rect) => rect.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Numeric(n) => n.to_string(),
+        Value::Date(days) => datetime::format_date(*days),
+        Value::Timestamp(micros) => datetime::format_timestamp(*micros),
+    }
+}
+
+// Writes `rows` as a table under `columns`, each column as wide as its widest value.
+fn write_table(out: &mut impl Write, columns: &[String], rows: &[Vec<String>]) -> io::Result<()> {
+    let mut widths: Vec<_> = columns.iter().map(|column| column.chars().count()).collect();
+    for row in rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+    let line = |values: &[String]| {
+        let cells: Vec<_> = values.iter().zip(&widths).map(|(value, &width)| format!(" {:width$} ", value)).collect();
+        cells.join("|").trim_end().to_string()
+    };
+    writeln!(out, "{}", line(columns))?;
+    let rule: Vec<_> = widths.iter().map(|&width| "-".repeat(width + 2)).collect();
+    writeln!(out, "{}", rule.join("+"))?;
+    for row in rows {
+        writeln!(out, "{}", line(row))?;
+    }
+    Ok(())
+}
+
+// The statements creating the tables, views and triggers, or `table` and its triggers alone.
+fn schema(catalog: &Catalog, table: Option<&str>) -> Result<Vec<String>, Error> {
+    let mut statements = vec![];
+    for info in catalog.tables().filter(|info| table.is_none_or(|name| info.name == name)) {
+        statements.push(dump::create_table(info));
+        statements.extend(dump::create_indexes(info));
+    }
+    match table {
+        Some(name) if statements.is_empty() => return Err(Error::Sql(sql::Error::UnknownTable(name.to_string()))),
+        Some(_) => {}
+        None => statements.extend(catalog.views().map(|view| format!("CREATE VIEW {} AS {}", view.name, view.sql))),
+    }
+    statements.extend(dump::create_triggers(catalog.triggers().filter(|info| table.is_none_or(|name| info.table == name))));
+    Ok(statements)
+}
+
+// Of each table, or `table` alone, a header with the rows counted and the statistics of its
+// columns as a table.
+#[allow(clippy::type_complexity)]
+fn stats(catalog: &Catalog, table: Option<&str>) -> Result<Vec<(String, Vec<String>, Vec<Vec<String>>)>, Error> {
+    if let Some(name) = table.filter(|name| catalog.table(name).is_none()) {
+        return Err(Error::Sql(sql::Error::UnknownTable(name.to_string())));
+    }
+    let columns = ["column", "nulls", "distinct", "min", "max"].map(String::from).to_vec();
+    let mut tables = vec![];
+    for info in catalog.tables().filter(|info| table.is_none_or(|name| info.name == name)) {
+        let Some(stats) = &info.stats else {
+            tables.push((format!("{}: not analyzed", info.name), columns.clone(), vec![]));
+            continue;
+        };
+        let rows = info
+            .columns
+            .iter()
+            .zip(&stats.columns)
+            .map(|(column, stats)| {
+                let (nulls, distinct) = (stats.null_count.to_string(), stats.distinct_count.to_string());
+                vec![column.name.clone(), nulls, distinct, format_value(&stats.min), format_value(&stats.max)]
+            })
+            .collect();
+        tables.push((format!("{}: {} rows", info.name, stats.row_count), columns.clone(), rows));
+    }
+    Ok(tables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        let dir = tempdir().unwrap();
+        let script = dir.path().join("script.sql");
+        std::fs::write(&script, "INSERT INTO t VALUES (3, 'c');\nSELECT nope FROM t;\nINSERT INTO t VALUES (4, 'd');\n").unwrap();
+        let input = format!(
+            "\
+-- statements span lines until one ends with ;
+CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT);
+CREATE INDEX t_name ON t (name); CREATE VIEW v AS SELECT name FROM t;
+INSERT INTO t
+  VALUES (1, 'a'), (2, NULL);
+SELECT * FROM t;
+.tables
+.schema
+.stats t
+ANALYZE;
+.stats
+.read {}
+SELECT count(*) AS n FROM t;
+.frobnicate
+.quit
+SELECT 1;
+",
+            script.display()
+        );
+        let db = Database::open(dir.path().join("db"), 16).unwrap();
+        let mut out = vec![];
+        let mut repl = Repl::new(db, &mut out);
+        assert!(!repl.run(input.as_bytes(), false).unwrap());
+        assert!(repl.run(input.as_bytes(), true).is_ok());
+        drop(repl);
+        let expected = format!(
+            "\
+OK
+OK
+OK
+2 rows affected
+ id | name
+----+------
+ 1  | a
+ 2  | NULL
+(2 rows)
+t
+CREATE TABLE t (id INTEGER, name TEXT, PRIMARY KEY (id));
+CREATE INDEX t_name ON t (name);
+CREATE VIEW v AS SELECT name FROM t;
+t: not analyzed
+OK
+t: 2 rows
+ column | nulls | distinct | min | max
+--------+-------+----------+-----+-----
+ id     | 0     | 2        | 1   | 2
+ name   | 1     | 1        | a   | a
+1 row affected
+Error: column not found: nope
+Error: {} stopped at a failure
+",
+            script.display()
+        );
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(expected, &out[..expected.len()]);
+        // a script stops at the first failure, where the shell goes on
+        let rest = &out[expected.len()..];
+        assert!(rest.starts_with(&format!("{0}{0}Error: table already exists: t\n", PROMPT)), "{}", rest);
+        assert!(rest.contains(" n\n---\n 3\n(1 row)\n"), "{}", rest);
+        assert!(rest.contains("Error: unknown command: .frobnicate (see .help)\n"), "{}", rest);
+        assert!(!rest.contains(" 1\n(1 row)"), "{}", rest);
+
+        // what's dumped to a file restores into another database
+        let db = Database::open(dir.path().join("db"), 16).unwrap();
+        let path = dir.path().join("dump.sql");
+        let mut repl = Repl::new(db, vec![]);
+        assert!(repl.run(format!(".dump {}\n", path.display()).as_bytes(), false).unwrap());
+        let mut repl = Repl::new(Database::open(dir.path().join("copy"), 16).unwrap(), vec![]);
+        let input = format!(".restore {}\nSELECT * FROM v;\n.restore\n", path.display());
+        assert!(!repl.run(input.as_bytes(), false).unwrap());
+        let out = String::from_utf8(repl.out).unwrap();
+        assert_eq!(" name\n------\n a\n NULL\n c\n(3 rows)\nError: usage: .restore <file>\n", out);
+    }
+}
@@ -0,0 +1,580 @@
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::buffer::{self, BufferPool, BufferPoolManager};
+use crate::disk::{DiskManager, PageId, PAGE_SIZE};
+use crate::recovery;
+use crate::wal::{self, Checkpoint, LogRecord, Lsn, Record, Wal};
+
+// Physical streaming replication: a replica, set up from a base backup of the primary, connects
+// to it and is sent the log from where it has to go on as the records become durable. It redoes
+// them on its own data file like recovery does, so that its pages are those of the primary as of
+// some LSN, and reports back how far it has got, the log it still needs being kept in a slot.
+//
+// Every page changed before a restart point of the replica has been written back, so it goes on
+// from the latest one when reopened. The pages of logged in-memory tables, which are in no data
+// file, are kept in a file of their own then.
+//
+// The replica keeps a copy of the log it applies, each record at the LSN it has on the primary,
+// from the log of the base backup on. Promoted, e.g. when the primary is gone, the replica is
+// recovered from it as the primary would be after a crash there, and goes on as a database of
+// its own, its log going on from the primary's.
+//
+// The replica keeps track of the transactions of the primary from their records, and serves
+// read-only transactions with snapshots of them. Before vacuum on the primary removes versions,
+// it logs as of which transactions, and replay waits for the readers of the replica which may
+// still see those, for up to the max delay, after which they're canceled.
+//
+// Messages, integers little endian:
+//   to the replica: [lsn: u64][next: u64][len: u32][body], a record and the LSN after it
+//   to the primary: [restart: u64][applied: u64], the first of which asks for the log from restart
+//
+// Neither end blocks, each being run a step at a time, e.g. between statements. A replica may
+// also be given the records otherwise than by a connection to the primary, as the followers of a
+// cluster under consensus are (see `raft`).
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Wal(#[from] wal::Error),
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error("the log before {0} has been removed")]
+    Removed(Lsn),
+    #[error("the other end has disconnected")]
+    Disconnected,
+}
+
+// Named as those of a database, which the replica is once promoted.
+const REPLICA_DATA_FILE: &str = "data";
+const REPLICA_WAL_DIR: &str = "wal";
+// Holds the LSN of the latest restart point and the segment size of the log, then the
+// transactions of the primary as of it as the body of a checkpoint end record.
+const RESTART_FILE: &str = "restart";
+// Holds the pages of logged in-memory tables as of the latest restart point, or later if it was
+// being made, each as [page id: u64][page].
+const MEMORY_FILE: &str = "memory";
+// How long replay waits for readers in its way, unless configured otherwise.
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(30);
+pub(crate) const RECORD_HEADER_SIZE: usize = 20;
+const FEEDBACK_SIZE: usize = 16;
+// Bytes of records waiting to be sent beyond which no more are read from the log.
+const MAX_OUTGOING: usize = 1024 * 1024;
+
+// The primary end, sending the log to a replica.
+pub struct Sender {
+    stream: TcpStream,
+    slot: String,
+    // where the next record sent is read from, once the replica has asked for the log
+    next: Option<Lsn>,
+    applied: Lsn,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+}
+
+impl Sender {
+    // Waits for a replica to connect to `listener`, the log it needs being kept in the slot `slot`.
+    pub fn accept(listener: &TcpListener, slot: &str) -> Result<Self, Error> {
+        let (stream, _) = listener.accept()?;
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self { stream, slot: slot.to_string(), next: None, applied: 0, incoming: vec![], outgoing: vec![] })
+    }
+
+    // LSN before which the replica has applied every record.
+    pub fn applied(&self) -> Lsn {
+        self.applied
+    }
+
+    // Takes in what the replica has reported, then sends it the records made durable since.
+    pub fn step(&mut self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        receive(&mut self.stream, &mut self.incoming)?;
+        let Some(wal) = bufmgr.wal() else {
+            return Err(wal::Error::Config("replication needs a log".to_string()).into());
+        };
+        for feedback in self.incoming.chunks_exact(FEEDBACK_SIZE) {
+            let restart = u64::from_le_bytes(feedback[..8].try_into().unwrap());
+            if self.next.is_none() {
+                if restart < wal.start() {
+                    return Err(Error::Removed(restart));
+                }
+                self.next = Some(restart);
+            }
+            self.applied = u64::from_le_bytes(feedback[8..].try_into().unwrap());
+            wal.set_slot(&self.slot, Some(restart))?;
+        }
+        self.incoming.drain(..self.incoming.len() / FEEDBACK_SIZE * FEEDBACK_SIZE);
+        if let Some(next) = &mut self.next {
+            while self.outgoing.len() < MAX_OUTGOING && *next < wal.flushed() {
+                let Some((record, after)) = wal.read(*next)? else {
+                    break;
+                };
+                encode_record(&record, after, &mut self.outgoing);
+                *next = after;
+            }
+        }
+        send(&mut self.stream, &mut self.outgoing)
+    }
+}
+
+// The replica end, applying the log of the primary.
+pub struct Replica {
+    dir: PathBuf,
+    bufmgr: BufferPoolManager,
+    // the copy of the log, and the begin records of the checkpoint being applied, if any, and of
+    // the latest one complete
+    wal: Wal,
+    checkpoint_begun: Option<Lsn>,
+    checkpoint: Lsn,
+    // None if the records are given by `receive`
+    stream: Option<TcpStream>,
+    // the latest restart point, and the LSN before which every record has been applied
+    restart: Lsn,
+    applied: Lsn,
+    max_delay: Duration,
+    // since when replay has been waiting for readers
+    waiting_since: Option<Instant>,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+}
+
+impl Replica {
+    // Sets up a replica in `dir` out of a base backup of the primary in `backup_dir`.
+    pub fn create(backup_dir: impl AsRef<Path>, dir: impl AsRef<Path>) -> Result<(), Error> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let (checkpoint, segment_size) = recovery::copy_backup(backup_dir.as_ref(), &dir.join(REPLICA_DATA_FILE))?;
+        recovery::copy_backup_log(backup_dir.as_ref(), &dir.join(REPLICA_WAL_DIR), checkpoint)?;
+        // the transactions are those of the checkpoint, which is replayed first
+        let state = Checkpoint { next_txid: 1, transactions: vec![], dirty_pages: vec![], aborted: vec![] };
+        write_restart(dir, checkpoint, segment_size, state)
+    }
+
+    // Opens the replica in `dir` and connects to the primary at `addr`, asking for the log from
+    // the latest restart point.
+    pub fn connect(dir: impl AsRef<Path>, pool_size: usize, addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        let mut replica = Self::open(dir, pool_size)?;
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        replica.stream = Some(stream);
+        replica.report()?;
+        Ok(replica)
+    }
+
+    // Opens the replica in `dir` to be given the records by `receive`, from the latest restart
+    // point on.
+    pub fn open(dir: impl AsRef<Path>, pool_size: usize) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        let bytes = fs::read(dir.join(RESTART_FILE))?;
+        let (header, state) = bytes.split_at_checked(16).ok_or(wal::Error::Malformed(0))?;
+        let restart = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let wal = Wal::open(dir.join(REPLICA_WAL_DIR), u64::from_le_bytes(header[8..].try_into().unwrap()))?;
+        let checkpoint = wal.last_checkpoint()?.unwrap_or(restart);
+        let disk = DiskManager::open(dir.join(REPLICA_DATA_FILE))?;
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(pool_size));
+        bufmgr.set_standby();
+        // none before the first restart point, the checkpoint of the backup logging them all
+        match fs::read(dir.join(MEMORY_FILE)) {
+            Ok(bytes) => {
+                for chunk in bytes.chunks(8 + PAGE_SIZE as usize) {
+                    let page = chunk[8..].try_into().map_err(|_| wal::Error::Malformed(restart))?;
+                    bufmgr.load_memory_page(PageId(u64::from_le_bytes(chunk[..8].try_into().unwrap())), page);
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        bufmgr.replay(&LogRecord::decode(restart, state)?);
+        Ok(Self {
+            dir,
+            bufmgr,
+            wal,
+            checkpoint_begun: None,
+            checkpoint,
+            stream: None,
+            restart,
+            applied: restart,
+            max_delay: DEFAULT_MAX_DELAY,
+            waiting_since: None,
+            incoming: vec![],
+            outgoing: vec![],
+        })
+    }
+
+    // Takes in records as the primary sends them (see `encode_record`), for `step` to apply.
+    // Those before where the replica has got to are skipped.
+    pub fn receive(&mut self, records: &[u8]) {
+        self.incoming.extend_from_slice(records);
+    }
+
+    // LSN before which every record has been applied.
+    pub fn applied(&self) -> Lsn {
+        self.applied
+    }
+
+    pub fn bufmgr(&mut self) -> &mut BufferPoolManager {
+        &mut self.bufmgr
+    }
+
+    // Sets how long replay waits for readers which may see versions it's to remove before
+    // canceling them.
+    pub fn set_max_delay(&mut self, delay: Duration) {
+        self.max_delay = delay;
+    }
+
+    // Applies the records received so far, returning how many there were, short of any which
+    // has to wait for readers.
+    pub fn step(&mut self) -> Result<usize, Error> {
+        if let Some(stream) = &mut self.stream {
+            receive(stream, &mut self.incoming)?;
+        }
+        // replayed outside of whichever reader is running
+        let state = self.bufmgr.switch(Default::default());
+        let result = self.apply();
+        self.bufmgr.switch(state);
+        let applied = result?;
+        if applied > 0 {
+            self.wal.flush_all()?;
+            self.report()?;
+        }
+        Ok(applied)
+    }
+
+    fn apply(&mut self) -> Result<usize, Error> {
+        let (mut pos, mut applied) = (0, 0);
+        while let Some(header) = self.incoming.get(pos..pos + RECORD_HEADER_SIZE) {
+            let lsn = u64::from_le_bytes(header[..8].try_into().unwrap());
+            let next = u64::from_le_bytes(header[8..16].try_into().unwrap());
+            let len = u32::from_le_bytes(header[16..].try_into().unwrap()) as usize;
+            let Some(body) = self.incoming.get(pos + RECORD_HEADER_SIZE..pos + RECORD_HEADER_SIZE + len) else {
+                break;
+            };
+            if lsn < self.applied {
+                pos += RECORD_HEADER_SIZE + len;
+                continue;
+            }
+            let record = LogRecord::decode(lsn, body)?;
+            if let Record::Vacuum { horizon } = record.record {
+                let conflicting = self.bufmgr.conflicting_snapshots(horizon);
+                if !conflicting.is_empty() {
+                    let since = *self.waiting_since.get_or_insert_with(Instant::now);
+                    if since.elapsed() < self.max_delay {
+                        break;
+                    }
+                    self.bufmgr.cancel_snapshots(&conflicting);
+                }
+                self.waiting_since = None;
+            }
+            match record.record {
+                Record::CheckpointBegin => self.checkpoint_begun = Some(lsn),
+                Record::CheckpointEnd(_) => self.checkpoint = self.checkpoint_begun.take().unwrap_or(self.checkpoint),
+                _ => {}
+            }
+            self.wal.copy(&record, next)?;
+            recovery::redo(&mut self.bufmgr, &record)?;
+            self.bufmgr.replay(&record);
+            self.applied = next;
+            pos += RECORD_HEADER_SIZE + len;
+            applied += 1;
+        }
+        self.incoming.drain(..pos);
+        Ok(applied)
+    }
+
+    // Writes back every page, making what's been applied so far the point the replica restarts
+    // from, before which the primary no longer keeps the log for it. The copy of the log is kept
+    // from the latest checkpoint applied, or where a transaction still running began if earlier.
+    pub fn restart_point(&mut self) -> Result<(), Error> {
+        self.bufmgr.flush()?;
+        self.wal.flush_all()?;
+        let mut memory = vec![];
+        for (page_id, page) in self.bufmgr.logged_memory_pages() {
+            memory.extend_from_slice(&page_id.0.to_le_bytes());
+            memory.extend_from_slice(&page[..]);
+        }
+        write_file(&self.dir, MEMORY_FILE, &memory)?;
+        write_restart(&self.dir, self.applied, self.wal.segment_size(), self.bufmgr.standby_state())?;
+        self.restart = self.applied;
+        if self.wal.last_checkpoint()? != Some(self.checkpoint) {
+            self.wal.set_last_checkpoint(self.checkpoint)?;
+        }
+        let needed = self.bufmgr.oldest_lsn().map_or(self.checkpoint, |lsn| lsn.min(self.checkpoint));
+        self.wal.remove_before(needed)?;
+        self.report()
+    }
+
+    // Makes the replica a database of its own, which takes writes, e.g. to stand in for the
+    // primary once it's gone: it's recovered from the copy of the log as the primary would be
+    // after a crash as of what's been applied, the transactions still running being rolled back.
+    // What's been received and not applied is dropped. The directory then holds a database, which
+    // `Database::open` opens given the segment size of the primary's log.
+    pub fn promote(mut self, pool_size: usize) -> Result<BufferPoolManager, Error> {
+        self.wal.flush_all()?;
+        self.bufmgr.flush()?;
+        let Replica { dir, bufmgr, wal, .. } = self;
+        drop(bufmgr);
+        fs::remove_file(dir.join(RESTART_FILE))?;
+        match fs::remove_file(dir.join(MEMORY_FILE)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let disk = DiskManager::open(dir.join(REPLICA_DATA_FILE))?;
+        Ok(BufferPoolManager::with_wal(disk, BufferPool::new(pool_size), wal)?)
+    }
+
+    fn report(&mut self) -> Result<(), Error> {
+        let Some(stream) = &mut self.stream else {
+            return Ok(());
+        };
+        self.outgoing.extend_from_slice(&self.restart.to_le_bytes());
+        self.outgoing.extend_from_slice(&self.applied.to_le_bytes());
+        send(stream, &mut self.outgoing)
+    }
+}
+
+// Appends `record`, before `after`, as it's sent to a replica.
+pub fn encode_record(record: &LogRecord, after: Lsn, out: &mut Vec<u8>) {
+    let body = record.encode();
+    out.extend_from_slice(&record.lsn.to_le_bytes());
+    out.extend_from_slice(&after.to_le_bytes());
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(&body);
+}
+
+fn write_restart(dir: &Path, lsn: Lsn, segment_size: u64, state: Checkpoint) -> Result<(), Error> {
+    let mut bytes = lsn.to_le_bytes().to_vec();
+    bytes.extend_from_slice(&segment_size.to_le_bytes());
+    bytes.extend_from_slice(&LogRecord { lsn, txid: 0, prev_lsn: None, record: Record::CheckpointEnd(state) }.encode());
+    write_file(dir, RESTART_FILE, &bytes)
+}
+
+// Writes the file elsewhere and renames it over the old one, so that a crash leaves either of them.
+pub(crate) fn write_file(dir: &Path, name: &str, bytes: &[u8]) -> Result<(), Error> {
+    let tmp = dir.join(format!("{}.tmp", name));
+    let mut file = File::create(&tmp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(tmp, dir.join(name))?;
+    Ok(())
+}
+
+// Reads whatever has arrived, failing if the other end has gone.
+pub(crate) fn receive(stream: &mut TcpStream, incoming: &mut Vec<u8>) -> Result<(), Error> {
+    let mut buf = [0; 8192];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => return Err(Error::Disconnected),
+            Ok(len) => incoming.extend_from_slice(&buf[..len]),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+// Writes as much of `outgoing` as can be written without waiting, leaving the rest.
+pub(crate) fn send(stream: &mut TcpStream, outgoing: &mut Vec<u8>) -> Result<(), Error> {
+    while !outgoing.is_empty() {
+        match stream.write(outgoing) {
+            Ok(0) => return Err(Error::Disconnected),
+            Ok(len) => {
+                outgoing.drain(..len);
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::{self, BTree, SearchMode};
+    use crate::mvcc::Isolation;
+    use crate::table::{self, Table};
+    use crate::tuple::{Tuple, Value};
+    use crate::wal::Wal;
+    use tempfile::{tempdir, NamedTempFile};
+
+    #[test]
+    fn test() {
+        let (data_file, data_path) = NamedTempFile::new().unwrap().into_parts();
+        drop(data_file);
+        let wal_dir = tempdir().unwrap();
+        let size = 16 * 1024;
+        let open = || {
+            let disk = DiskManager::open(&data_path).unwrap();
+            BufferPoolManager::with_wal(disk, BufferPool::new(8), Wal::open(wal_dir.path(), size).unwrap()).unwrap()
+        };
+        let mut primary = open();
+        let btree = BTree::create(&mut primary).unwrap();
+        let insert = |bufmgr: &mut BufferPoolManager, keys: std::ops::Range<u64>| {
+            for i in keys {
+                btree.insert(bufmgr, &i.to_be_bytes(), &[1; 100]).unwrap();
+            }
+            bufmgr.commit().unwrap();
+        };
+        let keys = |bufmgr: &mut BufferPoolManager| {
+            let mut iter = btree.search(bufmgr, SearchMode::Start).unwrap();
+            let mut keys = vec![];
+            while let Some((key, _)) = iter.next(bufmgr).unwrap() {
+                keys.push(u64::from_be_bytes(key.try_into().unwrap()));
+            }
+            keys
+        };
+        insert(&mut primary, 0..10);
+        // in-memory trees, the pages of logged ones coming from the checkpoint of the backup first
+        let memory = primary.creating_memory_pages(true, BTree::create).unwrap();
+        let unlogged = primary.creating_memory_pages(false, BTree::create).unwrap();
+        memory.insert(&mut primary, b"a", b"1").unwrap();
+        unlogged.insert(&mut primary, b"a", b"1").unwrap();
+        primary.commit().unwrap();
+        let all = |bufmgr: &mut BufferPoolManager, btree: &BTree| -> Result<Vec<Vec<u8>>, btree::Error> {
+            let mut iter = btree.search(bufmgr, SearchMode::Start)?;
+            let mut keys = vec![];
+            while let Some((key, _)) = iter.next(bufmgr)? {
+                keys.push(key);
+            }
+            Ok(keys)
+        };
+        let backup_dir = tempdir().unwrap();
+        recovery::backup(&mut primary, backup_dir.path()).unwrap();
+        insert(&mut primary, 10..20);
+
+        // the replica catches up from the backup, and goes on as the primary commits
+        let replica_dir = tempdir().unwrap();
+        Replica::create(backup_dir.path(), replica_dir.path()).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut replica = Replica::connect(replica_dir.path(), 8, addr).unwrap();
+        let mut sender = Sender::accept(&listener, "replica").unwrap();
+        let catch_up = |primary: &mut BufferPoolManager, sender: &mut Sender, replica: &mut Replica| {
+            let target = primary.wal().unwrap().flushed();
+            while replica.applied() < target || sender.applied() < target {
+                sender.step(primary).unwrap();
+                replica.step().unwrap();
+            }
+        };
+        catch_up(&mut primary, &mut sender, &mut replica);
+        assert_eq!((0..20).collect::<Vec<_>>(), keys(replica.bufmgr()));
+        assert_eq!(vec![b"a".to_vec()], all(replica.bufmgr(), &memory).unwrap());
+        assert!(matches!(all(replica.bufmgr(), &unlogged), Err(btree::Error::Buffer(buffer::Error::Unlogged))));
+        memory.insert(&mut primary, b"b", b"2").unwrap();
+        insert(&mut primary, 20..200);
+        catch_up(&mut primary, &mut sender, &mut replica);
+        assert_eq!((0..200).collect::<Vec<_>>(), keys(replica.bufmgr()));
+
+        // the slot keeps the log the replica needs from being removed by checkpoints
+        let needed = primary.wal().unwrap().slot("replica").unwrap();
+        primary.checkpoint().unwrap();
+        assert!(primary.wal().unwrap().start() <= needed);
+        replica.restart_point().unwrap();
+        let restart = replica.applied();
+        while primary.wal().unwrap().slot("replica") != Some(restart) {
+            sender.step(&mut primary).unwrap();
+        }
+
+        // a reopened replica goes on from its restart point, missing nothing since
+        drop(replica);
+        assert!(sender.step(&mut primary).is_err());
+        insert(&mut primary, 200..300);
+        let mut replica = Replica::connect(replica_dir.path(), 8, addr).unwrap();
+        let mut sender = Sender::accept(&listener, "replica").unwrap();
+        catch_up(&mut primary, &mut sender, &mut replica);
+        assert_eq!((0..300).collect::<Vec<_>>(), keys(replica.bufmgr()));
+        // those of logged in-memory trees kept in a file of their own
+        assert_eq!(vec![b"a".to_vec(), b"b".to_vec()], all(replica.bufmgr(), &memory).unwrap());
+
+        // the replica reads snapshots of the transactions of the primary, and doesn't write
+        let table = Table::create(&mut primary, 1).unwrap();
+        let row = |id: i64| vec![Value::Int(id)];
+        let scan = |bufmgr: &mut BufferPoolManager| -> Result<Vec<Tuple>, table::Error> {
+            let mut iter = table.scan(bufmgr)?;
+            let mut rows = vec![];
+            while let Some(row) = iter.next(bufmgr)? {
+                rows.push(row);
+            }
+            // each a transaction of its own, unless in one begun
+            if bufmgr.isolation().is_none() {
+                bufmgr.commit()?;
+            }
+            Ok(rows)
+        };
+        table.insert(&mut primary, &row(1)).unwrap();
+        primary.commit().unwrap();
+        table.insert(&mut primary, &row(2)).unwrap();
+        primary.wal().unwrap().flush_all().unwrap();
+        catch_up(&mut primary, &mut sender, &mut replica);
+        assert_eq!(vec![row(1)], scan(replica.bufmgr()).unwrap());
+        let result = table.insert(replica.bufmgr(), &row(3));
+        assert!(result.is_err_and(|e| e.to_string() == buffer::Error::ReadOnly.to_string()));
+        replica.bufmgr().abort().unwrap();
+        // which are kept across restart points
+        replica.restart_point().unwrap();
+        let restart = replica.applied();
+        while primary.wal().unwrap().slot("replica") != Some(restart) {
+            sender.step(&mut primary).unwrap();
+        }
+        drop((replica, sender));
+        let mut replica = Replica::connect(replica_dir.path(), 8, addr).unwrap();
+        let mut sender = Sender::accept(&listener, "replica").unwrap();
+        catch_up(&mut primary, &mut sender, &mut replica);
+        assert_eq!(vec![row(1)], scan(replica.bufmgr()).unwrap());
+        primary.commit().unwrap();
+        catch_up(&mut primary, &mut sender, &mut replica);
+        assert_eq!(vec![row(1), row(2)], scan(replica.bufmgr()).unwrap());
+
+        // replay waits for readers which may see versions vacuum removes, then cancels them
+        replica.bufmgr().begin(Isolation::RepeatableRead).unwrap();
+        assert_eq!(vec![row(1), row(2)], scan(replica.bufmgr()).unwrap());
+        assert!(table.delete(&mut primary, &[Value::Int(1)]).unwrap());
+        primary.commit().unwrap();
+        let before = primary.wal().unwrap().end();
+        assert_eq!(1, table.vacuum(&mut primary).unwrap().rows);
+        primary.commit().unwrap();
+        let target = primary.wal().unwrap().flushed();
+        while replica.applied() <= before {
+            sender.step(&mut primary).unwrap();
+            replica.step().unwrap();
+        }
+        for _ in 0..10 {
+            sender.step(&mut primary).unwrap();
+            replica.step().unwrap();
+        }
+        assert!(replica.applied() < target);
+        assert_eq!(vec![row(1), row(2)], scan(replica.bufmgr()).unwrap());
+        replica.set_max_delay(Duration::ZERO);
+        catch_up(&mut primary, &mut sender, &mut replica);
+        let result = scan(replica.bufmgr());
+        assert!(result.is_err_and(|e| e.to_string() == buffer::Error::ReplicationConflict.to_string()));
+        replica.bufmgr().abort().unwrap();
+        assert_eq!(vec![row(2)], scan(replica.bufmgr()).unwrap());
+
+        // once the slot is dropped, the log may be removed before where a replica would go on from
+        primary.retain_log("replica", None).unwrap();
+        insert(&mut primary, 300..600);
+        primary.flush().unwrap();
+        primary.checkpoint().unwrap();
+        assert!(primary.wal().unwrap().start() > restart);
+        drop((replica, sender));
+        let replica = Replica::connect(replica_dir.path(), 8, addr).unwrap();
+        let mut sender = Sender::accept(&listener, "replica").unwrap();
+        let result = loop {
+            match sender.step(&mut primary) {
+                Ok(()) => continue,
+                result => break result,
+            }
+        };
+        assert!(matches!(result, Err(Error::Removed(lsn)) if lsn == restart));
+        drop(replica);
+    }
+}
@@ -0,0 +1,419 @@
+use std::collections::BTreeSet;
+use std::convert::TryInto;
+
+use crate::buffer::{self, Buffer, BufferPoolManager, Page, PAGE_BODY_SIZE};
+use crate::disk::PageId;
+use crate::geometry::{Point, Rect};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error("value is too large")]
+    EntryTooLarge,
+    #[error("malformed r-tree page")]
+    Malformed,
+}
+
+// Limit on an entry's box + value length, well below a quarter of a page as that of a B+tree.
+pub const MAX_ENTRY_SIZE: usize = 1000;
+
+// An R-tree mapping boxes to values, e.g. the bounds of points and boxes to the index keys of
+// the rows they're of. Each branch has the box each child's entries are all in, so a search only
+// goes down the children whose boxes intersect the one searched for. An entry goes into the child
+// whose box it enlarges the least, and a node overflowing its page is split in two, its entries
+// sorted along the axis their centers are the most spread on and cut in half by bytes.
+//
+// Page layouts (all integers little endian), a box being [low x][low y][high x][high y], i64 each:
+//   meta:   [root_page_id: u64], as that of a B+tree
+//   leaf:   [LEAF: u8][num_entries: u16] + ([box][value_len: u16][value])*
+//   branch: [BRANCH: u8][num_children: u16] + ([box][child: u64])*
+// The node types aren't those of B+tree nodes, so that neither is read as the other.
+const LEAF: u8 = 2;
+const BRANCH: u8 = 3;
+const NODE_HEADER_SIZE: usize = 3;
+const BOX_SIZE: usize = 32;
+
+// (box, value)
+pub type Entry = (Rect, Vec<u8>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RTree {
+    pub meta_page_id: PageId,
+}
+
+// A node as read off its page, public for tools reading pages directly.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Node {
+    Leaf(Vec<Entry>),
+    Branch(Vec<(Rect, PageId)>),
+}
+
+impl RTree {
+    pub fn create(bufmgr: &mut BufferPoolManager) -> Result<Self, Error> {
+        let meta_buffer = bufmgr.create_page()?;
+        let root_buffer = bufmgr.create_page()?;
+        Node::Leaf(vec![]).write_to(bufmgr, &root_buffer)?;
+        set_root_page_id(bufmgr, &meta_buffer, root_buffer.page_id)?;
+        Ok(Self { meta_page_id: meta_buffer.page_id })
+    }
+
+    pub fn root_page_id(&self, bufmgr: &mut BufferPoolManager) -> Result<PageId, Error> {
+        let buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        let page = buffer.page.borrow();
+        Ok(PageId(u64::from_le_bytes(page[0..8].try_into().unwrap())))
+    }
+
+    // Inserts the entry unless there's one of the same box and value already.
+    pub fn insert(&self, bufmgr: &mut BufferPoolManager, rect: &Rect, value: &[u8]) -> Result<(), Error> {
+        if BOX_SIZE + value.len() > MAX_ENTRY_SIZE {
+            return Err(Error::EntryTooLarge);
+        }
+        let root_page_id = self.root_page_id(bufmgr)?;
+        if self.find(bufmgr, root_page_id, rect, value)?.is_some() {
+            return Ok(());
+        }
+        let (bounds, split) = insert_into(bufmgr, root_page_id, rect, value)?;
+        if let Some(right) = split {
+            // the root was split; grow the tree by one level
+            let root_buffer = bufmgr.create_page_beside(self.meta_page_id)?;
+            Node::Branch(vec![(bounds, root_page_id), right]).write_to(bufmgr, &root_buffer)?;
+            let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+            set_root_page_id(bufmgr, &meta_buffer, root_buffer.page_id)?;
+        }
+        Ok(())
+    }
+
+    // Removes the entry of the box and value, returning whether there was one. The boxes of the
+    // branches above aren't shrunk nor are nodes merged, as those of a B+tree aren't: they still
+    // have all the entries below them in, only not as tightly.
+    pub fn delete(&self, bufmgr: &mut BufferPoolManager, rect: &Rect, value: &[u8]) -> Result<bool, Error> {
+        let root_page_id = self.root_page_id(bufmgr)?;
+        let Some((page_id, pos)) = self.find(bufmgr, root_page_id, rect, value)? else {
+            return Ok(false);
+        };
+        let Node::Leaf(mut entries) = Node::load(bufmgr, page_id)? else {
+            return Err(Error::Malformed);
+        };
+        entries.remove(pos);
+        Node::Leaf(entries).store(bufmgr, page_id)?;
+        Ok(true)
+    }
+
+    // The leaf and position of the entry of the box and value, looked for in the children whose
+    // boxes have the box in.
+    fn find(&self, bufmgr: &mut BufferPoolManager, root_page_id: PageId, rect: &Rect, value: &[u8]) -> Result<Option<(PageId, usize)>, Error> {
+        let mut pending = vec![root_page_id];
+        while let Some(page_id) = pending.pop() {
+            match Node::load(bufmgr, page_id)? {
+                Node::Leaf(entries) => {
+                    if let Some(pos) = entries.iter().position(|(r, v)| r == rect && v == value) {
+                        return Ok(Some((page_id, pos)));
+                    }
+                }
+                Node::Branch(children) => pending.extend(children.into_iter().filter(|(r, _)| r.contains(rect)).map(|(_, child)| child)),
+            }
+        }
+        Ok(None)
+    }
+
+    // The entries whose boxes intersect `area`, in no particular order.
+    pub fn search(&self, bufmgr: &mut BufferPoolManager, area: &Rect) -> Result<Vec<Entry>, Error> {
+        let mut found = vec![];
+        let mut pending = vec![self.root_page_id(bufmgr)?];
+        while let Some(page_id) = pending.pop() {
+            match Node::load(bufmgr, page_id)? {
+                Node::Leaf(entries) => found.extend(entries.into_iter().filter(|(r, _)| r.intersects(area))),
+                Node::Branch(children) => pending.extend(children.into_iter().filter(|(r, _)| r.intersects(area)).map(|(_, child)| child)),
+            }
+        }
+        Ok(found)
+    }
+
+    // All the entries.
+    pub fn entries(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<Entry>, Error> {
+        let everything = Rect::new(Point { x: i64::MIN, y: i64::MIN }, Point { x: i64::MAX, y: i64::MAX });
+        self.search(bufmgr, &everything)
+    }
+
+    // The pages of the nodes of the tree, the meta page aside. Fails as malformed if a node is
+    // reached twice.
+    pub fn pages(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<PageId>, Error> {
+        let mut pages = BTreeSet::new();
+        let mut pending = vec![self.root_page_id(bufmgr)?];
+        while let Some(page_id) = pending.pop() {
+            if !pages.insert(page_id) {
+                return Err(Error::Malformed);
+            }
+            if let Node::Branch(children) = Node::load(bufmgr, page_id)? {
+                pending.extend(children.into_iter().map(|(_, child)| child));
+            }
+        }
+        Ok(pages.into_iter().collect())
+    }
+
+    // Empties the tree with a new root beside the meta page, whatever it held before.
+    pub fn clear(&self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        let root_buffer = bufmgr.create_page_beside(self.meta_page_id)?;
+        Node::Leaf(vec![]).write_to(bufmgr, &root_buffer)?;
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        set_root_page_id(bufmgr, &meta_buffer, root_buffer.page_id)
+    }
+
+    // Makes the nodes of `other` those of the tree in place of its own, which are left to the
+    // caller to free along with the meta page of `other`.
+    pub fn replace_nodes(&self, bufmgr: &mut BufferPoolManager, other: &RTree) -> Result<(), Error> {
+        let root_page_id = other.root_page_id(bufmgr)?;
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        set_root_page_id(bufmgr, &meta_buffer, root_page_id)
+    }
+}
+
+fn set_root_page_id(bufmgr: &mut BufferPoolManager, meta_buffer: &Buffer, root_page_id: PageId) -> Result<(), Error> {
+    let mut page = *meta_buffer.page.borrow();
+    page[0..8].copy_from_slice(&root_page_id.0.to_le_bytes());
+    bufmgr.update_page(meta_buffer, &page)?;
+    Ok(())
+}
+
+// Inserts the entry into the subtree rooted at `page_id`, returning the box its node's entries
+// are in after, and if the node had to be split, that of the new right sibling and its page id.
+fn insert_into(bufmgr: &mut BufferPoolManager, page_id: PageId, rect: &Rect, value: &[u8]) -> Result<(Rect, Option<(Rect, PageId)>), Error> {
+    let mut node = Node::load(bufmgr, page_id)?;
+    match &mut node {
+        Node::Leaf(entries) => entries.push((*rect, value.to_vec())),
+        Node::Branch(children) => {
+            // the child whose box would grow the least, then the smallest
+            let enlargement = |r: &Rect| (r.union(rect).area() - r.area(), r.area());
+            let i = (0..children.len()).min_by_key(|&i| enlargement(&children[i].0)).ok_or(Error::Malformed)?;
+            let (bounds, split) = insert_into(bufmgr, children[i].1, rect, value)?;
+            children[i].0 = bounds;
+            children.extend(split);
+        }
+    }
+
+    if node.size() <= PAGE_BODY_SIZE {
+        node.store(bufmgr, page_id)?;
+        return Ok((node.bounds().unwrap_or(*rect), None));
+    }
+
+    let right_buffer = bufmgr.create_page_beside(page_id)?;
+    let right_page_id = right_buffer.page_id;
+    drop(right_buffer);
+    let right = node.split();
+    node.store(bufmgr, page_id)?;
+    right.store(bufmgr, right_page_id)?;
+    Ok((node.bounds().unwrap(), Some((right.bounds().unwrap(), right_page_id))))
+}
+
+impl Node {
+    fn load(bufmgr: &mut BufferPoolManager, page_id: PageId) -> Result<Self, Error> {
+        let buffer = bufmgr.fetch_page(page_id)?;
+        let page = buffer.page.borrow();
+        Self::read(&page)
+    }
+
+    fn store(&self, bufmgr: &mut BufferPoolManager, page_id: PageId) -> Result<(), Error> {
+        let buffer = bufmgr.fetch_page(page_id)?;
+        self.write_to(bufmgr, &buffer)
+    }
+
+    // Writes the node to the page in `buffer` through the buffer pool manager, which logs the change.
+    fn write_to(&self, bufmgr: &mut BufferPoolManager, buffer: &Buffer) -> Result<(), Error> {
+        let mut page = *buffer.page.borrow();
+        self.write(&mut page);
+        bufmgr.update_page(buffer, &page)?;
+        Ok(())
+    }
+
+    pub fn read(page: &Page) -> Result<Self, Error> {
+        let body = &page[..PAGE_BODY_SIZE];
+        let mut pos = 0;
+        let mut take = |len: usize| -> Result<&[u8], Error> {
+            let slice = body.get(pos..pos + len).ok_or(Error::Malformed)?;
+            pos += len;
+            Ok(slice)
+        };
+        let node_type = take(1)?[0];
+        let count = u16::from_le_bytes(take(2)?.try_into().unwrap()) as usize;
+        match node_type {
+            LEAF => {
+                let mut entries = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let rect = read_rect(take(BOX_SIZE)?);
+                    let value_len = u16::from_le_bytes(take(2)?.try_into().unwrap()) as usize;
+                    entries.push((rect, take(value_len)?.to_vec()));
+                }
+                Ok(Node::Leaf(entries))
+            }
+            BRANCH => {
+                let mut children = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let rect = read_rect(take(BOX_SIZE)?);
+                    children.push((rect, PageId(u64::from_le_bytes(take(8)?.try_into().unwrap()))));
+                }
+                Ok(Node::Branch(children))
+            }
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    // Writes the node to the body of `page`, which it must fit in, as `read` reads it.
+    pub fn write(&self, page: &mut Page) {
+        let mut buf = Vec::with_capacity(PAGE_BODY_SIZE);
+        match self {
+            Node::Leaf(entries) => {
+                buf.push(LEAF);
+                buf.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+                for (rect, value) in entries {
+                    write_rect(rect, &mut buf);
+                    buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+                    buf.extend_from_slice(value);
+                }
+            }
+            Node::Branch(children) => {
+                buf.push(BRANCH);
+                buf.extend_from_slice(&(children.len() as u16).to_le_bytes());
+                for (rect, child) in children {
+                    write_rect(rect, &mut buf);
+                    buf.extend_from_slice(&child.0.to_le_bytes());
+                }
+            }
+        }
+        page[..buf.len()].copy_from_slice(&buf);
+    }
+
+    // Bytes of its page's body the node takes.
+    pub fn size(&self) -> usize {
+        NODE_HEADER_SIZE
+            + match self {
+                Node::Leaf(entries) => entries.iter().map(|(_, value)| BOX_SIZE + 2 + value.len()).sum::<usize>(),
+                Node::Branch(children) => children.len() * (BOX_SIZE + 8),
+            }
+    }
+
+    // The box all the node's entries are in, None if it has none.
+    pub fn bounds(&self) -> Option<Rect> {
+        let rects: Vec<Rect> = match self {
+            Node::Leaf(entries) => entries.iter().map(|(rect, _)| *rect).collect(),
+            Node::Branch(children) => children.iter().map(|(rect, _)| *rect).collect(),
+        };
+        rects.into_iter().reduce(|a, b| a.union(&b))
+    }
+
+    // Moves the upper half (by bytes) of the node's entries, sorted along the axis on which their
+    // centers are the most spread, into a new node, which is returned.
+    fn split(&mut self) -> Node {
+        let half = self.size() / 2;
+        match self {
+            Node::Leaf(entries) => {
+                sort_along_spread(entries, |(rect, _)| *rect);
+                let mut size = NODE_HEADER_SIZE;
+                let mut mid = 0;
+                while size < half && mid < entries.len() - 1 {
+                    size += BOX_SIZE + 2 + entries[mid].1.len();
+                    mid += 1;
+                }
+                Node::Leaf(entries.split_off(mid.max(1)))
+            }
+            Node::Branch(children) => {
+                sort_along_spread(children, |(rect, _)| *rect);
+                let mid = (children.len() / 2).max(1);
+                Node::Branch(children.split_off(mid))
+            }
+        }
+    }
+}
+
+fn read_rect(bytes: &[u8]) -> Rect {
+    let int = |i: usize| i64::from_le_bytes(bytes[i * 8..i * 8 + 8].try_into().unwrap());
+    Rect { low: Point { x: int(0), y: int(1) }, high: Point { x: int(2), y: int(3) } }
+}
+
+fn write_rect(rect: &Rect, buf: &mut Vec<u8>) {
+    for int in [rect.low.x, rect.low.y, rect.high.x, rect.high.y] {
+        buf.extend_from_slice(&int.to_le_bytes());
+    }
+}
+
+// Sorts `items` by the centers of their boxes along x or y, whichever they're the most spread on.
+// Centers are compared doubled, which can't overflow an i128.
+fn sort_along_spread<T>(items: &mut [T], rect: impl Fn(&T) -> Rect) {
+    let center_x = |item: &T| rect(item).low.x as i128 + rect(item).high.x as i128;
+    let center_y = |item: &T| rect(item).low.y as i128 + rect(item).high.y as i128;
+    let spread = |center: &dyn Fn(&T) -> i128| {
+        let centers = items.iter().map(center);
+        centers.clone().max().unwrap_or(0) - centers.min().unwrap_or(0)
+    };
+    if spread(&center_x) >= spread(&center_y) {
+        items.sort_by_key(center_x);
+    } else {
+        items.sort_by_key(center_y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(8));
+        let rtree = RTree::create(&mut bufmgr).unwrap();
+        let point = |x, y| Point { x, y };
+
+        // a grid of 50x50 unit boxes, inserted in a scrambled order, each with its number
+        let cell = |n: i64| Rect::new(point(n % 50 * 10, n / 50 * 10), point(n % 50 * 10 + 1, n / 50 * 10 + 1));
+        for i in 0..2500 {
+            let n = i * 7919 % 2500;
+            rtree.insert(&mut bufmgr, &cell(n), n.to_string().as_bytes()).unwrap();
+        }
+        // inserting it again changes nothing
+        rtree.insert(&mut bufmgr, &cell(42), b"42").unwrap();
+        assert!(matches!(rtree.insert(&mut bufmgr, &cell(0), &[0; MAX_ENTRY_SIZE]), Err(Error::EntryTooLarge)));
+        assert!(rtree.pages(&mut bufmgr).unwrap().len() > 20);
+        assert_eq!(2500, rtree.entries(&mut bufmgr).unwrap().len());
+
+        let found = |bufmgr: &mut BufferPoolManager, area: Rect| {
+            let mut found: Vec<i64> = rtree.search(bufmgr, &area).unwrap().into_iter().map(|(_, value)| String::from_utf8(value).unwrap().parse().unwrap()).collect();
+            found.sort();
+            found
+        };
+        assert_eq!(vec![51, 52, 101, 102], found(&mut bufmgr, Rect::new(point(11, 11), point(21, 20))));
+        assert_eq!(vec![2499], found(&mut bufmgr, Rect::of_point(point(491, 491))));
+        assert!(found(&mut bufmgr, Rect::of_point(point(5, 5))).is_empty());
+        assert_eq!(50, found(&mut bufmgr, Rect::new(point(0, 0), point(0, 1000))).len());
+
+        // the boxes of branches have all below them in, however high they were inserted
+        let mut pending = vec![(rtree.root_page_id(&mut bufmgr).unwrap(), None::<Rect>)];
+        while let Some((page_id, bounds)) = pending.pop() {
+            let node = Node::load(&mut bufmgr, page_id).unwrap();
+            assert!(node.size() <= PAGE_BODY_SIZE);
+            if let Some(bounds) = bounds {
+                assert!(bounds.contains(&node.bounds().unwrap()));
+            }
+            if let Node::Branch(children) = node {
+                pending.extend(children.into_iter().map(|(rect, child)| (child, Some(rect))));
+            }
+        }
+
+        for n in (0..2500).filter(|n| n % 100 != 0) {
+            assert!(rtree.delete(&mut bufmgr, &cell(n), n.to_string().as_bytes()).unwrap());
+        }
+        assert!(!rtree.delete(&mut bufmgr, &cell(1), b"1").unwrap());
+        assert!(!rtree.delete(&mut bufmgr, &cell(100), b"101").unwrap());
+        assert_eq!(vec![0, 100, 200], found(&mut bufmgr, Rect::new(point(0, 0), point(5, 45))));
+
+        // rebuilt elsewhere, the nodes taking the place of the old
+        let other = RTree::create(&mut bufmgr).unwrap();
+        other.insert(&mut bufmgr, &Rect::of_point(point(-5, -5)), b"x").unwrap();
+        rtree.replace_nodes(&mut bufmgr, &other).unwrap();
+        assert_eq!(vec![(Rect::of_point(point(-5, -5)), b"x".to_vec())], rtree.entries(&mut bufmgr).unwrap());
+        rtree.clear(&mut bufmgr).unwrap();
+        assert!(rtree.entries(&mut bufmgr).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,347 @@
+use std::fmt;
+use std::hint::black_box;
+use std::num::NonZeroU32;
+use std::str::FromStr;
+
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{digest, hmac, pbkdf2};
+
+// Passwords as SCRAM-SHA-256 (RFC 5802, RFC 7677) keeps them: a salted, iterated hash which a
+// client proves it knows the password of without sending it, and by which the server proves it
+// knew the hash too. Stored as PostgreSQL does, as
+//   SCRAM-SHA-256$<iterations>:<salt>$<StoredKey>:<ServerKey>
+// in base64. Channel binding isn't supported, so clients must send the gs2 header "n,," or "y,,".
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum Error {
+    #[error("malformed SCRAM message")]
+    Malformed,
+    #[error("channel binding is not supported")]
+    ChannelBinding,
+    #[error("password authentication failed")]
+    Failed,
+}
+
+pub const MECHANISM: &str = "SCRAM-SHA-256";
+// As PostgreSQL hashes them by default.
+pub const DEFAULT_ITERATIONS: u32 = 4096;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 18;
+
+// What is kept of a password.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub iterations: u32,
+    pub salt: Vec<u8>,
+    pub stored_key: [u8; 32],
+    pub server_key: [u8; 32],
+}
+
+impl Credentials {
+    // Hashes `password` with a salt of its own.
+    pub fn new(password: &str) -> Self {
+        Self::with_salt(password, &random_bytes(SALT_LEN), DEFAULT_ITERATIONS)
+    }
+
+    pub fn with_salt(password: &str, salt: &[u8], iterations: u32) -> Self {
+        let salted = salted_password(password, salt, iterations);
+        Self {
+            iterations,
+            salt: salt.to_vec(),
+            stored_key: sha256(&hmac(&salted, b"Client Key")),
+            server_key: hmac(&salted, b"Server Key"),
+        }
+    }
+}
+
+impl fmt::Display for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}${}:{}${}:{}",
+            MECHANISM,
+            self.iterations,
+            base64_encode(&self.salt),
+            base64_encode(&self.stored_key),
+            base64_encode(&self.server_key)
+        )
+    }
+}
+
+impl FromStr for Credentials {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let parse = || -> Option<Self> {
+            let rest = s.strip_prefix(MECHANISM)?.strip_prefix('$')?;
+            let (iterations, rest) = rest.split_once(':')?;
+            let (salt, keys) = rest.split_once('$')?;
+            let (stored_key, server_key) = keys.split_once(':')?;
+            Some(Self {
+                iterations: iterations.parse().ok()?,
+                salt: base64_decode(salt)?,
+                stored_key: base64_decode(stored_key)?.try_into().ok()?,
+                server_key: base64_decode(server_key)?.try_into().ok()?,
+            })
+        };
+        parse().ok_or(Error::Malformed)
+    }
+}
+
+// The server's side of an exchange, after it's answered the client-first message.
+pub struct ServerFirst {
+    // None if there's no such user, for whom the exchange goes on as if there were, to fail in
+    // the end without telling which users there are
+    credentials: Option<Credentials>,
+    client_first_bare: String,
+    server_first: String,
+    nonce: String,
+}
+
+impl ServerFirst {
+    // Answers the client-first message, e.g. "n,,n=,r=<client nonce>", with the server-first one.
+    // For `user` if there's no such user, with the credentials None, the salt and iteration count
+    // are derived from `secret`, the server's own, to be the same each time as a user's would be.
+    pub fn start(credentials: Option<Credentials>, user: &str, secret: &[u8], client_first: &str) -> Result<(Self, String), Error> {
+        let bare = match client_first.split_once(",,") {
+            Some(("n" | "y", bare)) => bare,
+            Some((header, _)) if header.starts_with("p=") => return Err(Error::ChannelBinding),
+            _ => return Err(Error::Malformed),
+        };
+        // the user is the one the connection started up with, whatever n= says
+        let client_nonce = attributes(bare).find_map(|(name, value)| (name == 'r').then_some(value)).ok_or(Error::Malformed)?;
+        let nonce = format!("{}{}", client_nonce, base64_encode(&random_bytes(NONCE_LEN)));
+        let (salt, iterations) = match &credentials {
+            Some(credentials) => (credentials.salt.clone(), credentials.iterations),
+            None => mock_parameters(secret, user),
+        };
+        let server_first = format!("r={},s={},i={}", nonce, base64_encode(&salt), iterations);
+        let exchange = Self {
+            credentials,
+            client_first_bare: bare.to_string(),
+            server_first: server_first.clone(),
+            nonce,
+        };
+        Ok((exchange, server_first))
+    }
+
+    // Checks the proof of the client-final message, "c=biws,r=<nonce>,p=<proof>", answering with
+    // the server-final one.
+    pub fn finish(self, client_final: &str) -> Result<String, Error> {
+        let (without_proof, proof) = client_final.rsplit_once(",p=").ok_or(Error::Malformed)?;
+        let mut channel_binding = None;
+        let mut nonce = None;
+        for (name, value) in attributes(without_proof) {
+            match name {
+                'c' => channel_binding = Some(value),
+                'r' => nonce = Some(value),
+                _ => {}
+            }
+        }
+        if !matches!(channel_binding, Some("biws" | "eSws")) {
+            return Err(Error::ChannelBinding);
+        }
+        if nonce != Some(self.nonce.as_str()) {
+            return Err(Error::Malformed);
+        }
+        let proof = base64_decode(proof).ok_or(Error::Malformed)?;
+        let Some(credentials) = &self.credentials else {
+            return Err(Error::Failed);
+        };
+        let auth_message = format!("{},{},{}", self.client_first_bare, self.server_first, without_proof);
+        let signature = hmac(&credentials.stored_key, auth_message.as_bytes());
+        if proof.len() != signature.len() {
+            return Err(Error::Failed);
+        }
+        let client_key: Vec<u8> = proof.iter().zip(signature).map(|(a, b)| a ^ b).collect();
+        if !constant_time_eq(&sha256(&client_key), &credentials.stored_key) {
+            return Err(Error::Failed);
+        }
+        Ok(format!("v={}", base64_encode(&hmac(&credentials.server_key, auth_message.as_bytes()))))
+    }
+}
+
+// The client's side of an exchange, after it's sent the client-first message.
+pub struct ClientFirst {
+    password: String,
+    client_first_bare: String,
+    nonce: String,
+}
+
+impl ClientFirst {
+    // Begins an exchange, returning the client-first message.
+    pub fn start(password: &str) -> (Self, String) {
+        let nonce = base64_encode(&random_bytes(NONCE_LEN));
+        let client_first_bare = format!("n=,r={}", nonce);
+        let message = format!("n,,{}", client_first_bare);
+        (Self { password: password.to_string(), client_first_bare, nonce }, message)
+    }
+
+    // Answers the server-first message with the client-final one, returning the server-final
+    // message to expect too.
+    pub fn finish(self, server_first: &str) -> Result<(String, String), Error> {
+        let (mut nonce, mut salt, mut iterations) = (None, None, None);
+        for (name, value) in attributes(server_first) {
+            match name {
+                'r' => nonce = Some(value),
+                's' => salt = base64_decode(value),
+                'i' => iterations = value.parse().ok(),
+                _ => {}
+            }
+        }
+        let (Some(nonce), Some(salt), Some(iterations)) = (nonce, salt, iterations) else {
+            return Err(Error::Malformed);
+        };
+        if !nonce.starts_with(&self.nonce) {
+            return Err(Error::Malformed);
+        }
+        let salted = salted_password(&self.password, &salt, iterations);
+        let client_key = hmac(&salted, b"Client Key");
+        let without_proof = format!("c=biws,r={}", nonce);
+        let auth_message = format!("{},{},{}", self.client_first_bare, server_first, without_proof);
+        let signature = hmac(&sha256(&client_key), auth_message.as_bytes());
+        let proof: Vec<u8> = client_key.iter().zip(signature).map(|(a, b)| a ^ b).collect();
+        let server_signature = hmac(&hmac(&salted, b"Server Key"), auth_message.as_bytes());
+        Ok((format!("{},p={}", without_proof, base64_encode(&proof)), format!("v={}", base64_encode(&server_signature))))
+    }
+}
+
+// The name=value attributes of a message, separated by commas.
+fn attributes(message: &str) -> impl Iterator<Item = (char, &str)> {
+    message.split(',').filter_map(|attribute| {
+        let mut chars = attribute.chars();
+        let name = chars.next()?;
+        Some((name, attribute.get(1..)?.strip_prefix('=')?))
+    })
+}
+
+// The salt and iteration count answered for `user` if there's no such user, the salt being the
+// HMAC of the name keyed by `secret` and the count the one passwords are hashed with here.
+fn mock_parameters(secret: &[u8], user: &str) -> (Vec<u8>, u32) {
+    (hmac(secret, user.as_bytes())[..SALT_LEN].to_vec(), DEFAULT_ITERATIONS)
+}
+
+// Hi() of the RFC, i.e. PBKDF2 with HMAC-SHA-256, of one block.
+fn salted_password(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut salted = [0; 32];
+    // of none, as of one
+    let iterations = NonZeroU32::new(iterations).unwrap_or(NonZeroU32::MIN);
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, password.as_bytes(), &mut salted);
+    salted
+}
+
+// Bytes nobody can guess, from the operating system's CSPRNG by way of ring.
+pub(crate) fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    SystemRandom::new().fill(&mut bytes).expect("the system's random number generator failed");
+    bytes
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> [u8; 32] {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), message).as_ref().try_into().unwrap()
+}
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    digest::digest(&digest::SHA256, data).as_ref().try_into().unwrap()
+}
+
+// Whether `a` and `b` are equal, taking as long whichever bytes differ, so that how long a wrong
+// proof takes to refuse tells nothing of the key.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && black_box(a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y))) == 0
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut s = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                s.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+// None if `s` isn't base64 with padding.
+pub fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut bytes = vec![];
+    for chunk in s.as_bytes().chunks(4) {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 {
+            return None;
+        }
+        let mut n = 0u32;
+        for (i, &c) in chunk[..4 - padding].iter().enumerate() {
+            let digit = BASE64.iter().position(|&d| d == c)? as u32;
+            n |= digit << (18 - 6 * i);
+        }
+        bytes.extend(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855", hex(&sha256(b"")));
+        assert_eq!("248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1", hex(&sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")));
+        assert_eq!("f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8", hex(&hmac(b"key", b"The quick brown fox jumps over the lazy dog")));
+        for s in ["", "f", "fo", "foo", "foob", "fooba", "foobar"] {
+            assert_eq!(Some(s.as_bytes().to_vec()), base64_decode(&base64_encode(s.as_bytes())));
+        }
+        assert_eq!("Zm9vYg==", base64_encode(b"foob"));
+        assert_eq!(None, base64_decode("Zm9v="));
+
+        // the example exchange of RFC 7677
+        let credentials = Credentials::with_salt("pencil", &base64_decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap(), 4096);
+        let client_first = "n,,n=user,r=rOprNGfwEbeRWgbNEkqO";
+        let mut exchange = ServerFirst::start(Some(credentials.clone()), "user", b"secret", client_first).unwrap().0;
+        exchange.nonce = "rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0".to_string();
+        exchange.server_first = format!("r={},s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096", exchange.nonce);
+        let client_final = "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=";
+        assert_eq!(Ok("v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=".to_string()), exchange.finish(client_final));
+
+        // stored as text and read back
+        assert_eq!(Ok(credentials.clone()), credentials.to_string().parse());
+        assert!(credentials.to_string().starts_with("SCRAM-SHA-256$4096:W22ZaJ0SNY7soEsUEjb6gQ==$"));
+        assert_eq!(Err(Error::Malformed), "md5abc".parse::<Credentials>());
+
+        // an exchange between the two sides, with the right password and a wrong one
+        let credentials = Credentials::new("secret");
+        for (password, ok) in [("secret", true), ("guess", false)] {
+            let (client, client_first) = ClientFirst::start(password);
+            let (server, server_first) = ServerFirst::start(Some(credentials.clone()), "user", b"secret", &client_first).unwrap();
+            let (client_final, expected) = client.finish(&server_first).unwrap();
+            assert_eq!(if ok { Ok(expected) } else { Err(Error::Failed) }, server.finish(&client_final));
+        }
+        // no such user, answered the same salt and iteration count each time, which differ from
+        // one user to another and from one server to another
+        let (client, client_first) = ClientFirst::start("secret");
+        let (server, server_first) = ServerFirst::start(None, "nobody", b"secret", &client_first).unwrap();
+        assert_eq!(Err(Error::Failed), server.finish(&client.finish(&server_first).unwrap().0));
+        let parameters = |user: &str, secret: &[u8]| {
+            let server_first = ServerFirst::start(None, user, secret, &ClientFirst::start("secret").1).unwrap().1;
+            server_first.split_once(",s=").unwrap().1.to_string()
+        };
+        assert_eq!(parameters("nobody", b"secret"), parameters("nobody", b"secret"));
+        assert!(parameters("nobody", b"secret").ends_with(",i=4096"));
+        assert_ne!(parameters("nobody", b"secret"), parameters("noone", b"secret"));
+        assert_ne!(parameters("nobody", b"secret"), parameters("nobody", b"other"));
+        assert_eq!(Err(Error::ChannelBinding), ServerFirst::start(None, "", b"", "p=tls-server-end-point,,n=,r=abc").map(|_| ()));
+        assert_eq!(Err(Error::Malformed), ServerFirst::start(None, "", b"", "n,,n=").map(|_| ()));
+        assert!(constant_time_eq(b"abc", b"abc") && !constant_time_eq(b"abc", b"abd") && !constant_time_eq(b"abc", b"ab"));
+    }
+}
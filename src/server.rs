@@ -0,0 +1,1824 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::ops::DerefMut;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rustls::{ConnectionCommon, ServerConnection, SideData, StreamOwned};
+
+use crate::buffer::{self, CancelToken};
+use crate::array;
+use crate::database::{Database, Session};
+use crate::datetime::{self, MICROS_PER_DAY};
+use crate::geometry::{Point, Rect};
+use crate::json;
+use crate::lock;
+use crate::numeric::{Float, Numeric};
+use crate::query;
+use crate::raft::Consensus;
+use crate::scram::{self, Credentials, ServerFirst};
+use crate::sql::{self, PreparedStatement, QueryResult};
+use crate::table;
+use crate::tls::{self, ServerConfig};
+use crate::tuple::{DataType, ElementType, Tuple, Value};
+use crate::wal::Lsn;
+
+// A server speaking the frontend/backend protocol of PostgreSQL (3.0), so that its drivers can
+// connect, each connection being a session of the database. The database is of one thread, so
+// the connections are served in turns, a message at a time, by the thread running `serve`, while
+// another accepts them, handles their startup and the requests to cancel what one is running. A
+// statement holds up every other connection while it runs, for as long as the statement timeout
+// of the server lets it at most (see `Limits`), if it has one. Messages are of a bounded length,
+// much shorter before the client is logged in, a client sending a longer one being disconnected.
+//
+// Once the database has a superuser with a password (see CREATE USER), who can log in to
+// administer the others, a client logs in as the user it starts up with, proving its password by
+// SCRAM-SHA-256, while until then anyone is let in, as any user. With TLS
+// configured (see `tls`), clients asking for SSL have their connections encrypted, and those
+// presenting a certificate of the user they start up as, issued by a CA the server trusts, are
+// logged in without a password; otherwise SSL is declined. GSSAPI encryption is always declined.
+// Connections over the limits of the server are refused, or kept waiting for others to close
+// before they're logged in (see `Limits`), and those of sessions terminated closed (see
+// `activity`). Answers are written as the client takes them, never waiting on it, and a client
+// leaving more than MAX_OUTPUT of them unread isn't read from until it takes them, and is
+// disconnected if it doesn't for a while (see `Limits`).
+//
+// Queries are simple or extended (Parse, Bind, Describe, Execute, Close, Sync and Flush), with
+// values of types int8, text, bool and json in text or binary format. Execute returns as many
+// rows as asked for, a SELECT with more left suspending its portal for the next Execute to go
+// on with, in a transaction of its own until Sync if none is running.
+//
+// Under consensus (see `raft`), what a connection is answered is held back until the log made
+// durable by then is committed, for the client not to hear of a commit a majority of the cluster
+// might not have. Once another node has been made the primary, clients are turned away with its
+// address, the connections being closed and what was held back dropped.
+
+pub(crate) const PROTOCOL_VERSION: i32 = 3 << 16;
+const SSL_REQUEST: i32 = 80877103;
+const GSSENC_REQUEST: i32 = 80877104;
+const CANCEL_REQUEST: i32 = 80877102;
+// Largest startup packet accepted, as in PostgreSQL, and message of the SCRAM exchange.
+const MAX_STARTUP_LEN: usize = 10000;
+// Largest message accepted once logged in, as in PostgreSQL.
+const MAX_MESSAGE_LEN: usize = (1 << 30) - 1;
+// How much input is read ahead of the messages handled, but for the whole of a longer one.
+const READ_AHEAD: usize = 1 << 16;
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+// Most connections started up at once, each in a thread of its own, those over it being closed
+// as they're accepted.
+const MAX_STARTUPS: usize = 64;
+// Bytes of answers left unread by a client beyond which its messages aren't read.
+const MAX_OUTPUT: usize = 1 << 20;
+// How long a client may leave more than MAX_OUTPUT unread, if the limits don't say.
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+// How long the threads sleep when there's nothing to do.
+const IDLE: Duration = Duration::from_millis(1);
+
+const SERVER_VERSION: &str = "16.0";
+
+// Authentication requests.
+const AUTH_OK: i32 = 0;
+const AUTH_SASL: i32 = 10;
+const AUTH_SASL_CONTINUE: i32 = 11;
+const AUTH_SASL_FINAL: i32 = 12;
+
+// Type OIDs.
+const BOOL_OID: u32 = 16;
+const INT8_OID: u32 = 20;
+const INT2_OID: u32 = 21;
+const INT4_OID: u32 = 23;
+const TEXT_OID: u32 = 25;
+const VARCHAR_OID: u32 = 1043;
+const JSON_OID: u32 = 114;
+const JSONB_OID: u32 = 3802;
+const BOOL_ARRAY_OID: u32 = 1000;
+const INT2_ARRAY_OID: u32 = 1005;
+const INT4_ARRAY_OID: u32 = 1007;
+const TEXT_ARRAY_OID: u32 = 1009;
+const VARCHAR_ARRAY_OID: u32 = 1015;
+const INT8_ARRAY_OID: u32 = 1016;
+const POINT_OID: u32 = 600;
+const BOX_OID: u32 = 603;
+const FLOAT4_OID: u32 = 700;
+const FLOAT8_OID: u32 = 701;
+const NUMERIC_OID: u32 = 1700;
+const DATE_OID: u32 = 1082;
+const TIMESTAMP_OID: u32 = 1114;
+
+// Days from 1970-01-01 to 2000-01-01, from which dates and timestamps are counted in binary.
+const POSTGRES_EPOCH_DAYS: i32 = 10957;
+
+// Sessions by the process ID and secret key the clients cancel them with.
+type Keys = Arc<Mutex<HashMap<(i32, i32), CancelToken>>>;
+
+// How many connections the server serves. Those over either limit are refused, or kept waiting
+// for a connection to close for up to `queue_timeout` if given, in the order they came. The
+// statements of the database time out after `statement_timeout` at the latest while it's served,
+// whatever their sessions set, as each holds up the other connections while it runs. A client
+// leaving more than MAX_OUTPUT of its answers unread for `send_timeout`, SEND_TIMEOUT if not
+// given, is disconnected.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub max_connections: Option<usize>,
+    pub max_connections_per_user: Option<usize>,
+    pub queue_timeout: Option<Duration>,
+    pub statement_timeout: Option<Duration>,
+    pub send_timeout: Option<Duration>,
+}
+
+impl Limits {
+    // Why a connection of `user` can't be served alongside `connections`, if it can't.
+    fn exceeded(&self, connections: &[Connection], user: &str) -> Option<String> {
+        if self.max_connections.is_some_and(|max| connections.len() >= max) {
+            return Some("sorry, too many clients already".to_string());
+        }
+        let of_user = connections.iter().filter(|connection| connection.user == user).count();
+        if self.max_connections_per_user.is_some_and(|max| of_user >= max) {
+            return Some(format!("too many connections for user {:?}", user));
+        }
+        None
+    }
+}
+
+pub struct Server {
+    db: Database,
+    limits: Limits,
+    local_addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    keys: Keys,
+    // what the SCRAM exchanges of users there aren't are answered with is derived from (see
+    // `ServerFirst::start`)
+    scram_secret: Rc<[u8]>,
+    // connections started up by the acceptor, with the users they're of
+    incoming: Receiver<(Stream, String)>,
+    acceptor: Option<JoinHandle<()>>,
+    consensus: Option<Consensus>,
+}
+
+impl Server {
+    // Listens on `addr` for connections to `db`.
+    pub fn bind(db: Database, addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Self::listen(db, addr, None)
+    }
+
+    // Listens on `addr` for connections to `db`, encrypting those of the clients asking for SSL.
+    pub fn bind_tls(db: Database, addr: impl ToSocketAddrs, tls: ServerConfig) -> io::Result<Self> {
+        Self::listen(db, addr, Some(Arc::new(tls)))
+    }
+
+    fn listen(db: Database, addr: impl ToSocketAddrs, tls: Option<Arc<ServerConfig>>) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let keys = Keys::default();
+        let (sender, incoming) = mpsc::channel();
+        let acceptor = {
+            let (stopped, keys) = (stopped.clone(), keys.clone());
+            thread::spawn(move || accept(listener, &stopped, &keys, tls, sender))
+        };
+        let scram_secret = scram::random_bytes(32).into();
+        Ok(Self { db, limits: Limits::default(), local_addr, stopped, keys, scram_secret, incoming, acceptor: Some(acceptor), consensus: None })
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    // Runs `consensus`, the node of the database in its cluster, along with the connections.
+    pub fn with_consensus(mut self, consensus: Consensus) -> Self {
+        self.consensus = Some(consensus);
+        self
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    // Set to stop `serve`, from any thread.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stopped.clone()
+    }
+
+    // Serves the connections until stopped, running the maintenance queued by the workers of the
+    // database between messages. Connections failing are closed, leaving the others be.
+    pub fn serve(&mut self) -> Result<(), crate::worker::Error> {
+        self.db.with_engine(|bufmgr, _| bufmgr.set_max_statement_timeout(self.limits.statement_timeout));
+        let mut connections: Vec<Connection> = vec![];
+        // started up, and waiting to be let in, since when
+        let mut queued: VecDeque<(Stream, String, Instant)> = VecDeque::new();
+        while !self.stopped.load(Ordering::Relaxed) {
+            let mut busy = false;
+            while let Ok((stream, user)) = self.incoming.try_recv() {
+                busy = true;
+                queued.push_back((stream, user, Instant::now()));
+            }
+            let redirect = self.consensus.as_ref().filter(|consensus| consensus.deposed()).map(|consensus| match consensus.primary_addr() {
+                Some(addr) => format!("the database is no longer the primary, which is at {}", addr),
+                None => "the database is no longer the primary".to_string(),
+            });
+            for (mut stream, user, since) in std::mem::take(&mut queued) {
+                if let Some(text) = &redirect {
+                    let mut out = vec![];
+                    error_response(&mut out, "FATAL", "25006", text);
+                    let _ = stream.write_all(&out);
+                    continue;
+                }
+                match self.limits.exceeded(&connections, &user) {
+                    None => {
+                        let send_timeout = self.limits.send_timeout.unwrap_or(SEND_TIMEOUT);
+                        if let Ok(connection) = Connection::start(stream, &user, &self.db, &self.keys, &self.scram_secret, send_timeout) {
+                            connections.push(connection);
+                        }
+                    }
+                    Some(_) if self.limits.queue_timeout.is_some_and(|timeout| since.elapsed() < timeout) => queued.push_back((stream, user, since)),
+                    Some(message) => {
+                        let mut out = vec![];
+                        error_response(&mut out, "FATAL", "53300", &message);
+                        let _ = stream.write_all(&out);
+                    }
+                }
+            }
+            let replicated = match &mut self.consensus {
+                Some(consensus) => Some(self.db.with_engine(|bufmgr, _| consensus.step(bufmgr))?),
+                None => None,
+            };
+            for connection in &mut connections {
+                if let Some(text) = &redirect {
+                    connection.redirect(text);
+                    let _ = connection.flush();
+                    continue;
+                }
+                // until the client takes what it's been answered
+                let polled = if connection.backed_up.is_some() { Ok(false) } else { connection.poll() };
+                if let Some(replicated) = replicated {
+                    connection.hold(self.db.with_engine(|bufmgr, _| bufmgr.wal().map_or(0, |wal| wal.flushed())));
+                    connection.release(replicated);
+                }
+                match polled.and_then(|read| connection.flush().map(|_| read)) {
+                    Ok(read) => busy |= read,
+                    Err(_) => connection.closed = true,
+                }
+            }
+            connections.retain(|connection| {
+                if connection.closed {
+                    self.keys.lock().unwrap().remove(&connection.key);
+                }
+                !connection.closed
+            });
+            self.db.maintain()?;
+            if !busy {
+                thread::sleep(IDLE);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+    }
+}
+
+// Accepts connections until `stopped`, starting each up in a thread of its own.
+fn accept(listener: TcpListener, stopped: &AtomicBool, keys: &Keys, tls: Option<Arc<ServerConfig>>, sender: Sender<(Stream, String)>) {
+    let starting = Arc::new(AtomicUsize::new(0));
+    while !stopped.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok(_) if starting.load(Ordering::Relaxed) >= MAX_STARTUPS => {}
+            Ok((stream, _)) => {
+                let (keys, tls, sender, starting) = (keys.clone(), tls.clone(), sender.clone(), starting.clone());
+                starting.fetch_add(1, Ordering::Relaxed);
+                thread::spawn(move || {
+                    if let Ok(Some(started)) = startup(stream, &keys, tls.as_deref()) {
+                        let _ = sender.send(started);
+                    }
+                    starting.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+            // none waiting, or one gone before it was accepted
+            Err(_) => thread::sleep(IDLE),
+        }
+    }
+}
+
+// Reads the startup packets of a connection, completing the TLS handshake if it asks for SSL
+// and `tls` is configured. Returns the connection and the user it's of if it's one of a client
+// of the protocol, None if it was a cancel request or turned away.
+fn startup(socket: TcpStream, keys: &Keys, tls: Option<&ServerConfig>) -> io::Result<Option<(Stream, String)>> {
+    socket.set_nonblocking(false)?;
+    socket.set_read_timeout(Some(STARTUP_TIMEOUT))?;
+    let mut stream = Stream::Plain(socket);
+    loop {
+        let mut len = [0; 4];
+        stream.read_exact(&mut len)?;
+        let len = i32::from_be_bytes(len) as usize;
+        if !(8..=MAX_STARTUP_LEN).contains(&len) {
+            return Err(invalid("invalid length of startup packet"));
+        }
+        let mut body = vec![0; len - 4];
+        stream.read_exact(&mut body)?;
+        let mut reader = Reader::new(&body);
+        match (reader.i32()?, tls, stream) {
+            // only before the handshake, as the request isn't sent again
+            (SSL_REQUEST, Some(tls), Stream::Plain(mut socket)) => {
+                socket.write_all(b"S")?;
+                stream = Stream::Tls(Box::new(tls::accept(socket, tls)?));
+            }
+            (SSL_REQUEST | GSSENC_REQUEST, _, mut plain) => {
+                plain.write_all(b"N")?;
+                stream = plain;
+            }
+            (CANCEL_REQUEST, ..) => {
+                let key = (reader.i32()?, reader.i32()?);
+                if let Some(token) = keys.lock().unwrap().get(&key) {
+                    token.cancel();
+                }
+                return Ok(None);
+            }
+            (PROTOCOL_VERSION, Some(tls), Stream::Plain(mut socket)) if tls.tls_required() => {
+                let mut out = vec![];
+                error_response(&mut out, "FATAL", "28000", "SSL is required");
+                socket.write_all(&out)?;
+                return Ok(None);
+            }
+            // of the parameters, only the user makes a difference
+            (PROTOCOL_VERSION, _, stream) => {
+                let mut user = String::new();
+                loop {
+                    let name = reader.cstr()?;
+                    if name.is_empty() {
+                        break;
+                    }
+                    let value = reader.cstr()?;
+                    if name == "user" {
+                        user = value;
+                    }
+                }
+                stream.socket().set_read_timeout(None)?;
+                stream.socket().set_nonblocking(true)?;
+                return Ok(Some((stream, user)));
+            }
+            (version, _, mut stream) => {
+                let mut out = vec![];
+                let message = format!("unsupported frontend protocol {}.{}", version >> 16, version & 0xffff);
+                error_response(&mut out, "FATAL", "0A000", &message);
+                stream.write_all(&out)?;
+                return Ok(None);
+            }
+        }
+    }
+}
+
+// A connection, encrypted if the client asked for SSL and the server has TLS configured, of the
+// server's side but for the client of the tests.
+enum Stream<C = ServerConnection> {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<C, TcpStream>>),
+}
+
+impl<C> Stream<C> {
+    fn socket(&self) -> &TcpStream {
+        match self {
+            Stream::Plain(socket) => socket,
+            Stream::Tls(tls) => &tls.sock,
+        }
+    }
+}
+
+impl Stream {
+    // The user the client's verified certificate is of, if it presented one.
+    fn certificate_user(&self) -> Option<String> {
+        match self {
+            Stream::Plain(_) => None,
+            Stream::Tls(tls) => tls.conn.peer_certificates()?.first().map(|certificate| tls::common_name(certificate).unwrap_or_default()),
+        }
+    }
+}
+
+impl<C: DerefMut<Target = ConnectionCommon<S>>, S: SideData> Read for Stream<C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(socket) => socket.read(buf),
+            Stream::Tls(tls) => tls.read(buf),
+        }
+    }
+}
+
+impl<C: DerefMut<Target = ConnectionCommon<S>>, S: SideData> Write for Stream<C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(socket) => socket.write(buf),
+            Stream::Tls(tls) => tls.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(socket) => socket.flush(),
+            Stream::Tls(tls) => tls.flush(),
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn utf8(bytes: &[u8]) -> io::Result<&str> {
+    std::str::from_utf8(bytes).map_err(|_| invalid("string not in UTF-8"))
+}
+
+struct Statement {
+    // None for an empty query
+    prepared: Option<Rc<PreparedStatement>>,
+    // types given by the client, 0 where left to the server
+    param_oids: Vec<u32>,
+}
+
+struct Portal {
+    statement: Rc<Statement>,
+    params: Vec<Value>,
+    // of the columns of the rows, one for all of them or none for text
+    formats: Vec<i16>,
+}
+
+// How far a connection logging in has got with the SCRAM exchange.
+enum Login {
+    // waiting for the SASLInitialResponse, with the credentials of the user if there's one, and
+    // the server's secret to answer as if there were otherwise
+    Started(Option<Credentials>, Rc<[u8]>),
+    // waiting for the SASLResponse
+    Challenged(ServerFirst),
+}
+
+struct Connection {
+    stream: Stream,
+    session: Session,
+    key: (i32, i32),
+    user: String,
+    // None once logged in
+    login: Option<Login>,
+    // read but not handled yet, and to be written
+    input: Vec<u8>,
+    output: Vec<u8>,
+    // since when more than MAX_OUTPUT has been waiting for the client to take it, and for how
+    // long it may
+    backed_up: Option<Instant>,
+    send_timeout: Duration,
+    // under consensus, the ends of the output held back, each until the log before an LSN is
+    // committed, and the end of that released since
+    held: VecDeque<(usize, Lsn)>,
+    released: usize,
+    statements: HashMap<String, Rc<Statement>>,
+    portals: HashMap<String, Portal>,
+    // set on an error in an extended query, until Sync
+    failed: bool,
+    closed: bool,
+}
+
+impl Connection {
+    // Completes the startup of the connection, asking for the password of the user if the
+    // database has a superuser with a password and the client presented no certificate, and
+    // registering the key to cancel its session with.
+    fn start(stream: Stream, user: &str, db: &Database, keys: &Keys, scram_secret: &Rc<[u8]>, send_timeout: Duration) -> io::Result<Self> {
+        let session = db.session();
+        let secret = i32::from_be_bytes(scram::random_bytes(4).try_into().unwrap());
+        let key = (session.id() as i32, secret);
+        keys.lock().unwrap().insert(key, session.cancel_token());
+        let (login, known) = db.with_engine(|_, catalog| {
+            let info = catalog.user(user);
+            let password = info.and_then(|info| info.password.as_ref());
+            let bootstrapped = catalog.users().any(|user| user.superuser && user.password.is_some());
+            let login = bootstrapped.then(|| Login::Started(password.and_then(|password| password.parse().ok()), scram_secret.clone()));
+            (login, info.is_some())
+        });
+        let certified = stream.certificate_user().map(|name| name == user && known);
+        let mut connection = Self {
+            stream,
+            session,
+            key,
+            user: user.to_string(),
+            login,
+            input: vec![],
+            output: vec![],
+            backed_up: None,
+            send_timeout,
+            held: VecDeque::new(),
+            released: 0,
+            statements: HashMap::new(),
+            portals: HashMap::new(),
+            failed: false,
+            closed: false,
+        };
+        match (&connection.login, certified) {
+            (None, _) => connection.greet(),
+            // by the certificate, instead of a password
+            (Some(_), Some(true)) => {
+                connection.login = None;
+                connection.session.set_user(Some(user));
+                connection.greet();
+            }
+            (Some(_), Some(false)) => {
+                error_response(&mut connection.output, "FATAL", "28000", &format!("certificate authentication failed for user {:?}", user));
+                connection.closed = true;
+            }
+            (Some(_), None) => message(&mut connection.output, b'R', |body| {
+                body.extend(AUTH_SASL.to_be_bytes());
+                cstr(body, scram::MECHANISM);
+                body.push(0);
+            }),
+        }
+        connection.flush()?;
+        Ok(connection)
+    }
+
+    // Tells the client it's logged in, and what it needs to know of the server.
+    fn greet(&mut self) {
+        let out = &mut self.output;
+        message(out, b'R', |body| body.extend(AUTH_OK.to_be_bytes()));
+        for (name, value) in [
+            ("server_version", SERVER_VERSION),
+            ("server_encoding", "UTF8"),
+            ("client_encoding", "UTF8"),
+            ("DateStyle", "ISO, MDY"),
+            ("integer_datetimes", "on"),
+            ("standard_conforming_strings", "on"),
+        ] {
+            message(out, b'S', |body| {
+                cstr(body, name);
+                cstr(body, value);
+            });
+        }
+        let key = self.key;
+        message(out, b'K', |body| {
+            body.extend(key.0.to_be_bytes());
+            body.extend(key.1.to_be_bytes());
+        });
+        self.ready_for_query();
+    }
+
+    // Handles a message of the SCRAM exchange, closing the connection if it fails.
+    fn log_in(&mut self, login: Login, tag: u8, body: &[u8]) -> io::Result<()> {
+        let result = match (tag, login) {
+            (b'p', Login::Started(credentials, secret)) => self.sasl_initial_response(credentials, &secret, &mut Reader::new(body)),
+            (b'p', Login::Challenged(exchange)) => self.sasl_response(exchange, body),
+            _ => Err(Failure::Protocol(format!("unexpected message type {:?} while logging in", tag as char))),
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(Failure::Io(e)) => Err(e),
+            Err(failure) => {
+                let (code, message) = match failure {
+                    Failure::Scram(scram::Error::Failed) => ("28P01", format!("password authentication failed for user {:?}", self.user)),
+                    failure => failure.describe(),
+                };
+                error_response(&mut self.output, "FATAL", code, &message);
+                self.closed = true;
+                Ok(())
+            }
+        }
+    }
+
+    // Answers the client-first message, of the mechanism the server asked for.
+    fn sasl_initial_response(&mut self, credentials: Option<Credentials>, secret: &[u8], reader: &mut Reader) -> Result<(), Failure> {
+        if reader.cstr()? != scram::MECHANISM {
+            return Err(Failure::Protocol("unsupported SASL mechanism".to_string()));
+        }
+        let len = reader.i32()?;
+        let client_first = utf8(reader.bytes(len.max(0) as usize)?)?;
+        let (exchange, server_first) = ServerFirst::start(credentials, &self.user, secret, client_first).map_err(Failure::Scram)?;
+        message(&mut self.output, b'R', |body| {
+            body.extend(AUTH_SASL_CONTINUE.to_be_bytes());
+            body.extend(server_first.as_bytes());
+        });
+        self.login = Some(Login::Challenged(exchange));
+        Ok(())
+    }
+
+    // Checks the client's proof, logging it in if it knows the password, to run its statements
+    // with the privileges of the user.
+    fn sasl_response(&mut self, exchange: ServerFirst, body: &[u8]) -> Result<(), Failure> {
+        let server_final = exchange.finish(utf8(body)?).map_err(Failure::Scram)?;
+        self.session.set_user(Some(&self.user));
+        message(&mut self.output, b'R', |body| {
+            body.extend(AUTH_SASL_FINAL.to_be_bytes());
+            body.extend(server_final.as_bytes());
+        });
+        self.greet();
+        Ok(())
+    }
+
+    // Handles the messages received so far, leaving the answers to be flushed. Returns whether
+    // there were any.
+    fn poll(&mut self) -> io::Result<bool> {
+        let mut read = false;
+        let mut buf = [0; 8192];
+        while self.input.len() < self.read_ahead() {
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(n) => {
+                    read = true;
+                    self.input.extend_from_slice(&buf[..n]);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        while self.input.len() >= 5 && !self.closed {
+            let len = i32::from_be_bytes(self.input[1..5].try_into().unwrap());
+            if len < 4 || len as usize > self.max_message_len() {
+                error_response(&mut self.output, "FATAL", "08P01", &format!("invalid message length {}", len));
+                self.closed = true;
+                break;
+            }
+            if self.input.len() < 1 + len as usize {
+                break;
+            }
+            let message: Vec<u8> = self.input.drain(..1 + len as usize).collect();
+            self.handle(message[0], &message[5..])?;
+        }
+        // by CANCEL SESSION, say, whether it's running anything or not
+        if self.session.is_terminated() && !self.closed {
+            error_response(&mut self.output, "FATAL", "57P01", "terminating connection due to administrator command");
+            self.closed = true;
+        }
+        Ok(read)
+    }
+
+    // The longest message the client may send, shorter until it's logged in.
+    fn max_message_len(&self) -> usize {
+        if self.login.is_some() {
+            MAX_STARTUP_LEN
+        } else {
+            MAX_MESSAGE_LEN
+        }
+    }
+
+    // How much input to read before handling it: the whole of the message at its front if it's
+    // of a length accepted, or else a buffer's worth, so that a client sending faster than it's
+    // served has no more held in memory.
+    fn read_ahead(&self) -> usize {
+        match self.input.get(1..5).map(|len| i32::from_be_bytes(len.try_into().unwrap())) {
+            Some(len) if (4..=self.max_message_len() as i32).contains(&len) => (1 + len as usize).max(READ_AHEAD),
+            _ => READ_AHEAD,
+        }
+    }
+
+    fn handle(&mut self, tag: u8, body: &[u8]) -> io::Result<()> {
+        if let Some(login) = self.login.take() {
+            return self.log_in(login, tag, body);
+        }
+        if self.failed && !matches!(tag, b'S' | b'X') {
+            return Ok(());
+        }
+        let mut reader = Reader::new(body);
+        let result = match tag {
+            b'Q' => {
+                let query = reader.cstr()?;
+                self.simple_query(&query);
+                Ok(())
+            }
+            b'P' => self.parse(&mut reader),
+            b'B' => self.bind(&mut reader),
+            b'D' => self.describe(&mut reader),
+            b'E' => self.execute(&mut reader),
+            b'C' => {
+                let kind = reader.u8()?;
+                let name = reader.cstr()?;
+                if kind == b'S' {
+                    self.statements.remove(&name);
+                } else {
+                    self.portals.remove(&name);
+                    self.session.close_portal(&name);
+                }
+                message(&mut self.output, b'3', |_| {});
+                Ok(())
+            }
+            b'S' => {
+                self.failed = false;
+                if let Err(err) = self.session.sync() {
+                    sql_error(&mut self.output, &err);
+                }
+                self.ready_for_query();
+                Ok(())
+            }
+            b'H' => Ok(()),
+            b'X' => {
+                self.closed = true;
+                Ok(())
+            }
+            _ => Err(Failure::Protocol(format!("unexpected message type {:?}", tag as char))),
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(Failure::Io(e)) => Err(e),
+            Err(failure) => {
+                let (code, message) = failure.describe();
+                error_response(&mut self.output, "ERROR", code, &message);
+                self.failed = true;
+                Ok(())
+            }
+        }
+    }
+
+    // Runs the statements of `query` one after another, stopping at the first which fails.
+    fn simple_query(&mut self, query: &str) {
+        let statements = match self.session.parse(query) {
+            Ok(statements) => statements,
+            Err(err) => {
+                sql_error(&mut self.output, &err);
+                self.ready_for_query();
+                return;
+            }
+        };
+        if statements.is_empty() {
+            message(&mut self.output, b'I', |_| {});
+        }
+        for (statement, text) in &statements {
+            let result = self.session.prepare_statement(statement).and_then(|prepared| {
+                let prepared = prepared.with_sql(text);
+                let result = self.session.execute_prepared(&prepared, &[])?;
+                Ok((prepared, result))
+            });
+            match result {
+                Ok((prepared, result)) => {
+                    if let QueryResult::Rows { rows, .. } = &result {
+                        let columns = result_columns(self.session.result_columns(&prepared), rows).unwrap_or_default();
+                        row_description(&mut self.output, &columns, &[]);
+                    }
+                    self.complete(&prepared, &result, &[]);
+                }
+                Err(err) => {
+                    sql_error(&mut self.output, &err);
+                    break;
+                }
+            }
+        }
+        self.ready_for_query();
+    }
+
+    fn parse(&mut self, reader: &mut Reader) -> Result<(), Failure> {
+        let name = reader.cstr()?;
+        let query = reader.cstr()?;
+        let num_params = reader.i16()?;
+        let param_oids = (0..num_params).map(|_| reader.i32().map(|oid| oid as u32)).collect::<io::Result<_>>()?;
+        let prepared = if self.session.parse(&query)?.is_empty() { None } else { Some(Rc::new(self.session.prepare(&query)?)) };
+        self.statements.insert(name, Rc::new(Statement { prepared, param_oids }));
+        message(&mut self.output, b'1', |_| {});
+        Ok(())
+    }
+
+    fn bind(&mut self, reader: &mut Reader) -> Result<(), Failure> {
+        let portal = reader.cstr()?;
+        let name = reader.cstr()?;
+        let statement = self.statement(&name)?;
+        let param_formats = reader.formats()?;
+        let num_params = reader.i16()? as usize;
+        let param_types = statement.prepared.as_ref().map_or(&[][..], |prepared| prepared.param_types());
+        let mut params = vec![];
+        for i in 0..num_params {
+            let len = reader.i32()?;
+            let bytes = if len < 0 { None } else { Some(reader.bytes(len as usize)?) };
+            let binary = format_of(&param_formats, i) == 1;
+            let oid = statement.param_oids.get(i).copied().unwrap_or(0);
+            let data_type = type_of_oid(oid).or(param_types.get(i).copied().flatten());
+            params.push(decode_param(bytes, data_type, binary).map_err(|err| {
+                Failure::Protocol(format!("invalid value of parameter ${}: {}", i + 1, err))
+            })?);
+        }
+        let formats = reader.formats()?;
+        // replacing one suspended
+        self.session.close_portal(&portal);
+        self.portals.insert(portal, Portal { statement, params, formats });
+        message(&mut self.output, b'2', |_| {});
+        Ok(())
+    }
+
+    fn describe(&mut self, reader: &mut Reader) -> Result<(), Failure> {
+        let kind = reader.u8()?;
+        let name = reader.cstr()?;
+        let (statement, formats) = if kind == b'S' {
+            let statement = self.statement(&name)?;
+            let param_types = statement.prepared.as_ref().map_or(&[][..], |prepared| prepared.param_types());
+            let oids: Vec<u32> = (0..param_types.len().max(statement.param_oids.len()))
+                .map(|i| match statement.param_oids.get(i) {
+                    Some(&oid) if oid != 0 => oid,
+                    _ => oid_of(param_types.get(i).copied().flatten().unwrap_or(DataType::Text)),
+                })
+                .collect();
+            message(&mut self.output, b't', |body| {
+                body.extend((oids.len() as i16).to_be_bytes());
+                for oid in oids {
+                    body.extend(oid.to_be_bytes());
+                }
+            });
+            (statement, vec![])
+        } else {
+            let portal = self.portals.get(&name).ok_or_else(|| Failure::Protocol(format!("portal {:?} does not exist", name)))?;
+            (portal.statement.clone(), portal.formats.clone())
+        };
+        match statement.prepared.as_ref().and_then(|prepared| self.session.result_columns(prepared)) {
+            Some(columns) => {
+                let columns: Vec<_> = columns.into_iter().map(|(name, data_type)| (name, data_type.unwrap_or(DataType::Text))).collect();
+                row_description(&mut self.output, &columns, &formats);
+            }
+            None => message(&mut self.output, b'n', |_| {}),
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, reader: &mut Reader) -> Result<(), Failure> {
+        let name = reader.cstr()?;
+        // the most rows to return, all if 0
+        let max_rows = reader.i32()?;
+        let portal = self.portals.get(&name).ok_or_else(|| Failure::Protocol(format!("portal {:?} does not exist", name)))?;
+        let Some(prepared) = portal.statement.prepared.clone() else {
+            message(&mut self.output, b'I', |_| {});
+            return Ok(());
+        };
+        let (params, formats) = (portal.params.clone(), portal.formats.clone());
+        let (result, suspended) = self.session.execute_portal(&name, &prepared, &params, (max_rows > 0).then_some(max_rows as u64))?;
+        match &result {
+            QueryResult::Rows { rows, .. } if suspended => {
+                self.data_rows(&prepared, rows, &formats);
+                message(&mut self.output, b's', |_| {});
+            }
+            _ => self.complete(&prepared, &result, &formats),
+        }
+        Ok(())
+    }
+
+    fn statement(&self, name: &str) -> Result<Rc<Statement>, Failure> {
+        self.statements.get(name).cloned().ok_or_else(|| Failure::Protocol(format!("prepared statement {:?} does not exist", name)))
+    }
+
+    // Sends the rows of `result`, if any, and the tag of the command.
+    fn complete(&mut self, prepared: &PreparedStatement, result: &QueryResult, formats: &[i16]) {
+        let command = prepared.command();
+        let tag = match result {
+            QueryResult::Rows { rows, .. } => {
+                self.data_rows(prepared, rows, formats);
+                match command {
+                    "SELECT" | "FETCH" | "UPDATE" | "DELETE" => format!("{} {}", command, rows.len()),
+                    // of RETURNING
+                    "INSERT" => format!("INSERT 0 {}", rows.len()),
+                    _ => command.to_string(),
+                }
+            }
+            QueryResult::RowsAffected(n) if command == "INSERT" => format!("INSERT 0 {}", n),
+            QueryResult::RowsAffected(n) => format!("{} {}", command, n),
+            QueryResult::Done => command.to_string(),
+            QueryResult::RolledBack => "ROLLBACK".to_string(),
+        };
+        message(&mut self.output, b'C', |body| cstr(body, &tag));
+    }
+
+    fn data_rows(&mut self, prepared: &PreparedStatement, rows: &[Tuple], formats: &[i16]) {
+        let types: Vec<_> = result_columns(self.session.result_columns(prepared), rows).unwrap_or_default().into_iter().map(|(_, data_type)| data_type).collect();
+        for row in rows {
+            data_row(&mut self.output, row, &types, formats);
+        }
+    }
+
+    fn ready_for_query(&mut self) {
+        let status = match (self.session.in_transaction(), self.session.transaction_failed()) {
+            (true, true) => b'E',
+            (true, false) => b'T',
+            (false, _) => b'I',
+        };
+        message(&mut self.output, b'Z', |body| body.push(status));
+    }
+
+    // Holds back the output not held yet until the log before `lsn` is committed.
+    fn hold(&mut self, lsn: Lsn) {
+        if self.output.len() > self.held.back().map_or(self.released, |&(end, _)| end) {
+            self.held.push_back((self.output.len(), lsn));
+        }
+    }
+
+    fn release(&mut self, replicated: Lsn) {
+        while let Some(&(end, lsn)) = self.held.front() {
+            if lsn > replicated {
+                break;
+            }
+            self.released = end;
+            self.held.pop_front();
+        }
+    }
+
+    // Turns the client away once another node has been made the primary, dropping the output
+    // held back, the log before which is never to be committed.
+    fn redirect(&mut self, text: &str) {
+        if !self.held.is_empty() {
+            self.output.truncate(self.released);
+            self.held.clear();
+        }
+        error_response(&mut self.output, "FATAL", "25006", text);
+        self.closed = true;
+    }
+
+    // Writes as much of the output as the client takes without waiting, but for what's held
+    // back. Fails if it's left more than MAX_OUTPUT unread for longer than it may.
+    fn flush(&mut self) -> io::Result<()> {
+        let len = if self.held.is_empty() { self.output.len() } else { self.released };
+        let mut written = 0;
+        while written < len {
+            match self.stream.write(&self.output[written..len]) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        // what TLS has yet to write of it
+        match self.stream.flush() {
+            Err(e) if e.kind() != io::ErrorKind::WouldBlock => return Err(e),
+            _ => {}
+        }
+        self.output.drain(..written);
+        for (end, _) in &mut self.held {
+            *end -= written;
+        }
+        self.released -= written.min(self.released);
+        if len - written <= MAX_OUTPUT {
+            self.backed_up = None;
+        } else if self.backed_up.get_or_insert_with(Instant::now).elapsed() >= self.send_timeout {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "the client left its answers unread"));
+        }
+        Ok(())
+    }
+}
+
+// Why a message failed.
+enum Failure {
+    Io(io::Error),
+    Protocol(String),
+    Sql(sql::Error),
+    Scram(scram::Error),
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        // messages cut short are errors of the protocol, not of the connection
+        if e.kind() == io::ErrorKind::UnexpectedEof || e.kind() == io::ErrorKind::InvalidData {
+            Failure::Protocol(e.to_string())
+        } else {
+            Failure::Io(e)
+        }
+    }
+}
+
+impl From<sql::Error> for Failure {
+    fn from(err: sql::Error) -> Self {
+        Failure::Sql(err)
+    }
+}
+
+impl Failure {
+    // The SQLSTATE and message sent for it.
+    fn describe(&self) -> (&'static str, String) {
+        match self {
+            Failure::Io(e) => ("58030", e.to_string()),
+            Failure::Protocol(message) => ("08P01", message.clone()),
+            Failure::Sql(err) => (sqlstate(err), err.to_string()),
+            Failure::Scram(err @ scram::Error::Failed) => ("28P01", err.to_string()),
+            Failure::Scram(err) => ("08P01", err.to_string()),
+        }
+    }
+}
+
+fn sql_error(out: &mut Vec<u8>, err: &sql::Error) {
+    error_response(out, "ERROR", sqlstate(err), &err.to_string());
+}
+
+// The SQLSTATE of the errors clients are likely to act on, XX000 (internal error) for the rest.
+pub(crate) fn sqlstate(err: &sql::Error) -> &'static str {
+    let table_error = match err {
+        sql::Error::Syntax(_) => return "42601",
+        sql::Error::UnknownTable(_) | sql::Error::Catalog(crate::catalog::Error::UnknownTable(_)) => return "42P01",
+        sql::Error::UnknownColumn(_) => return "42703",
+        sql::Error::AmbiguousColumn(_) => return "42702",
+        sql::Error::Invalid(_) => return "42000",
+        sql::Error::Catalog(
+            crate::catalog::Error::TableExists(_) | crate::catalog::Error::ViewExists(_) | crate::catalog::Error::IndexExists(_),
+        ) => return "42P07",
+        sql::Error::Catalog(crate::catalog::Error::UserExists(_)) => return "42710",
+        sql::Error::Catalog(crate::catalog::Error::UnknownUser(_)) => return "42704",
+        sql::Error::PermissionDenied(_) => return "42501",
+        sql::Error::TransactionAborted => return "25P02",
+        sql::Error::Query(query::Error::TypeMismatch(_)) => return "42804",
+        sql::Error::Query(query::Error::DivisionByZero) => return "22012",
+        sql::Error::Query(query::Error::NumericOverflow) => return "22003",
+        sql::Error::Query(query::Error::InvalidArgument(_)) => return "22023",
+        sql::Error::Query(query::Error::SubqueryReturnedMultipleRows) => return "21000",
+        sql::Error::Query(query::Error::Buffer(err)) => return buffer_sqlstate(err),
+        sql::Error::Query(query::Error::Table(err)) | sql::Error::Table(err) => err,
+        _ => return "XX000",
+    };
+    match table_error {
+        table::Error::WriteConflict | table::Error::Ssi(crate::ssi::Error::SerializationFailure) => "40001",
+        table::Error::Lock(lock::Error::Deadlock) => "40P01",
+        table::Error::Lock(lock::Error::Timeout(_)) => "55P03",
+        table::Error::Buffer(err) | table::Error::Lock(lock::Error::Buffer(err)) | table::Error::Ssi(crate::ssi::Error::Buffer(err)) => {
+            buffer_sqlstate(err)
+        }
+        _ => "XX000",
+    }
+}
+
+fn buffer_sqlstate(err: &buffer::Error) -> &'static str {
+    match err {
+        buffer::Error::StatementTimeout(_) | buffer::Error::Canceled => "57014",
+        buffer::Error::OutOfMemoryBudget(_) | buffer::Error::NoFreeBuffer => "53200",
+        buffer::Error::ReadOnly => "25006",
+        buffer::Error::ReplicationConflict => "40001",
+        buffer::Error::IdleTimeout(_) => "25P03",
+        buffer::Error::SnapshotTimeout(_) => "72000",
+        buffer::Error::Terminated => "57P01",
+        buffer::Error::Unlogged => "0A000",
+        buffer::Error::Io(_) | buffer::Error::Wal(_) => "58030",
+        buffer::Error::Checksum(_) => "XX001",
+    }
+}
+
+// The columns of the rows of a statement (see `Session::result_columns`), typed by the first
+// value which isn't null in `rows` where unknown before it ran. None if it returns no rows.
+fn result_columns(columns: Option<Vec<(String, Option<DataType>)>>, rows: &[Tuple]) -> Option<Vec<(String, DataType)>> {
+    let columns = columns?;
+    let typed = columns.into_iter().enumerate().map(|(i, (name, data_type))| {
+        let data_type = data_type.or_else(|| rows.iter().find_map(|row| type_of_value(&row[i]))).unwrap_or(DataType::Text);
+        (name, data_type)
+    });
+    Some(typed.collect())
+}
+
+fn type_of_value(value: &Value) -> Option<DataType> {
+    match value {
+        Value::Null => None,
+        Value::Int(_) => Some(DataType::Integer),
+        Value::Text(_) => Some(DataType::Text),
+        Value::Bool(_) => Some(DataType::Boolean),
+        Value::Json(_) => Some(DataType::Json),
+        Value::Array(_) => DataType::of(value),
+        Value::Point(_) => Some(DataType::Point),
+        Value::Box(_) => Some(DataType::Box),
+        Value::Float(_) => Some(DataType::Float),
+        Value::Numeric(_) => Some(DataType::Numeric),
+        Value::Date(_) => Some(DataType::Date),
+        Value::Timestamp(_) => Some(DataType::Timestamp),
+    }
+}
+
+fn oid_of(data_type: DataType) -> u32 {
+    match data_type {
+        DataType::Integer => INT8_OID,
+        DataType::Text => TEXT_OID,
+        DataType::Boolean => BOOL_OID,
+        DataType::Json => JSON_OID,
+        DataType::Array(ElementType::Integer) => INT8_ARRAY_OID,
+        DataType::Array(ElementType::Text) => TEXT_ARRAY_OID,
+        DataType::Array(ElementType::Boolean) => BOOL_ARRAY_OID,
+        DataType::Point => POINT_OID,
+        DataType::Box => BOX_OID,
+        DataType::Float => FLOAT8_OID,
+        DataType::Numeric => NUMERIC_OID,
+        DataType::Date => DATE_OID,
+        DataType::Timestamp => TIMESTAMP_OID,
+    }
+}
+
+fn type_of_oid(oid: u32) -> Option<DataType> {
+    match oid {
+        INT8_OID | INT4_OID | INT2_OID => Some(DataType::Integer),
+        TEXT_OID | VARCHAR_OID => Some(DataType::Text),
+        BOOL_OID => Some(DataType::Boolean),
+        JSON_OID | JSONB_OID => Some(DataType::Json),
+        INT8_ARRAY_OID | INT4_ARRAY_OID | INT2_ARRAY_OID => Some(DataType::Array(ElementType::Integer)),
+        TEXT_ARRAY_OID | VARCHAR_ARRAY_OID => Some(DataType::Array(ElementType::Text)),
+        BOOL_ARRAY_OID => Some(DataType::Array(ElementType::Boolean)),
+        POINT_OID => Some(DataType::Point),
+        BOX_OID => Some(DataType::Box),
+        FLOAT8_OID | FLOAT4_OID => Some(DataType::Float),
+        NUMERIC_OID => Some(DataType::Numeric),
+        DATE_OID => Some(DataType::Date),
+        TIMESTAMP_OID => Some(DataType::Timestamp),
+        _ => None,
+    }
+}
+
+// The format of the `i`th value given `formats`, which are one for each, one for all or none for
+// text.
+fn format_of(formats: &[i16], i: usize) -> i16 {
+    match formats {
+        [] => 0,
+        [format] => *format,
+        formats => formats.get(i).copied().unwrap_or(0),
+    }
+}
+
+// A parameter as sent, of `data_type` if known, else an integer if it reads as one or text.
+fn decode_param(bytes: Option<&[u8]>, data_type: Option<DataType>, binary: bool) -> Result<Value, String> {
+    let Some(bytes) = bytes else {
+        return Ok(Value::Null);
+    };
+    if binary {
+        return match (data_type, bytes.len()) {
+            (Some(DataType::Integer), 8) => Ok(Value::Int(i64::from_be_bytes(bytes.try_into().unwrap()))),
+            (Some(DataType::Integer), 4) => Ok(Value::Int(i32::from_be_bytes(bytes.try_into().unwrap()) as i64)),
+            (Some(DataType::Integer), 2) => Ok(Value::Int(i16::from_be_bytes(bytes.try_into().unwrap()) as i64)),
+            (Some(DataType::Boolean), 1) => Ok(Value::Bool(bytes[0] != 0)),
+            (Some(DataType::Float), 8) => Ok(Value::Float(Float(f64::from_be_bytes(bytes.try_into().unwrap())))),
+            (Some(DataType::Float), 4) => Ok(Value::Float(Float(f32::from_be_bytes(bytes.try_into().unwrap()) as f64))),
+            (Some(DataType::Date), 4) => Ok(Value::Date(i32::from_be_bytes(bytes.try_into().unwrap()).saturating_add(POSTGRES_EPOCH_DAYS))),
+            (Some(DataType::Timestamp), 8) => {
+                let micros = i64::from_be_bytes(bytes.try_into().unwrap());
+                Ok(Value::Timestamp(micros.saturating_add(POSTGRES_EPOCH_DAYS as i64 * MICROS_PER_DAY)))
+            }
+            (Some(DataType::Text), _) => String::from_utf8(bytes.to_vec()).map(Value::Text).map_err(|e| e.to_string()),
+            // of json, in text as in the text format (jsonb's has a version byte first, of 1)
+            (Some(DataType::Json), _) => {
+                let text = std::str::from_utf8(bytes.strip_prefix(&[1]).unwrap_or(bytes)).map_err(|e| e.to_string())?;
+                json::parse(text).map(Value::Json).map_err(|e| e.to_string())
+            }
+            (None, _) => Err("binary value of unknown type".to_string()),
+            (Some(_), len) => Err(format!("binary value of {} bytes", len)),
+        };
+    }
+    let text = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+    match data_type {
+        Some(DataType::Integer) => text.trim().parse().map(Value::Int).map_err(|_| format!("not an integer: {:?}", text)),
+        Some(DataType::Boolean) => match text.trim().to_ascii_lowercase().as_str() {
+            "t" | "true" | "on" | "yes" | "y" | "1" => Ok(Value::Bool(true)),
+            "f" | "false" | "off" | "no" | "n" | "0" => Ok(Value::Bool(false)),
+            _ => Err(format!("not a boolean: {:?}", text)),
+        },
+        Some(DataType::Text) => Ok(Value::Text(text.to_string())),
+        Some(DataType::Json) => json::parse(text).map(Value::Json).map_err(|e| e.to_string()),
+        Some(DataType::Array(element)) => array::parse(text, element).map(Value::Array).map_err(|e| e.to_string()),
+        Some(DataType::Point) => Point::parse(text).map(Value::Point).map_err(|e| e.to_string()),
+        Some(DataType::Box) => Rect::parse(text).map(Value::Box).map_err(|e| e.to_string()),
+        Some(DataType::Float) => Float::parse(text).map(Value::Float).map_err(|e| e.to_string()),
+        Some(DataType::Numeric) => Numeric::parse(text).map(Value::Numeric).map_err(|e| e.to_string()),
+        Some(DataType::Date) => datetime::parse_date(text).map(Value::Date).map_err(|e| e.to_string()),
+        Some(DataType::Timestamp) => datetime::parse_timestamp(text).map(Value::Timestamp).map_err(|e| e.to_string()),
+        None => Ok(text.parse().map(Value::Int).unwrap_or_else(|_| Value::Text(text.to_string()))),
+    }
+}
+
+// Appends a message: its type, its length and what `body` writes.
+pub(crate) fn message(out: &mut Vec<u8>, tag: u8, body: impl FnOnce(&mut Vec<u8>)) {
+    out.push(tag);
+    let start = out.len();
+    out.extend([0; 4]);
+    body(out);
+    let len = (out.len() - start) as i32;
+    out[start..start + 4].copy_from_slice(&len.to_be_bytes());
+}
+
+pub(crate) fn cstr(out: &mut Vec<u8>, s: &str) {
+    out.extend(s.as_bytes());
+    out.push(0);
+}
+
+fn error_response(out: &mut Vec<u8>, severity: &str, code: &str, text: &str) {
+    message(out, b'E', |body| {
+        for (field, value) in [(b'S', severity), (b'V', severity), (b'C', code), (b'M', text)] {
+            body.push(field);
+            cstr(body, value);
+        }
+        body.push(0);
+    });
+}
+
+fn row_description(out: &mut Vec<u8>, columns: &[(String, DataType)], formats: &[i16]) {
+    message(out, b'T', |body| {
+        body.extend((columns.len() as i16).to_be_bytes());
+        for (i, (name, data_type)) in columns.iter().enumerate() {
+            cstr(body, name);
+            // no table, no column number
+            body.extend(0i32.to_be_bytes());
+            body.extend(0i16.to_be_bytes());
+            body.extend(oid_of(*data_type).to_be_bytes());
+            let size: i16 = match data_type {
+                DataType::Integer | DataType::Float | DataType::Timestamp => 8,
+                DataType::Boolean => 1,
+                DataType::Date => 4,
+                DataType::Point => 16,
+                DataType::Box => 32,
+                DataType::Text | DataType::Json | DataType::Array(_) | DataType::Numeric => -1,
+            };
+            body.extend(size.to_be_bytes());
+            body.extend((-1i32).to_be_bytes());
+            body.extend(format_of(formats, i).to_be_bytes());
+        }
+    });
+}
+
+// Values are sent as the types in the row description say, in text where they don't agree.
+fn data_row(out: &mut Vec<u8>, row: &[Value], types: &[DataType], formats: &[i16]) {
+    message(out, b'D', |body| {
+        body.extend((row.len() as i16).to_be_bytes());
+        for (i, value) in row.iter().enumerate() {
+            let binary = format_of(formats, i) == 1;
+            let bytes = match (value, binary, types.get(i)) {
+                (Value::Null, _, _) => {
+                    body.extend((-1i32).to_be_bytes());
+                    continue;
+                }
+                (Value::Int(n), true, Some(DataType::Integer)) => n.to_be_bytes().to_vec(),
+                (Value::Bool(b), true, Some(DataType::Boolean)) => vec![*b as u8],
+                (Value::Float(f), true, Some(DataType::Float)) => f.0.to_be_bytes().to_vec(),
+                (Value::Date(days), true, Some(DataType::Date)) => (days - POSTGRES_EPOCH_DAYS).to_be_bytes().to_vec(),
+                (Value::Timestamp(micros), true, Some(DataType::Timestamp)) => {
+                    (micros - POSTGRES_EPOCH_DAYS as i64 * MICROS_PER_DAY).to_be_bytes().to_vec()
+                }
+                (Value::Int(n), _, _) => n.to_string().into_bytes(),
+                (Value::Bool(b), _, _) => if *b { b"t".to_vec() } else { b"f".to_vec() },
+                (Value::Text(text), _, _) => text.as_bytes().to_vec(),
+                (Value::Json(bytes), _, _) => json::to_string(bytes).into_bytes(),
+                (Value::Array(values), _, _) => array::to_string(values).into_bytes(),
+                (Value::Point(point), _, _) => point.to_string().into_bytes(),
+                (Value::Box(rect), _, _) => rect.to_string().into_bytes(),
+                (Value::Float(f), _, _) => f.to_string().into_bytes(),
+                (Value::Numeric(n), _, _) => n.to_string().into_bytes(),
+                (Value::Date(days), _, _) => datetime::format_date(*days).into_bytes(),
+                (Value::Timestamp(micros), _, _) => datetime::format_timestamp(*micros).into_bytes(),
+            };
+            body.extend((bytes.len() as i32).to_be_bytes());
+            body.extend(bytes);
+        }
+    });
+}
+
+// Reads the fields of a message.
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "message cut short"));
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn i16(&mut self) -> io::Result<i16> {
+        Ok(i16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn cstr(&mut self) -> io::Result<String> {
+        let len = self.buf.iter().position(|&b| b == 0).ok_or_else(|| invalid("string not terminated"))?;
+        let s = std::str::from_utf8(self.bytes(len)?).map_err(|_| invalid("string not in UTF-8"))?.to_string();
+        self.bytes(1)?;
+        Ok(s)
+    }
+
+    fn formats(&mut self) -> io::Result<Vec<i16>> {
+        let n = self.i16()?;
+        (0..n).map(|_| self.i16()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recovery;
+    use crate::replication::Replica;
+    use rustls::ClientConnection;
+    use tempfile::tempdir;
+
+    // A client of the protocol, as much of it as the test needs.
+    struct Client {
+        stream: Stream<ClientConnection>,
+    }
+
+    impl Client {
+        fn connect(addr: SocketAddr) -> (Self, Vec<(u8, Vec<u8>)>) {
+            Self::connect_as(addr, "test", None)
+        }
+
+        // Starts up as `user`, logging in with `password` when asked to. Returns the messages up
+        // to ReadyForQuery, or to the error if it fails to log in.
+        fn connect_as(addr: SocketAddr, user: &str, password: Option<&str>) -> (Self, Vec<(u8, Vec<u8>)>) {
+            Self::connect_tls(addr, user, password, None)
+        }
+
+        // Asks for SSL, which the server must accept if `tls` is given, and decline otherwise,
+        // then sends the startup packet.
+        fn start_up(addr: SocketAddr, user: &str, tls: Option<&tls::ClientConfig>) -> Self {
+            let mut socket = TcpStream::connect(addr).unwrap();
+            socket.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+            socket.write_all(&[0, 0, 0, 8]).unwrap();
+            socket.write_all(&SSL_REQUEST.to_be_bytes()).unwrap();
+            let mut answer = [0];
+            socket.read_exact(&mut answer).unwrap();
+            let mut stream = match tls {
+                Some(tls) => {
+                    assert_eq!(b'S', answer[0]);
+                    Stream::Tls(Box::new(tls::connect(socket, "localhost", tls).unwrap()))
+                }
+                None => {
+                    assert_eq!(b'N', answer[0]);
+                    Stream::Plain(socket)
+                }
+            };
+            let mut body = PROTOCOL_VERSION.to_be_bytes().to_vec();
+            for s in ["user", user, "database", "db", ""] {
+                cstr(&mut body, s);
+            }
+            stream.write_all(&((body.len() + 4) as i32).to_be_bytes()).unwrap();
+            stream.write_all(&body).unwrap();
+            Self { stream }
+        }
+
+        // Starts up as `start_up` does, then logs in as `connect_as` does.
+        fn connect_tls(addr: SocketAddr, user: &str, password: Option<&str>, tls: Option<&tls::ClientConfig>) -> (Self, Vec<(u8, Vec<u8>)>) {
+            let mut client = Self::start_up(addr, user, tls);
+            let (tag, body) = client.receive();
+            if body[..4] != AUTH_SASL.to_be_bytes() {
+                if tag == b'E' {
+                    return (client, vec![(tag, body)]);
+                }
+                let mut messages = vec![(tag, body)];
+                messages.extend(client.until_ready());
+                return (client, messages);
+            }
+            assert_eq!(format!("{}\0\0", scram::MECHANISM).as_bytes(), &body[4..]);
+            let (exchange, client_first) = scram::ClientFirst::start(password.unwrap_or(""));
+            client.send(b'p', |body| {
+                cstr(body, scram::MECHANISM);
+                body.extend((client_first.len() as i32).to_be_bytes());
+                body.extend(client_first.as_bytes());
+            });
+            let (tag, body) = client.receive();
+            assert_eq!((b'R', AUTH_SASL_CONTINUE.to_be_bytes()), (tag, body[..4].try_into().unwrap()));
+            let (client_final, server_final) = exchange.finish(std::str::from_utf8(&body[4..]).unwrap()).unwrap();
+            client.send(b'p', |body| body.extend(client_final.as_bytes()));
+            let (tag, body) = client.receive();
+            if tag == b'E' {
+                return (client, vec![(tag, body)]);
+            }
+            assert_eq!(server_final.as_bytes(), &body[4..]);
+            let messages = client.until_ready();
+            (client, messages)
+        }
+
+        fn send(&mut self, tag: u8, body: impl FnOnce(&mut Vec<u8>)) {
+            let mut out = vec![];
+            message(&mut out, tag, body);
+            self.stream.write_all(&out).unwrap();
+        }
+
+        fn receive(&mut self) -> (u8, Vec<u8>) {
+            let mut header = [0; 5];
+            self.stream.read_exact(&mut header).unwrap();
+            let len = i32::from_be_bytes(header[1..].try_into().unwrap()) as usize;
+            let mut body = vec![0; len - 4];
+            self.stream.read_exact(&mut body).unwrap();
+            (header[0], body)
+        }
+
+        // The messages up to and including ReadyForQuery.
+        fn until_ready(&mut self) -> Vec<(u8, Vec<u8>)> {
+            let mut messages = vec![];
+            loop {
+                let (tag, body) = self.receive();
+                messages.push((tag, body));
+                if tag == b'Z' {
+                    return messages;
+                }
+            }
+        }
+
+        fn query(&mut self, sql: &str) -> Vec<(u8, Vec<u8>)> {
+            self.send(b'Q', |body| cstr(body, sql));
+            self.until_ready()
+        }
+    }
+
+    fn tags(messages: &[(u8, Vec<u8>)]) -> String {
+        messages.iter().map(|(tag, _)| *tag as char).collect()
+    }
+
+    fn text(body: &[u8]) -> String {
+        String::from_utf8_lossy(body).into_owned()
+    }
+
+    // The values of a DataRow, None for NULL.
+    fn values(body: &[u8]) -> Vec<Option<Vec<u8>>> {
+        let mut reader = Reader::new(body);
+        let n = reader.i16().unwrap();
+        (0..n)
+            .map(|_| {
+                let len = reader.i32().unwrap();
+                (len >= 0).then(|| reader.bytes(len as usize).unwrap().to_vec())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path(), 16).unwrap();
+        let mut server = Server::bind(db, "127.0.0.1:0").unwrap();
+        let (addr, stopped) = (server.local_addr(), server.stop_flag());
+        let client = thread::spawn(move || {
+            let (mut client, startup) = Client::connect(addr);
+            assert_eq!("RSSSSSSKZ", tags(&startup));
+            let key = startup.iter().find(|(tag, _)| *tag == b'K').unwrap().1.clone();
+            assert_eq!(b"I", &startup.last().unwrap().1[..]);
+
+            // simple queries, one result after another, stopping at the first error
+            let messages = client.query("CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT, ok BOOLEAN)");
+            assert_eq!("CZ", tags(&messages));
+            assert_eq!("CREATE TABLE\0", text(&messages[0].1));
+            let messages = client.query("INSERT INTO t VALUES (1, 'a', true), (2, NULL, false); SELECT * FROM t; SELECT nope FROM t; SELECT 1");
+            assert_eq!("CTDDCEZ", tags(&messages));
+            assert_eq!("INSERT 0 2\0", text(&messages[0].1));
+            let description = text(&messages[1].1);
+            assert!(description.contains("id\0") && description.contains("name\0") && description.contains("ok\0"));
+            assert_eq!(vec![Some(b"1".to_vec()), Some(b"a".to_vec()), Some(b"t".to_vec())], values(&messages[2].1));
+            assert_eq!(vec![Some(b"2".to_vec()), None, Some(b"f".to_vec())], values(&messages[3].1));
+            assert_eq!("SELECT 2\0", text(&messages[4].1));
+            let error = text(&messages[5].1);
+            assert!(error.contains("C42703\0") && error.contains("column not found: nope"), "{}", error);
+            assert_eq!("IZ", tags(&client.query("")));
+            let messages = client.query("BEGIN; INSERT INTO t VALUES (3, 'c', NULL)");
+            assert_eq!(b"T", &messages.last().unwrap().1[..]);
+            assert_eq!(b"I", &client.query("COMMIT").last().unwrap().1[..]);
+            // a block failed until it's ended, its COMMIT rolling back
+            let messages = client.query("BEGIN; INSERT INTO t VALUES (3, 'c', NULL)");
+            assert_eq!(b"E", &messages.last().unwrap().1[..]);
+            let messages = client.query("INSERT INTO t VALUES (4, 'd', NULL)");
+            assert!(text(&messages[0].1).contains("C25P02\0") && messages[1].1 == b"E");
+            let messages = client.query("COMMIT");
+            assert_eq!(("ROLLBACK\0".to_string(), &b"I"[..]), (text(&messages[0].1), &messages[1].1[..]));
+            // a cursor's rows a batch at a time, described once fetched
+            let messages = client.query("BEGIN; DECLARE c CURSOR FOR SELECT id FROM t; FETCH 2 FROM c");
+            assert_eq!("CCTDDCZ", tags(&messages));
+            assert_eq!("DECLARE CURSOR\0", text(&messages[1].1));
+            assert!(text(&messages[2].1).contains("id\0"));
+            assert_eq!(vec![Some(b"2".to_vec())], values(&messages[4].1));
+            assert_eq!("FETCH 2\0", text(&messages[5].1));
+            let messages = client.query("FETCH ALL c; COMMIT");
+            assert_eq!("TDCCZ", tags(&messages));
+            assert_eq!("FETCH 1\0", text(&messages[2].1));
+            // the rows an INSERT returns, tagged as it is
+            let messages = client.query("INSERT INTO t VALUES (10, 'j', NULL) RETURNING id, name");
+            assert_eq!("TDCZ", tags(&messages));
+            assert_eq!(vec![Some(b"10".to_vec()), Some(b"j".to_vec())], values(&messages[1].1));
+            assert_eq!("INSERT 0 1\0", text(&messages[2].1));
+
+            // extended queries, with a parameter in text and one in binary, rows in binary
+            client.send(b'P', |body| {
+                cstr(body, "s");
+                cstr(body, "SELECT id, name FROM t WHERE id >= $1 AND ok = $2");
+                body.extend(1i16.to_be_bytes());
+                body.extend(0i32.to_be_bytes());
+            });
+            client.send(b'D', |body| {
+                body.push(b'S');
+                cstr(body, "s");
+            });
+            client.send(b'B', |body| {
+                cstr(body, "");
+                cstr(body, "s");
+                for n in [2i16, 0, 1, 2] {
+                    body.extend(n.to_be_bytes());
+                }
+                body.extend(1i32.to_be_bytes());
+                body.extend(b"1");
+                body.extend(1i32.to_be_bytes());
+                body.push(0);
+                body.extend(1i16.to_be_bytes());
+                body.extend(1i16.to_be_bytes());
+            });
+            client.send(b'E', |body| {
+                cstr(body, "");
+                body.extend(0i32.to_be_bytes());
+            });
+            client.send(b'S', |_| {});
+            let messages = client.until_ready();
+            assert_eq!("1tT2DCZ", tags(&messages));
+            // the parameters are typed by the columns they're compared with
+            assert_eq!([0, 2, 0, 0, 0, INT8_OID as u8, 0, 0, 0, BOOL_OID as u8], &messages[1].1[..]);
+            assert_eq!(vec![Some(2i64.to_be_bytes().to_vec()), None], values(&messages[4].1));
+            assert_eq!("SELECT 1\0", text(&messages[5].1));
+            // as many rows as asked for, the portal suspended for the next Execute to go on with
+            client.send(b'P', |body| {
+                cstr(body, "");
+                cstr(body, "SELECT * FROM generate_series(1, 3)");
+                body.extend(0i16.to_be_bytes());
+            });
+            client.send(b'B', |body| {
+                cstr(body, "");
+                cstr(body, "");
+                body.extend([0; 6]);
+            });
+            for _ in 0..2 {
+                client.send(b'E', |body| {
+                    cstr(body, "");
+                    body.extend(2i32.to_be_bytes());
+                });
+            }
+            client.send(b'S', |_| {});
+            let messages = client.until_ready();
+            assert_eq!("12DDsDCZ", tags(&messages));
+            assert_eq!(vec![Some(b"3".to_vec())], values(&messages[5].1));
+            assert_eq!(("SELECT 1\0", &b"I"[..]), (text(&messages[6].1).as_str(), &messages[7].1[..]));
+
+            // after an error, messages are skipped until Sync
+            client.send(b'P', |body| {
+                cstr(body, "");
+                cstr(body, "SELEC 1");
+                body.extend(0i16.to_be_bytes());
+            });
+            client.send(b'B', |body| {
+                cstr(body, "");
+                cstr(body, "");
+                body.extend([0; 6]);
+            });
+            client.send(b'S', |_| {});
+            let messages = client.until_ready();
+            assert_eq!("EZ", tags(&messages));
+            assert!(text(&messages[0].1).contains("C42601\0"));
+            client.send(b'C', |body| {
+                body.push(b'S');
+                cstr(body, "s");
+            });
+            client.send(b'D', |body| {
+                body.push(b'S');
+                cstr(body, "s");
+            });
+            client.send(b'S', |_| {});
+            assert_eq!("3EZ", tags(&client.until_ready()));
+
+            // a cancel request is answered by closing its connection, and others go on
+            let mut cancel = TcpStream::connect(addr).unwrap();
+            cancel.write_all(&16i32.to_be_bytes()).unwrap();
+            cancel.write_all(&CANCEL_REQUEST.to_be_bytes()).unwrap();
+            cancel.write_all(&key).unwrap();
+            assert_eq!(0, cancel.read(&mut [0]).unwrap());
+            let (mut other, _) = Client::connect(addr);
+            let messages = other.query("SELECT count(*) FROM t");
+            assert_eq!(vec![Some(b"4".to_vec())], values(&messages[1].1));
+
+            // a session terminated by another has its connection closed, idle as it is
+            let (mut doomed, startup) = Client::connect(addr);
+            let pid = i32::from_be_bytes(startup.iter().find(|(tag, _)| *tag == b'K').unwrap().1[..4].try_into().unwrap());
+            assert_eq!("CZ", tags(&other.query(&format!("CANCEL SESSION {}", pid))));
+            let (tag, body) = doomed.receive();
+            assert!(tag == b'E' && text(&body).contains("C57P01\0"), "{}", text(&body));
+            assert_eq!(0, doomed.stream.read(&mut [0]).unwrap());
+            other.send(b'X', |_| {});
+
+            // users get in without their passwords until there's a superuser with one
+            assert_eq!("CCZ", tags(&client.query("CREATE USER alice WITH PASSWORD 'secret'; CREATE USER bob")));
+            let error = text(&client.query("CREATE USER alice")[0].1);
+            assert!(error.contains("C42710\0") && error.contains("user already exists: alice"), "{}", error);
+            let (mut carol, startup) = Client::connect_as(addr, "carol", None);
+            assert_eq!("RSSSSSSKZ", tags(&startup));
+            assert_eq!("CZ", tags(&carol.query("CREATE USER root WITH SUPERUSER")));
+            carol.send(b'X', |_| {});
+            let (mut root, startup) = Client::connect_as(addr, "root", None);
+            assert_eq!(&AUTH_OK.to_be_bytes(), &startup[0].1[..]);
+            assert_eq!("CZ", tags(&root.query("ALTER USER root PASSWORD 'toor'")));
+            root.send(b'X', |_| {});
+            // after which only users get in, with their passwords
+            assert_eq!("CZ", tags(&client.query("GRANT SELECT ON t TO alice")));
+            let (mut alice, startup) = Client::connect_as(addr, "alice", Some("secret"));
+            assert_eq!("RSSSSSSKZ", tags(&startup));
+            // with the privileges granted to her only
+            assert_eq!(vec![Some(b"4".to_vec())], values(&alice.query("SELECT count(*) FROM t")[1].1));
+            let error = text(&alice.query("INSERT INTO t VALUES (9, 'x', true)")[0].1);
+            assert!(error.contains("C42501\0") && error.contains("permission denied: INSERT on t"), "{}", error);
+            alice.send(b'X', |_| {});
+            for (user, password) in [("alice", "guess"), ("bob", ""), ("carol", "secret"), ("root", "")] {
+                let (mut refused, messages) = Client::connect_as(addr, user, Some(password));
+                let error = text(&messages[0].1);
+                assert!(error.contains("SFATAL\0") && error.contains("C28P01\0") && error.contains(&format!("for user \"{}\"", user)), "{}", error);
+                assert_eq!(0, refused.stream.read(&mut [0]).unwrap());
+            }
+            assert_eq!("CZ", tags(&client.query("ALTER USER bob PASSWORD 'hunter2'")));
+            let (_, startup) = Client::connect_as(addr, "bob", Some("hunter2"));
+            assert_eq!(b'Z', startup.last().unwrap().0);
+            // whose messages are no longer than a startup packet until they're logged in
+            let mut long = Client::start_up(addr, "bob", None);
+            assert_eq!(AUTH_SASL.to_be_bytes(), long.receive().1[..4]);
+            long.stream.write_all(&[b'p', 0, 0, 0x27, 0x15]).unwrap();
+            let (tag, body) = long.receive();
+            assert!(tag == b'E' && text(&body).contains("C08P01\0Minvalid message length 10005\0"), "{}", text(&body));
+            assert_eq!(0, long.stream.read(&mut [0]).unwrap());
+            client.send(b'X', |_| {});
+            stopped.store(true, Ordering::Relaxed);
+        });
+        server.serve().unwrap();
+        client.join().unwrap();
+        drop(server);
+
+        // over TLS, those presenting a certificate of the user they start up as log in by it
+        let pem = |pem| tls::certificates_from_pem(pem).unwrap();
+        let key = |pem| tls::private_key_from_pem(pem).unwrap();
+        let ca = pem(include_str!("tls/testdata/ca.pem"));
+        let (chain, server_key) = (pem(include_str!("tls/testdata/server.pem")), key(include_str!("tls/testdata/server.key")));
+        let config = ServerConfig::with_client_cas(chain, server_key, ca.clone(), false).unwrap();
+        let alice = tls::ClientConfig {
+            roots: ca.clone(),
+            identity: Some((pem(include_str!("tls/testdata/alice.pem")), key(include_str!("tls/testdata/alice.key")))),
+        };
+        let db = Database::open(dir.path(), 16).unwrap();
+        let mut server = Server::bind_tls(db, "127.0.0.1:0", config.require_tls()).unwrap();
+        let (addr, stopped) = (server.local_addr(), server.stop_flag());
+        let client = thread::spawn(move || {
+            let (mut client, startup) = Client::connect_tls(addr, "alice", None, Some(&alice));
+            assert_eq!("RSSSSSSKZ", tags(&startup));
+            assert_eq!(&AUTH_OK.to_be_bytes(), &startup[0].1[..]);
+            assert_eq!(vec![Some(b"4".to_vec())], values(&client.query("SELECT count(*) FROM t")[1].1));
+            let error = text(&client.query("INSERT INTO t VALUES (9, 'x', true)")[0].1);
+            assert!(error.contains("C42501\0"), "{}", error);
+            client.send(b'X', |_| {});
+            let (_, messages) = Client::connect_tls(addr, "bob", None, Some(&alice));
+            let error = text(&messages[0].1);
+            assert!(error.contains("C28000\0") && error.contains("certificate authentication failed for user \"bob\""), "{}", error);
+            // or else by their passwords
+            let (_, startup) = Client::connect_tls(addr, "bob", Some("hunter2"), Some(&tls::ClientConfig { roots: ca, identity: None }));
+            assert_eq!(b'Z', startup.last().unwrap().0);
+            // while those not asking for SSL are turned away
+            let mut plain = TcpStream::connect(addr).unwrap();
+            let mut body = PROTOCOL_VERSION.to_be_bytes().to_vec();
+            for s in ["user", "bob", ""] {
+                cstr(&mut body, s);
+            }
+            plain.write_all(&((body.len() + 4) as i32).to_be_bytes()).unwrap();
+            plain.write_all(&body).unwrap();
+            let mut error = vec![];
+            plain.read_to_end(&mut error).unwrap();
+            assert!(text(&error).contains("C28000\0MSSL is required\0"), "{}", text(&error));
+            stopped.store(true, Ordering::Relaxed);
+        });
+        server.serve().unwrap();
+        client.join().unwrap();
+
+        // connections over the limits wait for others to close, for so long
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path(), 16).unwrap();
+        let limits = Limits {
+            max_connections: Some(3),
+            max_connections_per_user: Some(1),
+            queue_timeout: Some(Duration::from_millis(300)),
+            statement_timeout: Some(Duration::from_millis(50)),
+            send_timeout: None,
+        };
+        let mut server = Server::bind(db, "127.0.0.1:0").unwrap().with_limits(limits);
+        let (addr, stopped) = (server.local_addr(), server.stop_flag());
+        let client = thread::spawn(move || {
+            let (_a, startup) = Client::connect_as(addr, "a", None);
+            assert_eq!(b'Z', startup.last().unwrap().0);
+            let (mut b, startup) = Client::connect_as(addr, "b", None);
+            assert_eq!(b'Z', startup.last().unwrap().0);
+            let (_, messages) = Client::connect_as(addr, "a", None);
+            let error = text(&messages[0].1);
+            assert!(error.contains("C53300\0") && error.contains("too many connections for user \"a\""), "{}", error);
+            let (_c, startup) = Client::connect_as(addr, "c", None);
+            assert_eq!(b'Z', startup.last().unwrap().0);
+            let waiting = thread::spawn(move || Client::connect_as(addr, "d", None));
+            thread::sleep(Duration::from_millis(50));
+            b.send(b'X', |_| {});
+            let (mut d, startup) = waiting.join().unwrap();
+            assert_eq!(b'Z', startup.last().unwrap().0);
+            let (_, messages) = Client::connect_as(addr, "e", None);
+            let error = text(&messages[0].1);
+            assert!(error.contains("C53300\0") && error.contains("sorry, too many clients already"), "{}", error);
+
+            // statements time out for the others not to wait on them, whatever is set
+            let messages = d.query("SET statement_timeout = 3600000; SELECT count(*) FROM generate_series(1, 100000) a, generate_series(1, 100000) b");
+            assert!(text(&messages[1].1).contains("C57014\0"), "{}", text(&messages[1].1));
+            // and messages are no longer than the server accepts
+            d.stream.write_all(&[b'Q', 0x7f, 0xff, 0xff, 0xff]).unwrap();
+            let (tag, body) = d.receive();
+            assert!(tag == b'E' && text(&body).contains("SFATAL\0") && text(&body).contains("C08P01\0"), "{}", text(&body));
+            assert_eq!(0, d.stream.read(&mut [0]).unwrap());
+            stopped.store(true, Ordering::Relaxed);
+        });
+        server.serve().unwrap();
+        client.join().unwrap();
+
+        // a client leaving its answers unread holds up no other, and is disconnected after a while
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path(), 16).unwrap();
+        let limits = Limits { send_timeout: Some(Duration::from_millis(300)), ..Limits::default() };
+        let mut server = Server::bind(db, "127.0.0.1:0").unwrap().with_limits(limits);
+        let (addr, stopped) = (server.local_addr(), server.stop_flag());
+        let client = thread::spawn(move || {
+            let (mut stuck, _) = Client::connect(addr);
+            stuck.send(b'Q', |body| cstr(body, "SELECT n, n, n, n FROM generate_series(1, 500000) AS g (n)"));
+            thread::sleep(Duration::from_millis(100));
+            let started = Instant::now();
+            let (mut other, startup) = Client::connect(addr);
+            assert_eq!(b'Z', startup.last().unwrap().0);
+            assert_eq!("TDCZ", tags(&other.query("SELECT 1")));
+            assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+            thread::sleep(Duration::from_millis(500));
+            let mut answers = vec![];
+            let _ = stuck.stream.read_to_end(&mut answers);
+            assert!(answers.len() > 5 && !answers.ends_with(&[b'Z', 0, 0, 0, 5, b'I']), "{}", answers.len());
+            stopped.store(true, Ordering::Relaxed);
+        });
+        server.serve().unwrap();
+        client.join().unwrap();
+
+        // under consensus, answers wait for a majority of the cluster, here the database and its
+        // replica, to have the log before them
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path(), 16).unwrap();
+        let backup_dir = tempdir().unwrap();
+        db.with_engine(|bufmgr, _| recovery::backup(bufmgr, backup_dir.path())).unwrap();
+        let replica_dir = tempdir().unwrap();
+        Replica::create(backup_dir.path(), replica_dir.path()).unwrap();
+        let (listener, replica_listener) = (TcpListener::bind("127.0.0.1:0").unwrap(), TcpListener::bind("127.0.0.1:0").unwrap());
+        let (primary_addr, replica_addr) = (listener.local_addr().unwrap(), replica_listener.local_addr().unwrap());
+        let raft_dirs = (tempdir().unwrap(), tempdir().unwrap());
+        let consensus = Consensus::open(raft_dirs.0.path(), 1, listener, &[(2, replica_addr)], 1).unwrap();
+        let mut server = Server::bind(db, "127.0.0.1:0").unwrap().with_consensus(consensus);
+        let (addr, stopped) = (server.local_addr(), server.stop_flag());
+        let paused = Arc::new(AtomicBool::new(false));
+        let replica = {
+            let (paused, stopped) = (paused.clone(), stopped.clone());
+            let (raft_dir, replica_dir) = (raft_dirs.1.path().to_path_buf(), replica_dir.path().to_path_buf());
+            thread::spawn(move || {
+                let mut consensus = Consensus::open(raft_dir, 2, replica_listener, &[(1, primary_addr)], 1).unwrap();
+                let mut replica = Replica::open(replica_dir, 8).unwrap();
+                while !stopped.load(Ordering::Relaxed) {
+                    if !paused.load(Ordering::Relaxed) {
+                        consensus.step_replica(&mut replica).unwrap();
+                    }
+                    thread::sleep(IDLE);
+                }
+                replica.applied()
+            })
+        };
+        let client = thread::spawn(move || {
+            let (mut client, startup) = Client::connect(addr);
+            assert_eq!(b'Z', startup.last().unwrap().0);
+            assert_eq!("CZ", tags(&client.query("CREATE TABLE t (id INTEGER PRIMARY KEY)")));
+            paused.store(true, Ordering::Relaxed);
+            client.send(b'Q', |body| cstr(body, "INSERT INTO t VALUES (1)"));
+            client.stream.socket().set_read_timeout(Some(Duration::from_millis(300))).unwrap();
+            assert!(client.stream.socket().peek(&mut [0]).is_err());
+            client.stream.socket().set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+            paused.store(false, Ordering::Relaxed);
+            let messages = client.until_ready();
+            assert_eq!("INSERT 0 1\0", text(&messages[0].1));
+            stopped.store(true, Ordering::Relaxed);
+        });
+        server.serve().unwrap();
+        client.join().unwrap();
+        assert!(replica.join().unwrap() > 0);
+
+        // once another node is the primary, clients are turned away
+        let (dir, raft_dir) = (tempdir().unwrap(), tempdir().unwrap());
+        let (db, listener) = (Database::open(dir.path(), 16).unwrap(), TcpListener::bind("127.0.0.1:0").unwrap());
+        let consensus = Consensus::open(raft_dir.path(), 1, listener, &[(2, replica_addr)], 2).unwrap();
+        let mut server = Server::bind(db, "127.0.0.1:0").unwrap().with_consensus(consensus);
+        let (addr, stopped) = (server.local_addr(), server.stop_flag());
+        let client = thread::spawn(move || {
+            let (_, messages) = Client::connect(addr);
+            let error = text(&messages[0].1);
+            assert!(error.contains("SFATAL\0") && error.contains("C25006\0") && error.contains("no longer the primary"), "{}", error);
+            stopped.store(true, Ordering::Relaxed);
+        });
+        server.serve().unwrap();
+        client.join().unwrap();
+    }
+}
@@ -0,0 +1,535 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::database::Session;
+use crate::sql::{self, QueryResult};
+use crate::storage::{StorageBackend, StorageFile};
+
+// Deterministic simulation: storage which fails the way disks do and a scheduler interleaving the
+// statements of sessions, both driven by a seed, so that a failure found by trying many seeds is
+// found again by running the one it was found with. The engine does the same given the same
+// inputs, the only threads being those of parallel scans, which don't write.
+
+// Writes reach the disk in sectors of this many bytes, a write torn by a crash having some of
+// them only.
+pub const SECTOR_SIZE: usize = 512;
+
+// A xorshift generator, good enough for picking faults and turns.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // never zero, where xorshift would stay
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // In 0..n, which mustn't be empty.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    pub fn chance(&mut self, percent: u32) -> bool {
+        self.below(100) < percent as u64
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i as u64 + 1) as usize);
+        }
+    }
+}
+
+// The faults `SimStorage` injects, each the chance in percent of it happening.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Faults {
+    // that a read returns less than asked for
+    pub short_reads: u32,
+    // that a write which hasn't been synced at a crash is lost; those which aren't reach the disk
+    // in any order, like flushes reordered by a drive's cache
+    pub lost_writes: u32,
+    // that a write reaching the disk at a crash does so for some of its sectors only
+    pub torn_writes: u32,
+    // the operation, counted from 0, at which the storage crashes, it and every later one failing
+    // until `restart`
+    pub crash_at: Option<u64>,
+}
+
+// Storage in memory, which forgets what hasn't been synced when it crashes: writes to a file are
+// only durable once the file is synced, and files created, renamed or removed once their
+// directory is. A handle, clones of which are of the same storage.
+#[derive(Clone)]
+pub struct SimStorage(Rc<RefCell<State>>);
+
+struct State {
+    rng: Rng,
+    faults: Faults,
+    // operations so far, reads included
+    operations: u64,
+    crashed: bool,
+    dirs: BTreeSet<PathBuf>,
+    // the files as they're seen, and as they'd be after a crash
+    files: BTreeMap<PathBuf, Rc<RefCell<Inode>>>,
+    durable: BTreeMap<PathBuf, Rc<RefCell<Inode>>>,
+}
+
+#[derive(Default)]
+struct Inode {
+    data: Vec<u8>,
+    durable: Vec<u8>,
+    // since the last sync, in order
+    pending: Vec<Pending>,
+}
+
+enum Pending {
+    Write(u64, Vec<u8>),
+    SetLen(u64),
+}
+
+impl Pending {
+    fn apply(self, data: &mut Vec<u8>) {
+        match self {
+            Pending::Write(offset, bytes) => write(data, offset, &bytes),
+            Pending::SetLen(len) => data.resize(len as usize, 0),
+        }
+    }
+}
+
+impl SimStorage {
+    pub fn new(seed: u64, faults: Faults) -> Self {
+        let state = State {
+            rng: Rng::new(seed),
+            faults,
+            operations: 0,
+            crashed: false,
+            dirs: BTreeSet::new(),
+            files: BTreeMap::new(),
+            durable: BTreeMap::new(),
+        };
+        Self(Rc::new(RefCell::new(state)))
+    }
+
+    pub fn set_faults(&self, faults: Faults) {
+        self.0.borrow_mut().faults = faults;
+    }
+
+    pub fn operations(&self) -> u64 {
+        self.0.borrow().operations
+    }
+
+    pub fn crashed(&self) -> bool {
+        self.0.borrow().crashed
+    }
+
+    // Crashes now, failing every operation until `restart`.
+    pub fn crash(&self) {
+        self.0.borrow_mut().crashed = true;
+    }
+
+    // Comes back from a crash, or crashes and comes back, with what reached the disk: what was
+    // synced, and of what wasn't, whatever the faults let through.
+    pub fn restart(&self) {
+        let mut state = self.0.borrow_mut();
+        let state = &mut *state;
+        let Faults { lost_writes, torn_writes, .. } = state.faults;
+        // files created, renamed or removed in a directory which wasn't synced since, all or none
+        let dirs: BTreeSet<_> = state.files.keys().chain(state.durable.keys()).filter_map(|path| path.parent().map(Path::to_path_buf)).collect();
+        for dir in dirs {
+            let in_dir = |files: &BTreeMap<PathBuf, Rc<RefCell<Inode>>>| -> Vec<(PathBuf, *const RefCell<Inode>)> {
+                files.iter().filter(|(path, _)| path.parent() == Some(&dir)).map(|(path, inode)| (path.clone(), Rc::as_ptr(inode))).collect()
+            };
+            if in_dir(&state.files) != in_dir(&state.durable) && !state.rng.chance(lost_writes) {
+                sync_dir(&state.files, &mut state.durable, &dir);
+            }
+        }
+        // in the order of their paths, for the faults to be the same each run
+        let mut inodes: Vec<Rc<RefCell<Inode>>> = vec![];
+        for inode in state.durable.values() {
+            if !inodes.iter().any(|seen| Rc::ptr_eq(seen, inode)) {
+                inodes.push(inode.clone());
+            }
+        }
+        for inode in inodes {
+            let mut inode = inode.borrow_mut();
+            let mut pending: Vec<_> = std::mem::take(&mut inode.pending).into_iter().filter(|_| !state.rng.chance(lost_writes)).collect();
+            state.rng.shuffle(&mut pending);
+            for pending in pending {
+                match pending {
+                    Pending::Write(offset, data) if state.rng.chance(torn_writes) => {
+                        for (i, sector) in data.chunks(SECTOR_SIZE).enumerate() {
+                            if state.rng.chance(50) {
+                                write(&mut inode.durable, offset + (i * SECTOR_SIZE) as u64, sector);
+                            }
+                        }
+                    }
+                    pending => pending.apply(&mut inode.durable),
+                }
+            }
+            inode.data = inode.durable.clone();
+        }
+        state.files = state.durable.clone();
+        state.faults.crash_at = None;
+        state.crashed = false;
+    }
+
+    // Counts an operation, failing it if the storage has crashed, or does at it.
+    fn operation(state: &mut State) -> io::Result<()> {
+        if state.faults.crash_at == Some(state.operations) {
+            state.crashed = true;
+        }
+        state.operations += 1;
+        match state.crashed {
+            true => Err(io::Error::other("simulated crash")),
+            false => Ok(()),
+        }
+    }
+}
+
+fn sync_dir(files: &BTreeMap<PathBuf, Rc<RefCell<Inode>>>, durable: &mut BTreeMap<PathBuf, Rc<RefCell<Inode>>>, dir: &Path) {
+    durable.retain(|path, _| path.parent() != Some(dir));
+    durable.extend(files.iter().filter(|(path, _)| path.parent() == Some(dir)).map(|(path, inode)| (path.clone(), inode.clone())));
+}
+
+fn write(data: &mut Vec<u8>, offset: u64, bytes: &[u8]) {
+    let end = offset as usize + bytes.len();
+    if data.len() < end {
+        data.resize(end, 0);
+    }
+    data[offset as usize..end].copy_from_slice(bytes);
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path.display()))
+}
+
+impl StorageBackend for SimStorage {
+    fn open(&self, path: &Path, create: bool) -> io::Result<Box<dyn StorageFile>> {
+        let mut state = self.0.borrow_mut();
+        SimStorage::operation(&mut state)?;
+        let inode = match state.files.get(path) {
+            Some(inode) => inode.clone(),
+            None if create && path.parent().is_some_and(|dir| state.dirs.contains(dir)) => {
+                let inode = Rc::new(RefCell::new(Inode::default()));
+                state.files.insert(path.to_path_buf(), inode.clone());
+                inode
+            }
+            None => return Err(not_found(path)),
+        };
+        Ok(Box::new(SimFile { storage: self.clone(), inode }))
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        let mut state = self.0.borrow_mut();
+        SimStorage::operation(&mut state)?;
+        // directories are taken to be durable right away, those of a database being created once
+        state.dirs.extend(dir.ancestors().map(Path::to_path_buf));
+        Ok(())
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<(String, u64)>> {
+        let mut state = self.0.borrow_mut();
+        SimStorage::operation(&mut state)?;
+        if !state.dirs.contains(dir) {
+            return Err(not_found(dir));
+        }
+        let files = state.files.iter().filter(|(path, _)| path.parent() == Some(dir));
+        Ok(files.map(|(path, inode)| (path.file_name().unwrap().to_string_lossy().into_owned(), inode.borrow().data.len() as u64)).collect())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut state = self.0.borrow_mut();
+        SimStorage::operation(&mut state)?;
+        let inode = state.files.remove(from).ok_or_else(|| not_found(from))?;
+        state.files.insert(to.to_path_buf(), inode);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.0.borrow_mut();
+        SimStorage::operation(&mut state)?;
+        state.files.remove(path).map(drop).ok_or_else(|| not_found(path))
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        let mut state = self.0.borrow_mut();
+        SimStorage::operation(&mut state)?;
+        let State { files, durable, .. } = &mut *state;
+        sync_dir(files, durable, dir);
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.0.borrow().files.contains_key(path)
+    }
+}
+
+struct SimFile {
+    storage: SimStorage,
+    inode: Rc<RefCell<Inode>>,
+}
+
+impl StorageFile for SimFile {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.storage.0.borrow_mut();
+        SimStorage::operation(&mut state)?;
+        let inode = self.inode.borrow();
+        let available = inode.data.len().saturating_sub(offset as usize).min(buf.len());
+        let short_reads = state.faults.short_reads;
+        let len = match available > 1 && state.rng.chance(short_reads) {
+            true => 1 + state.rng.below(available as u64 - 1) as usize,
+            false => available,
+        };
+        if len > 0 {
+            buf[..len].copy_from_slice(&inode.data[offset as usize..offset as usize + len]);
+        }
+        Ok(len)
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        SimStorage::operation(&mut self.storage.0.borrow_mut())?;
+        let mut inode = self.inode.borrow_mut();
+        write(&mut inode.data, offset, data);
+        inode.pending.push(Pending::Write(offset, data.to_vec()));
+        Ok(())
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.inode.borrow().data.len() as u64)
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        SimStorage::operation(&mut self.storage.0.borrow_mut())?;
+        let mut inode = self.inode.borrow_mut();
+        inode.data.resize(len as usize, 0);
+        inode.pending.push(Pending::SetLen(len));
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        SimStorage::operation(&mut self.storage.0.borrow_mut())?;
+        let mut inode = self.inode.borrow_mut();
+        let inode = &mut *inode;
+        for pending in inode.pending.drain(..) {
+            pending.apply(&mut inode.durable);
+        }
+        Ok(())
+    }
+}
+
+// A statement run by `Scheduler`, with its result or what it failed with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub session: usize,
+    pub sql: String,
+    pub result: Result<Vec<QueryResult>, String>,
+}
+
+// Runs the statements queued for each of its sessions, in order for each session, taking turns
+// between them as the seed has it. A session's statements go on after one fails; which ones
+// did is in the trace of steps.
+pub struct Scheduler {
+    rng: Rng,
+    sessions: Vec<(Session, VecDeque<String>)>,
+    trace: Vec<Step>,
+}
+
+impl Scheduler {
+    pub fn new(seed: u64) -> Self {
+        Self { rng: Rng::new(seed), sessions: vec![], trace: vec![] }
+    }
+
+    // Adds a session to run `statements` in, returning its number in the trace.
+    pub fn add(&mut self, session: Session, statements: impl IntoIterator<Item = impl Into<String>>) -> usize {
+        self.sessions.push((session, statements.into_iter().map(Into::into).collect()));
+        self.sessions.len() - 1
+    }
+
+    pub fn session(&mut self, session: usize) -> &mut Session {
+        &mut self.sessions[session].0
+    }
+
+    // Runs the next statement of a session with some left, None once none has.
+    pub fn step(&mut self) -> Option<&Step> {
+        let ready: Vec<_> = (0..self.sessions.len()).filter(|&i| !self.sessions[i].1.is_empty()).collect();
+        if ready.is_empty() {
+            return None;
+        }
+        let session = ready[self.rng.below(ready.len() as u64) as usize];
+        let (runner, statements) = &mut self.sessions[session];
+        let sql = statements.pop_front().unwrap();
+        let result = runner.execute(&sql).map_err(|e: sql::Error| e.to_string());
+        self.trace.push(Step { session, sql, result });
+        self.trace.last()
+    }
+
+    // Runs every statement left.
+    pub fn run(&mut self) -> &[Step] {
+        while self.step().is_some() {}
+        &self.trace
+    }
+
+    pub fn trace(&self) -> &[Step] {
+        &self.trace
+    }
+
+    pub fn into_sessions(self) -> Vec<Session> {
+        self.sessions.into_iter().map(|(session, _)| session).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check;
+    use crate::database::Database;
+    use crate::storage;
+    use crate::tuple::Value;
+    use tempfile::tempdir;
+
+    // Sessions inserting pairs of rows in transactions, some rolled back, vacuuming now and then.
+    fn workload(seed: u64, db: &Database) -> Scheduler {
+        let mut scheduler = Scheduler::new(seed);
+        for session in 0..3 {
+            let mut statements = vec![];
+            for i in 0..8 {
+                let id = session * 100 + i * 2;
+                let end = match (seed + i) % 4 {
+                    0 => "ROLLBACK",
+                    _ => "COMMIT",
+                };
+                statements.push("BEGIN".to_string());
+                statements.push(format!("INSERT INTO t VALUES ({}, {}, '{}')", id, session, "x".repeat(200)));
+                statements.push(format!("INSERT INTO t VALUES ({}, {}, 'pair')", id + 1, session));
+                statements.push(end.to_string());
+                if i % 3 == 2 {
+                    statements.push("VACUUM".to_string());
+                }
+            }
+            scheduler.add(db.session(), statements);
+        }
+        scheduler
+    }
+
+    // Runs the workload, then what's left of it after a crash, checking that every transaction
+    // which committed is there, no other is, and the database isn't corrupt.
+    fn simulate(seed: u64, faults: Faults, crash_at: Option<u64>) -> (u64, Vec<Step>) {
+        let dir = tempdir().unwrap();
+        let storage = SimStorage::new(seed, faults.clone());
+        let db = Database::open_in(Rc::new(storage.clone()), dir.path(), 8).unwrap();
+        db.session().execute("CREATE TABLE t (id INTEGER PRIMARY KEY, session INTEGER, name TEXT); CREATE INDEX t_session ON t (session)").unwrap();
+        storage.set_faults(Faults { crash_at, ..faults });
+        let mut scheduler = workload(seed, &db);
+        let mut steps = 0;
+        while !storage.crashed() && scheduler.step().is_some() {
+            steps += 1;
+            if steps % 10 == 0 {
+                let _ = db.with_engine(|bufmgr, _| bufmgr.checkpoint());
+            }
+        }
+        let operations = storage.operations();
+        let trace = scheduler.trace().to_vec();
+        storage.crash();
+        drop(scheduler);
+        drop(db);
+        storage.restart();
+
+        let db = Database::open_in(Rc::new(storage.clone()), dir.path(), 8).unwrap();
+        let mut session = db.session();
+        let rows = match session.execute("SELECT id FROM t").unwrap().remove(0) {
+            QueryResult::Rows { rows, .. } => rows,
+            result => panic!("{:?}", result),
+        };
+        let ids: BTreeSet<_> = rows.iter().map(|row| match row[0] { Value::Int(id) => id, _ => panic!() }).collect();
+        // the pair of each COMMIT which succeeded is there, of one which didn't maybe, and of a
+        // rollback never
+        let mut pending: BTreeMap<usize, i64> = BTreeMap::new();
+        for step in &trace {
+            if let Some(id) = step.sql.strip_prefix("INSERT INTO t VALUES (").and_then(|sql| sql.split(',').next()) {
+                pending.entry(step.session).or_insert(id.parse().unwrap());
+            } else if step.sql == "COMMIT" || step.sql == "ROLLBACK" {
+                let Some(id) = pending.remove(&step.session) else { continue };
+                let found = (ids.contains(&id), ids.contains(&(id + 1)));
+                match (&*step.sql, step.result.is_ok()) {
+                    ("COMMIT", true) => assert_eq!((true, true), found, "seed {} lost {}: {:?}", seed, id, trace),
+                    ("ROLLBACK", _) => assert_eq!((false, false), found, "seed {} kept {}", seed, id),
+                    _ => assert_eq!(found.0, found.1, "seed {} tore {}", seed, id),
+                }
+            }
+        }
+        for (_, id) in pending {
+            assert!(!ids.contains(&id) && !ids.contains(&(id + 1)), "seed {} kept uncommitted {}", seed, id);
+        }
+        let report = db.with_engine(|bufmgr, catalog| check::check(bufmgr, catalog, None)).unwrap();
+        assert_eq!(0, report.errors(), "seed {}: {:?}", seed, report.problems);
+        (operations, trace)
+    }
+
+    #[test]
+    fn test() {
+        let mut rng = Rng::new(1);
+        let rolls: Vec<_> = (0..1000).map(|_| rng.below(6)).collect();
+        assert!((0..6).all(|n| rolls.contains(&n)));
+        assert_eq!(rolls, (0..1000).map({ let mut rng = Rng::new(1); move |_| rng.below(6) }).collect::<Vec<_>>());
+
+        // a crash keeps what was synced, the rest subject to the faults
+        let dir = Path::new("/sim");
+        let storage = SimStorage::new(7, Faults { lost_writes: 100, ..Faults::default() });
+        storage.create_dir_all(dir).unwrap();
+        let mut file = storage.open(&dir.join("a"), true).unwrap();
+        file.write_at(0, b"synced").unwrap();
+        file.sync().unwrap();
+        storage.sync_dir(dir).unwrap();
+        file.write_at(0, b"lost").unwrap();
+        storage.open(&dir.join("b"), true).unwrap();
+        assert_eq!(b"losted", &storage.read(&dir.join("a")).unwrap()[..]);
+        storage.crash();
+        assert!(file.write_at(0, b"more").is_err());
+        storage.restart();
+        assert_eq!(b"synced", &storage.read(&dir.join("a")).unwrap()[..]);
+        assert!(!storage.exists(&dir.join("b")));
+        // torn into sectors, some of which are written
+        storage.set_faults(Faults { torn_writes: 100, ..Faults::default() });
+        let mut file = storage.open(&dir.join("a"), false).unwrap();
+        file.write_at(0, &[1; SECTOR_SIZE * 16]).unwrap();
+        storage.restart();
+        let data = storage.read(&dir.join("a")).unwrap();
+        let written = data.chunks(SECTOR_SIZE).filter(|sector| sector.iter().all(|&b| b == 1)).count();
+        assert!(0 < written && written < 16, "{}", written);
+        // short reads, which `read_exact_at` reads past
+        storage.set_faults(Faults { short_reads: 100, ..Faults::default() });
+        let mut file = storage.open(&dir.join("a"), false).unwrap();
+        let mut buf = vec![0; data.len()];
+        assert!(file.read_at(0, &mut buf).unwrap() < buf.len());
+        storage::read_exact_at(&mut *file, 0, &mut buf).unwrap();
+        assert_eq!(data, buf);
+        // crashing at an operation
+        storage.set_faults(Faults { crash_at: Some(storage.operations() + 1), ..Faults::default() });
+        file.read_at(0, &mut buf).unwrap();
+        assert!(file.read_at(0, &mut buf).is_err());
+        assert!(storage.crashed());
+
+        // the same seed interleaves the same way, and ends the same
+        let faults = Faults { short_reads: 10, lost_writes: 50, ..Faults::default() };
+        let (operations, trace) = simulate(3, faults.clone(), None);
+        assert_eq!((operations, trace.clone()), simulate(3, faults.clone(), None));
+        assert!(trace.iter().any(|step| step.session == 2) && trace.windows(2).any(|steps| steps[0].session != steps[1].session));
+        assert!(trace.iter().all(|step| step.result.is_ok()), "{:?}", trace);
+        // crashing anywhere in the workload, even with the writes which weren't synced torn,
+        // committed transactions survive it
+        let torn = Faults { torn_writes: 50, ..faults };
+        for seed in 0..24 {
+            let mut rng = Rng::new(seed);
+            simulate(seed, torn.clone(), Some(rng.below(operations)));
+        }
+    }
+}
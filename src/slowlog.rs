@@ -0,0 +1,170 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::buffer::BufferStats;
+use crate::sql::{self, PreparedStatement, QueryResult};
+
+// Statements which take as long as a threshold or longer, logged with their plan, the rows they
+// returned or changed, and the pages they fetched, to a file or a callback. Sessions time each
+// statement they run, failed ones included, when the database has a log (see
+// `Database::set_slow_query_log`).
+
+// A statement which took as long as the threshold at least.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlowQuery {
+    pub session: u64,
+    pub sql: String,
+    pub duration: Duration,
+    // returned by a SELECT, or changed by an INSERT or COPY
+    pub rows: usize,
+    // as EXPLAIN renders it, empty if the statement has none
+    pub plan: Vec<String>,
+    // fetches served from the pool and read from disk while it ran
+    pub buffers: BufferStats,
+    pub error: Option<String>,
+}
+
+// One line for the statement, then its plan indented:
+//   duration: 1.2s session: 3 rows: 10 buffers: hits=120 misses=8 statement: SELECT ...
+impl fmt::Display for SlowQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "duration: {:?} session: {} rows: {} buffers: hits={} misses={}",
+            self.duration, self.session, self.rows, self.buffers.hits, self.buffers.misses
+        )?;
+        if let Some(error) = &self.error {
+            write!(f, " error: {}", error)?;
+        }
+        // a statement spanning lines is kept to one
+        write!(f, " statement: {}", self.sql.split_whitespace().collect::<Vec<_>>().join(" "))?;
+        for line in &self.plan {
+            write!(f, "\n  {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct SlowQueryLog {
+    threshold: Duration,
+    sink: Sink,
+}
+
+enum Sink {
+    File(File),
+    Callback(Box<dyn FnMut(&SlowQuery)>),
+}
+
+impl SlowQueryLog {
+    // Appends the statements to the file at `path`, created if there's none, each followed by
+    // an empty line.
+    pub fn to_file(threshold: Duration, path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { threshold, sink: Sink::File(file) })
+    }
+
+    pub fn with_callback(threshold: Duration, f: impl FnMut(&SlowQuery) + 'static) -> Self {
+        Self { threshold, sink: Sink::Callback(Box::new(f)) }
+    }
+
+    pub fn threshold(&self) -> Duration {
+        self.threshold
+    }
+
+    // Logs a statement which ran for `duration` if it's slow, `statement` being None if it failed
+    // before it was prepared.
+    pub fn log(
+        &mut self,
+        session: u64,
+        sql: &str,
+        statement: Option<&PreparedStatement>,
+        result: &Result<QueryResult, sql::Error>,
+        duration: Duration,
+        buffers: BufferStats,
+    ) {
+        if duration < self.threshold {
+            return;
+        }
+        let query = SlowQuery {
+            session,
+            sql: sql.to_string(),
+            duration,
+            rows: result.as_ref().map_or(0, QueryResult::num_rows),
+            plan: statement.and_then(PreparedStatement::plan).unwrap_or_default(),
+            buffers,
+            error: result.as_ref().err().map(|err| err.to_string()),
+        };
+        match &mut self.sink {
+            Sink::File(file) => {
+                // losing an entry of the log isn't worth failing the statement for
+                let _ = writeln!(file, "{}\n", query);
+            }
+            Sink::Callback(f) => f(&query),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::rc::Rc;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path(), 16).unwrap();
+        let logged = Rc::new(RefCell::new(vec![]));
+        let sink = logged.clone();
+        db.set_slow_query_log(Some(SlowQueryLog::with_callback(Duration::ZERO, move |query| sink.borrow_mut().push(query.clone()))));
+
+        let mut session = db.session();
+        session.execute("CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT);\n  INSERT INTO t VALUES (1, 'a'), (2, 'b')").unwrap();
+        session.execute("SELECT name\n  FROM t WHERE id > 0").unwrap();
+        assert!(session.execute("SELECT * FROM nothing").is_err());
+        let statement = session.prepare("SELECT id FROM t WHERE id = ?").unwrap();
+        session.execute_prepared(&statement, &[crate::tuple::Value::Int(2)]).unwrap();
+        {
+            let logged = logged.borrow();
+            let sqls: Vec<&str> = logged.iter().map(|query| query.sql.as_str()).collect();
+            assert_eq!(
+                vec![
+                    "CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT)",
+                    "INSERT INTO t VALUES (1, 'a'), (2, 'b')",
+                    "SELECT name\n  FROM t WHERE id > 0",
+                    "SELECT * FROM nothing",
+                    "SELECT id FROM t WHERE id = ?",
+                ],
+                sqls
+            );
+            assert!(logged.iter().all(|query| query.session == session.id()));
+            assert_eq!(vec![0, 2, 2, 0, 1], logged.iter().map(|query| query.rows).collect::<Vec<_>>());
+            assert!(logged[0].plan.is_empty() && logged[1].plan.is_empty());
+            assert!(!logged[2].plan.is_empty());
+            assert!(logged[2].buffers.hits + logged[2].buffers.misses > 0);
+            assert_eq!(Some("table not found: nothing"), logged[3].error.as_deref());
+            let line = logged[2].to_string();
+            assert!(line.contains(" rows: 2 ") && line.contains(" statement: SELECT name FROM t WHERE id > 0\n  "), "{}", line);
+        }
+
+        // nothing is as slow as an hour
+        db.set_slow_query_log(Some(SlowQueryLog::with_callback(Duration::from_secs(3600), |_| panic!("logged"))));
+        session.execute("SELECT * FROM t").unwrap();
+
+        let path = dir.path().join("slow.log");
+        db.set_slow_query_log(Some(SlowQueryLog::to_file(Duration::ZERO, &path).unwrap()));
+        session.execute("SELECT * FROM t; SELECT 1").unwrap();
+        db.set_slow_query_log(None);
+        session.execute("SELECT 2").unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert_eq!(2, text.matches("duration: ").count());
+        assert!(text.contains("statement: SELECT * FROM t\n"), "{}", text);
+        assert!(!text.contains("SELECT 2"));
+    }
+}
@@ -0,0 +1,2887 @@
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+#[cfg(feature = "parquet")]
+use std::io::{Read, Seek};
+use std::rc::Rc;
+
+use crate::array;
+#[cfg(feature = "arrow")]
+use crate::arrow::{self, RecordBatch, RecordBatchBuilder};
+use crate::btree;
+use crate::buffer::{BufferPoolManager, HeldStatement, MemoryReservation};
+use crate::catalog::{self, Catalog, Column, Privilege, TableInfo, UserInfo, ViewInfo};
+use crate::check;
+use crate::csv::{self, CsvOptions};
+use crate::datetime;
+use crate::json;
+use crate::lock;
+use crate::optimizer::PlannerSettings;
+#[cfg(feature = "parquet")]
+use crate::parquet;
+use crate::partition::PartitionBound;
+use crate::planner::{Planner, SelectPlan};
+use crate::query;
+use crate::query::explain::explain;
+use crate::query::{instrument, reset_stats, BoxExecutor};
+use crate::query::expr::{self, BinaryOp, Expr, OuterRow, Params};
+use crate::scram::Credentials;
+use crate::stats::TableStats;
+use crate::table::{self, IndexKind, Ttl};
+use crate::trace;
+use crate::trigger::{Action, Event, RowImages, Timing, TriggerInfo};
+use crate::tuple::{self, DataType, Tuple, Value};
+
+pub mod ast;
+mod lexer;
+mod parser;
+
+pub use parser::{constants, normalize, parse, parse_with_text, quote_ident};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("syntax error: {0}")]
+    Syntax(String),
+    #[error("table not found: {0}")]
+    UnknownTable(String),
+    #[error("column not found: {0}")]
+    UnknownColumn(String),
+    #[error("ambiguous column: {0}")]
+    AmbiguousColumn(String),
+    #[error("{0}")]
+    Invalid(String),
+    #[error("permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("current transaction is aborted, commands ignored until end of transaction block")]
+    TransactionAborted,
+    #[error(transparent)]
+    Catalog(#[from] catalog::Error),
+    #[error(transparent)]
+    Table(#[from] table::Error),
+    #[error(transparent)]
+    Query(#[from] query::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Check(#[from] check::Error),
+    #[cfg(feature = "parquet")]
+    #[error(transparent)]
+    Parquet(#[from] parquet::Error),
+    #[cfg(feature = "arrow")]
+    #[error(transparent)]
+    Arrow(#[from] arrow::Error),
+}
+
+// Percentage of the pages COPY fills, leaving room for rows inserted among those loaded.
+const COPY_FILL_FACTOR: usize = 90;
+// Rows CREATE INDEX CONCURRENTLY reads into the index a transaction.
+const INDEX_BUILD_ROWS: usize = 1024;
+// How deep the triggers run by the INSERTs of triggers may be, so that a trigger which runs itself
+// fails the statement.
+const MAX_TRIGGER_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementClass {
+    Ddl,
+    Dml,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryResult {
+    Rows { columns: Vec<String>, rows: Vec<Tuple> },
+    RowsAffected(usize),
+    Done,
+    // of a COMMIT of a transaction block which failed, and was rolled back instead
+    RolledBack,
+}
+
+impl QueryResult {
+    // Rows returned or changed.
+    pub fn num_rows(&self) -> usize {
+        match self {
+            QueryResult::Rows { rows, .. } => rows.len(),
+            QueryResult::RowsAffected(n) => *n,
+            QueryResult::Done | QueryResult::RolledBack => 0,
+        }
+    }
+}
+
+// Parses and runs every statement in `sql`, returning one result per statement.
+pub fn execute(bufmgr: &mut BufferPoolManager, catalog: &mut Catalog, sql: &str) -> Result<Vec<QueryResult>, Error> {
+    let mut results = vec![];
+    for statement in parse(sql)? {
+        results.push(execute_statement(bufmgr, catalog, &statement)?);
+    }
+    Ok(results)
+}
+
+pub fn execute_statement(
+    bufmgr: &mut BufferPoolManager,
+    catalog: &mut Catalog,
+    statement: &ast::Statement,
+) -> Result<QueryResult, Error> {
+    prepare_statement(catalog, statement)?.execute(bufmgr, catalog, &[])
+}
+
+// Parses and plans a single statement with `?` or `$n` placeholders, to be executed any number
+// of times with different parameters.
+pub fn prepare(catalog: &Catalog, sql: &str) -> Result<PreparedStatement, Error> {
+    prepare_with(catalog, sql, PlannerSettings::default())
+}
+
+// Prepares with the ways of reading and joining tables limited by `settings`.
+pub fn prepare_with(catalog: &Catalog, sql: &str, settings: PlannerSettings) -> Result<PreparedStatement, Error> {
+    let mut statements = parse_with_text(sql)?;
+    if statements.len() != 1 {
+        return Err(Error::Invalid("exactly one statement can be prepared at a time".to_string()));
+    }
+    let (statement, text) = statements.pop().unwrap();
+    Ok(prepare_statement_with(catalog, &statement, settings)?.with_sql(text))
+}
+
+pub fn prepare_statement(catalog: &Catalog, statement: &ast::Statement) -> Result<PreparedStatement, Error> {
+    prepare_statement_with(catalog, statement, PlannerSettings::default())
+}
+
+pub fn prepare_statement_with(catalog: &Catalog, statement: &ast::Statement, settings: PlannerSettings) -> Result<PreparedStatement, Error> {
+    let mut planner = Planner::with_settings(catalog, settings);
+    let prepared = match statement {
+        ast::Statement::Select(query) => Prepared::Select(traced(planner.plan_query(query)?)),
+        ast::Statement::Explain { query, analyze } => {
+            let mut plan = planner.plan_query(query)?;
+            if *analyze {
+                plan.plan = instrument(plan.plan);
+            }
+            Prepared::Explain { plan, analyze: *analyze }
+        }
+        ast::Statement::Insert(insert) => prepare_insert(&mut planner, catalog, insert)?,
+        ast::Statement::Update(update) => prepare_modify(&mut planner, catalog, &update.table, Some(&update.assignments), &update.condition, &update.returning)?,
+        ast::Statement::Delete(delete) => prepare_modify(&mut planner, catalog, &delete.table, None, &delete.condition, &delete.returning)?,
+        ast::Statement::CopyTo(copy) => Prepared::CopyTo {
+            plan: traced(planner.plan_query(&copy.query)?),
+            path: copy.path.clone(),
+            format: copy.format.clone(),
+        },
+        ast::Statement::Check(table) => {
+            if let Some(name) = table.as_ref().filter(|name| catalog.table(name).is_none()) {
+                return Err(Error::UnknownTable(name.clone()));
+            }
+            Prepared::Check(table.clone())
+        }
+        statement => Prepared::Other(statement.clone()),
+    };
+    // the tables and views it reads, those read through views needing no privilege of their own
+    let mut privileges: Vec<(String, Privilege)> = planner.tables().iter().map(|table| (table.clone(), Privilege::Select)).collect();
+    match &prepared {
+        Prepared::Insert { table, on_conflict, .. } => {
+            privileges.push((table.clone(), Privilege::Insert));
+            if let Some(Conflict::Update { .. }) = on_conflict {
+                privileges.push((table.clone(), Privilege::Update));
+            }
+        }
+        Prepared::Modify { table, condition, assignments, returning, .. } => {
+            // the rows are read, which takes SELECT, only if they're filtered, returned or set from
+            let mut columns = vec![];
+            for (_, expr) in assignments.iter().flatten() {
+                expr.columns(&mut columns);
+            }
+            if condition.is_none() && returning.is_none() && columns.is_empty() {
+                privileges.retain(|(name, _)| name != table);
+            }
+            privileges.push((table.clone(), if assignments.is_some() { Privilege::Update } else { Privilege::Delete }));
+        }
+        Prepared::Other(ast::Statement::CopyFrom(ast::CopyFrom { table, .. })) => privileges.push((table.clone(), Privilege::Insert)),
+        // a table is locked in share mode to read it, in the others to write it
+        Prepared::Other(ast::Statement::LockTable { table, mode }) => {
+            privileges.push((table.clone(), if *mode == ast::LockMode::Shared { Privilege::Select } else { Privilege::Insert }))
+        }
+        _ => {}
+    }
+    let (params, param_types) = planner.into_params();
+    Ok(PreparedStatement {
+        prepared,
+        params,
+        param_types,
+        privileges,
+        sql: String::new(),
+    })
+}
+
+// The RETURNING list of an INSERT, UPDATE or DELETE, evaluated on each row it writes or deletes.
+struct Returning {
+    exprs: Vec<Expr>,
+    columns: Vec<String>,
+    types: Vec<Option<DataType>>,
+}
+
+// What an INSERT does with a row whose primary key is taken.
+enum Conflict {
+    Nothing,
+    // the columns set to the values, if the condition holds, of the row there, with `excluded`
+    // set to the row proposed for it (see `Planner::bind_conflict`)
+    Update { assignments: Vec<(usize, Expr)>, condition: Option<Expr>, excluded: OuterRow },
+}
+
+fn prepare_insert(planner: &mut Planner<'_>, catalog: &Catalog, insert: &ast::Insert) -> Result<Prepared, Error> {
+    let info = catalog
+        .table(&insert.table)
+        .ok_or_else(|| Error::UnknownTable(insert.table.clone()))?;
+    // position in the table of each value of the VALUES rows
+    let targets: Vec<usize> = match &insert.columns {
+        Some(names) => names
+            .iter()
+            .map(|name| info.column_index(name).ok_or_else(|| Error::UnknownColumn(name.clone())))
+            .collect::<Result<_, _>>()?,
+        None => (0..info.columns.len()).collect(),
+    };
+    let mut rows = vec![];
+    for values in &insert.rows {
+        if values.len() != targets.len() {
+            return Err(Error::Invalid("INSERT has a different number of values than columns".to_string()));
+        }
+        let row = values
+            .iter()
+            .zip(&targets)
+            .map(|(expr, &target)| planner.bind_constant(expr, Some(info.columns[target].data_type)))
+            .collect::<Result<_, _>>()?;
+        rows.push(row);
+    }
+    let on_conflict = match &insert.on_conflict {
+        Some(on_conflict) => Some(prepare_conflict(planner, &insert.table, info, on_conflict)?),
+        None => None,
+    };
+    Ok(Prepared::Insert {
+        table: insert.table.clone(),
+        num_columns: info.columns.len(),
+        targets,
+        rows,
+        on_conflict,
+        returning: prepare_returning(planner, &insert.table, info, insert.returning.as_deref())?,
+    })
+}
+
+// An UPDATE of `table` with the assignments, or a DELETE without them.
+fn prepare_modify(
+    planner: &mut Planner<'_>,
+    catalog: &Catalog,
+    table: &str,
+    assignments: Option<&[(String, ast::Expr)]>,
+    condition: &Option<ast::Expr>,
+    returning: &Option<Vec<ast::SelectItem>>,
+) -> Result<Prepared, Error> {
+    let info = catalog.table(table).ok_or_else(|| Error::UnknownTable(table.to_string()))?;
+    // the rows are found as a SELECT of them would find them
+    let select = ast::Select {
+        hints: vec![],
+        distinct: false,
+        projection: vec![ast::SelectItem::Wildcard],
+        from: vec![ast::TableRef::Table { name: table.to_string(), alias: None }],
+        selection: condition.clone(),
+        group_by: vec![],
+        having: None,
+    };
+    let plan = planner.plan_select(&select)?;
+    let mut bound: Option<Vec<(usize, Expr)>> = None;
+    for (name, expr) in assignments.into_iter().flatten() {
+        let i = info.column_index(name).ok_or_else(|| Error::UnknownColumn(name.clone()))?;
+        if i < info.table.num_key_elems {
+            return Err(Error::Invalid(format!("UPDATE cannot change primary key column {}", name)));
+        }
+        let bound = bound.get_or_insert_default();
+        if bound.iter().any(|&(j, _)| j == i) {
+            return Err(Error::Invalid(format!("column {} is set more than once", name)));
+        }
+        bound.push((i, planner.bind_row(table, &info.columns, expr, Some(info.columns[i].data_type))?));
+    }
+    let condition = match condition {
+        Some(condition) => Some(planner.bind_row(table, &info.columns, condition, Some(DataType::Boolean))?),
+        None => None,
+    };
+    Ok(Prepared::Modify {
+        table: table.to_string(),
+        num_columns: info.columns.len(),
+        plan,
+        condition,
+        assignments: bound,
+        returning: prepare_returning(planner, table, info, returning.as_deref())?,
+    })
+}
+
+fn prepare_returning(planner: &mut Planner<'_>, table: &str, info: &TableInfo, items: Option<&[ast::SelectItem]>) -> Result<Option<Returning>, Error> {
+    let Some(items) = items else {
+        return Ok(None);
+    };
+    let mut returning = Returning { exprs: vec![], columns: vec![], types: vec![] };
+    for (expr, column, data_type) in planner.bind_returning(table, &info.columns, items)? {
+        returning.exprs.push(expr);
+        returning.columns.push(column);
+        returning.types.push(data_type);
+    }
+    Ok(Some(returning))
+}
+
+fn prepare_conflict(planner: &mut Planner<'_>, table: &str, info: &TableInfo, on_conflict: &ast::OnConflict) -> Result<Conflict, Error> {
+    let key = &info.columns[..info.table.num_key_elems];
+    if let Some(target) = &on_conflict.target {
+        let is_key = target.len() == key.len() && key.iter().all(|column| target.contains(&column.name));
+        if !is_key {
+            return Err(Error::Invalid("the ON CONFLICT columns must be those of the primary key".to_string()));
+        }
+    }
+    let (assignments, condition) = match &on_conflict.action {
+        ast::ConflictAction::Nothing => return Ok(Conflict::Nothing),
+        ast::ConflictAction::Update { assignments, condition } => (assignments, condition),
+    };
+    if on_conflict.target.is_none() {
+        return Err(Error::Invalid("ON CONFLICT DO UPDATE requires the columns of the primary key".to_string()));
+    }
+    let excluded = OuterRow::default();
+    let mut bound: Vec<(usize, Expr)> = vec![];
+    for (name, expr) in assignments {
+        let i = info.column_index(name).ok_or_else(|| Error::UnknownColumn(name.clone()))?;
+        if i < key.len() {
+            return Err(Error::Invalid(format!("ON CONFLICT DO UPDATE cannot change primary key column {}", name)));
+        }
+        if bound.iter().any(|&(j, _)| j == i) {
+            return Err(Error::Invalid(format!("column {} is set more than once", name)));
+        }
+        bound.push((i, planner.bind_conflict(table, &info.columns, &excluded, expr, Some(info.columns[i].data_type))?));
+    }
+    let condition = match condition {
+        Some(condition) => Some(planner.bind_conflict(table, &info.columns, &excluded, condition, Some(DataType::Boolean))?),
+        None => None,
+    };
+    Ok(Conflict::Update { assignments: bound, condition, excluded })
+}
+
+// Fails unless `user` has `privilege` on the table or view, as superusers have on every one.
+// Virtual tables may be read by anyone.
+pub fn authorize(catalog: &Catalog, user: &str, table: &str, privilege: Privilege) -> Result<(), Error> {
+    let info = catalog.user(user).ok_or_else(|| catalog::Error::UnknownUser(user.to_string()))?;
+    if info.superuser || (privilege == Privilege::Select && catalog.virtual_table(table).is_some()) || catalog.privileges(table, user).contains(&privilege) {
+        return Ok(());
+    }
+    Err(Error::PermissionDenied(format!("{} on {}", privilege, table)))
+}
+
+// The plan with its operators traced, in builds with tracing.
+fn traced(plan: SelectPlan) -> SelectPlan {
+    #[cfg(feature = "tracing")]
+    let plan = SelectPlan { plan: query::trace(plan.plan), ..plan };
+    plan
+}
+
+// A parsed and planned statement.
+//
+// The plan is fixed when the statement is prepared, so indexes created afterwards are
+// maintained by its INSERTs but not used by its SELECTs.
+pub struct PreparedStatement {
+    prepared: Prepared,
+    params: Params,
+    param_types: Vec<Option<DataType>>,
+    // what it needs of the tables and views it reads and writes
+    privileges: Vec<(String, Privilege)>,
+    // the text it was prepared from, empty if it was from a statement parsed already
+    sql: String,
+}
+
+enum Prepared {
+    Select(SelectPlan),
+    // with `analyze`, the plan is instrumented and run before it's rendered
+    Explain { plan: SelectPlan, analyze: bool },
+    Insert {
+        table: String,
+        num_columns: usize,
+        targets: Vec<usize>,
+        rows: Vec<Vec<Expr>>,
+        on_conflict: Option<Conflict>,
+        returning: Option<Returning>,
+    },
+    // UPDATE with `assignments`, or DELETE without, of the rows `plan` finds, each of them again
+    // if it has changed since, as long as it still satisfies `condition`
+    Modify {
+        table: String,
+        num_columns: usize,
+        plan: SelectPlan,
+        condition: Option<Expr>,
+        assignments: Option<Vec<(usize, Expr)>>,
+        returning: Option<Returning>,
+    },
+    CopyTo { plan: SelectPlan, path: String, format: ast::CopyFormat },
+    // CHECKDB of the table, or of the whole database
+    Check(Option<String>),
+    // DDL, which has nothing to plan
+    Other(ast::Statement),
+}
+
+impl PreparedStatement {
+    // Records `sql` as the text the statement was parsed from.
+    pub fn with_sql(mut self, sql: &str) -> Self {
+        self.sql = sql.to_string();
+        self
+    }
+
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    pub fn num_params(&self) -> usize {
+        self.param_types.len()
+    }
+
+    // Type expected for each parameter, None where any type goes.
+    pub fn param_types(&self) -> &[Option<DataType>] {
+        &self.param_types
+    }
+
+    // Fails unless `user` may run the statement: superusers may run anything, other users what
+    // they've been granted the privileges for on its tables, transaction control, SET and SHOW,
+    // ALTER USER of themselves, CANCEL, which sessions only let them run on their own, and the
+    // statements of cursors, whose SELECTs are authorized when they're declared.
+    pub fn authorize(&self, catalog: &Catalog, user: &str) -> Result<(), Error> {
+        let info = catalog.user(user).ok_or_else(|| catalog::Error::UnknownUser(user.to_string()))?;
+        if info.superuser {
+            return Ok(());
+        }
+        let superuser_only = match &self.prepared {
+            Prepared::Check(_) => true,
+            Prepared::Other(ast::Statement::AlterUser { name, .. }) => name != user,
+            Prepared::Other(statement) => !matches!(
+                statement,
+                ast::Statement::Begin { .. }
+                    | ast::Statement::Commit
+                    | ast::Statement::Rollback
+                    | ast::Statement::LockTable { .. }
+                    | ast::Statement::CopyFrom(_)
+                    | ast::Statement::Set { .. }
+                    | ast::Statement::Show(_)
+                    | ast::Statement::Cancel { .. }
+                    | ast::Statement::DeclareCursor { .. }
+                    | ast::Statement::Fetch { .. }
+                    | ast::Statement::CloseCursor(_)
+            ),
+            _ => false,
+        };
+        if superuser_only {
+            return Err(Error::PermissionDenied(format!("only superusers may run {}", self.command())));
+        }
+        for (table, privilege) in &self.privileges {
+            authorize(catalog, user, table, *privilege)?;
+        }
+        Ok(())
+    }
+
+    // The SQL command, e.g. SELECT or CREATE TABLE.
+    pub fn command(&self) -> &'static str {
+        match &self.prepared {
+            Prepared::Select(_) => "SELECT",
+            Prepared::Explain { .. } => "EXPLAIN",
+            Prepared::Insert { .. } => "INSERT",
+            Prepared::Modify { assignments: Some(_), .. } => "UPDATE",
+            Prepared::Modify { assignments: None, .. } => "DELETE",
+            Prepared::CopyTo { .. } => "COPY",
+            Prepared::Check(_) => "CHECKDB",
+            Prepared::Other(statement) => match statement {
+                ast::Statement::CreateTable(_) => "CREATE TABLE",
+                ast::Statement::CreateIndex(_) => "CREATE INDEX",
+                ast::Statement::CreateView(_) => "CREATE VIEW",
+                ast::Statement::CreateTrigger(_) => "CREATE TRIGGER",
+                ast::Statement::Drop { kind: ast::ObjectKind::Table, .. } => "DROP TABLE",
+                ast::Statement::Drop { kind: ast::ObjectKind::Index, .. } => "DROP INDEX",
+                ast::Statement::Drop { kind: ast::ObjectKind::View, .. } => "DROP VIEW",
+                ast::Statement::AlterTable { .. } => "ALTER TABLE",
+                ast::Statement::Analyze(_) => "ANALYZE",
+                ast::Statement::Vacuum { .. } => "VACUUM",
+                ast::Statement::Reindex { .. } => "REINDEX",
+                ast::Statement::Begin { .. } => "BEGIN",
+                ast::Statement::Commit => "COMMIT",
+                ast::Statement::Rollback => "ROLLBACK",
+                ast::Statement::LockTable { .. } => "LOCK TABLE",
+                ast::Statement::CopyFrom(_) => "COPY",
+                ast::Statement::Set { .. } => "SET",
+                ast::Statement::Show(_) => "SHOW",
+                ast::Statement::CreateUser { .. } => "CREATE USER",
+                ast::Statement::AlterUser { .. } => "ALTER USER",
+                ast::Statement::Grant(_) => "GRANT",
+                ast::Statement::Revoke(_) => "REVOKE",
+                ast::Statement::Cancel { terminate: false, .. } => "CANCEL QUERY",
+                ast::Statement::Cancel { terminate: true, .. } => "CANCEL SESSION",
+                ast::Statement::DeclareCursor { .. } => "DECLARE CURSOR",
+                ast::Statement::Fetch { .. } => "FETCH",
+                ast::Statement::CloseCursor(_) => "CLOSE CURSOR",
+                ast::Statement::Select(_)
+                | ast::Statement::Explain { .. }
+                | ast::Statement::Insert(_)
+                | ast::Statement::Update(_)
+                | ast::Statement::Delete(_)
+                | ast::Statement::CopyTo(_)
+                | ast::Statement::Check(_) => unreachable!("planned when prepared"),
+            },
+        }
+    }
+
+    // Whether it defines or changes the schema, the users or their privileges, or changes rows,
+    // None if neither, as audit logs tell statements apart.
+    pub fn class(&self) -> Option<StatementClass> {
+        match &self.prepared {
+            Prepared::Insert { .. } | Prepared::Modify { .. } | Prepared::Other(ast::Statement::CopyFrom(_)) => Some(StatementClass::Dml),
+            Prepared::Other(
+                ast::Statement::CreateTable(_)
+                | ast::Statement::CreateIndex(_)
+                | ast::Statement::CreateView(_)
+                | ast::Statement::CreateTrigger(_)
+                | ast::Statement::Drop { .. }
+                | ast::Statement::AlterTable { .. }
+                | ast::Statement::CreateUser { .. }
+                | ast::Statement::AlterUser { .. }
+                | ast::Statement::Grant(_)
+                | ast::Statement::Revoke(_),
+            ) => Some(StatementClass::Ddl),
+            _ => None,
+        }
+    }
+
+    // Whether the plan may be kept for any session to run again with the catalog as it was
+    // prepared with (see `PlanCache`).
+    pub fn is_cacheable(&self) -> bool {
+        matches!(self.prepared, Prepared::Select(_) | Prepared::Insert { .. } | Prepared::Modify { .. } | Prepared::CopyTo { .. })
+    }
+
+    // The tables and views the statement is about: the one it defines, writes or grants
+    // privileges on first, then those it reads.
+    pub fn tables(&self) -> Vec<&str> {
+        let mut tables: Vec<&str> = match &self.prepared {
+            Prepared::Other(ast::Statement::CreateTable(ast::CreateTable { name, .. }) | ast::Statement::CreateView(ast::CreateView { name, .. })) => vec![name],
+            Prepared::Other(ast::Statement::Drop { kind: ast::ObjectKind::Table | ast::ObjectKind::View, name, .. }) => vec![name],
+            Prepared::Other(
+                ast::Statement::CreateIndex(ast::CreateIndex { table, .. })
+                | ast::Statement::AlterTable { table, .. }
+                | ast::Statement::CreateTrigger(ast::CreateTrigger { table, .. })
+                | ast::Statement::Grant(ast::Grant { table, .. })
+                | ast::Statement::Revoke(ast::Grant { table, .. }),
+            ) => {
+                vec![table]
+            }
+            _ => vec![],
+        };
+        let (written, read): (Vec<_>, Vec<_>) = self.privileges.iter().partition(|(_, privilege)| *privilege != Privilege::Select);
+        for (table, _) in written.into_iter().chain(read) {
+            if !tables.contains(&table.as_str()) {
+                tables.push(table);
+            }
+        }
+        tables
+    }
+
+    // Name and type, if known before it runs, of each column of the rows it returns, None if it
+    // returns no rows.
+    pub fn result_columns(&self) -> Option<Vec<(String, Option<DataType>)>> {
+        match &self.prepared {
+            Prepared::Select(plan) => Some(plan.columns.iter().cloned().zip(plan.types.iter().copied()).collect()),
+            Prepared::Insert { returning: Some(returning), .. } | Prepared::Modify { returning: Some(returning), .. } => Some(returning.columns.iter().cloned().zip(returning.types.iter().copied()).collect()),
+            Prepared::Explain { .. } => Some(vec![("QUERY PLAN".to_string(), Some(DataType::Text))]),
+            Prepared::Check(_) => {
+                let types = [DataType::Text, DataType::Text, DataType::Integer, DataType::Text];
+                Some(check::REPORT_COLUMNS.iter().map(|name| name.to_string()).zip(types.map(Some)).collect())
+            }
+            Prepared::Other(ast::Statement::Show(Some(name))) => Some(vec![(name.clone(), Some(DataType::Text))]),
+            Prepared::Other(ast::Statement::Show(None)) => Some(vec![("name".to_string(), Some(DataType::Text)), ("setting".to_string(), Some(DataType::Text))]),
+            _ => None,
+        }
+    }
+
+    // The statement if it's a SET or SHOW, which sessions run themselves as it's about them, or
+    // a CANCEL, about the sessions of the database, or DECLARE, FETCH or CLOSE of a cursor, which
+    // they hold.
+    pub fn session_statement(&self) -> Option<&ast::Statement> {
+        match &self.prepared {
+            Prepared::Other(
+                statement @ (ast::Statement::Set { .. }
+                | ast::Statement::Show(_)
+                | ast::Statement::Cancel { .. }
+                | ast::Statement::DeclareCursor { .. }
+                | ast::Statement::Fetch { .. }
+                | ast::Statement::CloseCursor(_)),
+            ) => Some(statement),
+            _ => None,
+        }
+    }
+
+    // Lines of the plan, as EXPLAIN renders it, if the statement has one.
+    pub fn plan(&self) -> Option<Vec<String>> {
+        match &self.prepared {
+            Prepared::Select(plan) | Prepared::Explain { plan, .. } | Prepared::CopyTo { plan, .. } => Some(explain(plan.plan.as_ref())),
+            _ => None,
+        }
+    }
+
+    // Runs the statement as a transaction of its own, unless one was begun by BEGIN: its changes
+    // are committed if it succeeds and, if the database has a log, rolled back if it fails. In a
+    // transaction begun by BEGIN, a failing statement rolls back the whole transaction, and the
+    // block fails every statement but the COMMIT or ROLLBACK ending it, a COMMIT rolling back.
+    pub fn execute(&self, bufmgr: &mut BufferPoolManager, catalog: &mut Catalog, params: &[Value]) -> Result<QueryResult, Error> {
+        let in_block = bufmgr.isolation().is_some();
+        match &self.prepared {
+            Prepared::Other(ast::Statement::Commit) if bufmgr.block_failed() => {
+                abort(bufmgr, catalog)?;
+                return Ok(QueryResult::RolledBack);
+            }
+            Prepared::Other(ast::Statement::Commit | ast::Statement::Rollback) => {}
+            _ if bufmgr.block_failed() => return Err(Error::TransactionAborted),
+            _ => {}
+        }
+        match &self.prepared {
+            Prepared::Other(ast::Statement::Begin { isolation, read_only }) => {
+                if in_block {
+                    return Err(Error::Invalid("a transaction is already in progress".to_string()));
+                }
+                bufmgr.set_read_only(*read_only);
+                bufmgr.begin(isolation.unwrap_or(bufmgr.default_isolation())).map_err(query::Error::from)?;
+                return Ok(QueryResult::Done);
+            }
+            Prepared::Other(ast::Statement::Commit) => {
+                bufmgr.commit().map_err(query::Error::from)?;
+                return Ok(QueryResult::Done);
+            }
+            Prepared::Other(ast::Statement::Rollback) => {
+                abort(bufmgr, catalog)?;
+                return Ok(QueryResult::Done);
+            }
+            Prepared::Other(ast::Statement::CreateIndex(create)) if create.concurrently => {
+                if in_block {
+                    return Err(Error::Invalid("CREATE INDEX CONCURRENTLY cannot run inside a transaction block".to_string()));
+                }
+                return self.create_index_concurrently(bufmgr, catalog, create);
+            }
+            Prepared::Other(ast::Statement::Vacuum { full: true, .. }) => return self.vacuum_full(bufmgr, catalog),
+            Prepared::Other(
+                ast::Statement::Set { .. }
+                | ast::Statement::Show(_)
+                | ast::Statement::Cancel { .. }
+                | ast::Statement::DeclareCursor { .. }
+                | ast::Statement::Fetch { .. }
+                | ast::Statement::CloseCursor(_),
+            ) => {
+                return Err(Error::Invalid(format!("{} can only be run by a session", self.command())));
+            }
+            _ => {}
+        }
+        self.run_statement(bufmgr, catalog, |bufmgr, catalog| self.run(bufmgr, catalog, params))
+    }
+
+    // Runs a SELECT as `execute` does, writing its rows to `output` as they're produced rather
+    // than returning them (see `copy_to`). Returns how many there were.
+    pub fn copy_to(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        catalog: &mut Catalog,
+        params: &[Value],
+        output: impl Write,
+        format: &ast::CopyFormat,
+    ) -> Result<usize, Error> {
+        self.stream(bufmgr, catalog, params, |rows| copy_to(rows, output, format))
+    }
+
+    // Runs a SELECT as `execute` does, passing `f` the stream of its rows rather than returning
+    // them.
+    pub fn stream<T>(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        catalog: &mut Catalog,
+        params: &[Value],
+        f: impl FnOnce(RowStream<'_>) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let plan = match &self.prepared {
+            Prepared::Select(plan) => plan,
+            _ => return Err(Error::Invalid("only a SELECT can be streamed".to_string())),
+        };
+        self.run_statement(bufmgr, catalog, |bufmgr, _| {
+            self.set_params(params)?;
+            f(RowStream::start(plan, bufmgr)?)
+        })
+    }
+
+    // Creates an index without locking its table against writes, in transactions of its own: the
+    // index is added first, empty and not read from, so that every write from then on maintains
+    // it, then the versions of the rows already there are read into it a batch of rows a
+    // transaction, and it's made valid once they're all in. An error leaves it as it is,
+    // maintained but not read from.
+    fn create_index_concurrently(&self, bufmgr: &mut BufferPoolManager, catalog: &mut Catalog, create: &ast::CreateIndex) -> Result<QueryResult, Error> {
+        self.run_statement(bufmgr, catalog, |bufmgr, catalog| {
+            let (columns, paths) = index_columns(catalog, create)?;
+            Ok(catalog.add_index(bufmgr, &create.table, &create.name, columns, paths, create.kind)?)
+        })?;
+        let mut from = None;
+        loop {
+            let next = self.run_statement(bufmgr, catalog, |bufmgr, catalog| {
+                let info = catalog.table(&create.table).ok_or_else(|| Error::UnknownTable(create.table.clone()))?;
+                let i = info.index_names.iter().position(|name| *name == create.name).ok_or_else(|| catalog::Error::UnknownIndex(create.name.clone()))?;
+                Ok(info.table.build_index(bufmgr, i, from.as_deref(), INDEX_BUILD_ROWS)?)
+            })?;
+            match next {
+                Some(key) => from = Some(key),
+                None => break,
+            }
+        }
+        self.run_statement(bufmgr, catalog, |bufmgr, catalog| Ok(catalog.validate_index(bufmgr, &create.table, &create.name)?))?;
+        Ok(QueryResult::Done)
+    }
+
+    // Vacuums and rewrites the tables in one transaction, leaving the pages they no longer use
+    // free, then in another takes those at the end of the file out of the free list, the file
+    // being truncated once that's committed, as there's no undoing that.
+    fn vacuum_full(&self, bufmgr: &mut BufferPoolManager, catalog: &mut Catalog) -> Result<QueryResult, Error> {
+        self.run_statement(bufmgr, catalog, |bufmgr, catalog| self.run(bufmgr, catalog, &[]))?;
+        let num_pages = self.run_statement(bufmgr, catalog, |bufmgr, catalog| Ok(catalog.take_free_tail(bufmgr)?))?;
+        bufmgr.truncate(num_pages).map_err(query::Error::from)?;
+        Ok(QueryResult::Done)
+    }
+
+    // Runs `f` as a statement of the transaction begun by BEGIN, or of one of its own.
+    fn run_statement<T>(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        catalog: &mut Catalog,
+        f: impl FnOnce(&mut BufferPoolManager, &mut Catalog) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let read_only = matches!(self.prepared, Prepared::Select(_) | Prepared::Explain { .. } | Prepared::CopyTo { .. } | Prepared::Check(_));
+        let _span = trace::span!(Info, "statement", command = self.command());
+        run_statement(bufmgr, catalog, read_only, f)
+    }
+
+    fn run(&self, bufmgr: &mut BufferPoolManager, catalog: &mut Catalog, params: &[Value]) -> Result<QueryResult, Error> {
+        self.set_params(params)?;
+
+        match &self.prepared {
+            Prepared::Select(plan) => {
+                let rows = RowStream::start(plan, bufmgr)?.collect::<Result<_, _>>()?;
+                Ok(QueryResult::Rows {
+                    columns: plan.columns.clone(),
+                    rows,
+                })
+            }
+            Prepared::Explain { plan, analyze } => {
+                let plan = plan.plan.as_ref();
+                if *analyze {
+                    reset_stats(plan);
+                    let mut exec = plan.start(bufmgr)?;
+                    while exec.next(bufmgr)?.is_some() {}
+                }
+                Ok(QueryResult::Rows {
+                    columns: vec!["QUERY PLAN".to_string()],
+                    rows: explain(plan).into_iter().map(|line| vec![Value::Text(line)]).collect(),
+                })
+            }
+            Prepared::Insert {
+                table,
+                num_columns,
+                targets,
+                rows,
+                on_conflict,
+                returning,
+            } => {
+                let written = execute_insert(bufmgr, catalog, table, *num_columns, targets, rows, on_conflict.as_ref(), 0)?;
+                returned(bufmgr, returning.as_ref(), written)
+            }
+            Prepared::Modify { table, num_columns, plan, condition, assignments, returning } => {
+                let written = execute_modify(bufmgr, catalog, table, *num_columns, plan, condition.as_ref(), assignments.as_deref())?;
+                returned(bufmgr, returning.as_ref(), written)
+            }
+            Prepared::CopyTo { plan, path, format } => {
+                let mut output = BufWriter::new(File::create(path)?);
+                let written = copy_to(RowStream::start(plan, bufmgr)?, &mut output, format)?;
+                output.flush()?;
+                Ok(QueryResult::RowsAffected(written))
+            }
+            Prepared::Check(table) => {
+                let report = check::check(bufmgr, catalog, table.as_deref())?;
+                let object = table.as_ref().map_or("database".to_string(), |name| format!("table {}", name));
+                Ok(QueryResult::Rows {
+                    columns: check::REPORT_COLUMNS.iter().map(|name| name.to_string()).collect(),
+                    rows: report.rows(&object),
+                })
+            }
+            Prepared::Other(statement) => execute_ddl(bufmgr, catalog, statement),
+        }
+    }
+
+    // Starts running a SELECT, whose rows are then produced one at a time as the stream is read.
+    // No page stays pinned between rows since scans copy out the entries of their current page,
+    // so only that page and whatever sorts, hash tables and the like buffer are held in memory.
+    pub fn query<'a>(&'a self, bufmgr: &'a mut BufferPoolManager, params: &[Value]) -> Result<RowStream<'a>, Error> {
+        let plan = match &self.prepared {
+            Prepared::Select(plan) => plan,
+            _ => return Err(Error::Invalid("only a SELECT can be queried".to_string())),
+        };
+        self.set_params(params)?;
+        RowStream::start(plan, bufmgr)
+    }
+
+    fn set_params(&self, params: &[Value]) -> Result<(), Error> {
+        if params.len() != self.param_types.len() {
+            return Err(Error::Invalid(format!(
+                "expected {} parameters, got {}",
+                self.param_types.len(),
+                params.len()
+            )));
+        }
+        let mut assigned = Vec::with_capacity(params.len());
+        for (i, (value, data_type)) in params.iter().zip(&self.param_types).enumerate() {
+            assigned.push(match data_type {
+                Some(data_type) => assign(*data_type, value.clone())
+                    .ok_or_else(|| Error::Invalid(format!("invalid value for parameter ${}: {:?}", i + 1, value)))?,
+                None => value.clone(),
+            });
+        }
+        *self.params.borrow_mut() = assigned;
+        Ok(())
+    }
+}
+
+// Runs `f` as a statement: in the transaction block if there's one, where an error rolls it all
+// back and fails the block, or else in a transaction of its own, read-only if it's known to be,
+// committed when it ends. An error reloads the catalog if the transaction has changed it.
+pub fn run_statement<T>(
+    bufmgr: &mut BufferPoolManager,
+    catalog: &mut Catalog,
+    read_only: bool,
+    f: impl FnOnce(&mut BufferPoolManager, &mut Catalog) -> Result<T, Error>,
+) -> Result<T, Error> {
+    if bufmgr.block_failed() {
+        return Err(Error::TransactionAborted);
+    }
+    let in_block = bufmgr.isolation().is_some();
+    // a SELECT of its own is known to be read-only before it runs
+    if !in_block && read_only {
+        bufmgr.set_read_only(true);
+    }
+    bufmgr.start_statement();
+    match f(bufmgr, catalog) {
+        Ok(result) if in_block => {
+            bufmgr.end_statement();
+            Ok(result)
+        }
+        Ok(result) => {
+            bufmgr.commit().map_err(query::Error::from)?;
+            Ok(result)
+        }
+        Err(err) if in_block => {
+            fail_block(bufmgr, catalog)?;
+            Err(err)
+        }
+        Err(err) => {
+            abort(bufmgr, catalog)?;
+            Err(err)
+        }
+    }
+}
+
+// Rolls back the transaction of the block running, leaving the block failed for the statements
+// up to the COMMIT or ROLLBACK ending it to be refused (see `BufferPoolManager::fail_block`).
+pub(crate) fn fail_block(bufmgr: &mut BufferPoolManager, catalog: &mut Catalog) -> Result<(), Error> {
+    let Some(isolation) = bufmgr.isolation() else {
+        return Ok(());
+    };
+    abort(bufmgr, catalog)?;
+    bufmgr.fail_block(isolation);
+    Ok(())
+}
+
+// Rolls back the current transaction, reloading the catalog if it has changed it, in memory as
+// well as on disk. One which hasn't leaves it be, as it may have been reloaded with the changes
+// of another not committed (see `lock::lock_catalog`).
+fn abort(bufmgr: &mut BufferPoolManager, catalog: &mut Catalog) -> Result<(), Error> {
+    let changed = lock::holds_catalog(bufmgr);
+    if bufmgr.abort().map_err(query::Error::from)? && changed {
+        catalog.reload(bufmgr)?;
+    }
+    Ok(())
+}
+
+// Rows of a running SELECT, pulled from its executor as they're read. Ends after an error.
+pub struct RowStream<'a> {
+    columns: &'a [String],
+    types: &'a [Option<DataType>],
+    exec: Option<BoxExecutor<'a>>,
+    bufmgr: &'a mut BufferPoolManager,
+}
+
+impl<'a> RowStream<'a> {
+    fn start(plan: &'a SelectPlan, bufmgr: &'a mut BufferPoolManager) -> Result<Self, Error> {
+        Ok(Self {
+            columns: &plan.columns,
+            types: &plan.types,
+            exec: Some(plan.plan.start(bufmgr)?),
+            bufmgr,
+        })
+    }
+
+    pub fn columns(&self) -> &[String] {
+        self.columns
+    }
+
+    // Type of each column if it's known before the rows are.
+    pub fn types(&self) -> &[Option<DataType>] {
+        self.types
+    }
+}
+
+impl Iterator for RowStream<'_> {
+    type Item = Result<Tuple, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.exec.as_mut()?.next(self.bufmgr);
+        match result {
+            Ok(Some(row)) => Some(Ok(row)),
+            Ok(None) => {
+                self.exec = None;
+                None
+            }
+            Err(err) => {
+                self.exec = None;
+                Some(Err(err.into()))
+            }
+        }
+    }
+}
+
+// A SELECT declared as a cursor in a transaction block, whose rows are fetched some at a time by
+// later statements of the transaction, its executor going on from where the last fetch left it.
+// Each fetch reads the snapshot of the statement which declared it and spills to its temporary
+// pages, so that it sees the rows the SELECT would have even in read committed. To be closed by
+// the time the transaction ends, releasing those.
+pub struct Cursor {
+    // running the plan of `statement`, hence dropped first
+    exec: Option<BoxExecutor<'static>>,
+    held: Option<HeldStatement>,
+    statement: Rc<PreparedStatement>,
+}
+
+impl Cursor {
+    // Starts `statement`, a SELECT, with `params` as a statement of the transaction block. In an
+    // Rc, so the plan stays where it is however the cursor is moved.
+    pub fn declare(statement: Rc<PreparedStatement>, params: &[Value], bufmgr: &mut BufferPoolManager, catalog: &mut Catalog) -> Result<Self, Error> {
+        if bufmgr.isolation().is_none() {
+            return Err(Error::Invalid("DECLARE CURSOR can only be used in transaction blocks".to_string()));
+        }
+        let plan = match &statement.prepared {
+            Prepared::Select(plan) => plan,
+            _ => return Err(Error::Invalid("a cursor can only be declared for a SELECT".to_string())),
+        };
+        statement.run_statement(bufmgr, catalog, |bufmgr, _| {
+            statement.set_params(params)?;
+            let exec = plan.plan.start(bufmgr)?;
+            // SAFETY: the executor borrows only the plan, which is never changed or moved out of
+            // the Rc, and is kept alive by `statement` for as long as the executor is
+            let exec = unsafe { std::mem::transmute::<BoxExecutor<'_>, BoxExecutor<'static>>(exec) };
+            Ok(Self { exec: Some(exec), held: Some(bufmgr.hold_statement()), statement: statement.clone() })
+        })
+    }
+
+    pub fn columns(&self) -> &[String] {
+        match &self.statement.prepared {
+            Prepared::Select(plan) => &plan.columns,
+            _ => unreachable!("declared for a SELECT"),
+        }
+    }
+
+    // Name and type, if known before it runs, of each column, as `PreparedStatement::result_columns`.
+    pub fn result_columns(&self) -> Vec<(String, Option<DataType>)> {
+        self.statement.result_columns().unwrap_or_default()
+    }
+
+    // The next `count` rows, or all those left if None, fetched as a statement of the
+    // transaction, which an error rolls back. None are left once one fails.
+    pub fn fetch(&mut self, bufmgr: &mut BufferPoolManager, catalog: &mut Catalog, count: Option<u64>) -> Result<Vec<Tuple>, Error> {
+        let (Some(exec), Some(held)) = (&mut self.exec, &mut self.held) else {
+            return Ok(vec![]);
+        };
+        let result = run_statement(bufmgr, catalog, true, |bufmgr, _| {
+            bufmgr.resume(held, |bufmgr| {
+                let mut rows = vec![];
+                while count.is_none_or(|count| (rows.len() as u64) < count) {
+                    match exec.next(bufmgr)? {
+                        Some(row) => rows.push(row),
+                        None => break,
+                    }
+                }
+                Ok(rows)
+            })
+        });
+        if result.is_err() {
+            self.exec = None;
+        }
+        result
+    }
+
+    // Releases the snapshot and the temporary pages of the cursor.
+    pub fn close(mut self, bufmgr: &mut BufferPoolManager) {
+        self.exec = None;
+        if let Some(held) = self.held.take() {
+            bufmgr.release(held);
+        }
+    }
+}
+
+// The positions in its table of the columns of the index `create` defines.
+// The columns of the index, with the paths into those of JSON it's on (see `expr::column_path`).
+fn index_columns(catalog: &Catalog, create: &ast::CreateIndex) -> Result<(Vec<usize>, Vec<Option<json::Path>>), Error> {
+    let info = catalog
+        .table(&create.table)
+        .ok_or_else(|| Error::UnknownTable(create.table.clone()))?;
+    // the left operand, the operator and the constant right operand of a JSON operator
+    fn operator(expr: &ast::Expr) -> Option<(&ast::Expr, BinaryOp, &Value)> {
+        match expr {
+            ast::Expr::Binary { op: op @ (BinaryOp::JsonGet | BinaryOp::JsonGetText | BinaryOp::JsonPath | BinaryOp::JsonPathText), left, right } => match &**right {
+                ast::Expr::Literal(operand) => Some((&**left, *op, operand)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    let column = |expr: &ast::Expr| match expr {
+        ast::Expr::Column { table: None, name } => info.column_index(name),
+        _ => None,
+    };
+    let mut columns = vec![];
+    let mut paths = vec![];
+    for expr in &create.columns {
+        if let ast::Expr::Column { name, .. } = expr {
+            info.column_index(name).ok_or_else(|| Error::UnknownColumn(name.clone()))?;
+        }
+        let (c, path) = expr::column_path(expr, &operator, &column)
+            .ok_or_else(|| Error::Invalid("an index is on columns, or on paths into JSON columns of ->, ->>, #> and #>> with constants".to_string()))?;
+        if path.is_some() && info.columns[c].data_type != DataType::Json {
+            return Err(Error::Invalid(format!("column {} is not of type json", info.columns[c].name)));
+        }
+        columns.push(c);
+        paths.push(path);
+    }
+    // of the words of one text column
+    if create.kind == IndexKind::FullText && (columns.len() != 1 || paths[0].is_some() || info.columns[columns[0]].data_type != DataType::Text) {
+        return Err(Error::Invalid("a full-text index is on one column of type text".to_string()));
+    }
+    // of the boxes of one point or box column
+    if create.kind == IndexKind::RTree
+        && (columns.len() != 1 || paths[0].is_some() || !matches!(info.columns[columns[0]].data_type, DataType::Point | DataType::Box))
+    {
+        return Err(Error::Invalid("an R-tree index is on one column of type point or box".to_string()));
+    }
+    if create.kind == IndexKind::ZoneMap && paths.iter().any(Option::is_some) {
+        return Err(Error::Invalid("a zone map index is on columns".to_string()));
+    }
+    Ok((columns, paths))
+}
+
+fn execute_ddl(bufmgr: &mut BufferPoolManager, catalog: &mut Catalog, statement: &ast::Statement) -> Result<QueryResult, Error> {
+    match statement {
+        ast::Statement::CreateTable(ast::CreateTable { temporary: true, partition_by, partition_of, .. }) if partition_by.is_some() || partition_of.is_some() => {
+            Err(Error::Invalid("a temporary table can't be partitioned or a partition".to_string()))
+        }
+        ast::Statement::CreateTable(ast::CreateTable { engine: ast::Engine::Memory, partition_by, .. }) if partition_by.is_some() => {
+            Err(Error::Invalid("an in-memory table can't be partitioned".to_string()))
+        }
+        ast::Statement::CreateTable(ast::CreateTable { engine: ast::Engine::Columnar, partition_by, .. }) if partition_by.is_some() => {
+            Err(Error::Invalid("a columnar table can't be partitioned".to_string()))
+        }
+        ast::Statement::CreateTable(ast::CreateTable { temporary: true, engine: ast::Engine::Memory, .. }) => {
+            Err(Error::Invalid("a temporary table can't be in memory".to_string()))
+        }
+        ast::Statement::CreateTable(ast::CreateTable { temporary: true, engine: ast::Engine::Columnar, .. }) => {
+            Err(Error::Invalid("a temporary table can't be columnar".to_string()))
+        }
+        // the rows the worker deletes are those the catalog knows of, in tables it can delete them from
+        ast::Statement::CreateTable(ast::CreateTable { temporary, partition_by, engine, ttl: Some(_), .. })
+            if *temporary || partition_by.is_some() || *engine == ast::Engine::Columnar =>
+        {
+            Err(Error::Invalid("a temporary, partitioned or columnar table can't have a TTL".to_string()))
+        }
+        // a dictionary is of a table of the catalog's, rather than of a session or of the
+        // partitions, and the stripes of a columnar one have dictionaries of their own
+        ast::Statement::CreateTable(ast::CreateTable { temporary, partition_by, engine, dictionary, .. })
+            if !dictionary.is_empty() && (*temporary || partition_by.is_some() || *engine == ast::Engine::Columnar) =>
+        {
+            Err(Error::Invalid("a temporary, partitioned or columnar table can't have dictionary columns".to_string()))
+        }
+        // being lost when the database goes down, its pages can't be in the data file with the others
+        ast::Statement::CreateTable(ast::CreateTable { unlogged: true, engine, .. }) if *engine != ast::Engine::Memory => {
+            Err(Error::Invalid("an unlogged table must be in memory (USING memory)".to_string()))
+        }
+        ast::Statement::CreateTable(ast::CreateTable { name, partition_of: Some((parent, bound)), .. }) => {
+            let info = catalog.table(parent).ok_or_else(|| Error::UnknownTable(parent.clone()))?;
+            let data_type = info.partitioning.as_ref().map(|partitioning| info.columns[partitioning.column].data_type);
+            let mut value = |expr: &Option<Box<ast::Expr>>| -> Result<Option<Value>, Error> {
+                match expr {
+                    Some(expr) => Ok(Some(Planner::new(catalog).bind_constant(expr, data_type)?.eval(&[], bufmgr)?)),
+                    None => Ok(None),
+                }
+            };
+            let bound = match bound {
+                ast::PartitionBound::Range { lower, upper } => PartitionBound::Range { lower: value(lower)?, upper: value(upper)? },
+                ast::PartitionBound::Hash { modulus, remainder } => PartitionBound::Hash { modulus: *modulus, remainder: *remainder },
+            };
+            catalog.create_partition(bufmgr, name, parent, bound)?;
+            Ok(QueryResult::Done)
+        }
+        ast::Statement::CreateTable(create) => {
+            // tables are clustered on the primary key, which therefore has to be the leading columns
+            let is_leading = create
+                .primary_key
+                .iter()
+                .enumerate()
+                .all(|(i, name)| create.columns.get(i).map(|c| &c.name) == Some(name));
+            if create.primary_key.is_empty() || !is_leading {
+                return Err(Error::Invalid("the primary key must be the leading columns of the table".to_string()));
+            }
+            for (i, column) in create.columns.iter().enumerate() {
+                if create.columns[..i].iter().any(|c| c.name == column.name) {
+                    return Err(Error::Invalid(format!("duplicate column: {}", column.name)));
+                }
+            }
+            let ttl = match &create.ttl {
+                Some((name, seconds)) => {
+                    let column = create.columns.iter().position(|c| c.name == *name).ok_or_else(|| Error::UnknownColumn(name.clone()))?;
+                    if create.columns[column].data_type != DataType::Integer {
+                        return Err(Error::Invalid("the TTL column must be an INTEGER, of seconds since the Unix epoch".to_string()));
+                    }
+                    Some(Ttl { column, seconds: *seconds })
+                }
+                None => None,
+            };
+            let mut dictionary = vec![];
+            for name in &create.dictionary {
+                let column = create.columns.iter().position(|c| c.name == *name).ok_or_else(|| Error::UnknownColumn(name.clone()))?;
+                if create.columns[column].data_type != DataType::Text || column < create.primary_key.len() || dictionary.contains(&column) {
+                    return Err(Error::Invalid("dictionary columns must be distinct TEXT columns outside the primary key".to_string()));
+                }
+                dictionary.push(column);
+            }
+            let columns = create
+                .columns
+                .iter()
+                .map(|c| Column {
+                    name: c.name.clone(),
+                    data_type: c.data_type,
+                })
+                .collect();
+            match &create.partition_by {
+                None if create.temporary => {
+                    catalog.create_temp_table(bufmgr, &create.name, columns, create.primary_key.len())?;
+                }
+                None if create.engine == ast::Engine::Memory => {
+                    catalog.create_memory_table(bufmgr, &create.name, columns, create.primary_key.len(), !create.unlogged)?;
+                }
+                None if create.engine == ast::Engine::Columnar => {
+                    catalog.create_columnar_table(bufmgr, &create.name, columns, create.primary_key.len())?;
+                }
+                Some((strategy, key)) => {
+                    // so that the primary key is unique across the partitions
+                    let column = create.primary_key.iter().position(|name| name == key).ok_or_else(|| {
+                        if create.columns.iter().any(|c| c.name == *key) {
+                            Error::Invalid("the partition key must be a column of the primary key".to_string())
+                        } else {
+                            Error::UnknownColumn(key.clone())
+                        }
+                    })?;
+                    catalog.create_partitioned_table(bufmgr, &create.name, columns, create.primary_key.len(), *strategy, column)?;
+                }
+                None => {
+                    catalog.create_table(bufmgr, &create.name, columns, create.primary_key.len())?;
+                }
+            }
+            if let Some(ttl) = ttl {
+                catalog.set_ttl(bufmgr, &create.name, ttl)?;
+            }
+            if !dictionary.is_empty() {
+                catalog.set_dictionary(bufmgr, &create.name, dictionary)?;
+            }
+            Ok(QueryResult::Done)
+        }
+        ast::Statement::CreateIndex(create) => {
+            let (columns, paths) = index_columns(catalog, create)?;
+            catalog.create_index(bufmgr, &create.table, &create.name, columns, paths, create.kind)?;
+            Ok(QueryResult::Done)
+        }
+        ast::Statement::CreateView(create) => {
+            // planned only to check the query, which is planned again wherever the view is used
+            let mut planner = Planner::new(catalog);
+            let plan = planner.plan_query(&create.query)?;
+            if !planner.into_params().1.is_empty() {
+                return Err(Error::Invalid("views can't have parameters".to_string()));
+            }
+            let columns = match &create.columns {
+                Some(names) if names.len() != plan.columns.len() => {
+                    return Err(Error::Invalid(format!(
+                        "view {} has {} columns but its query returns {}",
+                        create.name,
+                        names.len(),
+                        plan.columns.len()
+                    )));
+                }
+                Some(names) => names.clone(),
+                None => plan.columns,
+            };
+            for (i, name) in columns.iter().enumerate() {
+                if columns[..i].contains(name) {
+                    return Err(Error::Invalid(format!("duplicate column: {}", name)));
+                }
+            }
+            let view = ViewInfo {
+                name: create.name.clone(),
+                sql: create.sql.clone(),
+                columns,
+            };
+            catalog.create_view(bufmgr, view)?;
+            Ok(QueryResult::Done)
+        }
+        ast::Statement::CreateTrigger(create) => {
+            // planned only to check the condition and the INSERTs, which are planned again each
+            // time the trigger is run
+            let columns = catalog.table(&create.table).ok_or_else(|| Error::UnknownTable(create.table.clone()))?.columns.clone();
+            let images = OuterRow::default();
+            let mut planner = Planner::new(catalog);
+            planner.add_row_images(&columns, &images, &images);
+            if let Some(when) = &create.when {
+                planner.bind_constant(when, Some(DataType::Boolean))?;
+            }
+            for insert in &create.body {
+                prepare_insert(&mut planner, catalog, insert)?;
+            }
+            if !planner.into_params().1.is_empty() {
+                return Err(Error::Invalid("triggers can't have parameters".to_string()));
+            }
+            let trigger = TriggerInfo {
+                name: create.name.clone(),
+                table: create.table.clone(),
+                timing: create.timing,
+                event: create.event,
+                action: Action::Sql(create.sql.clone()),
+            };
+            catalog.create_trigger(bufmgr, trigger)?;
+            Ok(QueryResult::Done)
+        }
+        ast::Statement::Drop { kind, name, if_exists } => {
+            let exists = match kind {
+                ast::ObjectKind::Table => catalog.table(name).is_some(),
+                ast::ObjectKind::Index => catalog.tables().chain(catalog.temp_tables()).any(|info| info.index_names.contains(name)),
+                ast::ObjectKind::View => catalog.view(name).is_some(),
+            };
+            if !exists && *if_exists {
+                return Ok(QueryResult::Done);
+            }
+            match kind {
+                ast::ObjectKind::Table => catalog.drop_table(bufmgr, name)?,
+                ast::ObjectKind::Index => catalog.drop_index(bufmgr, name)?,
+                ast::ObjectKind::View => catalog.drop_view(bufmgr, name)?,
+            }
+            Ok(QueryResult::Done)
+        }
+        ast::Statement::AlterTable { table, action } => {
+            match action {
+                ast::AlterTable::AddColumn(column) => {
+                    catalog.add_column(bufmgr, table, Column { name: column.name.clone(), data_type: column.data_type })?;
+                }
+                ast::AlterTable::Rename(new_name) => catalog.rename_table(bufmgr, table, new_name)?,
+                ast::AlterTable::RenameColumn { column, new_name } => catalog.rename_column(bufmgr, table, column, new_name)?,
+            }
+            Ok(QueryResult::Done)
+        }
+        ast::Statement::Analyze(table) => {
+            let names: Vec<String> = match table {
+                Some(name) => vec![catalog.table(name).ok_or_else(|| Error::UnknownTable(name.clone()))?.name.clone()],
+                None => catalog.tables().chain(catalog.temp_tables()).map(|info| info.name.clone()).collect(),
+            };
+            for name in names {
+                let info = catalog.table(&name).unwrap();
+                let stats = TableStats::collect(bufmgr, &info.table, info.columns.len())?;
+                catalog.set_stats(bufmgr, &name, stats)?;
+            }
+            Ok(QueryResult::Done)
+        }
+        ast::Statement::Select(_)
+        | ast::Statement::Explain { .. }
+        | ast::Statement::Insert(_)
+        | ast::Statement::Update(_)
+        | ast::Statement::Delete(_)
+        | ast::Statement::CopyTo(_)
+        | ast::Statement::Check(_) => unreachable!("planned when prepared"),
+        ast::Statement::Vacuum { table, full } => {
+            if bufmgr.isolation().is_some() {
+                return Err(Error::Invalid("VACUUM cannot run inside a transaction block".to_string()));
+            }
+            let names: Vec<String> = match table {
+                Some(name) => vec![catalog.table(name).ok_or_else(|| Error::UnknownTable(name.clone()))?.name.clone()],
+                None => catalog.tables().chain(catalog.temp_tables()).map(|info| info.name.clone()).collect(),
+            };
+            let horizon = bufmgr.horizon();
+            for name in &names {
+                catalog.table(name).unwrap().table.vacuum(bufmgr)?;
+                // so that only the versions kept are rewritten (see `vacuum_full`), which a
+                // temporary or in-memory table can't be, failing if it's named and only vacuumed otherwise
+                if *full && (table.is_some() || !catalog.is_temp(name) && !catalog.table(name).unwrap().table.in_memory()) {
+                    catalog.rewrite_table(bufmgr, name)?;
+                }
+            }
+            // the versions of the transactions which aborted before the horizon are all gone
+            if table.is_none() {
+                bufmgr.forget_aborted(horizon);
+            }
+            Ok(QueryResult::Done)
+        }
+        ast::Statement::Reindex { name, table } => {
+            if bufmgr.isolation().is_some() {
+                // others would write to the new nodes before it ends, which a rollback undoes
+                return Err(Error::Invalid("REINDEX cannot run inside a transaction block".to_string()));
+            }
+            let info = match table {
+                true => catalog.table(name).ok_or_else(|| Error::UnknownTable(name.clone()))?,
+                false => catalog.tables().find(|info| info.index_names.contains(name)).ok_or_else(|| catalog::Error::UnknownIndex(name.clone()))?,
+            };
+            let (table_name, index_names) = match table {
+                true => (info.name.clone(), info.index_names.clone()),
+                false => (info.name.clone(), vec![name.clone()]),
+            };
+            for index_name in &index_names {
+                catalog.reindex(bufmgr, &table_name, index_name)?;
+            }
+            Ok(QueryResult::Done)
+        }
+        ast::Statement::LockTable { table, mode } => {
+            if bufmgr.isolation().is_none() {
+                return Err(Error::Invalid("LOCK TABLE can only be used in transaction blocks".to_string()));
+            }
+            let info = catalog.table(table).ok_or_else(|| Error::UnknownTable(table.clone()))?;
+            info.table.lock(bufmgr, *mode)?;
+            // and its partitions, which hold its rows
+            for name in info.partitioning.iter().flat_map(|partitioning| &partitioning.partitions) {
+                catalog.table(name).unwrap().table.lock(bufmgr, *mode)?;
+            }
+            Ok(QueryResult::Done)
+        }
+        ast::Statement::CopyFrom(copy) => {
+            let input = BufReader::new(File::open(&copy.path)?);
+            let columns = copy.columns.as_deref();
+            let loaded = match &copy.format {
+                ast::CopyFormat::Csv(options) => copy_from(bufmgr, catalog, &copy.table, columns, input, options)?,
+                #[cfg(feature = "parquet")]
+                ast::CopyFormat::Parquet => copy_from_parquet(bufmgr, catalog, &copy.table, columns, input)?,
+                #[cfg(not(feature = "parquet"))]
+                ast::CopyFormat::Parquet => return Err(Error::Invalid(NO_PARQUET.to_string())),
+                ast::CopyFormat::JsonLines | ast::CopyFormat::Arrow => unreachable!("rejected by the parser"),
+            };
+            Ok(QueryResult::RowsAffected(loaded))
+        }
+        ast::Statement::CreateUser { name, password, superuser } => {
+            let password = password.as_deref().map(|password| Credentials::new(password).to_string());
+            catalog.create_user(bufmgr, UserInfo { name: name.clone(), password, superuser: *superuser })?;
+            Ok(QueryResult::Done)
+        }
+        ast::Statement::AlterUser { name, password } => {
+            let password = password.as_deref().map(|password| Credentials::new(password).to_string());
+            catalog.set_password(bufmgr, name, password)?;
+            Ok(QueryResult::Done)
+        }
+        ast::Statement::Grant(grant) => {
+            for user in &grant.users {
+                catalog.grant(bufmgr, &grant.table, user, &grant.privileges)?;
+            }
+            Ok(QueryResult::Done)
+        }
+        ast::Statement::Revoke(grant) => {
+            for user in &grant.users {
+                catalog.revoke(bufmgr, &grant.table, user, &grant.privileges)?;
+            }
+            Ok(QueryResult::Done)
+        }
+        ast::Statement::Begin { .. }
+        | ast::Statement::Commit
+        | ast::Statement::Rollback
+        | ast::Statement::Set { .. }
+        | ast::Statement::Show(_)
+        | ast::Statement::Cancel { .. }
+        | ast::Statement::DeclareCursor { .. }
+        | ast::Statement::Fetch { .. }
+        | ast::Statement::CloseCursor(_) => {
+            unreachable!("run by PreparedStatement::execute")
+        }
+    }
+}
+
+// Loads the CSV records read from `input` into `table`, their fields into `columns`, or all of
+// them in order, and the other columns NULL. The rows are loaded in bulk (see `BulkLoad`).
+// Returns how many there were.
+pub fn copy_from(
+    bufmgr: &mut BufferPoolManager,
+    catalog: &Catalog,
+    table: &str,
+    columns: Option<&[String]>,
+    input: impl BufRead,
+    options: &CsvOptions,
+) -> Result<usize, Error> {
+    let info = catalog.table(table).ok_or_else(|| Error::UnknownTable(table.to_string()))?;
+    let mut reader = csv::Reader::new(input, options.clone());
+    load_rows(bufmgr, catalog, info, columns, "line", None, || {
+        let record = reader.next_record()?;
+        Ok(record.map(|record| (reader.line(), record.into_iter().map(|field| field.map_or(Value::Null, Value::Text)).collect())))
+    })
+}
+
+// Like `copy_from`, of the rows of a Parquet file, its columns in order.
+#[cfg(feature = "parquet")]
+pub fn copy_from_parquet(
+    bufmgr: &mut BufferPoolManager,
+    catalog: &Catalog,
+    table: &str,
+    columns: Option<&[String]>,
+    input: impl Read + Seek,
+) -> Result<usize, Error> {
+    let info = catalog.table(table).ok_or_else(|| Error::UnknownTable(table.to_string()))?;
+    let mut reader = parquet::Reader::new(input)?;
+    let mut row = 0;
+    load_rows(bufmgr, catalog, info, columns, "row", None, || {
+        row += 1;
+        Ok(reader.next_row()?.map(|values| (row, values)))
+    })
+}
+
+// Loads the rows of `next` into `table` in bulk, of all its columns in order, as `copy_from`.
+// With `rejected`, a row which can't be stored, of a value which doesn't cast to its column's
+// type, a NULL key or too large, is passed to it, with its number, and the load goes on.
+pub fn load(
+    bufmgr: &mut BufferPoolManager,
+    catalog: &Catalog,
+    table: &str,
+    mut next: impl FnMut() -> Result<Option<Tuple>, Error>,
+    rejected: Option<&mut dyn FnMut(usize, Error)>,
+) -> Result<usize, Error> {
+    let info = catalog.table(table).ok_or_else(|| Error::UnknownTable(table.to_string()))?;
+    let mut row = 0;
+    load_rows(bufmgr, catalog, info, None, "row", rejected, || {
+        row += 1;
+        Ok(next()?.map(|values| (row, values)))
+    })
+}
+
+#[cfg(not(feature = "parquet"))]
+const NO_PARQUET: &str = "this build has no Parquet support";
+
+// Loads the rows `next` reads, each with its number in the input, which errors give as `unit`
+// and the number, casting each value to the type of its column. The errors of a row are passed to
+// `rejected`, if there is one, rather than ending the load.
+fn load_rows(
+    bufmgr: &mut BufferPoolManager,
+    catalog: &Catalog,
+    info: &TableInfo,
+    columns: Option<&[String]>,
+    unit: &str,
+    mut rejected: Option<&mut dyn FnMut(usize, Error)>,
+    mut next: impl FnMut() -> Result<Option<(usize, Tuple)>, Error>,
+) -> Result<usize, Error> {
+    let targets: Vec<usize> = match columns {
+        Some(names) => names
+            .iter()
+            .map(|name| info.column_index(name).ok_or_else(|| Error::UnknownColumn(name.clone())))
+            .collect::<Result<_, _>>()?,
+        None => (0..info.columns.len()).collect(),
+    };
+    // the rows of a partitioned table are loaded into its partitions, each in bulk
+    let mut loads = BTreeMap::new();
+    if info.partitioning.is_none() {
+        loads.insert(info.name.as_str(), info.table.bulk_load(bufmgr, COPY_FILL_FACTOR)?);
+    }
+    while let Some((n, values)) = next()? {
+        let row = (|| {
+            if values.len() != targets.len() {
+                return Err(Error::Invalid(format!("{} {}: {} fields for {} columns", unit, n, values.len(), targets.len())));
+            }
+            let mut row = vec![Value::Null; info.columns.len()];
+            for (value, &target) in values.into_iter().zip(&targets) {
+                let column = &info.columns[target];
+                row[target] = expr::cast(value, column.data_type)
+                    .map_err(|err| Error::Invalid(format!("{} {}: column {}: {}", unit, n, column.name, err)))?;
+            }
+            if row[..info.table.num_key_elems].iter().any(Value::is_null) {
+                return Err(Error::Invalid(format!("{} {}: primary key columns must not be NULL", unit, n)));
+            }
+            Ok(row)
+        })();
+        let pushed = row.and_then(|row| {
+            let target = catalog.route(info, &row).map_err(|err| Error::Invalid(format!("{} {}: {}", unit, n, err)))?;
+            let load = match loads.entry(target.name.as_str()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(target.table.bulk_load(bufmgr, COPY_FILL_FACTOR)?),
+            };
+            match load.push(bufmgr, row) {
+                Err(table::Error::Btree(btree::Error::EntryTooLarge)) => Err(Error::Invalid(format!("{} {}: too large to store", unit, n))),
+                result => Ok(result?),
+            }
+        });
+        match (pushed, &mut rejected) {
+            (Err(Error::Invalid(reason)), Some(rejected)) => rejected(n, Error::Invalid(reason)),
+            (result, _) => result?,
+        }
+    }
+    let mut loaded = 0;
+    for (_, load) in loads {
+        loaded += load.finish(bufmgr)?;
+    }
+    Ok(loaded)
+}
+
+// Writes the rows of a running SELECT to `output` as they're read, as CSV, after a line of the
+// names of the columns with HEADER, as a JSON object a line, or as Parquet or an Arrow stream,
+// of which the columns whose types aren't known are text. Returns how many there were.
+pub fn copy_to(rows: RowStream<'_>, mut output: impl Write, format: &ast::CopyFormat) -> Result<usize, Error> {
+    let columns = rows.columns().to_vec();
+    let mut written = 0;
+    match format {
+        #[cfg(feature = "parquet")]
+        ast::CopyFormat::Parquet => {
+            let types: Vec<DataType> = rows.types().iter().map(|t| t.unwrap_or(DataType::Text)).collect();
+            let mut writer = parquet::Writer::new(output, columns.iter().cloned().zip(types.iter().copied()).collect())?;
+            for row in rows {
+                let row = row?
+                    .into_iter()
+                    .zip(&types)
+                    .zip(&columns)
+                    .map(|((value, &data_type), name)| {
+                        expr::cast(value, data_type).map_err(|err| Error::Invalid(format!("column {}: {}", name, err)))
+                    })
+                    .collect::<Result<_, _>>()?;
+                writer.write_row(row)?;
+                written += 1;
+            }
+            writer.finish()?;
+        }
+        #[cfg(not(feature = "parquet"))]
+        ast::CopyFormat::Parquet => return Err(Error::Invalid(NO_PARQUET.to_string())),
+        #[cfg(feature = "arrow")]
+        ast::CopyFormat::Arrow => {
+            let mut writer = arrow::StreamWriter::new(output, arrow_fields(&rows))?;
+            written = record_batches(rows, |batch| Ok(writer.write(&batch)?))?;
+            writer.finish()?;
+        }
+        #[cfg(not(feature = "arrow"))]
+        ast::CopyFormat::Arrow => return Err(Error::Invalid("this build has no Arrow support".to_string())),
+        ast::CopyFormat::Csv(options) => {
+            let mut writer = csv::Writer::new(output, options.clone());
+            if options.header {
+                writer.write_record(columns.iter().map(|name| Some(name.as_str())))?;
+            }
+            for row in rows {
+                let fields: Vec<Option<String>> = row?
+                    .into_iter()
+                    .map(|value| match value {
+                        Value::Null => None,
+                        Value::Int(n) => Some(n.to_string()),
+                        Value::Text(s) => Some(s),
+                        Value::Bool(b) => Some(b.to_string()),
+                        Value::Json(bytes) => Some(json::to_string(&bytes)),
+                        Value::Array(values) => Some(array::to_string(&values)),
+                        Value::Point(point) => Some(point.to_string()),
+                        Value::Box(rect) => Some(rect.to_string()),
+                        Value::Float(f) => Some(f.to_string()),
+                        Value::Numeric(n) => Some(n.to_string()),
+                        Value::Date(days) => Some(datetime::format_date(days)),
+                        Value::Timestamp(micros) => Some(datetime::format_timestamp(micros)),
+                    })
+                    .collect();
+                writer.write_record(fields.iter().map(Option::as_deref))?;
+                written += 1;
+            }
+        }
+        ast::CopyFormat::JsonLines => {
+            for row in rows {
+                let mut line = String::from("{");
+                for (i, (name, value)) in columns.iter().zip(row?).enumerate() {
+                    if i > 0 {
+                        line.push(',');
+                    }
+                    push_json_string(&mut line, name);
+                    line.push(':');
+                    push_json_value(&mut line, value);
+                }
+                line.push_str("}\n");
+                output.write_all(line.as_bytes())?;
+                written += 1;
+            }
+        }
+    }
+    Ok(written)
+}
+
+// Passes the rows of a running SELECT to `f` in Arrow record batches, of which the columns whose
+// types aren't known are text, as many as there are rows for, and one at least. Returns how many
+// rows there were.
+#[cfg(feature = "arrow")]
+pub fn record_batches(rows: RowStream<'_>, mut f: impl FnMut(RecordBatch) -> Result<(), Error>) -> Result<usize, Error> {
+    let fields = arrow_fields(&rows);
+    let mut builder = RecordBatchBuilder::new(fields.clone());
+    let mut num_rows = 0;
+    for row in rows {
+        let row = row?
+            .into_iter()
+            .zip(&fields)
+            .map(|(value, field)| expr::cast(value, field.data_type).map_err(|err| Error::Invalid(format!("column {}: {}", field.name, err))))
+            .collect::<Result<_, _>>()?;
+        builder.push(row)?;
+        num_rows += 1;
+        if builder.is_full() {
+            f(builder.finish())?;
+        }
+    }
+    if !builder.is_empty() || num_rows == 0 {
+        f(builder.finish())?;
+    }
+    Ok(num_rows)
+}
+
+#[cfg(feature = "arrow")]
+fn arrow_fields(rows: &RowStream<'_>) -> Vec<arrow::Field> {
+    rows.columns()
+        .iter()
+        .zip(rows.types())
+        .map(|(name, data_type)| arrow::Field { name: name.clone(), data_type: data_type.unwrap_or(DataType::Text) })
+        .collect()
+}
+
+// Arrays as JSON arrays, points and boxes, dates and timestamps as strings of their text, as are
+// floats which are infinite or NaN.
+pub(crate) fn push_json_value(line: &mut String, value: Value) {
+    match value {
+        Value::Null => line.push_str("null"),
+        Value::Int(n) => line.push_str(&n.to_string()),
+        Value::Text(s) => push_json_string(line, &s),
+        Value::Bool(b) => line.push_str(if b { "true" } else { "false" }),
+        Value::Json(bytes) => line.push_str(&json::to_string(&bytes)),
+        Value::Array(values) => {
+            line.push('[');
+            for (i, value) in values.into_iter().enumerate() {
+                if i > 0 {
+                    line.push(',');
+                }
+                push_json_value(line, value);
+            }
+            line.push(']');
+        }
+        Value::Point(point) => push_json_string(line, &point.to_string()),
+        Value::Box(rect) => push_json_string(line, &rect.to_string()),
+        Value::Float(f) if f.0.is_finite() => line.push_str(&f.to_string()),
+        Value::Float(f) => push_json_string(line, &f.to_string()),
+        Value::Numeric(n) => line.push_str(&n.to_string()),
+        Value::Date(days) => push_json_string(line, &datetime::format_date(days)),
+        Value::Timestamp(micros) => push_json_string(line, &datetime::format_timestamp(micros)),
+    }
+}
+
+pub(crate) fn push_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// The result of a statement which wrote `written`, the rows of its RETURNING list if it has one.
+fn returned(bufmgr: &mut BufferPoolManager, returning: Option<&Returning>, written: Vec<Tuple>) -> Result<QueryResult, Error> {
+    let Some(returning) = returning else {
+        return Ok(QueryResult::RowsAffected(written.len()));
+    };
+    let mut rows = Vec::with_capacity(written.len());
+    for row in &written {
+        rows.push(returning.exprs.iter().map(|expr| expr.eval(row, bufmgr)).collect::<Result<_, _>>()?);
+    }
+    Ok(QueryResult::Rows {
+        columns: returning.columns.clone(),
+        rows,
+    })
+}
+
+// The table a statement prepared to write it writes to, looked up again so that indexes created
+// since are maintained.
+fn table_to_write<'c>(catalog: &'c Catalog, table: &str, num_columns: usize) -> Result<&'c TableInfo, Error> {
+    let info = catalog.table(table).ok_or_else(|| Error::UnknownTable(table.to_string()))?;
+    if info.columns.len() != num_columns {
+        return Err(Error::Invalid(format!("table {} has changed since the statement was prepared", table)));
+    }
+    Ok(info)
+}
+
+// `value` as a value of `data_type`, a number converted to the type of numbers it is, as a date to
+// a timestamp and back, or None if it's of another type.
+fn assign(data_type: DataType, value: Value) -> Option<Value> {
+    if data_type.accepts(&value) {
+        return Some(value);
+    }
+    DataType::of(&value)?.common(data_type)?;
+    expr::cast(value, data_type).ok()
+}
+
+// Inserts the rows, running the triggers on the table, as the triggers of triggers do, `depth`
+// deep. It returns the rows written, as inserted or updated.
+#[allow(clippy::too_many_arguments)]
+fn execute_insert(
+    bufmgr: &mut BufferPoolManager,
+    catalog: &Catalog,
+    table: &str,
+    num_columns: usize,
+    targets: &[usize],
+    rows: &[Vec<Expr>],
+    on_conflict: Option<&Conflict>,
+    depth: usize,
+) -> Result<Vec<Tuple>, Error> {
+    let info = table_to_write(catalog, table, num_columns)?;
+    let mut evaluated = Vec::with_capacity(rows.len());
+    for values in rows {
+        let mut row = vec![Value::Null; num_columns];
+        for (expr, &target) in values.iter().zip(targets) {
+            let value = expr.eval(&[], bufmgr)?;
+            let column = &info.columns[target];
+            row[target] = assign(column.data_type, value.clone())
+                .ok_or_else(|| Error::Invalid(format!("invalid value for column {}: {:?}", column.name, value)))?;
+        }
+        if row[..info.table.num_key_elems].iter().any(Value::is_null) {
+            return Err(Error::Invalid("primary key columns must not be NULL".to_string()));
+        }
+        let mut images = RowImages { old: None, new: Some(row) };
+        if run_triggers(bufmgr, catalog, info, Timing::Before, Event::Insert, &mut images, depth)? {
+            evaluated.push(images.new.unwrap());
+        }
+    }
+    // the partition each row belongs to if the table is partitioned
+    let tables = evaluated.iter().map(|row| catalog.route(info, row)).collect::<Result<Vec<_>, _>>()?;
+    // the rows the AFTER triggers are run for once all are written
+    let mut after = vec![];
+    let Some(on_conflict) = on_conflict else {
+        // a row alone is inserted as it is, many in batches, of each table
+        if let [row] = &evaluated[..] {
+            tables[0].table.insert(bufmgr, row)?;
+        } else {
+            let mut names: Vec<&str> = tables.iter().map(|info| info.name.as_str()).collect();
+            names.sort_unstable();
+            names.dedup();
+            for name in names {
+                let rows = evaluated.iter().zip(&tables).filter(|(_, info)| info.name == name).map(|(row, _)| row.clone());
+                catalog.table(name).unwrap().table.insert_many(bufmgr, rows)?;
+            }
+        }
+        for row in &evaluated {
+            after.push((Event::Insert, RowImages { old: None, new: Some(row.clone()) }));
+        }
+        run_after_triggers(bufmgr, catalog, info, after, depth)?;
+        return Ok(evaluated);
+    };
+    // the rows inserted or updated, one at a time, by their keys
+    let (mut changed, mut written) = (HashSet::new(), vec![]);
+    for (row, target) in evaluated.into_iter().zip(tables) {
+        let mut pkey = vec![];
+        tuple::encode_key(&row[..info.table.num_key_elems], &mut pkey);
+        let Some(existing) = target.table.insert_or_get(bufmgr, &row)? else {
+            changed.insert(pkey);
+            after.push((Event::Insert, RowImages { old: None, new: Some(row.clone()) }));
+            written.push(row);
+            continue;
+        };
+        let Conflict::Update { assignments, condition, excluded } = on_conflict else {
+            continue;
+        };
+        if changed.contains(&pkey) {
+            return Err(Error::Invalid("ON CONFLICT DO UPDATE cannot change a row a second time".to_string()));
+        }
+        *excluded.borrow_mut() = row;
+        if let Some(condition) = condition {
+            if condition.eval(&existing, bufmgr)? != Value::Bool(true) {
+                continue;
+            }
+        }
+        let mut updated = existing.clone();
+        for (target, expr) in assignments {
+            let value = expr.eval(&existing, bufmgr)?;
+            let column = &info.columns[*target];
+            updated[*target] = assign(column.data_type, value.clone())
+                .ok_or_else(|| Error::Invalid(format!("invalid value for column {}: {:?}", column.name, value)))?;
+        }
+        let mut images = RowImages { old: Some(existing), new: Some(updated) };
+        if !run_triggers(bufmgr, catalog, info, Timing::Before, Event::Update, &mut images, depth)? {
+            continue;
+        }
+        let updated = images.new.clone().unwrap();
+        target.table.update(bufmgr, &updated)?;
+        changed.insert(pkey);
+        after.push((Event::Update, images));
+        written.push(updated);
+    }
+    run_after_triggers(bufmgr, catalog, info, after, depth)?;
+    Ok(written)
+}
+
+// Updates the rows `plan` finds with `assignments`, or deletes them if there are none, running the
+// triggers on the table. The rows are all found first, for those updated not to be found again,
+// and each is then written as its newest version has it, which in read committed may have been
+// committed since, skipped if it no longer satisfies `condition`. It returns the rows as updated,
+// or as they were deleted.
+fn execute_modify(
+    bufmgr: &mut BufferPoolManager,
+    catalog: &Catalog,
+    table: &str,
+    num_columns: usize,
+    plan: &SelectPlan,
+    condition: Option<&Expr>,
+    assignments: Option<&[(usize, Expr)]>,
+) -> Result<Vec<Tuple>, Error> {
+    let info = table_to_write(catalog, table, num_columns)?;
+    let num_key_elems = info.table.num_key_elems;
+    // all found before any is written, held against the memory budget of the statement
+    let (mut found, mut memory) = (vec![], MemoryReservation::default());
+    let mut rows = plan.plan.start(bufmgr)?;
+    while let Some(row) = rows.next(bufmgr)? {
+        bufmgr.reserve_memory(&mut memory, query::memory_of(&row)).map_err(query::Error::from)?;
+        found.push(row);
+    }
+    drop(rows);
+    let event = if assignments.is_some() { Event::Update } else { Event::Delete };
+    let (mut after, mut written) = (vec![], vec![]);
+    for row in found {
+        // the partition it's in if the table is partitioned
+        let target = catalog.route(info, &row)?;
+        let Some(existing) = target.table.get_for_update(bufmgr, &row[..num_key_elems])? else {
+            continue;
+        };
+        if let Some(condition) = condition.filter(|_| existing != row) {
+            if condition.eval(&existing, bufmgr)? != Value::Bool(true) {
+                continue;
+            }
+        }
+        let mut images = RowImages { old: Some(existing), new: None };
+        if let Some(assignments) = assignments {
+            let existing = images.old.as_ref().unwrap();
+            let mut updated = existing.clone();
+            for (target, expr) in assignments {
+                let value = expr.eval(existing, bufmgr)?;
+                let column = &info.columns[*target];
+                updated[*target] = assign(column.data_type, value.clone())
+                    .ok_or_else(|| Error::Invalid(format!("invalid value for column {}: {:?}", column.name, value)))?;
+            }
+            images.new = Some(updated);
+        }
+        if !run_triggers(bufmgr, catalog, info, Timing::Before, event, &mut images, 0)? {
+            continue;
+        }
+        match &images.new {
+            Some(updated) => {
+                target.table.update(bufmgr, updated)?;
+                written.push(updated.clone());
+            }
+            None => {
+                let old = images.old.as_ref().unwrap();
+                target.table.delete(bufmgr, &old[..num_key_elems])?;
+                written.push(old.clone());
+            }
+        }
+        after.push((event, images));
+    }
+    run_after_triggers(bufmgr, catalog, info, after, 0)?;
+    Ok(written)
+}
+
+fn run_after_triggers(bufmgr: &mut BufferPoolManager, catalog: &Catalog, info: &TableInfo, rows: Vec<(Event, RowImages)>, depth: usize) -> Result<(), Error> {
+    for (event, mut images) in rows {
+        run_triggers(bufmgr, catalog, info, Timing::After, event, &mut images, depth)?;
+    }
+    Ok(())
+}
+
+// Runs the triggers on the table of `info` at `timing` for `event` on the row of `images`, and
+// returns whether the row is to be written, as the BEFORE triggers tell. NEW as a callback of a
+// BEFORE trigger leaves it must still be a row of the table, of the same primary key if updated.
+fn run_triggers(
+    bufmgr: &mut BufferPoolManager,
+    catalog: &Catalog,
+    info: &TableInfo,
+    timing: Timing,
+    event: Event,
+    images: &mut RowImages,
+    depth: usize,
+) -> Result<bool, Error> {
+    for trigger in catalog.triggers_on(&info.name, timing, event) {
+        if depth >= MAX_TRIGGER_DEPTH {
+            return Err(Error::Invalid(format!("trigger {} nested more than {} deep", trigger.name, MAX_TRIGGER_DEPTH)));
+        }
+        let failed = |message: String| Error::Invalid(format!("trigger {} failed: {}", trigger.name, message));
+        match &trigger.action {
+            Action::Callback(callback) => {
+                let before = images.clone();
+                let write = callback(images).map_err(failed)?;
+                if timing == Timing::After {
+                    *images = before;
+                    continue;
+                }
+                if event == Event::Delete {
+                    // there's no NEW to check of a row deleted
+                    images.new = None;
+                    if !write {
+                        return Ok(false);
+                    }
+                    continue;
+                }
+                let num_key_elems = info.table.num_key_elems;
+                let row = images.new.as_ref().ok_or_else(|| failed("NEW was taken".to_string()))?;
+                let fits = row.len() == info.columns.len() && row.iter().zip(&info.columns).all(|(value, column)| column.data_type.accepts(value));
+                if !fits || row[..num_key_elems].iter().any(Value::is_null) {
+                    return Err(failed(format!("NEW is not a row of table {}: {:?}", info.name, row)));
+                }
+                if images.old.as_ref().is_some_and(|old| old[..num_key_elems] != row[..num_key_elems]) {
+                    return Err(failed("the primary key of a row updated can't be changed".to_string()));
+                }
+                if !write {
+                    return Ok(false);
+                }
+            }
+            Action::Sql(sql) => {
+                let create = match parse(sql)?.pop() {
+                    Some(ast::Statement::CreateTrigger(create)) => create,
+                    _ => return Err(Error::Invalid(format!("malformed trigger: {}", trigger.name))),
+                };
+                let (new, old) = (OuterRow::default(), OuterRow::default());
+                for (row, image) in [(&new, &images.new), (&old, &images.old)] {
+                    *row.borrow_mut() = image.clone().unwrap_or_else(|| vec![Value::Null; info.columns.len()]);
+                }
+                let mut planner = Planner::new(catalog);
+                planner.add_row_images(&info.columns, &new, &old);
+                if let Some(when) = &create.when {
+                    if planner.bind_constant(when, Some(DataType::Boolean))?.eval(&[], bufmgr)? != Value::Bool(true) {
+                        continue;
+                    }
+                }
+                for insert in &create.body {
+                    if let Prepared::Insert { table, num_columns, targets, rows, on_conflict, .. } = prepare_insert(&mut planner, catalog, insert)? {
+                        execute_insert(bufmgr, catalog, &table, num_columns, &targets, &rows, on_conflict.as_ref(), depth + 1)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::numeric::{Float, Numeric};
+    use crate::wal::{Wal, DEFAULT_SEGMENT_SIZE};
+    use tempfile::tempfile;
+
+    fn query(bufmgr: &mut BufferPoolManager, catalog: &mut Catalog, sql: &str) -> Vec<Tuple> {
+        match execute(bufmgr, catalog, sql).unwrap().pop().unwrap() {
+            QueryResult::Rows { mut rows, .. } => {
+                rows.sort();
+                rows
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    fn ints(values: &[i64]) -> Vec<Tuple> {
+        values.iter().map(|&n| vec![Value::Int(n)]).collect()
+    }
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(16));
+        let mut catalog = Catalog::create(&mut bufmgr).unwrap();
+        execute(
+            &mut bufmgr,
+            &mut catalog,
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT, team INTEGER);
+             CREATE TABLE teams (id INTEGER PRIMARY KEY, title TEXT);
+             INSERT INTO users VALUES (1, 'alice', 10), (2, 'bob', 20), (3, 'carol', NULL), (4, 'dave', 10);
+             INSERT INTO teams (id, title) VALUES (10, 'db'), (20, 'web'), (30, 'ops');",
+        )
+        .unwrap();
+        let (b, c) = (&mut bufmgr, &mut catalog);
+
+        assert_eq!(
+            vec![vec![Value::Text("alice".to_string()), Value::Text("db".to_string())]],
+            query(b, c, "SELECT u.name, t.title FROM users u JOIN teams t ON u.team = t.id WHERE u.id = 1")
+        );
+        // IN / NOT IN, decorrelated into (null aware) semi / anti joins
+        assert_eq!(ints(&[1, 2, 4]), query(b, c, "SELECT id FROM users WHERE team IN (SELECT id FROM teams)"));
+        assert_eq!(ints(&[30]), query(b, c, "SELECT id FROM teams WHERE id NOT IN (SELECT team FROM users WHERE team IS NOT NULL)"));
+        assert_eq!(ints(&[]), query(b, c, "SELECT id FROM teams WHERE id NOT IN (SELECT team FROM users)"));
+        // correlated EXISTS, decorrelated by the correlation equality
+        assert_eq!(ints(&[10, 20]), query(b, c, "SELECT id FROM teams t WHERE EXISTS (SELECT * FROM users WHERE team = t.id)"));
+        assert_eq!(ints(&[30]), query(b, c, "SELECT id FROM teams t WHERE NOT EXISTS (SELECT * FROM users u WHERE u.team = t.id AND u.id > 0)"));
+        // correlated predicates which are not equalities are evaluated per row
+        assert_eq!(ints(&[20, 30]), query(b, c, "SELECT id FROM teams t WHERE EXISTS (SELECT * FROM users WHERE team < t.id)"));
+        // scalar subqueries, correlated or not
+        assert_eq!(
+            vec![vec![Value::Int(10), Value::Text("alice".to_string())], vec![Value::Int(20), Value::Text("bob".to_string())], vec![Value::Int(30), Value::Null]],
+            query(b, c, "SELECT id, (SELECT name FROM users WHERE team = t.id AND id < 4) FROM teams t")
+        );
+        assert_eq!(ints(&[4]), query(b, c, "SELECT id FROM users WHERE id = (SELECT 2 * 2)"));
+        assert!(matches!(
+            execute(b, c, "SELECT (SELECT id FROM users)"),
+            Err(Error::Query(query::Error::SubqueryReturnedMultipleRows))
+        ));
+
+        // DISTINCT, also inside aggregates, and grouping
+        assert_eq!(vec![vec![Value::Null], vec![Value::Int(10)], vec![Value::Int(20)]], query(b, c, "SELECT DISTINCT team FROM users"));
+        assert_eq!(vec![ints(&[4, 3, 2]).concat()], query(b, c, "SELECT count(*), count(team), count(DISTINCT team) FROM users"));
+        assert_eq!(
+            vec![vec![Value::Int(10), Value::Int(2), Value::Text("dave".to_string())]],
+            query(b, c, "SELECT team, count(*), max(name) FROM users GROUP BY team HAVING count(*) > 1")
+        );
+        assert_eq!(ints(&[10, 20, 30]), query(b, c, "SELECT t.id FROM teams t GROUP BY id"));
+        assert_eq!(vec![vec![Value::Int(0), Value::Null]], query(b, c, "SELECT count(*), sum(id) FROM users WHERE id > 100"));
+        assert!(matches!(execute(b, c, "SELECT name FROM users GROUP BY team"), Err(Error::Invalid(_))));
+        assert!(matches!(execute(b, c, "SELECT id FROM users WHERE count(*) > 1"), Err(Error::Invalid(_))));
+        // set operations
+        let nullable = |values: &[Option<i64>]| values.iter().map(|v| vec![v.map_or(Value::Null, Value::Int)]).collect::<Vec<_>>();
+        assert_eq!(
+            nullable(&[None, Some(10), Some(20), Some(30)]),
+            query(b, c, "SELECT id FROM teams UNION SELECT team FROM users")
+        );
+        assert_eq!(7, query(b, c, "SELECT team FROM users UNION ALL SELECT id FROM teams").len());
+        assert_eq!(ints(&[10, 20]), query(b, c, "SELECT id FROM teams INTERSECT SELECT team FROM users"));
+        assert_eq!(nullable(&[None, Some(10)]), query(b, c, "SELECT team FROM users EXCEPT ALL SELECT id FROM teams"));
+        assert_eq!(nullable(&[None, Some(1)]), query(b, c, "SELECT NULL UNION SELECT 1"));
+        assert_eq!(ints(&[1, 2, 4]), query(b, c, "SELECT id FROM users WHERE team IN (SELECT 10 UNION SELECT 20)"));
+        assert!(matches!(execute(b, c, "SELECT id FROM users UNION SELECT name FROM users"), Err(Error::Invalid(_))));
+        assert!(matches!(execute(b, c, "SELECT id FROM users INTERSECT SELECT id, name FROM users"), Err(Error::Invalid(_))));
+        // rows read in primary key order are deduplicated without hashing
+        let plan = query(b, c, "EXPLAIN SELECT DISTINCT id FROM users");
+        assert!(plan.iter().any(|row| matches!(&row[0], Value::Text(s) if s.contains("Sort Distinct"))));
+        let plan = query(b, c, "EXPLAIN SELECT DISTINCT team FROM users");
+        assert!(plan.iter().any(|row| matches!(&row[0], Value::Text(s) if s.contains("Hash Distinct"))));
+
+        // the join probes the primary key of teams for each user
+        let plan = query(b, c, "EXPLAIN SELECT u.name, t.title FROM users u JOIN teams t ON u.team = t.id WHERE u.id = 1");
+        let plan: Vec<_> = plan.iter().map(|row| row[0].clone()).collect();
+        assert!(plan.iter().any(|line| matches!(line, Value::Text(s) if s.contains("Index Nested Loop Join on teams using primary key"))));
+        let plan = query(b, c, "EXPLAIN ANALYZE SELECT id FROM users WHERE team = 10");
+        let plan: Vec<_> = plan.iter().map(|row| row[0].clone()).collect();
+        assert!(plan.iter().any(|line| matches!(line, Value::Text(s) if s.contains("Filter") && s.contains("rows=2 loops=1"))));
+
+        // estimates come from the statistics once the table is analyzed
+        execute(b, c, "ANALYZE users").unwrap();
+        assert_eq!(4, c.table("users").unwrap().stats.as_ref().unwrap().row_count);
+        let plan = query(b, c, "EXPLAIN SELECT * FROM users");
+        assert!(plan.iter().any(|row| matches!(&row[0], Value::Text(s) if s.contains("Seq Scan on users  (rows=4 "))));
+
+        assert!(matches!(execute(b, c, "SELECT nope FROM users"), Err(Error::UnknownColumn(_))));
+        assert!(matches!(execute(b, c, "SELECT id FROM users, teams"), Err(Error::AmbiguousColumn(_))));
+        assert!(matches!(execute(b, c, "INSERT INTO users VALUES (5, 6, 7)"), Err(Error::Invalid(_))));
+
+        // views are expanded wherever they're used, and joined like tables
+        execute(b, c, "CREATE VIEW members (member, team) AS SELECT u.name, t.title FROM users u JOIN teams t ON u.team = t.id").unwrap();
+        execute(b, c, "CREATE VIEW team_sizes AS SELECT team, count(*) AS size FROM users GROUP BY team").unwrap();
+        assert_eq!(
+            vec![vec![Value::Text("alice".to_string())], vec![Value::Text("dave".to_string())]],
+            query(b, c, "SELECT member FROM members WHERE team = 'db'")
+        );
+        assert_eq!(
+            vec![vec![Value::Text("db".to_string()), Value::Int(2)], vec![Value::Text("web".to_string()), Value::Int(1)]],
+            query(b, c, "SELECT t.title, s.size FROM team_sizes s JOIN teams t ON s.team = t.id")
+        );
+        assert_eq!(ints(&[2]), query(b, c, "SELECT id FROM users u WHERE EXISTS (SELECT * FROM members WHERE member = u.name AND team = 'web')"));
+        assert!(matches!(execute(b, c, "CREATE VIEW members AS SELECT 1"), Err(Error::Catalog(catalog::Error::ViewExists(_)))));
+        assert!(matches!(execute(b, c, "CREATE TABLE members (id INTEGER PRIMARY KEY)"), Err(Error::Catalog(_))));
+        assert!(matches!(execute(b, c, "CREATE VIEW v (a, b) AS SELECT id FROM users"), Err(Error::Invalid(_))));
+        assert!(matches!(execute(b, c, "CREATE VIEW v AS SELECT id FROM users WHERE id = ?"), Err(Error::Invalid(_))));
+        assert!(matches!(execute(b, c, "CREATE VIEW v AS SELECT * FROM users, teams"), Err(Error::Invalid(_))));
+        assert!(matches!(execute(b, c, "SELECT u.id FROM members"), Err(Error::UnknownColumn(_))));
+
+        // CTEs, planned in place if referred to once and materialized otherwise
+        assert_eq!(ints(&[2]), query(b, c, "WITH web AS (SELECT id FROM users WHERE team = 20) SELECT id FROM web"));
+        let twice = "WITH t AS (SELECT id FROM teams) SELECT x.id FROM t x JOIN t y ON x.id = y.id";
+        assert_eq!(ints(&[10, 20, 30]), query(b, c, twice));
+        let plan = query(b, c, &format!("EXPLAIN {}", twice));
+        assert!(plan.iter().any(|row| matches!(&row[0], Value::Text(s) if s.contains("Materialize CTEs (t)"))));
+        assert_eq!(2, plan.iter().filter(|row| matches!(&row[0], Value::Text(s) if s.contains("CTE Scan on t"))).count());
+        let inlined = twice.replace("AS (", "AS NOT MATERIALIZED (");
+        assert_eq!(ints(&[10, 20, 30]), query(b, c, &inlined));
+        let plan = query(b, c, &format!("EXPLAIN {}", inlined));
+        assert!(!plan.iter().any(|row| matches!(&row[0], Value::Text(s) if s.contains("CTE"))));
+        assert_eq!(
+            ints(&[1]),
+            query(b, c, "WITH x AS (SELECT 1 AS one) SELECT one FROM x WHERE one IN (WITH y AS (SELECT one FROM x) SELECT * FROM y)")
+        );
+        // recursive CTEs iterate until no new rows are produced
+        assert_eq!(
+            ints(&[1, 2, 3, 4, 5]),
+            query(b, c, "WITH RECURSIVE r (n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM r WHERE n < 5) SELECT n FROM r")
+        );
+        assert_eq!(ints(&[1, 2, 3]), query(b, c, "WITH RECURSIVE r (n) AS (SELECT 1 UNION SELECT n % 3 + 1 FROM r) SELECT * FROM r"));
+        assert!(matches!(execute(b, c, "WITH RECURSIVE r AS (SELECT * FROM r) SELECT 1"), Err(Error::Invalid(_))));
+        assert!(matches!(execute(b, c, "WITH x AS (SELECT 1), x AS (SELECT 2) SELECT 1"), Err(Error::Invalid(_))));
+        assert!(matches!(execute(b, c, "WITH x (a, b) AS (SELECT 1) SELECT 1"), Err(Error::Invalid(_))));
+
+        // pattern matching and string functions
+        let texts = |values: &[&str]| values.iter().map(|s| vec![Value::Text(s.to_string())]).collect::<Vec<_>>();
+        assert_eq!(texts(&["carol", "dave"]), query(b, c, "SELECT name FROM users WHERE name LIKE '%a_e%' OR name LIKE 'c%'"));
+        assert_eq!(texts(&["alice", "bob"]), query(b, c, "SELECT name FROM users WHERE name NOT ILIKE 'C%' AND name ILIKE '%B%' OR id = 1"));
+        assert_eq!(
+            vec![vec![Value::Text("ALICE".to_string()), Value::Int(5), Value::Text("lic".to_string()), Value::Text("alice#10".to_string())]],
+            query(b, c, "SELECT upper(name), length(name), substring(name FROM 2 FOR 3), concat(name, '#', team) FROM users WHERE id = 1")
+        );
+        assert_eq!(texts(&["x"]), query(b, c, "SELECT trim(BOTH '-' FROM lower(concat('--', 'X', '-')))"));
+        assert!(matches!(execute(b, c, "SELECT length(id) FROM users"), Err(Error::Query(query::Error::TypeMismatch(_)))));
+
+        // casts, and string literals taking the type of where they're used
+        assert_eq!(texts(&["alice"]), query(b, c, "SELECT name FROM users WHERE id = '1'"));
+        assert_eq!(texts(&["bob", "dave"]), query(b, c, "SELECT name FROM users WHERE id IN ('2', '4')"));
+        assert_eq!(
+            vec![vec![Value::Text("3".to_string()), Value::Int(15), Value::Bool(true)]],
+            query(b, c, "SELECT CAST(id AS TEXT), '5'::integer * 3, 'yes'::boolean FROM users WHERE id = 3")
+        );
+        execute(b, c, "CREATE TABLE flags (id INTEGER PRIMARY KEY, enabled BOOLEAN); INSERT INTO flags VALUES ('7', 'f')").unwrap();
+        assert_eq!(vec![vec![Value::Int(7), Value::Bool(false)]], query(b, c, "SELECT * FROM flags"));
+        assert!(matches!(execute(b, c, "SELECT CAST('x' AS INTEGER)"), Err(Error::Query(query::Error::InvalidArgument(_)))));
+        assert!(matches!(execute(b, c, "SELECT id FROM users WHERE id = name"), Err(Error::Invalid(_))));
+
+        // window functions, computed over sorted partitions
+        let rows = |values: &[[i64; 2]]| values.iter().map(|v| ints(v).concat()).collect::<Vec<_>>();
+        assert_eq!(
+            rows(&[[1, 2], [2, 1], [3, 1], [4, 1]]),
+            query(b, c, "SELECT id, row_number() OVER (PARTITION BY team ORDER BY id DESC) FROM users")
+        );
+        assert_eq!(
+            vec![
+                vec![Value::Int(1), Value::Int(2), Value::Null, Value::Int(2)],
+                ints(&[2, 4, 1, 3]).concat(),
+                ints(&[3, 1, 2, 4]).concat(),
+                ints(&[4, 2, 3, 0]).concat(),
+            ],
+            query(b, c, "SELECT id, rank() OVER (ORDER BY team), lag(id) OVER (ORDER BY id), lead(id, 1, 0) OVER (ORDER BY id) FROM users")
+        );
+        let running = "SELECT id, sum(id) OVER (ORDER BY id), sum(id) OVER (ORDER BY id ROWS BETWEEN 1 PRECEDING AND CURRENT ROW), \
+                       count(*) OVER () FROM users";
+        assert_eq!(
+            vec![ints(&[1, 1, 1, 4]).concat(), ints(&[2, 3, 3, 4]).concat(), ints(&[3, 6, 5, 4]).concat(), ints(&[4, 10, 7, 4]).concat()],
+            query(b, c, running)
+        );
+        assert_eq!(
+            vec![vec![Value::Null, Value::Int(1), Value::Int(2)], ints(&[10, 2, 1]).concat(), ints(&[20, 1, 2]).concat()],
+            query(b, c, "SELECT team, count(*), rank() OVER (ORDER BY count(*) DESC) FROM users GROUP BY team")
+        );
+        // functions over the same window share the sort
+        let plan = query(b, c, "EXPLAIN SELECT sum(id) OVER (ORDER BY id), row_number() OVER (ORDER BY id) FROM users");
+        assert_eq!(1, plan.iter().filter(|row| matches!(&row[0], Value::Text(s) if s.contains("Window ("))).count());
+        assert!(matches!(execute(b, c, "SELECT id FROM users WHERE row_number() OVER () > 1"), Err(Error::Invalid(_))));
+        assert!(matches!(execute(b, c, "SELECT sum(row_number() OVER ()) OVER () FROM users"), Err(Error::Invalid(_))));
+
+        // prepared statements, planned once and run with different parameters
+        let insert = prepare(c, "INSERT INTO users (id, name) VALUES (?, ?)").unwrap();
+        assert_eq!(vec![Some(DataType::Integer), Some(DataType::Text)], insert.param_types());
+        for (id, name) in [(5, "eve"), (6, "frank")] {
+            let params = [Value::Int(id), Value::Text(name.to_string())];
+            assert_eq!(QueryResult::RowsAffected(1), insert.execute(b, c, &params).unwrap());
+        }
+        assert!(matches!(insert.execute(b, c, &[Value::Int(7), Value::Int(7)]), Err(Error::Invalid(_))));
+        assert!(matches!(insert.execute(b, c, &[Value::Int(7)]), Err(Error::Invalid(_))));
+        let select = prepare(c, "SELECT id FROM users WHERE id > $1 AND id < $1 + $2").unwrap();
+        assert_eq!(2, select.num_params());
+        for (params, expected) in [([1, 3], vec![vec![Value::Int(2)], vec![Value::Int(3)]]), ([4, 3], ints(&[5, 6]))] {
+            match select.execute(b, c, &[Value::Int(params[0]), Value::Int(params[1])]).unwrap() {
+                QueryResult::Rows { rows, .. } => assert_eq!(expected, rows),
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+
+        // streamed rows, pulled one at a time
+        let mut stream = select.query(b, &[Value::Int(0), Value::Int(100)]).unwrap();
+        assert_eq!(vec!["id".to_string()], stream.columns());
+        assert_eq!(Some(vec![Value::Int(1)]), stream.next().map(Result::unwrap));
+        assert_eq!(ints(&[2, 3, 4, 5, 6]), stream.map(Result::unwrap).collect::<Vec<_>>());
+        let failing = prepare(c, "SELECT 10 / (id - 1) FROM users").unwrap();
+        let mut stream = failing.query(b, &[]).unwrap();
+        assert!(matches!(stream.next(), Some(Err(Error::Query(_)))));
+        assert!(stream.next().is_none());
+        drop(stream);
+        assert!(matches!(insert.query(b, &[]), Err(Error::Invalid(_))));
+
+        // with a log, statements are transactions
+        let (data_file, data_path) = tempfile::NamedTempFile::new().unwrap().into_parts();
+        let wal_dir = tempfile::tempdir().unwrap();
+        drop(data_file);
+        let open = || {
+            let disk = DiskManager::open(&data_path).unwrap();
+            let wal = Wal::open(wal_dir.path(), DEFAULT_SEGMENT_SIZE).unwrap();
+            BufferPoolManager::with_wal(disk, BufferPool::new(16), wal).unwrap()
+        };
+        let mut bufmgr = open();
+        let mut catalog = Catalog::create(&mut bufmgr).unwrap();
+        let (b, c) = (&mut bufmgr, &mut catalog);
+        execute(b, c, "CREATE TABLE t (id INTEGER PRIMARY KEY); INSERT INTO t VALUES (1)").unwrap();
+
+        // a failing statement is rolled back entirely
+        assert!(execute(b, c, "INSERT INTO t VALUES (2), (1)").is_err());
+        assert_eq!(ints(&[1]), query(b, c, "SELECT id FROM t"));
+        execute(b, c, "INSERT INTO t VALUES (3)").unwrap();
+
+        // in repeatable read, statements read the snapshot of the first one, and rows committed
+        // by others since can't be written
+        execute(b, c, "BEGIN ISOLATION LEVEL REPEATABLE READ").unwrap();
+        assert_eq!(ints(&[1, 3]), query(b, c, "SELECT id FROM t"));
+        let other = b.switch(Default::default());
+        execute(b, c, "INSERT INTO t VALUES (4)").unwrap();
+        let other = b.switch(other);
+        assert_eq!(ints(&[1, 3]), query(b, c, "SELECT id FROM t"));
+        assert!(execute(b, c, "BEGIN").is_err());
+        let err = execute(b, c, "INSERT INTO t VALUES (5), (4)").unwrap_err();
+        assert!(matches!(err, Error::Table(table::Error::WriteConflict)), "{:?}", err);
+        // which rolled back the transaction, the block failing what's run until it's ended
+        assert!(b.block_failed());
+        assert!(matches!(execute(b, c, "SELECT id FROM t"), Err(Error::TransactionAborted)));
+        assert!(matches!(execute(b, c, "COMMIT").unwrap()[..], [QueryResult::RolledBack]));
+        assert_eq!(None, b.isolation());
+        assert_eq!(ints(&[1, 3, 4]), query(b, c, "SELECT id FROM t"));
+        // in read committed, each statement reads a new snapshot
+        execute(b, c, "BEGIN").unwrap();
+        execute(b, c, "INSERT INTO t VALUES (5)").unwrap();
+        assert_eq!(ints(&[1, 3, 4, 5]), query(b, c, "SELECT id FROM t"));
+        let block = b.switch(other);
+        execute(b, c, "INSERT INTO t VALUES (6)").unwrap();
+        assert_eq!(ints(&[1, 3, 4, 6]), query(b, c, "SELECT id FROM t"));
+        b.switch(block);
+        assert_eq!(ints(&[1, 3, 4, 5, 6]), query(b, c, "SELECT id FROM t"));
+        execute(b, c, "COMMIT").unwrap();
+        // a rolled back transaction leaves neither rows nor tables behind
+        execute(b, c, "BEGIN; CREATE TABLE u (id INTEGER PRIMARY KEY); INSERT INTO u VALUES (1); INSERT INTO t VALUES (7)").unwrap();
+        assert_eq!(ints(&[1]), query(b, c, "SELECT id FROM u"));
+        execute(b, c, "ROLLBACK").unwrap();
+        assert!(execute(b, c, "SELECT id FROM u").is_err());
+        assert_eq!(ints(&[1, 3, 4, 5, 6]), query(b, c, "SELECT id FROM t"));
+        // with locking, LOCK TABLE keeps others from writing to the table until the transaction ends
+        assert!(execute(b, c, "LOCK TABLE t").is_err());
+        b.set_locking(true);
+        execute(b, c, "BEGIN; LOCK TABLE t IN SHARE MODE").unwrap();
+        let locker = b.switch(Default::default());
+        let err = execute(b, c, "INSERT INTO t VALUES (7)").unwrap_err();
+        assert!(matches!(err, Error::Table(table::Error::Lock(lock::Error::Wait))), "{:?}", err);
+        b.switch(locker);
+        execute(b, c, "COMMIT; INSERT INTO t VALUES (7)").unwrap();
+        // while read-only transactions, declared or inferred, neither wait for locks nor log anything
+        execute(b, c, "BEGIN; LOCK TABLE t").unwrap();
+        let locker = b.switch(Default::default());
+        let end = b.wal().unwrap().end();
+        assert_eq!(ints(&[1, 3, 4, 5, 6, 7]), query(b, c, "SELECT id FROM t"));
+        execute(b, c, "BEGIN READ ONLY").unwrap();
+        assert_eq!(ints(&[1, 3, 4, 5, 6, 7]), query(b, c, "SELECT id FROM t"));
+        let err = execute(b, c, "INSERT INTO t VALUES (8)").unwrap_err();
+        assert!(err.to_string().contains("read-only"), "{:?}", err);
+        assert!(b.block_failed());
+        execute(b, c, "ROLLBACK").unwrap();
+        assert_eq!(end, b.wal().unwrap().end());
+        b.switch(locker);
+        execute(b, c, "COMMIT").unwrap();
+        // CREATE INDEX CONCURRENTLY doesn't wait for the writers of the table, which go on
+        // writing while it's built, the rows already there read in batches
+        let values: Vec<String> = (0..1500).map(|i| format!("({}, {})", i, i % 10)).collect();
+        execute(b, c, &format!("CREATE TABLE n (id INTEGER PRIMARY KEY, n INTEGER); INSERT INTO n VALUES {}", values.join(", "))).unwrap();
+        execute(b, c, "BEGIN; INSERT INTO n VALUES (1500, 10)").unwrap();
+        let writer = b.switch(Default::default());
+        let err = execute(b, c, "CREATE INDEX n_n ON n (n)").unwrap_err();
+        assert_eq!("waiting for a lock held by another transaction", err.to_string());
+        execute(b, c, "CREATE INDEX CONCURRENTLY n_n ON n (n)").unwrap();
+        b.switch(writer);
+        execute(b, c, "INSERT INTO n VALUES (1501, 10); COMMIT").unwrap();
+        assert_eq!(ints(&[1500, 1501]), query(b, c, "SELECT id FROM n WHERE n = 10"));
+        assert_eq!(ints(&[150]), query(b, c, "SELECT count(*) FROM n WHERE n = 3"));
+        let plan = query(b, c, "EXPLAIN SELECT id FROM n WHERE n = 3");
+        assert!(plan.iter().any(|line| matches!(&line[0], Value::Text(line) if line.contains("index"))), "{:?}", plan);
+        assert!(c.table("n").unwrap().table.indexes[0].valid);
+        let err = execute(b, c, "BEGIN; CREATE INDEX CONCURRENTLY n_id ON n (id)").unwrap_err();
+        assert_eq!("CREATE INDEX CONCURRENTLY cannot run inside a transaction block", err.to_string());
+        execute(b, c, "ROLLBACK").unwrap();
+        assert!(matches!(execute(b, c, "CREATE INDEX CONCURRENTLY n_n ON n (id)"), Err(Error::Catalog(catalog::Error::IndexExists(_)))));
+        // REINDEX rebuilds an index in place of the old one, whose pages the next trees reuse
+        execute(b, c, "REINDEX INDEX n_n").unwrap();
+        let (num_pages, num_free) = (b.num_pages(), c.free_pages().count());
+        assert!(num_free > 1);
+        execute(b, c, "REINDEX TABLE n; REINDEX INDEX n_n").unwrap();
+        assert_eq!((num_pages, num_free), (b.num_pages(), c.free_pages().count()));
+        assert_eq!(ints(&[1500, 1501]), query(b, c, "SELECT id FROM n WHERE n = 10"));
+        assert_eq!(ints(&[150]), query(b, c, "SELECT count(*) FROM n WHERE n = 3"));
+        // the pages of the tables rolled back above aside
+        let report = check::check(b, c, None).unwrap();
+        assert!(report.problems.iter().all(|problem| problem.severity == check::Severity::Warning), "{:?}", report.problems);
+        assert_eq!("index not found: n_id", execute(b, c, "REINDEX INDEX n_id").unwrap_err().to_string());
+        let err = execute(b, c, "BEGIN; REINDEX TABLE n").unwrap_err();
+        assert_eq!("REINDEX cannot run inside a transaction block", err.to_string());
+        assert!(b.block_failed());
+        execute(b, c, "ROLLBACK").unwrap();
+        b.set_locking(false);
+        // vacuum leaves what's seen as it is
+        execute(b, c, "VACUUM; VACUUM t").unwrap();
+        assert_eq!(ints(&[1, 3, 4, 5, 6, 7]), query(b, c, "SELECT id FROM t"));
+        // VACUUM FULL rewrites what vacuum keeps into as few pages as it fits in, and the file is
+        // cut after the last page still used
+        for id in 100..1502 {
+            assert!(c.table("n").unwrap().table.delete(b, &[Value::Int(id)]).unwrap());
+        }
+        b.commit().unwrap();
+        let num_pages = b.num_pages();
+        execute(b, c, "VACUUM FULL n").unwrap();
+        assert!(b.num_pages() < num_pages, "{} {}", b.num_pages(), num_pages);
+        assert!(c.free_pages().all(|page_id| page_id.0 < b.num_pages()));
+        assert_eq!(ints(&[100]), query(b, c, "SELECT count(*) FROM n"));
+        assert_eq!(ints(&[10]), query(b, c, "SELECT count(*) FROM n WHERE n = 3"));
+        let report = check::check(b, c, None).unwrap();
+        assert!(report.problems.iter().all(|problem| problem.severity == check::Severity::Warning), "{:?}", report.problems);
+        let num_pages = b.num_pages();
+        execute(b, c, "VACUUM FULL").unwrap();
+        assert!(b.num_pages() <= num_pages);
+        assert_eq!(ints(&[1, 3, 4, 5, 6, 7]), query(b, c, "SELECT id FROM t"));
+        assert!(execute(b, c, "BEGIN; VACUUM FULL n").is_err());
+        execute(b, c, "ROLLBACK").unwrap();
+        assert!(execute(b, c, "BEGIN; VACUUM").is_err());
+        execute(b, c, "ROLLBACK").unwrap();
+
+        // COPY loads CSV in bulk, into an empty table with its index
+        let csv = wal_dir.path().join("big.csv");
+        let rows: String = (0..3000).map(|i| format!("{};name {}\n", 1000 + i, i)).collect();
+        std::fs::write(&csv, format!("id;name\n{}", rows)).unwrap();
+        execute(b, c, "CREATE TABLE big (id INTEGER PRIMARY KEY, name TEXT, ok BOOLEAN); CREATE INDEX big_name ON big (name)").unwrap();
+        let copy = |options: &str| format!("COPY big FROM '{}' {}", csv.display(), options);
+        let sql = format!("COPY big (id, name) FROM '{}' WITH (DELIMITER ';', HEADER)", csv.display());
+        assert_eq!(vec![QueryResult::RowsAffected(3000)], execute(b, c, &sql).unwrap());
+        assert_eq!(ints(&[3000]), query(b, c, "SELECT count(*) FROM big"));
+        assert_eq!(ints(&[1042]), query(b, c, "SELECT id FROM big WHERE name = 'name 42'"));
+        // or among the rows there
+        std::fs::write(&csv, "5,\"a, b\",true\n4500,x,\n").unwrap();
+        assert_eq!(vec![QueryResult::RowsAffected(2)], execute(b, c, &copy("")).unwrap());
+        assert_eq!(
+            vec![vec![Value::Int(5), Value::Text("a, b".to_string()), Value::Bool(true)], vec![Value::Int(4500), Value::Text("x".to_string()), Value::Null]],
+            query(b, c, "SELECT * FROM big WHERE id < 1000 OR id = 4500")
+        );
+        assert_eq!(ints(&[4500]), query(b, c, "SELECT id FROM big WHERE name = 'x'"));
+        // and a duplicate key or a value which isn't of its column fails all of it
+        std::fs::write(&csv, "6,y,\n1000,dup,\n").unwrap();
+        let err = execute(b, c, &copy("")).unwrap_err();
+        assert!(err.to_string().contains("duplicate key"), "{:?}", err);
+        std::fs::write(&csv, "6|y|maybe\n").unwrap();
+        let err = execute(b, c, &copy("(DELIMITER '|')")).unwrap_err();
+        assert!(err.to_string().starts_with("line 1: column ok"), "{:?}", err);
+        std::fs::write(&csv, "6\n").unwrap();
+        assert!(matches!(execute(b, c, &copy("")), Err(Error::Invalid(_))));
+        assert_eq!(ints(&[3002]), query(b, c, "SELECT count(*) FROM big"));
+        std::fs::remove_file(&csv).unwrap();
+        assert!(matches!(execute(b, c, &copy("")), Err(Error::Io(_))));
+
+        // COPY TO writes what COPY FROM reads back
+        let out = wal_dir.path().join("out.csv");
+        let sql = format!("COPY big TO '{}' WITH (HEADER, NULL 'NULL')", out.display());
+        assert_eq!(vec![QueryResult::RowsAffected(3002)], execute(b, c, &sql).unwrap());
+        execute(b, c, "CREATE TABLE big2 (id INTEGER PRIMARY KEY, name TEXT, ok BOOLEAN)").unwrap();
+        let sql = format!("COPY big2 FROM '{}' (HEADER, NULL 'NULL')", out.display());
+        assert_eq!(vec![QueryResult::RowsAffected(3002)], execute(b, c, &sql).unwrap());
+        assert_eq!(query(b, c, "SELECT * FROM big"), query(b, c, "SELECT * FROM big2"));
+        let sql = format!("COPY big (id, ok) TO '{}'", out.display());
+        execute(b, c, &sql).unwrap();
+        assert!(std::fs::read_to_string(&out).unwrap().starts_with("5,true\n1000,\n"));
+        // and JSON lines, of a query too
+        let sql = format!("COPY (SELECT id, 'say \"hi\"\\' AS s, ok FROM big WHERE id < 1001) TO '{}' FORMAT jsonl", out.display());
+        assert_eq!(vec![QueryResult::RowsAffected(2)], execute(b, c, &sql).unwrap());
+        assert_eq!(
+            "{\"id\":5,\"s\":\"say \\\"hi\\\"\\\\\",\"ok\":true}\n{\"id\":1000,\"s\":\"say \\\"hi\\\"\\\\\",\"ok\":null}\n",
+            std::fs::read_to_string(&out).unwrap()
+        );
+        let sql = format!("COPY big TO '{}'", wal_dir.path().join("none").join("out.csv").display());
+        assert!(matches!(execute(b, c, &sql), Err(Error::Io(_))));
+        // and an Arrow stream, of as many batches as there are rows for
+        #[cfg(feature = "arrow")]
+        {
+            let out = wal_dir.path().join("out.arrows");
+            let sql = format!("COPY big TO '{}' (FORMAT arrow)", out.display());
+            assert_eq!(vec![QueryResult::RowsAffected(3002)], execute(b, c, &sql).unwrap());
+            let stream = std::fs::read(&out).unwrap();
+            assert_eq!([0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0], stream[stream.len() - 8..]);
+            let statement = prepare(c, "SELECT * FROM big WHERE id >= $1").unwrap();
+            let mut batches = vec![];
+            let num_rows = statement
+                .stream(b, c, &[Value::Int(1000)], |rows| {
+                    record_batches(rows, |batch| {
+                        batches.push(batch);
+                        Ok(())
+                    })
+                })
+                .unwrap();
+            assert_eq!(3001, num_rows);
+            assert_eq!(1, batches.len());
+            assert_eq!(vec![Value::Int(1042), Value::Text("name 42".to_string()), Value::Null], batches[0].row(42));
+            let values: Vec<String> = (0..=arrow::BATCH_ROWS).map(|i| format!("({}, NULL, NULL)", i)).collect();
+            execute(b, c, &format!("CREATE TABLE many (id INTEGER PRIMARY KEY, name TEXT, ok BOOLEAN); INSERT INTO many VALUES {}", values.join(", "))).unwrap();
+            let statement = prepare(c, "SELECT * FROM many").unwrap();
+            let sizes = statement
+                .stream(b, c, &[], |rows| {
+                    let mut sizes = vec![];
+                    record_batches(rows, |batch| {
+                        sizes.push(batch.num_rows());
+                        Ok(())
+                    })?;
+                    Ok(sizes)
+                })
+                .unwrap();
+            assert_eq!(vec![arrow::BATCH_ROWS, 1], sizes);
+        }
+
+        // and Parquet
+        #[cfg(feature = "parquet")]
+        {
+            let out = wal_dir.path().join("out.parquet");
+            let sql = format!("COPY big TO '{}' FORMAT parquet", out.display());
+            assert_eq!(vec![QueryResult::RowsAffected(3002)], execute(b, c, &sql).unwrap());
+            execute(b, c, "CREATE TABLE big3 (id INTEGER PRIMARY KEY, name TEXT, ok BOOLEAN)").unwrap();
+            let sql = format!("COPY big3 FROM '{}' FORMAT parquet", out.display());
+            assert_eq!(vec![QueryResult::RowsAffected(3002)], execute(b, c, &sql).unwrap());
+            assert_eq!(query(b, c, "SELECT * FROM big"), query(b, c, "SELECT * FROM big3"));
+            let sql = format!("COPY big3 (id, name) FROM '{}' FORMAT parquet", out.display());
+            let err = execute(b, c, &sql).unwrap_err();
+            assert_eq!("row 1: 3 fields for 2 columns", err.to_string());
+            let sql = format!("COPY (SELECT id, NULL AS n FROM big WHERE id = 5) TO '{}' (FORMAT parquet)", out.display());
+            execute(b, c, &sql).unwrap();
+            let reader = parquet::Reader::new(File::open(&out).unwrap()).unwrap();
+            assert_eq!(vec![("id".to_string(), DataType::Integer), ("n".to_string(), DataType::Text)], reader.columns());
+            let sql = format!("COPY big3 FROM '{}' FORMAT parquet", out.display());
+            let err = execute(b, c, &sql).unwrap_err();
+            assert_eq!("row 1: 2 fields for 3 columns", err.to_string());
+        }
+
+        // upserts, a row whose primary key is taken being left as it is or updated with the
+        // excluded one proposed for it
+        execute(b, c, "CREATE TABLE stock (item TEXT, shop INTEGER, qty INTEGER, note TEXT, PRIMARY KEY (item, shop)); INSERT INTO stock VALUES ('a', 1, 5, NULL)").unwrap();
+        let upsert = "INSERT INTO stock VALUES ('a', 1, 9, 'x'), ('b', 1, 2, 'y') ON CONFLICT DO NOTHING";
+        assert_eq!(vec![QueryResult::RowsAffected(1)], execute(b, c, upsert).unwrap());
+        let upsert = "INSERT INTO stock VALUES ('a', 1, 3, 'z'), ('c', 2, 1, NULL)
+                      ON CONFLICT (shop, item) DO UPDATE SET qty = qty + excluded.qty, note = excluded.note";
+        assert_eq!(vec![QueryResult::RowsAffected(2)], execute(b, c, upsert).unwrap());
+        // updated only where the condition holds of the row there
+        let upsert = "INSERT INTO stock VALUES ('a', 1, 100, NULL), ('b', 1, 100, NULL) ON CONFLICT (item, shop) DO UPDATE SET qty = excluded.qty WHERE stock.qty < 5";
+        assert_eq!(vec![QueryResult::RowsAffected(1)], execute(b, c, upsert).unwrap());
+        let text = |s: &str| Value::Text(s.to_string());
+        assert_eq!(
+            vec![
+                vec![text("a"), Value::Int(1), Value::Int(8), text("z")],
+                vec![text("b"), Value::Int(1), Value::Int(100), text("y")],
+                vec![text("c"), Value::Int(2), Value::Int(1), Value::Null],
+            ],
+            query(b, c, "SELECT * FROM stock")
+        );
+        let err = |b: &mut BufferPoolManager, c: &mut Catalog, sql: &str| execute(b, c, sql).unwrap_err().to_string();
+        assert_eq!(
+            "ON CONFLICT DO UPDATE cannot change a row a second time",
+            err(b, c, "INSERT INTO stock VALUES ('d', 1, 1, NULL), ('d', 1, 2, NULL) ON CONFLICT (item, shop) DO UPDATE SET qty = 0")
+        );
+        assert_eq!(ints(&[3]), query(b, c, "SELECT count(*) FROM stock"));
+        assert_eq!(
+            "the ON CONFLICT columns must be those of the primary key",
+            err(b, c, "INSERT INTO stock VALUES ('a', 1, 1, NULL) ON CONFLICT (item) DO NOTHING")
+        );
+        assert_eq!(
+            "ON CONFLICT DO UPDATE requires the columns of the primary key",
+            err(b, c, "INSERT INTO stock VALUES ('a', 1, 1, NULL) ON CONFLICT DO UPDATE SET qty = 1")
+        );
+        assert_eq!(
+            "ON CONFLICT DO UPDATE cannot change primary key column shop",
+            err(b, c, "INSERT INTO stock VALUES ('a', 1, 1, NULL) ON CONFLICT (item, shop) DO UPDATE SET shop = 2")
+        );
+        assert!(execute(b, c, "INSERT INTO stock VALUES ('a', 1, 1, NULL) ON CONFLICT (item, shop) DO UPDATE SET qty = 'x'").is_err());
+        // and updating needs the privilege to
+        let privileges = |sql: &str| prepare(c, sql).unwrap().privileges.into_iter().map(|(_, privilege)| privilege).collect::<Vec<_>>();
+        assert_eq!(vec![Privilege::Insert], privileges("INSERT INTO stock VALUES ('a', 1, 1, NULL) ON CONFLICT DO NOTHING"));
+        assert_eq!(
+            vec![Privilege::Insert, Privilege::Update],
+            privileges("INSERT INTO stock VALUES ('a', 1, 1, NULL) ON CONFLICT (item, shop) DO UPDATE SET qty = 1")
+        );
+
+        // the rows written returned, those upserted as they are now, with the types known of them
+        let returning = prepare(c, "INSERT INTO stock (item, shop, qty) VALUES ('e', 1, 4), ('a', 1, 1) ON CONFLICT (item, shop) DO UPDATE SET qty = qty * 2 RETURNING item, qty + 1 AS next, *").unwrap();
+        let columns = returning.result_columns().unwrap();
+        assert_eq!(("next".to_string(), Some(DataType::Integer)), columns[1]);
+        assert_eq!(("note".to_string(), Some(DataType::Text)), columns[5]);
+        assert_eq!(
+            QueryResult::Rows {
+                columns: ["item", "next", "item", "shop", "qty", "note"].map(str::to_string).to_vec(),
+                rows: vec![
+                    vec![text("e"), Value::Int(5), text("e"), Value::Int(1), Value::Int(4), Value::Null],
+                    vec![text("a"), Value::Int(17), text("a"), Value::Int(1), Value::Int(16), text("z")],
+                ],
+            },
+            returning.execute(b, c, &[]).unwrap()
+        );
+        assert_eq!(
+            vec![QueryResult::Rows { columns: vec!["?column?".to_string()], rows: vec![] }],
+            execute(b, c, "INSERT INTO stock VALUES ('a', 1, 0, NULL) ON CONFLICT DO NOTHING RETURNING stock.qty = 0").unwrap()
+        );
+        assert!(prepare(c, "INSERT INTO stock VALUES ('f', 1, 0, NULL) RETURNING nothing").is_err());
+        assert!(prepare(c, "INSERT INTO stock VALUES ('f', 1, 0, NULL) RETURNING count(*)").is_err());
+        assert_eq!(ints(&[4]), query(b, c, "SELECT count(*) FROM stock"));
+
+        // UPDATE sets the columns of the rows the condition holds of, from what they were, and
+        // DELETE deletes them, RETURNING the rows as updated or as they were deleted
+        let update = "UPDATE stock SET qty = qty + 1, note = 'n' WHERE shop = 1 AND qty < 50";
+        assert_eq!(vec![QueryResult::RowsAffected(2)], execute(b, c, update).unwrap());
+        assert_eq!(
+            vec![QueryResult::Rows { columns: vec!["item".to_string(), "qty".to_string()], rows: vec![vec![text("c"), Value::Int(0)]] }],
+            execute(b, c, "UPDATE stock SET qty = qty - 1 WHERE item = 'c' RETURNING item, qty").unwrap()
+        );
+        assert_eq!(
+            vec![QueryResult::Rows { columns: vec!["item".to_string(), "qty".to_string()], rows: vec![vec![text("b"), Value::Int(100)]] }],
+            execute(b, c, "DELETE FROM stock WHERE qty >= (SELECT max(qty) FROM stock) RETURNING item, qty").unwrap()
+        );
+        let update = prepare(c, "UPDATE stock SET note = ? WHERE item = ?").unwrap();
+        assert_eq!(QueryResult::RowsAffected(1), update.execute(b, c, &[text("m"), text("e")]).unwrap());
+        assert_eq!(
+            vec![
+                vec![text("a"), Value::Int(17), text("n")],
+                vec![text("c"), Value::Int(0), Value::Null],
+                vec![text("e"), Value::Int(5), text("m")],
+            ],
+            query(b, c, "SELECT item, qty, note FROM stock")
+        );
+        assert_eq!("UPDATE cannot change primary key column item", err(b, c, "UPDATE stock SET item = 'z'"));
+        assert_eq!("column qty is set more than once", err(b, c, "UPDATE stock SET qty = 1, qty = 2"));
+        assert_eq!("table not found: nothing", err(b, c, "DELETE FROM nothing"));
+        assert!(execute(b, c, "UPDATE stock SET qty = note").is_err());
+        // which need the privileges to, and to SELECT if they read the rows
+        let privileges = |sql: &str| prepare(c, sql).unwrap().privileges.into_iter().map(|(_, privilege)| privilege).collect::<Vec<_>>();
+        assert_eq!(vec![Privilege::Update], privileges("UPDATE stock SET qty = 1"));
+        assert_eq!(vec![Privilege::Select, Privilege::Update], privileges("UPDATE stock SET qty = qty + 1"));
+        assert_eq!(vec![Privilege::Delete], privileges("DELETE FROM stock"));
+        assert_eq!(vec![Privilege::Select, Privilege::Delete], privileges("DELETE FROM stock WHERE qty = 0"));
+        assert_eq!(vec![Privilege::Select, Privilege::Delete], privileges("DELETE FROM stock RETURNING item"));
+        assert_eq!(vec![QueryResult::RowsAffected(3)], execute(b, c, "DELETE FROM stock").unwrap());
+        assert!(query(b, c, "SELECT * FROM stock").is_empty());
+
+        // rows of a partitioned table are written to the partition of their key, and read only
+        // from those the predicates on it leave
+        execute(
+            b,
+            c,
+            "CREATE TABLE events (day INTEGER, seq INTEGER, what TEXT, PRIMARY KEY (day, seq)) PARTITION BY RANGE (day);
+             CREATE TABLE events_old PARTITION OF events FOR VALUES FROM (MINVALUE) TO (100);
+             CREATE TABLE events_new PARTITION OF events FOR VALUES FROM (100) TO (200);
+             CREATE TABLE events_next PARTITION OF events FOR VALUES FROM ('200') TO (MAXVALUE);
+             INSERT INTO events VALUES (1, 1, 'a'), (150, 1, 'b'), (99, 2, 'c'), (250, 1, 'd');
+             INSERT INTO events VALUES (100, 1, 'e')",
+        )
+        .unwrap();
+        assert_eq!(ints(&[1, 99]), query(b, c, "SELECT day FROM events_old"));
+        assert_eq!(ints(&[100, 150]), query(b, c, "SELECT day FROM events_new"));
+        assert_eq!(ints(&[1, 99, 100, 150, 250]), query(b, c, "SELECT day FROM events"));
+        let plan = |b: &mut BufferPoolManager, c: &mut Catalog, sql: &str| query(b, c, &format!("EXPLAIN {}", sql)).iter().map(|row| format!("{:?}", row[0])).collect::<String>();
+        let pruned = plan(b, c, "SELECT what FROM events WHERE day >= 100 AND day < 200");
+        assert!(pruned.contains("events_new") && !pruned.contains("events_old") && !pruned.contains("events_next"), "{}", pruned);
+        let pruned = plan(b, c, "SELECT what FROM events WHERE day IN (1, 250) AND seq = 1");
+        assert!(pruned.contains("Append") && pruned.contains("events_old") && pruned.contains("events_next") && !pruned.contains("events_new"), "{}", pruned);
+        assert!(plan(b, c, "SELECT * FROM events").contains("events_new"));
+        assert_eq!(vec![vec![text("d")]], query(b, c, "SELECT what FROM events WHERE day > 200"));
+        assert_eq!(vec![vec![text("a")], vec![text("d")]], query(b, c, "SELECT what FROM events WHERE day IN (1, 250) AND seq = 1"));
+        assert!(query(b, c, "SELECT what FROM events WHERE day = 1 AND day = 250").is_empty());
+        assert_eq!(ints(&[2]), query(b, c, "SELECT count(*) FROM events e JOIN events_new n ON e.day = n.day"));
+        // upserts and loads too, and a row no partition holds fails the statement
+        execute(b, c, "INSERT INTO events VALUES (150, 1, 'x'), (151, 1, 'y') ON CONFLICT (day, seq) DO UPDATE SET what = excluded.what").unwrap();
+        assert_eq!(vec![vec![text("x")], vec![text("y")]], query(b, c, "SELECT what FROM events_new WHERE day > 100"));
+        let mut rows = vec![vec![Value::Int(5), Value::Int(1), Value::Null], vec![Value::Int(500), Value::Int(1), Value::Null]].into_iter();
+        assert_eq!(2, load(b, c, "events", || Ok(rows.next()), None).unwrap());
+        assert_eq!(ints(&[1, 5, 99]), query(b, c, "SELECT day FROM events_old"));
+        assert_eq!(ints(&[8]), query(b, c, "SELECT count(*) FROM events"));
+        assert_eq!("the row is outside the bound of partition events_old", err(b, c, "INSERT INTO events_old VALUES (100, 2, NULL)"));
+        // and updated and deleted in the partitions they're in
+        execute(b, c, "UPDATE events SET what = 'u' WHERE seq = 1 AND day < 200; DELETE FROM events WHERE day = 5").unwrap();
+        assert_eq!(vec![vec![text("u")]; 3], query(b, c, "SELECT what FROM events_new"));
+        assert_eq!(vec![vec![text("c")], vec![text("u")]], query(b, c, "SELECT what FROM events_old"));
+        execute(b, c, "CREATE TABLE parts (id INTEGER PRIMARY KEY, kind TEXT) PARTITION BY HASH (id)").unwrap();
+        assert_eq!("no partition of parts for the row", err(b, c, "INSERT INTO parts VALUES (1, 'a')"));
+        execute(b, c, "CREATE TABLE parts_0 PARTITION OF parts FOR VALUES WITH (MODULUS 2, REMAINDER 0); CREATE TABLE parts_1 PARTITION OF parts FOR VALUES WITH (MODULUS 2, REMAINDER 1)").unwrap();
+        let values: Vec<String> = (0..100).map(|i| format!("({}, 'k')", i)).collect();
+        execute(b, c, &format!("INSERT INTO parts VALUES {}", values.join(", "))).unwrap();
+        assert_eq!(ints(&[100]), query(b, c, "SELECT count(*) FROM parts"));
+        let counts = [query(b, c, "SELECT count(*) FROM parts_0"), query(b, c, "SELECT count(*) FROM parts_1")];
+        assert!(counts.iter().all(|count| count[0][0] != Value::Int(0) && count[0][0] != Value::Int(100)), "{:?}", counts);
+        let pruned = plan(b, c, "SELECT kind FROM parts WHERE id = 42");
+        assert!(pruned.matches("parts_").count() == 1 && query(b, c, "SELECT kind FROM parts WHERE id = 42") == vec![vec![text("k")]], "{}", pruned);
+        assert_eq!("partition parts_2 would overlap partition parts_0", err(b, c, "CREATE TABLE parts_2 PARTITION OF parts FOR VALUES WITH (MODULUS 4, REMAINDER 2)"));
+        assert_eq!("invalid bound of partition e: the lower bound must be below the upper", err(b, c, "CREATE TABLE e PARTITION OF events FOR VALUES FROM (300) TO (300)"));
+        assert_eq!("the partition key must be a column of the primary key", err(b, c, "CREATE TABLE p (id INTEGER PRIMARY KEY, kind TEXT) PARTITION BY RANGE (kind)"));
+        assert_eq!("table is partitioned: parts", err(b, c, "CREATE INDEX parts_kind ON parts (kind)"));
+        execute(b, c, "CREATE INDEX parts_0_kind ON parts_0 (kind)").unwrap();
+
+        // in-memory tables keep their pages in the pool, never evicted, rather than in the data file
+        execute(b, c, "CREATE TABLE hot (id INTEGER PRIMARY KEY, name TEXT) USING memory; CREATE INDEX hot_name ON hot (name); CREATE UNLOGGED TABLE cold (id INTEGER PRIMARY KEY) USING memory").unwrap();
+        let (num_pages, num_memory_pages) = (b.num_pages(), b.num_memory_pages());
+        let values: Vec<String> = (0..300).map(|i| format!("({}, 'name {}')", i, i)).collect();
+        execute(b, c, &format!("INSERT INTO hot VALUES {}; INSERT INTO cold VALUES (1), (2)", values.join(", "))).unwrap();
+        assert!(b.num_pages() == num_pages && b.num_memory_pages() > num_memory_pages + 4);
+        assert_eq!(ints(&[42]), query(b, c, "SELECT id FROM hot WHERE name = 'name 42'"));
+        assert!(execute(b, c, "INSERT INTO hot VALUES (300, 'x'), (0, 'y')").is_err());
+        // they aren't rebuilt, their pages being in no free list
+        assert_eq!("table is in memory: hot", err(b, c, "REINDEX TABLE hot"));
+        assert_eq!("table is in memory: cold", err(b, c, "VACUUM FULL cold"));
+        execute(b, c, "VACUUM FULL").unwrap();
+        assert_eq!("an unlogged table must be in memory (USING memory)", err(b, c, "CREATE UNLOGGED TABLE u (id INTEGER PRIMARY KEY)"));
+        assert_eq!("a temporary table can't be in memory", err(b, c, "CREATE TEMP TABLE u (id INTEGER PRIMARY KEY) USING memory"));
+        assert_eq!("an in-memory table can't be partitioned", err(b, c, "CREATE TABLE u (id INTEGER PRIMARY KEY) PARTITION BY HASH (id) USING memory"));
+        // columnar tables are compacted by vacuum, scans reading only the columns they need
+        execute(b, c, "CREATE TABLE facts (id INTEGER PRIMARY KEY, kind TEXT, amount INTEGER) USING columnar").unwrap();
+        let values: Vec<String> = (0..100).map(|i| format!("({}, 'k{}', {})", i, i % 3, i)).collect();
+        execute(b, c, &format!("INSERT INTO facts VALUES {}; VACUUM facts; INSERT INTO facts VALUES (100, 'k1', 100)", values.join(", "))).unwrap();
+        assert_eq!(vec![ints(&[34, 1717]).concat()], query(b, c, "SELECT count(*), sum(amount) FROM facts WHERE kind = 'k1'"));
+        assert!(plan(b, c, "SELECT sum(amount) FROM facts WHERE kind = 'k1'").contains("Columnar Scan on facts (2 of 3 columns)"));
+        assert!(query(b, c, "SELECT kind FROM facts WHERE id > 1000").is_empty());
+        // and rows compacted are updated and deleted as any other
+        execute(b, c, "INSERT INTO facts VALUES (1, 'x', 0) ON CONFLICT (id) DO UPDATE SET kind = excluded.kind").unwrap();
+        execute(b, c, "INSERT INTO facts VALUES (100, 'k2', 0) ON CONFLICT (id) DO UPDATE SET kind = excluded.kind").unwrap();
+        execute(b, c, "UPDATE facts SET amount = amount + 1000 WHERE kind = 'k0'; DELETE FROM facts WHERE id > 90").unwrap();
+        assert_eq!(vec![ints(&[91, 4095 + 31 * 1000]).concat()], query(b, c, "SELECT count(*), sum(amount) FROM facts"));
+        assert_eq!(vec![vec![text("x")]], query(b, c, "SELECT kind FROM facts WHERE id = 1"));
+        assert_eq!("table is columnar: facts", err(b, c, "CREATE INDEX facts_kind ON facts (kind)"));
+        assert_eq!("a columnar table can't be partitioned", err(b, c, "CREATE TABLE u (id INTEGER PRIMARY KEY) PARTITION BY HASH (id) USING columnar"));
+        assert_eq!("a temporary table can't be columnar", err(b, c, "CREATE TEMP TABLE u (id INTEGER PRIMARY KEY) USING columnar"));
+        // zone maps skip the extents of rows range predicates can't hold for
+        execute(b, c, "CREATE TABLE readings (id INTEGER PRIMARY KEY, at INTEGER, value INTEGER); CREATE INDEX readings_at ON readings USING zonemap (at, value)").unwrap();
+        let values: Vec<String> = (0..600).map(|i| format!("({}, {}, {})", i, i * 10, i % 7)).collect();
+        execute(b, c, &format!("INSERT INTO readings VALUES {}", values.join(", "))).unwrap();
+        assert_eq!(ints(&[5950, 5960, 5970]), query(b, c, "SELECT at FROM readings WHERE at > 5940 AND value < 3"));
+        assert!(plan(b, c, "SELECT at FROM readings WHERE at > 5940").contains("Seq Scan on readings using zone map [1, 2]"));
+        assert!(!plan(b, c, "SELECT at FROM readings WHERE id > 5940").contains("zone map"));
+        execute(b, c, "INSERT INTO readings VALUES (3, 9000, 0) ON CONFLICT (id) DO UPDATE SET at = excluded.at; REINDEX TABLE readings").unwrap();
+        assert_eq!(ints(&[3, 599]), query(b, c, "SELECT id FROM readings WHERE at >= 5990"));
+
+        // the rows of a table with a TTL are read by no one once expired, and make way for new ones
+        execute(b, c, "CREATE TABLE visits (id INTEGER PRIMARY KEY, at INTEGER, page TEXT) TTL at + 3600; CREATE INDEX visits_page ON visits (page)").unwrap();
+        execute(b, c, "INSERT INTO visits VALUES (1, 0, 'a'), (2, 4102444800, 'a'), (3, NULL, 'b')").unwrap();
+        assert_eq!(ints(&[2, 3]), query(b, c, "SELECT id FROM visits"));
+        assert!(query(b, c, "SELECT id FROM visits WHERE id = 1").is_empty());
+        assert_eq!(ints(&[2]), query(b, c, "SELECT id FROM visits WHERE page = 'a'"));
+        execute(b, c, "INSERT INTO visits VALUES (1, 4102444800, 'c'), (3, 0, 'd') ON CONFLICT (id) DO UPDATE SET at = excluded.at").unwrap();
+        assert_eq!(vec![vec![text("c")]], query(b, c, "SELECT page FROM visits WHERE id = 1"));
+        assert_eq!(ints(&[1, 2]), query(b, c, "SELECT id FROM visits"));
+        assert_eq!("the TTL column must be an INTEGER, of seconds since the Unix epoch", err(b, c, "CREATE TABLE u (id INTEGER PRIMARY KEY, at TEXT) TTL at + 1"));
+        assert_eq!("a temporary, partitioned or columnar table can't have a TTL", err(b, c, "CREATE TEMP TABLE u (id INTEGER PRIMARY KEY, at INTEGER) TTL at + 1"));
+        assert!(err(b, c, "CREATE TABLE u (id INTEGER PRIMARY KEY) TTL at + 1").contains("at"));
+
+        // the values of dictionary columns are kept once, in a dictionary of the table, and the
+        // rows take fewer pages for having their codes, which nothing above the table sees
+        execute(b, c, "CREATE TABLE hits (id INTEGER PRIMARY KEY, url TEXT, agent TEXT) DICTIONARY (url, agent); CREATE INDEX hits_url ON hits (url)").unwrap();
+        execute(b, c, "CREATE TABLE plain_hits (id INTEGER PRIMARY KEY, url TEXT, agent TEXT)").unwrap();
+        let agent = |i: i64| if i % 10 == 0 { "NULL".to_string() } else { format!("'Mozilla/5.0 (X11; Linux x86_64) {}'", i % 3) };
+        let values: Vec<_> = (0..1000).map(|i| format!("({}, 'https://example.com/page/{}', {})", i, i % 5, agent(i))).collect();
+        for table in ["hits", "plain_hits"] {
+            execute(b, c, &format!("INSERT INTO {} VALUES {}; INSERT INTO {} VALUES (1000, 'ab', '')", table, values.join(", "), table)).unwrap();
+        }
+        assert_eq!(query(b, c, "SELECT * FROM plain_hits"), query(b, c, "SELECT * FROM hits"));
+        let pages = |b: &mut BufferPoolManager, c: &mut Catalog, name: &str| c.table(name).unwrap().table.btree.pages(b).unwrap().len();
+        assert!(pages(b, c, "hits") < pages(b, c, "plain_hits"), "{} {}", pages(b, c, "hits"), pages(b, c, "plain_hits"));
+        assert_eq!(ints(&[3, 8]), query(b, c, "SELECT id FROM hits WHERE url = 'https://example.com/page/3' AND id < 10"));
+        assert!(plan(b, c, "SELECT id FROM hits WHERE url = 'https://example.com/page/3'").contains("Index Scan"));
+        assert_eq!(ints(&[300]), query(b, c, "SELECT COUNT(*) FROM hits WHERE agent LIKE '%) 1'"));
+        execute(b, c, "INSERT INTO hits VALUES (3, 'https://example.com/other', NULL) ON CONFLICT (id) DO UPDATE SET url = excluded.url; VACUUM hits").unwrap();
+        assert_eq!(vec![vec![text("https://example.com/other"), text("Mozilla/5.0 (X11; Linux x86_64) 0")]], query(b, c, "SELECT url, agent FROM hits WHERE id = 3"));
+        assert_eq!(0, check::check(b, c, Some("hits")).unwrap().errors());
+        assert_eq!("dictionary columns must be distinct TEXT columns outside the primary key", err(b, c, "CREATE TABLE u (id INTEGER PRIMARY KEY, n INTEGER) DICTIONARY (n)"));
+        assert_eq!("dictionary columns must be distinct TEXT columns outside the primary key", err(b, c, "CREATE TABLE u (id TEXT PRIMARY KEY) DICTIONARY (id)"));
+        assert_eq!("a temporary, partitioned or columnar table can't have dictionary columns", err(b, c, "CREATE TABLE u (id INTEGER PRIMARY KEY, s TEXT) USING columnar DICTIONARY (s)"));
+
+        // JSON is taken apart by ->, ->>, #> and #>>, and indexed by paths into it
+        let doc = |text: &str| Value::Json(json::parse(text).unwrap());
+        execute(b, c, "CREATE TABLE docs (id INTEGER PRIMARY KEY, doc JSON); CREATE INDEX docs_kind ON docs ((doc ->> 'kind')); CREATE INDEX docs_tag ON docs ((doc -> 'tags' -> 0))").unwrap();
+        execute(b, c, r#"INSERT INTO docs VALUES (1, '{"kind": "a", "n": 1, "tags": ["x", "y"]}'), (2, '{"tags": ["y"], "kind": "b"}'), (3, NULL), (4, '[1, {"kind": "a"}]')"#).unwrap();
+        assert_eq!(vec![vec![doc(r#"["x","y"]"#), text("a"), text("y"), Value::Int(1)]], query(b, c, "SELECT doc -> 'tags', doc ->> 'kind', doc #>> '{tags,-1}', (doc ->> 'n')::INTEGER FROM docs WHERE id = 1"));
+        assert_eq!(vec![vec![doc(r#"{"kind":"a"}"#), Value::Null, text(r#"{"kind":"b","tags":["y"]}"#)]], query(b, c, "SELECT doc -> 1, doc ->> 'kind', CAST((SELECT doc FROM docs WHERE id = 2) AS TEXT) FROM docs WHERE id = 4"));
+        let plan = |b: &mut BufferPoolManager, c: &mut Catalog, sql: &str| query(b, c, &format!("EXPLAIN {}", sql)).iter().map(|row| format!("{:?}", row[0])).collect::<String>();
+        for (sql, ids, index) in [
+            ("SELECT id FROM docs WHERE doc ->> 'kind' = 'a'", vec![1], true),
+            (r#"SELECT id FROM docs WHERE doc #> '{tags, 0}' = '"y"'"#, vec![2], true),
+            (r#"SELECT id FROM docs WHERE '"x"' = doc -> 'tags' -> 0"#, vec![1], true),
+            ("SELECT id FROM docs WHERE doc #>> '{1,kind}' = 'a'", vec![4], false),
+            (r#"SELECT id FROM docs WHERE doc = '{"tags": ["y"], "kind": "b"}'"#, vec![2], false),
+        ] {
+            assert_eq!(ints(&ids), query(b, c, sql), "{}", sql);
+            assert_eq!(index, plan(b, c, sql).contains("Index Scan"), "{}", sql);
+        }
+        assert_eq!("invalid argument: invalid JSON: expected a value at offset 9", err(b, c, "INSERT INTO docs VALUES (5, '{\"kind\": }')"));
+        assert!(err(b, c, "SELECT id ->> 'kind' FROM docs").contains("JsonGetText"));
+        assert_eq!("column id is not of type json", err(b, c, "CREATE INDEX docs_id ON docs ((id ->> 'a'))"));
+        assert_eq!("an index is on columns, or on paths into JSON columns of ->, ->>, #> and #>> with constants", err(b, c, "CREATE INDEX docs_id ON docs ((id + 1))"));
+        assert_eq!("a zone map index is on columns", err(b, c, "CREATE INDEX docs_zones ON docs USING zonemap ((doc ->> 'kind'))"));
+
+        // arrays are made by ARRAY[...] or of text, subscripted from 1, compared by ANY and ALL and unnested
+        let array = |values: Vec<Value>| Value::Array(values);
+        execute(b, c, "CREATE TABLE posts (id INTEGER PRIMARY KEY, tags TEXT[], scores INTEGER[]); CREATE INDEX posts_tags ON posts (tags)").unwrap();
+        execute(b, c, r#"INSERT INTO posts VALUES (1, ARRAY['a', 'b'], '{3,1}'), (2, '{b,"c d"}', ARRAY[5, NULL]), (3, NULL, '{}')"#).unwrap();
+        assert_eq!(vec![vec![text("a"), Value::Null, Value::Int(1), text("{a,b}")]], query(b, c, "SELECT tags[1], tags[3], scores[2], tags::TEXT FROM posts WHERE id = 1"));
+        assert_eq!(vec![vec![array(vec![text("b"), text("c d")]), array(vec![Value::Int(5), Value::Null])]], query(b, c, "SELECT tags, scores FROM posts WHERE id = 2"));
+        assert_eq!(ints(&[1, 2]), query(b, c, "SELECT id FROM posts WHERE 'b' = ANY (tags)"));
+        assert_eq!(ints(&[2]), query(b, c, "SELECT id FROM posts WHERE 4 < SOME (scores)"));
+        assert_eq!(ints(&[1, 3]), query(b, c, "SELECT id FROM posts WHERE 0 < ALL (scores)"));
+        assert_eq!(ints(&[2]), query(b, c, r#"SELECT id FROM posts WHERE tags = '{b,"c d"}'"#));
+        assert!(plan(b, c, r#"SELECT id FROM posts WHERE tags = '{b,"c d"}'"#).contains("Index Scan"));
+        // UNNEST may read the columns of the FROM items before it
+        assert_eq!(vec![vec![Value::Int(1), text("b")], vec![Value::Int(2), text("b")], vec![Value::Int(2), text("c d")]], query(b, c, "SELECT p.id, tag FROM posts p, unnest(p.tags) AS tag WHERE tag <> 'a' AND p.id < 3"));
+        assert_eq!(ints(&[4, 6]), query(b, c, "SELECT n * 2 FROM unnest(ARRAY[1, 2, 3]) AS t(n) WHERE n > 1"));
+        assert_eq!(ints(&[5]), query(b, c, "SELECT sum(id) FROM unnest('{2,3,9}'::INTEGER[]) AS u(k) JOIN posts ON id = k"));
+        assert_eq!(vec![ints(&[1, 1]).concat(), ints(&[2, 1]).concat(), ints(&[3, 0]).concat()], query(b, c, "SELECT id, (SELECT count(*) FROM unnest(scores) s WHERE s > 2) FROM posts"));
+        assert_eq!(ints(&[2]), query(b, c, "SELECT id FROM posts WHERE EXISTS (SELECT 1 FROM unnest(tags) t WHERE t LIKE 'c%')"));
+        assert!(err(b, c, "INSERT INTO posts VALUES (4, ARRAY[1], NULL)").contains("tags"));
+        assert_eq!("invalid argument: invalid input syntax for type integer: \"x\"", err(b, c, "SELECT ARRAY[1, 'x']"));
+        assert_eq!("ARRAY elements of types integer and boolean", err(b, c, "SELECT ARRAY[1, true]"));
+        assert_eq!("invalid argument: malformed array literal: \"{1,x}\"", err(b, c, "SELECT '{1,x}'::INTEGER[]"));
+        assert_eq!("unnest of type integer, which is not an array", err(b, c, "SELECT * FROM unnest(1)"));
+        assert!(err(b, c, "CREATE TABLE u (id INTEGER PRIMARY KEY, a JSON[])").contains("arrays of type json are not supported"));
+
+        // full-text indexes find the rows with every word of a query, those with the most first,
+        // kept up to date by writes
+        execute(b, c, "CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT); INSERT INTO notes VALUES (1, 'The quick brown fox'), (2, 'A lazy dog'), (3, 'Fox? FOX! and the brown fox'), (4, NULL)").unwrap();
+        execute(b, c, "CREATE INDEX notes_body ON notes USING fulltext (body)").unwrap();
+        // in the order they're found in, which `query` doesn't keep
+        let ranked = |b: &mut BufferPoolManager, c: &mut Catalog, sql: &str| match execute(b, c, sql).unwrap().pop() {
+            Some(QueryResult::Rows { rows, .. }) => rows,
+            result => panic!("unexpected result: {:?}", result),
+        };
+        assert_eq!(ints(&[3, 1]), ranked(b, c, "SELECT id FROM notes WHERE body @@ 'brown fox'"));
+        assert!(plan(b, c, "SELECT id FROM notes WHERE body @@ 'brown fox'").contains("Full-Text Scan on notes"));
+        execute(b, c, "INSERT INTO notes VALUES (5, 'fox brown fox fox fox'); INSERT INTO notes VALUES (1, 'a dog') ON CONFLICT (id) DO UPDATE SET body = excluded.body").unwrap();
+        assert_eq!(ints(&[5, 3]), ranked(b, c, "SELECT id FROM notes WHERE body @@ 'brown fox'"));
+        assert_eq!(ints(&[1, 2]), query(b, c, "SELECT id FROM notes WHERE body @@ 'DOG' AND id > 0"));
+        assert!(query(b, c, "SELECT id FROM notes WHERE body @@ '?!'").is_empty());
+        assert_eq!(vec![ints(&[1, 0]).concat(), ints(&[3, 3]).concat()], query(b, c, "SELECT id, ts_rank(body, 'fox') FROM notes WHERE id IN (1, 3)"));
+        assert_eq!("a full-text index is on one column of type text", err(b, c, "CREATE INDEX notes_id ON notes USING fulltext (id)"));
+
+        // R-tree indexes find the rows whose point or box is in, contains or overlaps a box
+        execute(b, c, "CREATE TABLE places (id INTEGER PRIMARY KEY, at POINT, area BOX)").unwrap();
+        execute(b, c, "INSERT INTO places VALUES (1, '(1,1)', '((0,0),(2,2))'), (2, '(5,5)', '((4,4),(9,9))'), (3, point(8, 2), box(point(7, 1), point(9, 3))), (4, NULL, NULL)").unwrap();
+        execute(b, c, "CREATE INDEX places_at ON places USING rtree (at); CREATE INDEX places_area ON places USING rtree (area)").unwrap();
+        assert_eq!(ints(&[1, 2]), query(b, c, "SELECT id FROM places WHERE at <@ '((0,0),(5,5))'"));
+        assert!(plan(b, c, "SELECT id FROM places WHERE at <@ '((0,0),(5,5))'").contains("Spatial Scan on places"));
+        assert_eq!(ints(&[2]), query(b, c, "SELECT id FROM places WHERE area @> '(6,6)'"));
+        assert_eq!(ints(&[2, 3]), query(b, c, "SELECT id FROM places WHERE '((6,2),(7,4))' && area"));
+        assert_eq!(ints(&[1]), query(b, c, "SELECT id FROM places WHERE area @> '((1,1),(2,2))'"));
+        execute(b, c, "INSERT INTO places VALUES (1, '(6,6)', NULL) ON CONFLICT (id) DO UPDATE SET at = excluded.at, area = excluded.area").unwrap();
+        assert_eq!(ints(&[2]), query(b, c, "SELECT id FROM places WHERE at <@ '((0,0),(5,5))'"));
+        assert_eq!(ints(&[1, 2]), query(b, c, "SELECT id FROM places WHERE at <@ '((6,6),(5,5))'"));
+        assert_eq!(vec![vec![text("(9,9),(4,4)")]], query(b, c, "SELECT area::TEXT FROM places WHERE id = 2"));
+        assert_eq!("an R-tree index is on one column of type point or box", err(b, c, "CREATE INDEX places_id ON places USING rtree (id)"));
+
+        // double precision, numeric, date and timestamp columns, numbers of different types
+        // compared and combined as the one they're converted to
+        execute(b, c, "CREATE TABLE prices (day DATE PRIMARY KEY, at TIMESTAMP, price NUMERIC, rate DOUBLE PRECISION); CREATE INDEX prices_price ON prices (price)").unwrap();
+        execute(b, c, "INSERT INTO prices VALUES ('2024-02-28', '2024-02-28 09:30', 10.50, 0.5), (DATE '2024-02-29', TIMESTAMP '2024-02-29 10:00:00.25', 9.75, 1.5e0), ('2024-03-01', NULL, 12, '2')").unwrap();
+        let number = |s: &str| Value::Numeric(Numeric::parse(s).unwrap());
+        assert_eq!(vec![vec![number("10.50"), Value::Float(Float(0.5))]], query(b, c, "SELECT price, rate FROM prices WHERE day = '2024-02-28'"));
+        assert_eq!(vec![vec![text("2024-02-29"), text("2024-02-29 10:00:00.25")]], query(b, c, "SELECT day::TEXT, at::TEXT FROM prices WHERE price < 10"));
+        assert_eq!(vec![vec![Value::Date(19782)]], query(b, c, "SELECT day FROM prices WHERE at > DATE '2024-02-29'"));
+        assert_eq!(vec![vec![number("32.25"), Value::Float(Float(4.0))]], query(b, c, "SELECT sum(price), sum(rate) FROM prices"));
+        assert_eq!(ints(&[2]), query(b, c, "SELECT count(*) FROM prices WHERE price > 10 AND rate >= 0.5"));
+        assert_eq!(vec![vec![number("21.00"), Value::Float(Float(5.25)), Value::Date(19782), Value::Int(2)]], query(b, c, "SELECT price * 2, price * rate + 0, day + 1, '2024-03-01' - day FROM prices WHERE day = '2024-02-28'"));
+        assert_eq!(vec![vec![number("3.3333333333333333"), Value::Int(11), Value::Float(Float(-2.5)), Value::Timestamp(19783 * datetime::MICROS_PER_DAY)]], query(b, c, "SELECT 10 / 3.0, CAST(10.5 AS INTEGER), -'2.5'::FLOAT, '2024-03-01'::DATE::TIMESTAMP"));
+        assert!(plan(b, c, "SELECT day FROM prices WHERE price = 12").contains("Index Scan on prices"));
+        assert_eq!(ints(&[3]), query(b, c, "SELECT count(*) FROM prices WHERE price > 9"));
+        assert!(matches!(execute(b, c, "SELECT 1.5 / 0"), Err(Error::Query(query::Error::DivisionByZero))));
+        assert_eq!("invalid argument: invalid input syntax for type date: \"2024-02-30\"", err(b, c, "INSERT INTO prices VALUES ('2024-02-30', NULL, 1, 1)"));
+
+        // triggers run INSERTs for the rows written, before or after, which refer to them as NEW and OLD
+        execute(b, c, "CREATE TABLE accounts (id INTEGER PRIMARY KEY, balance INTEGER); CREATE TABLE ledger (seq INTEGER PRIMARY KEY, account INTEGER, old INTEGER, new INTEGER)").unwrap();
+        execute(b, c, "CREATE TRIGGER opened AFTER INSERT ON accounts FOR EACH ROW BEGIN INSERT INTO ledger VALUES (new.id * 10, new.id, old.balance, new.balance); END").unwrap();
+        execute(b, c, "CREATE TRIGGER withdrawn BEFORE UPDATE ON accounts WHEN new.balance < old.balance BEGIN INSERT INTO ledger (seq, account, new) VALUES (new.id * 10 + 1, new.id, new.balance); INSERT INTO ledger VALUES (new.id * 10 + 2, new.id, old.balance, NULL); END").unwrap();
+        execute(b, c, "INSERT INTO accounts VALUES (1, 100), (2, 50)").unwrap();
+        execute(b, c, "INSERT INTO accounts VALUES (1, 70), (2, 80) ON CONFLICT (id) DO UPDATE SET balance = excluded.balance").unwrap();
+        let entry = |values: [Option<i64>; 4]| values.iter().map(|v| v.map_or(Value::Null, Value::Int)).collect::<Vec<_>>();
+        let expected = vec![entry([Some(10), Some(1), None, Some(100)]), entry([Some(11), Some(1), None, Some(70)]), entry([Some(12), Some(1), Some(100), None]), entry([Some(20), Some(2), None, Some(50)])];
+        assert_eq!(expected, query(b, c, "SELECT * FROM ledger"));
+        // of UPDATE and DELETE too, OLD being the row deleted
+        execute(b, c, "CREATE TRIGGER closed AFTER DELETE ON accounts BEGIN INSERT INTO ledger VALUES (old.id * 10 + 3, old.id, old.balance, new.balance); END").unwrap();
+        execute(b, c, "UPDATE accounts SET balance = balance - 20 WHERE id = 2; DELETE FROM accounts WHERE id = 1").unwrap();
+        let expected = vec![entry([Some(13), Some(1), Some(70), None]), entry([Some(21), Some(2), None, Some(60)]), entry([Some(22), Some(2), Some(80), None])];
+        assert_eq!(expected, query(b, c, "SELECT * FROM ledger WHERE seq = 13 OR seq > 20"));
+        assert_eq!("column not found: new.nothing", err(b, c, "CREATE TRIGGER bad AFTER INSERT ON accounts BEGIN INSERT INTO ledger VALUES (new.nothing, 1, 1, 1); END"));
+        assert_eq!("table not found: nothing", err(b, c, "CREATE TRIGGER bad AFTER INSERT ON nothing BEGIN INSERT INTO ledger VALUES (1, 1, 1, 1); END"));
+        assert_eq!("trigger already exists: opened", err(b, c, "CREATE TRIGGER opened AFTER UPDATE ON ledger BEGIN INSERT INTO ledger VALUES (1, 1, 1, 1); END"));
+        execute(b, c, "CREATE TABLE chain (n INTEGER PRIMARY KEY); CREATE TRIGGER again AFTER INSERT ON chain BEGIN INSERT INTO chain VALUES (new.n + 1); END").unwrap();
+        assert_eq!("trigger again nested more than 16 deep", err(b, c, "INSERT INTO chain VALUES (1)"));
+
+        // DROP and ALTER TABLE change the catalog as the transaction's changes, undone by its rollback
+        execute(b, c, "CREATE TABLE gone (id INTEGER PRIMARY KEY, n INTEGER); CREATE INDEX gone_n ON gone (n); CREATE VIEW gone_ids AS SELECT id FROM gone").unwrap();
+        execute(b, c, "INSERT INTO gone VALUES (1, 10), (2, 20); CREATE USER reader; GRANT SELECT ON gone TO reader").unwrap();
+        execute(b, c, "BEGIN; ALTER TABLE gone ADD COLUMN note TEXT; ALTER TABLE gone RENAME COLUMN n TO m; DROP INDEX gone_n; DROP VIEW gone_ids").unwrap();
+        execute(b, c, "ALTER TABLE gone RENAME TO went").unwrap();
+        let row = |id: i64, n: i64, note: Option<&str>| vec![Value::Int(id), Value::Int(n), note.map_or(Value::Null, text)];
+        assert_eq!(vec![row(1, 10, None), row(2, 20, None)], query(b, c, "SELECT * FROM went"));
+        assert_eq!(vec![Privilege::Select], c.privileges("went", "reader"));
+        assert_eq!("table not found: gone", err(b, c, "SELECT * FROM gone"));
+        execute(b, c, "ROLLBACK").unwrap();
+        assert_eq!(ints(&[2]), query(b, c, "SELECT id FROM gone WHERE n = 20"));
+        assert!(format!("{:?}", query(b, c, "EXPLAIN SELECT id FROM gone WHERE n = 20")).contains("Index Scan"));
+        assert_eq!(ints(&[1, 2]), query(b, c, "SELECT * FROM gone_ids"));
+        assert_eq!(vec![Privilege::Select], c.privileges("gone", "reader"));
+        // and kept once committed, the rows there being NULL in the column added
+        execute(b, c, "ALTER TABLE gone ADD note TEXT; INSERT INTO gone VALUES (3, 30, 'c'); UPDATE gone SET note = 'a' WHERE id = 1").unwrap();
+        assert_eq!(vec![row(1, 10, Some("a")), row(2, 20, None), row(3, 30, Some("c"))], query(b, c, "SELECT * FROM gone"));
+        assert_eq!(ints(&[2]), query(b, c, "SELECT id FROM gone WHERE note IS NULL"));
+        let report = check::check(b, c, None).unwrap();
+        assert!(report.problems.iter().all(|problem| problem.severity == check::Severity::Warning), "{:?}", report.problems);
+        assert_eq!("column already exists: note", err(b, c, "ALTER TABLE gone ADD note INTEGER"));
+        assert_eq!("column not found: nothing", err(b, c, "ALTER TABLE gone RENAME nothing TO something"));
+        assert_eq!("table has triggers: accounts", err(b, c, "ALTER TABLE accounts RENAME TO holdings"));
+        // the pages dropped being free for others
+        let num_free = c.free_pages().count();
+        execute(b, c, "DROP INDEX gone_n; DROP VIEW gone_ids; DROP TABLE gone").unwrap();
+        assert!(c.free_pages().count() > num_free);
+        assert!(c.privileges("gone", "reader").is_empty());
+        assert_eq!("table not found: gone", err(b, c, "DROP TABLE gone"));
+        assert_eq!("index not found: gone_n", err(b, c, "DROP INDEX gone_n"));
+        execute(b, c, "DROP TABLE IF EXISTS gone; DROP VIEW IF EXISTS gone_ids").unwrap();
+        let report = check::check(b, c, None).unwrap();
+        assert!(report.problems.iter().all(|problem| problem.severity == check::Severity::Warning), "{:?}", report.problems);
+        execute(b, c, "CREATE TABLE named (id INTEGER PRIMARY KEY); INSERT INTO named VALUES (1); ALTER TABLE named RENAME TO renamed; ALTER TABLE renamed ADD flag BOOLEAN").unwrap();
+
+        // the pages of logged ones are logged whole at a checkpoint, changes after it as usual
+        b.checkpoint().unwrap();
+        execute(b, c, "INSERT INTO hot VALUES (300, 'name 300'), (0, 'zero') ON CONFLICT (id) DO UPDATE SET name = excluded.name").unwrap();
+
+        // and committed ones survive a crash
+        drop(bufmgr);
+        let mut bufmgr = open();
+        let mut catalog = Catalog::open(&mut bufmgr).unwrap();
+        catalog.clear_unlogged_tables(&mut bufmgr).unwrap();
+        // those of logged in-memory tables brought back from the log, unlogged ones left empty
+        assert_eq!(vec![ints(&[301, 0, 300]).concat()], query(&mut bufmgr, &mut catalog, "SELECT count(*), min(id), max(id) FROM hot"));
+        assert_eq!(ints(&[0]), query(&mut bufmgr, &mut catalog, "SELECT id FROM hot WHERE name = 'zero'"));
+        assert!(query(&mut bufmgr, &mut catalog, "SELECT * FROM cold").is_empty());
+        execute(&mut bufmgr, &mut catalog, "INSERT INTO cold VALUES (1), (2)").unwrap();
+        assert_eq!(ints(&[2]), query(&mut bufmgr, &mut catalog, "SELECT count(*) FROM cold"));
+        assert_eq!(ints(&[1, 3, 4, 5, 6, 7]), query(&mut bufmgr, &mut catalog, "SELECT id FROM t"));
+        assert_eq!(ints(&[3002]), query(&mut bufmgr, &mut catalog, "SELECT count(*) FROM big"));
+        assert_eq!(ints(&[1042]), query(&mut bufmgr, &mut catalog, "SELECT id FROM big WHERE name = 'name 42'"));
+        assert_eq!(ints(&[150, 151]), query(&mut bufmgr, &mut catalog, "SELECT day FROM events WHERE day > 100 AND day < 200"));
+        assert_eq!(ints(&[100]), query(&mut bufmgr, &mut catalog, "SELECT count(*) FROM parts"));
+        assert_eq!(vec![ints(&[29, 1334]).concat()], query(&mut bufmgr, &mut catalog, "SELECT count(*), sum(amount) FROM facts WHERE kind = 'k1'"));
+        assert_eq!(vec![vec![text("x")]], query(&mut bufmgr, &mut catalog, "SELECT kind FROM facts WHERE id = 1"));
+        assert_eq!(ints(&[1, 2]), query(&mut bufmgr, &mut catalog, "SELECT id FROM visits"));
+        let plan = query(&mut bufmgr, &mut catalog, "EXPLAIN SELECT id FROM docs WHERE doc ->> 'kind' = 'b'");
+        assert!(format!("{:?}", plan).contains("Index Scan"), "{:?}", plan);
+        assert_eq!(ints(&[2]), query(&mut bufmgr, &mut catalog, "SELECT id FROM docs WHERE doc ->> 'kind' = 'b'"));
+        assert_eq!(vec![vec![Value::Array(vec![Value::Int(5), Value::Null])]], query(&mut bufmgr, &mut catalog, "SELECT scores FROM posts WHERE 'c d' = ANY (tags)"));
+        assert_eq!(ints(&[5, 3]), ranked(&mut bufmgr, &mut catalog, "SELECT id FROM notes WHERE body @@ 'FOX'"));
+        assert_eq!(ints(&[2, 3]), query(&mut bufmgr, &mut catalog, "SELECT id FROM places WHERE area && '((9,0),(9,9))'"));
+        assert_eq!(vec![vec![Value::Int(1), Value::Null]], query(&mut bufmgr, &mut catalog, "SELECT * FROM renamed"));
+        assert!(catalog.table("named").is_none() && catalog.table("gone").is_none());
+        execute(&mut bufmgr, &mut catalog, "INSERT INTO accounts VALUES (3, 5)").unwrap();
+        assert_eq!(vec![entry([Some(30), Some(3), None, Some(5)])], query(&mut bufmgr, &mut catalog, "SELECT * FROM ledger WHERE account = 3"));
+    }
+}
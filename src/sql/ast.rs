@@ -0,0 +1,427 @@
+pub use crate::catalog::Privilege;
+pub use crate::csv::CsvOptions;
+pub use crate::lock::LockMode;
+pub use crate::mvcc::Isolation;
+pub use crate::optimizer::JoinMethod;
+pub use crate::partition::Strategy as PartitionStrategy;
+pub use crate::query::expr::{BinaryOp, Function, UnaryOp};
+pub use crate::query::{AggregateFunc, Frame, FrameBound, FrameUnits, SetOperator, WindowFunc};
+pub use crate::table::IndexKind;
+pub use crate::trigger::{Event as TriggerEvent, Timing as TriggerTiming};
+pub use crate::tuple::DataType;
+use crate::tuple::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    CreateTable(CreateTable),
+    CreateIndex(CreateIndex),
+    CreateView(CreateView),
+    CreateTrigger(CreateTrigger),
+    // DROP TABLE | INDEX | VIEW [IF EXISTS] name, a partitioned table with its partitions
+    Drop { kind: ObjectKind, name: String, if_exists: bool },
+    // ALTER TABLE name ...
+    AlterTable { table: String, action: AlterTable },
+    Insert(Insert),
+    Update(Update),
+    Delete(Delete),
+    Select(Box<Query>),
+    Explain { query: Box<Query>, analyze: bool },
+    // ANALYZE without a table name analyzes every table
+    Analyze(Option<String>),
+    // so does VACUUM, which with FULL rewrites the tables into as few pages as they fit in,
+    // shrinking the file
+    Vacuum { table: Option<String>, full: bool },
+    // and CHECKDB, checking the catalog too
+    Check(Option<String>),
+    // REINDEX INDEX name, or REINDEX TABLE name to rebuild each index of the table
+    Reindex { name: String, table: bool },
+    // BEGIN without an isolation level begins a transaction of the session's default level, read
+    // committed unless set otherwise, a read write one unless READ ONLY
+    Begin { isolation: Option<Isolation>, read_only: bool },
+    Commit,
+    Rollback,
+    LockTable { table: String, mode: LockMode },
+    CopyFrom(CopyFrom),
+    CopyTo(CopyTo),
+    // SET name = value / TO value, the value None for DEFAULT, a word or words being text
+    Set { name: String, value: Option<Value> },
+    // SHOW name, or SHOW ALL if None
+    Show(Option<String>),
+    // CREATE USER name [WITH] [SUPERUSER] [PASSWORD 'password'], a user without one being unable
+    // to log in
+    CreateUser { name: String, password: Option<String>, superuser: bool },
+    // ALTER USER name [WITH] PASSWORD 'password' | NULL
+    AlterUser { name: String, password: Option<String> },
+    // GRANT privilege, ... | ALL [PRIVILEGES] ON [TABLE] table TO user, ...
+    Grant(Grant),
+    // REVOKE ... FROM user, ...
+    Revoke(Grant),
+    // CANCEL QUERY session_id, or CANCEL SESSION session_id to terminate the session
+    Cancel { session: u64, terminate: bool },
+    // DECLARE name CURSOR FOR query, in a transaction block, open until closed or it ends
+    DeclareCursor { name: String, query: Box<Query> },
+    // FETCH [NEXT | count | ALL | FORWARD [count | ALL]] [FROM | IN] name, the count None for ALL
+    Fetch { name: String, count: Option<u64> },
+    // CLOSE name, or CLOSE ALL if None
+    CloseCursor(Option<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    Table,
+    Index,
+    View,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlterTable {
+    // ADD [COLUMN] name type, NULL in the rows there are
+    AddColumn(ColumnDef),
+    // RENAME TO name
+    Rename(String),
+    // RENAME [COLUMN] column TO name
+    RenameColumn { column: String, new_name: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grant {
+    pub privileges: Vec<Privilege>,
+    pub table: String,
+    pub users: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDef {
+    pub name: String,
+    pub data_type: DataType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTable {
+    pub name: String,
+    // CREATE TEMP | TEMPORARY TABLE, of the session only
+    pub temporary: bool,
+    // CREATE UNLOGGED TABLE, emptied each time the database is opened
+    pub unlogged: bool,
+    pub columns: Vec<ColumnDef>,
+    pub primary_key: Vec<String>,
+    // PARTITION BY RANGE | HASH (column)
+    pub partition_by: Option<(PartitionStrategy, String)>,
+    // PARTITION OF parent FOR VALUES ..., of a table with the columns of the parent, which are
+    // left empty here
+    pub partition_of: Option<(String, PartitionBound)>,
+    // USING disk, memory or columnar
+    pub engine: Engine,
+    // TTL column + seconds, after which the rows expire (see `table::Ttl`)
+    pub ttl: Option<(String, i64)>,
+    // DICTIONARY (column, ...), of the text columns whose values are kept in a dictionary of the
+    // table (see `dictionary`)
+    pub dictionary: Vec<String>,
+}
+
+// How the rows of a table are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    // in the data file, as they are unless the table is created otherwise
+    Disk,
+    // in memory rather than in the data file
+    Memory,
+    // in the data file, compacted into stripes of columns (see `columnar`)
+    Columnar,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PartitionBound {
+    // FROM (lower) TO (upper), None for MINVALUE and MAXVALUE
+    Range { lower: Option<Box<Expr>>, upper: Option<Box<Expr>> },
+    // WITH (MODULUS modulus, REMAINDER remainder)
+    Hash { modulus: u64, remainder: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateIndex {
+    pub name: String,
+    pub table: String,
+    // columns, or in parentheses expressions of them
+    pub columns: Vec<Expr>,
+    // built without locking the table against writes, in transactions of its own
+    pub concurrently: bool,
+    // USING btree by default, or fulltext or rtree
+    pub kind: IndexKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateView {
+    pub name: String,
+    // None to name the columns after the query's
+    pub columns: Option<Vec<String>>,
+    pub query: Box<Query>,
+    // text of `query` as written, which is what the catalog keeps
+    pub sql: String,
+}
+
+// CREATE TRIGGER name {BEFORE | AFTER} {INSERT | UPDATE | DELETE} ON table [FOR EACH ROW]
+// [WHEN condition] BEGIN insert; ... END, run for each row written, whose images the condition
+// and the INSERTs refer to as the tables `new` and `old` (see `trigger`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTrigger {
+    pub name: String,
+    pub table: String,
+    pub timing: TriggerTiming,
+    pub event: TriggerEvent,
+    pub when: Option<Expr>,
+    pub body: Vec<Insert>,
+    // text of the statement as written, which is what the catalog keeps
+    pub sql: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Insert {
+    pub table: String,
+    pub columns: Option<Vec<String>>,
+    pub rows: Vec<Vec<Expr>>,
+    pub on_conflict: Option<OnConflict>,
+    // RETURNING, of the rows it inserts or updates
+    pub returning: Option<Vec<SelectItem>>,
+}
+
+// UPDATE table SET column = expr, ... [WHERE condition] [RETURNING ...], the expressions being of
+// the row as it was, and the columns set other than those of the primary key
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+    pub table: String,
+    pub assignments: Vec<(String, Expr)>,
+    pub condition: Option<Expr>,
+    // of the rows as updated
+    pub returning: Option<Vec<SelectItem>>,
+}
+
+// DELETE FROM table [WHERE condition] [RETURNING ...]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delete {
+    pub table: String,
+    pub condition: Option<Expr>,
+    // of the rows as they were
+    pub returning: Option<Vec<SelectItem>>,
+}
+
+// ON CONFLICT [(column, ...)] DO NOTHING | DO UPDATE SET column = expr, ... [WHERE condition],
+// the columns being those of the primary key, the one unique constraint of a table
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnConflict {
+    pub target: Option<Vec<String>>,
+    pub action: ConflictAction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConflictAction {
+    Nothing,
+    // of the row there, the one proposed for it being EXCLUDED
+    Update { assignments: Vec<(String, Expr)>, condition: Option<Expr> },
+}
+
+// COPY table [(column, ...)] FROM 'path' [[WITH] (option, ...)], loading a CSV or Parquet file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopyFrom {
+    pub table: String,
+    // None for all of them, in order
+    pub columns: Option<Vec<String>>,
+    pub path: String,
+    pub format: CopyFormat,
+}
+
+// COPY table [(column, ...)] TO 'path', or COPY (query) TO 'path', [[WITH] (option, ...)],
+// writing the rows of the table, which the parser makes a query of, to a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopyTo {
+    pub query: Box<Query>,
+    pub path: String,
+    pub format: CopyFormat,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CopyFormat {
+    Csv(CsvOptions),
+    // a JSON object a line, of the columns by name
+    JsonLines,
+    Parquet,
+    // the Arrow IPC stream format
+    Arrow,
+}
+
+// A SELECT, or set operations combining the rows of SELECTs, optionally with a WITH clause.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Select(Box<Select>),
+    SetOperation {
+        op: SetOperator,
+        // keep duplicates
+        all: bool,
+        left: Box<Query>,
+        right: Box<Query>,
+    },
+    // the CTEs can be referred to by name in `body` and in the CTEs after them, and with
+    // `recursive` in their own query too
+    With {
+        recursive: bool,
+        ctes: Vec<Cte>,
+        body: Box<Query>,
+    },
+}
+
+// A common table expression, i.e. a named query of a WITH clause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cte {
+    pub name: String,
+    // None to name the columns after the query's
+    pub columns: Option<Vec<String>>,
+    // [NOT] MATERIALIZED, None to decide from how many times the CTE is referred to
+    pub materialized: Option<bool>,
+    pub query: Box<Query>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    pub hints: Vec<Hint>,
+    pub distinct: bool,
+    pub projection: Vec<SelectItem>,
+    pub from: Vec<TableRef>,
+    pub selection: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    pub having: Option<Expr>,
+}
+
+// An optimizer hint, of those in a `/*+ ... */` comment right after SELECT, which has the query
+// planned as it says where it can be, whatever the estimates. Tables are named as in FROM.
+//   SEQSCAN(t)                 t read whole
+//   INDEX(t [index])           t read through the index, or through any if none is named
+//   LEADING(t1 t2 ...)         the tables joined first, in that order
+//   HASHJOIN(t1 t2 ...)        the tables joined by hashing, once they all are, and likewise
+//                              MERGEJOIN and NESTLOOP
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hint {
+    SeqScan(String),
+    Index { table: String, index: Option<String> },
+    Leading(Vec<String>),
+    Join { method: JoinMethod, tables: Vec<String> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    Wildcard,
+    Expr { expr: Expr, alias: Option<String> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableRef {
+    Table {
+        name: String,
+        alias: Option<String>,
+    },
+    Join {
+        left: Box<TableRef>,
+        right: Box<TableRef>,
+        on: Option<Expr>,
+    },
+    // unnest(expr) [AS] alias [(column)], a row for each element of the array, which may be of
+    // the columns of the FROM items before it
+    Unnest {
+        expr: Expr,
+        alias: Option<String>,
+        column: Option<String>,
+    },
+    // name(args) [AS] alias [(columns)], the rows of a table function
+    Function {
+        name: String,
+        args: Vec<Expr>,
+        alias: Option<String>,
+        columns: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    // bound parameter, numbered from 0
+    Parameter(usize),
+    Column {
+        table: Option<String>,
+        name: String,
+    },
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    InList {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    InSubquery {
+        expr: Box<Expr>,
+        subquery: Box<Query>,
+        negated: bool,
+    },
+    Exists {
+        subquery: Box<Query>,
+        negated: bool,
+    },
+    Subquery(Box<Query>),
+    Like {
+        expr: Box<Expr>,
+        pattern: Box<Expr>,
+        negated: bool,
+        case_insensitive: bool,
+    },
+    Function {
+        func: Function,
+        args: Vec<Expr>,
+    },
+    // of a function registered (see `udf`), by name
+    Call {
+        name: String,
+        args: Vec<Expr>,
+    },
+    // expr op ANY (array), or ALL
+    Quantified {
+        op: BinaryOp,
+        expr: Box<Expr>,
+        array: Box<Expr>,
+        all: bool,
+    },
+    // CAST(expr AS data_type), or expr::data_type
+    Cast {
+        expr: Box<Expr>,
+        data_type: DataType,
+    },
+    // `count(*)` has no argument
+    Aggregate {
+        func: AggregateFunc,
+        arg: Option<Box<Expr>>,
+        distinct: bool,
+    },
+    // func(args) OVER (PARTITION BY partition_by ORDER BY order_by frame)
+    Window {
+        func: WindowFunc,
+        args: Vec<Expr>,
+        partition_by: Vec<Expr>,
+        order_by: Vec<OrderBy>,
+        // None for the default frame
+        frame: Option<Frame>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy {
+    pub expr: Expr,
+    pub descending: bool,
+}
@@ -0,0 +1,164 @@
+use std::ops::Range;
+
+use super::Error;
+use crate::numeric::Numeric;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    // unquoted identifiers and keywords, lowercased
+    Word(String),
+    // "quoted" identifiers, case preserved and never treated as keywords
+    QuotedIdent(String),
+    Number(i64),
+    // a number with a fraction or an exponent
+    Decimal(Numeric),
+    String(String),
+    Symbol(&'static str),
+    // `?` is Param(None), `$n` is Param(Some(n))
+    Param(Option<usize>),
+    // the text of a `/*+ ... */` comment right after SELECT, which holds optimizer hints, other
+    // comments being dropped
+    Hint(String),
+}
+
+// longer symbols first, so that they aren't taken as their prefixes
+const SYMBOLS: [&str; 28] = [
+    "->>", "#>>", "->", "#>", "@@", "@>", "<@", "&&", "<>", "!=", "<=", ">=", "::", "(", ")", "[", "]", ",", ".", ";", "*", "+", "-", "/", "%", "=", "<", ">",
+];
+
+// Returns the tokens along with their byte ranges in `sql`.
+pub fn tokenize(sql: &str) -> Result<Vec<(Token, Range<usize>)>, Error> {
+    let mut tokens = vec![];
+    let mut spans = vec![];
+    let bytes = sql.as_bytes();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let start = pos;
+        let num_tokens = tokens.len();
+        let c = bytes[pos];
+        let rest = &sql[pos..];
+        if c.is_ascii_whitespace() {
+            pos += 1;
+        } else if rest.starts_with("--") {
+            pos += rest.find('\n').unwrap_or(rest.len());
+        } else if rest.starts_with("/*") {
+            let end = rest.find("*/").ok_or_else(|| Error::Syntax("unterminated comment".to_string()))?;
+            if rest.starts_with("/*+") && matches!(tokens.last(), Some(Token::Word(word)) if word == "select") {
+                tokens.push(Token::Hint(rest[3..end].trim().to_string()));
+            }
+            pos += end + 2;
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..len].to_ascii_lowercase()));
+            pos += len;
+        } else if c.is_ascii_digit() {
+            let digits = |from: usize| from + rest[from..].find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len() - from);
+            let mut len = digits(0);
+            let integer = len;
+            // 1.5, 1.5e-3 and 15e-4, but not the 1 of 1.x
+            let digit_at = |i: usize| rest.as_bytes().get(i).is_some_and(u8::is_ascii_digit);
+            if rest[len..].starts_with('.') && digit_at(len + 1) {
+                len = digits(len + 1);
+            }
+            if rest[len..].starts_with(['e', 'E']) {
+                let sign = rest[len + 1..].starts_with(['+', '-']) as usize;
+                if digit_at(len + 1 + sign) {
+                    len = digits(len + 1 + sign);
+                }
+            }
+            let out_of_range = || Error::Syntax(format!("number out of range: {}", &rest[..len]));
+            if len == integer {
+                tokens.push(Token::Number(rest[..len].parse().map_err(|_| out_of_range())?));
+            } else {
+                tokens.push(Token::Decimal(Numeric::parse(&rest[..len]).map_err(|_| out_of_range())?));
+            }
+            pos += len;
+        } else if c == b'\'' || c == b'"' {
+            let quote = c as char;
+            let mut value = String::new();
+            let mut chars = rest.char_indices().skip(1);
+            loop {
+                match chars.next() {
+                    Some((i, ch)) if ch == quote => {
+                        if rest[i + 1..].starts_with(quote) {
+                            value.push(quote);
+                            chars.next();
+                        } else {
+                            pos += i + 1;
+                            break;
+                        }
+                    }
+                    Some((_, ch)) => value.push(ch),
+                    None => return Err(Error::Syntax("unterminated quoted string".to_string())),
+                }
+            }
+            tokens.push(if quote == '\'' { Token::String(value) } else { Token::QuotedIdent(value) });
+        } else if c == b'?' {
+            tokens.push(Token::Param(None));
+            pos += 1;
+        } else if c == b'$' {
+            let len = rest[1..].find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len() - 1);
+            match rest[1..1 + len].parse() {
+                Ok(n) if n > 0 => tokens.push(Token::Param(Some(n))),
+                _ => return Err(Error::Syntax(format!("invalid parameter: {}", &rest[..1 + len]))),
+            }
+            pos += 1 + len;
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+            tokens.push(Token::Symbol(symbol));
+            pos += symbol.len();
+        } else {
+            return Err(Error::Syntax(format!("unexpected character: {}", rest.chars().next().unwrap())));
+        }
+        if tokens.len() > num_tokens {
+            spans.push(start..pos);
+        }
+    }
+    Ok(tokens.into_iter().zip(spans).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn without_spans(sql: &str) -> Result<Vec<Token>, Error> {
+        Ok(tokenize(sql)?.into_iter().map(|(token, _)| token).collect())
+    }
+
+    #[test]
+    fn test() {
+        let tokens = without_spans("SELECT \"Id\", 'it''s' -- comment\n FROM t WHERE a<>12/* x */;").unwrap();
+        assert_eq!(
+            vec![
+                Token::Word("select".to_string()),
+                Token::QuotedIdent("Id".to_string()),
+                Token::Symbol(","),
+                Token::String("it's".to_string()),
+                Token::Word("from".to_string()),
+                Token::Word("t".to_string()),
+                Token::Word("where".to_string()),
+                Token::Word("a".to_string()),
+                Token::Symbol("<>"),
+                Token::Number(12),
+                Token::Symbol(";"),
+            ],
+            tokens
+        );
+        assert_eq!(vec![Token::Param(None), Token::Param(Some(12))], without_spans("? $12").unwrap());
+        let arrows = [Token::Symbol("->>"), Token::Symbol("->"), Token::Symbol("#>>"), Token::Symbol("#>"), Token::Symbol("-"), Token::Symbol(">")];
+        assert_eq!(arrows.to_vec(), without_spans("->>-> #>>#> - >").unwrap());
+        let geometric = [Token::Symbol("@>"), Token::Symbol("<@"), Token::Symbol("&&"), Token::Symbol("<"), Token::Symbol("@@")];
+        assert_eq!(geometric.to_vec(), without_spans("@><@&& < @@").unwrap());
+        let subscript = [Token::Word("a".to_string()), Token::Symbol("["), Token::Number(1), Token::Symbol("]")];
+        assert_eq!(subscript.to_vec(), without_spans("a[1]").unwrap());
+        let decimal = |s: &str| Token::Decimal(Numeric::parse(s).unwrap());
+        let numbers = [decimal("1.50"), decimal("2.5e-3"), decimal("3e2"), Token::Number(4), Token::Symbol("."), Token::Word("x".to_string())];
+        assert_eq!(numbers.to_vec(), without_spans("1.50 2.5e-3 3e2 4.x").unwrap());
+        let spans: Vec<_> = tokenize("a, 'b''c'").unwrap().into_iter().map(|(_, span)| span).collect();
+        assert_eq!(vec![0..1, 1..2, 3..9], spans);
+        let hinted = [Token::Word("select".to_string()), Token::Hint("INDEX(t t_a)".to_string()), Token::Word("a".to_string())];
+        assert_eq!(hinted.to_vec(), without_spans("SELECT /*+ INDEX(t t_a) */ a /*+ not a hint */").unwrap());
+        assert!(tokenize("'open").is_err());
+        assert!(tokenize("$0").is_err());
+        assert!(tokenize("a # b").is_err());
+    }
+}
@@ -0,0 +1,2369 @@
+use std::ops::Range;
+
+use super::ast::*;
+use super::lexer::{tokenize, Token};
+use super::Error;
+use crate::query::expr::type_name;
+use crate::tuple::{ElementType, Value};
+
+// Words which can't be used as bare identifiers or implicit aliases.
+const RESERVED: &[&str] = &[
+    "all", "and", "as", "by", "create", "distinct", "except", "exists", "false", "from", "group", "having", "ilike", "in", "index",
+    "inner", "insert", "intersect", "into", "is", "join", "key", "like", "limit", "not", "null", "on", "or", "order", "primary", "select",
+    "table", "true", "union", "values", "where", "with",
+];
+
+// The name as an identifier which parses back to it, quoted unless it's a plain word of lower
+// case letters, digits and underscores which isn't reserved.
+pub fn quote_ident(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && !RESERVED.contains(&name);
+    if plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+pub fn parse(sql: &str) -> Result<Vec<Statement>, Error> {
+    Ok(parse_with_text(sql)?.into_iter().map(|(statement, _)| statement).collect())
+}
+
+// The statement with its constants and parameters replaced by `?`, comments dropped and
+// whitespace made single spaces, so that the statements which differ only in those are the same.
+//   SELECT * FROM t WHERE id = 1 -- the first  =>  select * from t where id = ?
+pub fn normalize(sql: &str) -> Result<String, Error> {
+    let mut normalized = String::new();
+    let mut space = false;
+    for (token, _) in tokenize(sql)? {
+        let text = match token {
+            Token::Word(word) => word,
+            Token::QuotedIdent(name) => format!("\"{}\"", name.replace('"', "\"\"")),
+            Token::Number(_) | Token::Decimal(_) | Token::String(_) | Token::Param(_) => "?".to_string(),
+            Token::Symbol(symbol) => symbol.to_string(),
+            Token::Hint(text) => format!("/*+ {} */", text),
+        };
+        if space && !matches!(text.as_str(), "," | ")" | "." | ";") {
+            normalized.push(' ');
+        }
+        space = !matches!(text.as_str(), "(" | ".");
+        normalized.push_str(&text);
+    }
+    Ok(normalized)
+}
+
+// The constants and parameters of the statement as written, in order, which tell apart the
+// statements `normalize` makes the same.
+//   SELECT * FROM t WHERE id = 1 AND name = $1  =>  ["1", "$1"]
+pub fn constants(sql: &str) -> Result<Vec<&str>, Error> {
+    Ok(tokenize(sql)?.into_iter().filter(|(token, _)| matches!(token, Token::Number(_) | Token::Decimal(_) | Token::String(_) | Token::Param(_))).map(|(_, span)| &sql[span]).collect())
+}
+
+// Like `parse`, with the text of each statement, from its first token to its last.
+pub fn parse_with_text(sql: &str) -> Result<Vec<(Statement, &str)>, Error> {
+    let (tokens, spans) = tokenize(sql)?.into_iter().unzip();
+    let mut parser = Parser {
+        sql,
+        tokens,
+        spans,
+        pos: 0,
+        params: None,
+    };
+    let mut statements = vec![];
+    loop {
+        while parser.consume_symbol(";") {}
+        if parser.peek().is_none() {
+            return Ok(statements);
+        }
+        parser.params = None;
+        let start = parser.pos;
+        let statement = parser.parse_statement()?;
+        let text = &sql[parser.spans[start].start..parser.spans[parser.pos - 1].end];
+        statements.push((statement, text));
+        if parser.peek().is_some() && !parser.consume_symbol(";") {
+            return Err(parser.unexpected());
+        }
+    }
+}
+
+struct Parser<'a> {
+    sql: &'a str,
+    tokens: Vec<Token>,
+    // byte ranges of `tokens` in `sql`
+    spans: Vec<Range<usize>>,
+    pos: usize,
+    // the placeholder style seen so far: Some(true) for `?` and Some(false) for `$n`,
+    // along with the number of `?` seen
+    params: Option<(bool, usize)>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w == keyword)
+    }
+
+    fn peek_nth_keyword(&self, n: usize, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.pos + n), Some(Token::Word(w)) if w == keyword)
+    }
+
+    // Whether a query (other than a parenthesized one) starts here.
+    fn peek_query(&self) -> bool {
+        self.peek_keyword("select") || self.peek_keyword("with")
+    }
+
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_keyword(keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), Error> {
+        if self.consume_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn consume_symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), Error> {
+        if self.consume_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn unexpected(&self) -> Error {
+        match self.peek() {
+            Some(token) => Error::Syntax(format!("unexpected token: {:?}", token)),
+            None => Error::Syntax("unexpected end of input".to_string()),
+        }
+    }
+
+    fn parse_ident(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(Token::Word(w)) if !RESERVED.contains(&w.as_str()) => {
+                let ident = w.clone();
+                self.pos += 1;
+                Ok(ident)
+            }
+            Some(Token::QuotedIdent(ident)) => {
+                let ident = ident.clone();
+                self.pos += 1;
+                Ok(ident)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn parse_ident_list(&mut self) -> Result<Vec<String>, Error> {
+        self.expect_symbol("(")?;
+        let mut idents = vec![self.parse_ident()?];
+        while self.consume_symbol(",") {
+            idents.push(self.parse_ident()?);
+        }
+        self.expect_symbol(")")?;
+        Ok(idents)
+    }
+
+    // [AS] alias, where AS may be omitted if the alias is not a reserved word
+    fn parse_alias(&mut self) -> Result<Option<String>, Error> {
+        if self.consume_keyword("as") {
+            return Ok(Some(self.parse_ident()?));
+        }
+        match self.peek() {
+            Some(Token::Word(w)) if !RESERVED.contains(&w.as_str()) => Ok(Some(self.parse_ident()?)),
+            Some(Token::QuotedIdent(_)) => Ok(Some(self.parse_ident()?)),
+            _ => Ok(None),
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, Error> {
+        if self.peek_query() || matches!(self.peek(), Some(Token::Symbol("("))) {
+            Ok(Statement::Select(Box::new(self.parse_query()?)))
+        } else if self.consume_keyword("explain") {
+            let analyze = self.consume_keyword("analyze");
+            Ok(Statement::Explain {
+                query: Box::new(self.parse_query()?),
+                analyze,
+            })
+        } else if self.consume_keyword("analyze") {
+            let table = match self.peek() {
+                Some(Token::Word(_)) | Some(Token::QuotedIdent(_)) => Some(self.parse_ident()?),
+                _ => None,
+            };
+            Ok(Statement::Analyze(table))
+        } else if self.consume_keyword("vacuum") {
+            let full = self.consume_keyword("full");
+            let table = match self.peek() {
+                Some(Token::Word(_)) | Some(Token::QuotedIdent(_)) => Some(self.parse_ident()?),
+                _ => None,
+            };
+            Ok(Statement::Vacuum { table, full })
+        } else if self.consume_keyword("checkdb") {
+            let table = match self.peek() {
+                Some(Token::Word(_)) | Some(Token::QuotedIdent(_)) => Some(self.parse_ident()?),
+                _ => None,
+            };
+            Ok(Statement::Check(table))
+        } else if self.consume_keyword("reindex") {
+            let table = self.consume_keyword("table");
+            if !table {
+                self.expect_keyword("index")?;
+            }
+            Ok(Statement::Reindex { name: self.parse_ident()?, table })
+        } else if self.consume_keyword("insert") {
+            self.parse_insert()
+        } else if self.consume_keyword("update") {
+            let table = self.parse_ident()?;
+            self.expect_keyword("set")?;
+            let assignments = self.parse_assignments()?;
+            let condition = if self.consume_keyword("where") { Some(self.parse_expr()?) } else { None };
+            let returning = if self.consume_keyword("returning") { Some(self.parse_select_items()?) } else { None };
+            Ok(Statement::Update(Update { table, assignments, condition, returning }))
+        } else if self.consume_keyword("delete") {
+            self.expect_keyword("from")?;
+            let table = self.parse_ident()?;
+            let condition = if self.consume_keyword("where") { Some(self.parse_expr()?) } else { None };
+            let returning = if self.consume_keyword("returning") { Some(self.parse_select_items()?) } else { None };
+            Ok(Statement::Delete(Delete { table, condition, returning }))
+        } else if self.consume_keyword("copy") {
+            self.parse_copy()
+        } else if self.consume_keyword("begin") {
+            self.consume_keyword("transaction");
+            let (mut isolation, mut read_only) = (None, false);
+            // the modes come in any order, separated by commas or not
+            loop {
+                if self.consume_keyword("isolation") {
+                    self.expect_keyword("level")?;
+                    if self.consume_keyword("read") {
+                        self.expect_keyword("committed")?;
+                        isolation = Some(Isolation::ReadCommitted);
+                    } else if self.consume_keyword("serializable") {
+                        isolation = Some(Isolation::Serializable);
+                    } else {
+                        self.expect_keyword("repeatable")?;
+                        self.expect_keyword("read")?;
+                        isolation = Some(Isolation::RepeatableRead);
+                    }
+                } else if self.consume_keyword("read") {
+                    read_only = self.consume_keyword("only");
+                    if !read_only {
+                        self.expect_keyword("write")?;
+                    }
+                } else {
+                    break;
+                }
+                self.consume_symbol(",");
+            }
+            Ok(Statement::Begin { isolation, read_only })
+        } else if self.consume_keyword("commit") {
+            self.consume_keyword("transaction");
+            Ok(Statement::Commit)
+        } else if self.consume_keyword("lock") {
+            self.consume_keyword("table");
+            let table = self.parse_ident()?;
+            let mut mode = LockMode::Exclusive;
+            if self.consume_keyword("in") {
+                if self.consume_keyword("share") {
+                    if self.consume_keyword("row") {
+                        self.expect_keyword("exclusive")?;
+                        mode = LockMode::SharedIntentionExclusive;
+                    } else {
+                        mode = LockMode::Shared;
+                    }
+                } else {
+                    self.expect_keyword("exclusive")?;
+                }
+                self.expect_keyword("mode")?;
+            }
+            Ok(Statement::LockTable { table, mode })
+        } else if self.consume_keyword("rollback") {
+            self.consume_keyword("transaction");
+            Ok(Statement::Rollback)
+        } else if self.consume_keyword("set") {
+            let name = self.parse_ident()?;
+            if !self.consume_keyword("to") {
+                self.expect_symbol("=")?;
+            }
+            let value = match self.peek() {
+                Some(Token::Word(word)) if word == "default" => {
+                    self.pos += 1;
+                    None
+                }
+                Some(Token::Number(_)) | Some(Token::Symbol("-")) => {
+                    let negative = self.consume_symbol("-");
+                    let Some(&Token::Number(n)) = self.peek() else {
+                        return Err(self.unexpected());
+                    };
+                    self.pos += 1;
+                    Some(Value::Int(if negative { -n } else { n }))
+                }
+                Some(Token::String(s)) => {
+                    let s = s.clone();
+                    self.pos += 1;
+                    Some(Value::Text(s))
+                }
+                // e.g. SET default_transaction_isolation = repeatable read
+                Some(Token::Word(_)) => {
+                    let mut words = vec![];
+                    while let Some(Token::Word(word)) = self.peek() {
+                        words.push(word.clone());
+                        self.pos += 1;
+                    }
+                    Some(Value::Text(words.join(" ")))
+                }
+                _ => return Err(self.unexpected()),
+            };
+            Ok(Statement::Set { name, value })
+        } else if self.consume_keyword("cancel") {
+            let terminate = self.consume_keyword("session");
+            if !terminate {
+                self.expect_keyword("query")?;
+            }
+            match self.peek() {
+                Some(&Token::Number(n)) if n >= 0 => {
+                    self.pos += 1;
+                    Ok(Statement::Cancel { session: n as u64, terminate })
+                }
+                _ => Err(self.unexpected()),
+            }
+        } else if self.consume_keyword("declare") {
+            let name = self.parse_ident()?;
+            self.expect_keyword("cursor")?;
+            self.expect_keyword("for")?;
+            Ok(Statement::DeclareCursor { name, query: Box::new(self.parse_query()?) })
+        } else if self.consume_keyword("fetch") {
+            let forward = self.consume_keyword("forward");
+            let count = if self.consume_keyword("all") {
+                None
+            } else if let Some(&Token::Number(n)) = self.peek() {
+                if n < 0 {
+                    return Err(self.unexpected());
+                }
+                self.pos += 1;
+                Some(n as u64)
+            } else {
+                // NEXT, or FORWARD alone
+                if !forward {
+                    self.consume_keyword("next");
+                }
+                Some(1)
+            };
+            if !self.consume_keyword("from") {
+                self.consume_keyword("in");
+            }
+            Ok(Statement::Fetch { name: self.parse_ident()?, count })
+        } else if self.consume_keyword("close") {
+            if self.consume_keyword("all") {
+                return Ok(Statement::CloseCursor(None));
+            }
+            Ok(Statement::CloseCursor(Some(self.parse_ident()?)))
+        } else if self.consume_keyword("show") {
+            if self.consume_keyword("all") {
+                return Ok(Statement::Show(None));
+            }
+            Ok(Statement::Show(Some(self.parse_ident()?)))
+        } else if self.consume_keyword("create") {
+            if self.consume_keyword("temp") || self.consume_keyword("temporary") {
+                self.expect_keyword("table")?;
+                self.parse_create_table(true, false)
+            } else if self.consume_keyword("unlogged") {
+                self.expect_keyword("table")?;
+                self.parse_create_table(false, true)
+            } else if self.consume_keyword("table") {
+                self.parse_create_table(false, false)
+            } else if self.consume_keyword("index") {
+                self.parse_create_index()
+            } else if self.consume_keyword("view") {
+                self.parse_create_view()
+            } else if self.consume_keyword("trigger") {
+                self.parse_create_trigger()
+            } else if self.consume_keyword("user") {
+                let name = self.parse_ident()?;
+                self.consume_keyword("with");
+                let superuser = self.consume_keyword("superuser");
+                let password = if matches!(self.peek(), Some(Token::Word(_))) { self.parse_password()? } else { None };
+                Ok(Statement::CreateUser { name, password, superuser })
+            } else {
+                Err(self.unexpected())
+            }
+        } else if self.consume_keyword("drop") {
+            let kind = if self.consume_keyword("table") {
+                ObjectKind::Table
+            } else if self.consume_keyword("index") {
+                ObjectKind::Index
+            } else {
+                self.expect_keyword("view")?;
+                ObjectKind::View
+            };
+            let if_exists = self.peek_keyword("if") && self.peek_nth_keyword(1, "exists");
+            if if_exists {
+                self.pos += 2;
+            }
+            Ok(Statement::Drop { kind, name: self.parse_ident()?, if_exists })
+        } else if self.consume_keyword("alter") {
+            if self.consume_keyword("table") {
+                let table = self.parse_ident()?;
+                return Ok(Statement::AlterTable { table, action: self.parse_alter_table()? });
+            }
+            self.expect_keyword("user")?;
+            let name = self.parse_ident()?;
+            let password = self.parse_password()?;
+            Ok(Statement::AlterUser { name, password })
+        } else if self.consume_keyword("grant") {
+            Ok(Statement::Grant(self.parse_grant("to")?))
+        } else if self.consume_keyword("revoke") {
+            Ok(Statement::Revoke(self.parse_grant("from")?))
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    // What follows ALTER TABLE name.
+    fn parse_alter_table(&mut self) -> Result<AlterTable, Error> {
+        if self.consume_keyword("add") {
+            self.consume_keyword("column");
+            let name = self.parse_ident()?;
+            return Ok(AlterTable::AddColumn(ColumnDef { name, data_type: self.parse_data_type()? }));
+        }
+        self.expect_keyword("rename")?;
+        if self.consume_keyword("to") {
+            return Ok(AlterTable::Rename(self.parse_ident()?));
+        }
+        self.consume_keyword("column");
+        let column = self.parse_ident()?;
+        self.expect_keyword("to")?;
+        Ok(AlterTable::RenameColumn { column, new_name: self.parse_ident()? })
+    }
+
+    // [WITH] PASSWORD 'password' | NULL
+    fn parse_password(&mut self) -> Result<Option<String>, Error> {
+        self.consume_keyword("with");
+        self.expect_keyword("password")?;
+        if self.consume_keyword("null") {
+            return Ok(None);
+        }
+        Ok(Some(self.parse_string()?))
+    }
+
+    // What follows GRANT or REVOKE, the users after `preposition`.
+    fn parse_grant(&mut self, preposition: &str) -> Result<Grant, Error> {
+        let privileges = if self.consume_keyword("all") {
+            self.consume_keyword("privileges");
+            Privilege::ALL.to_vec()
+        } else {
+            let mut privileges = vec![];
+            loop {
+                let privilege = if self.consume_keyword("select") {
+                    Privilege::Select
+                } else if self.consume_keyword("insert") {
+                    Privilege::Insert
+                } else if self.consume_keyword("update") {
+                    Privilege::Update
+                } else {
+                    self.expect_keyword("delete")?;
+                    Privilege::Delete
+                };
+                if !privileges.contains(&privilege) {
+                    privileges.push(privilege);
+                }
+                if !self.consume_symbol(",") {
+                    break privileges;
+                }
+            }
+        };
+        self.expect_keyword("on")?;
+        self.consume_keyword("table");
+        let table = self.parse_ident()?;
+        self.expect_keyword(preposition)?;
+        let mut users = vec![self.parse_ident()?];
+        while self.consume_symbol(",") {
+            users.push(self.parse_ident()?);
+        }
+        Ok(Grant { privileges, table, users })
+    }
+
+    fn parse_create_table(&mut self, temporary: bool, unlogged: bool) -> Result<Statement, Error> {
+        let name = self.parse_ident()?;
+        if self.consume_keyword("partition") {
+            self.expect_keyword("of")?;
+            let parent = self.parse_ident()?;
+            self.expect_keyword("for")?;
+            self.expect_keyword("values")?;
+            let bound = if self.consume_keyword("from") {
+                let lower = self.parse_range_bound("minvalue")?;
+                self.expect_keyword("to")?;
+                PartitionBound::Range { lower, upper: self.parse_range_bound("maxvalue")? }
+            } else {
+                self.expect_keyword("with")?;
+                self.expect_symbol("(")?;
+                self.expect_keyword("modulus")?;
+                let modulus = self.parse_count()?;
+                self.expect_symbol(",")?;
+                self.expect_keyword("remainder")?;
+                let remainder = self.parse_count()?;
+                self.expect_symbol(")")?;
+                PartitionBound::Hash { modulus, remainder }
+            };
+            return Ok(Statement::CreateTable(CreateTable {
+                name,
+                temporary,
+                unlogged,
+                columns: vec![],
+                primary_key: vec![],
+                partition_by: None,
+                partition_of: Some((parent, bound)),
+                engine: Engine::Disk,
+                ttl: None,
+                dictionary: vec![],
+            }));
+        }
+        self.expect_symbol("(")?;
+        let mut columns = vec![];
+        let mut primary_key = vec![];
+        loop {
+            if self.consume_keyword("primary") {
+                self.expect_keyword("key")?;
+                primary_key = self.parse_ident_list()?;
+            } else {
+                let column_name = self.parse_ident()?;
+                let data_type = self.parse_data_type()?;
+                if self.consume_keyword("primary") {
+                    self.expect_keyword("key")?;
+                    primary_key = vec![column_name.clone()];
+                }
+                columns.push(ColumnDef {
+                    name: column_name,
+                    data_type,
+                });
+            }
+            if !self.consume_symbol(",") {
+                break;
+            }
+        }
+        self.expect_symbol(")")?;
+        let partition_by = if self.consume_keyword("partition") {
+            self.expect_keyword("by")?;
+            let strategy = if self.consume_keyword("range") {
+                PartitionStrategy::Range
+            } else {
+                self.expect_keyword("hash")?;
+                PartitionStrategy::Hash
+            };
+            self.expect_symbol("(")?;
+            let column = self.parse_ident()?;
+            self.expect_symbol(")")?;
+            Some((strategy, column))
+        } else {
+            None
+        };
+        let engine = match self.consume_keyword("using") {
+            true if self.consume_keyword("memory") => Engine::Memory,
+            true if self.consume_keyword("columnar") => Engine::Columnar,
+            true => {
+                self.expect_keyword("disk")?;
+                Engine::Disk
+            }
+            false => Engine::Disk,
+        };
+        let ttl = match self.consume_keyword("ttl") {
+            true => {
+                let column = self.parse_ident()?;
+                self.expect_symbol("+")?;
+                Some((column, self.parse_count()? as i64))
+            }
+            false => None,
+        };
+        let dictionary = match self.consume_keyword("dictionary") {
+            true => self.parse_ident_list()?,
+            false => vec![],
+        };
+        Ok(Statement::CreateTable(CreateTable {
+            name,
+            temporary,
+            unlogged,
+            columns,
+            primary_key,
+            partition_by,
+            partition_of: None,
+            engine,
+            ttl,
+            dictionary,
+        }))
+    }
+
+    // (value) or (MINVALUE) or (MAXVALUE), as `unbounded` is, for None.
+    fn parse_range_bound(&mut self, unbounded: &str) -> Result<Option<Box<Expr>>, Error> {
+        self.expect_symbol("(")?;
+        let bound = if self.consume_keyword(unbounded) { None } else { Some(Box::new(self.parse_expr()?)) };
+        self.expect_symbol(")")?;
+        Ok(bound)
+    }
+
+    // An integer literal, not negative.
+    fn parse_count(&mut self) -> Result<u64, Error> {
+        match self.peek() {
+            Some(&Token::Number(n)) if n >= 0 => {
+                self.pos += 1;
+                Ok(n as u64)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn parse_data_type(&mut self) -> Result<DataType, Error> {
+        let double = matches!(self.peek(), Some(Token::Word(w)) if w == "double");
+        let data_type = match self.peek() {
+            Some(Token::Word(w)) => match w.as_str() {
+                "int" | "integer" | "bigint" => DataType::Integer,
+                "text" | "varchar" => DataType::Text,
+                "bool" | "boolean" => DataType::Boolean,
+                "json" | "jsonb" => DataType::Json,
+                "point" => DataType::Point,
+                "box" => DataType::Box,
+                "float" | "float8" | "float4" | "real" | "double" => DataType::Float,
+                "numeric" | "decimal" => DataType::Numeric,
+                "date" => DataType::Date,
+                "timestamp" => DataType::Timestamp,
+                _ => return Err(self.unexpected()),
+            },
+            _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        if double {
+            self.expect_keyword("precision")?;
+        }
+        // type[] of one-dimensional arrays
+        if !self.consume_symbol("[") {
+            return Ok(data_type);
+        }
+        self.expect_symbol("]")?;
+        if matches!(self.peek(), Some(Token::Symbol("["))) {
+            return Err(Error::Syntax("arrays of more than one dimension are not supported".to_string()));
+        }
+        match ElementType::of(data_type) {
+            Some(element) => Ok(DataType::Array(element)),
+            None => Err(Error::Syntax(format!("arrays of type {} are not supported", type_name(data_type)))),
+        }
+    }
+
+    fn parse_create_index(&mut self) -> Result<Statement, Error> {
+        // unless it's the name of the index
+        let concurrently = !self.peek_nth_keyword(1, "on") && self.consume_keyword("concurrently");
+        let name = self.parse_ident()?;
+        self.expect_keyword("on")?;
+        let table = self.parse_ident()?;
+        let kind = match self.consume_keyword("using") {
+            true if self.consume_keyword("fulltext") => IndexKind::FullText,
+            true if self.consume_keyword("rtree") => IndexKind::RTree,
+            true if self.consume_keyword("zonemap") => IndexKind::ZoneMap,
+            true => {
+                self.expect_keyword("btree")?;
+                IndexKind::BTree
+            }
+            false => IndexKind::BTree,
+        };
+        self.expect_symbol("(")?;
+        let mut columns = vec![];
+        loop {
+            columns.push(match self.consume_symbol("(") {
+                true => {
+                    let expr = self.parse_expr()?;
+                    self.expect_symbol(")")?;
+                    expr
+                }
+                false => Expr::Column { table: None, name: self.parse_ident()? },
+            });
+            if !self.consume_symbol(",") {
+                break;
+            }
+        }
+        self.expect_symbol(")")?;
+        Ok(Statement::CreateIndex(CreateIndex { name, table, columns, concurrently, kind }))
+    }
+
+    fn parse_create_view(&mut self) -> Result<Statement, Error> {
+        let name = self.parse_ident()?;
+        let columns = if matches!(self.peek(), Some(Token::Symbol("("))) {
+            Some(self.parse_ident_list()?)
+        } else {
+            None
+        };
+        self.expect_keyword("as")?;
+        let start = self.spans.get(self.pos).ok_or_else(|| self.unexpected())?.start;
+        let query = Box::new(self.parse_query()?);
+        let sql = self.sql[start..self.spans[self.pos - 1].end].to_string();
+        Ok(Statement::CreateView(CreateView { name, columns, query, sql }))
+    }
+
+    fn parse_create_trigger(&mut self) -> Result<Statement, Error> {
+        let start = self.spans[self.pos - 2].start;
+        let name = self.parse_ident()?;
+        let timing = if self.consume_keyword("before") {
+            TriggerTiming::Before
+        } else {
+            self.expect_keyword("after")?;
+            TriggerTiming::After
+        };
+        let event = if self.consume_keyword("insert") {
+            TriggerEvent::Insert
+        } else if self.consume_keyword("update") {
+            TriggerEvent::Update
+        } else {
+            self.expect_keyword("delete")?;
+            TriggerEvent::Delete
+        };
+        self.expect_keyword("on")?;
+        let table = self.parse_ident()?;
+        if self.consume_keyword("for") {
+            self.expect_keyword("each")?;
+            self.expect_keyword("row")?;
+        }
+        let when = match self.consume_keyword("when") {
+            true => Some(self.parse_expr()?),
+            false => None,
+        };
+        self.expect_keyword("begin")?;
+        let mut body = vec![];
+        loop {
+            self.expect_keyword("insert")?;
+            match self.parse_insert()? {
+                Statement::Insert(insert) => body.push(insert),
+                _ => unreachable!(),
+            }
+            self.expect_symbol(";")?;
+            if self.consume_keyword("end") {
+                break;
+            }
+        }
+        let sql = self.sql[start..self.spans[self.pos - 1].end].to_string();
+        Ok(Statement::CreateTrigger(CreateTrigger { name, table, timing, event, when, body, sql }))
+    }
+
+    fn parse_insert(&mut self) -> Result<Statement, Error> {
+        self.expect_keyword("into")?;
+        let table = self.parse_ident()?;
+        let columns = if matches!(self.peek(), Some(Token::Symbol("("))) {
+            Some(self.parse_ident_list()?)
+        } else {
+            None
+        };
+        self.expect_keyword("values")?;
+        let mut rows = vec![];
+        loop {
+            self.expect_symbol("(")?;
+            let mut row = vec![self.parse_expr()?];
+            while self.consume_symbol(",") {
+                row.push(self.parse_expr()?);
+            }
+            self.expect_symbol(")")?;
+            rows.push(row);
+            if !self.consume_symbol(",") {
+                break;
+            }
+        }
+        let on_conflict = if self.consume_keyword("on") {
+            self.expect_keyword("conflict")?;
+            Some(self.parse_on_conflict()?)
+        } else {
+            None
+        };
+        let returning = if self.consume_keyword("returning") { Some(self.parse_select_items()?) } else { None };
+        Ok(Statement::Insert(Insert { table, columns, rows, on_conflict, returning }))
+    }
+
+    // [(column, ...)] DO NOTHING | DO UPDATE SET column = expr, ... [WHERE condition]
+    fn parse_on_conflict(&mut self) -> Result<OnConflict, Error> {
+        let target = if matches!(self.peek(), Some(Token::Symbol("("))) {
+            Some(self.parse_ident_list()?)
+        } else {
+            None
+        };
+        self.expect_keyword("do")?;
+        if self.consume_keyword("nothing") {
+            return Ok(OnConflict { target, action: ConflictAction::Nothing });
+        }
+        self.expect_keyword("update")?;
+        self.expect_keyword("set")?;
+        let assignments = self.parse_assignments()?;
+        let condition = if self.consume_keyword("where") { Some(self.parse_expr()?) } else { None };
+        Ok(OnConflict { target, action: ConflictAction::Update { assignments, condition } })
+    }
+
+    // column = expr, ... of a SET
+    fn parse_assignments(&mut self) -> Result<Vec<(String, Expr)>, Error> {
+        let mut assignments = vec![];
+        loop {
+            let column = self.parse_ident()?;
+            self.expect_symbol("=")?;
+            assignments.push((column, self.parse_expr()?));
+            if !self.consume_symbol(",") {
+                break;
+            }
+        }
+        Ok(assignments)
+    }
+
+    fn parse_copy(&mut self) -> Result<Statement, Error> {
+        if self.consume_symbol("(") {
+            let query = Box::new(self.parse_query()?);
+            self.expect_symbol(")")?;
+            self.expect_keyword("to")?;
+            let path = self.parse_string()?;
+            let format = self.parse_copy_options()?;
+            return Ok(Statement::CopyTo(CopyTo { query, path, format }));
+        }
+        let table = self.parse_ident()?;
+        let columns = if matches!(self.peek(), Some(Token::Symbol("("))) {
+            Some(self.parse_ident_list()?)
+        } else {
+            None
+        };
+        if self.consume_keyword("to") {
+            let path = self.parse_string()?;
+            let format = self.parse_copy_options()?;
+            let projection = match columns {
+                Some(names) => names
+                    .into_iter()
+                    .map(|name| SelectItem::Expr { expr: Expr::Column { table: None, name }, alias: None })
+                    .collect(),
+                None => vec![SelectItem::Wildcard],
+            };
+            let query = Box::new(Query::Select(Box::new(Select {
+                hints: vec![],
+                distinct: false,
+                projection,
+                from: vec![TableRef::Table { name: table, alias: None }],
+                selection: None,
+                group_by: vec![],
+                having: None,
+            })));
+            return Ok(Statement::CopyTo(CopyTo { query, path, format }));
+        }
+        self.expect_keyword("from")?;
+        let path = self.parse_string()?;
+        let format = self.parse_copy_options()?;
+        if matches!(format, CopyFormat::JsonLines | CopyFormat::Arrow) {
+            return Err(Error::Syntax("COPY FROM supports only FORMAT csv and parquet".to_string()));
+        }
+        Ok(Statement::CopyFrom(CopyFrom { table, columns, path, format }))
+    }
+
+    // [WITH] (FORMAT csv|jsonl|parquet|arrow, DELIMITER 'c', QUOTE 'c', HEADER [boolean], NULL 'marker'), or the
+    // options without the parentheses and commas; all but FORMAT are of CSV only
+    fn parse_copy_options(&mut self) -> Result<CopyFormat, Error> {
+        let mut options = CsvOptions::default();
+        let (mut format, mut csv_only) = (None, false);
+        let with = self.consume_keyword("with");
+        let parenthesized = self.consume_symbol("(");
+        loop {
+            let name = match self.peek() {
+                Some(Token::Word(w)) => w.clone(),
+                _ if parenthesized || with => return Err(self.unexpected()),
+                _ => break,
+            };
+            self.pos += 1;
+            match name.as_str() {
+                "format" => {
+                    if self.consume_keyword("jsonl") {
+                        format = Some(CopyFormat::JsonLines);
+                    } else if self.consume_keyword("parquet") {
+                        format = Some(CopyFormat::Parquet);
+                    } else if self.consume_keyword("arrow") {
+                        format = Some(CopyFormat::Arrow);
+                    } else if self.consume_keyword("csv") {
+                        format = None;
+                    } else {
+                        return Err(Error::Syntax("only FORMAT csv, jsonl, parquet and arrow are supported".to_string()));
+                    }
+                }
+                "delimiter" => options.delimiter = self.parse_char()?,
+                "quote" => options.quote = self.parse_char()?,
+                "null" => options.null = self.parse_string()?,
+                "header" => {
+                    options.header = !self.consume_keyword("false");
+                    self.consume_keyword("true");
+                }
+                _ => return Err(Error::Syntax(format!("unknown COPY option: {}", name))),
+            }
+            csv_only |= name != "format";
+            if parenthesized && !self.consume_symbol(",") {
+                break;
+            }
+            if !parenthesized && !matches!(self.peek(), Some(Token::Word(_))) {
+                break;
+            }
+        }
+        if parenthesized {
+            self.expect_symbol(")")?;
+        }
+        if let Some(format) = format {
+            if csv_only {
+                return Err(Error::Syntax("COPY options other than FORMAT are of FORMAT csv only".to_string()));
+            }
+            return Ok(format);
+        }
+        if options.delimiter == options.quote {
+            return Err(Error::Syntax("the COPY delimiter and quote must differ".to_string()));
+        }
+        Ok(CopyFormat::Csv(options))
+    }
+
+    fn parse_string(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(Token::String(s)) => {
+                let s = s.clone();
+                self.pos += 1;
+                Ok(s)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn parse_char(&mut self) -> Result<char, Error> {
+        let s = self.parse_string()?;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c != '\n' && c != '\r' => Ok(c),
+            _ => Err(Error::Syntax(format!("expected a single character: {:?}", s))),
+        }
+    }
+
+    // UNION and EXCEPT, which bind less tightly than INTERSECT, all left-associative
+    fn parse_query(&mut self) -> Result<Query, Error> {
+        if self.consume_keyword("with") {
+            return self.parse_with();
+        }
+        let mut left = self.parse_intersect()?;
+        loop {
+            let op = if self.consume_keyword("union") {
+                SetOperator::Union
+            } else if self.consume_keyword("except") {
+                SetOperator::Except
+            } else {
+                return Ok(left);
+            };
+            let all = self.parse_set_quantifier();
+            let right = self.parse_intersect()?;
+            left = set_operation(op, all, left, right);
+        }
+    }
+
+    // WITH [RECURSIVE] name [(column, ...)] AS [[NOT] MATERIALIZED] (query), ... query
+    fn parse_with(&mut self) -> Result<Query, Error> {
+        let recursive = self.consume_keyword("recursive");
+        let mut ctes = vec![];
+        loop {
+            let name = self.parse_ident()?;
+            let columns = if matches!(self.peek(), Some(Token::Symbol("("))) {
+                Some(self.parse_ident_list()?)
+            } else {
+                None
+            };
+            self.expect_keyword("as")?;
+            let materialized = if self.consume_keyword("materialized") {
+                Some(true)
+            } else if self.peek_keyword("not") && self.peek_nth_keyword(1, "materialized") {
+                self.pos += 2;
+                Some(false)
+            } else {
+                None
+            };
+            self.expect_symbol("(")?;
+            let query = Box::new(self.parse_query()?);
+            self.expect_symbol(")")?;
+            ctes.push(Cte {
+                name,
+                columns,
+                materialized,
+                query,
+            });
+            if !self.consume_symbol(",") {
+                break;
+            }
+        }
+        let body = Box::new(self.parse_query()?);
+        Ok(Query::With { recursive, ctes, body })
+    }
+
+    fn parse_intersect(&mut self) -> Result<Query, Error> {
+        let mut left = self.parse_query_primary()?;
+        while self.consume_keyword("intersect") {
+            let all = self.parse_set_quantifier();
+            let right = self.parse_query_primary()?;
+            left = set_operation(SetOperator::Intersect, all, left, right);
+        }
+        Ok(left)
+    }
+
+    // [ALL | DISTINCT], returning whether it's ALL
+    fn parse_set_quantifier(&mut self) -> bool {
+        let all = self.consume_keyword("all");
+        if !all {
+            self.consume_keyword("distinct");
+        }
+        all
+    }
+
+    fn parse_query_primary(&mut self) -> Result<Query, Error> {
+        if self.consume_symbol("(") {
+            let query = self.parse_query()?;
+            self.expect_symbol(")")?;
+            return Ok(query);
+        }
+        Ok(Query::Select(Box::new(self.parse_select()?)))
+    }
+
+    // * | expr [[AS] alias], ...
+    fn parse_select_items(&mut self) -> Result<Vec<SelectItem>, Error> {
+        let mut items = vec![];
+        loop {
+            if self.consume_symbol("*") {
+                items.push(SelectItem::Wildcard);
+            } else {
+                let expr = self.parse_expr()?;
+                let alias = self.parse_alias()?;
+                items.push(SelectItem::Expr { expr, alias });
+            }
+            if !self.consume_symbol(",") {
+                return Ok(items);
+            }
+        }
+    }
+
+    fn parse_select(&mut self) -> Result<Select, Error> {
+        self.expect_keyword("select")?;
+        let hints = match self.peek() {
+            Some(Token::Hint(text)) => {
+                let hints = parse_hints(text)?;
+                self.pos += 1;
+                hints
+            }
+            _ => vec![],
+        };
+        let distinct = self.consume_keyword("distinct");
+        if !distinct {
+            self.consume_keyword("all");
+        }
+        let projection = self.parse_select_items()?;
+
+        let mut from = vec![];
+        if self.consume_keyword("from") {
+            loop {
+                from.push(self.parse_table_ref()?);
+                if !self.consume_symbol(",") {
+                    break;
+                }
+            }
+        }
+
+        let selection = if self.consume_keyword("where") {
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+        let mut group_by = vec![];
+        if self.consume_keyword("group") {
+            self.expect_keyword("by")?;
+            loop {
+                group_by.push(self.parse_expr()?);
+                if !self.consume_symbol(",") {
+                    break;
+                }
+            }
+        }
+        let having = if self.consume_keyword("having") {
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+        Ok(Select {
+            hints,
+            distinct,
+            projection,
+            from,
+            selection,
+            group_by,
+            having,
+        })
+    }
+
+    fn parse_table_ref(&mut self) -> Result<TableRef, Error> {
+        let mut table_ref = self.parse_table_factor()?;
+        loop {
+            if self.consume_keyword("inner") {
+                self.expect_keyword("join")?;
+            } else if !self.consume_keyword("join") {
+                return Ok(table_ref);
+            }
+            let right = self.parse_table_factor()?;
+            self.expect_keyword("on")?;
+            let on = self.parse_expr()?;
+            table_ref = TableRef::Join {
+                left: Box::new(table_ref),
+                right: Box::new(right),
+                on: Some(on),
+            };
+        }
+    }
+
+    // a name qualified by a schema is `schema.name` as a whole, e.g. information_schema.tables
+    fn parse_table_factor(&mut self) -> Result<TableRef, Error> {
+        if self.peek_keyword("unnest") && matches!(self.tokens.get(self.pos + 1), Some(Token::Symbol("("))) {
+            self.pos += 2;
+            let expr = self.parse_expr()?;
+            self.expect_symbol(")")?;
+            let alias = self.parse_alias()?;
+            let column = match alias.is_some() && self.consume_symbol("(") {
+                true => {
+                    let column = self.parse_ident()?;
+                    self.expect_symbol(")")?;
+                    Some(column)
+                }
+                false => None,
+            };
+            return Ok(TableRef::Unnest { expr, alias, column });
+        }
+        let mut name = self.parse_ident()?;
+        if self.consume_symbol(".") {
+            name = format!("{}.{}", name, self.parse_ident()?);
+        }
+        if self.consume_symbol("(") {
+            let mut args = vec![];
+            if !self.consume_symbol(")") {
+                args.push(self.parse_expr()?);
+                while self.consume_symbol(",") {
+                    args.push(self.parse_expr()?);
+                }
+                self.expect_symbol(")")?;
+            }
+            let alias = self.parse_alias()?;
+            let mut columns = vec![];
+            if alias.is_some() && self.consume_symbol("(") {
+                columns.push(self.parse_ident()?);
+                while self.consume_symbol(",") {
+                    columns.push(self.parse_ident()?);
+                }
+                self.expect_symbol(")")?;
+            }
+            return Ok(TableRef::Function { name, args, alias, columns });
+        }
+        let alias = self.parse_alias()?;
+        Ok(TableRef::Table { name, alias })
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, Error> {
+        self.parse_or()
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, Error> {
+        let mut left = self.parse_and()?;
+        while self.consume_keyword("or") {
+            let right = self.parse_and()?;
+            left = binary(BinaryOp::Or, left, right);
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, Error> {
+        let mut left = self.parse_not()?;
+        while self.consume_keyword("and") {
+            let right = self.parse_not()?;
+            left = binary(BinaryOp::And, left, right);
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, Error> {
+        if self.peek_keyword("not") && !self.peek_nth_keyword(1, "exists") {
+            self.pos += 1;
+            let expr = self.parse_not()?;
+            return Ok(Expr::Unary {
+                op: UnaryOp::Not,
+                expr: Box::new(expr),
+            });
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, Error> {
+        let left = self.parse_json_access()?;
+        if self.consume_keyword("is") {
+            let negated = self.consume_keyword("not");
+            self.expect_keyword("null")?;
+            return Ok(Expr::IsNull {
+                expr: Box::new(left),
+                negated,
+            });
+        }
+        let negated = if self.peek_keyword("not") && ["in", "like", "ilike"].iter().any(|k| self.peek_nth_keyword(1, k)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        };
+        if self.peek_keyword("like") || self.peek_keyword("ilike") {
+            let case_insensitive = self.peek_keyword("ilike");
+            self.pos += 1;
+            let pattern = self.parse_json_access()?;
+            return Ok(Expr::Like {
+                expr: Box::new(left),
+                pattern: Box::new(pattern),
+                negated,
+                case_insensitive,
+            });
+        }
+        if self.consume_keyword("in") {
+            self.expect_symbol("(")?;
+            let expr = if self.peek_query() {
+                Expr::InSubquery {
+                    expr: Box::new(left),
+                    subquery: Box::new(self.parse_query()?),
+                    negated,
+                }
+            } else {
+                let mut list = vec![self.parse_expr()?];
+                while self.consume_symbol(",") {
+                    list.push(self.parse_expr()?);
+                }
+                Expr::InList {
+                    expr: Box::new(left),
+                    list,
+                    negated,
+                }
+            };
+            self.expect_symbol(")")?;
+            return Ok(expr);
+        }
+        let op = match self.peek() {
+            Some(Token::Symbol("=")) => BinaryOp::Eq,
+            Some(Token::Symbol("<>")) | Some(Token::Symbol("!=")) => BinaryOp::NotEq,
+            Some(Token::Symbol("<")) => BinaryOp::Lt,
+            Some(Token::Symbol("<=")) => BinaryOp::LtEq,
+            Some(Token::Symbol(">")) => BinaryOp::Gt,
+            Some(Token::Symbol(">=")) => BinaryOp::GtEq,
+            Some(Token::Symbol("@@")) => BinaryOp::Match,
+            Some(Token::Symbol("@>")) => BinaryOp::Contains,
+            Some(Token::Symbol("<@")) => BinaryOp::ContainedBy,
+            Some(Token::Symbol("&&")) => BinaryOp::Overlaps,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        // op ANY (array), SOME being ANY, or op ALL (array)
+        let quantifier = ["any", "some", "all"].into_iter().find(|&q| self.peek_keyword(q) && matches!(self.tokens.get(self.pos + 1), Some(Token::Symbol("("))));
+        if let Some(quantifier) = quantifier {
+            self.pos += 2;
+            let array = self.parse_expr()?;
+            self.expect_symbol(")")?;
+            return Ok(Expr::Quantified {
+                op,
+                expr: Box::new(left),
+                array: Box::new(array),
+                all: quantifier == "all",
+            });
+        }
+        let right = self.parse_json_access()?;
+        Ok(binary(op, left, right))
+    }
+
+    // JSON operators bind looser than arithmetic and tighter than comparisons, as in PostgreSQL.
+    fn parse_json_access(&mut self) -> Result<Expr, Error> {
+        let mut left = self.parse_additive()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol("->")) => BinaryOp::JsonGet,
+                Some(Token::Symbol("->>")) => BinaryOp::JsonGetText,
+                Some(Token::Symbol("#>")) => BinaryOp::JsonPath,
+                Some(Token::Symbol("#>>")) => BinaryOp::JsonPathText,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.parse_additive()?;
+            left = binary(op, left, right);
+        }
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, Error> {
+        let mut left = self.parse_multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol("+")) => BinaryOp::Add,
+                Some(Token::Symbol("-")) => BinaryOp::Sub,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.parse_multiplicative()?;
+            left = binary(op, left, right);
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr, Error> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol("*")) => BinaryOp::Mul,
+                Some(Token::Symbol("/")) => BinaryOp::Div,
+                Some(Token::Symbol("%")) => BinaryOp::Mod,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.parse_unary()?;
+            left = binary(op, left, right);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, Error> {
+        if self.consume_symbol("-") {
+            // fold negative literals so that i64::MIN can be written
+            if let Some(Token::Number(n)) = self.peek() {
+                let n = *n;
+                self.pos += 1;
+                return Ok(Expr::Literal(Value::Int(-n)));
+            }
+            let expr = self.parse_unary()?;
+            return Ok(Expr::Unary {
+                op: UnaryOp::Neg,
+                expr: Box::new(expr),
+            });
+        }
+        if self.consume_symbol("+") {
+            return self.parse_unary();
+        }
+        let mut expr = self.parse_primary()?;
+        loop {
+            if self.consume_symbol("::") {
+                expr = Expr::Cast {
+                    expr: Box::new(expr),
+                    data_type: self.parse_data_type()?,
+                };
+            } else if self.consume_symbol("[") {
+                let index = self.parse_expr()?;
+                self.expect_symbol("]")?;
+                expr = binary(BinaryOp::Subscript, expr, index);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, Error> {
+        let token = self.peek().cloned().ok_or_else(|| self.unexpected())?;
+        match token {
+            Token::Number(n) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Int(n)))
+            }
+            Token::Decimal(n) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Numeric(n)))
+            }
+            Token::String(s) => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Text(s)))
+            }
+            Token::Param(n) => {
+                self.pos += 1;
+                match (n, self.params) {
+                    (None, None) | (None, Some((true, _))) => {
+                        let index = self.params.map_or(0, |(_, count)| count);
+                        self.params = Some((true, index + 1));
+                        Ok(Expr::Parameter(index))
+                    }
+                    (Some(n), None) | (Some(n), Some((false, _))) => {
+                        self.params = Some((false, 0));
+                        Ok(Expr::Parameter(n - 1))
+                    }
+                    _ => Err(Error::Syntax("cannot mix ? and $n parameters".to_string())),
+                }
+            }
+            Token::Symbol("(") => {
+                self.pos += 1;
+                let expr = if self.peek_query() {
+                    Expr::Subquery(Box::new(self.parse_query()?))
+                } else {
+                    self.parse_expr()?
+                };
+                self.expect_symbol(")")?;
+                Ok(expr)
+            }
+            Token::Word(w) if w == "null" => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Null))
+            }
+            Token::Word(w) if w == "true" || w == "false" => {
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Bool(w == "true")))
+            }
+            // DATE '2024-02-29' and TIMESTAMP '2024-02-29 13:45', the text cast to the type
+            Token::Word(w) if (w == "date" || w == "timestamp") && matches!(self.tokens.get(self.pos + 1), Some(Token::String(_))) => {
+                let data_type = self.parse_data_type()?;
+                let expr = Box::new(self.parse_primary()?);
+                Ok(Expr::Cast { expr, data_type })
+            }
+            // ARRAY[element, ...]
+            Token::Word(w) if w == "array" && matches!(self.tokens.get(self.pos + 1), Some(Token::Symbol("["))) => {
+                self.pos += 2;
+                let mut args = vec![];
+                if !self.consume_symbol("]") {
+                    loop {
+                        args.push(self.parse_expr()?);
+                        if !self.consume_symbol(",") {
+                            break;
+                        }
+                    }
+                    self.expect_symbol("]")?;
+                }
+                Ok(Expr::Function { func: Function::Array, args })
+            }
+            Token::Word(w) if w == "exists" || w == "not" => {
+                let negated = self.consume_keyword("not");
+                self.expect_keyword("exists")?;
+                self.expect_symbol("(")?;
+                let subquery = Box::new(self.parse_query()?);
+                self.expect_symbol(")")?;
+                Ok(Expr::Exists { subquery, negated })
+            }
+            _ => {
+                let name = self.parse_ident()?;
+                if matches!(self.tokens.get(self.pos), Some(Token::Symbol("("))) {
+                    return self.parse_function(&name);
+                }
+                if self.consume_symbol(".") {
+                    let column = self.parse_ident()?;
+                    return Ok(Expr::Column {
+                        table: Some(name),
+                        name: column,
+                    });
+                }
+                Ok(Expr::Column { table: None, name })
+            }
+        }
+    }
+
+    // name(...), where the opening parenthesis is the next token
+    fn parse_function(&mut self, name: &str) -> Result<Expr, Error> {
+        let func = match name {
+            "count" => AggregateFunc::Count,
+            "sum" => AggregateFunc::Sum,
+            "min" => AggregateFunc::Min,
+            "max" => AggregateFunc::Max,
+            _ => return self.parse_scalar_function(name),
+        };
+        self.expect_symbol("(")?;
+        let (arg, distinct) = if func == AggregateFunc::Count && self.consume_symbol("*") {
+            (None, false)
+        } else {
+            let distinct = self.consume_keyword("distinct");
+            if !distinct {
+                self.consume_keyword("all");
+            }
+            (Some(Box::new(self.parse_expr()?)), distinct)
+        };
+        self.expect_symbol(")")?;
+        if self.consume_keyword("over") {
+            if distinct {
+                return Err(Error::Syntax("DISTINCT is not supported for window functions".to_string()));
+            }
+            let args = arg.into_iter().map(|arg| *arg).collect();
+            return self.parse_over(WindowFunc::Aggregate(func), args);
+        }
+        Ok(Expr::Aggregate { func, arg, distinct })
+    }
+
+    // name(arg, ...), along with the SQL forms SUBSTRING(s FROM start [FOR length]) and
+    // TRIM([LEADING | TRAILING | BOTH] [characters] FROM s)
+    fn parse_scalar_function(&mut self, name: &str) -> Result<Expr, Error> {
+        if name == "cast" {
+            self.expect_symbol("(")?;
+            let expr = self.parse_expr()?;
+            self.expect_keyword("as")?;
+            let data_type = self.parse_data_type()?;
+            self.expect_symbol(")")?;
+            return Ok(Expr::Cast {
+                expr: Box::new(expr),
+                data_type,
+            });
+        }
+        let (func, min_args, max_args) = match name {
+            "length" | "char_length" => (Function::Length, 1, 1),
+            "upper" => (Function::Upper, 1, 1),
+            "lower" => (Function::Lower, 1, 1),
+            "substring" | "substr" => (Function::Substring, 2, 3),
+            "concat" => (Function::Concat, 1, usize::MAX),
+            "trim" | "btrim" => (Function::Trim { leading: true, trailing: true }, 1, 2),
+            "ltrim" => (Function::Trim { leading: true, trailing: false }, 1, 2),
+            "rtrim" => (Function::Trim { leading: false, trailing: true }, 1, 2),
+            "ts_rank" => (Function::TsRank, 2, 2),
+            "point" => (Function::Point, 2, 2),
+            "box" => (Function::Box, 2, 2),
+            _ => return self.parse_window_function(name),
+        };
+        self.expect_symbol("(")?;
+        if name == "trim" {
+            let side = ["leading", "trailing", "both"].into_iter().find(|&side| self.consume_keyword(side));
+            let func = match side {
+                Some("leading") => Function::Trim { leading: true, trailing: false },
+                Some("trailing") => Function::Trim { leading: false, trailing: true },
+                _ => func,
+            };
+            let args = if self.consume_keyword("from") {
+                vec![self.parse_expr()?]
+            } else {
+                let first = self.parse_expr()?;
+                if self.consume_keyword("from") {
+                    vec![self.parse_expr()?, first]
+                } else if side.is_some() {
+                    return Err(self.unexpected());
+                } else if self.consume_symbol(",") {
+                    vec![first, self.parse_expr()?]
+                } else {
+                    vec![first]
+                }
+            };
+            self.expect_symbol(")")?;
+            return Ok(Expr::Function { func, args });
+        }
+        let mut args = vec![self.parse_expr()?];
+        if func == Function::Substring && self.consume_keyword("from") {
+            args.push(self.parse_expr()?);
+            if self.consume_keyword("for") {
+                args.push(self.parse_expr()?);
+            }
+        } else {
+            while self.consume_symbol(",") {
+                args.push(self.parse_expr()?);
+            }
+        }
+        self.expect_symbol(")")?;
+        if args.len() < min_args || args.len() > max_args {
+            return Err(Error::Syntax(format!("wrong number of arguments to {}", name)));
+        }
+        Ok(Expr::Function { func, args })
+    }
+
+    // name(arg, ...) OVER (...) of a function which is only a window function, or name(arg, ...)
+    // of another
+    fn parse_window_function(&mut self, name: &str) -> Result<Expr, Error> {
+        let func = match name {
+            "row_number" => Some(WindowFunc::RowNumber),
+            "rank" => Some(WindowFunc::Rank),
+            "dense_rank" => Some(WindowFunc::DenseRank),
+            "lag" => Some(WindowFunc::Lag),
+            "lead" => Some(WindowFunc::Lead),
+            _ => None,
+        };
+        self.expect_symbol("(")?;
+        let mut args = vec![];
+        if !self.consume_symbol(")") {
+            args.push(self.parse_expr()?);
+            while self.consume_symbol(",") {
+                args.push(self.parse_expr()?);
+            }
+            self.expect_symbol(")")?;
+        }
+        // of no built-in function, so maybe of one registered, which binding finds
+        let Some(func) = func else {
+            return Ok(Expr::Call { name: name.to_string(), args });
+        };
+        let valid = match func {
+            WindowFunc::Lag | WindowFunc::Lead => (1..=3).contains(&args.len()),
+            _ => args.is_empty(),
+        };
+        if !valid {
+            return Err(Error::Syntax(format!("wrong number of arguments to {}", name)));
+        }
+        if !self.consume_keyword("over") {
+            return Err(Error::Syntax(format!("window function {} requires an OVER clause", name)));
+        }
+        self.parse_over(func, args)
+    }
+
+    // (PARTITION BY expr, ... ORDER BY expr [ASC | DESC], ... frame), each part being optional
+    fn parse_over(&mut self, func: WindowFunc, args: Vec<Expr>) -> Result<Expr, Error> {
+        self.expect_symbol("(")?;
+        let mut partition_by = vec![];
+        if self.consume_keyword("partition") {
+            self.expect_keyword("by")?;
+            partition_by.push(self.parse_expr()?);
+            while self.consume_symbol(",") {
+                partition_by.push(self.parse_expr()?);
+            }
+        }
+        let mut order_by = vec![];
+        if self.consume_keyword("order") {
+            self.expect_keyword("by")?;
+            loop {
+                let expr = self.parse_expr()?;
+                let descending = self.consume_keyword("desc");
+                if !descending {
+                    self.consume_keyword("asc");
+                }
+                order_by.push(OrderBy { expr, descending });
+                if !self.consume_symbol(",") {
+                    break;
+                }
+            }
+        }
+        let frame = if self.peek_keyword("rows") || self.peek_keyword("range") {
+            Some(self.parse_frame()?)
+        } else {
+            None
+        };
+        self.expect_symbol(")")?;
+        Ok(Expr::Window {
+            func,
+            args,
+            partition_by,
+            order_by,
+            frame,
+        })
+    }
+
+    // {ROWS | RANGE} {start | BETWEEN start AND end}, where the end is CURRENT ROW if omitted
+    fn parse_frame(&mut self) -> Result<Frame, Error> {
+        let units = if self.consume_keyword("rows") {
+            FrameUnits::Rows
+        } else {
+            self.expect_keyword("range")?;
+            FrameUnits::Range
+        };
+        let (start, end) = if self.consume_keyword("between") {
+            let start = self.parse_frame_bound()?;
+            self.expect_keyword("and")?;
+            (start, self.parse_frame_bound()?)
+        } else {
+            (self.parse_frame_bound()?, FrameBound::CurrentRow)
+        };
+        if start == FrameBound::UnboundedFollowing || end == FrameBound::UnboundedPreceding {
+            return Err(Error::Syntax("invalid window frame".to_string()));
+        }
+        let offset = |bound| matches!(bound, FrameBound::Preceding(_) | FrameBound::Following(_));
+        if units == FrameUnits::Range && (offset(start) || offset(end)) {
+            return Err(Error::Syntax("RANGE frames with offsets are not supported".to_string()));
+        }
+        Ok(Frame { units, start, end })
+    }
+
+    fn parse_frame_bound(&mut self) -> Result<FrameBound, Error> {
+        if self.consume_keyword("current") {
+            self.expect_keyword("row")?;
+            return Ok(FrameBound::CurrentRow);
+        }
+        let offset = match self.peek() {
+            Some(Token::Number(n)) => Some(*n as usize),
+            _ => None,
+        };
+        if offset.is_some() {
+            self.pos += 1;
+        } else {
+            self.expect_keyword("unbounded")?;
+        }
+        if self.consume_keyword("preceding") {
+            Ok(offset.map_or(FrameBound::UnboundedPreceding, FrameBound::Preceding))
+        } else {
+            self.expect_keyword("following")?;
+            Ok(offset.map_or(FrameBound::UnboundedFollowing, FrameBound::Following))
+        }
+    }
+}
+
+// The hints of a `/*+ ... */` comment, each a name and the arguments in parentheses, separated
+// by spaces or commas.
+fn parse_hints(text: &str) -> Result<Vec<Hint>, Error> {
+    let (tokens, spans) = tokenize(text)?.into_iter().unzip();
+    let mut parser = Parser { sql: text, tokens, spans, pos: 0, params: None };
+    let mut hints = vec![];
+    while parser.peek().is_some() {
+        let start = parser.spans[parser.pos].start;
+        // of the names, INDEX is a keyword
+        let name = match parser.peek() {
+            Some(Token::Word(word)) => word.clone(),
+            _ => return Err(parser.unexpected()),
+        };
+        parser.pos += 1;
+        parser.expect_symbol("(")?;
+        let mut args = vec![];
+        while !parser.consume_symbol(")") {
+            args.push(parser.parse_ident()?);
+            parser.consume_symbol(",");
+        }
+        let method = |method| Hint::Join { method, tables: args.clone() };
+        hints.push(match (name.as_str(), &args[..]) {
+            ("seqscan", [table]) => Hint::SeqScan(table.clone()),
+            ("index", [table]) => Hint::Index { table: table.clone(), index: None },
+            ("index", [table, index]) => Hint::Index { table: table.clone(), index: Some(index.clone()) },
+            ("leading", [_, _, ..]) => Hint::Leading(args),
+            ("hashjoin", [_, _, ..]) => method(JoinMethod::Hash),
+            ("mergejoin", [_, _, ..]) => method(JoinMethod::Merge),
+            ("nestloop", [_, _, ..]) => method(JoinMethod::NestedLoop),
+            _ => return Err(Error::Syntax(format!("invalid hint: {}", &text[start..parser.spans[parser.pos - 1].end]))),
+        });
+    }
+    Ok(hints)
+}
+
+fn set_operation(op: SetOperator, all: bool, left: Query, right: Query) -> Query {
+    Query::SetOperation {
+        op,
+        all,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+    Expr::Binary {
+        op,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str) -> Expr {
+        Expr::Column {
+            table: None,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test() {
+        let statements = parse(
+            "CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT);
+             SELECT t.id, 1 + 2 * 3 AS n FROM t x JOIN u ON x.id = u.id
+             WHERE NOT EXISTS (SELECT * FROM u) AND id NOT IN (SELECT id FROM u) AND name IS NOT NULL",
+        )
+        .unwrap();
+        assert_eq!(2, statements.len());
+        assert_eq!(
+            Statement::CreateTable(CreateTable {
+                name: "t".to_string(),
+                temporary: false,
+                unlogged: false,
+                columns: vec![
+                    ColumnDef { name: "id".to_string(), data_type: DataType::Integer },
+                    ColumnDef { name: "name".to_string(), data_type: DataType::Text },
+                ],
+                primary_key: vec!["id".to_string()],
+                partition_by: None,
+                partition_of: None,
+                engine: Engine::Disk,
+                ttl: None,
+                dictionary: vec![],
+            }),
+            statements[0]
+        );
+        let select = match &statements[1] {
+            Statement::Select(query) => match &**query {
+                Query::Select(select) => select,
+                _ => panic!(),
+            },
+            _ => panic!(),
+        };
+        assert_eq!(
+            SelectItem::Expr {
+                expr: binary(
+                    BinaryOp::Add,
+                    Expr::Literal(Value::Int(1)),
+                    binary(BinaryOp::Mul, Expr::Literal(Value::Int(2)), Expr::Literal(Value::Int(3)))
+                ),
+                alias: Some("n".to_string()),
+            },
+            select.projection[1]
+        );
+        assert!(matches!(select.from[0], TableRef::Join { .. }));
+        let subquery = Select {
+            hints: vec![],
+            distinct: false,
+            projection: vec![SelectItem::Expr { expr: column("id"), alias: None }],
+            from: vec![TableRef::Table { name: "u".to_string(), alias: None }],
+            selection: None,
+            group_by: vec![],
+            having: None,
+        };
+        match select.selection.as_ref().unwrap() {
+            Expr::Binary { op: BinaryOp::And, left, right } => {
+                assert!(matches!(**right, Expr::IsNull { negated: true, .. }));
+                match &**left {
+                    Expr::Binary { right, .. } => assert_eq!(
+                        Expr::InSubquery {
+                            expr: Box::new(column("id")),
+                            subquery: Box::new(Query::Select(Box::new(subquery))),
+                            negated: true,
+                        },
+                        **right
+                    ),
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        }
+
+        match &parse("SELECT ? + ?").unwrap()[0] {
+            Statement::Select(query) => assert_eq!(
+                SelectItem::Expr {
+                    expr: binary(BinaryOp::Add, Expr::Parameter(0), Expr::Parameter(1)),
+                    alias: None,
+                },
+                match &**query {
+                    Query::Select(select) => select.projection[0].clone(),
+                    _ => panic!(),
+                }
+            ),
+            _ => panic!(),
+        }
+        match &parse("CREATE VIEW v (a) AS SELECT id  FROM t WHERE id > 1; SELECT 1").unwrap()[0] {
+            Statement::CreateView(view) => {
+                assert_eq!(("v", Some(vec!["a".to_string()])), (view.name.as_str(), view.columns.clone()));
+                assert_eq!("SELECT id  FROM t WHERE id > 1", view.sql);
+            }
+            _ => panic!(),
+        }
+        let sql = "CREATE TRIGGER log BEFORE UPDATE ON t FOR EACH ROW WHEN new.id > 1 BEGIN INSERT INTO u VALUES (old.id); INSERT INTO u VALUES (2); END";
+        match &parse(&format!("{}; SELECT 1", sql)).unwrap()[0] {
+            Statement::CreateTrigger(create) => {
+                assert_eq!(("log", "t", TriggerTiming::Before, TriggerEvent::Update), (create.name.as_str(), create.table.as_str(), create.timing, create.event));
+                let new_id = Expr::Column { table: Some("new".to_string()), name: "id".to_string() };
+                assert_eq!(Some(binary(BinaryOp::Gt, new_id, Expr::Literal(Value::Int(1)))), create.when);
+                assert_eq!((2, sql), (create.body.len(), create.sql.as_str()));
+            }
+            _ => panic!(),
+        }
+        assert!(parse("CREATE TRIGGER log AFTER INSERT ON t BEGIN SELECT 1; END").is_err());
+        match &parse("WITH RECURSIVE a (n) AS MATERIALIZED (SELECT 1), b AS (SELECT 2) SELECT * FROM a").unwrap()[0] {
+            Statement::Select(query) => match &**query {
+                Query::With { recursive, ctes, .. } => {
+                    assert!(recursive);
+                    assert_eq!(vec!["a", "b"], ctes.iter().map(|c| c.name.as_str()).collect::<Vec<_>>());
+                    assert_eq!((Some(vec!["n".to_string()]), Some(true)), (ctes[0].columns.clone(), ctes[0].materialized));
+                }
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+        assert!(matches!(parse("EXPLAIN SELECT 1").unwrap()[0], Statement::Explain { analyze: false, .. }));
+        assert!(matches!(parse("EXPLAIN ANALYZE SELECT 1").unwrap()[0], Statement::Explain { analyze: true, .. }));
+        assert_eq!(
+            vec![Statement::Analyze(Some("t".to_string())), Statement::Analyze(None)],
+            parse("ANALYZE t; ANALYZE").unwrap()
+        );
+        match &parse("SELECT DISTINCT team, count(DISTINCT name), count(*) FROM t GROUP BY team HAVING sum(id) > 1").unwrap()[0] {
+            Statement::Select(query) => {
+                let select = match &**query {
+                    Query::Select(select) => select,
+                    _ => panic!(),
+                };
+                assert!(select.distinct);
+                assert_eq!(vec![column("team")], select.group_by);
+                assert_eq!(
+                    SelectItem::Expr {
+                        expr: Expr::Aggregate {
+                            func: AggregateFunc::Count,
+                            arg: Some(Box::new(column("name"))),
+                            distinct: true,
+                        },
+                        alias: None,
+                    },
+                    select.projection[1]
+                );
+                assert!(matches!(&select.projection[2], SelectItem::Expr { expr: Expr::Aggregate { arg: None, .. }, .. }));
+                assert!(matches!(select.having, Some(Expr::Binary { op: BinaryOp::Gt, .. })));
+            }
+            _ => panic!(),
+        }
+        // of no built-in function, so left for binding to find among those registered
+        assert!(matches!(&parse("SELECT avg(x) FROM t").unwrap()[0], Statement::Select(query) if matches!(&**query, Query::Select(select) if matches!(&select.projection[0], SelectItem::Expr { expr: Expr::Call { .. }, .. }))));
+        let sql = "SELECT rank() OVER (PARTITION BY team ORDER BY score DESC, id), \
+                   sum(score) OVER (ROWS BETWEEN 2 PRECEDING AND CURRENT ROW), count(*) OVER () FROM t";
+        match &parse(sql).unwrap()[0] {
+            Statement::Select(query) => {
+                let select = match &**query {
+                    Query::Select(select) => select,
+                    _ => panic!(),
+                };
+                assert_eq!(
+                    SelectItem::Expr {
+                        expr: Expr::Window {
+                            func: WindowFunc::Rank,
+                            args: vec![],
+                            partition_by: vec![column("team")],
+                            order_by: vec![
+                                OrderBy { expr: column("score"), descending: true },
+                                OrderBy { expr: column("id"), descending: false },
+                            ],
+                            frame: None,
+                        },
+                        alias: None,
+                    },
+                    select.projection[0]
+                );
+                let frame = Frame {
+                    units: FrameUnits::Rows,
+                    start: FrameBound::Preceding(2),
+                    end: FrameBound::CurrentRow,
+                };
+                assert!(matches!(&select.projection[1], SelectItem::Expr { expr: Expr::Window { frame: Some(f), .. }, .. } if *f == frame));
+                assert!(matches!(
+                    &select.projection[2],
+                    SelectItem::Expr { expr: Expr::Window { func: WindowFunc::Aggregate(AggregateFunc::Count), .. }, .. }
+                ));
+            }
+            _ => panic!(),
+        }
+        match &parse("SELECT name NOT ILIKE 'a%', substring(name FROM 2 FOR 3), trim(LEADING 'x' FROM name) FROM t").unwrap()[0] {
+            Statement::Select(query) => {
+                let select = match &**query {
+                    Query::Select(select) => select,
+                    _ => panic!(),
+                };
+                let exprs: Vec<_> = select
+                    .projection
+                    .iter()
+                    .map(|item| match item {
+                        SelectItem::Expr { expr, .. } => expr.clone(),
+                        SelectItem::Wildcard => panic!(),
+                    })
+                    .collect();
+                let text = |s: &str| Expr::Literal(Value::Text(s.to_string()));
+                assert_eq!(
+                    vec![
+                        Expr::Like {
+                            expr: Box::new(column("name")),
+                            pattern: Box::new(text("a%")),
+                            negated: true,
+                            case_insensitive: true,
+                        },
+                        Expr::Function {
+                            func: Function::Substring,
+                            args: vec![column("name"), Expr::Literal(Value::Int(2)), Expr::Literal(Value::Int(3))],
+                        },
+                        Expr::Function {
+                            func: Function::Trim { leading: true, trailing: false },
+                            args: vec![column("name"), text("x")],
+                        },
+                    ],
+                    exprs
+                );
+            }
+            _ => panic!(),
+        }
+        assert!(parse("SELECT upper('a', 'b')").is_err());
+        let cast = |expr, data_type| Expr::Cast { expr: Box::new(expr), data_type };
+        match &parse("SELECT CAST(x AS INTEGER)::text FROM t").unwrap()[0] {
+            Statement::Select(query) => match &**query {
+                Query::Select(select) => assert_eq!(
+                    SelectItem::Expr {
+                        expr: cast(cast(column("x"), DataType::Integer), DataType::Text),
+                        alias: None,
+                    },
+                    select.projection[0]
+                ),
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+        assert!(parse("SELECT row_number() FROM t").is_err());
+        assert!(parse("SELECT lag() OVER () FROM t").is_err());
+        assert!(parse("SELECT count(DISTINCT x) OVER () FROM t").is_err());
+        assert!(parse("SELECT sum(x) OVER (ORDER BY x RANGE 1 PRECEDING) FROM t").is_err());
+        assert!(parse("SELECT sum(x) OVER (ROWS UNBOUNDED FOLLOWING) FROM t").is_err());
+        // INTERSECT binds more tightly than UNION
+        match &parse("SELECT 1 UNION ALL SELECT 2 INTERSECT (SELECT 3 EXCEPT SELECT 4)").unwrap()[0] {
+            Statement::Select(query) => match &**query {
+                Query::SetOperation { op: SetOperator::Union, all: true, right, .. } => match &**right {
+                    Query::SetOperation { op: SetOperator::Intersect, all: false, right, .. } => {
+                        assert!(matches!(**right, Query::SetOperation { op: SetOperator::Except, .. }))
+                    }
+                    query => panic!("unexpected query: {:?}", query),
+                },
+                query => panic!("unexpected query: {:?}", query),
+            },
+            _ => panic!(),
+        }
+        assert_eq!(
+            vec![
+                Statement::Begin { isolation: None, read_only: false },
+                Statement::Begin { isolation: Some(Isolation::RepeatableRead), read_only: false },
+                Statement::Commit,
+                Statement::Rollback,
+            ],
+            parse("BEGIN; BEGIN TRANSACTION ISOLATION LEVEL REPEATABLE READ; COMMIT; ROLLBACK TRANSACTION").unwrap()
+        );
+        assert_eq!(
+            vec![Statement::Begin { isolation: Some(Isolation::Serializable), read_only: false }],
+            parse("BEGIN ISOLATION LEVEL SERIALIZABLE").unwrap()
+        );
+        assert_eq!(
+            vec![
+                Statement::Begin { isolation: None, read_only: true },
+                Statement::Begin { isolation: Some(Isolation::RepeatableRead), read_only: true },
+                Statement::Begin { isolation: Some(Isolation::ReadCommitted), read_only: false },
+            ],
+            parse("BEGIN READ ONLY; BEGIN READ ONLY, ISOLATION LEVEL REPEATABLE READ; BEGIN ISOLATION LEVEL READ COMMITTED READ WRITE")
+                .unwrap()
+        );
+        assert!(parse("BEGIN ISOLATION LEVEL READ UNCOMMITTED").is_err());
+        assert_eq!(
+            vec![
+                Statement::Vacuum { table: None, full: false },
+                Statement::Vacuum { table: Some("t".to_string()), full: false },
+                Statement::Vacuum { table: None, full: true },
+                Statement::Vacuum { table: Some("t".to_string()), full: true },
+            ],
+            parse("VACUUM; VACUUM t; VACUUM FULL; VACUUM FULL t").unwrap()
+        );
+        assert_eq!(vec![Statement::Check(None), Statement::Check(Some("t".to_string()))], parse("CHECKDB; checkdb t").unwrap());
+        assert_eq!(
+            vec![Statement::Reindex { name: "i".to_string(), table: false }, Statement::Reindex { name: "t".to_string(), table: true }],
+            parse("REINDEX INDEX i; reindex table t").unwrap()
+        );
+        assert!(parse("REINDEX t").is_err());
+        let partition = |name: &str, bound| {
+            Statement::CreateTable(CreateTable {
+                name: name.to_string(),
+                temporary: false,
+                unlogged: false,
+                columns: vec![],
+                primary_key: vec![],
+                partition_by: None,
+                partition_of: Some(("m".to_string(), bound)),
+                engine: Engine::Disk,
+                ttl: None,
+                dictionary: vec![],
+            })
+        };
+        assert_eq!(
+            vec![
+                Statement::CreateTable(CreateTable {
+                    name: "m".to_string(),
+                    temporary: false,
+                    unlogged: false,
+                    columns: vec![ColumnDef { name: "id".to_string(), data_type: DataType::Integer }],
+                    primary_key: vec!["id".to_string()],
+                    partition_by: Some((PartitionStrategy::Range, "id".to_string())),
+                    partition_of: None,
+                    engine: Engine::Disk,
+                    ttl: None,
+                    dictionary: vec![],
+                }),
+                partition("a", PartitionBound::Range { lower: None, upper: Some(Box::new(Expr::Literal(Value::Int(-10)))) }),
+                partition("b", PartitionBound::Range { lower: Some(Box::new(Expr::Literal(Value::Int(10)))), upper: None }),
+                partition("c", PartitionBound::Hash { modulus: 4, remainder: 0 }),
+            ],
+            parse(
+                "CREATE TABLE m (id INTEGER PRIMARY KEY) PARTITION BY RANGE (id);
+                 CREATE TABLE a PARTITION OF m FOR VALUES FROM (MINVALUE) TO (-10);
+                 CREATE TABLE b PARTITION OF m FOR VALUES FROM (10) TO (maxvalue);
+                 CREATE TABLE c PARTITION OF m FOR VALUES WITH (MODULUS 4, REMAINDER 0)"
+            )
+            .unwrap()
+        );
+        assert!(parse("CREATE TABLE m (id INTEGER PRIMARY KEY) PARTITION BY LIST (id)").is_err());
+        assert!(parse("CREATE TABLE c PARTITION OF m FOR VALUES WITH (MODULUS -1, REMAINDER 0)").is_err());
+        let temporary = |sql| match parse(sql).unwrap().pop() {
+            Some(Statement::CreateTable(create)) => create.temporary,
+            statement => panic!("unexpected statement: {:?}", statement),
+        };
+        assert!(temporary("CREATE TEMP TABLE t (id INTEGER PRIMARY KEY)"));
+        assert!(temporary("create temporary table t (id INTEGER PRIMARY KEY)"));
+        assert!(!temporary("CREATE TABLE t (id INTEGER PRIMARY KEY)"));
+        assert!(parse("CREATE TEMP INDEX i ON t (id)").is_err());
+        let engine = |sql| match parse(sql).unwrap().pop() {
+            Some(Statement::CreateTable(create)) => (create.unlogged, create.engine),
+            statement => panic!("unexpected statement: {:?}", statement),
+        };
+        assert_eq!((false, Engine::Memory), engine("CREATE TABLE t (id INTEGER PRIMARY KEY) USING memory"));
+        assert_eq!((true, Engine::Memory), engine("create unlogged table t (id INTEGER PRIMARY KEY) using MEMORY"));
+        assert_eq!((false, Engine::Disk), engine("CREATE TABLE t (id INTEGER PRIMARY KEY) USING disk"));
+        assert_eq!((false, Engine::Columnar), engine("CREATE TABLE t (id INTEGER PRIMARY KEY) USING columnar"));
+        assert!(parse("CREATE TABLE t (id INTEGER PRIMARY KEY) USING heap").is_err());
+        let ttl = |sql| match parse(sql).unwrap().pop() {
+            Some(Statement::CreateTable(create)) => create.ttl,
+            statement => panic!("unexpected statement: {:?}", statement),
+        };
+        assert_eq!(Some(("seen".to_string(), 3600)), ttl("CREATE TABLE t (id INTEGER PRIMARY KEY, seen INTEGER) USING memory TTL seen + 3600"));
+        assert_eq!(None, ttl("CREATE TABLE t (id INTEGER PRIMARY KEY, seen INTEGER)"));
+        let dictionary = |sql| match parse(sql).unwrap().pop() {
+            Some(Statement::CreateTable(create)) => create.dictionary,
+            statement => panic!("unexpected statement: {:?}", statement),
+        };
+        assert_eq!(vec!["city", "country"], dictionary("CREATE TABLE t (id INTEGER PRIMARY KEY, city TEXT, country TEXT, seen INTEGER) TTL seen + 60 DICTIONARY (city, country)"));
+        assert!(dictionary("CREATE TABLE t (id INTEGER PRIMARY KEY)").is_empty());
+        assert!(parse("CREATE TABLE t (id INTEGER PRIMARY KEY, city TEXT) DICTIONARY ()").is_err());
+        assert!(parse("CREATE TABLE t (id INTEGER PRIMARY KEY, seen INTEGER) TTL seen + -1").is_err());
+        assert_eq!(
+            vec![
+                Statement::LockTable { table: "t".to_string(), mode: LockMode::Exclusive },
+                Statement::LockTable { table: "u".to_string(), mode: LockMode::SharedIntentionExclusive },
+            ],
+            parse("LOCK t; LOCK TABLE u IN SHARE ROW EXCLUSIVE MODE").unwrap()
+        );
+        let set = |name: &str, value: Option<Value>| Statement::Set { name: name.to_string(), value };
+        assert_eq!(
+            vec![
+                set("lock_timeout", Some(Value::Int(100))),
+                set("memory_budget", Some(Value::Int(-1))),
+                set("default_transaction_isolation", Some(Value::Text("repeatable read".to_string()))),
+                set("durability", Some(Value::Text("async".to_string()))),
+                set("enable_hashjoin", None),
+                Statement::Show(Some("lock_timeout".to_string())),
+                Statement::Show(None),
+            ],
+            parse("SET lock_timeout = 100; SET memory_budget TO -1; SET default_transaction_isolation = REPEATABLE READ; SET durability = 'async'; SET enable_hashjoin TO DEFAULT; SHOW lock_timeout; SHOW ALL").unwrap()
+        );
+        assert!(parse("SET lock_timeout 100").is_err());
+        assert_eq!(
+            vec![Statement::Cancel { session: 3, terminate: false }, Statement::Cancel { session: 4, terminate: true }],
+            parse("CANCEL QUERY 3; cancel session 4").unwrap()
+        );
+        assert!(parse("CANCEL 3").is_err() && parse("CANCEL QUERY -1").is_err());
+        let fetch = |name: &str, count| Statement::Fetch { name: name.to_string(), count };
+        assert_eq!(
+            vec![
+                fetch("c", Some(1)),
+                fetch("c", Some(1)),
+                fetch("c", Some(10)),
+                fetch("c", None),
+                fetch("c", Some(1)),
+                fetch("c", Some(5)),
+                fetch("c", None),
+                Statement::CloseCursor(Some("c".to_string())),
+                Statement::CloseCursor(None),
+            ],
+            parse("FETCH c; FETCH NEXT FROM c; FETCH 10 c; FETCH ALL IN c; FETCH FORWARD c; FETCH FORWARD 5 FROM c; FETCH FORWARD ALL c; CLOSE c; CLOSE ALL").unwrap()
+        );
+        assert!(matches!(&parse("DECLARE c CURSOR FOR SELECT * FROM t").unwrap()[..], [Statement::DeclareCursor { name, .. }] if name == "c"));
+        assert!(parse("DECLARE c FOR SELECT 1").is_err() && parse("FETCH -1 c").is_err() && parse("FETCH").is_err());
+        match &parse("INSERT INTO t VALUES (1, 'a') ON CONFLICT (id) DO UPDATE SET name = excluded.name, n = 1 WHERE t.n > 0").unwrap()[..] {
+            [Statement::Insert(Insert { on_conflict: Some(OnConflict { target, action: ConflictAction::Update { assignments, condition } }), .. })] => {
+                assert_eq!(Some(vec!["id".to_string()]), *target);
+                assert_eq!(vec!["name", "n"], assignments.iter().map(|(column, _)| column.as_str()).collect::<Vec<_>>());
+                assert!(matches!(&assignments[0].1, Expr::Column { table: Some(table), name } if table == "excluded" && name == "name"));
+                assert!(condition.is_some());
+            }
+            statements => panic!("{:?}", statements),
+        }
+        assert!(matches!(
+            &parse("INSERT INTO t VALUES (1) ON CONFLICT DO NOTHING").unwrap()[..],
+            [Statement::Insert(Insert { on_conflict: Some(OnConflict { target: None, action: ConflictAction::Nothing }), .. })]
+        ));
+        assert!(matches!(
+            &parse("INSERT INTO t VALUES (1) ON CONFLICT DO NOTHING RETURNING *, id AS i").unwrap()[..],
+            [Statement::Insert(Insert { returning: Some(items), .. })] if items.len() == 2 && matches!(&items[1], SelectItem::Expr { alias: Some(alias), .. } if alias == "i")
+        ));
+        assert!(parse("INSERT INTO t VALUES (1) RETURNING").is_err());
+        assert!(parse("INSERT INTO t VALUES (1) ON CONFLICT DO").is_err() && parse("INSERT INTO t VALUES (1) ON CONFLICT (id) DO UPDATE SET").is_err());
+        assert_eq!(
+            vec![
+                Statement::CreateUser { name: "alice".to_string(), password: Some("secret".to_string()), superuser: false },
+                Statement::CreateUser { name: "bob".to_string(), password: None, superuser: false },
+                Statement::AlterUser { name: "bob".to_string(), password: Some("x".to_string()) },
+                Statement::AlterUser { name: "alice".to_string(), password: None },
+            ],
+            parse("CREATE USER alice WITH PASSWORD 'secret'; CREATE USER bob; ALTER USER bob PASSWORD 'x'; ALTER USER alice WITH PASSWORD NULL").unwrap()
+        );
+        assert!(parse("ALTER USER bob").is_err());
+        let drop = |kind: ObjectKind, name: &str, if_exists: bool| Statement::Drop { kind, name: name.to_string(), if_exists };
+        assert_eq!(
+            vec![drop(ObjectKind::Table, "t", false), drop(ObjectKind::Index, "i", true), drop(ObjectKind::View, "if", false)],
+            parse("DROP TABLE t; DROP INDEX IF EXISTS i; DROP VIEW if").unwrap()
+        );
+        let alter = |action: AlterTable| Statement::AlterTable { table: "t".to_string(), action };
+        assert_eq!(
+            vec![
+                alter(AlterTable::AddColumn(ColumnDef { name: "a".to_string(), data_type: DataType::Text })),
+                alter(AlterTable::AddColumn(ColumnDef { name: "b".to_string(), data_type: DataType::Integer })),
+                alter(AlterTable::Rename("u".to_string())),
+                alter(AlterTable::RenameColumn { column: "a".to_string(), new_name: "c".to_string() }),
+                alter(AlterTable::RenameColumn { column: "b".to_string(), new_name: "d".to_string() }),
+            ],
+            parse("ALTER TABLE t ADD COLUMN a TEXT; ALTER TABLE t ADD b INT; ALTER TABLE t RENAME TO u; ALTER TABLE t RENAME COLUMN a TO c; ALTER TABLE t RENAME b TO d").unwrap()
+        );
+        assert!(parse("DROP t").is_err() && parse("ALTER TABLE t ADD").is_err() && parse("ALTER TABLE t RENAME a").is_err());
+        let grant = |privileges: &[Privilege], table: &str, users: &[&str]| Grant {
+            privileges: privileges.to_vec(),
+            table: table.to_string(),
+            users: users.iter().map(|u| u.to_string()).collect(),
+        };
+        assert_eq!(
+            vec![
+                Statement::CreateUser { name: "root".to_string(), password: Some("x".to_string()), superuser: true },
+                Statement::Grant(grant(&[Privilege::Select, Privilege::Insert], "t", &["alice", "bob"])),
+                Statement::Grant(grant(&Privilege::ALL, "v", &["bob"])),
+                Statement::Revoke(grant(&[Privilege::Delete, Privilege::Update], "t", &["alice"])),
+            ],
+            parse("CREATE USER root WITH SUPERUSER PASSWORD 'x'; GRANT SELECT, insert, SELECT ON t TO alice, bob; GRANT ALL PRIVILEGES ON TABLE v TO bob; REVOKE DELETE, UPDATE ON t FROM alice").unwrap()
+        );
+        assert!(parse("GRANT ON t TO alice").is_err());
+        assert!(parse("REVOKE SELECT ON t TO alice").is_err());
+        assert_eq!(
+            vec![
+                Statement::CopyFrom(CopyFrom {
+                    table: "t".to_string(),
+                    columns: Some(vec!["a".to_string(), "b".to_string()]),
+                    path: "/tmp/t.csv".to_string(),
+                    format: CopyFormat::Csv(CsvOptions { delimiter: ';', header: true, null: "NULL".to_string(), ..Default::default() }),
+                }),
+                Statement::CopyFrom(CopyFrom {
+                    table: "t".to_string(),
+                    columns: None,
+                    path: "t.csv".to_string(),
+                    format: CopyFormat::Csv(CsvOptions::default()),
+                }),
+                Statement::CopyFrom(CopyFrom { table: "t".to_string(), columns: None, path: "t.parquet".to_string(), format: CopyFormat::Parquet }),
+            ],
+            parse("COPY t (a, b) FROM '/tmp/t.csv' WITH (FORMAT csv, DELIMITER ';', HEADER, NULL 'NULL'); COPY t FROM 't.csv'; COPY t FROM 't.parquet' FORMAT parquet")
+                .unwrap()
+        );
+        assert!(parse("COPY t FROM 't.csv' (FORMAT binary)").is_err());
+        assert!(parse("COPY t FROM 't.csv' (DELIMITER '\"')").is_err());
+        let query = |sql: &str| match parse(sql).unwrap().pop() {
+            Some(Statement::Select(query)) => query,
+            statement => panic!("{:?}", statement),
+        };
+        assert_eq!(
+            vec![
+                Statement::CopyTo(CopyTo { query: query("SELECT a, b FROM t"), path: "t.jsonl".to_string(), format: CopyFormat::JsonLines }),
+                Statement::CopyTo(CopyTo {
+                    query: query("SELECT * FROM t"),
+                    path: "t.csv".to_string(),
+                    format: CopyFormat::Csv(CsvOptions { header: true, ..Default::default() }),
+                }),
+                Statement::CopyTo(CopyTo {
+                    query: query("SELECT a FROM t WHERE a > 1"),
+                    path: "t.csv".to_string(),
+                    format: CopyFormat::Csv(CsvOptions { delimiter: '|', ..Default::default() }),
+                }),
+            ],
+            parse("COPY t (a, b) TO 't.jsonl' FORMAT jsonl; COPY t TO 't.csv' WITH FORMAT csv HEADER; COPY (SELECT a FROM t WHERE a > 1) TO 't.csv' (DELIMITER '|')")
+                .unwrap()
+        );
+        assert!(parse("COPY t TO 't.jsonl' (FORMAT jsonl, HEADER)").is_err());
+        assert!(parse("COPY t FROM 't.jsonl' (FORMAT jsonl)").is_err());
+        assert!(parse("COPY t FROM 't.arrows' (FORMAT arrow)").is_err());
+        assert_eq!(CopyFormat::Arrow, match parse("COPY t TO 't.arrows' FORMAT arrow").unwrap().pop() {
+            Some(Statement::CopyTo(copy)) => copy.format,
+            statement => panic!("{:?}", statement),
+        });
+        assert!(parse("COPY t TO 't.parquet' (FORMAT parquet, NULL '')").is_err());
+        assert!(parse("COPY t TO 't.csv' WITH").is_err());
+        for name in ["t_1", "_t", "Id", "select", "a b", "say \"hi\"", "1t"] {
+            let quoted = quote_ident(name);
+            assert_eq!(name != "t_1" && name != "_t", quoted.starts_with('"'));
+            let sql = format!("CREATE INDEX i ON {} (a)", quoted);
+            assert!(matches!(&parse(&sql).unwrap()[0], Statement::CreateIndex(create) if create.table == name));
+        }
+        assert!(matches!(&parse("CREATE INDEX CONCURRENTLY i ON t (a)").unwrap()[0], Statement::CreateIndex(create) if create.name == "i" && create.concurrently));
+        assert!(matches!(&parse("CREATE INDEX concurrently ON t (a)").unwrap()[0], Statement::CreateIndex(create) if create.name == "concurrently" && !create.concurrently));
+        // expressions in parentheses, of which JSON operators bind tighter than comparisons and looser than arithmetic
+        let text = |s: &str| Expr::Literal(Value::Text(s.to_string()));
+        let get = binary(BinaryOp::JsonGet, column("doc"), text("a"));
+        let expected = vec![column("a"), binary(BinaryOp::JsonPathText, get, binary(BinaryOp::Add, Expr::Literal(Value::Int(1)), Expr::Literal(Value::Int(2))))];
+        assert!(matches!(&parse("CREATE INDEX i ON t (a, (doc->'a' #>> 1 + 2))").unwrap()[0], Statement::CreateIndex(create) if create.columns == expected));
+        assert!(parse("CREATE INDEX i ON t (doc -> 'a')").is_err());
+        // ARRAY[...], subscripts binding as tightly as ::, op ANY (array) and unnest in FROM
+        let int = |n: i64| Expr::Literal(Value::Int(n));
+        let select = match parse("SELECT ARRAY[1, 2][a + 1], ARRAY[]::integer[] FROM unnest(x) AS u (e) WHERE e = ANY (b) AND e < ALL (b)").unwrap().pop() {
+            Some(Statement::Select(query)) => match *query {
+                Query::Select(select) => select,
+                query => panic!("{:?}", query),
+            },
+            statement => panic!("{:?}", statement),
+        };
+        let array = Expr::Function { func: Function::Array, args: vec![int(1), int(2)] };
+        let empty = Expr::Cast { expr: Box::new(Expr::Function { func: Function::Array, args: vec![] }), data_type: DataType::Array(ElementType::Integer) };
+        let items: Vec<_> = [binary(BinaryOp::Subscript, array, binary(BinaryOp::Add, column("a"), int(1))), empty].into_iter().map(|expr| SelectItem::Expr { expr, alias: None }).collect();
+        assert_eq!(items, select.projection);
+        assert_eq!(vec![TableRef::Unnest { expr: column("x"), alias: Some("u".to_string()), column: Some("e".to_string()) }], select.from);
+        let quantified = |all| Expr::Quantified { op: if all { BinaryOp::Lt } else { BinaryOp::Eq }, expr: Box::new(column("e")), array: Box::new(column("b")), all };
+        assert_eq!(Some(binary(BinaryOp::And, quantified(false), quantified(true))), select.selection);
+        let select = match parse("SELECT * FROM generate_series(1, 3) AS g(n)").unwrap().pop() {
+            Some(Statement::Select(query)) => match *query {
+                Query::Select(select) => select,
+                query => panic!("{:?}", query),
+            },
+            statement => panic!("{:?}", statement),
+        };
+        let function = TableRef::Function { name: "generate_series".to_string(), args: vec![int(1), int(3)], alias: Some("g".to_string()), columns: vec!["n".to_string()] };
+        assert_eq!(vec![function], select.from);
+        assert!(parse("CREATE TABLE t (a INTEGER[][])").is_err());
+        assert!(parse("SELECT a[1").is_err());
+        assert!(matches!(&parse("CREATE INDEX i ON t USING fulltext (body)").unwrap()[0], Statement::CreateIndex(create) if create.kind == IndexKind::FullText));
+        assert!(matches!(&parse("CREATE INDEX i ON t USING rtree (area)").unwrap()[0], Statement::CreateIndex(create) if create.kind == IndexKind::RTree));
+        assert!(matches!(&parse("CREATE INDEX i ON t USING zonemap (a, b)").unwrap()[0], Statement::CreateIndex(create) if create.kind == IndexKind::ZoneMap));
+        assert!(matches!(&parse("CREATE INDEX i ON t USING btree (a)").unwrap()[0], Statement::CreateIndex(create) if create.kind == IndexKind::BTree));
+        assert!(parse("CREATE INDEX i ON t USING hash (a)").is_err());
+        let select = match parse("SELECT a FROM t WHERE body @@ 'a b'").unwrap().pop() {
+            Some(Statement::Select(query)) => match *query {
+                Query::Select(select) => select,
+                query => panic!("{:?}", query),
+            },
+            statement => panic!("{:?}", statement),
+        };
+        assert_eq!(Some(binary(BinaryOp::Match, column("body"), Expr::Literal(Value::Text("a b".to_string())))), select.selection);
+        let select = match parse("SELECT box(point(0, 0), p) FROM t WHERE area @> '(1,2)'::point OR p <@ area AND area && b").unwrap().pop() {
+            Some(Statement::Select(query)) => match *query {
+                Query::Select(select) => select,
+                query => panic!("{:?}", query),
+            },
+            statement => panic!("{:?}", statement),
+        };
+        let origin = Expr::Function { func: Function::Point, args: vec![int(0), int(0)] };
+        let expr = Expr::Function { func: Function::Box, args: vec![origin, column("p")] };
+        assert_eq!(vec![SelectItem::Expr { expr, alias: None }], select.projection);
+        let point = Expr::Cast { expr: Box::new(Expr::Literal(Value::Text("(1,2)".to_string()))), data_type: DataType::Point };
+        let contained = binary(BinaryOp::And, binary(BinaryOp::ContainedBy, column("p"), column("area")), binary(BinaryOp::Overlaps, column("area"), column("b")));
+        assert_eq!(Some(binary(BinaryOp::Or, binary(BinaryOp::Contains, column("area"), point), contained)), select.selection);
+        assert!(matches!(&parse("CREATE TABLE t (p POINT, b box)").unwrap()[0], Statement::CreateTable(create) if create.columns[1].data_type == DataType::Box));
+        let types = |sql: &str| match &parse(sql).unwrap()[0] {
+            Statement::CreateTable(create) => create.columns.iter().map(|column| column.data_type).collect::<Vec<_>>(),
+            statement => panic!("{:?}", statement),
+        };
+        assert_eq!(vec![DataType::Float, DataType::Float, DataType::Numeric, DataType::Date, DataType::Timestamp], types("CREATE TABLE t (a DOUBLE PRECISION, b real, c DECIMAL, d date, e TIMESTAMP)"));
+        assert!(parse("CREATE TABLE t (a DOUBLE)").is_err());
+        // calls of functions which aren't built in, found when binding
+        let call = Expr::Call { name: "my_fn".to_string(), args: vec![] };
+        assert!(matches!(&parse("SELECT My_Fn()").unwrap()[0], Statement::Select(query) if matches!(&**query, Query::Select(select) if select.projection == vec![SelectItem::Expr { expr: call.clone(), alias: None }])));
+        assert!(parse("SELECT my_fn(1,)").is_err());
+        assert!(parse("SELECT ?, $1").is_err());
+        assert_eq!(
+            "select \"Id\", count (*) from t.a where b in (?, ?) and c = ?",
+            normalize("SELECT \"Id\" , count( * )\n  FROM t . a WHERE b IN (1, 'x') /* c */ AND c = $1").unwrap()
+        );
+        let hints = vec![
+            Hint::Index { table: "t".to_string(), index: Some("t_a".to_string()) },
+            Hint::Leading(vec!["t".to_string(), "u".to_string()]),
+            Hint::Join { method: JoinMethod::Merge, tables: vec!["t".to_string(), "u".to_string()] },
+        ];
+        let sql = "SELECT /*+ INDEX(t t_a) LEADING(t, u) MERGEJOIN(t u) */ 1 FROM t, u";
+        assert!(matches!(&parse(sql).unwrap()[0], Statement::Select(query) if matches!(&**query, Query::Select(select) if select.hints == hints)));
+        assert_eq!("select /*+ INDEX(t t_a) */ ? from t", normalize("SELECT /*+ INDEX(t t_a) */ 1 FROM t").unwrap());
+        assert!(parse("SELECT /*+ INDEX(t) LEADING(t) */ 1 FROM t").is_err());
+        assert!(parse("SELECT /*+ FULL(t) */ 1 FROM t").is_err());
+        assert_eq!(vec!["1", "'x'", "$1"], constants("SELECT \"Id\" FROM t WHERE b IN (1, 'x') AND c = $1").unwrap());
+        assert!(parse("SELECT FROM").is_err());
+        assert!(parse("SELECT 1 2").is_err());
+    }
+}
@@ -0,0 +1,929 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::database::{Database, Session};
+use crate::dump;
+use crate::numeric::Float;
+use crate::sql::{self, quote_ident};
+use crate::tuple::{DataType, Value};
+
+// Migration from SQLite: reads the file of a SQLite database, of the format described at
+// https://www.sqlite.org/fileformat.html, and recreates its tables, their rows and their indexes
+// in a database, in a transaction of its own, so all of them or, after an error, none. A row
+// which can't be stored, of a value which doesn't cast to the type of its column or too large for
+// an entry, is skipped with a warning, and the rest of the table loaded.
+//
+// Names are folded to lower case, as SQLite matches them regardless of case, so queries written
+// for it still find them. A table's primary key becomes its leading columns, and a table without
+// one gets one of its rowids, as `rowid`. Declared types map by SQLite's affinity rules to
+// integer, double precision or text, or boolean if named BOOL, and NUMERIC columns become text.
+// Values are cast to the column's type: reals which are integers to integers, and blobs to text,
+// theirs if it's UTF-8 and else the hex of their bytes after \x, as PostgreSQL writes a bytea.
+// The rows of WITHOUT ROWID tables are read from the b-trees of keys they're stored in. Constraints
+// other than the primary key aren't carried over, nor the indexes SQLite makes for them, and
+// UNIQUE indexes become plain ones. Views, triggers, indexes of expressions or partial ones and
+// virtual tables are skipped, with a warning. Files of UTF-16 text aren't read.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Sql(#[from] sql::Error),
+    #[error("invalid SQLite file: {0}")]
+    Corrupt(String),
+    #[error("unsupported SQLite file: {0}")]
+    Unsupported(String),
+}
+
+const MAGIC: &[u8; 16] = b"SQLite format 3\0";
+const HEADER_SIZE: usize = 100;
+
+const INDEX_INTERIOR: u8 = 0x02;
+const TABLE_INTERIOR: u8 = 0x05;
+const INDEX_LEAF: u8 = 0x0a;
+const TABLE_LEAF: u8 = 0x0d;
+
+// What an import recreated.
+#[derive(Debug, Default, PartialEq)]
+pub struct Import {
+    // of each table, its name and the rows loaded
+    pub tables: Vec<(String, usize)>,
+    pub indexes: Vec<String>,
+    // what was skipped or changed, and why
+    pub warnings: Vec<String>,
+}
+
+// Recreates the tables and indexes of the SQLite database read from `input` in `db`.
+pub fn import(db: &Database, input: impl Read + Seek) -> Result<Import, Error> {
+    let mut file = File::open(input)?;
+    let schema = file.schema()?;
+    let mut session = db.session();
+    session.execute("BEGIN")?;
+    let result = import_schema(&mut file, &mut session, &schema);
+    let end = session.execute(if result.is_ok() { "COMMIT" } else { "ROLLBACK" });
+    let import = result?;
+    end?;
+    Ok(import)
+}
+
+fn import_schema<R: Read + Seek>(file: &mut File<R>, session: &mut Session, schema: &[Object]) -> Result<Import, Error> {
+    let mut import = Import::default();
+    for object in schema.iter().filter(|object| object.kind == "table" && !object.name.starts_with("sqlite_")) {
+        let table = match parse_table(&object.sql) {
+            Ok(table) => table,
+            Err(reason) => {
+                import.warnings.push(format!("table {}: {}, skipped", object.name, reason));
+                continue;
+            }
+        };
+        let (columns, sources) = plan_table(&object.name, &table, &mut import.warnings)?;
+        let name = object.name.to_ascii_lowercase();
+        let mut defs: Vec<_> = columns.iter().map(|(column, data_type)| format!("{} {}", quote_ident(column), dump::type_name(*data_type))).collect();
+        let key: Vec<_> = columns[..table.primary_key.len().max(1)].iter().map(|(column, _)| quote_ident(column)).collect();
+        defs.push(format!("PRIMARY KEY ({})", key.join(", ")));
+        session.execute(&format!("CREATE TABLE {} ({})", quote_ident(&name), defs.join(", ")))?;
+
+        let mut rows = Rows::new(object.root, table.without_rowid);
+        let mut failed = None;
+        // the columns of blobs which aren't UTF-8, loaded as hex
+        let mut binary = vec![];
+        let mut rejected = |_, err| {
+            let reason = match err {
+                sql::Error::Invalid(reason) => reason,
+                err => err.to_string(),
+            };
+            import.warnings.push(format!("table {}: {}, skipped", name, reason));
+        };
+        let loaded = session.load(
+            &name,
+            || {
+                let result = rows.next(file).and_then(|row| {
+                    let Some((rowid, fields)) = row else { return Ok(None) };
+                    let row = sources
+                        .iter()
+                        .zip(&columns)
+                        .map(|(source, (column, _))| match source {
+                            Source::Rowid => rowid.map(Value::Int).ok_or_else(|| Error::Corrupt(format!("table {}: no rowids", name))),
+                            Source::Field(i) => {
+                                let field = fields.get(*i).cloned().unwrap_or(Field::Null);
+                                if matches!(&field, Field::Blob(bytes) if std::str::from_utf8(bytes).is_err()) && !binary.contains(column) {
+                                    binary.push(column.clone());
+                                }
+                                Ok(value(field))
+                            }
+                        })
+                        .collect::<Result<_, _>>()?;
+                    Ok(Some(row))
+                });
+                // kept to return after the load, which takes errors of SQL only
+                result.or_else(|err| {
+                    failed = Some(err);
+                    Ok(None)
+                })
+            },
+            Some(&mut rejected),
+        )?;
+        if let Some(err) = failed {
+            return Err(err);
+        }
+        for column in binary {
+            import.warnings.push(format!("column {}.{}: binary values as hex text", name, column));
+        }
+        import.tables.push((name, loaded));
+    }
+
+    for object in schema {
+        match object.kind.as_str() {
+            "table" => {}
+            // of a constraint, whose SQL is NULL
+            "index" if object.sql.is_empty() => {}
+            "index" => {
+                let table = object.table.to_ascii_lowercase();
+                if !import.tables.iter().any(|(name, _)| *name == table) {
+                    import.warnings.push(format!("index {}: of table {}, which was skipped", object.name, object.table));
+                    continue;
+                }
+                let (unique, columns) = match parse_index(&object.sql) {
+                    Ok(index) => index,
+                    Err(reason) => {
+                        import.warnings.push(format!("index {}: {}, skipped", object.name, reason));
+                        continue;
+                    }
+                };
+                if unique {
+                    import.warnings.push(format!("index {}: unique, created as a plain index", object.name));
+                }
+                let name = object.name.to_ascii_lowercase();
+                let columns: Vec<_> = columns.iter().map(|column| quote_ident(&column.to_ascii_lowercase())).collect();
+                session.execute(&format!("CREATE INDEX {} ON {} ({})", quote_ident(&name), quote_ident(&table), columns.join(", ")))?;
+                import.indexes.push(name);
+            }
+            kind => import.warnings.push(format!("{} {}: skipped", kind, object.name)),
+        }
+    }
+    Ok(import)
+}
+
+// Where a column's values come from.
+enum Source {
+    Rowid,
+    Field(usize),
+}
+
+// The columns of the table as created, the primary key's first, with where their values come
+// from in the records of its rows.
+#[allow(clippy::type_complexity)]
+fn plan_table(name: &str, table: &TableDef, warnings: &mut Vec<String>) -> Result<(Vec<(String, DataType)>, Vec<Source>), Error> {
+    let names: Vec<String> = table.columns.iter().map(|column| column.name.to_ascii_lowercase()).collect();
+    let mut order: Vec<usize> = vec![];
+    for key in &table.primary_key {
+        let i = names
+            .iter()
+            .position(|name| *name == key.to_ascii_lowercase())
+            .ok_or_else(|| Error::Corrupt(format!("table {}: no column {} of its primary key", name, key)))?;
+        order.push(i);
+    }
+    let rest: Vec<_> = (0..names.len()).filter(|i| !order.contains(i)).collect();
+    order.extend(rest);
+
+    let mut columns = vec![];
+    let mut sources = vec![];
+    if table.primary_key.is_empty() {
+        if names.iter().any(|name| name == "rowid") {
+            return Err(Error::Unsupported(format!("table {}: no primary key, and a column named rowid", name)));
+        }
+        columns.push(("rowid".to_string(), DataType::Integer));
+        sources.push(Source::Rowid);
+    }
+    // a single key column INTEGER is the rowid, and NULL in the records, of a table with rowids
+    let is_rowid = !table.without_rowid && table.primary_key.len() == 1 && table.columns[order[0]].data_type.eq_ignore_ascii_case("integer");
+    for (n, &i) in order.iter().enumerate() {
+        let column = &table.columns[i];
+        let data_type = affinity(&column.data_type);
+        if data_type.is_none() {
+            warnings.push(format!("column {}.{}: {} as text", name, column.name, column.data_type));
+        }
+        columns.push((names[i].clone(), data_type.unwrap_or(DataType::Text)));
+        // the records of a table WITHOUT ROWID have the key's fields first, as the columns here
+        sources.push(match n {
+            0 if is_rowid => Source::Rowid,
+            n if table.without_rowid => Source::Field(n),
+            _ => Source::Field(i),
+        });
+    }
+    Ok((columns, sources))
+}
+
+// The type of a column declared as `declared`, by SQLite's rules for its affinity, None for
+// NUMERIC, whose values may be of any type.
+fn affinity(declared: &str) -> Option<DataType> {
+    let declared = declared.to_ascii_uppercase();
+    if declared.contains("INT") {
+        Some(DataType::Integer)
+    } else if declared.contains("BOOL") {
+        Some(DataType::Boolean)
+    } else if declared.contains("CHAR") || declared.contains("CLOB") || declared.contains("TEXT") || declared.contains("BLOB") || declared.is_empty() {
+        Some(DataType::Text)
+    } else if declared.contains("REAL") || declared.contains("FLOA") || declared.contains("DOUB") {
+        Some(DataType::Float)
+    } else {
+        None
+    }
+}
+
+// The field as a value, which the load casts to the type of its column.
+fn value(field: Field) -> Value {
+    match field {
+        Field::Null => Value::Null,
+        Field::Int(n) => Value::Int(n),
+        Field::Real(x) if x.fract() == 0.0 && x.abs() < 9.0e18 => Value::Int(x as i64),
+        Field::Real(x) => Value::Float(Float(x)),
+        Field::Text(s) => Value::Text(s),
+        Field::Blob(bytes) => Value::Text(match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(err) => format!("\\x{}", err.into_bytes().iter().map(|b| format!("{:02x}", b)).collect::<String>()),
+        }),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Null,
+    Int(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+// A row of sqlite_schema.
+struct Object {
+    // table, index, view or trigger
+    kind: String,
+    name: String,
+    table: String,
+    root: u32,
+    // empty for the indexes of constraints
+    sql: String,
+}
+
+struct File<R> {
+    input: R,
+    page_size: usize,
+    // of each page, less what's reserved at its end
+    usable: usize,
+    pages: u32,
+}
+
+impl<R: Read + Seek> File<R> {
+    fn open(mut input: R) -> Result<Self, Error> {
+        let mut header = [0; HEADER_SIZE];
+        input.read_exact(&mut header).map_err(|_| Error::Corrupt("no header".to_string()))?;
+        if &header[..16] != MAGIC {
+            return Err(Error::Corrupt("not a SQLite database".to_string()));
+        }
+        let page_size = match u16::from_be_bytes([header[16], header[17]]) {
+            1 => 65536,
+            size if size >= 512 && size.is_power_of_two() => size as usize,
+            size => return Err(Error::Corrupt(format!("page size {}", size))),
+        };
+        let usable = page_size - header[20] as usize;
+        let encoding = u32::from_be_bytes(header[56..60].try_into().unwrap());
+        if encoding > 1 {
+            return Err(Error::Unsupported("text of UTF-16".to_string()));
+        }
+        let pages = (input.seek(SeekFrom::End(0))? / page_size as u64) as u32;
+        Ok(Self { input, page_size, usable, pages })
+    }
+
+    fn page(&mut self, n: u32) -> Result<Vec<u8>, Error> {
+        if n == 0 || n > self.pages {
+            return Err(Error::Corrupt(format!("page {} of {}", n, self.pages)));
+        }
+        let mut page = vec![0; self.page_size];
+        self.input.seek(SeekFrom::Start((n as u64 - 1) * self.page_size as u64))?;
+        self.input.read_exact(&mut page)?;
+        Ok(page)
+    }
+
+    fn schema(&mut self) -> Result<Vec<Object>, Error> {
+        let mut rows = Rows::new(1, false);
+        let mut objects = vec![];
+        while let Some((_, fields)) = rows.next(self)? {
+            let text = |i: usize| match fields.get(i) {
+                Some(Field::Text(s)) => Ok(s.clone()),
+                Some(Field::Null) => Ok(String::new()),
+                _ => Err(Error::Corrupt("malformed sqlite_schema".to_string())),
+            };
+            let root = match fields.get(3) {
+                Some(Field::Int(n)) => *n as u32,
+                _ => 0,
+            };
+            objects.push(Object { kind: text(0)?, name: text(1)?, table: text(2)?, root, sql: text(4)? });
+        }
+        Ok(objects)
+    }
+
+    // The payload of a cell at `offset` of `page`, `size` bytes, of which what doesn't fit in the
+    // page is on a chain of overflow pages, sooner in a b-tree of keys.
+    fn payload(&mut self, page: &[u8], offset: usize, size: usize, keys: bool) -> Result<Vec<u8>, Error> {
+        let corrupt = || Error::Corrupt("cell out of its page".to_string());
+        let usable = self.usable;
+        let max_local = if keys { (usable - 12) * 64 / 255 - 23 } else { usable - 35 };
+        if size <= max_local {
+            return Ok(page.get(offset..offset + size).ok_or_else(corrupt)?.to_vec());
+        }
+        let min_local = (usable - 12) * 32 / 255 - 23;
+        let local = min_local + (size - min_local) % (usable - 4);
+        let local = if local <= max_local { local } else { min_local };
+        let mut payload = page.get(offset..offset + local).ok_or_else(corrupt)?.to_vec();
+        let mut next = u32::from_be_bytes(page.get(offset + local..offset + local + 4).ok_or_else(corrupt)?.try_into().unwrap());
+        while payload.len() < size {
+            let overflow = self.page(next)?;
+            next = u32::from_be_bytes(overflow[..4].try_into().unwrap());
+            let len = (size - payload.len()).min(usable - 4);
+            payload.extend_from_slice(&overflow[4..4 + len]);
+        }
+        Ok(payload)
+    }
+}
+
+// The rows of a table b-tree in rowid order, read a leaf page at a time, or of the b-tree of keys
+// of a table WITHOUT ROWID in key order, without rowids.
+struct Rows {
+    // pages yet to read and, of a b-tree of keys, the rows of interior pages between them, the
+    // next last
+    stack: Vec<Pending>,
+    leaf: VecDeque<Row>,
+    // pages read, more than there are only if the tree has a cycle
+    visited: u32,
+    keys: bool,
+}
+
+// A row's rowid, if its b-tree has them, and its fields.
+type Row = (Option<i64>, Vec<Field>);
+
+enum Pending {
+    Page(u32),
+    Row(Vec<Field>),
+}
+
+impl Rows {
+    fn new(root: u32, keys: bool) -> Self {
+        Self { stack: vec![Pending::Page(root)], leaf: VecDeque::new(), visited: 0, keys }
+    }
+
+    fn next<R: Read + Seek>(&mut self, file: &mut File<R>) -> Result<Option<Row>, Error> {
+        loop {
+            if let Some(row) = self.leaf.pop_front() {
+                return Ok(Some(row));
+            }
+            let n = match self.stack.pop() {
+                Some(Pending::Page(n)) => n,
+                Some(Pending::Row(fields)) => return Ok(Some((None, fields))),
+                None => return Ok(None),
+            };
+            self.visited += 1;
+            if self.visited > file.pages {
+                return Err(Error::Corrupt("b-tree with a cycle".to_string()));
+            }
+            let page = file.page(n)?;
+            // page 1 starts with the file's header
+            let header = if n == 1 { HEADER_SIZE } else { 0 };
+            let corrupt = || Error::Corrupt(format!("page {}: malformed", n));
+            let kind = page[header];
+            let cells = u16::from_be_bytes([page[header + 3], page[header + 4]]) as usize;
+            let pointers = header + if matches!(kind, TABLE_INTERIOR | INDEX_INTERIOR) { 12 } else { 8 };
+            let cell = |i: usize| -> Result<usize, Error> {
+                let at = pointers + 2 * i;
+                Ok(u16::from_be_bytes(page.get(at..at + 2).ok_or_else(corrupt)?.try_into().unwrap()) as usize)
+            };
+            match (kind, self.keys) {
+                (TABLE_INTERIOR, false) | (INDEX_INTERIOR, true) => {
+                    self.stack.push(Pending::Page(u32::from_be_bytes(page[header + 8..header + 12].try_into().unwrap())));
+                    for i in (0..cells).rev() {
+                        let at = cell(i)?;
+                        // of a b-tree of keys, the row between the child and the next
+                        if self.keys {
+                            let (size, at) = varint(&page, at + 4).ok_or_else(corrupt)?;
+                            let payload = file.payload(&page, at, size as usize, true)?;
+                            self.stack.push(Pending::Row(record(&payload).ok_or_else(corrupt)?));
+                        }
+                        self.stack.push(Pending::Page(u32::from_be_bytes(page.get(at..at + 4).ok_or_else(corrupt)?.try_into().unwrap())));
+                    }
+                }
+                (TABLE_LEAF, false) | (INDEX_LEAF, true) => {
+                    for i in 0..cells {
+                        let (size, at) = varint(&page, cell(i)?).ok_or_else(corrupt)?;
+                        let (rowid, at) = match self.keys {
+                            true => (None, at),
+                            false => varint(&page, at).map(|(rowid, at)| (Some(rowid as i64), at)).ok_or_else(corrupt)?,
+                        };
+                        let payload = file.payload(&page, at, size as usize, self.keys)?;
+                        self.leaf.push_back((rowid, record(&payload).ok_or_else(corrupt)?));
+                    }
+                }
+                (kind, false) => return Err(Error::Corrupt(format!("page {}: of type {}, not of a table", n, kind))),
+                (kind, true) => return Err(Error::Corrupt(format!("page {}: of type {}, not of a table WITHOUT ROWID", n, kind))),
+            }
+        }
+    }
+}
+
+// A variable-length integer at `at`, big-endian 7 bits a byte but the ninth, with the offset
+// after it.
+fn varint(buf: &[u8], mut at: usize) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for i in 0..9 {
+        let b = *buf.get(at)?;
+        at += 1;
+        if i == 8 {
+            return Some(((value << 8) | b as u64, at));
+        }
+        value = (value << 7) | (b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+            break;
+        }
+    }
+    Some((value, at))
+}
+
+// The fields of a record: the size of its header, the serial types of the fields, their values.
+fn record(payload: &[u8]) -> Option<Vec<Field>> {
+    let (header_size, mut at) = varint(payload, 0)?;
+    let mut types = vec![];
+    while at < header_size as usize {
+        let (serial_type, next) = varint(payload, at)?;
+        types.push(serial_type);
+        at = next;
+    }
+    let mut body = payload.get(header_size as usize..)?;
+    let mut fields = vec![];
+    for serial_type in types {
+        let len = match serial_type {
+            0 | 8 | 9 => 0,
+            1..=4 => serial_type as usize,
+            5 => 6,
+            6 | 7 => 8,
+            10 | 11 => return None,
+            n => (n as usize - 12) / 2,
+        };
+        let (bytes, rest) = body.split_at_checked(len)?;
+        body = rest;
+        let int = || bytes.iter().fold(if bytes[0] & 0x80 != 0 { -1i64 } else { 0 }, |n, &b| (n << 8) | b as i64);
+        fields.push(match serial_type {
+            0 => Field::Null,
+            1..=6 => Field::Int(int()),
+            7 => Field::Real(f64::from_bits(u64::from_be_bytes(bytes.try_into().unwrap()))),
+            8 => Field::Int(0),
+            9 => Field::Int(1),
+            n if n % 2 == 0 => Field::Blob(bytes.to_vec()),
+            _ => Field::Text(String::from_utf8_lossy(bytes).into_owned()),
+        });
+    }
+    Some(fields)
+}
+
+// Of the CREATE TABLE of SQLite, the columns and the primary key.
+struct TableDef {
+    columns: Vec<ColumnDef>,
+    primary_key: Vec<String>,
+    without_rowid: bool,
+}
+
+struct ColumnDef {
+    name: String,
+    // the words of the type as declared, empty if there's none
+    data_type: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    // an identifier in "", `` or []
+    Quoted(String),
+    // a string, whose text isn't needed
+    Literal,
+    Symbol(char),
+}
+
+impl Token {
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Word(w) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn name(&self) -> Option<&str> {
+        match self {
+            Token::Word(name) | Token::Quoted(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+// The tokens of SQLite's SQL as far as CREATE TABLE and CREATE INDEX need them, of which the
+// numbers are words.
+fn tokenize(sql: &str) -> Vec<Token> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i += 2;
+        } else if let Some(close) = match c {
+            '"' | '`' | '\'' => Some(c),
+            '[' => Some(']'),
+            _ => None,
+        } {
+            let mut text = String::new();
+            i += 1;
+            while i < chars.len() {
+                if chars[i] == close && chars.get(i + 1) == Some(&close) && close != ']' {
+                    text.push(close);
+                    i += 2;
+                } else if chars[i] == close {
+                    i += 1;
+                    break;
+                } else {
+                    text.push(chars[i]);
+                    i += 1;
+                }
+            }
+            tokens.push(if c == '\'' { Token::Literal } else { Token::Quoted(text) });
+        } else if c.is_alphanumeric() || c == '_' || c == '$' || c == '.' && chars.get(i + 1).is_some_and(char::is_ascii_digit) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$' || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token::Word(chars[start..i].iter().collect()));
+        } else {
+            tokens.push(Token::Symbol(c));
+            i += 1;
+        }
+    }
+    tokens
+}
+
+// The tokens between the parenthesis at `open` and its match, split at the commas between them,
+// and the index after the match.
+fn split_parenthesized(tokens: &[Token], open: usize) -> Option<(Vec<&[Token]>, usize)> {
+    let mut parts = vec![];
+    let (mut depth, mut start) = (0, open + 1);
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Symbol('(') => depth += 1,
+            Token::Symbol(')') if depth == 1 => {
+                parts.push(&tokens[start..i]);
+                return Some((parts, i + 1));
+            }
+            Token::Symbol(')') => depth -= 1,
+            Token::Symbol(',') if depth == 1 => {
+                parts.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Words which end the type of a column and start its constraints.
+const CONSTRAINTS: &[&str] = &[
+    "constraint", "primary", "not", "null", "unique", "check", "default", "collate", "references", "generated", "as",
+];
+
+// Of a CREATE TABLE, the columns and the primary key, or why it can't be imported.
+fn parse_table(sql: &str) -> Result<TableDef, String> {
+    let tokens = tokenize(sql);
+    if tokens.iter().take(3).any(|token| token.is_keyword("virtual")) {
+        return Err("a virtual table".to_string());
+    }
+    let open = tokens.iter().position(|token| *token == Token::Symbol('(')).ok_or("no columns")?;
+    let (parts, end) = split_parenthesized(&tokens, open).ok_or("unbalanced parentheses")?;
+    let without_rowid = tokens[end..].iter().any(|token| token.is_keyword("without"));
+    let mut table = TableDef { columns: vec![], primary_key: vec![], without_rowid };
+    for part in parts {
+        let first = part.first().ok_or("an empty column definition")?;
+        if ["constraint", "primary", "unique", "check", "foreign"].iter().any(|keyword| first.is_keyword(keyword)) {
+            if let Some(at) = part.windows(2).position(|pair| pair[0].is_keyword("primary") && pair[1].is_keyword("key")) {
+                let open = part.iter().skip(at).position(|token| *token == Token::Symbol('(')).ok_or("no columns of the primary key")? + at;
+                let (columns, _) = split_parenthesized(part, open).ok_or("unbalanced parentheses")?;
+                table.primary_key = columns
+                    .iter()
+                    .map(|column| column.first().and_then(Token::name).map(String::from).ok_or("a primary key of expressions"))
+                    .collect::<Result<_, _>>()?;
+            }
+            continue;
+        }
+        let name = first.name().ok_or("a column without a name")?.to_string();
+        let mut words = vec![];
+        for token in &part[1..] {
+            match token {
+                Token::Word(w) if !CONSTRAINTS.iter().any(|keyword| w.eq_ignore_ascii_case(keyword)) => words.push(w.as_str()),
+                _ => break,
+            }
+        }
+        if part.windows(2).any(|pair| pair[0].is_keyword("primary") && pair[1].is_keyword("key")) {
+            table.primary_key = vec![name.clone()];
+        }
+        table.columns.push(ColumnDef { name, data_type: words.join(" ") });
+    }
+    Ok(table)
+}
+
+// Of a CREATE INDEX, whether it's UNIQUE and the columns, or why it can't be imported.
+fn parse_index(sql: &str) -> Result<(bool, Vec<String>), String> {
+    let tokens = tokenize(sql);
+    let unique = tokens.get(1).is_some_and(|token| token.is_keyword("unique"));
+    let open = tokens.iter().position(|token| *token == Token::Symbol('(')).ok_or("no columns")?;
+    let (parts, end) = split_parenthesized(&tokens, open).ok_or("unbalanced parentheses")?;
+    if tokens[end..].iter().any(|token| token.is_keyword("where")) {
+        return Err("a partial index".to_string());
+    }
+    let columns = parts
+        .iter()
+        .map(|part| {
+            // a name, then COLLATE and its name, ASC or DESC only
+            let name = part.first().and_then(Token::name).ok_or("an index of expressions")?;
+            if part[1..].iter().any(|token| !matches!(token, Token::Word(_) | Token::Quoted(_))) {
+                return Err("an index of expressions");
+            }
+            Ok(name.to_string())
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((unique, columns))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::QueryResult;
+    use std::io::Cursor;
+    use tempfile::tempdir;
+
+    const PAGE_SIZE: usize = 512;
+
+    fn put_varint(n: u64, out: &mut Vec<u8>) {
+        let mut bytes = vec![(n & 0x7f) as u8];
+        let mut n = n >> 7;
+        while n > 0 {
+            bytes.push((n & 0x7f) as u8 | 0x80);
+            n >>= 7;
+        }
+        out.extend(bytes.iter().rev());
+    }
+
+    fn encode_record(fields: &[Field]) -> Vec<u8> {
+        let (mut types, mut body) = (vec![], vec![]);
+        for field in fields {
+            match field {
+                Field::Null => types.push(0),
+                Field::Int(n) => {
+                    let (serial_type, len) = match n {
+                        -128..=127 => (1, 1),
+                        -32768..=32767 => (2, 2),
+                        -8388608..=8388607 => (3, 3),
+                        -2147483648..=2147483647 => (4, 4),
+                        -140737488355328..=140737488355327 => (5, 6),
+                        _ => (6, 8),
+                    };
+                    types.push(serial_type);
+                    body.extend_from_slice(&n.to_be_bytes()[8 - len..]);
+                }
+                Field::Real(x) => {
+                    types.push(7);
+                    body.extend_from_slice(&x.to_bits().to_be_bytes());
+                }
+                Field::Text(s) => {
+                    types.push(13 + 2 * s.len() as u64);
+                    body.extend_from_slice(s.as_bytes());
+                }
+                Field::Blob(bytes) => {
+                    types.push(12 + 2 * bytes.len() as u64);
+                    body.extend_from_slice(bytes);
+                }
+            }
+        }
+        let mut header = vec![];
+        for serial_type in types {
+            put_varint(serial_type, &mut header);
+        }
+        let mut record = vec![header.len() as u8 + 1];
+        record.extend(header);
+        record.extend(body);
+        record
+    }
+
+    // A page of the type with cells, laid out from its end, after `header` bytes.
+    fn page(kind: u8, header: usize, right: u32, cells: &[Vec<u8>]) -> Vec<u8> {
+        let mut page = vec![0; PAGE_SIZE];
+        page[header] = kind;
+        page[header + 3..header + 5].copy_from_slice(&(cells.len() as u16).to_be_bytes());
+        let interior = matches!(kind, TABLE_INTERIOR | INDEX_INTERIOR);
+        if interior {
+            page[header + 8..header + 12].copy_from_slice(&right.to_be_bytes());
+        }
+        let mut pointer = header + if interior { 12 } else { 8 };
+        let mut end = PAGE_SIZE;
+        for cell in cells {
+            end -= cell.len();
+            page[end..end + cell.len()].copy_from_slice(cell);
+            page[pointer..pointer + 2].copy_from_slice(&(end as u16).to_be_bytes());
+            pointer += 2;
+        }
+        page[header + 5..header + 7].copy_from_slice(&(end as u16).to_be_bytes());
+        page
+    }
+
+    // A cell of a table leaf, of which what doesn't fit goes to the overflow pages from `overflow`
+    // on.
+    fn leaf_cell(rowid: i64, fields: &[Field], overflow: u32, pages: &mut Vec<(u32, Vec<u8>)>) -> Vec<u8> {
+        let payload = encode_record(fields);
+        let mut cell = vec![];
+        put_varint(payload.len() as u64, &mut cell);
+        put_varint(rowid as u64, &mut cell);
+        let (max_local, min_local) = (PAGE_SIZE - 35, (PAGE_SIZE - 12) * 32 / 255 - 23);
+        if payload.len() <= max_local {
+            cell.extend(payload);
+            return cell;
+        }
+        let local = min_local + (payload.len() - min_local) % (PAGE_SIZE - 4);
+        let local = if local <= max_local { local } else { min_local };
+        cell.extend_from_slice(&payload[..local]);
+        cell.extend_from_slice(&overflow.to_be_bytes());
+        for (i, chunk) in payload[local..].chunks(PAGE_SIZE - 4).enumerate() {
+            let n = overflow + i as u32;
+            let mut overflow_page = vec![0; PAGE_SIZE];
+            if local + (i + 1) * (PAGE_SIZE - 4) < payload.len() {
+                overflow_page[..4].copy_from_slice(&(n + 1).to_be_bytes());
+            }
+            overflow_page[4..4 + chunk.len()].copy_from_slice(chunk);
+            pages.push((n, overflow_page));
+        }
+        cell
+    }
+
+    // A cell of a b-tree of keys, of an interior page if it has a `child`, its row small enough to
+    // be all in the page.
+    fn key_cell(child: Option<u32>, fields: &[Field]) -> Vec<u8> {
+        let payload = encode_record(fields);
+        let mut cell = child.map_or(vec![], |child| child.to_be_bytes().to_vec());
+        put_varint(payload.len() as u64, &mut cell);
+        cell.extend(payload);
+        cell
+    }
+
+    #[test]
+    fn test() {
+        let text = |s: &str| Field::Text(s.to_string());
+        let schema = [
+            ("table", "Users", "Users", 2, "CREATE TABLE \"Users\" (\n  id INTEGER PRIMARY KEY, -- the rowid\n  [Name] VARCHAR(20) NOT NULL DEFAULT 'x, y',\n  score REAL,\n  active BOOLEAN CHECK (active IN (0, 1)),\n  bio\n)"),
+            ("table", "tags", "tags", 6, "CREATE TABLE tags (user INT REFERENCES Users (id), tag TEXT, CONSTRAINT pk PRIMARY KEY (tag COLLATE NOCASE, user))"),
+            ("table", "log", "log", 7, "CREATE TABLE log (msg TEXT, at NUMERIC)"),
+            ("table", "kv", "kv", 22, "CREATE TABLE kv (v, k TEXT, PRIMARY KEY (k)) WITHOUT ROWID"),
+            ("table", "files", "files", 25, "CREATE TABLE files (name TEXT PRIMARY KEY, data BLOB)"),
+            ("table", "sqlite_sequence", "sqlite_sequence", 8, "CREATE TABLE sqlite_sequence(name,seq)"),
+            ("index", "users_name", "Users", 9, "CREATE INDEX users_name ON \"Users\" (Name COLLATE NOCASE DESC, active)"),
+            ("index", "tags_user", "tags", 9, "CREATE UNIQUE INDEX tags_user ON tags (user)"),
+            ("index", "sqlite_autoindex_tags_1", "tags", 9, ""),
+            ("index", "users_lower", "Users", 9, "CREATE INDEX users_lower ON Users (lower(Name))"),
+            ("index", "log_recent", "log", 9, "CREATE INDEX log_recent ON log (at) WHERE at > 0"),
+            ("view", "active_users", "active_users", 0, "CREATE VIEW active_users AS SELECT * FROM Users WHERE active"),
+        ];
+        // sqlite_schema over a leaf a row under page 1
+        let mut pages = vec![];
+        let mut children = vec![];
+        for (i, &(kind, name, table, root, sql)) in schema.iter().enumerate() {
+            let sql = if sql.is_empty() { Field::Null } else { text(sql) };
+            let cell = leaf_cell(i as i64 + 1, &[text(kind), text(name), text(table), Field::Int(root), sql], 0, &mut pages);
+            pages.push((10 + i as u32, page(TABLE_LEAF, 0, 0, &[cell])));
+            let mut child = (10 + i as u32).to_be_bytes().to_vec();
+            put_varint(i as u64 + 1, &mut child);
+            children.push(child);
+        }
+        let right = children.pop().unwrap();
+        let mut first = page(TABLE_INTERIOR, HEADER_SIZE, u32::from_be_bytes(right[..4].try_into().unwrap()), &children);
+        first[..16].copy_from_slice(MAGIC);
+        first[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+        first[56..60].copy_from_slice(&1u32.to_be_bytes());
+        pages.push((1, first));
+
+        // Users over two leaves under an interior page, the second row's bio on an overflow page
+        let long = "long ".repeat(101);
+        let users = [
+            vec![Field::Null, text("ann"), Field::Real(3.0), Field::Int(1)],
+            vec![Field::Null, text("bob"), Field::Real(2.5), Field::Int(0), text(&long)],
+            vec![Field::Null, Field::Blob(b"cy".to_vec()), Field::Null, Field::Null, Field::Int(7)],
+        ];
+        let cells = [leaf_cell(1, &users[0], 0, &mut pages), leaf_cell(5, &users[1], 5, &mut pages)];
+        pages.push((3, page(TABLE_LEAF, 0, 0, &cells)));
+        let cell = leaf_cell(9, &users[2], 0, &mut pages);
+        pages.push((4, page(TABLE_LEAF, 0, 0, &[cell])));
+        let mut interior = vec![0, 0, 0, 3];
+        put_varint(5, &mut interior);
+        pages.push((2, page(TABLE_INTERIOR, 0, 4, &[interior])));
+        let tags = [vec![Field::Int(5), text("b")], vec![Field::Int(1), text("a")]];
+        let cells: Vec<_> = tags.iter().enumerate().map(|(i, row)| leaf_cell(i as i64 + 1, row, 0, &mut pages)).collect();
+        pages.push((6, page(TABLE_LEAF, 0, 0, &cells)));
+        let cells = [leaf_cell(10, &[text("up")], 0, &mut pages), leaf_cell(11, &[text("down"), Field::Real(1.5)], 0, &mut pages)];
+        pages.push((7, page(TABLE_LEAF, 0, 0, &cells)));
+        pages.push((8, page(TABLE_LEAF, 0, 0, &[])));
+        pages.push((9, page(TABLE_LEAF, 0, 0, &[])));
+        // kv's b-tree of keys, a row in its interior page between its two leaves, the key first
+        pages.push((22, page(INDEX_INTERIOR, 0, 24, &[key_cell(Some(23), &[text("b"), Field::Int(2)])])));
+        pages.push((23, page(INDEX_LEAF, 0, 0, &[key_cell(None, &[text("a"), text("1")])])));
+        pages.push((24, page(INDEX_LEAF, 0, 0, &[key_cell(None, &[text("c"), Field::Null])])));
+        // files of blobs, binary, too large once in hex, on two overflow pages, and of UTF-8
+        let files = [
+            vec![text("a.bin"), Field::Blob(vec![0xff, 0, 1])],
+            vec![text("big.bin"), Field::Blob(vec![0xab; 1200])],
+            vec![text("hi.txt"), Field::Blob(b"hi".to_vec())],
+        ];
+        let cells: Vec<_> = files.iter().enumerate().map(|(i, row)| leaf_cell(i as i64 + 1, row, 26, &mut pages)).collect();
+        pages.push((25, page(TABLE_LEAF, 0, 0, &cells)));
+        pages.sort_by_key(|(n, _)| *n);
+        assert!(pages.iter().enumerate().all(|(i, (n, _))| *n as usize == i + 1));
+        let file: Vec<u8> = pages.into_iter().flat_map(|(_, page)| page).collect();
+
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path().join("db"), 64).unwrap();
+        let import = import(&db, Cursor::new(&file)).unwrap();
+        assert_eq!(
+            vec![("users".to_string(), 3), ("tags".to_string(), 2), ("log".to_string(), 2), ("kv".to_string(), 3), ("files".to_string(), 2)],
+            import.tables
+        );
+        assert_eq!(vec!["users_name".to_string(), "tags_user".to_string()], import.indexes);
+        assert_eq!(
+            vec![
+                "column log.at: NUMERIC as text",
+                "table files: row 2: too large to store, skipped",
+                "column files.data: binary values as hex text",
+                "index tags_user: unique, created as a plain index",
+                "index users_lower: an index of expressions, skipped",
+                "index log_recent: a partial index, skipped",
+                "view active_users: skipped",
+            ],
+            import.warnings
+        );
+        let mut session = db.session();
+        let rows = |session: &mut Session, sql: &str| match session.execute(sql).unwrap().remove(0) {
+            QueryResult::Rows { rows, .. } => rows,
+            result => panic!("{:?}", result),
+        };
+        assert_eq!(
+            vec![
+                vec![Value::Int(1), Value::Text("ann".to_string()), Value::Float(Float(3.0)), Value::Bool(true), Value::Null],
+                vec![Value::Int(5), Value::Text("bob".to_string()), Value::Float(Float(2.5)), Value::Bool(false), Value::Text(long)],
+                vec![Value::Int(9), Value::Text("cy".to_string()), Value::Null, Value::Null, Value::Text("7".to_string())],
+            ],
+            rows(&mut session, "SELECT id, name, score, active, bio FROM users")
+        );
+        assert_eq!(
+            vec![vec![Value::Text("a".to_string()), Value::Int(1)], vec![Value::Text("b".to_string()), Value::Int(5)]],
+            rows(&mut session, "SELECT * FROM tags")
+        );
+        assert_eq!(
+            vec![vec![Value::Int(10), Value::Text("up".to_string()), Value::Null], vec![Value::Int(11), Value::Text("down".to_string()), Value::Text("1.5".to_string())]],
+            rows(&mut session, "SELECT * FROM log")
+        );
+        assert_eq!(1, rows(&mut session, "SELECT id FROM users WHERE name = 'bob'").len());
+        assert_eq!(
+            vec![
+                vec![Value::Text("a".to_string()), Value::Text("1".to_string())],
+                vec![Value::Text("b".to_string()), Value::Text("2".to_string())],
+                vec![Value::Text("c".to_string()), Value::Null],
+            ],
+            rows(&mut session, "SELECT * FROM kv")
+        );
+        assert_eq!(
+            vec![
+                vec![Value::Text("a.bin".to_string()), Value::Text("\\xff0001".to_string())],
+                vec![Value::Text("hi.txt".to_string()), Value::Text("hi".to_string())],
+            ],
+            rows(&mut session, "SELECT * FROM files")
+        );
+
+        // all or nothing: the tables exist now, so importing again fails, leaving them
+        assert!(matches!(super::import(&db, Cursor::new(&file)), Err(Error::Sql(_))));
+        assert_eq!(3, rows(&mut session, "SELECT * FROM users").len());
+        // of which tags' leaf is corrupt, after users is loaded
+        let mut corrupt = file.clone();
+        corrupt[5 * PAGE_SIZE] = 0x02;
+        let empty = Database::open(dir.path().join("corrupt"), 64).unwrap();
+        assert!(matches!(super::import(&empty, Cursor::new(&corrupt)), Err(Error::Corrupt(_))));
+        assert!(empty.with_engine(|_, catalog| catalog.tables().count() == 0));
+        assert!(matches!(super::import(&empty, Cursor::new(&file[..4 * PAGE_SIZE])), Err(Error::Corrupt(_))));
+        assert!(empty.with_engine(|_, catalog| catalog.tables().count() == 0));
+        assert!(matches!(super::import(&empty, Cursor::new(b"not sqlite")), Err(Error::Corrupt(_))));
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::buffer::{self, BufferPoolManager};
+use crate::disk::PageId;
+use crate::mvcc::{Isolation, Snapshot, Version};
+use crate::wal::TxId;
+
+// Serializable snapshot isolation: serializable transactions read snapshots like repeatable read
+// ones, and the read/write dependencies between them are tracked to find where they may not be
+// serializable. T1 depends on T2 (T1 -rw-> T2) if they're concurrent and T1 read a row, or may
+// have, that T2 wrote, i.e. T1 didn't see the changes of T2 it should have if it were run after.
+// Every cycle of dependencies which makes transactions not serializable has a pivot, which both
+// depends on and is depended on by others, so a transaction fails rather than making one a pivot.
+//
+// The rows read are recorded as rows of a table whose primary keys start with a prefix, the empty
+// one when read through a secondary index or scanned, and like the dependencies they are kept
+// after a transaction commits until no transaction concurrent with it is left running.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error("could not serialize access due to read/write dependencies among transactions")]
+    SerializationFailure,
+}
+
+#[derive(Debug, Default)]
+pub struct Ssi {
+    transactions: BTreeMap<TxId, Transaction>,
+}
+
+#[derive(Debug)]
+struct Transaction {
+    snapshot: Snapshot,
+    committed: bool,
+    // (table, primary key prefix) of the rows read
+    reads: Vec<(PageId, Vec<u8>)>,
+    // transactions depending on this one, and those this one depends on
+    dependents: BTreeSet<TxId>,
+    dependencies: BTreeSet<TxId>,
+}
+
+impl Ssi {
+    pub fn register(&mut self, txid: TxId, snapshot: Snapshot) {
+        let txn = Transaction {
+            snapshot,
+            committed: false,
+            reads: vec![],
+            dependents: BTreeSet::new(),
+            dependencies: BTreeSet::new(),
+        };
+        self.transactions.insert(txid, txn);
+    }
+
+    // Adds `reader -rw-> writer` if both are serializable, failing instead if either would be a
+    // pivot then.
+    fn depend(&mut self, reader: TxId, writer: TxId) -> Result<(), Error> {
+        if reader == writer || !self.transactions.contains_key(&reader) || !self.transactions.contains_key(&writer) {
+            return Ok(());
+        }
+        if !self.transactions[&reader].dependents.is_empty() || !self.transactions[&writer].dependencies.is_empty() {
+            return Err(Error::SerializationFailure);
+        }
+        self.transactions.get_mut(&reader).unwrap().dependencies.insert(writer);
+        self.transactions.get_mut(&writer).unwrap().dependents.insert(reader);
+        Ok(())
+    }
+
+    // Ends `txid`, forgetting the transactions which no running one is concurrent with.
+    pub fn end(&mut self, txid: TxId, committed: bool) {
+        if committed {
+            if let Some(txn) = self.transactions.get_mut(&txid) {
+                txn.committed = true;
+            }
+        } else if self.transactions.remove(&txid).is_some() {
+            for txn in self.transactions.values_mut() {
+                txn.dependents.remove(&txid);
+                txn.dependencies.remove(&txid);
+            }
+        }
+        let running: Vec<_> = self.transactions.values().filter(|txn| !txn.committed).map(|txn| &txn.snapshot).collect();
+        let released: Vec<_> = self
+            .transactions
+            .iter()
+            .filter(|(&txid, txn)| txn.committed && running.iter().all(|snapshot| snapshot.sees(txid)))
+            .map(|(&txid, _)| txid)
+            .collect();
+        for txid in released {
+            self.transactions.remove(&txid);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+}
+
+// Id of the current transaction if it's serializable.
+fn current(bufmgr: &mut BufferPoolManager) -> Option<TxId> {
+    if bufmgr.isolation() != Some(Isolation::Serializable) {
+        return None;
+    }
+    let txid = bufmgr.current_txid()?;
+    bufmgr.ssi().transactions.contains_key(&txid).then_some(txid)
+}
+
+// Records that the current transaction has read the rows of `table` whose primary keys start
+// with `prefix`.
+pub fn read(bufmgr: &mut BufferPoolManager, table: PageId, prefix: &[u8]) {
+    if let Some(txid) = current(bufmgr) {
+        let reads = &mut bufmgr.ssi().transactions.get_mut(&txid).unwrap().reads;
+        if !reads.iter().any(|(t, p)| *t == table && prefix.starts_with(p)) {
+            reads.push((table, prefix.to_vec()));
+        }
+    }
+}
+
+// Adds the dependencies of the current transaction reading a row with `versions`, newest first:
+// it depends on the writers of the versions newer than the one it sees, and the one deleting or
+// replacing it.
+pub fn read_versions(bufmgr: &mut BufferPoolManager, versions: &[Version]) -> Result<(), Error> {
+    let Some(reader) = current(bufmgr) else {
+        return Ok(());
+    };
+    for version in versions {
+        if bufmgr.sees(version.xmin) {
+            if let Some(xmax) = version.xmax.filter(|&xmax| !bufmgr.sees(xmax) && !bufmgr.is_aborted(xmax)) {
+                bufmgr.ssi().depend(reader, xmax)?;
+            }
+            break;
+        }
+        if !bufmgr.is_aborted(version.xmin) {
+            bufmgr.ssi().depend(reader, version.xmin)?;
+        }
+    }
+    Ok(())
+}
+
+// Adds the dependencies on the current transaction writing the row of `table` with `pkey` of the
+// concurrent transactions which have read it.
+pub fn write(bufmgr: &mut BufferPoolManager, table: PageId, pkey: &[u8]) -> Result<(), Error> {
+    let Some(writer) = current(bufmgr) else {
+        return Ok(());
+    };
+    let readers: Vec<_> = bufmgr
+        .ssi()
+        .transactions
+        .iter()
+        .filter(|(_, txn)| txn.reads.iter().any(|(t, prefix)| *t == table && pkey.starts_with(prefix)))
+        .map(|(&txid, txn)| (txid, txn.committed))
+        .collect();
+    for (reader, committed) in readers {
+        // a committed reader is concurrent unless it's seen
+        if !committed || !bufmgr.sees(reader) {
+            bufmgr.ssi().depend(reader, writer)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::table::{self, Table};
+    use crate::tuple::Value;
+    use crate::wal::{Wal, DEFAULT_SEGMENT_SIZE};
+    use tempfile::{tempdir, tempfile};
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let wal_dir = tempdir().unwrap();
+        let wal = Wal::open(wal_dir.path(), DEFAULT_SEGMENT_SIZE).unwrap();
+        let mut bufmgr = BufferPoolManager::with_wal(disk, BufferPool::new(8), wal).unwrap();
+        let table = Table::create(&mut bufmgr, 1).unwrap();
+        table.insert(&mut bufmgr, &[Value::Int(1)]).unwrap();
+        bufmgr.commit().unwrap();
+        let count = |bufmgr: &mut BufferPoolManager| {
+            let mut iter = table.scan(bufmgr).unwrap();
+            let mut count = 0;
+            while iter.next(bufmgr).unwrap().is_some() {
+                count += 1;
+            }
+            count
+        };
+        let is_failure = |result: Result<(), table::Error>| matches!(result, Err(table::Error::Ssi(Error::SerializationFailure)));
+
+        // write skew: each inserts a row if there's one, which it wouldn't do after the other
+        for isolation in [Isolation::RepeatableRead, Isolation::Serializable] {
+            bufmgr.begin(isolation).unwrap();
+            assert_eq!(1, count(&mut bufmgr));
+            let first = bufmgr.switch(Default::default());
+            bufmgr.begin(isolation).unwrap();
+            assert_eq!(1, count(&mut bufmgr));
+            table.insert(&mut bufmgr, &[Value::Int(2)]).unwrap();
+            let second = bufmgr.switch(first);
+            let result = table.insert(&mut bufmgr, &[Value::Int(3)]);
+            if isolation == Isolation::Serializable {
+                assert!(is_failure(result));
+                bufmgr.abort().unwrap();
+            } else {
+                result.unwrap();
+                bufmgr.commit().unwrap();
+            }
+            bufmgr.switch(second);
+            bufmgr.commit().unwrap();
+            assert!(table.delete(&mut bufmgr, &[Value::Int(2)]).unwrap());
+            table.delete(&mut bufmgr, &[Value::Int(3)]).unwrap();
+            bufmgr.commit().unwrap();
+        }
+
+        // a dependency on a committed writer of a version not seen
+        bufmgr.begin(Isolation::Serializable).unwrap();
+        assert_eq!(1, count(&mut bufmgr));
+        let first = bufmgr.switch(Default::default());
+        bufmgr.begin(Isolation::Serializable).unwrap();
+        table.insert(&mut bufmgr, &[Value::Int(2)]).unwrap();
+        bufmgr.commit().unwrap();
+        bufmgr.switch(first);
+        // the reader depends on it, so can't be depended on
+        assert_eq!(1, count(&mut bufmgr));
+        let first = bufmgr.switch(Default::default());
+        bufmgr.begin(Isolation::Serializable).unwrap();
+        assert_eq!(2, count(&mut bufmgr));
+        let third = bufmgr.switch(first);
+        assert!(is_failure(table.insert(&mut bufmgr, &[Value::Int(3)])));
+        bufmgr.abort().unwrap();
+        bufmgr.switch(third);
+        bufmgr.commit().unwrap();
+        // transactions are forgotten once nothing running is concurrent with them
+        assert!(bufmgr.ssi().is_empty());
+
+        // a dependency alone, here of the one reading row 1 on the one updating it, is fine
+        bufmgr.begin(Isolation::Serializable).unwrap();
+        assert_eq!(1, table.lookup(&mut bufmgr, table::Access::PrimaryKey, &[Value::Int(1)]).unwrap().len());
+        let first = bufmgr.switch(Default::default());
+        bufmgr.begin(Isolation::Serializable).unwrap();
+        assert_eq!(0, table.lookup(&mut bufmgr, table::Access::PrimaryKey, &[Value::Int(5)]).unwrap().len());
+        table.update(&mut bufmgr, &[Value::Int(1)]).unwrap();
+        let second = bufmgr.switch(first);
+        table.insert(&mut bufmgr, &[Value::Int(4)]).unwrap();
+        bufmgr.commit().unwrap();
+        bufmgr.switch(second);
+        bufmgr.commit().unwrap();
+        assert_eq!(3, count(&mut bufmgr));
+        assert_eq!(0, bufmgr.ssi().len());
+    }
+}
@@ -0,0 +1,189 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::buffer::{BufferPoolManager, BufferStats};
+use crate::catalog::{Column, VirtualTable};
+use crate::query;
+use crate::sql;
+use crate::tuple::{DataType, Tuple, Value};
+
+// Statistics of the statements the sessions ran, as pg_stat_statements keeps them: how often each
+// ran, for how long and with how many rows and fetches, the statements which differ only in their
+// constants and parameters counted together (see `sql::normalize`). Those which fail aren't
+// counted. Kept in memory only, readable as the virtual table `stat_statements` or with
+// `Database::statement_stats`.
+
+pub const TABLE_NAME: &str = "stat_statements";
+
+// Statements kept track of, beyond which the one run least often is dropped for a new one.
+pub const MAX_STATEMENTS: usize = 5000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatementStat {
+    // normalized
+    pub query: String,
+    pub calls: u64,
+    pub total_time: Duration,
+    // returned or changed, over all the calls
+    pub rows: u64,
+    pub buffers: BufferStats,
+}
+
+impl StatementStat {
+    pub fn mean_time(&self) -> Duration {
+        Duration::from_nanos((self.total_time.as_nanos() / self.calls.max(1) as u128) as u64)
+    }
+}
+
+#[derive(Debug)]
+pub struct StatementStats {
+    statements: HashMap<String, StatementStat>,
+    max: usize,
+}
+
+impl Default for StatementStats {
+    fn default() -> Self {
+        Self::new(MAX_STATEMENTS)
+    }
+}
+
+impl StatementStats {
+    pub fn new(max: usize) -> Self {
+        Self { statements: HashMap::new(), max }
+    }
+
+    // Counts a call of the statement parsed from `sql`.
+    pub fn record(&mut self, sql: &str, duration: Duration, rows: usize, buffers: BufferStats) {
+        let query = sql::normalize(sql).unwrap_or_else(|_| sql.to_string());
+        if !self.statements.contains_key(&query) && self.statements.len() >= self.max {
+            let least = self.statements.values().min_by_key(|stat| stat.calls).map(|stat| stat.query.clone());
+            if let Some(least) = least {
+                self.statements.remove(&least);
+            }
+        }
+        let stat = self.statements.entry(query.clone()).or_insert_with(|| StatementStat {
+            query,
+            calls: 0,
+            total_time: Duration::ZERO,
+            rows: 0,
+            buffers: BufferStats::default(),
+        });
+        stat.calls += 1;
+        stat.total_time += duration;
+        stat.rows += rows as u64;
+        stat.buffers.hits += buffers.hits;
+        stat.buffers.misses += buffers.misses;
+    }
+
+    // The statistics of the statements like `sql`.
+    pub fn get(&self, sql: &str) -> Option<&StatementStat> {
+        self.statements.get(&sql::normalize(sql).ok()?)
+    }
+
+    // The statements, those which took the longest in all first.
+    pub fn statements(&self) -> Vec<&StatementStat> {
+        let mut statements: Vec<_> = self.statements.values().collect();
+        statements.sort_by(|a, b| b.total_time.cmp(&a.total_time).then_with(|| a.query.cmp(&b.query)));
+        statements
+    }
+
+    pub fn reset(&mut self) {
+        self.statements.clear();
+    }
+}
+
+// The statements as a virtual table, with one row each of
+//   query TEXT, calls, total_time_us, mean_time_us, rows, buffer_hits, buffer_misses INTEGER
+pub struct StatementsTable(pub Rc<RefCell<StatementStats>>);
+
+impl VirtualTable for StatementsTable {
+    fn columns(&self) -> Vec<Column> {
+        let column = |name: &str, data_type| Column { name: name.to_string(), data_type };
+        vec![
+            column("query", DataType::Text),
+            column("calls", DataType::Integer),
+            column("total_time_us", DataType::Integer),
+            column("mean_time_us", DataType::Integer),
+            column("rows", DataType::Integer),
+            column("buffer_hits", DataType::Integer),
+            column("buffer_misses", DataType::Integer),
+        ]
+    }
+
+    fn rows(&self, _bufmgr: &mut BufferPoolManager) -> Result<Vec<Tuple>, query::Error> {
+        let int = |n: u64| Value::Int(n.try_into().unwrap_or(i64::MAX));
+        let micros = |duration: Duration| int(duration.as_micros().try_into().unwrap_or(u64::MAX));
+        Ok(self
+            .0
+            .borrow()
+            .statements()
+            .into_iter()
+            .map(|stat| {
+                vec![
+                    Value::Text(stat.query.clone()),
+                    int(stat.calls),
+                    micros(stat.total_time),
+                    micros(stat.mean_time()),
+                    int(stat.rows),
+                    int(stat.buffers.hits),
+                    int(stat.buffers.misses),
+                ]
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use crate::sql::QueryResult;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        let mut stats = StatementStats::new(2);
+        let buffers = BufferStats { hits: 3, misses: 1 };
+        stats.record("SELECT * FROM t WHERE id = 1", Duration::from_millis(2), 1, buffers);
+        stats.record("select *  from t where id = $1", Duration::from_millis(4), 0, buffers);
+        stats.record("SELECT 1", Duration::from_millis(1), 1, buffers);
+        let stat = stats.get("SELECT * FROM t WHERE id = 7").unwrap();
+        assert_eq!(("select * from t where id = ?", 2, 1), (stat.query.as_str(), stat.calls, stat.rows));
+        assert_eq!((Duration::from_millis(6), Duration::from_millis(3)), (stat.total_time, stat.mean_time()));
+        assert_eq!(BufferStats { hits: 6, misses: 2 }, stat.buffers);
+        // full, so the one run least often makes way
+        stats.record("SELECT 2, 3", Duration::from_millis(1), 1, buffers);
+        assert!(stats.get("SELECT 1").is_none());
+        assert_eq!(vec!["select * from t where id = ?", "select ?, ?"], stats.statements().iter().map(|stat| stat.query.as_str()).collect::<Vec<_>>());
+
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path(), 16).unwrap();
+        let mut session = db.session();
+        session.execute("CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        for i in 0..3 {
+            session.execute(&format!("INSERT INTO t VALUES ({}, 'a')", i)).unwrap();
+        }
+        let select = session.prepare("SELECT name FROM t WHERE id = ?").unwrap();
+        session.execute_prepared(&select, &[Value::Int(1)]).unwrap();
+        assert!(session.execute("INSERT INTO t VALUES (0, 'again')").is_err());
+        let stats = db.statement_stats();
+        let calls = |query: &str| stats.iter().find(|stat| stat.query == query).map(|stat| (stat.calls, stat.rows));
+        assert_eq!(Some((3, 3)), calls("insert into t values (?, ?)"));
+        assert_eq!(Some((1, 1)), calls("select name from t where id = ?"));
+        assert_eq!(Some((1, 0)), calls("create table t (id integer primary key, name text)"));
+
+        let result = session.execute("SELECT query, calls, rows FROM stat_statements WHERE calls > 2").unwrap();
+        assert_eq!(
+            vec![QueryResult::Rows {
+                columns: vec!["query".to_string(), "calls".to_string(), "rows".to_string()],
+                rows: vec![vec![Value::Text("insert into t values (?, ?)".to_string()), Value::Int(3), Value::Int(3)]],
+            }],
+            result
+        );
+        assert!(session.execute("CREATE TABLE stat_statements (a INTEGER PRIMARY KEY)").is_err());
+        db.reset_statement_stats();
+        assert!(db.statement_stats().is_empty());
+    }
+}
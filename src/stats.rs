@@ -0,0 +1,196 @@
+use crate::buffer::BufferPoolManager;
+use crate::sim::Rng;
+use crate::table::{self, Table};
+use crate::tuple::{Tuple, Value};
+
+// Number of buckets of the equi-depth histograms.
+pub const NUM_BUCKETS: usize = 10;
+
+// Rows sampled for the histograms and the numbers of distinct values, as in PostgreSQL with its
+// default statistics target.
+const SAMPLE_ROWS: usize = 30000;
+
+// Text histogram bounds and min/max values are cut to this many characters so that the
+// statistics of a column fit in a catalog entry.
+const MAX_TEXT_LEN: usize = 32;
+
+// Statistics of a table as of the last ANALYZE.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableStats {
+    pub row_count: u64,
+    pub columns: Vec<ColumnStats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnStats {
+    pub null_count: u64,
+    pub distinct_count: u64,
+    // NULL if the column has no value other than NULL
+    pub min: Value,
+    pub max: Value,
+    // Upper bounds of the buckets of an equi-depth histogram of the values other than NULL:
+    // each bucket holds about the same number of values, the last bound being `max`.
+    pub histogram: Vec<Value>,
+}
+
+impl TableStats {
+    // Reads the whole table, counting its rows and NULLs and finding the least and greatest
+    // values of its columns, the rest estimated from a sample of its rows.
+    pub fn collect(bufmgr: &mut BufferPoolManager, table: &Table, num_columns: usize) -> Result<Self, table::Error> {
+        Self::sample(bufmgr, table, num_columns, SAMPLE_ROWS)
+    }
+
+    // As `collect`, from a uniform sample of up to `sample_rows` rows, kept as a reservoir while
+    // the table is read.
+    fn sample(bufmgr: &mut BufferPoolManager, table: &Table, num_columns: usize, sample_rows: usize) -> Result<Self, table::Error> {
+        let mut sample: Vec<Tuple> = vec![];
+        let mut null_counts = vec![0; num_columns];
+        let mut bounds: Vec<Option<(Value, Value)>> = vec![None; num_columns];
+        let mut row_count = 0;
+        let mut rng = Rng::new(1);
+        let mut iter = table.scan(bufmgr)?;
+        while let Some(row) = iter.next(bufmgr)? {
+            row_count += 1;
+            for (i, value) in row.iter().enumerate() {
+                match &mut bounds[i] {
+                    _ if value.is_null() => null_counts[i] += 1,
+                    None => bounds[i] = Some((value.clone(), value.clone())),
+                    Some((min, _)) if value < min => *min = value.clone(),
+                    Some((_, max)) if value > max => *max = value.clone(),
+                    Some(_) => {}
+                }
+            }
+            // each of the rows read so far is in the sample with the same chance
+            if sample.len() < sample_rows {
+                sample.push(row);
+            } else if let Some(slot) = sample.get_mut(rng.below(row_count) as usize) {
+                *slot = row;
+            }
+        }
+        let columns = bounds
+            .into_iter()
+            .zip(null_counts)
+            .enumerate()
+            .map(|(i, (bounds, null_count))| {
+                let values = sample.iter().filter(|row| !row[i].is_null()).map(|row| row[i].clone()).collect();
+                ColumnStats::from_sample(values, row_count - null_count, null_count, bounds)
+            })
+            .collect();
+        Ok(Self { row_count, columns })
+    }
+}
+
+impl ColumnStats {
+    // From the values other than NULL of the rows sampled, of `count` in the whole table.
+    fn from_sample(mut values: Vec<Value>, count: u64, null_count: u64, bounds: Option<(Value, Value)>) -> Self {
+        values.sort();
+        let histogram = (1..=NUM_BUCKETS.min(values.len()))
+            .map(|bucket| truncate(&values[bucket * values.len() / NUM_BUCKETS.min(values.len()) - 1]))
+            .collect();
+        let (min, max) = bounds.map_or((Value::Null, Value::Null), |(min, max)| (truncate(&min), truncate(&max)));
+        Self { null_count, distinct_count: distinct_count(&values, count), min, max, histogram }
+    }
+
+    pub fn null_fraction(&self, row_count: u64) -> f64 {
+        if row_count == 0 {
+            return 0.0;
+        }
+        self.null_count as f64 / row_count as f64
+    }
+
+    // Estimated fraction of the rows equal to a value other than NULL, assuming values are
+    // evenly distributed.
+    pub fn eq_fraction(&self, row_count: u64) -> f64 {
+        (1.0 - self.null_fraction(row_count)) / self.distinct_count.max(1) as f64
+    }
+
+    // Estimated fraction of the rows whose value is less than `value`, from the histogram.
+    pub fn lt_fraction(&self, row_count: u64, value: &Value) -> f64 {
+        if self.histogram.is_empty() || value <= &self.min {
+            return 0.0;
+        }
+        let below = self.histogram.iter().filter(|bound| *bound < value).count();
+        // `value` falls in the middle of bucket `below` on average
+        let fraction = ((below as f64 + 0.5) / self.histogram.len() as f64).min(1.0);
+        fraction * (1.0 - self.null_fraction(row_count))
+    }
+}
+
+// Estimated number of distinct values of the `count` in the table, from those of the sorted
+// sample `values`, by the estimator of Haas and Stokes as in PostgreSQL: the values seen once in
+// the sample are taken to be those it misses most of.
+fn distinct_count(values: &[Value], count: u64) -> u64 {
+    let mut distinct = 0;
+    let mut once = 0;
+    for run in values.chunk_by(|a, b| a == b) {
+        distinct += 1;
+        if run.len() == 1 {
+            once += 1;
+        }
+    }
+    if values.len() as u64 == count {
+        return distinct;
+    }
+    // every value seen once, as in a key
+    if once == values.len() {
+        return count;
+    }
+    let (n, total) = (values.len() as f64, count as f64);
+    let estimate = n * distinct as f64 / (n - once as f64 + once as f64 * n / total);
+    (estimate.round() as u64).clamp(distinct, count)
+}
+
+fn truncate(value: &Value) -> Value {
+    match value {
+        Value::Text(s) if s.chars().count() > MAX_TEXT_LEN => Value::Text(s.chars().take(MAX_TEXT_LEN).collect()),
+        value => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(8));
+        let table = Table::create(&mut bufmgr, 1).unwrap();
+        for i in 0..100 {
+            let team = if i % 10 == 0 { Value::Null } else { Value::Int(i % 4) };
+            table.insert(&mut bufmgr, &[Value::Int(i), team]).unwrap();
+        }
+
+        let stats = TableStats::collect(&mut bufmgr, &table, 2).unwrap();
+        assert_eq!(100, stats.row_count);
+        let id = &stats.columns[0];
+        assert_eq!((0, 100), (id.null_count, id.distinct_count));
+        assert_eq!((Value::Int(0), Value::Int(99)), (id.min.clone(), id.max.clone()));
+        assert_eq!((1..=10).map(|i| Value::Int(i * 10 - 1)).collect::<Vec<_>>(), id.histogram);
+        let team = &stats.columns[1];
+        assert_eq!((10, 4), (team.null_count, team.distinct_count));
+        assert_eq!(Some(&Value::Int(3)), team.histogram.last());
+        assert!((team.eq_fraction(100) - 0.225).abs() < 1e-9);
+        assert!((id.lt_fraction(100, &Value::Int(25)) - 0.25).abs() < 1e-9);
+        assert_eq!(0.0, id.lt_fraction(100, &Value::Int(0)));
+
+        // from a sample, the counts and bounds are still those of the whole table
+        let sampled = TableStats::sample(&mut bufmgr, &table, 2, 30).unwrap();
+        assert_eq!(100, sampled.row_count);
+        let id = &sampled.columns[0];
+        assert_eq!((0, 100), (id.null_count, id.distinct_count));
+        assert_eq!((Value::Int(0), Value::Int(99)), (id.min.clone(), id.max.clone()));
+        assert_eq!(10, id.histogram.len());
+        let team = &sampled.columns[1];
+        assert_eq!((10, 4), (team.null_count, team.distinct_count));
+        assert!(id.lt_fraction(100, &Value::Int(50)) > 0.2 && id.lt_fraction(100, &Value::Int(50)) < 0.8);
+        assert_eq!(0, distinct_count(&[], 0));
+        // most values seen once in a sample of a tenth are taken to be of many more
+        let mut values: Vec<_> = (0..95).chain([1; 5]).map(Value::Int).collect();
+        values.sort();
+        assert!(distinct_count(&values, 1000) > 500);
+    }
+}
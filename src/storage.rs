@@ -0,0 +1,174 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Where the data file and the log are kept: the file system, or something standing in for it,
+// e.g. `sim::SimStorage`, which loses and tears writes at a crash. Temporary files, which
+// don't outlive the process anyway, are always on the file system.
+pub trait StorageBackend {
+    // Opens the file at `path`, creating it empty if `create` and there's none.
+    fn open(&self, path: &Path, create: bool) -> io::Result<Box<dyn StorageFile>>;
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()>;
+
+    // Names and lengths of the files in `dir`.
+    fn list(&self, dir: &Path) -> io::Result<Vec<(String, u64)>>;
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    // Makes the files created in, renamed in or removed from `dir` so durably.
+    fn sync_dir(&self, dir: &Path) -> io::Result<()>;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.open(path, false).is_ok()
+    }
+
+    // The contents of the file at `path`.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut file = self.open(path, false)?;
+        let mut data = vec![0; file.size()? as usize];
+        read_exact_at(&mut *file, 0, &mut data)?;
+        Ok(data)
+    }
+
+    // Replaces the file at `path` with `data`, written elsewhere and renamed over it, so that a
+    // crash leaves either of them.
+    fn replace(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let mut file = self.open(&tmp, true)?;
+        file.set_len(0)?;
+        file.write_at(0, data)?;
+        file.sync()?;
+        self.rename(&tmp, path)?;
+        self.sync_dir(path.parent().unwrap_or(Path::new(".")))
+    }
+}
+
+pub trait StorageFile {
+    // Reads into `buf` from `offset`, returning how much was read, which may be less than asked
+    // for, and 0 only at the end of the file.
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()>;
+
+    fn size(&self) -> io::Result<u64>;
+
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+
+    // Makes what's been written durable.
+    fn sync(&mut self) -> io::Result<()>;
+
+    // A reader of the file for other threads, if it can be read from them while it's read and
+    // written by the one it's of.
+    fn reader(&self) -> Option<Arc<dyn ReadAt>> {
+        None
+    }
+}
+
+// Reads a file from any thread, as `StorageFile::read_at` does.
+pub trait ReadAt: Send + Sync {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+#[cfg(unix)]
+impl ReadAt for File {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(self, buf, offset)
+    }
+}
+
+// Fills `buf` from `offset` however many reads it takes, failing with UnexpectedEof at the end
+// of the file.
+pub fn read_exact_at(file: &mut dyn StorageFile, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    fill(|offset, buf| file.read_at(offset, buf), offset, buf)
+}
+
+// As `read_exact_at`, by `read`, reading into a buffer from an offset as `StorageFile::read_at`.
+pub fn fill(mut read: impl FnMut(u64, &mut [u8]) -> io::Result<usize>, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match read(offset + filled as u64, &mut buf[filled..])? {
+            0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            n => filled += n,
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileSystem;
+
+impl StorageBackend for FileSystem {
+    fn open(&self, path: &Path, create: bool) -> io::Result<Box<dyn StorageFile>> {
+        let file = OpenOptions::new().read(true).write(true).create(create).truncate(false).open(path)?;
+        Ok(Box::new(OsFile(file)))
+    }
+
+    fn create_dir_all(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<(String, u64)>> {
+        let mut files = vec![];
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if let Some(name) = entry.file_name().to_str() {
+                files.push((name.to_string(), entry.metadata()?.len()));
+            }
+        }
+        Ok(files)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        File::open(dir)?.sync_all()
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+}
+
+// A file of the file system.
+pub struct OsFile(pub File);
+
+impl StorageFile for OsFile {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.seek(SeekFrom::Start(offset))?;
+        self.0.read(buf)
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.0.seek(SeekFrom::Start(offset))?;
+        self.0.write_all(data)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.0.metadata()?.len())
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.0.set_len(len)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.0.sync_all()
+    }
+
+    #[cfg(unix)]
+    fn reader(&self) -> Option<Arc<dyn ReadAt>> {
+        Some(Arc::new(self.0.try_clone().ok()?))
+    }
+}
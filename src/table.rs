@@ -0,0 +1,1443 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::ops::Bound;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::btree::{self, BTree, Entry, SearchMode};
+use crate::buffer::{self, BufferPoolManager};
+use crate::columnar::{Columnar, Stripe, StripeIter};
+use crate::dictionary::Dictionary;
+use crate::disk::PageId;
+use crate::fulltext;
+use crate::geometry::{self, Rect};
+use crate::json;
+use crate::mvcc::{self, Isolation, Version, Visibility, FROZEN};
+use crate::lock::{self, LockMode, Resource};
+use crate::rtree::{self, RTree};
+use crate::ssi;
+use crate::tuple::{self, Tuple, Value};
+use crate::wal::Relation;
+use crate::zonemap::{self, Extent};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Btree(#[from] btree::Error),
+    #[error(transparent)]
+    Rtree(#[from] rtree::Error),
+    #[error(transparent)]
+    Tuple(#[from] tuple::Error),
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error(transparent)]
+    Ssi(#[from] ssi::Error),
+    #[error(transparent)]
+    Lock(#[from] lock::Error),
+    #[error("the row has been changed by a concurrent transaction")]
+    WriteConflict,
+}
+
+// A table clustered on its primary key, which is the first `num_key_elems` columns.
+// Rows are stored in a B+tree mapping the encoded primary key to the versions of the row (see
+// `mvcc::encode_versions`), of which reads return the one in the snapshot of the transaction.
+//
+// Secondary indexes map the encoded (index columns ++ primary key) to the encoded primary key,
+// so they don't have to be unique. There's an entry for each version, so the version read through
+// one is checked to have the values of the entry. Entries of old versions aren't removed.
+// Full-text indexes are alike, of (word ++ primary key) for each word of the indexed column.
+// R-tree indexes have the same keys as a B+tree index on their column would, but under the bounds
+// of the point or box in it, in an R-tree rather than a B+tree (see `rtree`). Zone map indexes
+// have an entry of each extent of rows rather than of each version (see `zonemap`).
+//
+// A columnar table also has the stripes vacuum compacts rows into (see `columnar`), and one with
+// dictionary columns the dictionary the values in their rows are codes into (see `dictionary`).
+//
+// Rows written before columns were added to the table (see `Catalog::add_column`) are stored with
+// fewer values than it has columns, and read with NULLs in the columns added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    pub btree: BTree,
+    pub num_key_elems: usize,
+    pub indexes: Vec<Index>,
+    pub columnar: Option<Columnar>,
+    pub ttl: Option<Ttl>,
+    pub dictionary: Option<Dictionary>,
+    // that the rows read are padded to with NULLs, None to leave them as they are stored
+    pub num_columns: Option<usize>,
+    // its name and columns, logged with its changes for logical decoding, kept in step with the
+    // catalog's
+    pub relation: Option<Relation>,
+}
+
+// Rows of a table with a TTL expire `seconds` after the time in `column`, in seconds since the
+// Unix epoch, those with NULL there never. Expired rows are seen by no snapshot, and take the
+// place of no row, until `Table::expire` deletes them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ttl {
+    pub column: usize,
+    pub seconds: i64,
+}
+
+impl Ttl {
+    pub fn expired(&self, row: &[Value], now: i64) -> bool {
+        matches!(row[self.column], Value::Int(time) if time.saturating_add(self.seconds) <= now)
+    }
+}
+
+// The time TTLs are compared with, in seconds since the Unix epoch.
+pub fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    // of an R-tree index, that of the meta page of the R-tree (see `Index::rtree`)
+    pub btree: BTree,
+    pub columns: Vec<usize>,
+    // of each of `columns`, the value into its JSON the index keys on instead of the column, if
+    // it's an index on expressions (see `json::Path`)
+    pub paths: Vec<Option<json::Path>>,
+    // false while it's being built by CREATE INDEX CONCURRENTLY: kept up to date by writes, but
+    // not read from
+    pub valid: bool,
+    pub kind: IndexKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexKind {
+    BTree,
+    // an inverted index of the words of its one text column (see `fulltext`), with an entry of
+    // each word of a row rather than one of the value
+    FullText,
+    // of the bounds of the points or boxes of its one column, looked up by those they overlap
+    RTree,
+    // of the least and greatest values of its columns in each extent of rows, by which scans skip
+    // extents rather than look rows up
+    ZoneMap,
+}
+
+// Rows a bulk load sorts and writes at a time, and index entries it defers at most.
+const BULK_BATCH_ROWS: usize = 1024;
+const BULK_INDEX_ENTRIES: usize = 64 * 1024;
+// How full `insert_many` fills the pages it appends, leaving room for updates as COPY does.
+const INSERT_FILL_FACTOR: usize = 90;
+// How full `rebuild_index` fills the pages of the index, leaving room for entries inserted.
+const REBUILD_FILL_FACTOR: usize = 90;
+// How full `rewrite` fills the pages of the table, and the entries it writes at a time.
+const REWRITE_FILL_FACTOR: usize = 100;
+const REWRITE_BATCH_ENTRIES: usize = 1024;
+
+// What vacuum removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VacuumStats {
+    pub versions: usize,
+    pub rows: usize,
+    pub index_entries: usize,
+    // rows moved into stripes, of a columnar table
+    pub compacted: usize,
+}
+
+// How rows of a table can be looked up by key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    PrimaryKey,
+    Index(usize),
+}
+
+impl Table {
+    pub fn create(bufmgr: &mut BufferPoolManager, num_key_elems: usize) -> Result<Self, Error> {
+        Ok(Self {
+            btree: BTree::create(bufmgr)?,
+            num_key_elems,
+            indexes: vec![],
+            columnar: None,
+            ttl: None,
+            dictionary: None,
+            num_columns: None,
+            relation: None,
+        })
+    }
+
+    // Creates a columnar table (see `columnar`).
+    pub fn create_columnar(bufmgr: &mut BufferPoolManager, num_key_elems: usize) -> Result<Self, Error> {
+        Ok(Self {
+            columnar: Some(Columnar::create(bufmgr)?),
+            ..Self::create(bufmgr, num_key_elems)?
+        })
+    }
+
+    // Fails with a duplicate key unless there's no row with the primary key of `row`, or it has
+    // been deleted by a transaction which has committed.
+    pub fn insert(&self, bufmgr: &mut BufferPoolManager, row: &[Value]) -> Result<(), Error> {
+        match self.insert_or_get(bufmgr, row)? {
+            Some(_) => Err(btree::Error::DuplicateKey.into()),
+            None => Ok(()),
+        }
+    }
+
+    // Inserts `row` as `insert` does, unless there's a row with its primary key, which is
+    // returned instead, locked for the transaction to write. That's the newest version, which
+    // in read committed may have been committed after the snapshot, as an update would change.
+    pub fn insert_or_get(&self, bufmgr: &mut BufferPoolManager, row: &[Value]) -> Result<Option<Tuple>, Error> {
+        let pkey = self.encode_pkey(row);
+        let mut versions = self.versions_to_write(bufmgr, &pkey)?;
+        self.retire_expired(bufmgr, &mut versions)?;
+        if let Some(newest) = versions.first().filter(|newest| newest.xmax.is_none()) {
+            return Ok(Some(newest.row.clone()));
+        }
+        if let Some(row) = self.compacted(bufmgr, &row[..self.num_key_elems])? {
+            return Ok(Some(row));
+        }
+        let xmin = bufmgr.txid()?.unwrap_or(FROZEN);
+        versions.insert(0, Version { xmin, xmax: None, row: row.to_vec() });
+        self.write(bufmgr, &pkey, &versions)?;
+        self.log_change(bufmgr, None, Some(row))?;
+        Ok(None)
+    }
+
+    // Inserts `rows` as `insert` does each, but in batches as a bulk load writes them (see
+    // `BulkLoad`): the rows above those there are appended to the tree a page at a time, each
+    // page written and logged once, and the index entries added sorted. Only the rows are locked,
+    // as they are by `insert`, not the table. Returns how many there were.
+    pub fn insert_many(&self, bufmgr: &mut BufferPoolManager, rows: impl IntoIterator<Item = Tuple>) -> Result<usize, Error> {
+        let mut load = BulkLoad {
+            table: self,
+            fill_factor: INSERT_FILL_FACTOR,
+            lock_rows: true,
+            rows: vec![],
+            index_entries: vec![vec![]; self.indexes.len()],
+            loaded: 0,
+        };
+        for row in rows {
+            load.push(bufmgr, row)?;
+        }
+        load.finish(bufmgr)
+    }
+
+    // The newest version of the row with the primary key `pkey`, locked for the transaction to
+    // write, as `insert_or_get` returns it, None if there's none.
+    pub fn get_for_update(&self, bufmgr: &mut BufferPoolManager, pkey: &[Value]) -> Result<Option<Tuple>, Error> {
+        let mut key = vec![];
+        tuple::encode_key(pkey, &mut key);
+        let versions = self.versions_to_write(bufmgr, &key)?;
+        if let Some(newest) = versions.first().filter(|newest| newest.xmax.is_none()) {
+            return Ok(Some(newest.row.clone()));
+        }
+        self.compacted(bufmgr, pkey)
+    }
+
+    // Deletes the row with the primary key `pkey`, returning whether there was one.
+    pub fn delete(&self, bufmgr: &mut BufferPoolManager, pkey: &[Value]) -> Result<bool, Error> {
+        let mut key = vec![];
+        tuple::encode_key(pkey, &mut key);
+        let mut versions = self.versions_to_write(bufmgr, &key)?;
+        if versions.first().is_none_or(|newest| newest.xmax.is_some()) {
+            let Some(old) = self.delete_compacted(bufmgr, pkey)? else {
+                return Ok(false);
+            };
+            self.log_change(bufmgr, Some(&old), None)?;
+            return Ok(true);
+        }
+        let old = bufmgr.logical().then(|| versions[0].row.clone());
+        match bufmgr.txid()? {
+            Some(txid) => versions[0].xmax = Some(txid),
+            None => {
+                versions.remove(0);
+            }
+        }
+        self.write(bufmgr, &key, &versions)?;
+        self.log_change(bufmgr, old.as_deref(), None)?;
+        Ok(true)
+    }
+
+    // Replaces the row with the primary key of `row`, returning whether there was one.
+    pub fn update(&self, bufmgr: &mut BufferPoolManager, row: &[Value]) -> Result<bool, Error> {
+        let pkey = self.encode_pkey(row);
+        let mut versions = self.versions_to_write(bufmgr, &pkey)?;
+        let xmin = bufmgr.txid()?.unwrap_or(FROZEN);
+        if versions.first().is_none_or(|newest| newest.xmax.is_some()) {
+            let Some(old) = self.delete_compacted(bufmgr, &row[..self.num_key_elems])? else {
+                return Ok(false);
+            };
+            // back into the B+tree, above the versions from before it was compacted
+            versions.insert(0, Version { xmin, xmax: None, row: row.to_vec() });
+            self.write(bufmgr, &pkey, &versions)?;
+            self.log_change(bufmgr, Some(&old), Some(row))?;
+            return Ok(true);
+        }
+        let old = bufmgr.logical().then(|| versions[0].row.clone());
+        if versions[0].xmin == xmin {
+            // the newest version is of this transaction, so nothing else sees it
+            versions[0].row = row.to_vec();
+        } else {
+            versions[0].xmax = Some(xmin);
+            versions.insert(0, Version { xmin, xmax: None, row: row.to_vec() });
+        }
+        self.write(bufmgr, &pkey, &versions)?;
+        self.log_change(bufmgr, old.as_deref(), Some(row))?;
+        Ok(true)
+    }
+
+    // Deletes the newest of `versions` if it has expired, as `delete` would, for a row to be
+    // inserted in its place.
+    fn retire_expired(&self, bufmgr: &mut BufferPoolManager, versions: &mut Vec<Version>) -> Result<(), Error> {
+        let now = now();
+        if !versions.first().is_some_and(|newest| newest.xmax.is_none() && self.expired(&newest.row, now)) {
+            return Ok(());
+        }
+        self.log_change(bufmgr, Some(&versions[0].row), None)?;
+        match bufmgr.txid()? {
+            Some(txid) => versions[0].xmax = Some(txid),
+            None => {
+                versions.remove(0);
+            }
+        }
+        Ok(())
+    }
+
+    // Whether `row` has expired as of `now`, the table having a TTL.
+    pub fn expired(&self, row: &[Value], now: i64) -> bool {
+        self.ttl.is_some_and(|ttl| ttl.expired(row, now))
+    }
+
+    // Deletes up to `limit` of the rows which have expired, from the one of primary key `from` on,
+    // or the first. Those being written by other transactions are left for the next time. Returns
+    // how many were deleted, and the primary key to go on from, None once all rows are checked.
+    pub fn expire(&self, bufmgr: &mut BufferPoolManager, from: Option<&[u8]>, limit: usize) -> Result<(usize, Option<Vec<u8>>), Error> {
+        let now = now();
+        let mode = from.map_or(SearchMode::Start, |from| SearchMode::Key(from.to_vec()));
+        let mut iter = self.btree.search(bufmgr, mode)?;
+        let mut expired = vec![];
+        let next = loop {
+            let Some((pkey, value)) = iter.next(bufmgr)? else {
+                break None;
+            };
+            if expired.len() == limit {
+                break Some(pkey);
+            }
+            if let Some(row) = visible_row(bufmgr, mvcc::decode_versions(&value)?)?.filter(|row| self.expired(row, now)) {
+                expired.push(row[..self.num_key_elems].to_vec());
+            }
+        };
+        let mut deleted = 0;
+        for pkey in expired {
+            match self.delete(bufmgr, &pkey) {
+                Ok(true) => deleted += 1,
+                Ok(false) | Err(Error::WriteConflict | Error::Lock(lock::Error::Wait)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok((deleted, next))
+    }
+
+    // The row with primary key `key` in the stripes of the table, if it's columnar.
+    fn compacted(&self, bufmgr: &mut BufferPoolManager, key: &[Value]) -> Result<Option<Tuple>, Error> {
+        match &self.columnar {
+            Some(columnar) => Ok(columnar.find(bufmgr, key)?.map(|(_, _, row)| row)),
+            None => Ok(None),
+        }
+    }
+
+    // Deletes the row with primary key `key` from the stripe it's in, if the table is columnar and
+    // it's in one, by inserting a delete marker of it. Returns the row deleted. The row must have
+    // been locked by `versions_to_write`.
+    fn delete_compacted(&self, bufmgr: &mut BufferPoolManager, key: &[Value]) -> Result<Option<Tuple>, Error> {
+        let Some(columnar) = &self.columnar else {
+            return Ok(None);
+        };
+        let Some((number, i, row)) = columnar.find(bufmgr, key)? else {
+            return Ok(None);
+        };
+        let markers = columnar.markers();
+        let marker = vec![Value::Int(number), Value::Int(i as i64)];
+        let pkey = markers.encode_pkey(&marker);
+        if !markers.versions_to_write(bufmgr, &pkey)?.is_empty() {
+            // deleted by a transaction which committed after the snapshot
+            return Ok(None);
+        }
+        let xmin = bufmgr.txid()?.unwrap_or(FROZEN);
+        markers.write(bufmgr, &pkey, &[Version { xmin, xmax: None, row: marker }])?;
+        Ok(Some(row))
+    }
+
+    // The versions of the row with `pkey` other than those of aborted transactions, failing if
+    // the newest one is being written by another transaction, or in repeatable read, was written
+    // by one which committed after the snapshot.
+    fn versions_to_write(&self, bufmgr: &mut BufferPoolManager, pkey: &[u8]) -> Result<Vec<Version>, Error> {
+        lock::lock_row(bufmgr, self.btree.meta_page_id, pkey, LockMode::Exclusive)?;
+        ssi::write(bufmgr, self.btree.meta_page_id, pkey)?;
+        let mut versions = self.versions(bufmgr, pkey)?;
+        versions.retain(|version| !bufmgr.is_aborted(version.xmin));
+        if let Some(newest) = versions.first_mut() {
+            if newest.xmax.is_some_and(|xmax| bufmgr.is_aborted(xmax)) {
+                newest.xmax = None;
+            }
+            let mut writers = [Some(newest.xmin), newest.xmax].into_iter().flatten();
+            let repeatable = matches!(bufmgr.isolation(), Some(Isolation::RepeatableRead | Isolation::Serializable));
+            if writers.any(|txid| bufmgr.is_running_elsewhere(txid) || repeatable && !bufmgr.sees(txid)) {
+                return Err(Error::WriteConflict);
+            }
+        }
+        Ok(versions)
+    }
+
+    fn versions(&self, bufmgr: &mut BufferPoolManager, pkey: &[u8]) -> Result<Vec<Version>, Error> {
+        match self.btree.search(bufmgr, SearchMode::Key(pkey.to_vec()))?.next(bufmgr)? {
+            Some((key, value)) if key == pkey => self.decode_versions(bufmgr, &value),
+            _ => Ok(vec![]),
+        }
+    }
+
+    // The versions of a row as stored, with the codes in the dictionary columns decoded.
+    fn decode_versions(&self, bufmgr: &mut BufferPoolManager, value: &[u8]) -> Result<Vec<Version>, Error> {
+        let mut versions = mvcc::decode_versions(value)?;
+        if let Some(dictionary) = &self.dictionary {
+            for version in &mut versions {
+                dictionary.decode(bufmgr, &mut version.row)?;
+            }
+        }
+        if let Some(num_columns) = self.num_columns {
+            for version in &mut versions {
+                pad(&mut version.row, num_columns);
+            }
+        }
+        Ok(versions)
+    }
+
+    // The versions of a row to store, with the values of the dictionary columns encoded.
+    fn encode_versions(&self, bufmgr: &mut BufferPoolManager, versions: &[Version]) -> Result<Vec<u8>, Error> {
+        let mut value = vec![];
+        match &self.dictionary {
+            Some(dictionary) => {
+                let mut versions = versions.to_vec();
+                for version in &mut versions {
+                    dictionary.encode(bufmgr, &mut version.row)?;
+                }
+                mvcc::encode_versions(&versions, &mut value);
+            }
+            None => mvcc::encode_versions(versions, &mut value),
+        }
+        Ok(value)
+    }
+
+    // Writes the versions of a row along with the index entries of the newest one. The changes
+    // are left in place if the transaction aborts, the versions being invisible then.
+    fn write(&self, bufmgr: &mut BufferPoolManager, pkey: &[u8], versions: &[Version]) -> Result<(), Error> {
+        bufmgr.redo_only(|bufmgr| {
+            let value = self.encode_versions(bufmgr, versions)?;
+            self.btree.upsert(bufmgr, pkey, &value)?;
+            if let Some(newest) = versions.first().filter(|newest| newest.xmax.is_none()) {
+                for index in &self.indexes {
+                    index.insert(bufmgr, &newest.row, pkey, self.num_key_elems)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    // Logs the change of a row from `old` to `new` if logical decoding is turned on.
+    fn log_change(&self, bufmgr: &mut BufferPoolManager, old: Option<&[Value]>, new: Option<&[Value]>) -> Result<(), Error> {
+        if !bufmgr.logical() {
+            return Ok(());
+        }
+        let encode = |row: &[Value]| {
+            let mut bytes = vec![];
+            tuple::encode(row, &mut bytes);
+            bytes
+        };
+        Ok(bufmgr.log_change(self.btree.meta_page_id, self.relation.as_ref(), old.map(encode), new.map(encode))?)
+    }
+
+    // Removes the versions which no snapshot in use or taken from now on sees, along with the
+    // index entries of none of the others, and the rows left without versions. Like writing rows,
+    // the changes are left in place if the transaction aborts.
+    pub fn vacuum(&self, bufmgr: &mut BufferPoolManager) -> Result<VacuumStats, Error> {
+        // so that transactions left idle don't keep what they may see from being removed
+        bufmgr.reap()?;
+        let (mut pruned, mut compacted) = (vec![], vec![]);
+        // versions are pruned as of the horizon, which replicas are told of first
+        let horizon = bufmgr.horizon();
+        let mut iter = self.btree.search(bufmgr, SearchMode::Start)?;
+        while let Some((pkey, value)) = iter.next(bufmgr)? {
+            let versions = self.decode_versions(bufmgr, &value)?;
+            let kept = mvcc::prune(bufmgr, &versions);
+            // every snapshot sees the row as it is
+            if let (Some(_), [Version { xmin: FROZEN, xmax: None, row }]) = (self.columnar, kept.as_slice()) {
+                compacted.push((pkey.clone(), row.clone()));
+            }
+            if kept != versions {
+                pruned.push((pkey, versions, kept));
+            }
+        }
+        let mut stats = VacuumStats::default();
+        if !pruned.is_empty() {
+            bufmgr.log_vacuum(horizon)?;
+        }
+        bufmgr.redo_only(|bufmgr| -> Result<_, Error> {
+            for (pkey, versions, kept) in pruned {
+                stats.versions += versions.len() - kept.len();
+                if kept.is_empty() {
+                    self.btree.delete(bufmgr, &pkey)?;
+                    stats.rows += 1;
+                } else {
+                    let value = self.encode_versions(bufmgr, &kept)?;
+                    self.btree.upsert(bufmgr, &pkey, &value)?;
+                }
+                for index in &self.indexes {
+                    let keys = |version: &Version| index.keys(&version.row, self.num_key_elems);
+                    let kept_keys: BTreeSet<_> = kept.iter().flat_map(keys).collect();
+                    let removed_keys: BTreeSet<_> = versions.iter().flat_map(keys).filter(|k| !kept_keys.contains(k)).collect();
+                    for key in removed_keys {
+                        if index.remove(bufmgr, &key)? {
+                            stats.index_entries += 1;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        if let Some(columnar) = &self.columnar {
+            columnar.descriptors().vacuum(bufmgr)?;
+            stats.compacted = self.compact(bufmgr, columnar, compacted)?;
+        }
+        Ok(stats)
+    }
+
+    // Moves `rows`, by primary key, into new stripes of `columnar`, deleting them from the B+tree
+    // in the transaction which inserts the stripes, as it would delete rows. Returns how many there
+    // were.
+    fn compact(&self, bufmgr: &mut BufferPoolManager, columnar: &Columnar, rows: Vec<(Vec<u8>, Tuple)>) -> Result<usize, Error> {
+        if rows.is_empty() {
+            return Ok(0);
+        }
+        let (pkeys, rows): (Vec<_>, Vec<_>) = rows.into_iter().unzip();
+        let txid = bufmgr.txid()?;
+        let descriptors = columnar.descriptors();
+        for stripe in columnar.write(bufmgr, &rows)? {
+            let row = stripe.encode();
+            let pkey = descriptors.encode_pkey(&row);
+            descriptors.write(bufmgr, &pkey, &[Version { xmin: txid.unwrap_or(FROZEN), xmax: None, row }])?;
+        }
+        for pkey in &pkeys {
+            let mut versions = self.versions_to_write(bufmgr, pkey)?;
+            match (txid, versions.first_mut()) {
+                (Some(txid), Some(newest)) => {
+                    newest.xmax = Some(txid);
+                    self.write(bufmgr, pkey, &versions)?;
+                }
+                _ => {
+                    bufmgr.redo_only(|bufmgr| self.btree.delete(bufmgr, pkey))?;
+                }
+            }
+        }
+        Ok(pkeys.len())
+    }
+
+    // Creates a secondary index on `columns`, or on the values of `paths` into them where given
+    // (of which those left out are None), or a full-text or R-tree one on a column, or a zone map
+    // on columns, and fills it
+    // with the versions of the existing rows.
+    pub fn create_index(&mut self, bufmgr: &mut BufferPoolManager, columns: Vec<usize>, paths: Vec<Option<json::Path>>, kind: IndexKind) -> Result<(), Error> {
+        self.lock(bufmgr, LockMode::Exclusive)?;
+        let index = Index {
+            btree: bufmgr.creating_pages_beside(self.btree.meta_page_id, |bufmgr| Index::create_tree(bufmgr, kind))?,
+            paths: Index::paths_of(&columns, paths),
+            columns,
+            valid: true,
+            kind,
+        };
+        let mut iter = self.btree.search(bufmgr, SearchMode::Start)?;
+        while let Some((pkey, value)) = iter.next(bufmgr)? {
+            for version in self.decode_versions(bufmgr, &value)? {
+                index.insert(bufmgr, &version.row, &pkey, self.num_key_elems)?;
+            }
+        }
+        self.indexes.push(index);
+        Ok(())
+    }
+
+    // Adds an empty secondary index on `columns`, not valid until `build_index` has filled it,
+    // and without locking the table: the writes from now on keep it up to date.
+    pub fn add_index(&mut self, bufmgr: &mut BufferPoolManager, columns: Vec<usize>, paths: Vec<Option<json::Path>>, kind: IndexKind) -> Result<(), Error> {
+        self.indexes.push(Index {
+            btree: bufmgr.creating_pages_beside(self.btree.meta_page_id, |bufmgr| Index::create_tree(bufmgr, kind))?,
+            paths: Index::paths_of(&columns, paths),
+            columns,
+            valid: false,
+            kind,
+        });
+        Ok(())
+    }
+
+    // Makes `columns`, of text, dictionary columns (see `dictionary`), the table having no rows yet.
+    pub fn create_dictionary(&mut self, bufmgr: &mut BufferPoolManager, columns: Vec<usize>) -> Result<(), Error> {
+        self.dictionary = Some(bufmgr.creating_pages_beside(self.btree.meta_page_id, |bufmgr| Dictionary::create(bufmgr, columns))?);
+        Ok(())
+    }
+
+    // Fills index `i` with the versions of up to `limit` rows, from the one of primary key `from`
+    // on, or the first. Returns the primary key to go on from, None once all rows are in. The
+    // entries are left in place if the transaction aborts, as those of writes are, the versions
+    // being in the table either way.
+    pub fn build_index(&self, bufmgr: &mut BufferPoolManager, i: usize, from: Option<&[u8]>, limit: usize) -> Result<Option<Vec<u8>>, Error> {
+        let index = &self.indexes[i];
+        let mode = from.map_or(SearchMode::Start, |from| SearchMode::Key(from.to_vec()));
+        let mut iter = self.btree.search(bufmgr, mode)?;
+        bufmgr.redo_only(|bufmgr| {
+            for _ in 0..limit {
+                let Some((pkey, value)) = iter.next(bufmgr)? else {
+                    return Ok(None);
+                };
+                for version in self.decode_versions(bufmgr, &value)? {
+                    index.insert(bufmgr, &version.row, &pkey, self.num_key_elems)?;
+                }
+            }
+            Ok(iter.next(bufmgr)?.map(|(pkey, _)| pkey))
+        })
+    }
+
+    // Rebuilds index `i` from the versions of the rows, its entries sorted and appended to new
+    // pages, whose root then takes the place of the old one in the meta page of the index, so
+    // that whatever holds the index reads the new nodes. The old nodes, unless they don't make a
+    // tree, and the meta page of the new tree are freed (see `BufferPoolManager::free_page`), the
+    // old ones before the new are created so that they may be reused for them.
+    pub fn rebuild_index(&self, bufmgr: &mut BufferPoolManager, i: usize) -> Result<(), Error> {
+        self.lock(bufmgr, LockMode::Exclusive)?;
+        let index = &self.indexes[i];
+        let (mut entries, mut rows) = (vec![], vec![]);
+        let mut iter = self.btree.search(bufmgr, SearchMode::Start)?;
+        while let Some((pkey, value)) = iter.next(bufmgr)? {
+            for version in self.decode_versions(bufmgr, &value)? {
+                match index.kind {
+                    IndexKind::ZoneMap => rows.push((pkey.clone(), version.row)),
+                    _ => entries.extend(index.keys(&version.row, self.num_key_elems).into_iter().map(|key| (key, pkey.clone()))),
+                }
+            }
+        }
+        if index.kind == IndexKind::ZoneMap {
+            entries = zonemap::entries(&index.columns, rows, self.num_key_elems);
+        }
+        entries.sort();
+        entries.dedup();
+        match index.kind {
+            IndexKind::RTree => rebuild_rtree(bufmgr, &index.rtree(), &entries),
+            _ => rebuild(bufmgr, &index.btree, &entries, REBUILD_FILL_FACTOR),
+        }
+    }
+
+    // Rewrites the rows, every version kept as it is, into pages filled as full as they go, then
+    // rebuilds the indexes, as VACUUM FULL does once vacuum has removed what it can. The rows are
+    // moved from the old B+tree to a new one REWRITE_BATCH_ENTRIES at a time, each leaf freed once
+    // read, and the pages are reused as `rebuild_index` does, the lowest first, so that the table
+    // ends up at the start of the pages it had and of those freed before.
+    pub fn rewrite(&self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        self.lock(bufmgr, LockMode::Exclusive)?;
+        let pages = self.btree.pages(bufmgr)?;
+        // made once the first leaves are freed, for its pages to be among them
+        let mut tree = None;
+        let mut leaves = BTreeSet::new();
+        let mut batch = vec![];
+        let mut iter = self.btree.search(bufmgr, SearchMode::Start)?;
+        loop {
+            let entries = iter.next_leaf(bufmgr)?;
+            let done = entries.is_none();
+            if let Some(entries) = entries {
+                batch.extend(entries);
+                leaves.insert(iter.leaf());
+                bufmgr.free_page(iter.leaf());
+            }
+            if done || batch.len() >= REWRITE_BATCH_ENTRIES {
+                let tree = match tree {
+                    Some(tree) => tree,
+                    None => *tree.insert(BTree::create(bufmgr)?),
+                };
+                tree.append(bufmgr, &batch, REWRITE_FILL_FACTOR)?;
+                batch.clear();
+            }
+            if done {
+                break;
+            }
+        }
+        let tree = tree.unwrap();
+        self.btree.replace_nodes(bufmgr, &tree)?;
+        // the branches, and the leaves left empty
+        pages.into_iter().filter(|page_id| !leaves.contains(page_id)).for_each(|page_id| bufmgr.free_page(page_id));
+        bufmgr.free_page(tree.meta_page_id);
+        (0..self.indexes.len()).try_for_each(|i| self.rebuild_index(bufmgr, i))
+    }
+
+    // Starts loading rows in bulk, with the pages filled to `fill_factor` percent.
+    pub fn bulk_load(&self, bufmgr: &mut BufferPoolManager, fill_factor: usize) -> Result<BulkLoad<'_>, Error> {
+        self.lock(bufmgr, LockMode::Exclusive)?;
+        Ok(BulkLoad {
+            table: self,
+            fill_factor,
+            lock_rows: false,
+            rows: vec![],
+            index_entries: vec![vec![]; self.indexes.len()],
+            loaded: 0,
+        })
+    }
+
+    pub fn scan(&self, bufmgr: &mut BufferPoolManager) -> Result<TableIter, Error> {
+        self.scan_columns(bufmgr, None, |_| true)
+    }
+
+    // Like `scan`, but reads of the stripes of a columnar table only those `keep` holds for, and of
+    // them only `columns` if given, the others being NULL.
+    pub fn scan_columns(&self, bufmgr: &mut BufferPoolManager, columns: Option<Vec<usize>>, keep: impl Fn(&Stripe) -> bool) -> Result<TableIter, Error> {
+        self.lock(bufmgr, LockMode::Shared)?;
+        ssi::read(bufmgr, self.btree.meta_page_id, &[]);
+        let stripes = match &self.columnar {
+            Some(columnar) => Some(columnar.scan(bufmgr, columns, keep)?),
+            None => None,
+        };
+        Ok(TableIter {
+            btree: self.btree,
+            iter: self.btree.search(bufmgr, SearchMode::Start)?,
+            skipped: VecDeque::new(),
+            stripes,
+            ttl: self.ttl,
+            now: now(),
+            dictionary: self.dictionary.clone(),
+            num_columns: self.num_columns,
+        })
+    }
+
+    // Like `scan`, but skips the extents of the zone map index `i` which `keep` doesn't hold for.
+    pub fn scan_zones(&self, bufmgr: &mut BufferPoolManager, i: usize, keep: impl Fn(&Extent) -> bool) -> Result<TableIter, Error> {
+        let mut iter = self.scan(bufmgr)?;
+        iter.skipped = zonemap::skipped(&zonemap::extents(bufmgr, &self.indexes[i].btree)?, keep);
+        Ok(iter)
+    }
+
+    // Columns by which `scan` returns the rows: those of the primary key, unless the table is
+    // columnar, the rows of its stripes coming after the others.
+    pub fn scan_ordering(&self) -> Vec<usize> {
+        match self.columnar {
+            Some(_) => vec![],
+            None => self.key_columns(Access::PrimaryKey),
+        }
+    }
+
+    // Whether the pages of the table and its indexes are kept in memory rather than in the data
+    // file (see `BufferPoolManager::creating_memory_pages`), and whether changes to them are logged.
+    pub fn in_memory(&self) -> bool {
+        self.btree.meta_page_id.is_memory()
+    }
+
+    pub fn is_logged(&self) -> bool {
+        self.btree.meta_page_id.is_logged()
+    }
+
+    // Empties the table and its indexes, e.g. an unlogged one when the database is opened again.
+    pub fn clear(&self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        self.btree.clear(bufmgr)?;
+        for index in &self.indexes {
+            match index.kind {
+                IndexKind::RTree => index.rtree().clear(bufmgr)?,
+                _ => index.btree.clear(bufmgr)?,
+            }
+        }
+        if let Some(dictionary) = &self.dictionary {
+            dictionary.clear(bufmgr)?;
+        }
+        Ok(())
+    }
+
+    // The pages of the table, of its indexes, of its stripes and of its dictionary, the meta pages
+    // included.
+    pub fn pages(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<PageId>, Error> {
+        let mut pages = vec![self.btree.meta_page_id];
+        pages.extend(self.btree.pages(bufmgr)?);
+        for index in &self.indexes {
+            pages.extend(index.pages(bufmgr)?);
+        }
+        if let Some(columnar) = &self.columnar {
+            pages.extend(columnar.pages(bufmgr)?);
+        }
+        if let Some(dictionary) = &self.dictionary {
+            pages.extend(dictionary.pages(bufmgr)?);
+        }
+        Ok(pages)
+    }
+
+    // Locks the whole table for the current transaction if locking is turned on.
+    pub fn lock(&self, bufmgr: &mut BufferPoolManager, mode: LockMode) -> Result<(), Error> {
+        Ok(lock::lock(bufmgr, Resource::Table(self.btree.meta_page_id), mode)?)
+    }
+
+    // Every access path along with its leading key columns, the primary key first, leaving out
+    // indexes not yet valid. There's none into the stripes of a columnar table, only scans. Those of
+    // an index on expressions are the columns before the first expression. Full-text indexes look
+    // up words rather than values, by `search`, and R-tree ones bounds, by `search_area`.
+    pub fn access_paths(&self) -> Vec<(Access, Vec<usize>)> {
+        if self.columnar.is_some() {
+            return vec![];
+        }
+        let pkey_columns: Vec<_> = (0..self.num_key_elems).collect();
+        std::iter::once((Access::PrimaryKey, pkey_columns))
+            .chain(
+                self.indexes
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, index)| index.valid && index.kind == IndexKind::BTree)
+                    .map(|(i, index)| (Access::Index(i), index.columns[..index.num_plain()].to_vec())),
+            )
+            .collect()
+    }
+
+    // The rows whose column of the full-text index `i` has every word of `query` (see
+    // `fulltext::matches`), the highest ranking first, then by primary key. Those with the first
+    // word are looked up, and checked for the others.
+    pub fn search(&self, bufmgr: &mut BufferPoolManager, i: usize, query: &str) -> Result<Vec<Tuple>, Error> {
+        let Some(first) = fulltext::tokenize(query).next() else {
+            return Ok(vec![]);
+        };
+        let column = self.indexes[i].columns[0];
+        let text = |row: &Tuple| match &row[column] {
+            Value::Text(text) => text.clone(),
+            _ => String::new(),
+        };
+        let mut rows: Vec<_> = self
+            .lookup(bufmgr, Access::Index(i), &[Value::Text(first)])?
+            .into_iter()
+            .filter(|row| fulltext::matches(&text(row), query))
+            .map(|row| (fulltext::rank(&text(&row), query), row))
+            .collect();
+        rows.sort_by(|(a, _), (b, _)| b.cmp(a));
+        Ok(rows.into_iter().map(|(_, row)| row).collect())
+    }
+
+    // The rows whose point or box in the column of the R-tree index `i` overlaps `area`, by
+    // primary key. The entries of other versions than those read are skipped, as `lookup` skips
+    // them, and one of the version read is only there once, so each row is returned once.
+    pub fn search_area(&self, bufmgr: &mut BufferPoolManager, i: usize, area: &Rect) -> Result<Vec<Tuple>, Error> {
+        let index = &self.indexes[i];
+        ssi::read(bufmgr, self.btree.meta_page_id, &[]);
+        let mut keys: Vec<_> = index.rtree().search(bufmgr, area)?.into_iter().map(|(_, key)| key).collect();
+        keys.sort();
+        let mut rows = vec![];
+        let now = now();
+        for key in keys {
+            let pkey = index.pkey_of(&key)?;
+            lock::lock_row(bufmgr, self.btree.meta_page_id, &pkey, LockMode::Shared)?;
+            let versions = self.versions(bufmgr, &pkey)?;
+            let row = visible_row(bufmgr, versions)?.filter(|row| index.keys(row, self.num_key_elems).contains(&key));
+            rows.extend(row.filter(|row| !self.expired(row, now)));
+        }
+        Ok(rows)
+    }
+
+    // Like the leading key columns of `access_paths`, all of them, each with the path of the
+    // value into its JSON an index on expressions keys on instead, if it does.
+    pub fn key_expressions(&self, access: Access) -> Vec<(usize, Option<&json::Path>)> {
+        match access {
+            Access::PrimaryKey => (0..self.num_key_elems).map(|c| (c, None)).collect(),
+            Access::Index(i) => {
+                let index = &self.indexes[i];
+                index.columns.iter().copied().zip(index.paths.iter().map(Option::as_ref)).collect()
+            }
+        }
+    }
+
+    // Returns an access path which can look up rows by equality on all of `columns`, along with
+    // the order in which the key values must be passed to `lookup` (as positions in `columns`).
+    pub fn access_path(&self, columns: &[usize]) -> Option<(Access, Vec<usize>)> {
+        for (access, key_columns) in self.access_paths() {
+            if columns.is_empty() || key_columns.len() < columns.len() {
+                continue;
+            }
+            let order: Option<Vec<_>> = key_columns[..columns.len()]
+                .iter()
+                .map(|c| columns.iter().position(|x| x == c))
+                .collect();
+            if let Some(order) = order {
+                return Some((access, order));
+            }
+        }
+        None
+    }
+
+    // Like `access_path`, but may use only some of `columns`: returns the access path which covers
+    // the most of them with its leading key columns, along with those columns in key order.
+    pub fn best_access_path(&self, columns: &[usize]) -> Option<(Access, Vec<usize>)> {
+        let mut best: Option<(Access, Vec<usize>)> = None;
+        for (access, key_columns) in self.access_paths() {
+            let prefix: Vec<_> = key_columns.iter().copied().take_while(|c| columns.contains(c)).collect();
+            if !prefix.is_empty() && best.as_ref().is_none_or(|(_, b)| prefix.len() > b.len()) {
+                best = Some((access, prefix));
+            }
+        }
+        best
+    }
+
+    // Columns by which the rows read through `access` are sorted, up to the first expression of
+    // an index on expressions.
+    pub fn key_columns(&self, access: Access) -> Vec<usize> {
+        let pkey = 0..self.num_key_elems;
+        match access {
+            Access::PrimaryKey => pkey.collect(),
+            Access::Index(i) => {
+                let index = &self.indexes[i];
+                match index.num_plain() == index.columns.len() {
+                    true => index.columns.iter().copied().chain(pkey).collect(),
+                    false => index.columns[..index.num_plain()].to_vec(),
+                }
+            }
+        }
+    }
+
+    // Returns all rows whose leading key columns of `access` equal `prefix`, in key order.
+    pub fn lookup(&self, bufmgr: &mut BufferPoolManager, access: Access, prefix: &[Value]) -> Result<Vec<Tuple>, Error> {
+        self.lookup_range(bufmgr, access, prefix, (Bound::Unbounded, Bound::Unbounded))
+    }
+
+    // Like `lookup`, but also only returns the rows whose next key column is within `range`, and
+    // not NULL unless the range is unbounded. The bounds must be of the column's type.
+    pub fn lookup_range(
+        &self,
+        bufmgr: &mut BufferPoolManager,
+        access: Access,
+        prefix: &[Value],
+        range: (Bound<&Value>, Bound<&Value>),
+    ) -> Result<Vec<Tuple>, Error> {
+        let encode = |value: Option<&Value>| {
+            let mut key = vec![];
+            tuple::encode_key(prefix, &mut key);
+            if let Some(value) = value {
+                tuple::encode_key(std::slice::from_ref(value), &mut key);
+            }
+            key
+        };
+        let key = encode(None);
+        // reads through an index are taken as reads of the whole table
+        let read = if access == Access::PrimaryKey { key.as_slice() } else { &[] };
+        ssi::read(bufmgr, self.btree.meta_page_id, read);
+        let null = (range != (Bound::Unbounded, Bound::Unbounded)).then(|| encode(Some(&Value::Null)));
+        let (start, excluded) = match range.0 {
+            Bound::Included(value) => (encode(Some(value)), None),
+            Bound::Excluded(value) => (encode(Some(value)), Some(encode(Some(value)))),
+            Bound::Unbounded => (key.clone(), None),
+        };
+        // since the encoding of a value is a prefix of that of the value followed by others, an
+        // entry key is below the encoded bound if the value is, and starts with it if they're equal
+        let below_upper = |entry_key: &[u8]| match range.1 {
+            Bound::Included(value) => {
+                let upper = encode(Some(value));
+                entry_key < upper.as_slice() || entry_key.starts_with(&upper)
+            }
+            Bound::Excluded(value) => entry_key < encode(Some(value)).as_slice(),
+            Bound::Unbounded => true,
+        };
+        let btree = match access {
+            Access::PrimaryKey => self.btree,
+            Access::Index(i) => self.indexes[i].btree,
+        };
+        let mut iter = btree.search(bufmgr, SearchMode::Key(start))?;
+        let mut rows = vec![];
+        let now = now();
+        while let Some((entry_key, value)) = iter.next(bufmgr)? {
+            if !entry_key.starts_with(&key) || !below_upper(&entry_key) {
+                break;
+            }
+            let skipped = [&excluded, &null].into_iter().flatten().any(|k| entry_key.starts_with(k));
+            if skipped {
+                continue;
+            }
+            let pkey = match access {
+                Access::PrimaryKey => entry_key.clone(),
+                Access::Index(_) => value.clone(),
+            };
+            lock::lock_row(bufmgr, self.btree.meta_page_id, &pkey, LockMode::Shared)?;
+            let versions = match access {
+                Access::PrimaryKey => self.decode_versions(bufmgr, &value)?,
+                Access::Index(_) => self.versions(bufmgr, &value)?,
+            };
+            let row = visible_row(bufmgr, versions)?.filter(|row| match access {
+                Access::PrimaryKey => true,
+                // the entry may be of another version
+                Access::Index(i) => self.indexes[i].keys(row, self.num_key_elems).contains(&entry_key),
+            });
+            rows.extend(row.filter(|row| !self.expired(row, now)));
+        }
+        Ok(rows)
+    }
+
+    fn encode_pkey(&self, row: &[Value]) -> Vec<u8> {
+        let mut pkey = vec![];
+        tuple::encode_key(&row[..self.num_key_elems], &mut pkey);
+        pkey
+    }
+}
+
+// Rows being loaded into a table without the overhead of inserting them one at a time: the table
+// is locked once rather than each row, and the rows are sorted by primary key a batch at a time.
+// A batch above the rows there, as all are in an empty table, is appended to the tree with the
+// pages filled to the fill factor and each written, and logged, once. Others are inserted row by
+// row. The index entries are deferred and added sorted, appended where they can be, in batches of
+// their own. Rows are checked for duplicate keys as `Table::insert` does.
+pub struct BulkLoad<'a> {
+    table: &'a Table,
+    fill_factor: usize,
+    // whether the rows appended are each locked, the table not being locked as a whole
+    lock_rows: bool,
+    rows: Vec<Tuple>,
+    // of each index
+    index_entries: Vec<Vec<Entry>>,
+    loaded: usize,
+}
+
+impl BulkLoad<'_> {
+    // A row whose entry is too large for the tree is refused here rather than with its batch, the
+    // rows before it still to be written.
+    pub fn push(&mut self, bufmgr: &mut BufferPoolManager, row: Tuple) -> Result<(), Error> {
+        let value = self.table.encode_versions(bufmgr, &[Version { xmin: FROZEN, xmax: None, row: row.clone() }])?;
+        if self.table.encode_pkey(&row).len() + value.len() > btree::MAX_ENTRY_SIZE {
+            return Err(btree::Error::EntryTooLarge.into());
+        }
+        self.rows.push(row);
+        if self.rows.len() >= BULK_BATCH_ROWS {
+            self.write_rows(bufmgr)?;
+        }
+        Ok(())
+    }
+
+    // Writes what's left, returning the rows loaded.
+    pub fn finish(mut self, bufmgr: &mut BufferPoolManager) -> Result<usize, Error> {
+        self.write_rows(bufmgr)?;
+        self.write_index_entries(bufmgr)?;
+        Ok(self.loaded)
+    }
+
+    fn write_rows(&mut self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        let table = self.table;
+        let mut rows: Vec<_> = std::mem::take(&mut self.rows).into_iter().map(|row| (table.encode_pkey(&row), row)).collect();
+        if rows.is_empty() {
+            return Ok(());
+        }
+        rows.sort_by(|a, b| a.0.cmp(&b.0));
+        if rows.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(btree::Error::DuplicateKey.into());
+        }
+        for (_, row) in &rows {
+            if table.compacted(bufmgr, &row[..table.num_key_elems])?.is_some() {
+                return Err(btree::Error::DuplicateKey.into());
+            }
+        }
+        let xmin = bufmgr.txid()?.unwrap_or(FROZEN);
+        let mut entries = vec![];
+        for (pkey, row) in &rows {
+            if self.lock_rows {
+                lock::lock_row(bufmgr, table.btree.meta_page_id, pkey, LockMode::Exclusive)?;
+            }
+            ssi::write(bufmgr, table.btree.meta_page_id, pkey)?;
+            let value = table.encode_versions(bufmgr, &[Version { xmin, xmax: None, row: row.clone() }])?;
+            entries.push((pkey.clone(), value));
+        }
+        let fill_factor = self.fill_factor;
+        if !bufmgr.redo_only(|bufmgr| table.btree.append(bufmgr, &entries, fill_factor))? {
+            for ((pkey, row), (_, value)) in rows.iter().zip(&entries) {
+                let mut versions = table.versions_to_write(bufmgr, pkey)?;
+                table.retire_expired(bufmgr, &mut versions)?;
+                if versions.first().is_some_and(|newest| newest.xmax.is_none()) {
+                    return Err(btree::Error::DuplicateKey.into());
+                }
+                if versions.is_empty() {
+                    bufmgr.redo_only(|bufmgr| table.btree.upsert(bufmgr, pkey, value))?;
+                } else {
+                    let versions: Vec<_> = std::iter::once(Version { xmin, xmax: None, row: row.clone() }).chain(versions).collect();
+                    let value = table.encode_versions(bufmgr, &versions)?;
+                    bufmgr.redo_only(|bufmgr| table.btree.upsert(bufmgr, pkey, &value))?;
+                }
+            }
+        }
+        for (pkey, row) in &rows {
+            table.log_change(bufmgr, None, Some(row))?;
+            for (index, entries) in table.indexes.iter().zip(&mut self.index_entries) {
+                match index.kind {
+                    IndexKind::ZoneMap => bufmgr.redo_only(|bufmgr| index.insert(bufmgr, row, pkey, table.num_key_elems))?,
+                    _ => entries.extend(index.keys(row, table.num_key_elems).into_iter().map(|key| (key, pkey.clone()))),
+                }
+            }
+        }
+        self.loaded += rows.len();
+        if self.index_entries.iter().map(Vec::len).sum::<usize>() >= BULK_INDEX_ENTRIES {
+            self.write_index_entries(bufmgr)?;
+        }
+        Ok(())
+    }
+
+    fn write_index_entries(&mut self, bufmgr: &mut BufferPoolManager) -> Result<(), Error> {
+        let fill_factor = self.fill_factor;
+        for (index, entries) in self.table.indexes.iter().zip(&mut self.index_entries) {
+            let mut entries = std::mem::take(entries);
+            entries.sort();
+            entries.dedup();
+            bufmgr.redo_only(|bufmgr| {
+                if index.kind == IndexKind::RTree || !index.btree.append(bufmgr, &entries, fill_factor)? {
+                    for (key, pkey) in &entries {
+                        index.put(bufmgr, key, pkey)?;
+                    }
+                }
+                Ok::<_, Error>(())
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl Index {
+    // The tree of an index of `kind`, of which a B+tree stands for that of an R-tree by its meta page.
+    fn create_tree(bufmgr: &mut BufferPoolManager, kind: IndexKind) -> Result<BTree, Error> {
+        Ok(match kind {
+            IndexKind::RTree => BTree { meta_page_id: RTree::create(bufmgr)?.meta_page_id },
+            _ => BTree::create(bufmgr)?,
+        })
+    }
+
+    // The R-tree of an R-tree index.
+    pub fn rtree(&self) -> RTree {
+        RTree { meta_page_id: self.btree.meta_page_id }
+    }
+
+    // The pages of the index, its meta page included.
+    pub fn pages(&self, bufmgr: &mut BufferPoolManager) -> Result<Vec<PageId>, Error> {
+        let mut pages = vec![self.btree.meta_page_id];
+        match self.kind {
+            IndexKind::RTree => pages.extend(self.rtree().pages(bufmgr)?),
+            _ => pages.extend(self.btree.pages(bufmgr)?),
+        }
+        Ok(pages)
+    }
+
+    // Adds the entries of `row` unless another version of it has the same ones.
+    fn insert(&self, bufmgr: &mut BufferPoolManager, row: &[Value], pkey: &[u8], num_key_elems: usize) -> Result<(), Error> {
+        if self.kind == IndexKind::ZoneMap {
+            return zonemap::add(bufmgr, &self.btree, &self.columns, row, pkey, num_key_elems);
+        }
+        for key in self.keys(row, num_key_elems) {
+            self.put(bufmgr, &key, pkey)?;
+        }
+        Ok(())
+    }
+
+    // Adds the entry of `key`, of the row of `pkey`, unless it's there. That of an R-tree index is
+    // the key under the bounds of the point or box it starts with.
+    fn put(&self, bufmgr: &mut BufferPoolManager, key: &[u8], pkey: &[u8]) -> Result<(), Error> {
+        match self.kind {
+            IndexKind::RTree => self.rtree().insert(bufmgr, &bounds_of_key(key)?, key)?,
+            _ => self.btree.upsert(bufmgr, key, pkey)?,
+        }
+        Ok(())
+    }
+
+    // Removes the entry of `key`, returning whether there was one.
+    fn remove(&self, bufmgr: &mut BufferPoolManager, key: &[u8]) -> Result<bool, Error> {
+        Ok(match self.kind {
+            IndexKind::RTree => self.rtree().delete(bufmgr, &bounds_of_key(key)?, key)?,
+            _ => self.btree.delete(bufmgr, key)?,
+        })
+    }
+
+    // The encoded primary key at the end of `key`, which an entry of an R-tree index has only
+    // there.
+    pub fn pkey_of(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        let values = tuple::decode_key(key)?;
+        let mut pkey = vec![];
+        tuple::encode_key(values.get(self.columns.len()..).ok_or(tuple::Error::Malformed)?, &mut pkey);
+        Ok(pkey)
+    }
+
+    // The keys of the entries of `row`: one, or of a full-text index one of each word of the
+    // column, and none if it's NULL, as of an R-tree index, nor of a zone map index, whose entries
+    // are of extents.
+    pub fn keys(&self, row: &[Value], num_key_elems: usize) -> Vec<Vec<u8>> {
+        let pkey = || (0..num_key_elems).map(|c| row[c].clone());
+        let encode = |values: Vec<Value>| {
+            let mut key = vec![];
+            tuple::encode_key(&values, &mut key);
+            key
+        };
+        if self.kind == IndexKind::ZoneMap || (self.kind == IndexKind::RTree && row[self.columns[0]].is_null()) {
+            return vec![];
+        }
+        if self.kind == IndexKind::FullText {
+            let words = match &row[self.columns[0]] {
+                Value::Text(text) => fulltext::terms(text),
+                _ => BTreeSet::new(),
+            };
+            return words.into_iter().map(|word| encode(std::iter::once(Value::Text(word)).chain(pkey()).collect())).collect();
+        }
+        let values = self
+            .columns
+            .iter()
+            .zip(&self.paths)
+            .map(|(&c, path)| path.as_ref().map_or_else(|| row[c].clone(), |path| path.extract(&row[c])))
+            .chain(pkey())
+            .collect();
+        vec![encode(values)]
+    }
+
+    // `paths` of as many as `columns`, those left out None.
+    pub fn paths_of(columns: &[usize], mut paths: Vec<Option<json::Path>>) -> Vec<Option<json::Path>> {
+        paths.resize(columns.len(), None);
+        paths
+    }
+
+    // How many of the key columns come before the first expression.
+    fn num_plain(&self) -> usize {
+        self.paths.iter().take_while(|path| path.is_none()).count()
+    }
+}
+
+// Puts `entries`, sorted, in new pages of `btree` in place of its nodes, which are freed first
+// unless they don't make a tree, as is the meta page of the tree the new nodes are made in.
+fn rebuild(bufmgr: &mut BufferPoolManager, btree: &BTree, entries: &[btree::Entry], fill_factor: usize) -> Result<(), Error> {
+    match btree.pages(bufmgr) {
+        Ok(pages) => pages.into_iter().for_each(|page_id| bufmgr.free_page(page_id)),
+        Err(btree::Error::Malformed) => {}
+        Err(err) => return Err(err.into()),
+    }
+    let tree = BTree::create(bufmgr)?;
+    tree.append(bufmgr, entries, fill_factor)?;
+    btree.replace_nodes(bufmgr, &tree)?;
+    bufmgr.free_page(tree.meta_page_id);
+    Ok(())
+}
+
+// Puts the entries, of (key, primary key), of an R-tree index in new pages in place of its nodes,
+// which are freed first unless they don't make a tree, as `rebuild` does those of a B+tree.
+fn rebuild_rtree(bufmgr: &mut BufferPoolManager, rtree: &RTree, entries: &[btree::Entry]) -> Result<(), Error> {
+    match rtree.pages(bufmgr) {
+        Ok(pages) => pages.into_iter().for_each(|page_id| bufmgr.free_page(page_id)),
+        Err(rtree::Error::Malformed) => {}
+        Err(err) => return Err(err.into()),
+    }
+    let tree = RTree::create(bufmgr)?;
+    for (key, _) in entries {
+        tree.insert(bufmgr, &bounds_of_key(key)?, key)?;
+    }
+    rtree.replace_nodes(bufmgr, &tree)?;
+    bufmgr.free_page(tree.meta_page_id);
+    Ok(())
+}
+
+// The bounds of the point or box the key of an entry of an R-tree index starts with.
+fn bounds_of_key(key: &[u8]) -> Result<Rect, Error> {
+    let values = tuple::decode_key(key)?;
+    Ok(values.first().and_then(geometry::bounds).ok_or(tuple::Error::Malformed)?)
+}
+
+// Fills the columns added to the table since `row` was written with NULLs.
+fn pad(row: &mut Tuple, num_columns: usize) {
+    if row.len() < num_columns {
+        row.resize(num_columns, Value::Null);
+    }
+}
+
+// The version of a row in the snapshot of the current transaction.
+fn visible_row(bufmgr: &mut BufferPoolManager, versions: Vec<Version>) -> Result<Option<Tuple>, Error> {
+    ssi::read_versions(bufmgr, &versions)?;
+    Ok(versions.into_iter().find(|version| mvcc::visible(bufmgr, version)).map(|version| version.row))
+}
+
+// The version of a row seen by `visibility`, out of its chain of versions as stored.
+pub fn visible_in(visibility: &Visibility, versions: &[u8]) -> Result<Option<Tuple>, tuple::Error> {
+    Ok(mvcc::decode_versions(versions)?.into_iter().find(|version| visibility.visible(version)).map(|version| version.row))
+}
+
+pub struct TableIter {
+    btree: BTree,
+    iter: btree::Iter,
+    // ranges of primary keys to skip (see `zonemap::skipped`)
+    skipped: VecDeque<(Vec<u8>, Option<Vec<u8>>)>,
+    // read once the B+tree has been, of a columnar table
+    stripes: Option<StripeIter>,
+    // the rows expired as of when the scan started are left out
+    ttl: Option<Ttl>,
+    now: i64,
+    dictionary: Option<Dictionary>,
+    num_columns: Option<usize>,
+}
+
+impl TableIter {
+    pub fn next(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Tuple>, Error> {
+        while let Some((pkey, value)) = self.iter.next(bufmgr)? {
+            while self.skipped.front().is_some_and(|(_, to)| to.as_ref().is_some_and(|to| *to <= pkey)) {
+                self.skipped.pop_front();
+            }
+            if self.skipped.front().is_some_and(|(from, _)| *from <= pkey) {
+                match self.skipped.pop_front().unwrap().1 {
+                    Some(to) => self.iter = self.btree.search(bufmgr, SearchMode::Key(to))?,
+                    None => break,
+                }
+                continue;
+            }
+            if let Some(mut row) = visible_row(bufmgr, mvcc::decode_versions(&value)?)?.filter(|row| self.live(row)) {
+                self.decode(bufmgr, &mut row)?;
+                return Ok(Some(row));
+            }
+        }
+        match &mut self.stripes {
+            Some(stripes) => stripes.next(bufmgr),
+            None => Ok(None),
+        }
+    }
+
+    // Whether `row` hasn't expired, as the rows of `next_page` are to be checked.
+    pub fn live(&self, row: &[Value]) -> bool {
+        !self.ttl.is_some_and(|ttl| ttl.expired(row, self.now))
+    }
+
+    // Decodes the codes in the dictionary columns of `row`, one of those of `next_page`.
+    pub fn decode(&self, bufmgr: &mut BufferPoolManager, row: &mut Tuple) -> Result<(), Error> {
+        if let Some(dictionary) = &self.dictionary {
+            dictionary.decode(bufmgr, row)?;
+        }
+        if let Some(num_columns) = self.num_columns {
+            pad(row, num_columns);
+        }
+        Ok(())
+    }
+
+    // The pages of up to `n` of those `next_page` reads next, for them to be read ahead.
+    pub fn pages_ahead(&self, bufmgr: &mut BufferPoolManager, n: usize) -> Vec<PageId> {
+        self.iter.leaves_ahead(bufmgr, n)
+    }
+
+    // The chains of versions of the rows left on the current page, or those of the next one, to
+    // be checked by `visible_in` and `live`, and decoded by `decode`. The stripes of a columnar table aren't read.
+    pub fn next_page(&mut self, bufmgr: &mut BufferPoolManager) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        Ok(self.iter.next_leaf(bufmgr)?.map(|entries| entries.into_iter().map(|(_, value)| value).collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(8));
+        let mut table = Table::create(&mut bufmgr, 1).unwrap();
+        for i in 0..100 {
+            let row = vec![Value::Int(i), Value::Text(format!("name{}", i % 10)), Value::Int(i % 3)];
+            table.insert(&mut bufmgr, &row).unwrap();
+        }
+        table.create_index(&mut bufmgr, vec![2, 1], vec![], IndexKind::BTree).unwrap();
+        table.insert(&mut bufmgr, &[Value::Int(100), Value::Text("name0".to_string()), Value::Int(1)]).unwrap();
+
+        let mut iter = table.scan(&mut bufmgr).unwrap();
+        let mut n = 0;
+        while let Some(row) = iter.next(&mut bufmgr).unwrap() {
+            assert_eq!(Value::Int(n), row[0]);
+            n += 1;
+        }
+        assert_eq!(101, n);
+
+        let rows = table.lookup(&mut bufmgr, Access::PrimaryKey, &[Value::Int(42)]).unwrap();
+        assert_eq!(vec![vec![Value::Int(42), Value::Text("name2".to_string()), Value::Int(0)]], rows);
+
+        assert_eq!(Some((Access::Index(0), vec![1, 0])), table.access_path(&[1, 2]));
+        assert_eq!(None, table.access_path(&[1]));
+        assert_eq!(Some((Access::Index(0), vec![2, 1])), table.best_access_path(&[0, 1, 2]));
+        assert_eq!(Some((Access::Index(0), vec![2])), table.best_access_path(&[2]));
+        assert_eq!(None, table.best_access_path(&[1]));
+        let rows = table.lookup(&mut bufmgr, Access::Index(0), &[Value::Int(1), Value::Text("name0".to_string())]).unwrap();
+        let ids: Vec<_> = rows.iter().map(|r| r[0].clone()).collect();
+        assert_eq!(vec![Value::Int(10), Value::Int(40), Value::Int(70), Value::Int(100)], ids);
+
+        let (low, high) = (Value::Int(10), Value::Int(13));
+        let rows = table.lookup_range(&mut bufmgr, Access::PrimaryKey, &[], (Bound::Excluded(&low), Bound::Included(&high))).unwrap();
+        let ids: Vec<_> = rows.iter().map(|r| r[0].clone()).collect();
+        assert_eq!(vec![Value::Int(11), Value::Int(12), Value::Int(13)], ids);
+        let (low, high) = (Value::Text("name3".to_string()), Value::Text("name5".to_string()));
+        let rows = table.lookup_range(&mut bufmgr, Access::Index(0), &[Value::Int(0)], (Bound::Included(&low), Bound::Excluded(&high))).unwrap();
+        let ids: Vec<_> = rows.iter().map(|r| r[0].clone()).collect();
+        assert_eq!(vec![Value::Int(3), Value::Int(33), Value::Int(63), Value::Int(93), Value::Int(24), Value::Int(54), Value::Int(84)], ids);
+        // many rows inserted at once, appended where they're above the others, with their index
+        // entries, and inserted one by one where they're not
+        let row = |i: i64| vec![Value::Int(i), Value::Text(format!("name{}", i % 10)), Value::Int(i % 3)];
+        assert_eq!(400, table.insert_many(&mut bufmgr, (101..501).rev().map(row)).unwrap());
+        assert_eq!(2, table.insert_many(&mut bufmgr, [row(-1), row(501)]).unwrap());
+        assert!(table.insert_many(&mut bufmgr, [row(502), row(42)]).is_err());
+        assert!(table.insert_many(&mut bufmgr, [row(503), row(503)]).is_err());
+        let mut iter = table.scan(&mut bufmgr).unwrap();
+        let mut ids = vec![];
+        while let Some(row) = iter.next(&mut bufmgr).unwrap() {
+            ids.push(row[0].clone());
+        }
+        assert_eq!((-1..=501).map(Value::Int).collect::<Vec<_>>(), ids);
+        let rows = table.lookup(&mut bufmgr, Access::Index(0), &[Value::Int(1), Value::Text("name0".to_string())]).unwrap();
+        assert_eq!(17, rows.len());
+
+        // rows expired by the TTL of the table are neither read nor in the way of others
+        let table = Table { ttl: Some(Ttl { column: 1, seconds: 60 }), ..Table::create(&mut bufmgr, 1).unwrap() };
+        let row = |id: i64, time: Value| vec![Value::Int(id), time];
+        for id in 0..5 {
+            table.insert(&mut bufmgr, &row(id, Value::Int(if id % 2 == 0 { 0 } else { now() }))).unwrap();
+        }
+        table.insert(&mut bufmgr, &row(5, Value::Null)).unwrap();
+        let ids = |bufmgr: &mut BufferPoolManager, table: &Table| {
+            let mut iter = table.scan(bufmgr).unwrap();
+            let mut ids = vec![];
+            while let Some(row) = iter.next(bufmgr).unwrap() {
+                ids.push(row[0].clone());
+            }
+            ids
+        };
+        assert_eq!(vec![Value::Int(1), Value::Int(3), Value::Int(5)], ids(&mut bufmgr, &table));
+        assert!(table.lookup(&mut bufmgr, Access::PrimaryKey, &[Value::Int(2)]).unwrap().is_empty());
+        table.insert(&mut bufmgr, &row(0, Value::Int(now()))).unwrap();
+        assert_eq!(4, ids(&mut bufmgr, &table).len());
+        // and are deleted a batch at a time
+        let (deleted, next) = table.expire(&mut bufmgr, None, 1).unwrap();
+        assert!(deleted == 1 && next.is_some());
+        assert_eq!((1, None), table.expire(&mut bufmgr, next.as_deref(), 10).unwrap());
+        assert_eq!((0, None), table.expire(&mut bufmgr, None, 10).unwrap());
+        let all = Table { ttl: None, ..table.clone() };
+        assert_eq!(vec![Value::Int(0), Value::Int(1), Value::Int(3), Value::Int(5)], ids(&mut bufmgr, &all));
+
+        // a full-text index finds the rows with every word searched for, those with the most first,
+        // and keeps up with the words rows are updated to have
+        let mut table = Table::create(&mut bufmgr, 1).unwrap();
+        let row = |id: i64, body: &str| vec![Value::Int(id), Value::Text(body.to_string())];
+        table.insert(&mut bufmgr, &row(1, "The quick brown fox")).unwrap();
+        table.insert(&mut bufmgr, &row(2, "A lazy dog")).unwrap();
+        table.create_index(&mut bufmgr, vec![1], vec![], IndexKind::FullText).unwrap();
+        table.insert_many(&mut bufmgr, [row(3, "Fox, fox, and the brown fox"), vec![Value::Int(4), Value::Null]]).unwrap();
+        assert!(table.access_paths().iter().all(|(access, _)| *access == Access::PrimaryKey));
+        let search = |bufmgr: &mut BufferPoolManager, table: &Table, query: &str| {
+            table.search(bufmgr, 0, query).unwrap().into_iter().map(|row| row[0].clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(vec![Value::Int(3), Value::Int(1)], search(&mut bufmgr, &table, "FOX brown"));
+        assert_eq!(vec![Value::Int(1)], search(&mut bufmgr, &table, "fox quick"));
+        assert!(search(&mut bufmgr, &table, "!").is_empty());
+        table.update(&mut bufmgr, &row(1, "a quick dog")).unwrap();
+        table.delete(&mut bufmgr, &[Value::Int(3)]).unwrap();
+        assert!(search(&mut bufmgr, &table, "fox").is_empty());
+        assert_eq!(vec![Value::Int(1), Value::Int(2)], search(&mut bufmgr, &table, "dog"));
+        table.vacuum(&mut bufmgr).unwrap();
+        assert_eq!(vec![Value::Int(1)], search(&mut bufmgr, &table, "quick"));
+
+        // rewritten a batch at a time into as few pages as the rows fit in, the old ones reused
+        // and the rest left free
+        let table = Table::create(&mut bufmgr, 1).unwrap();
+        let row = |id: i64| vec![Value::Int(id), Value::Text("x".repeat(20))];
+        table.insert_many(&mut bufmgr, (0..3000).map(row)).unwrap();
+        for id in (0..3000).filter(|id| id % 2 == 1) {
+            assert!(table.delete(&mut bufmgr, &[Value::Int(id)]).unwrap());
+        }
+        let old = table.btree.pages(&mut bufmgr).unwrap();
+        let mut free = BTreeSet::new();
+        bufmgr.reusing_pages(&mut free, |bufmgr| table.rewrite(bufmgr)).unwrap();
+        let new = table.btree.pages(&mut bufmgr).unwrap();
+        assert!(new.len() < old.len() && new.iter().all(|page_id| old.contains(page_id) && !free.contains(page_id)));
+        assert_eq!(old.len() - new.len(), free.len());
+        assert_eq!((0..3000).step_by(2).map(Value::Int).collect::<Vec<_>>(), ids(&mut bufmgr, &table));
+    }
+}
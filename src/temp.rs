@@ -0,0 +1,194 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::disk::PAGE_SIZE;
+
+// Temporary pages, such as those of the runs spilled by sorts and hash joins, kept apart from the
+// data file in a file of their own which is neither logged nor synced. The pages freed are
+// recycled, the file is removed when the manager is dropped, and anything left in its directory,
+// by a crash say, when it's opened.
+//
+// Pages are cached in a small ring of buffers of their own, so that a large sort doesn't evict
+// the pages of the tables from the buffer pool, and written to the file when evicted from it.
+
+const FILE_NAME: &str = "pages";
+// Buffers of the ring.
+const RING_SIZE: usize = 8;
+
+pub type TempPage = [u8; PAGE_SIZE as usize];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TempPageId(pub u64);
+
+struct Frame {
+    page_id: Option<TempPageId>,
+    page: Box<TempPage>,
+    dirty: bool,
+    // cleared by the clock hand on its way round
+    referenced: bool,
+}
+
+pub struct TempFileManager {
+    dir: PathBuf,
+    file: File,
+    next_page_id: u64,
+    free: Vec<TempPageId>,
+    ring: Vec<Frame>,
+    hand: usize,
+}
+
+impl TempFileManager {
+    // Opens the temporary files in `dir`, which is theirs alone, removing whatever is there.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(dir.join(FILE_NAME))?;
+        let ring = (0..RING_SIZE)
+            .map(|_| Frame { page_id: None, page: Box::new([0; PAGE_SIZE as usize]), dirty: false, referenced: false })
+            .collect();
+        Ok(Self { dir, file, next_page_id: 0, free: vec![], ring, hand: 0 })
+    }
+
+    // Opens temporary files in a directory of their own under that of the system.
+    pub fn open_default() -> io::Result<Self> {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let name = format!("beyond_rdb-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+        Self::open(std::env::temp_dir().join(name))
+    }
+
+    // The directory of the files, which other files of temporary data may go in too.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // A page, zeroed, for the caller alone until freed.
+    pub fn allocate(&mut self) -> io::Result<TempPageId> {
+        let page_id = self.free.pop().unwrap_or_else(|| {
+            self.next_page_id += 1;
+            TempPageId(self.next_page_id - 1)
+        });
+        let frame = self.load(page_id, false)?;
+        frame.page.fill(0);
+        frame.dirty = true;
+        Ok(page_id)
+    }
+
+    // Frees `page_id` to be allocated again, without writing it back.
+    pub fn free(&mut self, page_id: TempPageId) {
+        if let Some(frame) = self.ring.iter_mut().find(|frame| frame.page_id == Some(page_id)) {
+            frame.page_id = None;
+            frame.dirty = false;
+            frame.referenced = false;
+        }
+        self.free.push(page_id);
+    }
+
+    pub fn page(&mut self, page_id: TempPageId) -> io::Result<&TempPage> {
+        Ok(&self.load(page_id, true)?.page)
+    }
+
+    pub fn page_mut(&mut self, page_id: TempPageId) -> io::Result<&mut TempPage> {
+        let frame = self.load(page_id, true)?;
+        frame.dirty = true;
+        Ok(&mut frame.page)
+    }
+
+    // Pages in the file, and how many of them are free.
+    pub fn num_pages(&self) -> u64 {
+        self.next_page_id
+    }
+
+    pub fn num_free_pages(&self) -> usize {
+        self.free.len()
+    }
+
+    // The frame holding `page_id`, read from the file into the one the clock hand stops at
+    // unless it's there already or `read` is false.
+    fn load(&mut self, page_id: TempPageId, read: bool) -> io::Result<&mut Frame> {
+        if let Some(i) = self.ring.iter().position(|frame| frame.page_id == Some(page_id)) {
+            self.ring[i].referenced = true;
+            return Ok(&mut self.ring[i]);
+        }
+        while self.ring[self.hand].referenced {
+            self.ring[self.hand].referenced = false;
+            self.hand = (self.hand + 1) % RING_SIZE;
+        }
+        let i = self.hand;
+        self.hand = (self.hand + 1) % RING_SIZE;
+        let frame = &mut self.ring[i];
+        if let (Some(evicted), true) = (frame.page_id, frame.dirty) {
+            self.file.seek(SeekFrom::Start(evicted.0 * PAGE_SIZE))?;
+            self.file.write_all(frame.page.as_ref())?;
+        }
+        frame.page_id = None;
+        if read {
+            self.file.seek(SeekFrom::Start(page_id.0 * PAGE_SIZE))?;
+            // pages never written back read as zeros
+            match self.file.read_exact(frame.page.as_mut()) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => frame.page.fill(0),
+                result => result?,
+            }
+        }
+        frame.page_id = Some(page_id);
+        frame.dirty = false;
+        frame.referenced = true;
+        Ok(frame)
+    }
+}
+
+impl Drop for TempFileManager {
+    fn drop(&mut self) {
+        // what's left is removed when opened again
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        let parent = tempdir().unwrap();
+        let dir = parent.path().join("temp");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("left-behind"), b"by a crash").unwrap();
+        let mut temp = TempFileManager::open(&dir).unwrap();
+        assert!(!dir.join("left-behind").exists());
+
+        // more pages than the ring holds are written to the file and read back
+        let ids: Vec<_> = (0..RING_SIZE as u64 * 3).map(|_| temp.allocate().unwrap()).collect();
+        for &id in &ids {
+            temp.page_mut(id).unwrap()[..8].copy_from_slice(&id.0.to_le_bytes());
+        }
+        for &id in ids.iter().rev() {
+            assert_eq!(id.0.to_le_bytes(), temp.page(id).unwrap()[..8]);
+        }
+        assert!(fs::metadata(dir.join(FILE_NAME)).unwrap().len() >= RING_SIZE as u64 * 2 * PAGE_SIZE);
+
+        // freed pages are allocated again, zeroed, rather than growing the file
+        temp.free(ids[0]);
+        temp.free(ids[RING_SIZE * 3 - 1]);
+        assert_eq!(2, temp.num_free_pages());
+        let (a, b) = (temp.allocate().unwrap(), temp.allocate().unwrap());
+        assert_eq!((ids[RING_SIZE * 3 - 1], ids[0]), (a, b));
+        assert!(temp.page(a).unwrap().iter().all(|&byte| byte == 0));
+        assert!(temp.page(b).unwrap().iter().all(|&byte| byte == 0));
+        assert_eq!(RING_SIZE as u64 * 3, temp.num_pages());
+
+        // and the files are removed when done with
+        drop(temp);
+        assert!(!dir.exists());
+        let temp = TempFileManager::open_default().unwrap();
+        let dir = temp.dir.clone();
+        assert!(dir.join(FILE_NAME).exists());
+        drop(temp);
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,240 @@
+use std::io;
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::crypto::ring;
+use rustls::pki_types::pem::{self, PemObject};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::{VerifierBuilderError, WebPkiClientVerifier};
+use rustls::{ClientConnection, RootCertStore, ServerConnection, StreamOwned};
+
+// TLS 1.3, to encrypt the connections of the server, by rustls and the cryptography of ring.
+// The server presents a chain of certificates in PEM, of any key rustls supports, and may ask
+// clients for certificates, which must then have been issued by one of the CAs it's given, the
+// common name of a client's telling the user it's of.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Tls(#[from] rustls::Error),
+    #[error("malformed PEM: {0}")]
+    Pem(#[from] pem::Error),
+    #[error("no certificate in the PEM")]
+    NoCertificate,
+    #[error(transparent)]
+    Verifier(#[from] VerifierBuilderError),
+    #[error("invalid server name: {0}")]
+    ServerName(String),
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+pub type Certificate = CertificateDer<'static>;
+pub type PrivateKey = PrivateKeyDer<'static>;
+
+// A connection encrypted by TLS, on the server's side, once the handshake is done.
+pub type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+// The certificates in `pem`, of which there must be one at least.
+pub fn certificates_from_pem(pem: &str) -> Result<Vec<Certificate>, Error> {
+    let certificates = CertificateDer::pem_slice_iter(pem.as_bytes()).collect::<Result<Vec<_>, _>>()?;
+    if certificates.is_empty() {
+        return Err(Error::NoCertificate);
+    }
+    Ok(certificates)
+}
+
+// The private key in `pem`, in PKCS #8, PKCS #1 or SEC1.
+pub fn private_key_from_pem(pem: &str) -> Result<PrivateKey, Error> {
+    Ok(PrivateKeyDer::from_pem_slice(pem.as_bytes())?)
+}
+
+// The common name of the subject of `certificate`, if it has one.
+pub fn common_name(certificate: &Certificate) -> Option<String> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(certificate).ok()?;
+    let name = certificate.subject().iter_common_name().next()?.as_str().ok()?.to_string();
+    Some(name)
+}
+
+// What the server presents and asks of its clients.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    rustls: Arc<rustls::ServerConfig>,
+    tls_required: bool,
+}
+
+impl ServerConfig {
+    // Of the server's certificate first, then those of the CAs issuing it, and its private key.
+    pub fn new(certificates: Vec<Certificate>, key: PrivateKey) -> Result<Self, Error> {
+        Self::build(certificates, key, None)
+    }
+
+    // Of the chain of certificates and the private key in the PEM files at the paths.
+    pub fn load(certificates: impl AsRef<Path>, key: impl AsRef<Path>) -> Result<Self, Error> {
+        let certificates = certificates_from_pem(&std::fs::read_to_string(certificates)?)?;
+        Self::new(certificates, private_key_from_pem(&std::fs::read_to_string(key)?)?)
+    }
+
+    // Of `certificates` and `key` as `new`, asking clients for certificates issued by one of
+    // `cas`, turning away those without one if `required`.
+    pub fn with_client_cas(certificates: Vec<Certificate>, key: PrivateKey, cas: Vec<Certificate>, required: bool) -> Result<Self, Error> {
+        Self::build(certificates, key, Some((cas, required)))
+    }
+
+    fn build(certificates: Vec<Certificate>, key: PrivateKey, client_cas: Option<(Vec<Certificate>, bool)>) -> Result<Self, Error> {
+        let provider = Arc::new(ring::default_provider());
+        let builder = rustls::ServerConfig::builder_with_provider(provider.clone()).with_protocol_versions(&[&rustls::version::TLS13])?;
+        let config = match client_cas {
+            Some((cas, required)) => {
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots(cas)?), provider);
+                let verifier = if required { verifier } else { verifier.allow_unauthenticated() };
+                builder.with_client_cert_verifier(verifier.build()?).with_single_cert(certificates, key)?
+            }
+            None => builder.with_no_client_auth().with_single_cert(certificates, key)?,
+        };
+        Ok(Self { rustls: Arc::new(config), tls_required: false })
+    }
+
+    // Makes the server turn away clients which don't ask for TLS.
+    pub fn require_tls(mut self) -> Self {
+        self.tls_required = true;
+        self
+    }
+
+    pub fn tls_required(&self) -> bool {
+        self.tls_required
+    }
+}
+
+// What a client trusts and presents.
+#[derive(Debug, Default)]
+pub struct ClientConfig {
+    // the CAs the server's certificate must be issued by
+    pub roots: Vec<Certificate>,
+    // the chain of certificates and the key to present if asked for one
+    pub identity: Option<(Vec<Certificate>, PrivateKey)>,
+}
+
+// Completes the handshake of a client connected on `stream`, as its server.
+pub fn accept(mut stream: TcpStream, config: &ServerConfig) -> Result<TlsStream, Error> {
+    let mut connection = ServerConnection::new(config.rustls.clone())?;
+    while connection.is_handshaking() {
+        connection.complete_io(&mut stream)?;
+    }
+    Ok(StreamOwned::new(connection, stream))
+}
+
+// Completes the handshake with the server of `server_name` connected to on `stream`, as its
+// client.
+pub fn connect(mut stream: TcpStream, server_name: &str, config: &ClientConfig) -> Result<StreamOwned<ClientConnection, TcpStream>, Error> {
+    let builder = rustls::ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_root_certificates(roots(config.roots.clone())?);
+    let rustls = match &config.identity {
+        Some((certificates, key)) => builder.with_client_auth_cert(certificates.clone(), key.clone_key())?,
+        None => builder.with_no_client_auth(),
+    };
+    let server_name = ServerName::try_from(server_name.to_string()).map_err(|_| Error::ServerName(server_name.to_string()))?;
+    let mut connection = ClientConnection::new(Arc::new(rustls), server_name)?;
+    while connection.is_handshaking() {
+        connection.complete_io(&mut stream)?;
+    }
+    Ok(StreamOwned::new(connection, stream))
+}
+
+fn roots(cas: Vec<Certificate>) -> Result<RootCertStore, Error> {
+    let mut roots = RootCertStore::empty();
+    for ca in cas {
+        roots.add(ca)?;
+    }
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const CA: &str = include_str!("tls/testdata/ca.pem");
+    const SERVER: (&str, &str) = (include_str!("tls/testdata/server.pem"), include_str!("tls/testdata/server.key"));
+    const ALICE: (&str, &str) = (include_str!("tls/testdata/alice.pem"), include_str!("tls/testdata/alice.key"));
+    const MALLORY: (&str, &str) = (include_str!("tls/testdata/mallory.pem"), include_str!("tls/testdata/mallory.key"));
+
+    fn identity((certificates, key): (&str, &str)) -> (Vec<Certificate>, PrivateKey) {
+        (certificates_from_pem(certificates).unwrap(), private_key_from_pem(key).unwrap())
+    }
+
+    // The results of the handshakes of a server of `config` and a client of `client`, the
+    // server echoing what it reads back, once. The server's is of the client's certificates.
+    fn handshake(config: ServerConfig, client: ClientConfig) -> (Result<Vec<Certificate>, Error>, Result<(), Error>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut tls = accept(stream, &config)?;
+            let mut buf = vec![0; 100000];
+            tls.read_exact(&mut buf)?;
+            tls.write_all(&buf)?;
+            tls.flush()?;
+            Ok(tls.conn.peer_certificates().unwrap_or_default().to_vec())
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(std::time::Duration::from_secs(30))).unwrap();
+        let client = connect(stream, "localhost", &client).and_then(|mut tls| {
+            // of several records each way
+            let data: Vec<u8> = (0..100000).map(|i| i as u8).collect();
+            tls.write_all(&data)?;
+            let mut echoed = vec![0; data.len()];
+            tls.read_exact(&mut echoed)?;
+            assert_eq!(data, echoed);
+            Ok(())
+        });
+        (server.join().unwrap(), client)
+    }
+
+    #[test]
+    fn test() {
+        let ca = certificates_from_pem(CA).unwrap();
+        let (chain, key) = identity(SERVER);
+        assert_eq!(Some("localhost".to_string()), common_name(&chain[0]));
+        assert!(matches!(ServerConfig::new(chain.clone(), identity(ALICE).1), Err(Error::Tls(_))));
+        assert!(matches!(certificates_from_pem("-----BEGIN CERTIFICATE-----\n!!!!\n-----END CERTIFICATE-----"), Err(Error::Pem(_))));
+        assert!(matches!(certificates_from_pem(""), Err(Error::NoCertificate)));
+        let config = ServerConfig::new(chain.clone(), key.clone_key()).unwrap();
+
+        // encrypted, the client verifying the server's certificate
+        let (server, client) = handshake(config.clone(), ClientConfig { roots: ca.clone(), identity: None });
+        assert_eq!(Vec::<Certificate>::new(), server.unwrap());
+        client.unwrap();
+        // but not if it's issued by another
+        let (server, client) = handshake(config, ClientConfig { roots: identity(MALLORY).0, identity: None });
+        assert!(server.is_err());
+        assert!(matches!(client, Err(Error::Io(_))), "{:?}", client);
+
+        // clients presenting certificates when asked to, which are verified
+        let config = ServerConfig::with_client_cas(chain.clone(), key.clone_key(), ca.clone(), false).unwrap();
+        let (server, _) = handshake(config.clone(), ClientConfig { roots: ca.clone(), identity: Some(identity(ALICE)) });
+        assert_eq!(Some("alice".to_string()), common_name(&server.unwrap()[0]));
+        let (server, _) = handshake(config.clone(), ClientConfig { roots: ca.clone(), identity: None });
+        assert_eq!(Vec::<Certificate>::new(), server.unwrap());
+        let (server, client) = handshake(config, ClientConfig { roots: ca.clone(), identity: Some(identity(MALLORY)) });
+        assert!(server.is_err());
+        assert!(client.is_err());
+        let config = ServerConfig::with_client_cas(chain, key, ca.clone(), true).unwrap();
+        let (server, client) = handshake(config, ClientConfig { roots: ca, identity: None });
+        assert!(server.is_err());
+        assert!(client.is_err());
+    }
+}
@@ -0,0 +1,298 @@
+#[cfg(feature = "tracing")]
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Debug,
+    io::Write,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+#[cfg(feature = "tracing")]
+pub use tracing::{Dispatch, Level, Subscriber};
+#[cfg(feature = "tracing")]
+use tracing::{
+    dispatcher::DefaultGuard,
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    subscriber::Interest,
+    Event, Metadata,
+};
+
+// Spans and events of what the engine does, for finding where the time goes: disk reads, writes
+// and syncs, buffer hits, misses and evictions, WAL appends and flushes, statements and the
+// operators of their plans. They're those of the `tracing` crate, going to whichever subscriber
+// is the default, and cost a check that there's none otherwise. They're compiled out without the
+// `tracing` feature, where the macros expand to nothing.
+//
+// `set_subscriber` sets one for the thread, the engine running on one, of which `Log` writes them
+// to a writer as lines.
+
+// `event!(Level, name, field = value, ...)`, the level one of Error, Warn, Info, Debug and Trace,
+// of which the values are only evaluated, and must be Debug, if the subscriber wants the event.
+#[cfg(feature = "tracing")]
+macro_rules! event {
+    ($level:ident, $name:expr $(, $field:ident = $value:expr)* $(,)?) => {
+        tracing::event!(name: $name, $crate::trace::level!($level), { $($field = ?$value),* })
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! event {
+    ($($args:tt)*) => {};
+}
+
+// `span!(Level, name, field = value, ...)`, entering a span which is left when what it returns is
+// dropped.
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($level:ident, $name:expr $(, $field:ident = $value:expr)* $(,)?) => {
+        tracing::span!($crate::trace::level!($level), $name, $($field = ?$value),*).entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($($args:tt)*) => {
+        ()
+    };
+}
+
+// `enabled!(Level)`, whether the subscriber wants what's of the level here.
+#[cfg(feature = "tracing")]
+macro_rules! enabled {
+    ($level:ident) => {
+        tracing::enabled!($crate::trace::level!($level))
+    };
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! level {
+    (Error) => {
+        tracing::Level::ERROR
+    };
+    (Warn) => {
+        tracing::Level::WARN
+    };
+    (Info) => {
+        tracing::Level::INFO
+    };
+    (Debug) => {
+        tracing::Level::DEBUG
+    };
+    (Trace) => {
+        tracing::Level::TRACE
+    };
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) use {enabled, level};
+pub(crate) use {event, span};
+
+#[cfg(feature = "tracing")]
+thread_local! {
+    static SUBSCRIBER: RefCell<Option<(Arc<dyn Subscriber + Send + Sync>, DefaultGuard)>> = const { RefCell::new(None) };
+}
+
+// Sets the subscriber of the thread, or none, returning the one before.
+#[cfg(feature = "tracing")]
+pub fn set_subscriber(subscriber: Option<Arc<dyn Subscriber + Send + Sync>>) -> Option<Arc<dyn Subscriber + Send + Sync>> {
+    SUBSCRIBER.with(|current| {
+        // the guard of the one before puts back the default it replaced, so it's dropped first
+        let before = current.borrow_mut().take().map(|(subscriber, _)| subscriber);
+        *current.borrow_mut() = subscriber.map(|subscriber| {
+            let guard = tracing::dispatcher::set_default(&Dispatch::from(subscriber.clone()));
+            (subscriber, guard)
+        });
+        before
+    })
+}
+
+// Writes the events and spans up to `level` as lines, indented by the spans they're in:
+//   DEBUG beyond_rdb::wal flush lsn=4096
+//     DEBUG beyond_rdb::wal sync segment=0
+//   DEBUG beyond_rdb::wal flush done in 1.2ms
+#[cfg(feature = "tracing")]
+pub struct Log<W> {
+    output: Mutex<W>,
+    level: Level,
+    depth: AtomicUsize,
+    next_id: AtomicU64,
+    // the spans not yet closed, by id, with their fields and when they were last entered
+    spans: Mutex<HashMap<u64, LogSpan>>,
+}
+
+#[cfg(feature = "tracing")]
+struct LogSpan {
+    metadata: &'static Metadata<'static>,
+    fields: String,
+    entered: Option<Instant>,
+}
+
+#[cfg(feature = "tracing")]
+impl<W: Write> Log<W> {
+    pub fn new(output: W, level: Level) -> Self {
+        Self {
+            output: Mutex::new(output),
+            level,
+            depth: AtomicUsize::new(0),
+            next_id: AtomicU64::new(1),
+            spans: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self, metadata: &Metadata<'_>, fields: &str, suffix: &str) {
+        let indent = "  ".repeat(self.depth.load(Ordering::Relaxed));
+        let line = format!("{}{} {} {}{}{}", indent, metadata.level(), metadata.target(), metadata.name(), fields, suffix);
+        // tracing is best effort, never failing what's traced
+        let mut output = self.output.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = writeln!(output, "{}", line);
+    }
+
+    fn spans(&self) -> std::sync::MutexGuard<'_, HashMap<u64, LogSpan>> {
+        self.spans.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// The fields of an event or span as " name=value" each.
+#[cfg(feature = "tracing")]
+struct Fields<'a>(&'a mut String);
+
+#[cfg(feature = "tracing")]
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.push_str(&format!(" {}={:?}", field.name(), value));
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<W: Write + Send + 'static> Subscriber for Log<W> {
+    // Asked each time, the subscriber being of a thread where the callsite is of them all.
+    fn register_callsite(&self, _: &'static Metadata<'static>) -> Interest {
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= &self.level
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut fields = String::new();
+        attributes.record(&mut Fields(&mut fields));
+        self.spans().insert(id, LogSpan { metadata: attributes.metadata(), fields, entered: None });
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Some(span) = self.spans().get_mut(&span.into_u64()) {
+            values.record(&mut Fields(&mut span.fields));
+        }
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = String::new();
+        event.record(&mut Fields(&mut fields));
+        self.write(event.metadata(), &fields, "");
+    }
+
+    fn enter(&self, span: &Id) {
+        let Some((metadata, fields)) = self.spans().get_mut(&span.into_u64()).map(|span| {
+            span.entered = Some(Instant::now());
+            (span.metadata, span.fields.clone())
+        }) else {
+            return;
+        };
+        self.write(metadata, &fields, "");
+        self.depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn exit(&self, span: &Id) {
+        let Some((metadata, entered)) = self.spans().get(&span.into_u64()).map(|span| (span.metadata, span.entered)) else {
+            return;
+        };
+        let _ = self.depth.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |depth| depth.checked_sub(1));
+        let elapsed = entered.map(|entered| entered.elapsed()).unwrap_or_default();
+        self.write(metadata, "", &format!(" done in {:?}", elapsed));
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        self.spans().remove(&span.into_u64()).is_some()
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::*;
+    use crate::database::Database;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        let log = Arc::new(Log::new(vec![], Level::DEBUG));
+        assert!(set_subscriber(Some(log.clone())).is_none());
+        {
+            let _span = span!(Info, "outer", n = 1);
+            event!(Debug, "inner", s = "a", n = 2 + 2);
+            // of which the fields aren't evaluated
+            event!(Trace, "hidden", n = unevaluated());
+        }
+        set_subscriber(None);
+        event!(Info, "unseen");
+        let output = String::from_utf8(Arc::try_unwrap(log).ok().unwrap().into_inner()).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(3, lines.len(), "{}", output);
+        assert_eq!("INFO beyond_rdb::trace::tests outer n=1", lines[0]);
+        assert_eq!("  DEBUG beyond_rdb::trace::tests inner s=\"a\" n=4", lines[1]);
+        assert!(lines[2].starts_with("INFO beyond_rdb::trace::tests outer done in "), "{}", output);
+
+        // of the engine, up to the level of the subscriber
+        let dir = tempdir().unwrap();
+        let db = Database::open(dir.path(), 16).unwrap();
+        let mut session = db.session();
+        session.execute("CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT); INSERT INTO t VALUES (1, 'a'), (2, 'b')").unwrap();
+        let traced = |level: Level, sql: &str| {
+            let log = Arc::new(Log::new(vec![], level));
+            set_subscriber(Some(log.clone()));
+            db.session().execute(sql).unwrap();
+            set_subscriber(None);
+            String::from_utf8(Arc::try_unwrap(log).ok().unwrap().into_inner()).unwrap()
+        };
+        let output = traced(Level::INFO, "SELECT name FROM t WHERE id > 1");
+        assert_eq!("INFO beyond_rdb::sql statement command=\"SELECT\"", output.lines().next().unwrap());
+        assert_eq!(2, output.lines().count(), "{}", output);
+        let output = traced(Level::TRACE, "SELECT name FROM t WHERE id > 1");
+        assert!(output.contains("\n  DEBUG beyond_rdb::query::instrument start operator=\"Project"), "{}", output);
+        assert!(output.contains("TRACE beyond_rdb::buffer hit page_id="), "{}", output);
+        assert!(output.contains("DEBUG beyond_rdb::query::instrument done operator=\"Project"), "{}", output);
+        assert!(output.contains(" rows=1 elapsed="), "{}", output);
+        let output = traced(Level::TRACE, "INSERT INTO t VALUES (3, 'c')");
+        assert!(output.contains("TRACE beyond_rdb::wal append lsn="), "{}", output);
+        assert!(output.contains("DEBUG beyond_rdb::wal flush lsn="), "{}", output);
+        drop(session);
+        drop(db);
+        let output = traced_open(dir.path());
+        assert!(output.contains("TRACE beyond_rdb::disk read page_id="), "{}", output);
+    }
+
+    fn unevaluated() -> u8 {
+        panic!("a field of an event not wanted evaluated")
+    }
+
+    fn traced_open(dir: &std::path::Path) -> String {
+        let log = Arc::new(Log::new(vec![], Level::TRACE));
+        set_subscriber(Some(log.clone()));
+        let db = Database::open(dir, 16).unwrap();
+        db.session().execute("SELECT * FROM t").unwrap();
+        set_subscriber(None);
+        drop(db);
+        String::from_utf8(Arc::try_unwrap(log).ok().unwrap().into_inner()).unwrap()
+    }
+}
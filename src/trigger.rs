@@ -0,0 +1,96 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::tuple::Tuple;
+
+// Triggers run for each row a statement writes to their table, before it's written or after,
+// given the row images: OLD, the row as it was, of an UPDATE or a DELETE, and NEW, the row as it's
+// written, of an INSERT or an UPDATE, the other being all NULLs to a trigger of SQL. Those of a
+// partitioned table are run for the rows written to it, whichever partitions they go to. Of the
+// statements, INSERT writes rows, and updates them with ON CONFLICT DO UPDATE, UPDATE updates them
+// and DELETE deletes them, while COPY FROM, loading rows in bulk, runs no triggers. AFTER triggers
+// are run once the statement has written all its rows.
+//
+// A trigger is created either by CREATE TRIGGER, whose INSERTs refer to the row images as the
+// tables `new` and `old`, and which is kept in the catalog, or by `Database::create_trigger` with
+// a callback, which is kept in memory only, so registered again each time the database is opened.
+// The triggers of a table which are run at once are run in order of their names.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    Before,
+    After,
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Timing::Before => "BEFORE",
+            Timing::After => "AFTER",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Event::Insert => "INSERT",
+            Event::Update => "UPDATE",
+            Event::Delete => "DELETE",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggerInfo {
+    pub name: String,
+    pub table: String,
+    pub timing: Timing,
+    pub event: Event,
+    pub action: Action,
+}
+
+#[derive(Clone)]
+pub enum Action {
+    // the CREATE TRIGGER statement, parsed again each time the trigger is run
+    Sql(String),
+    Callback(Rc<CallbackBody>),
+}
+
+// Called with the row images, of which a BEFORE trigger may change NEW, to be written in place of
+// the row, and returns whether the row is to be written, which only a BEFORE trigger can prevent.
+// An error it returns fails the statement.
+pub type CallbackBody = dyn Fn(&mut RowImages) -> Result<bool, String>;
+
+impl fmt::Debug for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Sql(sql) => f.debug_tuple("Sql").field(sql).finish(),
+            Action::Callback(_) => f.write_str("Callback"),
+        }
+    }
+}
+
+// By the statements, the callbacks being the same only if they're one.
+impl PartialEq for Action {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Action::Sql(a), Action::Sql(b)) => a == b,
+            (Action::Callback(a), Action::Callback(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowImages {
+    pub old: Option<Tuple>,
+    pub new: Option<Tuple>,
+}
@@ -0,0 +1,514 @@
+use std::convert::TryInto;
+
+use crate::geometry::{Point, Rect};
+use crate::numeric::{Float, Numeric};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("malformed tuple")]
+    Malformed,
+}
+
+// A single column value.
+// The derived ordering is used wherever tuples are compared (Null sorts first).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Value {
+    Null,
+    Int(i64),
+    Text(String),
+    Bool(bool),
+    // the binary form of `json::parse`
+    Json(Vec<u8>),
+    // of elements of one scalar type, or NULL
+    Array(Vec<Value>),
+    // see `geometry`
+    Point(Point),
+    Box(Rect),
+    // see `numeric`
+    Float(Float),
+    Numeric(Numeric),
+    // see `datetime`: days since 1970-01-01, and microseconds since its midnight
+    Date(i32),
+    Timestamp(i64),
+}
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+}
+
+pub type Tuple = Vec<Value>;
+
+// Declared type of a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataType {
+    Integer,
+    Text,
+    Boolean,
+    Json,
+    // one-dimensional
+    Array(ElementType),
+    Point,
+    Box,
+    Float,
+    Numeric,
+    Date,
+    Timestamp,
+}
+
+// Type of the elements of an array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElementType {
+    Integer,
+    Text,
+    Boolean,
+}
+
+impl DataType {
+    // NULL is a valid value of every type.
+    pub fn accepts(&self, value: &Value) -> bool {
+        match (self, value) {
+            (DataType::Array(element), Value::Array(values)) => values.iter().all(|v| element.data_type().accepts(v)),
+            _ => matches!(
+                (self, value),
+                (_, Value::Null) | (DataType::Integer, Value::Int(_)) | (DataType::Text, Value::Text(_)) | (DataType::Boolean, Value::Bool(_))
+                    | (DataType::Json, Value::Json(_)) | (DataType::Point, Value::Point(_)) | (DataType::Box, Value::Box(_))
+                    | (DataType::Float, Value::Float(_)) | (DataType::Numeric, Value::Numeric(_)) | (DataType::Date, Value::Date(_))
+                    | (DataType::Timestamp, Value::Timestamp(_))
+            ),
+        }
+    }
+
+    // Type of a value, None for NULL, as for an array of nothing but NULLs.
+    pub fn of(value: &Value) -> Option<DataType> {
+        match value {
+            Value::Null => None,
+            Value::Int(_) => Some(DataType::Integer),
+            Value::Text(_) => Some(DataType::Text),
+            Value::Bool(_) => Some(DataType::Boolean),
+            Value::Json(_) => Some(DataType::Json),
+            Value::Array(values) => values.iter().find_map(DataType::of).and_then(ElementType::of).map(DataType::Array),
+            Value::Point(_) => Some(DataType::Point),
+            Value::Box(_) => Some(DataType::Box),
+            Value::Float(_) => Some(DataType::Float),
+            Value::Numeric(_) => Some(DataType::Numeric),
+            Value::Date(_) => Some(DataType::Date),
+            Value::Timestamp(_) => Some(DataType::Timestamp),
+        }
+    }
+
+    // Whether it's one of the numeric types, which are compared with each other and mixed in
+    // arithmetic (see `common`).
+    pub fn is_number(self) -> bool {
+        matches!(self, DataType::Integer | DataType::Numeric | DataType::Float)
+    }
+
+    // The type values of `self` and `other` are converted to, to be compared or combined:
+    // numbers to the one of the two which integers, then numerics, then floats are converted to,
+    // and dates to timestamps. None if they're of types which aren't.
+    pub fn common(self, other: DataType) -> Option<DataType> {
+        let rank = |data_type: DataType| match data_type {
+            DataType::Integer => Some(0),
+            DataType::Numeric => Some(1),
+            DataType::Float => Some(2),
+            _ => None,
+        };
+        match (self, other) {
+            _ if self == other => Some(self),
+            (DataType::Date, DataType::Timestamp) | (DataType::Timestamp, DataType::Date) => Some(DataType::Timestamp),
+            _ => match (rank(self)?, rank(other)?) {
+                (l, r) if l >= r => Some(self),
+                _ => Some(other),
+            },
+        }
+    }
+
+    // Type of the elements of an array type.
+    pub fn element(self) -> Option<DataType> {
+        match self {
+            DataType::Array(element) => Some(element.data_type()),
+            _ => None,
+        }
+    }
+}
+
+impl ElementType {
+    pub fn data_type(self) -> DataType {
+        match self {
+            ElementType::Integer => DataType::Integer,
+            ElementType::Text => DataType::Text,
+            ElementType::Boolean => DataType::Boolean,
+        }
+    }
+
+    // None for the types which arrays can't be of.
+    pub fn of(data_type: DataType) -> Option<ElementType> {
+        match data_type {
+            DataType::Integer => Some(ElementType::Integer),
+            DataType::Text => Some(ElementType::Text),
+            DataType::Boolean => Some(ElementType::Boolean),
+            DataType::Json | DataType::Array(_) | DataType::Point | DataType::Box => None,
+            DataType::Float | DataType::Numeric | DataType::Date | DataType::Timestamp => None,
+        }
+    }
+}
+
+const TAG_NULL: u8 = 0;
+const TAG_INT: u8 = 1;
+const TAG_TEXT: u8 = 2;
+const TAG_BOOL: u8 = 3;
+const TAG_JSON: u8 = 4;
+const TAG_ARRAY: u8 = 5;
+const TAG_POINT: u8 = 6;
+const TAG_BOX: u8 = 7;
+const TAG_FLOAT: u8 = 8;
+const TAG_NUMERIC: u8 = 9;
+const TAG_DATE: u8 = 10;
+const TAG_TIMESTAMP: u8 = 11;
+
+// Layout: [num_values: u32] followed by each value as [tag: u8][payload].
+// Int payload is 8 bytes little endian, Text payload is [len: u32][utf-8 bytes], as is Json's
+// with its bytes. Array payload is its elements laid out as a tuple. Point payload is x then y
+// as ints are, and Box's is its low then its high corner. Float payload is the 8 bytes of its bits,
+// Numeric's its 16 bytes of digits and byte of scale (see `Numeric::to_bytes`), Date's 4 bytes and
+// Timestamp's 8, little endian.
+pub fn encode(tuple: &[Value], buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&(tuple.len() as u32).to_le_bytes());
+    for value in tuple {
+        match value {
+            Value::Null => buf.push(TAG_NULL),
+            Value::Int(n) => {
+                buf.push(TAG_INT);
+                buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Text(s) => {
+                buf.push(TAG_TEXT);
+                buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                buf.extend_from_slice(s.as_bytes());
+            }
+            Value::Bool(b) => {
+                buf.push(TAG_BOOL);
+                buf.push(*b as u8);
+            }
+            Value::Json(bytes) => {
+                buf.push(TAG_JSON);
+                buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                buf.extend_from_slice(bytes);
+            }
+            Value::Array(values) => {
+                buf.push(TAG_ARRAY);
+                encode(values, buf);
+            }
+            Value::Point(point) => {
+                buf.push(TAG_POINT);
+                encode_point(point, buf, i64::to_le_bytes);
+            }
+            Value::Box(rect) => {
+                buf.push(TAG_BOX);
+                encode_point(&rect.low, buf, i64::to_le_bytes);
+                encode_point(&rect.high, buf, i64::to_le_bytes);
+            }
+            Value::Float(f) => {
+                buf.push(TAG_FLOAT);
+                buf.extend_from_slice(&f.0.to_bits().to_le_bytes());
+            }
+            Value::Numeric(n) => {
+                buf.push(TAG_NUMERIC);
+                buf.extend_from_slice(&n.to_bytes());
+            }
+            Value::Date(days) => {
+                buf.push(TAG_DATE);
+                buf.extend_from_slice(&days.to_le_bytes());
+            }
+            Value::Timestamp(micros) => {
+                buf.push(TAG_TIMESTAMP);
+                buf.extend_from_slice(&micros.to_le_bytes());
+            }
+        }
+    }
+}
+
+// Order preserving (memcomparable) encoding: comparing two encodings bytewise gives the same
+// result as comparing the values. The encoding of a prefix of `values` is a prefix of the encoding.
+// Text, and the bytes of Json, are escaped so that they can be terminated: 0x00 becomes [0x00, 0xff]
+// and the end is [0x00, 0x00]. Each element of an array follows a 0x01, and the end of them is 0x00.
+// The coordinates of points and boxes are encoded as ints are, as are dates and timestamps, and
+// floats and numerics as `Float::key` and `Numeric::key` encode them.
+pub fn encode_key(values: &[Value], buf: &mut Vec<u8>) {
+    for value in values {
+        match value {
+            Value::Null => buf.push(TAG_NULL),
+            Value::Int(n) => {
+                buf.push(TAG_INT);
+                buf.extend_from_slice(&((*n as u64) ^ (1 << 63)).to_be_bytes());
+            }
+            Value::Text(s) => {
+                buf.push(TAG_TEXT);
+                escape(s.as_bytes(), buf);
+            }
+            Value::Bool(b) => {
+                buf.push(TAG_BOOL);
+                buf.push(*b as u8);
+            }
+            Value::Json(bytes) => {
+                buf.push(TAG_JSON);
+                escape(bytes, buf);
+            }
+            Value::Array(values) => {
+                buf.push(TAG_ARRAY);
+                for value in values {
+                    buf.push(1);
+                    encode_key(std::slice::from_ref(value), buf);
+                }
+                buf.push(0);
+            }
+            Value::Point(point) => {
+                buf.push(TAG_POINT);
+                encode_point(point, buf, key_int);
+            }
+            Value::Box(rect) => {
+                buf.push(TAG_BOX);
+                encode_point(&rect.low, buf, key_int);
+                encode_point(&rect.high, buf, key_int);
+            }
+            Value::Float(f) => {
+                buf.push(TAG_FLOAT);
+                buf.extend_from_slice(&f.key());
+            }
+            Value::Numeric(n) => {
+                buf.push(TAG_NUMERIC);
+                buf.extend_from_slice(&n.key());
+            }
+            Value::Date(days) => {
+                buf.push(TAG_DATE);
+                buf.extend_from_slice(&((*days as u32) ^ (1 << 31)).to_be_bytes());
+            }
+            Value::Timestamp(micros) => {
+                buf.push(TAG_TIMESTAMP);
+                buf.extend_from_slice(&key_int(*micros));
+            }
+        }
+    }
+}
+
+fn key_int(n: i64) -> [u8; 8] {
+    ((n as u64) ^ (1 << 63)).to_be_bytes()
+}
+
+fn encode_point(point: &Point, buf: &mut Vec<u8>, int: fn(i64) -> [u8; 8]) {
+    buf.extend_from_slice(&int(point.x));
+    buf.extend_from_slice(&int(point.y));
+}
+
+fn escape(bytes: &[u8], buf: &mut Vec<u8>) {
+    for &b in bytes {
+        buf.push(b);
+        if b == 0 {
+            buf.push(0xff);
+        }
+    }
+    buf.extend_from_slice(&[0, 0]);
+}
+
+// Decodes the values of an `encode_key` encoding, for tools reading keys off pages.
+pub fn decode_key(bytes: &[u8]) -> Result<Tuple, Error> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let mut values = vec![];
+    while reader.pos < bytes.len() {
+        values.push(decode_key_value(&mut reader)?);
+    }
+    Ok(values)
+}
+
+fn decode_key_value(reader: &mut Reader) -> Result<Value, Error> {
+    Ok(match reader.u8()? {
+        TAG_NULL => Value::Null,
+        TAG_INT => Value::Int((u64::from_be_bytes(reader.take(8)?.try_into().unwrap()) ^ (1 << 63)) as i64),
+        TAG_TEXT => Value::Text(String::from_utf8(unescape(reader)?).map_err(|_| Error::Malformed)?),
+        TAG_BOOL => Value::Bool(reader.u8()? != 0),
+        TAG_JSON => Value::Json(unescape(reader)?),
+        TAG_ARRAY => {
+            let mut values = vec![];
+            while reader.u8()? != 0 {
+                values.push(decode_key_value(reader)?);
+            }
+            Value::Array(values)
+        }
+        TAG_POINT => Value::Point(reader.point(key_int_of)?),
+        TAG_BOX => Value::Box(Rect { low: reader.point(key_int_of)?, high: reader.point(key_int_of)? }),
+        TAG_FLOAT => Value::Float(Float::from_key(reader.array()?)),
+        TAG_NUMERIC => Value::Numeric(Numeric::from_key(reader.array()?).map_err(|_| Error::Malformed)?),
+        TAG_DATE => Value::Date((u32::from_be_bytes(reader.array()?) ^ (1 << 31)) as i32),
+        TAG_TIMESTAMP => Value::Timestamp(key_int_of(reader.array()?)),
+        _ => return Err(Error::Malformed),
+    })
+}
+
+fn key_int_of(bytes: [u8; 8]) -> i64 {
+    (u64::from_be_bytes(bytes) ^ (1 << 63)) as i64
+}
+
+fn unescape(reader: &mut Reader) -> Result<Vec<u8>, Error> {
+    let mut bytes = vec![];
+    loop {
+        match reader.u8()? {
+            0 if reader.u8()? == 0 => break,
+            0 => bytes.push(0),
+            b => bytes.push(b),
+        }
+    }
+    Ok(bytes)
+}
+
+// Decodes one tuple from the head of `bytes` and returns it with the number of bytes consumed.
+pub fn decode(bytes: &[u8]) -> Result<(Tuple, usize), Error> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let num_values = reader.u32()? as usize;
+    // each value takes a byte at least, so a malformed count doesn't allocate more than that
+    let mut tuple = Vec::with_capacity(num_values.min(bytes.len()));
+    for _ in 0..num_values {
+        let value = match reader.u8()? {
+            TAG_NULL => Value::Null,
+            TAG_INT => Value::Int(i64::from_le_bytes(reader.take(8)?.try_into().unwrap())),
+            TAG_TEXT => {
+                let len = reader.u32()? as usize;
+                let s = std::str::from_utf8(reader.take(len)?).map_err(|_| Error::Malformed)?;
+                Value::Text(s.to_string())
+            }
+            TAG_BOOL => Value::Bool(reader.u8()? != 0),
+            TAG_JSON => {
+                let len = reader.u32()? as usize;
+                Value::Json(reader.take(len)?.to_vec())
+            }
+            TAG_ARRAY => {
+                let (values, len) = decode(&bytes[reader.pos..])?;
+                reader.pos += len;
+                Value::Array(values)
+            }
+            TAG_POINT => Value::Point(reader.point(i64::from_le_bytes)?),
+            TAG_BOX => Value::Box(Rect { low: reader.point(i64::from_le_bytes)?, high: reader.point(i64::from_le_bytes)? }),
+            TAG_FLOAT => Value::Float(Float(f64::from_bits(u64::from_le_bytes(reader.array()?)))),
+            TAG_NUMERIC => Value::Numeric(Numeric::from_bytes(reader.array()?).map_err(|_| Error::Malformed)?),
+            TAG_DATE => Value::Date(i32::from_le_bytes(reader.array()?)),
+            TAG_TIMESTAMP => Value::Timestamp(i64::from_le_bytes(reader.array()?)),
+            _ => return Err(Error::Malformed),
+        };
+        tuple.push(value);
+    }
+    Ok((tuple, reader.pos))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self.pos.checked_add(len).ok_or(Error::Malformed)?;
+        let slice = self.bytes.get(self.pos..end).ok_or(Error::Malformed)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn point(&mut self, int: fn([u8; 8]) -> i64) -> Result<Point, Error> {
+        let x = int(self.take(8)?.try_into().unwrap());
+        Ok(Point { x, y: int(self.take(8)?.try_into().unwrap()) })
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let tuple = vec![Value::Int(-42), Value::Null, Value::Text("hello".to_string()), Value::Bool(true), Value::Json(vec![3, 0, 1])];
+        let rect = Rect { low: Point { x: -1, y: 2 }, high: Point { x: 3, y: 4 } };
+        let tuple = [tuple.clone(), vec![Value::Array(vec![Value::Int(1), Value::Null]), Value::Array(tuple), Value::Point(Point { x: i64::MIN, y: 5 }), Value::Box(rect)]].concat();
+        let numeric = |text: &str| Value::Numeric(Numeric::parse(text).unwrap());
+        let tuple = [tuple, vec![Value::Float(Float(-2.5)), numeric("1.50"), Value::Date(-1), Value::Timestamp(i64::MIN)]].concat();
+        let mut buf = vec![];
+        encode(&tuple, &mut buf);
+        encode(&[], &mut buf);
+        let (decoded, len) = decode(&buf).unwrap();
+        assert_eq!(tuple, decoded);
+        let (empty, _) = decode(&buf[len..]).unwrap();
+        assert!(empty.is_empty());
+        assert!(decode(&buf[..len - 1]).is_err());
+
+        let mut values = vec![
+            vec![Value::Null],
+            vec![Value::Int(i64::MIN)],
+            vec![Value::Int(-1)],
+            vec![Value::Int(0), Value::Text("b".to_string())],
+            vec![Value::Int(0), Value::Text("b\0".to_string())],
+            vec![Value::Int(0), Value::Text("ba".to_string())],
+            vec![Value::Int(7)],
+            vec![Value::Text("".to_string())],
+            vec![Value::Text("a".to_string())],
+            vec![Value::Bool(false)],
+            vec![Value::Bool(true)],
+            vec![Value::Json(vec![0])],
+            vec![Value::Json(vec![3, 0, 1])],
+            vec![Value::Json(vec![3, 1])],
+            vec![Value::Array(vec![])],
+            vec![Value::Array(vec![Value::Null])],
+            vec![Value::Array(vec![Value::Int(1)])],
+            vec![Value::Array(vec![Value::Int(1), Value::Int(0)])],
+            vec![Value::Array(vec![Value::Int(2)]), Value::Int(0)],
+            vec![Value::Array(vec![Value::Text("a".to_string())])],
+            vec![Value::Point(Point { x: -1, y: 7 })],
+            vec![Value::Point(Point { x: 0, y: -7 })],
+            vec![Value::Box(Rect { low: Point { x: 0, y: 0 }, high: Point { x: 1, y: 1 } })],
+            vec![Value::Box(Rect { low: Point { x: 0, y: 1 }, high: Point { x: 0, y: 1 } })],
+            vec![Value::Float(Float(f64::NEG_INFINITY))],
+            vec![Value::Float(Float(-0.5))],
+            vec![Value::Float(Float(0.0)), Value::Int(1)],
+            vec![Value::Float(Float(f64::NAN))],
+            vec![numeric("-1.5")],
+            vec![numeric("0.001")],
+            vec![numeric("2"), Value::Int(0)],
+            vec![Value::Date(-1)],
+            vec![Value::Date(19782)],
+            vec![Value::Timestamp(-1)],
+            vec![Value::Timestamp(0)],
+        ];
+        let key = |values: &Tuple| {
+            let mut buf = vec![];
+            encode_key(values, &mut buf);
+            buf
+        };
+        values.reverse();
+        let mut keys: Vec<_> = values.iter().map(key).collect();
+        keys.sort();
+        values.sort();
+        assert_eq!(values.iter().map(key).collect::<Vec<_>>(), keys);
+        for values in &values {
+            assert_eq!(values, &decode_key(&key(values)).unwrap());
+        }
+        let mut tuple = key(&values.concat());
+        assert_eq!(values.concat(), decode_key(&tuple).unwrap());
+        tuple.pop();
+        assert!(decode_key(&tuple).is_err());
+
+        let array = Value::Array(vec![Value::Int(1), Value::Null]);
+        assert!(DataType::Array(ElementType::Integer).accepts(&array));
+        assert!(!DataType::Array(ElementType::Text).accepts(&array));
+        assert_eq!(Some(DataType::Array(ElementType::Integer)), DataType::of(&array));
+        assert_eq!(None, DataType::of(&Value::Array(vec![Value::Null])));
+    }
+}
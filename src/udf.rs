@@ -0,0 +1,114 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::catalog::Column;
+use crate::tuple::{DataType, Tuple, Value};
+
+// Functions an embedder registers as SQL functions (see `Database::create_function` and
+// `Database::create_aggregate`) and table functions to read from in FROM (see
+// `Database::create_table_function`), kept in the catalog in memory only, so registered again each time
+// the database is opened. Those of the names of built-in functions are never called, those being
+// found first.
+
+// A scalar function, called with the values of its arguments cast to the types it's declared
+// with, NULLs as they are. What it returns must be of type `returns` or NULL, and an error it
+// returns fails the statement.
+pub struct ScalarFunction {
+    pub name: String,
+    pub args: Vec<DataType>,
+    pub returns: DataType,
+    pub func: Box<ScalarBody>,
+}
+
+pub type ScalarBody = dyn Fn(&[Value]) -> Result<Value, String>;
+
+impl fmt::Debug for ScalarFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScalarFunction").field("name", &self.name).field("args", &self.args).field("returns", &self.returns).finish()
+    }
+}
+
+// An aggregate function of one argument, computed as a state which starts as `init` and which
+// `step` is given with each value of the argument in the group, cast to type `arg`, to return the
+// next state of. NULLs are skipped, and so are duplicates with DISTINCT. `finalize` returns the
+// result, of type `returns` or NULL, of the last state. Parallel workers aggregate groups of their
+// own, so the closures are Send and Sync.
+pub struct AggregateFunction {
+    pub name: String,
+    pub arg: DataType,
+    pub returns: DataType,
+    pub init: Value,
+    pub step: Box<StepBody>,
+    pub finalize: Box<FinalizeBody>,
+}
+
+pub type StepBody = dyn Fn(Value, &Value) -> Result<Value, String> + Send + Sync;
+pub type FinalizeBody = dyn Fn(Value) -> Result<Value, String> + Send + Sync;
+
+impl fmt::Debug for AggregateFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AggregateFunction").field("name", &self.name).field("arg", &self.arg).field("returns", &self.returns).finish()
+    }
+}
+
+// By name, there being one of a name at a time.
+impl PartialEq for AggregateFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for AggregateFunction {}
+
+// A function of the rows of a table, `FROM name(args) [AS] alias [(columns)]`, called each time
+// it's scanned with the values of its arguments cast as those of a scalar function are. The
+// arguments after the first `required` may be left out, and aren't passed then. The rows it
+// returns must be of `columns`.
+pub struct TableFunction {
+    pub name: String,
+    pub args: Vec<DataType>,
+    pub required: usize,
+    pub columns: Vec<Column>,
+    pub func: Box<TableBody>,
+}
+
+pub type TableBody = dyn Fn(&[Value]) -> Result<Vec<Tuple>, String>;
+
+impl fmt::Debug for TableFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TableFunction").field("name", &self.name).field("args", &self.args).field("columns", &self.columns).finish()
+    }
+}
+
+// generate_series(start, stop[, step]), the integers from `start` to `stop` by `step`, 1 if left
+// out, none if any is NULL.
+pub fn builtin_table_function(name: &str) -> Option<Rc<TableFunction>> {
+    match name {
+        "generate_series" => Some(Rc::new(TableFunction {
+            name: name.to_string(),
+            args: vec![DataType::Integer; 3],
+            required: 2,
+            columns: vec![Column { name: name.to_string(), data_type: DataType::Integer }],
+            func: Box::new(generate_series),
+        })),
+        _ => None,
+    }
+}
+
+fn generate_series(args: &[Value]) -> Result<Vec<Tuple>, String> {
+    let (start, stop, step) = match args {
+        [Value::Int(start), Value::Int(stop)] => (*start, *stop, 1),
+        [Value::Int(start), Value::Int(stop), Value::Int(step)] => (*start, *stop, *step),
+        _ => return Ok(vec![]),
+    };
+    if step == 0 {
+        return Err("step size cannot be zero".to_string());
+    }
+    let mut rows = vec![];
+    let mut n = Some(start);
+    while let Some(i) = n.filter(|&i| if step > 0 { i <= stop } else { i >= stop }) {
+        rows.push(vec![Value::Int(i)]);
+        n = i.checked_add(step);
+    }
+    Ok(rows)
+}
@@ -0,0 +1,1076 @@
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::disk::PageId;
+use crate::lz4;
+use crate::storage::{self, FileSystem, StorageBackend, StorageFile};
+use crate::trace;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("malformed log record at {0}")]
+    Malformed(Lsn),
+    #[error("{0}")]
+    Config(String),
+    #[error("log record is larger than a segment")]
+    RecordTooLarge,
+}
+
+// Position of a record in the log: the start of its segment plus its offset in the file.
+pub type Lsn = u64;
+pub type TxId = u64;
+
+pub const DEFAULT_SEGMENT_SIZE: u64 = 16 * 1024 * 1024;
+// Big enough for a record of two page images.
+pub const MIN_SEGMENT_SIZE: u64 = 16 * 1024;
+// Recycled segments kept for reuse; any more are removed.
+const MAX_SPARE_SEGMENTS: usize = 4;
+// Each segment file starts with this, so no record is at LSN 0, which is the LSN of pages which
+// have never been written through the log.
+const MAGIC: &[u8; 8] = b"BRDBWAL4";
+// Holds the control data, `[checkpoint: u64][logical: u8]`: the LSN of the latest complete
+// checkpoint, 0 if there's none yet, and whether row changes are logged for logical decoding.
+// Written by earlier versions without the flag.
+const CONTROL_FILE: &str = "checkpoint";
+// Holds a `[name] [lsn]` line for each slot.
+const SLOTS_FILE: &str = "slots";
+// [len: u32][checksum: u32] followed by `len` bytes of body
+const FRAME_HEADER_SIZE: usize = 8;
+// Bodies are at most two page images and a few integers, or a checkpoint listing the transactions
+// and dirty pages, so anything much longer is garbage.
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+// Encoded as the previous LSN of the first record of a transaction.
+const NO_LSN: u64 = u64::MAX;
+// Changes shorter than this aren't worth compressing.
+const COMPRESS_MIN: usize = 64;
+
+// Body layouts (all integers little endian), after [kind: u8][txid: u64][prev_lsn: u64]:
+//   update:         [page_id: u64][offset: u16][len: u16][change]
+//   compensation:   [page_id: u64][offset: u16][len: u16][change][undo_next: u64]
+//   redo:           [page_id: u64][offset: u16][len: u16][change]
+//   page image:     as redo, at offset 0
+//   where change:   [compressed: u8][data_len: u16][data], data being [before][after XOR before]
+//                   for an update, zero where unchanged, and [after] for the others, LZ4 compressed
+//                   if that makes it smaller
+//   change:         [table: u64][present: u8] then if present the relation, [num_columns: u32]
+//                   and [len: u32][name] for the table's name and each column's, then
+//                   [present: u8][len: u32][row] for each of old and new
+//   commit:         [time: u64] in microseconds since the Unix epoch
+//   vacuum:         [horizon: u64]
+//   atomic end:     [undo_next: u64]
+//   checkpoint end: [next_txid: u64][num_transactions: u32]([txid: u64][last_lsn: u64])*
+//                   [num_dirty_pages: u32]([page_id: u64][rec_lsn: u64])*
+//                   [num_aborted: u32]([txid: u64])*
+//   the others:     nothing
+const BEGIN: u8 = 0;
+const UPDATE: u8 = 1;
+const COMPENSATION: u8 = 2;
+const COMMIT: u8 = 3;
+const ABORT: u8 = 4;
+const END: u8 = 5;
+const CHECKPOINT_BEGIN: u8 = 6;
+const CHECKPOINT_END: u8 = 7;
+const REDO: u8 = 8;
+const CHANGE: u8 = 9;
+const VACUUM: u8 = 10;
+const TRUNCATE: u8 = 11;
+const PAGE_IMAGE: u8 = 12;
+const ATOMIC_END: u8 = 13;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    Begin,
+    // bytes `offset..offset + before.len()` of the page were changed from `before` to `after`
+    Update {
+        page_id: PageId,
+        offset: u16,
+        before: Vec<u8>,
+        after: Vec<u8>,
+    },
+    // an update undone while rolling back, which is redone but never undone itself; the rollback
+    // continues from `undo_next`, the record before the undone one
+    Compensation {
+        page_id: PageId,
+        offset: u16,
+        after: Vec<u8>,
+        undo_next: Option<Lsn>,
+    },
+    // an update which is only redone, e.g. a page of a logged in-memory table logged whole by a
+    // checkpoint
+    Redo {
+        page_id: PageId,
+        offset: u16,
+        after: Vec<u8>,
+    },
+    // the body of the page as it was before its first change since the latest checkpoint, which
+    // is redone whatever the page has, so that a write of it torn by a crash is made whole again
+    PageImage {
+        page_id: PageId,
+        image: Vec<u8>,
+    },
+    // the updates of the transaction since `undo_next` are one change, e.g. a split of a tree
+    // node, which is redone but never rolled back from here on, the rollback going on from
+    // `undo_next`; one cut short, e.g. by a crash, is rolled back as any update is
+    AtomicEnd {
+        undo_next: Option<Lsn>,
+    },
+    // a row of the table with meta page `table` changed from `old` to `new`, each an encoded tuple
+    // or None if there isn't one, logged on top of the page changes for logical decoding, with the
+    // name and columns of the table then if it has them
+    Change {
+        table: PageId,
+        relation: Option<Relation>,
+        old: Option<Vec<u8>>,
+        new: Option<Vec<u8>>,
+    },
+    // `time` is when the transaction committed, by which point-in-time recovery can stop
+    Commit {
+        time: SystemTime,
+    },
+    Abort,
+    // the transaction has been committed or rolled back entirely
+    End,
+    // A fuzzy checkpoint: the tables of the end record were taken while the changes between the
+    // two records were being logged, so recovery reads from the begin record. Not of any transaction.
+    CheckpointBegin,
+    CheckpointEnd(Checkpoint),
+    // vacuum is about to remove the row versions which no snapshot whose transactions before
+    // `horizon` have all ended sees, which replicas check their readers against. Not of any
+    // transaction.
+    Vacuum {
+        horizon: TxId,
+    },
+    // the data file was cut to its first `num_pages` pages, those after being of no tree and
+    // allocated anew from then on. Not of any transaction.
+    Truncate {
+        num_pages: u64,
+    },
+}
+
+// The name and columns of a table as of a change to it, logged with the change for it to be
+// decoded as it was made, whatever has become of the table since.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relation {
+    pub name: String,
+    pub columns: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Checkpoint {
+    pub next_txid: TxId,
+    // (id, latest LSN) of the running transactions
+    pub transactions: Vec<(TxId, Lsn)>,
+    // (page, LSN of the first change since it was last written) of the dirty pages
+    pub dirty_pages: Vec<(PageId, Lsn)>,
+    // the transactions which have aborted, whose row versions are invisible
+    pub aborted: Vec<TxId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    pub lsn: Lsn,
+    pub txid: TxId,
+    // the previous record of the same transaction
+    pub prev_lsn: Option<Lsn>,
+    pub record: Record,
+}
+
+impl Record {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Record::Begin => "BEGIN",
+            Record::Update { .. } => "UPDATE",
+            Record::Compensation { .. } => "COMPENSATION",
+            Record::Redo { .. } => "REDO",
+            Record::PageImage { .. } => "PAGE IMAGE",
+            Record::AtomicEnd { .. } => "ATOMIC END",
+            Record::Change { .. } => "CHANGE",
+            Record::Commit { .. } => "COMMIT",
+            Record::Abort => "ABORT",
+            Record::End => "END",
+            Record::CheckpointBegin => "CHECKPOINT BEGIN",
+            Record::CheckpointEnd(_) => "CHECKPOINT END",
+            Record::Vacuum { .. } => "VACUUM",
+            Record::Truncate { .. } => "TRUNCATE",
+        }
+    }
+
+    // The page changed by the record, if it changes one.
+    pub fn page_id(&self) -> Option<PageId> {
+        match self {
+            Record::Update { page_id, .. } | Record::Compensation { page_id, .. } | Record::Redo { page_id, .. } | Record::PageImage { page_id, .. } => Some(*page_id),
+            _ => None,
+        }
+    }
+}
+
+impl LogRecord {
+    // The body of the frame the record is logged in, e.g. to send it elsewhere.
+    pub fn encode(&self) -> Vec<u8> {
+        encode(self.txid, self.prev_lsn, &self.record)
+    }
+
+    pub fn decode(lsn: Lsn, body: &[u8]) -> Result<Self, Error> {
+        decode(lsn, body)
+    }
+
+    // The frame, header and body, as it's written to a segment at the record's LSN.
+    pub fn encode_frame(&self) -> Vec<u8> {
+        let mut frame = vec![];
+        write_frame(&mut frame, self.lsn, &self.encode());
+        frame
+    }
+
+    // Decodes the frame at the head of `bytes`, read from a segment at `lsn`, returning the
+    // record and the LSN after it.
+    pub fn decode_frame(lsn: Lsn, bytes: &[u8]) -> Result<(Self, Lsn), Error> {
+        let header = bytes.get(..FRAME_HEADER_SIZE).ok_or(Error::Malformed(lsn))?;
+        let (body, next) = parse_frame(lsn, header, bytes.get(FRAME_HEADER_SIZE..))?;
+        Ok((decode(lsn, body)?, next))
+    }
+}
+
+// Called with the first LSN and the path of each segment once it's full, e.g. to copy it
+// elsewhere for point-in-time recovery. A segment isn't recycled until this has succeeded.
+pub type Archiver = Box<dyn FnMut(Lsn, &Path) -> io::Result<()>>;
+
+// An append-only log of records, in a directory of segment files of `segment_size` bytes, each
+// named after the LSN at its start. A record which doesn't fit in the rest of a segment goes to
+// the next one, the rest being zeroed. Appended records are buffered until flushed.
+//
+// Segments no longer needed by recovery are recycled: they're renamed to come after the last one
+// and overwritten when the log gets there, so that the files don't have to be allocated again.
+pub struct Wal {
+    storage: Rc<dyn StorageBackend>,
+    dir: PathBuf,
+    segment_size: u64,
+    // start of the oldest segment
+    first: Lsn,
+    // starts of the recycled segments which are yet to be reused, all after the last segment
+    spares: Vec<Lsn>,
+    // the segment being appended to and one being read from, which may be the same
+    current: Option<(Lsn, Box<dyn StorageFile>)>,
+    reading: Option<(Lsn, Box<dyn StorageFile>)>,
+    // records at and after `flushed` which are only in `tail` so far, segment headers and
+    // padding included
+    flushed: Lsn,
+    tail: Vec<u8>,
+    archiver: Option<Archiver>,
+    // start of the first segment which hasn't been archived
+    archived: Lsn,
+    // the first LSN which has to be kept for each consumer of the log, e.g. a replica
+    slots: BTreeMap<String, Lsn>,
+}
+
+impl Wal {
+    // Opens the log in `dir`, creating it if needed and ignoring a torn record left at its end by a crash.
+    pub fn open(dir: impl AsRef<Path>, segment_size: u64) -> Result<Self, Error> {
+        Self::open_in(Rc::new(FileSystem), dir, segment_size)
+    }
+
+    pub fn open_in(storage: Rc<dyn StorageBackend>, dir: impl AsRef<Path>, segment_size: u64) -> Result<Self, Error> {
+        if segment_size < MIN_SEGMENT_SIZE {
+            return Err(Error::Config(format!("segments must be at least {} bytes", MIN_SEGMENT_SIZE)));
+        }
+        let dir = dir.as_ref().to_path_buf();
+        storage.create_dir_all(&dir)?;
+        let mut starts = vec![];
+        for (name, len) in storage.list(&dir)? {
+            let Some(start) = name.strip_suffix(".wal") else {
+                continue;
+            };
+            let start = Lsn::from_str_radix(start, 16).map_err(|_| Error::Malformed(0))?;
+            if !start.is_multiple_of(segment_size) || len != segment_size {
+                return Err(Error::Config(format!("{:?} is not a segment of {} bytes", name, segment_size)));
+            }
+            starts.push(start);
+        }
+        starts.sort_unstable();
+        let first = starts.first().copied().unwrap_or(0);
+        let mut wal = Self {
+            storage,
+            dir,
+            segment_size,
+            first,
+            spares: vec![],
+            current: None,
+            reading: None,
+            // until the end is found, everything is read from the files
+            flushed: Lsn::MAX,
+            tail: vec![],
+            archiver: None,
+            archived: first,
+            slots: BTreeMap::new(),
+        };
+        match wal.storage.read(&wal.dir.join(SLOTS_FILE)) {
+            Ok(slots) => {
+                for line in String::from_utf8(slots).map_err(|_| Error::Malformed(0))?.lines() {
+                    let (name, lsn) = line.split_once(' ').ok_or(Error::Malformed(0))?;
+                    wal.slots.insert(name.to_string(), lsn.parse().map_err(|_| Error::Malformed(0))?);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        let mut lsn = match wal.last_checkpoint()? {
+            Some(lsn) if lsn >= first => lsn,
+            _ => first + MAGIC.len() as u64,
+        };
+        if starts.is_empty() {
+            wal.flushed = 0;
+            wal.tail.extend_from_slice(MAGIC);
+            wal.flush_all()?;
+            return Ok(wal);
+        }
+        while let Some((_, next)) = wal.read_file(lsn)? {
+            lsn = next;
+        }
+        // anything after the end, e.g. records torn by a crash during a flush, is overwritten later
+        wal.flushed = lsn;
+        let last = wal.segment_start(lsn);
+        wal.spares = starts.into_iter().filter(|&start| start > last).collect();
+        Ok(wal)
+    }
+
+    pub fn set_archiver(&mut self, archiver: Archiver) {
+        self.archiver = Some(archiver);
+    }
+
+    fn segment_start(&self, lsn: Lsn) -> Lsn {
+        lsn - lsn % self.segment_size
+    }
+
+    // LSN of the first record which hasn't been recycled.
+    pub fn start(&self) -> Lsn {
+        self.first + MAGIC.len() as u64
+    }
+
+    // LSN after the last appended record.
+    pub fn end(&self) -> Lsn {
+        self.flushed + self.tail.len() as u64
+    }
+
+    // LSN before which every record is durable.
+    pub fn flushed(&self) -> Lsn {
+        self.flushed
+    }
+
+    // Start of each segment holding records, in order.
+    pub fn segments(&self) -> Vec<Lsn> {
+        let last = self.segment_start(self.end() - 1);
+        (self.first..=last).step_by(self.segment_size as usize).collect()
+    }
+
+    pub fn segment_size(&self) -> u64 {
+        self.segment_size
+    }
+
+    pub fn append(&mut self, txid: TxId, prev_lsn: Option<Lsn>, record: &Record) -> Result<Lsn, Error> {
+        let body = encode(txid, prev_lsn, record);
+        let frame_len = (FRAME_HEADER_SIZE + body.len()) as u64;
+        if frame_len > self.segment_size - MAGIC.len() as u64 {
+            return Err(Error::RecordTooLarge);
+        }
+        let mut lsn = self.end();
+        let offset = lsn % self.segment_size;
+        if offset + frame_len > self.segment_size {
+            self.tail.resize(self.tail.len() + (self.segment_size - offset) as usize, 0);
+            lsn = self.end();
+        }
+        if lsn.is_multiple_of(self.segment_size) {
+            self.tail.extend_from_slice(MAGIC);
+            lsn = self.end();
+        }
+        write_frame(&mut self.tail, lsn, &body);
+        trace::event!(Trace, "append", lsn = lsn, txid = txid, len = body.len());
+        Ok(lsn)
+    }
+
+    // Appends `record` of the log this is a copy of, e.g. on a replica, at the LSN it has there,
+    // `next` being the LSN after it. Those before the end have been copied already and are skipped.
+    pub fn copy(&mut self, record: &LogRecord, next: Lsn) -> Result<(), Error> {
+        let mut pos = self.end();
+        if record.lsn < pos {
+            return Ok(());
+        }
+        // the padding and segment headers in between, as the other log has them
+        while pos < record.lsn {
+            if pos.is_multiple_of(self.segment_size) {
+                self.tail.extend_from_slice(MAGIC);
+            } else {
+                let len = record.lsn.min(self.segment_start(pos) + self.segment_size) - pos;
+                self.tail.resize(self.tail.len() + len as usize, 0);
+            }
+            pos = self.end();
+        }
+        write_frame(&mut self.tail, record.lsn, &record.encode());
+        if self.end() != next {
+            return Err(Error::Malformed(record.lsn));
+        }
+        Ok(())
+    }
+
+    // The contents of the segment starting at `start`, e.g. to copy it elsewhere.
+    pub fn read_segment(&self, start: Lsn) -> Result<Vec<u8>, Error> {
+        Ok(self.storage.read(&segment_path(&self.dir, start))?)
+    }
+
+    // Makes every record before `lsn` durable, along with all records in the same write, then
+    // archives the segments which are full.
+    pub fn flush(&mut self, lsn: Lsn) -> Result<(), Error> {
+        if lsn > self.flushed && !self.tail.is_empty() {
+            let _span = trace::span!(Debug, "flush", lsn = self.flushed, len = self.tail.len());
+            let tail = std::mem::take(&mut self.tail);
+            let mut written = 0;
+            while written < tail.len() {
+                let pos = self.flushed + written as u64;
+                let start = self.segment_start(pos);
+                let len = (tail.len() - written).min((start + self.segment_size - pos) as usize);
+                let file = self.segment_file(start)?;
+                file.write_at(pos - start, &tail[written..written + len])?;
+                file.sync()?;
+                written += len;
+            }
+            self.flushed += tail.len() as u64;
+        }
+        self.archive()
+    }
+
+    // Passes the segments which have been filled since the last call to the archiver.
+    fn archive(&mut self) -> Result<(), Error> {
+        while self.archived + self.segment_size <= self.flushed {
+            if let Some(archiver) = &mut self.archiver {
+                archiver(self.archived, &segment_path(&self.dir, self.archived))?;
+            }
+            self.archived += self.segment_size;
+        }
+        Ok(())
+    }
+
+    pub fn flush_all(&mut self) -> Result<(), Error> {
+        self.flush(self.end())
+    }
+
+    // The file of the segment starting at `start`, which is made out of a spare one if it's new.
+    fn segment_file(&mut self, start: Lsn) -> Result<&mut dyn StorageFile, Error> {
+        if self.current.as_ref().is_none_or(|(s, _)| *s != start) {
+            let path = segment_path(&self.dir, start);
+            if let Some(i) = self.spares.iter().position(|&s| s == start) {
+                self.spares.remove(i);
+            } else if !self.storage.exists(&path) {
+                match self.spares.pop() {
+                    Some(spare) => self.storage.rename(&segment_path(&self.dir, spare), &path)?,
+                    None => {
+                        let mut file = self.storage.open(&path, true)?;
+                        file.set_len(self.segment_size)?;
+                        file.sync()?;
+                    }
+                }
+                self.storage.sync_dir(&self.dir)?;
+            }
+            self.current = Some((start, self.storage.open(&path, false)?));
+        }
+        Ok(&mut *self.current.as_mut().unwrap().1)
+    }
+
+    // Reads the record at `lsn` along with the LSN after it. None at the end of the log.
+    pub fn read(&mut self, lsn: Lsn) -> Result<Option<(LogRecord, Lsn)>, Error> {
+        if lsn < self.flushed {
+            return match self.read_file(lsn)? {
+                Some(found) => Ok(Some(found)),
+                None => Err(Error::Malformed(lsn)),
+            };
+        }
+        let mut pos = (lsn - self.flushed) as usize;
+        loop {
+            let lsn = self.flushed + pos as u64;
+            let Some(header) = self.tail.get(pos..pos + FRAME_HEADER_SIZE) else {
+                return Ok(None);
+            };
+            let in_segment = lsn % self.segment_size;
+            if in_segment == 0 {
+                pos += MAGIC.len();
+                continue;
+            }
+            let len = u32::from_le_bytes(header[0..4].try_into().unwrap());
+            if len == 0 || in_segment + FRAME_HEADER_SIZE as u64 > self.segment_size {
+                // padding
+                pos += (self.segment_size - in_segment) as usize;
+                continue;
+            }
+            let (body, next) = parse_frame(lsn, header, self.tail.get(pos + FRAME_HEADER_SIZE..))?;
+            return Ok(Some((decode(lsn, body)?, next)));
+        }
+    }
+
+    // Like `read`, but from the segment files; a torn record is the end of the log.
+    fn read_file(&mut self, mut lsn: Lsn) -> Result<Option<(LogRecord, Lsn)>, Error> {
+        loop {
+            let start = self.segment_start(lsn);
+            let in_segment = lsn - start;
+            if in_segment < MAGIC.len() as u64 {
+                lsn = start + MAGIC.len() as u64;
+                continue;
+            }
+            if self.reading.as_ref().is_none_or(|(s, _)| *s != start) {
+                match self.storage.open(&segment_path(&self.dir, start), false) {
+                    Ok(file) => self.reading = Some((start, file)),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            let file = &mut *self.reading.as_mut().unwrap().1;
+            let mut header = [0; FRAME_HEADER_SIZE];
+            if in_segment + FRAME_HEADER_SIZE as u64 <= self.segment_size {
+                storage::read_exact_at(file, in_segment, &mut header)?;
+            }
+            let len = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+            if len == 0 {
+                // padding, or the end of the log if the next segment doesn't go on from here
+                lsn = start + self.segment_size;
+                continue;
+            }
+            if len > MAX_BODY_SIZE || in_segment + (FRAME_HEADER_SIZE + len) as u64 > self.segment_size {
+                return Ok(None);
+            }
+            let mut body = vec![0; len];
+            storage::read_exact_at(file, in_segment + FRAME_HEADER_SIZE as u64, &mut body)?;
+            return match parse_frame(lsn, &header, Some(&body)) {
+                Ok((body, next)) => Ok(Some((decode(lsn, body)?, next))),
+                Err(_) => Ok(None),
+            };
+        }
+    }
+
+    // Recycles the segments which only hold records before `lsn` and have been archived.
+    pub fn remove_before(&mut self, lsn: Lsn) -> Result<(), Error> {
+        self.archive()?;
+        let lsn = self.slots.values().copied().fold(lsn, Lsn::min);
+        while self.first + self.segment_size <= self.segment_start(lsn).min(self.archived) {
+            let path = segment_path(&self.dir, self.first);
+            if self.spares.len() < MAX_SPARE_SEGMENTS {
+                // named after a segment beyond all the others
+                let last = self.spares.iter().copied().chain([self.segment_start(self.end())]).max().unwrap();
+                let spare = last + self.segment_size;
+                self.storage.rename(&path, &segment_path(&self.dir, spare))?;
+                self.spares.push(spare);
+            } else {
+                self.storage.remove_file(&path)?;
+            }
+            if self.reading.as_ref().is_some_and(|(s, _)| *s == self.first) {
+                self.reading = None;
+            }
+            self.first += self.segment_size;
+        }
+        Ok(())
+    }
+
+    // LSN of the begin record of the latest complete checkpoint.
+    pub fn last_checkpoint(&self) -> Result<Option<Lsn>, Error> {
+        Ok(read_control(&*self.storage, &self.dir)?.0)
+    }
+
+    // Keeps the records from `lsn` on from being removed for the slot `name`, which has no spaces
+    // or newlines, or drops the slot if None. Slots last across restarts.
+    pub fn set_slot(&mut self, name: &str, lsn: Option<Lsn>) -> Result<(), Error> {
+        if name.is_empty() || name.contains([' ', '\n']) {
+            return Err(Error::Config(format!("invalid slot name {:?}", name)));
+        }
+        let old = match lsn {
+            Some(lsn) => self.slots.insert(name.to_string(), lsn),
+            None => self.slots.remove(name),
+        };
+        if old == lsn {
+            return Ok(());
+        }
+        let slots: String = self.slots.iter().map(|(name, lsn)| format!("{} {}\n", name, lsn)).collect();
+        self.storage.replace(&self.dir.join(SLOTS_FILE), slots.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn slot(&self, name: &str) -> Option<Lsn> {
+        self.slots.get(name).copied()
+    }
+
+    // Records the checkpoint begun at `lsn` as the latest one, once its end record is durable.
+    pub fn set_last_checkpoint(&mut self, lsn: Lsn) -> Result<(), Error> {
+        let (_, logical) = read_control(&*self.storage, &self.dir)?;
+        write_control(&*self.storage, &self.dir, Some(lsn), logical)
+    }
+
+    // Whether row changes are logged for logical decoding, as last set.
+    pub fn logical(&self) -> Result<bool, Error> {
+        Ok(read_control(&*self.storage, &self.dir)?.1)
+    }
+
+    pub fn set_logical(&mut self, logical: bool) -> Result<(), Error> {
+        let (checkpoint, old) = read_control(&*self.storage, &self.dir)?;
+        if old == logical {
+            return Ok(());
+        }
+        write_control(&*self.storage, &self.dir, checkpoint, logical)
+    }
+
+    // Drops the records from `lsn` on, which must be where a record starts, e.g. to stop recovery
+    // at some point in the past.
+    pub fn truncate(&mut self, lsn: Lsn) -> Result<(), Error> {
+        self.flush_all()?;
+        let start = self.segment_start(lsn);
+        let segments = self.segments();
+        let zeros = vec![0; (start + self.segment_size - lsn) as usize];
+        let file = self.segment_file(start)?;
+        file.write_at(lsn - start, &zeros)?;
+        file.sync()?;
+        for later in segments.into_iter().filter(|&s| s > start) {
+            self.storage.remove_file(&segment_path(&self.dir, later))?;
+        }
+        self.reading = None;
+        self.flushed = lsn;
+        Ok(())
+    }
+}
+
+// Records the checkpoint begun at `lsn` as the latest one of the log in `dir`.
+pub fn write_last_checkpoint(dir: &Path, lsn: Lsn) -> Result<(), Error> {
+    let (_, logical) = read_control(&FileSystem, dir)?;
+    write_control(&FileSystem, dir, Some(lsn), logical)
+}
+
+// The latest checkpoint and the logical flag of the control data of the log in `dir`.
+fn read_control(storage: &dyn StorageBackend, dir: &Path) -> Result<(Option<Lsn>, bool), Error> {
+    let bytes = match storage.read(&dir.join(CONTROL_FILE)) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((None, false)),
+        Err(e) => return Err(e.into()),
+    };
+    let (checkpoint, logical) = match bytes.len() {
+        8 => (&bytes[..], false),
+        9 if bytes[8] <= 1 => (&bytes[..8], bytes[8] == 1),
+        _ => return Err(Error::Malformed(0)),
+    };
+    let checkpoint = u64::from_le_bytes(checkpoint.try_into().unwrap());
+    Ok(((checkpoint != 0).then_some(checkpoint), logical))
+}
+
+fn write_control(storage: &dyn StorageBackend, dir: &Path, checkpoint: Option<Lsn>, logical: bool) -> Result<(), Error> {
+    let mut bytes = checkpoint.unwrap_or(0).to_le_bytes().to_vec();
+    bytes.push(logical as u8);
+    storage.replace(&dir.join(CONTROL_FILE), &bytes)?;
+    Ok(())
+}
+
+pub fn segment_path(dir: &Path, start: Lsn) -> PathBuf {
+    dir.join(format!("{:016x}.wal", start))
+}
+
+// Copies each segment to `dir` as it fills up.
+pub fn archive_to(dir: impl AsRef<Path>) -> Archiver {
+    let dir = dir.as_ref().to_path_buf();
+    Box::new(move |start, path| {
+        fs::create_dir_all(&dir)?;
+        // copied under another name first so that the archive never has a partial segment
+        let tmp = dir.join(format!("{:016x}.tmp", start));
+        fs::copy(path, &tmp)?;
+        File::open(&tmp)?.sync_all()?;
+        fs::rename(tmp, segment_path(&dir, start))
+    })
+}
+
+fn encode(txid: TxId, prev_lsn: Option<Lsn>, record: &Record) -> Vec<u8> {
+    let mut body = vec![];
+    let kind = match record {
+        Record::Begin => BEGIN,
+        Record::Update { .. } => UPDATE,
+        Record::Compensation { .. } => COMPENSATION,
+        Record::Redo { .. } => REDO,
+        Record::PageImage { .. } => PAGE_IMAGE,
+        Record::AtomicEnd { .. } => ATOMIC_END,
+        Record::Change { .. } => CHANGE,
+        Record::Commit { .. } => COMMIT,
+        Record::Abort => ABORT,
+        Record::End => END,
+        Record::CheckpointBegin => CHECKPOINT_BEGIN,
+        Record::CheckpointEnd(_) => CHECKPOINT_END,
+        Record::Vacuum { .. } => VACUUM,
+        Record::Truncate { .. } => TRUNCATE,
+    };
+    body.push(kind);
+    body.extend_from_slice(&txid.to_le_bytes());
+    body.extend_from_slice(&prev_lsn.unwrap_or(NO_LSN).to_le_bytes());
+    match record {
+        Record::Update { page_id, offset, before, after } => {
+            let delta = before.iter().zip(after).map(|(b, a)| b ^ a);
+            encode_change(&mut body, *page_id, *offset, before.len(), &before.iter().copied().chain(delta).collect::<Vec<_>>());
+        }
+        Record::Compensation { page_id, offset, after, undo_next } => {
+            encode_change(&mut body, *page_id, *offset, after.len(), after);
+            body.extend_from_slice(&undo_next.unwrap_or(NO_LSN).to_le_bytes());
+        }
+        Record::Redo { page_id, offset, after } => encode_change(&mut body, *page_id, *offset, after.len(), after),
+        Record::PageImage { page_id, image } => encode_change(&mut body, *page_id, 0, image.len(), image),
+        Record::AtomicEnd { undo_next } => body.extend_from_slice(&undo_next.unwrap_or(NO_LSN).to_le_bytes()),
+        Record::Change { table, relation, old, new } => {
+            body.extend_from_slice(&table.0.to_le_bytes());
+            body.push(relation.is_some() as u8);
+            if let Some(relation) = relation {
+                body.extend_from_slice(&(relation.columns.len() as u32).to_le_bytes());
+                for name in std::iter::once(&relation.name).chain(&relation.columns) {
+                    body.extend_from_slice(&(name.len() as u32).to_le_bytes());
+                    body.extend_from_slice(name.as_bytes());
+                }
+            }
+            for row in [old, new] {
+                body.push(row.is_some() as u8);
+                let row = row.as_deref().unwrap_or_default();
+                body.extend_from_slice(&(row.len() as u32).to_le_bytes());
+                body.extend_from_slice(row);
+            }
+        }
+        Record::CheckpointEnd(checkpoint) => {
+            body.extend_from_slice(&checkpoint.next_txid.to_le_bytes());
+            body.extend_from_slice(&(checkpoint.transactions.len() as u32).to_le_bytes());
+            for (txid, lsn) in &checkpoint.transactions {
+                body.extend_from_slice(&txid.to_le_bytes());
+                body.extend_from_slice(&lsn.to_le_bytes());
+            }
+            body.extend_from_slice(&(checkpoint.dirty_pages.len() as u32).to_le_bytes());
+            for (page_id, lsn) in &checkpoint.dirty_pages {
+                body.extend_from_slice(&page_id.0.to_le_bytes());
+                body.extend_from_slice(&lsn.to_le_bytes());
+            }
+            body.extend_from_slice(&(checkpoint.aborted.len() as u32).to_le_bytes());
+            for txid in &checkpoint.aborted {
+                body.extend_from_slice(&txid.to_le_bytes());
+            }
+        }
+        Record::Commit { time } => {
+            let micros = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64;
+            body.extend_from_slice(&micros.to_le_bytes());
+        }
+        Record::Vacuum { horizon } => body.extend_from_slice(&horizon.to_le_bytes()),
+        Record::Truncate { num_pages } => body.extend_from_slice(&num_pages.to_le_bytes()),
+        Record::Begin | Record::Abort | Record::End | Record::CheckpointBegin => {}
+    }
+    body
+}
+
+fn encode_change(body: &mut Vec<u8>, page_id: PageId, offset: u16, len: usize, data: &[u8]) {
+    body.extend_from_slice(&page_id.0.to_le_bytes());
+    body.extend_from_slice(&offset.to_le_bytes());
+    body.extend_from_slice(&(len as u16).to_le_bytes());
+    let compressed = Some(data).filter(|data| data.len() >= COMPRESS_MIN).map(lz4::compress).filter(|c| c.len() < data.len());
+    body.push(compressed.is_some() as u8);
+    let data = compressed.as_deref().unwrap_or(data);
+    body.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    body.extend_from_slice(data);
+}
+
+fn write_frame(out: &mut Vec<u8>, lsn: Lsn, body: &[u8]) {
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(&checksum(lsn, body).to_le_bytes());
+    out.extend_from_slice(body);
+}
+
+// Checks the frame at `lsn` with `header`, whose body starts `rest`, returning the body and the
+// LSN after the frame.
+fn parse_frame<'a>(lsn: Lsn, header: &[u8], rest: Option<&'a [u8]>) -> Result<(&'a [u8], Lsn), Error> {
+    let len = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+    let body = rest.and_then(|rest| rest.get(..len)).ok_or(Error::Malformed(lsn))?;
+    if checksum(lsn, body) != u32::from_le_bytes(header[4..8].try_into().unwrap()) {
+        return Err(Error::Malformed(lsn));
+    }
+    Ok((body, lsn + (FRAME_HEADER_SIZE + len) as u64))
+}
+
+fn decode(lsn: Lsn, body: &[u8]) -> Result<LogRecord, Error> {
+    let mut pos = 0;
+    let mut take = |len: usize| -> Result<&[u8], Error> {
+        let slice = body.get(pos..pos + len).ok_or(Error::Malformed(lsn))?;
+        pos += len;
+        Ok(slice)
+    };
+    let u64_at = |bytes: &[u8]| u64::from_le_bytes(bytes.try_into().unwrap());
+    let lsn_at = |bytes: &[u8]| Some(u64_at(bytes)).filter(|&lsn| lsn != NO_LSN);
+    let kind = take(1)?[0];
+    let txid = u64_at(take(8)?);
+    let prev_lsn = lsn_at(take(8)?);
+    let record = match kind {
+        BEGIN => Record::Begin,
+        UPDATE | COMPENSATION | REDO | PAGE_IMAGE => {
+            let page_id = PageId(u64_at(take(8)?));
+            let offset = u16::from_le_bytes(take(2)?.try_into().unwrap());
+            let len = u16::from_le_bytes(take(2)?.try_into().unwrap()) as usize;
+            let compressed = take(1)?[0] != 0;
+            let data_len = u16::from_le_bytes(take(2)?.try_into().unwrap()) as usize;
+            let data = take(data_len)?;
+            let expected = if kind == UPDATE { 2 * len } else { len };
+            let data = match compressed {
+                true => lz4::decompress(data, expected).ok_or(Error::Malformed(lsn))?,
+                false => Some(data.to_vec()).filter(|data| data.len() == expected).ok_or(Error::Malformed(lsn))?,
+            };
+            if kind == UPDATE {
+                let (before, delta) = data.split_at(len);
+                let after = before.iter().zip(delta).map(|(b, d)| b ^ d).collect();
+                Record::Update { page_id, offset, before: before.to_vec(), after }
+            } else if kind == COMPENSATION {
+                let undo_next = lsn_at(take(8)?);
+                Record::Compensation { page_id, offset, after: data, undo_next }
+            } else if kind == PAGE_IMAGE {
+                Record::PageImage { page_id, image: data }
+            } else {
+                Record::Redo { page_id, offset, after: data }
+            }
+        }
+        CHANGE => {
+            let table = PageId(u64_at(take(8)?));
+            let relation = match take(1)?[0] != 0 {
+                true => {
+                    let num_columns = u32::from_le_bytes(take(4)?.try_into().unwrap());
+                    let mut name = || -> Result<String, Error> {
+                        let len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+                        String::from_utf8(take(len)?.to_vec()).map_err(|_| Error::Malformed(lsn))
+                    };
+                    let table_name = name()?;
+                    let columns = (0..num_columns).map(|_| name()).collect::<Result<_, _>>()?;
+                    Some(Relation { name: table_name, columns })
+                }
+                false => None,
+            };
+            let mut row = || -> Result<Option<Vec<u8>>, Error> {
+                let present = take(1)?[0] != 0;
+                let len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+                Ok(Some(take(len)?.to_vec()).filter(|_| present))
+            };
+            let old = row()?;
+            let new = row()?;
+            Record::Change { table, relation, old, new }
+        }
+        COMMIT => Record::Commit {
+            time: UNIX_EPOCH + Duration::from_micros(u64_at(take(8)?)),
+        },
+        ABORT => Record::Abort,
+        END => Record::End,
+        CHECKPOINT_BEGIN => Record::CheckpointBegin,
+        VACUUM => Record::Vacuum { horizon: u64_at(take(8)?) },
+        ATOMIC_END => Record::AtomicEnd { undo_next: lsn_at(take(8)?) },
+        TRUNCATE => Record::Truncate { num_pages: u64_at(take(8)?) },
+        CHECKPOINT_END => {
+            let next_txid = u64_at(take(8)?);
+            let mut pairs = || -> Result<Vec<(u64, u64)>, Error> {
+                let len = u32::from_le_bytes(take(4)?.try_into().unwrap());
+                (0..len).map(|_| Ok((u64_at(take(8)?), u64_at(take(8)?)))).collect()
+            };
+            let transactions = pairs()?;
+            let dirty_pages = pairs()?.into_iter().map(|(page_id, lsn)| (PageId(page_id), lsn)).collect();
+            let len = u32::from_le_bytes(take(4)?.try_into().unwrap());
+            let aborted = (0..len).map(|_| Ok(u64_at(take(8)?))).collect::<Result<_, Error>>()?;
+            Record::CheckpointEnd(Checkpoint { next_txid, transactions, dirty_pages, aborted })
+        }
+        _ => return Err(Error::Malformed(lsn)),
+    };
+    Ok(LogRecord { lsn, txid, prev_lsn, record })
+}
+
+// FNV-1a of the LSN and the body, enough to tell a torn record from a complete one and a record
+// from one left in a recycled segment.
+fn checksum(lsn: Lsn, body: &[u8]) -> u32 {
+    lsn.to_le_bytes().iter().chain(body).fold(0x811c9dc5, |hash, &b| (hash ^ b as u32).wrapping_mul(0x01000193))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io::{Seek, SeekFrom, Write};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        let dir = tempdir().unwrap();
+        let size = MIN_SEGMENT_SIZE;
+        assert!(matches!(Wal::open(dir.path(), size - 1), Err(Error::Config(_))));
+        let mut wal = Wal::open(dir.path(), size).unwrap();
+        let update = Record::Update {
+            page_id: PageId(3),
+            offset: 10,
+            before: vec![0, 0],
+            after: vec![1, 2],
+        };
+        let begin = wal.append(1, None, &Record::Begin).unwrap();
+        assert_eq!(MAGIC.len() as u64, begin);
+        let first = wal.append(1, Some(begin), &update).unwrap();
+        wal.flush(first + 1).unwrap();
+        let compensation = Record::Compensation {
+            page_id: PageId(3),
+            offset: 10,
+            after: vec![0, 0],
+            undo_next: Some(begin),
+        };
+        let second = wal.append(1, Some(first), &compensation).unwrap();
+
+        // read back from both the file and the unflushed tail
+        let (record, next) = wal.read(first).unwrap().unwrap();
+        assert_eq!(LogRecord { lsn: first, txid: 1, prev_lsn: Some(begin), record: update }, record);
+        assert_eq!(second, next);
+        let (record, next) = wal.read(second).unwrap().unwrap();
+        assert_eq!(compensation, record.record);
+        assert_eq!(None, wal.read(next).unwrap());
+
+        // only flushed records survive, and a torn one at the end is dropped
+        let end = wal.end();
+        drop(wal);
+        let mut wal = Wal::open(dir.path(), size).unwrap();
+        assert_eq!(second, wal.end());
+        wal.append(1, Some(first), &compensation).unwrap();
+        wal.flush_all().unwrap();
+        assert_eq!(end, wal.end());
+        drop(wal);
+        let path = segment_path(dir.path(), 0);
+        assert_eq!(size, fs::metadata(&path).unwrap().len());
+        let mut file = File::options().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(end - 3)).unwrap();
+        file.write_all(&[0xff; 3]).unwrap();
+        drop(file);
+        let mut wal = Wal::open(dir.path(), size).unwrap();
+        assert_eq!(second, wal.end());
+        assert!(wal.read(begin).unwrap().is_some());
+
+        let noise = |seed: u64, len: usize| -> Vec<u8> {
+            let mut x = seed + 1;
+            (0..len)
+                .map(|_| {
+                    x ^= x << 13;
+                    x ^= x >> 7;
+                    x ^= x << 17;
+                    x as u8
+                })
+                .collect()
+        };
+        // a record which doesn't fit goes to the next segment, and records are read across them
+        let big = Record::Update {
+            page_id: PageId(4),
+            offset: 0,
+            before: noise(0, 4000),
+            after: noise(1, 4000),
+        };
+        let too_big = Record::Compensation {
+            page_id: PageId(4),
+            offset: 0,
+            after: noise(0, size as usize),
+            undo_next: None,
+        };
+        assert!(matches!(wal.append(1, None, &too_big), Err(Error::RecordTooLarge)));
+        let archived = Rc::new(RefCell::new(vec![]));
+        let log = archived.clone();
+        wal.set_archiver(Box::new(move |start, path| {
+            log.borrow_mut().push((start, fs::read(path)?));
+            Ok(())
+        }));
+        let bigs: Vec<_> = (0..3).map(|_| wal.append(1, Some(first), &big).unwrap()).collect();
+        assert_eq!(size + MAGIC.len() as u64, bigs[2]);
+        wal.flush_all().unwrap();
+        assert_eq!(vec![0, size], wal.segments());
+        assert_eq!(vec![(0, fs::read(&path).unwrap())], *archived.borrow());
+        let checkpoint = Checkpoint {
+            next_txid: 2,
+            transactions: vec![(1, second)],
+            dirty_pages: vec![(PageId(3), first)],
+            aborted: vec![1],
+        };
+        let redo = Record::Redo {
+            page_id: PageId(3),
+            offset: 12,
+            after: vec![7],
+        };
+        let image = Record::PageImage {
+            page_id: PageId(3),
+            image: noise(2, 8),
+        };
+        let atomic_end = Record::AtomicEnd { undo_next: None };
+        wal.append(2, None, &redo).unwrap();
+        wal.append(2, None, &image).unwrap();
+        wal.append(2, None, &atomic_end).unwrap();
+        let checkpoint_begin = wal.append(0, None, &Record::CheckpointBegin).unwrap();
+        wal.append(0, None, &Record::CheckpointEnd(checkpoint.clone())).unwrap();
+        wal.flush_all().unwrap();
+        assert_eq!(None, wal.last_checkpoint().unwrap());
+        // the control data keeps the logical flag apart from the checkpoint
+        assert!(!wal.logical().unwrap());
+        wal.set_logical(true).unwrap();
+        assert_eq!(None, wal.last_checkpoint().unwrap());
+        wal.set_last_checkpoint(checkpoint_begin).unwrap();
+        assert!(wal.logical().unwrap());
+        let mut lsn = begin;
+        let mut records = vec![];
+        while let Some((record, next)) = wal.read(lsn).unwrap() {
+            records.push(record.record);
+            lsn = next;
+        }
+        assert_eq!(vec![big.clone(); 3], records[2..5]);
+        assert_eq!([redo, image, atomic_end], records[5..8]);
+        assert_eq!(Record::CheckpointEnd(checkpoint), records[9]);
+
+        // segments entirely before an LSN are recycled once archived, the log going on into them
+        drop(wal);
+        let mut wal = Wal::open(dir.path(), size).unwrap();
+        assert_eq!(Some(checkpoint_begin), wal.last_checkpoint().unwrap());
+        assert!(wal.logical().unwrap());
+        write_last_checkpoint(dir.path(), checkpoint_begin).unwrap();
+        assert!(wal.logical().unwrap());
+        fs::write(dir.path().join(CONTROL_FILE), checkpoint_begin.to_le_bytes()).unwrap();
+        assert_eq!((Some(checkpoint_begin), false), (wal.last_checkpoint().unwrap(), wal.logical().unwrap()));
+        wal.set_logical(true).unwrap();
+        wal.remove_before(checkpoint_begin).unwrap();
+        assert_eq!(vec![size], wal.segments());
+        assert_eq!(size + MAGIC.len() as u64, wal.start());
+        assert!(!path.exists());
+        let spare = segment_path(dir.path(), 2 * size);
+        assert!(spare.exists());
+        let end = wal.end();
+        drop(wal);
+        // the old records in a spare aren't taken for new ones
+        let mut wal = Wal::open(dir.path(), size).unwrap();
+        assert_eq!(end, wal.end());
+        let lsns: Vec<_> = (0..3).map(|_| wal.append(2, None, &big).unwrap()).collect();
+        wal.flush_all().unwrap();
+        assert_eq!(2 * size + MAGIC.len() as u64, lsns[1]);
+        assert_eq!(vec![size, 2 * size], wal.segments());
+        assert_eq!(size, fs::metadata(&spare).unwrap().len());
+        drop(wal);
+        let mut wal = Wal::open(dir.path(), size).unwrap();
+        let (record, next) = wal.read(lsns[2]).unwrap().unwrap();
+        assert_eq!(big, record.record);
+        assert_eq!(None, wal.read(next).unwrap());
+        assert_eq!(next, wal.end());
+
+        // records a slot retains aren't removed, even after reopening
+        let segments = wal.segments();
+        wal.set_slot("replica", Some(wal.start())).unwrap();
+        wal.set_slot("decoding", Some(wal.end())).unwrap();
+        assert!(wal.set_slot("a b", Some(0)).is_err());
+        drop(wal);
+        let mut wal = Wal::open(dir.path(), size).unwrap();
+        assert_eq!(Some(wal.start()), wal.slot("replica"));
+        wal.remove_before(wal.end()).unwrap();
+        assert_eq!(segments, wal.segments());
+        wal.set_slot("replica", None).unwrap();
+        assert_eq!(None, wal.slot("replica"));
+        wal.remove_before(wal.end()).unwrap();
+        assert_eq!(1, wal.segments().len());
+
+        // big changes are compressed, the after image of an update as its difference from the before one
+        let mut after = noise(0, 4000);
+        after[100..110].fill(7);
+        let compressible = Record::Update { page_id: PageId(4), offset: 0, before: noise(0, 4000), after };
+        let lsn = wal.append(1, None, &compressible).unwrap();
+        assert!(wal.end() - lsn < 4200);
+        assert_eq!(compressible, wal.read(lsn).unwrap().unwrap().0.record);
+    }
+}
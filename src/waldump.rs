@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::disk::PageId;
+use crate::inspect;
+use crate::tuple;
+use crate::wal::{self, Lsn, LogRecord, Record, TxId, Wal};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Wal(#[from] wal::Error),
+    #[error("no log segments in {0}")]
+    NoSegments(PathBuf),
+}
+
+// Bytes of a change shown, the rest being elided.
+const MAX_HEX: usize = 32;
+
+// Which records to print; the others are only read past.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    pub txid: Option<TxId>,
+    // records changing the page
+    pub page_id: Option<PageId>,
+    // the LSN of the first record, the start of the log if None
+    pub start: Option<Lsn>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    // records printed, by kind
+    pub records: BTreeMap<&'static str, usize>,
+    // LSN after the last record
+    pub end: Lsn,
+    pub last_checkpoint: Option<Lsn>,
+    // whether the bytes at the end are of something which isn't a record
+    pub torn: bool,
+}
+
+// Prints the records of the log segments in `dir`, a line each of its LSN, transaction, previous
+// LSN of the transaction, kind and what it holds: the page and bytes changed, the rows of a
+// change, the tables of a checkpoint, and so on. Then how many of each kind there were and where
+// the log ends, which is where a record torn by a crash or left in a recycled segment is found.
+//
+// The segments are only read, so this works on the log of a database which is open, and on an
+// archive of segments.
+pub fn dump(dir: impl AsRef<Path>, output: &mut impl Write, filter: &Filter) -> Result<Summary, Error> {
+    let dir = dir.as_ref();
+    let mut segment_size = None;
+    if dir.is_dir() {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_name().to_str().is_some_and(|name| name.ends_with(".wal")) {
+                segment_size = Some(entry.metadata()?.len());
+                break;
+            }
+        }
+    }
+    let segment_size = segment_size.ok_or_else(|| Error::NoSegments(dir.to_path_buf()))?;
+    let mut wal = Wal::open(dir, segment_size)?;
+    let mut summary = Summary { last_checkpoint: wal.last_checkpoint()?, ..Summary::default() };
+    writeln!(output, "{:>12} {:>8} {:>12}  {:<16} details", "lsn", "txid", "prev_lsn", "kind")?;
+    let mut lsn = filter.start.unwrap_or(wal.start());
+    while let Some((record, next)) = wal.read(lsn)? {
+        let page_id = match &record.record {
+            Record::Change { table, .. } => Some(*table),
+            record => record.page_id(),
+        };
+        if filter.txid.is_none_or(|txid| txid == record.txid) && filter.page_id.is_none_or(|id| Some(id) == page_id) {
+            writeln!(output, "{}", format_record(&record))?;
+            *summary.records.entry(record.record.name()).or_default() += 1;
+        }
+        lsn = next;
+    }
+    summary.end = lsn;
+    summary.torn = torn(dir, segment_size, lsn)?;
+    let counts: Vec<_> = summary.records.iter().map(|(name, count)| format!("{} {}", count, name)).collect();
+    writeln!(output, "{} records: {}", summary.records.values().sum::<usize>(), counts.join(", "))?;
+    let checkpoint = summary.last_checkpoint.map_or("none".to_string(), |lsn| lsn.to_string());
+    writeln!(output, "end of log at {}, last checkpoint at {}", summary.end, checkpoint)?;
+    if summary.torn {
+        writeln!(output, "followed by bytes which aren't a record: one torn by a crash, or left in a recycled segment")?;
+    }
+    Ok(summary)
+}
+
+pub fn format_record(record: &LogRecord) -> String {
+    let prev = record.prev_lsn.map_or("-".to_string(), |lsn| lsn.to_string());
+    let details = match &record.record {
+        Record::Update { page_id, offset, before, after } => {
+            format!("page {} offset {} len {} before {} after {}", page_id.0, offset, before.len(), hex(before), hex(after))
+        }
+        Record::Compensation { page_id, offset, after, undo_next } => {
+            let undo_next = undo_next.map_or("-".to_string(), |lsn| lsn.to_string());
+            format!("page {} offset {} len {} after {} undo_next {}", page_id.0, offset, after.len(), hex(after), undo_next)
+        }
+        Record::Redo { page_id, offset, after } => format!("page {} offset {} len {} after {}", page_id.0, offset, after.len(), hex(after)),
+        Record::PageImage { page_id, image } => format!("page {} len {}", page_id.0, image.len()),
+        Record::AtomicEnd { undo_next } => format!("undo_next {}", undo_next.map_or("-".to_string(), |lsn| lsn.to_string())),
+        Record::Change { table, relation, old, new } => {
+            let relation = relation.as_ref().map_or("-".to_string(), |relation| format!("{}({})", relation.name, relation.columns.join(", ")));
+            format!("table {} {} old {} new {}", table.0, relation, row(old), row(new))
+        }
+        Record::Commit { time } => {
+            let time = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            format!("time {}.{:06}", time.as_secs(), time.subsec_micros())
+        }
+        Record::CheckpointEnd(checkpoint) => {
+            let pairs = |pairs: &mut dyn Iterator<Item = (u64, Lsn)>| pairs.map(|(id, lsn)| format!("{}@{}", id, lsn)).collect::<Vec<_>>().join(" ");
+            format!(
+                "next_txid {} transactions [{}] dirty_pages [{}] aborted [{}]",
+                checkpoint.next_txid,
+                pairs(&mut checkpoint.transactions.iter().copied()),
+                pairs(&mut checkpoint.dirty_pages.iter().map(|&(page_id, lsn)| (page_id.0, lsn))),
+                checkpoint.aborted.iter().map(|txid| txid.to_string()).collect::<Vec<_>>().join(" ")
+            )
+        }
+        Record::Vacuum { horizon } => format!("horizon {}", horizon),
+        Record::Truncate { num_pages } => format!("pages {}", num_pages),
+        Record::Begin | Record::Abort | Record::End | Record::CheckpointBegin => String::new(),
+    };
+    format!("{:>12} {:>8} {:>12}  {:<16} {}", record.lsn, record.txid, prev, record.record.name(), details).trim_end().to_string()
+}
+
+fn row(row: &Option<Vec<u8>>) -> String {
+    match row.as_deref().map(tuple::decode) {
+        None => "-".to_string(),
+        Some(Ok((values, _))) => inspect::format_values(&values),
+        Some(Err(_)) => format!("malformed {}", hex(row.as_deref().unwrap())),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let shown: String = bytes.iter().take(MAX_HEX).map(|b| format!("{:02x}", b)).collect();
+    match bytes.len() > MAX_HEX {
+        true => format!("{}..", shown),
+        false => shown,
+    }
+}
+
+// Whether a frame header which isn't zero padding follows the end in its segment.
+fn torn(dir: &Path, segment_size: u64, end: Lsn) -> Result<bool, Error> {
+    let start = end - end % segment_size;
+    let mut header = [0; 8];
+    if end - start + header.len() as u64 > segment_size {
+        return Ok(false);
+    }
+    let mut file = match File::open(wal::segment_path(dir, start)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    file.seek(SeekFrom::Start(end - start))?;
+    file.read_exact(&mut header)?;
+    Ok(header != [0; 8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Database, WAL_DIR};
+    use std::fs::OpenOptions;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test() {
+        let dir = tempdir().unwrap();
+        assert!(matches!(dump(dir.path(), &mut vec![], &Filter::default()), Err(Error::NoSegments(_))));
+        let db = Database::open(dir.path(), 16).unwrap();
+        let mut session = db.session();
+        session.execute("CREATE TABLE t (id INTEGER PRIMARY KEY, name TEXT)").unwrap();
+        session.execute("BEGIN; INSERT INTO t VALUES (1, 'a'); ROLLBACK").unwrap();
+        session.execute("INSERT INTO t VALUES (2, 'b')").unwrap();
+        drop(session);
+        db.close().unwrap();
+
+        let wal_dir = dir.path().join(WAL_DIR);
+        let mut output = vec![];
+        let summary = dump(&wal_dir, &mut output, &Filter::default()).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!("         lsn     txid     prev_lsn  kind             details", lines[0]);
+        assert_eq!("           8        0            -  CHECKPOINT BEGIN", lines[1]);
+        assert!(output.contains(" UPDATE           page "), "{}", output);
+        assert!(output.contains(" PAGE IMAGE       page 3 len 4084\n"), "{}", output);
+        assert!(output.contains(" ATOMIC END       undo_next "), "{}", output);
+        assert!(output.contains(" ABORT\n"), "{}", output);
+        assert!(output.contains(" COMMIT           time "), "{}", output);
+        assert!(output.contains(" CHECKPOINT END   next_txid 5 transactions [] dirty_pages [] aborted [3]\n"), "{}", output);
+        assert_eq!(Some(&1), summary.records.get("ABORT"));
+        assert!(summary.last_checkpoint.is_some());
+        assert!(!summary.torn);
+        assert!(output.ends_with(&format!("end of log at {}, last checkpoint at {}\n", summary.end, summary.last_checkpoint.unwrap())));
+
+        // only the records of a transaction, from an LSN on
+        let begins: Vec<_> = output.lines().filter(|line| line.ends_with(" BEGIN")).map(|line| line.split_whitespace().collect::<Vec<_>>()).collect();
+        let (lsn, txid) = (begins[2][0].parse().unwrap(), begins[2][1].parse().unwrap());
+        let mut output = vec![];
+        let filter = Filter { txid: Some(txid), start: Some(lsn), ..Filter::default() };
+        let filtered = dump(&wal_dir, &mut output, &filter).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.lines().skip(1).take_while(|line| !line.contains(" records: ")).all(|line| line.split_whitespace().nth(1) == Some(&*txid.to_string())), "{}", output);
+        assert_eq!(Some(&1), filtered.records.get("COMMIT"));
+        assert_eq!(None, filtered.records.get("ABORT"));
+        let filter = Filter { page_id: Some(PageId(3)), ..Filter::default() };
+        let mut output = vec![];
+        dump(&wal_dir, &mut output, &filter).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.lines().nth(1).unwrap().contains(" page 3 "), "{}", output);
+
+        let mut new = vec![];
+        tuple::encode(&[tuple::Value::Int(2), tuple::Value::Text("b".to_string())], &mut new);
+        let change = LogRecord { lsn: 100, txid: 4, prev_lsn: Some(90), record: Record::Change { table: PageId(2), relation: Some(wal::Relation { name: "t".to_string(), columns: vec!["id".to_string(), "name".to_string()] }), old: None, new: Some(new) } };
+        assert_eq!("         100        4           90  CHANGE           table 2 t(id, name) old - new (2, 'b')", format_record(&change));
+        let update = Record::Update { page_id: PageId(3), offset: 1, before: vec![0; 40], after: vec![1; 40] };
+        let line = format_record(&LogRecord { lsn: 100, txid: 4, prev_lsn: None, record: update });
+        assert!(line.ends_with(&format!("page 3 offset 1 len 40 before {}.. after {}..", "00".repeat(32), "01".repeat(32))), "{}", line);
+
+        // garbage after the end, as a crash during a flush leaves
+        let size = wal::DEFAULT_SEGMENT_SIZE;
+        let mut file = OpenOptions::new().write(true).open(wal::segment_path(&wal_dir, summary.end - summary.end % size)).unwrap();
+        file.seek(SeekFrom::Start(summary.end % size)).unwrap();
+        file.write_all(&[1, 2, 3, 4, 5, 6, 7, 8, 9]).unwrap();
+        drop(file);
+        let mut output = vec![];
+        let torn = dump(&wal_dir, &mut output, &Filter::default()).unwrap();
+        assert!(torn.torn);
+        assert_eq!(summary.end, torn.end);
+        assert!(String::from_utf8(output).unwrap().ends_with("left in a recycled segment\n"));
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::buffer::{self, BufferPoolManager};
+use crate::catalog::{self, Catalog};
+use crate::lock;
+use crate::table::{self, Table};
+use crate::sql::{self, ast};
+
+// Background maintenance: a worker is a thread which queues its task every interval of its own
+// until it's stopped. The buffer pool is of one thread only, so the tasks are run by the thread
+// the engine is in, taking them off the queue between the calls of its clients (see
+// `Workers::queued`).
+//
+// Workers are stopped those making the most work first, the stats collector, expiry and vacuum, then
+// the checkpointer and the flusher, so nothing is queued after the last flush.
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error(transparent)]
+    Sql(#[from] sql::Error),
+    #[error(transparent)]
+    Table(#[from] table::Error),
+    #[error(transparent)]
+    Raft(#[from] crate::raft::Error),
+}
+
+// Dirty pages a flush writes back.
+const FLUSH_PAGES: usize = 64;
+// Rows an expiry deletes in each of its transactions.
+const EXPIRE_ROWS: usize = 100;
+
+// In the order they run when queued together, and the reverse of the order they're stopped in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Task {
+    // flushes the log, and writes back the pages dirty the longest
+    Flush,
+    Checkpoint,
+    // vacuums every table
+    Vacuum,
+    // deletes the rows of every table with a TTL which have expired, in batches
+    Expire,
+    // collects the statistics of every table
+    Analyze,
+}
+
+impl Task {
+    // Runs the task in a transaction of its own, if it needs one.
+    pub fn run(self, bufmgr: &mut BufferPoolManager, catalog: &mut Catalog) -> Result<(), Error> {
+        let state = bufmgr.switch(Default::default());
+        let result = match self {
+            Task::Flush => bufmgr.flush_log().and_then(|()| bufmgr.write_back(FLUSH_PAGES)).map_err(Error::from),
+            Task::Checkpoint => bufmgr.checkpoint().map_err(Error::from),
+            Task::Vacuum => sql::execute_statement(bufmgr, catalog, &ast::Statement::Vacuum { table: None, full: false }).map(|_| ()).map_err(Error::from),
+            Task::Expire => {
+                let tables: Vec<Table> = catalog.tables().map(|info| info.table.clone()).filter(|table| table.ttl.is_some()).collect();
+                tables.iter().try_for_each(|table| expire(bufmgr, table))
+            }
+            Task::Analyze => match sql::execute_statement(bufmgr, catalog, &ast::Statement::Analyze(None)) {
+                // left to the next time while another transaction is changing the catalog
+                Err(sql::Error::Catalog(catalog::Error::Lock(lock::Error::Wait))) => Ok(()),
+                result => result.map(|_| ()).map_err(Error::from),
+            },
+        };
+        bufmgr.switch(state);
+        result
+    }
+}
+
+// Deletes the expired rows of `table`, each batch in a transaction of its own so that the rows
+// aren't kept locked long.
+fn expire(bufmgr: &mut BufferPoolManager, table: &Table) -> Result<(), Error> {
+    let mut from = None;
+    loop {
+        match table.expire(bufmgr, from.as_deref(), EXPIRE_ROWS) {
+            Ok((_, next)) => {
+                bufmgr.commit()?;
+                from = match next {
+                    Some(next) => Some(next),
+                    None => return Ok(()),
+                };
+            }
+            Err(err) => {
+                bufmgr.abort()?;
+                return Err(err.into());
+            }
+        }
+    }
+}
+
+struct Worker {
+    // set when the worker is to stop
+    stopped: Arc<(Mutex<bool>, Condvar)>,
+    handle: JoinHandle<()>,
+}
+
+pub struct Workers {
+    sender: Sender<Task>,
+    queue: Receiver<Task>,
+    workers: BTreeMap<Task, Worker>,
+}
+
+impl Default for Workers {
+    fn default() -> Self {
+        let (sender, queue) = mpsc::channel();
+        Self { sender, queue, workers: BTreeMap::new() }
+    }
+}
+
+impl Workers {
+    // Starts a worker queuing `task` every `interval`, in place of the one for it if any.
+    pub fn start(&mut self, task: Task, interval: Duration) {
+        self.stop(task);
+        let stopped = Arc::new((Mutex::new(false), Condvar::new()));
+        let (worker_stopped, sender) = (stopped.clone(), self.sender.clone());
+        let handle = thread::spawn(move || {
+            let (lock, condvar) = &*worker_stopped;
+            let mut stopped = lock.lock().unwrap();
+            loop {
+                let (guard, wait) = condvar.wait_timeout_while(stopped, interval, |stopped| !*stopped).unwrap();
+                stopped = guard;
+                if *stopped || (wait.timed_out() && sender.send(task).is_err()) {
+                    return;
+                }
+            }
+        });
+        self.workers.insert(task, Worker { stopped, handle });
+    }
+
+    // Stops the worker for `task` and waits for it to exit. Returns false if there was none.
+    pub fn stop(&mut self, task: Task) -> bool {
+        let Some(worker) = self.workers.remove(&task) else {
+            return false;
+        };
+        let (lock, condvar) = &*worker.stopped;
+        *lock.lock().unwrap() = true;
+        condvar.notify_one();
+        // a worker can't panic but for a poisoned lock, which is nothing to report
+        let _ = worker.handle.join();
+        true
+    }
+
+    pub fn running(&self) -> Vec<Task> {
+        self.workers.keys().copied().collect()
+    }
+
+    // Takes the tasks queued so far off the queue, each once however many times it was queued.
+    pub fn queued(&mut self) -> Vec<Task> {
+        let tasks: BTreeSet<_> = self.queue.try_iter().collect();
+        tasks.into_iter().collect()
+    }
+
+    // Stops all the workers, in the shutdown order.
+    pub fn shutdown(&mut self) {
+        for task in self.running().into_iter().rev() {
+            self.stop(task);
+        }
+    }
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::wal::{Wal, DEFAULT_SEGMENT_SIZE};
+    use std::time::Instant;
+    use tempfile::{tempdir, tempfile};
+
+    #[test]
+    fn test() {
+        let mut workers = Workers::default();
+        assert!(workers.queued().is_empty());
+        workers.start(Task::Analyze, Duration::from_millis(1));
+        workers.start(Task::Flush, Duration::from_millis(1));
+        workers.start(Task::Checkpoint, Duration::from_secs(3600));
+        assert_eq!(vec![Task::Flush, Task::Checkpoint, Task::Analyze], workers.running());
+
+        // each queued task comes out once, in order
+        let start = Instant::now();
+        let mut queued = vec![];
+        while queued.len() < 2 && start.elapsed() < Duration::from_secs(10) {
+            thread::sleep(Duration::from_millis(5));
+            queued = workers.queued();
+        }
+        assert_eq!(vec![Task::Flush, Task::Analyze], queued);
+
+        // a stopped worker queues nothing more, and stopping one doesn't wait for its interval
+        assert!(workers.stop(Task::Analyze));
+        assert!(!workers.stop(Task::Analyze));
+        workers.queued();
+        thread::sleep(Duration::from_millis(20));
+        assert!(!workers.queued().contains(&Task::Analyze));
+        let start = Instant::now();
+        workers.shutdown();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(workers.running().is_empty());
+        workers.queued();
+        thread::sleep(Duration::from_millis(20));
+        assert!(workers.queued().is_empty());
+
+        // the tasks run in transactions of their own, leaving the one running alone
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let wal_dir = tempdir().unwrap();
+        let wal = Wal::open(wal_dir.path(), DEFAULT_SEGMENT_SIZE).unwrap();
+        let mut bufmgr = BufferPoolManager::with_wal(disk, BufferPool::new(16), wal).unwrap();
+        let mut catalog = Catalog::create(&mut bufmgr).unwrap();
+        bufmgr.commit().unwrap();
+        sql::execute(&mut bufmgr, &mut catalog, "CREATE TABLE t (id INTEGER PRIMARY KEY); INSERT INTO t VALUES (1), (2)").unwrap();
+        sql::execute(&mut bufmgr, &mut catalog, "BEGIN; INSERT INTO t VALUES (3)").unwrap();
+        for task in [Task::Flush, Task::Checkpoint, Task::Vacuum, Task::Analyze] {
+            task.run(&mut bufmgr, &mut catalog).unwrap();
+        }
+        assert!(bufmgr.isolation().is_some());
+        assert_eq!(2, catalog.table("t").unwrap().stats.as_ref().unwrap().row_count);
+        sql::execute(&mut bufmgr, &mut catalog, "COMMIT").unwrap();
+        Task::Analyze.run(&mut bufmgr, &mut catalog).unwrap();
+        assert_eq!(3, catalog.table("t").unwrap().stats.as_ref().unwrap().row_count);
+
+        // expiry deletes the rows a TTL has expired, however many batches they take
+        let values: Vec<String> = (0..EXPIRE_ROWS * 2 + 10).map(|i| format!("({}, 0)", i)).collect();
+        sql::execute(&mut bufmgr, &mut catalog, "CREATE TABLE e (id INTEGER PRIMARY KEY, at INTEGER) TTL at + 60").unwrap();
+        sql::execute(&mut bufmgr, &mut catalog, &format!("INSERT INTO e VALUES {}, (-1, NULL)", values.join(", "))).unwrap();
+        let table = catalog.table("e").unwrap().table.clone();
+        Task::Expire.run(&mut bufmgr, &mut catalog).unwrap();
+        assert_eq!((0, None), table.expire(&mut bufmgr, None, usize::MAX).unwrap());
+        bufmgr.commit().unwrap();
+        assert_eq!(table::VacuumStats { versions: EXPIRE_ROWS * 2 + 10, rows: EXPIRE_ROWS * 2 + 10, index_entries: 0, compacted: 0 }, table.vacuum(&mut bufmgr).unwrap());
+        bufmgr.commit().unwrap();
+    }
+}
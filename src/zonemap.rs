@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+
+use crate::btree::{self, BTree, SearchMode};
+use crate::buffer::BufferPoolManager;
+use crate::columnar::MAX_ZONE_TEXT;
+use crate::table::Error;
+use crate::tuple::{self, Tuple, Value};
+
+// A zone map index keeps, of each extent of the rows of a table, the least and greatest values of
+// its columns, by which a scan skips the extents its predicates can't hold for, as it does the
+// stripes of a columnar table (see `columnar`), with an entry of each extent rather than of each
+// row. The extents are ranges of primary keys, each from the key it starts at up to that of the
+// next, the first from before any.
+//
+// A version of a row written widens the zones of the extent of its primary key, unless the extent
+// has had EXTENT_ROWS written to it already and the key is after all of theirs: then it starts an
+// extent of its own. Zones are never narrowed by updates and deletes, so they hold every version a
+// snapshot may see, until the index is rebuilt, by REINDEX or VACUUM FULL, from the versions there
+// are. A column has no zone in an extent once it's had text too long in it.
+//
+// The entries are keyed by the start of their extent with its bytes inverted, the first extent's
+// after all of those, so that they're in the reverse order of the extents and the extent of a
+// primary key is that of the first entry from the key's own on.
+
+// Versions of rows written to an extent before one after them starts another.
+pub const EXTENT_ROWS: usize = 256;
+
+const EXTENT: u8 = 1;
+const FIRST_EXTENT: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Zone {
+    // of no values but NULL yet
+    Empty,
+    Range(Value, Value),
+    Unbounded,
+}
+
+impl Zone {
+    // The least and greatest values other than NULL, if they're known.
+    pub fn bounds(&self) -> Option<(&Value, &Value)> {
+        match self {
+            Zone::Range(min, max) => Some((min, max)),
+            _ => None,
+        }
+    }
+
+    fn widen(&mut self, value: &Value) {
+        if matches!(value, Value::Text(s) if s.len() > MAX_ZONE_TEXT) {
+            *self = Zone::Unbounded;
+            return;
+        }
+        match self {
+            _ if value.is_null() => {}
+            Zone::Empty => *self = Zone::Range(value.clone(), value.clone()),
+            Zone::Range(min, max) if value < min => *min = value.clone(),
+            Zone::Range(min, max) if value > max => *max = value.clone(),
+            Zone::Range(..) | Zone::Unbounded => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extent {
+    // the encoded primary key it starts at, None of the first
+    pub start: Option<Vec<u8>>,
+    // versions of rows written to it
+    pub rows: usize,
+    // the greatest primary key of those
+    pub last: Tuple,
+    // of each column of the index
+    pub zones: Vec<Zone>,
+}
+
+impl Extent {
+    fn new(start: Option<Vec<u8>>, num_columns: usize) -> Self {
+        Self { start, rows: 0, last: vec![], zones: vec![Zone::Empty; num_columns] }
+    }
+
+    // Whether the row of the encoded primary key `pkey` starts an extent after this one.
+    fn full_before(&self, pkey: &[u8]) -> bool {
+        let mut last = vec![];
+        tuple::encode_key(&self.last, &mut last);
+        self.rows >= EXTENT_ROWS && pkey > last.as_slice()
+    }
+
+    fn add(&mut self, columns: &[usize], row: &[Value], num_key_elems: usize) {
+        if self.rows == 0 || row[..num_key_elems] > self.last[..] {
+            self.last = row[..num_key_elems].to_vec();
+        }
+        self.rows += 1;
+        for (zone, &c) in self.zones.iter_mut().zip(columns) {
+            zone.widen(&row[c]);
+        }
+    }
+
+    // The entry: [rows, num_key_elems, last*, (min, max)*], min and max NULL of an empty zone, and
+    // NULL and TRUE of an unbounded one.
+    fn entry(&self) -> btree::Entry {
+        let key = match &self.start {
+            Some(start) => std::iter::once(EXTENT).chain(start.iter().map(|b| !b)).collect(),
+            None => vec![FIRST_EXTENT],
+        };
+        let mut row = vec![Value::Int(self.rows as i64), Value::Int(self.last.len() as i64)];
+        row.extend(self.last.iter().cloned());
+        for zone in &self.zones {
+            row.extend(match zone {
+                Zone::Empty => [Value::Null, Value::Null],
+                Zone::Range(min, max) => [min.clone(), max.clone()],
+                Zone::Unbounded => [Value::Null, Value::Bool(true)],
+            });
+        }
+        let mut value = vec![];
+        tuple::encode(&row, &mut value);
+        (key, value)
+    }
+
+    fn decode(key: &[u8], value: &[u8]) -> Result<Self, tuple::Error> {
+        let start = match key.split_first() {
+            Some((&FIRST_EXTENT, [])) => None,
+            Some((&EXTENT, start)) => Some(start.iter().map(|b| !b).collect()),
+            _ => return Err(tuple::Error::Malformed),
+        };
+        let (row, _) = tuple::decode(value)?;
+        let (rows, num_key_elems) = match row.get(..2) {
+            Some([Value::Int(rows), Value::Int(n)]) if row.len() >= 2 + *n as usize && (row.len() - *n as usize).is_multiple_of(2) => (*rows as usize, *n as usize),
+            _ => return Err(tuple::Error::Malformed),
+        };
+        let last = row[2..2 + num_key_elems].to_vec();
+        let zones = row[2 + num_key_elems..]
+            .chunks(2)
+            .map(|zone| match zone {
+                [Value::Null, Value::Null] => Zone::Empty,
+                [Value::Null, _] => Zone::Unbounded,
+                _ => Zone::Range(zone[0].clone(), zone[1].clone()),
+            })
+            .collect();
+        Ok(Self { start, rows, last, zones })
+    }
+}
+
+// Widens the zones of `columns` of the extent of `row`, of the encoded primary key `pkey`, by it.
+pub fn add(bufmgr: &mut BufferPoolManager, btree: &BTree, columns: &[usize], row: &[Value], pkey: &[u8], num_key_elems: usize) -> Result<(), Error> {
+    let mut key = vec![EXTENT];
+    key.extend(pkey.iter().map(|b| !b));
+    let mut extent = match btree.search(bufmgr, SearchMode::Key(key))?.next(bufmgr)? {
+        Some((key, value)) => Extent::decode(&key, &value)?,
+        None => Extent::new(None, columns.len()),
+    };
+    if extent.full_before(pkey) {
+        extent = Extent::new(Some(pkey.to_vec()), columns.len());
+    }
+    extent.add(columns, row, num_key_elems);
+    let (key, value) = extent.entry();
+    btree.upsert(bufmgr, &key, &value)?;
+    Ok(())
+}
+
+// The extents, in order.
+pub fn extents(bufmgr: &mut BufferPoolManager, btree: &BTree) -> Result<Vec<Extent>, Error> {
+    let mut iter = btree.search(bufmgr, SearchMode::Start)?;
+    let mut extents = vec![];
+    while let Some((key, value)) = iter.next(bufmgr)? {
+        extents.push(Extent::decode(&key, &value)?);
+    }
+    extents.reverse();
+    Ok(extents)
+}
+
+// The entries, sorted, of the extents of `rows`, of (encoded primary key, version of the row) in
+// primary key order, as they'd be added one at a time.
+pub fn entries(columns: &[usize], rows: impl IntoIterator<Item = (Vec<u8>, Tuple)>, num_key_elems: usize) -> Vec<btree::Entry> {
+    let mut extents: Vec<Extent> = vec![];
+    for (pkey, row) in rows {
+        match extents.last_mut() {
+            Some(extent) if !extent.full_before(&pkey) => extent.add(columns, &row, num_key_elems),
+            last => {
+                let start = last.is_some().then_some(pkey);
+                let mut extent = Extent::new(start, columns.len());
+                extent.add(columns, &row, num_key_elems);
+                extents.push(extent);
+            }
+        }
+    }
+    let mut entries: Vec<_> = extents.iter().map(Extent::entry).collect();
+    entries.sort();
+    entries
+}
+
+// The ranges of encoded primary keys, from the first included up to the second, or to the end if
+// None, of the runs of `extents` which `keep` doesn't hold for.
+pub fn skipped(extents: &[Extent], keep: impl Fn(&Extent) -> bool) -> VecDeque<(Vec<u8>, Option<Vec<u8>>)> {
+    let mut ranges: VecDeque<(Vec<u8>, Option<Vec<u8>>)> = VecDeque::new();
+    for (i, extent) in extents.iter().enumerate().filter(|(_, extent)| !keep(extent)) {
+        let start = extent.start.clone().unwrap_or_default();
+        let end = extents.get(i + 1).and_then(|next| next.start.clone());
+        match ranges.back_mut() {
+            Some((_, to)) if to.as_ref() == Some(&start) => *to = end,
+            _ => ranges.push_back((start, end)),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPool;
+    use crate::disk::DiskManager;
+    use crate::table::{IndexKind, Table};
+    use tempfile::tempfile;
+
+    #[test]
+    fn test() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let mut bufmgr = BufferPoolManager::new(disk, BufferPool::new(16));
+        let mut table = Table::create(&mut bufmgr, 1).unwrap();
+        // `at` rising with the primary key, `kind` not
+        let row = |i: i64| vec![Value::Int(i), Value::Int(i * 10), Value::Int(i % 3)];
+        for i in 0..300 {
+            table.insert(&mut bufmgr, &row(i)).unwrap();
+        }
+        table.create_index(&mut bufmgr, vec![1, 2], vec![], IndexKind::ZoneMap).unwrap();
+        let zones = |table: &Table, bufmgr: &mut BufferPoolManager| extents(bufmgr, &table.indexes[0].btree).unwrap();
+        let extents = zones(&table, &mut bufmgr);
+        assert_eq!(2, extents.len());
+        assert_eq!((None, 256), (extents[0].start.clone(), extents[0].rows));
+        assert_eq!(vec![Zone::Range(Value::Int(0), Value::Int(2550)), Zone::Range(Value::Int(0), Value::Int(2))], extents[0].zones);
+        assert_eq!(vec![Zone::Range(Value::Int(2560), Value::Int(2990)), Zone::Range(Value::Int(0), Value::Int(2))], extents[1].zones);
+        assert_eq!(extents.iter().map(Extent::entry).rev().collect::<Vec<_>>(), entries(&[1, 2], (0..300).map(|i| (key(i), row(i))), 1));
+
+        // the rows written since widen the zones, of the extent of their key, or a new one after
+        for i in 300..600 {
+            table.insert(&mut bufmgr, &row(i)).unwrap();
+        }
+        table.update(&mut bufmgr, &[Value::Int(10), Value::Int(-5), Value::Int(7)]).unwrap();
+        table.insert(&mut bufmgr, &[Value::Int(-1), Value::Null, Value::Text("x".repeat(100))]).unwrap();
+        let extents = zones(&table, &mut bufmgr);
+        assert_eq!(3, extents.len());
+        assert_eq!(vec![Zone::Range(Value::Int(-5), Value::Int(2550)), Zone::Unbounded], extents[0].zones);
+        assert_eq!((258, 256, Some(key(512))), (extents[0].rows, extents[1].rows, extents[2].start.clone()));
+
+        // of the scans, by `at`, that skipping extents reads only those of the rows looked for
+        let scan = |bufmgr: &mut BufferPoolManager, keep: &dyn Fn(&Extent) -> bool| {
+            let mut iter = table.scan_zones(bufmgr, 0, keep).unwrap();
+            let mut ids = vec![];
+            while let Some(row) = iter.next(bufmgr).unwrap() {
+                ids.push(row[0].clone());
+            }
+            ids
+        };
+        let from = |low: i64| move |extent: &Extent| extent.zones[0].bounds().is_none_or(|(_, max)| *max >= Value::Int(low));
+        assert_eq!((512..600).map(Value::Int).collect::<Vec<_>>(), scan(&mut bufmgr, &from(5200)));
+        assert_eq!(600 - 256, scan(&mut bufmgr, &from(2560)).len());
+        assert_eq!(601, scan(&mut bufmgr, &|_| true).len());
+        // a run skipped in the middle
+        assert_eq!(257 + 88, scan(&mut bufmgr, &|extent| extent.start.is_none() || extent.start == Some(key(512))).len());
+
+        // rebuilt from the versions there are
+        bufmgr.reusing_pages(&mut Default::default(), |bufmgr| table.rebuild_index(bufmgr, 0)).unwrap();
+        let extents = zones(&table, &mut bufmgr);
+        assert_eq!(3, extents.len());
+        assert_eq!((Some(key(255)), 256), (extents[1].start.clone(), extents[1].rows));
+    }
+
+    fn key(i: i64) -> Vec<u8> {
+        let mut key = vec![];
+        tuple::encode_key(&[Value::Int(i)], &mut key);
+        key
+    }
+}